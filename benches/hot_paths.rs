@@ -0,0 +1,197 @@
+//! Micro-benchmarks of the hot paths below the engine: buffer pool hits
+//! and evictions, B+Tree lookups and inserts, tuple encoding, and
+//! expression evaluation.
+//!
+//! Run with `cargo bench`, or `cargo bench -- btree` for the benchmarks
+//! whose names contain `btree`. Each prints the time per iteration of the
+//! fastest, median and slowest of its samples, to compare builds by; for
+//! whole workloads through SQL, see `neru7db-bench`.
+
+use std::env;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use neru7db::btree::BTree;
+use neru7db::buffer::BufferPoolManager;
+use neru7db::disk::{DiskManager, PageId};
+use neru7db::expr::{BinaryOp, Expr};
+use neru7db::tuple;
+use neru7db::value::Value;
+
+const SAMPLES: usize = 20;
+const SAMPLE_TIME: Duration = Duration::from_millis(50);
+
+struct Bencher {
+    filter: Option<String>,
+}
+
+impl Bencher {
+    /// Times `f`, after a warm-up that also sizes the samples.
+    fn bench(&self, name: &str, mut f: impl FnMut()) {
+        if self
+            .filter
+            .as_ref()
+            .is_some_and(|filter| !name.contains(filter))
+        {
+            return;
+        }
+        let started = Instant::now();
+        let mut warmup = 0u64;
+        while started.elapsed() < SAMPLE_TIME {
+            f();
+            warmup += 1;
+        }
+        let per_iteration = started.elapsed() / warmup as u32;
+        let iterations = (SAMPLE_TIME.as_nanos() / per_iteration.as_nanos().max(1)).max(1) as u32;
+        let mut samples: Vec<Duration> = (0..SAMPLES)
+            .map(|_| {
+                let started = Instant::now();
+                for _ in 0..iterations {
+                    f();
+                }
+                started.elapsed() / iterations
+            })
+            .collect();
+        samples.sort_unstable();
+        println!(
+            "{name:<28} [{:>10.1?} {:>10.1?} {:>10.1?}]",
+            samples[0],
+            samples[SAMPLES / 2],
+            samples[SAMPLES - 1]
+        );
+    }
+}
+
+fn bufmgr(pool_size: usize) -> BufferPoolManager {
+    let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+    BufferPoolManager::new(disk, pool_size)
+}
+
+/// xorshift64*, for keys that do not follow the order they were made in.
+fn random(state: &mut u64) -> u64 {
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+    state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+}
+
+fn key(i: u64) -> Vec<u8> {
+    let mut key = vec![];
+    tuple::encode_key(&[Value::Int(i as i64)], &mut key);
+    key
+}
+
+fn buffer_pool(b: &Bencher) {
+    let pool = bufmgr(64);
+    let page_id = pool.create_page().unwrap().page_id;
+    b.bench("fetch_page/hit", || {
+        black_box(pool.fetch_page(black_box(page_id)).unwrap());
+    });
+
+    // Four times the pages the pool holds, cycled through so that every
+    // fetch misses and evicts.
+    let pool = bufmgr(64);
+    let pages = 256;
+    for _ in 0..pages {
+        pool.create_page().unwrap();
+    }
+    pool.flush().unwrap();
+    let mut next = 0;
+    b.bench("fetch_page/evict_clean", || {
+        black_box(pool.fetch_page(PageId(next)).unwrap());
+        next = (next + 1) % pages;
+    });
+    b.bench("fetch_page/evict_dirty", || {
+        pool.fetch_page(PageId(next)).unwrap().write()[0] ^= 1;
+        next = (next + 1) % pages;
+    });
+}
+
+fn btree(b: &Bencher) {
+    let keys = 100_000;
+    let pool = bufmgr(4096);
+    let tree = BTree::create(&pool).unwrap();
+    let value = [0u8; 10];
+    for i in 0..keys {
+        tree.insert(&pool, &key(i * 2), &value).unwrap();
+    }
+    let mut state = 42;
+    b.bench("btree/get", || {
+        let i = random(&mut state) % keys;
+        black_box(tree.get(&pool, &key(i * 2)).unwrap());
+    });
+    let mut next = keys * 2;
+    b.bench("btree/insert_ascending", || {
+        tree.insert(&pool, &key(next), &value).unwrap();
+        next += 1;
+    });
+    // Odd keys fall between the ones loaded. The odd repeat fails as a
+    // duplicate after the same descent, so it is timed all the same.
+    b.bench("btree/insert_random", || {
+        let i = random(&mut state) % (u32::MAX as u64);
+        let _ = tree.insert(&pool, &key(i * 2 + 1), &value);
+    });
+}
+
+fn tuples(b: &Bencher) {
+    let row = vec![
+        Value::Int(42),
+        Value::Text("a short string of some text".to_string()),
+        Value::Float(1.5),
+        Value::Null,
+        Value::Bool(true),
+    ];
+    let mut buf = vec![];
+    b.bench("tuple/encode", || {
+        buf.clear();
+        tuple::encode(black_box(&row), &mut buf);
+    });
+    let mut record = vec![];
+    tuple::encode(&row, &mut record);
+    b.bench("tuple/decode", || {
+        black_box(tuple::decode(black_box(&record)).unwrap());
+    });
+    b.bench("tuple/encode_key", || {
+        buf.clear();
+        tuple::encode_key(black_box(&row[..2]), &mut buf);
+    });
+}
+
+fn expressions(b: &Bencher) {
+    // (c0 + 1) * 2 > 10 AND c1 LIKE 'a%'
+    let predicate = Expr::binary(
+        BinaryOp::And,
+        Expr::binary(
+            BinaryOp::Gt,
+            Expr::binary(
+                BinaryOp::Mul,
+                Expr::binary(BinaryOp::Add, Expr::column(0), Expr::literal(1i64)),
+                Expr::literal(2i64),
+            ),
+            Expr::literal(10i64),
+        ),
+        Expr::binary(BinaryOp::Like, Expr::column(1), Expr::literal("a%")),
+    );
+    let row = vec![Value::Int(42), Value::Text("a short string".to_string())];
+    b.bench("expr/eval_predicate", || {
+        black_box(predicate.eval_predicate(black_box(&row)).unwrap());
+    });
+    let len = 1024;
+    let columns = vec![
+        (0..len as i64).map(Value::Int).collect(),
+        vec![Value::Text("a short string".to_string()); len],
+    ];
+    b.bench("expr/eval_batch_1024", || {
+        black_box(predicate.eval_batch(black_box(&columns), len).unwrap());
+    });
+}
+
+fn main() {
+    // cargo passes --bench, and other flags a harness would take.
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let b = Bencher { filter };
+    buffer_pool(&b);
+    btree(&b);
+    tuples(&b);
+    expressions(&b);
+}
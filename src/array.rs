@@ -0,0 +1,169 @@
+//! One-dimensional arrays, and their text form.
+//!
+//! An array holds values of one scalar type, any of them NULL. Its text
+//! is as Postgres writes it, `{1,2,NULL}`: an element is in double quotes
+//! if it is empty, reads as NULL or holds whitespace, a quote, a
+//! backslash, a comma or a brace, and within the quotes a backslash
+//! escapes the character after it.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::value::{DataType, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Array {
+    /// Never itself an array.
+    pub element: DataType,
+    /// Each NULL or of type `element`.
+    pub items: Vec<Value>,
+}
+
+impl Array {
+    pub fn new(element: DataType, items: Vec<Value>) -> Self {
+        Self { element, items }
+    }
+
+    /// The element at `index`, counting from 1 as SQL does.
+    pub fn get(&self, index: i64) -> Option<&Value> {
+        let index = usize::try_from(index.checked_sub(1)?).ok()?;
+        self.items.get(index)
+    }
+
+    /// Element by element in the total order of values, a shorter array
+    /// first where one is a prefix of the other.
+    pub fn total_cmp(&self, other: &Array) -> Ordering {
+        (self.items.iter().zip(&other.items))
+            .map(|(a, b)| a.total_cmp(b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| self.items.len().cmp(&other.items.len()))
+    }
+
+    /// Reads the text form of an array of `element`s.
+    pub fn parse(text: &str, element: DataType) -> Option<Array> {
+        let mut chars = text.trim().chars().peekable();
+        let skip_whitespace = |chars: &mut Peekable<Chars>| {
+            while chars.next_if(|ch| ch.is_whitespace()).is_some() {}
+        };
+        if chars.next()? != '{' {
+            return None;
+        }
+        let mut items = vec![];
+        skip_whitespace(&mut chars);
+        if chars.next_if_eq(&'}').is_none() {
+            loop {
+                skip_whitespace(&mut chars);
+                let item = if chars.next_if_eq(&'"').is_some() {
+                    let mut item = String::new();
+                    loop {
+                        match chars.next()? {
+                            '"' => break,
+                            '\\' => item.push(chars.next()?),
+                            ch => item.push(ch),
+                        }
+                    }
+                    Some(item)
+                } else {
+                    let mut item = String::new();
+                    while let Some(ch) = chars.next_if(|ch| !matches!(ch, ',' | '}')) {
+                        if matches!(ch, '{' | '"' | '\\') {
+                            return None;
+                        }
+                        item.push(ch);
+                    }
+                    let item = item.trim_end();
+                    if item.is_empty() {
+                        return None;
+                    }
+                    (!item.eq_ignore_ascii_case("null")).then(|| item.to_string())
+                };
+                items.push(match item {
+                    Some(item) => Value::parse(&item, element)?,
+                    None => Value::Null,
+                });
+                skip_whitespace(&mut chars);
+                match chars.next()? {
+                    ',' => {}
+                    '}' => break,
+                    _ => return None,
+                }
+            }
+        }
+        chars.next().is_none().then(|| Array::new(element, items))
+    }
+}
+
+impl fmt::Display for Array {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, item) in self.items.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            if item.is_null() {
+                f.write_str("NULL")?;
+                continue;
+            }
+            let text = item.to_string();
+            let quoted = text.is_empty()
+                || text.eq_ignore_ascii_case("null")
+                || (text.chars()).any(|ch| ch.is_whitespace() || "\"\\,{}".contains(ch));
+            if quoted {
+                f.write_str("\"")?;
+                for ch in text.chars() {
+                    if matches!(ch, '"' | '\\') {
+                        f.write_str("\\")?;
+                    }
+                    write!(f, "{ch}")?;
+                }
+                f.write_str("\"")?;
+            } else {
+                f.write_str(&text)?;
+            }
+        }
+        f.write_str("}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_form() {
+        let texts = Array::new(
+            DataType::Text,
+            vec![
+                "a".into(),
+                Value::Null,
+                "".into(),
+                "null".into(),
+                "x \"y\", {z}\\".into(),
+            ],
+        );
+        let text = texts.to_string();
+        assert_eq!(r#"{a,NULL,"","null","x \"y\", {z}\\"}"#, text);
+        assert_eq!(Some(texts), Array::parse(&text, DataType::Text));
+
+        let ints = Array::parse(" { 1 , -2,NULL } ", DataType::Int).unwrap();
+        assert_eq!(vec![Value::Int(1), Value::Int(-2), Value::Null], ints.items);
+        assert_eq!(Some(&Value::Int(1)), ints.get(1));
+        assert_eq!(None, ints.get(0));
+        assert_eq!(
+            Some(vec![]),
+            Array::parse("{}", DataType::Int).map(|a| a.items)
+        );
+        for invalid in ["", "1,2", "{1,}", "{1,x}", "{{1}}", "{1} 2", "{\"1}"] {
+            assert_eq!(None, Array::parse(invalid, DataType::Int), "{invalid}");
+        }
+
+        let shorter = Array::new(DataType::Int, vec![Value::Int(1)]);
+        assert_eq!(Ordering::Less, shorter.total_cmp(&ints));
+        assert_eq!(
+            Ordering::Greater,
+            shorter.total_cmp(&Array::new(DataType::Int, vec![]))
+        );
+    }
+}
@@ -0,0 +1,247 @@
+//! Password verifiers and SCRAM-SHA-256 authentication.
+//!
+//! Passwords are never stored. A user's catalog entry keeps a
+//! [`Verifier`] derived from the password with PBKDF2 over a random salt,
+//! written the way Postgres writes its own:
+//! `SCRAM-SHA-256$<iterations>:<salt>$<StoredKey>:<ServerKey>`. A
+//! verifier can check a password given in the clear, and lets a client
+//! prove it knows the password without sending it at all; see [`scram`].
+//!
+//! Passwords are hashed as given, without the SASLprep normalization of
+//! RFC 4013, which only matters for passwords that are not ASCII.
+//!
+//! SHA-256, HMAC and PBKDF2 are ring's, and salts and nonces come from
+//! its [`SystemRandom`]; without a random source there are none, and no
+//! verifier or exchange is made.
+
+pub mod scram;
+
+use std::fmt;
+use std::num::NonZeroU32;
+use std::str::FromStr;
+
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{digest, hmac, pbkdf2};
+
+const DIGEST_LEN: usize = digest::SHA256_OUTPUT_LEN;
+
+/// PBKDF2 rounds for new verifiers, the same as Postgres's default.
+pub const DEFAULT_ITERATIONS: u32 = 4096;
+
+const SALT_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Error {
+    #[error("malformed password verifier")]
+    Verifier,
+    #[error("malformed SCRAM message: {0}")]
+    Message(&'static str),
+    #[error("password authentication failed")]
+    Failed,
+    #[error("cannot get random bytes from the system")]
+    Random,
+}
+
+/// What the server keeps to check a password.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Verifier {
+    pub iterations: u32,
+    pub salt: Vec<u8>,
+    pub stored_key: [u8; DIGEST_LEN],
+    pub server_key: [u8; DIGEST_LEN],
+}
+
+impl Verifier {
+    /// A verifier for `password` with a fresh random salt.
+    pub fn new(password: &str) -> Result<Self, Error> {
+        let salt = random_bytes::<SALT_LEN>()?;
+        Ok(Self::with_salt(password, &salt, DEFAULT_ITERATIONS))
+    }
+
+    pub fn with_salt(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let salted = pbkdf2(password.as_bytes(), salt, iterations);
+        let (stored_key, server_key) = keys(&salted);
+        Self {
+            iterations,
+            salt: salt.to_vec(),
+            stored_key,
+            server_key,
+        }
+    }
+
+    /// Whether `password` is the one the verifier was made from.
+    pub fn verify(&self, password: &str) -> bool {
+        let salted = pbkdf2(password.as_bytes(), &self.salt, self.iterations);
+        constant_time_eq(&keys(&salted).0, &self.stored_key)
+    }
+}
+
+/// StoredKey and ServerKey from the salted password.
+fn keys(salted: &[u8]) -> ([u8; DIGEST_LEN], [u8; DIGEST_LEN]) {
+    let client_key = hmac(salted, b"Client Key");
+    (sha256(&client_key), hmac(salted, b"Server Key"))
+}
+
+impl fmt::Display for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "SCRAM-SHA-256${}:{}${}:{}",
+            self.iterations,
+            base64_encode(&self.salt),
+            base64_encode(&self.stored_key),
+            base64_encode(&self.server_key)
+        )
+    }
+}
+
+/// Only the scheme and the iteration count, so that verifiers do not end
+/// up in logs.
+impl fmt::Debug for Verifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SCRAM-SHA-256${}:...", self.iterations)
+    }
+}
+
+impl FromStr for Verifier {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let rest = s.strip_prefix("SCRAM-SHA-256$").ok_or(Error::Verifier)?;
+        let (params, keys) = rest.split_once('$').ok_or(Error::Verifier)?;
+        let (iterations, salt) = params.split_once(':').ok_or(Error::Verifier)?;
+        let (stored_key, server_key) = keys.split_once(':').ok_or(Error::Verifier)?;
+        let key = |s: &str| {
+            base64_decode(s)
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or(Error::Verifier)
+        };
+        Ok(Self {
+            iterations: iterations.parse().map_err(|_| Error::Verifier)?,
+            salt: base64_decode(salt).ok_or(Error::Verifier)?,
+            stored_key: key(stored_key)?,
+            server_key: key(server_key)?,
+        })
+    }
+}
+
+/// Compares without stopping at the first difference, so that the time
+/// taken says nothing about where it is.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Bytes from the system's random source.
+fn random_bytes<const N: usize>() -> Result<[u8; N], Error> {
+    let mut bytes = [0; N];
+    (SystemRandom::new().fill(&mut bytes)).map_err(|_| Error::Random)?;
+    Ok(bytes)
+}
+
+fn sha256(data: &[u8]) -> [u8; DIGEST_LEN] {
+    let digest = digest::digest(&digest::SHA256, data);
+    digest.as_ref().try_into().unwrap()
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; DIGEST_LEN] {
+    let tag = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data);
+    tag.as_ref().try_into().unwrap()
+}
+
+/// PBKDF2-HMAC-SHA-256 to one digest's length, which is all SCRAM uses.
+/// No iterations at all are taken as one, the first being always run.
+fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> [u8; DIGEST_LEN] {
+    let mut out = [0; DIGEST_LEN];
+    let iterations = NonZeroU32::new(iterations.max(1)).unwrap();
+    let algorithm = pbkdf2::PBKDF2_HMAC_SHA256;
+    pbkdf2::derive(algorithm, iterations, salt, password, &mut out);
+    out
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = vec![];
+    for chunk in text.chunks(4) {
+        let padding = chunk.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 {
+            return None;
+        }
+        let mut n = 0u32;
+        for &b in &chunk[..4 - padding] {
+            let digit = BASE64.iter().position(|&d| d == b)? as u32;
+            n = n << 6 | digit;
+        }
+        n <<= 6 * padding;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verifier() {
+        for (bytes, text) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+        ] {
+            assert_eq!(text, base64_encode(bytes));
+            assert_eq!(Some(bytes.to_vec()), base64_decode(text));
+        }
+        assert_eq!(None, base64_decode("Zm9"));
+
+        let verifier = Verifier::new("hunter2").unwrap();
+        assert!(verifier.verify("hunter2"));
+        assert!(!verifier.verify("hunter3"));
+        let text = verifier.to_string();
+        assert!(text.starts_with("SCRAM-SHA-256$4096:"), "{text}");
+        assert_eq!(verifier, text.parse().unwrap());
+        assert_ne!(verifier.salt, Verifier::new("hunter2").unwrap().salt);
+        assert!(!format!("{verifier:?}").contains(&base64_encode(&verifier.salt)));
+        assert_eq!(Err(Error::Verifier), "md5abc".parse::<Verifier>());
+    }
+
+    #[test]
+    fn test_known_answers() {
+        let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{b:02x}")).collect() };
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            hex(&sha256(b"abc"))
+        );
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            hex(&hmac(b"Jefe", b"what do ya want for nothing?"))
+        );
+        assert_eq!(
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a",
+            hex(&pbkdf2(b"password", b"salt", 4096))
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! The server side of SCRAM-SHA-256 (RFC 5802, RFC 7677) as Postgres
+//! speaks it: without channel binding, and with the user named by the
+//! startup message rather than the exchange.
+//!
+//! The client proves it knows the password by a signature over the
+//! messages exchanged, which the server checks against the verifier's
+//! StoredKey; the server proves it holds the verifier by one made with
+//! its ServerKey.
+
+use super::{
+    base64_decode, base64_encode, constant_time_eq, hmac, random_bytes, sha256, Error, Verifier,
+    DEFAULT_ITERATIONS, DIGEST_LEN, SALT_LEN,
+};
+
+/// The SASL mechanism name.
+pub const MECHANISM: &str = "SCRAM-SHA-256";
+
+/// An exchange between its two messages.
+pub struct Exchange {
+    verifier: Verifier,
+    /// Unset for a made-up verifier, which stands in for an unknown user
+    /// so that the exchange looks no different until it fails.
+    known: bool,
+    gs2_header: String,
+    client_first_bare: String,
+    server_first: String,
+    nonce: String,
+}
+
+impl Exchange {
+    /// Answers the client-first message with the server-first one. With
+    /// no `verifier`, the exchange fails at the end as it would for a
+    /// wrong password.
+    pub fn start(verifier: Option<&Verifier>, client_first: &str) -> Result<(Self, String), Error> {
+        let server_nonce = base64_encode(&random_bytes::<18>()?);
+        Self::start_with_nonce(verifier, client_first, &server_nonce)
+    }
+
+    fn start_with_nonce(
+        verifier: Option<&Verifier>,
+        client_first: &str,
+        server_nonce: &str,
+    ) -> Result<(Self, String), Error> {
+        let mut parts = client_first.splitn(3, ',');
+        let (Some(flag), Some(authzid), Some(bare)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(Error::Message("client-first message is incomplete"));
+        };
+        match flag {
+            "n" | "y" => {}
+            _ if flag.starts_with("p=") => {
+                return Err(Error::Message("channel binding is not supported"))
+            }
+            _ => return Err(Error::Message("unknown channel binding flag")),
+        }
+        if !authzid.is_empty() {
+            return Err(Error::Message("authorization identities are not supported"));
+        }
+        if bare.starts_with("m=") {
+            return Err(Error::Message("mandatory extensions are not supported"));
+        }
+        let client_nonce = bare
+            .split(',')
+            .find_map(|attr| attr.strip_prefix("r="))
+            .filter(|nonce| !nonce.is_empty())
+            .ok_or(Error::Message("client-first message has no nonce"))?;
+        let (verifier, known) = match verifier {
+            Some(verifier) => (verifier.clone(), true),
+            None => {
+                let made_up = Verifier {
+                    iterations: DEFAULT_ITERATIONS,
+                    salt: random_bytes::<SALT_LEN>()?.to_vec(),
+                    stored_key: random_bytes()?,
+                    server_key: random_bytes()?,
+                };
+                (made_up, false)
+            }
+        };
+        let nonce = format!("{client_nonce}{server_nonce}");
+        let server_first = format!(
+            "r={nonce},s={},i={}",
+            base64_encode(&verifier.salt),
+            verifier.iterations
+        );
+        let exchange = Self {
+            verifier,
+            known,
+            gs2_header: format!("{flag},{authzid},"),
+            client_first_bare: bare.to_string(),
+            server_first: server_first.clone(),
+            nonce,
+        };
+        Ok((exchange, server_first))
+    }
+
+    /// Checks the proof in the client-final message and answers with the
+    /// server-final one, or fails with [`Error::Failed`].
+    pub fn finish(self, client_final: &str) -> Result<String, Error> {
+        let (without_proof, proof) = client_final
+            .rsplit_once(",p=")
+            .ok_or(Error::Message("client-final message has no proof"))?;
+        let attr = |name: &str| {
+            without_proof
+                .split(',')
+                .find_map(|attr| attr.strip_prefix(name))
+        };
+        if attr("c=") != Some(&base64_encode(self.gs2_header.as_bytes())) {
+            return Err(Error::Message("channel binding does not match"));
+        }
+        if attr("r=") != Some(&self.nonce) {
+            return Err(Error::Message("nonce does not match"));
+        }
+        let proof = base64_decode(proof)
+            .filter(|proof| proof.len() == DIGEST_LEN)
+            .ok_or(Error::Message("malformed proof"))?;
+        let auth_message = format!(
+            "{},{},{without_proof}",
+            self.client_first_bare, self.server_first
+        );
+        let signature = hmac(&self.verifier.stored_key, auth_message.as_bytes());
+        let client_key: Vec<u8> = proof.iter().zip(signature).map(|(p, s)| p ^ s).collect();
+        if !constant_time_eq(&sha256(&client_key), &self.verifier.stored_key) || !self.known {
+            return Err(Error::Failed);
+        }
+        let server_signature = hmac(&self.verifier.server_key, auth_message.as_bytes());
+        Ok(format!("v={}", base64_encode(&server_signature)))
+    }
+}
+
+/// The client-final message a client knowing `password` would send.
+#[cfg(test)]
+pub(crate) fn client_final(password: &str, client_first: &str, server_first: &str) -> String {
+    use super::pbkdf2;
+
+    let attr = |name: &str| {
+        server_first
+            .split(',')
+            .find_map(|attr| attr.strip_prefix(name))
+            .unwrap()
+    };
+    let salt = base64_decode(attr("s=")).unwrap();
+    let salted = pbkdf2(password.as_bytes(), &salt, attr("i=").parse().unwrap());
+    let client_key = hmac(&salted, b"Client Key");
+    let without_proof = format!("c=biws,r={}", attr("r="));
+    let bare = client_first.strip_prefix("n,,").unwrap();
+    let auth_message = format!("{bare},{server_first},{without_proof}");
+    let signature = hmac(&sha256(&client_key), auth_message.as_bytes());
+    let proof: Vec<u8> = client_key
+        .iter()
+        .zip(signature)
+        .map(|(k, s)| k ^ s)
+        .collect();
+    format!("{without_proof},p={}", base64_encode(&proof))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exchange() {
+        // The example of RFC 7677.
+        let salt = base64_decode("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+        let verifier = Verifier::with_salt("pencil", &salt, 4096);
+        let client_first = "n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+        let (exchange, server_first) = Exchange::start_with_nonce(
+            Some(&verifier),
+            client_first,
+            "%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0",
+        )
+        .unwrap();
+        assert_eq!(
+            "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+             s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096",
+            server_first
+        );
+        let client_final = client_final("pencil", client_first, &server_first);
+        assert_eq!(
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+             p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+            client_final
+        );
+        assert_eq!(
+            Ok("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=".to_string()),
+            exchange.finish(&client_final)
+        );
+
+        let (exchange, server_first) = Exchange::start(Some(&verifier), client_first).unwrap();
+        let wrong = super::client_final("pen", client_first, &server_first);
+        assert_eq!(Err(Error::Failed), exchange.finish(&wrong));
+        let (exchange, server_first) = Exchange::start(None, client_first).unwrap();
+        let unknown = super::client_final("pencil", client_first, &server_first);
+        assert_eq!(Err(Error::Failed), exchange.finish(&unknown));
+        assert!(Exchange::start(None, "p=tls-server-end-point,,n=,r=x").is_err());
+    }
+}
@@ -0,0 +1,490 @@
+//! Copying a database file while it is in use, and putting a copy back.
+//!
+//! [`backup`] writes every page of a database to a new file, taking the
+//! ones the buffer pool holds from the pool, so the copy includes changes
+//! not yet written back. It runs between statements, which keeps the file
+//! from changing under it, so the copy is consistent without a log of the
+//! writes made while it was taken. It refuses to run inside a
+//! transaction, whose changes are still in the pool and may be rolled
+//! back. The copy is marked as shut down cleanly and opens without a
+//! check. [`snapshot`] makes the same copy in memory, without the
+//! [checksums](crate::disk::Layout) that follow each page in a file.
+//!
+//! A [`SnapshotFile`] writes the same copy a batch of pages at a time,
+//! with statements running in between. It sees the pages as they were
+//! when it began through a [`Frozen`](crate::buffer::Frozen) view of the
+//! pool, which keeps a page as it was when it is first written after
+//! that, until the copy has taken it. Writes then cost a page copy at most
+//! each while the snapshot runs, and the file it makes is of that moment.
+//!
+//! [`backup_incremental`] copies only the pages that changed since the
+//! backups it is given were taken. Pages carry no log sequence number to
+//! tell, so it reads the earlier backups and compares each page with
+//! theirs. An incremental backup is a header holding the page count,
+//! then each changed page after its page id.
+//!
+//! [`restore`] and [`restore_chain`] check a backup, or a full backup and
+//! the incremental ones after it applied in order, and put the result in
+//! place of a database file that is not open, replacing it all at once.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::buffer::{self, BufferPoolManager, Frozen, Page};
+use crate::catalog::CATALOG_PAGE_ID;
+use crate::check::{self, Report};
+use crate::database::{CLEAN_MARK, CLEAN_MARK_RANGE};
+use crate::disk::{self, DiskManager, Layout, PageId, PAGE_SIZE};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Check(#[from] check::Error),
+    #[error("cannot back up inside a transaction")]
+    InTransaction,
+    #[error("the backup is damaged:\n{0}")]
+    Damaged(Box<Report>),
+    #[error("a backup chain needs a full backup")]
+    EmptyChain,
+    #[error("{} is an incremental backup, not a full one", .0.display())]
+    NotFull(PathBuf),
+    #[error("{} is not an incremental backup", .0.display())]
+    NotIncrement(PathBuf),
+}
+
+/// The start of an incremental backup, which a database file never has
+/// since it starts with the catalog's meta page.
+const INCREMENT_MAGIC: [u8; 8] = *b"N7INCR\0\0";
+
+/// Copies the database to a new file at `path`, which must not exist,
+/// and syncs it. A copy cut short is removed.
+pub fn backup(bufmgr: &BufferPoolManager, path: impl AsRef<Path>) -> Result<(), Error> {
+    if bufmgr.in_transaction() {
+        return Err(Error::InTransaction);
+    }
+    let path = path.as_ref();
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let result = write_pages(bufmgr, file);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// The pages [`backup`] would write, as a file image in memory.
+pub fn snapshot(bufmgr: &BufferPoolManager) -> Result<Vec<u8>, Error> {
+    if bufmgr.in_transaction() {
+        return Err(Error::InTransaction);
+    }
+    let mut image = Vec::with_capacity(bufmgr.num_pages() as usize * PAGE_SIZE);
+    copy_pages(bufmgr, &mut image, Layout::Plain)?;
+    Ok(image)
+}
+
+/// A copy of the database as it was when it was created, being written to
+/// a new file a batch of pages at a time. One dropped before it is
+/// finished removes its file.
+pub struct SnapshotFile {
+    path: PathBuf,
+    /// `None` once finished.
+    out: Option<BufWriter<File>>,
+    frozen: Arc<Frozen>,
+    /// Pages written so far.
+    pages: u64,
+}
+
+impl SnapshotFile {
+    /// Starts a copy at `path`, which must not exist.
+    pub fn create(bufmgr: &BufferPoolManager, path: impl AsRef<Path>) -> Result<Self, Error> {
+        if bufmgr.in_transaction() {
+            return Err(Error::InTransaction);
+        }
+        let path = path.as_ref().to_path_buf();
+        let frozen = bufmgr.freeze()?;
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            path,
+            out: Some(BufWriter::new(file)),
+            frozen,
+            pages: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Copies up to `limit` more pages, and returns how many it copied;
+    /// fewer than `limit` means the copy is complete and synced.
+    pub fn copy(&mut self, bufmgr: &BufferPoolManager, limit: u64) -> Result<u64, Error> {
+        let mut copied = 0;
+        while copied < limit {
+            let Some(out) = &mut self.out else {
+                break;
+            };
+            let Some(mut data) = self.frozen.take(bufmgr)? else {
+                let out = self.out.take().unwrap();
+                out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+                break;
+            };
+            if self.pages == CATALOG_PAGE_ID.to_u64() {
+                data[CLEAN_MARK_RANGE].copy_from_slice(&CLEAN_MARK);
+            }
+            out.write_all(&Layout::Checksummed.encode(PageId(self.pages), &data[..]))?;
+            self.pages += 1;
+            copied += 1;
+        }
+        Ok(copied)
+    }
+}
+
+impl Drop for SnapshotFile {
+    fn drop(&mut self) {
+        if self.out.take().is_some() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn write_pages(bufmgr: &BufferPoolManager, file: File) -> Result<(), Error> {
+    let mut out = BufWriter::new(file);
+    copy_pages(bufmgr, &mut out, Layout::Checksummed)?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+fn copy_pages(
+    bufmgr: &BufferPoolManager,
+    out: &mut impl Write,
+    layout: Layout,
+) -> Result<(), Error> {
+    for page_id in (0..bufmgr.num_pages()).map(PageId) {
+        let mut data = *bufmgr.fetch_page(page_id)?.read();
+        if page_id == CATALOG_PAGE_ID {
+            data[CLEAN_MARK_RANGE].copy_from_slice(&CLEAN_MARK);
+        }
+        out.write_all(&layout.encode(page_id, &data))?;
+    }
+    Ok(())
+}
+
+/// Copies only the pages that differ from the state `chain` restores
+/// to, to a new file at `path`; see [`restore_chain`]. Returns how many
+/// pages it copied.
+pub fn backup_incremental<P: AsRef<Path>>(
+    bufmgr: &BufferPoolManager,
+    chain: &[P],
+    path: impl AsRef<Path>,
+) -> Result<u64, Error> {
+    if bufmgr.in_transaction() {
+        return Err(Error::InTransaction);
+    }
+    let mut previous = Image::open(chain)?;
+    let path = path.as_ref();
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let result = write_changed_pages(bufmgr, &mut previous, file);
+    if result.is_err() {
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+fn write_changed_pages(
+    bufmgr: &BufferPoolManager,
+    previous: &mut Image,
+    file: File,
+) -> Result<u64, Error> {
+    let mut out = BufWriter::new(file);
+    let num_pages = bufmgr.num_pages();
+    out.write_all(&INCREMENT_MAGIC)?;
+    out.write_all(&num_pages.to_le_bytes())?;
+    let mut copied = 0;
+    for page_id in (0..num_pages).map(PageId) {
+        let mut data = *bufmgr.fetch_page(page_id)?.read();
+        if page_id == CATALOG_PAGE_ID {
+            data[CLEAN_MARK_RANGE].copy_from_slice(&CLEAN_MARK);
+        }
+        if previous.page(page_id)?.as_ref() != Some(&data) {
+            out.write_all(&page_id.to_bytes())?;
+            out.write_all(&data)?;
+            copied += 1;
+        }
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(copied)
+}
+
+/// Replaces the database file at `path`, or creates it, with the backup
+/// at `backup` once [`check::check_file`] finds nothing wrong with it.
+/// Fails if the database is open for writing.
+pub fn restore(backup: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<(), Error> {
+    restore_chain(&[backup], path)
+}
+
+/// As [`restore`], for a full backup followed by the incremental ones
+/// taken after it, in the order they were taken.
+pub fn restore_chain<P: AsRef<Path>>(chain: &[P], path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    // Held until the copy is in place, so nothing opens the file meanwhile.
+    let _lock = DiskManager::open(path)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let copy = tempfile::NamedTempFile::new_in(dir)?;
+    let (base, increments) = chain.split_first().ok_or(Error::EmptyChain)?;
+    if is_increment(base.as_ref())? {
+        return Err(Error::NotFull(base.as_ref().to_path_buf()));
+    }
+    fs::copy(base, copy.path())?;
+    let mut file = copy.reopen()?;
+    let layout = Layout::of(&mut file)?;
+    for increment in increments {
+        let Increment { num_pages, pages } = read_increment(increment.as_ref())?;
+        for (page_id, data) in pages {
+            layout.write_page(&mut file, page_id, &data[..])?;
+        }
+        file.set_len(layout.slot_size() as u64 * num_pages)?;
+    }
+    file.sync_all()?;
+    let report = check::check_file(copy.path())?;
+    if !report.is_ok() {
+        return Err(Error::Damaged(Box::new(report)));
+    }
+    // Pages a crash left to the old file are not the copy's to replay.
+    for beside in [disk::journal_path(path), disk::doublewrite_path(path)] {
+        match fs::remove_file(beside) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+    }
+    copy.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+fn is_increment(path: &Path) -> io::Result<bool> {
+    let mut magic = [0; 8];
+    match File::open(path)?.read_exact(&mut magic) {
+        Ok(()) => Ok(magic == INCREMENT_MAGIC),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// An incremental backup: the page count and the pages that changed.
+struct Increment {
+    num_pages: u64,
+    pages: Vec<(PageId, Box<Page>)>,
+}
+
+fn read_increment(path: &Path) -> Result<Increment, Error> {
+    let not_increment = || Error::NotIncrement(path.to_path_buf());
+    let data = fs::read(path)?;
+    let (header, mut records) = data.split_at_checked(16).ok_or_else(not_increment)?;
+    if header[..8] != INCREMENT_MAGIC {
+        return Err(not_increment());
+    }
+    let num_pages = u64::from_le_bytes(header[8..].try_into().unwrap());
+    let mut pages = vec![];
+    while !records.is_empty() {
+        let (record, rest) = records
+            .split_at_checked(8 + PAGE_SIZE)
+            .ok_or_else(not_increment)?;
+        let page_id = PageId::from_bytes(&record[..8]);
+        pages.push((page_id, Box::new(record[8..].try_into().unwrap())));
+        records = rest;
+    }
+    Ok(Increment { num_pages, pages })
+}
+
+/// The pages a backup chain restores to, read from the full backup as
+/// needed and held in memory where increments replace them.
+struct Image {
+    base: File,
+    layout: Layout,
+    base_pages: u64,
+    num_pages: u64,
+    pages: HashMap<PageId, Box<Page>>,
+}
+
+impl Image {
+    fn open<P: AsRef<Path>>(chain: &[P]) -> Result<Self, Error> {
+        let (base, increments) = chain.split_first().ok_or(Error::EmptyChain)?;
+        if is_increment(base.as_ref())? {
+            return Err(Error::NotFull(base.as_ref().to_path_buf()));
+        }
+        let mut base = File::open(base)?;
+        let layout = Layout::of(&mut base)?;
+        let base_pages = base.metadata()?.len() / layout.slot_size() as u64;
+        let mut image = Self {
+            base,
+            layout,
+            base_pages,
+            num_pages: base_pages,
+            pages: HashMap::new(),
+        };
+        for increment in increments {
+            let Increment { num_pages, pages } = read_increment(increment.as_ref())?;
+            image.num_pages = num_pages;
+            image.base_pages = image.base_pages.min(num_pages);
+            image
+                .pages
+                .retain(|page_id, _| page_id.to_u64() < num_pages);
+            image.pages.extend(pages);
+        }
+        Ok(image)
+    }
+
+    fn page(&mut self, page_id: PageId) -> io::Result<Option<Page>> {
+        if page_id.to_u64() >= self.num_pages {
+            return Ok(None);
+        }
+        if let Some(data) = self.pages.get(&page_id) {
+            return Ok(Some(**data));
+        }
+        if page_id.to_u64() >= self.base_pages {
+            return Ok(None);
+        }
+        let mut data = [0; PAGE_SIZE];
+        (self.layout).read_page(&mut self.base, page_id, &mut data)?;
+        Ok(Some(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, Options};
+
+    #[test]
+    fn test_backup_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let copy = dir.path().join("copy");
+        let count = |db: &mut Database| {
+            let rows = db.query("SELECT count(*) FROM t").unwrap();
+            rows.get(0).unwrap().get::<i64>(0).unwrap()
+        };
+
+        let mut db = Database::open(&path, Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY)").unwrap();
+        db.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+        db.engine_mut().begin().unwrap();
+        assert!(matches!(
+            db.backup_to(&copy),
+            Err(crate::database::Error::Backup(Error::InTransaction))
+        ));
+        db.engine_mut().rollback().unwrap();
+        let sql = format!("BACKUP TO '{}'", copy.display());
+        db.execute(&sql).unwrap();
+        assert!(db.execute(&sql).is_err(), "overwrote a file");
+        db.execute("INSERT INTO t VALUES (3)").unwrap();
+        assert!(matches!(
+            Database::restore(&copy, &path),
+            Err(crate::database::Error::Backup(Error::Io(_)))
+        ));
+        db.close().unwrap();
+
+        Database::restore(&copy, &path).unwrap();
+        let mut db = Database::open(&path, Options::default()).unwrap();
+        assert!(db.was_clean());
+        assert_eq!(2, count(&mut db));
+    }
+
+    #[test]
+    fn test_incremental_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let chain: Vec<_> = (0..3).map(|i| dir.path().join(i.to_string())).collect();
+        let mut db = Database::open(&path, Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        for i in 0..500 {
+            db.execute(&format!("INSERT INTO t VALUES ({i}, 'row {i}')"))
+                .unwrap();
+        }
+        db.backup_to(&chain[0]).unwrap();
+        db.execute("UPDATE t SET name = 'changed' WHERE id = 7")
+            .unwrap();
+        let copied = db.backup_incremental(&chain[..1], &chain[1]).unwrap();
+        assert!(copied > 0 && copied < db.engine().bufmgr().num_pages());
+        db.execute("DELETE FROM t WHERE id < 100").unwrap();
+        db.backup_incremental(&chain[..2], &chain[2]).unwrap();
+        assert_eq!(
+            0,
+            db.backup_incremental(&chain, dir.path().join("3")).unwrap()
+        );
+        db.close().unwrap();
+
+        let restored = dir.path().join("restored");
+        assert!(matches!(
+            Database::restore_chain(&chain[1..], &restored),
+            Err(crate::database::Error::Backup(Error::NotFull(_)))
+        ));
+        Database::restore_chain(&chain[..2], &restored).unwrap();
+        let mut db = Database::open(&restored, Options::default()).unwrap();
+        let rows = db
+            .query("SELECT count(*) FROM t WHERE name = 'changed'")
+            .unwrap();
+        assert_eq!(1, rows.get(0).unwrap().get::<i64>(0).unwrap());
+        db.close().unwrap();
+        Database::restore_chain(&chain, &restored).unwrap();
+        let mut db = Database::open(&restored, Options::default()).unwrap();
+        let rows = db.query("SELECT count(*) FROM t").unwrap();
+        assert_eq!(400, rows.get(0).unwrap().get::<i64>(0).unwrap());
+    }
+
+    #[test]
+    fn test_snapshot_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = dir.path().join("fixture");
+        let later = dir.path().join("later");
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        for i in 0..300 {
+            db.execute(&format!("INSERT INTO t VALUES ({i}, 'row {i}')"))
+                .unwrap();
+        }
+        db.start_snapshot_to(&fixture).unwrap();
+        assert_eq!(2, db.copy_snapshots(2).unwrap());
+        // Pages taken already, pages still to take and new pages.
+        db.execute("UPDATE t SET name = 'changed'").unwrap();
+        db.execute("DELETE FROM t WHERE id >= 200").unwrap();
+        db.execute("CREATE TABLE u (id INT)").unwrap();
+        db.execute("BEGIN").unwrap();
+        db.execute("DELETE FROM t WHERE id < 10").unwrap();
+        assert!(db.copy_snapshots(u64::MAX).unwrap() > 0);
+        db.execute("ROLLBACK").unwrap();
+        db.snapshot_to(&later).unwrap();
+        assert!(db.snapshot_to(&later).is_err(), "overwrote a file");
+
+        let mut copy = Database::open(&fixture, Options::default()).unwrap();
+        assert!(copy.was_clean());
+        let rows = copy
+            .query("SELECT count(*) FROM t WHERE name LIKE 'row %'")
+            .unwrap();
+        assert_eq!(300, rows.get(0).unwrap().get::<i64>(0).unwrap());
+        assert!(copy.query("SELECT * FROM u").is_err());
+        copy.close().unwrap();
+        let mut copy = Database::open(&later, Options::default()).unwrap();
+        let rows = copy
+            .query("SELECT count(*) FROM t WHERE name = 'changed'")
+            .unwrap();
+        assert_eq!(200, rows.get(0).unwrap().get::<i64>(0).unwrap());
+
+        db.start_snapshot_to(dir.path().join("abandoned")).unwrap();
+        drop(db);
+        assert!(!dir.path().join("abandoned").exists());
+    }
+}
@@ -0,0 +1,476 @@
+//! Benchmark workloads, run through the engine's public API.
+//!
+//! Each run creates a table `bench (id INT PRIMARY KEY, value TEXT)`,
+//! loads and analyzes it with [`BenchConfig::records`] rows unless the
+//! workload is the load itself, and then times [`BenchConfig::operations`] prepared
+//! statements one by one. The [`Report`] gives the throughput and, for
+//! each kind of operation, latency percentiles, so that two builds can be
+//! compared on the same configuration and seed.
+//!
+//! The mixed workload follows YCSB: reads and updates of keys that are
+//! picked from a scrambled Zipfian distribution unless told otherwise.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use crate::buffer::BufferPoolManager;
+use crate::disk::DiskManager;
+use crate::engine::{self, Engine, PreparedStatement};
+use crate::value::Value;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Engine(#[from] engine::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Workload {
+    /// Inserts with ascending keys into an empty table.
+    SequentialInsert,
+    /// Lookups of single keys.
+    PointRead,
+    /// Reads of [`BenchConfig::scan_length`] consecutive keys.
+    RangeScan,
+    /// Point reads, and updates of single keys for the rest.
+    Mixed { read_percent: u8 },
+}
+
+impl FromStr for Workload {
+    type Err = String;
+
+    /// `insert`, `read`, `scan`, or `mixed` with an optional read
+    /// percentage, as in `mixed:95`; plain `mixed` is YCSB's workload A.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "insert" => Ok(Workload::SequentialInsert),
+            None if s == "read" => Ok(Workload::PointRead),
+            None if s == "scan" => Ok(Workload::RangeScan),
+            None if s == "mixed" => Ok(Workload::Mixed { read_percent: 50 }),
+            Some(("mixed", percent)) => match percent.parse() {
+                Ok(read_percent @ 0..=100) => Ok(Workload::Mixed { read_percent }),
+                _ => Err(format!("bad read percentage {percent:?}")),
+            },
+            _ => Err(format!(
+                "unknown workload {s:?}; use insert, read, scan or mixed[:PERCENT]"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Workload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Workload::SequentialInsert => f.write_str("insert"),
+            Workload::PointRead => f.write_str("read"),
+            Workload::RangeScan => f.write_str("scan"),
+            Workload::Mixed { read_percent } => write!(f, "mixed:{read_percent}"),
+        }
+    }
+}
+
+/// How keys are picked for reads, scans and updates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    /// A few keys are hot, spread over the key space as YCSB does.
+    Zipfian,
+}
+
+impl FromStr for Distribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(Distribution::Uniform),
+            "zipfian" => Ok(Distribution::Zipfian),
+            _ => Err(format!(
+                "unknown distribution {s:?}; use uniform or zipfian"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchConfig {
+    pub workload: Workload,
+    /// Rows loaded before the timed operations.
+    pub records: u64,
+    pub operations: u64,
+    pub scan_length: u64,
+    /// Length of the text in each row.
+    pub value_size: usize,
+    pub distribution: Distribution,
+    pub pool_size: usize,
+    pub seed: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            workload: Workload::PointRead,
+            records: 10_000,
+            operations: 10_000,
+            scan_length: 100,
+            value_size: 100,
+            distribution: Distribution::Zipfian,
+            pool_size: 1024,
+            seed: 42,
+        }
+    }
+}
+
+/// Latencies of one kind of operation.
+#[derive(Debug, Clone)]
+pub struct OpStats {
+    pub name: &'static str,
+    /// Sorted.
+    latencies: Vec<Duration>,
+}
+
+impl OpStats {
+    fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            latencies: vec![],
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn mean(&self) -> Duration {
+        let total: Duration = self.latencies.iter().sum();
+        total / self.count().max(1) as u32
+    }
+
+    /// The latency `percent` of the operations were no slower than, by
+    /// nearest rank.
+    pub fn percentile(&self, percent: f64) -> Duration {
+        let Some(&max) = self.latencies.last() else {
+            return Duration::ZERO;
+        };
+        let rank = (percent / 100.0 * self.count() as f64).ceil() as usize;
+        self.latencies
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or(max)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub config: BenchConfig,
+    /// Time spent on the timed operations, without the load.
+    pub elapsed: Duration,
+    pub ops: Vec<OpStats>,
+}
+
+impl Report {
+    pub fn operations(&self) -> usize {
+        self.ops.iter().map(OpStats::count).sum()
+    }
+
+    /// Operations per second.
+    pub fn throughput(&self) -> f64 {
+        self.operations() as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+fn format_duration(d: Duration) -> String {
+    let micros = d.as_secs_f64() * 1e6;
+    if micros < 1e3 {
+        format!("{micros:.1}us")
+    } else if micros < 1e6 {
+        format!("{:.2}ms", micros / 1e3)
+    } else {
+        format!("{:.2}s", micros / 1e6)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let config = &self.config;
+        write!(f, "workload {}: ", config.workload)?;
+        if config.workload != Workload::SequentialInsert {
+            write!(f, "{} records, ", config.records)?;
+        }
+        writeln!(
+            f,
+            "{} operations in {} ({:.0} ops/s)",
+            self.operations(),
+            format_duration(self.elapsed),
+            self.throughput()
+        )?;
+        writeln!(
+            f,
+            "{:<8}{:>10}{:>10}{:>10}{:>10}{:>10}{:>10}",
+            "op", "count", "mean", "p50", "p95", "p99", "max"
+        )?;
+        for op in &self.ops {
+            write!(f, "{:<8}{:>10}", op.name, op.count())?;
+            for latency in [
+                op.mean(),
+                op.percentile(50.0),
+                op.percentile(95.0),
+                op.percentile(99.0),
+                op.percentile(100.0),
+            ] {
+                write!(f, "{:>10}", format_duration(latency))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// xorshift64*: fast, and the same on every platform for a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must not be zero.
+        Self(seed ^ 0x9e37_79b9_7f4a_7c15 | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n.max(1)
+    }
+
+    fn text(&mut self, len: usize) -> String {
+        (0..len)
+            .map(|_| (b'a' + self.below(26) as u8) as char)
+            .collect()
+    }
+}
+
+/// Zipfian ranks over `[0, n)` by the method of Gray et al., which YCSB
+/// uses, with its skew of 0.99.
+struct Zipfian {
+    n: u64,
+    theta: f64,
+    alpha: f64,
+    zetan: f64,
+    eta: f64,
+}
+
+impl Zipfian {
+    const THETA: f64 = 0.99;
+
+    fn new(n: u64) -> Self {
+        let theta = Self::THETA;
+        let zeta = |n: u64| (1..=n).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+        let zetan = zeta(n);
+        let zeta2 = zeta(2.min(n));
+        Self {
+            n,
+            theta,
+            alpha: 1.0 / (1.0 - theta),
+            zetan,
+            eta: (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta2 / zetan),
+        }
+    }
+
+    fn next(&self, rng: &mut Rng) -> u64 {
+        let u = rng.next_f64();
+        let uz = u * self.zetan;
+        if uz < 1.0 {
+            return 0;
+        }
+        if uz < 1.0 + 0.5f64.powf(self.theta) {
+            return 1.min(self.n - 1);
+        }
+        let rank = self.n as f64 * (self.eta * u - self.eta + 1.0).powf(self.alpha);
+        (rank as u64).min(self.n - 1)
+    }
+}
+
+struct KeyChooser {
+    records: u64,
+    zipfian: Option<Zipfian>,
+}
+
+impl KeyChooser {
+    fn new(distribution: Distribution, records: u64) -> Self {
+        let zipfian =
+            (distribution == Distribution::Zipfian && records > 0).then(|| Zipfian::new(records));
+        Self { records, zipfian }
+    }
+
+    fn next(&self, rng: &mut Rng) -> i64 {
+        let key = match &self.zipfian {
+            // FNV-1a scatters the hot ranks over the key space.
+            Some(zipfian) => {
+                let rank = zipfian.next(rng);
+                let hash = rank
+                    .to_le_bytes()
+                    .iter()
+                    .fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+                        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+                    });
+                hash % self.records
+            }
+            None => rng.below(self.records),
+        };
+        key as i64
+    }
+}
+
+/// Runs the workload on a new database in a temporary file.
+pub fn run(config: &BenchConfig) -> Result<Report, Error> {
+    let disk = DiskManager::new(tempfile::tempfile()?)?;
+    let mut engine = Engine::open(BufferPoolManager::new(disk, config.pool_size))?;
+    run_on(&mut engine, config)
+}
+
+/// Runs the workload on `engine`, which must not have a table `bench`.
+pub fn run_on(engine: &mut Engine, config: &BenchConfig) -> Result<Report, Error> {
+    let mut rng = Rng::new(config.seed);
+    engine.execute("CREATE TABLE bench (id INT PRIMARY KEY, value TEXT)")?;
+    let insert = engine.prepare("INSERT INTO bench VALUES ($1, $2)")?;
+    let mut run = |statement: &PreparedStatement, params: &[Value]| -> Result<Duration, Error> {
+        let start = Instant::now();
+        engine.execute_prepared(statement, params)?;
+        Ok(start.elapsed())
+    };
+
+    if config.workload == Workload::SequentialInsert {
+        let mut stats = OpStats::new("insert");
+        let start = Instant::now();
+        for id in 0..config.operations as i64 {
+            let params = [Value::Int(id), Value::Text(rng.text(config.value_size))];
+            stats.latencies.push(run(&insert, &params)?);
+        }
+        return Ok(report(config, start.elapsed(), vec![stats]));
+    }
+    for id in 0..config.records as i64 {
+        run(
+            &insert,
+            &[Value::Int(id), Value::Text(rng.text(config.value_size))],
+        )?;
+    }
+    // Without statistics the planner takes the table to be small.
+    engine.execute("ANALYZE bench")?;
+
+    let keys = KeyChooser::new(config.distribution, config.records);
+    let read = engine.prepare("SELECT value FROM bench WHERE id = $1")?;
+    let scan = engine.prepare("SELECT id, value FROM bench WHERE id >= $1 AND id < $2")?;
+    let update = engine.prepare("UPDATE bench SET value = $2 WHERE id = $1")?;
+    let mut reads = OpStats::new("read");
+    let mut scans = OpStats::new("scan");
+    let mut updates = OpStats::new("update");
+    let mut run = |statement: &PreparedStatement, params: &[Value]| -> Result<Duration, Error> {
+        let start = Instant::now();
+        engine.execute_prepared(statement, params)?;
+        Ok(start.elapsed())
+    };
+    let start = Instant::now();
+    for _ in 0..config.operations {
+        let key = keys.next(&mut rng);
+        match config.workload {
+            Workload::SequentialInsert => unreachable!(),
+            Workload::PointRead => reads.latencies.push(run(&read, &[Value::Int(key)])?),
+            Workload::RangeScan => {
+                let end = key + config.scan_length as i64;
+                scans
+                    .latencies
+                    .push(run(&scan, &[Value::Int(key), Value::Int(end)])?);
+            }
+            Workload::Mixed { read_percent } => {
+                if rng.below(100) < read_percent as u64 {
+                    reads.latencies.push(run(&read, &[Value::Int(key)])?);
+                } else {
+                    let value = Value::Text(rng.text(config.value_size));
+                    updates
+                        .latencies
+                        .push(run(&update, &[Value::Int(key), value])?);
+                }
+            }
+        }
+    }
+    let ops = [reads, scans, updates]
+        .into_iter()
+        .filter(|op| op.count() > 0)
+        .collect();
+    Ok(report(config, start.elapsed(), ops))
+}
+
+fn report(config: &BenchConfig, elapsed: Duration, mut ops: Vec<OpStats>) -> Report {
+    for op in &mut ops {
+        op.latencies.sort_unstable();
+    }
+    Report {
+        config: config.clone(),
+        elapsed,
+        ops,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run() {
+        let config = |workload: &str| BenchConfig {
+            workload: workload.parse().unwrap(),
+            records: 200,
+            operations: 100,
+            scan_length: 10,
+            value_size: 8,
+            pool_size: 64,
+            ..BenchConfig::default()
+        };
+        let report = run(&config("insert")).unwrap();
+        assert_eq!(100, report.operations());
+        assert_eq!("insert", report.ops[0].name);
+        let report = run(&config("mixed:70")).unwrap();
+        let names: Vec<_> = report.ops.iter().map(|op| op.name).collect();
+        assert_eq!(vec!["read", "update"], names);
+        assert_eq!(100, report.operations());
+        let text = report.to_string();
+        assert!(text.starts_with("workload mixed:70: 200 records, 100 operations in "));
+        assert!(text.contains("\nop           count      mean"));
+        let report = run(&config("scan")).unwrap();
+        assert_eq!(100, report.ops[0].count());
+        assert!(report.ops[0].percentile(50.0) <= report.ops[0].percentile(99.0));
+        assert!("mixed:101".parse::<Workload>().is_err());
+    }
+
+    #[test]
+    fn test_keys_and_percentiles() {
+        let mut rng = Rng::new(7);
+        let keys = KeyChooser::new(Distribution::Zipfian, 1000);
+        let mut counts = vec![0; 1000];
+        for _ in 0..10_000 {
+            counts[keys.next(&mut rng) as usize] += 1;
+        }
+        // The hottest key gets far more than its share of 10.
+        assert!(counts.iter().max().unwrap() > &500);
+        let uniform = KeyChooser::new(Distribution::Uniform, 10);
+        assert!((0..100).all(|_| uniform.next(&mut rng) < 10));
+
+        let mut op = OpStats::new("read");
+        op.latencies = (1..=100).map(Duration::from_micros).collect();
+        assert_eq!(Duration::from_micros(50), op.percentile(50.0));
+        assert_eq!(Duration::from_micros(99), op.percentile(99.0));
+        assert_eq!(Duration::from_micros(100), op.percentile(100.0));
+        assert_eq!(Duration::from_nanos(50_500), op.mean());
+    }
+}
@@ -0,0 +1,71 @@
+//! Runs a benchmark workload and prints its throughput and latencies.
+//!
+//! See [`neru7db::bench`] for the workloads. Runs with the same options
+//! and seed do the same operations, so their reports can be compared.
+
+use std::env;
+use std::process::ExitCode;
+
+use neru7db::bench::{self, BenchConfig};
+
+const USAGE: &str = "\
+usage: neru7db-bench [OPTIONS]
+  -w, --workload NAME       insert, read, scan or mixed[:READ_PERCENT] (read)
+  -r, --records N           rows loaded before timing (10000)
+  -n, --operations N        operations timed (10000)
+      --scan-length N       keys each scan reads (100)
+      --value-size N        bytes of text in each row (100)
+  -d, --distribution NAME   uniform or zipfian (zipfian)
+      --pool-size N         pages in the buffer pool (1024)
+      --seed N              seed for keys and values (42)
+  -h, --help                show this help";
+
+/// Reads the command line; `Ok(None)` asks for the usage.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<BenchConfig>, String> {
+    let mut config = BenchConfig::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        let value = || value.ok_or(format!("{arg} needs a value"));
+        fn number<T: std::str::FromStr>(value: String) -> Result<T, String> {
+            value.parse().map_err(|_| format!("bad number {value:?}"))
+        }
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-w" | "--workload" => config.workload = value()?.parse()?,
+            "-r" | "--records" => config.records = number(value()?)?,
+            "-n" | "--operations" => config.operations = number(value()?)?,
+            "--scan-length" => config.scan_length = number(value()?)?,
+            "--value-size" => config.value_size = number(value()?)?,
+            "-d" | "--distribution" => config.distribution = value()?.parse()?,
+            "--pool-size" => config.pool_size = number(value()?)?,
+            "--seed" => config.seed = number(value()?)?,
+            _ => return Err(format!("unknown option {arg}")),
+        }
+    }
+    Ok(Some(config))
+}
+
+fn main() -> ExitCode {
+    let config = match parse_args(env::args().skip(1)) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("neru7db-bench: {e}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match bench::run(&config) {
+        Ok(report) => {
+            print!("{report}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("neru7db-bench: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
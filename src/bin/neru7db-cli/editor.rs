@@ -0,0 +1,368 @@
+//! A small line editor with history.
+//!
+//! On a terminal the editor switches it to raw mode with `stty` while a
+//! line is read and handles the usual keys itself: arrows to move and to
+//! step through the history, Home/End, Backspace/Delete, Ctrl-A/E/U/K,
+//! Ctrl-C to drop the line and Ctrl-D to leave. Elsewhere, or if `stty`
+//! is missing, lines are read as they come.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Entries kept in the history file.
+const HISTORY_SIZE: usize = 1000;
+
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Line(String),
+    /// Ctrl-C: the line, and any statement being typed, is dropped.
+    Interrupted,
+    Eof,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    KillToStart,
+    KillToEnd,
+    Interrupt,
+    Eof,
+}
+
+/// Reads one key from raw terminal input, or `None` at the end of input.
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let mut byte = [0];
+    let mut next = |input: &mut dyn Read| -> io::Result<Option<u8>> {
+        Ok(match input.read(&mut byte)? {
+            0 => None,
+            _ => Some(byte[0]),
+        })
+    };
+    let Some(first) = next(input)? else {
+        return Ok(None);
+    };
+    let key = match first {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x02 => Key::Left,
+        0x06 => Key::Right,
+        0x10 => Key::Up,
+        0x0e => Key::Down,
+        0x15 => Key::KillToStart,
+        0x0b => Key::KillToEnd,
+        0x03 => Key::Interrupt,
+        0x04 => Key::Eof,
+        0x1b => {
+            if !matches!(next(input)?, Some(b'[' | b'O')) {
+                return read_key(input);
+            }
+            match next(input)? {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                Some(digit @ b'0'..=b'9') => {
+                    // Sequences like ESC [ 3 ~; only a few are known.
+                    let mut code = vec![digit];
+                    while let Some(byte) = next(input)? {
+                        if byte == b'~' {
+                            break;
+                        }
+                        code.push(byte);
+                    }
+                    match code.as_slice() {
+                        b"3" => Key::Delete,
+                        b"1" | b"7" => Key::Home,
+                        b"4" | b"8" => Key::End,
+                        _ => return read_key(input),
+                    }
+                }
+                _ => return read_key(input),
+            }
+        }
+        byte if byte < 0x20 => return read_key(input),
+        byte => {
+            // The rest of a UTF-8 sequence follows its first byte.
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            while bytes.len() < len {
+                match next(input)? {
+                    Some(byte) => bytes.push(byte),
+                    None => break,
+                }
+            }
+            let s = String::from_utf8_lossy(&bytes);
+            Key::Char(s.chars().next().unwrap_or(char::REPLACEMENT_CHARACTER))
+        }
+    };
+    Ok(Some(key))
+}
+
+/// The line being edited.
+#[derive(Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+    /// Position in the history while stepping through it, and the line
+    /// that was being typed before that.
+    browsing: Option<(usize, Vec<char>)>,
+}
+
+impl Line {
+    /// Applies a key; returns the outcome once the line is finished.
+    fn key(&mut self, key: Key, history: &[String]) -> Option<Input> {
+        match key {
+            Key::Char(ch) => {
+                self.chars.insert(self.cursor, ch);
+                self.cursor += 1;
+            }
+            Key::Enter => return Some(Input::Line(self.chars.iter().collect())),
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+            }
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.chars.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.chars.len(),
+            Key::KillToStart => {
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::KillToEnd => self.chars.truncate(self.cursor),
+            Key::Up => {
+                let index = match &self.browsing {
+                    Some((index, _)) => index.checked_sub(1)?,
+                    None => history.len().checked_sub(1)?,
+                };
+                let typed = match self.browsing.take() {
+                    Some((_, typed)) => typed,
+                    None => self.chars.clone(),
+                };
+                self.browsing = Some((index, typed));
+                self.replace(history[index].chars().collect());
+            }
+            Key::Down => {
+                let (index, typed) = self.browsing.take()?;
+                if index + 1 < history.len() {
+                    self.browsing = Some((index + 1, typed));
+                    self.replace(history[index + 1].chars().collect());
+                } else {
+                    self.replace(typed);
+                }
+            }
+            Key::Interrupt => return Some(Input::Interrupted),
+            Key::Eof if self.chars.is_empty() => return Some(Input::Eof),
+            Key::Backspace | Key::Delete | Key::Eof => {}
+        }
+        None
+    }
+
+    fn replace(&mut self, chars: Vec<char>) {
+        self.chars = chars;
+        self.cursor = self.chars.len();
+    }
+
+    /// Redraws the line after `prompt`, leaving the cursor in place.
+    fn render(&self, prompt: &str, out: &mut impl Write) -> io::Result<()> {
+        let text: String = self.chars.iter().collect();
+        write!(out, "\r{prompt}{text}\x1b[K")?;
+        let back = self.chars.len() - self.cursor;
+        if back > 0 {
+            write!(out, "\x1b[{back}D")?;
+        }
+        out.flush()
+    }
+}
+
+/// Puts the terminal back the way it was when dropped.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enter() -> Option<Self> {
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let saved = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        stty(&["raw", "-echo"]).then_some(Self { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+fn stty(args: &[&str]) -> bool {
+    Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+pub struct Editor {
+    history: Vec<String>,
+    path: Option<PathBuf>,
+    interactive: bool,
+}
+
+impl Editor {
+    /// An editor whose history is kept in the file at `path`, if given.
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let history = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        Self {
+            history,
+            path,
+            interactive: io::stdin().is_terminal() && io::stdout().is_terminal(),
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Input> {
+        let raw = if self.interactive {
+            RawMode::enter()
+        } else {
+            None
+        };
+        let Some(_raw) = raw else {
+            return self.read_plain(prompt);
+        };
+        let mut stdin = io::stdin().lock();
+        let mut stdout = io::stdout().lock();
+        let mut line = Line::default();
+        line.render(prompt, &mut stdout)?;
+        let input = loop {
+            let Some(key) = read_key(&mut stdin)? else {
+                break Input::Eof;
+            };
+            if let Some(input) = line.key(key, &self.history) {
+                break input;
+            }
+            line.render(prompt, &mut stdout)?;
+        };
+        if input == Input::Interrupted {
+            write!(stdout, "^C")?;
+        }
+        // Raw mode does not turn "\n" into "\r\n".
+        write!(stdout, "\r\n")?;
+        stdout.flush()?;
+        Ok(input)
+    }
+
+    fn read_plain(&mut self, prompt: &str) -> io::Result<Input> {
+        if self.interactive {
+            print!("{prompt}");
+            io::stdout().flush()?;
+        }
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(Input::Eof);
+        }
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+        Ok(Input::Line(line))
+    }
+
+    /// Remembers a line for the arrow keys, and in the history file.
+    pub fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > HISTORY_SIZE {
+            self.history.remove(0);
+        }
+        let Some(path) = &self.path else {
+            return;
+        };
+        // Losing the history is not worth interrupting the session over.
+        let _ = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{line}"));
+    }
+
+    /// Trims the history file to the entries kept in memory.
+    pub fn save_history(&self) {
+        if let Some(path) = &self.path {
+            let mut text = self.history.join("\n");
+            text.push('\n');
+            let _ = fs::write(path, text);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(input: &[u8], history: &[String]) -> Option<Input> {
+        let mut input = input;
+        let mut line = Line::default();
+        while let Some(key) = read_key(&mut input).unwrap() {
+            if let Some(result) = line.key(key, history) {
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn test_editing() {
+        let history = vec!["SELECT 1;".to_string(), "SELECT 2;".to_string()];
+        let line = |s: &str| Some(Input::Line(s.to_string()));
+        assert_eq!(
+            line("héllo"),
+            edit("hxllo\x01\x1b[C\x1b[3~é\r".as_bytes(), &[])
+        );
+        assert_eq!(line("ab"), edit(b"b\x01a\x05cd\x7f\x1b[D\x1b[3~\r", &[]));
+        assert_eq!(line("a"), edit(b"abc\x01\x1b[C\x0b\r", &[]));
+        assert_eq!(line("SELECT 1;"), edit(b"\x1b[A\x1b[A\x1b[A\r", &history));
+        assert_eq!(line("SELECT 2;"), edit(b"\x1b[A\x1b[A\x1b[B\r", &history));
+        assert_eq!(line("typed"), edit(b"typed\x1b[A\x1b[B\r", &history));
+        assert_eq!(line("new"), edit(b"old\x15new\r", &[]));
+        assert_eq!(Some(Input::Interrupted), edit(b"abc\x03", &[]));
+        assert_eq!(Some(Input::Eof), edit(b"\x04", &[]));
+        assert_eq!(line("a"), edit(b"a\x04\r", &[]));
+    }
+}
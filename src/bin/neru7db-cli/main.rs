@@ -0,0 +1,388 @@
+//! Shell for Neru7DB.
+//!
+//! `neru7db-cli [OPTIONS] [FILE]` opens the database in FILE, creating it
+//! if it does not exist, or without FILE a scratch database that is gone
+//! when the shell exits. Statements end with `;` and may span lines; lines
+//! starting with a backslash are shell commands, see `\?`.
+//!
+//! On a terminal the shell prompts for input, keeping its history in
+//! `~/.neru7db_history` or the file named by `NERU7DB_HISTORY`. Otherwise,
+//! or with `--file`, it runs the script it is given and stops at the first
+//! error unless told to `--force` on; the exit status is 1 if any
+//! statement failed. `--output csv` or `--output json` prints results for
+//! other programs to read, leaving out the tags of other statements.
+
+mod editor;
+mod meta;
+mod output;
+mod table;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use neru7db::database::{Database, Options};
+use neru7db::engine::Output;
+use neru7db::sql::{self, Token};
+
+use editor::{Editor, Input};
+use meta::Command;
+use output::Format;
+
+const USAGE: &str = "\
+usage: neru7db-cli [OPTIONS] [FILE]
+  -f, --file SCRIPT    run the statements in SCRIPT (- for stdin) and exit
+      --force          keep going after a statement fails
+  -o, --output FORMAT  print results as table (the default), csv or json
+  -h, --help           show this help";
+
+/// Pages the shell's buffer pool holds.
+const POOL_SIZE: usize = 1024;
+
+struct Shell {
+    db: Database,
+    format: Format,
+    /// Whether a script goes on after an error.
+    force: bool,
+    /// Lines of a statement not yet ended by a semicolon.
+    pending: String,
+    failed: bool,
+}
+
+impl Shell {
+    fn interactive(&mut self, editor: &mut Editor) -> io::Result<()> {
+        println!("neru7db {}. Type \\? for help.", env!("CARGO_PKG_VERSION"));
+        loop {
+            let prompt = if self.pending.is_empty() {
+                "neru7db=> "
+            } else {
+                "neru7db-> "
+            };
+            let line = match editor.read_line(prompt)? {
+                Input::Line(line) => line,
+                Input::Interrupted => {
+                    self.pending.clear();
+                    continue;
+                }
+                Input::Eof => break,
+            };
+            editor.add_history(&line);
+            if self.pending.is_empty() && line.trim_start().starts_with('\\') {
+                if meta::parse(&line) == Command::History {
+                    for line in editor.history() {
+                        println!("{line}");
+                    }
+                    continue;
+                }
+                if !self.command(&line) {
+                    break;
+                }
+                continue;
+            }
+            self.line(&line);
+        }
+        editor.save_history();
+        self.close()
+    }
+
+    /// Runs the statements `input` holds, up to the first failure unless
+    /// forced; `name` says where they come from in error messages.
+    fn script(&mut self, name: &str, input: impl BufRead) -> io::Result<()> {
+        for (number, line) in input.lines().enumerate() {
+            let line = line?;
+            if self.pending.is_empty() && line.trim_start().starts_with('\\') {
+                if !self.command(&line) {
+                    return self.close();
+                }
+            } else if !self.line(&line) {
+                eprintln!("{name}:{}: statement failed", number + 1);
+                if !self.force {
+                    return self.close();
+                }
+            }
+        }
+        // A last statement may go without its semicolon.
+        let last = std::mem::take(&mut self.pending);
+        if !last.trim().is_empty() && !self.execute(&last) {
+            eprintln!("{name}: last statement failed");
+        }
+        self.close()
+    }
+
+    /// Adds a line of SQL, running the statements it completes; returns
+    /// false once one fails.
+    fn line(&mut self, line: &str) -> bool {
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        let pending = std::mem::take(&mut self.pending);
+        let (statements, rest) = sql::split_statements(&pending);
+        let mut ok = true;
+        for statement in statements {
+            ok &= self.execute(statement);
+        }
+        if !rest.trim().is_empty() {
+            self.pending = rest.to_string();
+        }
+        ok
+    }
+
+    /// Runs a backslash command; returns false to stop.
+    fn command(&mut self, line: &str) -> bool {
+        let catalog = self.db.engine().catalog();
+        match meta::parse(line) {
+            Command::Quit => return false,
+            Command::Help => print!("{}", meta::HELP),
+            Command::ListTables => print!("{}", meta::list_tables(catalog)),
+            Command::Describe(name) => match meta::describe(catalog, name) {
+                Some(description) => print!("{description}"),
+                None => eprintln!("Did not find any table named \"{name}\"."),
+            },
+            Command::History => eprintln!("no history outside the interactive shell"),
+            Command::Unknown(name) => {
+                eprintln!("invalid command {name}\nTry \\? for help.")
+            }
+        }
+        true
+    }
+
+    /// Runs one statement and prints what it returned; returns false if
+    /// it failed.
+    fn execute(&mut self, statement: &str) -> bool {
+        match self.db.engine_mut().execute(statement) {
+            Ok(Output::Rows { fields, rows }) => print!("{}", self.format.rows(&fields, &rows)),
+            Ok(output) => {
+                if self.format == Format::Table {
+                    match output {
+                        Output::Affected(n) => println!("{} {n}", command_tag(statement)),
+                        _ => println!("{}", command_tag(statement)),
+                    }
+                }
+                // Changes reach the file before the next statement, or
+                // with the transaction they are part of once it commits.
+                if self.db.engine().in_transaction() {
+                    return true;
+                }
+                if let Err(e) = self.db.engine().bufmgr().flush() {
+                    eprintln!("ERROR:  {e}");
+                    self.failed = true;
+                    return false;
+                }
+            }
+            Err(e) => {
+                eprintln!("ERROR:  {e}");
+                self.failed = true;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Writes what the session changed to the file; a transaction left
+    /// open is rolled back, as the server does when a client goes.
+    fn close(&mut self) -> io::Result<()> {
+        let engine = self.db.engine_mut();
+        if engine.in_transaction() {
+            engine.rollback().map_err(io::Error::other)?;
+        }
+        engine.bufmgr().flush().map_err(io::Error::other)
+    }
+}
+
+/// What psql would print for a statement that returns no rows: its
+/// leading keyword, and what it creates or drops.
+fn command_tag(statement: &str) -> String {
+    let words: Vec<String> = sql::tokenize(statement)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(token, _)| match token {
+            Token::Word { value, .. } => Some(value.to_uppercase()),
+            _ => None,
+        })
+        .filter(|word| word != "UNIQUE")
+        .take(3)
+        .collect();
+    match words.as_slice() {
+        [first, second, third] if second == "MATERIALIZED" => format!("{first} {second} {third}"),
+        [first, second, ..] if first == "CREATE" || first == "DROP" => format!("{first} {second}"),
+        [first, ..] if first == "INSERT" => "INSERT 0".to_string(),
+        [first, ..] => first.clone(),
+        [] => "OK".to_string(),
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NERU7DB_HISTORY") {
+        return Some(path.into());
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".neru7db_history"))
+}
+
+/// Opens the database as any other program would, which replays what a
+/// crash left behind and upgrades an older format first.
+fn open(path: Option<&str>) -> Result<Database, String> {
+    let options = Options {
+        pool_size: POOL_SIZE,
+        ..Options::default()
+    };
+    match path {
+        Some(path) => Database::open(path, options),
+        None => Database::temporary(options),
+    }
+    .map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    database: Option<String>,
+    script: Option<String>,
+    force: bool,
+    format: Format,
+}
+
+/// Reads the command line; `Ok(None)` asks for the usage.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{name} needs a value"));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-f" | "--file" => parsed.script = Some(value(&arg)?),
+            "--force" => parsed.force = true,
+            "-o" | "--output" => parsed.format = value(&arg)?.parse()?,
+            flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+            _ if parsed.database.is_some() => return Err("too many arguments".to_string()),
+            _ => parsed.database = Some(arg),
+        }
+    }
+    Ok(Some(parsed))
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("neru7db-cli: {e}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let db = match open(args.database.as_deref()) {
+        Ok(db) => db,
+        Err(e) => {
+            let name = args.database.as_deref().unwrap_or("database");
+            eprintln!("neru7db-cli: cannot open {name}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut shell = Shell {
+        db,
+        format: args.format,
+        force: args.force,
+        pending: String::new(),
+        failed: false,
+    };
+    let result = match args.script.as_deref() {
+        Some("-") => shell.script("<stdin>", io::stdin().lock()),
+        Some(path) => match File::open(path) {
+            Ok(file) => shell.script(path, BufReader::new(file)),
+            Err(e) => {
+                eprintln!("neru7db-cli: cannot read {path}: {e}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None if !io::stdin().is_terminal() => shell.script("<stdin>", io::stdin().lock()),
+        None => shell.interactive(&mut Editor::new(history_path())),
+    };
+    let failed = shell.failed;
+    // Marks the file shut down cleanly, for the next open to trust.
+    let result = result.and_then(|()| shell.db.close().map_err(io::Error::other));
+    match result {
+        Ok(()) if !failed => ExitCode::SUCCESS,
+        Ok(()) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("neru7db-cli: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neru7db::value::Value;
+
+    #[test]
+    fn test_command_tag() {
+        for (statement, tag) in [
+            ("insert into t values (1)", "INSERT 0"),
+            ("CREATE UNIQUE INDEX ON t (a)", "CREATE INDEX"),
+            ("/*+ SeqScan(t) */ DELETE FROM t", "DELETE"),
+            ("drop table t", "DROP TABLE"),
+            ("drop materialized view v", "DROP MATERIALIZED VIEW"),
+        ] {
+            assert_eq!(tag, command_tag(statement));
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parse = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(
+            Ok(Some(Args {
+                database: Some("db".into()),
+                script: Some("-".into()),
+                force: true,
+                format: Format::Csv,
+            })),
+            parse(&["--force", "db", "-f", "-", "--output", "csv"])
+        );
+        assert_eq!(Ok(None), parse(&["-h"]));
+        assert!(parse(&["--output"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+        assert!(parse(&["--fast"]).is_err());
+    }
+
+    #[test]
+    fn test_script() {
+        let mut shell = Shell {
+            db: Database::temporary(Options::default()).unwrap(),
+            format: Format::Csv,
+            force: false,
+            pending: String::new(),
+            failed: false,
+        };
+        let script = "CREATE TABLE t (id INT);\nINSERT INTO t VALUES (1);\n\
+                      INSERT INTO u VALUES (2);\nINSERT INTO t VALUES (3);\n";
+        shell.script("test", script.as_bytes()).unwrap();
+        assert!(shell.failed);
+        let count = |shell: &mut Shell| {
+            let rows = shell.db.engine_mut().execute("SELECT count(*) FROM t");
+            let rows = rows.unwrap();
+            rows.into_rows()[0][0].clone()
+        };
+        assert_eq!(Value::Int(1), count(&mut shell));
+
+        shell.force = true;
+        shell.failed = false;
+        let script = "INSERT INTO u VALUES (2);\nINSERT INTO t\nVALUES (3)";
+        shell.script("test", script.as_bytes()).unwrap();
+        assert!(shell.failed);
+        assert_eq!(Value::Int(2), count(&mut shell));
+
+        shell.force = false;
+        shell.failed = false;
+        let script = "BEGIN;
+INSERT INTO t VALUES (4);
+COMMIT;
+";
+        shell.script("test", script.as_bytes()).unwrap();
+        assert!(!shell.failed);
+        assert_eq!(Value::Int(3), count(&mut shell));
+    }
+}
@@ -0,0 +1,166 @@
+//! Backslash commands, which the shell handles itself.
+
+use neru7db::catalog::{Catalog, TableInfo};
+use neru7db::planner::Field;
+use neru7db::value::{DataType, Value};
+
+use crate::table;
+
+pub const HELP: &str = "\
+  \\d [TABLE]   describe TABLE, or list tables
+  \\dt          list tables
+  \\s           show the input history
+  \\?           show this help
+  \\q           quit
+Statements end with a semicolon and may span several lines.
+";
+
+#[derive(Debug, PartialEq)]
+pub enum Command<'a> {
+    Quit,
+    Help,
+    ListTables,
+    Describe(&'a str),
+    History,
+    /// Not a command; holds the name as typed.
+    Unknown(&'a str),
+}
+
+/// Reads a line starting with a backslash.
+pub fn parse(line: &str) -> Command<'_> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or_default();
+    let arg = words.next();
+    match (name, arg) {
+        ("\\q", None) => Command::Quit,
+        ("\\?", None) => Command::Help,
+        ("\\dt" | "\\d", None) => Command::ListTables,
+        ("\\d", Some(table)) if words.next().is_none() => Command::Describe(table),
+        ("\\s", None) => Command::History,
+        _ => Command::Unknown(name),
+    }
+}
+
+fn text_fields(names: &[&str]) -> Vec<Field> {
+    names
+        .iter()
+        .map(|name| Field::new(*name, Some(DataType::Text)))
+        .collect()
+}
+
+fn column_names(table: &TableInfo) -> Vec<String> {
+    table
+        .schema
+        .columns
+        .iter()
+        .map(|column| column.name.clone())
+        .collect()
+}
+
+pub fn list_tables(catalog: &Catalog) -> String {
+    let mut tables: Vec<&TableInfo> = catalog.tables().collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    let fields = vec![
+        Field::new("Name", Some(DataType::Text)),
+        Field::new("Columns", Some(DataType::Int)),
+        Field::new("Indexes", Some(DataType::Int)),
+    ];
+    let rows: Vec<_> = tables
+        .iter()
+        .map(|table| {
+            vec![
+                Value::from(table.name.as_str()),
+                Value::Int(table.schema.len() as i64),
+                Value::Int(table.indexes.len() as i64),
+            ]
+        })
+        .collect();
+    format!("List of tables\n{}", table::format(&fields, &rows))
+}
+
+/// The columns and indexes of a table, or `None` if there is no such
+/// table.
+pub fn describe(catalog: &Catalog, name: &str) -> Option<String> {
+    let table = catalog.table(name)?;
+    let rows: Vec<_> = table
+        .schema
+        .columns
+        .iter()
+        .map(|column| {
+            vec![
+                Value::from(column.name.as_str()),
+                Value::from(column.data_type.to_string()),
+                Value::from(if column.nullable { "" } else { "not null" }),
+            ]
+        })
+        .collect();
+    let mut out = format!(
+        "Table \"{}\"\n{}",
+        table.name,
+        table::format(&text_fields(&["Column", "Type", "Nullable"]), &rows)
+    );
+    if !table.indexes.is_empty() {
+        let columns = column_names(table);
+        out.push_str("Indexes:\n");
+        for index in &table.indexes {
+            let keys: Vec<String> = index
+                .keys
+                .iter()
+                .map(|key| key.expr.display_with(&columns).to_string())
+                .collect();
+            out.push_str(&format!("    \"{}\"", index.name));
+            if index.unique {
+                out.push_str(" UNIQUE");
+            }
+            out.push_str(&format!(" ({})", keys.join(", ")));
+            if let Some(predicate) = &index.predicate {
+                out.push_str(&format!(" WHERE {}", predicate.display_with(&columns)));
+            }
+            out.push('\n');
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neru7db::buffer::BufferPoolManager;
+    use neru7db::disk::DiskManager;
+    use neru7db::engine::Engine;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::Quit, parse("\\q"));
+        assert_eq!(Command::ListTables, parse("  \\d "));
+        assert_eq!(Command::Describe("t"), parse("\\d t"));
+        assert_eq!(Command::Unknown("\\d"), parse("\\d t u"));
+        assert_eq!(Command::Unknown("\\x"), parse("\\x"));
+    }
+
+    #[test]
+    fn test_describe() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut engine = Engine::new(BufferPoolManager::new(disk, 32));
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        engine
+            .execute("CREATE INDEX t_name ON t (lower(name)) WHERE id > 1")
+            .unwrap();
+        assert_eq!(
+            "Table \"t\"\n \
+             Column | Type | Nullable\n\
+             --------+------+----------\n \
+             id     | INT  | not null\n \
+             name   | TEXT |\n\
+             (2 rows)\n\
+             Indexes:\n    \
+             \"t_pkey\" UNIQUE (id)\n    \
+             \"t_name\" (lower(name)) WHERE (id > 1)\n",
+            describe(engine.catalog(), "t").unwrap()
+        );
+        assert!(describe(engine.catalog(), "u").is_none());
+        assert!(list_tables(engine.catalog()).contains(" t    |       2 |       2\n"));
+    }
+}
@@ -0,0 +1,161 @@
+//! The formats query results can be printed in.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use neru7db::planner::Field;
+use neru7db::value::{Tuple, Value};
+
+use crate::table;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    /// Aligned columns for people to read.
+    #[default]
+    Table,
+    /// A header line, then a line per row, quoted as RFC 4180 says. NULL
+    /// is an empty field.
+    Csv,
+    /// A line per result: an array with an object per row.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "unknown output format {s:?}; use table, csv or json"
+            )),
+        }
+    }
+}
+
+impl Format {
+    pub fn rows(self, fields: &[Field], rows: &[Tuple]) -> String {
+        match self {
+            Format::Table => format!("{}\n", table::format(fields, rows)),
+            Format::Csv => csv(fields, rows),
+            Format::Json => json(fields, rows),
+        }
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn csv(fields: &[Field], rows: &[Tuple]) -> String {
+    let mut out = String::new();
+    let header: Vec<String> = fields.iter().map(|field| csv_field(&field.name)).collect();
+    out.push_str(&header.join(","));
+    out.push_str("\r\n");
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .map(|value| csv_field(&table::cell(value)))
+            .collect();
+        out.push_str(&line.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn json_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{b}").unwrap(),
+        Value::Int(i) => write!(out, "{i}").unwrap(),
+        // JSON has no infinities or NaN.
+        Value::Float(x) if !x.is_finite() => json_string(&x.to_string(), out),
+        Value::Float(x) => write!(out, "{x:?}").unwrap(),
+        Value::Text(s) => json_string(s, out),
+        value @ Value::Bytes(_) => json_string(&value.to_string(), out),
+        Value::Json(json) => write!(out, "{json}").unwrap(),
+        Value::Array(array) => {
+            out.push('[');
+            for (i, item) in array.items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                json_value(item, out);
+            }
+            out.push(']');
+        }
+    }
+}
+
+fn json(fields: &[Field], rows: &[Tuple]) -> String {
+    let mut out = String::from("[");
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push('{');
+        for (j, (field, value)) in fields.iter().zip(row).enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            json_string(&field.name, &mut out);
+            out.push(':');
+            json_value(value, &mut out);
+        }
+        out.push('}');
+    }
+    out.push_str("]\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use neru7db::value::DataType;
+
+    #[test]
+    fn test_csv_and_json() {
+        let fields = [
+            Field::new("id", Some(DataType::Int)),
+            Field::new("note", Some(DataType::Text)),
+            Field::new("score", Some(DataType::Float)),
+        ];
+        let rows = vec![
+            vec![Value::Int(1), Value::from("a, \"b\""), Value::Float(2.0)],
+            vec![Value::Int(2), Value::Null, Value::Float(0.5)],
+        ];
+        assert_eq!(
+            "id,note,score\r\n1,\"a, \"\"b\"\"\",2\r\n2,,0.5\r\n",
+            Format::Csv.rows(&fields, &rows)
+        );
+        assert_eq!(
+            "[{\"id\":1,\"note\":\"a, \\\"b\\\"\",\"score\":2.0},\
+             {\"id\":2,\"note\":null,\"score\":0.5}]\n",
+            Format::Json.rows(&fields, &rows)
+        );
+        assert_eq!("[]\n", Format::Json.rows(&fields, &[]));
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
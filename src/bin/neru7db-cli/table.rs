@@ -0,0 +1,106 @@
+//! Query results as aligned text tables, laid out like psql's.
+
+use neru7db::planner::Field;
+use neru7db::value::{DataType, Tuple, Value};
+
+/// How a value is shown in a cell. NULL is left blank.
+pub fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+/// `rows` under a header of `fields`, with a row count below. Numbers are
+/// aligned right, everything else left.
+pub fn format(fields: &[Field], rows: &[Tuple]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(cell).collect())
+        .collect();
+    let mut widths: Vec<usize> = fields.iter().map(|field| width(&field.name)).collect();
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(self::width(cell));
+        }
+    }
+    let numeric: Vec<bool> = fields
+        .iter()
+        .map(|field| matches!(field.data_type, Some(DataType::Int | DataType::Float)))
+        .collect();
+
+    let mut out = String::new();
+    let header: Vec<String> = fields
+        .iter()
+        .zip(&widths)
+        .map(|(field, &width)| {
+            let padding = width - self::width(&field.name);
+            let left = padding / 2;
+            format!(
+                "{}{}{}",
+                " ".repeat(left),
+                field.name,
+                " ".repeat(padding - left)
+            )
+        })
+        .collect();
+    push_line(&mut out, &header);
+    let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width + 2)).collect();
+    out.push_str(rule.join("+").trim_end());
+    out.push('\n');
+    for row in &cells {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .zip(&numeric)
+            .map(|((cell, &width), &numeric)| {
+                let padding = " ".repeat(width - self::width(cell));
+                if numeric {
+                    format!("{padding}{cell}")
+                } else {
+                    format!("{cell}{padding}")
+                }
+            })
+            .collect();
+        push_line(&mut out, &line);
+    }
+    let plural = if rows.len() == 1 { "" } else { "s" };
+    out.push_str(&format!("({} row{plural})\n", rows.len()));
+    out
+}
+
+fn width(s: &str) -> usize {
+    s.chars().count()
+}
+
+fn push_line(out: &mut String, cells: &[String]) {
+    let line: Vec<String> = cells.iter().map(|cell| format!(" {cell} ")).collect();
+    out.push_str(line.join("|").trim_end());
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let fields = [
+            Field::new("id", Some(DataType::Int)),
+            Field::new("name", Some(DataType::Text)),
+        ];
+        let rows = vec![
+            vec![Value::Int(1), Value::from("Ann")],
+            vec![Value::Int(20), Value::Null],
+        ];
+        assert_eq!(
+            " id | name\n\
+             ----+------\n  \
+             1 | Ann\n \
+             20 |\n\
+             (2 rows)\n",
+            format(&fields, &rows)
+        );
+        assert_eq!(" x\n---\n(0 rows)\n", format(&[Field::new("x", None)], &[]));
+    }
+}
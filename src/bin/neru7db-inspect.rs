@@ -0,0 +1,110 @@
+//! Shows what a database file holds, page by page.
+//!
+//! `neru7db-inspect FILE` lists the pages the catalog reaches and what
+//! each one is; the other commands dump a single page, or walk a table's
+//! heap chain or an index's tree. The file is only read: a missing or
+//! empty file is refused rather than created.
+
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+use neru7db::buffer::BufferPoolManager;
+use neru7db::catalog::{Catalog, CATALOG_PAGE_ID};
+use neru7db::disk::{DiskManager, PageId};
+use neru7db::heap::HeapFile;
+use neru7db::inspect;
+
+const USAGE: &str = "\
+usage: neru7db-inspect FILE [COMMAND]
+  pages          list every page and what it holds (the default)
+  page ID        dump a page: header, slots and cells, decoded and in hex
+  heap TABLE     walk a table's heap chain; catalog walks the catalog's own
+  btree INDEX    walk an index from its root";
+
+const POOL_SIZE: usize = 64;
+
+fn run(args: &[String]) -> Result<String, String> {
+    let (path, command) = match args {
+        [path, command @ ..] => (path, command),
+        [] => return Err(USAGE.to_string()),
+    };
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.len() > 0 => {}
+        Ok(_) => return Err(format!("{path} is empty")),
+        Err(e) => return Err(format!("cannot open {path}: {e}")),
+    }
+    let disk = DiskManager::open_read_only(path).map_err(|e| format!("cannot open {path}: {e}"))?;
+    let bufmgr = BufferPoolManager::new(disk, POOL_SIZE);
+    // Pages can still be dumped when the catalog cannot be read.
+    let catalog = Catalog::open(&bufmgr).map_err(|e| format!("cannot read the catalog: {e}"));
+    let error = |e: inspect::Error| e.to_string();
+    let command: Vec<&str> = command.iter().map(String::as_str).collect();
+    match command.as_slice() {
+        [] | ["pages"] => {
+            let catalog = catalog?;
+            let map = inspect::page_map(&bufmgr, &catalog).map_err(error)?;
+            let mut out = String::new();
+            for page_id in 0..bufmgr.num_pages() {
+                let line = match map.get(&PageId(page_id)) {
+                    Some(owner) => format!("{page_id:6}  {:<12}  {}\n", owner.kind, owner.object),
+                    None => format!("{page_id:6}  unused\n"),
+                };
+                out.push_str(&line);
+            }
+            Ok(out)
+        }
+        ["page", id] => {
+            let id: u64 = id.parse().map_err(|_| format!("bad page id {id:?}"))?;
+            let kind = match &catalog {
+                Ok(catalog) => {
+                    let map = inspect::page_map(&bufmgr, catalog).map_err(error)?;
+                    map.get(&PageId(id)).map(|owner| owner.kind)
+                }
+                Err(e) => {
+                    eprintln!("neru7db-inspect: {e}");
+                    None
+                }
+            };
+            inspect::dump_page(&bufmgr, PageId(id), kind).map_err(error)
+        }
+        ["heap", "catalog"] => {
+            inspect::walk_heap(&bufmgr, HeapFile::new(CATALOG_PAGE_ID)).map_err(error)
+        }
+        ["heap", name] => {
+            let catalog = catalog?;
+            let table = catalog
+                .table(name)
+                .ok_or(format!("no table named {name:?}"))?;
+            inspect::walk_heap(&bufmgr, table.heap).map_err(error)
+        }
+        ["btree", name] => {
+            let catalog = catalog?;
+            let index = catalog
+                .tables()
+                .flat_map(|table| &table.indexes)
+                .find(|index| index.name == *name)
+                .ok_or(format!("no index named {name:?}"))?;
+            inspect::walk_btree(&bufmgr, index.btree).map_err(error)
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    match run(&args) {
+        Ok(out) => {
+            print!("{out}");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("neru7db-inspect: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
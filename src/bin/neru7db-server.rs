@@ -0,0 +1,171 @@
+//! Serves a database file over the PostgreSQL protocol, and optionally
+//! over HTTP.
+//!
+//! See [`neru7db::server`] for how connections are handled and
+//! [`neru7db::pgwire`] for what the protocol support covers, and
+//! [`neru7db::http`] for the HTTP endpoints of `--http`.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use neru7db::database::{Database, Options};
+use neru7db::pgwire::Tls;
+use neru7db::server::{Config, Server};
+use neru7db::trace;
+
+const USAGE: &str = "\
+usage: neru7db-server [OPTIONS] FILE
+  -l, --listen ADDR          address to listen on (127.0.0.1:5432)
+      --http ADDR            also answer HTTP requests on ADDR (off)
+      --password WORD        password clients must give while the database
+                             has no users (none)
+      --workers N            connections served at once (one per CPU)
+      --max-connections N    connections served or waiting at once (100)
+      --expire-interval SECS how often to delete rows past their time to
+                             live, 0 for never (10)
+      --idle-in-transaction-timeout SECS
+                             how long a client may sit idle in a
+                             transaction, 0 for ever (60)
+      --tls-cert FILE        accept TLS with the PEM certificate chain in
+                             FILE (off)
+      --tls-key FILE         the PEM private key of --tls-cert
+      --require-tls          turn away clients that do not ask for TLS
+  -c, --config FILE          read database options from FILE
+      --trace                log page I/O and operator timings to stderr
+  -h, --help                 show this help
+
+Once the database has users (see CREATE USER), clients must log in as one
+of them. Database options are also read from NERU7DB_* environment
+variables.";
+
+struct Args {
+    path: PathBuf,
+    config: Config,
+    options_file: Option<PathBuf>,
+    /// The certificate chain and key files, if TLS is on.
+    tls: Option<(PathBuf, PathBuf)>,
+    trace: bool,
+}
+
+/// Reads the command line; `Ok(None)` asks for the usage.
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut path = None;
+    let mut config = Config::default();
+    let mut options_file = None;
+    let (mut tls_cert, mut tls_key) = (None, None);
+    let mut trace = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+        fn number(value: String) -> Result<usize, String> {
+            value.parse().map_err(|_| format!("bad number {value:?}"))
+        }
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-l" | "--listen" => config.listen = value()?,
+            "--http" => config.http = Some(value()?),
+            "--password" => config.protocol.password = Some(value()?),
+            "--workers" => config.workers = number(value()?)?,
+            "--max-connections" => config.max_connections = number(value()?)?,
+            "--expire-interval" => {
+                config.expire_interval = match number(value()?)? {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs as u64)),
+                }
+            }
+            "--idle-in-transaction-timeout" => {
+                config.protocol.idle_in_transaction_timeout = match number(value()?)? {
+                    0 => None,
+                    secs => Some(Duration::from_secs(secs as u64)),
+                }
+            }
+            "-c" | "--config" => options_file = Some(PathBuf::from(value()?)),
+            "--tls-cert" => tls_cert = Some(PathBuf::from(value()?)),
+            "--tls-key" => tls_key = Some(PathBuf::from(value()?)),
+            "--require-tls" => config.protocol.require_tls = true,
+            "--trace" => trace = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument {arg}")),
+        }
+    }
+    let path = path.ok_or("no database file given")?;
+    if config.protocol.require_tls && config.http.is_some() {
+        return Err("--require-tls rules out --http, which has no TLS".to_string());
+    }
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert), Some(key)) => Some((cert, key)),
+        (None, None) if config.protocol.require_tls => {
+            return Err("--require-tls needs --tls-cert and --tls-key".to_string())
+        }
+        (None, None) => None,
+        _ => return Err("--tls-cert and --tls-key go together".to_string()),
+    };
+    Ok(Some(Args {
+        path,
+        config,
+        options_file,
+        tls,
+        trace,
+    }))
+}
+
+fn main() -> ExitCode {
+    let mut args = match parse_args(env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Err(e) => {
+            eprintln!("neru7db-server: {e}\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    if args.trace {
+        trace::set_subscriber(Arc::new(trace::Logger::stderr()));
+    }
+    if let Some((cert, key)) = &args.tls {
+        match Tls::from_pem_files(cert, key) {
+            Ok(tls) => args.config.protocol.tls = Some(tls),
+            Err(e) => {
+                eprintln!("neru7db-server: {e}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let db = Options::load(args.options_file.as_deref())
+        .map_err(|e| e.to_string())
+        .and_then(|options| Database::open(&args.path, options).map_err(|e| e.to_string()));
+    let mut db = match db {
+        Ok(db) => db,
+        Err(e) => {
+            eprintln!("neru7db-server: cannot open {}: {e}", args.path.display());
+            return ExitCode::FAILURE;
+        }
+    };
+    db.engine_mut()
+        .set_slow_query_sink(|query| eprintln!("neru7db-server: slow query: {query}"));
+    let listen = match &args.config.http {
+        Some(http) => format!("{} and {http} (HTTP)", args.config.listen),
+        None => args.config.listen.clone(),
+    };
+    let server = match Server::bind(db, args.config) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!("neru7db-server: cannot listen on {listen}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    eprintln!("neru7db-server: listening on {listen}");
+    match server.run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("neru7db-server: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
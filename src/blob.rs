@@ -0,0 +1,237 @@
+//! Large binary values kept outside of tables, streamed a page at a time.
+//!
+//! A row holds at most a page, so a value of several megabytes goes into
+//! a chain of overflow pages instead, and the row keeps its [`BlobId`].
+//! [`BlobWriter`] fills the chain through [`io::Write`] and [`BlobReader`]
+//! reads it back through [`io::Read`], each holding no more than the page
+//! at hand: a blob never has to fit in memory, only in the file.
+//!
+//! Each page starts with the id of the next page in the chain and the
+//! number of its bytes in use. Blob pages go through the buffer pool like
+//! any other, so they commit and roll back with the transaction that
+//! wrote them. Nothing records which pages a blob owns apart from the
+//! chain itself, so unlike the pages of dropped tables they are never
+//! freed for reuse.
+
+use std::io;
+use std::sync::Arc;
+
+use crate::buffer::{self, Buffer, BufferPoolManager};
+use crate::disk::{PageId, PAGE_SIZE};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error("blob page {page_id} is corrupt: {reason}")]
+    Corrupt { page_id: u64, reason: &'static str },
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Buffer(buffer::Error::Io(e)) => e,
+            Error::Corrupt { .. } => io::Error::new(io::ErrorKind::InvalidData, e),
+            e => io::Error::other(e),
+        }
+    }
+}
+
+const USED_RANGE: std::ops::Range<usize> = 8..12;
+const HEADER_SIZE: usize = 12;
+/// Bytes of the blob each page holds.
+const CAPACITY: usize = PAGE_SIZE - HEADER_SIZE;
+
+/// Where a blob starts: the id of its first page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlobId(pub PageId);
+
+fn corrupt(page_id: PageId, reason: &'static str) -> Error {
+    Error::Corrupt {
+        page_id: page_id.to_u64(),
+        reason,
+    }
+}
+
+fn new_page(bufmgr: &BufferPoolManager) -> Result<Arc<Buffer>, Error> {
+    let buffer = bufmgr.create_page()?;
+    buffer.write()[..8].copy_from_slice(&PageId::INVALID_PAGE_ID.to_bytes());
+    Ok(buffer)
+}
+
+/// Writes a new blob. Every write goes straight to pages of the buffer
+/// pool, the last of which stays pinned until the writer is dropped.
+pub struct BlobWriter<'a> {
+    bufmgr: &'a BufferPoolManager,
+    id: BlobId,
+    last: Arc<Buffer>,
+    used: usize,
+}
+
+impl<'a> BlobWriter<'a> {
+    /// Starts an empty blob.
+    pub fn create(bufmgr: &'a BufferPoolManager) -> Result<Self, Error> {
+        let last = new_page(bufmgr)?;
+        Ok(Self {
+            bufmgr,
+            id: BlobId(last.page_id),
+            last,
+            used: 0,
+        })
+    }
+
+    pub fn id(&self) -> BlobId {
+        self.id
+    }
+}
+
+impl io::Write for BlobWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.used == CAPACITY {
+            let next = new_page(self.bufmgr)?;
+            self.last.write()[..8].copy_from_slice(&next.page_id.to_bytes());
+            self.last = next;
+            self.used = 0;
+        }
+        let n = buf.len().min(CAPACITY - self.used);
+        let mut page = self.last.write();
+        let start = HEADER_SIZE + self.used;
+        page[start..start + n].copy_from_slice(&buf[..n]);
+        self.used += n;
+        page[USED_RANGE].copy_from_slice(&(self.used as u32).to_le_bytes());
+        Ok(n)
+    }
+
+    /// Nothing is held back from the buffer pool, which writes pages to
+    /// the file as it evicts them and when it is flushed.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads a blob from the start.
+pub struct BlobReader<'a> {
+    bufmgr: &'a BufferPoolManager,
+    next: Option<PageId>,
+    page: Option<Arc<Buffer>>,
+    /// Position within `page`, and the bytes in use there.
+    offset: usize,
+    used: usize,
+}
+
+impl<'a> BlobReader<'a> {
+    pub fn open(bufmgr: &'a BufferPoolManager, id: BlobId) -> Self {
+        Self {
+            bufmgr,
+            next: Some(id.0),
+            page: None,
+            offset: 0,
+            used: 0,
+        }
+    }
+
+    /// Moves to the next page of the chain; `false` at its end.
+    fn advance(&mut self) -> Result<bool, Error> {
+        let Some(page_id) = self.next else {
+            return Ok(false);
+        };
+        let buffer = self.bufmgr.fetch_page(page_id)?;
+        {
+            let page = buffer.read();
+            let used = u32::from_le_bytes(page[USED_RANGE].try_into().unwrap()) as usize;
+            if used > CAPACITY {
+                return Err(corrupt(page_id, "used size exceeds the page"));
+            }
+            self.next = PageId::from_bytes(&page[..8]).valid();
+            if self.next == Some(page_id) {
+                return Err(corrupt(page_id, "page links to itself"));
+            }
+            self.used = used;
+        }
+        self.page = Some(buffer);
+        self.offset = 0;
+        Ok(true)
+    }
+}
+
+impl io::Read for BlobReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.page.is_none() || self.offset == self.used {
+            if !self.advance()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.used - self.offset);
+        let page = self.page.as_ref().unwrap().read();
+        let start = HEADER_SIZE + self.offset;
+        buf[..n].copy_from_slice(&page[start..start + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+/// Length of the blob in bytes, found by walking its chain without
+/// reading its contents.
+pub fn len(bufmgr: &BufferPoolManager, id: BlobId) -> Result<u64, Error> {
+    let mut reader = BlobReader::open(bufmgr, id);
+    let mut len = 0;
+    while reader.advance()? {
+        len += reader.used as u64;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test_stream_through_small_pool() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        // Far fewer frames than the blob has pages.
+        let bufmgr = BufferPoolManager::new(disk, 4);
+        let data: Vec<u8> = (0..100 * PAGE_SIZE as u32)
+            .map(|i| (i % 251) as u8)
+            .collect();
+
+        let mut writer = BlobWriter::create(&bufmgr).unwrap();
+        // Uneven chunks cross page boundaries at different offsets.
+        for chunk in data.chunks(1000) {
+            writer.write_all(chunk).unwrap();
+        }
+        let id = writer.id();
+        drop(writer);
+        let empty = BlobWriter::create(&bufmgr).unwrap().id();
+        bufmgr.flush().unwrap();
+
+        assert_eq!(data.len() as u64, len(&bufmgr, id).unwrap());
+        let mut read = vec![];
+        let mut reader = BlobReader::open(&bufmgr, id);
+        let mut chunk = [0; 777];
+        loop {
+            let n = reader.read(&mut chunk).unwrap();
+            if n == 0 {
+                break;
+            }
+            read.extend_from_slice(&chunk[..n]);
+        }
+        assert!(read == data);
+
+        let mut read = vec![];
+        BlobReader::open(&bufmgr, empty)
+            .read_to_end(&mut read)
+            .unwrap();
+        assert!(read.is_empty());
+        assert_eq!(0, len(&bufmgr, empty).unwrap());
+    }
+}
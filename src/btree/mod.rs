@@ -0,0 +1,757 @@
+//! Disk-based B+Tree mapping byte-string keys to byte-string values.
+//!
+//! Keys are compared byte-wise, so callers encode typed keys with
+//! [`crate::tuple::encode_key`]. Deletion removes pairs from leaves without
+//! rebalancing; empty leaves stay in the chain and are skipped by iterators.
+//!
+//! [`BTree::rebuild`] instead loads a whole tree from sorted pairs bottom
+//! up, filling each node to [`FILL_FACTOR`] percent, which is how an index
+//! that deletes and splits have left sparse is packed again. [`BTree::stats`]
+//! tells how sparse a tree is.
+
+pub(crate) mod node;
+
+use std::sync::Arc;
+
+use crate::buffer::{self, Buffer, BufferPoolManager};
+use crate::disk::{PageId, PAGE_SIZE};
+use node::{Node, NODE_TYPE_BRANCH, NODE_TYPE_LEAF};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("duplicate key")]
+    DuplicateKey,
+    #[error("key and value too large: {0} bytes")]
+    TooLarge(usize),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+}
+
+/// Percent of each node that [`BTree::rebuild`] fills, leaving the rest
+/// for inserts before the first split.
+pub const FILL_FACTOR: usize = 90;
+
+/// A `(key, value)` pair read out of a leaf.
+pub type Pair = (Vec<u8>, Vec<u8>);
+
+#[derive(Debug, Clone)]
+pub enum SearchMode {
+    Start,
+    /// Position at the first key greater than or equal to the given key.
+    Key(Vec<u8>),
+}
+
+impl SearchMode {
+    fn child_page_id(&self, branch: &Node<&[u8]>) -> PageId {
+        match self {
+            SearchMode::Start => branch.child_at(0),
+            SearchMode::Key(key) => branch.child_at(branch.search_child_idx(key)),
+        }
+    }
+
+    fn slot_id(&self, leaf: &Node<&[u8]>) -> usize {
+        match self {
+            SearchMode::Start => 0,
+            SearchMode::Key(key) => leaf.search_slot_id(key).unwrap_or_else(|slot_id| slot_id),
+        }
+    }
+}
+
+/// A B+Tree identified by its meta page, which stores the root page id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BTree {
+    pub meta_page_id: PageId,
+}
+
+impl BTree {
+    pub fn create(bufmgr: &BufferPoolManager) -> Result<Self, Error> {
+        let meta_buffer = bufmgr.create_page()?;
+        let root_buffer = bufmgr.create_page()?;
+        Node::new(&mut root_buffer.write()[..]).initialize_as_leaf();
+        meta_buffer.write()[..8].copy_from_slice(&root_buffer.page_id.to_bytes());
+        Ok(Self::new(meta_buffer.page_id))
+    }
+
+    /// A tree with no root yet, for [`BTree::rebuild`] to load before it
+    /// is used.
+    pub fn create_unloaded(bufmgr: &BufferPoolManager) -> Result<Self, Error> {
+        Ok(Self::new(bufmgr.create_page()?.page_id))
+    }
+
+    pub fn new(meta_page_id: PageId) -> Self {
+        Self { meta_page_id }
+    }
+
+    fn fetch_root_page(&self, bufmgr: &BufferPoolManager) -> Result<Arc<Buffer>, Error> {
+        let root_page_id = {
+            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+            let page = meta_buffer.read();
+            PageId::from_bytes(&page[..8])
+        };
+        Ok(bufmgr.fetch_page(root_page_id)?)
+    }
+
+    pub fn search(
+        &self,
+        bufmgr: &BufferPoolManager,
+        search_mode: SearchMode,
+    ) -> Result<Iter, Error> {
+        let mut buffer = self.fetch_root_page(bufmgr)?;
+        loop {
+            let child_page_id = {
+                let page = buffer.read();
+                let node = Node::new(&page[..]);
+                if node.node_type() == NODE_TYPE_LEAF {
+                    let slot_id = search_mode.slot_id(&node);
+                    drop(page);
+                    return Ok(Iter { buffer, slot_id });
+                }
+                search_mode.child_page_id(&node)
+            };
+            buffer = bufmgr.fetch_page(child_page_id)?;
+        }
+    }
+
+    /// Looks up the value stored under exactly `key`.
+    pub fn get(&self, bufmgr: &BufferPoolManager, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let mut iter = self.search(bufmgr, SearchMode::Key(key.to_vec()))?;
+        match iter.next(bufmgr)? {
+            Some((found, value)) if found == key => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Fails with [`Error::TooLarge`] if the pair can never be stored.
+    pub fn check_size(key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let size = node::pair_size(key, value);
+        if size > node::max_pair_size(PAGE_SIZE) {
+            return Err(Error::TooLarge(size));
+        }
+        Ok(())
+    }
+
+    pub fn insert(
+        &self,
+        bufmgr: &BufferPoolManager,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<(), Error> {
+        Self::check_size(key, value)?;
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let root_page_id = PageId::from_bytes(&meta_buffer.read()[..8]);
+        let root_buffer = bufmgr.fetch_page(root_page_id)?;
+        if let Some((key, left_page_id)) = self.insert_internal(bufmgr, root_buffer, key, value)? {
+            let new_root_buffer = bufmgr.create_page()?;
+            let mut page = new_root_buffer.write();
+            let mut branch = Node::new(&mut page[..]);
+            branch.initialize_as_branch(root_page_id);
+            branch.insert(0, &key, &left_page_id.to_bytes()).unwrap();
+            meta_buffer.write()[..8].copy_from_slice(&new_root_buffer.page_id.to_bytes());
+        }
+        Ok(())
+    }
+
+    /// Inserts into the subtree rooted at `buffer`. When the node splits,
+    /// returns the separator key and the page id of the new left sibling.
+    fn insert_internal(
+        &self,
+        bufmgr: &BufferPoolManager,
+        buffer: Arc<Buffer>,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<Option<(Vec<u8>, PageId)>, Error> {
+        let node_type = node::node_type(&buffer.read()[..]);
+        if node_type == NODE_TYPE_LEAF {
+            let mut page = buffer.write();
+            let mut leaf = Node::new(&mut page[..]);
+            let slot_id = match leaf.search_slot_id(key) {
+                Ok(_) => return Err(Error::DuplicateKey),
+                Err(slot_id) => slot_id,
+            };
+            if leaf.insert(slot_id, key, value).is_some() {
+                return Ok(None);
+            }
+            let mut pairs = leaf.pairs();
+            pairs.insert(slot_id, (key.to_vec(), value.to_vec()));
+            let mid = split_point(&pairs);
+            let prev_page_id = leaf.prev_page_id();
+            let new_buffer = bufmgr.create_page()?;
+            {
+                let mut new_page = new_buffer.write();
+                let mut new_leaf = Node::new(&mut new_page[..]);
+                new_leaf.initialize_as_leaf();
+                new_leaf.set_prev_page_id(prev_page_id);
+                new_leaf.set_next_page_id(Some(buffer.page_id));
+                new_leaf.set_pairs(&pairs[..mid]);
+            }
+            leaf.set_prev_page_id(Some(new_buffer.page_id));
+            leaf.set_pairs(&pairs[mid..]);
+            drop(page);
+            if let Some(prev_page_id) = prev_page_id {
+                let prev_buffer = bufmgr.fetch_page(prev_page_id)?;
+                Node::new(&mut prev_buffer.write()[..]).set_next_page_id(Some(new_buffer.page_id));
+            }
+            let separator = pairs.swap_remove(mid).0;
+            return Ok(Some((separator, new_buffer.page_id)));
+        }
+        assert_eq!(node_type, NODE_TYPE_BRANCH);
+        let (child_idx, child_page_id) = {
+            let page = buffer.read();
+            let branch = Node::new(&page[..]);
+            let child_idx = branch.search_child_idx(key);
+            (child_idx, branch.child_at(child_idx))
+        };
+        let child_buffer = bufmgr.fetch_page(child_page_id)?;
+        let Some((child_key, child_left)) =
+            self.insert_internal(bufmgr, child_buffer, key, value)?
+        else {
+            return Ok(None);
+        };
+        let mut page = buffer.write();
+        let mut branch = Node::new(&mut page[..]);
+        let child_left = child_left.to_bytes();
+        if branch.insert(child_idx, &child_key, &child_left).is_some() {
+            return Ok(None);
+        }
+        let mut pairs = branch.pairs();
+        pairs.insert(child_idx, (child_key, child_left.to_vec()));
+        let mid = split_point(&pairs);
+        let new_buffer = bufmgr.create_page()?;
+        {
+            let mut new_page = new_buffer.write();
+            let mut new_branch = Node::new(&mut new_page[..]);
+            new_branch.initialize_as_branch(PageId::from_bytes(&pairs[mid].1));
+            new_branch.set_pairs(&pairs[..mid]);
+        }
+        branch.set_pairs(&pairs[mid + 1..]);
+        let separator = pairs.swap_remove(mid).0;
+        Ok(Some((separator, new_buffer.page_id)))
+    }
+
+    /// Replaces every pair of the tree with `pairs`, which must come in
+    /// increasing order of key, building the new nodes bottom up. Fails
+    /// with [`Error::DuplicateKey`] on a key equal to the one before it, in
+    /// which case the tree is left as it was. The old nodes are freed once
+    /// the new ones replace them.
+    pub fn rebuild(
+        &self,
+        bufmgr: &BufferPoolManager,
+        pairs: impl IntoIterator<Item = Pair>,
+    ) -> Result<(), Error> {
+        let mut loader = Loader {
+            bufmgr,
+            levels: vec![],
+        };
+        let mut last: Option<Vec<u8>> = None;
+        for (key, value) in pairs {
+            Self::check_size(&key, &value)?;
+            if let Some(last) = &last {
+                match last.as_slice().cmp(&key) {
+                    std::cmp::Ordering::Less => {}
+                    std::cmp::Ordering::Equal => return Err(Error::DuplicateKey),
+                    std::cmp::Ordering::Greater => panic!("pairs out of order"),
+                }
+            }
+            loader.push(0, &key, &value)?;
+            last = Some(key);
+        }
+        let root_page_id = match loader.levels.last() {
+            Some((root, _)) => root.page_id,
+            None => {
+                let root = bufmgr.create_page()?;
+                Node::new(&mut root.write()[..]).initialize_as_leaf();
+                root.page_id
+            }
+        };
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        // The meta page of a tree not loaded yet is all zeros: no root.
+        let old_root_page_id = PageId::from_bytes(&meta_buffer.read()[..8]);
+        let old = match old_root_page_id {
+            PageId(0) => vec![],
+            _ => node_page_ids(bufmgr, old_root_page_id)?,
+        };
+        meta_buffer.write()[..8].copy_from_slice(&root_page_id.to_bytes());
+        for page_id in old {
+            bufmgr.free_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Removes every pair by giving the tree a new, empty root, and frees
+    /// the old nodes.
+    pub fn truncate(&self, bufmgr: &BufferPoolManager) -> Result<(), Error> {
+        let old_root_page_id = self.fetch_root_page(bufmgr)?.page_id;
+        let old = node_page_ids(bufmgr, old_root_page_id)?;
+        let root_buffer = bufmgr.create_page()?;
+        Node::new(&mut root_buffer.write()[..]).initialize_as_leaf();
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        meta_buffer.write()[..8].copy_from_slice(&root_buffer.page_id.to_bytes());
+        for page_id in old {
+            bufmgr.free_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Frees the meta page and every node, for a tree nothing refers to
+    /// any more.
+    pub fn free(&self, bufmgr: &BufferPoolManager) -> Result<(), Error> {
+        let root_page_id = self.fetch_root_page(bufmgr)?.page_id;
+        for page_id in node_page_ids(bufmgr, root_page_id)? {
+            bufmgr.free_page(page_id)?;
+        }
+        bufmgr.free_page(self.meta_page_id)?;
+        Ok(())
+    }
+
+    /// Walks every node of the tree.
+    pub fn stats(&self, bufmgr: &BufferPoolManager) -> Result<Stats, Error> {
+        let mut stats = Stats::default();
+        let mut pending = vec![self.fetch_root_page(bufmgr)?.page_id];
+        while let Some(page_id) = pending.pop() {
+            let buffer = bufmgr.fetch_page(page_id)?;
+            let page = buffer.read();
+            let node = Node::new(&page[..]);
+            if node.node_type() == NODE_TYPE_BRANCH {
+                stats.branch_pages += 1;
+                pending.extend((0..=node.num_pairs()).map(|i| node.child_at(i)));
+                continue;
+            }
+            stats.leaf_pages += 1;
+            stats.empty_leaves += (node.num_pairs() == 0) as u64;
+            stats.pairs += node.num_pairs() as u64;
+            stats.leaf_bytes_used += (node.capacity() - node.free_space()) as u64;
+            stats.leaf_bytes += node.capacity() as u64;
+        }
+        Ok(stats)
+    }
+
+    /// Removes `key` from the tree. Returns whether it was present.
+    pub fn delete(&self, bufmgr: &BufferPoolManager, key: &[u8]) -> Result<bool, Error> {
+        let mut buffer = self.fetch_root_page(bufmgr)?;
+        loop {
+            let child_page_id = {
+                let page = buffer.read();
+                let node = Node::new(&page[..]);
+                if node.node_type() == NODE_TYPE_LEAF {
+                    break;
+                }
+                node.child_at(node.search_child_idx(key))
+            };
+            buffer = bufmgr.fetch_page(child_page_id)?;
+        }
+        let mut page = buffer.write();
+        let mut leaf = Node::new(&mut page[..]);
+        match leaf.search_slot_id(key) {
+            Ok(slot_id) => {
+                leaf.remove(slot_id);
+                Ok(true)
+            }
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+/// What a walk over every node of a tree found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub leaf_pages: u64,
+    pub branch_pages: u64,
+    /// Leaves without a pair, which deletes leave in the chain.
+    pub empty_leaves: u64,
+    pub pairs: u64,
+    /// Bytes of the leaves taken by pairs and their slots, and the bytes
+    /// they could take.
+    pub leaf_bytes_used: u64,
+    pub leaf_bytes: u64,
+}
+
+impl Stats {
+    /// Fraction of the leaves' space in use.
+    pub fn fill_factor(&self) -> f64 {
+        match self.leaf_bytes {
+            0 => 0.0,
+            total => self.leaf_bytes_used as f64 / total as f64,
+        }
+    }
+
+    /// Fraction of the leaves that are empty.
+    pub fn empty_ratio(&self) -> f64 {
+        match self.leaf_pages {
+            0 => 0.0,
+            leaves => self.empty_leaves as f64 / leaves as f64,
+        }
+    }
+}
+
+/// Nodes a bulk load is filling, one per level with the first key under
+/// each, leaves first.
+struct Loader<'a> {
+    bufmgr: &'a BufferPoolManager,
+    levels: Vec<(Arc<Buffer>, Vec<u8>)>,
+}
+
+impl Loader<'_> {
+    /// Appends a pair to the node of `level`, starting a new one when it is
+    /// filled to [`FILL_FACTOR`], and passes a new node's first key and
+    /// page id up to the level above.
+    fn push(&mut self, level: usize, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        if self.levels.is_empty() {
+            let buffer = self.bufmgr.create_page()?;
+            let mut page = buffer.write();
+            let mut leaf = Node::new(&mut page[..]);
+            leaf.initialize_as_leaf();
+            leaf.insert(0, key, value).unwrap();
+            drop(page);
+            self.levels.push((buffer, key.to_vec()));
+            return Ok(());
+        }
+        let buffer = Arc::clone(&self.levels[level].0);
+        let mut page = buffer.write();
+        let mut node = Node::new(&mut page[..]);
+        let reserved = node.capacity() * (100 - FILL_FACTOR) / 100;
+        let size = node::pair_size(key, value) + node::SLOT_SIZE;
+        let fits = node.free_space() >= size + reserved;
+        if level == 0 {
+            if fits || node.num_pairs() == 0 {
+                node.insert(node.num_pairs(), key, value).unwrap();
+                return Ok(());
+            }
+        } else if fits {
+            // A branch's pair points at the child left of its key, and the
+            // new child becomes the rightmost.
+            let left = node.right_child().to_bytes();
+            node.insert(node.num_pairs(), key, &left).unwrap();
+            node.set_right_child(PageId::from_bytes(value));
+            return Ok(());
+        }
+        let new_buffer = self.bufmgr.create_page()?;
+        {
+            let mut new_page = new_buffer.write();
+            let mut new_node = Node::new(&mut new_page[..]);
+            if level == 0 {
+                new_node.initialize_as_leaf();
+                new_node.set_prev_page_id(Some(buffer.page_id));
+                new_node.insert(0, key, value).unwrap();
+                node.set_next_page_id(Some(new_buffer.page_id));
+            } else {
+                new_node.initialize_as_branch(PageId::from_bytes(value));
+            }
+        }
+        drop(page);
+        let (_, first_key) = std::mem::replace(&mut self.levels[level], (new_buffer, key.to_vec()));
+        if level + 1 == self.levels.len() {
+            // The full node becomes the leftmost child of a new level.
+            let root = self.bufmgr.create_page()?;
+            Node::new(&mut root.write()[..]).initialize_as_branch(buffer.page_id);
+            self.levels.push((root, first_key));
+        }
+        let page_id = self.levels[level].0.page_id;
+        self.push(level + 1, key, &page_id.to_bytes())
+    }
+}
+
+/// Index at which to split an overflowing node: the first pair past half
+/// of the total payload, clamped so both sides keep at least one pair.
+/// The pages of the subtree at `root_page_id`.
+fn node_page_ids(bufmgr: &BufferPoolManager, root_page_id: PageId) -> Result<Vec<PageId>, Error> {
+    let mut page_ids = vec![];
+    let mut pending = vec![root_page_id];
+    while let Some(page_id) = pending.pop() {
+        page_ids.push(page_id);
+        let buffer = bufmgr.fetch_page(page_id)?;
+        let page = buffer.read();
+        let node = Node::new(&page[..]);
+        if node.node_type() == NODE_TYPE_BRANCH {
+            pending.extend((0..=node.num_pairs()).map(|i| node.child_at(i)));
+        }
+    }
+    Ok(page_ids)
+}
+
+fn split_point(pairs: &[Pair]) -> usize {
+    let total: usize = pairs.iter().map(|(k, v)| node::pair_size(k, v)).sum();
+    let mut acc = 0;
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        acc += node::pair_size(key, value);
+        if acc >= total / 2 {
+            return (i + 1).clamp(1, pairs.len() - 2);
+        }
+    }
+    pairs.len() / 2
+}
+
+/// Cursor over the leaf chain in key order.
+pub struct Iter {
+    buffer: Arc<Buffer>,
+    slot_id: usize,
+}
+
+impl Iter {
+    pub fn next(&mut self, bufmgr: &BufferPoolManager) -> Result<Option<Pair>, Error> {
+        loop {
+            let next_page_id = {
+                let page = self.buffer.read();
+                let leaf = Node::new(&page[..]);
+                if self.slot_id < leaf.num_pairs() {
+                    let (key, value) = leaf.pair_at(self.slot_id);
+                    self.slot_id += 1;
+                    return Ok(Some((key.to_vec(), value.to_vec())));
+                }
+                leaf.next_page_id()
+            };
+            let Some(next_page_id) = next_page_id else {
+                return Ok(None);
+            };
+            self.buffer = bufmgr.fetch_page(next_page_id)?;
+            self.slot_id = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::DiskManager;
+    use crate::prop::{self, Rng};
+    use std::collections::btree_map::{BTreeMap, Entry};
+    use tempfile::tempfile;
+
+    fn bufmgr(pool_size: usize) -> BufferPoolManager {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        BufferPoolManager::new(disk, pool_size)
+    }
+
+    #[test]
+    fn test_insert_search() {
+        let bufmgr = bufmgr(10);
+        let btree = BTree::create(&bufmgr).unwrap();
+        btree.insert(&bufmgr, b"kywrd", b"hello").unwrap();
+        btree.insert(&bufmgr, b"abc", b"world").unwrap();
+        assert!(matches!(
+            btree.insert(&bufmgr, b"abc", b"again"),
+            Err(Error::DuplicateKey)
+        ));
+        assert_eq!(
+            Some(b"hello".to_vec()),
+            btree.get(&bufmgr, b"kywrd").unwrap()
+        );
+        assert_eq!(None, btree.get(&bufmgr, b"abd").unwrap());
+        let mut iter = btree
+            .search(&bufmgr, SearchMode::Key(b"abd".to_vec()))
+            .unwrap();
+        assert_eq!(
+            Some((b"kywrd".to_vec(), b"hello".to_vec())),
+            iter.next(&bufmgr).unwrap()
+        );
+        assert_eq!(None, iter.next(&bufmgr).unwrap());
+    }
+
+    #[test]
+    fn test_split_and_delete() {
+        let bufmgr = bufmgr(16);
+        let btree = BTree::create(&bufmgr).unwrap();
+        let mut model = BTreeMap::new();
+        let mut x: u64 = 1;
+        for _ in 0..3000 {
+            x = x
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let key = format!("{:016}", x % 100_000).into_bytes();
+            let value = vec![b'v'; (x % 97) as usize];
+            if let Entry::Vacant(entry) = model.entry(key.clone()) {
+                btree.insert(&bufmgr, &key, &value).unwrap();
+                entry.insert(value);
+            }
+        }
+        let removed: Vec<Vec<u8>> = model.keys().step_by(3).cloned().collect();
+        for key in &removed {
+            assert!(btree.delete(&bufmgr, key).unwrap());
+            assert!(!btree.delete(&bufmgr, key).unwrap());
+            model.remove(key);
+        }
+        let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+        let mut scanned = vec![];
+        while let Some(pair) = iter.next(&bufmgr).unwrap() {
+            scanned.push(pair);
+        }
+        let expected: Vec<_> = model.into_iter().collect();
+        assert_eq!(expected, scanned);
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let bufmgr = bufmgr(16);
+        let btree = BTree::create(&bufmgr).unwrap();
+        let pairs: Vec<Pair> = (0..5000u32)
+            .map(|i| {
+                (
+                    format!("{i:08}").into_bytes(),
+                    vec![b'v'; (i % 53) as usize],
+                )
+            })
+            .collect();
+        for (key, value) in pairs.iter().rev() {
+            btree.insert(&bufmgr, key, value).unwrap();
+        }
+        for (key, _) in pairs.iter().filter(|(key, _)| key[7] != b'0') {
+            btree.delete(&bufmgr, key).unwrap();
+        }
+        let sparse = btree.stats(&bufmgr).unwrap();
+        assert_eq!(500, sparse.pairs);
+        assert!(sparse.fill_factor() < 0.2);
+
+        let kept: Vec<Pair> = pairs.into_iter().step_by(10).collect();
+        btree.rebuild(&bufmgr, kept.clone()).unwrap();
+        let packed = btree.stats(&bufmgr).unwrap();
+        assert_eq!(500, packed.pairs);
+        assert_eq!(0, packed.empty_leaves);
+        assert!(packed.leaf_pages * 5 < sparse.leaf_pages);
+        assert!(packed.fill_factor() > 0.8);
+        let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+        let mut scanned = vec![];
+        while let Some(pair) = iter.next(&bufmgr).unwrap() {
+            scanned.push(pair);
+        }
+        assert_eq!(kept, scanned);
+        assert_eq!(
+            Some(kept[77].1.clone()),
+            btree.get(&bufmgr, &kept[77].0).unwrap()
+        );
+        btree.insert(&bufmgr, b"00000001", b"new").unwrap();
+        assert_eq!(
+            Some(b"new".to_vec()),
+            btree.get(&bufmgr, b"00000001").unwrap()
+        );
+
+        let duplicate = vec![(b"a".to_vec(), vec![]), (b"a".to_vec(), vec![])];
+        assert!(matches!(
+            btree.rebuild(&bufmgr, duplicate),
+            Err(Error::DuplicateKey)
+        ));
+        assert_eq!(501, btree.stats(&bufmgr).unwrap().pairs);
+        // Enough leaves for branches over branches.
+        let wide: Vec<Pair> = (0..5000u32)
+            .map(|i| (format!("{i:08}").into_bytes(), vec![b'w'; 300]))
+            .collect();
+        btree.rebuild(&bufmgr, wide.clone()).unwrap();
+        for (key, value) in wide.iter().step_by(97) {
+            assert_eq!(Some(value.clone()), btree.get(&bufmgr, key).unwrap());
+        }
+        assert!(btree.stats(&bufmgr).unwrap().branch_pages > 1);
+        // The nodes a rebuild replaces are freed for the next to reuse.
+        bufmgr.set_free_list(bufmgr.create_page().unwrap().page_id);
+        btree.rebuild(&bufmgr, wide.clone()).unwrap();
+        let pages = bufmgr.num_pages();
+        btree.rebuild(&bufmgr, wide).unwrap();
+        assert_eq!(pages, bufmgr.num_pages());
+        btree.rebuild(&bufmgr, vec![]).unwrap();
+        assert_eq!(
+            None,
+            btree
+                .search(&bufmgr, SearchMode::Start)
+                .unwrap()
+                .next(&bufmgr)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_too_large() {
+        let bufmgr = bufmgr(10);
+        let btree = BTree::create(&bufmgr).unwrap();
+        let value = vec![0u8; PAGE_SIZE];
+        assert!(matches!(
+            btree.insert(&bufmgr, b"key", &value),
+            Err(Error::TooLarge(_))
+        ));
+    }
+
+    /// Keys come from a small range so that operations meet.
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(u16, usize),
+        Delete(u16),
+        /// Replaces the value, deleting and reinserting as index
+        /// maintenance does.
+        Update(u16, usize),
+    }
+
+    fn key(k: u16) -> Vec<u8> {
+        format!("{k:05}").into_bytes()
+    }
+
+    fn value(k: u16, len: usize) -> Vec<u8> {
+        vec![k as u8; len]
+    }
+
+    fn generate(rng: &mut Rng) -> Vec<Op> {
+        let keys = 1 + rng.below(2000) as u16;
+        (0..rng.below(3000))
+            .map(|_| {
+                let k = rng.below(keys as usize) as u16;
+                match rng.below(5) {
+                    0 => Op::Delete(k),
+                    1 => Op::Update(k, rng.below(400)),
+                    _ => Op::Insert(k, rng.below(400)),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_against_model() {
+        prop::check(64, generate, |ops| {
+            let bufmgr = bufmgr(16);
+            let btree = BTree::create(&bufmgr).unwrap();
+            let mut model = BTreeMap::new();
+            for op in ops {
+                match *op {
+                    Op::Insert(k, len) => {
+                        let inserted = btree.insert(&bufmgr, &key(k), &value(k, len));
+                        match (model.entry(key(k)), inserted) {
+                            (Entry::Vacant(entry), Ok(())) => {
+                                entry.insert(value(k, len));
+                            }
+                            (Entry::Occupied(_), Err(Error::DuplicateKey)) => {}
+                            (_, inserted) => return Err(format!("{op:?}: {inserted:?}")),
+                        }
+                    }
+                    Op::Delete(k) => {
+                        let deleted = btree.delete(&bufmgr, &key(k)).unwrap();
+                        if deleted != model.remove(&key(k)).is_some() {
+                            return Err(format!("{op:?}: deleted is {deleted}"));
+                        }
+                    }
+                    Op::Update(k, len) => {
+                        if btree.delete(&bufmgr, &key(k)).unwrap() {
+                            btree.insert(&bufmgr, &key(k), &value(k, len)).unwrap();
+                        }
+                        if let Some(v) = model.get_mut(&key(k)) {
+                            *v = value(k, len);
+                        }
+                    }
+                }
+            }
+            for op in ops {
+                let (Op::Insert(k, _) | Op::Delete(k) | Op::Update(k, _)) = *op;
+                let found = btree.get(&bufmgr, &key(k)).unwrap();
+                if found.as_ref() != model.get(&key(k)) {
+                    return Err(format!("get {k}: {found:?}"));
+                }
+            }
+            let mut iter = btree.search(&bufmgr, SearchMode::Start).unwrap();
+            let mut scanned = vec![];
+            while let Some(pair) = iter.next(&bufmgr).unwrap() {
+                scanned.push(pair);
+            }
+            if !scanned.iter().map(|(k, v)| (k, v)).eq(model.iter()) {
+                return Err(format!(
+                    "scan returned {} pairs of {}",
+                    scanned.len(),
+                    model.len()
+                ));
+            }
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,189 @@
+//! On-page layout of B+Tree nodes.
+//!
+//! Every node starts with a 24-byte header: a node type byte followed by two
+//! page ids (`prev`/`next` for leaves, `right_child` for branches). The rest
+//! of the page is a slotted area of `(key, value)` pairs kept in key order.
+
+use super::Pair;
+use crate::disk::PageId;
+use crate::slotted::Slotted;
+
+pub const NODE_TYPE_LEAF: u8 = b'L';
+pub const NODE_TYPE_BRANCH: u8 = b'B';
+
+pub(crate) const HEADER_SIZE: usize = 24;
+pub(crate) const PAIR_HEADER_SIZE: usize = 2;
+/// Bytes each pair takes in the slot array.
+pub(crate) const SLOT_SIZE: usize = crate::slotted::POINTER_SIZE;
+
+pub fn node_type(page: &[u8]) -> u8 {
+    page[0]
+}
+
+pub struct Node<B> {
+    page: B,
+}
+
+impl<B: AsRef<[u8]>> Node<B> {
+    pub fn new(page: B) -> Self {
+        Self { page }
+    }
+
+    pub fn node_type(&self) -> u8 {
+        node_type(self.page.as_ref())
+    }
+
+    /// Previous leaf in the leaf chain.
+    pub fn prev_page_id(&self) -> Option<PageId> {
+        PageId::from_bytes(&self.page.as_ref()[8..16]).valid()
+    }
+
+    /// Next leaf in the leaf chain.
+    pub fn next_page_id(&self) -> Option<PageId> {
+        PageId::from_bytes(&self.page.as_ref()[16..24]).valid()
+    }
+
+    /// Child holding keys greater than or equal to every key of a branch.
+    pub fn right_child(&self) -> PageId {
+        PageId::from_bytes(&self.page.as_ref()[8..16])
+    }
+
+    pub fn num_pairs(&self) -> usize {
+        self.body().num_slots()
+    }
+
+    pub fn key_at(&self, slot_id: usize) -> &[u8] {
+        self.pair_at(slot_id).0
+    }
+
+    pub fn pair_at(&self, slot_id: usize) -> (&[u8], &[u8]) {
+        let range = self.body().data_range(slot_id);
+        let data = &self.page.as_ref()[HEADER_SIZE..][range];
+        let key_len = u16::from_le_bytes([data[0], data[1]]) as usize;
+        let key = &data[PAIR_HEADER_SIZE..PAIR_HEADER_SIZE + key_len];
+        let value = &data[PAIR_HEADER_SIZE + key_len..];
+        (key, value)
+    }
+
+    pub fn pairs(&self) -> Vec<Pair> {
+        (0..self.num_pairs())
+            .map(|slot_id| {
+                let (key, value) = self.pair_at(slot_id);
+                (key.to_vec(), value.to_vec())
+            })
+            .collect()
+    }
+
+    pub fn search_slot_id(&self, key: &[u8]) -> Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.num_pairs());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            match self.key_at(mid).cmp(key) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    /// Index of the branch child that covers `key`.
+    pub fn search_child_idx(&self, key: &[u8]) -> usize {
+        match self.search_slot_id(key) {
+            Ok(slot_id) => slot_id + 1,
+            Err(slot_id) => slot_id,
+        }
+    }
+
+    pub fn child_at(&self, child_idx: usize) -> PageId {
+        if child_idx == self.num_pairs() {
+            self.right_child()
+        } else {
+            PageId::from_bytes(self.pair_at(child_idx).1)
+        }
+    }
+
+    /// Bytes of the slotted area not taken by pairs or their slots.
+    pub fn free_space(&self) -> usize {
+        self.body().free_space()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.body().capacity()
+    }
+
+    fn body(&self) -> Slotted<&[u8]> {
+        Slotted::new(&self.page.as_ref()[HEADER_SIZE..])
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Node<B> {
+    pub fn initialize_as_leaf(&mut self) {
+        self.initialize(NODE_TYPE_LEAF);
+        self.set_prev_page_id(None);
+        self.set_next_page_id(None);
+    }
+
+    pub fn initialize_as_branch(&mut self, right_child: PageId) {
+        self.initialize(NODE_TYPE_BRANCH);
+        self.set_right_child(right_child);
+    }
+
+    pub fn set_prev_page_id(&mut self, page_id: Option<PageId>) {
+        self.page.as_mut()[8..16].copy_from_slice(&PageId::from(page_id).to_bytes());
+    }
+
+    pub fn set_next_page_id(&mut self, page_id: Option<PageId>) {
+        self.page.as_mut()[16..24].copy_from_slice(&PageId::from(page_id).to_bytes());
+    }
+
+    pub fn set_right_child(&mut self, page_id: PageId) {
+        self.page.as_mut()[8..16].copy_from_slice(&page_id.to_bytes());
+    }
+
+    /// Inserts a pair at `slot_id`; returns `None` when the node is full.
+    pub fn insert(&mut self, slot_id: usize, key: &[u8], value: &[u8]) -> Option<()> {
+        let mut body = self.body_mut();
+        body.insert(slot_id, pair_size(key, value))?;
+        let data = body.data_mut(slot_id);
+        data[..PAIR_HEADER_SIZE].copy_from_slice(&(key.len() as u16).to_le_bytes());
+        data[PAIR_HEADER_SIZE..PAIR_HEADER_SIZE + key.len()].copy_from_slice(key);
+        data[PAIR_HEADER_SIZE + key.len()..].copy_from_slice(value);
+        Some(())
+    }
+
+    pub fn remove(&mut self, slot_id: usize) {
+        self.body_mut().remove(slot_id);
+    }
+
+    /// Replaces every pair of the node, keeping its header.
+    pub fn set_pairs(&mut self, pairs: &[Pair]) {
+        self.body_mut().initialize();
+        for (slot_id, (key, value)) in pairs.iter().enumerate() {
+            self.insert(slot_id, key, value)
+                .expect("split halves must fit in a page");
+        }
+    }
+
+    fn initialize(&mut self, node_type: u8) {
+        let page = self.page.as_mut();
+        page[..HEADER_SIZE].fill(0);
+        page[0] = node_type;
+        self.body_mut().initialize();
+    }
+
+    fn body_mut(&mut self) -> Slotted<&mut [u8]> {
+        Slotted::new(&mut self.page.as_mut()[HEADER_SIZE..])
+    }
+}
+
+/// Bytes a pair occupies in the slotted area, excluding its slot pointer.
+pub fn pair_size(key: &[u8], value: &[u8]) -> usize {
+    PAIR_HEADER_SIZE + key.len() + value.len()
+}
+
+/// Largest pair accepted by the tree: small enough that any node holds at
+/// least four pairs, so a split always leaves both halves non-empty.
+pub fn max_pair_size(page_size: usize) -> usize {
+    (page_size - HEADER_SIZE - 4) / 4 - 4
+}
@@ -0,0 +1,1136 @@
+mod frozen;
+mod history;
+mod latch;
+mod lineage;
+mod savepoint;
+
+use std::collections::HashMap;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::panic::Location;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLockReadGuard, RwLockWriteGuard, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::disk::{DiskManager, DiskStats, IoPriority, PageId, PAGE_SIZE};
+use crate::trace::{self, Event};
+pub use frozen::Frozen;
+use frozen::Registry;
+pub use history::{History, PagesAsOf, Point as HistoryPoint};
+use latch::Latch;
+use lineage::Lineage;
+pub use lineage::{Entry as LineageEntry, Operation as LineageOperation};
+use savepoint::Slot;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("no free buffer available in buffer pool")]
+    NoFreeBuffer,
+    #[error("transaction changed more pages than the buffer pool holds")]
+    TransactionTooLarge,
+    #[error("cannot flush pages during a transaction")]
+    InTransaction,
+}
+
+pub type Page = [u8; PAGE_SIZE];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferId(usize);
+
+/// The number of the file a pool was made with, among those
+/// [attached](BufferPoolManager::attach) to it.
+const MAIN_FILE: usize = 0;
+
+/// A page image cached in the pool. Holding an `Arc<Buffer>` pins the page,
+/// and reading or writing it takes its [latch](latch).
+pub struct Buffer {
+    pub page_id: PageId,
+    /// The file the page is of.
+    file: usize,
+    page: Latch,
+    is_dirty: AtomicBool,
+    lineage: Option<Arc<Lineage>>,
+    history: Option<Arc<History>>,
+    frozen: Registry,
+    savepoint: Slot,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self {
+            page_id: Default::default(),
+            file: MAIN_FILE,
+            page: Latch::new(&Arc::default()),
+            is_dirty: AtomicBool::new(false),
+            lineage: None,
+            history: None,
+            frozen: Registry::default(),
+            savepoint: Slot::default(),
+        }
+    }
+}
+
+impl Buffer {
+    /// Latches the page for reading, alongside any other readers.
+    pub fn read(&self) -> PageRef<'_> {
+        PageRef(self.page.shared())
+    }
+
+    /// Latches the page for writing, alone, and marks it dirty.
+    #[track_caller]
+    pub fn write(&self) -> PageMut<'_> {
+        let guard = self.page.exclusive();
+        if let Some(history) = self.history.as_ref().filter(|_| self.file == MAIN_FILE) {
+            history.record(self.page_id, &guard);
+        }
+        for frozen in self.frozen.lock().unwrap().iter() {
+            if let Some(frozen) = frozen.upgrade() {
+                frozen.record(self.file, self.page_id, &guard);
+            }
+        }
+        if let Some(savepoint) = &mut *self.savepoint.lock().unwrap() {
+            savepoint.record(self.file, self.page_id, &guard);
+        }
+        self.is_dirty.store(true, Ordering::Release);
+        if let Some(lineage) = self.lineage.as_ref().filter(|_| self.file == MAIN_FILE) {
+            lineage.record(self.page_id, lineage::Operation::Modify, Location::caller());
+        }
+        PageMut(guard)
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.is_dirty.load(Ordering::Acquire)
+    }
+}
+
+pub struct PageRef<'a>(RwLockReadGuard<'a, Box<Page>>);
+
+impl Deref for PageRef<'_> {
+    type Target = Page;
+
+    fn deref(&self) -> &Page {
+        &self.0
+    }
+}
+
+pub struct PageMut<'a>(RwLockWriteGuard<'a, Box<Page>>);
+
+impl Deref for PageMut<'_> {
+    type Target = Page;
+
+    fn deref(&self) -> &Page {
+        &self.0
+    }
+}
+
+impl DerefMut for PageMut<'_> {
+    fn deref_mut(&mut self) -> &mut Page {
+        &mut self.0
+    }
+}
+
+#[derive(Default)]
+struct Frame {
+    usage_count: u64,
+    buffer: Arc<Buffer>,
+}
+
+impl Frame {
+    fn is_pinned(&mut self) -> bool {
+        Arc::get_mut(&mut self.buffer).is_none()
+    }
+}
+
+struct BufferPool {
+    buffers: Vec<Frame>,
+    next_victim_id: BufferId,
+}
+
+impl BufferPool {
+    fn new(
+        pool_size: usize,
+        lineage: Option<&Arc<Lineage>>,
+        frozen: &Registry,
+        savepoint: &Slot,
+        latch_waits: &Arc<AtomicU64>,
+    ) -> Self {
+        let mut buffers = vec![];
+        buffers.resize_with(pool_size, || Frame {
+            usage_count: 0,
+            buffer: Arc::new(Buffer {
+                page: Latch::new(latch_waits),
+                lineage: lineage.cloned(),
+                frozen: Arc::clone(frozen),
+                savepoint: Arc::clone(savepoint),
+                ..Buffer::default()
+            }),
+        });
+        Self {
+            buffers,
+            next_victim_id: Default::default(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Clock-sweep replacement: returns an unpinned frame whose usage count
+    /// dropped to zero, or `None` when every frame is pinned. With
+    /// `keep_dirty`, dirty frames count as pinned.
+    fn evict(&mut self, keep_dirty: bool) -> Option<BufferId> {
+        let pool_size = self.size();
+        let mut consecutive_pinned = 0;
+        loop {
+            let next_victim_id = self.next_victim_id;
+            let frame = &mut self.buffers[next_victim_id.0];
+            if frame.is_pinned() || (keep_dirty && frame.buffer.is_dirty()) {
+                consecutive_pinned += 1;
+                if consecutive_pinned >= pool_size {
+                    return None;
+                }
+            } else {
+                if frame.usage_count == 0 {
+                    return Some(next_victim_id);
+                }
+                frame.usage_count -= 1;
+                consecutive_pinned = 0;
+            }
+            self.next_victim_id = BufferId((next_victim_id.0 + 1) % pool_size);
+        }
+    }
+}
+
+struct Inner {
+    /// The files whose pages the pool holds, by number; those detached
+    /// are gone.
+    files: Vec<Option<DiskManager>>,
+    pool: BufferPool,
+    page_table: HashMap<(usize, PageId), BufferId>,
+    /// The number of pages in each file when the running transaction
+    /// began.
+    transaction: Option<Vec<u64>>,
+    /// The page heading the list of free pages of each file that keeps
+    /// one; see [`BufferPoolManager::set_free_list`].
+    free_lists: HashMap<usize, PageId>,
+    stats: BufferStats,
+    lineage: Option<Arc<Lineage>>,
+    history: Option<Arc<History>>,
+    /// Shared with every buffer.
+    frozen: Registry,
+    /// Shared with every buffer too.
+    savepoint: Slot,
+    latch_waits: Arc<AtomicU64>,
+}
+
+/// What a [`BufferPoolManager`] has done since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferStats {
+    /// Fetches of a page the pool already held.
+    pub hits: u64,
+    /// Fetches that read the page from the file.
+    pub misses: u64,
+    pub evictions: u64,
+    /// Evictions that wrote the page back first.
+    pub dirty_evictions: u64,
+    /// Times a thread had to wait for another to let go of a page's latch.
+    pub latch_waits: u64,
+    pub disk: DiskStats,
+}
+
+impl BufferStats {
+    /// The share of fetches that found their page in the pool, or `None`
+    /// before the first fetch.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let fetches = self.hits + self.misses;
+        (fetches > 0).then(|| self.hits as f64 / fetches as f64)
+    }
+}
+
+impl Inner {
+    fn disk(&self, file: usize) -> &DiskManager {
+        self.files[file]
+            .as_ref()
+            .expect("a pool is detached only as it drops")
+    }
+
+    fn disk_mut(&mut self, file: usize) -> &mut DiskManager {
+        self.files[file]
+            .as_mut()
+            .expect("a pool is detached only as it drops")
+    }
+
+    /// Lineage covers only the main file.
+    fn record(
+        &self,
+        file: usize,
+        page_id: PageId,
+        operation: LineageOperation,
+        location: &'static Location,
+    ) {
+        if let Some(lineage) = self.lineage.as_ref().filter(|_| file == MAIN_FILE) {
+            lineage.record(page_id, operation, location);
+        }
+    }
+
+    /// Writes back the dirty pages of `file` and syncs it, unless a
+    /// transaction is running, then drops its pages from the pool and
+    /// closes it. If a write or the sync fails, the file stays attached
+    /// with the pages not yet written still dirty.
+    fn detach(&mut self, file: usize) -> Result<(), Error> {
+        let Some(disk) = self.files[file].as_mut() else {
+            return Ok(());
+        };
+        if self.transaction.is_none() {
+            let _batch = disk.begin_batch()?;
+            let mut data = vec![0u8; PAGE_SIZE];
+            for (&(page_file, page_id), buffer_id) in &self.page_table {
+                let buffer = &self.pool.buffers[buffer_id.0].buffer;
+                if page_file != file || !buffer.is_dirty() {
+                    continue;
+                }
+                {
+                    let page = buffer.page.shared();
+                    data.copy_from_slice(&page[..]);
+                    buffer.is_dirty.store(false, Ordering::Release);
+                }
+                if let Err(err) = disk.write_page_data(page_id, &data) {
+                    buffer.is_dirty.store(true, Ordering::Release);
+                    return Err(err.into());
+                }
+            }
+            disk.sync()?;
+        }
+        self.files[file] = None;
+        self.free_lists.remove(&file);
+        self.page_table.retain(|&(page_file, _), buffer_id| {
+            if page_file != file {
+                return true;
+            }
+            let frame = &mut self.pool.buffers[buffer_id.0];
+            frame.buffer.is_dirty.store(false, Ordering::Release);
+            frame.usage_count = 0;
+            false
+        });
+        Ok(())
+    }
+
+    /// Picks a victim frame and writes its page back if it is dirty, for
+    /// the caller of the pool at `location`.
+    fn prepare_victim(&mut self, location: &'static Location) -> Result<BufferId, Error> {
+        let in_transaction = self.transaction.is_some();
+        let buffer_id = self.pool.evict(in_transaction).ok_or(if in_transaction {
+            Error::TransactionTooLarge
+        } else {
+            Error::NoFreeBuffer
+        })?;
+        let frame = &mut self.pool.buffers[buffer_id.0];
+        let evict_page_id = frame.buffer.page_id;
+        let evict_file = frame.buffer.file;
+        let buffer = Arc::get_mut(&mut frame.buffer).unwrap();
+        let dirty = buffer.is_dirty.load(Ordering::Acquire);
+        if evict_page_id.valid().is_some() {
+            self.stats.evictions += 1;
+            self.stats.dirty_evictions += u64::from(dirty);
+            if trace::enabled() {
+                trace::emit(&Event::Eviction {
+                    page_id: evict_page_id,
+                    dirty,
+                });
+            }
+            if let Some(lineage) = self.lineage.as_ref().filter(|_| evict_file == MAIN_FILE) {
+                let operation = LineageOperation::Evict { dirty };
+                lineage.record(evict_page_id, operation, location);
+            }
+        }
+        // The page of a file detached since has nowhere to go.
+        if let Some(disk) = self.files[evict_file].as_mut().filter(|_| dirty) {
+            let _batch = disk.begin_batch()?;
+            disk.write_page_data(evict_page_id, &buffer.page.get_mut()[..])?;
+        }
+        // A frame dropped by a rollback may have lost its page to another.
+        let key = (evict_file, evict_page_id);
+        if self.page_table.get(&key) == Some(&buffer_id) {
+            self.page_table.remove(&key);
+        }
+        Ok(buffer_id)
+    }
+}
+
+/// Writes back at most `limit` dirty pages of the pool behind `inner`, as
+/// [`BufferPoolManager::write_back`] does, waiting on the scheduler of its
+/// main file before each write of `priority`. A transaction that begins
+/// meanwhile stops it, leaving the pages it has not written dirty.
+fn write_back(
+    inner: &Mutex<Inner>,
+    file: usize,
+    limit: usize,
+    priority: IoPriority,
+    location: &'static Location,
+) -> Result<usize, Error> {
+    let lock = || inner.lock().unwrap();
+    let (buffers, scheduler): (Vec<Arc<Buffer>>, _) = {
+        let inner = lock();
+        if inner.transaction.is_some() {
+            return Err(Error::InTransaction);
+        }
+        let mut frames: Vec<_> = (inner.page_table.values())
+            .map(|buffer_id| &inner.pool.buffers[buffer_id.0])
+            .filter(|frame| frame.buffer.is_dirty())
+            .collect();
+        if frames.len() > limit {
+            frames.sort_by_key(|frame| frame.usage_count);
+            frames.truncate(limit);
+        }
+        let buffers = (frames.into_iter())
+            .map(|frame| Arc::clone(&frame.buffer))
+            .collect();
+        (buffers, Arc::clone(inner.disk(MAIN_FILE).scheduler()))
+    };
+    // A snapshot of a file sees all the pages written back here or none,
+    // and so none of a transaction's until it has committed.
+    let _batches = {
+        let inner = lock();
+        (inner.files.iter().flatten())
+            .map(DiskManager::begin_batch)
+            .collect::<io::Result<Vec<_>>>()?
+    };
+    let mut written = 0;
+    let mut files = vec![file];
+    // Page latches are never taken while holding the pool lock, so a
+    // thread that holds a page and fetches another cannot deadlock us.
+    let mut data = vec![0u8; PAGE_SIZE];
+    for buffer in buffers {
+        scheduler.admit(priority);
+        {
+            let page = buffer.page.shared();
+            data.copy_from_slice(&page[..]);
+            buffer.is_dirty.store(false, Ordering::Release);
+        }
+        let mut inner = lock();
+        if inner.transaction.is_some() {
+            buffer.is_dirty.store(true, Ordering::Release);
+            return Err(Error::InTransaction);
+        }
+        let Some(disk) = inner.files[buffer.file].as_mut() else {
+            continue;
+        };
+        // Dirty again, so that the page is written later rather than
+        // dropped from the pool as if it had been.
+        if let Err(err) = disk.write_page_data(buffer.page_id, &data) {
+            buffer.is_dirty.store(true, Ordering::Release);
+            return Err(err.into());
+        }
+        written += 1;
+        inner.record(
+            buffer.file,
+            buffer.page_id,
+            LineageOperation::Flush,
+            location,
+        );
+        if !files.contains(&buffer.file) {
+            files.push(buffer.file);
+        }
+    }
+    let mut inner = lock();
+    for file in files {
+        if let Some(disk) = inner.files[file].as_mut() {
+            disk.sync()?;
+        }
+    }
+    Ok(written)
+}
+
+/// Writes back the dirty pages of a pool at background priority, which
+/// yields to the pages its sessions read; see
+/// [`IoScheduler`](crate::disk::IoScheduler). It keeps
+/// the pool from filling up with dirty pages that a commit, an eviction
+/// or closing the database would otherwise have to write all at once,
+/// and does nothing once the pool is gone.
+#[derive(Clone)]
+pub struct BackgroundWriter {
+    inner: Weak<Mutex<Inner>>,
+}
+
+impl BackgroundWriter {
+    /// Writes back at most `limit` dirty pages, as
+    /// [`BufferPoolManager::write_back`] does, and returns how many it
+    /// wrote. Fails with [`Error::InTransaction`] while a transaction
+    /// runs.
+    #[track_caller]
+    pub fn write_back(&self, limit: usize) -> Result<usize, Error> {
+        let location = Location::caller();
+        let Some(inner) = self.inner.upgrade() else {
+            return Ok(0);
+        };
+        write_back(&inner, MAIN_FILE, limit, IoPriority::Background, location)
+    }
+}
+
+/// Caches database pages in a fixed number of frames.
+///
+/// The manager is `Sync`; page contents are protected by a per-buffer
+/// [latch](latch) taken through [`Buffer::read`] and [`Buffer::write`].
+///
+/// Between [`BufferPoolManager::begin`] and a commit or rollback, dirty
+/// pages are never written back (no-steal), so the file keeps the state
+/// the transaction began with; a commit writes them all and syncs (force).
+/// A transaction can change only as many pages as the pool holds, and
+/// can go back to a [savepoint](Self::savepoint) within it.
+/// There is no write-ahead log of records: what a commit writes are the
+/// pages themselves, which stay uncompressed so that they can be read in
+/// place. A [`DiskManager`] with full-page writes puts them whole in a
+/// journal first, and may compress them there; see
+/// [`DiskManager::with_journal_compression`].
+///
+/// Other files can be [attached](Self::attach) to a pool, which then holds
+/// their pages in the same frames. Each file is reached through a manager
+/// of its own, but transactions, flushes and the stats of the pool cover
+/// them all. Lineage and history cover only the file the pool was made
+/// with.
+pub struct BufferPoolManager {
+    inner: Arc<Mutex<Inner>>,
+    /// The file this manager reads and creates pages in.
+    file: usize,
+}
+
+/// The first page and the number of pages on a free list, from the page
+/// heading it.
+pub fn read_free_list(list: &[u8]) -> (Option<PageId>, u64) {
+    let head = PageId::from_bytes(&list[..8]);
+    let count = u64::from_le_bytes(list[8..16].try_into().unwrap());
+    // A list page never written holds zeros, and page 0 is never free.
+    (head.valid().filter(|&head| head != PageId(0)), count)
+}
+
+impl BufferPoolManager {
+    pub fn new(disk: DiskManager, pool_size: usize) -> Self {
+        Self::with_lineage(disk, pool_size, 0)
+    }
+
+    /// A pool that records the last `depth` operations on each page, for
+    /// debugging; see [`lineage`](Self::lineage). A depth of 0 records
+    /// nothing, as [`new`](Self::new) does.
+    pub fn with_lineage(disk: DiskManager, pool_size: usize, depth: usize) -> Self {
+        let lineage = (depth > 0).then(|| Arc::new(Lineage::new(depth)));
+        let frozen = Registry::default();
+        let savepoint = Slot::default();
+        let latch_waits = Arc::default();
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                files: vec![Some(disk)],
+                pool: BufferPool::new(
+                    pool_size,
+                    lineage.as_ref(),
+                    &frozen,
+                    &savepoint,
+                    &latch_waits,
+                ),
+                page_table: HashMap::new(),
+                transaction: None,
+                free_lists: HashMap::new(),
+                stats: BufferStats::default(),
+                lineage,
+                history: None,
+                frozen,
+                savepoint,
+                latch_waits,
+            })),
+            file: MAIN_FILE,
+        }
+    }
+
+    /// A manager of the pages of `disk` in the frames of this pool, until
+    /// [detached](Self::detach) or dropped. A file attached during a
+    /// transaction is part of it from then on, so a rollback drops the
+    /// pages created in it since.
+    pub fn attach(&self, disk: DiskManager) -> Result<BufferPoolManager, Error> {
+        let mut inner = self.lock();
+        let num_pages = disk.num_pages();
+        if let Some(transaction) = &mut inner.transaction {
+            transaction.push(num_pages);
+        }
+        if let Some(savepoint) = inner.savepoint.lock().unwrap().as_mut() {
+            savepoint.attached(num_pages);
+        }
+        let scheduler = Arc::clone(inner.disk(MAIN_FILE).scheduler());
+        inner.files.push(Some(disk.with_scheduler(scheduler)));
+        Ok(Self {
+            inner: Arc::clone(&self.inner),
+            file: inner.files.len() - 1,
+        })
+    }
+
+    /// The file as it was at the point numbered `lsn` of the pool's
+    /// history, if that is retained, to open a read-only
+    /// [`DiskManager`] over.
+    pub fn pages_as_of(&self, lsn: u64) -> Option<PagesAsOf> {
+        let history = self.history()?;
+        let point = history.point(lsn)?;
+        Some(PagesAsOf {
+            inner: Arc::clone(&self.inner),
+            history,
+            point,
+        })
+    }
+
+    /// Keeps past versions of pages for `retention`, so that the pages as
+    /// of each point [`mark_history`](Self::mark_history) marks can be
+    /// read again; see [`History`].
+    pub fn with_history(mut self, retention: Duration) -> Self {
+        let inner = Arc::get_mut(&mut self.inner).expect("a new pool has no files attached");
+        let inner = inner.get_mut().unwrap();
+        let history = Arc::new(History::new(retention, inner.disk(MAIN_FILE).num_pages()));
+        for frame in &mut inner.pool.buffers {
+            let buffer = Arc::get_mut(&mut frame.buffer).expect("a new pool pins no pages");
+            buffer.history = Some(Arc::clone(&history));
+        }
+        inner.history = Some(history);
+        self
+    }
+
+    /// The history the pool keeps, if it was made
+    /// [`with_history`](Self::with_history), of the file it was made with.
+    pub fn history(&self) -> Option<Arc<History>> {
+        self.lock()
+            .history
+            .clone()
+            .filter(|_| self.file == MAIN_FILE)
+    }
+
+    /// Marks the pages as they are now as a point of the history, and
+    /// returns its log sequence number; 0 without history.
+    pub fn mark_history(&self) -> u64 {
+        let inner = self.lock();
+        match &inner.history {
+            Some(history) => history.mark(inner.disk(MAIN_FILE).num_pages()),
+            None => 0,
+        }
+    }
+
+    /// Drops the past versions of pages that fell out of the history's
+    /// retention window, which otherwise wait for the next mark, and
+    /// returns how many it dropped; 0 without history.
+    pub fn prune_history(&self) -> usize {
+        self.lock()
+            .history
+            .as_ref()
+            .map_or(0, |history| history.prune())
+    }
+
+    /// The operations recorded on `page_id`, oldest first; none unless the
+    /// pool was made [`with_lineage`](Self::with_lineage).
+    pub fn lineage(&self, page_id: PageId) -> Vec<LineageEntry> {
+        match &self.lock().lineage {
+            Some(lineage) if self.file == MAIN_FILE => lineage.entries(page_id),
+            _ => vec![],
+        }
+    }
+
+    /// The [`lineage`](Self::lineage) of `page_id`, an entry a line.
+    pub fn format_lineage(&self, page_id: PageId) -> String {
+        let mut out = format!("lineage of page {}:\n", page_id.to_u64());
+        for entry in self.lineage(page_id) {
+            out.push_str(&format!("  {entry}\n"));
+        }
+        out
+    }
+
+    pub fn pool_size(&self) -> usize {
+        self.lock().pool.size()
+    }
+
+    /// Number of pages in the database file, counting ones still cached.
+    pub fn num_pages(&self) -> u64 {
+        self.lock().disk(self.file).num_pages()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.lock().disk(self.file).is_read_only()
+    }
+
+    pub fn stats(&self) -> BufferStats {
+        let inner = self.lock();
+        BufferStats {
+            latch_waits: inner.latch_waits.load(Ordering::Relaxed),
+            disk: inner.disk(self.file).stats(),
+            ..inner.stats
+        }
+    }
+
+    #[track_caller]
+    pub fn fetch_page(&self, page_id: PageId) -> Result<Arc<Buffer>, Error> {
+        let location = Location::caller();
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        let file = self.file;
+        if let Some(&buffer_id) = inner.page_table.get(&(file, page_id)) {
+            inner.record(
+                file,
+                page_id,
+                LineageOperation::Fetch { hit: true },
+                location,
+            );
+            let frame = &mut inner.pool.buffers[buffer_id.0];
+            frame.usage_count += 1;
+            inner.stats.hits += 1;
+            return Ok(Arc::clone(&frame.buffer));
+        }
+        inner.stats.misses += 1;
+        let buffer_id = inner.prepare_victim(location)?;
+        let frame = &mut inner.pool.buffers[buffer_id.0];
+        {
+            let buffer = Arc::get_mut(&mut frame.buffer).unwrap();
+            buffer.page_id = page_id;
+            buffer.file = file;
+            buffer.is_dirty.store(false, Ordering::Release);
+            let disk = inner.files[file].as_mut().unwrap();
+            if let Err(err) = disk.read_page_data(page_id, &mut buffer.page.get_mut()[..]) {
+                buffer.page_id = PageId::INVALID_PAGE_ID;
+                return Err(err.into());
+            }
+        }
+        frame.usage_count = 1;
+        let buffer = Arc::clone(&frame.buffer);
+        inner.page_table.insert((file, page_id), buffer_id);
+        inner.record(
+            file,
+            page_id,
+            LineageOperation::Fetch { hit: false },
+            location,
+        );
+        Ok(buffer)
+    }
+
+    /// A page of zeros, one off the free list if there is any, else one
+    /// past the end of the file.
+    #[track_caller]
+    pub fn create_page(&self) -> Result<Arc<Buffer>, Error> {
+        let location = Location::caller();
+        if let Some(buffer) = self.reuse_page(location)? {
+            return Ok(buffer);
+        }
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        let buffer_id = inner.prepare_victim(location)?;
+        let file = self.file;
+        let page_id = inner.disk_mut(file).allocate_page();
+        let frame = &mut inner.pool.buffers[buffer_id.0];
+        {
+            let buffer = Arc::get_mut(&mut frame.buffer).unwrap();
+            buffer.page_id = page_id;
+            buffer.file = file;
+            buffer.is_dirty.store(true, Ordering::Release);
+            buffer.page.get_mut().fill(0);
+        }
+        frame.usage_count = 1;
+        let buffer = Arc::clone(&frame.buffer);
+        inner.page_table.insert((file, page_id), buffer_id);
+        inner.record(file, page_id, LineageOperation::Create, location);
+        Ok(buffer)
+    }
+
+    /// Keeps the pages [freed](Self::free_page) in this manager's file on
+    /// a list headed by the page `list`, for [`create_page`](Self::create_page)
+    /// to hand out again before it grows the file. The list page holds the
+    /// first free page and the number of them, and each free page the
+    /// next one; a list page of zeros is taken for an empty list.
+    pub fn set_free_list(&self, list: PageId) {
+        self.lock().free_lists.insert(self.file, list);
+    }
+
+    /// The page heading the free list, if the file keeps one.
+    pub fn free_list(&self) -> Option<PageId> {
+        self.lock().free_lists.get(&self.file).copied()
+    }
+
+    /// Puts `page_id`, which nothing may use any more, on the free list.
+    /// A file without one leaves the page unused until the file is
+    /// compacted. Freeing during a transaction is undone by a rollback
+    /// like any other change.
+    pub fn free_page(&self, page_id: PageId) -> Result<(), Error> {
+        let Some(list) = self.free_list() else {
+            return Ok(());
+        };
+        let list_buffer = self.fetch_page(list)?;
+        let mut list_page = list_buffer.write();
+        let (head, count) = read_free_list(&list_page[..]);
+        let buffer = self.fetch_page(page_id)?;
+        buffer.write()[..8].copy_from_slice(&PageId::from(head).to_bytes());
+        list_page[..8].copy_from_slice(&page_id.to_bytes());
+        list_page[8..16].copy_from_slice(&(count + 1).to_le_bytes());
+        Ok(())
+    }
+
+    /// Takes the first page off the free list and clears it.
+    fn reuse_page(&self, location: &'static Location) -> Result<Option<Arc<Buffer>>, Error> {
+        let Some(list) = self.free_list() else {
+            return Ok(None);
+        };
+        let list_buffer = self.fetch_page(list)?;
+        let mut list_page = list_buffer.write();
+        let (Some(head), count) = read_free_list(&list_page[..]) else {
+            return Ok(None);
+        };
+        let buffer = self.fetch_page(head)?;
+        {
+            let mut page = buffer.write();
+            list_page[..8].copy_from_slice(&page[..8]);
+            list_page[8..16].copy_from_slice(&count.saturating_sub(1).to_le_bytes());
+            page.fill(0);
+        }
+        (self.lock()).record(self.file, head, LineageOperation::Create, location);
+        Ok(Some(buffer))
+    }
+
+    /// Writes every dirty page back to disk and syncs the files.
+    #[track_caller]
+    pub fn flush(&self) -> Result<(), Error> {
+        self.write_back(usize::MAX).map(|_| ())
+    }
+
+    /// Pages changed in the pool but not yet in the file.
+    pub fn dirty_pages(&self) -> usize {
+        let inner = self.lock();
+        (inner.page_table.values())
+            .filter(|buffer_id| inner.pool.buffers[buffer_id.0].buffer.is_dirty())
+            .count()
+    }
+
+    /// Writes back at most `limit` dirty pages, the least used first, and
+    /// syncs the files they are of and this manager's. Returns how many it
+    /// wrote.
+    #[track_caller]
+    pub fn write_back(&self, limit: usize) -> Result<usize, Error> {
+        let location = Location::caller();
+        write_back(
+            &self.inner,
+            self.file,
+            limit,
+            IoPriority::Foreground,
+            location,
+        )
+    }
+
+    /// A writer that writes back the pool's dirty pages in the background,
+    /// from a thread of its own.
+    pub fn background_writer(&self) -> BackgroundWriter {
+        BackgroundWriter {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+
+    /// Starts a transaction, flushing first so that the file holds what
+    /// a rollback returns to.
+    #[track_caller]
+    pub fn begin(&self) -> Result<(), Error> {
+        self.flush()?;
+        let mut inner = self.lock();
+        let num_pages = (inner.files.iter())
+            .map(|disk| disk.as_ref().map_or(0, DiskManager::num_pages))
+            .collect();
+        inner.transaction = Some(num_pages);
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.lock().transaction.is_some()
+    }
+
+    /// Ends the transaction, writing the pages it changed.
+    #[track_caller]
+    pub fn commit(&self) -> Result<(), Error> {
+        {
+            let mut inner = self.lock();
+            inner.transaction = None;
+            inner.savepoint.lock().unwrap().take();
+        }
+        self.flush()
+    }
+
+    /// Ends the transaction, dropping the pages it changed and created so
+    /// that they are read from the file again. Pages still pinned keep
+    /// their contents for whoever holds them, but are never written.
+    #[track_caller]
+    pub fn rollback(&self) {
+        let location = Location::caller();
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        let Some(num_pages) = inner.transaction.take() else {
+            return;
+        };
+        inner.savepoint.lock().unwrap().take();
+        inner.page_table.retain(|&(file, page_id), buffer_id| {
+            let frame = &mut inner.pool.buffers[buffer_id.0];
+            if !frame.buffer.is_dirty() {
+                return true;
+            }
+            if let Some(lineage) = inner.lineage.as_ref().filter(|_| file == MAIN_FILE) {
+                lineage.record(page_id, LineageOperation::Discard, location);
+            }
+            frame.buffer.is_dirty.store(false, Ordering::Release);
+            frame.usage_count = 0;
+            if let Some(buffer) = Arc::get_mut(&mut frame.buffer) {
+                buffer.page_id = PageId::INVALID_PAGE_ID;
+            }
+            false
+        });
+        for (disk, num_pages) in inner.files.iter_mut().zip(num_pages) {
+            if let Some(disk) = disk {
+                disk.release_pages_from(num_pages);
+            }
+        }
+    }
+
+    /// Detaches the file of a manager made by [`attach`](Self::attach)
+    /// from the pool, writing back and syncing its dirty pages first,
+    /// unless a transaction is running, in which case they are dropped as
+    /// a rollback would. If that fails, the file stays attached with the
+    /// pages not yet written. Does nothing for the pool's own file.
+    pub fn detach(&self) -> Result<(), Error> {
+        if self.file == MAIN_FILE {
+            return Ok(());
+        }
+        self.lock().detach(self.file)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap()
+    }
+}
+
+impl Drop for BufferPoolManager {
+    /// Detaches an attached file, warning on stderr if its pages could
+    /// not be written back, which then stay in the pool for an eviction
+    /// to write; and otherwise reports the lineage of the dirty pages if
+    /// a panic is unwinding through the owner of the pool.
+    fn drop(&mut self) {
+        if self.file != MAIN_FILE {
+            if let Ok(mut inner) = self.inner.lock() {
+                if let Err(err) = inner.detach(self.file) {
+                    eprintln!("neru7db: could not write back a detached file: {err}");
+                }
+            }
+            return;
+        }
+        if !thread::panicking() {
+            return;
+        }
+        let Ok(inner) = self.inner.lock() else {
+            return;
+        };
+        let Some(lineage) = &inner.lineage else {
+            return;
+        };
+        let mut dirty: Vec<PageId> = inner
+            .page_table
+            .iter()
+            .filter(|(&(file, _), buffer_id)| {
+                file == MAIN_FILE && inner.pool.buffers[buffer_id.0].buffer.is_dirty()
+            })
+            .map(|(&(_, page_id), _)| page_id)
+            .collect();
+        dirty.sort_unstable();
+        for page_id in dirty {
+            eprintln!("lineage of dirty page {}:", page_id.to_u64());
+            for entry in lineage.entries(page_id) {
+                eprintln!("  {entry}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{Faults, SimFile};
+    use tempfile::tempfile;
+
+    /// Fails the next operation on `file`.
+    fn fail_next(file: &SimFile) {
+        file.set_faults(Faults {
+            fail_at: Some(file.operations()),
+            ..Faults::default()
+        });
+    }
+
+    /// The first byte of `page_id` in the image of `file`, read afresh.
+    fn stored(file: &SimFile, page_id: PageId) -> u8 {
+        let disk = DiskManager::with_storage(SimFile::from_image(file.image(), 0)).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 1);
+        let page = bufmgr.fetch_page(page_id).unwrap().read()[0];
+        page
+    }
+
+    #[test]
+    fn test_evict_and_refetch() {
+        let mut hello = Vec::with_capacity(PAGE_SIZE);
+        hello.extend_from_slice(b"hello");
+        hello.resize(PAGE_SIZE, 0);
+        let mut world = Vec::with_capacity(PAGE_SIZE);
+        world.extend_from_slice(b"world");
+        world.resize(PAGE_SIZE, 0);
+
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 1);
+        let page1_id = {
+            let buffer = bufmgr.create_page().unwrap();
+            assert!(bufmgr.create_page().is_err());
+            buffer.write().copy_from_slice(&hello);
+            buffer.page_id
+        };
+        {
+            let buffer = bufmgr.fetch_page(page1_id).unwrap();
+            assert_eq!(&hello, &buffer.read()[..]);
+        }
+        let page2_id = {
+            let buffer = bufmgr.create_page().unwrap();
+            buffer.write().copy_from_slice(&world);
+            buffer.page_id
+        };
+        {
+            let buffer = bufmgr.fetch_page(page1_id).unwrap();
+            assert_eq!(&hello, &buffer.read()[..]);
+        }
+        {
+            let buffer = bufmgr.fetch_page(page2_id).unwrap();
+            assert_eq!(&world, &buffer.read()[..]);
+        }
+    }
+
+    #[test]
+    fn test_transaction() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 2);
+        let page_id = bufmgr.create_page().unwrap().page_id;
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 1;
+
+        bufmgr.begin().unwrap();
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 2;
+        bufmgr.create_page().unwrap().write()[0] = 3;
+        // Both frames hold dirty pages, which must stay in memory.
+        assert!(matches!(
+            bufmgr.create_page(),
+            Err(Error::TransactionTooLarge)
+        ));
+        assert!(matches!(bufmgr.flush(), Err(Error::InTransaction)));
+        bufmgr.rollback();
+        assert_eq!(1, bufmgr.num_pages());
+        assert_eq!(1, bufmgr.fetch_page(page_id).unwrap().read()[0]);
+
+        bufmgr.begin().unwrap();
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 4;
+        bufmgr.commit().unwrap();
+        bufmgr.rollback();
+        assert_eq!(4, bufmgr.fetch_page(page_id).unwrap().read()[0]);
+    }
+
+    #[test]
+    fn test_flush_clears_dirty() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 2);
+        let buffer = bufmgr.create_page().unwrap();
+        buffer.write()[0] = 7;
+        assert!(buffer.is_dirty());
+        bufmgr.flush().unwrap();
+        assert!(!buffer.is_dirty());
+    }
+
+    #[test]
+    fn test_failed_write_keeps_page_dirty() {
+        let file = SimFile::new(1);
+        let bufmgr = BufferPoolManager::new(DiskManager::with_storage(file.clone()).unwrap(), 2);
+        let buffer = bufmgr.create_page().unwrap();
+        buffer.write()[0] = 7;
+        fail_next(&file);
+        assert!(bufmgr.flush().is_err());
+        assert!(buffer.is_dirty());
+        file.set_faults(Faults::default());
+        bufmgr.flush().unwrap();
+        assert!(!buffer.is_dirty());
+        assert_eq!(7, stored(&file, buffer.page_id));
+    }
+
+    #[test]
+    fn test_background_writer() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 8);
+        let writer = bufmgr.background_writer();
+        for _ in 0..3 {
+            bufmgr.create_page().unwrap().write()[0] = 1;
+        }
+        assert_eq!(2, writer.write_back(2).unwrap());
+        assert_eq!(1, bufmgr.dirty_pages());
+        let stats = bufmgr.stats().disk;
+        assert_eq!((2, 2), (stats.background_writes, stats.pages_written));
+
+        bufmgr.begin().unwrap();
+        bufmgr.create_page().unwrap().write()[0] = 1;
+        assert!(matches!(writer.write_back(8), Err(Error::InTransaction)));
+        bufmgr.commit().unwrap();
+        drop(bufmgr);
+        assert_eq!(0, writer.write_back(8).unwrap());
+    }
+
+    #[test]
+    fn test_attach() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other");
+        let bufmgr = BufferPoolManager::new(DiskManager::new(tempfile().unwrap()).unwrap(), 2);
+        let main_page = bufmgr.create_page().unwrap().page_id;
+        bufmgr.fetch_page(main_page).unwrap().write()[0] = 1;
+        let other = bufmgr.attach(DiskManager::open(&path).unwrap()).unwrap();
+        let other_page = other.create_page().unwrap().page_id;
+        assert_eq!(main_page, other_page);
+        other.fetch_page(other_page).unwrap().write()[0] = 2;
+        // The files share both frames.
+        bufmgr.create_page().unwrap();
+        assert_eq!(1, bufmgr.fetch_page(main_page).unwrap().read()[0]);
+        assert_eq!(2, other.fetch_page(other_page).unwrap().read()[0]);
+
+        bufmgr.begin().unwrap();
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let scratch = bufmgr.attach(disk).unwrap();
+        scratch.create_page().unwrap();
+        other.fetch_page(other_page).unwrap().write()[0] = 3;
+        bufmgr.rollback();
+        assert_eq!(0, scratch.num_pages());
+        drop(scratch);
+        assert_eq!(2, other.fetch_page(other_page).unwrap().read()[0]);
+        other.fetch_page(other_page).unwrap().write()[0] = 4;
+        drop(other);
+        let other = BufferPoolManager::new(DiskManager::open(&path).unwrap(), 2);
+        assert_eq!(4, other.fetch_page(other_page).unwrap().read()[0]);
+        assert_eq!(1, bufmgr.fetch_page(main_page).unwrap().read()[0]);
+    }
+
+    #[test]
+    fn test_failed_detach_keeps_pages() {
+        let bufmgr = BufferPoolManager::new(DiskManager::new(tempfile().unwrap()).unwrap(), 2);
+        let file = SimFile::new(1);
+        let other = bufmgr
+            .attach(DiskManager::with_storage(file.clone()).unwrap())
+            .unwrap();
+        let page_id = other.create_page().unwrap().page_id;
+        other.fetch_page(page_id).unwrap().write()[0] = 5;
+        fail_next(&file);
+        assert!(other.detach().is_err());
+        let buffer = other.fetch_page(page_id).unwrap();
+        assert!(buffer.is_dirty());
+        assert_eq!(5, buffer.read()[0]);
+        // A failed sync leaves it attached too, with its pages written.
+        drop(buffer);
+        file.set_faults(Faults {
+            fail_at: Some(file.operations() + 1),
+            ..Faults::default()
+        });
+        assert!(other.detach().is_err());
+        assert_eq!(5, other.fetch_page(page_id).unwrap().read()[0]);
+        file.set_faults(Faults::default());
+        other.detach().unwrap();
+        assert_eq!(5, stored(&file, page_id));
+    }
+}
@@ -0,0 +1,126 @@
+//! Pages kept as they were when a copy of the database began.
+//!
+//! [`BufferPoolManager::freeze`] starts tracking the pages the file had
+//! then. The first write to one of them afterwards keeps a copy of the page
+//! as it was, unless the copy has taken it already, so that the copy can
+//! go on between writes and still see every page as of the same moment.
+//! Tracking stops once the [`Frozen`] is dropped.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use super::{BufferPoolManager, Error, Page};
+use crate::disk::PageId;
+
+/// What the buffers of a pool tell of their writes.
+pub(super) type Registry = Arc<Mutex<Vec<Weak<Frozen>>>>;
+
+#[derive(Debug)]
+pub struct Frozen {
+    /// The file of the pool frozen.
+    file: usize,
+    num_pages: u64,
+    /// The next page to be taken; those before it need no keeping.
+    next: AtomicU64,
+    kept: Mutex<HashMap<PageId, Box<Page>>>,
+}
+
+impl Frozen {
+    pub(super) fn new(file: usize, num_pages: u64) -> Self {
+        Self {
+            file,
+            num_pages,
+            next: AtomicU64::new(0),
+            kept: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Pages the file had when frozen.
+    pub fn num_pages(&self) -> u64 {
+        self.num_pages
+    }
+
+    /// Called, with the page locked, before `page` of `page_id` in `file`
+    /// is written.
+    pub(super) fn record(&self, file: usize, page_id: PageId, page: &Page) {
+        let n = page_id.to_u64();
+        if file != self.file || n >= self.num_pages || n < self.next.load(Ordering::Acquire) {
+            return;
+        }
+        let mut kept = self.kept.lock().unwrap();
+        kept.entry(page_id).or_insert_with(|| Box::new(*page));
+    }
+
+    /// The next page as it was when frozen, or `None` once every page has
+    /// been taken. Pages are taken in order, each once.
+    pub fn take(&self, bufmgr: &BufferPoolManager) -> Result<Option<Box<Page>>, Error> {
+        let n = self.next.load(Ordering::Acquire);
+        if n == self.num_pages {
+            return Ok(None);
+        }
+        let page_id = PageId(n);
+        let buffer = bufmgr.fetch_page(page_id)?;
+        // The page stays locked until it counts as taken, so that a write
+        // either comes first and keeps it or comes after and need not.
+        let page = buffer.read();
+        let kept = self.kept.lock().unwrap().remove(&page_id);
+        self.next.store(n + 1, Ordering::Release);
+        Ok(Some(kept.unwrap_or_else(|| Box::new(*page))))
+    }
+}
+
+impl BufferPoolManager {
+    /// Starts keeping the pages of the file as they are now, for as long
+    /// as the returned [`Frozen`] lives.
+    pub fn freeze(&self) -> Result<Arc<Frozen>, Error> {
+        let inner = self.lock();
+        if inner.transaction.is_some() {
+            return Err(Error::InTransaction);
+        }
+        let frozen = Arc::new(Frozen::new(self.file, inner.disk(self.file).num_pages()));
+        let mut registry = inner.frozen.lock().unwrap();
+        registry.retain(|frozen| frozen.strong_count() > 0);
+        registry.push(Arc::downgrade(&frozen));
+        Ok(frozen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BufferPoolManager;
+    use crate::disk::DiskManager;
+
+    #[test]
+    fn test_pages_as_frozen() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 4);
+        let page_ids: Vec<_> = (0..3)
+            .map(|i| {
+                let buffer = bufmgr.create_page().unwrap();
+                buffer.write()[0] = i;
+                buffer.page_id
+            })
+            .collect();
+        let frozen = bufmgr.freeze().unwrap();
+        let first = frozen.take(&bufmgr).unwrap().unwrap();
+        for (i, &page_id) in page_ids.iter().enumerate() {
+            bufmgr.fetch_page(page_id).unwrap().write()[0] = 10 + i as u8;
+            bufmgr.fetch_page(page_id).unwrap().write()[0] = 20 + i as u8;
+        }
+        bufmgr.create_page().unwrap();
+        bufmgr.flush().unwrap();
+        let mut pages = vec![first];
+        while let Some(page) = frozen.take(&bufmgr).unwrap() {
+            pages.push(page);
+        }
+        assert_eq!(
+            vec![0, 1, 2],
+            pages.iter().map(|p| p[0]).collect::<Vec<_>>()
+        );
+        assert!(frozen.kept.lock().unwrap().is_empty());
+
+        bufmgr.begin().unwrap();
+        assert!(bufmgr.freeze().is_err());
+    }
+}
@@ -0,0 +1,284 @@
+//! Past versions of pages, for reading the database as it was.
+//!
+//! A pool that keeps history is told to mark a point whenever its pages
+//! hold a state worth going back to: the engine marks one after each
+//! statement outside a transaction and after each commit. Points are
+//! numbered by a log sequence number counting up from 0, the state the
+//! pool was opened in, and carry the time they were marked.
+//!
+//! The first write to a page after a point keeps a copy of the page as it
+//! was at that point. A page as of a point is then the copy tagged with
+//! the oldest point at or after it, or the page itself if it has not been
+//! written since. The copies live in memory only, so history starts over
+//! each time the database is opened. Each mark drops the points that fell
+//! out of the retention window, but for the newest of them, which is
+//! still the state at the start of the window, along with the copies that
+//! only they needed.
+//!
+//! [`PagesAsOf`] reads the file as of a point without copying it: a page
+//! reads as its copy if it has one, and otherwise as it is now.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{Inner, Page, MAIN_FILE};
+use crate::disk::{PageId, Storage, PAGE_SIZE};
+
+/// A state of the database that can be read again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Point {
+    pub lsn: u64,
+    /// When it was marked, in seconds since the Unix epoch.
+    pub time: u64,
+    /// Pages the file had.
+    pub num_pages: u64,
+}
+
+#[derive(Debug)]
+struct State {
+    /// Oldest first, and never empty.
+    points: VecDeque<Point>,
+    /// Pages written since the last point.
+    written: HashSet<PageId>,
+    /// Copies of each page, by the point they are as of.
+    images: HashMap<PageId, BTreeMap<u64, Box<Page>>>,
+}
+
+#[derive(Debug)]
+pub struct History {
+    retention: Duration,
+    state: Mutex<State>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl History {
+    /// History of a file of `num_pages` pages, kept for `retention`.
+    pub(super) fn new(retention: Duration, num_pages: u64) -> Self {
+        let point = Point {
+            lsn: 0,
+            time: now(),
+            num_pages,
+        };
+        Self {
+            retention,
+            state: Mutex::new(State {
+                points: VecDeque::from([point]),
+                written: HashSet::new(),
+                images: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Called before `page` of `page_id` is written.
+    pub(super) fn record(&self, page_id: PageId, page: &Page) {
+        let mut state = self.state.lock().unwrap();
+        if !state.written.insert(page_id) {
+            return;
+        }
+        let last = *state.points.back().unwrap();
+        // A page created since has no past to keep.
+        if page_id.to_u64() < last.num_pages {
+            let images = state.images.entry(page_id).or_default();
+            images.insert(last.lsn, Box::new(*page));
+        }
+    }
+
+    /// Marks the current state, of a file of `num_pages` pages, as a new
+    /// point unless nothing changed since the last one. Returns the
+    /// point's lsn.
+    pub(super) fn mark(&self, num_pages: u64) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let last = *state.points.back().unwrap();
+        if state.written.is_empty() && last.num_pages == num_pages {
+            return last.lsn;
+        }
+        state.written.clear();
+        let time = now();
+        state.points.push_back(Point {
+            lsn: last.lsn + 1,
+            time,
+            num_pages,
+        });
+        prune(&mut state, time.saturating_sub(self.retention.as_secs()));
+        last.lsn + 1
+    }
+
+    /// Drops the points that fell out of the retention window since the
+    /// last mark, as the next mark would, and the copies only they
+    /// needed. Returns how many copies it dropped.
+    pub(super) fn prune(&self) -> usize {
+        let mut state = self.state.lock().unwrap();
+        prune(&mut state, now().saturating_sub(self.retention.as_secs()))
+    }
+
+    /// The points still retained, oldest first.
+    pub fn points(&self) -> Vec<Point> {
+        self.state.lock().unwrap().points.iter().copied().collect()
+    }
+
+    /// The retained point numbered `lsn`.
+    pub fn point(&self, lsn: u64) -> Option<Point> {
+        let state = self.state.lock().unwrap();
+        state.points.iter().find(|point| point.lsn == lsn).copied()
+    }
+
+    /// The point that was current at `time`, in seconds since the Unix
+    /// epoch, if that is within the retention window.
+    pub fn at(&self, time: u64) -> Option<Point> {
+        if time < now().saturating_sub(self.retention.as_secs()) {
+            return None;
+        }
+        let state = self.state.lock().unwrap();
+        state
+            .points
+            .iter()
+            .rev()
+            .find(|point| point.time <= time)
+            .copied()
+    }
+
+    /// The page as of the point `lsn`, or `None` if it is the same now.
+    pub fn page(&self, page_id: PageId, lsn: u64) -> Option<Box<Page>> {
+        let state = self.state.lock().unwrap();
+        let images = state.images.get(&page_id)?;
+        images.range(lsn..).next().map(|(_, image)| image.clone())
+    }
+}
+
+/// The file of a pool as it was at a point of its history, as storage
+/// for a read-only [`DiskManager`](crate::disk::DiskManager): each page is
+/// read when asked for, from the copy kept of it if it has been written
+/// since, and otherwise from the pool, or the file if the pool does not
+/// hold it. Writes fail.
+pub struct PagesAsOf {
+    pub(super) inner: Arc<Mutex<Inner>>,
+    pub(super) history: Arc<History>,
+    pub(super) point: Point,
+}
+
+impl Storage for PagesAsOf {
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.point.num_pages * PAGE_SIZE as u64)
+    }
+
+    fn read_at(&mut self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        let page_id = PageId(offset / PAGE_SIZE as u64);
+        if !offset.is_multiple_of(PAGE_SIZE as u64) || data.len() != PAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "pages as of a point are read whole",
+            ));
+        }
+        if page_id.to_u64() >= self.point.num_pages {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        // The page as it is now is read first: a write after that keeps a
+        // copy of it before it changes, which is then read instead.
+        let cached = {
+            let mut inner = self.inner.lock().unwrap();
+            match inner.page_table.get(&(MAIN_FILE, page_id)) {
+                Some(buffer_id) => Some(Arc::clone(&inner.pool.buffers[buffer_id.0].buffer)),
+                None => {
+                    inner.disk_mut(MAIN_FILE).read_page_data(page_id, data)?;
+                    None
+                }
+            }
+        };
+        if let Some(buffer) = cached {
+            data.copy_from_slice(&buffer.page.shared()[..]);
+        }
+        if let Some(page) = self.history.page(page_id, self.point.lsn) {
+            data.copy_from_slice(&page[..]);
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, _offset: u64, _data: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "the past of a database cannot be written",
+        ))
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Drops the points before `cutoff` but the newest of them, and the
+/// copies no point left needs; returns how many copies went.
+fn prune(state: &mut State, cutoff: u64) -> usize {
+    while state.points.len() > 1 && state.points[1].time <= cutoff {
+        state.points.pop_front();
+    }
+    let oldest = state.points[0].lsn;
+    let mut dropped = 0;
+    state.images.retain(|_, images| {
+        let kept = images.split_off(&oldest);
+        dropped += images.len();
+        *images = kept;
+        !images.is_empty()
+    });
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BufferPoolManager;
+    use crate::disk::{DiskManager, PAGE_SIZE};
+    use std::time::Duration;
+
+    #[test]
+    fn test_pages_as_of() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 4).with_history(Duration::from_secs(60));
+        let page_id = bufmgr.create_page().unwrap().page_id;
+        let history = bufmgr.history().unwrap();
+        assert_eq!(1, bufmgr.mark_history());
+        assert_eq!(1, bufmgr.mark_history());
+
+        for byte in [1, 2] {
+            bufmgr.fetch_page(page_id).unwrap().write()[0] = byte;
+            bufmgr.fetch_page(page_id).unwrap().write()[1] = byte;
+            bufmgr.mark_history();
+        }
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            (history.points().iter()).map(|p| p.lsn).collect::<Vec<_>>()
+        );
+        assert_eq!(0, history.point(0).unwrap().num_pages);
+        assert_eq!(
+            Some([0, 0]),
+            history.page(page_id, 1).map(|page| [page[0], page[1]])
+        );
+        assert_eq!(
+            Some([1, 1]),
+            history.page(page_id, 2).map(|page| [page[0], page[1]])
+        );
+        assert_eq!(None, history.page(page_id, 3));
+        // The file as of a point reads its pages from the history, or
+        // from the pool as they are now.
+        for (lsn, byte) in [(2, 1), (3, 2)] {
+            let pages = bufmgr.pages_as_of(lsn).unwrap();
+            let mut disk = DiskManager::with_storage(pages).unwrap().into_read_only();
+            let mut data = [0; PAGE_SIZE];
+            disk.read_page_data(page_id, &mut data).unwrap();
+            assert_eq!([byte, byte], [data[0], data[1]]);
+        }
+        assert_eq!(
+            0,
+            DiskManager::with_storage(bufmgr.pages_as_of(0).unwrap())
+                .unwrap()
+                .num_pages()
+        );
+        assert_eq!(Some(3), history.at(u64::MAX).map(|point| point.lsn));
+        assert_eq!(None, history.at(0));
+    }
+}
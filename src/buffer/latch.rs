@@ -0,0 +1,109 @@
+//! Latches on the pages in the pool.
+//!
+//! A latch guards the bytes of one page while a thread reads or changes
+//! them, so that heap and index code only ever see a page as it was before
+//! or after a change, never halfway through one. Any number of readers
+//! share a page's latch, and a writer holds it alone. Latches are held
+//! only for as long as a page is looked at, never across statements, and
+//! never taken while holding the pool's own lock, so a thread that holds
+//! one and fetches another page cannot deadlock the pool.
+//!
+//! Latches have nothing to do with what transactions see of each other.
+//! That is decided above the pool, by which session holds the database
+//! and by the pool keeping the changes of a transaction out of the file
+//! until it commits; latches only keep the pages themselves whole while
+//! threads, such as the workers of a parallel scan, share them.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+
+use super::Page;
+use crate::disk::PAGE_SIZE;
+
+pub(super) struct Latch {
+    page: RwLock<Box<Page>>,
+    /// Times a thread had to wait for a latch of the pool, shared by all
+    /// of them.
+    waits: Arc<AtomicU64>,
+}
+
+impl Latch {
+    pub(super) fn new(waits: &Arc<AtomicU64>) -> Self {
+        Self {
+            page: RwLock::new(Box::new([0u8; PAGE_SIZE])),
+            waits: Arc::clone(waits),
+        }
+    }
+
+    pub(super) fn shared(&self) -> RwLockReadGuard<'_, Box<Page>> {
+        match self.page.try_read() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                self.waits.fetch_add(1, Ordering::Relaxed);
+                self.page.read().unwrap()
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+        }
+    }
+
+    pub(super) fn exclusive(&self) -> RwLockWriteGuard<'_, Box<Page>> {
+        match self.page.try_write() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                self.waits.fetch_add(1, Ordering::Relaxed);
+                self.page.write().unwrap()
+            }
+            Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+        }
+    }
+
+    /// The page, for a pool that holds its frame alone.
+    pub(super) fn get_mut(&mut self) -> &mut Page {
+        self.page.get_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+    use std::thread;
+
+    use super::super::BufferPoolManager;
+    use crate::disk::DiskManager;
+
+    #[test]
+    fn test_shared_and_exclusive_latches() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 4);
+        let page_id = bufmgr.create_page().unwrap().page_id;
+        let buffer = bufmgr.fetch_page(page_id).unwrap();
+        let barrier = Barrier::new(2);
+        thread::scope(|scope| {
+            let reader = scope.spawn(|| {
+                let page = buffer.read();
+                // Both readers hold the latch at once.
+                barrier.wait();
+                page[0]
+            });
+            let page = buffer.read();
+            barrier.wait();
+            assert_eq!(0, page[0]);
+            drop(page);
+            reader.join().unwrap();
+        });
+        assert_eq!(0, bufmgr.stats().latch_waits);
+
+        let page = buffer.read();
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| buffer.write()[0] = 1);
+            while bufmgr.stats().latch_waits == 0 {
+                thread::yield_now();
+            }
+            assert_eq!(0, page[0]);
+            drop(page);
+            writer.join().unwrap();
+        });
+        assert_eq!(1, buffer.read()[0]);
+        assert_eq!(1, bufmgr.stats().latch_waits);
+    }
+}
@@ -0,0 +1,162 @@
+//! Page lineage: the last operations on each page, with where in the
+//! code each came from, for finding out why a page is dirty or holds what
+//! it does.
+//!
+//! Recording is off unless the pool is made with
+//! [`BufferPoolManager::with_lineage`](super::BufferPoolManager::with_lineage),
+//! and keeps a set number of entries per page. A pool recording lineage
+//! prints that of its dirty pages to stderr when it is dropped by a
+//! panic, so a failed assertion comes with the history of the pages.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::panic::Location;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::disk::PageId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Create,
+    /// A fetch, and whether the pool held the page.
+    Fetch {
+        hit: bool,
+    },
+    /// The page was locked for writing, which marks it dirty.
+    Modify,
+    /// The page was written to the file by a flush or commit.
+    Flush,
+    /// The page left the pool, written back first if dirty.
+    Evict {
+        dirty: bool,
+    },
+    /// A rollback dropped the changes to the page.
+    Discard,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Create => f.write_str("create"),
+            Operation::Fetch { hit: true } => f.write_str("fetch (hit)"),
+            Operation::Fetch { hit: false } => f.write_str("fetch (read)"),
+            Operation::Modify => f.write_str("modify"),
+            Operation::Flush => f.write_str("flush"),
+            Operation::Evict { dirty: true } => f.write_str("evict (written back)"),
+            Operation::Evict { dirty: false } => f.write_str("evict"),
+            Operation::Discard => f.write_str("discard"),
+        }
+    }
+}
+
+/// An operation on a page.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Counts operations on all pages of the pool, to order entries of
+    /// different pages.
+    pub sequence: u64,
+    pub operation: Operation,
+    /// The code that called into the pool.
+    pub location: &'static Location<'static>,
+    pub thread: String,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} at {} on thread {}",
+            self.sequence, self.operation, self.location, self.thread
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    sequence: u64,
+    pages: HashMap<PageId, VecDeque<Entry>>,
+}
+
+#[derive(Debug)]
+pub(super) struct Lineage {
+    depth: usize,
+    state: Mutex<State>,
+}
+
+impl Lineage {
+    pub(super) fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            state: Mutex::default(),
+        }
+    }
+
+    pub(super) fn record(
+        &self,
+        page_id: PageId,
+        operation: Operation,
+        location: &'static Location<'static>,
+    ) {
+        let current = thread::current();
+        let thread = match current.name() {
+            Some(name) => name.to_string(),
+            None => format!("{:?}", current.id()),
+        };
+        let mut state = self.state.lock().unwrap();
+        let sequence = state.sequence;
+        state.sequence += 1;
+        let entries = state.pages.entry(page_id).or_default();
+        if entries.len() == self.depth {
+            entries.pop_front();
+        }
+        entries.push_back(Entry {
+            sequence,
+            operation,
+            location,
+            thread,
+        });
+    }
+
+    pub(super) fn entries(&self, page_id: PageId) -> Vec<Entry> {
+        let state = self.state.lock().unwrap();
+        state
+            .pages
+            .get(&page_id)
+            .map_or_else(Vec::new, |entries| entries.iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BufferPoolManager;
+    use super::*;
+    use crate::disk::DiskManager;
+
+    #[test]
+    fn test_lineage() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::with_lineage(disk, 1, 3);
+        let page_id = bufmgr.create_page().unwrap().page_id;
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 1;
+        bufmgr.flush().unwrap();
+        let other = bufmgr.create_page().unwrap().page_id;
+        let operations: Vec<Operation> = bufmgr
+            .lineage(page_id)
+            .into_iter()
+            .map(|entry| entry.operation)
+            .collect();
+        assert_eq!(
+            vec![
+                Operation::Modify,
+                Operation::Flush,
+                Operation::Evict { dirty: false }
+            ],
+            operations
+        );
+        let entry = &bufmgr.lineage(other)[0];
+        assert_eq!(Operation::Create, entry.operation);
+        assert_eq!(file!(), entry.location.file());
+        assert!(bufmgr.format_lineage(page_id).contains("flush at"));
+    }
+}
@@ -0,0 +1,170 @@
+//! Savepoints, for undoing a statement alone.
+//!
+//! [`BufferPoolManager::savepoint`] notes the pages each file has, and
+//! from then on the first write to each page keeps a copy of it as it
+//! was. [`BufferPoolManager::rollback_to_savepoint`] puts the copies back
+//! and drops the pages created since, as a rollback would, which leaves
+//! the pool as it was at the savepoint while the transaction goes on.
+//! Since the pages of a transaction stay in the pool until it ends, every
+//! page written since is still there to put back. Outside a transaction,
+//! pages may be written back meanwhile: those are written again as they
+//! were, and pages created since that were written stay in the file,
+//! unused, until their ids are handed out again. The copies are kept in
+//! memory either way. There is one savepoint at a time: setting one
+//! replaces the last.
+
+use std::collections::HashMap;
+use std::panic::Location;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use super::{BufferPoolManager, Error, LineageOperation, Page, PageId};
+
+/// What the buffers of a pool keep their pages in while a savepoint is
+/// set.
+pub(super) type Slot = Arc<Mutex<Option<Savepoint>>>;
+
+#[derive(Debug)]
+pub(super) struct Savepoint {
+    /// Pages each file had when it was set.
+    num_pages: Vec<u64>,
+    kept: HashMap<(usize, PageId), Box<Page>>,
+}
+
+impl Savepoint {
+    /// Called, with the page locked, before `page` of `page_id` in `file`
+    /// is written.
+    pub(super) fn record(&mut self, file: usize, page_id: PageId, page: &Page) {
+        // A page created since has nothing to go back to.
+        if page_id.to_u64() >= self.num_pages.get(file).copied().unwrap_or(0) {
+            return;
+        }
+        self.kept
+            .entry((file, page_id))
+            .or_insert_with(|| Box::new(*page));
+    }
+
+    /// Called as a file of `num_pages` pages is attached to the pool.
+    pub(super) fn attached(&mut self, num_pages: u64) {
+        self.num_pages.push(num_pages);
+    }
+}
+
+impl BufferPoolManager {
+    /// Sets a savepoint, replacing the last.
+    pub fn savepoint(&self) {
+        let inner = self.lock();
+        let num_pages = (inner.files.iter())
+            .map(|disk| disk.as_ref().map_or(0, |disk| disk.num_pages()))
+            .collect();
+        *inner.savepoint.lock().unwrap() = Some(Savepoint {
+            num_pages,
+            kept: HashMap::new(),
+        });
+    }
+
+    /// Forgets the savepoint, keeping the changes made since.
+    pub fn release_savepoint(&self) {
+        self.lock().savepoint.lock().unwrap().take();
+    }
+
+    /// Undoes the changes made since the savepoint, which is released.
+    /// Does nothing without one. Fails if a page written back since
+    /// cannot be written again as it was.
+    #[track_caller]
+    pub fn rollback_to_savepoint(&self) -> Result<(), Error> {
+        let location = Location::caller();
+        let mut inner = self.lock();
+        let inner = &mut *inner;
+        let Some(mut savepoint) = inner.savepoint.lock().unwrap().take() else {
+            return Ok(());
+        };
+        inner.page_table.retain(|&(file, page_id), buffer_id| {
+            let frame = &mut inner.pool.buffers[buffer_id.0];
+            let lineage = inner.lineage.as_ref().filter(|_| file == super::MAIN_FILE);
+            if page_id.to_u64() < savepoint.num_pages[file] {
+                if let Some(page) = savepoint.kept.remove(&(file, page_id)) {
+                    // Put back around the hooks of `Buffer::write`: the
+                    // page is as history and copies have it already. It
+                    // may have been written back and read again since.
+                    frame.buffer.page.exclusive().copy_from_slice(&page[..]);
+                    frame.buffer.is_dirty.store(true, Ordering::Release);
+                    if let Some(lineage) = lineage {
+                        lineage.record(page_id, LineageOperation::Discard, location);
+                    }
+                }
+                return true;
+            }
+            if let Some(lineage) = lineage {
+                lineage.record(page_id, LineageOperation::Discard, location);
+            }
+            frame.buffer.is_dirty.store(false, Ordering::Release);
+            frame.usage_count = 0;
+            if let Some(buffer) = Arc::get_mut(&mut frame.buffer) {
+                buffer.page_id = PageId::INVALID_PAGE_ID;
+            }
+            false
+        });
+        for (disk, &num_pages) in inner.files.iter_mut().zip(&savepoint.num_pages) {
+            if let Some(disk) = disk {
+                disk.release_pages_from(num_pages);
+            }
+        }
+        // What is left was written back and dropped from the pool since.
+        for ((file, page_id), page) in savepoint.kept {
+            if let Some(disk) = inner.files[file].as_mut() {
+                disk.write_page_data(page_id, &page[..])?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::BufferPoolManager;
+    use crate::disk::DiskManager;
+
+    #[test]
+    fn test_rollback_to_savepoint() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 8);
+        let page_id = bufmgr.create_page().unwrap().page_id;
+        bufmgr.begin().unwrap();
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 1;
+        bufmgr.savepoint();
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 2;
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 3;
+        let created = bufmgr.create_page().unwrap().page_id;
+        bufmgr.rollback_to_savepoint().unwrap();
+        assert_eq!(1, bufmgr.fetch_page(page_id).unwrap().read()[0]);
+        assert_eq!(1, bufmgr.num_pages());
+        // The page id is handed out again, and the transaction goes on.
+        assert_eq!(created, bufmgr.create_page().unwrap().page_id);
+        bufmgr.fetch_page(page_id).unwrap().write()[0] = 4;
+        bufmgr.rollback_to_savepoint().unwrap();
+        bufmgr.commit().unwrap();
+        assert_eq!(4, bufmgr.fetch_page(page_id).unwrap().read()[0]);
+    }
+
+    #[test]
+    fn test_rollback_to_savepoint_outside_transaction() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 2);
+        let pages: Vec<_> = (0..3)
+            .map(|_| bufmgr.create_page().unwrap().page_id)
+            .collect();
+        bufmgr.flush().unwrap();
+        bufmgr.savepoint();
+        // With two frames, writing three pages writes the first back.
+        for &page_id in &pages {
+            bufmgr.fetch_page(page_id).unwrap().write()[0] = 1;
+        }
+        let created = bufmgr.create_page().unwrap().page_id;
+        bufmgr.rollback_to_savepoint().unwrap();
+        for &page_id in &pages {
+            assert_eq!(0, bufmgr.fetch_page(page_id).unwrap().read()[0]);
+        }
+        assert_eq!(created, bufmgr.create_page().unwrap().page_id);
+    }
+}
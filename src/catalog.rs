@@ -0,0 +1,757 @@
+//! Table and index metadata.
+//!
+//! A materialized view is a table filled from a query, which its
+//! [`ViewInfo`] keeps along with the tables the query reads. Those
+//! cannot be dropped while the view exists. A partitioned table keeps
+//! its rows in the [partitions](partition) attached to it, and the rows
+//! of a table with a [time to live](ttl) expire, and those of a table
+//! with [policies](policy) are only some users'. Other databases can be
+//! [attached](attach) to a catalog, their tables named after them, and
+//! [temporary and unlogged](temp) tables keep their rows out of the file.
+//! Indexes built concurrently are [pending](pending) until they are
+//! filled.
+
+mod attach;
+mod function;
+mod partition;
+mod pending;
+mod policy;
+mod store;
+mod system;
+mod temp;
+mod trigger;
+mod ttl;
+mod user;
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::btree::{self, BTree, SearchMode};
+use crate::buffer::BufferPoolManager;
+use crate::collation::Collation;
+use crate::expr::{self, Expr, UserAggregate, UserFunction};
+use crate::heap::{self, HeapFile, Rid};
+use crate::stats::{self, TableStats};
+use crate::tuple;
+use crate::value::{DataType, Value};
+
+pub use attach::AttachedDatabase;
+pub use partition::{PartitionBound, PartitionMethod, PartitionOf, Partitioning};
+pub use pending::PendingIndex;
+pub use policy::PolicyInfo;
+pub use store::{CATALOG_PAGE_ID, FREE_LIST_RANGE};
+pub use system::SystemTable;
+pub use temp::{Persistence, TempTables};
+pub use trigger::{RowImage, TriggerAction, TriggerEvent, TriggerInfo, TriggerTiming};
+pub use ttl::Ttl;
+pub use user::{Privileges, User};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("table {0:?} already exists")]
+    TableExists(String),
+    #[error("a database is already attached as {0:?}")]
+    DatabaseExists(String),
+    #[error("no database is attached as {0:?}")]
+    DatabaseNotFound(String),
+    #[error("table {0:?} does not exist")]
+    TableNotFound(String),
+    #[error("index {0:?} already exists")]
+    IndexExists(String),
+    #[error("index {0:?} does not exist")]
+    IndexNotFound(String),
+    #[error("column {0:?} does not exist")]
+    ColumnNotFound(String),
+    #[error("duplicate column {0:?}")]
+    DuplicateColumn(String),
+    #[error("a table must have at least one column")]
+    NoColumns,
+    #[error("user {0:?} already exists")]
+    UserExists(String),
+    #[error("user {0:?} does not exist")]
+    UserNotFound(String),
+    #[error("could not create unique index {0:?}: duplicate key")]
+    DuplicateKey(String),
+    #[error("trigger {0:?} already exists")]
+    TriggerExists(String),
+    #[error("trigger {0:?} does not exist")]
+    TriggerNotFound(String),
+    #[error("{0:?} is not a table")]
+    NotATable(String),
+    #[error("{0:?} is not a materialized view")]
+    NotAView(String),
+    #[error("cannot drop {name:?} because materialized view {view:?} depends on it")]
+    HasDependents { name: String, view: String },
+    #[error("{0:?} is a partitioned table")]
+    Partitioned(String),
+    #[error("{0:?} is not partitioned")]
+    NotPartitioned(String),
+    #[error("{0:?} is already a partition")]
+    AlreadyPartition(String),
+    #[error("{partition:?} is not a partition of {parent:?}")]
+    NotAPartition { partition: String, parent: String },
+    #[error("partition {partition:?} must have the columns of {parent:?}")]
+    PartitionColumns { partition: String, parent: String },
+    #[error("invalid bound for partition {partition:?}: {reason}")]
+    InvalidPartitionBound {
+        partition: String,
+        reason: &'static str,
+    },
+    #[error("partition {partition:?} would overlap partition {other:?}")]
+    PartitionOverlap { partition: String, other: String },
+    #[error("a row of {0:?} is outside the partition bound")]
+    RowOutsideBound(String),
+    #[error("policy {name:?} for table {table:?} already exists")]
+    PolicyExists { name: String, table: String },
+    #[error("policy {name:?} for table {table:?} does not exist")]
+    PolicyNotFound { name: String, table: String },
+    #[error("{0:?} is a partition, which only has the policies of its parent")]
+    PolicyOnPartition(String),
+    #[error("cannot drop user {user:?} because policy {policy:?} is for them")]
+    UserHasPolicy { user: String, policy: String },
+    #[error("TTL column {0:?} must be of type INT")]
+    TtlColumn(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Heap(#[from] heap::Error),
+    #[error(transparent)]
+    BTree(#[from] btree::Error),
+    #[error(transparent)]
+    Expr(#[from] expr::Error),
+    #[error("stored catalog is corrupt: {0}")]
+    Corrupt(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataType,
+    pub nullable: bool,
+    /// How the column's text compares, and how indexes on it order it.
+    pub collation: Collation,
+}
+
+impl Column {
+    pub fn new(name: impl Into<String>, data_type: DataType) -> Self {
+        Self {
+            name: name.into(),
+            data_type,
+            nullable: true,
+            collation: Collation::Binary,
+        }
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.nullable = false;
+        self
+    }
+
+    pub fn collate(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub columns: Vec<Column>,
+}
+
+impl Schema {
+    pub fn new(columns: Vec<Column>) -> Self {
+        Self { columns }
+    }
+
+    pub fn len(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_empty()
+    }
+
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|column| column.name == name)
+    }
+}
+
+/// One key of an index: a column, or an expression over the columns.
+/// Keys are kept in ascending order, with NULLs first unless made with
+/// NULLS LAST.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexKey {
+    pub expr: Expr,
+    pub data_type: DataType,
+    pub nulls_first: bool,
+}
+
+impl IndexKey {
+    pub fn column(column: usize, data_type: DataType) -> Self {
+        Self {
+            expr: Expr::Column(column),
+            data_type,
+            nulls_first: true,
+        }
+    }
+
+    /// A key on `column` of `schema`, under the column's collation.
+    pub fn for_column(schema: &Schema, column: usize) -> Self {
+        let Column {
+            data_type,
+            collation,
+            ..
+        } = schema.columns[column];
+        Self {
+            expr: Expr::Column(column).collate(collation),
+            data_type,
+            nulls_first: true,
+        }
+    }
+
+    /// The column a plain key indexes, which may be collated.
+    pub fn as_column(&self) -> Option<usize> {
+        match &self.expr {
+            Expr::Column(column) => Some(*column),
+            Expr::Collate { expr, .. } => match **expr {
+                Expr::Column(column) => Some(column),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// A secondary index over some columns of a table, or over expressions
+/// of them.
+///
+/// Entries map the memcmpable encoding of the keys to the rid of the
+/// tuple. Unless the index is unique and the key has no NULLs, the rid is
+/// appended to the entry key as well so that entries stay distinct. A
+/// partial index only has entries for the rows its predicate accepts.
+#[derive(Debug, Clone)]
+pub struct IndexInfo {
+    pub name: String,
+    pub keys: Vec<IndexKey>,
+    pub predicate: Option<Expr>,
+    pub unique: bool,
+    pub btree: BTree,
+}
+
+impl IndexInfo {
+    /// The indexed columns, unless some key is an expression.
+    pub fn columns(&self) -> Option<Vec<usize>> {
+        self.keys.iter().map(IndexKey::as_column).collect()
+    }
+
+    /// Whether the index has an entry for `tuple`.
+    pub fn covers(&self, tuple: &[Value]) -> Result<bool, Error> {
+        match &self.predicate {
+            Some(predicate) => Ok(predicate.eval_predicate(tuple)?),
+            None => Ok(true),
+        }
+    }
+
+    /// Evaluates the keys against a full tuple.
+    pub fn key_values(&self, tuple: &[Value]) -> Result<Vec<Value>, Error> {
+        let values = self.keys.iter().map(|key| key.expr.eval(tuple));
+        Ok(values.collect::<Result<_, _>>()?)
+    }
+
+    /// The encoding of key values, or of a prefix of them, that entries
+    /// are ordered by.
+    pub fn encode_values(&self, values: &[Value]) -> Vec<u8> {
+        let mut key = vec![];
+        let nulls_first = |i: usize| self.keys.get(i).is_none_or(|key| key.nulls_first);
+        tuple::encode_key_ordered(values, nulls_first, &mut key);
+        key
+    }
+
+    /// The encoded key of `tuple`, or `None` if the index leaves it out.
+    pub fn encode_key(&self, tuple: &[Value]) -> Result<Option<Vec<u8>>, Error> {
+        if !self.covers(tuple)? {
+            return Ok(None);
+        }
+        Ok(Some(self.encode_values(&self.key_values(tuple)?)))
+    }
+
+    /// The entry key of `tuple` and whether it must be unique, if the
+    /// index has an entry for it. NULLs never conflict.
+    pub(crate) fn entry_key(
+        &self,
+        tuple: &[Value],
+        rid: Rid,
+    ) -> Result<Option<(Vec<u8>, bool)>, Error> {
+        if !self.covers(tuple)? {
+            return Ok(None);
+        }
+        let values = self.key_values(tuple)?;
+        let mut key = self.encode_values(&values);
+        let unique = self.unique && values.iter().all(|value| !value.is_null());
+        if !unique {
+            key.extend_from_slice(&rid.to_bytes());
+        }
+        Ok(Some((key, unique)))
+    }
+
+    /// Returns the rid of a different tuple with the same key as `tuple`,
+    /// if inserting `tuple` would violate this index's uniqueness.
+    pub fn find_conflict(
+        &self,
+        bufmgr: &BufferPoolManager,
+        tuple: &[Value],
+    ) -> Result<Option<Rid>, Error> {
+        match self.entry_key(tuple, Rid::default())? {
+            Some((_, true)) => Ok(self.lookup(bufmgr, tuple)?.into_iter().next()),
+            _ => Ok(None),
+        }
+    }
+
+    /// Rids of every tuple whose keys equal those of `tuple`.
+    pub fn lookup(&self, bufmgr: &BufferPoolManager, tuple: &[Value]) -> Result<Vec<Rid>, Error> {
+        let Some(prefix) = self.encode_key(tuple)? else {
+            return Ok(vec![]);
+        };
+        let mut iter = self.btree.search(bufmgr, SearchMode::Key(prefix.clone()))?;
+        let mut rids = vec![];
+        while let Some((key, value)) = iter.next(bufmgr)? {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            rids.push(Rid::from_bytes(&value));
+        }
+        Ok(rids)
+    }
+
+    /// Fails if the entry for `tuple` is too large to be indexed.
+    pub fn check_entry_size(&self, tuple: &[Value]) -> Result<(), Error> {
+        if let Some((key, _)) = self.entry_key(tuple, Rid::default())? {
+            BTree::check_size(&key, &Rid::default().to_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn insert_entry(
+        &self,
+        bufmgr: &BufferPoolManager,
+        tuple: &[Value],
+        rid: Rid,
+    ) -> Result<(), Error> {
+        if let Some((key, _)) = self.entry_key(tuple, rid)? {
+            self.btree.insert(bufmgr, &key, &rid.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_entry(
+        &self,
+        bufmgr: &BufferPoolManager,
+        tuple: &[Value],
+        rid: Rid,
+    ) -> Result<bool, Error> {
+        match self.entry_key(tuple, rid)? {
+            Some((key, _)) => Ok(self.btree.delete(bufmgr, &key)?),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Replaces the entries of `index` with those of the rows of `heap`,
+/// sorted in memory and bulk loaded.
+fn load_index(bufmgr: &BufferPoolManager, heap: &HeapFile, index: &IndexInfo) -> Result<(), Error> {
+    let mut entries = vec![];
+    let mut scan = heap.scan(bufmgr)?;
+    while let Some((rid, tuple)) = scan.next(bufmgr)? {
+        if let Some((key, _)) = index.entry_key(&tuple, rid)? {
+            entries.push((key, rid.to_bytes().to_vec()));
+        }
+    }
+    entries.sort_unstable();
+    match index.btree.rebuild(bufmgr, entries) {
+        Err(btree::Error::DuplicateKey) => Err(Error::DuplicateKey(index.name.clone())),
+        result => Ok(result?),
+    }
+}
+
+/// The query of a materialized view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewInfo {
+    /// The query's text, normalized.
+    pub definition: String,
+    /// Tables and views the query reads, in order of name.
+    pub dependencies: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TableInfo {
+    pub name: String,
+    pub schema: Schema,
+    pub heap: HeapFile,
+    pub indexes: Vec<IndexInfo>,
+    /// Set by [`Catalog::analyze`]; stale once the table changes.
+    pub stats: Option<TableStats>,
+    /// Set if the table is a materialized view.
+    pub view: Option<ViewInfo>,
+    /// In order of name, which is the order they fire in.
+    pub triggers: Vec<TriggerInfo>,
+    /// Set if the table is partitioned.
+    pub partitioning: Option<Partitioning>,
+    /// Set if the table is a partition.
+    pub partition_of: Option<PartitionOf>,
+    /// Set if the rows of the table expire.
+    pub ttl: Option<Ttl>,
+    /// In order of name.
+    pub policies: Vec<PolicyInfo>,
+}
+
+impl TableInfo {
+    pub fn index(&self, name: &str) -> Option<&IndexInfo> {
+        self.indexes.iter().find(|index| index.name == name)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Catalog {
+    tables: HashMap<String, TableInfo>,
+    users: BTreeMap<String, User>,
+    functions: BTreeMap<String, UserFunction>,
+    aggregates: BTreeMap<String, UserAggregate>,
+    /// Where the catalog is kept, if it outlives the process; see
+    /// [`Catalog::open`].
+    store: Option<HeapFile>,
+    attached: BTreeMap<String, AttachedDatabase>,
+    pending: Vec<PendingIndex>,
+    temp: Option<TempTables>,
+    unlogged: Option<TempTables>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The table `name`, which may be a temporary or unlogged table or the
+    /// qualified name of a table of an attached database.
+    pub fn table(&self, name: &str) -> Option<&TableInfo> {
+        match self.persistence(name) {
+            Persistence::Permanent => (self.tables.get(name)).or_else(|| self.attached_table(name)),
+            _ => self.temp_table(name),
+        }
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &TableInfo> {
+        self.tables.values()
+    }
+
+    pub fn create_table(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        schema: Schema,
+    ) -> Result<&TableInfo, Error> {
+        self.create(bufmgr, name, schema, None)
+    }
+
+    /// Creates a materialized view: a table with `schema`, the columns of
+    /// the view's query, left empty for the caller to fill.
+    pub fn create_materialized_view(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        schema: Schema,
+        view: ViewInfo,
+    ) -> Result<&TableInfo, Error> {
+        if let Some(missing) = view
+            .dependencies
+            .iter()
+            .find(|dependency| !self.tables.contains_key(*dependency))
+        {
+            return Err(Error::TableNotFound(missing.clone()));
+        }
+        self.create(bufmgr, name, schema, Some(view))
+    }
+
+    fn create(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        schema: Schema,
+        view: Option<ViewInfo>,
+    ) -> Result<&TableInfo, Error> {
+        if self.name_taken(name, Persistence::Permanent) {
+            return Err(Error::TableExists(name.to_string()));
+        }
+        if schema.is_empty() {
+            return Err(Error::NoColumns);
+        }
+        for (i, column) in schema.columns.iter().enumerate() {
+            if schema.columns[..i].iter().any(|c| c.name == column.name) {
+                return Err(Error::DuplicateColumn(column.name.clone()));
+            }
+        }
+        let heap = HeapFile::create(bufmgr)?;
+        let table = TableInfo {
+            name: name.to_string(),
+            schema,
+            heap,
+            indexes: vec![],
+            stats: None,
+            view,
+            triggers: vec![],
+            partitioning: None,
+            partition_of: None,
+            ttl: None,
+            policies: vec![],
+        };
+        store::save_table(self.store, bufmgr, &table)?;
+        Ok(self.tables.entry(name.to_string()).or_insert(table))
+    }
+
+    /// The materialized views whose queries read `name`, in order of name.
+    pub fn dependents(&self, name: &str) -> Vec<&str> {
+        let mut views: Vec<&str> = self
+            .tables
+            .values()
+            .filter(|table| {
+                table
+                    .view
+                    .as_ref()
+                    .is_some_and(|view| view.dependencies.iter().any(|d| d == name))
+            })
+            .map(|table| table.name.as_str())
+            .collect();
+        views.sort_unstable();
+        views
+    }
+
+    /// Creates an index over plain columns and fills it from the rows
+    /// already in the table.
+    pub fn create_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+        index_name: &str,
+        columns: &[&str],
+        unique: bool,
+    ) -> Result<&IndexInfo, Error> {
+        let table = self
+            .table(table_name)
+            .ok_or_else(|| Error::TableNotFound(table_name.to_string()))?;
+        let keys = columns
+            .iter()
+            .map(|name| {
+                let column = table
+                    .schema
+                    .column_index(name)
+                    .ok_or_else(|| Error::ColumnNotFound(name.to_string()))?;
+                Ok(IndexKey::for_column(&table.schema, column))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        self.create_expr_index(bufmgr, table_name, index_name, keys, None, unique)
+    }
+
+    /// Creates an index over `keys`, partial if it has a `predicate`, and
+    /// fills it from the rows already in the table.
+    pub fn create_expr_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+        index_name: &str,
+        keys: Vec<IndexKey>,
+        predicate: Option<Expr>,
+        unique: bool,
+    ) -> Result<&IndexInfo, Error> {
+        if self.persistence(table_name) != Persistence::Permanent {
+            return self.temp_create_index(bufmgr, table_name, index_name, keys, predicate, unique);
+        }
+        if self.index_name_taken(index_name) {
+            return Err(Error::IndexExists(index_name.to_string()));
+        }
+        let table = self
+            .tables
+            .get_mut(table_name)
+            .ok_or_else(|| Error::TableNotFound(table_name.to_string()))?;
+        if table.partitioning.is_some() {
+            return Err(Error::Partitioned(table_name.to_string()));
+        }
+        let index = IndexInfo {
+            name: index_name.to_string(),
+            keys,
+            predicate,
+            unique,
+            btree: BTree::create_unloaded(bufmgr)?,
+        };
+        load_index(bufmgr, &table.heap, &index)?;
+        self.add_index(bufmgr, table_name, index)
+    }
+
+    /// Rebuilds the index `name` from the rows of its table, packing its
+    /// entries into as few pages as the bulk loader leaves room for. The
+    /// index keeps its meta page, so the catalog entry does not change;
+    /// the old nodes are freed for reuse.
+    pub fn reindex(&self, bufmgr: &BufferPoolManager, name: &str) -> Result<(), Error> {
+        if self.temp_reindex(name)? {
+            return Ok(());
+        }
+        let (table, index) = self
+            .tables
+            .values()
+            .find_map(|table| Some((table, table.index(name)?)))
+            .ok_or_else(|| Error::IndexNotFound(name.to_string()))?;
+        load_index(bufmgr, &table.heap, index)
+    }
+
+    /// Deletes every row of the table `name` and every entry of its
+    /// indexes, freeing their pages, with no triggers fired.
+    pub fn truncate(&self, bufmgr: &BufferPoolManager, name: &str) -> Result<(), Error> {
+        let table =
+            (self.tables.get(name)).ok_or_else(|| Error::TableNotFound(name.to_string()))?;
+        table.heap.truncate(bufmgr)?;
+        for index in &table.indexes {
+            index.btree.truncate(bufmgr)?;
+        }
+        Ok(())
+    }
+
+    /// Adds `index`, already filled with the rows of the table, to the
+    /// table's indexes.
+    pub fn add_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+        index: IndexInfo,
+    ) -> Result<&IndexInfo, Error> {
+        if self.index_name_taken(&index.name) {
+            return Err(Error::IndexExists(index.name));
+        }
+        let table = self
+            .tables
+            .get_mut(table_name)
+            .ok_or_else(|| Error::TableNotFound(table_name.to_string()))?;
+        store::save_index(self.store, bufmgr, table_name, &index)?;
+        // In the order of their meta pages, as they are read back, which
+        // is not that they were made in once pages are reused.
+        let at = (table.indexes.iter())
+            .position(|other| other.btree.meta_page_id > index.btree.meta_page_id)
+            .unwrap_or(table.indexes.len());
+        table.indexes.insert(at, index);
+        Ok(&table.indexes[at])
+    }
+
+    /// Whether an index of a table, or one pending, is named `name`.
+    fn index_name_taken(&self, name: &str) -> bool {
+        (self.tables.values().chain(self.unlogged_tables()))
+            .any(|table| table.index(name).is_some())
+            || self.pending_index(name).is_some()
+    }
+
+    /// Removes a table, its indexes and triggers and the grants on it,
+    /// unless a materialized view depends on it, and the partitions of a
+    /// partitioned table with it. Their pages are freed for reuse.
+    pub fn drop_table(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<TableInfo, Error> {
+        if self.persistence(name) != Persistence::Permanent {
+            return self.temp_drop_table(bufmgr, name);
+        }
+        self.drop_relation(bufmgr, name, false)
+    }
+
+    /// Removes a materialized view as [`Catalog::drop_table`] does a
+    /// table.
+    pub fn drop_materialized_view(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<TableInfo, Error> {
+        self.drop_relation(bufmgr, name, true)
+    }
+
+    fn drop_relation(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        view: bool,
+    ) -> Result<TableInfo, Error> {
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| Error::TableNotFound(name.to_string()))?;
+        match (view, table.view.is_some()) {
+            (false, true) => return Err(Error::NotATable(name.to_string())),
+            (true, false) => return Err(Error::NotAView(name.to_string())),
+            _ => {}
+        }
+        let partitions: Vec<String> = (self.partitions(name).into_iter())
+            .map(|partition| partition.name.clone())
+            .collect();
+        for name in std::iter::once(name).chain(partitions.iter().map(String::as_str)) {
+            if let Some(dependent) = self.dependents(name).first() {
+                return Err(Error::HasDependents {
+                    name: name.to_string(),
+                    view: dependent.to_string(),
+                });
+            }
+        }
+        for partition in &partitions {
+            self.drop_relation(bufmgr, partition, false)?;
+        }
+        store::remove(self.store, bufmgr, "table", name, true)?;
+        if view {
+            store::remove(self.store, bufmgr, "view", name, false)?;
+        }
+        self.revoke_all(bufmgr, name)?;
+        let table = self.tables.remove(name).unwrap();
+        table.heap.free(bufmgr)?;
+        for index in &table.indexes {
+            index.btree.free(bufmgr)?;
+        }
+        let pending: Vec<String> = (self.pending.iter())
+            .filter(|pending| pending.table == name)
+            .map(|pending| pending.index.name.clone())
+            .collect();
+        for index in pending {
+            self.drop_pending_index(bufmgr, &index)?;
+        }
+        Ok(table)
+    }
+
+    /// Removes an index from whichever table has it, or a pending one,
+    /// freeing its pages for reuse.
+    pub fn drop_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<IndexInfo, Error> {
+        if let Some(index) = self.temp_drop_index(bufmgr, name)? {
+            return Ok(index);
+        }
+        for table in self.tables.values_mut() {
+            if let Some(i) = table.indexes.iter().position(|index| index.name == name) {
+                store::remove(self.store, bufmgr, "index", name, false)?;
+                let index = table.indexes.remove(i);
+                index.btree.free(bufmgr)?;
+                return Ok(index);
+            }
+        }
+        self.drop_pending_index(bufmgr, name)
+    }
+
+    /// Recomputes the statistics of a table.
+    pub fn analyze(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+    ) -> Result<&TableStats, Error> {
+        if self.persistence(table_name) != Persistence::Permanent {
+            return self.temp_analyze(table_name);
+        }
+        let table = self
+            .tables
+            .get_mut(table_name)
+            .ok_or_else(|| Error::TableNotFound(table_name.to_string()))?;
+        let stats = stats::analyze(bufmgr, &table.heap, table.schema.len())?;
+        Ok(table.stats.insert(stats))
+    }
+}
@@ -0,0 +1,154 @@
+//! Databases attached to the one a catalog is of.
+//!
+//! An attached database keeps its own file, which the pool of the main one
+//! holds pages of too, and its tables are named with the name it was
+//! attached as in front: `other.t`. Only its plain tables can be reached
+//! that way; its materialized views and partitioned tables are left out,
+//! and triggers on its tables do not fire. Its schema cannot be changed
+//! through the main database, and attaching it is not kept in the file.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{Catalog, Error, TableInfo};
+use crate::buffer::BufferPoolManager;
+
+#[derive(Clone)]
+pub struct AttachedDatabase {
+    pub name: String,
+    pub path: PathBuf,
+    bufmgr: Arc<BufferPoolManager>,
+    /// By their name in the attached database; their [`TableInfo::name`]
+    /// is the qualified one.
+    tables: HashMap<String, TableInfo>,
+}
+
+impl fmt::Debug for AttachedDatabase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AttachedDatabase")
+            .field("name", &self.name)
+            .field("path", &self.path)
+            .field("tables", &self.tables)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AttachedDatabase {
+    /// The manager of the database's pages within the main pool.
+    pub fn bufmgr(&self) -> &BufferPoolManager {
+        &self.bufmgr
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &TableInfo> {
+        self.tables.values()
+    }
+}
+
+impl Catalog {
+    /// Attaches the database that `catalog`, read from the file at `path`
+    /// through `bufmgr`, is of as `name`.
+    pub fn attach(
+        &mut self,
+        name: &str,
+        path: &Path,
+        bufmgr: BufferPoolManager,
+        catalog: Catalog,
+    ) -> Result<&AttachedDatabase, Error> {
+        if self.attached.contains_key(name) {
+            return Err(Error::DatabaseExists(name.to_string()));
+        }
+        let tables = (catalog.tables.into_values())
+            .filter(|table| {
+                table.view.is_none() && table.partitioning.is_none() && table.partition_of.is_none()
+            })
+            .map(|table| {
+                let qualified = TableInfo {
+                    name: format!("{name}.{}", table.name),
+                    triggers: vec![],
+                    ..table
+                };
+                (table.name, qualified)
+            })
+            .collect();
+        let database = AttachedDatabase {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            bufmgr: Arc::new(bufmgr),
+            tables,
+        };
+        Ok(self.attached.entry(name.to_string()).or_insert(database))
+    }
+
+    /// Detaches the database attached as `name`, which lets go of its file
+    /// once the last copy of the catalog that has it is dropped.
+    pub fn detach(&mut self, name: &str) -> Result<(), Error> {
+        self.attached
+            .remove(name)
+            .map(drop)
+            .ok_or_else(|| Error::DatabaseNotFound(name.to_string()))
+    }
+
+    /// The databases attached, in order of name.
+    pub fn attached(&self) -> impl Iterator<Item = &AttachedDatabase> {
+        self.attached.values()
+    }
+
+    /// The manager of the pages of `table` if it is the table of an
+    /// attached database, or `None` if it is one of the main database's.
+    pub(super) fn attached_bufmgr(&self, table: &str) -> Option<&BufferPoolManager> {
+        if self.tables.contains_key(table) {
+            return None;
+        }
+        let (database, _) = table.split_once('.')?;
+        self.attached.get(database).map(AttachedDatabase::bufmgr)
+    }
+
+    /// The table `name` of an attached database, `name` being qualified.
+    pub(super) fn attached_table(&self, name: &str) -> Option<&TableInfo> {
+        let (database, table) = name.split_once('.')?;
+        self.attached.get(database)?.tables.get(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{Column, Schema};
+    use crate::disk::DiskManager;
+    use crate::value::DataType;
+
+    #[test]
+    fn test_attached_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 16);
+        let mut catalog = Catalog::open(&bufmgr).unwrap();
+        let path = dir.path().join("other");
+        let other = bufmgr.attach(DiskManager::open(&path).unwrap()).unwrap();
+        let mut other_catalog = Catalog::open(&other).unwrap();
+        let schema = Schema::new(vec![Column::new("id", DataType::Int)]);
+        other_catalog
+            .create_table(&other, "t", schema.clone())
+            .unwrap();
+        catalog.create_table(&bufmgr, "t", schema).unwrap();
+
+        catalog
+            .attach("other", &path, other, other_catalog)
+            .unwrap();
+        assert_eq!("other.t", catalog.table("other.t").unwrap().name);
+        assert_eq!("t", catalog.table("t").unwrap().name);
+        assert!(catalog.table_bufmgr("other.t").is_some());
+        assert!(catalog.table_bufmgr("t").is_none());
+        assert!(catalog.table("other.u").is_none());
+        assert_eq!(1, catalog.tables().count());
+
+        catalog.detach("other").unwrap();
+        assert!(catalog.table("other.t").is_none());
+        assert!(matches!(
+            catalog.detach("other"),
+            Err(Error::DatabaseNotFound(_))
+        ));
+    }
+}
@@ -0,0 +1,51 @@
+//! Scalar and aggregate functions the embedder registers.
+//!
+//! Unlike the rest of the catalog they are not stored, since their bodies
+//! are closures of the process: an embedder registers them each time it
+//! opens the database, and statements that call a function it has not
+//! registered fail to bind.
+
+use super::Catalog;
+use crate::expr::{UserAggregate, UserFunction};
+
+impl Catalog {
+    pub fn function(&self, name: &str) -> Option<&UserFunction> {
+        self.functions.get(name)
+    }
+
+    /// The registered functions, in order of name.
+    pub fn functions(&self) -> impl Iterator<Item = &UserFunction> {
+        self.functions.values()
+    }
+
+    /// Registers `function`, replacing any scalar or aggregate function
+    /// of the same name.
+    pub fn create_function(&mut self, function: UserFunction) {
+        self.aggregates.remove(&function.name);
+        self.functions.insert(function.name.clone(), function);
+    }
+
+    pub fn drop_function(&mut self, name: &str) -> Option<UserFunction> {
+        self.functions.remove(name)
+    }
+
+    pub fn aggregate(&self, name: &str) -> Option<&UserAggregate> {
+        self.aggregates.get(name)
+    }
+
+    /// The registered aggregate functions, in order of name.
+    pub fn aggregates(&self) -> impl Iterator<Item = &UserAggregate> {
+        self.aggregates.values()
+    }
+
+    /// Registers `aggregate`, replacing any scalar or aggregate function
+    /// of the same name.
+    pub fn create_aggregate(&mut self, aggregate: UserAggregate) {
+        self.functions.remove(&aggregate.name);
+        self.aggregates.insert(aggregate.name.clone(), aggregate);
+    }
+
+    pub fn drop_aggregate(&mut self, name: &str) -> Option<UserAggregate> {
+        self.aggregates.remove(name)
+    }
+}
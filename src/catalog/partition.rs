@@ -0,0 +1,417 @@
+//! Declarative partitioning.
+//!
+//! A partitioned table holds no rows of its own: each row lives in the one
+//! partition whose bound admits the value of the table's partition key, a
+//! single column. Partitions are ordinary tables with the same columns,
+//! attached to the partitioned table with a bound that overlaps no other.
+//! A range bound admits keys from its lower bound, inclusive, up to its
+//! upper bound, exclusive; a hash bound admits keys whose hash leaves its
+//! remainder when divided by its modulus.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::{store, Catalog, Error, Schema, TableInfo};
+use crate::buffer::BufferPoolManager;
+use crate::expr::{self, BinaryOp, Expr};
+use crate::tuple;
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PartitionMethod {
+    Range,
+    Hash,
+}
+
+impl fmt::Display for PartitionMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PartitionMethod::Range => "RANGE",
+            PartitionMethod::Hash => "HASH",
+        })
+    }
+}
+
+/// How a partitioned table divides its rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Partitioning {
+    pub method: PartitionMethod,
+    /// The partition key.
+    pub column: usize,
+}
+
+/// The keys a partition admits.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionBound {
+    /// `None` is MINVALUE below and MAXVALUE above.
+    Range {
+        from: Option<Value>,
+        to: Option<Value>,
+    },
+    Hash {
+        modulus: u64,
+        remainder: u64,
+    },
+}
+
+impl PartitionBound {
+    pub fn method(&self) -> PartitionMethod {
+        match self {
+            PartitionBound::Range { .. } => PartitionMethod::Range,
+            PartitionBound::Hash { .. } => PartitionMethod::Hash,
+        }
+    }
+
+    /// Whether a row with partition key `key` belongs here. A NULL key
+    /// is in no range and hashes like any other value.
+    pub fn contains(&self, key: &Value) -> bool {
+        match self {
+            PartitionBound::Range { from, to } => {
+                let above = from.as_ref().map_or(Some(true), |from| {
+                    key.sql_cmp(from).map(|o| o != Ordering::Less)
+                });
+                let below = to.as_ref().map_or(Some(true), |to| {
+                    key.sql_cmp(to).map(|o| o == Ordering::Less)
+                });
+                above == Some(true) && below == Some(true) && !key.is_null()
+            }
+            PartitionBound::Hash { modulus, remainder } => hash(key) % modulus == *remainder,
+        }
+    }
+
+    /// Whether the bound leaves room for the rows of `predicate` to have
+    /// keys in column `column`; false only where a conjunct comparing the
+    /// key with a literal rules every admitted key out.
+    pub fn admits(&self, column: usize, predicate: &Expr) -> bool {
+        let Expr::Binary { op, lhs, rhs } = predicate else {
+            return true;
+        };
+        let (op, value) = match (op, lhs.as_ref(), rhs.as_ref()) {
+            (BinaryOp::And, _, _) => {
+                return self.admits(column, lhs) && self.admits(column, rhs);
+            }
+            (op, Expr::Column(c), Expr::Literal(value)) if *c == column => (*op, value),
+            (op, Expr::Literal(value), Expr::Column(c)) if *c == column => match op {
+                BinaryOp::Lt => (BinaryOp::Gt, value),
+                BinaryOp::LtEq => (BinaryOp::GtEq, value),
+                BinaryOp::Gt => (BinaryOp::Lt, value),
+                BinaryOp::GtEq => (BinaryOp::LtEq, value),
+                op => (*op, value),
+            },
+            _ => return true,
+        };
+        if op == BinaryOp::Eq {
+            return self.contains(value);
+        }
+        let PartitionBound::Range { from, to } = self else {
+            return true;
+        };
+        // Whether `bound` compares with `value` as `expected`, which rules
+        // the comparison out.
+        let excludes = |bound: &Option<Value>, expected: &[Ordering]| {
+            bound
+                .as_ref()
+                .and_then(|bound| bound.sql_cmp(value))
+                .is_some_and(|o| expected.contains(&o))
+        };
+        match op {
+            BinaryOp::Lt => !excludes(from, &[Ordering::Equal, Ordering::Greater]),
+            BinaryOp::LtEq => !excludes(from, &[Ordering::Greater]),
+            BinaryOp::Gt | BinaryOp::GtEq => !excludes(to, &[Ordering::Less, Ordering::Equal]),
+            _ => true,
+        }
+    }
+
+    /// Whether some key is admitted by both bounds. Hash bounds overlap
+    /// when their remainders agree modulo the greatest common divisor of
+    /// their moduli.
+    fn overlaps(&self, other: &PartitionBound) -> bool {
+        match (self, other) {
+            (
+                PartitionBound::Range { from, to },
+                PartitionBound::Range {
+                    from: other_from,
+                    to: other_to,
+                },
+            ) => below(from, other_to) && below(other_from, to),
+            (
+                PartitionBound::Hash { modulus, remainder },
+                PartitionBound::Hash {
+                    modulus: other_modulus,
+                    remainder: other_remainder,
+                },
+            ) => {
+                let divisor = gcd(*modulus, *other_modulus);
+                remainder % divisor == other_remainder % divisor
+            }
+            _ => false,
+        }
+    }
+
+    /// Why the bound admits nothing, if it does not.
+    fn check(&self) -> Result<(), &'static str> {
+        match self {
+            PartitionBound::Range {
+                from: Some(from),
+                to: Some(to),
+            } if from.sql_cmp(to) != Some(Ordering::Less) => {
+                Err("the lower bound must be below the upper bound")
+            }
+            PartitionBound::Hash { modulus: 0, .. } => Err("the modulus must be positive"),
+            PartitionBound::Hash { modulus, remainder } if remainder >= modulus => {
+                Err("the remainder must be less than the modulus")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for PartitionBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |bound: &Option<Value>, unbounded| {
+            bound
+                .as_ref()
+                .map_or(String::from(unbounded), Value::to_sql)
+        };
+        match self {
+            PartitionBound::Range { from, to } => write!(
+                f,
+                "FROM ({}) TO ({})",
+                value(from, "MINVALUE"),
+                value(to, "MAXVALUE")
+            ),
+            PartitionBound::Hash { modulus, remainder } => {
+                write!(f, "WITH (MODULUS {modulus}, REMAINDER {remainder})")
+            }
+        }
+    }
+}
+
+/// Whether lower bound `from` is below upper bound `to`.
+fn below(from: &Option<Value>, to: &Option<Value>) -> bool {
+    match (from, to) {
+        (Some(from), Some(to)) => from.sql_cmp(to) == Some(Ordering::Less),
+        _ => true,
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// FNV-1a over the key's encoding, so that rows hash the same way in
+/// every process. Whole floats hash as the ints they equal.
+fn hash(key: &Value) -> u64 {
+    let key = match key {
+        Value::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => Value::Int(*f as i64),
+        key => key.clone(),
+    };
+    let mut bytes = vec![];
+    tuple::encode_key(&[key], &mut bytes);
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// What makes a table a partition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartitionOf {
+    pub parent: String,
+    pub bound: PartitionBound,
+}
+
+impl Catalog {
+    /// Creates a partitioned table, which has no partitions until they
+    /// are attached.
+    pub fn create_partitioned_table(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        schema: Schema,
+        partitioning: Partitioning,
+    ) -> Result<&TableInfo, Error> {
+        if partitioning.column >= schema.len() {
+            return Err(expr::Error::ColumnOutOfRange(partitioning.column).into());
+        }
+        self.create(bufmgr, name, schema, None)?;
+        store::save_partitioning(self.store, bufmgr, name, &partitioning)?;
+        let table = self.tables.get_mut(name).unwrap();
+        table.partitioning = Some(partitioning);
+        Ok(table)
+    }
+
+    /// The partitions of `parent`, in order of name.
+    pub fn partitions(&self, parent: &str) -> Vec<&TableInfo> {
+        let mut partitions: Vec<&TableInfo> = self
+            .tables
+            .values()
+            .filter(|table| {
+                table
+                    .partition_of
+                    .as_ref()
+                    .is_some_and(|partition| partition.parent == parent)
+            })
+            .collect();
+        partitions.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+        partitions
+    }
+
+    /// The partition of `parent` that `tuple` belongs in.
+    pub fn partition_for(&self, parent: &TableInfo, tuple: &[Value]) -> Option<&TableInfo> {
+        let key = &tuple[parent.partitioning?.column];
+        self.partitions(&parent.name).into_iter().find(|table| {
+            table
+                .partition_of
+                .as_ref()
+                .is_some_and(|partition| partition.bound.contains(key))
+        })
+    }
+
+    /// Makes the table `name` a partition of `parent`. It must have the
+    /// same columns, no rows outside `bound`, and `bound` must overlap no
+    /// other partition's.
+    pub fn attach_partition(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        parent: &str,
+        name: &str,
+        bound: PartitionBound,
+    ) -> Result<&TableInfo, Error> {
+        let parent_table = self
+            .tables
+            .get(parent)
+            .ok_or_else(|| Error::TableNotFound(parent.to_string()))?;
+        let partitioning = parent_table
+            .partitioning
+            .ok_or_else(|| Error::NotPartitioned(parent.to_string()))?;
+        let table = self
+            .tables
+            .get(name)
+            .ok_or_else(|| Error::TableNotFound(name.to_string()))?;
+        if table.view.is_some() || table.partitioning.is_some() {
+            return Err(Error::NotATable(name.to_string()));
+        }
+        if table.partition_of.is_some() {
+            return Err(Error::AlreadyPartition(name.to_string()));
+        }
+        let same_columns = table.schema.len() == parent_table.schema.len()
+            && (table.schema.columns.iter())
+                .zip(&parent_table.schema.columns)
+                .all(|(a, b)| a.name == b.name && a.data_type == b.data_type);
+        if !same_columns {
+            return Err(Error::PartitionColumns {
+                partition: name.to_string(),
+                parent: parent.to_string(),
+            });
+        }
+        let invalid = |reason| Error::InvalidPartitionBound {
+            partition: name.to_string(),
+            reason,
+        };
+        if bound.method() != partitioning.method {
+            return Err(invalid(match partitioning.method {
+                PartitionMethod::Range => "a range partition needs FROM ... TO bounds",
+                PartitionMethod::Hash => "a hash partition needs a modulus and remainder",
+            }));
+        }
+        bound.check().map_err(invalid)?;
+        let overlapping = self.partitions(parent).into_iter().find(|other| {
+            other
+                .partition_of
+                .as_ref()
+                .is_some_and(|other| other.bound.overlaps(&bound))
+        });
+        if let Some(other) = overlapping {
+            return Err(Error::PartitionOverlap {
+                partition: name.to_string(),
+                other: other.name.clone(),
+            });
+        }
+        let mut scan = table.heap.scan(bufmgr)?;
+        while let Some((_, tuple)) = scan.next(bufmgr)? {
+            if !bound.contains(&tuple[partitioning.column]) {
+                return Err(Error::RowOutsideBound(name.to_string()));
+            }
+        }
+        let partition = PartitionOf {
+            parent: parent.to_string(),
+            bound,
+        };
+        store::save_partition(self.store, bufmgr, name, &partition)?;
+        let table = self.tables.get_mut(name).unwrap();
+        table.partition_of = Some(partition);
+        Ok(table)
+    }
+
+    /// Makes a partition of `parent` a table of its own again, keeping
+    /// its rows.
+    pub fn detach_partition(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        parent: &str,
+        name: &str,
+    ) -> Result<&TableInfo, Error> {
+        let table = self
+            .tables
+            .get_mut(name)
+            .ok_or_else(|| Error::TableNotFound(name.to_string()))?;
+        if table.partition_of.as_ref().map(|p| p.parent.as_str()) != Some(parent) {
+            return Err(Error::NotAPartition {
+                partition: name.to_string(),
+                parent: parent.to_string(),
+            });
+        }
+        store::remove(self.store, bufmgr, "partition", name, false)?;
+        table.partition_of = None;
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds() {
+        let range = |from: i64, to: i64| PartitionBound::Range {
+            from: Some(Value::Int(from)),
+            to: Some(Value::Int(to)),
+        };
+        let bound = range(10, 20);
+        assert!(bound.contains(&Value::Int(10)));
+        assert!(!bound.contains(&Value::Int(20)));
+        assert!(!bound.contains(&Value::Null));
+        assert!(bound.overlaps(&range(19, 30)));
+        assert!(!bound.overlaps(&range(20, 30)));
+        let unbounded = PartitionBound::Range {
+            from: None,
+            to: Some(Value::Int(11)),
+        };
+        assert!(bound.overlaps(&unbounded));
+
+        let key = |op, value: i64| Expr::binary(op, Expr::column(0), Expr::literal(value));
+        assert!(!bound.admits(0, &key(BinaryOp::Eq, 20)));
+        assert!(!bound.admits(0, &key(BinaryOp::Lt, 10)));
+        assert!(bound.admits(0, &key(BinaryOp::LtEq, 10)));
+        assert!(!bound.admits(0, &key(BinaryOp::GtEq, 20)));
+        assert!(bound.admits(1, &key(BinaryOp::Eq, 20)));
+        let both = Expr::binary(BinaryOp::And, key(BinaryOp::Gt, 0), key(BinaryOp::Lt, 5));
+        assert!(!bound.admits(0, &both));
+
+        let hash = |modulus, remainder| PartitionBound::Hash { modulus, remainder };
+        assert!(hash(4, 1).overlaps(&hash(2, 1)));
+        assert!(!hash(4, 2).overlaps(&hash(2, 1)));
+        let held = (0..4)
+            .filter(|r| hash(4, *r).contains(&Value::Int(7)))
+            .count();
+        assert_eq!(1, held);
+        assert_eq!(
+            Err("the remainder must be less than the modulus"),
+            hash(2, 2).check()
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Indexes being built.
+//!
+//! An index that `CREATE INDEX CONCURRENTLY` is filling is in the catalog
+//! from the start, as a [`PendingIndex`] beside the table's indexes: the
+//! planner does not know of it and writes do not maintain it, but its
+//! name is taken and its pages are its own. Finishing the build moves it
+//! to the table's indexes. One whose build fails stays, with the error,
+//! for DROP INDEX to clean up; one that a close cut short is built again
+//! once the database is opened.
+
+use super::{store, Catalog, Error, IndexInfo};
+use crate::buffer::BufferPoolManager;
+
+#[derive(Debug, Clone)]
+pub struct PendingIndex {
+    pub table: String,
+    pub index: IndexInfo,
+    /// Why the build failed, if it did.
+    pub error: Option<String>,
+}
+
+impl Catalog {
+    /// The indexes being built, and those whose build failed.
+    pub fn pending_indexes(&self) -> &[PendingIndex] {
+        &self.pending
+    }
+
+    pub fn pending_index(&self, name: &str) -> Option<&PendingIndex> {
+        self.pending
+            .iter()
+            .find(|pending| pending.index.name == name)
+    }
+
+    /// Records `index`, still empty, as being built for `table`.
+    pub fn add_pending_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table: &str,
+        index: IndexInfo,
+    ) -> Result<&PendingIndex, Error> {
+        if self.index_name_taken(&index.name) {
+            return Err(Error::IndexExists(index.name));
+        }
+        if !self.tables.contains_key(table) {
+            return Err(Error::TableNotFound(table.to_string()));
+        }
+        let pending = PendingIndex {
+            table: table.to_string(),
+            index,
+            error: None,
+        };
+        store::save_pending_index(self.store, bufmgr, &pending)?;
+        self.pending.push(pending);
+        Ok(self.pending.last().unwrap())
+    }
+
+    /// Moves the pending index `name`, now filled with the rows of its
+    /// table, to the table's indexes.
+    pub fn finish_pending_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<&IndexInfo, Error> {
+        let pending = self.remove_pending(bufmgr, name)?;
+        self.add_index(bufmgr, &pending.table, pending.index)
+    }
+
+    /// Records that building the pending index `name` failed with
+    /// `error`.
+    pub fn fail_pending_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        error: String,
+    ) -> Result<(), Error> {
+        let i = self.pending_position(name)?;
+        store::remove(self.store, bufmgr, "pending index", name, false)?;
+        self.pending[i].error = Some(error);
+        store::save_pending_index(self.store, bufmgr, &self.pending[i])
+    }
+
+    /// Removes the pending index `name`, freeing its pages for reuse.
+    pub(super) fn drop_pending_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<IndexInfo, Error> {
+        let pending = self.remove_pending(bufmgr, name)?;
+        pending.index.btree.free(bufmgr)?;
+        Ok(pending.index)
+    }
+
+    fn remove_pending(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<PendingIndex, Error> {
+        let i = self.pending_position(name)?;
+        store::remove(self.store, bufmgr, "pending index", name, false)?;
+        Ok(self.pending.remove(i))
+    }
+
+    fn pending_position(&self, name: &str) -> Result<usize, Error> {
+        (self.pending.iter())
+            .position(|pending| pending.index.name == name)
+            .ok_or_else(|| Error::IndexNotFound(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::btree::BTree;
+    use crate::catalog::{Column, IndexKey, Schema};
+    use crate::disk::DiskManager;
+    use crate::value::DataType;
+
+    #[test]
+    fn test_pending_index() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 16);
+        let mut catalog = Catalog::open(&bufmgr).unwrap();
+        let schema = Schema::new(vec![Column::new("id", DataType::Int)]);
+        catalog.create_table(&bufmgr, "t", schema.clone()).unwrap();
+        let index = |name: &str| IndexInfo {
+            name: name.to_string(),
+            keys: vec![IndexKey::for_column(&schema, 0)],
+            predicate: None,
+            unique: true,
+            btree: BTree::create(&bufmgr).unwrap(),
+        };
+        catalog.add_pending_index(&bufmgr, "t", index("a")).unwrap();
+        catalog.add_pending_index(&bufmgr, "t", index("b")).unwrap();
+        assert!(matches!(
+            catalog.create_index(&bufmgr, "t", "a", &["id"], false),
+            Err(Error::IndexExists(_))
+        ));
+        catalog
+            .fail_pending_index(&bufmgr, "b", "duplicate key".to_string())
+            .unwrap();
+
+        // Both outlive the catalog, and the failure with them.
+        let mut catalog = Catalog::open(&bufmgr).unwrap();
+        let names: Vec<_> = (catalog.pending_indexes().iter())
+            .map(|pending| (pending.index.name.as_str(), pending.error.as_deref()))
+            .collect();
+        assert_eq!(vec![("a", None), ("b", Some("duplicate key"))], names);
+        catalog.finish_pending_index(&bufmgr, "a").unwrap();
+        catalog.drop_index(&bufmgr, "b").unwrap();
+        let catalog = Catalog::open(&bufmgr).unwrap();
+        assert!(catalog.pending_indexes().is_empty());
+        assert!(catalog.table("t").unwrap().index("a").is_some());
+    }
+}
@@ -0,0 +1,161 @@
+//! Row security policies.
+//!
+//! A policy on a table gives some users, or all of them, the rows for
+//! which its condition holds. Once a table has a policy, users other than
+//! superusers read, update and delete only the rows some policy of the
+//! table gives them, and may only write rows that some policy would give
+//! them back; a user no policy is for sees no rows at all. A partitioned
+//! table's policies cover its partitions, which have none of their own.
+//! Policies go away with their table.
+
+use super::{store, Catalog, Error};
+use crate::buffer::BufferPoolManager;
+use crate::expr::{BinaryOp, Expr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyInfo {
+    pub name: String,
+    /// Users the policy is for, in order of name; every user if empty.
+    pub users: Vec<String>,
+    /// Which rows the policy gives, over the columns of the table.
+    pub condition: Expr,
+}
+
+impl PolicyInfo {
+    pub fn applies_to(&self, user: &str) -> bool {
+        self.users.is_empty() || self.users.iter().any(|name| name == user)
+    }
+}
+
+impl Catalog {
+    /// Adds a policy to a table, whose policies are kept in order of name.
+    pub fn create_policy(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+        policy: PolicyInfo,
+    ) -> Result<&PolicyInfo, Error> {
+        if let Some(user) = policy.users.iter().find(|user| self.user(user).is_none()) {
+            return Err(Error::UserNotFound(user.clone()));
+        }
+        let table = self
+            .tables
+            .get_mut(table_name)
+            .ok_or_else(|| Error::TableNotFound(table_name.to_string()))?;
+        if table.view.is_some() {
+            return Err(Error::NotATable(table_name.to_string()));
+        }
+        if table.partition_of.is_some() {
+            return Err(Error::PolicyOnPartition(table_name.to_string()));
+        }
+        if table.policies.iter().any(|other| other.name == policy.name) {
+            return Err(Error::PolicyExists {
+                name: policy.name,
+                table: table_name.to_string(),
+            });
+        }
+        store::save_policy(self.store, bufmgr, table_name, &policy)?;
+        let i = table
+            .policies
+            .partition_point(|other| other.name < policy.name);
+        table.policies.insert(i, policy);
+        Ok(&table.policies[i])
+    }
+
+    pub fn drop_policy(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+        name: &str,
+    ) -> Result<PolicyInfo, Error> {
+        let table = self
+            .tables
+            .get_mut(table_name)
+            .ok_or_else(|| Error::TableNotFound(table_name.to_string()))?;
+        let Some(i) = table.policies.iter().position(|policy| policy.name == name) else {
+            return Err(Error::PolicyNotFound {
+                name: name.to_string(),
+                table: table_name.to_string(),
+            });
+        };
+        store::remove_policy(self.store, bufmgr, table_name, name)?;
+        Ok(table.policies.remove(i))
+    }
+
+    /// The first policy that is for `user`, if one names the user.
+    pub(super) fn policy_naming(&self, user: &str) -> Option<&PolicyInfo> {
+        (self.tables.values())
+            .flat_map(|table| &table.policies)
+            .find(|policy| policy.users.iter().any(|name| name == user))
+    }
+
+    /// The condition the rows of `table` that `user` may see and write
+    /// meet, or `None` if the user may have them all: as a superuser, or
+    /// since the table has no policies. It is false if no policy is for
+    /// the user.
+    pub fn row_filter(&self, table: &str, user: &str) -> Option<Expr> {
+        if self.user(user).is_some_and(|user| user.superuser) {
+            return None;
+        }
+        let mut info = self.table(table)?;
+        if let Some(partition) = &info.partition_of {
+            info = self.table(&partition.parent)?;
+        }
+        if info.policies.is_empty() {
+            return None;
+        }
+        let conditions = (info.policies.iter())
+            .filter(|policy| policy.applies_to(user))
+            .map(|policy| policy.condition.clone());
+        let filter = conditions.reduce(|any, condition| Expr::binary(BinaryOp::Or, any, condition));
+        Some(filter.unwrap_or_else(|| Expr::literal(false)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{Column, Schema, User};
+    use crate::disk::DiskManager;
+    use crate::value::DataType;
+
+    #[test]
+    fn test_row_filter() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 16);
+        let mut catalog = Catalog::open(&bufmgr).unwrap();
+        let schema = Schema::new(vec![Column::new("tenant", DataType::Text)]);
+        catalog.create_table(&bufmgr, "t", schema).unwrap();
+        for name in ["alice", "bob", "root"] {
+            let mut user = User::new(name);
+            user.superuser = name == "root";
+            catalog.create_user(&bufmgr, user).unwrap();
+        }
+        assert_eq!(None, catalog.row_filter("t", "alice"));
+
+        let tenant = |name: &str| Expr::binary(BinaryOp::Eq, Expr::column(0), Expr::literal(name));
+        let policy = PolicyInfo {
+            name: "own".to_string(),
+            users: vec!["alice".to_string()],
+            condition: tenant("a"),
+        };
+        catalog.create_policy(&bufmgr, "t", policy.clone()).unwrap();
+        assert!(matches!(
+            catalog.create_policy(&bufmgr, "t", policy),
+            Err(Error::PolicyExists { .. })
+        ));
+        assert_eq!(Some(tenant("a")), catalog.row_filter("t", "alice"));
+        assert_eq!(Some(Expr::literal(false)), catalog.row_filter("t", "bob"));
+        assert_eq!(None, catalog.row_filter("t", "root"));
+        assert!(matches!(
+            catalog.drop_user(&bufmgr, "alice"),
+            Err(Error::UserHasPolicy { .. })
+        ));
+
+        // Kept in the file until dropped.
+        let reopened = Catalog::open(&bufmgr).unwrap();
+        assert_eq!(Some(tenant("a")), reopened.row_filter("t", "alice"));
+        catalog.drop_policy(&bufmgr, "t", "own").unwrap();
+        assert_eq!(None, Catalog::open(&bufmgr).unwrap().row_filter("t", "bob"));
+    }
+}
@@ -0,0 +1,1088 @@
+//! Keeping the catalog in the database file.
+//!
+//! A catalog opened with [`Catalog::open`] lives in a heap whose meta page
+//! is the first page of the file, one row per table, view, index, trigger,
+//! partition, time to live, policy, collated column and user; the rows change
+//! along with the catalog. Each row is a flat list of values:
+//!
+//! - `'table', name, heap meta page`, then the number of columns and
+//!   `name, type, nullable` for each;
+//! - `'collation', table, column, name`, for a column of a table whose
+//!   collation is not `binary`;
+//! - `'view', name, definition`, for a table that is a materialized view,
+//!   then the number of tables it depends on and their names;
+//! - `'index', name, table, btree meta page, unique`, then the number of
+//!   keys and `type, expr` for each, then whether there is a predicate and
+//!   the predicate;
+//! - `'pending index'` and then as an index, for one being built, then
+//!   why its build failed or NULL;
+//! - `'nulls last', index, table`, then the number of keys of the index
+//!   kept with NULLS LAST and their positions, for an index with some;
+//! - `'trigger', name, table, timing`, then the number of events and
+//!   their names, then `'statement', definition` and the number of
+//!   parameters and `image, column` for each, or `'function', name`;
+//! - `'partitioning', table, method, column`, for a partitioned table;
+//! - `'partition', table, parent`, then `'range', from, to`, NULL for an
+//!   unbounded end, or `'hash', modulus, remainder`;
+//! - `'ttl', table, column, duration`, the duration NULL for a TTL
+//!   without one;
+//! - `'policy', name, table`, then the number of users and their names,
+//!   then the condition;
+//! - `'unlogged', name`, for an unlogged table, whose `'table'` row has
+//!   the meta page its heap had in its temporary file, which means
+//!   nothing once the file is gone, and whose indexes' rows are alike;
+//! - `'user', name, verifier, superuser`, the verifier NULL for a user
+//!   without a password, then the number of grants and `table,
+//!   privileges` for each, the privileges as a bit set, then the number
+//!   of tables with column grants and for each the table, the number of
+//!   its columns granted SELECT and their names.
+//!
+//! Expressions are written in prefix order, each node a tag naming its
+//! variant followed by its fields. Statistics are not stored.
+//!
+//! The heap's meta page also names the page heading the file's list of
+//! free pages, which is made when the catalog is first opened for
+//! writing; see [`BufferPoolManager::set_free_list`].
+
+use std::ops::Range;
+use std::vec;
+
+use super::{
+    Catalog, Column, Error, IndexInfo, IndexKey, PartitionBound, PartitionMethod, PartitionOf,
+    Partitioning, PendingIndex, PolicyInfo, Privileges, RowImage, Schema, TableInfo, TriggerAction,
+    TriggerEvent, TriggerInfo, TriggerTiming, Ttl, User, ViewInfo,
+};
+use crate::btree::BTree;
+use crate::buffer::BufferPoolManager;
+use crate::collation::Collation;
+use crate::disk::PageId;
+use crate::expr::{BinaryOp, Expr, ScalarFunction, UnaryOp};
+use crate::heap::{self, HeapFile};
+use crate::value::{DataType, Value};
+
+/// Meta page of the heap holding the catalog.
+pub const CATALOG_PAGE_ID: PageId = PageId(0);
+
+/// Where the catalog's meta page keeps the page heading the free list, 0
+/// before there is one.
+pub const FREE_LIST_RANGE: Range<usize> = 48..56;
+
+const DATA_TYPES: [DataType; 6] = [
+    DataType::Bool,
+    DataType::Int,
+    DataType::Float,
+    DataType::Text,
+    DataType::Bytes,
+    DataType::Json,
+];
+
+const UNARY_OPS: [UnaryOp; 2] = [UnaryOp::Not, UnaryOp::Neg];
+
+const BINARY_OPS: [BinaryOp; 18] = [
+    BinaryOp::Add,
+    BinaryOp::Sub,
+    BinaryOp::Mul,
+    BinaryOp::Div,
+    BinaryOp::Mod,
+    BinaryOp::Eq,
+    BinaryOp::NotEq,
+    BinaryOp::Lt,
+    BinaryOp::LtEq,
+    BinaryOp::Gt,
+    BinaryOp::GtEq,
+    BinaryOp::And,
+    BinaryOp::Or,
+    BinaryOp::Concat,
+    BinaryOp::Like,
+    BinaryOp::JsonGet,
+    BinaryOp::JsonGetText,
+    BinaryOp::Subscript,
+];
+
+const TRIGGER_TIMINGS: [TriggerTiming; 2] = [TriggerTiming::Before, TriggerTiming::After];
+
+const TRIGGER_EVENTS: [TriggerEvent; 3] = [
+    TriggerEvent::Insert,
+    TriggerEvent::Update,
+    TriggerEvent::Delete,
+];
+
+const ROW_IMAGES: [RowImage; 2] = [RowImage::Old, RowImage::New];
+
+const PARTITION_METHODS: [PartitionMethod; 2] = [PartitionMethod::Range, PartitionMethod::Hash];
+
+impl Catalog {
+    /// Reads the catalog stored in the file behind `bufmgr`, or starts an
+    /// empty one if the file has no pages yet.
+    pub fn open(bufmgr: &BufferPoolManager) -> Result<Self, Error> {
+        if bufmgr.num_pages() == 0 {
+            let store = HeapFile::create(bufmgr)?;
+            assert_eq!(CATALOG_PAGE_ID, store.meta_page_id);
+            open_free_list(bufmgr)?;
+            return Ok(Self {
+                store: Some(store),
+                ..Self::default()
+            });
+        }
+        open_free_list(bufmgr)?;
+        let store = HeapFile::new(CATALOG_PAGE_ID);
+        let mut catalog = Self {
+            store: Some(store),
+            ..Self::default()
+        };
+        let mut indexes = vec![];
+        let mut views = vec![];
+        let mut triggers = vec![];
+        let mut partitionings = vec![];
+        let mut partitions = vec![];
+        let mut ttls = vec![];
+        let mut policies = vec![];
+        let mut collations = vec![];
+        let mut nulls_last = vec![];
+        let mut unlogged = vec![];
+        let mut scan = store.scan(bufmgr)?;
+        while let Some((_, row)) = scan.next(bufmgr)? {
+            let mut row = Reader(row.into_iter());
+            match row.text()?.as_str() {
+                "table" => {
+                    let table = row.table()?;
+                    catalog.tables.insert(table.name.clone(), table);
+                }
+                "index" => indexes.push(row.index()?),
+                "pending index" => catalog.pending.push(row.pending_index()?),
+                "view" => views.push(row.view()?),
+                "trigger" => triggers.push(row.trigger()?),
+                "partitioning" => partitionings.push(row.partitioning()?),
+                "partition" => partitions.push(row.partition()?),
+                "ttl" => ttls.push(row.ttl()?),
+                "policy" => policies.push(row.policy()?),
+                "collation" => collations.push(row.collation()?),
+                "nulls last" => nulls_last.push(row.nulls_last()?),
+                "unlogged" => unlogged.push(row.text()?),
+                "user" => {
+                    let user = row.user()?;
+                    catalog.users.insert(user.name.clone(), user);
+                }
+                _ => return Err(corrupt("unknown kind of row")),
+            }
+        }
+        for (table, column, collation) in collations {
+            catalog
+                .tables
+                .get_mut(&table)
+                .and_then(|table| table.schema.columns.get_mut(column))
+                .ok_or_else(|| corrupt("collation of a missing column"))?
+                .collation = collation;
+        }
+        for (table, index) in indexes {
+            catalog
+                .tables
+                .get_mut(&table)
+                .ok_or_else(|| corrupt("index of a missing table"))?
+                .indexes
+                .push(index);
+        }
+        for (index, table, keys) in nulls_last {
+            let pending = (catalog.pending.iter_mut())
+                .filter(|pending| pending.table == table)
+                .map(|pending| &mut pending.index);
+            let index = (catalog.tables.get_mut(&table))
+                .into_iter()
+                .flat_map(|table| table.indexes.iter_mut())
+                .chain(pending)
+                .find(|other| other.name == index)
+                .ok_or_else(|| corrupt("null ordering of a missing index"))?;
+            for key in keys {
+                index
+                    .keys
+                    .get_mut(key)
+                    .ok_or_else(|| corrupt("null ordering of a missing key"))?
+                    .nulls_first = false;
+            }
+        }
+        for (table, view) in views {
+            catalog
+                .tables
+                .get_mut(&table)
+                .ok_or_else(|| corrupt("view of a missing table"))?
+                .view = Some(view);
+        }
+        for (table, trigger) in triggers {
+            catalog
+                .tables
+                .get_mut(&table)
+                .ok_or_else(|| corrupt("trigger of a missing table"))?
+                .triggers
+                .push(trigger);
+        }
+        for (table, partitioning) in partitionings {
+            catalog
+                .tables
+                .get_mut(&table)
+                .ok_or_else(|| corrupt("partitioning of a missing table"))?
+                .partitioning = Some(partitioning);
+        }
+        for (table, partition) in partitions {
+            catalog
+                .tables
+                .get_mut(&table)
+                .ok_or_else(|| corrupt("partition of a missing table"))?
+                .partition_of = Some(partition);
+        }
+        for (table, ttl) in ttls {
+            catalog
+                .tables
+                .get_mut(&table)
+                .ok_or_else(|| corrupt("TTL of a missing table"))?
+                .ttl = Some(ttl);
+        }
+        for (table, policy) in policies {
+            catalog
+                .tables
+                .get_mut(&table)
+                .ok_or_else(|| corrupt("policy of a missing table"))?
+                .policies
+                .push(policy);
+        }
+        for pending in &catalog.pending {
+            if !catalog.tables.contains_key(&pending.table) {
+                return Err(corrupt("pending index of a missing table"));
+            }
+        }
+        catalog
+            .pending
+            .sort_by(|a, b| a.index.name.cmp(&b.index.name));
+        for table in catalog.tables.values_mut() {
+            table.indexes.sort_by_key(|index| index.btree.meta_page_id);
+            table.triggers.sort_by(|a, b| a.name.cmp(&b.name));
+            table.policies.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+        if !unlogged.is_empty() {
+            catalog.open_unlogged(bufmgr, unlogged)?;
+        }
+        Ok(catalog)
+    }
+}
+
+/// Lets `bufmgr` reuse the pages freed in its file, first giving the file
+/// a free list if it has none and can be written.
+fn open_free_list(bufmgr: &BufferPoolManager) -> Result<(), Error> {
+    let meta_buffer = bufmgr
+        .fetch_page(CATALOG_PAGE_ID)
+        .map_err(heap::Error::from)?;
+    let list = PageId::from_bytes(&meta_buffer.read()[FREE_LIST_RANGE]);
+    if list != PageId(0) {
+        bufmgr.set_free_list(list);
+    } else if !bufmgr.is_read_only() {
+        let list = bufmgr.create_page().map_err(heap::Error::from)?.page_id;
+        meta_buffer.write()[FREE_LIST_RANGE].copy_from_slice(&list.to_bytes());
+        bufmgr.set_free_list(list);
+    }
+    Ok(())
+}
+
+pub(super) fn save_table(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &TableInfo,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let mut row = vec![
+        "table".into(),
+        table.name.as_str().into(),
+        page_value(table.heap.meta_page_id),
+        Value::Int(table.schema.len() as i64),
+    ];
+    for column in &table.schema.columns {
+        row.push(column.name.as_str().into());
+        row.push(column.data_type.to_string().into());
+        row.push(column.nullable.into());
+    }
+    store.insert(bufmgr, &row)?;
+    for (i, column) in table.schema.columns.iter().enumerate() {
+        if column.collation != Collation::Binary {
+            let row = [
+                "collation".into(),
+                table.name.as_str().into(),
+                Value::Int(i as i64),
+                column.collation.to_string().into(),
+            ];
+            store.insert(bufmgr, &row)?;
+        }
+    }
+    if let Some(view) = &table.view {
+        let mut row = vec![
+            "view".into(),
+            table.name.as_str().into(),
+            view.definition.as_str().into(),
+            Value::Int(view.dependencies.len() as i64),
+        ];
+        row.extend(view.dependencies.iter().map(|name| name.as_str().into()));
+        store.insert(bufmgr, &row)?;
+    }
+    Ok(())
+}
+
+pub(super) fn save_index(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    index: &IndexInfo,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    store.insert(bufmgr, &index_row("index", table, index))?;
+    save_nulls_last(store, bufmgr, table, index)
+}
+
+pub(super) fn save_pending_index(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    pending: &PendingIndex,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let mut row = index_row("pending index", &pending.table, &pending.index);
+    row.push(pending.error.as_deref().map_or(Value::Null, Value::from));
+    store.insert(bufmgr, &row)?;
+    save_nulls_last(store, bufmgr, &pending.table, &pending.index)
+}
+
+fn index_row(kind: &str, table: &str, index: &IndexInfo) -> Vec<Value> {
+    let mut row = vec![
+        kind.into(),
+        index.name.as_str().into(),
+        table.into(),
+        page_value(index.btree.meta_page_id),
+        index.unique.into(),
+        Value::Int(index.keys.len() as i64),
+    ];
+    for key in &index.keys {
+        row.push(key.data_type.to_string().into());
+        write_expr(&key.expr, &mut row);
+    }
+    row.push(index.predicate.is_some().into());
+    if let Some(predicate) = &index.predicate {
+        write_expr(predicate, &mut row);
+    }
+    row
+}
+
+fn save_nulls_last(
+    store: HeapFile,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    index: &IndexInfo,
+) -> Result<(), Error> {
+    let nulls_last: Vec<Value> = (index.keys.iter().enumerate())
+        .filter(|(_, key)| !key.nulls_first)
+        .map(|(i, _)| Value::Int(i as i64))
+        .collect();
+    if !nulls_last.is_empty() {
+        let mut row = vec![
+            "nulls last".into(),
+            index.name.as_str().into(),
+            table.into(),
+            Value::Int(nulls_last.len() as i64),
+        ];
+        row.extend(nulls_last);
+        store.insert(bufmgr, &row)?;
+    }
+    Ok(())
+}
+
+pub(super) fn save_unlogged(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    store.insert(bufmgr, &["unlogged".into(), table.into()])?;
+    Ok(())
+}
+
+pub(super) fn save_trigger(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    trigger: &TriggerInfo,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let mut row = vec![
+        "trigger".into(),
+        trigger.name.as_str().into(),
+        table.into(),
+        trigger.timing.to_string().into(),
+        Value::Int(trigger.events.len() as i64),
+    ];
+    row.extend(trigger.events.iter().map(|event| event.to_string().into()));
+    match &trigger.action {
+        TriggerAction::Statement {
+            definition,
+            parameters,
+        } => {
+            row.push("statement".into());
+            row.push(definition.as_str().into());
+            row.push(Value::Int(parameters.len() as i64));
+            for (image, column) in parameters {
+                row.push(image.to_string().into());
+                row.push(Value::Int(*column as i64));
+            }
+        }
+        TriggerAction::Function(name) => {
+            row.push("function".into());
+            row.push(name.as_str().into());
+        }
+    }
+    store.insert(bufmgr, &row)?;
+    Ok(())
+}
+
+pub(super) fn save_partitioning(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    partitioning: &Partitioning,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let row = vec![
+        "partitioning".into(),
+        table.into(),
+        partitioning.method.to_string().into(),
+        Value::Int(partitioning.column as i64),
+    ];
+    store.insert(bufmgr, &row)?;
+    Ok(())
+}
+
+pub(super) fn save_partition(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    partition: &PartitionOf,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let mut row = vec![
+        "partition".into(),
+        table.into(),
+        partition.parent.as_str().into(),
+    ];
+    match &partition.bound {
+        PartitionBound::Range { from, to } => {
+            row.push("range".into());
+            row.push(from.clone().unwrap_or(Value::Null));
+            row.push(to.clone().unwrap_or(Value::Null));
+        }
+        PartitionBound::Hash { modulus, remainder } => {
+            row.push("hash".into());
+            row.push(Value::Int(*modulus as i64));
+            row.push(Value::Int(*remainder as i64));
+        }
+    }
+    store.insert(bufmgr, &row)?;
+    Ok(())
+}
+
+pub(super) fn save_ttl(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    ttl: &Ttl,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let row = vec![
+        "ttl".into(),
+        table.into(),
+        Value::Int(ttl.column as i64),
+        ttl.duration
+            .map_or(Value::Null, |duration| Value::Int(duration as i64)),
+    ];
+    store.insert(bufmgr, &row)?;
+    Ok(())
+}
+
+pub(super) fn save_policy(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    policy: &PolicyInfo,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let mut row = vec![
+        "policy".into(),
+        policy.name.as_str().into(),
+        table.into(),
+        Value::Int(policy.users.len() as i64),
+    ];
+    row.extend(policy.users.iter().map(|user| user.as_str().into()));
+    write_expr(&policy.condition, &mut row);
+    store.insert(bufmgr, &row)?;
+    Ok(())
+}
+
+pub(super) fn save_user(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    user: &User,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let verifier = user
+        .verifier
+        .as_ref()
+        .map_or(Value::Null, |verifier| verifier.to_string().into());
+    let mut row = vec![
+        "user".into(),
+        user.name.as_str().into(),
+        verifier,
+        user.superuser.into(),
+        Value::Int(user.grants.len() as i64),
+    ];
+    for (table, privileges) in &user.grants {
+        row.push(table.as_str().into());
+        row.push(Value::Int(privileges.bits().into()));
+    }
+    row.push(Value::Int(user.column_grants.len() as i64));
+    for (table, columns) in &user.column_grants {
+        row.push(table.as_str().into());
+        row.push(Value::Int(columns.len() as i64));
+        row.extend(columns.iter().map(|column| column.as_str().into()));
+    }
+    store.insert(bufmgr, &row)?;
+    Ok(())
+}
+
+/// Deletes the rows of tables, indexes, pending indexes, triggers,
+/// partitions, TTLs or users named `name`, and with `with_indexes` those
+/// of the table's indexes, pending indexes, triggers, policies, partitioning, TTL, collations and
+/// unlogged mark too.
+pub(super) fn remove(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    kind: &str,
+    name: &str,
+    with_indexes: bool,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let mut doomed = vec![];
+    let mut scan = store.scan(bufmgr)?;
+    while let Some((rid, row)) = scan.next(bufmgr)? {
+        let matches = match row.as_slice() {
+            [Value::Text(k), Value::Text(n), ..] if k == kind && n == name => true,
+            [Value::Text(k), Value::Text(n), ..]
+                if k == "nulls last" && kind.ends_with("index") =>
+            {
+                n == name
+            }
+            [Value::Text(k), Value::Text(n), ..]
+                if ["collation", "partitioning", "partition", "ttl", "unlogged"]
+                    .contains(&k.as_str()) =>
+            {
+                with_indexes && n == name
+            }
+            [Value::Text(k), _, Value::Text(t), ..] => {
+                with_indexes
+                    && ["index", "pending index", "nulls last", "trigger", "policy"]
+                        .contains(&k.as_str())
+                    && t == name
+            }
+            _ => false,
+        };
+        if matches {
+            doomed.push(rid);
+        }
+    }
+    for rid in doomed {
+        store.delete(bufmgr, rid)?;
+    }
+    Ok(())
+}
+
+/// Deletes the row of the policy of `table` named `name`, which policies
+/// of other tables may share.
+pub(super) fn remove_policy(
+    store: Option<HeapFile>,
+    bufmgr: &BufferPoolManager,
+    table: &str,
+    name: &str,
+) -> Result<(), Error> {
+    let Some(store) = store else {
+        return Ok(());
+    };
+    let mut doomed = None;
+    let mut scan = store.scan(bufmgr)?;
+    while let Some((rid, row)) = scan.next(bufmgr)? {
+        if let [Value::Text(k), Value::Text(n), Value::Text(t), ..] = row.as_slice() {
+            if k == "policy" && n == name && t == table {
+                doomed = Some(rid);
+            }
+        }
+    }
+    if let Some(rid) = doomed {
+        store.delete(bufmgr, rid)?;
+    }
+    Ok(())
+}
+
+fn corrupt(reason: &'static str) -> Error {
+    Error::Corrupt(reason)
+}
+
+fn page_value(page_id: PageId) -> Value {
+    Value::Int(page_id.to_u64() as i64)
+}
+
+fn write_expr(expr: &Expr, row: &mut Vec<Value>) {
+    match expr {
+        Expr::Column(column) => {
+            row.push("column".into());
+            row.push(Value::Int(*column as i64));
+        }
+        Expr::Literal(value) => {
+            row.push("literal".into());
+            row.push(value.clone());
+        }
+        Expr::Parameter(n) => {
+            row.push("parameter".into());
+            row.push(Value::Int(*n as i64));
+        }
+        Expr::Unary { op, expr } => {
+            row.push("unary".into());
+            row.push(op.to_string().into());
+            write_expr(expr, row);
+        }
+        Expr::Binary { op, lhs, rhs } => {
+            row.push("binary".into());
+            row.push(op.to_string().into());
+            write_expr(lhs, row);
+            write_expr(rhs, row);
+        }
+        Expr::IsNull { expr, negated } => {
+            row.push("is_null".into());
+            row.push((*negated).into());
+            write_expr(expr, row);
+        }
+        Expr::Cast { expr, data_type } => {
+            row.push("cast".into());
+            row.push(data_type.to_string().into());
+            write_expr(expr, row);
+        }
+        Expr::Function { func, arg } => {
+            row.push("function".into());
+            row.push(func.to_string().into());
+            write_expr(arg, row);
+        }
+        Expr::Array { element, items } => {
+            row.push("array".into());
+            row.push(element.to_string().into());
+            row.push(Value::Int(items.len() as i64));
+            for item in items {
+                write_expr(item, row);
+            }
+        }
+        Expr::Any {
+            op,
+            lhs,
+            array,
+            all,
+        } => {
+            row.push("any".into());
+            row.push(op.to_string().into());
+            row.push((*all).into());
+            write_expr(lhs, row);
+            write_expr(array, row);
+        }
+        Expr::Collate { expr, collation } => {
+            row.push("collate".into());
+            row.push(collation.to_string().into());
+            write_expr(expr, row);
+        }
+        Expr::Call { .. } => unreachable!("indexes and policies do not call registered functions"),
+    }
+}
+
+/// Takes the fields of a catalog row in order.
+struct Reader(vec::IntoIter<Value>);
+
+impl Reader {
+    fn value(&mut self) -> Result<Value, Error> {
+        self.0.next().ok_or_else(|| corrupt("row ends early"))
+    }
+
+    fn text(&mut self) -> Result<String, Error> {
+        match self.value()? {
+            Value::Text(s) => Ok(s),
+            _ => Err(corrupt("expected text")),
+        }
+    }
+
+    fn int(&mut self) -> Result<u64, Error> {
+        match self.value()? {
+            Value::Int(i) if i >= 0 => Ok(i as u64),
+            _ => Err(corrupt("expected a count")),
+        }
+    }
+
+    fn bool(&mut self) -> Result<bool, Error> {
+        match self.value()? {
+            Value::Bool(b) => Ok(b),
+            _ => Err(corrupt("expected a boolean")),
+        }
+    }
+
+    fn data_type(&mut self) -> Result<DataType, Error> {
+        let name = self.text()?;
+        let (element, array) = match name.strip_suffix("[]") {
+            Some(element) => (element, true),
+            None => (name.as_str(), false),
+        };
+        let data_type = DATA_TYPES
+            .into_iter()
+            .find(|data_type| data_type.to_string() == element)
+            .ok_or_else(|| corrupt("unknown type"))?;
+        match array {
+            true => Ok(DataType::array(data_type).unwrap()),
+            false => Ok(data_type),
+        }
+    }
+
+    fn table(&mut self) -> Result<TableInfo, Error> {
+        let name = self.text()?;
+        let heap = HeapFile::new(PageId(self.int()?));
+        let columns = (0..self.int()?)
+            .map(|_| {
+                let name = self.text()?;
+                let data_type = self.data_type()?;
+                let nullable = self.bool()?;
+                Ok(Column {
+                    name,
+                    data_type,
+                    nullable,
+                    collation: Collation::Binary,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(TableInfo {
+            name,
+            schema: Schema::new(columns),
+            heap,
+            indexes: vec![],
+            stats: None,
+            view: None,
+            triggers: vec![],
+            partitioning: None,
+            partition_of: None,
+            ttl: None,
+            policies: vec![],
+        })
+    }
+
+    /// A view and the name of its table.
+    fn view(&mut self) -> Result<(String, ViewInfo), Error> {
+        let name = self.text()?;
+        let definition = self.text()?;
+        let dependencies = (0..self.int()?)
+            .map(|_| self.text())
+            .collect::<Result<_, Error>>()?;
+        Ok((
+            name,
+            ViewInfo {
+                definition,
+                dependencies,
+            },
+        ))
+    }
+
+    /// An index and the name of its table.
+    fn index(&mut self) -> Result<(String, IndexInfo), Error> {
+        let name = self.text()?;
+        let table = self.text()?;
+        let btree = BTree::new(PageId(self.int()?));
+        let unique = self.bool()?;
+        let keys = (0..self.int()?)
+            .map(|_| {
+                let data_type = self.data_type()?;
+                let expr = self.expr()?;
+                Ok(IndexKey {
+                    expr,
+                    data_type,
+                    nulls_first: true,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let predicate = if self.bool()? {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let index = IndexInfo {
+            name,
+            keys,
+            predicate,
+            unique,
+            btree,
+        };
+        Ok((table, index))
+    }
+
+    /// A pending index.
+    fn pending_index(&mut self) -> Result<PendingIndex, Error> {
+        let (table, index) = self.index()?;
+        let error = match self.value()? {
+            Value::Null => None,
+            Value::Text(error) => Some(error),
+            _ => return Err(corrupt("error of a pending index is not text")),
+        };
+        Ok(PendingIndex {
+            table,
+            index,
+            error,
+        })
+    }
+
+    /// A trigger and the name of its table.
+    fn trigger(&mut self) -> Result<(String, TriggerInfo), Error> {
+        let name = self.text()?;
+        let table = self.text()?;
+        let timing = self.named(TRIGGER_TIMINGS)?;
+        let events = (0..self.int()?)
+            .map(|_| self.named(TRIGGER_EVENTS))
+            .collect::<Result<_, Error>>()?;
+        let action = match self.text()?.as_str() {
+            "statement" => TriggerAction::Statement {
+                definition: self.text()?,
+                parameters: (0..self.int()?)
+                    .map(|_| Ok((self.named(ROW_IMAGES)?, self.int()? as usize)))
+                    .collect::<Result<_, Error>>()?,
+            },
+            "function" => TriggerAction::Function(self.text()?),
+            _ => return Err(corrupt("unknown trigger action")),
+        };
+        let trigger = TriggerInfo {
+            name,
+            timing,
+            events,
+            action,
+        };
+        Ok((table, trigger))
+    }
+
+    /// A policy and the name of its table.
+    fn policy(&mut self) -> Result<(String, PolicyInfo), Error> {
+        let name = self.text()?;
+        let table = self.text()?;
+        let users = (0..self.int()?)
+            .map(|_| self.text())
+            .collect::<Result<_, Error>>()?;
+        let condition = self.expr()?;
+        let policy = PolicyInfo {
+            name,
+            users,
+            condition,
+        };
+        Ok((table, policy))
+    }
+
+    /// How a table is partitioned, and the name of the table.
+    fn partitioning(&mut self) -> Result<(String, Partitioning), Error> {
+        let table = self.text()?;
+        let method = self.named(PARTITION_METHODS)?;
+        let column = self.int()? as usize;
+        Ok((table, Partitioning { method, column }))
+    }
+
+    /// What makes a table a partition, and the name of the table.
+    fn partition(&mut self) -> Result<(String, PartitionOf), Error> {
+        let table = self.text()?;
+        let parent = self.text()?;
+        let bound = match self.text()?.as_str() {
+            "range" => {
+                let mut bound = || Ok::<_, Error>(Some(self.value()?).filter(|v| !v.is_null()));
+                PartitionBound::Range {
+                    from: bound()?,
+                    to: bound()?,
+                }
+            }
+            "hash" => PartitionBound::Hash {
+                modulus: self.int()?,
+                remainder: self.int()?,
+            },
+            _ => return Err(corrupt("unknown partition bound")),
+        };
+        Ok((table, PartitionOf { parent, bound }))
+    }
+
+    /// When the rows of a table expire, and the name of the table.
+    fn ttl(&mut self) -> Result<(String, Ttl), Error> {
+        let table = self.text()?;
+        let column = self.int()? as usize;
+        let duration = match self.value()? {
+            Value::Null => None,
+            Value::Int(duration) => Some(duration as u64),
+            _ => return Err(corrupt("expected a duration")),
+        };
+        Ok((table, Ttl { column, duration }))
+    }
+
+    /// An index, its table and the positions of the keys it keeps with
+    /// NULLS LAST.
+    fn nulls_last(&mut self) -> Result<(String, String, Vec<usize>), Error> {
+        let index = self.text()?;
+        let table = self.text()?;
+        let keys = (0..self.int()?)
+            .map(|_| Ok(self.int()? as usize))
+            .collect::<Result<_, Error>>()?;
+        Ok((index, table, keys))
+    }
+
+    /// A column of a table that is not `binary`: the table, the column's
+    /// position and its collation.
+    fn collation(&mut self) -> Result<(String, usize, Collation), Error> {
+        let table = self.text()?;
+        let column = self.int()? as usize;
+        Ok((table, column, self.named(Collation::ALL)?))
+    }
+
+    /// One of `values`, by the name it displays as.
+    fn named<T: std::fmt::Display, const N: usize>(&mut self, values: [T; N]) -> Result<T, Error> {
+        let name = self.text()?;
+        values
+            .into_iter()
+            .find(|value| value.to_string() == name)
+            .ok_or_else(|| corrupt("unknown name"))
+    }
+
+    fn user(&mut self) -> Result<User, Error> {
+        let name = self.text()?;
+        let verifier = match self.value()? {
+            Value::Null => None,
+            Value::Text(text) => Some(text.parse().map_err(|_| corrupt("bad verifier"))?),
+            _ => return Err(corrupt("expected a verifier")),
+        };
+        let superuser = self.bool()?;
+        let grants = (0..self.int()?)
+            .map(|_| {
+                let table = self.text()?;
+                let privileges = u8::try_from(self.int()?)
+                    .ok()
+                    .and_then(Privileges::from_bits)
+                    .ok_or_else(|| corrupt("bad privileges"))?;
+                Ok((table, privileges))
+            })
+            .collect::<Result<_, Error>>()?;
+        let column_grants = (0..self.int()?)
+            .map(|_| {
+                let table = self.text()?;
+                let columns = (0..self.int()?)
+                    .map(|_| self.text())
+                    .collect::<Result<_, Error>>()?;
+                Ok((table, columns))
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(User {
+            name,
+            verifier,
+            superuser,
+            grants,
+            column_grants,
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let boxed = |reader: &mut Self| reader.expr().map(Box::new);
+        Ok(match self.text()?.as_str() {
+            "column" => Expr::Column(self.int()? as usize),
+            "literal" => Expr::Literal(self.value()?),
+            "parameter" => Expr::Parameter(self.int()? as usize),
+            "unary" => {
+                let op = self.text()?;
+                let op = UNARY_OPS
+                    .into_iter()
+                    .find(|candidate| candidate.to_string() == op)
+                    .ok_or_else(|| corrupt("unknown operator"))?;
+                Expr::Unary {
+                    op,
+                    expr: boxed(self)?,
+                }
+            }
+            "binary" => {
+                let op = self.text()?;
+                let op = BINARY_OPS
+                    .into_iter()
+                    .find(|candidate| candidate.to_string() == op)
+                    .ok_or_else(|| corrupt("unknown operator"))?;
+                Expr::Binary {
+                    op,
+                    lhs: boxed(self)?,
+                    rhs: boxed(self)?,
+                }
+            }
+            "is_null" => {
+                let negated = self.bool()?;
+                Expr::IsNull {
+                    expr: boxed(self)?,
+                    negated,
+                }
+            }
+            "cast" => {
+                let data_type = self.data_type()?;
+                Expr::Cast {
+                    expr: boxed(self)?,
+                    data_type,
+                }
+            }
+            "function" => {
+                let func = ScalarFunction::from_name(&self.text()?)
+                    .ok_or_else(|| corrupt("unknown function"))?;
+                Expr::Function {
+                    func,
+                    arg: boxed(self)?,
+                }
+            }
+            "array" => {
+                let element = self.data_type()?;
+                let items = (0..self.int()?)
+                    .map(|_| self.expr())
+                    .collect::<Result<_, _>>()?;
+                Expr::Array { element, items }
+            }
+            "any" => {
+                let op = self.text()?;
+                let op = BINARY_OPS
+                    .into_iter()
+                    .find(|candidate| candidate.to_string() == op)
+                    .ok_or_else(|| corrupt("unknown operator"))?;
+                Expr::Any {
+                    op,
+                    all: self.bool()?,
+                    lhs: boxed(self)?,
+                    array: boxed(self)?,
+                }
+            }
+            "collate" => Expr::Collate {
+                collation: self.named(Collation::ALL)?,
+                expr: boxed(self)?,
+            },
+            _ => return Err(corrupt("unknown expression")),
+        })
+    }
+}
@@ -0,0 +1,131 @@
+//! Read-only tables that the engine fills from its own state when they
+//! are scanned, rather than from a heap.
+//!
+//! A system table is found by name only when no table of the catalog has
+//! it, so queries keep working on databases with a table of the same
+//! name.
+
+use std::fmt;
+
+use super::{Column, Schema};
+use crate::value::DataType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SystemTable {
+    /// One row of counters of the buffer pool and the file under it.
+    StatBuffer,
+    /// A row per table, with its size and what ANALYZE last found.
+    StatTables,
+    /// A row per index, with how full its pages are, so that it shows
+    /// when REINDEX would pack it; pending indexes are not valid, and
+    /// show why their build failed if it did.
+    StatIndexes,
+    /// The locks held on the database.
+    Locks,
+    /// The statements running, which is only the one reading the table,
+    /// since statements run one at a time, with how far it has got.
+    ActiveQueries,
+    /// The statements the slow query log kept, oldest first.
+    SlowQueries,
+    /// The points AS OF queries can read, oldest first; none unless the
+    /// pool keeps history.
+    History,
+}
+
+impl SystemTable {
+    pub const ALL: &'static [SystemTable] = &[
+        SystemTable::StatBuffer,
+        SystemTable::StatTables,
+        SystemTable::StatIndexes,
+        SystemTable::Locks,
+        SystemTable::ActiveQueries,
+        SystemTable::SlowQueries,
+        SystemTable::History,
+    ];
+
+    pub fn lookup(name: &str) -> Option<SystemTable> {
+        Self::ALL.iter().copied().find(|table| table.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SystemTable::StatBuffer => "neru_stat_buffer",
+            SystemTable::StatTables => "neru_stat_tables",
+            SystemTable::StatIndexes => "neru_stat_indexes",
+            SystemTable::Locks => "neru_locks",
+            SystemTable::ActiveQueries => "neru_active_queries",
+            SystemTable::SlowQueries => "neru_slow_queries",
+            SystemTable::History => "neru_history",
+        }
+    }
+
+    pub fn schema(self) -> Schema {
+        let column = Column::new;
+        let count = |name| column(name, DataType::Int).not_null();
+        Schema::new(match self {
+            SystemTable::StatBuffer => vec![
+                count("pool_pages"),
+                count("pages"),
+                count("hits"),
+                count("misses"),
+                column("hit_ratio", DataType::Float),
+                count("evictions"),
+                count("dirty_evictions"),
+                count("latch_waits"),
+                count("pages_read"),
+                count("pages_written"),
+                count("syncs"),
+            ],
+            SystemTable::StatTables => vec![
+                column("name", DataType::Text).not_null(),
+                count("columns"),
+                count("indexes"),
+                count("pages"),
+                // NULL until the table is analyzed.
+                column("analyzed_rows", DataType::Int),
+            ],
+            SystemTable::StatIndexes => vec![
+                column("name", DataType::Text).not_null(),
+                column("table_name", DataType::Text).not_null(),
+                count("pages"),
+                count("leaf_pages"),
+                count("entries"),
+                // Of the leaves' space in use, and of the leaves empty.
+                column("fill_factor", DataType::Float).not_null(),
+                column("empty_ratio", DataType::Float).not_null(),
+                column("valid", DataType::Bool).not_null(),
+                column("build_error", DataType::Text),
+            ],
+            SystemTable::Locks => vec![
+                column("object", DataType::Text).not_null(),
+                column("mode", DataType::Text).not_null(),
+                column("holder", DataType::Text),
+            ],
+            SystemTable::ActiveQueries => vec![
+                column("sql", DataType::Text).not_null(),
+                column("user", DataType::Text),
+                column("duration_ms", DataType::Float).not_null(),
+                column("in_transaction", DataType::Bool).not_null(),
+                count("pages_scanned"),
+                count("pages_total"),
+                // Of pages_total; NULL until a table is scanned.
+                column("progress", DataType::Float),
+                count("rows"),
+                count("spilled_bytes"),
+            ],
+            SystemTable::SlowQueries => vec![
+                column("sql", DataType::Text).not_null(),
+                column("duration_ms", DataType::Float).not_null(),
+                column("rows", DataType::Int).not_null(),
+                column("plan", DataType::Text),
+            ],
+            SystemTable::History => vec![count("lsn"), count("time"), count("pages")],
+        })
+    }
+}
+
+impl fmt::Display for SystemTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
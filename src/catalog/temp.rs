@@ -0,0 +1,359 @@
+//! Temporary and unlogged tables, whose rows are kept out of the file.
+//!
+//! Both keep their pages in an anonymous temporary file of their own,
+//! which the pool holds pages of as it does those of an attached
+//! database, so writing them neither grows the database file nor goes
+//! through its journal.
+//!
+//! A temporary table is for one session. The catalog has the temporary
+//! tables of the session running on it, which a server swaps in and out
+//! with [`Catalog::set_temp_tables`], and they go away with the last
+//! copy of them, as their session ends. Their names hide those of other
+//! tables, and any user may create them and do anything to them.
+//!
+//! An unlogged table is for every session, and its definition is kept in
+//! the file along with its indexes, but not its rows: it is empty each
+//! time the database is opened, whether it was closed or crashed.
+//!
+//! Neither may be partitioned, have triggers, policies or a time to live,
+//! or be read by a materialized view. They are left out of
+//! [`Catalog::tables`], and with it out of statistics, checks, dumps and
+//! maintenance.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::{load_index, store, Catalog, Error, IndexInfo, IndexKey, Schema, TableInfo};
+use crate::btree::BTree;
+use crate::buffer::BufferPoolManager;
+use crate::disk::DiskManager;
+use crate::expr::Expr;
+use crate::heap::{self, HeapFile};
+use crate::stats::TableStats;
+
+/// How long the rows of a table last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Persistence {
+    /// Kept in the file.
+    #[default]
+    Permanent,
+    /// `TEMP`: kept for the session that created the table.
+    Temporary,
+    /// `UNLOGGED`: kept until the database is closed.
+    Unlogged,
+}
+
+/// Tables whose pages are in a temporary file of their own.
+#[derive(Clone)]
+pub struct TempTables {
+    id: u64,
+    bufmgr: Arc<BufferPoolManager>,
+    catalog: Box<Catalog>,
+}
+
+impl fmt::Debug for TempTables {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TempTables")
+            .field("id", &self.id)
+            .field("tables", &self.catalog.tables)
+            .finish_non_exhaustive()
+    }
+}
+
+impl TempTables {
+    /// No tables yet, with a new temporary file in the pool of `bufmgr`.
+    fn new(bufmgr: &BufferPoolManager) -> Result<Self, Error> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let disk = DiskManager::new(tempfile::tempfile()?)?;
+        let bufmgr = bufmgr.attach(disk).map_err(heap::Error::from)?;
+        Ok(Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            bufmgr: Arc::new(bufmgr),
+            catalog: Box::default(),
+        })
+    }
+
+    /// Tells these tables from others, for caches to key plans by.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &TableInfo> {
+        self.catalog.tables.values()
+    }
+}
+
+/// The tables of `space`, if there is one.
+fn tables_of(space: &Option<TempTables>) -> impl Iterator<Item = &TableInfo> {
+    space.iter().flat_map(TempTables::tables)
+}
+
+impl Catalog {
+    /// What `name` is the table of: [`Persistence::Permanent`] for any
+    /// name that is not that of a temporary or unlogged table.
+    pub fn persistence(&self, name: &str) -> Persistence {
+        let has = |space: &Option<TempTables>| {
+            (space.as_ref()).is_some_and(|space| space.catalog.tables.contains_key(name))
+        };
+        if has(&self.temp) {
+            Persistence::Temporary
+        } else if has(&self.unlogged) {
+            Persistence::Unlogged
+        } else {
+            Persistence::Permanent
+        }
+    }
+
+    /// Whether a table named `name` keeps one of `persistence` from being
+    /// created: temporary tables share names only among themselves.
+    pub fn name_taken(&self, name: &str, persistence: Persistence) -> bool {
+        match persistence {
+            Persistence::Temporary => self.persistence(name) == Persistence::Temporary,
+            _ => self.tables.contains_key(name) || self.persistence(name) == Persistence::Unlogged,
+        }
+    }
+
+    /// The table with the index `name`, among those `name` would find.
+    pub fn index_table(&self, name: &str) -> Option<&TableInfo> {
+        (tables_of(&self.temp).chain(self.tables.values()))
+            .chain(tables_of(&self.unlogged))
+            .find(|table| table.index(name).is_some())
+    }
+
+    /// The temporary tables of the session, if it has created any.
+    pub fn temp_tables(&self) -> Option<&TempTables> {
+        self.temp.as_ref()
+    }
+
+    /// Makes `temp` the temporary tables, replacing any there were.
+    pub fn set_temp_tables(&mut self, temp: Option<TempTables>) {
+        self.temp = temp;
+    }
+
+    /// Takes the temporary tables out of the catalog.
+    pub fn take_temp_tables(&mut self) -> Option<TempTables> {
+        self.temp.take()
+    }
+
+    pub fn unlogged_tables(&self) -> impl Iterator<Item = &TableInfo> {
+        tables_of(&self.unlogged)
+    }
+
+    pub fn create_temp_table(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        schema: Schema,
+    ) -> Result<&TableInfo, Error> {
+        if self.temp.is_none() {
+            self.temp = Some(TempTables::new(bufmgr)?);
+        }
+        let temp = self.temp.as_mut().unwrap();
+        temp.catalog.create_table(&temp.bufmgr, name, schema)
+    }
+
+    pub fn create_unlogged_table(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        schema: Schema,
+    ) -> Result<&TableInfo, Error> {
+        if self.tables.contains_key(name) {
+            return Err(Error::TableExists(name.to_string()));
+        }
+        if self.unlogged.is_none() {
+            self.unlogged = Some(TempTables::new(bufmgr)?);
+        }
+        let unlogged = self.unlogged.as_mut().unwrap();
+        let table = unlogged
+            .catalog
+            .create_table(&unlogged.bufmgr, name, schema)?;
+        store::save_table(self.store, bufmgr, table)?;
+        store::save_unlogged(self.store, bufmgr, name)?;
+        Ok(table)
+    }
+
+    /// The manager of the pages of `table` if they are not the main
+    /// database's: those of a temporary or unlogged table, or of a table
+    /// of an attached database.
+    pub fn table_bufmgr(&self, table: &str) -> Option<&BufferPoolManager> {
+        match self.persistence(table) {
+            Persistence::Permanent => self.attached_bufmgr(table),
+            persistence => Some(&self.space(persistence).bufmgr),
+        }
+    }
+
+    /// The temporary or unlogged table `name`.
+    pub(super) fn temp_table(&self, name: &str) -> Option<&TableInfo> {
+        (self.temp.iter().chain(&self.unlogged)).find_map(|space| space.catalog.tables.get(name))
+    }
+
+    pub(super) fn temp_create_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+        index_name: &str,
+        keys: Vec<IndexKey>,
+        predicate: Option<Expr>,
+        unique: bool,
+    ) -> Result<&IndexInfo, Error> {
+        let persistence = self.persistence(table_name);
+        let taken = (self.tables.values().chain(self.unlogged_tables()))
+            .any(|table| table.index(index_name).is_some());
+        if persistence == Persistence::Unlogged && taken {
+            return Err(Error::IndexExists(index_name.to_string()));
+        }
+        let store = self.store;
+        let space = self.space_mut(persistence);
+        let index = (space.catalog).create_expr_index(
+            &space.bufmgr,
+            table_name,
+            index_name,
+            keys,
+            predicate,
+            unique,
+        )?;
+        if persistence == Persistence::Unlogged {
+            store::save_index(store, bufmgr, table_name, index)?;
+        }
+        Ok(index)
+    }
+
+    pub(super) fn temp_drop_table(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<TableInfo, Error> {
+        let persistence = self.persistence(name);
+        let space = self.space_mut(persistence);
+        let table = space.catalog.drop_table(&space.bufmgr, name)?;
+        if persistence == Persistence::Unlogged {
+            store::remove(self.store, bufmgr, "table", name, true)?;
+            self.revoke_all(bufmgr, name)?;
+        }
+        Ok(table)
+    }
+
+    /// Drops the index `name` if a temporary or unlogged table has it,
+    /// returning `None` otherwise.
+    pub(super) fn temp_drop_index(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<Option<IndexInfo>, Error> {
+        let Some(table) = self.index_table(name).map(|table| table.name.clone()) else {
+            return Ok(None);
+        };
+        let persistence = self.persistence(&table);
+        if persistence == Persistence::Permanent {
+            return Ok(None);
+        }
+        let space = self.space_mut(persistence);
+        let index = space.catalog.drop_index(&space.bufmgr, name)?;
+        if persistence == Persistence::Unlogged {
+            store::remove(self.store, bufmgr, "index", name, false)?;
+        }
+        Ok(Some(index))
+    }
+
+    pub(super) fn temp_analyze(&mut self, table_name: &str) -> Result<&TableStats, Error> {
+        let space = self.space_mut(self.persistence(table_name));
+        space.catalog.analyze(&space.bufmgr, table_name)
+    }
+
+    /// Rebuilds the index `name` if a temporary or unlogged table has it,
+    /// returning whether one did.
+    pub(super) fn temp_reindex(&self, name: &str) -> Result<bool, Error> {
+        for space in self.temp.iter().chain(&self.unlogged) {
+            if space.catalog.index_table(name).is_some() {
+                space.catalog.reindex(&space.bufmgr, name)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Moves the unlogged tables `names`, as read from the file, out of
+    /// the main tables, and gives them heaps and indexes with no rows in
+    /// a temporary file.
+    pub(super) fn open_unlogged(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        names: Vec<String>,
+    ) -> Result<(), Error> {
+        let mut unlogged = TempTables::new(bufmgr)?;
+        for name in names {
+            let mut table = (self.tables.remove(&name))
+                .ok_or(Error::Corrupt("unlogged mark of a missing table"))?;
+            table.heap = HeapFile::create(&unlogged.bufmgr)?;
+            for index in &mut table.indexes {
+                index.btree = BTree::create_unloaded(&unlogged.bufmgr)?;
+                load_index(&unlogged.bufmgr, &table.heap, index)?;
+            }
+            unlogged.catalog.tables.insert(name, table);
+        }
+        self.unlogged = Some(unlogged);
+        Ok(())
+    }
+
+    fn space(&self, persistence: Persistence) -> &TempTables {
+        let space = match persistence {
+            Persistence::Temporary => &self.temp,
+            _ => &self.unlogged,
+        };
+        space.as_ref().expect("a table of the persistence")
+    }
+
+    fn space_mut(&mut self, persistence: Persistence) -> &mut TempTables {
+        let space = match persistence {
+            Persistence::Temporary => &mut self.temp,
+            _ => &mut self.unlogged,
+        };
+        space.as_mut().expect("a table of the persistence")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Column;
+    use crate::value::{DataType, Value};
+
+    #[test]
+    fn test_unlogged_tables_empty_on_open() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 16);
+        let mut catalog = Catalog::open(&bufmgr).unwrap();
+        let schema = Schema::new(vec![Column::new("id", DataType::Int)]);
+        let table = catalog
+            .create_unlogged_table(&bufmgr, "u", schema.clone())
+            .unwrap();
+        let (heap, staging) = (table.heap, catalog.table_bufmgr("u").unwrap());
+        heap.insert(staging, &[Value::Int(1)]).unwrap();
+        catalog
+            .create_index(&bufmgr, "u", "u_id", &["id"], true)
+            .unwrap();
+        catalog
+            .create_temp_table(&bufmgr, "t", schema.clone())
+            .unwrap();
+        assert!(catalog.name_taken("u", Persistence::Permanent));
+        assert!(!catalog.name_taken("u", Persistence::Temporary));
+        assert_eq!(0, catalog.tables().count());
+
+        // The definitions outlive the rows; temporary tables go entirely.
+        let reopened = Catalog::open(&bufmgr).unwrap();
+        assert_eq!(Persistence::Unlogged, reopened.persistence("u"));
+        assert!(reopened.table("t").is_none());
+        let table = reopened.table("u").unwrap();
+        let staging = reopened.table_bufmgr("u").unwrap();
+        assert_eq!(0, table.heap.counts(staging).unwrap().rows);
+        assert_eq!("u", reopened.index_table("u_id").unwrap().name);
+
+        catalog.drop_table(&bufmgr, "u").unwrap();
+        catalog.drop_table(&bufmgr, "t").unwrap();
+        let reopened = Catalog::open(&bufmgr).unwrap();
+        assert!(reopened.table("u").is_none());
+        assert!(!reopened.name_taken("u", Persistence::Permanent));
+    }
+}
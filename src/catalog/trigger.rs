@@ -0,0 +1,142 @@
+//! Row-level triggers.
+//!
+//! A trigger belongs to a table and runs its action for each row an
+//! INSERT, UPDATE or DELETE changes there, before or after the change.
+//! The action is either a statement of SQL that refers to the row through
+//! parameters, or a function the embedder registers with the engine under
+//! the trigger's function name. Triggers go away with their table.
+
+use std::fmt;
+
+use super::{store, Catalog, Error};
+use crate::buffer::BufferPoolManager;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TriggerTiming {
+    Before,
+    After,
+}
+
+impl fmt::Display for TriggerTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TriggerTiming::Before => "BEFORE",
+            TriggerTiming::After => "AFTER",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TriggerEvent {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl fmt::Display for TriggerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TriggerEvent::Insert => "INSERT",
+            TriggerEvent::Update => "UPDATE",
+            TriggerEvent::Delete => "DELETE",
+        })
+    }
+}
+
+/// Which image of the changed row a trigger reads: the row before the
+/// change, which inserts lack, or after it, which deletes lack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RowImage {
+    Old,
+    New,
+}
+
+impl fmt::Display for RowImage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RowImage::Old => "old",
+            RowImage::New => "new",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerAction {
+    /// An INSERT, UPDATE or DELETE, normalized, whose parameter `$n`
+    /// stands for the column given by the `n`th entry of `parameters`.
+    /// A column of the image the row lacks reads as NULL.
+    Statement {
+        definition: String,
+        parameters: Vec<(RowImage, usize)>,
+    },
+    /// The function registered with the engine under this name.
+    Function(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerInfo {
+    pub name: String,
+    pub timing: TriggerTiming,
+    pub events: Vec<TriggerEvent>,
+    pub action: TriggerAction,
+}
+
+impl TriggerInfo {
+    pub fn fires(&self, timing: TriggerTiming, event: TriggerEvent) -> bool {
+        self.timing == timing && self.events.contains(&event)
+    }
+}
+
+impl Catalog {
+    /// The table with the trigger called `name`, and the trigger.
+    pub fn trigger(&self, name: &str) -> Option<(&str, &TriggerInfo)> {
+        self.tables.values().find_map(|table| {
+            let trigger = table.triggers.iter().find(|trigger| trigger.name == name)?;
+            Some((table.name.as_str(), trigger))
+        })
+    }
+
+    /// Adds a trigger to a table, whose triggers fire in order of name.
+    /// Materialized views have none, since only refresh changes them, and
+    /// partitioned tables none, since their partitions' fire instead.
+    pub fn create_trigger(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table_name: &str,
+        trigger: TriggerInfo,
+    ) -> Result<&TriggerInfo, Error> {
+        if self.trigger(&trigger.name).is_some() {
+            return Err(Error::TriggerExists(trigger.name));
+        }
+        let table = self
+            .tables
+            .get_mut(table_name)
+            .ok_or_else(|| Error::TableNotFound(table_name.to_string()))?;
+        if table.view.is_some() {
+            return Err(Error::NotATable(table_name.to_string()));
+        }
+        if table.partitioning.is_some() {
+            return Err(Error::Partitioned(table_name.to_string()));
+        }
+        store::save_trigger(self.store, bufmgr, table_name, &trigger)?;
+        let i = table
+            .triggers
+            .partition_point(|other| other.name < trigger.name);
+        table.triggers.insert(i, trigger);
+        Ok(&table.triggers[i])
+    }
+
+    pub fn drop_trigger(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+    ) -> Result<TriggerInfo, Error> {
+        for table in self.tables.values_mut() {
+            if let Some(i) = table.triggers.iter().position(|t| t.name == name) {
+                store::remove(self.store, bufmgr, "trigger", name, false)?;
+                return Ok(table.triggers.remove(i));
+            }
+        }
+        Err(Error::TriggerNotFound(name.to_string()))
+    }
+}
@@ -0,0 +1,93 @@
+//! Row expiry.
+//!
+//! A table with a time to live keeps a time in one of its INT columns, in
+//! seconds since the Unix epoch, and its rows expire once that time is
+//! past, or once it is `duration` seconds past if the TTL has one. Rows
+//! are not hidden once they expire: they stay until the engine deletes
+//! them, which the server does in the background.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{store, Catalog, Error, TableInfo};
+use crate::buffer::BufferPoolManager;
+use crate::expr;
+use crate::value::DataType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ttl {
+    pub column: usize,
+    /// Seconds a row lives after the time in `column`. With a duration,
+    /// a row inserted with `column` NULL is stamped with the time of the
+    /// insert.
+    pub duration: Option<u64>,
+}
+
+impl Ttl {
+    /// The current time, in seconds since the Unix epoch.
+    pub fn now() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs() as i64)
+    }
+
+    /// The latest time in `column` of a row that has expired at `now`.
+    pub fn cutoff(&self, now: i64) -> i64 {
+        let duration = self.duration.unwrap_or(0).min(i64::MAX as u64) as i64;
+        now.saturating_sub(duration)
+    }
+}
+
+impl Catalog {
+    /// Sets the time to live of `table`, or with `None` removes it.
+    pub fn set_ttl(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table: &str,
+        ttl: Option<Ttl>,
+    ) -> Result<&TableInfo, Error> {
+        let info = self
+            .tables
+            .get_mut(table)
+            .ok_or_else(|| Error::TableNotFound(table.to_string()))?;
+        if info.view.is_some() {
+            return Err(Error::NotATable(table.to_string()));
+        }
+        if let Some(ttl) = ttl {
+            let column = (info.schema.columns.get(ttl.column))
+                .ok_or(expr::Error::ColumnOutOfRange(ttl.column))?;
+            if column.data_type != DataType::Int {
+                return Err(Error::TtlColumn(column.name.clone()));
+            }
+        }
+        store::remove(self.store, bufmgr, "ttl", table, false)?;
+        if let Some(ttl) = &ttl {
+            store::save_ttl(self.store, bufmgr, table, ttl)?;
+        }
+        info.ttl = ttl;
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cutoff() {
+        let expiry = Ttl {
+            column: 0,
+            duration: None,
+        };
+        assert_eq!(1000, expiry.cutoff(1000));
+        let lifetime = Ttl {
+            duration: Some(300),
+            ..expiry
+        };
+        assert_eq!(700, lifetime.cutoff(1000));
+        let forever = Ttl {
+            duration: Some(u64::MAX),
+            ..expiry
+        };
+        assert_eq!(i64::MIN, forever.cutoff(-1));
+    }
+}
@@ -0,0 +1,308 @@
+//! Users and the privileges they hold on tables.
+//!
+//! A superuser may do anything. Other users may read and change tables
+//! only as far as they were granted, and may not change the schema or
+//! other users. SELECT may also be granted on some columns of a table
+//! alone, which the user may then read while the others stay hidden;
+//! revoking SELECT on the whole table revokes it on those columns too.
+//! Grants on a table go away with it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+use super::{store, Catalog, Error};
+use crate::auth::Verifier;
+use crate::buffer::BufferPoolManager;
+
+/// A set of the privileges a table can be granted with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Privileges(u8);
+
+impl Privileges {
+    pub const NONE: Self = Self(0);
+    pub const SELECT: Self = Self(1);
+    pub const INSERT: Self = Self(2);
+    pub const UPDATE: Self = Self(4);
+    pub const DELETE: Self = Self(8);
+    pub const ALL: Self = Self(15);
+
+    const NAMES: [(Self, &'static str); 4] = [
+        (Self::SELECT, "SELECT"),
+        (Self::INSERT, "INSERT"),
+        (Self::UPDATE, "UPDATE"),
+        (Self::DELETE, "DELETE"),
+    ];
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn without(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub(super) fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub(super) fn from_bits(bits: u8) -> Option<Self> {
+        (bits & !Self::ALL.0 == 0).then_some(Self(bits))
+    }
+
+    /// The privilege called `name` in GRANT and REVOKE, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(_, n)| n.eq_ignore_ascii_case(name))
+            .map(|(privilege, _)| *privilege)
+    }
+}
+
+impl BitOr for Privileges {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Privileges {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+impl fmt::Display for Privileges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = Self::NAMES
+            .iter()
+            .filter(|(privilege, _)| self.contains(*privilege))
+            .map(|(_, name)| *name)
+            .collect();
+        f.write_str(&names.join(", "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    /// `None` for a user who cannot log in with a password.
+    pub verifier: Option<Verifier>,
+    pub superuser: bool,
+    /// Privileges granted, by table name.
+    pub grants: BTreeMap<String, Privileges>,
+    /// Columns granted SELECT on alone, by table name.
+    pub column_grants: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl User {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            verifier: None,
+            superuser: false,
+            grants: BTreeMap::new(),
+            column_grants: BTreeMap::new(),
+        }
+    }
+
+    /// What the user may do to `table`.
+    pub fn privileges(&self, table: &str) -> Privileges {
+        if self.superuser {
+            return Privileges::ALL;
+        }
+        self.grants.get(table).copied().unwrap_or_default()
+    }
+
+    /// The columns of `table` the user may read without SELECT on all of
+    /// it, if granted any.
+    pub fn readable_columns(&self, table: &str) -> Option<&BTreeSet<String>> {
+        self.column_grants.get(table)
+    }
+}
+
+impl Catalog {
+    pub fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    /// Users in name order.
+    pub fn users(&self) -> impl Iterator<Item = &User> {
+        self.users.values()
+    }
+
+    /// Takes the users, with their privileges, and the row policies of the
+    /// tables from `current`, in memory only, so that this catalog of an
+    /// older state of the database is read under the rules of now. A table
+    /// `current` no longer has is left without policies.
+    pub fn adopt_access(&mut self, current: &Catalog) {
+        self.users = current.users.clone();
+        for (name, table) in &mut self.tables {
+            table.policies = (current.tables.get(name))
+                .map(|table| table.policies.clone())
+                .unwrap_or_default();
+        }
+    }
+
+    pub fn create_user(&mut self, bufmgr: &BufferPoolManager, user: User) -> Result<&User, Error> {
+        if self.users.contains_key(&user.name) {
+            return Err(Error::UserExists(user.name));
+        }
+        store::save_user(self.store, bufmgr, &user)?;
+        Ok(self.users.entry(user.name.clone()).or_insert(user))
+    }
+
+    /// Changes a user with `change`, which may not rename it.
+    pub fn alter_user(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        name: &str,
+        change: impl FnOnce(&mut User),
+    ) -> Result<&User, Error> {
+        let user = self
+            .users
+            .get_mut(name)
+            .ok_or_else(|| Error::UserNotFound(name.to_string()))?;
+        change(user);
+        assert_eq!(name, user.name, "users cannot be renamed");
+        store::remove(self.store, bufmgr, "user", name, false)?;
+        store::save_user(self.store, bufmgr, user)?;
+        Ok(user)
+    }
+
+    pub fn drop_user(&mut self, bufmgr: &BufferPoolManager, name: &str) -> Result<User, Error> {
+        if !self.users.contains_key(name) {
+            return Err(Error::UserNotFound(name.to_string()));
+        }
+        if let Some(policy) = self.policy_naming(name) {
+            return Err(Error::UserHasPolicy {
+                user: name.to_string(),
+                policy: policy.name.clone(),
+            });
+        }
+        store::remove(self.store, bufmgr, "user", name, false)?;
+        Ok(self.users.remove(name).unwrap())
+    }
+
+    /// Adds `privileges` on `table` to those of `user`.
+    pub fn grant(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table: &str,
+        user: &str,
+        privileges: Privileges,
+    ) -> Result<(), Error> {
+        if !self.tables.contains_key(table) {
+            return Err(Error::TableNotFound(table.to_string()));
+        }
+        self.alter_user(bufmgr, user, |user| {
+            *user.grants.entry(table.to_string()).or_default() |= privileges;
+        })?;
+        Ok(())
+    }
+
+    /// Takes `privileges` on `table` away from `user`.
+    pub fn revoke(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table: &str,
+        user: &str,
+        privileges: Privileges,
+    ) -> Result<(), Error> {
+        if !self.tables.contains_key(table) {
+            return Err(Error::TableNotFound(table.to_string()));
+        }
+        self.alter_user(bufmgr, user, |user| {
+            if let Some(granted) = user.grants.get_mut(table) {
+                *granted = granted.without(privileges);
+                if granted.is_empty() {
+                    user.grants.remove(table);
+                }
+            }
+            if privileges.contains(Privileges::SELECT) {
+                user.column_grants.remove(table);
+            }
+        })?;
+        Ok(())
+    }
+
+    /// Lets `user` read `columns` of `table`.
+    pub fn grant_columns(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table: &str,
+        user: &str,
+        columns: &[String],
+    ) -> Result<(), Error> {
+        self.check_columns(table, columns)?;
+        self.alter_user(bufmgr, user, |user| {
+            (user.column_grants.entry(table.to_string()).or_default())
+                .extend(columns.iter().cloned());
+        })?;
+        Ok(())
+    }
+
+    /// Takes SELECT on `columns` of `table` away from `user`, which leaves
+    /// SELECT on the whole table, if granted, as it was.
+    pub fn revoke_columns(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table: &str,
+        user: &str,
+        columns: &[String],
+    ) -> Result<(), Error> {
+        self.check_columns(table, columns)?;
+        self.alter_user(bufmgr, user, |user| {
+            if let Some(granted) = user.column_grants.get_mut(table) {
+                granted.retain(|column| !columns.contains(column));
+                if granted.is_empty() {
+                    user.column_grants.remove(table);
+                }
+            }
+        })?;
+        Ok(())
+    }
+
+    fn check_columns(&self, table: &str, columns: &[String]) -> Result<(), Error> {
+        let info = self
+            .tables
+            .get(table)
+            .ok_or_else(|| Error::TableNotFound(table.to_string()))?;
+        match columns
+            .iter()
+            .find(|column| info.schema.column_index(column).is_none())
+        {
+            Some(column) => Err(Error::ColumnNotFound(column.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Forgets the grants on a table being dropped, so that a table
+    /// created later under its name starts without them.
+    pub(super) fn revoke_all(
+        &mut self,
+        bufmgr: &BufferPoolManager,
+        table: &str,
+    ) -> Result<(), Error> {
+        let holders: Vec<String> = self
+            .users
+            .values()
+            .filter(|user| {
+                user.grants.contains_key(table) || user.column_grants.contains_key(table)
+            })
+            .map(|user| user.name.clone())
+            .collect();
+        for name in holders {
+            self.alter_user(bufmgr, &name, |user| {
+                user.grants.remove(table);
+                user.column_grants.remove(table);
+            })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,745 @@
+//! Offline integrity checking of a database file.
+//!
+//! [`check`] walks every structure the catalog describes, reading pages
+//! without trusting them, and reports what does not hold together: slot
+//! directories that run into their records, rows that do not fit their
+//! table, heap chains that loop or end away from where their meta page
+//! says, B+Tree keys out of order or outside the bounds their parent sets,
+//! leaves at different depths or wrongly linked, index entries without a
+//! row and rows without an entry, catalog entries naming missing columns,
+//! pages claimed twice and free lists that lose count. [`check_file`]
+//! also reports each page that does not match its
+//! [checksum](crate::disk::Layout), as one torn by a crash would not, and
+//! goes on to check what it can make of it; [`check`] reads pages through
+//! the pool, where such a page fails to read. Pages on the free list and
+//! those left behind by files from before there was one are counted, but
+//! are not a problem.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::btree::{node, BTree};
+use crate::buffer::{self, BufferPoolManager};
+use crate::catalog::{Catalog, IndexInfo, Schema, TableInfo, CATALOG_PAGE_ID, FREE_LIST_RANGE};
+use crate::disk::{DiskManager, PageId};
+use crate::heap::{self, HeapFile, Rid};
+use crate::inspect::{hex, read_page_id, read_u16, split_pair};
+use crate::slotted;
+use crate::tuple;
+use crate::value::Tuple;
+
+/// Problems reported before the rest are only counted.
+pub const MAX_PROBLEMS: usize = 1000;
+
+/// Pages the buffer pool of [`check_file`] holds.
+const POOL_SIZE: usize = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+}
+
+/// Something wrong with `object`, such as `table t`, and where.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub object: String,
+    pub page_id: Option<PageId>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.page_id {
+            Some(page_id) => write!(f, "{}: page {}: {}", self.object, page_id.0, self.message),
+            None => write!(f, "{}: {}", self.object, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub pages: u64,
+    /// Pages no table or index reaches, nor the free list.
+    pub unused_pages: u64,
+    /// Pages on the free list.
+    pub free_pages: u64,
+    pub tables: usize,
+    pub indexes: usize,
+    pub problems: Vec<Problem>,
+    /// Problems found past [`MAX_PROBLEMS`].
+    pub omitted: usize,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "pages: {} ({} unused, {} free)",
+            self.pages, self.unused_pages, self.free_pages
+        )?;
+        writeln!(f, "tables: {}", self.tables)?;
+        writeln!(f, "indexes: {}", self.indexes)?;
+        writeln!(f, "problems: {}", self.problems.len() + self.omitted)?;
+        for problem in &self.problems {
+            writeln!(f, "  {problem}")?;
+        }
+        if self.omitted > 0 {
+            writeln!(f, "  and {} more", self.omitted)?;
+        }
+        Ok(())
+    }
+}
+
+/// Opens the file at `path` and checks it. The file is not changed, and
+/// may be open for writing elsewhere, though pages written meanwhile can
+/// then show up as problems.
+pub fn check_file(path: impl AsRef<Path>) -> Result<Report, Error> {
+    let path = path.as_ref();
+    let len = fs::metadata(path)?.len();
+    let mut disk = DiskManager::open_read_only(path)?;
+    let layout = disk.layout();
+    let mut problems = vec![];
+    let trailing = len % layout.slot_size() as u64;
+    if trailing > 0 {
+        problems.push(Problem {
+            object: "file".to_string(),
+            page_id: None,
+            message: format!("ends with {trailing} bytes that are not a whole page"),
+        });
+    }
+    for page_id in (0..disk.num_pages()).map(PageId) {
+        if !disk.verify_page(page_id)? {
+            problems.push(Problem {
+                object: "file".to_string(),
+                page_id: Some(page_id),
+                message: "does not match its checksum".to_string(),
+            });
+        }
+    }
+    let bufmgr = BufferPoolManager::new(disk.without_verification(), POOL_SIZE);
+    let mut report = check(&bufmgr)?;
+    report.problems.splice(0..0, problems);
+    if report.problems.len() > MAX_PROBLEMS {
+        report.omitted += report.problems.len() - MAX_PROBLEMS;
+        report.problems.truncate(MAX_PROBLEMS);
+    }
+    Ok(report)
+}
+
+/// Checks the database behind `bufmgr`. Only failures to read pages are
+/// errors; everything else found ends up in the report.
+pub fn check(bufmgr: &BufferPoolManager) -> Result<Report, Error> {
+    let mut checker = Checker {
+        bufmgr,
+        owners: HashMap::new(),
+        report: Report {
+            pages: bufmgr.num_pages(),
+            ..Report::default()
+        },
+    };
+    checker.check_database()?;
+    let mut report = checker.report;
+    report.unused_pages = report.pages - checker.owners.len() as u64;
+    Ok(report)
+}
+
+struct Checker<'a> {
+    bufmgr: &'a BufferPoolManager,
+    /// The object each page reached so far belongs to.
+    owners: HashMap<PageId, String>,
+    report: Report,
+}
+
+impl Checker<'_> {
+    fn problem(&mut self, object: &str, page_id: Option<PageId>, message: impl Into<String>) {
+        if self.report.problems.len() == MAX_PROBLEMS {
+            self.report.omitted += 1;
+            return;
+        }
+        self.report.problems.push(Problem {
+            object: object.to_string(),
+            page_id,
+            message: message.into(),
+        });
+    }
+
+    fn check_database(&mut self) -> Result<(), Error> {
+        if self.bufmgr.num_pages() == 0 {
+            self.problem("file", None, "has no pages");
+            return Ok(());
+        }
+        // The catalog heap is read as-is below, so it is checked first.
+        self.check_heap("catalog", HeapFile::new(CATALOG_PAGE_ID), None)?;
+        if !self.report.is_ok() {
+            self.problem("catalog", None, "damaged; tables and indexes not checked");
+            return Ok(());
+        }
+        let catalog = match Catalog::open(self.bufmgr) {
+            Ok(catalog) => catalog,
+            Err(e) => {
+                self.problem("catalog", None, format!("cannot be read: {e}"));
+                return Ok(());
+            }
+        };
+        let mut tables: Vec<&TableInfo> = catalog.tables().collect();
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        self.check_catalog(&tables);
+        for table in tables {
+            self.report.tables += 1;
+            let object = format!("table {}", table.name);
+            let rows = self.check_heap(&object, table.heap, Some(&table.schema))?;
+            for index in &table.indexes {
+                self.report.indexes += 1;
+                self.check_index(&format!("index {}", index.name), index, &rows)?;
+            }
+        }
+        // Only their pages are checked, as a build may be under way.
+        for pending in catalog.pending_indexes() {
+            let object = format!("pending index {}", pending.index.name);
+            self.check_btree(&object, pending.index.btree)?;
+        }
+        self.check_free_list()
+    }
+
+    /// Every page on the free list must be used by nothing else, and the
+    /// list must count them right.
+    fn check_free_list(&mut self) -> Result<(), Error> {
+        let object = "free list";
+        let meta = self.bufmgr.fetch_page(CATALOG_PAGE_ID)?;
+        let list = PageId::from_bytes(&meta.read()[FREE_LIST_RANGE]);
+        if list == PageId(0) {
+            return Ok(());
+        }
+        let Some(page) = self.claim(object, list)? else {
+            return Ok(());
+        };
+        let (mut page_id, count) = buffer::read_free_list(&page);
+        while let Some(id) = page_id {
+            let Some(page) = self.claim(object, id)? else {
+                break;
+            };
+            self.report.free_pages += 1;
+            page_id = read_page_id(&page, 0);
+        }
+        if self.report.free_pages != count {
+            let message = format!(
+                "counts {count} pages, but {} are on it",
+                self.report.free_pages
+            );
+            self.problem(object, Some(list), message);
+        }
+        Ok(())
+    }
+
+    /// Index names must be unique and keys refer to columns that exist.
+    fn check_catalog(&mut self, tables: &[&TableInfo]) {
+        let mut names = HashSet::new();
+        for table in tables {
+            let columns = &table.schema.columns;
+            for index in &table.indexes {
+                let object = format!("index {}", index.name);
+                if !names.insert(&index.name) {
+                    self.problem(&object, None, "another index has the same name");
+                }
+                let missing = |expr: &crate::expr::Expr| {
+                    let mut missing = false;
+                    expr.visit_columns(&mut |column| missing |= column >= columns.len());
+                    missing
+                };
+                for (i, key) in index.keys.iter().enumerate() {
+                    if missing(&key.expr) {
+                        let message = format!("key {i} refers to a column {} lacks", table.name);
+                        self.problem(&object, None, message);
+                    } else if let Some(column) = key.as_column() {
+                        let column = &columns[column];
+                        if column.data_type != key.data_type {
+                            let message = format!(
+                                "key {i} is {} but column {} is {}",
+                                key.data_type, column.name, column.data_type
+                            );
+                            self.problem(&object, None, message);
+                        }
+                    }
+                }
+                if index.predicate.as_ref().is_some_and(missing) {
+                    let message = format!("predicate refers to a column {} lacks", table.name);
+                    self.problem(&object, None, message);
+                }
+            }
+        }
+    }
+
+    /// Reads a page of `object`, which no other object may have claimed;
+    /// `None` if it cannot be used.
+    fn claim(&mut self, object: &str, page_id: PageId) -> Result<Option<Box<[u8]>>, Error> {
+        if page_id.0 >= self.bufmgr.num_pages() {
+            self.problem(object, Some(page_id), "past the end of the file");
+            return Ok(None);
+        }
+        if let Some(owner) = self.owners.get(&page_id) {
+            let message = if owner == object {
+                "reached twice".to_string()
+            } else {
+                format!("also used by {owner}")
+            };
+            self.problem(object, Some(page_id), message);
+            return Ok(None);
+        }
+        self.owners.insert(page_id, object.to_string());
+        Ok(Some(Box::new(*self.bufmgr.fetch_page(page_id)?.read())))
+    }
+
+    /// The records of the slotted area at `base`, by slot. A slot whose
+    /// record lies outside the record area or overlaps another is `None`.
+    fn records<'p>(
+        &mut self,
+        object: &str,
+        page_id: PageId,
+        page: &'p [u8],
+        base: usize,
+    ) -> Vec<Option<&'p [u8]>> {
+        let data_start = base + slotted::HEADER_SIZE;
+        let capacity = page.len() - data_start;
+        let num_slots = read_u16(page, base).unwrap();
+        let free_end = read_u16(page, base + 2).unwrap();
+        if free_end > capacity {
+            let message = format!("free space ends at {free_end}, past the end of the page");
+            self.problem(object, Some(page_id), message);
+            return vec![];
+        }
+        if num_slots * slotted::POINTER_SIZE > free_end {
+            let message = format!("the directory of {num_slots} slots runs into the records");
+            self.problem(object, Some(page_id), message);
+            return vec![];
+        }
+        let mut records = vec![];
+        let mut ranges = vec![];
+        for slot_id in 0..num_slots {
+            let pointer = data_start + slot_id * slotted::POINTER_SIZE;
+            let offset = read_u16(page, pointer).unwrap();
+            let len = read_u16(page, pointer + 2).unwrap();
+            if len == 0 {
+                records.push(Some(&[][..]));
+            } else if offset < free_end || offset + len > capacity {
+                let message = format!("slot {slot_id} lies outside the records");
+                self.problem(object, Some(page_id), message);
+                records.push(None);
+            } else {
+                ranges.push((offset, offset + len, slot_id));
+                records.push(Some(&page[data_start + offset..data_start + offset + len]));
+            }
+        }
+        ranges.sort_unstable();
+        for pair in ranges.windows(2) {
+            let ((_, end, a), (start, _, b)) = (pair[0], pair[1]);
+            if end > start {
+                let message = format!("the records of slots {a} and {b} overlap");
+                self.problem(object, Some(page_id), message);
+                records[b] = None;
+            }
+        }
+        records
+    }
+
+    /// Checks a heap chain, and its rows against `schema` if given.
+    /// Returns the rows that could be read.
+    fn check_heap(
+        &mut self,
+        object: &str,
+        heap: HeapFile,
+        schema: Option<&Schema>,
+    ) -> Result<HashMap<Rid, Tuple>, Error> {
+        let mut rows = HashMap::new();
+        let Some(meta) = self.claim(object, heap.meta_page_id)? else {
+            return Ok(rows);
+        };
+        let (first, last) = (read_page_id(&meta, 0), read_page_id(&meta, 8));
+        let mut page_id = first;
+        let mut end = None;
+        while let Some(id) = page_id {
+            let Some(page) = self.claim(object, id)? else {
+                break;
+            };
+            end = Some(id);
+            let records = self.records(object, id, &page, heap::PAGE_HEADER_SIZE);
+            for (slot_id, record) in records.into_iter().enumerate() {
+                let Some(record) = record.filter(|record| !record.is_empty()) else {
+                    continue;
+                };
+                let rid = Rid {
+                    page_id: id,
+                    slot_id: slot_id as u16,
+                };
+                match tuple::decode(record) {
+                    Ok(row) => {
+                        if let Some(schema) = schema {
+                            self.check_row(object, rid, &row, schema);
+                        }
+                        rows.insert(rid, row);
+                    }
+                    Err(e) => self.problem(object, Some(id), format!("slot {slot_id}: {e}")),
+                }
+            }
+            page_id = read_page_id(&page, 0);
+        }
+        for room in heap::room_pages(&meta) {
+            if self.owners.get(&room).map(String::as_str) != Some(object) {
+                let message = format!("lists page {} as having room, but it is not its", room.0);
+                self.problem(object, Some(heap.meta_page_id), message);
+            }
+        }
+        if first.is_none() {
+            self.problem(object, Some(heap.meta_page_id), "no first page");
+        } else if end != last {
+            let message = format!(
+                "the last page should be {}, but the chain ends at {}",
+                page_name(last),
+                page_name(end)
+            );
+            self.problem(object, Some(heap.meta_page_id), message);
+        }
+        Ok(rows)
+    }
+
+    fn check_row(&mut self, object: &str, rid: Rid, row: &Tuple, schema: &Schema) {
+        let at = format!("row ({}, {})", rid.page_id.0, rid.slot_id);
+        if row.len() != schema.len() {
+            let message = format!("{at} has {} values for {} columns", row.len(), schema.len());
+            self.problem(object, Some(rid.page_id), message);
+            return;
+        }
+        for (value, column) in row.iter().zip(&schema.columns) {
+            let message = match value.data_type() {
+                None if !column.nullable => format!("{at} has NULL in column {}", column.name),
+                Some(data_type) if data_type != column.data_type => {
+                    format!(
+                        "{at} has {data_type} in {} column {}",
+                        column.data_type, column.name
+                    )
+                }
+                _ => continue,
+            };
+            self.problem(object, Some(rid.page_id), message);
+        }
+    }
+
+    /// Checks an index's tree, then that its entries and the table's rows
+    /// match one for one.
+    fn check_index(
+        &mut self,
+        object: &str,
+        index: &IndexInfo,
+        rows: &HashMap<Rid, Tuple>,
+    ) -> Result<(), Error> {
+        let entries = self.check_btree(object, index.btree)?;
+        let mut indexed = HashSet::new();
+        for (key, value) in entries {
+            if value.len() != Rid::SIZE {
+                let message = format!("entry {} has a {}-byte value", hex(&key), value.len());
+                self.problem(object, None, message);
+                continue;
+            }
+            let rid = Rid::from_bytes(&value);
+            let at = format!("({}, {})", rid.page_id.0, rid.slot_id);
+            let Some(row) = rows.get(&rid) else {
+                let message = format!("entry {} points to {at}, which is not a row", hex(&key));
+                self.problem(object, None, message);
+                continue;
+            };
+            if !indexed.insert(rid) {
+                self.problem(object, None, format!("row {at} has more than one entry"));
+                continue;
+            }
+            let message = match index.entry_key(row, rid) {
+                Ok(Some((expected, _))) if expected == key => continue,
+                Ok(Some(_)) => format!("entry {} does not match row {at}", hex(&key)),
+                Ok(None) => format!("row {at} has an entry but the predicate leaves it out"),
+                Err(e) => format!("the key of row {at} cannot be computed: {e}"),
+            };
+            self.problem(object, None, message);
+        }
+        let mut missing: Vec<Rid> = rows
+            .iter()
+            .filter(|(rid, row)| !indexed.contains(rid) && index.covers(row).unwrap_or(false))
+            .map(|(rid, _)| *rid)
+            .collect();
+        missing.sort_unstable();
+        for rid in missing {
+            let message = format!("row ({}, {}) has no entry", rid.page_id.0, rid.slot_id);
+            self.problem(object, None, message);
+        }
+        Ok(())
+    }
+
+    /// Checks the shape of a tree and returns the pairs of its leaves, in
+    /// order.
+    fn check_btree(&mut self, object: &str, btree: BTree) -> Result<Vec<Pair>, Error> {
+        let mut entries = vec![];
+        let Some(meta) = self.claim(object, btree.meta_page_id)? else {
+            return Ok(entries);
+        };
+        let Some(root) = read_page_id(&meta, 0) else {
+            self.problem(object, Some(btree.meta_page_id), "no root page");
+            return Ok(entries);
+        };
+        let mut leaves = vec![];
+        let mut walk = Walk {
+            leaves: &mut leaves,
+            entries: &mut entries,
+        };
+        self.check_node(object, root, 0, (None, None), &mut walk)?;
+        let depth = leaves.first().map(|leaf| leaf.depth);
+        for (i, leaf) in leaves.iter().enumerate() {
+            if Some(leaf.depth) != depth {
+                let message = format!("leaf at depth {}, not {}", leaf.depth, depth.unwrap());
+                self.problem(object, Some(leaf.page_id), message);
+            }
+            let prev = i.checked_sub(1).map(|i| leaves[i].page_id);
+            let next = leaves.get(i + 1).map(|leaf| leaf.page_id);
+            for (name, link, expected) in [("prev", leaf.prev, prev), ("next", leaf.next, next)] {
+                if link != expected {
+                    let message = format!(
+                        "{name} leaf should be {}, not {}",
+                        page_name(expected),
+                        page_name(link)
+                    );
+                    self.problem(object, Some(leaf.page_id), message);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Checks the subtree at `page_id`, whose keys must lie in `bounds`:
+    /// at least the lower bound and below the upper one.
+    fn check_node(
+        &mut self,
+        object: &str,
+        page_id: PageId,
+        depth: usize,
+        bounds: (Option<&[u8]>, Option<&[u8]>),
+        walk: &mut Walk,
+    ) -> Result<(), Error> {
+        let Some(page) = self.claim(object, page_id)? else {
+            return Ok(());
+        };
+        let node_type = page[0];
+        if node_type != node::NODE_TYPE_LEAF && node_type != node::NODE_TYPE_BRANCH {
+            let message = format!("not a B+Tree node (type byte {node_type:#04x})");
+            self.problem(object, Some(page_id), message);
+            return Ok(());
+        }
+        let mut pairs: Vec<(&[u8], &[u8])> = vec![];
+        let records = self.records(object, page_id, &page, node::HEADER_SIZE);
+        for (slot_id, record) in records.into_iter().enumerate() {
+            match record.map(split_pair) {
+                Some(Some(pair)) => pairs.push(pair),
+                Some(None) => {
+                    let message = format!("slot {slot_id}: the key runs past the pair");
+                    self.problem(object, Some(page_id), message);
+                }
+                None => {}
+            }
+        }
+        if let Some(i) = pairs.windows(2).position(|pair| pair[0].0 >= pair[1].0) {
+            let message = format!("keys {i} and {} are out of order", i + 1);
+            self.problem(object, Some(page_id), message);
+        }
+        let (lower, upper) = bounds;
+        let outside = |key: &[u8]| {
+            lower.is_some_and(|lower| key < lower) || upper.is_some_and(|upper| key >= upper)
+        };
+        if let Some(key) = pairs.iter().map(|pair| pair.0).find(|key| outside(key)) {
+            let message = format!("key {} is outside the range its parent gives", hex(key));
+            self.problem(object, Some(page_id), message);
+        }
+        if node_type == node::NODE_TYPE_LEAF {
+            walk.leaves.push(Leaf {
+                page_id,
+                depth,
+                prev: read_page_id(&page, 8),
+                next: read_page_id(&page, 16),
+            });
+            let pairs = pairs
+                .iter()
+                .map(|(key, value)| (key.to_vec(), value.to_vec()));
+            walk.entries.extend(pairs);
+            return Ok(());
+        }
+        let mut lower = lower;
+        for (i, &(key, value)) in pairs.iter().enumerate() {
+            match <[u8; 8]>::try_from(value)
+                .ok()
+                .map(|bytes| PageId::from_bytes(&bytes))
+            {
+                Some(child) => {
+                    self.check_node(object, child, depth + 1, (lower, Some(key)), walk)?
+                }
+                None => {
+                    let message = format!("slot {i} holds a {}-byte child", value.len());
+                    self.problem(object, Some(page_id), message);
+                }
+            }
+            lower = Some(key);
+        }
+        match read_page_id(&page, 8) {
+            Some(child) => self.check_node(object, child, depth + 1, (lower, upper), walk)?,
+            None => self.problem(object, Some(page_id), "branch without a right child"),
+        }
+        Ok(())
+    }
+}
+
+type Pair = (Vec<u8>, Vec<u8>);
+
+struct Leaf {
+    page_id: PageId,
+    depth: usize,
+    prev: Option<PageId>,
+    next: Option<PageId>,
+}
+
+/// What walking a tree collects, in key order.
+struct Walk<'a> {
+    leaves: &'a mut Vec<Leaf>,
+    entries: &'a mut Vec<Pair>,
+}
+
+fn page_name(page_id: Option<PageId>) -> String {
+    match page_id {
+        Some(page_id) => page_id.0.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, Options};
+    use crate::disk::Layout;
+    use crate::engine::Engine;
+    use crate::value::Value;
+
+    #[test]
+    fn test_check() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut engine = Engine::open(BufferPoolManager::new(disk, 64)).unwrap();
+        let rows: Vec<String> = (1..=2000).map(|n| format!("({n}, 'name {n}')")).collect();
+        let insert = format!("INSERT INTO t VALUES {}", rows.join(", "));
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        engine
+            .execute("CREATE INDEX t_name ON t (name) WHERE id > 1")
+            .unwrap();
+        engine.execute(&insert).unwrap();
+        engine.execute("DELETE FROM t WHERE id % 3 = 0").unwrap();
+        engine.execute("DROP TABLE t").unwrap();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        engine.execute(&insert).unwrap();
+        let report = check(engine.bufmgr()).unwrap();
+        assert!(report.is_ok(), "{report}");
+        assert_eq!((1, 1), (report.tables, report.indexes));
+        assert!(report.free_pages > 0);
+
+        // Rows and entries that no longer match.
+        let bufmgr = engine.bufmgr();
+        let table = engine.catalog().table("t").unwrap();
+        let (rid, _) = table
+            .heap
+            .scan(bufmgr)
+            .unwrap()
+            .next(bufmgr)
+            .unwrap()
+            .unwrap();
+        table.heap.delete(bufmgr, rid).unwrap();
+        table
+            .heap
+            .insert(bufmgr, &[Value::Int(5000), Value::Null])
+            .unwrap();
+        table.heap.insert(bufmgr, &[Value::Int(1)]).unwrap();
+        let report = check(bufmgr).unwrap();
+        let problems: Vec<String> = report.problems.iter().map(Problem::to_string).collect();
+        let data_page = rid.page_id.0;
+        assert!(problems[0].starts_with("table t: page "), "{report}");
+        assert!(
+            problems[0].ends_with(" has 1 values for 2 columns"),
+            "{report}"
+        );
+        assert_eq!(
+            format!("index t_pkey: entry 018000000000000001 points to ({data_page}, 0), which is not a row"),
+            problems[1]
+        );
+        assert!(problems[2].ends_with(") has no entry"), "{report}");
+        assert_eq!(4, problems.len(), "{report}");
+        assert!(report.to_string().starts_with("pages: "));
+
+        // A leaf out of the chain and a slot outside its page.
+        let meta_page_id = table.indexes[0].btree.meta_page_id;
+        let root_page_id =
+            PageId::from_bytes(&bufmgr.fetch_page(meta_page_id).unwrap().read()[..8]);
+        {
+            let buffer = bufmgr.fetch_page(root_page_id).unwrap();
+            let mut page = buffer.write();
+            assert_eq!(node::NODE_TYPE_BRANCH, page[0]);
+            let first = node::HEADER_SIZE + slotted::HEADER_SIZE;
+            page[first..first + 2].copy_from_slice(&4060u16.to_le_bytes());
+        }
+        {
+            let buffer = bufmgr.fetch_page(rid.page_id).unwrap();
+            buffer.write()[..8].copy_from_slice(&rid.page_id.to_bytes());
+        }
+        let report = check(bufmgr).unwrap();
+        let text = report.to_string();
+        assert!(
+            text.contains(&format!("table t: page {data_page}: reached twice\n")),
+            "{text}"
+        );
+        assert!(text.contains("the last page should be"), "{text}");
+        assert!(
+            text.contains(&format!(
+                "index t_pkey: page {}: slot 0 lies outside the records\n",
+                root_page_id.0
+            )),
+            "{text}"
+        );
+        assert!(text.contains("prev leaf should be none, not "), "{text}");
+    }
+
+    #[test]
+    fn test_check_file_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let mut db = Database::open(&path, Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        db.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+        db.close().unwrap();
+        assert!(check_file(&path).unwrap().is_ok());
+
+        // A byte flipped in the last page, as a torn write would leave it.
+        let mut data = fs::read(&path).unwrap();
+        let slot = Layout::Checksummed.slot_size();
+        let last = data.len() / slot - 1;
+        data[last * slot + 2000] ^= 0xff;
+        fs::write(&path, data).unwrap();
+        let report = check_file(&path).unwrap();
+        assert_eq!(
+            format!("file: page {last}: does not match its checksum"),
+            report.problems[0].to_string()
+        );
+    }
+}
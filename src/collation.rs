@@ -0,0 +1,70 @@
+//! How text compares: the collation of a column or of an expression.
+//!
+//! A collation is applied by mapping each value to a sort key, so that
+//! comparing keys in the usual order of values compares the values under
+//! the collation. The same key is what an index on a collated column
+//! stores, which keeps index lookups, ORDER BY and the evaluator in
+//! agreement. Only `binary`, the order of the bytes, and `nocase`, which
+//! ignores case, exist: locale-aware collations would need a copy of the
+//! Unicode collation tables that this crate does not carry.
+
+use std::fmt;
+
+use crate::value::Value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Collation {
+    #[default]
+    Binary,
+    /// Text compared as if lowercase, by Unicode's simple case mapping.
+    NoCase,
+}
+
+impl Collation {
+    pub const ALL: [Collation; 2] = [Collation::Binary, Collation::NoCase];
+
+    /// The collation a `COLLATE` clause names, in any case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|collation| collation.to_string().eq_ignore_ascii_case(name))
+    }
+
+    /// The value that stands for `value` in comparisons under the
+    /// collation: text, or an array of text with each element its key.
+    /// Other values are their own keys.
+    pub fn key(self, value: Value) -> Value {
+        match (self, value) {
+            (Collation::NoCase, Value::Text(text)) => Value::Text(text.to_lowercase()),
+            (Collation::NoCase, Value::Array(mut array)) => {
+                array.items = array.items.into_iter().map(|item| self.key(item)).collect();
+                Value::Array(array)
+            }
+            (_, value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Collation::Binary => "binary",
+            Collation::NoCase => "nocase",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(Some(Collation::NoCase), Collation::from_name("NOCASE"));
+        assert_eq!(None, Collation::from_name("en_US"));
+        let key = |collation: Collation, text: &str| collation.key(text.into());
+        assert_eq!(key(Collation::NoCase, "ÄbC"), key(Collation::NoCase, "äBc"));
+        assert_ne!(key(Collation::Binary, "ÄbC"), key(Collation::Binary, "äBc"));
+        assert_eq!(Value::Int(1), Collation::NoCase.key(Value::Int(1)));
+    }
+}
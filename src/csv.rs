@@ -0,0 +1,211 @@
+//! Reading and writing CSV, for COPY.
+//!
+//! Records are separated by newlines (LF or CRLF) and fields by the
+//! delimiter. A field in quotes may hold delimiters, newlines and, doubled,
+//! the quote itself. An unquoted field equal to the NULL string reads as
+//! NULL; quoted, it is the string itself, which is how such strings are
+//! written.
+
+use std::io::{self, BufRead, Write};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("line {line}: {message}")]
+    Syntax { line: u64, message: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub delimiter: char,
+    pub quote: char,
+    /// Whether the first record names the columns.
+    pub header: bool,
+    pub null: String,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            quote: '"',
+            header: false,
+            null: String::new(),
+        }
+    }
+}
+
+/// A record and the line it starts on, counting from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub line: u64,
+    pub fields: Vec<Option<String>>,
+}
+
+pub struct Reader<R> {
+    input: R,
+    options: Options,
+    line: u64,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(input: R, options: Options) -> Self {
+        Self {
+            input,
+            options,
+            line: 0,
+        }
+    }
+
+    /// The next record, or `None` at the end of the input.
+    pub fn read_record(&mut self) -> Result<Option<Record>, Error> {
+        let mut text = String::new();
+        if self.input.read_line(&mut text)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        let line = self.line;
+        let (delimiter, quote) = (self.options.delimiter, self.options.quote);
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut in_quotes = false;
+        loop {
+            let mut chars = text.chars().peekable();
+            while let Some(c) = chars.next() {
+                if in_quotes {
+                    if c != quote {
+                        field.push(c);
+                    } else if chars.next_if_eq(&quote).is_some() {
+                        field.push(quote);
+                    } else {
+                        in_quotes = false;
+                    }
+                } else if c == quote {
+                    in_quotes = true;
+                    quoted = true;
+                } else if c == delimiter {
+                    fields.push(self.finish(std::mem::take(&mut field), quoted));
+                    quoted = false;
+                } else if c == '\n' || (c == '\r' && chars.peek() == Some(&'\n')) {
+                    // Only ever at the end of the line.
+                } else {
+                    field.push(c);
+                }
+            }
+            if !in_quotes {
+                break;
+            }
+            text.clear();
+            if self.input.read_line(&mut text)? == 0 {
+                return Err(Error::Syntax {
+                    line,
+                    message: "unterminated quoted field".to_string(),
+                });
+            }
+            self.line += 1;
+        }
+        fields.push(self.finish(field, quoted));
+        Ok(Some(Record { line, fields }))
+    }
+
+    fn finish(&self, field: String, quoted: bool) -> Option<String> {
+        if !quoted && field == self.options.null {
+            None
+        } else {
+            Some(field)
+        }
+    }
+}
+
+pub struct Writer<W> {
+    output: W,
+    options: Options,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(output: W, options: Options) -> Self {
+        Self { output, options }
+    }
+
+    pub fn write_record<S: AsRef<str>>(
+        &mut self,
+        fields: impl IntoIterator<Item = Option<S>>,
+    ) -> io::Result<()> {
+        let (delimiter, quote) = (self.options.delimiter, self.options.quote);
+        let mut line = String::new();
+        for (i, field) in fields.into_iter().enumerate() {
+            if i > 0 {
+                line.push(delimiter);
+            }
+            let Some(field) = field else {
+                line.push_str(&self.options.null);
+                continue;
+            };
+            let field = field.as_ref();
+            let needs_quotes = field == self.options.null
+                || field.contains([delimiter, quote, '\n', '\r'])
+                || field.starts_with(char::is_whitespace)
+                || field.ends_with(char::is_whitespace);
+            if needs_quotes {
+                line.push(quote);
+                for c in field.chars() {
+                    if c == quote {
+                        line.push(quote);
+                    }
+                    line.push(c);
+                }
+                line.push(quote);
+            } else {
+                line.push_str(field);
+            }
+        }
+        line.push('\n');
+        self.output.write_all(line.as_bytes())
+    }
+
+    /// Flushes what was written and hands back the output.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let options = Options {
+            delimiter: ';',
+            ..Options::default()
+        };
+        let records = vec![
+            vec![Some("1"), Some("plain"), None],
+            vec![Some("2"), Some("semi;colon \"quoted\""), Some("")],
+            vec![Some("3"), Some("two\r\nlines"), Some(" padded ")],
+        ];
+        let mut writer = Writer::new(vec![], options.clone());
+        for record in &records {
+            writer.write_record(record.iter().copied()).unwrap();
+        }
+        let text = writer.into_inner().unwrap();
+        assert!(text.starts_with(b"1;plain;\n2;\"semi;colon \"\"quoted\"\"\";\"\"\n"));
+
+        let mut reader = Reader::new(&text[..], options);
+        for (line, record) in [1, 2, 3].into_iter().zip(&records) {
+            let fields = record.iter().map(|f| f.map(str::to_string)).collect();
+            assert_eq!(Some(Record { line, fields }), reader.read_record().unwrap());
+        }
+        assert_eq!(None, reader.read_record().unwrap());
+
+        let mut reader = Reader::new(&b"a,b\n\"c,d\n"[..], Options::default());
+        reader.read_record().unwrap();
+        assert!(matches!(
+            reader.read_record(),
+            Err(Error::Syntax { line: 2, .. })
+        ));
+    }
+}
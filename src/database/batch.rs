@@ -0,0 +1,429 @@
+//! Query results column by column, in the memory layout of Apache Arrow.
+//!
+//! A [`RecordBatch`] holds an [`Array`] per column, laid out as Arrow lays
+//! out its arrays: the values one after another, with a slot for NULLs
+//! too; a validity bitmap with a bit per row, least significant first,
+//! set where the value is not NULL; and for text and bytes, 64-bit
+//! offsets into one buffer of data, as in Arrow's LargeUtf8 and
+//! LargeBinary; JSON values and arrays are held as their text. With the
+//! `arrow` feature, [`RecordBatch::into_arrow`] hands the vectors over to
+//! an arrow-rs record batch without copying them, and from there to
+//! polars or DataFusion, without going through the rows again.
+
+use crate::planner::Field;
+use crate::value::{DataType, Value};
+
+use super::Rows;
+
+/// A bit per row, least significant bit first.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Bitmap {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bitmap {
+    pub fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        i < self.len && self.bytes[i / 8] & (1 << (i % 8)) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bits packed into bytes, padded with zeros.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+/// One column of a [`RecordBatch`]. Value `i` of the variable-length
+/// arrays is `data[offsets[i]..offsets[i + 1]]`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Array {
+    /// A column of unknown type, since every value is NULL.
+    Null {
+        len: usize,
+    },
+    Boolean {
+        validity: Bitmap,
+        values: Bitmap,
+    },
+    Int64 {
+        validity: Bitmap,
+        values: Vec<i64>,
+    },
+    Float64 {
+        validity: Bitmap,
+        values: Vec<f64>,
+    },
+    LargeUtf8 {
+        validity: Bitmap,
+        offsets: Vec<i64>,
+        data: Vec<u8>,
+    },
+    LargeBinary {
+        validity: Bitmap,
+        offsets: Vec<i64>,
+        data: Vec<u8>,
+    },
+}
+
+impl Array {
+    fn new(data_type: Option<DataType>) -> Self {
+        let validity = Bitmap::default();
+        match data_type {
+            None => Array::Null { len: 0 },
+            Some(DataType::Bool) => Array::Boolean {
+                validity,
+                values: Bitmap::default(),
+            },
+            Some(DataType::Int) => Array::Int64 {
+                validity,
+                values: vec![],
+            },
+            Some(DataType::Float) => Array::Float64 {
+                validity,
+                values: vec![],
+            },
+            Some(DataType::Text | DataType::Json | DataType::Array(_)) => Array::LargeUtf8 {
+                validity,
+                offsets: vec![0],
+                data: vec![],
+            },
+            Some(DataType::Bytes) => Array::LargeBinary {
+                validity,
+                offsets: vec![0],
+                data: vec![],
+            },
+        }
+    }
+
+    fn push(&mut self, value: &Value) {
+        let push_bytes =
+            |validity: &mut Bitmap, offsets: &mut Vec<i64>, data: &mut Vec<u8>, bytes: &[u8]| {
+                validity.push(!value.is_null());
+                data.extend(bytes);
+                offsets.push(data.len() as i64);
+            };
+        match (self, value) {
+            (Array::Null { len }, _) => *len += 1,
+            (Array::Boolean { validity, values }, _) => {
+                validity.push(!value.is_null());
+                values.push(matches!(value, Value::Bool(true)));
+            }
+            (Array::Int64 { validity, values }, _) => {
+                validity.push(!value.is_null());
+                values.push(match value {
+                    Value::Int(i) => *i,
+                    _ => 0,
+                });
+            }
+            (Array::Float64 { validity, values }, _) => {
+                validity.push(!value.is_null());
+                values.push(match value {
+                    Value::Float(x) => *x,
+                    Value::Int(i) => *i as f64,
+                    _ => 0.0,
+                });
+            }
+            (
+                Array::LargeUtf8 {
+                    validity,
+                    offsets,
+                    data,
+                },
+                Value::Text(s),
+            ) => push_bytes(validity, offsets, data, s.as_bytes()),
+            (
+                Array::LargeUtf8 {
+                    validity,
+                    offsets,
+                    data,
+                },
+                Value::Json(_) | Value::Array(_),
+            ) => push_bytes(validity, offsets, data, value.to_string().as_bytes()),
+            (
+                Array::LargeBinary {
+                    validity,
+                    offsets,
+                    data,
+                },
+                Value::Bytes(bytes),
+            ) => push_bytes(validity, offsets, data, bytes),
+            (
+                Array::LargeUtf8 {
+                    validity,
+                    offsets,
+                    data,
+                }
+                | Array::LargeBinary {
+                    validity,
+                    offsets,
+                    data,
+                },
+                _,
+            ) => push_bytes(validity, offsets, data, &[]),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Array::Null { len } => *len,
+            Array::Boolean { validity, .. }
+            | Array::Int64 { validity, .. }
+            | Array::Float64 { validity, .. }
+            | Array::LargeUtf8 { validity, .. }
+            | Array::LargeBinary { validity, .. } => validity.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The validity bitmap, which a column of NULLs does without.
+    pub fn validity(&self) -> Option<&Bitmap> {
+        match self {
+            Array::Null { .. } => None,
+            Array::Boolean { validity, .. }
+            | Array::Int64 { validity, .. }
+            | Array::Float64 { validity, .. }
+            | Array::LargeUtf8 { validity, .. }
+            | Array::LargeBinary { validity, .. } => Some(validity),
+        }
+    }
+
+    /// The array as an arrow-rs one, holding the same buffers.
+    #[cfg(feature = "arrow")]
+    pub fn into_arrow(self) -> arrow::array::ArrayRef {
+        use std::sync::Arc;
+
+        use arrow::array::{
+            BooleanArray, Float64Array, Int64Array, LargeBinaryArray, LargeStringArray, NullArray,
+        };
+        use arrow::buffer::{BooleanBuffer, Buffer, NullBuffer, OffsetBuffer, ScalarBuffer};
+
+        let bits = |bitmap: Bitmap| {
+            let len = bitmap.len();
+            BooleanBuffer::new(Buffer::from_vec(bitmap.into_bytes()), 0, len)
+        };
+        let nulls = |validity: Bitmap| {
+            Some(NullBuffer::new(bits(validity))).filter(|nulls| nulls.null_count() > 0)
+        };
+        let offsets = |offsets: Vec<i64>| OffsetBuffer::new(ScalarBuffer::from(offsets));
+        match self {
+            Array::Null { len } => Arc::new(NullArray::new(len)),
+            Array::Boolean { validity, values } => {
+                Arc::new(BooleanArray::new(bits(values), nulls(validity)))
+            }
+            Array::Int64 { validity, values } => {
+                Arc::new(Int64Array::new(ScalarBuffer::from(values), nulls(validity)))
+            }
+            Array::Float64 { validity, values } => Arc::new(Float64Array::new(
+                ScalarBuffer::from(values),
+                nulls(validity),
+            )),
+            Array::LargeUtf8 {
+                validity,
+                offsets: starts,
+                data,
+            } => Arc::new(LargeStringArray::new(
+                offsets(starts),
+                Buffer::from_vec(data),
+                nulls(validity),
+            )),
+            Array::LargeBinary {
+                validity,
+                offsets: starts,
+                data,
+            } => Arc::new(LargeBinaryArray::new(
+                offsets(starts),
+                Buffer::from_vec(data),
+                nulls(validity),
+            )),
+        }
+    }
+
+    /// Value `i`, or `None` past the end.
+    pub fn value(&self, i: usize) -> Option<Value> {
+        if i >= self.len() {
+            return None;
+        }
+        if !self.validity().is_some_and(|validity| validity.get(i)) {
+            return Some(Value::Null);
+        }
+        let slice = |offsets: &[i64], data: &'_ [u8]| {
+            data[offsets[i] as usize..offsets[i + 1] as usize].to_vec()
+        };
+        Some(match self {
+            Array::Null { .. } => Value::Null,
+            Array::Boolean { values, .. } => Value::Bool(values.get(i)),
+            Array::Int64 { values, .. } => Value::Int(values[i]),
+            Array::Float64 { values, .. } => Value::Float(values[i]),
+            Array::LargeUtf8 { offsets, data, .. } => {
+                Value::Text(String::from_utf8(slice(offsets, data)).unwrap())
+            }
+            Array::LargeBinary { offsets, data, .. } => Value::Bytes(slice(offsets, data)),
+        })
+    }
+}
+
+/// Rows of a result as a column each; see the [module](self).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordBatch {
+    fields: Vec<Field>,
+    columns: Vec<Array>,
+    num_rows: usize,
+}
+
+impl RecordBatch {
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn columns(&self) -> &[Array] {
+        &self.columns
+    }
+
+    pub fn column(&self, i: usize) -> Option<&Array> {
+        self.columns.get(i)
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    pub fn into_columns(self) -> Vec<Array> {
+        self.columns
+    }
+
+    /// The batch as an arrow-rs one, its columns holding the same buffers
+    /// and all of them nullable.
+    #[cfg(feature = "arrow")]
+    pub fn into_arrow(self) -> arrow::record_batch::RecordBatch {
+        use std::sync::Arc;
+
+        use arrow::datatypes::{DataType as ArrowType, Field as ArrowField, Schema};
+        use arrow::record_batch::RecordBatchOptions;
+
+        let fields: Vec<_> = (self.fields.iter().zip(&self.columns))
+            .map(|(field, column)| {
+                let data_type = match column {
+                    Array::Null { .. } => ArrowType::Null,
+                    Array::Boolean { .. } => ArrowType::Boolean,
+                    Array::Int64 { .. } => ArrowType::Int64,
+                    Array::Float64 { .. } => ArrowType::Float64,
+                    Array::LargeUtf8 { .. } => ArrowType::LargeUtf8,
+                    Array::LargeBinary { .. } => ArrowType::LargeBinary,
+                };
+                ArrowField::new(&field.name, data_type, true)
+            })
+            .collect();
+        let columns = (self.columns.into_iter()).map(Array::into_arrow).collect();
+        let options = RecordBatchOptions::new().with_row_count(Some(self.num_rows));
+        arrow::record_batch::RecordBatch::try_new_with_options(
+            Arc::new(Schema::new(fields)),
+            columns,
+            &options,
+        )
+        .expect("the columns match the schema and the row count")
+    }
+}
+
+impl From<&Rows> for RecordBatch {
+    fn from(rows: &Rows) -> Self {
+        let mut columns: Vec<_> = rows
+            .fields()
+            .iter()
+            .map(|field| Array::new(field.data_type))
+            .collect();
+        for row in rows.iter() {
+            for (column, value) in columns.iter_mut().zip(row.values()) {
+                column.push(value);
+            }
+        }
+        Self {
+            fields: rows.fields().to_vec(),
+            columns,
+            num_rows: rows.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, Options};
+
+    #[test]
+    fn test_record_batch() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT, name TEXT, ok BOOL)")
+            .unwrap();
+        db.execute("INSERT INTO t VALUES (1, 'ab', true), (2, NULL, NULL), (3, 'c', false)")
+            .unwrap();
+        let rows = db
+            .query("SELECT id, name, ok, id * 0.5, NULL FROM t ORDER BY id")
+            .unwrap();
+        let batch = rows.to_record_batch();
+        assert_eq!(3, batch.num_rows());
+        assert_eq!(
+            Array::LargeUtf8 {
+                validity: Bitmap {
+                    bytes: vec![0b101],
+                    len: 3
+                },
+                offsets: vec![0, 2, 2, 3],
+                data: b"abc".to_vec(),
+            },
+            batch.columns()[1]
+        );
+        assert_eq!(Array::Null { len: 3 }, batch.columns()[4]);
+        for (i, row) in rows.iter().enumerate() {
+            let values: Vec<_> = batch
+                .columns()
+                .iter()
+                .map(|column| column.value(i).unwrap())
+                .collect();
+            assert_eq!(row.values(), values);
+        }
+
+        #[cfg(feature = "arrow")]
+        {
+            use arrow::array::{Array as _, AsArray};
+            use arrow::datatypes::Int64Type;
+
+            let arrow = batch.into_arrow();
+            assert_eq!((3, 5), (arrow.num_rows(), arrow.num_columns()));
+            assert_eq!("name", arrow.schema().field(1).name());
+            let ids = arrow.column(0).as_primitive::<Int64Type>();
+            assert_eq!(&[1, 2, 3], ids.values().as_ref());
+            let names = arrow.column(1).as_string::<i64>();
+            assert_eq!(
+                vec![Some("ab"), None, Some("c")],
+                names.iter().collect::<Vec<_>>()
+            );
+            assert!(arrow.column(2).as_boolean().is_null(1));
+            assert_eq!(3, arrow.column(4).len());
+        }
+    }
+}
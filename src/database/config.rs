@@ -0,0 +1,569 @@
+//! [`Options`] and reading them from a config file or the environment.
+//!
+//! A config file is a flat TOML document, one `option = value` per line:
+//!
+//! ```toml
+//! # neru7db.toml
+//! pool_size = 4096
+//! sync_mode = "off"
+//! work_mem = "64MB"
+//! statement_timeout = "30s"
+//! ```
+//!
+//! Each option can also be set by an environment variable named after it,
+//! such as `NERU7DB_WORK_MEM=64MB`. Sizes are bytes or a number with a
+//! unit of B, kB, MB or GB; durations are milliseconds or a number with a
+//! unit of ms, s, min or h.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::disk::{SyncMode, DEFAULT_BACKGROUND_WRITE_RATE, PAGE_SIZE};
+use crate::engine::settings::{parse_duration, parse_size, MIN_WORK_MEM};
+use crate::engine::{Concurrency, DEFAULT_MAINTENANCE_IDLE, DEFAULT_PLAN_CACHE_CAPACITY};
+use crate::executor::NullOrdering;
+
+/// Prefix of the environment variables [`Options::apply_env`] reads.
+pub const ENV_PREFIX: &str = "NERU7DB_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("cannot read {path}: {source}", path = .path.display())]
+    Read {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("line {line}: {message}")]
+    Syntax { line: usize, message: String },
+    #[error("unknown option {0:?}")]
+    UnknownOption(String),
+    #[error("invalid value {value:?} for {option}: {reason}")]
+    Invalid {
+        option: String,
+        value: String,
+        reason: &'static str,
+    },
+}
+
+/// How to open a database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Pages the buffer pool holds. A transaction can change at most this
+    /// many.
+    pub pool_size: usize,
+    /// Only [`PAGE_SIZE`] is supported; the option exists so a config
+    /// written for another page size is refused rather than misread.
+    pub page_size: usize,
+    pub sync_mode: SyncMode,
+    /// Bytes each sort, aggregation or hash join may hold before it
+    /// spills; `None` never spills.
+    pub work_mem: Option<usize>,
+    /// Bytes a statement may hold in all before it fails; `None` has no
+    /// limit.
+    pub memory_limit: Option<usize>,
+    /// Where spill files go; the system's temporary directory if `None`.
+    pub temp_dir: Option<PathBuf>,
+    /// Threads a parallel scan may use; one per CPU if `None`.
+    pub worker_threads: Option<usize>,
+    pub plan_cache_capacity: usize,
+    /// Query results kept to answer the same queries again; 0 keeps none.
+    pub result_cache_capacity: usize,
+    pub statement_timeout: Option<Duration>,
+    /// How long a server session waits for another to release the
+    /// database; `None`, which 0 spells, waits for as long as it takes.
+    pub lock_timeout: Option<Duration>,
+    /// Statements running at least this long go to the slow query log;
+    /// `None`, which -1 spells, logs none.
+    pub log_min_duration_statement: Option<Duration>,
+    /// The file statements that change rows or the catalog are recorded
+    /// in, as an [`AuditLog`](crate::engine::AuditLog); `None` records
+    /// none.
+    pub audit_log: Option<PathBuf>,
+    /// Operations recorded per page for debugging, as in
+    /// [`BufferPoolManager::with_lineage`](crate::buffer::BufferPoolManager::with_lineage);
+    /// 0 records none.
+    pub page_lineage: usize,
+    /// How long past versions of pages are kept for AS OF queries, as in
+    /// [`BufferPoolManager::with_history`](crate::buffer::BufferPoolManager::with_history);
+    /// `None`, which 0 spells, keeps none.
+    pub history_retention: Option<Duration>,
+    /// Dirty pages past which writing statements are held back, as in
+    /// [`WriteThrottle`](crate::engine::WriteThrottle); `None`, which 0
+    /// spells, holds none back.
+    pub dirty_page_soft_limit: Option<usize>,
+    pub dirty_page_hard_limit: Option<usize>,
+    /// Pages a second the background writer writes back at the most, as
+    /// in [`IoScheduler`](crate::disk::IoScheduler); `None`, which 0
+    /// spells, writes as fast as reads let it.
+    pub background_write_rate: Option<u64>,
+    /// Whether pages go whole to a journal before they are written in
+    /// place, so that a crash cannot tear them; see
+    /// [`DiskManager::with_full_page_writes`](crate::disk::DiskManager::with_full_page_writes).
+    /// On by default.
+    pub full_page_writes: bool,
+    /// Whether the journal of full-page writes holds its pages compressed
+    /// with LZ4, which writes and syncs less for each commit; see
+    /// [`DiskManager::with_journal_compression`](crate::disk::DiskManager::with_journal_compression).
+    pub journal_compression: bool,
+    /// Whether pages go to a doublewrite area a batch at a time before
+    /// they are written in place, the other way to keep a crash from
+    /// tearing them; see
+    /// [`DiskManager::with_doublewrite`](crate::disk::DiskManager::with_doublewrite).
+    /// At most one of the two may be on, so setting this one on turns
+    /// full-page writes off.
+    pub doublewrite: bool,
+    /// How long no statement must have run for before idle-time
+    /// maintenance does; see
+    /// [`Engine::run_maintenance`](crate::engine::Engine::run_maintenance).
+    pub maintenance_idle: Duration,
+    /// How transactions keep out of each other's way: by holding the
+    /// database, or by validating what they read at commit; see
+    /// [`Concurrency`].
+    pub concurrency: Concurrency,
+    /// Where NULLs sort when ORDER BY or an index key does not say, until
+    /// a session sets `null_ordering`; see [`NullOrdering`].
+    pub null_ordering: NullOrdering,
+    /// Whether [`Database::open`](super::Database::open) upgrades a file in
+    /// an older format rather than refusing it.
+    pub auto_upgrade: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            pool_size: 1024,
+            page_size: PAGE_SIZE,
+            sync_mode: SyncMode::Full,
+            work_mem: None,
+            memory_limit: None,
+            temp_dir: None,
+            worker_threads: None,
+            plan_cache_capacity: DEFAULT_PLAN_CACHE_CAPACITY,
+            result_cache_capacity: 0,
+            statement_timeout: None,
+            lock_timeout: None,
+            log_min_duration_statement: None,
+            audit_log: None,
+            page_lineage: 0,
+            history_retention: None,
+            dirty_page_soft_limit: None,
+            dirty_page_hard_limit: None,
+            background_write_rate: Some(DEFAULT_BACKGROUND_WRITE_RATE),
+            full_page_writes: true,
+            journal_compression: false,
+            doublewrite: false,
+            maintenance_idle: DEFAULT_MAINTENANCE_IDLE,
+            concurrency: Concurrency::Locking,
+            null_ordering: NullOrdering::Low,
+            auto_upgrade: true,
+        }
+    }
+}
+
+impl Options {
+    /// Names of the options [`Options::set`] accepts.
+    pub const NAMES: &'static [&'static str] = &[
+        "pool_size",
+        "page_size",
+        "sync_mode",
+        "work_mem",
+        "memory_limit",
+        "temp_dir",
+        "worker_threads",
+        "plan_cache_capacity",
+        "result_cache_capacity",
+        "statement_timeout",
+        "lock_timeout",
+        "log_min_duration_statement",
+        "audit_log",
+        "page_lineage",
+        "history_retention",
+        "dirty_page_soft_limit",
+        "dirty_page_hard_limit",
+        "background_write_rate",
+        "full_page_writes",
+        "journal_compression",
+        "doublewrite",
+        "maintenance_idle",
+        "concurrency",
+        "null_ordering",
+        "auto_upgrade",
+    ];
+
+    /// The defaults, overridden by the config file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| Error::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut options = Self::default();
+        options.apply_toml(&text)?;
+        Ok(options)
+    }
+
+    /// The defaults, overridden by the config file at `path` if given and
+    /// then by the environment, and checked.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let mut options = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        options.apply_env(std::env::vars())?;
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Sets the options a config file sets.
+    pub fn apply_toml(&mut self, text: &str) -> Result<(), Error> {
+        for (i, line) in text.lines().enumerate() {
+            let syntax = |message: &str| Error::Syntax {
+                line: i + 1,
+                message: message.to_string(),
+            };
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                return Err(syntax("tables are not supported"));
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| syntax("expected `option = value`"))?;
+            let key = key.trim();
+            let value = parse_toml_value(value.trim()).map_err(syntax)?;
+            self.set(key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Sets the options named by `NERU7DB_*` variables among `vars`;
+    /// other variables are ignored.
+    pub fn apply_env(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), Error> {
+        for (name, value) in vars {
+            let Some(option) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let option = option.to_lowercase();
+            self.set(&option, &value).map_err(|e| match e {
+                Error::UnknownOption(_) => Error::UnknownOption(name.clone()),
+                Error::Invalid { value, reason, .. } => Error::Invalid {
+                    option: name.clone(),
+                    value,
+                    reason,
+                },
+                e => e,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Changes an option by name, parsing `value` as a config file or
+    /// environment variable would spell it.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let invalid = |reason| Error::Invalid {
+            option: name.to_string(),
+            value: value.to_string(),
+            reason,
+        };
+        let count = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| invalid("expected a whole number"))
+        };
+        match name {
+            "pool_size" => self.pool_size = count()?,
+            "page_size" => {
+                self.page_size = parse_size(value).ok_or_else(|| invalid("expected a size"))?
+            }
+            "sync_mode" => {
+                self.sync_mode = value
+                    .parse()
+                    .map_err(|()| invalid("expected \"full\" or \"off\""))?
+            }
+            "work_mem" => {
+                self.work_mem = Some(parse_size(value).ok_or_else(|| invalid("expected a size"))?)
+            }
+            "memory_limit" => {
+                let limit = parse_size(value).ok_or_else(|| invalid("expected a size"))?;
+                self.memory_limit = Some(limit)
+            }
+            "temp_dir" => self.temp_dir = Some(PathBuf::from(value)),
+            "audit_log" => self.audit_log = Some(PathBuf::from(value)),
+            "worker_threads" => self.worker_threads = Some(count()?),
+            "plan_cache_capacity" => self.plan_cache_capacity = count()?,
+            "result_cache_capacity" => self.result_cache_capacity = count()?,
+            "statement_timeout" => {
+                let timeout =
+                    parse_duration(value).ok_or_else(|| invalid("expected a duration"))?;
+                self.statement_timeout = (!timeout.is_zero()).then_some(timeout);
+            }
+            "lock_timeout" => {
+                let timeout =
+                    parse_duration(value).ok_or_else(|| invalid("expected a duration"))?;
+                self.lock_timeout = (!timeout.is_zero()).then_some(timeout);
+            }
+            "log_min_duration_statement" if value.trim() == "-1" => {
+                self.log_min_duration_statement = None
+            }
+            "log_min_duration_statement" => {
+                self.log_min_duration_statement =
+                    Some(parse_duration(value).ok_or_else(|| invalid("expected a duration"))?)
+            }
+            "page_lineage" => self.page_lineage = count()?,
+            "history_retention" => {
+                let retention =
+                    parse_duration(value).ok_or_else(|| invalid("expected a duration"))?;
+                self.history_retention = (!retention.is_zero()).then_some(retention);
+            }
+            "dirty_page_soft_limit" => {
+                self.dirty_page_soft_limit = Some(count()?).filter(|&limit| limit > 0)
+            }
+            "dirty_page_hard_limit" => {
+                self.dirty_page_hard_limit = Some(count()?).filter(|&limit| limit > 0)
+            }
+            "background_write_rate" => {
+                self.background_write_rate = Some(count()? as u64).filter(|&rate| rate > 0)
+            }
+            "full_page_writes" => {
+                self.full_page_writes = value
+                    .parse()
+                    .map_err(|_| invalid("expected true or false"))?
+            }
+            "journal_compression" => {
+                self.journal_compression = value
+                    .parse()
+                    .map_err(|_| invalid("expected true or false"))?
+            }
+            "doublewrite" => {
+                self.doublewrite = value
+                    .parse()
+                    .map_err(|_| invalid("expected true or false"))?;
+                self.full_page_writes &= !self.doublewrite;
+            }
+            "maintenance_idle" => {
+                self.maintenance_idle =
+                    parse_duration(value).ok_or_else(|| invalid("expected a duration"))?
+            }
+            "concurrency" => {
+                self.concurrency = value
+                    .parse()
+                    .map_err(|()| invalid("expected \"locking\" or \"optimistic\""))?
+            }
+            "null_ordering" => {
+                self.null_ordering = value
+                    .parse()
+                    .map_err(|()| invalid("expected \"low\" or \"high\""))?
+            }
+            "auto_upgrade" => {
+                self.auto_upgrade = value
+                    .parse()
+                    .map_err(|_| invalid("expected true or false"))?
+            }
+            _ => return Err(Error::UnknownOption(name.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Fails on the first option that cannot work.
+    pub fn validate(&self) -> Result<(), Error> {
+        let invalid = |option: &str, value: String, reason| {
+            Err(Error::Invalid {
+                option: option.to_string(),
+                value,
+                reason,
+            })
+        };
+        if self.pool_size < MIN_POOL_SIZE {
+            return invalid(
+                "pool_size",
+                self.pool_size.to_string(),
+                "must be at least 8 pages",
+            );
+        }
+        if self.page_size != PAGE_SIZE {
+            return invalid(
+                "page_size",
+                self.page_size.to_string(),
+                "only 4096 is supported",
+            );
+        }
+        if let Some(work_mem) = self.work_mem {
+            if work_mem < MIN_WORK_MEM {
+                return invalid("work_mem", work_mem.to_string(), "must be at least 64kB");
+            }
+        }
+        if let Some(limit) = self.memory_limit {
+            if limit < MIN_WORK_MEM {
+                return invalid("memory_limit", limit.to_string(), "must be at least 64kB");
+            }
+        }
+        if let Some(dir) = &self.temp_dir {
+            if !dir.is_dir() {
+                return invalid("temp_dir", dir.display().to_string(), "not a directory");
+            }
+        }
+        if self.worker_threads == Some(0) {
+            return invalid("worker_threads", "0".to_string(), "must be at least 1");
+        }
+        if let (Some(soft), Some(hard)) = (self.dirty_page_soft_limit, self.dirty_page_hard_limit) {
+            if soft > hard {
+                return invalid(
+                    "dirty_page_soft_limit",
+                    soft.to_string(),
+                    "must not exceed dirty_page_hard_limit",
+                );
+            }
+        }
+        if self.doublewrite && self.full_page_writes {
+            return invalid(
+                "doublewrite",
+                "true".to_string(),
+                "cannot be on with full_page_writes",
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Enough frames for the pages a B+Tree split holds at once.
+const MIN_POOL_SIZE: usize = 8;
+
+/// The line up to a `#` that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// A TOML string, integer or boolean, spelled as [`Options::set`] reads it.
+fn parse_toml_value(value: &str) -> Result<String, &'static str> {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    return if chars.as_str().trim().is_empty() {
+                        Ok(out)
+                    } else {
+                        Err("unexpected text after the string")
+                    };
+                }
+                '\\' => out.push(match chars.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    _ => return Err("unsupported escape in string"),
+                }),
+                c => out.push(c),
+            }
+        }
+        return Err("unterminated string");
+    }
+    if value == "true" || value == "false" {
+        return Ok(value.to_string());
+    }
+    let digits = value.strip_prefix(['+', '-']).unwrap_or(value);
+    if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit() || c == '_') {
+        return Ok(value.replace('_', ""));
+    }
+    Err("expected a string, integer or boolean")
+}
+
+/// Splits `"64MB"` into 64 and `"MB"`.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options() {
+        let mut options = Options::default();
+        options
+            .apply_toml(
+                "# tuning\n\
+                 pool_size = 4_096\n\
+                 sync_mode = \"off\"  # fast\n\
+                 work_mem = \"64MB\"\n\
+                 memory_limit = \"1GB\"\n\
+                 statement_timeout = \"30s\"\n\
+                 concurrency = \"optimistic\"\n\
+                 null_ordering = \"high\"\n",
+            )
+            .unwrap();
+        options
+            .apply_env([
+                ("PATH".to_string(), "/bin".to_string()),
+                ("NERU7DB_WORKER_THREADS".to_string(), "2".to_string()),
+                ("NERU7DB_WORK_MEM".to_string(), "128kB".to_string()),
+            ])
+            .unwrap();
+        assert_eq!(4096, options.pool_size);
+        assert_eq!(SyncMode::Off, options.sync_mode);
+        assert_eq!(Some(128 << 10), options.work_mem);
+        assert_eq!(Some(1 << 30), options.memory_limit);
+        assert_eq!(Some(2), options.worker_threads);
+        assert_eq!(Some(Duration::from_secs(30)), options.statement_timeout);
+        assert_eq!(Concurrency::Optimistic, options.concurrency);
+        assert_eq!(NullOrdering::High, options.null_ordering);
+        options.validate().unwrap();
+
+        let error = |toml: &str| {
+            let mut options = Options::default();
+            options
+                .apply_toml(toml)
+                .and_then(|()| options.validate())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            "line 2: expected `option = value`",
+            error("pool_size = 8\npool_size")
+        );
+        assert_eq!("line 1: tables are not supported", error("[database]"));
+        assert_eq!("unknown option \"wal_dir\"", error("wal_dir = \"wal\""));
+        assert_eq!(
+            "invalid value \"lots\" for work_mem: expected a size",
+            error("work_mem = \"lots\"")
+        );
+        assert_eq!(
+            "invalid value \"8192\" for page_size: only 4096 is supported",
+            error("page_size = 8192")
+        );
+        assert_eq!(
+            "invalid value \"64\" for dirty_page_soft_limit: must not exceed dirty_page_hard_limit",
+            error("dirty_page_soft_limit = 64\ndirty_page_hard_limit = 32")
+        );
+        assert_eq!(
+            "invalid value \"true\" for doublewrite: cannot be on with full_page_writes",
+            error("doublewrite = true\nfull_page_writes = true")
+        );
+        // Pages are kept from tearing one way or the other.
+        let mut options = Options::default();
+        assert!(options.full_page_writes);
+        options.apply_toml("doublewrite = true").unwrap();
+        assert!(options.doublewrite && !options.full_page_writes);
+        assert_eq!(
+            "invalid value \"x\" for NERU7DB_POOL_SIZE: expected a whole number",
+            Options::default()
+                .apply_env([("NERU7DB_POOL_SIZE".to_string(), "x".to_string())])
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
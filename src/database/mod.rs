@@ -0,0 +1,1413 @@
+//! The embedded API: a database in a file, opened in one call.
+//!
+//! A [`Database`] sets up the disk, the buffer pool and an [`Engine`]
+//! for a file and hands back query results whose values can be read as
+//! Rust types. Statements run one at a time on their own, or together in
+//! [`Database::transaction`], which writes all their changes to the file
+//! or none of them.
+//!
+//! Outside a transaction, changes reach the file as the buffer pool
+//! evicts pages and when the database is closed, so a crash can lose the
+//! latest of them; [`Database::close`] writes everything and syncs.
+//!
+//! Closing also marks the file as shut down cleanly, and opening clears
+//! the mark until the next close. A file opened without it may have been
+//! cut off mid-write, so it is checked with [`check`](crate::check) first
+//! and refused if damaged. Dropping a database without closing it closes
+//! it as well as it can, warning on stderr if that fails.
+//!
+//! [`Database::open_read_only`] opens a copy of the file taken in memory,
+//! while another process may have it open for writing. Nothing is ever
+//! written to the file, not even the mark, and statements that would
+//! change the database fail with [`engine::Error::ReadOnly`].
+//! [`Database::snapshot`] opens one the same way from a copy of an open
+//! database, to run queries on while it goes on changing.
+//!
+//! Files keep the version of the format they are in, and
+//! [`Database::open`] upgrades one of an older version before it opens it;
+//! see [`upgrade`].
+//!
+//! Binary values too large for a row are written and read as streams
+//! with [`Database::create_blob`] and [`Database::open_blob`]; a table
+//! keeps the [`BlobId`] of each, as an INT.
+
+mod batch;
+pub mod config;
+mod params;
+mod row;
+pub mod upgrade;
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::backup;
+use crate::blob::{self, BlobId, BlobReader, BlobWriter};
+use crate::buffer::{self, BufferPoolManager};
+use crate::catalog::CATALOG_PAGE_ID;
+use crate::check::{self, Report};
+use crate::disk::{self, DiskManager, IoScheduler};
+use crate::dump;
+use crate::engine::{self, AuditLog, Engine, MaintenanceTask, Output, WriteThrottle};
+use crate::expr::Aggregator;
+use crate::metrics::Metrics;
+use crate::sql;
+use crate::sqlite;
+use crate::value::DataType;
+
+pub use crate::executor::{Progress, QueryProgress};
+pub use crate::value::Value;
+pub use batch::{Array, Bitmap, RecordBatch};
+pub use config::Options;
+pub use params::{Element, Params, SqlArray, ToValue};
+pub use row::{ColumnIndex, FromRow, FromValue, Iter, Row, Rows};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Engine(#[from] engine::Error),
+    #[error(transparent)]
+    Config(#[from] config::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Check(#[from] check::Error),
+    #[error(transparent)]
+    Backup(#[from] backup::Error),
+    #[error(transparent)]
+    Blob(#[from] blob::Error),
+    #[error(transparent)]
+    Sqlite(#[from] sqlite::Error),
+    #[error(transparent)]
+    Dump(#[from] dump::Error),
+    #[error(
+        "the file is in format version {0}, older than {version}; upgrade it with `neru7db upgrade`",
+        version = upgrade::FORMAT_VERSION
+    )]
+    OldFormat(u32),
+    #[error(
+        "the file is in format version {0}, newer than {version}, the latest this build reads",
+        version = upgrade::FORMAT_VERSION
+    )]
+    NewerFormat(u32),
+    #[error(
+        "upgrading failed ({source}), and so did putting back the backup {}: {restore}",
+        .backup.display()
+    )]
+    Unrestored {
+        source: Box<Error>,
+        backup: PathBuf,
+        restore: io::Error,
+    },
+    #[error("the database was not shut down cleanly and is damaged:\n{0}")]
+    Damaged(Box<Report>),
+    #[error("no column named {0:?}")]
+    NoSuchColumn(String),
+    #[error("column {index} does not exist; the row has {len}")]
+    ColumnIndex { index: usize, len: usize },
+    #[error("column {column:?} is {actual}, not {expected}")]
+    Type {
+        column: String,
+        expected: &'static str,
+        actual: String,
+    },
+    #[error("statement {index} of the batch failed: {source}")]
+    Batch { index: usize, source: Box<Error> },
+    #[error("a batch runs in a transaction of its own and cannot control it")]
+    TransactionInBatch,
+}
+
+/// Where the catalog's meta page keeps the shutdown mark, after the
+/// heap's own fields.
+pub(crate) const CLEAN_MARK_RANGE: std::ops::Range<usize> = 16..24;
+pub(crate) const CLEAN_MARK: [u8; 8] = *b"N7CLEAN\0";
+
+/// Copies of a file [`Database::open_read_only`] takes before it gives up
+/// on finding one that passes the check.
+const SNAPSHOT_ATTEMPTS: usize = 5;
+
+pub struct Database {
+    engine: Engine,
+    was_clean: bool,
+    closed: bool,
+}
+
+impl Database {
+    /// Opens the database in the file at `path`, creating it if need be.
+    /// See [`Options::load`] for options from a config file.
+    /// A file in an older format is upgraded first unless
+    /// [`Options::auto_upgrade`] is off; see [`upgrade`].
+    /// With [`Options::full_page_writes`], pages go through a journal at
+    /// [`disk::journal_path`], and with [`Options::doublewrite`] through a
+    /// doublewrite area at [`disk::doublewrite_path`]; what a crash left in
+    /// either is replayed first whatever the options.
+    pub fn open(path: impl AsRef<Path>, options: Options) -> Result<Self, Error> {
+        options.validate()?;
+        let disk = open_file(path, &options)?;
+        Self::with_disk(disk, options)
+    }
+
+    /// Opens the database in the file at `path` for reading only, from a
+    /// [snapshot](DiskManager::snapshot) of the file in memory. A writer
+    /// may hold the file open meanwhile. The copy is taken between the
+    /// batches the writer writes pages in, so it has all of the changes
+    /// of a committed transaction or none; as the writer also writes
+    /// pages as it evicts them outside transactions, the copy is checked
+    /// unless the file was closed cleanly, and taken again if damaged.
+    /// Changes the writer has not yet written to the file are not in it.
+    /// The copy is not refreshed, so open the file again to see later
+    /// changes.
+    pub fn open_read_only(path: impl AsRef<Path>, options: Options) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut attempts = 1;
+        loop {
+            match Self::with_disk(DiskManager::snapshot(path)?, options.clone()) {
+                Err(Error::Damaged(_)) if attempts < SNAPSHOT_ATTEMPTS => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_millis(10));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// A scratch database in an unnamed file that is gone once closed.
+    pub fn temporary(options: Options) -> Result<Self, Error> {
+        Self::with_disk(DiskManager::new(tempfile::tempfile()?)?, options)
+    }
+
+    /// Opens the database in `disk`, such as one over
+    /// [simulated storage](crate::sim). A read-only disk opens the
+    /// database read-only, as [`Database::open_read_only`] does.
+    pub fn with_disk(disk: DiskManager, options: Options) -> Result<Self, Error> {
+        options.validate()?;
+        if disk.is_read_only() && disk.num_pages() == 0 {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an empty database file cannot be opened read-only",
+            )));
+        }
+        let scheduler = IoScheduler::new(options.background_write_rate);
+        let disk = (disk.with_sync_mode(options.sync_mode)).with_scheduler(Arc::new(scheduler));
+        let mut bufmgr =
+            BufferPoolManager::with_lineage(disk, options.pool_size, options.page_lineage);
+        if let Some(retention) = options.history_retention {
+            bufmgr = bufmgr.with_history(retention);
+        }
+        let is_new = bufmgr.num_pages() == 0;
+        let was_clean = check_on_open(&bufmgr)?;
+        let mut engine =
+            Engine::open(bufmgr)?.with_plan_cache_capacity(options.plan_cache_capacity);
+        engine.set_attach_options(options.clone());
+        engine.set_result_cache_capacity(options.result_cache_capacity);
+        engine.set_statement_timeout(options.statement_timeout);
+        engine.set_lock_timeout(options.lock_timeout);
+        engine.set_log_min_duration_statement(options.log_min_duration_statement);
+        if let Some(path) = &options.audit_log {
+            engine.set_audit_log(Some(AuditLog::open(path)?));
+        }
+        engine.set_work_mem(options.work_mem);
+        engine.set_memory_limit(options.memory_limit);
+        engine.set_temp_dir(options.temp_dir);
+        engine.set_max_parallel_workers(options.worker_threads);
+        engine.set_maintenance_idle(options.maintenance_idle);
+        engine.set_concurrency(options.concurrency);
+        let mut planner = engine.planner_settings().clone();
+        planner.null_ordering = options.null_ordering;
+        engine.set_planner_settings(planner);
+        engine.set_write_throttle(WriteThrottle {
+            soft_limit: options.dirty_page_soft_limit,
+            hard_limit: options.dirty_page_hard_limit,
+        });
+        mark_open(engine.bufmgr(), is_new)?;
+        Ok(Self {
+            engine,
+            was_clean,
+            closed: false,
+        })
+    }
+
+    /// Whether the file had been closed cleanly when it was opened. A new
+    /// file counts as clean.
+    pub fn was_clean(&self) -> bool {
+        self.was_clean
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.engine.bufmgr().is_read_only()
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    /// See [`Engine::metrics`].
+    pub fn metrics(&self) -> Metrics {
+        self.engine.metrics()
+    }
+
+    /// A handle to read, from another thread, how far the statement the
+    /// database is running has got, as for a progress bar; see
+    /// [`Engine::query_progress`].
+    pub fn query_progress(&self) -> QueryProgress {
+        self.engine.query_progress()
+    }
+
+    /// Registers a scalar function for SQL to call, as long as the
+    /// database stays open; see [`Engine::create_function`].
+    pub fn create_function(
+        &mut self,
+        name: &str,
+        arguments: &[DataType],
+        return_type: DataType,
+        function: impl Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        Ok(self
+            .engine
+            .create_function(name, arguments, return_type, function)?)
+    }
+
+    /// Registers an aggregate function for SQL to call, as long as the
+    /// database stays open; see [`Engine::create_aggregate`].
+    pub fn create_aggregate(
+        &mut self,
+        name: &str,
+        argument: DataType,
+        return_type: DataType,
+        aggregator: impl Aggregator,
+    ) -> Result<(), Error> {
+        Ok(self
+            .engine
+            .create_aggregate(name, argument, return_type, aggregator)?)
+    }
+
+    /// Runs a statement and returns the number of rows it inserted,
+    /// updated or deleted. The rows of a query are dropped.
+    pub fn execute(&mut self, sql: &str) -> Result<u64, Error> {
+        execute(&mut self.engine, sql)
+    }
+
+    /// Deletes up to `limit` rows whose time to live has run out; see
+    /// [`Engine::expire_rows`].
+    pub fn expire_rows(&mut self, limit: usize) -> Result<u64, Error> {
+        Ok(self.engine.expire_rows(limit)?)
+    }
+
+    /// Scans up to `limit` pages for the indexes `CREATE INDEX
+    /// CONCURRENTLY` is building; see [`Engine::build_indexes`].
+    pub fn build_indexes(&mut self, limit: usize) -> Result<u64, Error> {
+        Ok(self.engine.build_indexes(limit)?)
+    }
+
+    /// The rows of `table`, from a count kept as rows change rather than a
+    /// scan; see [`Engine::estimated_row_count`].
+    pub fn estimated_row_count(&self, table: &str) -> Result<u64, Error> {
+        Ok(self.engine.estimated_row_count(table)?)
+    }
+
+    /// The bytes `table` takes in the file, without its indexes; see
+    /// [`Engine::table_size_bytes`].
+    pub fn table_size_bytes(&self, table: &str) -> Result<u64, Error> {
+        Ok(self.engine.table_size_bytes(table)?)
+    }
+
+    /// Does one step of idle-time maintenance, if any is due; see
+    /// [`Engine::run_maintenance`].
+    pub fn run_maintenance(&mut self) -> Result<Option<MaintenanceTask>, Error> {
+        Ok(self.engine.run_maintenance()?)
+    }
+
+    /// Attaches the database in the file at `path` as `name`, whose tables
+    /// statements then reach as `name.table`; see [`Engine::attach`].
+    pub fn attach(&mut self, path: impl AsRef<Path>, name: &str) -> Result<(), Error> {
+        Ok(self.engine.attach(path, name)?)
+    }
+
+    /// Detaches the database attached as `name`.
+    pub fn detach(&mut self, name: &str) -> Result<(), Error> {
+        Ok(self.engine.detach(name)?)
+    }
+
+    /// Starts copying the database as it is now to a new file at `path`,
+    /// which [`Database::copy_snapshots`] writes a batch of pages at a
+    /// time while statements go on changing the database; see
+    /// [`SnapshotFile`](crate::backup::SnapshotFile).
+    pub fn start_snapshot_to(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        Ok(self.engine.start_snapshot(path)?)
+    }
+
+    /// Copies up to `limit` pages to the files of
+    /// [`Database::start_snapshot_to`]; see [`Engine::copy_snapshots`].
+    pub fn copy_snapshots(&mut self, limit: u64) -> Result<u64, Error> {
+        Ok(self.engine.copy_snapshots(limit)?)
+    }
+
+    /// Writes a database file at `path` that holds the database as it is
+    /// now and opens on its own, such as a fixture for tests.
+    pub fn snapshot_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let bufmgr = self.engine.bufmgr();
+        let mut snapshot = backup::SnapshotFile::create(bufmgr, path)?;
+        snapshot.copy(bufmgr, u64::MAX)?;
+        Ok(())
+    }
+
+    /// Runs a statement and returns the rows it produced, if any.
+    pub fn query(&mut self, sql: &str) -> Result<Rows, Error> {
+        query(&mut self.engine, sql)
+    }
+
+    /// Runs a query and reads each of its rows as a `T`.
+    pub fn query_as<T: FromRow>(&mut self, sql: &str) -> Result<Vec<T>, Error> {
+        self.query(sql)?.decode()
+    }
+
+    /// [`execute`](Self::execute) with `params` for the statement's
+    /// parameters; see [`Params`].
+    pub fn execute_with(&mut self, sql: &str, params: impl Params) -> Result<u64, Error> {
+        Ok(affected(execute_with(&mut self.engine, sql, params)?))
+    }
+
+    /// [`query`](Self::query) with `params` for the statement's
+    /// parameters.
+    pub fn query_with(&mut self, sql: &str, params: impl Params) -> Result<Rows, Error> {
+        Ok(rows(execute_with(&mut self.engine, sql, params)?))
+    }
+
+    /// [`query_as`](Self::query_as) with `params` for the statement's
+    /// parameters.
+    pub fn query_as_with<T: FromRow>(
+        &mut self,
+        sql: &str,
+        params: impl Params,
+    ) -> Result<Vec<T>, Error> {
+        self.query_with(sql, params)?.decode()
+    }
+
+    /// Copies the JSON lines in the file at `path` into `table`, as `COPY
+    /// table FROM 'path' WITH (FORMAT json)` does, and returns how many.
+    /// A table that does not exist is created first, with the columns
+    /// [`engine::infer_json_columns`] finds.
+    pub fn import_json(&mut self, table: &str, path: impl AsRef<Path>) -> Result<u64, Error> {
+        let path = path.as_ref();
+        let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+        if self.engine.catalog().table(table).is_none() {
+            let columns: Vec<_> = engine::infer_json_columns(path)?
+                .into_iter()
+                .map(|(column, data_type)| format!("{} {data_type}", quote(&column)))
+                .collect();
+            let sql = format!("CREATE TABLE {} ({})", quote(table), columns.join(", "));
+            self.execute(&sql)?;
+        }
+        let file = path.to_string_lossy().replace('\'', "''");
+        let sql = format!("COPY {} FROM '{file}' WITH (FORMAT json)", quote(table));
+        self.execute(&sql)
+    }
+
+    /// Runs the statements in `script`, separated by semicolons, one at a
+    /// time, and returns how many rows they changed in all. It stops at
+    /// the first that fails, leaving the changes before it unless the
+    /// script started a transaction.
+    pub fn execute_script(&mut self, script: &str) -> Result<u64, Error> {
+        let (statements, rest) = sql::split_statements(script);
+        let mut count = 0;
+        for statement in statements.into_iter().chain(Some(rest.trim())) {
+            if !statement.is_empty() {
+                count += self.execute(statement)?;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Runs the statements in `batch`, separated by semicolons, in one
+    /// transaction, and returns what each did. All of them are parsed
+    /// before any runs; if one fails to parse or run, none of the batch's
+    /// changes are kept and the error gives its index, counting from 0.
+    /// The batch cannot be run inside a transaction, nor hold BEGIN,
+    /// COMMIT, ROLLBACK or savepoints.
+    pub fn execute_batch(&mut self, batch: &str) -> Result<Vec<StatementResult>, Error> {
+        let (statements, rest) = sql::split_statements(batch);
+        let statements: Vec<_> = (statements.into_iter().chain(Some(rest.trim())))
+            .filter(|statement| !statement.is_empty())
+            .collect();
+        let failed = |index, source: Error| Error::Batch {
+            index,
+            source: Box::new(source),
+        };
+        for (i, statement) in statements.iter().enumerate() {
+            match sql::parse_statement(statement) {
+                Ok(sql::ast::Statement::Transaction(_)) => {
+                    return Err(failed(i, Error::TransactionInBatch))
+                }
+                Ok(_) => {}
+                Err(e) => return Err(failed(i, engine::Error::from(e).into())),
+            }
+        }
+        self.transaction(|tx| {
+            (statements.iter().enumerate())
+                .map(|(i, statement)| run(tx.engine, statement).map_err(|e| failed(i, e)))
+                .collect()
+        })
+    }
+
+    /// Starts a blob, to write into a page at a time and refer to from
+    /// rows by its id; see [`blob`].
+    pub fn create_blob(&self) -> Result<BlobWriter<'_>, Error> {
+        if self.is_read_only() {
+            return Err(engine::Error::ReadOnly.into());
+        }
+        Ok(BlobWriter::create(self.engine.bufmgr())?)
+    }
+
+    /// Reads the blob `id` from its start.
+    pub fn open_blob(&self, id: BlobId) -> BlobReader<'_> {
+        BlobReader::open(self.engine.bufmgr(), id)
+    }
+
+    /// The length of the blob `id` in bytes.
+    pub fn blob_len(&self, id: BlobId) -> Result<u64, Error> {
+        Ok(blob::len(self.engine.bufmgr(), id)?)
+    }
+
+    /// Writes SQL that recreates the database's tables, rows and indexes
+    /// to `output`; see [`dump`](crate::dump). [`Database::execute_script`]
+    /// runs it.
+    pub fn dump(&self, output: impl io::Write) -> Result<(), Error> {
+        dump::dump(self.engine.catalog(), self.engine.bufmgr(), output)?;
+        Ok(())
+    }
+
+    /// Recreates the tables of the SQLite database in the file at `path`,
+    /// with their rows, and then their indexes; see [`sqlite`] for how
+    /// the schema carries over. The tables must not exist yet. A failure
+    /// drops the tables the import created, so either all of them are
+    /// imported or none. They are not imported in one transaction, which
+    /// could change no more pages than the buffer pool holds, so a crash
+    /// during the import can still leave some of them.
+    pub fn import_sqlite(&mut self, path: impl AsRef<Path>) -> Result<sqlite::Import, Error> {
+        let file = sqlite::File::open(path)?;
+        let schema = file.schema()?;
+        let mut import = sqlite::Import {
+            skipped: schema.skipped.clone(),
+            ..sqlite::Import::default()
+        };
+        let mut created = Vec::new();
+        let result = (|| {
+            for table in &schema.tables {
+                self.execute(&table.create_sql())?;
+                created.push(table);
+                let insert = self.engine.prepare(&table.insert_sql())?;
+                let mut count = 0;
+                for row in file.rows(table) {
+                    self.engine.execute_prepared(&insert, &row?)?;
+                    count += 1;
+                }
+                import.tables.push((table.name.clone(), count));
+            }
+            for index in &schema.indexes {
+                self.execute(&index.create_sql())?;
+                import.indexes.push(index.name.clone());
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            for table in created.iter().rev() {
+                self.execute(&table.drop_sql())?;
+            }
+            return Err(e);
+        }
+        Ok(import)
+    }
+
+    /// Runs `f` in a transaction, which commits if `f` returns `Ok` and
+    /// rolls back otherwise, or if `f` panics.
+    pub fn transaction<T, E: From<Error>>(
+        &mut self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.engine.begin().map_err(Error::from)?;
+        let mut tx = Transaction {
+            engine: &mut self.engine,
+        };
+        let value = f(&mut tx)?;
+        tx.engine.commit().map_err(Error::from)?;
+        Ok(value)
+    }
+
+    /// A read-only database holding a copy of this one as it is now, for
+    /// any number of queries to see the same rows while statements here
+    /// go on changing them. With no versions of rows to keep, the copy is
+    /// of every page, taken into memory as [`backup`](crate::backup)
+    /// would; statements here wait only while it is made, which takes as
+    /// long as reading the database does. It opens with the default
+    /// options, and cannot be taken inside a transaction.
+    pub fn snapshot(&self) -> Result<Database, Error> {
+        if self.engine.in_transaction() {
+            return Err(engine::Error::TransactionActive.into());
+        }
+        let image = backup::snapshot(self.engine.bufmgr())?;
+        let disk = DiskManager::with_storage(image)?.into_read_only();
+        Self::with_disk(disk, Options::default())
+    }
+
+    /// Copies the database to a new file at `path` while it stays open;
+    /// see [`backup`](crate::backup). SQL does the same with `BACKUP TO
+    /// 'path'`.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        backup::backup(self.engine.bufmgr(), path)?;
+        Ok(())
+    }
+
+    /// Copies the pages changed since the backups in `chain`, a full one
+    /// and those taken after it, to a new file at `path`. SQL does the
+    /// same with `BACKUP TO 'path' INCREMENTAL FROM 'full', ...`.
+    pub fn backup_incremental<P: AsRef<Path>>(
+        &self,
+        chain: &[P],
+        path: impl AsRef<Path>,
+    ) -> Result<u64, Error> {
+        Ok(backup::backup_incremental(
+            self.engine.bufmgr(),
+            chain,
+            path,
+        )?)
+    }
+
+    /// Puts the backup at `backup` in place of the database file at
+    /// `path`, which must not be open.
+    pub fn restore(backup: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<(), Error> {
+        backup::restore(backup, path)?;
+        Ok(())
+    }
+
+    /// As [`Database::restore`], for a full backup and the incremental
+    /// ones taken after it, in order.
+    pub fn restore_chain<P: AsRef<Path>>(chain: &[P], path: impl AsRef<Path>) -> Result<(), Error> {
+        backup::restore_chain(chain, path)?;
+        Ok(())
+    }
+
+    /// Writes every change to the file, then marks it as shut down
+    /// cleanly.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.closed = true;
+        if self.is_read_only() {
+            return Ok(());
+        }
+        self.mark_closed()
+    }
+
+    /// Marks the file, and those attached to it, as shut down cleanly.
+    fn mark_closed(&mut self) -> Result<(), Error> {
+        for database in self.engine.catalog().attached() {
+            mark_closed(database.bufmgr())?;
+        }
+        mark_closed(self.engine.bufmgr())
+    }
+}
+
+impl Drop for Database {
+    fn drop(&mut self) {
+        if let Some(warning) = self.close_for_drop() {
+            eprintln!("{warning}");
+        }
+    }
+}
+
+impl Database {
+    /// Closes the database as well as dropping it can, returning what to
+    /// warn of if recent changes may be lost: as they are if it is in a
+    /// transaction, whose changes never reach the file.
+    fn close_for_drop(&mut self) -> Option<String> {
+        if self.closed || self.is_read_only() {
+            return None;
+        }
+        self.closed = true;
+        let e = self.mark_closed().err()?;
+        Some(format!(
+            "neru7db: could not close the database, recent changes may be lost: {e}"
+        ))
+    }
+}
+
+fn read_clean_mark(bufmgr: &BufferPoolManager) -> Result<bool, Error> {
+    let page = bufmgr.fetch_page(CATALOG_PAGE_ID)?;
+    let clean = page.read()[CLEAN_MARK_RANGE] == CLEAN_MARK;
+    Ok(clean)
+}
+
+/// Sets or clears the mark. Setting it writes every other change first,
+/// so that the mark is only on the file once they are.
+fn write_clean_mark(bufmgr: &BufferPoolManager, clean: bool) -> Result<(), Error> {
+    if clean {
+        bufmgr.flush()?;
+    }
+    let mark = if clean { CLEAN_MARK } else { [0; 8] };
+    bufmgr.fetch_page(CATALOG_PAGE_ID)?.write()[CLEAN_MARK_RANGE].copy_from_slice(&mark);
+    bufmgr.flush()?;
+    Ok(())
+}
+
+/// Opens the database file at `path` for writing, as every writer of one
+/// must: what a crash left in its journal of full-page writes or its
+/// doublewrite area is replayed, a file in an older format is upgraded if
+/// [`Options::auto_upgrade`] says so, and the pages written from then on
+/// go through whichever of the two `options` turn on. The file is then
+/// for [`check_on_open`] and [`mark_open`] to take over once its pool
+/// is made, as [`Database::open`] and `ATTACH` do.
+pub fn open_file(path: impl AsRef<Path>, options: &Options) -> Result<DiskManager, Error> {
+    let path = path.as_ref();
+    replay(path, options)?;
+    if options.auto_upgrade {
+        upgrade::upgrade_disk(path, open_disk(path, options)?)?;
+    }
+    open_disk(path, options)
+}
+
+fn open_beside(path: &Path) -> io::Result<fs::File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+/// Puts back the pages a crash left in the journal or the doublewrite
+/// area of the file at `path`, whatever `options` say, and removes the
+/// one `options` turn off.
+fn replay(path: &Path, options: &Options) -> Result<(), Error> {
+    let journal_path = disk::journal_path(path);
+    let doublewrite_path = disk::doublewrite_path(path);
+    if journal_path.exists() {
+        DiskManager::open(path)?.replay_journal(open_beside(&journal_path)?)?;
+        if !options.full_page_writes {
+            fs::remove_file(&journal_path)?;
+        }
+    }
+    if doublewrite_path.exists() {
+        DiskManager::open(path)?.replay_doublewrite(open_beside(&doublewrite_path)?)?;
+        if !options.doublewrite {
+            fs::remove_file(&doublewrite_path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Opens the file at `path`, its journal or doublewrite area replayed
+/// already, with the defense against torn pages `options` turn on.
+fn open_disk(path: &Path, options: &Options) -> Result<DiskManager, Error> {
+    let mut disk = DiskManager::open(path)?;
+    if options.full_page_writes {
+        disk = (disk.with_full_page_writes(open_beside(&disk::journal_path(path))?)?)
+            .with_journal_compression(options.journal_compression);
+    } else if options.doublewrite {
+        disk = disk.with_doublewrite(open_beside(&disk::doublewrite_path(path))?)?;
+    }
+    Ok(disk)
+}
+
+/// Checks the file behind `bufmgr` before its catalog is opened: it must
+/// be in this build's format and, unless it was shut down cleanly, pass
+/// the [check](crate::check). Returns whether it was shut down cleanly;
+/// a new file counts as such.
+pub(crate) fn check_on_open(bufmgr: &BufferPoolManager) -> Result<bool, Error> {
+    if bufmgr.num_pages() == 0 {
+        return Ok(true);
+    }
+    match upgrade::read_version(bufmgr)? {
+        version if version < upgrade::FORMAT_VERSION => return Err(Error::OldFormat(version)),
+        version if version > upgrade::FORMAT_VERSION => return Err(Error::NewerFormat(version)),
+        _ => {}
+    }
+    let was_clean = read_clean_mark(bufmgr)?;
+    if !was_clean {
+        let report = check::check(bufmgr)?;
+        if !report.is_ok() {
+            return Err(Error::Damaged(Box::new(report)));
+        }
+    }
+    Ok(was_clean)
+}
+
+/// Marks the file behind `bufmgr`, its catalog open, as in use: with the
+/// format version if it `is_new`, and without the shutdown mark until
+/// [`mark_closed`]. A read-only file is left alone.
+pub(crate) fn mark_open(bufmgr: &BufferPoolManager, is_new: bool) -> Result<(), Error> {
+    if bufmgr.is_read_only() {
+        return Ok(());
+    }
+    if is_new {
+        upgrade::write_version(bufmgr, upgrade::FORMAT_VERSION)?;
+    }
+    write_clean_mark(bufmgr, false)
+}
+
+/// Writes every change to the file behind `bufmgr`, then marks it as
+/// shut down cleanly, unless it is read-only.
+pub(crate) fn mark_closed(bufmgr: &BufferPoolManager) -> Result<(), Error> {
+    match bufmgr.is_read_only() {
+        true => Ok(()),
+        false => write_clean_mark(bufmgr, true),
+    }
+}
+
+/// What a statement of [`Database::execute_batch`] returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatementResult {
+    Rows(Rows),
+    /// Rows inserted, updated or deleted; 0 for other statements.
+    Affected(u64),
+}
+
+/// The statements of a running [`Database::transaction`].
+pub struct Transaction<'a> {
+    engine: &'a mut Engine,
+}
+
+impl Transaction<'_> {
+    pub fn execute(&mut self, sql: &str) -> Result<u64, Error> {
+        execute(self.engine, sql)
+    }
+
+    pub fn query(&mut self, sql: &str) -> Result<Rows, Error> {
+        query(self.engine, sql)
+    }
+
+    pub fn query_as<T: FromRow>(&mut self, sql: &str) -> Result<Vec<T>, Error> {
+        self.query(sql)?.decode()
+    }
+
+    pub fn execute_with(&mut self, sql: &str, params: impl Params) -> Result<u64, Error> {
+        Ok(affected(execute_with(self.engine, sql, params)?))
+    }
+
+    pub fn query_with(&mut self, sql: &str, params: impl Params) -> Result<Rows, Error> {
+        Ok(rows(execute_with(self.engine, sql, params)?))
+    }
+
+    pub fn query_as_with<T: FromRow>(
+        &mut self,
+        sql: &str,
+        params: impl Params,
+    ) -> Result<Vec<T>, Error> {
+        self.query_with(sql, params)?.decode()
+    }
+}
+
+impl Drop for Transaction<'_> {
+    /// Rolls back unless the transaction got as far as committing. That
+    /// may be while a panic unwinds, so a failure is only warned of.
+    fn drop(&mut self) {
+        if self.engine.in_transaction() || self.engine.in_optimistic_transaction() {
+            if let Err(e) = self.engine.rollback() {
+                eprintln!("neru7db: could not roll back the transaction: {e}");
+            }
+        }
+    }
+}
+
+fn execute(engine: &mut Engine, sql: &str) -> Result<u64, Error> {
+    Ok(affected(engine.execute(sql)?))
+}
+
+fn execute_with(engine: &mut Engine, sql: &str, params: impl Params) -> Result<Output, Error> {
+    let statement = engine.prepare(sql)?;
+    Ok(engine.execute_prepared(&statement, &params.to_values())?)
+}
+
+fn affected(output: Output) -> u64 {
+    match output {
+        Output::Affected(n) => n,
+        Output::Rows { .. } | Output::Done => 0,
+    }
+}
+
+fn rows(output: Output) -> Rows {
+    match output {
+        Output::Rows { fields, rows } => Rows::new(fields, rows),
+        Output::Affected(_) | Output::Done => Rows::new(vec![], vec![]),
+    }
+}
+
+fn run(engine: &mut Engine, sql: &str) -> Result<StatementResult, Error> {
+    match engine.execute(sql)? {
+        Output::Rows { fields, rows } => Ok(StatementResult::Rows(Rows::new(fields, rows))),
+        Output::Affected(n) => Ok(StatementResult::Affected(n)),
+        Output::Done => Ok(StatementResult::Affected(0)),
+    }
+}
+
+fn query(engine: &mut Engine, sql: &str) -> Result<Rows, Error> {
+    Ok(rows(engine.execute(sql)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorCode;
+
+    #[test]
+    fn test_database() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut db = Database::open(file.path(), Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT COLLATE nocase, score FLOAT)")
+            .unwrap();
+        assert_eq!(
+            2,
+            db.execute("INSERT INTO t VALUES (1, 'a', 0.5), (2, NULL, 2)")
+                .unwrap()
+        );
+        let rows = db
+            .query("SELECT id, name, score FROM t ORDER BY id")
+            .unwrap();
+        assert_eq!(2, rows.len());
+        let row = rows.get(0).unwrap();
+        assert_eq!(1, row.get::<i64>("id").unwrap());
+        assert_eq!("a", row.get::<String>(1).unwrap());
+        let names: Vec<Option<String>> = rows.iter().map(|row| row.get(1).unwrap()).collect();
+        assert_eq!(vec![Some("a".to_string()), None], names);
+        assert_eq!(2.0, rows.get(1).unwrap().get::<f64>("score").unwrap());
+        assert!(matches!(
+            row.get::<i64>("nope"),
+            Err(Error::NoSuchColumn(_))
+        ));
+        assert!(matches!(
+            row.get::<i64>(3),
+            Err(Error::ColumnIndex { index: 3, len: 3 })
+        ));
+        assert_eq!(
+            "column \"name\" is TEXT, not INT",
+            row.get::<i64>("name").unwrap_err().to_string()
+        );
+        assert!(rows.get(1).unwrap().get::<String>("name").is_err());
+
+        let result: Result<(), Error> = db.transaction(|tx| {
+            tx.execute("INSERT INTO t VALUES (3, 'c', 1)")?;
+            tx.execute("INSERT INTO t VALUES (1, 'dup', 1)")?;
+            Ok(())
+        });
+        assert!(result.is_err());
+        let count = |db: &mut Database| {
+            let rows = db.query("SELECT count(*) FROM t").unwrap();
+            rows.get(0).unwrap().get::<i64>(0).unwrap()
+        };
+        assert_eq!(2, count(&mut db));
+        let inserted = db
+            .transaction(|tx| tx.execute("INSERT INTO t VALUES (3, 'c', 1)"))
+            .unwrap();
+        assert_eq!(1, inserted);
+        db.close().unwrap();
+
+        let mut db = Database::open(file.path(), Options::default()).unwrap();
+        assert!(db.was_clean());
+        assert_eq!(3, count(&mut db));
+        // The collation is kept with the table.
+        let rows = db.query("SELECT id, name FROM t WHERE name = 'C'").unwrap();
+        assert_eq!(
+            vec![vec![Value::from(3), Value::from("c")]],
+            rows.into_values()
+        );
+        drop(db);
+        // Clear the mark, as a crash would have left it.
+        let mut disk = DiskManager::open(file.path()).unwrap();
+        let mut data = [0; crate::disk::PAGE_SIZE];
+        disk.read_page_data(CATALOG_PAGE_ID, &mut data).unwrap();
+        data[CLEAN_MARK_RANGE].fill(0);
+        disk.write_page_data(CATALOG_PAGE_ID, &data).unwrap();
+        drop(disk);
+        let db = Database::open(file.path(), Options::default()).unwrap();
+        assert!(!db.was_clean());
+        drop(db);
+        let db = Database::open(file.path(), Options::default()).unwrap();
+        assert!(db.was_clean());
+    }
+
+    #[test]
+    fn test_drop() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || Database::open(file.path(), Options::default()).unwrap();
+        let count = |db: &mut Database| {
+            let rows = db.query("SELECT count(*) FROM t").unwrap();
+            rows.get(0).unwrap().get::<i64>(0).unwrap()
+        };
+        let mut db = open();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        db.execute("INSERT INTO t VALUES (1)").unwrap();
+        drop(db);
+        // Dropping flushed the rows and marked the file.
+        let mut db = open();
+        assert!(db.was_clean());
+        assert_eq!(1, count(&mut db));
+
+        // A panic in a transaction rolls it back as it unwinds.
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            db.transaction(|tx| -> Result<(), Error> {
+                tx.execute("INSERT INTO t VALUES (2)")?;
+                panic!("in the transaction")
+            })
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(1, count(&mut db));
+
+        // Dropped in a transaction, it warns that the changes are lost.
+        db.execute("BEGIN").unwrap();
+        db.execute("INSERT INTO t VALUES (2)").unwrap();
+        let warning = db.close_for_drop().unwrap();
+        assert!(warning.contains("recent changes may be lost"), "{warning}");
+        assert_eq!(None, db.close_for_drop());
+        drop(db);
+        let mut db = open();
+        assert!(!db.was_clean());
+        assert_eq!(1, count(&mut db));
+    }
+
+    #[test]
+    fn test_query_as() {
+        #[derive(Debug, PartialEq)]
+        struct Item {
+            id: i64,
+            name: Option<String>,
+        }
+
+        impl FromRow for Item {
+            fn from_row(row: &Row<'_>) -> Result<Self, Error> {
+                Ok(Self {
+                    id: row.get("id")?,
+                    name: row.get("name")?,
+                })
+            }
+        }
+
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        db.execute("INSERT INTO t VALUES (1, 'a'), (2, NULL)")
+            .unwrap();
+        let items: Vec<Item> = db.query_as("SELECT name, id FROM t ORDER BY id").unwrap();
+        assert_eq!(
+            vec![
+                Item {
+                    id: 1,
+                    name: Some("a".to_string())
+                },
+                Item { id: 2, name: None },
+            ],
+            items
+        );
+        let pairs: Vec<(i64, Option<String>)> =
+            db.query_as("SELECT id, name FROM t ORDER BY id").unwrap();
+        assert_eq!((2, None), pairs[1]);
+        assert!(matches!(
+            db.query_as::<Item>("SELECT id FROM t"),
+            Err(Error::NoSuchColumn(name)) if name == "name"
+        ));
+        assert!(matches!(
+            db.query_as::<(i64, i64)>("SELECT id, name FROM t"),
+            Err(Error::Type { .. })
+        ));
+    }
+
+    #[test]
+    fn test_execute_batch() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        let results = db
+            .execute_batch(
+                "CREATE TABLE t (id INT PRIMARY KEY); INSERT INTO t VALUES (1), (2);
+                 SELECT count(*) FROM t",
+            )
+            .unwrap();
+        assert_eq!(
+            vec![
+                StatementResult::Affected(0),
+                StatementResult::Affected(2),
+                StatementResult::Rows(db.query("SELECT 2 AS count").unwrap()),
+            ],
+            results
+        );
+
+        // A failing statement undoes the ones before it.
+        let count = |db: &mut Database| db.query_as::<(i64,)>("SELECT count(*) FROM t").unwrap();
+        let result = db.execute_batch("INSERT INTO t VALUES (3); INSERT INTO t VALUES (1)");
+        assert!(matches!(result, Err(Error::Batch { index: 1, .. })));
+        assert_eq!(vec![(2,)], count(&mut db));
+        // Nothing runs if one does not parse.
+        let result = db.execute_batch("INSERT INTO t VALUES (3); INSERT t");
+        assert!(matches!(result, Err(Error::Batch { index: 1, .. })));
+        assert_eq!(vec![(2,)], count(&mut db));
+        let result = db.execute_batch("INSERT INTO t VALUES (3); COMMIT");
+        assert!(matches!(
+            result,
+            Err(Error::Batch { index: 1, source }) if matches!(*source, Error::TransactionInBatch)
+        ));
+        assert_eq!(vec![(2,)], count(&mut db));
+    }
+
+    #[test]
+    fn test_estimates() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, padding TEXT)",
+            "CREATE TABLE events (at INT) PARTITION BY RANGE (at)",
+            "CREATE TABLE events_old PARTITION OF events FOR VALUES FROM (MINVALUE) TO (100)",
+            "CREATE TABLE events_new PARTITION OF events FOR VALUES FROM (100) TO (200)",
+            "INSERT INTO events VALUES (1), (2), (150)",
+        ] {
+            db.execute(sql).unwrap();
+        }
+        let values: Vec<_> = (0..1000).map(|id| format!("({id}, 'row {id}')")).collect();
+        db.execute(&format!("INSERT INTO t VALUES {}", values.join(", ")))
+            .unwrap();
+        db.execute("DELETE FROM t WHERE id < 100").unwrap();
+        db.execute("UPDATE t SET padding = 'x' WHERE id < 200")
+            .unwrap();
+        assert_eq!(900, db.estimated_row_count("t").unwrap());
+        let size = db.table_size_bytes("t").unwrap();
+        assert!(size > 2 * crate::disk::PAGE_SIZE as u64, "{size}");
+        let rolled_back: Result<u64, Error> = db.transaction(|tx| {
+            tx.execute("DELETE FROM t")?;
+            tx.execute("SELECT * FROM no_such_table")
+        });
+        assert!(rolled_back.is_err());
+        assert_eq!(900, db.estimated_row_count("t").unwrap());
+        assert_eq!(3, db.estimated_row_count("events").unwrap());
+        assert_eq!(2, db.estimated_row_count("events_old").unwrap());
+        assert!(db.estimated_row_count("missing").is_err());
+    }
+
+    #[test]
+    fn test_open_read_only() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = Database::open(file.path(), Options::default()).unwrap();
+        writer
+            .transaction(|tx| {
+                tx.execute("CREATE TABLE t (id INT PRIMARY KEY)")?;
+                tx.execute("INSERT INTO t VALUES (1), (2)")
+            })
+            .unwrap();
+        let mut reader = Database::open_read_only(file.path(), Options::default()).unwrap();
+        assert!(reader.is_read_only());
+        writer
+            .transaction(|tx| tx.execute("INSERT INTO t VALUES (3)"))
+            .unwrap();
+        let ids: Vec<(i64,)> = reader.query_as("SELECT id FROM t ORDER BY id").unwrap();
+        assert_eq!(vec![(1,), (2,)], ids);
+        for sql in [
+            "INSERT INTO t VALUES (4)",
+            "DELETE FROM t",
+            "CREATE INDEX t_id ON t (id)",
+            "ANALYZE t",
+        ] {
+            let e = reader.execute(sql).unwrap_err();
+            assert!(matches!(e, Error::Engine(engine::Error::ReadOnly)), "{sql}");
+            assert_eq!("25006", e.code().sqlstate());
+        }
+        reader.execute("BEGIN").unwrap();
+        reader.execute("COMMIT").unwrap();
+        reader.close().unwrap();
+        writer.close().unwrap();
+
+        let reader = Database::open_read_only(file.path(), Options::default()).unwrap();
+        assert!(reader.was_clean());
+        drop(reader);
+        // The readers left the mark alone.
+        let db = Database::open(file.path(), Options::default()).unwrap();
+        assert!(db.was_clean());
+        drop(db);
+        let empty = tempfile::NamedTempFile::new().unwrap();
+        assert!(Database::open_read_only(empty.path(), Options::default()).is_err());
+    }
+
+    #[test]
+    fn test_blobs() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let options = Options {
+            pool_size: 8,
+            ..Options::default()
+        };
+        let data: Vec<u8> = (0..3_000_000u32).map(|i| (i * 7 % 256) as u8).collect();
+        let mut db = Database::open(file.path(), options.clone()).unwrap();
+        db.execute("CREATE TABLE files (name TEXT PRIMARY KEY, blob INT)")
+            .unwrap();
+        let mut writer = db.create_blob().unwrap();
+        io::copy(&mut &data[..], &mut writer).unwrap();
+        let id = writer.id();
+        drop(writer);
+        db.execute(&format!(
+            "INSERT INTO files VALUES ('big', {})",
+            id.0.to_u64()
+        ))
+        .unwrap();
+        db.close().unwrap();
+
+        let mut db = Database::open(file.path(), options).unwrap();
+        let (blob,): (i64,) = db
+            .query_as("SELECT blob FROM files WHERE name = 'big'")
+            .unwrap()
+            .remove(0);
+        let id = BlobId(crate::disk::PageId(blob as u64));
+        assert_eq!(data.len() as u64, db.blob_len(id).unwrap());
+        let mut read = vec![];
+        io::Read::read_to_end(&mut db.open_blob(id), &mut read).unwrap();
+        assert!(read == data);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY)").unwrap();
+        db.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+        let mut snapshot = db.snapshot().unwrap();
+        db.execute("DELETE FROM t WHERE id = 1").unwrap();
+        db.execute("INSERT INTO t VALUES (3)").unwrap();
+        db.execute("CREATE TABLE u (id INT)").unwrap();
+        for _ in 0..2 {
+            let ids: Vec<(i64,)> = snapshot.query_as("SELECT id FROM t ORDER BY id").unwrap();
+            assert_eq!(vec![(1,), (2,)], ids);
+        }
+        assert!(snapshot.query("SELECT * FROM u").is_err());
+        assert!(matches!(
+            snapshot.execute("INSERT INTO t VALUES (4)"),
+            Err(Error::Engine(engine::Error::ReadOnly))
+        ));
+        let ids: Vec<(i64,)> = db.query_as("SELECT id FROM t ORDER BY id").unwrap();
+        assert_eq!(vec![(2,), (3,)], ids);
+
+        db.execute("BEGIN").unwrap();
+        assert!(db.snapshot().is_err());
+        db.execute("ROLLBACK").unwrap();
+    }
+
+    #[test]
+    fn test_as_of() {
+        let options = Options {
+            history_retention: Some(Duration::from_secs(3600)),
+            ..Options::default()
+        };
+        let mut db = Database::temporary(options).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, v TEXT)")
+            .unwrap();
+        db.execute("INSERT INTO t VALUES (1, 'a')").unwrap();
+        let (lsn, time): (i64, i64) = db
+            .query_as("SELECT lsn, time FROM neru_history ORDER BY lsn DESC LIMIT 1")
+            .unwrap()
+            .remove(0);
+        db.execute("UPDATE t SET v = 'b'").unwrap();
+        db.transaction::<_, Error>(|tx| tx.execute("INSERT INTO t VALUES (2, 'c')"))
+            .unwrap();
+        // Every point from `time` on has the row.
+        let count: Vec<(i64,)> = db
+            .query_as(&format!("SELECT count(*) FROM t AS OF TIMESTAMP {time}"))
+            .unwrap();
+        assert!(count[0].0 >= 1);
+        db.execute("BEGIN").unwrap();
+        db.execute("DELETE FROM t").unwrap();
+        db.execute("ROLLBACK").unwrap();
+        db.execute("DROP TABLE t").unwrap();
+
+        let rows: Vec<(i64, String)> = db
+            .query_as(&format!("SELECT id, v FROM t AS OF LSN {lsn}"))
+            .unwrap();
+        assert_eq!(vec![(1, "a".to_string())], rows);
+        let rows: Vec<(i64, String)> = db
+            .query_as(&format!(
+                "SELECT * FROM t WHERE id > 0 ORDER BY id AS OF LSN {}",
+                lsn + 2
+            ))
+            .unwrap();
+        assert_eq!(vec![(1, "b".to_string()), (2, "c".to_string())], rows);
+        assert!(db.query("SELECT * FROM t AS OF LSN 0").is_err());
+        assert!(db.query("SELECT * FROM t").is_err());
+        assert!(matches!(
+            db.query("SELECT 1 AS OF LSN 1000"),
+            Err(Error::Engine(engine::Error::NoHistory(_)))
+        ));
+        assert!(matches!(
+            db.query("SELECT 1 AS OF TIMESTAMP 0"),
+            Err(Error::Engine(engine::Error::NoHistory(_)))
+        ));
+
+        let mut db = Database::temporary(Options::default()).unwrap();
+        assert!(matches!(
+            db.query("SELECT 1 AS OF LSN 0"),
+            Err(Error::Engine(engine::Error::NoHistory(_)))
+        ));
+    }
+
+    #[test]
+    fn test_import_json() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            file.path(),
+            "{\"id\": 1, \"n\": 1, \"tag\": null}\n{\"id\": 2, \"n\": 2.5, \"Mixed Case\": true}\n",
+        )
+        .unwrap();
+        let mut db = Database::temporary(Options::default()).unwrap();
+        assert_eq!(2, db.import_json("Items", file.path()).unwrap());
+        assert_eq!(2, db.import_json("Items", file.path()).unwrap());
+        let columns: Vec<_> = db
+            .engine()
+            .catalog()
+            .table("Items")
+            .unwrap()
+            .schema
+            .columns
+            .iter()
+            .map(|column| format!("{} {}", column.name, column.data_type))
+            .collect();
+        assert_eq!(
+            vec!["id INT", "n FLOAT", "tag TEXT", "Mixed Case BOOL"],
+            columns
+        );
+        let rows = db.query("SELECT sum(n) FROM \"Items\"").unwrap();
+        assert_eq!(7.0, rows.get(0).unwrap().get::<f64>(0).unwrap());
+    }
+
+    #[test]
+    fn test_create_function() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.create_function("slugify", &[DataType::Text], DataType::Text, |args| {
+            let Value::Text(text) = &args[0] else {
+                return Ok(Value::Null);
+            };
+            let words: Vec<_> = text
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect();
+            Ok(Value::Text(words.join("-")))
+        })
+        .unwrap();
+        db.create_function(
+            "clamp",
+            &[DataType::Float, DataType::Float, DataType::Float],
+            DataType::Float,
+            |args| match args {
+                [Value::Float(x), Value::Float(lo), Value::Float(hi)] if lo <= hi => {
+                    Ok(Value::Float(x.clamp(*lo, *hi)))
+                }
+                [Value::Float(_), Value::Float(_), Value::Float(_)] => {
+                    Err("the bounds are reversed".into())
+                }
+                _ => Ok(Value::Null),
+            },
+        )
+        .unwrap();
+        db.execute("CREATE TABLE posts (title TEXT, score INT)")
+            .unwrap();
+        db.execute("INSERT INTO posts VALUES ('Hello, World!', 12), (NULL, -3)")
+            .unwrap();
+
+        let rows: Vec<(Option<String>, f64)> = db
+            .query_as("SELECT slugify(title), clamp(score, 0, 10) FROM posts ORDER BY score DESC")
+            .unwrap();
+        assert_eq!(
+            vec![(Some("hello-world".to_string()), 10.0), (None, 0.0)],
+            rows
+        );
+        let e = db.query("SELECT clamp(1, 2, 0)").unwrap_err();
+        assert_eq!(ErrorCode::ExternalRoutineException, e.code());
+        assert!(e.to_string().contains("the bounds are reversed"));
+        let e = db.query("SELECT slugify(1)").unwrap_err();
+        assert_eq!(ErrorCode::UndefinedFunction, e.code());
+        assert!(db
+            .create_function("lower", &[DataType::Text], DataType::Text, |_| Ok(
+                Value::Null
+            ))
+            .is_err());
+        assert!(db
+            .execute("CREATE INDEX by_slug ON posts (slugify(title))")
+            .is_err());
+        assert!(db.engine_mut().drop_function("slugify"));
+        assert!(db.query("SELECT slugify('a')").is_err());
+    }
+
+    #[test]
+    fn test_create_aggregate() {
+        struct Median;
+
+        impl Aggregator for Median {
+            type State = Vec<f64>;
+
+            fn init(&self) -> Vec<f64> {
+                vec![]
+            }
+
+            fn accumulate(&self, state: &mut Vec<f64>, value: &Value) -> Result<(), String> {
+                match value {
+                    Value::Float(x) if x.is_nan() => Err("NaN has no place in order".into()),
+                    Value::Float(x) => {
+                        state.push(*x);
+                        Ok(())
+                    }
+                    _ => unreachable!("the binder casts the argument"),
+                }
+            }
+
+            fn merge(&self, state: &mut Vec<f64>, other: Vec<f64>) -> Result<(), String> {
+                state.extend(other);
+                Ok(())
+            }
+
+            fn finalize(&self, state: &Vec<f64>) -> Result<Value, String> {
+                let mut sorted = state.clone();
+                sorted.sort_by(f64::total_cmp);
+                let n = sorted.len();
+                Ok(match n {
+                    0 => Value::Null,
+                    _ if n % 2 == 1 => Value::Float(sorted[n / 2]),
+                    _ => Value::Float((sorted[n / 2 - 1] + sorted[n / 2]) / 2.0),
+                })
+            }
+        }
+
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.create_aggregate("median", DataType::Float, DataType::Float, Median)
+            .unwrap();
+        db.execute("CREATE TABLE scores (id INT, team TEXT, score INT)")
+            .unwrap();
+        db.execute(
+            "INSERT INTO scores VALUES (1, 'a', 3), (2, 'a', 1), (3, 'b', 10), \
+             (4, 'a', NULL), (5, 'b', 20), (6, 'a', 8)",
+        )
+        .unwrap();
+
+        let rows: Vec<(String, f64)> = db
+            .query_as("SELECT team, median(score) FROM scores GROUP BY team ORDER BY team")
+            .unwrap();
+        assert_eq!(vec![("a".to_string(), 3.0), ("b".to_string(), 15.0)], rows);
+        let rows: Vec<(f64,)> = db.query_as("SELECT median(score) FROM scores").unwrap();
+        assert_eq!(vec![(8.0,)], rows);
+        let rows: Vec<(Option<f64>,)> = db
+            .query_as("SELECT median(score) FROM scores WHERE id > 100")
+            .unwrap();
+        assert_eq!(vec![(None,)], rows);
+        let rows: Vec<(i64, f64)> = db
+            .query_as(
+                "SELECT id, median(score) OVER (PARTITION BY team ORDER BY id) \
+                 FROM scores WHERE team = 'a' ORDER BY id",
+            )
+            .unwrap();
+        assert_eq!(vec![(1, 3.0), (2, 2.0), (4, 2.0), (6, 3.0)], rows);
+
+        let e = db.query("SELECT median(team) FROM scores").unwrap_err();
+        assert_eq!(ErrorCode::UndefinedFunction, e.code());
+        let e = db
+            .query("SELECT median(CAST('NaN' AS FLOAT)) FROM scores")
+            .unwrap_err();
+        assert_eq!(ErrorCode::ExternalRoutineException, e.code());
+        assert!(db
+            .create_aggregate("count", DataType::Int, DataType::Int, Median)
+            .is_err());
+        assert!(db.engine_mut().drop_aggregate("median"));
+        assert!(db.query("SELECT median(score) FROM scores").is_err());
+    }
+}
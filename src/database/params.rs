@@ -0,0 +1,266 @@
+//! Rust values bound to the parameters of a statement.
+//!
+//! A statement's parameters are written `$1`, `$2`, ... and given, in
+//! order, as anything [`Params`]: a tuple of [`ToValue`] types, which may
+//! differ, or a slice, array or `Vec` of one such type. `()` is no
+//! parameters. A `Vec<u8>` is BYTES, so an array parameter is given as a
+//! [`SqlArray`] of its elements.
+//!
+//! ```
+//! use neru7db::database::{Database, Options};
+//!
+//! let mut db = Database::temporary(Options::default()).unwrap();
+//! db.execute("CREATE TABLE t (id INT, name TEXT)").unwrap();
+//! db.execute_with("INSERT INTO t VALUES ($1, $2)", (1, "a")).unwrap();
+//! db.execute_with("INSERT INTO t VALUES ($1, $2)", (2, None::<&str>))
+//!     .unwrap();
+//! let names: Vec<(Option<String>,)> = db
+//!     .query_as_with("SELECT name FROM t WHERE id >= $1 ORDER BY id", [1])
+//!     .unwrap();
+//! assert_eq!(vec![(Some("a".to_string()),), (None,)], names);
+//! ```
+
+use super::FromValue;
+use crate::array::Array;
+use crate::json::Json;
+use crate::value::{DataType, Value};
+
+/// A Rust type a parameter can be given as.
+pub trait ToValue {
+    fn to_value(&self) -> Value;
+}
+
+impl ToValue for Value {
+    fn to_value(&self) -> Value {
+        self.clone()
+    }
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+macro_rules! int_to_value {
+    ($($t:ty),+) => {
+        $(impl ToValue for $t {
+            fn to_value(&self) -> Value {
+                Value::Int(i64::from(*self))
+            }
+        })+
+    };
+}
+
+int_to_value!(i16, i32, i64, u8, u16, u32);
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float(f64::from(*self))
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Value {
+        Value::Text(self.to_string())
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::Text(self.clone())
+    }
+}
+
+impl ToValue for [u8] {
+    fn to_value(&self) -> Value {
+        Value::Bytes(self.to_vec())
+    }
+}
+
+impl ToValue for Vec<u8> {
+    fn to_value(&self) -> Value {
+        Value::Bytes(self.clone())
+    }
+}
+
+impl ToValue for Json {
+    fn to_value(&self) -> Value {
+        Value::json(self.clone())
+    }
+}
+
+/// `None` is NULL.
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Value {
+        self.as_ref().map_or(Value::Null, T::to_value)
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Value {
+        (**self).to_value()
+    }
+}
+
+/// An array, such as INT[] or TEXT[], of `T`s, which are `Option`s if
+/// elements may be NULL. It reads from an array of `T`'s type alone.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SqlArray<T>(pub Vec<T>);
+
+/// A Rust type a [`SqlArray`] can hold.
+pub trait Element: ToValue {
+    /// The type of the array's elements.
+    const ELEMENT: DataType;
+    /// What an array of the type is called in errors.
+    const ARRAY_NAME: &'static str;
+}
+
+macro_rules! element {
+    ($($t:ty => $element:ident, $name:literal),+) => {
+        $(impl Element for $t {
+            const ELEMENT: DataType = DataType::$element;
+            const ARRAY_NAME: &'static str = $name;
+        })+
+    };
+}
+
+element!(
+    bool => Bool, "BOOL[]",
+    i16 => Int, "INT[] of 16-bit elements",
+    i32 => Int, "INT[] of 32-bit elements",
+    i64 => Int, "INT[]",
+    f64 => Float, "FLOAT[]",
+    String => Text, "TEXT[]",
+    &str => Text, "TEXT[]",
+    Vec<u8> => Bytes, "BYTES[]",
+    Json => Json, "JSON[]"
+);
+
+/// NULL elements are `None`.
+impl<T: Element> Element for Option<T> {
+    const ELEMENT: DataType = T::ELEMENT;
+    const ARRAY_NAME: &'static str = T::ARRAY_NAME;
+}
+
+impl<T: Element> ToValue for SqlArray<T> {
+    fn to_value(&self) -> Value {
+        let items = self.0.iter().map(T::to_value).collect();
+        Value::array(Array::new(T::ELEMENT, items))
+    }
+}
+
+impl<T: Element + FromValue> FromValue for SqlArray<T> {
+    const NAME: &'static str = T::ARRAY_NAME;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(array) if array.element == T::ELEMENT => {
+                let items = array.items.iter().map(T::from_value);
+                items.collect::<Option<_>>().map(SqlArray)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The parameters of a statement, in order.
+pub trait Params {
+    fn to_values(&self) -> Vec<Value>;
+}
+
+impl<T: ToValue> Params for [T] {
+    fn to_values(&self) -> Vec<Value> {
+        self.iter().map(T::to_value).collect()
+    }
+}
+
+impl<T: ToValue, const N: usize> Params for [T; N] {
+    fn to_values(&self) -> Vec<Value> {
+        self.as_slice().to_values()
+    }
+}
+
+impl<T: ToValue> Params for Vec<T> {
+    fn to_values(&self) -> Vec<Value> {
+        self.as_slice().to_values()
+    }
+}
+
+impl<P: Params + ?Sized> Params for &P {
+    fn to_values(&self) -> Vec<Value> {
+        (**self).to_values()
+    }
+}
+
+impl Params for () {
+    fn to_values(&self) -> Vec<Value> {
+        vec![]
+    }
+}
+
+macro_rules! tuple_params {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: ToValue),+> Params for ($($t,)+) {
+            fn to_values(&self) -> Vec<Value> {
+                vec![$(self.$i.to_value()),+]
+            }
+        }
+    };
+}
+
+tuple_params!(A 0);
+tuple_params!(A 0, B 1);
+tuple_params!(A 0, B 1, C 2);
+tuple_params!(A 0, B 1, C 2, D 3);
+tuple_params!(A 0, B 1, C 2, D 3, E 4);
+tuple_params!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_params!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_params!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Database, Options};
+    use super::*;
+
+    #[test]
+    fn test_params() {
+        assert_eq!(
+            vec![
+                Value::Int(1),
+                Value::Text("a".to_string()),
+                Value::Null,
+                Value::Float(0.5),
+                Value::Bytes(vec![1, 2]),
+            ],
+            (1u8, "a", None::<i64>, 0.5f32, &[1u8, 2][..]).to_values()
+        );
+        let ids = vec![1, 2];
+        assert_eq!(vec![Value::Int(1), Value::Int(2)], ids.to_values());
+        assert!(().to_values().is_empty());
+
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (ids INT[], tags TEXT[])")
+            .unwrap();
+        let (ids, tags) = (SqlArray(vec![1, 2]), SqlArray(vec![Some("a"), None]));
+        db.execute_with("INSERT INTO t VALUES ($1, $2)", (&ids, &tags))
+            .unwrap();
+        let rows: Vec<(SqlArray<i64>, SqlArray<Option<String>>)> =
+            db.query_as("SELECT ids, tags FROM t").unwrap();
+        let tags = SqlArray(vec![Some("a".to_string()), None]);
+        assert_eq!(vec![(ids, tags)], rows);
+        // Elements of another type, or NULL where they may not be.
+        assert!(db
+            .query_as::<(SqlArray<String>,)>("SELECT ids FROM t")
+            .is_err());
+        assert!(db
+            .query_as::<(SqlArray<String>,)>("SELECT tags FROM t")
+            .is_err());
+    }
+}
@@ -0,0 +1,313 @@
+//! Query results with typed access to their values.
+
+use super::batch::RecordBatch;
+use super::Error;
+use crate::json::Json;
+use crate::planner::Field;
+use crate::value::{Tuple, Value};
+
+/// A Rust type a column value can be read as.
+pub trait FromValue: Sized {
+    /// What the type is called in errors.
+    const NAME: &'static str;
+
+    /// The value as `Self`, or `None` if it is not one.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for Value {
+    const NAME: &'static str = "any value";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+
+impl FromValue for bool {
+    const NAME: &'static str = "BOOL";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for i64 {
+    const NAME: &'static str = "INT";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for i32 {
+    const NAME: &'static str = "INT that fits in 32 bits";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        i64::from_value(value)?.try_into().ok()
+    }
+}
+
+impl FromValue for i16 {
+    const NAME: &'static str = "INT that fits in 16 bits";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        i64::from_value(value)?.try_into().ok()
+    }
+}
+
+impl FromValue for u32 {
+    const NAME: &'static str = "INT from 0 to 2^32 - 1";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        i64::from_value(value)?.try_into().ok()
+    }
+}
+
+impl FromValue for f64 {
+    const NAME: &'static str = "FLOAT";
+
+    /// Ints are read as floats too.
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(x) => Some(*x),
+            Value::Int(i) => Some(*i as f64),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for String {
+    const NAME: &'static str = "TEXT";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Text(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Vec<u8> {
+    const NAME: &'static str = "BYTES";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bytes(bytes) => Some(bytes.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl FromValue for Json {
+    const NAME: &'static str = "JSON";
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Json(json) => Some((**json).clone()),
+            _ => None,
+        }
+    }
+}
+
+/// NULL is `None`; other values must be a `T`.
+impl<T: FromValue> FromValue for Option<T> {
+    const NAME: &'static str = T::NAME;
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+/// A Rust type a whole row can be read as, for [`Database::query_as`].
+///
+/// Structs usually read their fields by column name:
+///
+/// ```
+/// use neru7db::database::{Error, FromRow, Row};
+///
+/// struct User {
+///     id: i64,
+///     name: Option<String>,
+/// }
+///
+/// impl FromRow for User {
+///     fn from_row(row: &Row<'_>) -> Result<Self, Error> {
+///         Ok(Self {
+///             id: row.get("id")?,
+///             name: row.get("name")?,
+///         })
+///     }
+/// }
+/// ```
+///
+/// Tuples read the columns in order.
+///
+/// [`Database::query_as`]: super::Database::query_as
+pub trait FromRow: Sized {
+    fn from_row(row: &Row<'_>) -> Result<Self, Error>;
+}
+
+macro_rules! tuple_from_row {
+    ($($t:ident $i:tt),+) => {
+        impl<$($t: FromValue),+> FromRow for ($($t,)+) {
+            fn from_row(row: &Row<'_>) -> Result<Self, Error> {
+                Ok(($(row.get::<$t>($i)?,)+))
+            }
+        }
+    };
+}
+
+tuple_from_row!(A 0);
+tuple_from_row!(A 0, B 1);
+tuple_from_row!(A 0, B 1, C 2);
+tuple_from_row!(A 0, B 1, C 2, D 3);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4);
+tuple_from_row!(A 0, B 1, C 2, D 3, E 4, F 5);
+
+/// Picks a column of a row: by position, or by name.
+pub trait ColumnIndex {
+    fn index(&self, fields: &[Field]) -> Result<usize, Error>;
+}
+
+impl ColumnIndex for usize {
+    fn index(&self, fields: &[Field]) -> Result<usize, Error> {
+        if *self < fields.len() {
+            Ok(*self)
+        } else {
+            Err(Error::ColumnIndex {
+                index: *self,
+                len: fields.len(),
+            })
+        }
+    }
+}
+
+impl ColumnIndex for &str {
+    /// The first column with the name, as SQL output can repeat names.
+    fn index(&self, fields: &[Field]) -> Result<usize, Error> {
+        fields
+            .iter()
+            .position(|field| field.name == *self)
+            .ok_or_else(|| Error::NoSuchColumn(self.to_string()))
+    }
+}
+
+/// The rows a query returned, and what their columns are called.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rows {
+    fields: Vec<Field>,
+    rows: Vec<Tuple>,
+}
+
+impl Rows {
+    pub(super) fn new(fields: Vec<Field>, rows: Vec<Tuple>) -> Self {
+        Self { fields, rows }
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Row<'_>> {
+        let values = self.rows.get(index)?;
+        Some(Row {
+            fields: &self.fields,
+            values,
+        })
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            fields: &self.fields,
+            rows: self.rows.iter(),
+        }
+    }
+
+    /// Every row as a `T`, stopping at the first that cannot be read.
+    pub fn decode<T: FromRow>(&self) -> Result<Vec<T>, Error> {
+        self.iter().map(|row| T::from_row(&row)).collect()
+    }
+
+    pub fn into_values(self) -> Vec<Tuple> {
+        self.rows
+    }
+
+    /// The rows column by column, laid out as Apache Arrow does.
+    pub fn to_record_batch(&self) -> RecordBatch {
+        RecordBatch::from(self)
+    }
+}
+
+impl<'a> IntoIterator for &'a Rows {
+    type Item = Row<'a>;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a> {
+    fields: &'a [Field],
+    rows: std::slice::Iter<'a, Tuple>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Row<'a>;
+
+    fn next(&mut self) -> Option<Row<'a>> {
+        let values = self.rows.next()?;
+        Some(Row {
+            fields: self.fields,
+            values,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Row<'a> {
+    fields: &'a [Field],
+    values: &'a [Value],
+}
+
+impl<'a> Row<'a> {
+    pub fn fields(&self) -> &'a [Field] {
+        self.fields
+    }
+
+    pub fn values(&self) -> &'a [Value] {
+        self.values
+    }
+
+    /// The value of a column as a `T`; use an `Option` for columns that
+    /// may be NULL.
+    pub fn get<T: FromValue>(&self, column: impl ColumnIndex) -> Result<T, Error> {
+        let index = column.index(self.fields)?;
+        let value = &self.values[index];
+        T::from_value(value).ok_or_else(|| Error::Type {
+            column: self.fields[index].name.clone(),
+            expected: T::NAME,
+            actual: match value.data_type() {
+                Some(data_type) => data_type.to_string(),
+                None => "NULL".to_string(),
+            },
+        })
+    }
+}
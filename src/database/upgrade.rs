@@ -0,0 +1,285 @@
+//! Versions of the file format, and upgrading files of older ones.
+//!
+//! The catalog's meta page keeps the version of the format its file is
+//! in, after the shutdown mark. Files written before versions were kept
+//! have zeros there and read as version 0. Every change to the format
+//! comes with a [`Migration`] in [`MIGRATIONS`] that rewrites the pages of
+//! a file of the version before it, and bumps [`FORMAT_VERSION`].
+//!
+//! [`Database::open`](super::Database::open) upgrades a file of an older
+//! version as it opens it, unless the `auto_upgrade` option is off, and
+//! `neru7db upgrade FILE` does so on its own. Either way [`upgrade_file`]
+//! first backs the file up to `FILE.vN.bak` next to it, N being the version
+//! it was in, and then runs the migrations from that version on in order.
+//! Migrations write straight to the pool, with no transaction around them:
+//! a file whose upgrade fails is put back from the backup rather than
+//! rolled back, and the error says so if that fails too. Files of a
+//! newer version than this build knows are never opened.
+
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use super::{read_clean_mark, Error, Options};
+use crate::backup;
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{self, Catalog, CATALOG_PAGE_ID};
+use crate::check;
+use crate::disk::{self, DiskManager};
+use crate::engine;
+
+/// The version of the format this build writes.
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Where the catalog's meta page keeps the version, after the shutdown
+/// mark.
+const VERSION_RANGE: Range<usize> = 24..28;
+
+/// Frames of the pool migrations run with.
+const UPGRADE_POOL_SIZE: usize = 256;
+
+/// A change of the file format, from version `from` to the next.
+pub struct Migration {
+    pub from: u32,
+    pub summary: &'static str,
+    /// Rewrites the pages of a file of version `from`.
+    pub run: fn(&BufferPoolManager) -> Result<(), Error>,
+}
+
+/// Every migration, oldest first.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: 0,
+        summary: "record the format version in the catalog's meta page",
+        run: |_| Ok(()),
+    },
+    Migration {
+        from: 1,
+        summary: "count the rows and pages of each table in its meta page",
+        run: |bufmgr| {
+            let catalog = Catalog::open(bufmgr).map_err(engine::Error::from)?;
+            for table in catalog.tables() {
+                let recount = table.heap.recount(bufmgr).map_err(catalog::Error::from);
+                recount.map_err(engine::Error::from)?;
+            }
+            Ok(())
+        },
+    },
+    Migration {
+        from: 2,
+        summary: "follow each page with a checksum",
+        // Below the pool, so upgrade_disk rewrites the file itself
+        // once the migrations are done.
+        run: |_| Ok(()),
+    },
+];
+
+/// The version of the format the file behind `bufmgr` is in.
+pub(super) fn read_version(bufmgr: &BufferPoolManager) -> Result<u32, Error> {
+    let page = bufmgr.fetch_page(CATALOG_PAGE_ID)?;
+    let bytes = page.read()[VERSION_RANGE].try_into().unwrap();
+    Ok(u32::from_le_bytes(bytes))
+}
+
+pub(super) fn write_version(bufmgr: &BufferPoolManager, version: u32) -> Result<(), Error> {
+    let page = bufmgr.fetch_page(CATALOG_PAGE_ID)?;
+    page.write()[VERSION_RANGE].copy_from_slice(&version.to_le_bytes());
+    Ok(())
+}
+
+/// Where [`upgrade_file`] backs up the file at `path` of `version`.
+pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".v{version}.bak"));
+    PathBuf::from(name)
+}
+
+/// What [`upgrade_file`] did to a file of an older version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upgrade {
+    pub from: u32,
+    /// The copy of the file as it was.
+    pub backup: PathBuf,
+}
+
+/// Upgrades the database file at `path`, which must not be open, to
+/// [`FORMAT_VERSION`] if it is in an older version. Returns what it did,
+/// or `None` if the file was up to date, new or of a newer version.
+/// The file is opened as [`open_file`](super::open_file) opens it, with
+/// the default [`Options`].
+pub fn upgrade_file(path: impl AsRef<Path>) -> Result<Option<Upgrade>, Error> {
+    let path = path.as_ref();
+    let options = Options::default();
+    super::replay(path, &options)?;
+    upgrade_disk(path, super::open_disk(path, &options)?)
+}
+
+/// [`upgrade_file`] for the file at `path`, opened as `disk`.
+pub(super) fn upgrade_disk(path: &Path, disk: DiskManager) -> Result<Option<Upgrade>, Error> {
+    let bufmgr = BufferPoolManager::new(disk, UPGRADE_POOL_SIZE);
+    if bufmgr.num_pages() == 0 {
+        return Ok(None);
+    }
+    let from = read_version(&bufmgr)?;
+    if from >= FORMAT_VERSION {
+        return Ok(None);
+    }
+    if !read_clean_mark(&bufmgr)? {
+        let report = check::check(&bufmgr)?;
+        if !report.is_ok() {
+            return Err(Error::Damaged(Box::new(report)));
+        }
+    }
+    let backup = backup_path(path, from);
+    backup::backup(&bufmgr, &backup)?;
+    let migrated = migrate(&bufmgr, from);
+    drop(bufmgr);
+    let migrated = migrated.and_then(|()| Ok(disk::add_checksums(path)?));
+    if let Err(source) = migrated {
+        // Evictions may have written some of what the migrations did.
+        return Err(match put_back(&backup, path) {
+            Ok(()) => source,
+            Err(restore) => Error::Unrestored {
+                source: Box::new(source),
+                backup,
+                restore,
+            },
+        });
+    }
+    Ok(Some(Upgrade { from, backup }))
+}
+
+/// Puts `backup` in place of the file at `path`, all at once. It is a copy
+/// of the file from just before, so unlike [`backup::restore`] this does
+/// not check it again, which would fail for a migration that failed on
+/// what the check finds too.
+fn put_back(backup: &Path, path: &Path) -> io::Result<()> {
+    // Held until the copy is in place, so nothing opens the file meanwhile.
+    let _lock = DiskManager::open(path)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let copy = tempfile::NamedTempFile::new_in(dir)?;
+    fs::copy(backup, copy.path())?;
+    copy.as_file().sync_all()?;
+    // Pages the migrations left to replay are not the copy's.
+    for beside in [disk::journal_path(path), disk::doublewrite_path(path)] {
+        match fs::remove_file(beside) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    copy.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Runs the migrations from version `from` on, and writes the file back.
+fn migrate(bufmgr: &BufferPoolManager, from: u32) -> Result<(), Error> {
+    for migration in MIGRATIONS.iter().filter(|migration| migration.from >= from) {
+        (migration.run)(bufmgr)?;
+        write_version(bufmgr, migration.from + 1)?;
+    }
+    bufmgr.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Database, Options};
+    use super::*;
+    use crate::heap::HeapFile;
+
+    #[test]
+    fn test_upgrade_on_open() {
+        assert_eq!(
+            Some(FORMAT_VERSION),
+            MIGRATIONS.last().map(|migration| migration.from + 1)
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let mut db = Database::open(&path, Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY)").unwrap();
+        db.execute("INSERT INTO t VALUES (1)").unwrap();
+        assert_eq!(FORMAT_VERSION, read_version(db.engine().bufmgr()).unwrap());
+        // As a build from before versions were kept would have left it,
+        // with no counts in the table's meta page either.
+        write_version(db.engine().bufmgr(), 0).unwrap();
+        let meta_page_id = db.engine().catalog().table("t").unwrap().heap.meta_page_id;
+        db.engine()
+            .bufmgr()
+            .fetch_page(meta_page_id)
+            .unwrap()
+            .write()[32..48]
+            .fill(0);
+        db.close().unwrap();
+        // Nor checksums after its pages.
+        let slot = disk::Layout::Checksummed.slot_size();
+        let plain: Vec<u8> = (fs::read(&path).unwrap().chunks(slot))
+            .flat_map(|slot| slot[..disk::PAGE_SIZE].to_vec())
+            .collect();
+        fs::write(&path, plain).unwrap();
+
+        let manual = Options {
+            auto_upgrade: false,
+            ..Options::default()
+        };
+        assert!(matches!(
+            Database::open(&path, manual.clone()),
+            Err(Error::OldFormat(0))
+        ));
+        let mut db = Database::open(&path, Options::default()).unwrap();
+        let ids: Vec<(i64,)> = db.query_as("SELECT id FROM t").unwrap();
+        assert_eq!(vec![(1,)], ids);
+        assert_eq!(1, db.estimated_row_count("t").unwrap());
+        db.close().unwrap();
+        let layout = DiskManager::open_read_only(&path).unwrap().layout();
+        assert_eq!(disk::Layout::Checksummed, layout);
+        let backup = backup_path(&path, 0);
+        assert!(backup.exists());
+        assert_eq!(None, upgrade_file(&path).unwrap());
+        Database::open(&path, manual.clone())
+            .unwrap()
+            .close()
+            .unwrap();
+        assert!(matches!(
+            Database::open(&backup, manual),
+            Err(Error::OldFormat(0))
+        ));
+
+        let db = Database::open(&path, Options::default()).unwrap();
+        write_version(db.engine().bufmgr(), FORMAT_VERSION + 1).unwrap();
+        db.close().unwrap();
+        assert!(matches!(
+            Database::open(&path, Options::default()),
+            Err(Error::NewerFormat(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_upgrade_restores() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db");
+        let db = Database::open(&path, Options::default()).unwrap();
+        let bufmgr = db.engine().bufmgr();
+        write_version(bufmgr, 0).unwrap();
+        // A row the catalog cannot read fails the second migration.
+        let catalog = HeapFile::new(CATALOG_PAGE_ID);
+        catalog.insert(bufmgr, &["bogus".into()]).unwrap();
+        db.close().unwrap();
+        let before = std::fs::read(&path).unwrap();
+
+        let result = upgrade_file(&path);
+        assert!(matches!(result, Err(Error::Engine(_))), "{result:?}");
+        assert_eq!(before, std::fs::read(&path).unwrap());
+        let manual = Options {
+            auto_upgrade: false,
+            ..Options::default()
+        };
+        assert!(matches!(
+            Database::open(&path, manual),
+            Err(Error::OldFormat(0))
+        ));
+    }
+}
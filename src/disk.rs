@@ -0,0 +1,771 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::trace::{self, Event};
+
+mod doublewrite;
+mod journal;
+mod layout;
+mod schedule;
+
+use doublewrite::Doublewrite;
+pub use doublewrite::{DOUBLEWRITE_BATCH, DOUBLEWRITE_SLOTS};
+use journal::Journal;
+pub use journal::MAX_STAGED;
+pub use layout::{Layout, CHECKSUM_SIZE};
+pub use schedule::{IoPriority, IoScheduler, DEFAULT_BACKGROUND_WRITE_RATE, MAX_DEFER, READ_QUIET};
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// Whether syncing the file waits for the data to reach the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// fsync on every sync, so committed data survives a power loss.
+    #[default]
+    Full,
+    /// Hand writes to the OS and trust it; only a crash of the process
+    /// itself is survived.
+    Off,
+}
+
+impl std::str::FromStr for SyncMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_lowercase().as_str() {
+            "full" | "on" => Ok(Self::Full),
+            "off" => Ok(Self::Off),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PageId(pub u64);
+
+impl PageId {
+    pub const INVALID_PAGE_ID: PageId = PageId(u64::MAX);
+
+    pub fn valid(self) -> Option<PageId> {
+        if self == Self::INVALID_PAGE_ID {
+            None
+        } else {
+            Some(self)
+        }
+    }
+
+    pub fn to_u64(self) -> u64 {
+        self.0
+    }
+
+    pub fn to_bytes(self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> PageId {
+        PageId(u64::from_le_bytes(bytes[..8].try_into().unwrap()))
+    }
+}
+
+impl Default for PageId {
+    fn default() -> Self {
+        Self::INVALID_PAGE_ID
+    }
+}
+
+impl From<Option<PageId>> for PageId {
+    fn from(page_id: Option<PageId>) -> Self {
+        page_id.unwrap_or_default()
+    }
+}
+
+/// Reads and writes fixed-size pages of a single database file.
+///
+/// Where a [`DiskManager`] keeps its pages, as bytes at offsets.
+pub trait Storage: Send {
+    /// Bytes stored, of which whole pages count.
+    fn size(&mut self) -> io::Result<u64>;
+    fn read_at(&mut self, offset: u64, data: &mut [u8]) -> io::Result<()>;
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()>;
+    /// Waits for the writes so far to reach stable storage.
+    fn sync(&mut self) -> io::Result<()>;
+}
+
+impl Storage for File {
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn read_at(&mut self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.read_exact(data)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.write_all(data)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.sync_all()
+    }
+}
+
+/// The bytes of a file held in memory, as [`DiskManager::snapshot`] reads
+/// them.
+impl Storage for Vec<u8> {
+    fn size(&mut self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn read_at(&mut self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        let start = offset as usize;
+        let bytes = self
+            .get(start..start + data.len())
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        data.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let start = offset as usize;
+        if self.len() < start + data.len() {
+            self.resize(start + data.len(), 0);
+        }
+        self[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Times [`DiskManager::snapshot`] reads a file that keeps changing
+/// before it gives up.
+const SNAPSHOT_READS: usize = 8;
+
+/// Where the journal of [full-page writes](DiskManager::with_full_page_writes)
+/// for the file at `path` goes: next to it, as `FILE-fpw`.
+pub fn journal_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push("-fpw");
+    PathBuf::from(name)
+}
+
+/// Where the [doublewrite area](DiskManager::with_doublewrite) for the
+/// file at `path` goes: next to it, as `FILE-dblwr`.
+pub fn doublewrite_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push("-dblwr");
+    PathBuf::from(name)
+}
+
+/// Where a writer of the file at `path` holds the lock that
+/// [`DiskManager::snapshot`] waits on: next to it, as `FILE-lock`.
+pub fn batch_lock_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push("-lock");
+    PathBuf::from(name)
+}
+
+/// The lock on `FILE-lock` of a writer, held while any of its
+/// [batches](DiskManager::begin_batch) is.
+struct BatchLock {
+    file: File,
+    batches: Mutex<usize>,
+}
+
+/// A batch of writes, which no snapshot of the file sees only part of;
+/// see [`DiskManager::begin_batch`]. It ends once dropped.
+pub struct Batch {
+    lock: Option<Arc<BatchLock>>,
+}
+
+impl Drop for Batch {
+    fn drop(&mut self) {
+        let Some(lock) = &self.lock else {
+            return;
+        };
+        let mut batches = lock.batches.lock().unwrap();
+        *batches -= 1;
+        if *batches == 0 {
+            let _ = lock.file.unlock();
+        }
+    }
+}
+
+/// How a manager keeps a crash from tearing the pages it writes.
+enum Defense {
+    FullPageWrites(Journal),
+    Doublewrite(Doublewrite),
+}
+
+/// A file opened with [`DiskManager::open`] is locked for writing with an
+/// advisory lock until the manager is dropped, so a second writer, in
+/// this process or another, fails to open it. Readers opened with
+/// [`DiskManager::open_read_only`] take no lock and may run beside the
+/// writer; they see whatever pages it has written so far.
+/// [Snapshots](DiskManager::snapshot) wait for the writer's
+/// [batches](DiskManager::begin_batch) instead, so they see all of the
+/// pages of each or none.
+///
+/// Reads of pages go through the manager's [`IoScheduler`], which
+/// background writers [wait on](IoScheduler::admit) so as to yield to
+/// them.
+pub struct DiskManager {
+    storage: Box<dyn Storage>,
+    layout: Layout,
+    /// Whether pages that do not match their checksums fail to read.
+    verify: bool,
+    scheduler: Arc<IoScheduler>,
+    /// Where pages go before they are written in place, if anywhere.
+    defense: Option<Defense>,
+    /// Pages written since they last went there, which are held back
+    /// until they do.
+    staged: BTreeMap<u64, Vec<u8>>,
+    next_page_id: u64,
+    sync_mode: SyncMode,
+    read_only: bool,
+    /// The writer's lock that snapshots wait on, for a file opened by path.
+    batch_lock: Option<Arc<BatchLock>>,
+    stats: DiskStats,
+}
+
+/// What a [`DiskManager`] has done since it was opened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiskStats {
+    pub pages_read: u64,
+    pub pages_written: u64,
+    /// Syncs that waited for the disk, which [`SyncMode::Off`] skips.
+    pub syncs: u64,
+    /// Writes its [`IoScheduler`] admitted at background priority, those
+    /// of them that yielded to reads first, and those its rate held back.
+    pub background_writes: u64,
+    pub yielded_writes: u64,
+    pub rate_limited_writes: u64,
+    /// Pages written whole to the journal of full-page writes or the
+    /// doublewrite area first.
+    pub full_page_writes: u64,
+    /// Bytes written to the journal of full-page writes, compressed if
+    /// it compresses them.
+    pub journal_bytes: u64,
+}
+
+impl DiskManager {
+    /// A manager of the pages in `heap_file`, in whichever [`Layout`] it
+    /// has; a new file gets checksums.
+    pub fn new(mut heap_file: File) -> io::Result<Self> {
+        let layout = Layout::of(&mut heap_file)?;
+        Self::with_layout(heap_file, layout)
+    }
+
+    /// A manager of the pages in `storage`, such as a
+    /// [simulated file](crate::sim::SimFile), back to back as images in
+    /// memory keep them.
+    pub fn with_storage(storage: impl Storage + 'static) -> io::Result<Self> {
+        Self::with_layout(storage, Layout::Plain)
+    }
+
+    /// A manager of the pages in `storage`, laid out as `layout` says.
+    pub fn with_layout(mut storage: impl Storage + 'static, layout: Layout) -> io::Result<Self> {
+        let next_page_id = storage.size()? / layout.slot_size() as u64;
+        Ok(Self {
+            storage: Box::new(storage),
+            layout,
+            verify: true,
+            scheduler: Arc::default(),
+            defense: None,
+            staged: BTreeMap::new(),
+            next_page_id,
+            sync_mode: SyncMode::default(),
+            read_only: false,
+            batch_lock: None,
+            stats: DiskStats::default(),
+        })
+    }
+
+    pub fn with_sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    /// The manager, scheduling its I/O with `scheduler`, which it may share
+    /// with the managers of other files.
+    pub fn with_scheduler(mut self, scheduler: Arc<IoScheduler>) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    pub fn scheduler(&self) -> &Arc<IoScheduler> {
+        &self.scheduler
+    }
+
+    /// The manager, writing pages whole to `journal` before it writes them
+    /// in place, which lets a crash tear none; see [`journal`]. What a crash
+    /// left in `journal` is [replayed](Self::replay_journal) first.
+    /// Written pages are held back in memory until the next sync, or
+    /// until [`MAX_STAGED`] of them are, and readers of the file see them
+    /// only from then on.
+    pub fn with_full_page_writes(mut self, journal: impl Storage + 'static) -> io::Result<Self> {
+        let mut journal = Journal::new(Box::new(journal));
+        self.put_back(journal.read()?)?;
+        journal.clear()?;
+        self.defense = Some(Defense::FullPageWrites(journal));
+        Ok(self)
+    }
+
+    /// The manager, compressing the pages it writes to its journal of
+    /// full-page writes with LZ4 if `compress` is set; see [`journal`].
+    /// Does nothing without full-page writes.
+    pub fn with_journal_compression(mut self, compress: bool) -> Self {
+        if let Some(Defense::FullPageWrites(journal)) = &mut self.defense {
+            journal.compress(compress);
+        }
+        self
+    }
+
+    /// Writes the pages in `journal`, as a manager with full-page writes
+    /// left it, back in place if it holds them whole, and syncs. Returns
+    /// how many it wrote.
+    pub fn replay_journal(&mut self, journal: impl Storage + 'static) -> io::Result<usize> {
+        let mut journal = Journal::new(Box::new(journal));
+        let replayed = self.put_back(journal.read()?)?;
+        journal.clear()?;
+        Ok(replayed)
+    }
+
+    /// The manager, writing pages to the doublewrite area in `area` a
+    /// batch at a time before it writes them in place, which lets a crash
+    /// tear none for good; see [`doublewrite`]. What a crash left in
+    /// `area` is [replayed](Self::replay_doublewrite) first. Written pages
+    /// are held back in memory until [`DOUBLEWRITE_BATCH`] of them are, or
+    /// until the next sync.
+    pub fn with_doublewrite(mut self, area: impl Storage + 'static) -> io::Result<Self> {
+        let mut area = Doublewrite::open(Box::new(area))?;
+        self.put_back(area.read()?)?;
+        self.defense = Some(Defense::Doublewrite(area));
+        Ok(self)
+    }
+
+    /// Writes the newest copy of each page in the doublewrite area `area`
+    /// back in place, and syncs. Returns how many pages it wrote.
+    pub fn replay_doublewrite(&mut self, area: impl Storage + 'static) -> io::Result<usize> {
+        let mut area = Doublewrite::open(Box::new(area))?;
+        let replayed = self.put_back(area.read()?)?;
+        // The pages are in place for good, so the slots can go.
+        area.restart(true)?;
+        Ok(replayed)
+    }
+
+    /// Writes `pages`, taken from a journal or doublewrite area, in place
+    /// and syncs.
+    fn put_back(&mut self, pages: impl IntoIterator<Item = (u64, Vec<u8>)>) -> io::Result<usize> {
+        let mut replayed = 0;
+        for (page_id, data) in pages {
+            if self.read_only {
+                return Err(read_only());
+            }
+            (self.layout).write_page(&mut *self.storage, PageId(page_id), &data)?;
+            self.next_page_id = self.next_page_id.max(page_id + 1);
+            replayed += 1;
+        }
+        if replayed > 0 {
+            self.storage.sync()?;
+        }
+        Ok(replayed)
+    }
+
+    /// The manager, reading pages whatever their checksums say, so that
+    /// [`check`](crate::check) can look into those that do not match.
+    pub fn without_verification(mut self) -> Self {
+        self.verify = false;
+        self
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Whether the page at `page_id` in the file matches its checksum.
+    pub fn verify_page(&mut self, page_id: PageId) -> io::Result<bool> {
+        let mut data = [0; PAGE_SIZE];
+        (self.layout).read_unverified(&mut *self.storage, page_id, &mut data)
+    }
+
+    /// The manager, refusing to write pages from now on.
+    pub fn into_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn open(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let heap_file_path = heap_file_path.as_ref();
+        let heap_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(heap_file_path)?;
+        match heap_file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "the database file is already open for writing",
+                ))
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        let file = open_lock(batch_lock_path(heap_file_path))?;
+        let mut disk = Self::new(heap_file)?;
+        disk.batch_lock = Some(Arc::new(BatchLock {
+            file,
+            batches: Mutex::new(0),
+        }));
+        Ok(disk)
+    }
+
+    /// Opens an existing file for reading only. Writing a page fails.
+    pub fn open_read_only(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let heap_file = File::open(heap_file_path)?;
+        Ok(Self::new(heap_file)?.into_read_only())
+    }
+
+    /// A copy of the file at `path` as it is now, held in memory and read
+    /// only, so that what a writer does to the file later does not show.
+    /// It is taken between the [batches](Self::begin_batch) of the
+    /// writer, waiting for the one it is writing to end. In case the
+    /// writer takes no such lock, the file is read until two reads in a
+    /// row agree; a file that never settles fails with
+    /// [`io::ErrorKind::WouldBlock`].
+    pub fn snapshot(heap_file_path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = heap_file_path.as_ref();
+        let lock = match File::open(batch_lock_path(path)) {
+            Ok(lock) => Some(lock),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        if let Some(lock) = &lock {
+            lock.lock_shared()?;
+        }
+        let mut image = fs::read(path)?;
+        for _ in 0..SNAPSHOT_READS {
+            let again = fs::read(path)?;
+            if again == image {
+                let layout = Layout::of(&mut image)?;
+                return Ok(Self::with_layout(image, layout)?.into_read_only());
+            }
+            image = again;
+        }
+        Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "the database file kept changing while it was read",
+        ))
+    }
+
+    /// Starts a batch of writes, which ends when the returned guard is
+    /// dropped: until then, [snapshots](Self::snapshot) of the file wait,
+    /// so that they see all of the pages written in it or none. Batches
+    /// may overlap, and snapshots wait for the last to end. A manager not
+    /// opened by path is never snapshotted and has nothing to lock.
+    pub fn begin_batch(&self) -> io::Result<Batch> {
+        if let Some(lock) = &self.batch_lock {
+            let mut batches = lock.batches.lock().unwrap();
+            if *batches == 0 {
+                lock.file.lock()?;
+            }
+            *batches += 1;
+        }
+        Ok(Batch {
+            lock: self.batch_lock.clone(),
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn stats(&self) -> DiskStats {
+        let (background_writes, yielded_writes, rate_limited_writes) = self.scheduler.counts();
+        DiskStats {
+            background_writes,
+            yielded_writes,
+            rate_limited_writes,
+            ..self.stats
+        }
+    }
+
+    pub fn allocate_page(&mut self) -> PageId {
+        let page_id = self.next_page_id;
+        self.next_page_id += 1;
+        PageId(page_id)
+    }
+
+    /// Number of pages allocated so far, including ones not yet written.
+    pub fn num_pages(&self) -> u64 {
+        self.next_page_id
+    }
+
+    /// Forgets the pages allocated from `num_pages` on, along with any of
+    /// them held back to be written. Those written already stay in the
+    /// file until their ids are handed out again.
+    pub fn release_pages_from(&mut self, num_pages: u64) {
+        self.staged.split_off(&num_pages);
+        self.next_page_id = self.next_page_id.min(num_pages);
+    }
+
+    pub fn read_page_data(&mut self, page_id: PageId, data: &mut [u8]) -> io::Result<()> {
+        if let Some(staged) = self.staged.get(&page_id.to_u64()) {
+            data.copy_from_slice(staged);
+            return Ok(());
+        }
+        let start = trace::start();
+        let reading = self.scheduler.reading();
+        let storage = &mut *self.storage;
+        match self.verify {
+            true => self.layout.read_page(storage, page_id, data)?,
+            false => _ = self.layout.read_unverified(storage, page_id, data)?,
+        }
+        drop(reading);
+        self.stats.pages_read += 1;
+        if let Some(start) = start {
+            trace::emit(&Event::PageRead {
+                page_id,
+                elapsed: start.elapsed(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn write_page_data(&mut self, page_id: PageId, data: &[u8]) -> io::Result<()> {
+        if self.read_only {
+            return Err(read_only());
+        }
+        let start = trace::start();
+        if let Some(defense) = &self.defense {
+            let limit = match defense {
+                Defense::FullPageWrites(_) => MAX_STAGED,
+                Defense::Doublewrite(_) => DOUBLEWRITE_BATCH,
+            };
+            self.staged.insert(page_id.to_u64(), data.to_vec());
+            if self.staged.len() >= limit {
+                self.write_staged(false)?;
+            }
+        } else {
+            (self.layout).write_page(&mut *self.storage, page_id, data)?;
+        }
+        self.stats.pages_written += 1;
+        if let Some(start) = start {
+            trace::emit(&Event::PageWrite {
+                page_id,
+                elapsed: start.elapsed(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        if !self.staged.is_empty() {
+            return self.write_staged(true);
+        }
+        if self.sync_mode == SyncMode::Off {
+            return Ok(());
+        }
+        sync_traced(&mut *self.storage)?;
+        self.stats.syncs += 1;
+        Ok(())
+    }
+
+    /// Writes the pages held back to the journal or doublewrite area, then
+    /// in place, syncing after each unless syncs are off. The file is
+    /// synced afterwards for full-page writes, and for a doublewrite area
+    /// if `sync` is set. The pages are kept until they are in place, for a
+    /// later sync to write again if this one fails.
+    fn write_staged(&mut self, sync: bool) -> io::Result<()> {
+        let full = self.sync_mode == SyncMode::Full;
+        let (staged, layout) = (&self.staged, self.layout);
+        let storage = &mut *self.storage;
+        let in_place = |storage: &mut dyn Storage| -> io::Result<()> {
+            for (&page_id, data) in staged {
+                layout.write_page(storage, PageId(page_id), data)?;
+            }
+            Ok(())
+        };
+        let mut syncs = 0;
+        match self.defense.as_mut().expect("only a defense stages pages") {
+            Defense::FullPageWrites(journal) => {
+                self.stats.journal_bytes += journal.write(staged, full)?;
+                in_place(storage)?;
+                if full {
+                    sync_traced(storage)?;
+                    syncs += 2;
+                }
+                journal.clear()?;
+            }
+            Defense::Doublewrite(area) => {
+                if area.room() < staged.len() {
+                    if full {
+                        sync_traced(storage)?;
+                        syncs += 2;
+                    }
+                    area.restart(full)?;
+                }
+                area.write(staged, full)?;
+                in_place(storage)?;
+                if full {
+                    syncs += 1;
+                    if sync {
+                        sync_traced(storage)?;
+                        syncs += 1;
+                    }
+                }
+            }
+        }
+        self.stats.syncs += syncs;
+        self.stats.full_page_writes += staged.len() as u64;
+        self.staged.clear();
+        Ok(())
+    }
+}
+
+/// Syncs `storage`, tracing how long it took.
+fn sync_traced(storage: &mut dyn Storage) -> io::Result<()> {
+    let start = trace::start();
+    storage.sync()?;
+    if let Some(start) = start {
+        trace::emit(&Event::Sync {
+            elapsed: start.elapsed(),
+        });
+    }
+    Ok(())
+}
+
+impl Drop for DiskManager {
+    /// Writes out the pages held back, as they would have been written
+    /// without a defense against torn pages.
+    fn drop(&mut self) {
+        if !self.staged.is_empty() {
+            let _ = self.write_staged(true);
+        }
+    }
+}
+
+/// Rewrites the file at `path`, which must not be open, with a checksum
+/// after each page, unless it has them already; see [`Layout`]. The file
+/// is replaced all at once.
+pub fn add_checksums(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    // Held until the copy is in place, so nothing opens the file meanwhile.
+    let mut disk = DiskManager::open(path)?;
+    if disk.layout == Layout::Checksummed {
+        return Ok(());
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut copy = tempfile::NamedTempFile::new_in(dir)?;
+    let mut data = [0; PAGE_SIZE];
+    for page_id in (0..disk.num_pages()).map(PageId) {
+        disk.read_page_data(page_id, &mut data)?;
+        Layout::Checksummed.write_page(copy.as_file_mut(), page_id, &data)?;
+    }
+    copy.as_file().sync_all()?;
+    copy.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Opens the file a writer locks for its batches, creating it if needed.
+fn open_lock(path: PathBuf) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "the database file is open read-only",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_read_write() {
+        let (data_file, data_file_path) = NamedTempFile::new().unwrap().into_parts();
+        let mut disk = DiskManager::new(data_file).unwrap();
+        let mut hello = Vec::with_capacity(PAGE_SIZE);
+        hello.extend_from_slice(b"hello");
+        hello.resize(PAGE_SIZE, 0);
+        let hello_page_id = disk.allocate_page();
+        disk.write_page_data(hello_page_id, &hello).unwrap();
+        let mut world = Vec::with_capacity(PAGE_SIZE);
+        world.extend_from_slice(b"world");
+        world.resize(PAGE_SIZE, 0);
+        let world_page_id = disk.allocate_page();
+        disk.write_page_data(world_page_id, &world).unwrap();
+        drop(disk);
+
+        let mut disk2 = DiskManager::open(&data_file_path).unwrap();
+        assert_eq!(disk2.num_pages(), 2);
+        let mut buf = vec![0; PAGE_SIZE];
+        disk2.read_page_data(hello_page_id, &mut buf).unwrap();
+        assert_eq!(hello, buf);
+        disk2.read_page_data(world_page_id, &mut buf).unwrap();
+        assert_eq!(world, buf);
+    }
+
+    #[test]
+    fn test_lock() {
+        let file = NamedTempFile::new().unwrap();
+        let writer = DiskManager::open(file.path()).unwrap();
+        let e = DiskManager::open(file.path()).err().unwrap();
+        assert_eq!(io::ErrorKind::WouldBlock, e.kind());
+        let mut reader = DiskManager::open_read_only(file.path()).unwrap();
+        let page_id = reader.allocate_page();
+        let e = reader
+            .write_page_data(page_id, &[0; PAGE_SIZE])
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::PermissionDenied, e.kind());
+        drop(writer);
+        let mut writer = DiskManager::open(file.path()).unwrap();
+        let page_id = writer.allocate_page();
+        writer.write_page_data(page_id, &[1; PAGE_SIZE]).unwrap();
+
+        let mut snapshot = DiskManager::snapshot(file.path()).unwrap();
+        writer.write_page_data(page_id, &[2; PAGE_SIZE]).unwrap();
+        let mut data = [0; PAGE_SIZE];
+        snapshot.read_page_data(page_id, &mut data).unwrap();
+        assert_eq!([1; PAGE_SIZE], data);
+        assert!(snapshot.is_read_only());
+
+        // A snapshot waits for the batch being written to end.
+        let batch = writer.begin_batch().unwrap();
+        writer.write_page_data(page_id, &[3; PAGE_SIZE]).unwrap();
+        let path = file.path().to_path_buf();
+        let reader = std::thread::spawn(move || DiskManager::snapshot(path).unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!reader.is_finished());
+        let other = writer.allocate_page();
+        writer.write_page_data(other, &[4; PAGE_SIZE]).unwrap();
+        drop(batch);
+        let mut snapshot = reader.join().unwrap();
+        assert_eq!(2, snapshot.num_pages());
+        snapshot.read_page_data(page_id, &mut data).unwrap();
+        assert_eq!([3; PAGE_SIZE], data);
+    }
+}
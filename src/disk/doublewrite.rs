@@ -0,0 +1,137 @@
+//! A doublewrite area, the other defense against torn pages.
+//!
+//! Where [full-page writes](super::journal) hold a sync's pages back and
+//! write them together, a [`DiskManager`](super::DiskManager) with a
+//! doublewrite area writes pages in small batches as it goes, as InnoDB
+//! does: [`DOUBLEWRITE_BATCH`] pages at a time go to the next free slots
+//! of the area, a file next to the database, which is synced before they
+//! are written in place. A crash can then tear a page in place, but not
+//! its copy in the area as well, and opening the file writes the newest
+//! copy of each page in the area back in place. Unlike full-page writes,
+//! a sync's pages do not reach the file all or none: part of a commit
+//! that crashed may be there, as without either defense, and is for the
+//! consistency check to find.
+//!
+//! The area holds [`DOUBLEWRITE_SLOTS`] pages after a header. Each slot is
+//! a page after its page id, the epoch it was written in and a checksum,
+//! so a slot torn by the crash is passed over. Once the slots run out,
+//! the file is synced, which puts every page in the area in place for
+//! good, and the area starts over in the next epoch, whose header is
+//! synced before any slot of it is written; slots of earlier epochs are
+//! then never replayed over the pages written since.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use super::journal::checksum;
+use super::{Storage, PAGE_SIZE};
+
+/// Pages written to the area at a time, and held back until then.
+pub const DOUBLEWRITE_BATCH: usize = 32;
+
+/// Pages the area holds before it starts over.
+pub const DOUBLEWRITE_SLOTS: usize = 128;
+
+const MAGIC: [u8; 8] = *b"N7DBLWR\0";
+
+const HEADER_SIZE: usize = 24;
+
+const SLOT_SIZE: usize = 24 + PAGE_SIZE;
+
+pub(super) struct Doublewrite {
+    storage: Box<dyn Storage>,
+    epoch: u64,
+    next_slot: usize,
+}
+
+impl Doublewrite {
+    /// The area in `storage`, with the epoch it was left in.
+    pub(super) fn open(mut storage: Box<dyn Storage>) -> io::Result<Self> {
+        let epoch = read_header(&mut *storage)?.unwrap_or(0);
+        Ok(Self {
+            storage,
+            epoch,
+            // What the slots hold is replayed, then the area starts over.
+            next_slot: DOUBLEWRITE_SLOTS,
+        })
+    }
+
+    /// Slots free in this epoch.
+    pub(super) fn room(&self) -> usize {
+        DOUBLEWRITE_SLOTS - self.next_slot
+    }
+
+    /// Starts the next epoch, which the pages in the slots so far must
+    /// be in place for good before.
+    pub(super) fn restart(&mut self, sync: bool) -> io::Result<()> {
+        self.epoch += 1;
+        let mut header = MAGIC.to_vec();
+        header.extend_from_slice(&self.epoch.to_le_bytes());
+        header.extend_from_slice(&checksum(&header).to_le_bytes());
+        self.storage.write_at(0, &header)?;
+        if sync {
+            self.storage.sync()?;
+        }
+        self.next_slot = 0;
+        Ok(())
+    }
+
+    /// Writes `pages` to the next free slots, of which there must be
+    /// enough, and syncs if `sync` is set.
+    pub(super) fn write(&mut self, pages: &BTreeMap<u64, Vec<u8>>, sync: bool) -> io::Result<()> {
+        let mut slots = Vec::with_capacity(pages.len() * SLOT_SIZE);
+        for (page_id, data) in pages {
+            let start = slots.len();
+            slots.extend_from_slice(&page_id.to_le_bytes());
+            slots.extend_from_slice(&self.epoch.to_le_bytes());
+            slots.extend_from_slice(&[0; 8]);
+            slots.extend_from_slice(data);
+            let sum = checksum(&slots[start..]).to_le_bytes();
+            slots[start + 16..start + 24].copy_from_slice(&sum);
+        }
+        let offset = HEADER_SIZE + self.next_slot * SLOT_SIZE;
+        self.storage.write_at(offset as u64, &slots)?;
+        if sync {
+            self.storage.sync()?;
+        }
+        self.next_slot += pages.len();
+        Ok(())
+    }
+
+    /// The newest copy of each page in the slots of the current epoch
+    /// that were written whole.
+    pub(super) fn read(&mut self) -> io::Result<BTreeMap<u64, Vec<u8>>> {
+        let mut pages = BTreeMap::new();
+        if read_header(&mut *self.storage)? != Some(self.epoch) {
+            return Ok(pages);
+        }
+        let size = self.storage.size()? as usize;
+        let slots = (size.saturating_sub(HEADER_SIZE) / SLOT_SIZE).min(DOUBLEWRITE_SLOTS);
+        let mut slot = vec![0; SLOT_SIZE];
+        for i in 0..slots {
+            self.storage
+                .read_at((HEADER_SIZE + i * SLOT_SIZE) as u64, &mut slot)?;
+            let field = |at: usize| u64::from_le_bytes(slot[at..at + 8].try_into().unwrap());
+            let (page_id, epoch, sum) = (field(0), field(8), field(16));
+            slot[16..24].fill(0);
+            if epoch == self.epoch && checksum(&slot) == sum {
+                pages.insert(page_id, slot[24..].to_vec());
+            }
+        }
+        Ok(pages)
+    }
+}
+
+/// The epoch in the header of `storage`, if it holds one whole.
+fn read_header(storage: &mut dyn Storage) -> io::Result<Option<u64>> {
+    if storage.size()? < HEADER_SIZE as u64 {
+        return Ok(None);
+    }
+    let mut header = [0; HEADER_SIZE];
+    storage.read_at(0, &mut header)?;
+    let sum = u64::from_le_bytes(header[16..].try_into().unwrap());
+    if header[..8] != MAGIC || checksum(&header[..16]) != sum {
+        return Ok(None);
+    }
+    Ok(Some(u64::from_le_bytes(header[8..16].try_into().unwrap())))
+}
@@ -0,0 +1,206 @@
+//! Full-page writes, which keep pages from being lost to torn writes.
+//!
+//! A crash while a page is being written can leave it torn: part of it
+//! new and part old, which no longer holds together. With full-page
+//! writes on, a [`DiskManager`](super::DiskManager) puts each page it is
+//! asked to write aside instead, and at the next sync first writes them
+//! all, whole, to a journal next to the file, and syncs that. Only then
+//! does it write them in place and sync the file, after which the journal
+//! is cleared. Opening the file replays a journal that was written whole:
+//! whatever the crash interrupted in place is written again from it, and
+//! a journal cut short by the crash is ignored, the file not having been
+//! touched yet. So the writes of one sync reach the file all together or
+//! not at all, which makes a commit's pages do so too, unless it writes
+//! more than [`MAX_STAGED`] of them.
+//!
+//! The journal is a header followed by the pages, each after its page id.
+//! The header holds a magic number, the number of pages and a checksum of
+//! the pages, which tells a journal written whole from one that was not.
+//!
+//! A journal may [compress](Journal::compress) its pages with LZ4, which
+//! mostly empty or repetitive pages shrink well under, so that less is
+//! written and synced before the pages go in place. Each page then goes
+//! after its page id and its compressed length, and the header has a
+//! magic number of its own; either kind of journal is replayed.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use super::{Storage, PAGE_SIZE};
+
+/// Pages a manager puts aside before it writes them out without waiting
+/// for a sync.
+pub const MAX_STAGED: usize = 1024;
+
+const MAGIC: [u8; 8] = *b"N7FPW\0\0\0";
+
+/// The magic number of a journal of compressed pages.
+const COMPRESSED_MAGIC: [u8; 8] = *b"N7FPWZ\0\0";
+
+const HEADER_SIZE: usize = 24;
+
+const RECORD_SIZE: usize = 8 + PAGE_SIZE;
+
+/// A page id and a length, before each compressed page.
+const COMPRESSED_PREFIX: usize = 12;
+
+/// FNV-1a, which catches the torn and missing writes a crash leaves.
+pub(super) fn checksum(bytes: &[u8]) -> u64 {
+    extend_checksum(0xcbf2_9ce4_8422_2325, bytes)
+}
+
+/// The checksum of what `hash` is the checksum of, followed by `bytes`.
+pub(super) fn extend_checksum(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub(super) struct Journal {
+    storage: Box<dyn Storage>,
+    compress: bool,
+}
+
+impl Journal {
+    pub(super) fn new(storage: Box<dyn Storage>) -> Self {
+        Self {
+            storage,
+            compress: false,
+        }
+    }
+
+    /// Whether the pages written from now on are compressed.
+    pub(super) fn compress(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// Writes `pages`, then the header that vouches for them, and syncs
+    /// if `sync` is set. Returns how many bytes it wrote.
+    pub(super) fn write(&mut self, pages: &BTreeMap<u64, Vec<u8>>, sync: bool) -> io::Result<u64> {
+        let mut records = Vec::with_capacity(pages.len() * RECORD_SIZE);
+        for (page_id, data) in pages {
+            records.extend_from_slice(&page_id.to_le_bytes());
+            if self.compress {
+                let compressed = lz4_flex::compress(data);
+                records.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+                records.extend_from_slice(&compressed);
+            } else {
+                records.extend_from_slice(data);
+            }
+        }
+        self.storage.write_at(HEADER_SIZE as u64, &records)?;
+        let mut header = if self.compress {
+            COMPRESSED_MAGIC
+        } else {
+            MAGIC
+        }
+        .to_vec();
+        header.extend_from_slice(&(pages.len() as u64).to_le_bytes());
+        header.extend_from_slice(&checksum(&records).to_le_bytes());
+        self.storage.write_at(0, &header)?;
+        if sync {
+            self.storage.sync()?;
+        }
+        Ok((HEADER_SIZE + records.len()) as u64)
+    }
+
+    /// Forgets the pages written last. It need not be synced, since
+    /// replaying pages already in place writes them as they are.
+    pub(super) fn clear(&mut self) -> io::Result<()> {
+        self.storage.write_at(0, &[0; HEADER_SIZE])
+    }
+
+    /// The pages written last, by page id, if they were written whole.
+    pub(super) fn read(&mut self) -> io::Result<Vec<(u64, Vec<u8>)>> {
+        let size = self.storage.size()? as usize;
+        if size < HEADER_SIZE {
+            return Ok(vec![]);
+        }
+        let mut header = [0; HEADER_SIZE];
+        self.storage.read_at(0, &mut header)?;
+        let count = u64::from_le_bytes(header[8..16].try_into().unwrap()) as usize;
+        let sum = u64::from_le_bytes(header[16..24].try_into().unwrap());
+        if header[..8] == COMPRESSED_MAGIC {
+            let mut records = vec![0; size - HEADER_SIZE];
+            self.storage.read_at(HEADER_SIZE as u64, &mut records)?;
+            return Ok(read_compressed(&records, count, sum).unwrap_or_default());
+        }
+        let Some(length) = count.checked_mul(RECORD_SIZE) else {
+            return Ok(vec![]);
+        };
+        if header[..8] != MAGIC || size - HEADER_SIZE < length {
+            return Ok(vec![]);
+        }
+        let mut records = vec![0; length];
+        self.storage.read_at(HEADER_SIZE as u64, &mut records)?;
+        if checksum(&records) != sum {
+            return Ok(vec![]);
+        }
+        Ok((records.chunks(RECORD_SIZE))
+            .map(|record| {
+                let page_id = u64::from_le_bytes(record[..8].try_into().unwrap());
+                (page_id, record[8..].to_vec())
+            })
+            .collect())
+    }
+}
+
+/// The `count` compressed pages at the start of `bytes`, if they are all
+/// there and match the checksum `sum`.
+fn read_compressed(bytes: &[u8], count: usize, sum: u64) -> Option<Vec<(u64, Vec<u8>)>> {
+    let mut pages = Vec::new();
+    let mut at = 0;
+    for _ in 0..count {
+        let prefix = bytes.get(at..at + COMPRESSED_PREFIX)?;
+        let page_id = u64::from_le_bytes(prefix[..8].try_into().unwrap());
+        let length = u32::from_le_bytes(prefix[8..].try_into().unwrap()) as usize;
+        let start = at + COMPRESSED_PREFIX;
+        let compressed = bytes.get(start..start.checked_add(length)?)?;
+        pages.push((page_id, compressed));
+        at = start + length;
+    }
+    if checksum(&bytes[..at]) != sum {
+        return None;
+    }
+    (pages.into_iter())
+        .map(|(page_id, compressed)| {
+            let data = lz4_flex::decompress(compressed, PAGE_SIZE).ok()?;
+            (data.len() == PAGE_SIZE).then_some((page_id, data))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compressed() {
+        let pages: BTreeMap<u64, Vec<u8>> = (0..4u8)
+            .map(|i| {
+                let mut data = vec![0; PAGE_SIZE];
+                data[..64].fill(i + 1);
+                (u64::from(i) * 3, data)
+            })
+            .collect();
+        let mut plain = Journal::new(Box::new(Vec::new()));
+        let plain_bytes = plain.write(&pages, false).unwrap();
+        let mut journal = Journal::new(Box::new(Vec::new()));
+        journal.compress(true);
+        let bytes = journal.write(&pages, false).unwrap();
+        assert!(bytes * 10 < plain_bytes, "{bytes} of {plain_bytes}");
+        assert_eq!(
+            pages.clone().into_iter().collect::<Vec<_>>(),
+            journal.read().unwrap()
+        );
+        assert_eq!(plain.read().unwrap(), journal.read().unwrap());
+
+        // One cut short, or with a page changed, was not written whole.
+        let mut image = vec![0; bytes as usize];
+        journal.storage.read_at(0, &mut image).unwrap();
+        let cut = Journal::new(Box::new(image[..image.len() - 1].to_vec())).read();
+        assert!(cut.unwrap().is_empty());
+        image[HEADER_SIZE + COMPRESSED_PREFIX] ^= 1;
+        assert!(Journal::new(Box::new(image)).read().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,174 @@
+//! How pages sit in a database file.
+//!
+//! Files are [checksummed](Layout::Checksummed): each page is followed by
+//! [`CHECKSUM_SIZE`] bytes, a magic number and a checksum of the page and
+//! its id, so that a page torn by a crash, or written to the wrong place,
+//! fails to read rather than being taken for what it says. Files from
+//! before there were checksums have their pages back to back, as do the
+//! images of files kept in memory; they are [plain](Layout::Plain), and
+//! an upgrade rewrites a file of them. A page of zeros, checksum and all,
+//! is one allocated but never written, and reads as such.
+
+use std::io;
+
+use super::journal::{checksum, extend_checksum};
+use super::{PageId, Storage, PAGE_SIZE};
+
+/// Bytes after each page of a checksummed file.
+pub const CHECKSUM_SIZE: usize = 8;
+
+const MAGIC: [u8; 4] = *b"N7PC";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Pages back to back, with nothing to check them against.
+    Plain,
+    /// Each page followed by its checksum.
+    Checksummed,
+}
+
+impl Layout {
+    /// The layout of the file in `storage`: checksummed if it is new or
+    /// its first page is followed by the magic number, else plain.
+    pub fn of(storage: &mut dyn Storage) -> io::Result<Self> {
+        let size = storage.size()?;
+        if size == 0 {
+            return Ok(Self::Checksummed);
+        }
+        if size >= Self::Checksummed.slot_size() as u64 {
+            let mut magic = [0; MAGIC.len()];
+            storage.read_at(PAGE_SIZE as u64, &mut magic)?;
+            if magic == MAGIC {
+                return Ok(Self::Checksummed);
+            }
+        }
+        Ok(Self::Plain)
+    }
+
+    /// Bytes each page takes up in the file.
+    pub fn slot_size(self) -> usize {
+        match self {
+            Self::Plain => PAGE_SIZE,
+            Self::Checksummed => PAGE_SIZE + CHECKSUM_SIZE,
+        }
+    }
+
+    fn offset(self, page_id: PageId) -> u64 {
+        self.slot_size() as u64 * page_id.to_u64()
+    }
+
+    /// Reads the page at `page_id` from `storage` into `data`, failing
+    /// with [`io::ErrorKind::InvalidData`] if it does not match its
+    /// checksum.
+    pub fn read_page(
+        self,
+        storage: &mut dyn Storage,
+        page_id: PageId,
+        data: &mut [u8],
+    ) -> io::Result<()> {
+        match self.read_unverified(storage, page_id, data)? {
+            true => Ok(()),
+            false => Err(mismatch(page_id)),
+        }
+    }
+
+    /// As [`read_page`](Self::read_page), but returns whether the page
+    /// matches its checksum instead of failing if not. Plain pages always
+    /// do.
+    pub fn read_unverified(
+        self,
+        storage: &mut dyn Storage,
+        page_id: PageId,
+        data: &mut [u8],
+    ) -> io::Result<bool> {
+        if self == Self::Plain {
+            storage.read_at(self.offset(page_id), data)?;
+            return Ok(true);
+        }
+        let mut slot = vec![0; self.slot_size()];
+        storage.read_at(self.offset(page_id), &mut slot)?;
+        let (page, trailer) = slot.split_at(PAGE_SIZE);
+        data.copy_from_slice(page);
+        let never_written = slot.iter().all(|&byte| byte == 0);
+        Ok(never_written || *trailer == seal(page_id, page))
+    }
+
+    /// Writes `data` to `storage` as the page at `page_id`.
+    pub fn write_page(
+        self,
+        storage: &mut dyn Storage,
+        page_id: PageId,
+        data: &[u8],
+    ) -> io::Result<()> {
+        if self == Self::Plain {
+            return storage.write_at(self.offset(page_id), data);
+        }
+        storage.write_at(self.offset(page_id), &self.encode(page_id, data))
+    }
+
+    /// What the file holds for `data`, the page at `page_id`, for files
+    /// written a page after another.
+    pub fn encode(self, page_id: PageId, data: &[u8]) -> Vec<u8> {
+        let mut slot = Vec::with_capacity(self.slot_size());
+        slot.extend_from_slice(data);
+        if self == Self::Checksummed {
+            slot.extend_from_slice(&seal(page_id, data));
+        }
+        slot
+    }
+}
+
+/// What follows `data`, the page at `page_id`, in a checksummed file.
+fn seal(page_id: PageId, data: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    let hash = extend_checksum(checksum(&page_id.to_bytes()), data);
+    let sum = (hash ^ (hash >> 32)) as u32;
+    let mut trailer = [0; CHECKSUM_SIZE];
+    trailer[..MAGIC.len()].copy_from_slice(&MAGIC);
+    trailer[MAGIC.len()..].copy_from_slice(&sum.to_le_bytes());
+    trailer
+}
+
+fn mismatch(page_id: PageId) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("page {} does not match its checksum", page_id.to_u64()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        let mut file = vec![];
+        let layout = Layout::of(&mut file).unwrap();
+        assert_eq!(Layout::Checksummed, layout);
+        let (page, other) = ([1; PAGE_SIZE], [2; PAGE_SIZE]);
+        layout.write_page(&mut file, PageId(0), &page).unwrap();
+        layout.write_page(&mut file, PageId(2), &other).unwrap();
+        assert_eq!(Layout::Checksummed, Layout::of(&mut file).unwrap());
+        let mut data = [0; PAGE_SIZE];
+        layout.read_page(&mut file, PageId(2), &mut data).unwrap();
+        assert_eq!(other, data);
+        // The hole between reads as zeros.
+        layout.read_page(&mut file, PageId(1), &mut data).unwrap();
+        assert_eq!([0; PAGE_SIZE], data);
+
+        // A torn page, and one written where another should be.
+        file[100] ^= 1;
+        let e = layout
+            .read_page(&mut file, PageId(0), &mut data)
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, e.kind());
+        let slot = layout.slot_size();
+        file.copy_within(2 * slot..3 * slot, slot);
+        assert!(!layout
+            .read_unverified(&mut file, PageId(1), &mut data)
+            .unwrap());
+        assert_eq!(other, data);
+
+        let mut plain = page.to_vec();
+        assert_eq!(Layout::Plain, Layout::of(&mut plain).unwrap());
+    }
+}
@@ -0,0 +1,186 @@
+//! Scheduling the I/O of a file between queries and background work.
+//!
+//! Pages a query reads are waited for on the spot, while pages written
+//! back in the background, such as by the server's writer, can wait their
+//! turn. An [`IoScheduler`] makes them: each background write is admitted
+//! only once no page has been read for [`READ_QUIET`], or after
+//! [`MAX_DEFER`] at the most so that it is never put off for good, and
+//! then no faster than the rate it was given. Foreground writes, those of
+//! commits, evictions and flushes, are never held back.
+//!
+//! Background writers wait without holding the pool's lock, which lets
+//! the reads they yield to go ahead meanwhile: the scheduler only delays
+//! them, and the pool takes its lock to write once they are admitted.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long reads must have stopped before a background write goes.
+pub const READ_QUIET: Duration = Duration::from_millis(2);
+
+/// How long a background write yields to reads at the most.
+pub const MAX_DEFER: Duration = Duration::from_millis(50);
+
+/// Background pages written a second at the most, unless configured
+/// otherwise.
+pub const DEFAULT_BACKGROUND_WRITE_RATE: u64 = 1000;
+
+/// Whom an I/O is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoPriority {
+    /// A query or commit waiting on it.
+    Foreground,
+    /// Work nobody waits on, which yields to foreground reads.
+    Background,
+}
+
+#[derive(Debug)]
+pub struct IoScheduler {
+    /// Background pages written per second at the most.
+    rate: Option<u64>,
+    start: Instant,
+    /// Foreground reads running now.
+    reading: AtomicUsize,
+    /// When the last foreground read ended, as nanoseconds since `start`.
+    last_read: AtomicU64,
+    /// When the next background write may go, as nanoseconds since
+    /// `start`.
+    next_write: AtomicU64,
+    background_writes: AtomicU64,
+    yielded: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+/// Ends a foreground read as it is dropped.
+pub(super) struct Reading<'a>(&'a IoScheduler);
+
+impl Drop for Reading<'_> {
+    fn drop(&mut self) {
+        self.0.last_read.store(self.0.now(), Ordering::SeqCst);
+        self.0.reading.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Default for IoScheduler {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl IoScheduler {
+    /// A scheduler writing at most `rate` background pages a second, or
+    /// as fast as reads let it without one.
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            rate: rate.filter(|&rate| rate > 0),
+            start: Instant::now(),
+            reading: AtomicUsize::new(0),
+            last_read: AtomicU64::new(0),
+            next_write: AtomicU64::new(0),
+            background_writes: AtomicU64::new(0),
+            yielded: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+        }
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.rate
+    }
+
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    /// Marks a foreground read as running until the guard is dropped.
+    pub(super) fn reading(&self) -> Reading<'_> {
+        self.reading.fetch_add(1, Ordering::SeqCst);
+        Reading(self)
+    }
+
+    /// Whether a foreground read is running or has just ended.
+    fn reads_busy(&self) -> bool {
+        let since = self
+            .now()
+            .saturating_sub(self.last_read.load(Ordering::SeqCst));
+        self.reading.load(Ordering::SeqCst) > 0
+            || (self.last_read.load(Ordering::SeqCst) > 0 && since < READ_QUIET.as_nanos() as u64)
+    }
+
+    /// Waits until a write of `priority` may go: at once for foreground
+    /// ones, and for background ones once reads have quieted down and the
+    /// rate allows.
+    pub fn admit(&self, priority: IoPriority) {
+        if priority == IoPriority::Foreground {
+            return;
+        }
+        let deadline = Instant::now() + MAX_DEFER;
+        let mut yielded = false;
+        while self.reads_busy() && Instant::now() < deadline {
+            yielded = true;
+            thread::sleep(READ_QUIET / 4);
+        }
+        self.yielded
+            .fetch_add(u64::from(yielded), Ordering::Relaxed);
+        if let Some(rate) = self.rate {
+            let interval = 1_000_000_000 / rate;
+            let now = self.now();
+            // Claims the next slot, which may lie ahead of others waiting.
+            let slot = self
+                .next_write
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |next| {
+                    Some(next.max(now) + interval)
+                })
+                .unwrap()
+                .max(now);
+            if slot > now {
+                self.rate_limited.fetch_add(1, Ordering::Relaxed);
+                thread::sleep(Duration::from_nanos(slot - now));
+            }
+        }
+        self.background_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Background writes admitted, those that yielded to reads first, and
+    /// those the rate held back.
+    pub(super) fn counts(&self) -> (u64, u64, u64) {
+        (
+            self.background_writes.load(Ordering::Relaxed),
+            self.yielded.load(Ordering::Relaxed),
+            self.rate_limited.load(Ordering::Relaxed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_background_writes_yield_and_are_rate_limited() {
+        let scheduler = IoScheduler::new(Some(200));
+        let start = Instant::now();
+        for _ in 0..5 {
+            scheduler.admit(IoPriority::Background);
+        }
+        // The first goes at once, and the others a slot of 5ms apart.
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!((5, 0, 4), scheduler.counts());
+
+        let scheduler = IoScheduler::new(None);
+        let reading = scheduler.reading();
+        let start = Instant::now();
+        scheduler.admit(IoPriority::Foreground);
+        assert!(start.elapsed() < MAX_DEFER);
+        // Put off for as long as it may be while a read runs.
+        scheduler.admit(IoPriority::Background);
+        assert!(start.elapsed() >= MAX_DEFER);
+        drop(reading);
+        // Once the read is done, only until reads have quieted down.
+        let start = Instant::now();
+        scheduler.admit(IoPriority::Background);
+        assert!(start.elapsed() >= READ_QUIET / 2);
+        assert!(start.elapsed() < MAX_DEFER);
+        assert_eq!((2, 2, 0), scheduler.counts());
+    }
+}
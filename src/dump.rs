@@ -0,0 +1,340 @@
+//! Logical dumps: a database written out as the SQL that recreates it.
+//!
+//! [`dump`] writes a CREATE TABLE for each table, in order of name, with
+//! its primary key and unique constraints, then its rows as INSERTs of
+//! up to [`ROWS_PER_INSERT`] rows each. Partitions are written as tables
+//! of their own and attached once all the tables are in. Materialized
+//! views follow as their
+//! definitions, each after the views it reads, so restoring one
+//! computes its rows afresh. The other indexes come last, since building them over
+//! the rows is quicker than keeping them up to date while the rows go in.
+//! Triggers follow them, so that restoring the rows fires none, and
+//! then the times to live, so that none of the rows is stamped anew.
+//! Users, their privileges and the policies of tables, which are for
+//! them, are left out.
+//!
+//! Unlike a [backup](crate::backup), the script does not depend on how
+//! pages are laid out, so it carries data to a file of another version or
+//! into a database that already has other tables. Running it is all a
+//! restore takes; see [`Database::execute_script`](crate::database::Database::execute_script).
+
+use std::io::{self, Write};
+
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{Catalog, IndexInfo, TableInfo, TriggerAction, TriggerInfo};
+use crate::collation::Collation;
+use crate::heap;
+use crate::sql::{self, Token};
+use crate::value::Value;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Heap(#[from] heap::Error),
+}
+
+pub const ROWS_PER_INSERT: usize = 100;
+
+/// Writes the script for the tables of `catalog` to `output`.
+pub fn dump(
+    catalog: &Catalog,
+    bufmgr: &BufferPoolManager,
+    mut output: impl Write,
+) -> Result<(), Error> {
+    writeln!(output, "-- Neru7DB dump")?;
+    let mut tables: Vec<_> = catalog.tables().collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    for table in tables.iter().filter(|table| table.view.is_none()) {
+        writeln!(output, "\n{};", create_table(table))?;
+        let mut scan = table.heap.scan(bufmgr)?;
+        let mut rows = vec![];
+        loop {
+            let row = scan.next(bufmgr)?;
+            if let Some((_, row)) = &row {
+                let values: Vec<_> = row.iter().map(Value::to_sql).collect();
+                rows.push(format!("({})", values.join(", ")));
+            }
+            if rows.len() == ROWS_PER_INSERT || (row.is_none() && !rows.is_empty()) {
+                writeln!(
+                    output,
+                    "INSERT INTO {} VALUES\n  {};",
+                    quote(&table.name),
+                    rows.join(",\n  ")
+                )?;
+                rows.clear();
+            }
+            if row.is_none() {
+                break;
+            }
+        }
+    }
+    for table in &tables {
+        if let Some(partition) = &table.partition_of {
+            writeln!(
+                output,
+                "\nALTER TABLE {} ATTACH PARTITION {} FOR VALUES {};",
+                quote(&partition.parent),
+                quote(&table.name),
+                partition.bound
+            )?;
+        }
+    }
+    for table in views(&tables) {
+        let definition = &table.view.as_ref().unwrap().definition;
+        writeln!(
+            output,
+            "\nCREATE MATERIALIZED VIEW {} AS {definition};",
+            quote(&table.name)
+        )?;
+    }
+    for table in &tables {
+        for index in &table.indexes {
+            if table.view.is_some() || constraint(table, index).is_none() {
+                writeln!(output, "\n{};", create_index(table, index))?;
+            }
+        }
+    }
+    for table in &tables {
+        for trigger in &table.triggers {
+            writeln!(output, "\n{};", create_trigger(table, trigger))?;
+        }
+    }
+    for table in &tables {
+        if let Some(ttl) = &table.ttl {
+            let column = quote(&table.schema.columns[ttl.column].name);
+            let duration = ttl.duration.map_or(String::new(), |d| format!(", {d}"));
+            writeln!(
+                output,
+                "\nALTER TABLE {} SET TTL ({column}{duration});",
+                quote(&table.name)
+            )?;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// The materialized views among `tables`, each after those it depends on.
+fn views<'a>(tables: &[&'a TableInfo]) -> Vec<&'a TableInfo> {
+    let mut pending: Vec<_> = tables.iter().filter(|table| table.view.is_some()).collect();
+    let mut views: Vec<&TableInfo> = vec![];
+    while !pending.is_empty() {
+        let ready = |table: &TableInfo| {
+            table
+                .view
+                .as_ref()
+                .unwrap()
+                .dependencies
+                .iter()
+                .all(|name| {
+                    views.iter().any(|view| view.name == *name)
+                        || tables.iter().any(|t| t.name == *name && t.view.is_none())
+                })
+        };
+        // A view only reads relations older than itself, so one is ready.
+        let i = pending.iter().position(|table| ready(table)).unwrap_or(0);
+        views.push(pending.remove(i));
+    }
+    views
+}
+
+fn create_trigger(table: &TableInfo, trigger: &TriggerInfo) -> String {
+    let events: Vec<_> = trigger.events.iter().map(ToString::to_string).collect();
+    let action = match &trigger.action {
+        TriggerAction::Statement {
+            definition,
+            parameters,
+        } => {
+            // The definition parsed once, so it lexes again.
+            let mut tokens = sql::tokenize(definition).unwrap_or_default();
+            tokens.pop();
+            for (token, _) in &mut tokens {
+                if let Token::Parameter(n) = token {
+                    let (image, column) = parameters[*n - 1];
+                    *token = Token::Word {
+                        value: format!("{image}.{}", quote(&table.schema.columns[column].name)),
+                        quoted: false,
+                    };
+                }
+            }
+            sql::render(&tokens)
+        }
+        TriggerAction::Function(function) => format!("EXECUTE FUNCTION {}()", quote(function)),
+    };
+    format!(
+        "CREATE TRIGGER {} {} {} ON {} FOR EACH ROW {action}",
+        quote(&trigger.name),
+        trigger.timing,
+        events.join(" OR "),
+        quote(&table.name)
+    )
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Which constraint of the CREATE TABLE made `index`, if one did: the
+/// binder names them `{table}_pkey` and `{table}_{columns}_key`.
+fn constraint(table: &TableInfo, index: &IndexInfo) -> Option<&'static str> {
+    if !index.unique || index.predicate.is_some() {
+        return None;
+    }
+    let columns = index.columns()?;
+    let names: Vec<_> = columns
+        .iter()
+        .map(|&i| table.schema.columns[i].name.as_str())
+        .collect();
+    let not_null = columns.iter().all(|&i| !table.schema.columns[i].nullable);
+    if index.name == format!("{}_pkey", table.name) && not_null {
+        Some("PRIMARY KEY")
+    } else if index.name == format!("{}_{}_key", table.name, names.join("_")) {
+        Some("UNIQUE")
+    } else {
+        None
+    }
+}
+
+fn create_table(table: &TableInfo) -> String {
+    let mut definitions: Vec<_> = table
+        .schema
+        .columns
+        .iter()
+        .map(|column| {
+            let not_null = if column.nullable { "" } else { " NOT NULL" };
+            let collate = match column.collation {
+                Collation::Binary => String::new(),
+                collation => format!(" COLLATE {collation}"),
+            };
+            format!(
+                "{} {}{not_null}{collate}",
+                quote(&column.name),
+                column.data_type
+            )
+        })
+        .collect();
+    for index in &table.indexes {
+        if let Some(constraint) = constraint(table, index) {
+            let columns: Vec<_> = index
+                .columns()
+                .unwrap()
+                .iter()
+                .map(|&i| quote(&table.schema.columns[i].name))
+                .collect();
+            definitions.push(format!("{constraint} ({})", columns.join(", ")));
+        }
+    }
+    let mut sql = format!(
+        "CREATE TABLE {} (\n  {}\n)",
+        quote(&table.name),
+        definitions.join(",\n  ")
+    );
+    if let Some(partitioning) = &table.partitioning {
+        sql.push_str(&format!(
+            " PARTITION BY {} ({})",
+            partitioning.method,
+            quote(&table.schema.columns[partitioning.column].name)
+        ));
+    }
+    sql
+}
+
+fn create_index(table: &TableInfo, index: &IndexInfo) -> String {
+    let columns: Vec<_> = table
+        .schema
+        .columns
+        .iter()
+        .map(|column| quote(&column.name))
+        .collect();
+    let keys: Vec<_> = index
+        .keys
+        .iter()
+        .map(|key| {
+            let nulls = if key.nulls_first { "" } else { " NULLS LAST" };
+            format!("{}{nulls}", key.expr.display_with(&columns))
+        })
+        .collect();
+    let mut sql = format!(
+        "CREATE {}INDEX {} ON {} ({})",
+        if index.unique { "UNIQUE " } else { "" },
+        quote(&index.name),
+        quote(&table.name),
+        keys.join(", ")
+    );
+    if let Some(predicate) = &index.predicate {
+        sql.push_str(&format!(" WHERE {}", predicate.display_with(&columns)));
+    }
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{Database, Options};
+
+    #[test]
+    fn test_dump_and_restore() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute(
+            "CREATE TABLE \"odd \"\"name\"\"\" (id INT PRIMARY KEY, name TEXT UNIQUE COLLATE nocase, \
+             score FLOAT, ok BOOL NOT NULL, data BYTES)",
+        )
+        .unwrap();
+        db.execute("CREATE TABLE empty (a INT, b INT, UNIQUE (a, b))")
+            .unwrap();
+        db.execute(
+            "INSERT INTO \"odd \"\"name\"\"\" VALUES \
+             (1, 'it''s; -- not a comment', -0.5, true, x'00ff'), \
+             (-9223372036854775807 - 1, NULL, CAST('-Infinity' AS FLOAT), false, NULL), \
+             (3, 'two\nlines', 1e300, true, x'')",
+        )
+        .unwrap();
+        db.execute("CREATE INDEX by_lower ON \"odd \"\"name\"\"\" (lower(name)) WHERE score > 0.0")
+            .unwrap();
+        db.execute("CREATE UNIQUE INDEX by_score ON \"odd \"\"name\"\"\" (score, ok)")
+            .unwrap();
+        db.execute("CREATE MATERIALIZED VIEW v AS SELECT id * 2 AS id FROM empty JOIN \"odd \"\"name\"\"\" ON a = id")
+            .unwrap();
+        db.execute("CREATE MATERIALIZED VIEW a AS SELECT count(*) FROM v")
+            .unwrap();
+        db.execute(
+            "CREATE TRIGGER audit AFTER UPDATE OR DELETE ON \"odd \"\"name\"\"\" \
+             INSERT INTO empty VALUES (old.id, new.\"id\")",
+        )
+        .unwrap();
+        db.execute("CREATE TABLE events (at INT, what TEXT) PARTITION BY RANGE (at)")
+            .unwrap();
+        db.execute(
+            "CREATE TABLE events_old PARTITION OF events FOR VALUES FROM (MINVALUE) TO (10)",
+        )
+        .unwrap();
+        db.execute("INSERT INTO events VALUES (1, 'a')").unwrap();
+        db.execute("ALTER TABLE empty SET TTL (b, 3600)").unwrap();
+        let mut script = vec![];
+        db.dump(&mut script).unwrap();
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("\"name\" TEXT COLLATE nocase,"));
+        assert!(script.contains("PRIMARY KEY (\"id\"),\n  UNIQUE (\"name\")"));
+        assert!(script.contains(
+            "ALTER TABLE \"events\" ATTACH PARTITION \"events_old\" \
+             FOR VALUES FROM (MINVALUE) TO (10);"
+        ));
+        assert!(script.find("VIEW \"v\"").unwrap() < script.find("VIEW \"a\"").unwrap());
+        assert!(script.contains(
+            "CREATE TRIGGER \"audit\" AFTER UPDATE OR DELETE ON \"odd \"\"name\"\"\" \
+             FOR EACH ROW insert into empty values ( old.\"id\" , new.\"id\" );"
+        ));
+        assert!(script.ends_with("ALTER TABLE \"empty\" SET TTL (\"b\", 3600);\n"));
+
+        let mut copy = Database::temporary(Options::default()).unwrap();
+        copy.execute_script(&script).unwrap();
+        let mut again = vec![];
+        copy.dump(&mut again).unwrap();
+        assert_eq!(script, String::from_utf8(again).unwrap());
+        let query = "SELECT * FROM \"odd \"\"name\"\"\" ORDER BY id";
+        assert_eq!(db.query(query).unwrap(), copy.query(query).unwrap());
+        let query = "SELECT * FROM events";
+        assert_eq!(db.query(query).unwrap(), copy.query(query).unwrap());
+    }
+}
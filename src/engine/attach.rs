@@ -0,0 +1,180 @@
+//! Other database files attached to the engine's.
+//!
+//! `ATTACH 'path' AS other` opens the database in another file, starting
+//! a new one if there is none, and its tables can be queried and changed
+//! as `other.t` alongside those of the main database, in the same
+//! statements and transactions. Its pages share the main buffer pool; see
+//! [`BufferPoolManager::attach`](crate::buffer::BufferPoolManager::attach)
+//! and [`AttachedDatabase`](crate::catalog::AttachedDatabase).
+//!
+//! The file is opened as [`Database::open`](crate::database::Database::open)
+//! opens one, with the engine's [attach
+//! options](Engine::set_attach_options): what a crash left in its journal
+//! is replayed, an older format upgraded and a file not shut down cleanly
+//! checked, and it is marked shut down cleanly again once detached or
+//! closed with the main one. A transaction that changes more than one
+//! file is not atomic across them, though: each file's pages are written
+//! and synced in turn as it commits, so a crash in between can leave the
+//! changes in one file and not in another.
+
+use std::path::Path;
+
+use super::{Engine, Error};
+use crate::catalog::Catalog;
+use crate::database::{self, Options};
+use crate::disk::DiskManager;
+
+impl Engine {
+    /// How files are opened for `ATTACH`: with which defense against torn
+    /// pages, and whether one in an older format is upgraded. A
+    /// [`Database`](database::Database) attaches with its own options.
+    pub fn set_attach_options(&mut self, options: Options) {
+        self.attach_options = options;
+    }
+
+    /// Attaches the database in the file at `path` as `name`. A database
+    /// opened read-only attaches others read-only too.
+    pub fn attach(&mut self, path: impl AsRef<Path>, name: &str) -> Result<(), Error> {
+        if self.in_transaction() {
+            return Err(Error::TransactionActive);
+        }
+        let path = path.as_ref();
+        let failed = |source| Error::Attach {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        };
+        let disk = match self.bufmgr.is_read_only() {
+            true => DiskManager::open_read_only(path)?,
+            false => database::open_file(path, &self.attach_options).map_err(failed)?,
+        };
+        let bufmgr = self.bufmgr.attach(disk)?;
+        let is_new = bufmgr.num_pages() == 0;
+        database::check_on_open(&bufmgr).map_err(failed)?;
+        let catalog = Catalog::open(&bufmgr)?;
+        database::mark_open(&bufmgr, is_new).map_err(failed)?;
+        self.catalog.attach(name, path, bufmgr, catalog)?;
+        self.catalog_version += 1;
+        self.plan_cache.clear();
+        Ok(())
+    }
+
+    /// Detaches the database attached as `name`, writing back its pages
+    /// and marking it shut down cleanly first. If they cannot be written,
+    /// it stays attached.
+    pub fn detach(&mut self, name: &str) -> Result<(), Error> {
+        if self.in_transaction() {
+            return Err(Error::TransactionActive);
+        }
+        // Outside a transaction no copy of the catalog shares the file,
+        // so it can be let go of here, where errors are reported, rather
+        // than when the catalog drops it.
+        if let Some(database) = (self.catalog.attached()).find(|database| database.name == name) {
+            database::mark_closed(database.bufmgr()).map_err(|source| Error::Attach {
+                path: database.path.clone(),
+                source: Box::new(source),
+            })?;
+            database.bufmgr().detach()?;
+        }
+        self.catalog.detach(name)?;
+        self.catalog_version += 1;
+        self.plan_cache.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::*;
+    use crate::buffer::BufferPoolManager;
+    use crate::database::Database;
+    use crate::value::Value;
+
+    #[test]
+    fn test_attach_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other.ndb");
+        let mut other = Database::open(&path, Options::default()).unwrap();
+        other
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        other.execute("INSERT INTO t VALUES (1, 'one')").unwrap();
+        other.close().unwrap();
+
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, score INT)")
+            .unwrap();
+        engine.execute("INSERT INTO t VALUES (1, 10)").unwrap();
+        let sql = format!("ATTACH '{}' AS other", path.display());
+        engine.execute(&sql).unwrap();
+        assert!(engine.execute(&sql).is_err());
+        engine
+            .execute("INSERT INTO other.t VALUES (2, 'two')")
+            .unwrap();
+        engine.begin().unwrap();
+        engine
+            .execute("UPDATE other.t SET name = 'uno' WHERE id = 1")
+            .unwrap();
+        engine.execute("DELETE FROM t").unwrap();
+        engine.rollback().unwrap();
+        engine
+            .execute("UPDATE other.t SET name = 'ONE' WHERE id = 1")
+            .unwrap();
+        assert_eq!(
+            vec![vec![
+                Value::Int(1),
+                Value::Text("ONE".into()),
+                Value::Int(10)
+            ]],
+            engine
+                .execute("SELECT t.id, o.name, t.score FROM t JOIN other.t o ON o.id = t.id")
+                .unwrap()
+                .into_rows()
+        );
+        assert!(matches!(
+            engine.execute(&format!("ATTACH '{}' AS again", path.display())),
+            Err(Error::Attach { .. })
+        ));
+        // Attached, the file is not marked shut down cleanly.
+        let open = Database::open_read_only(&path, Options::default()).unwrap();
+        assert!(!open.was_clean());
+        drop(open);
+        engine.execute("DETACH other").unwrap();
+        assert!(engine.execute("SELECT * FROM other.t").is_err());
+
+        let mut other = Database::open(&path, Options::default()).unwrap();
+        assert!(other.was_clean());
+        let rows: Vec<(i64, String)> = other.query_as("SELECT * FROM t ORDER BY id").unwrap();
+        assert_eq!(vec![(1, "ONE".into()), (2, "two".into())], rows);
+    }
+
+    #[test]
+    fn test_attach_upgrades_and_checks() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("other.ndb");
+        // A file as a build from before format versions would leave it,
+        // and not shut down cleanly.
+        {
+            let bufmgr = BufferPoolManager::new(DiskManager::open(&path).unwrap(), 16);
+            let mut other = Engine::open(bufmgr).unwrap();
+            other.execute("CREATE TABLE t (id INT)").unwrap();
+            other.bufmgr().flush().unwrap();
+        }
+        let mut engine = engine();
+        engine.set_attach_options(Options {
+            auto_upgrade: false,
+            ..Options::default()
+        });
+        let sql = format!("ATTACH '{}' AS other", path.display());
+        let e = engine.execute(&sql).unwrap_err();
+        assert!(e.to_string().contains("older than"), "{e}");
+        engine.set_attach_options(Options::default());
+        engine.execute(&sql).unwrap();
+        engine.execute("INSERT INTO other.t VALUES (1)").unwrap();
+        engine.execute("DETACH other").unwrap();
+        let db = Database::open(&path, Options::default()).unwrap();
+        assert!(db.was_clean());
+        assert!(database::upgrade::backup_path(&path, 0).exists());
+    }
+}
@@ -0,0 +1,220 @@
+//! A record of who changed what, kept in a file that is only appended to.
+//!
+//! With an [`AuditLog`] set, each statement that changes rows or the
+//! catalog, from INSERT to CREATE TABLE and GRANT, is added to its file
+//! once it has run, with the user who ran it, the time, the rows it
+//! changed and the transaction it ran in. One that fails as it runs is
+//! recorded with its error and no rows, as is any statement denied for
+//! want of a privilege; queries are not recorded otherwise, nor are
+//! statements that fail to plan, having touched nothing. A statement is
+//! recorded when it runs, so one whose transaction then rolls back is
+//! recorded all the same; the transaction ids tell which statements went
+//! together. Transactions are numbered in the order they begin, each
+//! statement outside one counting as its own, and an engine given a log
+//! numbers on from its last entry.
+//!
+//! Each entry is a line of CSV: the time in milliseconds since the Unix
+//! epoch, the user, empty if none, the transaction, the rows, the
+//! statement and the error, empty if none, quoted where they need to be.
+//! [`read_audit_log`] reads them back, and the entries of logs written
+//! before there was an error field.
+
+use std::fs::{File, OpenOptions};
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::Error;
+use crate::csv;
+
+/// A statement the audit log recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// When the statement ended, in milliseconds since the Unix epoch.
+    pub time: u64,
+    pub user: Option<String>,
+    pub transaction: u64,
+    /// Rows the statement inserted, updated or deleted.
+    pub rows: u64,
+    pub sql: String,
+    /// What the statement failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl AuditEntry {
+    pub(super) fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+/// The file statements are recorded in.
+pub struct AuditLog {
+    writer: csv::Writer<File>,
+    last_transaction: u64,
+    failures: u64,
+}
+
+impl AuditLog {
+    /// Opens the log at `path` for appending, creating it if need be.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let last_transaction = (read_audit_log(path)?.iter())
+            .map(|entry| entry.transaction)
+            .max()
+            .unwrap_or(0);
+        Ok(Self {
+            writer: csv::Writer::new(file, csv::Options::default()),
+            last_transaction,
+            failures: 0,
+        })
+    }
+
+    /// The latest transaction the log has an entry of.
+    pub fn last_transaction(&self) -> u64 {
+        self.last_transaction
+    }
+
+    /// Entries that could not be written. The statements they were for
+    /// had run by then, and stand.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    pub(super) fn record(&mut self, entry: &AuditEntry) {
+        let (time, transaction, rows) = (
+            entry.time.to_string(),
+            entry.transaction.to_string(),
+            entry.rows.to_string(),
+        );
+        let fields = [
+            Some(time.as_str()),
+            entry.user.as_deref(),
+            Some(&transaction),
+            Some(&rows),
+            Some(&entry.sql),
+            entry.error.as_deref(),
+        ];
+        match self.writer.write_record(fields) {
+            Ok(()) => self.last_transaction = self.last_transaction.max(entry.transaction),
+            Err(_) => self.failures += 1,
+        }
+    }
+}
+
+/// The entries of the audit log at `path`, oldest first; none if there
+/// is no file.
+pub fn read_audit_log(path: impl AsRef<Path>) -> Result<Vec<AuditEntry>, Error> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let mut reader = csv::Reader::new(BufReader::new(file), csv::Options::default());
+    let mut entries = vec![];
+    while let Some(record) = reader.read_record()? {
+        let line = record.line;
+        let invalid = |message| Error::InvalidAuditLog { line, message };
+        let mut fields = record.fields;
+        if fields.len() == 5 {
+            fields.push(None);
+        }
+        let [Some(time), user, Some(transaction), Some(rows), Some(sql), error] =
+            <[_; 6]>::try_from(fields).map_err(|_| invalid("expected 6 fields"))?
+        else {
+            return Err(invalid("missing field"));
+        };
+        let number = |field: String| field.parse().map_err(|_| invalid("expected a number"));
+        entries.push(AuditEntry {
+            time: number(time)?,
+            user,
+            transaction: number(transaction)?,
+            rows: number(rows)?,
+            sql,
+            error,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.csv");
+        let mut engine = engine();
+        engine.set_audit_log(Some(AuditLog::open(&path).unwrap()));
+        for sql in [
+            "CREATE TABLE t (id INT, note TEXT)",
+            "CREATE TABLE s (id INT)",
+            "CREATE USER bob",
+            "GRANT ALL ON t TO bob",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        engine.set_user(Some("bob".to_string()));
+        engine
+            .execute("INSERT INTO t VALUES (1, 'a,\nb'), (2, NULL)")
+            .unwrap();
+        engine.execute("SELECT * FROM t").unwrap();
+        assert!(engine.execute("INSERT INTO t VALUES ('x')").is_err());
+        assert!(engine.execute("UPDATE t SET id = id / 0").is_err());
+        assert!(engine.execute("INSERT INTO s VALUES (1)").is_err());
+        engine.execute("BEGIN").unwrap();
+        engine.execute("UPDATE t SET id = id + 1").unwrap();
+        engine.execute("DELETE FROM t WHERE id = 2").unwrap();
+        engine.execute("ROLLBACK").unwrap();
+
+        let entries = read_audit_log(&path).unwrap();
+        let summary: Vec<_> = (entries.iter())
+            .map(|entry| {
+                let user = entry.user.as_deref();
+                let failed = entry.error.is_some();
+                (
+                    user,
+                    entry.transaction,
+                    entry.rows,
+                    failed,
+                    entry.sql.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (None, 1, 0, false, "CREATE TABLE t (id INT, note TEXT)"),
+                (None, 2, 0, false, "CREATE TABLE s (id INT)"),
+                (None, 3, 0, false, "CREATE USER bob"),
+                (None, 4, 0, false, "GRANT ALL ON t TO bob"),
+                (
+                    Some("bob"),
+                    5,
+                    2,
+                    false,
+                    "INSERT INTO t VALUES (1, 'a,\nb'), (2, NULL)"
+                ),
+                (Some("bob"), 6, 0, true, "UPDATE t SET id = id / 0"),
+                (Some("bob"), 7, 0, true, "INSERT INTO s VALUES (1)"),
+                (Some("bob"), 8, 2, false, "UPDATE t SET id = id + 1"),
+                (Some("bob"), 8, 1, false, "DELETE FROM t WHERE id = 2"),
+            ],
+            summary
+        );
+        assert!(entries[6]
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("permission denied"));
+
+        // Another engine numbers its transactions on from the log's.
+        let mut other = super::super::tests::engine();
+        other.set_audit_log(Some(AuditLog::open(&path).unwrap()));
+        other.execute("CREATE TABLE u (id INT)").unwrap();
+        assert_eq!(9, read_audit_log(&path).unwrap()[9].transaction);
+    }
+}
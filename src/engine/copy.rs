@@ -0,0 +1,360 @@
+//! COPY between tables and files.
+//!
+//! COPY FROM reads the file a record at a time, parsing each field as the
+//! type of its column, and inserts the rows in batches of
+//! [`DEFAULT_BATCH_SIZE`] through [`Insert`], which checks them as INSERT
+//! does. A field that does not parse stops it with the line it is on; the
+//! batches before it stay inserted unless a transaction is rolled back.
+//!
+//! In JSON, each line is an object whose members are matched to columns
+//! by name; columns without a member are NULL and members without a
+//! column are ignored. Strings are parsed as the type of their column,
+//! so that byte strings, and the floats JSON has no numbers for, come
+//! back as JSON results write them.
+//!
+//! Parquet is only written, a row group at a time by the writer in
+//! `crate::parquet`, in builds with the `parquet` feature, as by default.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use super::Error;
+use crate::catalog::TableInfo;
+use crate::csv;
+use crate::executor::{ExecContext, Insert, Plan, DEFAULT_BATCH_SIZE};
+use crate::json::{self, Json};
+#[cfg(feature = "parquet")]
+use crate::parquet;
+use crate::planner::{CopyFormat, Field};
+use crate::value::{DataType, Tuple, Value};
+
+/// Inserts the records of `file` into `table` and returns how many.
+pub(super) fn copy_from(
+    ctx: &ExecContext<'_>,
+    table: &str,
+    columns: &[usize],
+    file: &str,
+    format: &CopyFormat,
+) -> Result<u64, Error> {
+    let info = ctx.table(table)?;
+    let input = BufReader::new(File::open(file)?);
+    let mut count = 0;
+    let mut rows = vec![];
+    let mut push = |row| {
+        rows.push(row);
+        if rows.len() == DEFAULT_BATCH_SIZE {
+            count += insert(ctx, table, std::mem::take(&mut rows))?;
+        }
+        Ok::<_, Error>(())
+    };
+    match format {
+        CopyFormat::Csv(options) => {
+            let mut reader = csv::Reader::new(input, options.clone());
+            if options.header {
+                reader.read_record()?;
+            }
+            while let Some(record) = reader.read_record()? {
+                push(csv_row(info, columns, record)?)?;
+            }
+        }
+        CopyFormat::Json => {
+            for (i, text) in input.lines().enumerate() {
+                let text = text?;
+                if !text.trim().is_empty() {
+                    push(json_row(info, columns, i as u64 + 1, &text)?)?;
+                }
+            }
+        }
+        CopyFormat::Parquet => unreachable!("the binder rejects COPY FROM in Parquet"),
+    }
+    count += insert(ctx, table, rows)?;
+    Ok(count)
+}
+
+/// Lays the fields of `record` out as a row of `table`, with NULL for
+/// the columns not copied.
+fn csv_row(table: &TableInfo, columns: &[usize], record: csv::Record) -> Result<Tuple, Error> {
+    let line = record.line;
+    if record.fields.len() != columns.len() {
+        return Err(Error::CopyData {
+            line,
+            message: format!(
+                "expected {} fields, got {}",
+                columns.len(),
+                record.fields.len()
+            ),
+        });
+    }
+    let mut row = vec![Value::Null; table.schema.columns.len()];
+    for (&i, field) in columns.iter().zip(record.fields) {
+        let Some(text) = field else {
+            continue;
+        };
+        let column = &table.schema.columns[i];
+        row[i] = Value::parse(&text, column.data_type).ok_or_else(|| Error::CopyData {
+            line,
+            message: format!(
+                "invalid input for type {} in column {:?}: {text:?}",
+                column.data_type, column.name
+            ),
+        })?;
+    }
+    Ok(row)
+}
+
+fn json_row(table: &TableInfo, columns: &[usize], line: u64, text: &str) -> Result<Tuple, Error> {
+    let error = |message| Error::CopyData { line, message };
+    let object = json::parse(text).map_err(error)?;
+    if !matches!(object, Json::Object(_)) {
+        return Err(error("expected an object".to_string()));
+    }
+    let mut row = vec![Value::Null; table.schema.columns.len()];
+    for &i in columns {
+        let column = &table.schema.columns[i];
+        let value = match object.get(&column.name) {
+            None | Some(Json::Null) => continue,
+            Some(Json::Bool(b)) => Value::Bool(*b),
+            Some(Json::Int(i)) => Value::Int(*i),
+            Some(Json::Float(x)) => Value::Float(*x),
+            Some(Json::String(s)) => {
+                Value::parse(s, column.data_type).unwrap_or_else(|| s.as_str().into())
+            }
+            Some(Json::Array(_) | Json::Object(_)) => {
+                return Err(error(format!(
+                    "column {:?} cannot hold an array or object",
+                    column.name
+                )))
+            }
+        };
+        let actual = value.data_type().unwrap();
+        row[i] = value.coerce_to(column.data_type).ok_or_else(|| {
+            error(format!(
+                "column {:?} is of type {} but the value is {actual}",
+                column.name, column.data_type
+            ))
+        })?;
+    }
+    Ok(row)
+}
+
+/// Columns for a table to copy the JSON lines in `file` into: one for
+/// each member name, in the order they first appear, of the type all its
+/// values share. Numbers mixing integers and floats are FLOAT, other
+/// mixtures and columns with nothing but nulls TEXT.
+pub fn infer_json_columns(file: impl AsRef<Path>) -> Result<Vec<(String, DataType)>, Error> {
+    let mut columns: Vec<(String, Option<DataType>)> = vec![];
+    for (i, text) in BufReader::new(File::open(file)?).lines().enumerate() {
+        let text = text?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let error = |message| Error::CopyData {
+            line: i as u64 + 1,
+            message,
+        };
+        let Json::Object(members) = json::parse(&text).map_err(error)? else {
+            return Err(error("expected an object".to_string()));
+        };
+        for (name, value) in members {
+            let data_type = match value {
+                Json::Null => None,
+                Json::Bool(_) => Some(DataType::Bool),
+                Json::Int(_) => Some(DataType::Int),
+                Json::Float(_) => Some(DataType::Float),
+                Json::String(_) => Some(DataType::Text),
+                Json::Array(_) | Json::Object(_) => {
+                    return Err(error(format!("member {name:?} is an array or object")))
+                }
+            };
+            let Some((_, seen)) = columns.iter_mut().find(|(seen, _)| *seen == name) else {
+                columns.push((name, data_type));
+                continue;
+            };
+            *seen = match (*seen, data_type) {
+                (seen, None) => seen,
+                (None, data_type) => data_type,
+                (Some(a), Some(b)) if a == b => Some(a),
+                (Some(DataType::Int | DataType::Float), Some(DataType::Int | DataType::Float)) => {
+                    Some(DataType::Float)
+                }
+                _ => Some(DataType::Text),
+            };
+        }
+    }
+    Ok(columns
+        .into_iter()
+        .map(|(name, data_type)| (name, data_type.unwrap_or(DataType::Text)))
+        .collect())
+}
+
+fn insert(ctx: &ExecContext<'_>, table: &str, rows: Vec<Tuple>) -> Result<u64, Error> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+    ctx.check_interrupt()?;
+    let insert = Insert {
+        table: table.to_string(),
+        source: Plan::values(rows),
+        on_conflict: None,
+    };
+    Ok(insert.execute(ctx)?)
+}
+
+/// Writes the rows of `plan` to `file`, replacing it, and returns how
+/// many.
+pub(super) fn copy_to(
+    ctx: &ExecContext<'_>,
+    fields: &[Field],
+    plan: &Plan,
+    file: &str,
+    format: &CopyFormat,
+) -> Result<u64, Error> {
+    let mut output = BufWriter::new(File::create(file)?);
+    let mut count = 0;
+    match format {
+        CopyFormat::Csv(options) => {
+            let mut writer = csv::Writer::new(output, options.clone());
+            if options.header {
+                writer.write_record(fields.iter().map(|field| Some(&field.name)))?;
+            }
+            for row in plan.cursor(ctx)? {
+                let row = row?;
+                let fields = row
+                    .iter()
+                    .map(|value| (!value.is_null()).then(|| value.to_string()));
+                writer.write_record(fields)?;
+                count += 1;
+            }
+            output = writer.into_inner()?;
+        }
+        CopyFormat::Json => {
+            for row in plan.cursor(ctx)? {
+                let mut line = String::from("{");
+                for (i, (field, value)) in fields.iter().zip(&row?).enumerate() {
+                    if i > 0 {
+                        line.push_str(", ");
+                    }
+                    json::write_string(&mut line, &field.name);
+                    line.push_str(": ");
+                    json::write_value(&mut line, value);
+                }
+                line.push_str("}\n");
+                output.write_all(line.as_bytes())?;
+                count += 1;
+            }
+        }
+        #[cfg(feature = "parquet")]
+        CopyFormat::Parquet => {
+            let mut writer = parquet::Writer::new(output, fields)?;
+            for row in plan.cursor(ctx)? {
+                writer.write_row(&row?)?;
+                count += 1;
+            }
+            output = writer.finish()?;
+        }
+        #[cfg(not(feature = "parquet"))]
+        CopyFormat::Parquet => unreachable!("the binder rejects Parquet without the feature"),
+    }
+    output.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::super::Output;
+
+    #[test]
+    fn test_copy_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("t.ndjson");
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT, score FLOAT, data BYTES)")
+            .unwrap();
+        std::fs::write(
+            &file,
+            "{\"id\": 1, \"score\": 2, \"data\": \"\\\\xff\", \"extra\": [1]}\n\n\
+             {\"id\": 2, \"score\": \"Infinity\"}\n",
+        )
+        .unwrap();
+        let file = file.display();
+        engine
+            .execute(&format!("COPY t FROM '{file}' WITH (FORMAT json)"))
+            .unwrap();
+        engine
+            .execute(&format!("COPY t TO '{file}' WITH (FORMAT json)"))
+            .unwrap();
+        assert_eq!(
+            "{\"id\": 1, \"score\": 2.0, \"data\": \"\\\\xff\"}\n\
+             {\"id\": 2, \"score\": \"Infinity\", \"data\": null}\n",
+            std::fs::read_to_string(dir.path().join("t.ndjson")).unwrap()
+        );
+
+        if cfg!(feature = "parquet") {
+            engine
+                .execute(&format!("COPY t TO '{file}' WITH (FORMAT parquet)"))
+                .unwrap();
+            let parquet = std::fs::read(dir.path().join("t.ndjson")).unwrap();
+            assert!(parquet.starts_with(b"PAR1") && parquet.ends_with(b"PAR1"));
+        }
+        assert!(engine
+            .execute(&format!("COPY t FROM '{file}' WITH (FORMAT parquet)"))
+            .is_err());
+
+        std::fs::write(dir.path().join("t.ndjson"), "{\"id\": \"x\"}\n").unwrap();
+        let error = engine
+            .execute(&format!("COPY t FROM '{file}' WITH (FORMAT json)"))
+            .unwrap_err();
+        assert_eq!(
+            "line 1: column \"id\" is of type INT but the value is TEXT",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("t.csv");
+        let file = file.display();
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT, ok BOOL)")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (1, 'a, b', true), (2, NULL, false), (3, '', NULL)")
+            .unwrap();
+        assert_eq!(
+            Output::Affected(3),
+            engine
+                .execute(&format!("COPY t TO '{file}' WITH (HEADER)"))
+                .unwrap()
+        );
+        assert_eq!(
+            "id,name,ok\n1,\"a, b\",true\n2,,false\n3,\"\",\n",
+            std::fs::read_to_string(dir.path().join("t.csv")).unwrap()
+        );
+
+        engine
+            .execute("CREATE TABLE u (id INT, name TEXT, ok BOOL)")
+            .unwrap();
+        engine
+            .execute(&format!(
+                "COPY u FROM '{file}' WITH (FORMAT csv, HEADER true)"
+            ))
+            .unwrap();
+        let copied = engine.execute("SELECT * FROM u ORDER BY id").unwrap();
+        let original = engine.execute("SELECT * FROM t ORDER BY id").unwrap();
+        assert_eq!(original, copied);
+
+        std::fs::write(dir.path().join("t.csv"), "7\n8\nx\n").unwrap();
+        let error = engine
+            .execute(&format!("COPY u (id) FROM '{file}'"))
+            .unwrap_err();
+        assert_eq!(
+            "line 3: invalid input for type INT in column \"id\": \"x\"",
+            error.to_string()
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! Building indexes while their tables stay writable.
+//!
+//! `CREATE INDEX CONCURRENTLY` starts a build and returns at once. The
+//! build takes the list of the table's pages as they were then, and
+//! [`Engine::build_indexes`] fills the new index from them a batch of
+//! pages at a time, between the statements of other sessions. Until it
+//! is done the index is [pending](catalog::PendingIndex) in the catalog:
+//! the planner does not know of it and writes do not maintain it. They
+//! record the rows they store and remove in the table instead, and once
+//! the pages are scanned the build catches up on them: it removes the
+//! entries of every row recorded and adds those of the rows now at their
+//! rids, then moves the index to its table's.
+//!
+//! A build ends without an index if its table is dropped, which drops
+//! the pending index too, or if the rows turn out not to be unique, and
+//! then the pending index stays with the error for DROP INDEX to clean
+//! up. A build that a close cuts short starts again, from an empty
+//! index, when the database is next opened.
+
+use std::collections::HashMap;
+
+use super::{Engine, Error};
+use crate::btree::{self, BTree};
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{self, IndexInfo};
+use crate::disk::PageId;
+use crate::heap::{HeapFile, Rid};
+use crate::planner::IndexDef;
+use crate::value::{Tuple, Value};
+
+/// An index being filled from its table.
+pub(super) struct IndexBuild {
+    pub table: String,
+    heap: HeapFile,
+    pub index: IndexInfo,
+    /// The table's pages when the build began, and how many are scanned.
+    pub pages: Vec<PageId>,
+    pub scanned: usize,
+    /// The rows stored or removed at each rid since the build began.
+    changed: HashMap<Rid, Vec<Tuple>>,
+}
+
+impl IndexBuild {
+    fn scan_page(&mut self, bufmgr: &BufferPoolManager) -> Result<(), catalog::Error> {
+        let page_id = self.pages[self.scanned];
+        for (rid, tuple) in self.heap.page_tuples(bufmgr, page_id)? {
+            // Changed rows are caught up on at the end.
+            if !self.changed.contains_key(&rid) {
+                self.add_entry(bufmgr, &tuple, rid)?;
+            }
+        }
+        self.scanned += 1;
+        Ok(())
+    }
+
+    /// Brings the index up to date with the rows changed since the build
+    /// began. Their entries go first, so that a row moved or changed
+    /// does not collide with an entry it had itself.
+    fn catch_up(&self, bufmgr: &BufferPoolManager) -> Result<(), catalog::Error> {
+        for (&rid, rows) in &self.changed {
+            for row in rows {
+                if self.index.lookup(bufmgr, row)?.contains(&rid) {
+                    self.index.delete_entry(bufmgr, row, rid)?;
+                }
+            }
+        }
+        for &rid in self.changed.keys() {
+            if let Some(tuple) = self.heap.get(bufmgr, rid)? {
+                self.add_entry(bufmgr, &tuple, rid)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the entry of `tuple` at `rid` unless the index has it
+    /// already. An entry of a changed row, which may be one it no longer
+    /// has, gives way: the row gets its entry back when caught up on if
+    /// it is still there.
+    fn add_entry(
+        &self,
+        bufmgr: &BufferPoolManager,
+        tuple: &[Value],
+        rid: Rid,
+    ) -> Result<(), catalog::Error> {
+        match self.index.insert_entry(bufmgr, tuple, rid) {
+            Err(catalog::Error::BTree(btree::Error::DuplicateKey)) => {
+                let rids = self.index.lookup(bufmgr, tuple)?;
+                if rids.contains(&rid) {
+                    return Ok(());
+                }
+                if !rids.iter().all(|rid| self.changed.contains_key(rid)) {
+                    return Err(catalog::Error::DuplicateKey(self.index.name.clone()));
+                }
+                // Only unique entries collide, and they are keyed by the
+                // values alone.
+                self.index.delete_entry(bufmgr, tuple, rid)?;
+                self.index.insert_entry(bufmgr, tuple, rid)
+            }
+            result => result,
+        }
+    }
+}
+
+impl Engine {
+    /// Starts again the builds of the pending indexes that have not
+    /// failed, emptying what a close left of them.
+    pub(super) fn resume_index_builds(&mut self) -> Result<(), Error> {
+        if self.bufmgr.is_read_only() {
+            return Ok(());
+        }
+        let pending = self.catalog.pending_indexes().iter();
+        for pending in pending.filter(|pending| pending.error.is_none()) {
+            let heap = self.catalog.table(&pending.table).unwrap().heap;
+            let index = pending.index.clone();
+            index
+                .btree
+                .truncate(&self.bufmgr)
+                .map_err(catalog::Error::from)?;
+            self.index_builds.push(IndexBuild {
+                table: pending.table.clone(),
+                heap,
+                index,
+                pages: (heap.page_ids(&self.bufmgr)).map_err(catalog::Error::from)?,
+                scanned: 0,
+                changed: HashMap::new(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Starts building `index` for [`Engine::build_indexes`] to finish.
+    pub(super) fn start_index_build(
+        &mut self,
+        index: IndexDef,
+        if_not_exists: bool,
+    ) -> Result<(), Error> {
+        if self.in_transaction() {
+            return Err(Error::ConcurrentIndexInTransaction);
+        }
+        let exists = self.catalog.index_table(&index.name).is_some()
+            || self.catalog.pending_index(&index.name).is_some();
+        if exists {
+            return match if_not_exists {
+                true => Ok(()),
+                false => Err(catalog::Error::IndexExists(index.name).into()),
+            };
+        }
+        let table = self
+            .catalog
+            .table(&index.table)
+            .ok_or_else(|| catalog::Error::TableNotFound(index.table.clone()))?;
+        if table.partitioning.is_some() {
+            return Err(catalog::Error::Partitioned(index.table).into());
+        }
+        let pages = (table.heap.page_ids(&self.bufmgr)).map_err(catalog::Error::from)?;
+        let heap = table.heap;
+        let info = IndexInfo {
+            name: index.name,
+            keys: index.keys,
+            predicate: index.predicate,
+            unique: index.unique,
+            btree: BTree::create(&self.bufmgr).map_err(catalog::Error::from)?,
+        };
+        self.catalog
+            .add_pending_index(&self.bufmgr, &index.table, info.clone())?;
+        self.catalog_version += 1;
+        self.index_builds.push(IndexBuild {
+            table: index.table,
+            heap,
+            index: info,
+            pages,
+            scanned: 0,
+            changed: HashMap::new(),
+        });
+        Ok(())
+    }
+
+    /// Notes rows that statements stored in or removed from tables with
+    /// an index being built, once they are committed.
+    pub(super) fn record_rows(&mut self, rows: Vec<(String, Rid, Tuple)>) {
+        if self.in_transaction() {
+            self.pending_rows.extend(rows);
+            return;
+        }
+        for (table, rid, row) in rows {
+            for build in &mut self.index_builds {
+                if build.table == table {
+                    build.changed.entry(rid).or_default().push(row.clone());
+                }
+            }
+        }
+    }
+
+    /// Scans up to `limit` pages for the indexes being built, oldest build
+    /// first, finishing each build whose pages are all scanned, and
+    /// returns how many pages it scanned; fewer than `limit` means every
+    /// build is finished. Nothing is built in a transaction or in a
+    /// database opened read-only. A build that fails is abandoned, its
+    /// index left pending with the error, and the call returns it.
+    pub fn build_indexes(&mut self, limit: usize) -> Result<u64, Error> {
+        if self.in_transaction() || self.bufmgr.is_read_only() {
+            return Ok(0);
+        }
+        let mut scanned = 0;
+        while !self.index_builds.is_empty() {
+            let mut build = self.index_builds.remove(0);
+            let pending = self.catalog.pending_index(&build.index.name);
+            if !pending.is_some_and(|pending| pending.index.btree == build.index.btree) {
+                continue;
+            }
+            let mut result = Ok(());
+            while result.is_ok() && scanned < limit && build.scanned < build.pages.len() {
+                result = build.scan_page(&self.bufmgr);
+                scanned += 1;
+            }
+            if result.is_ok() && build.scanned < build.pages.len() {
+                self.index_builds.insert(0, build);
+                break;
+            }
+            if let Err(e) = result.and_then(|()| build.catch_up(&self.bufmgr)) {
+                let error = e.to_string();
+                (self.catalog).fail_pending_index(&self.bufmgr, &build.index.name, error)?;
+                return Err(e.into());
+            }
+            (self.catalog).finish_pending_index(&self.bufmgr, &build.index.name)?;
+            self.catalog_version += 1;
+            self.plan_cache.clear();
+        }
+        Ok(scanned as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::super::{Engine, Error};
+    use crate::btree::SearchMode;
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog;
+    use crate::disk::DiskManager;
+    use crate::value::Value;
+
+    #[test]
+    fn test_build_concurrently() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        for i in 0..300 {
+            let sql = format!("INSERT INTO t VALUES ({i}, 'name{i}')");
+            engine.execute(&sql).unwrap();
+        }
+        engine
+            .execute("CREATE UNIQUE INDEX CONCURRENTLY t_name ON t (name)")
+            .unwrap();
+        assert!(engine
+            .catalog()
+            .table("t")
+            .unwrap()
+            .index("t_name")
+            .is_none());
+        assert!(engine
+            .execute("CREATE INDEX CONCURRENTLY t_name ON t (id)")
+            .is_err());
+        assert_eq!(1, engine.build_indexes(1).unwrap());
+        assert!(engine
+            .catalog()
+            .table("t")
+            .unwrap()
+            .index("t_name")
+            .is_none());
+
+        // Changes to scanned pages, pages still to scan and new pages.
+        engine
+            .execute("UPDATE t SET name = 'first' WHERE id = 0")
+            .unwrap();
+        engine.execute("DELETE FROM t WHERE id = 299").unwrap();
+        engine
+            .execute("UPDATE t SET name = 'name299' WHERE id = 298")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (300, 'name0')")
+            .unwrap();
+        engine.begin().unwrap();
+        engine.execute("INSERT INTO t VALUES (301, 'x')").unwrap();
+        engine.rollback().unwrap();
+        assert!(engine.build_indexes(1000).unwrap() < 1000);
+
+        let index = engine
+            .catalog()
+            .table("t")
+            .unwrap()
+            .index("t_name")
+            .cloned();
+        let index = index.unwrap();
+        let table = engine.catalog().table("t").unwrap().clone();
+        let mut scan = table.heap.scan(engine.bufmgr()).unwrap();
+        let mut rows = 0;
+        while let Some((rid, tuple)) = scan.next(engine.bufmgr()).unwrap() {
+            assert_eq!(vec![rid], index.lookup(engine.bufmgr(), &tuple).unwrap());
+            rows += 1;
+        }
+        assert_eq!(300, rows);
+        let mut entries = index
+            .btree
+            .search(engine.bufmgr(), SearchMode::Start)
+            .unwrap();
+        let mut count = 0;
+        while entries.next(engine.bufmgr()).unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(300, count);
+        let plan = engine
+            .execute("EXPLAIN SELECT id FROM t WHERE name = 'name299'")
+            .unwrap()
+            .into_rows();
+        assert!(format!("{plan:?}").contains("t_name"));
+        assert_eq!(
+            vec![vec![Value::Int(298)]],
+            engine
+                .execute("SELECT id FROM t WHERE name = 'name299'")
+                .unwrap()
+                .into_rows()
+        );
+
+        // Rows that are not unique by the time the build finishes.
+        engine
+            .execute("CREATE UNIQUE INDEX CONCURRENTLY t_dup ON t ((lower(name)))")
+            .unwrap();
+        engine
+            .execute("UPDATE t SET name = 'NAME1' WHERE id = 2")
+            .unwrap();
+        assert!(matches!(
+            engine.build_indexes(1000),
+            Err(Error::Catalog(catalog::Error::DuplicateKey(_)))
+        ));
+        assert_eq!(0, engine.build_indexes(1000).unwrap());
+        assert!(engine
+            .catalog()
+            .table("t")
+            .unwrap()
+            .index("t_dup")
+            .is_none());
+        let pending = engine
+            .execute("SELECT valid, build_error FROM neru_stat_indexes WHERE name = 't_dup'")
+            .unwrap()
+            .into_rows();
+        assert_eq!(Value::Bool(false), pending[0][0]);
+        assert!(matches!(&pending[0][1], Value::Text(e) if e.contains("t_dup")));
+        assert!(engine
+            .execute("CREATE INDEX CONCURRENTLY t_dup ON t (id)")
+            .is_err());
+        engine.execute("DROP INDEX t_dup").unwrap();
+        assert!(engine.catalog().pending_indexes().is_empty());
+
+        engine.begin().unwrap();
+        assert!(matches!(
+            engine.execute("CREATE INDEX CONCURRENTLY t_id ON t (id)"),
+            Err(Error::ConcurrentIndexInTransaction)
+        ));
+    }
+
+    #[test]
+    fn test_resume_build() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        engine
+            .execute("CREATE TABLE t (id INT, name TEXT)")
+            .unwrap();
+        for i in 0..300 {
+            let sql = format!("INSERT INTO t VALUES ({i}, 'name{i}')");
+            engine.execute(&sql).unwrap();
+        }
+        engine
+            .execute("CREATE INDEX CONCURRENTLY t_name ON t (name)")
+            .unwrap();
+        assert_eq!(1, engine.build_indexes(1).unwrap());
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        // The build is cut short, and starts again on open.
+        let mut engine = open();
+        assert!(engine.catalog().pending_index("t_name").is_some());
+        engine.execute("DELETE FROM t WHERE id < 100").unwrap();
+        assert!(engine.build_indexes(1000).unwrap() < 1000);
+        assert!(engine.catalog().pending_indexes().is_empty());
+        let index = engine.catalog().table("t").unwrap().index("t_name");
+        let index = index.unwrap().clone();
+        let entries = index.lookup(engine.bufmgr(), &[Value::Null, "name7".into()]);
+        assert!(entries.unwrap().is_empty());
+        let entries = index.lookup(engine.bufmgr(), &[Value::Null, "name107".into()]);
+        assert_eq!(1, entries.unwrap().len());
+    }
+}
@@ -0,0 +1,295 @@
+//! Maintenance run while the database is idle.
+//!
+//! Some upkeep is best done when nothing else is running: unlinking the
+//! pages of a table whose rows have all been deleted and freeing them for
+//! reuse, and dropping the past versions of pages that fell out of the
+//! history's retention window, which otherwise wait for the next
+//! statement to mark a point ("vacuum"); gathering the statistics of
+//! tables that have none, as none are kept across opens, or whose size
+//! has drifted since they were gathered ("analyze"); and writing every
+//! dirty page back and syncing the file ("checkpoint"), so that the next
+//! commit or close finds little left to write. Rows are deleted in
+//! place, leaving no dead rows behind, and inserts fill the room deletes
+//! leave before growing a table, so a vacuum has only the emptied pages
+//! to reclaim; those of dropped tables are freed as they are dropped.
+//!
+//! [`Engine::run_maintenance`] does one bounded step of whichever task is
+//! due, and is meant to be called every so often, as the server does.
+//! The heuristic is simple: a task is due once no statement has run for
+//! the engine's [idle time](Engine::set_maintenance_idle) and it has
+//! something to do. Through a [`Maintenance`] handle, another thread can
+//! pause a task, which then only runs when triggered, or trigger one,
+//! which runs at its next turn however busy the engine is.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::{Engine, Error};
+use crate::catalog;
+
+/// How long no statement must have run for before maintenance does,
+/// unless configured otherwise.
+pub const DEFAULT_MAINTENANCE_IDLE: Duration = Duration::from_secs(1);
+
+/// Drift in a table's pages since its statistics were gathered, as a
+/// fraction of them, past which they are gathered again.
+const ANALYZE_DRIFT: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaintenanceTask {
+    Vacuum,
+    Analyze,
+    Checkpoint,
+}
+
+impl MaintenanceTask {
+    /// Every task, in the order their turns come.
+    pub const ALL: [MaintenanceTask; 3] = [
+        MaintenanceTask::Vacuum,
+        MaintenanceTask::Analyze,
+        MaintenanceTask::Checkpoint,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MaintenanceTask::Vacuum => "vacuum",
+            MaintenanceTask::Analyze => "analyze",
+            MaintenanceTask::Checkpoint => "checkpoint",
+        }
+    }
+}
+
+/// Times each task has run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MaintenanceStats {
+    pub vacuums: u64,
+    /// Tables analyzed.
+    pub analyzes: u64,
+    pub checkpoints: u64,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    paused: [AtomicBool; 3],
+    triggered: [AtomicBool; 3],
+    runs: [AtomicU64; 3],
+}
+
+/// Pauses and triggers the maintenance of an engine from anywhere.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    shared: Arc<Shared>,
+}
+
+impl Maintenance {
+    /// Keeps `task` from running while idle until resumed.
+    pub fn pause(&self, task: MaintenanceTask) {
+        self.shared.paused[task as usize].store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self, task: MaintenanceTask) {
+        self.shared.paused[task as usize].store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self, task: MaintenanceTask) -> bool {
+        self.shared.paused[task as usize].load(Ordering::SeqCst)
+    }
+
+    /// Runs `task` at its next turn, even if paused or the engine is busy.
+    /// A triggered analyze gathers the statistics of every table.
+    pub fn trigger(&self, task: MaintenanceTask) {
+        self.shared.triggered[task as usize].store(true, Ordering::SeqCst);
+    }
+
+    pub fn stats(&self) -> MaintenanceStats {
+        let runs = |task: MaintenanceTask| self.shared.runs[task as usize].load(Ordering::Relaxed);
+        MaintenanceStats {
+            vacuums: runs(MaintenanceTask::Vacuum),
+            analyzes: runs(MaintenanceTask::Analyze),
+            checkpoints: runs(MaintenanceTask::Checkpoint),
+        }
+    }
+
+    /// Whether `task` was triggered, forgetting that it was.
+    fn take_trigger(&self, task: MaintenanceTask) -> bool {
+        self.shared.triggered[task as usize].swap(false, Ordering::SeqCst)
+    }
+
+    fn count(&self, task: MaintenanceTask) {
+        self.shared.runs[task as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Engine {
+    /// A handle that pauses and triggers this engine's maintenance.
+    pub fn maintenance(&self) -> Maintenance {
+        self.maintenance.clone()
+    }
+
+    /// Lets maintenance run once no statement has run for `idle`.
+    pub fn set_maintenance_idle(&mut self, idle: Duration) {
+        self.maintenance_idle = idle;
+    }
+
+    /// Does one step of the first task due, if any: the vacuum or the
+    /// analyze of one table, or a checkpoint. Returns the task it ran. Nothing
+    /// runs in a transaction or in a database opened read-only.
+    pub fn run_maintenance(&mut self) -> Result<Option<MaintenanceTask>, Error> {
+        if self.in_transaction() || self.bufmgr.is_read_only() {
+            return Ok(None);
+        }
+        let idle = self.last_activity.elapsed() >= self.maintenance_idle;
+        for task in MaintenanceTask::ALL {
+            let triggered = self.maintenance.take_trigger(task);
+            let due = idle && !self.maintenance.is_paused(task);
+            if task == MaintenanceTask::Analyze && triggered {
+                self.analyze_queue = self.catalog.tables().map(|t| t.name.clone()).collect();
+            }
+            // A triggered analyze goes on a table at a time, however busy.
+            let queued = task == MaintenanceTask::Analyze && !self.analyze_queue.is_empty();
+            if !(triggered || queued || due) {
+                continue;
+            }
+            let ran = match task {
+                MaintenanceTask::Vacuum => {
+                    let pruned = self.bufmgr.prune_history() > 0;
+                    self.vacuum_next()? || pruned
+                }
+                MaintenanceTask::Analyze => self.analyze_next(due)?,
+                MaintenanceTask::Checkpoint => {
+                    let dirty = self.bufmgr.dirty_pages() > 0;
+                    if dirty {
+                        self.bufmgr.flush()?;
+                    }
+                    dirty
+                }
+            };
+            if ran {
+                self.maintenance.count(task);
+                return Ok(Some(task));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Vacuums the first table with pages emptied since it last was, if
+    /// any, returning whether there was one.
+    fn vacuum_next(&mut self) -> Result<bool, Error> {
+        for table in self.catalog.tables() {
+            let heap = table.heap;
+            if heap
+                .emptied_pages(&self.bufmgr)
+                .map_err(catalog::Error::from)?
+                > 0
+            {
+                heap.vacuum(&self.bufmgr).map_err(catalog::Error::from)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Analyzes the next table left by a triggered analyze, or else, if
+    /// `stale`, the first whose statistics are missing or stale. Returns
+    /// whether there was one.
+    fn analyze_next(&mut self, stale: bool) -> Result<bool, Error> {
+        while let Some(table) = self.analyze_queue.pop() {
+            match self.catalog.analyze(&self.bufmgr, &table) {
+                // Dropped since it was queued.
+                Err(catalog::Error::TableNotFound(_)) => continue,
+                result => {
+                    result?;
+                    return Ok(true);
+                }
+            }
+        }
+        let Some(table) = stale.then(|| self.stale_table()).transpose()?.flatten() else {
+            return Ok(false);
+        };
+        self.catalog.analyze(&self.bufmgr, &table)?;
+        Ok(true)
+    }
+
+    fn stale_table(&self) -> Result<Option<String>, Error> {
+        for table in self.catalog.tables() {
+            let Some(stats) = &table.stats else {
+                return Ok(Some(table.name.clone()));
+            };
+            let pages = table
+                .heap
+                .page_ids(&self.bufmgr)
+                .map_err(catalog::Error::from)?;
+            let drift = pages.len().abs_diff(stats.pages) as f64;
+            if drift > stats.pages.max(1) as f64 * ANALYZE_DRIFT {
+                return Ok(Some(table.name.clone()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPoolManager;
+    use crate::disk::DiskManager;
+
+    #[test]
+    fn test_maintenance_runs_when_idle() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut engine = Engine::open(BufferPoolManager::new(disk, 32)).unwrap();
+        for sql in [
+            "CREATE TABLE a (id INT)",
+            "CREATE TABLE b (id INT)",
+            "INSERT INTO a VALUES (1), (2)",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        let maintenance = engine.maintenance();
+        engine.set_maintenance_idle(Duration::from_secs(3600));
+        assert_eq!(None, engine.run_maintenance().unwrap());
+
+        // Triggered tasks run however busy the engine is, even paused.
+        maintenance.pause(MaintenanceTask::Checkpoint);
+        maintenance.trigger(MaintenanceTask::Checkpoint);
+        assert_eq!(
+            Some(MaintenanceTask::Checkpoint),
+            engine.run_maintenance().unwrap()
+        );
+        assert_eq!(0, engine.bufmgr().dirty_pages());
+
+        engine.set_maintenance_idle(Duration::ZERO);
+        engine.execute("INSERT INTO b VALUES (3)").unwrap();
+        let mut ran = vec![];
+        while let Some(task) = engine.run_maintenance().unwrap() {
+            ran.push(task);
+        }
+        // Both tables lacked statistics; the checkpoint waits for a
+        // trigger while paused.
+        assert_eq!(vec![MaintenanceTask::Analyze; 2], ran);
+        assert!(engine.catalog().tables().all(|table| table.stats.is_some()));
+        assert!(engine.bufmgr().dirty_pages() > 0);
+
+        maintenance.resume(MaintenanceTask::Checkpoint);
+        maintenance.trigger(MaintenanceTask::Analyze);
+        let ran: Vec<_> = (0..4).map(|_| engine.run_maintenance().unwrap()).collect();
+        assert_eq!(
+            vec![
+                Some(MaintenanceTask::Analyze),
+                Some(MaintenanceTask::Analyze),
+                Some(MaintenanceTask::Checkpoint),
+                None,
+            ],
+            ran
+        );
+        assert_eq!(
+            MaintenanceStats {
+                vacuums: 0,
+                analyzes: 4,
+                checkpoints: 2,
+            },
+            maintenance.stats()
+        );
+    }
+}
@@ -0,0 +1,3125 @@
+//! Running SQL end to end.
+//!
+//! An [`Engine`] owns the buffer pool and the catalog, which it keeps in
+//! the database file when opened with [`Engine::open`], and takes each
+//! statement through the parser, the binder, the optimizer and the
+//! physical planner before executing it. Statements can also be prepared
+//! once and executed many times with different parameters; see
+//! [`PreparedStatement`]. Plans are cached by statement text, so running
+//! the same SQL again skips the planner as well, and with a
+//! [`ResultCache`] configured, a query of tables no commit has changed
+//! since skips execution too.
+//!
+//! The planner follows the engine's [`PlannerSettings`], which a statement
+//! can override for itself with hints in a leading `/*+ ... */` comment.
+//! These and the other [`SessionSettings`] can be changed with SET and
+//! read with SHOW.
+//!
+//! A running statement can be cancelled from another thread through
+//! [`Engine::cancel_token`], and is aborted once it runs past the
+//! engine's statement timeout. Its progress can be followed from another
+//! thread through [`Engine::query_progress`], or read within it from
+//! `neru_active_queries`.
+//!
+//! Statements between [`Engine::begin`] and [`Engine::commit`], or BEGIN
+//! and COMMIT, reach the file together or, after [`Engine::rollback`] or
+//! ROLLBACK, not at all; see [`BufferPoolManager`] for how. A statement
+//! that fails is undone, back to a savepoint set before it, so that it
+//! changes nothing either way; in a transaction, the transaction goes on
+//! as if it had not run. Under
+//! [`Concurrency::Optimistic`], transactions instead keep their writes to
+//! themselves until they are validated at commit; see
+//! [`OptimisticTransaction`].
+//!
+//! A query `FOR UPDATE`, `FOR NO KEY UPDATE`, `FOR SHARE` or `FOR KEY
+//! SHARE` locks the rows it returns until its transaction ends, for a
+//! transaction to read rows and then change them knowing that no other
+//! has meanwhile; UPDATE and DELETE lock the rows they change. A locking
+//! query needs the UPDATE privilege on the tables it reads, and rows that
+//! stand for one row of one table each: no joins, aggregates, grouping,
+//! DISTINCT or UNION. Locks are kept by row in a [`LockManager`], which
+//! matters under optimistic concurrency, where transactions run side by
+//! side: a row another holds in a conflicting mode is left out by `SKIP
+//! LOCKED`, fails the statement at once with `NOWAIT`, and is otherwise
+//! waited for, which takes a session that lets go of the engine (see
+//! [`Engine::set_lock_waits`]); without one, or where the wait would
+//! close a cycle, the statement is deadlocked and its transaction rolled
+//! back.
+//!
+//! Over a buffer pool that keeps [history](crate::buffer::History), a
+//! query ending in `AS OF LSN n` or `AS OF TIMESTAMP seconds` reads the
+//! database as it was at that point, which `neru_history` lists. The
+//! engine marks a point after each statement outside a transaction and
+//! after each commit. Past versions are whole pages held in memory for
+//! the retention window, not row versions, and only queries can read
+//! them, under the privileges and row policies of now.
+//!
+//! Statements run as the engine's user, if it has one, and only as far as
+//! the user's privileges allow; see [`Engine::set_user`]. Where tables
+//! have [policies](catalog::PolicyInfo), they see and change only the
+//! rows those give the user, so that tenants sharing a table are kept
+//! apart by the engine itself.
+//!
+//! Triggers run their actions as statements change rows; those that call
+//! functions of the embedder find them registered with
+//! [`Engine::register_trigger_function`].
+//!
+//! Rows of tables with a [time to live](crate::catalog::Ttl) are deleted
+//! once expired, a batch at a time, by [`Engine::expire_rows`]. Indexes
+//! made with `CREATE INDEX CONCURRENTLY` are filled the same way, by
+//! [`Engine::build_indexes`], while their tables stay writable, and
+//! snapshot files started with [`Engine::start_snapshot`] are written by
+//! [`Engine::copy_snapshots`].
+//!
+//! Vacuums, statistics and checkpoints are left to
+//! [`Engine::run_maintenance`], which runs them while the engine is idle;
+//! see [`Maintenance`].
+//!
+//! Writing statements can be held back while the buffer pool has too many
+//! dirty pages, by a [`WriteThrottle`].
+//!
+//! Subscribers registered with [`Engine::subscribe`] learn of every row
+//! that committed statements inserted, updated or deleted, for keeping
+//! caches or other stores in step.
+//!
+//! NOTIFY sends a payload on a channel, once its transaction commits, to
+//! those that LISTEN on it; see [`Listener`].
+//!
+//! Statements that change rows or the catalog can be recorded, with who
+//! ran them, in an [`AuditLog`].
+//!
+//! Statements that run for at least `log_min_duration_statement` are kept
+//! in the [`SlowQueryLog`], which the system table `neru_slow_queries`
+//! shows, and handed to the sink of [`Engine::set_slow_query_sink`].
+//! That and the other [system tables](crate::catalog::SystemTable), such
+//! as `neru_stat_buffer` and `neru_stat_tables`, can be queried like any
+//! table but not changed.
+
+mod attach;
+mod audit;
+mod copy;
+mod index_build;
+mod maintenance;
+mod notify;
+mod optimistic;
+mod plan_cache;
+mod prepared;
+mod result_cache;
+mod row_locks;
+pub mod settings;
+mod slow_log;
+mod snapshot;
+mod system;
+mod throttle;
+mod trigger;
+mod ttl;
+
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::backup::{self, SnapshotFile};
+use crate::buffer::{self, BufferPoolManager};
+use crate::catalog::{self, Catalog, Persistence, TempTables};
+use crate::csv;
+use crate::database::{self, Options};
+use crate::disk::DiskManager;
+use crate::disk::PAGE_SIZE;
+use crate::executor::{
+    self, CancellationToken, Change, ChangeLog, ExecContext, Interrupt, MemoryContext,
+    QueryProgress, RowLog, TableLog, TriggerFunction, Triggers, WorkerPool,
+};
+use crate::expr::{Aggregator, UserAggregate, UserFunction};
+use crate::heap::{HeapCounts, Rid};
+use crate::lock::{LockManager, RowLocker};
+use crate::metrics::{Histogram, Metrics};
+use crate::planner::{self, BoundStatement, Field, IndexDef, Optimizer, PlannerSettings};
+use crate::sql::{self, ast::AsOfPoint, ast::TransactionControl};
+use crate::value::{DataType, Tuple, Value};
+
+pub use audit::{read_audit_log, AuditEntry, AuditLog};
+pub use copy::infer_json_columns;
+pub use maintenance::{Maintenance, MaintenanceStats, MaintenanceTask, DEFAULT_MAINTENANCE_IDLE};
+pub use notify::{Listener, Notification, MAX_NOTIFICATION_PAYLOAD};
+pub use optimistic::{Concurrency, OptimisticTransaction};
+pub use plan_cache::{PlanCache, DEFAULT_PLAN_CACHE_CAPACITY};
+pub use prepared::PreparedStatement;
+pub use result_cache::{ResultCache, MAX_CACHED_ROWS};
+pub use row_locks::retry_row_waits;
+pub use settings::{IsolationLevel, SessionSettings};
+pub use slow_log::{SlowQuery, SlowQueryLog, DEFAULT_SLOW_QUERY_LOG_CAPACITY};
+pub use throttle::{WriteThrottle, THROTTLE_BATCH};
+
+use index_build::IndexBuild;
+use notify::{Channels, ListenerHandle};
+use prepared::Planned;
+use system::SystemRows;
+use throttle::Throttle;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Syntax(#[from] sql::Error),
+    #[error(transparent)]
+    Plan(#[from] planner::Error),
+    #[error(transparent)]
+    Execute(#[from] executor::Error),
+    #[error(transparent)]
+    Catalog(#[from] catalog::Error),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+    #[error(transparent)]
+    Backup(#[from] backup::Error),
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("cannot attach {path}: {source}")]
+    Attach {
+        path: PathBuf,
+        source: Box<database::Error>,
+    },
+    #[error("line {line}: {message}")]
+    CopyData { line: u64, message: String },
+    #[error("line {line} of the audit log: {message}")]
+    InvalidAuditLog { line: u64, message: &'static str },
+    #[error("a transaction is already in progress")]
+    TransactionActive,
+    #[error("no transaction is in progress")]
+    NoTransaction,
+    #[error("could not serialize access: {0} changed since the transaction read it")]
+    SerializationFailure(String),
+    #[error("could not obtain lock on the database: another session holds it")]
+    LockNotAvailable,
+    #[error("canceling statement due to lock timeout: waited {0:?} for another session")]
+    LockTimeout(Duration),
+    #[error("payload of NOTIFY must be shorter than {MAX_NOTIFICATION_PAYLOAD} bytes")]
+    PayloadTooLong,
+    #[error("LISTEN needs a listener to send notifications to")]
+    NoListener,
+    #[error("cannot change a database that is open read-only")]
+    ReadOnly,
+    #[error("no history is kept of {0}")]
+    NoHistory(String),
+    #[error("CREATE INDEX CONCURRENTLY cannot run inside a transaction")]
+    ConcurrentIndexInTransaction,
+    #[error("trigger function {0:?} is not registered")]
+    UnknownTriggerFunction(String),
+    #[error("function {0}() is built in")]
+    BuiltinFunction(String),
+    #[error("statement takes {expected} parameter(s), got {actual}")]
+    ParameterCount { expected: usize, actual: usize },
+    #[error("parameter ${number} must be of type {expected}, not {actual}")]
+    ParameterType {
+        number: usize,
+        expected: DataType,
+        actual: DataType,
+    },
+}
+
+/// What a statement produced.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    Rows {
+        fields: Vec<Field>,
+        rows: Vec<Tuple>,
+    },
+    /// Number of rows an INSERT, UPDATE or DELETE affected.
+    Affected(u64),
+    /// The statement changed the schema or statistics.
+    Done,
+}
+
+impl Output {
+    /// The rows of a query, or none for other statements.
+    pub fn into_rows(self) -> Vec<Tuple> {
+        match self {
+            Output::Rows { rows, .. } => rows,
+            _ => vec![],
+        }
+    }
+
+    /// Rows returned, or inserted, updated or deleted.
+    pub fn row_count(&self) -> u64 {
+        match self {
+            Output::Rows { rows, .. } => rows.len() as u64,
+            Output::Affected(rows) => *rows,
+            Output::Done => 0,
+        }
+    }
+}
+
+pub struct Engine {
+    bufmgr: BufferPoolManager,
+    catalog: Catalog,
+    optimizer: Optimizer,
+    plan_cache: PlanCache,
+    result_cache: ResultCache,
+    settings: SessionSettings,
+    /// What RESET puts settings back to.
+    defaults: SessionSettings,
+    cancel: CancellationToken,
+    progress: QueryProgress,
+    /// Where sorts and aggregations spill to.
+    temp_dir: Option<PathBuf>,
+    max_parallel_workers: Option<usize>,
+    /// The threads parallel operators run on, one per CPU.
+    pool: WorkerPool,
+    /// Bumped whenever the catalog changes, so that prepared statements
+    /// notice their plans may be stale.
+    catalog_version: u64,
+    /// The catalog and its version as the running transaction found them.
+    saved_catalog: Option<(Catalog, u64)>,
+    user: Option<String>,
+    subscribers: Vec<Subscriber>,
+    /// Changes the running transaction made, for its subscribers once it
+    /// commits.
+    pending_changes: Vec<Change>,
+    /// Where LISTEN and UNLISTEN apply.
+    listener: Option<ListenerHandle>,
+    channels: Channels,
+    /// Notifications the running transaction sends once it commits.
+    pending_notifications: Vec<Notification>,
+    /// Indexes being built concurrently, oldest first.
+    index_builds: Vec<IndexBuild>,
+    /// Rows the running transaction stored or removed in their tables.
+    pending_rows: Vec<(String, Rid, Tuple)>,
+    /// Snapshot files being written, oldest first.
+    snapshots: Vec<SnapshotFile>,
+    throttle: Throttle,
+    statements: u64,
+    failed_statements: u64,
+    statement_duration: Histogram,
+    slow_queries: SlowQueryLog,
+    slow_query_sink: Option<SlowQuerySink>,
+    audit_log: Option<AuditLog>,
+    /// The id of the running transaction, or of the last.
+    transaction_id: u64,
+    /// The last id given to a transaction, or to a statement run outside
+    /// one, which counts as its own.
+    last_transaction_id: u64,
+    trigger_functions: HashMap<String, TriggerFunction>,
+    maintenance: Maintenance,
+    maintenance_idle: Duration,
+    /// When the last statement ended, or the engine was made.
+    last_activity: Instant,
+    /// Tables a triggered analyze has yet to reach.
+    analyze_queue: Vec<String>,
+    concurrency: Concurrency,
+    optimistic: Option<OptimisticTransaction>,
+    /// Where statements record the tables they read and change.
+    table_log: TableLog,
+    /// The tables read by the statements of an optimistic transaction.
+    statement_reads: Option<BTreeSet<String>>,
+    /// The tables read by the last statement.
+    last_reads: BTreeSet<String>,
+    /// Commits that changed each table so far, which validate optimistic
+    /// transactions.
+    table_commits: HashMap<String, u64>,
+    /// Commits that changed the catalog so far.
+    catalog_commits: u64,
+    /// Tables the running transaction changed.
+    pending_tables: BTreeSet<String>,
+    optimistic_commits: u64,
+    serialization_failures: u64,
+    row_locks: LockManager,
+    /// Whether a statement can wait for a locked row; see
+    /// [`Engine::set_lock_waits`].
+    lock_waits: bool,
+    /// How `ATTACH` opens files; see [`Engine::set_attach_options`].
+    attach_options: Options,
+}
+
+/// Told of the changes of each commit; dropped once it returns false.
+type Subscriber = Box<dyn FnMut(&[Change]) -> bool + Send>;
+
+type SlowQuerySink = Box<dyn FnMut(&SlowQuery) + Send>;
+
+impl Engine {
+    /// An engine whose catalog lives only as long as it does.
+    pub fn new(bufmgr: BufferPoolManager) -> Self {
+        Self::with_catalog(bufmgr, Catalog::new())
+    }
+
+    /// An engine over the database in the file behind `bufmgr`, starting
+    /// a new one if the file is empty. Tables and indexes created through
+    /// it are kept in the file; their statistics are not.
+    pub fn open(bufmgr: BufferPoolManager) -> Result<Self, Error> {
+        let catalog = Catalog::open(&bufmgr)?;
+        let mut engine = Self::with_catalog(bufmgr, catalog);
+        engine.resume_index_builds()?;
+        Ok(engine)
+    }
+
+    fn with_catalog(bufmgr: BufferPoolManager, catalog: Catalog) -> Self {
+        Self {
+            bufmgr,
+            catalog,
+            optimizer: Optimizer::new(),
+            plan_cache: PlanCache::new(DEFAULT_PLAN_CACHE_CAPACITY),
+            result_cache: ResultCache::new(0),
+            settings: SessionSettings::default(),
+            defaults: SessionSettings::default(),
+            cancel: CancellationToken::new(),
+            progress: QueryProgress::new(),
+            temp_dir: None,
+            max_parallel_workers: None,
+            pool: WorkerPool::new(thread::available_parallelism().map_or(1, usize::from)),
+            catalog_version: 0,
+            saved_catalog: None,
+            user: None,
+            subscribers: vec![],
+            pending_changes: vec![],
+            listener: None,
+            channels: Channels::new(),
+            pending_notifications: vec![],
+            index_builds: vec![],
+            pending_rows: vec![],
+            snapshots: vec![],
+            throttle: Throttle::default(),
+            statements: 0,
+            failed_statements: 0,
+            statement_duration: Histogram::default(),
+            slow_queries: SlowQueryLog::new(DEFAULT_SLOW_QUERY_LOG_CAPACITY),
+            slow_query_sink: None,
+            audit_log: None,
+            transaction_id: 0,
+            last_transaction_id: 0,
+            trigger_functions: HashMap::new(),
+            maintenance: Maintenance::default(),
+            maintenance_idle: DEFAULT_MAINTENANCE_IDLE,
+            last_activity: Instant::now(),
+            analyze_queue: vec![],
+            concurrency: Concurrency::default(),
+            optimistic: None,
+            table_log: TableLog::new(),
+            statement_reads: None,
+            last_reads: BTreeSet::new(),
+            table_commits: HashMap::new(),
+            catalog_commits: 0,
+            pending_tables: BTreeSet::new(),
+            optimistic_commits: 0,
+            serialization_failures: 0,
+            row_locks: LockManager::new(),
+            lock_waits: false,
+            attach_options: Options::default(),
+        }
+    }
+
+    /// Caps the plan cache at `capacity` statements; 0 disables it.
+    pub fn with_plan_cache_capacity(mut self, capacity: usize) -> Self {
+        self.set_plan_cache_capacity(capacity);
+        self
+    }
+
+    pub fn set_plan_cache_capacity(&mut self, capacity: usize) {
+        self.plan_cache.set_capacity(capacity);
+    }
+
+    pub fn plan_cache(&self) -> &PlanCache {
+        &self.plan_cache
+    }
+
+    /// Keeps the results of up to `capacity` queries to answer them again
+    /// without running them; 0, the default, keeps none.
+    pub fn set_result_cache_capacity(&mut self, capacity: usize) {
+        self.result_cache.set_capacity(capacity);
+    }
+
+    pub fn result_cache(&self) -> &ResultCache {
+        &self.result_cache
+    }
+
+    pub fn planner_settings(&self) -> &PlannerSettings {
+        &self.settings.planner
+    }
+
+    /// Plans later statements with `settings`, by default as well as now.
+    pub fn set_planner_settings(&mut self, settings: PlannerSettings) {
+        self.defaults.planner = settings.clone();
+        self.set_settings(SessionSettings {
+            planner: settings,
+            ..self.settings.clone()
+        });
+    }
+
+    pub fn settings(&self) -> &SessionSettings {
+        &self.settings
+    }
+
+    /// The settings RESET goes back to, which are those the engine was
+    /// configured with through its setters.
+    pub fn default_settings(&self) -> &SessionSettings {
+        &self.defaults
+    }
+
+    /// Runs later statements with `settings` until they are changed by
+    /// SET, as for a session taking its turn. Cached plans are dropped if
+    /// the planner's settings differ, since they may be ones the settings
+    /// now rule out.
+    pub fn set_settings(&mut self, settings: SessionSettings) {
+        if settings.planner != self.settings.planner {
+            self.plan_cache.clear();
+        }
+        self.settings = settings;
+    }
+
+    /// A token that cancels the statement running when it is raised.
+    /// Raising it while no statement runs does nothing.
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Where the statement running records how far it has got; see
+    /// [`QueryProgress`]. Between statements it reads as nothing.
+    pub fn query_progress(&self) -> QueryProgress {
+        self.progress.clone()
+    }
+
+    pub fn statement_timeout(&self) -> Option<Duration> {
+        self.settings.statement_timeout
+    }
+
+    /// Aborts statements that run longer than `timeout`; `None`, the
+    /// default, lets them run for as long as they take.
+    pub fn set_statement_timeout(&mut self, timeout: Option<Duration>) {
+        self.settings.statement_timeout = timeout;
+        self.defaults.statement_timeout = timeout;
+    }
+
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.settings.lock_timeout
+    }
+
+    /// Has sessions sharing the engine give up waiting for one another,
+    /// and for the rows of one another's transactions, after `timeout`;
+    /// `None`, the default, has them wait for as long as it takes. The
+    /// engine itself never waits: this is for the server and
+    /// [`retry_row_waits`].
+    pub fn set_lock_timeout(&mut self, timeout: Option<Duration>) {
+        self.settings.lock_timeout = timeout;
+        self.defaults.lock_timeout = timeout;
+    }
+
+    /// Records statements that run for at least `threshold` in the slow
+    /// query log; `None`, the default, records none.
+    pub fn set_log_min_duration_statement(&mut self, threshold: Option<Duration>) {
+        self.settings.log_min_duration_statement = threshold;
+        self.defaults.log_min_duration_statement = threshold;
+    }
+
+    /// The statements the slow query log kept, which the table
+    /// `neru_slow_queries` also shows.
+    pub fn slow_queries(&self) -> &SlowQueryLog {
+        &self.slow_queries
+    }
+
+    pub fn slow_queries_mut(&mut self) -> &mut SlowQueryLog {
+        &mut self.slow_queries
+    }
+
+    /// Records the statements that change rows or the catalog in `log`
+    /// from now on, or none with `None`.
+    pub fn set_audit_log(&mut self, log: Option<AuditLog>) {
+        if let Some(log) = &log {
+            self.last_transaction_id = self.last_transaction_id.max(log.last_transaction());
+        }
+        self.audit_log = log;
+    }
+
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit_log.as_ref()
+    }
+
+    /// Hands each statement the slow query log records to `sink` as well,
+    /// such as to write it to a log file.
+    pub fn set_slow_query_sink(&mut self, sink: impl FnMut(&SlowQuery) + Send + 'static) {
+        self.slow_query_sink = Some(Box::new(sink));
+    }
+
+    /// Lets SQL call `function` as `name(...)` with arguments of the types
+    /// `arguments`, which the binder coerces them to, and a result of
+    /// `return_type`. An error the function returns fails the statement
+    /// with its message. Registering a name again replaces the function;
+    /// the names of built-in functions cannot be taken.
+    pub fn create_function(
+        &mut self,
+        name: &str,
+        arguments: &[DataType],
+        return_type: DataType,
+        function: impl Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Result<(), Error> {
+        if planner::is_builtin_function(name) {
+            return Err(Error::BuiltinFunction(name.to_string()));
+        }
+        let function = UserFunction::new(name, arguments, return_type, function);
+        // Functions are not part of a transaction, so a rollback keeps them.
+        if let Some((saved, _)) = &mut self.saved_catalog {
+            saved.create_function(function.clone());
+        }
+        self.catalog.create_function(function);
+        self.catalog_version += 1;
+        self.plan_cache.clear();
+        Ok(())
+    }
+
+    /// Forgets the function registered as `name`, returning whether there
+    /// was one.
+    pub fn drop_function(&mut self, name: &str) -> bool {
+        if let Some((saved, _)) = &mut self.saved_catalog {
+            saved.drop_function(name);
+        }
+        let dropped = self.catalog.drop_function(name).is_some();
+        if dropped {
+            self.catalog_version += 1;
+            self.plan_cache.clear();
+        }
+        dropped
+    }
+
+    /// Lets SQL call `aggregator` as the aggregate `name(...)`, in GROUP BY
+    /// queries and as a window function, with an argument of the type
+    /// `argument` and a result of `return_type`. As with
+    /// [`Engine::create_function`], it replaces any function of the name
+    /// but not a built-in one.
+    pub fn create_aggregate(
+        &mut self,
+        name: &str,
+        argument: DataType,
+        return_type: DataType,
+        aggregator: impl Aggregator,
+    ) -> Result<(), Error> {
+        if planner::is_builtin_function(name) {
+            return Err(Error::BuiltinFunction(name.to_string()));
+        }
+        let aggregate = UserAggregate::new(name, argument, return_type, aggregator);
+        if let Some((saved, _)) = &mut self.saved_catalog {
+            saved.create_aggregate(aggregate.clone());
+        }
+        self.catalog.create_aggregate(aggregate);
+        self.catalog_version += 1;
+        self.plan_cache.clear();
+        Ok(())
+    }
+
+    /// Forgets the aggregate registered as `name`, returning whether there
+    /// was one.
+    pub fn drop_aggregate(&mut self, name: &str) -> bool {
+        if let Some((saved, _)) = &mut self.saved_catalog {
+            saved.drop_aggregate(name);
+        }
+        let dropped = self.catalog.drop_aggregate(name).is_some();
+        if dropped {
+            self.catalog_version += 1;
+            self.plan_cache.clear();
+        }
+        dropped
+    }
+
+    /// Lets each sort, aggregation and hash join buffer `work_mem` bytes
+    /// of rows before it spills to disk; `None`, the default, never
+    /// spills.
+    pub fn set_work_mem(&mut self, work_mem: Option<usize>) {
+        self.settings.work_mem = work_mem;
+        self.defaults.work_mem = work_mem;
+    }
+
+    /// Fails a statement whose operators hold more than `memory_limit`
+    /// bytes in all with [`executor::Error::OutOfBudget`]; `None`, the
+    /// default, lets them grow.
+    pub fn set_memory_limit(&mut self, memory_limit: Option<usize>) {
+        self.settings.memory_limit = memory_limit;
+        self.defaults.memory_limit = memory_limit;
+    }
+
+    /// Puts spill files in `temp_dir` instead of the system's temporary
+    /// directory.
+    pub fn set_temp_dir(&mut self, temp_dir: Option<PathBuf>) {
+        self.temp_dir = temp_dir;
+    }
+
+    /// Caps the threads a parallel scan or sort may use; `None` uses one
+    /// per CPU. Parallel operators share the engine's pool of workers,
+    /// which keeps to one per CPU between them whatever the cap.
+    pub fn set_max_parallel_workers(&mut self, workers: Option<usize>) {
+        self.max_parallel_workers = workers;
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// Runs later statements as `user`, checking them against the user's
+    /// privileges; `None`, the default, runs them unchecked. Statements
+    /// prepared for another user are planned again before they run.
+    pub fn set_user(&mut self, user: Option<String>) {
+        self.user = user;
+    }
+
+    /// Has later statements see `temp` as their temporary tables, in place
+    /// of any there were, as a server does for each session's statements.
+    /// Statements prepared with other temporary tables are planned again
+    /// before they run.
+    pub fn set_temp_tables(&mut self, temp: Option<TempTables>) {
+        self.catalog.set_temp_tables(temp);
+    }
+
+    /// Takes out the temporary tables that CREATE TEMP TABLE made or
+    /// [`Engine::set_temp_tables`] set.
+    pub fn take_temp_tables(&mut self) -> Option<TempTables> {
+        self.catalog.take_temp_tables()
+    }
+
+    fn temp_tables_id(&self) -> Option<u64> {
+        self.catalog.temp_tables().map(TempTables::id)
+    }
+
+    pub fn bufmgr(&self) -> &BufferPoolManager {
+        &self.bufmgr
+    }
+
+    /// A snapshot of the metrics of the database and of the statements
+    /// this engine ran.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            buffer: self.bufmgr.stats(),
+            buffer_pool_pages: self.bufmgr.pool_size(),
+            pages: self.bufmgr.num_pages(),
+            active_transactions: u64::from(self.in_transaction()),
+            statements: self.statements,
+            failed_statements: self.failed_statements,
+            optimistic_commits: self.optimistic_commits,
+            serialization_failures: self.serialization_failures,
+            dirty_pages: self.bufmgr.dirty_pages(),
+            throttled_writes: self.throttle.throttled,
+            stalled_writes: self.throttle.stalled,
+            statement_duration: self.statement_duration.clone(),
+            plan_cache_entries: self.plan_cache.len(),
+            plan_cache_hits: self.plan_cache.hits(),
+            plan_cache_misses: self.plan_cache.misses(),
+            result_cache_entries: self.result_cache.len(),
+            result_cache_hits: self.result_cache.hits(),
+            result_cache_misses: self.result_cache.misses(),
+            workers: self.pool.stats(),
+            maintenance: self.maintenance.stats(),
+        }
+    }
+
+    pub fn catalog(&self) -> &Catalog {
+        &self.catalog
+    }
+
+    /// The rows of `table` as its heap counts them, which takes no scan;
+    /// for a partitioned table, those of its partitions.
+    pub fn estimated_row_count(&self, table: &str) -> Result<u64, Error> {
+        Ok(self.table_counts(table)?.rows)
+    }
+
+    /// The bytes the heap pages of `table`, or of its partitions, take in
+    /// the file, not counting its indexes; this takes no scan either.
+    pub fn table_size_bytes(&self, table: &str) -> Result<u64, Error> {
+        Ok(self.table_counts(table)?.pages * PAGE_SIZE as u64)
+    }
+
+    /// What the heaps of `name` count, meta pages included in the pages.
+    fn table_counts(&self, name: &str) -> Result<HeapCounts, Error> {
+        let table = (self.catalog.table(name))
+            .ok_or_else(|| catalog::Error::TableNotFound(name.to_string()))?;
+        let bufmgr = self.catalog.table_bufmgr(name).unwrap_or(&self.bufmgr);
+        let mut tables = vec![table];
+        if table.partitioning.is_some() {
+            tables.extend(self.catalog.partitions(name));
+        }
+        let mut counts = HeapCounts::default();
+        for table in tables {
+            let heap = table.heap.counts(bufmgr).map_err(catalog::Error::from)?;
+            counts.rows += heap.rows;
+            counts.pages += heap.pages + 1;
+        }
+        Ok(counts)
+    }
+
+    /// Calls `subscriber` with the rows each transaction changed once it
+    /// commits, in the order they changed, for as long as it returns
+    /// true. A statement outside a transaction commits once it has run;
+    /// one that fails changes no rows. Rows that go with a dropped table are not reported.
+    pub fn subscribe(&mut self, subscriber: impl FnMut(&[Change]) -> bool + Send + 'static) {
+        self.subscribers.push(Box::new(subscriber));
+    }
+
+    /// [`Engine::subscribe`] through a channel that receives the changes
+    /// of each commit as one message, until the receiver is dropped.
+    pub fn subscribe_channel(&mut self) -> mpsc::Receiver<Vec<Change>> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribe(move |changes| sender.send(changes.to_vec()).is_ok());
+        receiver
+    }
+
+    /// Hands the changes of a statement to the subscribers, or keeps them
+    /// for the end of the running transaction.
+    fn publish(&mut self, changes: Vec<Change>) {
+        if self.in_transaction() {
+            self.pending_changes.extend(changes);
+        } else if !changes.is_empty() {
+            self.subscribers
+                .retain_mut(|subscriber| subscriber(&changes));
+        }
+    }
+
+    /// Starts a transaction. Until it ends, changes stay in the buffer
+    /// pool, which they must fit in; under [`Concurrency::Optimistic`],
+    /// they stay out of the database altogether.
+    pub fn begin(&mut self) -> Result<(), Error> {
+        if self.saved_catalog.is_some() || self.optimistic.is_some() {
+            return Err(Error::TransactionActive);
+        }
+        self.last_transaction_id += 1;
+        self.transaction_id = self.last_transaction_id;
+        match self.concurrency {
+            Concurrency::Locking => self.begin_locked(),
+            Concurrency::Optimistic => {
+                self.begin_optimistic();
+                Ok(())
+            }
+        }
+    }
+
+    fn begin_locked(&mut self) -> Result<(), Error> {
+        if self.saved_catalog.is_some() {
+            return Err(Error::TransactionActive);
+        }
+        self.bufmgr.begin()?;
+        self.saved_catalog = Some((self.catalog.clone(), self.catalog_version));
+        Ok(())
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.saved_catalog.is_some()
+    }
+
+    /// Writes the transaction's changes to the file and syncs it. An
+    /// optimistic transaction is validated first, and rolled back if that
+    /// fails.
+    pub fn commit(&mut self) -> Result<(), Error> {
+        let result = match self.optimistic.take() {
+            Some(transaction) => self.commit_optimistic(transaction),
+            None => self.commit_locked(),
+        };
+        self.release_row_locks();
+        result
+    }
+
+    fn commit_locked(&mut self) -> Result<(), Error> {
+        let Some((_, version)) = self.saved_catalog.take() else {
+            return Err(Error::NoTransaction);
+        };
+        let changes = std::mem::take(&mut self.pending_changes);
+        let rows = std::mem::take(&mut self.pending_rows);
+        let tables = std::mem::take(&mut self.pending_tables);
+        self.bufmgr.commit()?;
+        self.count_commit(tables, self.catalog_version != version);
+        self.bufmgr.mark_history();
+        self.publish(changes);
+        self.record_rows(rows);
+        let notifications = std::mem::take(&mut self.pending_notifications);
+        self.send_notifications(notifications);
+        Ok(())
+    }
+
+    /// Undoes every change since [`Engine::begin`], to the catalog as
+    /// well as to the data.
+    pub fn rollback(&mut self) -> Result<(), Error> {
+        let result = match self.optimistic.take() {
+            Some(_) => Ok(()),
+            None => self.rollback_locked(),
+        };
+        self.release_row_locks();
+        result
+    }
+
+    fn rollback_locked(&mut self) -> Result<(), Error> {
+        let (catalog, version) = self.saved_catalog.take().ok_or(Error::NoTransaction)?;
+        self.bufmgr.rollback();
+        self.pending_changes.clear();
+        self.pending_rows.clear();
+        self.pending_tables.clear();
+        self.pending_notifications.clear();
+        // Plans made for the catalog being dropped are stale.
+        if self.catalog_version != version {
+            self.catalog_version += 1;
+            self.plan_cache.clear();
+        }
+        self.catalog = catalog;
+        Ok(())
+    }
+
+    /// Counts a commit that changed `tables`, and the catalog if
+    /// `catalog` is set, against the optimistic transactions that read
+    /// them.
+    fn count_commit(&mut self, tables: BTreeSet<String>, catalog: bool) {
+        for table in tables {
+            *self.table_commits.entry(table).or_default() += 1;
+        }
+        self.catalog_commits += u64::from(catalog);
+    }
+
+    /// Undoes a statement that failed, back to what was saved before it
+    /// ran.
+    fn rollback_statement(&mut self, saved: (Catalog, u64, (usize, usize))) -> Result<(), Error> {
+        let (catalog, version, (changes, rows)) = saved;
+        let restored = self.bufmgr.rollback_to_savepoint();
+        self.pending_changes.truncate(changes);
+        self.pending_rows.truncate(rows);
+        if self.catalog_version != version {
+            self.catalog_version += 1;
+            self.plan_cache.clear();
+        }
+        self.catalog = catalog;
+        Ok(restored?)
+    }
+
+    /// Parses, plans and runs one statement.
+    pub fn execute(&mut self, sql: &str) -> Result<Output, Error> {
+        let statement = self.prepare(sql)?;
+        self.execute_prepared(&statement, &[])
+    }
+
+    /// Parses, binds and plans `sql` for [`Engine::execute_prepared`], or
+    /// takes its plan from the cache. Parameters are written `$1`, `$2`, ...
+    /// and get their types from the context they appear in.
+    pub fn prepare(&mut self, sql: &str) -> Result<PreparedStatement, Error> {
+        if self.plans_optimistic() {
+            return self.prepare_optimistic(sql);
+        }
+        let mut key = sql::normalize(sql)?;
+        // Plans were checked against the privileges of their user, and
+        // found names among the temporary tables there were.
+        if let Some(user) = &self.user {
+            key = format!("{user}\0{key}");
+        }
+        if let Some(id) = self.temp_tables_id() {
+            key = format!("{key}\0{id}");
+        }
+        if let Some(statement) = self.plan_cache.get(&key) {
+            return Ok(statement.clone());
+        }
+        let statement = match self.plan(sql) {
+            // Denied, the statement is a failed attempt worth recording.
+            Err(
+                e @ Error::Plan(
+                    planner::Error::PermissionDenied { .. }
+                    | planner::Error::ColumnPermissionDenied { .. },
+                ),
+            ) if self.audit_log.is_some() => {
+                self.audit(sql, Err(&e), !self.in_transaction());
+                return Err(e);
+            }
+            result => result?,
+        };
+        // Catalog changes are not worth caching: they empty the cache.
+        if !matches!(
+            statement.planned,
+            Planned::Other(_) | Planned::CreateView { .. }
+        ) {
+            self.plan_cache.insert(key, statement.clone());
+        }
+        Ok(statement)
+    }
+
+    fn plan(&self, sql: &str) -> Result<PreparedStatement, Error> {
+        self.plan_statement(sql, &sql::parse_statement(sql)?)
+    }
+
+    /// Plans `statement`, parsed from `sql`.
+    fn plan_statement(
+        &self,
+        sql: &str,
+        statement: &sql::ast::Statement,
+    ) -> Result<PreparedStatement, Error> {
+        let settings = self.settings.planner.with_hints(&sql::parse_hints(sql)?)?;
+        let locking = match statement {
+            sql::ast::Statement::Select(query) => query.locking,
+            _ => None,
+        };
+        let (statement, parameters) = planner::bind_prepared(
+            &self.catalog,
+            statement,
+            self.user.as_deref(),
+            settings.null_ordering,
+        )?;
+        let statement = self.optimizer.optimize_statement(statement);
+        let null_ordering = settings.null_ordering;
+        let mut planned = Planned::new(&self.catalog, statement, &parameters, settings);
+        if let (Some(locking), Planned::Query { plan, .. }) = (locking, &mut planned) {
+            let query = std::mem::replace(plan, executor::Plan::Values { rows: vec![] });
+            *plan = (query.lock_rows(&self.catalog, locking.mode, locking.wait))
+                .map_err(|clause| planner::Error::ForUpdateNotAllowed(locking.mode, clause))?;
+        }
+        let triggers = match planned.target() {
+            Some(table) => self.plan_triggers(table)?,
+            None => Triggers::new(),
+        };
+        Ok(PreparedStatement {
+            sql: sql.to_string(),
+            parameters,
+            planned,
+            catalog_version: self.catalog_version,
+            user: self.user.clone(),
+            temp_tables: self.temp_tables_id(),
+            null_ordering,
+            triggers: Arc::new(triggers),
+        })
+    }
+
+    /// Runs a prepared statement with `params` for its parameters, in
+    /// order. Values must have the parameter's type, except that ints are
+    /// accepted for floats; NULL fits any parameter. A statement prepared
+    /// before the catalog last changed, or for another user or other
+    /// temporary tables, is planned again first. A statement that is
+    /// deadlocked waiting for a row rolls back its transaction.
+    pub fn execute_prepared(
+        &mut self,
+        statement: &PreparedStatement,
+        params: &[Value],
+    ) -> Result<Output, Error> {
+        let output = self.execute_statement(statement, params);
+        self.abort_deadlocked(output)
+    }
+
+    fn execute_statement(
+        &mut self,
+        statement: &PreparedStatement,
+        params: &[Value],
+    ) -> Result<Output, Error> {
+        if self.routes_optimistic(statement) {
+            return self.execute_optimistic(statement, params);
+        }
+        if statement.catalog_version != self.catalog_version
+            || statement.user != self.user
+            || statement.temp_tables != self.temp_tables_id()
+        {
+            let statement = self.prepare(&statement.sql)?;
+            return self.execute_statement(&statement, params);
+        }
+        let params = statement.check_parameters(params)?;
+        let key = self.result_key(statement, &params);
+        if let Some(key) = &key {
+            let commits = &self.table_commits;
+            let cached = self.result_cache.get(key, self.catalog_version, |table| {
+                commits.get(table).copied().unwrap_or(0)
+            });
+            if let Some((fields, rows)) = cached {
+                self.statements += 1;
+                self.last_activity = Instant::now();
+                return Ok(Output::Rows { fields, rows });
+            }
+        }
+        let planned = statement.planned.replace_parameters(&params);
+        let output = self.run(&statement.sql, planned, &statement.triggers)?;
+        if let (Some(key), Output::Rows { fields, rows }) = (key, &output) {
+            let reads = (self.last_reads.iter())
+                .map(|table| (table.clone(), self.table_commits(table)))
+                .collect();
+            let (fields, rows) = (fields.clone(), rows.clone());
+            (self.result_cache).insert(key, fields, rows, self.catalog_version, reads);
+        }
+        Ok(output)
+    }
+
+    /// The key `statement` run with `params` keeps its result under, if
+    /// the result cache may keep it.
+    fn result_key(&self, statement: &PreparedStatement, params: &[Value]) -> Option<String> {
+        if self.result_cache.capacity() == 0 || self.in_transaction() || self.optimistic.is_some() {
+            return None;
+        }
+        let Planned::Query { plan, .. } = &statement.planned else {
+            return None;
+        };
+        if plan.reads_system_table() || plan.locks_rows() {
+            return None;
+        }
+        let sql = sql::normalize(&statement.sql).ok()?;
+        let user = self.user.as_deref().unwrap_or("");
+        let temp = self.temp_tables_id();
+        // The same text sorts differently under another null_ordering.
+        let nulls = statement.null_ordering;
+        Some(format!("{user}\0{sql}\0{params:?}\0{temp:?}\0{nulls}"))
+    }
+
+    /// Adds `sql` to the audit log with the rows it changed or the error
+    /// it failed with, as a transaction of its own if `own_transaction`.
+    fn audit(&mut self, sql: &str, result: Result<u64, &Error>, own_transaction: bool) {
+        if own_transaction {
+            self.last_transaction_id += 1;
+            self.transaction_id = self.last_transaction_id;
+        }
+        let entry = AuditEntry {
+            time: AuditEntry::now(),
+            user: self.user.clone(),
+            transaction: self.transaction_id,
+            rows: *result.as_ref().unwrap_or(&0),
+            sql: sql.to_string(),
+            error: result.err().map(Error::to_string),
+        };
+        self.audit_log.as_mut().unwrap().record(&entry);
+    }
+
+    fn run(&mut self, sql: &str, planned: Planned, triggers: &Triggers) -> Result<Output, Error> {
+        let threshold = self.settings.log_min_duration_statement;
+        let plan = threshold.and_then(|_| planned.plan().cloned());
+        let start = Instant::now();
+        let autocommit = !self.in_transaction();
+        let audited = self.audit_log.is_some() && planned.writes();
+        let version = self.catalog_version;
+        let locker = self.row_locker(&planned);
+        // A statement outside a transaction counts as its own.
+        let counted = autocommit && self.statement_reads.is_none() && locker.is_some();
+        let saved = planned.writes().then(|| {
+            self.bufmgr.savepoint();
+            let lengths = (self.pending_changes.len(), self.pending_rows.len());
+            (self.catalog.clone(), self.catalog_version, lengths)
+        });
+        let mut output = self.run_planned(sql, start, planned, triggers, locker.as_ref());
+        if counted {
+            self.release_row_locks();
+        }
+        match saved {
+            Some(saved) if output.is_err() => {
+                if let Err(e) = self.rollback_statement(saved) {
+                    output = Err(e);
+                }
+            }
+            Some(_) => self.bufmgr.release_savepoint(),
+            None => {}
+        }
+        let (read, written) = self.table_log.take();
+        if let Some(reads) = &mut self.statement_reads {
+            reads.extend(read.iter().cloned());
+        }
+        self.last_reads = read;
+        if !self.in_transaction() {
+            self.bufmgr.mark_history();
+            if autocommit {
+                self.count_commit(written, self.catalog_version != version);
+            }
+        } else {
+            self.pending_tables.extend(written);
+        }
+        let duration = start.elapsed();
+        self.statements += 1;
+        self.failed_statements += u64::from(output.is_err());
+        self.statement_duration.observe(duration);
+        self.last_activity = Instant::now();
+        if audited {
+            let result = output.as_ref().map(Output::row_count);
+            self.audit(sql, result, autocommit && !counted);
+        }
+        if let (Some(threshold), Ok(output)) = (threshold, &output) {
+            if duration >= threshold {
+                let query = SlowQuery {
+                    sql: sql.to_string(),
+                    duration,
+                    rows: output.row_count(),
+                    plan: plan.map(|plan| planner::explain(&self.catalog, &plan)),
+                };
+                if let Some(sink) = &mut self.slow_query_sink {
+                    sink(&query);
+                }
+                self.slow_queries.record(query);
+            }
+        }
+        output
+    }
+
+    fn run_planned(
+        &mut self,
+        sql: &str,
+        started: Instant,
+        planned: Planned,
+        triggers: &Triggers,
+        locker: Option<&RowLocker>,
+    ) -> Result<Output, Error> {
+        if planned.writes() {
+            if self.bufmgr.is_read_only() {
+                return Err(Error::ReadOnly);
+            }
+            self.admit_write()?;
+        }
+        if let Planned::CreateView {
+            name,
+            schema,
+            view,
+            if_not_exists,
+            insert,
+        } = planned
+        {
+            if if_not_exists && self.catalog.table(&name).is_some() {
+                return Ok(Output::Done);
+            }
+            self.catalog
+                .create_materialized_view(&self.bufmgr, &name, schema, view)?;
+            self.catalog_version += 1;
+            self.plan_cache.clear();
+            let insert = Planned::Insert(insert);
+            let result = self.run_planned(sql, started, insert, triggers, locker);
+            if result.is_err() {
+                self.catalog.drop_materialized_view(&self.bufmgr, &name)?;
+            }
+            return result;
+        }
+        self.cancel.reset();
+        let mut interrupt = Interrupt::new().with_token(self.cancel.clone());
+        if let Some(timeout) = self.settings.statement_timeout {
+            interrupt = interrupt.with_timeout(timeout);
+        }
+        let _running = self.progress.start(sql);
+        let work_mem = self.settings.work_mem.unwrap_or(usize::MAX);
+        let memory = MemoryContext::new(work_mem, self.settings.memory_limit)
+            .with_temp_dir(self.temp_dir.clone())
+            .with_progress(self.progress.clone());
+        let system_tables = SystemRows {
+            sql,
+            started,
+            progress: &self.progress,
+            user: self.user.as_deref(),
+            in_transaction: self.in_transaction(),
+            slow_queries: &self.slow_queries,
+        };
+        let mut ctx = ExecContext::new(&self.bufmgr, &self.catalog)
+            .with_system_tables(&system_tables)
+            .with_interrupt(&interrupt)
+            .with_progress(&self.progress)
+            .with_memory(&memory)
+            .with_pool(&self.pool)
+            .with_tables(&self.table_log);
+        if let Some(workers) = self.max_parallel_workers {
+            ctx = ctx.with_max_parallel_workers(workers);
+        }
+        if let Some(user) = self.user.as_deref() {
+            ctx = ctx.with_user(user);
+        }
+        if !triggers.is_empty() {
+            ctx = ctx.with_triggers(triggers);
+        }
+        if let Some(locker) = locker {
+            ctx = ctx.with_locker(locker);
+        }
+        let changes = ChangeLog::new();
+        if !self.subscribers.is_empty() {
+            ctx = ctx.with_changes(&changes);
+        }
+        let rows = RowLog::new(self.index_builds.iter().map(|b| b.table.clone()).collect());
+        if !self.index_builds.is_empty() {
+            ctx = ctx.with_rows(&rows);
+        }
+        match planned {
+            Planned::Query { fields, plan } => Ok(Output::Rows {
+                fields,
+                rows: (plan.cursor(&ctx)?)
+                    .inspect(|_| ctx.count_rows(1))
+                    .collect::<Result<_, _>>()?,
+            }),
+            Planned::Insert(insert) => {
+                let affected = insert.execute(&ctx)?;
+                self.publish(changes.into_changes());
+                self.record_rows(rows.into_rows());
+                Ok(Output::Affected(affected))
+            }
+            Planned::Update(update) => {
+                let affected = update.execute(&ctx)?;
+                self.publish(changes.into_changes());
+                self.record_rows(rows.into_rows());
+                Ok(Output::Affected(affected))
+            }
+            Planned::Delete(delete) => {
+                let affected = delete.execute(&ctx)?;
+                self.publish(changes.into_changes());
+                self.record_rows(rows.into_rows());
+                Ok(Output::Affected(affected))
+            }
+            Planned::RefreshView { insert } => {
+                // The old rows go with their pages, rather than one by
+                // one leaving them empty, and the new rows take them.
+                self.catalog.truncate(&self.bufmgr, &insert.table)?;
+                let affected = insert.execute(&ctx)?;
+                self.publish(changes.into_changes());
+                self.record_rows(rows.into_rows());
+                Ok(Output::Affected(affected))
+            }
+            Planned::CreateView { .. } => unreachable!("created above"),
+            Planned::Explain { analyze, plan } => {
+                let text = if analyze {
+                    planner::explain_analyze(&ctx, &plan)?
+                } else {
+                    planner::explain(&self.catalog, &plan)
+                };
+                Ok(Output::Rows {
+                    fields: vec![Field::new("QUERY PLAN", Some(DataType::Text))],
+                    rows: text.lines().map(|line| vec![line.into()]).collect(),
+                })
+            }
+            Planned::CopyTo {
+                fields,
+                plan,
+                file,
+                format,
+            } => Ok(Output::Affected(copy::copy_to(
+                &ctx, &fields, &plan, &file, &format,
+            )?)),
+            Planned::Other(BoundStatement::CopyFrom {
+                table,
+                columns,
+                file,
+                format,
+            }) => {
+                let copied = copy::copy_from(&ctx, &table, &columns, &file, &format)?;
+                self.publish(changes.into_changes());
+                self.record_rows(rows.into_rows());
+                Ok(Output::Affected(copied))
+            }
+            Planned::Other(BoundStatement::Transaction(control)) => {
+                match control {
+                    TransactionControl::Begin => self.begin()?,
+                    TransactionControl::Commit => self.commit()?,
+                    TransactionControl::Rollback => self.rollback()?,
+                }
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::Set { name, value }) => {
+                let mut settings = self.settings.clone();
+                match (name, value) {
+                    (Some(name), Some(value)) => settings.set(&name, &value)?,
+                    (Some(name), None) => settings.reset(&name, &self.defaults)?,
+                    (None, _) => settings = self.defaults.clone(),
+                }
+                self.set_settings(settings);
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::Show { name }) => self.show(name),
+            Planned::Other(BoundStatement::CreateIndex {
+                index,
+                if_not_exists,
+                concurrently: true,
+            }) => {
+                self.start_index_build(index, if_not_exists)?;
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::AsOf { query, point }) => {
+                self.run_as_of(sql, *query, point)
+            }
+            Planned::Other(BoundStatement::Attach { path, name }) => {
+                self.attach(path, &name)?;
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::Detach { name }) => {
+                self.detach(&name)?;
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::Backup { path, chain }) => {
+                if chain.is_empty() {
+                    backup::backup(&self.bufmgr, path)?;
+                } else {
+                    backup::backup_incremental(&self.bufmgr, &chain, path)?;
+                }
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::Notify { channel, payload }) => {
+                self.notify(&channel, &payload)?;
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::Listen { channel }) => {
+                self.listen_current(&channel)?;
+                Ok(Output::Done)
+            }
+            Planned::Other(BoundStatement::Unlisten { channel }) => {
+                self.unlisten_current(channel.as_deref());
+                Ok(Output::Done)
+            }
+            Planned::Other(statement) => {
+                let result = self.alter_catalog(statement);
+                self.catalog_version += 1;
+                self.plan_cache.clear();
+                result.map(|()| Output::Done)
+            }
+        }
+    }
+
+    /// Runs `query` over the database as of `point`, through an engine of
+    /// its own over the pages as they were then, read from the history as
+    /// the query needs them. The query sees the tables of that time and
+    /// their rows, but under the privileges, column grants and row
+    /// policies of now, so that revoking access also revokes it to the
+    /// past.
+    fn run_as_of(
+        &self,
+        sql: &str,
+        query: sql::ast::Query,
+        point: AsOfPoint,
+    ) -> Result<Output, Error> {
+        if self.in_transaction() {
+            return Err(Error::TransactionActive);
+        }
+        let no_history = || {
+            Error::NoHistory(match point {
+                AsOfPoint::Lsn(lsn) => format!("LSN {lsn}"),
+                AsOfPoint::Timestamp(time) => format!("TIMESTAMP {time}"),
+            })
+        };
+        let history = self.bufmgr.history().ok_or_else(no_history)?;
+        let found = match point {
+            AsOfPoint::Lsn(lsn) => history.point(lsn),
+            AsOfPoint::Timestamp(time) => history.at(time),
+        };
+        let lsn = found.ok_or_else(no_history)?.lsn;
+        let pages = self.bufmgr.pages_as_of(lsn).ok_or_else(no_history)?;
+        let disk = DiskManager::with_storage(pages)?.into_read_only();
+        let mut engine = Engine::open(BufferPoolManager::new(disk, self.bufmgr.pool_size()))?;
+        engine.catalog.adopt_access(&self.catalog);
+        engine.user = self.user.clone();
+        engine.settings = self.settings.clone();
+        let statement =
+            engine.plan_statement(sql, &sql::ast::Statement::Select(Box::new(query)))?;
+        engine.execute_prepared(&statement, &[])
+    }
+
+    /// The value of one setting, or with no `name` a row for each.
+    fn show(&self, name: Option<String>) -> Result<Output, Error> {
+        let text = |name: &str| Field::new(name, Some(DataType::Text));
+        Ok(match name {
+            Some(name) => Output::Rows {
+                rows: vec![vec![self.settings.get(&name)?.into()]],
+                fields: vec![text(&name)],
+            },
+            None => Output::Rows {
+                fields: vec![text("name"), text("setting")],
+                rows: SessionSettings::names()
+                    .map(|name| Ok(vec![name.into(), self.settings.get(name)?.into()]))
+                    .collect::<Result<_, Error>>()?,
+            },
+        })
+    }
+
+    /// Runs a statement that changes the catalog rather than table rows.
+    fn alter_catalog(&mut self, statement: BoundStatement) -> Result<(), Error> {
+        match statement {
+            BoundStatement::CreateTable {
+                name,
+                persistence,
+                schema,
+                indexes,
+                partitioning,
+                if_not_exists,
+            } => {
+                if if_not_exists && self.catalog.name_taken(&name, persistence) {
+                    return Ok(());
+                }
+                match (partitioning, persistence) {
+                    (Some(partitioning), _) => {
+                        self.catalog.create_partitioned_table(
+                            &self.bufmgr,
+                            &name,
+                            schema,
+                            partitioning,
+                        )?;
+                    }
+                    (None, Persistence::Permanent) => {
+                        self.catalog.create_table(&self.bufmgr, &name, schema)?;
+                    }
+                    (None, Persistence::Temporary) => {
+                        self.catalog
+                            .create_temp_table(&self.bufmgr, &name, schema)?;
+                    }
+                    (None, Persistence::Unlogged) => {
+                        (self.catalog).create_unlogged_table(&self.bufmgr, &name, schema)?;
+                    }
+                }
+                for index in &indexes {
+                    if let Err(e) = self.create_index(index) {
+                        self.catalog.drop_table(&self.bufmgr, &name)?;
+                        return Err(e);
+                    }
+                }
+            }
+            BoundStatement::CreatePartition {
+                name,
+                parent,
+                bound,
+                if_not_exists,
+            } => {
+                if if_not_exists && self.catalog.table(&name).is_some() {
+                    return Ok(());
+                }
+                let schema = (self.catalog.table(&parent))
+                    .ok_or_else(|| catalog::Error::TableNotFound(parent.clone()))?
+                    .schema
+                    .clone();
+                self.catalog.create_table(&self.bufmgr, &name, schema)?;
+                if let Err(e) = self
+                    .catalog
+                    .attach_partition(&self.bufmgr, &parent, &name, bound)
+                {
+                    self.catalog.drop_table(&self.bufmgr, &name)?;
+                    return Err(e.into());
+                }
+            }
+            BoundStatement::AttachPartition {
+                table,
+                partition,
+                bound,
+            } => {
+                self.catalog
+                    .attach_partition(&self.bufmgr, &table, &partition, bound)?;
+            }
+            BoundStatement::DetachPartition { table, partition } => {
+                self.catalog
+                    .detach_partition(&self.bufmgr, &table, &partition)?;
+            }
+            BoundStatement::SetTtl { table, ttl } => {
+                self.catalog.set_ttl(&self.bufmgr, &table, ttl)?;
+            }
+            BoundStatement::CreateIndex {
+                index,
+                if_not_exists,
+                concurrently: _,
+            } => {
+                let exists = self.catalog.index_table(&index.name).is_some();
+                if !(if_not_exists && exists) {
+                    self.create_index(&index)?;
+                }
+            }
+            BoundStatement::DropTable { name, if_exists } => {
+                if !(if_exists && self.catalog.table(&name).is_none()) {
+                    self.catalog.drop_table(&self.bufmgr, &name)?;
+                }
+            }
+            BoundStatement::DropMaterializedView { name, if_exists } => {
+                if !(if_exists && self.catalog.table(&name).is_none()) {
+                    self.catalog.drop_materialized_view(&self.bufmgr, &name)?;
+                }
+            }
+            BoundStatement::CreateTrigger { table, trigger } => {
+                if let catalog::TriggerAction::Function(function) = &trigger.action {
+                    if !self.trigger_functions.contains_key(function) {
+                        return Err(Error::UnknownTriggerFunction(function.clone()));
+                    }
+                }
+                self.catalog.create_trigger(&self.bufmgr, &table, trigger)?;
+            }
+            BoundStatement::DropTrigger { name, if_exists } => {
+                match self.catalog.drop_trigger(&self.bufmgr, &name) {
+                    Err(catalog::Error::TriggerNotFound(_)) if if_exists => {}
+                    result => {
+                        result?;
+                    }
+                }
+            }
+            BoundStatement::CreatePolicy { table, policy } => {
+                self.catalog.create_policy(&self.bufmgr, &table, policy)?;
+            }
+            BoundStatement::DropPolicy {
+                table,
+                name,
+                if_exists,
+            } => match self.catalog.drop_policy(&self.bufmgr, &table, &name) {
+                Err(catalog::Error::PolicyNotFound { .. }) if if_exists => {}
+                result => {
+                    result?;
+                }
+            },
+            BoundStatement::DropIndex { name, if_exists } => {
+                match self.catalog.drop_index(&self.bufmgr, &name) {
+                    Err(catalog::Error::IndexNotFound(_)) if if_exists => {}
+                    result => {
+                        result?;
+                    }
+                }
+            }
+            BoundStatement::Analyze { tables } => {
+                for table in tables {
+                    self.catalog.analyze(&self.bufmgr, &table)?;
+                }
+            }
+            BoundStatement::Reindex { indexes } => {
+                for index in indexes {
+                    self.catalog.reindex(&self.bufmgr, &index)?;
+                }
+            }
+            BoundStatement::CreateUser(user) => {
+                self.catalog.create_user(&self.bufmgr, user)?;
+            }
+            BoundStatement::AlterUser {
+                name,
+                verifier,
+                superuser,
+            } => {
+                self.catalog.alter_user(&self.bufmgr, &name, |user| {
+                    if let Some(verifier) = verifier {
+                        user.verifier = verifier;
+                    }
+                    if let Some(superuser) = superuser {
+                        user.superuser = superuser;
+                    }
+                })?;
+            }
+            BoundStatement::DropUser { name, if_exists } => {
+                if !(if_exists && self.catalog.user(&name).is_none()) {
+                    self.catalog.drop_user(&self.bufmgr, &name)?;
+                }
+            }
+            BoundStatement::Grant(grant) => {
+                for table in &grant.tables {
+                    for user in &grant.users {
+                        if !grant.privileges.is_empty() {
+                            self.catalog
+                                .grant(&self.bufmgr, table, user, grant.privileges)?;
+                        }
+                        if !grant.columns.is_empty() {
+                            self.catalog.grant_columns(
+                                &self.bufmgr,
+                                table,
+                                user,
+                                &grant.columns,
+                            )?;
+                        }
+                    }
+                }
+            }
+            BoundStatement::Revoke(grant) => {
+                for table in &grant.tables {
+                    for user in &grant.users {
+                        self.catalog
+                            .revoke(&self.bufmgr, table, user, grant.privileges)?;
+                        if !grant.columns.is_empty() {
+                            self.catalog.revoke_columns(
+                                &self.bufmgr,
+                                table,
+                                user,
+                                &grant.columns,
+                            )?;
+                        }
+                    }
+                }
+            }
+            BoundStatement::Query(_)
+            | BoundStatement::AsOf { .. }
+            | BoundStatement::Insert { .. }
+            | BoundStatement::Update { .. }
+            | BoundStatement::Delete { .. }
+            | BoundStatement::Explain { .. }
+            | BoundStatement::Transaction(_)
+            | BoundStatement::Set { .. }
+            | BoundStatement::Show { .. }
+            | BoundStatement::CopyFrom { .. }
+            | BoundStatement::CopyTo { .. }
+            | BoundStatement::Attach { .. }
+            | BoundStatement::Detach { .. }
+            | BoundStatement::Backup { .. }
+            | BoundStatement::Notify { .. }
+            | BoundStatement::Listen { .. }
+            | BoundStatement::Unlisten { .. }
+            | BoundStatement::CreateMaterializedView { .. }
+            | BoundStatement::RefreshMaterializedView { .. } => unreachable!("planned separately"),
+        }
+        Ok(())
+    }
+
+    fn create_index(&mut self, index: &IndexDef) -> Result<(), Error> {
+        self.catalog.create_expr_index(
+            &self.bufmgr,
+            &index.table,
+            &index.name,
+            index.keys.clone(),
+            index.predicate.clone(),
+            index.unique,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::Array;
+    use crate::catalog::Privileges;
+    use crate::disk::DiskManager;
+    use crate::json::Json;
+    use std::sync::Mutex;
+    use tempfile::tempfile;
+
+    pub(super) fn engine() -> Engine {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        Engine::new(BufferPoolManager::new(disk, 32))
+    }
+
+    #[test]
+    fn test_execute() {
+        let mut engine = engine();
+        let done =
+            |engine: &mut Engine, sql| assert_eq!(Output::Done, engine.execute(sql).unwrap());
+        done(
+            &mut engine,
+            "CREATE TABLE t (id INT PRIMARY KEY, name TEXT, score FLOAT)",
+        );
+        done(&mut engine, "CREATE TABLE IF NOT EXISTS t (id INT)");
+        assert_eq!(
+            Output::Affected(3),
+            engine
+                .execute("INSERT INTO t VALUES (1, 'a', 1.5), (2, 'b', 2), (3, 'c', NULL)")
+                .unwrap()
+        );
+        assert_eq!(
+            Output::Affected(1),
+            engine
+                .execute("UPDATE t SET score = score + 1 WHERE id = 2")
+                .unwrap()
+        );
+        assert_eq!(
+            Output::Affected(1),
+            engine.execute("DELETE FROM t WHERE name = 'c'").unwrap()
+        );
+        let Output::Rows { fields, rows } = engine
+            .execute("SELECT name, score FROM t ORDER BY id")
+            .unwrap()
+        else {
+            panic!("not rows");
+        };
+        assert_eq!(
+            vec![
+                Field::new("name", Some(DataType::Text)),
+                Field::new("score", Some(DataType::Float))
+            ],
+            fields
+        );
+        assert_eq!(
+            vec![
+                vec![Value::from("a"), 1.5.into()],
+                vec![Value::from("b"), 3.0.into()]
+            ],
+            rows
+        );
+        assert!(matches!(
+            engine.execute("INSERT INTO t VALUES (1, 'dup', 0)"),
+            Err(Error::Execute(executor::Error::UniqueViolation(_)))
+        ));
+
+        done(&mut engine, "CREATE INDEX t_name ON t (name)");
+        done(&mut engine, "ANALYZE");
+        let plan = engine
+            .execute("EXPLAIN SELECT id FROM t WHERE id = 1")
+            .unwrap()
+            .into_rows();
+        assert!(!plan.is_empty());
+        done(&mut engine, "DROP INDEX t_name");
+        done(&mut engine, "DROP INDEX IF EXISTS t_name");
+        done(&mut engine, "DROP TABLE t");
+        assert!(engine.catalog().table("t").is_none());
+        assert!(matches!(
+            engine.execute("SELECT * FROM t"),
+            Err(Error::Plan(planner::Error::TableNotFound(_)))
+        ));
+        assert!(matches!(engine.execute("SELEC 1"), Err(Error::Syntax(_))));
+    }
+
+    #[test]
+    fn test_hierarchy_query() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE staff (id INT PRIMARY KEY, name TEXT, boss INT)")
+            .unwrap();
+        engine
+            .execute(
+                "INSERT INTO staff VALUES (1, 'ceo', NULL), (2, 'cto', 1), (3, 'cfo', 1), \
+                 (4, 'dev', 2), (5, 'intern', 4), (6, 'clerk', 3)",
+            )
+            .unwrap();
+        let sql = "WITH RECURSIVE reports (id, name, depth) AS ( \
+                       SELECT id, name, 0 FROM staff WHERE id = 2 \
+                       UNION ALL \
+                       SELECT s.id, s.name, r.depth + 1 FROM staff s JOIN reports r ON s.boss = r.id) \
+                   SELECT name, depth FROM reports ORDER BY depth";
+        assert_eq!(
+            vec![
+                vec![Value::from("cto"), 0.into()],
+                vec![Value::from("dev"), 1.into()],
+                vec![Value::from("intern"), 2.into()],
+            ],
+            engine.execute(sql).unwrap().into_rows()
+        );
+
+        let text: Vec<_> = engine
+            .execute(&format!("EXPLAIN ANALYZE {sql}"))
+            .unwrap()
+            .into_rows()
+            .into_iter()
+            .map(|row| row[0].to_string())
+            .collect();
+        let text = text.join("\n");
+        assert!(text.contains("Recursive Union All #0"), "{text}");
+        // One round per level, and a last one that finds nothing.
+        assert!(
+            text.lines()
+                .any(|line| line.contains("Work Table Scan #0") && line.contains("loops=3")),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn test_expression_and_partial_indexes() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE users (id INT PRIMARY KEY, name TEXT, active BOOL)")
+            .unwrap();
+        let values: Vec<String> = (0..2000)
+            .map(|i| format!("({i}, 'User{i}', {})", i % 10 == 0))
+            .collect();
+        engine
+            .execute(&format!("INSERT INTO users VALUES {}", values.join(", ")))
+            .unwrap();
+        engine
+            .execute("CREATE UNIQUE INDEX ON users ((lower(name))) WHERE active")
+            .unwrap();
+        engine.execute("ANALYZE").unwrap();
+        let index = &engine.catalog().table("users").unwrap().indexes[1];
+        assert_eq!("users_expr_idx", index.name);
+
+        let explain = |engine: &mut Engine, sql: &str| -> String {
+            let rows = engine.execute(&format!("EXPLAIN {sql}")).unwrap();
+            let lines: Vec<_> = rows
+                .into_rows()
+                .iter()
+                .map(|row| row[0].to_string())
+                .collect();
+            lines.join("\n")
+        };
+        let select = "SELECT id FROM users WHERE lower(name) = 'user30' AND active";
+        assert!(explain(&mut engine, select).contains("Index Scan using users_expr_idx"));
+        assert_eq!(
+            vec![vec![Value::from(30)]],
+            engine.execute(select).unwrap().into_rows()
+        );
+        // The index has no entries for inactive users, so it cannot answer
+        // a query that does not require `active`.
+        let all = "SELECT id FROM users WHERE lower(name) = 'user31'";
+        assert!(!explain(&mut engine, all).contains("users_expr_idx"));
+        assert_eq!(
+            vec![vec![Value::from(31)]],
+            engine.execute(all).unwrap().into_rows()
+        );
+
+        // Uniqueness holds among active users alone.
+        engine
+            .execute("INSERT INTO users VALUES (5000, 'USER31', TRUE)")
+            .unwrap();
+        assert!(matches!(
+            engine.execute("INSERT INTO users VALUES (5001, 'user30', TRUE)"),
+            Err(Error::Execute(executor::Error::UniqueViolation(_)))
+        ));
+        engine
+            .execute("UPDATE users SET active = FALSE WHERE id = 30")
+            .unwrap();
+        engine
+            .execute("INSERT INTO users VALUES (5001, 'user30', TRUE)")
+            .unwrap();
+        assert_eq!(
+            vec![vec![Value::from(5001)]],
+            engine.execute(select).unwrap().into_rows()
+        );
+        engine.execute("DELETE FROM users WHERE id = 5001").unwrap();
+        assert!(engine.execute(select).unwrap().into_rows().is_empty());
+
+        assert!(matches!(
+            engine.execute("CREATE INDEX ON users (name, name)"),
+            Err(Error::Plan(planner::Error::DuplicateColumn(_)))
+        ));
+        assert!(matches!(
+            engine.execute("CREATE INDEX ON users ((lower(id)))"),
+            Err(Error::Plan(planner::Error::FunctionType { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_json() {
+        let mut engine = engine();
+        let rows = |engine: &mut Engine, sql: &str| engine.execute(sql).unwrap().into_rows();
+        engine
+            .execute("CREATE TABLE events (id INT PRIMARY KEY, doc JSON)")
+            .unwrap();
+        let values: Vec<String> = (0..500)
+            .map(|i| format!(r#"({i}, '{{"user": {{"name": "u{i}"}}, "tags": [{i}, "t"]}}')"#))
+            .collect();
+        engine
+            .execute(&format!("INSERT INTO events VALUES {}", values.join(", ")))
+            .unwrap();
+        engine
+            .execute(r#"INSERT INTO events VALUES (-1, '{"b": null, "a": 1, "a": 2.5}')"#)
+            .unwrap();
+
+        // Objects are stored with their keys sorted, the last of a
+        // duplicate key winning.
+        assert_eq!(
+            vec![vec![Value::from(r#"{"a": 2.5, "b": null}"#)]],
+            rows(
+                &mut engine,
+                "SELECT CAST(doc AS TEXT) FROM events WHERE id = -1"
+            )
+        );
+        assert_eq!(
+            vec![vec![
+                Value::json(Json::String("u7".into())),
+                Value::from("u7"),
+                Value::from(7),
+                Value::from("t"),
+                Value::from("object"),
+            ]],
+            rows(
+                &mut engine,
+                "SELECT doc -> 'user' -> 'name', doc -> 'user' ->> 'name', \
+                 CAST(doc -> 'tags' -> 0 AS INT), doc -> 'tags' ->> -1, json_typeof(doc) \
+                 FROM events WHERE id = 7"
+            )
+        );
+        // A missing member or element is NULL, and so is a JSON null.
+        assert_eq!(
+            vec![vec![Value::Null, Value::Null, Value::Null]],
+            rows(
+                &mut engine,
+                "SELECT doc -> 'user', doc -> 'a' -> 0, doc ->> 'b' FROM events WHERE id = -1"
+            )
+        );
+
+        engine
+            .execute("CREATE UNIQUE INDEX events_name ON events ((doc -> 'user' ->> 'name'))")
+            .unwrap();
+        engine.execute("ANALYZE").unwrap();
+        let select = "SELECT id FROM events WHERE doc -> 'user' ->> 'name' = 'u42'";
+        let plan = rows(&mut engine, &format!("EXPLAIN {select}"));
+        assert!(plan
+            .iter()
+            .any(|line| line[0].to_string().contains("Index Scan using events_name")));
+        assert_eq!(vec![vec![Value::from(42)]], rows(&mut engine, select));
+
+        assert!(matches!(
+            engine.execute("INSERT INTO events VALUES (-2, '{\"a\": }')"),
+            Err(Error::Execute(_))
+        ));
+        assert!(matches!(
+            engine.execute("SELECT id -> 'a' FROM events"),
+            Err(Error::Plan(_))
+        ));
+    }
+
+    #[test]
+    fn test_arrays() {
+        let mut engine = engine();
+        let rows = |engine: &mut Engine, sql: &str| engine.execute(sql).unwrap().into_rows();
+        let ints = |items: &[i64]| {
+            let items = items.iter().map(|&i| Value::Int(i)).collect();
+            Value::array(Array::new(DataType::Int, items))
+        };
+        engine
+            .execute("CREATE TABLE posts (id INT PRIMARY KEY, tags TEXT[], scores INT[])")
+            .unwrap();
+        for values in [
+            "(1, ARRAY['rust', 'db'], ARRAY[3, 1, 2]), (3, NULL, ARRAY[5])",
+            "(2, '{sql,\"a b\",NULL}', '{}')",
+        ] {
+            engine
+                .execute(&format!("INSERT INTO posts VALUES {values}"))
+                .unwrap();
+        }
+
+        assert_eq!(
+            vec![
+                vec![
+                    Value::from("rust"),
+                    Value::Null,
+                    Value::from(2),
+                    Value::from(2)
+                ],
+                vec![Value::from("a b"), Value::Null, Value::from(3), Value::Null],
+            ],
+            rows(
+                &mut engine,
+                "SELECT tags[id], tags[0], cardinality(tags), scores[3] FROM posts \
+                 WHERE id < 3 ORDER BY id"
+            )
+        );
+        assert_eq!(
+            vec![vec![Value::from(1)]],
+            rows(&mut engine, "SELECT id FROM posts WHERE 'db' = ANY (tags)")
+        );
+        // Comparisons with NULL elements leave ANY unknown rather than false.
+        assert_eq!(
+            vec![vec![Value::Bool(false), Value::Null, Value::Bool(true)]],
+            rows(
+                &mut engine,
+                "SELECT 'x' = ANY (tags), 'x' = ANY (ARRAY['y', NULL]), 0 < ALL (scores) \
+                 FROM posts WHERE id = 1"
+            )
+        );
+        assert_eq!(
+            vec![
+                vec![Value::from(1), Value::from("rust")],
+                vec![Value::from(1), Value::from("db")],
+                vec![Value::from(2), Value::from("sql")],
+                vec![Value::from(2), Value::from("a b")],
+                vec![Value::from(2), Value::Null],
+            ],
+            rows(
+                &mut engine,
+                "SELECT p.id, tag FROM posts p, unnest(p.tags) AS tag ORDER BY p.id"
+            )
+        );
+        assert_eq!(
+            vec![vec![Value::from(6)]],
+            rows(
+                &mut engine,
+                "SELECT sum(n) FROM unnest(ARRAY[1, 2, 3]) AS u (n)"
+            )
+        );
+        assert_eq!(
+            vec![vec![
+                ints(&[3, 1, 2, 5]),
+                Value::from("{3,1,2}"),
+                ints(&[]),
+                Value::array(Array::new(
+                    DataType::Float,
+                    vec![Value::Float(1.0), Value::Float(2.5)]
+                )),
+            ]],
+            rows(
+                &mut engine,
+                "SELECT scores || ARRAY[5], CAST(scores AS TEXT), CAST(ARRAY[] AS INT[]), \
+                 ARRAY[1, 2.5] FROM posts WHERE id = 1"
+            )
+        );
+
+        for sql in [
+            "SELECT ARRAY[]",
+            "SELECT ARRAY[1, 'a']",
+            "SELECT tags[1] FROM posts WHERE scores = ARRAY['a']",
+            "SELECT * FROM unnest(1) AS u",
+            "SELECT 1 = ANY (scores || tags) FROM posts",
+        ] {
+            assert!(matches!(engine.execute(sql), Err(Error::Plan(_))), "{sql}");
+        }
+        assert!(matches!(
+            engine.execute("INSERT INTO posts VALUES (4, '{unclosed', NULL)"),
+            Err(Error::Execute(_))
+        ));
+    }
+
+    #[test]
+    fn test_collations() {
+        let mut engine = engine();
+        let rows = |engine: &mut Engine, sql: &str| engine.execute(sql).unwrap().into_rows();
+        engine
+            .execute(
+                "CREATE TABLE users \
+                 (id INT PRIMARY KEY, name TEXT UNIQUE COLLATE NoCase, code TEXT)",
+            )
+            .unwrap();
+        let values: Vec<String> = (0..1000)
+            .map(|i| format!("({i}, 'User{i}', 'c')"))
+            .collect();
+        engine
+            .execute(&format!("INSERT INTO users VALUES {}", values.join(", ")))
+            .unwrap();
+        engine
+            .execute("INSERT INTO users VALUES (1000, 'bob', 'b'), (1001, 'Carol', 'B')")
+            .unwrap();
+        engine.execute("ANALYZE").unwrap();
+        assert!(matches!(
+            engine.execute("INSERT INTO users VALUES (1002, 'BOB', 'x')"),
+            Err(Error::Execute(executor::Error::UniqueViolation(_)))
+        ));
+
+        let select = "SELECT id FROM users WHERE name = 'USER42'";
+        let plan = rows(&mut engine, &format!("EXPLAIN {select}"));
+        assert!(plan.iter().any(|line| line[0]
+            .to_string()
+            .contains("Index Scan using users_name_key")));
+        assert_eq!(vec![vec![Value::from(42)]], rows(&mut engine, select));
+        // An explicit COLLATE outranks the column's.
+        assert!(rows(
+            &mut engine,
+            "SELECT id FROM users WHERE name COLLATE binary = 'USER42'"
+        )
+        .is_empty());
+        assert_eq!(
+            vec![vec![Value::from(1000)], vec![Value::from(1001)]],
+            rows(
+                &mut engine,
+                "SELECT id FROM users WHERE code COLLATE nocase IN ('B') ORDER BY id"
+            )
+        );
+        assert_eq!(
+            vec![
+                vec![Value::from("bob")],
+                vec![Value::from("Carol")],
+                vec![Value::from("User999")]
+            ],
+            rows(
+                &mut engine,
+                "SELECT name FROM users WHERE name > 'User998' OR id >= 1000 ORDER BY name"
+            )
+        );
+        // Binary by default, but a collated column keeps its collation
+        // through the subquery.
+        assert_eq!(
+            vec![
+                vec![Value::from("B"), Value::from("Carol")],
+                vec![Value::from("b"), Value::from("bob")]
+            ],
+            rows(
+                &mut engine,
+                "SELECT * FROM (SELECT code, name FROM users WHERE id >= 1000) AS s \
+                 ORDER BY code"
+            )
+        );
+        assert_eq!(
+            vec![vec![Value::from("bob")], vec![Value::from("Carol")]],
+            rows(
+                &mut engine,
+                "SELECT name FROM (SELECT name FROM users WHERE id >= 1000) AS s \
+                 ORDER BY name"
+            )
+        );
+
+        for sql in [
+            "CREATE TABLE bad (n INT COLLATE nocase)",
+            "CREATE TABLE bad (t TEXT COLLATE klingon)",
+            "SELECT id COLLATE nocase FROM users",
+        ] {
+            assert!(matches!(engine.execute(sql), Err(Error::Plan(_))), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_planner_hints() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, v TEXT)")
+            .unwrap();
+        let values: Vec<String> = (0..5000).map(|i| format!("({i}, 'v{i}')")).collect();
+        engine
+            .execute(&format!("INSERT INTO t VALUES {}", values.join(", ")))
+            .unwrap();
+        engine.execute("ANALYZE t").unwrap();
+        let plan = |engine: &mut Engine, sql: &str| -> String {
+            let rows = engine.execute(&format!("EXPLAIN {sql}")).unwrap();
+            let lines: Vec<_> = rows
+                .into_rows()
+                .iter()
+                .map(|row| row[0].to_string())
+                .collect();
+            lines.join("\n")
+        };
+        let select = "SELECT v FROM t WHERE id = 7";
+        assert!(plan(&mut engine, select).contains("Index Scan"));
+        let hinted = format!("/*+ SeqScan(t) */ {select}");
+        assert!(plan(&mut engine, &hinted).contains("Seq Scan on t"));
+        assert_eq!(
+            vec![vec![Value::from("v7")]],
+            engine.execute(&hinted).unwrap().into_rows()
+        );
+        // The hint applies to its statement alone.
+        assert!(plan(&mut engine, select).contains("Index Scan"));
+
+        let mut settings = engine.planner_settings().clone();
+        settings.set("enable_indexscan", "off").unwrap();
+        engine.set_planner_settings(settings);
+        assert!(engine.plan_cache().is_empty());
+        assert!(plan(&mut engine, select).contains("Seq Scan on t"));
+        let hinted = format!("/*+ IndexScan(t) */ {select}");
+        assert!(plan(&mut engine, &hinted).contains("Index Scan"));
+
+        engine.execute("SET enable_indexscan TO on").unwrap();
+        assert!(plan(&mut engine, select).contains("Index Scan"));
+        engine.execute("SET statement_timeout = '5s'").unwrap();
+        let show = |engine: &mut Engine, name: &str| {
+            let rows = engine.execute(&format!("SHOW {name}")).unwrap().into_rows();
+            rows[0][0].to_string()
+        };
+        assert_eq!("on", show(&mut engine, "enable_indexscan"));
+        assert_eq!(Some(Duration::from_secs(5)), engine.statement_timeout());
+        let all = engine.execute("SHOW ALL").unwrap().into_rows();
+        assert_eq!(SessionSettings::names().count(), all.len());
+        // RESET goes back to what the engine was configured with.
+        engine.execute("RESET ALL").unwrap();
+        assert_eq!("off", show(&mut engine, "enable_indexscan"));
+        assert_eq!("0", show(&mut engine, "statement_timeout"));
+        assert!(matches!(
+            engine.execute("SET work_mem = 1"),
+            Err(Error::Plan(planner::Error::InvalidSetting { .. }))
+        ));
+        assert!(matches!(
+            engine.execute("SHOW nope"),
+            Err(Error::Plan(planner::Error::UnknownSetting(_)))
+        ));
+
+        assert!(matches!(
+            engine.execute("/*+ NoSuchHint */ SELECT 1"),
+            Err(Error::Plan(planner::Error::UnknownHint(_)))
+        ));
+        assert!(matches!(
+            engine.execute("/*+ SeqScan( */ SELECT 1"),
+            Err(Error::Syntax(sql::Error::InvalidHint { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_plan_cache() {
+        let mut engine = engine().with_plan_cache_capacity(2);
+        engine.execute("CREATE TABLE t (id INT, v TEXT)").unwrap();
+        engine.execute("INSERT INTO t VALUES (1, 'a')").unwrap();
+        assert_eq!(1, engine.plan_cache().len());
+        let select = "SELECT v FROM t WHERE id = 1";
+        engine.execute(select).unwrap();
+        engine.execute("select v\n  from T where ID=1;").unwrap();
+        assert_eq!(
+            (1, 2),
+            (engine.plan_cache().hits(), engine.plan_cache().len())
+        );
+
+        // The cached plan must not outlive the table it reads.
+        engine.execute("DROP TABLE t").unwrap();
+        assert!(engine.plan_cache().is_empty());
+        engine.execute("CREATE TABLE t (v TEXT, id INT)").unwrap();
+        engine.execute("INSERT INTO t VALUES ('b', 1)").unwrap();
+        assert_eq!(
+            vec![vec![Value::from("b")]],
+            engine.execute(select).unwrap().into_rows()
+        );
+
+        engine.set_plan_cache_capacity(0);
+        engine.execute(select).unwrap();
+        assert!(engine.plan_cache().is_empty());
+    }
+
+    #[test]
+    fn test_cancel_and_statement_timeout() {
+        let mut engine = engine();
+        let count = "WITH RECURSIVE n (i) AS ( \
+                         SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 100000000) \
+                     SELECT count(*) FROM n";
+        engine.set_statement_timeout(Some(Duration::from_millis(10)));
+        assert!(matches!(
+            engine.execute(count),
+            Err(Error::Execute(executor::Error::StatementTimeout(_)))
+        ));
+
+        engine.set_statement_timeout(None);
+        let token = engine.cancel_token();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            token.cancel();
+        });
+        assert!(matches!(
+            engine.execute(count),
+            Err(Error::Execute(executor::Error::Cancelled))
+        ));
+        canceller.join().unwrap();
+        // The cancellation does not carry over to the next statement.
+        assert_eq!(
+            vec![vec![Value::Int(1)]],
+            engine.execute("SELECT 1").unwrap().into_rows()
+        );
+    }
+
+    #[test]
+    fn test_reopen() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, name TEXT NOT NULL)",
+            "CREATE TABLE gone (x INT)",
+            "CREATE INDEX ON t (lower(name)) WHERE id > 1",
+            "CREATE INDEX t_name ON t (name)",
+            "INSERT INTO t VALUES (1, 'Ann'), (2, 'Bob'), (3, 'Cy')",
+            "DROP INDEX t_name",
+            "DROP TABLE gone",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        engine.bufmgr().flush().unwrap();
+        let before = engine.catalog().table("t").unwrap().clone();
+        drop(engine);
+
+        let mut engine = open();
+        let table = engine.catalog().table("t").unwrap();
+        assert_eq!(before.schema, table.schema);
+        let indexes = |table: &catalog::TableInfo| {
+            table
+                .indexes
+                .iter()
+                .map(|index| {
+                    (
+                        index.name.clone(),
+                        index.keys.clone(),
+                        index.predicate.clone(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(indexes(&before), indexes(table));
+        assert!(engine.catalog().table("gone").is_none());
+        assert!(matches!(
+            engine.execute("INSERT INTO t VALUES (3, 'Dee')"),
+            Err(Error::Execute(executor::Error::UniqueViolation(_)))
+        ));
+        let select = "/*+ IndexScan(t) */ SELECT id FROM t WHERE lower(name) = 'bob' AND id > 1";
+        assert!(engine
+            .execute(&format!("EXPLAIN {select}"))
+            .unwrap()
+            .into_rows()
+            .iter()
+            .any(|row| row[0].to_string().contains("Index Scan using t_expr_idx")));
+        assert_eq!(
+            vec![vec![Value::Int(2)]],
+            engine.execute(select).unwrap().into_rows()
+        );
+    }
+
+    #[test]
+    fn test_partitioned_tables() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 64)).unwrap()
+        };
+        let mut engine = open();
+        for sql in [
+            "CREATE TABLE events (at INT, what TEXT) PARTITION BY RANGE (at)",
+            "CREATE TABLE events_old PARTITION OF events FOR VALUES FROM (MINVALUE) TO (100)",
+            "CREATE TABLE events_new PARTITION OF events FOR VALUES FROM (100) TO (200)",
+            "CREATE TABLE buckets (id INT, n INT) PARTITION BY HASH (id)",
+            "CREATE TABLE buckets_0 PARTITION OF buckets FOR VALUES WITH (MODULUS 2, REMAINDER 0)",
+            "CREATE TABLE buckets_1 PARTITION OF buckets FOR VALUES WITH (MODULUS 2, REMAINDER 1)",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        let mut engine = open();
+        engine
+            .execute("INSERT INTO events VALUES (5, 'a'), (150, 'b'), (99, 'c')")
+            .unwrap();
+        let rows = |engine: &mut Engine, sql: &str| engine.execute(sql).unwrap().into_rows();
+        assert_eq!(
+            vec![vec![Value::from(5)], vec![Value::from(99)]],
+            rows(&mut engine, "SELECT at FROM events_old ORDER BY at")
+        );
+        assert_eq!(
+            vec![vec![Value::from(3)]],
+            rows(&mut engine, "SELECT count(*) FROM events")
+        );
+        assert!(matches!(
+            engine.execute("INSERT INTO events VALUES (200, 'late')"),
+            Err(Error::Execute(executor::Error::NoPartition(_)))
+        ));
+        assert!(matches!(
+            engine.execute("INSERT INTO events_new VALUES (5, 'early')"),
+            Err(Error::Execute(executor::Error::PartitionConstraint(_)))
+        ));
+        assert!(matches!(
+            engine.execute("UPDATE events SET at = 120 WHERE at = 5"),
+            Err(Error::Execute(executor::Error::PartitionConstraint(_)))
+        ));
+
+        let explain = |engine: &mut Engine, sql: &str| -> String {
+            let rows = engine.execute(&format!("EXPLAIN {sql}")).unwrap();
+            let lines: Vec<_> = (rows.into_rows().iter())
+                .map(|row| row[0].to_string())
+                .collect();
+            lines.join("\n")
+        };
+        let plan = explain(&mut engine, "SELECT * FROM events WHERE at >= 120");
+        assert!(plan.contains("Seq Scan on events_new") && !plan.contains("events_old"));
+        let plan = explain(&mut engine, "SELECT * FROM events WHERE at = 300");
+        assert!(plan.contains("Values (0 rows)"));
+        assert!(explain(&mut engine, "SELECT * FROM events").contains("Append"));
+        assert_eq!(
+            Output::Affected(1),
+            engine
+                .execute("UPDATE events SET what = 'z' WHERE at > 100")
+                .unwrap()
+        );
+        assert_eq!(
+            Output::Affected(2),
+            engine.execute("DELETE FROM events WHERE at < 100").unwrap()
+        );
+
+        let values: Vec<String> = (0..20).map(|i| format!("({i}, {i})")).collect();
+        engine
+            .execute(&format!("INSERT INTO buckets VALUES {}", values.join(", ")))
+            .unwrap();
+        let count = |engine: &mut Engine, table: &str| match rows(
+            engine,
+            &format!("SELECT count(*) FROM {table}"),
+        )[0][0]
+        {
+            Value::Int(n) => n,
+            _ => unreachable!(),
+        };
+        let (zero, one) = (
+            count(&mut engine, "buckets_0"),
+            count(&mut engine, "buckets_1"),
+        );
+        assert!(zero > 0 && one > 0);
+        assert_eq!(20, zero + one);
+        assert!(!explain(&mut engine, "SELECT n FROM buckets WHERE id = 7").contains("Append"));
+        assert_eq!(
+            vec![vec![Value::from(7)]],
+            rows(&mut engine, "SELECT n FROM buckets WHERE id = 7")
+        );
+
+        // A detached partition keeps its rows, and one attached again must
+        // fit its bound.
+        engine
+            .execute("ALTER TABLE events DETACH PARTITION events_new")
+            .unwrap();
+        assert_eq!(
+            vec![vec![Value::from(0)]],
+            rows(&mut engine, "SELECT count(*) FROM events")
+        );
+        assert!(matches!(
+            engine.execute(
+                "ALTER TABLE events ATTACH PARTITION events_new FOR VALUES FROM (0) TO (100)"
+            ),
+            Err(Error::Catalog(catalog::Error::PartitionOverlap { .. }))
+        ));
+        assert!(matches!(
+            engine.execute(
+                "ALTER TABLE events ATTACH PARTITION events_new FOR VALUES FROM (100) TO (150)"
+            ),
+            Err(Error::Catalog(catalog::Error::RowOutsideBound(_)))
+        ));
+        engine
+            .execute("ALTER TABLE events ATTACH PARTITION events_new FOR VALUES FROM (100) TO (MAXVALUE)")
+            .unwrap();
+        assert_eq!(
+            vec![vec![Value::from("z")]],
+            rows(&mut engine, "SELECT what FROM events WHERE at = 150")
+        );
+        assert!(engine.execute("CREATE INDEX ON events (at)").is_err());
+        engine.execute("DROP TABLE events").unwrap();
+        assert!(engine.catalog().table("events_old").is_none());
+    }
+
+    #[test]
+    fn test_materialized_view() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        engine.execute("CREATE TABLE t (name TEXT, n INT)").unwrap();
+        engine
+            .execute("INSERT INTO t VALUES ('a', 1), ('b', 2), ('a', 3)")
+            .unwrap();
+        assert_eq!(
+            Output::Affected(2),
+            engine
+                .execute("CREATE MATERIALIZED VIEW v AS SELECT name, sum(n) AS total FROM t GROUP BY name")
+                .unwrap()
+        );
+        engine
+            .execute("CREATE MATERIALIZED VIEW w AS SELECT total FROM v WHERE name = 'a'")
+            .unwrap();
+        let totals = |engine: &mut Engine| {
+            engine
+                .execute("SELECT name, total FROM v ORDER BY name")
+                .unwrap()
+                .into_rows()
+        };
+        let a = |n: i64| vec![Value::from("a"), Value::Int(n)];
+        assert_eq!(
+            vec![a(4), vec!["b".into(), Value::Int(2)]],
+            totals(&mut engine)
+        );
+
+        // The view keeps its rows until refreshed, and only refresh
+        // changes them.
+        engine.execute("INSERT INTO t VALUES ('a', 5)").unwrap();
+        assert_eq!(a(4), totals(&mut engine)[0]);
+        assert_eq!(
+            Output::Affected(2),
+            engine.execute("REFRESH MATERIALIZED VIEW v").unwrap()
+        );
+        assert_eq!(a(9), totals(&mut engine)[0]);
+        // Refreshing again reuses the pages the old rows are freed from.
+        let pages = engine.bufmgr().num_pages();
+        for _ in 0..3 {
+            engine.execute("REFRESH MATERIALIZED VIEW v").unwrap();
+        }
+        assert_eq!(pages, engine.bufmgr().num_pages());
+        assert!(matches!(
+            engine.execute("INSERT INTO v VALUES ('c', 1)"),
+            Err(Error::Plan(planner::Error::ViewNotWritable(_)))
+        ));
+        assert!(matches!(
+            engine.execute("REFRESH MATERIALIZED VIEW t"),
+            Err(Error::Plan(planner::Error::NotAView(_)))
+        ));
+        assert!(matches!(
+            engine.execute("DROP TABLE t"),
+            Err(Error::Catalog(catalog::Error::HasDependents { .. }))
+        ));
+        assert!(matches!(
+            engine.execute("DROP TABLE v"),
+            Err(Error::Catalog(catalog::Error::NotATable(_)))
+        ));
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        let mut engine = open();
+        assert_eq!(a(9), totals(&mut engine)[0]);
+        engine.execute("DELETE FROM t WHERE name = 'a'").unwrap();
+        engine.execute("REFRESH MATERIALIZED VIEW v").unwrap();
+        assert_eq!(1, totals(&mut engine).len());
+        engine.execute("REFRESH MATERIALIZED VIEW w").unwrap();
+        assert!(engine
+            .execute("SELECT * FROM w")
+            .unwrap()
+            .into_rows()
+            .is_empty());
+        engine.execute("DROP MATERIALIZED VIEW w").unwrap();
+        engine.execute("DROP MATERIALIZED VIEW v").unwrap();
+        engine
+            .execute("DROP MATERIALIZED VIEW IF EXISTS v")
+            .unwrap();
+        engine.execute("DROP TABLE t").unwrap();
+    }
+
+    #[test]
+    fn test_transaction() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY)")
+            .unwrap();
+        let count = engine.prepare("SELECT count(*) FROM t").unwrap();
+        let count = |engine: &mut Engine| engine.execute_prepared(&count, &[]).unwrap().into_rows();
+
+        engine.begin().unwrap();
+        assert!(matches!(engine.begin(), Err(Error::TransactionActive)));
+        engine.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+        engine.execute("CREATE TABLE u (x INT)").unwrap();
+        engine.execute("DROP TABLE t").unwrap();
+        engine.rollback().unwrap();
+        assert!(engine.catalog().table("u").is_none());
+        assert_eq!(vec![vec![Value::Int(0)]], count(&mut engine));
+
+        engine.execute("BEGIN").unwrap();
+        engine.execute("INSERT INTO t VALUES (3)").unwrap();
+        engine.execute("COMMIT").unwrap();
+        assert!(matches!(
+            engine.execute("COMMIT"),
+            Err(Error::NoTransaction)
+        ));
+        drop(engine);
+        let mut engine = open();
+        assert_eq!(vec![vec![Value::Int(1)]], count(&mut engine));
+    }
+
+    #[test]
+    fn test_failed_statement_in_transaction() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY)")
+            .unwrap();
+        let ids = |engine: &mut Engine| engine.execute("SELECT id FROM t").unwrap().into_rows();
+        engine.begin().unwrap();
+        engine.execute("INSERT INTO t VALUES (1)").unwrap();
+        // Fails at its third row, after inserting two.
+        let failed = engine.execute("INSERT INTO t VALUES (2), (3), (1), (4)");
+        assert!(failed.is_err());
+        assert!(engine.in_transaction());
+        assert_eq!(vec![vec![Value::Int(1)]], ids(&mut engine));
+        engine.execute("INSERT INTO t VALUES (2)").unwrap();
+        let failed = engine.execute("CREATE MATERIALIZED VIEW u AS SELECT 1 / (id - 2) FROM t");
+        assert!(failed.is_err());
+        assert!(engine.catalog().table("u").is_none());
+        engine.commit().unwrap();
+        assert_eq!(
+            vec![vec![Value::Int(1)], vec![Value::Int(2)]],
+            ids(&mut engine)
+        );
+    }
+
+    #[test]
+    fn test_failed_statement_in_autocommit() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, v INT)")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (1, 1), (5, 5), (6, 6)")
+            .unwrap();
+        let changes = engine.subscribe_channel();
+        let rows = |engine: &mut Engine| {
+            (engine.execute("SELECT id, v FROM t ORDER BY id"))
+                .unwrap()
+                .into_rows()
+        };
+        let before = rows(&mut engine);
+        // Row 1 becomes 2 before row 5 runs into row 6.
+        let failed = engine.execute("UPDATE t SET id = id + 1, v = v + 100");
+        assert!(failed.is_err());
+        assert_eq!(before, rows(&mut engine));
+        let failed = engine.execute("INSERT INTO t VALUES (100, 1), (1, 5)");
+        assert!(failed.is_err());
+        assert_eq!(before, rows(&mut engine));
+        assert!(changes.try_recv().is_err());
+        engine.execute("INSERT INTO t VALUES (100, 1)").unwrap();
+        assert_eq!(4, rows(&mut engine).len());
+    }
+
+    #[test]
+    fn test_change_subscription() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, v TEXT)")
+            .unwrap();
+        let changes = engine.subscribe_channel();
+        let row = |id: i64, v: &str| vec![Value::Int(id), v.into()];
+        engine
+            .execute("INSERT INTO t VALUES (1, 'a'), (2, 'b') ON CONFLICT DO NOTHING")
+            .unwrap();
+        assert_eq!(
+            vec![
+                Change::Insert {
+                    table: "t".into(),
+                    row: row(1, "a"),
+                },
+                Change::Insert {
+                    table: "t".into(),
+                    row: row(2, "b"),
+                },
+            ],
+            changes.try_recv().unwrap()
+        );
+        // Nothing changes, so nothing is sent.
+        engine
+            .execute("INSERT INTO t VALUES (1, 'z') ON CONFLICT DO NOTHING")
+            .unwrap();
+        engine.execute("UPDATE t SET v = 'a' WHERE id = 1").unwrap();
+        assert!(changes.try_recv().is_err());
+
+        engine.begin().unwrap();
+        engine.execute("UPDATE t SET v = 'c' WHERE id = 2").unwrap();
+        engine.execute("DELETE FROM t WHERE id = 1").unwrap();
+        assert!(changes.try_recv().is_err());
+        engine.commit().unwrap();
+        assert_eq!(
+            vec![
+                Change::Update {
+                    table: "t".into(),
+                    before: row(2, "b"),
+                    after: row(2, "c"),
+                },
+                Change::Delete {
+                    table: "t".into(),
+                    row: row(1, "a"),
+                },
+            ],
+            changes.try_recv().unwrap()
+        );
+        engine.begin().unwrap();
+        engine.execute("DELETE FROM t").unwrap();
+        engine.rollback().unwrap();
+        assert!(changes.try_recv().is_err());
+
+        drop(changes);
+        engine.execute("DELETE FROM t").unwrap();
+        assert!(engine.subscribers.is_empty());
+    }
+
+    #[test]
+    fn test_users_and_privileges() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY, name TEXT)",
+            "CREATE TABLE secret (x INT)",
+            "CREATE USER admin WITH PASSWORD 'a' SUPERUSER",
+            "CREATE USER bob PASSWORD 'b'",
+            "GRANT SELECT, INSERT ON t TO bob",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        let select_secret = engine.prepare("SELECT * FROM secret").unwrap();
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        let mut engine = open();
+        let bob = engine.catalog().user("bob").unwrap();
+        assert!(bob.verifier.as_ref().unwrap().verify("b"));
+        assert_eq!(Privileges::SELECT | Privileges::INSERT, bob.privileges("t"));
+        engine.set_user(Some("bob".to_string()));
+        engine.execute("INSERT INTO t VALUES (1, 'a')").unwrap();
+        engine.execute("SELECT name FROM t WHERE id = 1").unwrap();
+        let denied = |engine: &mut Engine, sql: &str| match engine.execute(sql) {
+            Err(Error::Plan(planner::Error::PermissionDenied { privileges, .. })) => privileges,
+            result => panic!("{sql}: {result:?}"),
+        };
+        assert_eq!(Privileges::DELETE, denied(&mut engine, "DELETE FROM t"));
+        assert_eq!(
+            Privileges::UPDATE,
+            denied(&mut engine, "UPDATE t SET name = 'b' WHERE id = 1")
+        );
+        assert_eq!(
+            Privileges::SELECT,
+            denied(&mut engine, "SELECT * FROM t, secret")
+        );
+        // Locking rows takes leave to update them.
+        assert_eq!(
+            Privileges::UPDATE,
+            denied(&mut engine, "SELECT name FROM t WHERE id = 1 FOR UPDATE")
+        );
+        // Plans checked for no user are checked again for bob.
+        assert!(engine.execute_prepared(&select_secret, &[]).is_err());
+        assert!(matches!(
+            engine.execute("CREATE TABLE mine (x INT)"),
+            Err(Error::Plan(planner::Error::MustBeSuperuser(_)))
+        ));
+        assert!(engine.execute("ALTER USER bob SUPERUSER").is_err());
+        engine.execute("ALTER USER bob PASSWORD 'c'").unwrap();
+        assert!(engine
+            .catalog()
+            .user("bob")
+            .unwrap()
+            .verifier
+            .as_ref()
+            .unwrap()
+            .verify("c"));
+
+        engine.set_user(Some("admin".to_string()));
+        engine.execute("SELECT * FROM secret").unwrap();
+        engine.execute("REVOKE ALL ON t FROM bob").unwrap();
+        assert!(engine.catalog().user("bob").unwrap().grants.is_empty());
+        engine.execute("GRANT DELETE ON secret TO bob").unwrap();
+        engine.execute("DROP TABLE secret").unwrap();
+        engine.execute("CREATE TABLE secret (x INT)").unwrap();
+        assert!(engine.catalog().user("bob").unwrap().grants.is_empty());
+        engine.execute("DROP USER bob").unwrap();
+        assert!(engine.catalog().user("bob").is_none());
+    }
+
+    #[test]
+    fn test_column_privileges() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        for sql in [
+            "CREATE TABLE people (id INT PRIMARY KEY, name TEXT, salary INT)",
+            "INSERT INTO people VALUES (1, 'a', 10), (2, 'b', 20)",
+            "CREATE USER bob",
+            "GRANT SELECT (id, name), UPDATE ON people TO bob",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        let mut engine = open();
+        engine.set_user(Some("bob".to_string()));
+        let rows = engine
+            .execute("SELECT name FROM people WHERE id = 2")
+            .unwrap();
+        assert_eq!(vec![vec![Value::from("b")]], rows.into_rows());
+        engine.execute("SELECT count(*) FROM people").unwrap();
+        engine
+            .execute("UPDATE people SET name = 'c' WHERE id = 1")
+            .unwrap();
+        let denied = |engine: &mut Engine, sql: &str| match engine.execute(sql) {
+            Err(Error::Plan(planner::Error::ColumnPermissionDenied { column, table })) => {
+                assert_eq!("people", table);
+                column
+            }
+            result => panic!("{sql}: {result:?}"),
+        };
+        assert_eq!("salary", denied(&mut engine, "SELECT * FROM people"));
+        assert_eq!(
+            "salary",
+            denied(&mut engine, "SELECT p.id FROM people p WHERE p.salary > 10")
+        );
+        assert_eq!(
+            "salary",
+            denied(&mut engine, "UPDATE people SET salary = salary + 1")
+        );
+        // Writing a column without reading it takes no SELECT on it.
+        engine.execute("UPDATE people SET salary = 0").unwrap();
+
+        engine.set_user(None);
+        engine
+            .execute("REVOKE SELECT (name) ON people FROM bob")
+            .unwrap();
+        engine.set_user(Some("bob".to_string()));
+        assert_eq!("name", denied(&mut engine, "SELECT name FROM people"));
+        engine.set_user(None);
+        engine.execute("REVOKE SELECT ON people FROM bob").unwrap();
+        let bob = engine.catalog().user("bob").unwrap();
+        assert!(bob.column_grants.is_empty());
+        assert_eq!(Privileges::UPDATE, bob.privileges("people"));
+        assert!(matches!(
+            engine.execute("GRANT SELECT (wage) ON people TO bob"),
+            Err(Error::Plan(planner::Error::ColumnNotFound(_)))
+        ));
+    }
+
+    #[test]
+    fn test_row_security_policies() {
+        let mut engine = engine();
+        for sql in [
+            "CREATE TABLE docs (id INT PRIMARY KEY, tenant TEXT, body TEXT)",
+            "CREATE USER alice",
+            "CREATE USER bob",
+            "GRANT ALL ON docs TO alice, bob",
+            "INSERT INTO docs VALUES (1, 'a', 'x'), (2, 'b', 'y'), (3, 'shared', 'z')",
+            "CREATE POLICY own_a ON docs TO alice USING (tenant = 'a')",
+            "CREATE POLICY own_b ON docs TO bob USING (tenant = 'b')",
+            "CREATE POLICY shared ON docs USING (tenant = 'shared')",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        let ids = |engine: &mut Engine, sql: &str| -> Vec<Value> {
+            let rows = engine.execute(sql).unwrap().into_rows();
+            rows.into_iter().map(|row| row[0].clone()).collect()
+        };
+        engine.set_user(Some("alice".to_string()));
+        let all = "SELECT id FROM docs ORDER BY id";
+        assert_eq!(vec![Value::Int(1), Value::Int(3)], ids(&mut engine, all));
+        // Subqueries and joins see the same rows; UPDATE and DELETE leave
+        // bob's alone.
+        assert_eq!(
+            vec![Value::Int(4)],
+            ids(
+                &mut engine,
+                "SELECT count(*) FROM docs d, (SELECT id FROM docs) e"
+            )
+        );
+        assert!(matches!(
+            engine.execute("UPDATE docs SET body = 'w'"),
+            Ok(Output::Affected(2))
+        ));
+        assert!(matches!(
+            engine.execute("DELETE FROM docs WHERE id = 2"),
+            Ok(Output::Affected(0))
+        ));
+        let violation = |result| {
+            matches!(
+                result,
+                Err(Error::Execute(executor::Error::PolicyViolation(_)))
+            )
+        };
+        assert!(violation(
+            engine.execute("INSERT INTO docs VALUES (4, 'b', 'v')")
+        ));
+        assert!(violation(
+            engine.execute("UPDATE docs SET tenant = 'b' WHERE id = 1")
+        ));
+        engine
+            .execute("INSERT INTO docs VALUES (4, 'a', 'v')")
+            .unwrap();
+
+        engine.set_user(Some("bob".to_string()));
+        assert_eq!(vec![Value::Int(2), Value::Int(3)], ids(&mut engine, all));
+        engine.set_user(None);
+        engine.execute("DROP POLICY shared ON docs").unwrap();
+        engine.execute("CREATE USER carol").unwrap();
+        engine.execute("GRANT SELECT ON docs TO carol").unwrap();
+        engine.set_user(Some("carol".to_string()));
+        assert!(ids(&mut engine, all).is_empty());
+        engine.set_user(None);
+        assert_eq!(4, ids(&mut engine, all).len());
+    }
+
+    #[test]
+    fn test_as_of_access() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 32).with_history(Duration::from_secs(3600));
+        let mut engine = Engine::open(bufmgr).unwrap();
+        for sql in [
+            "CREATE TABLE docs (id INT PRIMARY KEY, tenant TEXT, salary INT)",
+            "INSERT INTO docs VALUES (1, 'a', 10), (2, 'b', 20)",
+            "CREATE USER bob",
+            "GRANT SELECT ON docs TO bob",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        let lsn = engine.bufmgr().mark_history();
+        let as_of = |engine: &mut Engine, columns: &str| {
+            let sql = format!("SELECT {columns} FROM docs ORDER BY id AS OF LSN {lsn}");
+            engine.execute(&sql).map(Output::into_rows)
+        };
+        engine.set_user(Some("bob".to_string()));
+        assert_eq!(2, as_of(&mut engine, "*").unwrap().len());
+
+        // The past is read under the privileges of now.
+        engine.set_user(None);
+        engine.execute("REVOKE SELECT ON docs FROM bob").unwrap();
+        engine.set_user(Some("bob".to_string()));
+        assert!(matches!(
+            as_of(&mut engine, "id"),
+            Err(Error::Plan(planner::Error::PermissionDenied { .. }))
+        ));
+        engine.set_user(None);
+        engine.execute("GRANT SELECT (id) ON docs TO bob").unwrap();
+        engine
+            .execute("CREATE POLICY own ON docs TO bob USING (tenant = 'b')")
+            .unwrap();
+        engine.set_user(Some("bob".to_string()));
+        assert!(matches!(
+            as_of(&mut engine, "salary"),
+            Err(Error::Plan(planner::Error::ColumnPermissionDenied { .. }))
+        ));
+        assert_eq!(vec![vec![Value::Int(2)]], as_of(&mut engine, "id").unwrap());
+    }
+
+    #[test]
+    fn test_temp_and_unlogged_tables() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        let count = |engine: &mut Engine, table: &str| {
+            let sql = format!("SELECT count(*) FROM {table}");
+            engine.execute(&sql).unwrap().into_rows()[0][0].clone()
+        };
+        // The first temporary table may come in a transaction, and go
+        // with its rollback.
+        engine.execute("BEGIN").unwrap();
+        engine
+            .execute("CREATE TEMP TABLE scratch (id INT)")
+            .unwrap();
+        engine.execute("INSERT INTO scratch VALUES (1)").unwrap();
+        engine.execute("ROLLBACK").unwrap();
+        assert!(engine.catalog().table("scratch").is_none());
+
+        for sql in [
+            "CREATE TABLE t (id INT)",
+            "INSERT INTO t VALUES (1), (2)",
+            "CREATE UNLOGGED TABLE staging (id INT PRIMARY KEY, note TEXT)",
+            "INSERT INTO staging VALUES (1, 'a'), (2, 'b')",
+            "CREATE USER bob",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        assert!(engine
+            .execute("INSERT INTO staging VALUES (1, 'c')")
+            .is_err());
+        assert_eq!(Value::Int(2), count(&mut engine, "staging"));
+
+        // Anyone may create a temporary table, whose name hides others'.
+        engine.set_user(Some("bob".to_string()));
+        for sql in [
+            "CREATE TEMP TABLE t (id INT PRIMARY KEY, note TEXT)",
+            "INSERT INTO t VALUES (5, 'x')",
+            "CREATE INDEX t_note ON t (note)",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        assert_eq!(Value::Int(1), count(&mut engine, "t"));
+        assert!(matches!(
+            engine.execute("CREATE UNLOGGED TABLE u (id INT)"),
+            Err(Error::Plan(planner::Error::MustBeSuperuser(_)))
+        ));
+        engine.set_user(None);
+        assert!(matches!(
+            engine.execute("ALTER TABLE t SET TTL (id)"),
+            Err(Error::Plan(planner::Error::Unsupported(_)))
+        ));
+        assert!(matches!(
+            engine.execute("CREATE MATERIALIZED VIEW v AS SELECT * FROM staging"),
+            Err(Error::Plan(planner::Error::Unsupported(_)))
+        ));
+
+        // Taken out, as a session's are between its statements.
+        let temp = engine.take_temp_tables();
+        assert_eq!(Value::Int(2), count(&mut engine, "t"));
+        engine.set_temp_tables(temp);
+        assert_eq!(Value::Int(1), count(&mut engine, "t"));
+        assert_eq!(1, engine.catalog().tables().count());
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        // Unlogged tables come back empty, temporary ones not at all.
+        let mut engine = open();
+        assert_eq!(Value::Int(2), count(&mut engine, "t"));
+        assert_eq!(Value::Int(0), count(&mut engine, "staging"));
+        engine
+            .execute("INSERT INTO staging VALUES (1, 'a')")
+            .unwrap();
+        assert!(engine
+            .execute("INSERT INTO staging VALUES (1, 'b')")
+            .is_err());
+        engine.execute("DROP TABLE staging").unwrap();
+        assert!(engine.catalog().table("staging").is_none());
+    }
+
+    #[test]
+    fn test_null_ordering() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let mut engine = open();
+        let ids = |engine: &mut Engine, sql: &str| -> Vec<Value> {
+            let rows = engine.execute(sql).unwrap().into_rows();
+            rows.into_iter().map(|row| row[0].clone()).collect()
+        };
+        let plan = |engine: &mut Engine, sql: &str| -> String {
+            let rows = engine
+                .execute(&format!("EXPLAIN {sql}"))
+                .unwrap()
+                .into_rows();
+            let lines: Vec<_> = rows.iter().map(|row| row[0].to_string()).collect();
+            lines.join("\n")
+        };
+        engine
+            .execute("CREATE TABLE t (id INT, score INT)")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (1, 20), (2, NULL), (3, 10)")
+            .unwrap();
+        let ints = |ids: [i64; 3]| ids.map(Value::from).to_vec();
+        for (order, expected) in [
+            ("score", [2, 3, 1]),
+            ("score NULLS LAST", [3, 1, 2]),
+            ("score DESC", [1, 3, 2]),
+            ("score DESC NULLS FIRST", [2, 1, 3]),
+        ] {
+            let sql = format!("SELECT id FROM t ORDER BY {order}");
+            assert_eq!(ints(expected), ids(&mut engine, &sql), "{order}");
+        }
+
+        // With the high ordering, as in PostgreSQL, an index made now
+        // keeps its NULLs last and can give the rows of ORDER BY in order.
+        engine.execute("SET null_ordering TO high").unwrap();
+        assert_eq!(
+            ints([3, 1, 2]),
+            ids(&mut engine, "SELECT id FROM t ORDER BY score")
+        );
+        assert_eq!(
+            ints([2, 1, 3]),
+            ids(&mut engine, "SELECT id FROM t ORDER BY score DESC")
+        );
+        engine.execute("CREATE INDEX t_score ON t (score)").unwrap();
+        engine.execute("SET enable_seqscan TO off").unwrap();
+        let select = "SELECT id FROM t ORDER BY score";
+        let explained = plan(&mut engine, select);
+        assert!(!explained.contains("Sort"), "{explained}");
+        assert!(
+            explained.contains("Index Scan using t_score"),
+            "{explained}"
+        );
+        assert_eq!(ints([3, 1, 2]), ids(&mut engine, select));
+        let explained = plan(&mut engine, "SELECT id FROM t ORDER BY score NULLS FIRST");
+        assert!(explained.contains("Sort Key: #1\n"), "{explained}");
+        assert!(matches!(
+            engine.execute("CREATE INDEX ON t (score DESC)"),
+            Err(Error::Plan(planner::Error::Unsupported(_)))
+        ));
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        // The index keeps its ordering, whatever the ordering now.
+        let mut engine = open();
+        assert!(!engine.catalog().table("t").unwrap().indexes[0].keys[0].nulls_first);
+        engine.execute("SET enable_seqscan TO off").unwrap();
+        let select = "SELECT id FROM t WHERE score > 0 OR score IS NULL ORDER BY score NULLS LAST";
+        assert!(!plan(&mut engine, select).contains("Sort"));
+        assert_eq!(ints([3, 1, 2]), ids(&mut engine, select));
+        engine.execute("DROP INDEX t_score").unwrap();
+        assert_eq!(
+            ints([2, 3, 1]),
+            ids(&mut engine, "SELECT id FROM t ORDER BY score")
+        );
+    }
+
+    #[test]
+    fn test_query_progress() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT, name TEXT)")
+            .unwrap();
+        let values: Vec<_> = (0..1000).map(|i| format!("({i}, '{i:0>100}')")).collect();
+        let sql = format!("INSERT INTO t VALUES {}", values.join(", "));
+        engine.execute(&sql).unwrap();
+        let progress = engine.query_progress();
+        assert_eq!(None, progress.get());
+
+        // Each row the query returns sees how far the query had got.
+        let log = Arc::new(Mutex::new(vec![]));
+        let (observer, observed) = (progress.clone(), log.clone());
+        engine
+            .create_function("observe", &[DataType::Int], DataType::Int, move |args| {
+                observed.lock().unwrap().push(observer.get().unwrap());
+                Ok(args[0].clone())
+            })
+            .unwrap();
+        engine.execute("SELECT observe(id) FROM t").unwrap();
+        let seen = std::mem::take(&mut *log.lock().unwrap());
+        assert_eq!(1000, seen.len());
+        let pages = seen[0].pages_total;
+        assert!(pages > 1);
+        for (i, progress) in seen.iter().enumerate() {
+            assert_eq!("SELECT observe(id) FROM t", progress.sql);
+            assert_eq!(pages, progress.pages_total);
+            assert_eq!(i as u64, progress.rows);
+        }
+        assert_eq!(Some(100.0), seen[999].percent());
+        assert!(seen[0].percent().unwrap() < 100.0);
+        assert_eq!(None, progress.get());
+
+        // A sort past work_mem counts what it spills.
+        engine.execute("SET work_mem = '64kB'").unwrap();
+        let sql = "WITH s AS (SELECT id FROM t ORDER BY name DESC) SELECT observe(id) FROM s";
+        engine.execute(sql).unwrap();
+        let seen = std::mem::take(&mut *log.lock().unwrap());
+        assert_eq!(0, seen[0].rows);
+        assert!(seen[0].spilled_bytes > 0);
+        assert_eq!(Some(100.0), seen[0].percent());
+
+        // Within the statement, the view reads it as it is so far.
+        let rows = engine
+            .execute("SELECT pages_scanned, pages_total, progress, rows, spilled_bytes FROM neru_active_queries")
+            .unwrap()
+            .into_rows();
+        assert_eq!(
+            vec![vec![
+                Value::Int(0),
+                Value::Int(0),
+                Value::Null,
+                Value::Int(0),
+                Value::Int(0)
+            ]],
+            rows
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Notifications on named channels, as NOTIFY sends and LISTEN receives.
+//!
+//! A [`Listener`] receives the notifications of the channels it listens
+//! to, from [`Engine::listen`] or from LISTEN run while it is the engine's
+//! [listener](Engine::set_listener), as a server session's is. NOTIFY in
+//! a transaction sends its notification once the transaction commits, and
+//! not at all if it rolls back; the same notification twice in one
+//! transaction is sent once. Outside a transaction it is sent at once.
+//! Listening takes effect at once either way, and is not undone by a
+//! rollback. A listener that is dropped stops listening by the next
+//! notification on its channels.
+//!
+//! Nothing is kept for a listener but what its channel has not yet
+//! handed over, so notifications sent before it listened, or while the
+//! engine was closed, are not seen. At most
+//! [`MAX_PENDING_NOTIFICATIONS`] wait for it at once; ones that find its
+//! queue full are dropped, and [counted](Listener::take_dropped) for it
+//! to tell.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use super::{Engine, Error};
+
+/// Bytes a payload may have, as in Postgres.
+pub const MAX_NOTIFICATION_PAYLOAD: usize = 8000;
+
+/// Notifications a listener can have waiting before more are dropped.
+pub const MAX_PENDING_NOTIFICATIONS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub channel: String,
+    pub payload: String,
+}
+
+/// Where notifications arrive for one listener.
+#[derive(Debug)]
+pub struct Listener {
+    id: u64,
+    sender: mpsc::SyncSender<Notification>,
+    receiver: mpsc::Receiver<Notification>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Listener {
+    pub fn new() -> Self {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let (sender, receiver) = mpsc::sync_channel(MAX_PENDING_NOTIFICATIONS);
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            sender,
+            receiver,
+            dropped: Arc::default(),
+        }
+    }
+
+    /// The next notification, if one has arrived.
+    pub fn try_recv(&self) -> Option<Notification> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Waits up to `timeout` for the next notification. Another thread
+    /// must be running the engine for one to come.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Notification> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+
+    /// The notifications that have arrived, without waiting for more.
+    pub fn try_iter(&self) -> impl Iterator<Item = Notification> + '_ {
+        self.receiver.try_iter()
+    }
+
+    /// How many notifications were dropped for a full queue since last
+    /// asked.
+    pub fn take_dropped(&self) -> u64 {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl Default for Listener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the engine keeps of a listener.
+#[derive(Debug, Clone)]
+pub(super) struct ListenerHandle {
+    id: u64,
+    sender: mpsc::SyncSender<Notification>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl ListenerHandle {
+    /// Sends `notification`, or counts it dropped if the queue is full.
+    /// Returns whether the listener is still there.
+    fn send(&self, notification: Notification) -> bool {
+        match self.sender.try_send(notification) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+impl From<&Listener> for ListenerHandle {
+    fn from(listener: &Listener) -> Self {
+        Self {
+            id: listener.id,
+            sender: listener.sender.clone(),
+            dropped: Arc::clone(&listener.dropped),
+        }
+    }
+}
+
+/// The listeners of each channel, by id.
+pub(super) type Channels = HashMap<String, HashMap<u64, ListenerHandle>>;
+
+impl Engine {
+    /// Has LISTEN and UNLISTEN run for `listener` until changed.
+    pub fn set_listener(&mut self, listener: Option<&Listener>) {
+        self.listener = listener.map(ListenerHandle::from);
+    }
+
+    /// Sends `listener` the notifications on `channel` from now on.
+    pub fn listen(&mut self, listener: &Listener, channel: &str) {
+        self.listen_with(ListenerHandle::from(listener), channel);
+    }
+
+    /// Stops sending `listener` the notifications on `channel`, or on
+    /// every channel if `None`.
+    pub fn unlisten(&mut self, listener: &Listener, channel: Option<&str>) {
+        self.unlisten_id(listener.id, channel);
+    }
+
+    /// Sends `payload` to the listeners of `channel`, once the running
+    /// transaction commits if there is one.
+    pub fn notify(&mut self, channel: &str, payload: &str) -> Result<(), Error> {
+        if payload.len() >= MAX_NOTIFICATION_PAYLOAD {
+            return Err(Error::PayloadTooLong);
+        }
+        let notification = Notification {
+            channel: channel.to_string(),
+            payload: payload.to_string(),
+        };
+        if !self.in_transaction() {
+            self.send_notifications(vec![notification]);
+        } else if !self.pending_notifications.contains(&notification) {
+            self.pending_notifications.push(notification);
+        }
+        Ok(())
+    }
+
+    /// LISTEN, for the engine's listener.
+    pub(super) fn listen_current(&mut self, channel: &str) -> Result<(), Error> {
+        let listener = self.listener.clone().ok_or(Error::NoListener)?;
+        self.listen_with(listener, channel);
+        Ok(())
+    }
+
+    /// UNLISTEN, for the engine's listener, which need not have one.
+    pub(super) fn unlisten_current(&mut self, channel: Option<&str>) {
+        if let Some(listener) = &self.listener {
+            self.unlisten_id(listener.id, channel);
+        }
+    }
+
+    pub(super) fn send_notifications(&mut self, notifications: Vec<Notification>) {
+        for notification in notifications {
+            let Some(listeners) = self.channels.get_mut(&notification.channel) else {
+                continue;
+            };
+            listeners.retain(|_, listener| listener.send(notification.clone()));
+            if listeners.is_empty() {
+                self.channels.remove(&notification.channel);
+            }
+        }
+    }
+
+    fn listen_with(&mut self, listener: ListenerHandle, channel: &str) {
+        (self.channels.entry(channel.to_string()).or_default()).insert(listener.id, listener);
+    }
+
+    fn unlisten_id(&mut self, id: u64, channel: Option<&str>) {
+        self.channels.retain(|name, listeners| {
+            if channel.is_none_or(|channel| channel == name) {
+                listeners.remove(&id);
+            }
+            !listeners.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::*;
+
+    #[test]
+    fn test_notify_at_commit() {
+        let mut engine = engine();
+        let listener = Listener::new();
+        engine.set_listener(Some(&listener));
+        engine.execute("LISTEN jobs").unwrap();
+        engine.execute("NOTIFY jobs, 'one'").unwrap();
+        engine.execute("NOTIFY other").unwrap();
+        let notification = |payload: &str| Notification {
+            channel: "jobs".to_string(),
+            payload: payload.to_string(),
+        };
+        assert_eq!(
+            vec![notification("one")],
+            listener.try_iter().collect::<Vec<_>>()
+        );
+
+        engine.execute("BEGIN").unwrap();
+        engine.execute("NOTIFY jobs, 'two'").unwrap();
+        engine.execute("NOTIFY jobs, 'two'").unwrap();
+        assert_eq!(None, listener.try_recv());
+        engine.execute("COMMIT").unwrap();
+        assert_eq!(
+            vec![notification("two")],
+            listener.try_iter().collect::<Vec<_>>()
+        );
+        engine.execute("BEGIN").unwrap();
+        engine.execute("NOTIFY jobs, 'three'").unwrap();
+        engine.execute("ROLLBACK").unwrap();
+        assert_eq!(None, listener.try_recv());
+
+        engine.execute("UNLISTEN *").unwrap();
+        engine.execute("NOTIFY jobs").unwrap();
+        assert_eq!(None, listener.try_recv());
+        engine.set_listener(None);
+        assert!(matches!(
+            engine.execute("LISTEN jobs"),
+            Err(Error::NoListener)
+        ));
+        let payload = format!("NOTIFY jobs, '{}'", "x".repeat(MAX_NOTIFICATION_PAYLOAD));
+        assert!(matches!(
+            engine.execute(&payload),
+            Err(Error::PayloadTooLong)
+        ));
+
+        // A full queue drops what does not fit, and counts it.
+        engine.listen(&listener, "jobs");
+        for _ in 0..MAX_PENDING_NOTIFICATIONS + 2 {
+            engine.notify("jobs", "").unwrap();
+        }
+        assert_eq!(2, listener.take_dropped());
+        assert_eq!(0, listener.take_dropped());
+        assert_eq!(MAX_PENDING_NOTIFICATIONS, listener.try_iter().count());
+    }
+}
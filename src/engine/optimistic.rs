@@ -0,0 +1,401 @@
+//! Optimistic concurrency control, the alternative to holding the
+//! database for the length of a transaction.
+//!
+//! Under [`Concurrency::Locking`], the default, a transaction has the
+//! engine to itself from BEGIN to COMMIT: the server keeps every other
+//! session waiting meanwhile. Under [`Concurrency::Optimistic`], BEGIN
+//! starts an [`OptimisticTransaction`] instead, which goes through the
+//! three phases of OCC:
+//!
+//! - Reading. Each statement runs on its own against what is committed,
+//!   and the tables it reads go into the read set, with how many commits
+//!   had changed each table when it first did. Writes go into the write
+//!   set without reaching the database: the statements that change rows
+//!   are kept, and every later statement runs in a transaction where they
+//!   are replayed first, which is rolled back once it has run, so the
+//!   transaction sees its own changes and nobody else does. Between
+//!   statements the transaction holds only the rows it locked, and can be
+//!   [suspended](Engine::suspend_transaction) while others run.
+//! - Validation. COMMIT fails with [`Error::SerializationFailure`] if a
+//!   table in the read set has been changed by a commit since the
+//!   transaction read it, or the catalog by any commit since it began;
+//!   the transaction is then rolled back, and can be retried.
+//! - Writing. Otherwise the write set is replayed once more and committed.
+//!   What it read being unchanged, it does what it did when it first ran.
+//!
+//! Tables count as read by every statement that scans them, changes them
+//! or checks rows against them, including through triggers; writing a
+//! partition counts as writing its partitioned table and the other way
+//! round. Validation is by table, not row, so transactions writing
+//! different rows of a table still conflict. This suits workloads where
+//! transactions seldom touch the same tables: those that do pay for the
+//! replays and for retries. Statements are replayed as they were written,
+//! so an insert into a table with a time to live, which stamps rows with
+//! the time they are inserted, stores the time of the commit instead.
+//!
+//! This is stricter than the serializable snapshot isolation of Postgres,
+//! which lets a transaction whose reads were overwritten commit as long
+//! as no two rw-antidependencies meet in a dangerous structure. SSI rests
+//! on every transaction reading one snapshot, which takes versions of
+//! rows that this engine does not keep: each statement here reads what
+//! is committed when it runs, and with the reads of one transaction
+//! spanning others' commits, an overwritten read is a conflict already.
+//! Past pages kept as [history](crate::buffer::History) are no substitute:
+//! queries can read the database as it was from them, but they are kept
+//! for a retention window rather than for as long as a transaction runs,
+//! and whole pages tell nothing of which rows a later commit overwrote.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use super::prepared::Planned;
+use super::{Engine, Error, Output, PreparedStatement};
+use crate::planner::BoundStatement;
+use crate::value::Value;
+
+/// How transactions keep out of each other's way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Concurrency {
+    /// A transaction holds the database until it ends.
+    #[default]
+    Locking,
+    /// Transactions run side by side and are validated at commit.
+    Optimistic,
+}
+
+impl fmt::Display for Concurrency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Concurrency::Locking => "locking",
+            Concurrency::Optimistic => "optimistic",
+        })
+    }
+}
+
+impl FromStr for Concurrency {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_lowercase().as_str() {
+            "locking" => Ok(Concurrency::Locking),
+            "optimistic" | "occ" => Ok(Concurrency::Optimistic),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A transaction under [`Concurrency::Optimistic`], with its read and
+/// write sets.
+#[derive(Debug, Clone)]
+pub struct OptimisticTransaction {
+    /// The engine's id of the transaction.
+    id: u64,
+    /// Commits that had changed each table read when it was first read.
+    reads: BTreeMap<String, u64>,
+    /// Commits that had changed the catalog when the transaction began.
+    catalog: u64,
+    /// The statements that can change the database, in order, with their
+    /// parameters.
+    writes: Vec<(PreparedStatement, Vec<Value>)>,
+    /// Whether one of them changes the catalog, which later statements
+    /// must then be planned against.
+    changes_catalog: bool,
+}
+
+impl OptimisticTransaction {
+    /// The tables read so far.
+    pub fn read_set(&self) -> impl Iterator<Item = &str> {
+        self.reads.keys().map(String::as_str)
+    }
+
+    /// The statements kept for commit so far.
+    pub fn write_set(&self) -> impl Iterator<Item = &str> {
+        self.writes.iter().map(|(statement, _)| statement.sql())
+    }
+}
+
+impl Engine {
+    pub fn concurrency(&self) -> Concurrency {
+        self.concurrency
+    }
+
+    /// Makes later transactions run under `concurrency`.
+    pub fn set_concurrency(&mut self, concurrency: Concurrency) {
+        self.concurrency = concurrency;
+    }
+
+    pub fn in_optimistic_transaction(&self) -> bool {
+        self.optimistic.is_some()
+    }
+
+    /// Takes the running optimistic transaction out of the engine, for
+    /// other sessions to use it until it is resumed.
+    pub fn suspend_transaction(&mut self) -> Option<OptimisticTransaction> {
+        self.optimistic.take()
+    }
+
+    /// Goes on with a suspended transaction.
+    pub fn resume_transaction(&mut self, transaction: OptimisticTransaction) -> Result<(), Error> {
+        if self.in_transaction() || self.optimistic.is_some() {
+            return Err(Error::TransactionActive);
+        }
+        self.transaction_id = transaction.id;
+        self.optimistic = Some(transaction);
+        Ok(())
+    }
+
+    pub(super) fn begin_optimistic(&mut self) {
+        self.optimistic = Some(OptimisticTransaction {
+            id: self.transaction_id,
+            reads: BTreeMap::new(),
+            catalog: self.catalog_commits,
+            writes: vec![],
+            changes_catalog: false,
+        });
+    }
+
+    /// Validates `transaction` and, if nothing it read has changed since,
+    /// commits its writes.
+    pub(super) fn commit_optimistic(
+        &mut self,
+        transaction: OptimisticTransaction,
+    ) -> Result<(), Error> {
+        self.validate(&transaction)?;
+        if !transaction.writes.is_empty() {
+            self.begin_locked()?;
+            for (statement, params) in &transaction.writes {
+                if let Err(e) = self.replay(statement, params) {
+                    self.rollback_locked()?;
+                    return Err(e);
+                }
+            }
+            let (_, written) = self.table_log.take();
+            self.pending_tables.extend(written);
+            self.commit_locked()?;
+        }
+        self.optimistic_commits += 1;
+        Ok(())
+    }
+
+    /// Runs `statement` in the optimistic transaction.
+    pub(super) fn execute_optimistic(
+        &mut self,
+        statement: &PreparedStatement,
+        params: &[Value],
+    ) -> Result<Output, Error> {
+        let mut transaction = self
+            .optimistic
+            .take()
+            .expect("in an optimistic transaction");
+        self.statement_reads = Some(BTreeSet::new());
+        // What changes the database must not reach it yet, nor
+        // notifications anyone.
+        let deferred = statement.planned.writes() || statement.planned.notifies();
+        let output = match transaction.writes.is_empty() && !deferred {
+            true => self.execute_statement(statement, params),
+            false => self.with_writes(&transaction, |engine| {
+                engine.execute_statement(statement, params)
+            }),
+        };
+        for table in self.statement_reads.take().unwrap_or_default() {
+            let commits = self.table_commits(&table);
+            transaction.reads.entry(table).or_insert(commits);
+        }
+        if output.is_ok() && deferred {
+            transaction.changes_catalog |= statement.planned.writes()
+                && matches!(
+                    statement.planned,
+                    Planned::Other(_) | Planned::CreateView { .. }
+                );
+            transaction
+                .writes
+                .push((statement.clone(), params.to_vec()));
+        }
+        self.optimistic = Some(transaction);
+        output
+    }
+
+    /// Plans `sql` in the optimistic transaction, in the catalog its
+    /// writes have made if they changed it.
+    pub(super) fn prepare_optimistic(&mut self, sql: &str) -> Result<PreparedStatement, Error> {
+        let transaction = self
+            .optimistic
+            .take()
+            .expect("in an optimistic transaction");
+        let statement = self.with_writes(&transaction, |engine| engine.prepare(sql));
+        self.optimistic = Some(transaction);
+        let mut statement = statement?;
+        // Rolling back left the catalog a version on, which the plans of
+        // the transaction's statements are replanned for as they run but
+        // those of transaction control, which are not, do not depend on.
+        if !self.routes_optimistic(&statement) {
+            statement.catalog_version = self.catalog_version;
+        }
+        Ok(statement)
+    }
+
+    /// Whether statements go through the running optimistic transaction
+    /// rather than straight to the database.
+    pub(super) fn routes_optimistic(&self, statement: &PreparedStatement) -> bool {
+        self.optimistic.is_some()
+            && !matches!(
+                statement.planned,
+                Planned::Other(BoundStatement::Transaction(_))
+            )
+    }
+
+    /// Whether planning goes through the running optimistic transaction.
+    pub(super) fn plans_optimistic(&self) -> bool {
+        self.optimistic
+            .as_ref()
+            .is_some_and(|transaction| transaction.changes_catalog)
+    }
+
+    pub(super) fn table_commits(&self, table: &str) -> u64 {
+        self.table_commits.get(table).copied().unwrap_or(0)
+    }
+
+    /// Fails if a commit has changed what `transaction` read.
+    fn validate(&mut self, transaction: &OptimisticTransaction) -> Result<(), Error> {
+        let changed = match self.catalog_commits != transaction.catalog {
+            true => Some("the catalog".to_string()),
+            false => (transaction.reads.iter())
+                .find(|&(table, &commits)| self.table_commits(table) != commits)
+                .map(|(table, _)| format!("table {table:?}")),
+        };
+        match changed {
+            Some(changed) => {
+                self.serialization_failures += 1;
+                Err(Error::SerializationFailure(changed))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Runs `f` in a transaction where the writes of `transaction` have
+    /// been replayed, which is rolled back after. Fails without running
+    /// `f` if `transaction` can no longer commit, since its writes might
+    /// not replay as they ran.
+    fn with_writes<T>(
+        &mut self,
+        transaction: &OptimisticTransaction,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.validate(transaction)?;
+        self.begin_locked()?;
+        let result = (transaction.writes.iter())
+            .try_for_each(|(statement, params)| self.replay(statement, params))
+            .and_then(|()| f(self));
+        self.rollback_locked()?;
+        // What the replays and `f` changed was rolled back with them.
+        self.table_log.take();
+        result
+    }
+
+    /// Runs a statement of the write set again, planning it anew if the
+    /// catalog has changed, without counting it as a statement run.
+    fn replay(&mut self, statement: &PreparedStatement, params: &[Value]) -> Result<(), Error> {
+        let replanned;
+        let statement = match statement.catalog_version == self.catalog_version {
+            true => statement,
+            false => {
+                replanned = self.plan(&statement.sql)?;
+                &replanned
+            }
+        };
+        let params = statement.check_parameters(params)?;
+        let planned = statement.planned.replace_parameters(&params);
+        let started = std::time::Instant::now();
+        // Its rows were locked as it first ran.
+        self.run_planned(&statement.sql, started, planned, &statement.triggers, None)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPoolManager;
+    use crate::disk::DiskManager;
+
+    fn rows(engine: &mut Engine, sql: &str) -> Vec<Vec<Value>> {
+        engine.execute(sql).unwrap().into_rows()
+    }
+
+    #[test]
+    fn test_optimistic_transactions() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut engine = Engine::open(BufferPoolManager::new(disk, 64)).unwrap();
+        engine.set_concurrency(Concurrency::Optimistic);
+        for sql in [
+            "CREATE TABLE a (id INT PRIMARY KEY)",
+            "CREATE TABLE b (id INT PRIMARY KEY)",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+
+        // Two sessions taking turns; the first reads and writes only a.
+        engine.execute("BEGIN").unwrap();
+        engine.execute("INSERT INTO a VALUES (1)").unwrap();
+        assert_eq!(
+            vec![vec![Value::Int(1)]],
+            rows(&mut engine, "SELECT id FROM a")
+        );
+        let first = engine.suspend_transaction().unwrap();
+        assert!(rows(&mut engine, "SELECT id FROM a").is_empty());
+        engine.execute("BEGIN").unwrap();
+        engine.execute("INSERT INTO b VALUES (1)").unwrap();
+        let second = engine.suspend_transaction().unwrap();
+        engine.resume_transaction(first).unwrap();
+        assert_eq!(
+            vec!["a"],
+            engine
+                .optimistic
+                .as_ref()
+                .unwrap()
+                .read_set()
+                .collect::<Vec<_>>()
+        );
+        engine.execute("COMMIT").unwrap();
+        engine.resume_transaction(second).unwrap();
+        engine.execute("COMMIT").unwrap();
+        assert_eq!(
+            vec![vec![Value::Int(1)]],
+            rows(&mut engine, "SELECT id FROM b")
+        );
+
+        // One reads a table the other changes and commits first.
+        engine.execute("BEGIN").unwrap();
+        rows(&mut engine, "SELECT count(*) FROM a");
+        engine.execute("INSERT INTO b VALUES (2)").unwrap();
+        let reader = engine.suspend_transaction().unwrap();
+        engine.execute("INSERT INTO a VALUES (2)").unwrap();
+        engine.resume_transaction(reader).unwrap();
+        assert!(matches!(
+            engine.execute("COMMIT"),
+            Err(Error::SerializationFailure(_))
+        ));
+        assert!(!engine.in_optimistic_transaction());
+        assert_eq!(1, rows(&mut engine, "SELECT id FROM b").len());
+
+        // A table created in the transaction can be used in it.
+        engine.execute("BEGIN").unwrap();
+        engine.execute("CREATE TABLE c (id INT)").unwrap();
+        engine.execute("INSERT INTO c VALUES (3)").unwrap();
+        assert_eq!(
+            vec![vec![Value::Int(3)]],
+            rows(&mut engine, "SELECT id FROM c")
+        );
+        engine.execute("COMMIT").unwrap();
+        assert_eq!(
+            vec![vec![Value::Int(3)]],
+            rows(&mut engine, "SELECT id FROM c")
+        );
+        let metrics = engine.metrics();
+        assert_eq!(
+            (3, 1),
+            (metrics.optimistic_commits, metrics.serialization_failures)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Reuse of plans across executions of the same statement text.
+
+use std::collections::{BTreeMap, HashMap};
+
+use super::PreparedStatement;
+
+/// Entries kept unless configured otherwise.
+pub const DEFAULT_PLAN_CACHE_CAPACITY: usize = 128;
+
+/// Prepared statements by normalized SQL (see [`crate::sql::normalize`]),
+/// evicting the least recently used beyond `capacity`. Statements that
+/// take `$n` parameters share one entry for all their parameter values.
+#[derive(Debug)]
+pub struct PlanCache {
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+    /// Keys by the tick of their last use, oldest first.
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    statement: PreparedStatement,
+    used: u64,
+}
+
+impl PlanCache {
+    /// A cache of at most `capacity` plans; 0 disables caching.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups that found a plan.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Shrinks the cache to `capacity` entries if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<&PreparedStatement> {
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        self.hits += 1;
+        let key = self.recency.remove(&entry.used).unwrap();
+        self.tick += 1;
+        entry.used = self.tick;
+        self.recency.insert(self.tick, key);
+        Some(&entry.statement)
+    }
+
+    pub fn insert(&mut self, key: String, statement: PreparedStatement) {
+        self.tick += 1;
+        let entry = Entry {
+            statement,
+            used: self.tick,
+        };
+        if let Some(old) = self.entries.insert(key.clone(), entry) {
+            self.recency.remove(&old.used);
+        }
+        self.recency.insert(self.tick, key);
+        self.evict();
+    }
+
+    /// Drops every plan, as when the catalog changes under them.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let (_, key) = self.recency.pop_first().unwrap();
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::*;
+
+    #[test]
+    fn test_lru() {
+        let mut engine = engine();
+        let statement = engine.prepare("SELECT 1").unwrap();
+        let mut cache = PlanCache::new(2);
+        for key in ["a", "b"] {
+            cache.insert(key.into(), statement.clone());
+        }
+        assert!(cache.get("a").is_some());
+        cache.insert("c".into(), statement.clone());
+        assert_eq!(2, cache.len());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert_eq!((3, 1), (cache.hits(), cache.misses()));
+
+        cache.set_capacity(1);
+        assert!(cache.get("a").is_none());
+        assert!(cache.get("c").is_some());
+        cache.set_capacity(0);
+        cache.insert("d".into(), statement);
+        assert!(cache.is_empty());
+    }
+}
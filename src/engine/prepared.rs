@@ -0,0 +1,435 @@
+//! Statements planned once and executed many times.
+
+use std::sync::Arc;
+
+use super::Error;
+use crate::catalog::{Catalog, Schema, ViewInfo};
+use crate::executor::{Delete, Insert, NullOrdering, Plan, Triggers, Update};
+use crate::planner::{BoundStatement, CopyFormat, Field, PhysicalPlanner, PlannerSettings};
+use crate::value::{DataType, Value};
+
+/// A parsed, bound and planned statement, made by [`Engine::prepare`] and
+/// run by [`Engine::execute_prepared`].
+///
+/// The plan is generic in its parameters: index scans may use them as keys,
+/// and their values are substituted at execution, so running the statement
+/// again costs neither parsing nor planning.
+///
+/// [`Engine::prepare`]: super::Engine::prepare
+/// [`Engine::execute_prepared`]: super::Engine::execute_prepared
+#[derive(Debug, Clone)]
+pub struct PreparedStatement {
+    pub(super) sql: String,
+    pub(super) parameters: Vec<Option<DataType>>,
+    pub(super) planned: Planned,
+    /// Catalog version the statement was planned against.
+    pub(super) catalog_version: u64,
+    /// The user the statement was checked for.
+    pub(super) user: Option<String>,
+    /// The id of the temporary tables its names were looked up among.
+    pub(super) temp_tables: Option<u64>,
+    /// Where its sorts put NULLs unless they say, as planned.
+    pub(super) null_ordering: NullOrdering,
+    /// The triggers the statement may fire, planned with it.
+    pub(super) triggers: Arc<Triggers>,
+}
+
+impl PreparedStatement {
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    /// Types of `$1..$n`; `None` for a parameter nothing constrains, which
+    /// accepts any value.
+    pub fn parameter_types(&self) -> &[Option<DataType>] {
+        &self.parameters
+    }
+
+    /// Output columns, for a statement that returns rows.
+    pub fn fields(&self) -> Option<&[Field]> {
+        match &self.planned {
+            Planned::Query { fields, .. } => Some(fields),
+            _ => None,
+        }
+    }
+
+    /// Checks `params` against the parameter types, widening ints passed
+    /// for floats.
+    pub(super) fn check_parameters(&self, params: &[Value]) -> Result<Vec<Value>, Error> {
+        if params.len() != self.parameters.len() {
+            return Err(Error::ParameterCount {
+                expected: self.parameters.len(),
+                actual: params.len(),
+            });
+        }
+        params
+            .iter()
+            .zip(&self.parameters)
+            .enumerate()
+            .map(|(i, (value, data_type))| {
+                let Some(expected) = *data_type else {
+                    return Ok(value.clone());
+                };
+                value
+                    .clone()
+                    .coerce_to(expected)
+                    .ok_or_else(|| Error::ParameterType {
+                        number: i + 1,
+                        expected,
+                        actual: value.data_type().unwrap(),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// A bound statement with its executable plan.
+#[derive(Debug, Clone)]
+pub(super) enum Planned {
+    Query {
+        fields: Vec<Field>,
+        plan: Plan,
+    },
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    Explain {
+        analyze: bool,
+        plan: Plan,
+    },
+    CopyTo {
+        fields: Vec<Field>,
+        plan: Plan,
+        file: String,
+        format: CopyFormat,
+    },
+    /// A materialized view, created then filled by `insert`.
+    CreateView {
+        name: String,
+        schema: Schema,
+        view: ViewInfo,
+        if_not_exists: bool,
+        insert: Insert,
+    },
+    /// Empties a materialized view and fills it again.
+    RefreshView {
+        insert: Insert,
+    },
+    /// Changes to the catalog, which have nothing to plan.
+    Other(BoundStatement),
+}
+
+impl Planned {
+    /// The plan of a statement that reads through one.
+    pub(super) fn plan(&self) -> Option<&Plan> {
+        match self {
+            Planned::Query { plan, .. }
+            | Planned::Explain { plan, .. }
+            | Planned::CopyTo { plan, .. } => Some(plan),
+            Planned::Insert(insert)
+            | Planned::CreateView { insert, .. }
+            | Planned::RefreshView { insert, .. } => Some(&insert.source),
+            _ => None,
+        }
+    }
+
+    /// The table whose rows the statement changes, which fire its
+    /// triggers.
+    pub(super) fn target(&self) -> Option<&str> {
+        match self {
+            Planned::Insert(Insert { table, .. })
+            | Planned::Update(Update { table, .. })
+            | Planned::Delete(Delete { table, .. })
+            | Planned::Other(BoundStatement::CopyFrom { table, .. }) => Some(table),
+            _ => None,
+        }
+    }
+
+    /// Whether running the statement can change the database, which a
+    /// read-only one refuses to.
+    pub(super) fn writes(&self) -> bool {
+        match self {
+            Planned::Query { .. } | Planned::Explain { .. } | Planned::CopyTo { .. } => false,
+            Planned::Insert(_)
+            | Planned::Update(_)
+            | Planned::Delete(_)
+            | Planned::CreateView { .. }
+            | Planned::RefreshView { .. } => true,
+            Planned::Other(statement) => !matches!(
+                statement,
+                BoundStatement::AsOf { .. }
+                    | BoundStatement::Transaction(_)
+                    | BoundStatement::Set { .. }
+                    | BoundStatement::Show { .. }
+                    | BoundStatement::Attach { .. }
+                    | BoundStatement::Detach { .. }
+                    | BoundStatement::Backup { .. }
+                    | BoundStatement::Notify { .. }
+                    | BoundStatement::Listen { .. }
+                    | BoundStatement::Unlisten { .. }
+            ),
+        }
+    }
+
+    /// Whether the statement is a NOTIFY, which an optimistic transaction
+    /// sends at commit, as it does its writes.
+    pub(super) fn notifies(&self) -> bool {
+        matches!(self, Planned::Other(BoundStatement::Notify { .. }))
+    }
+
+    pub(super) fn new(
+        catalog: &Catalog,
+        statement: BoundStatement,
+        parameters: &[Option<DataType>],
+        settings: PlannerSettings,
+    ) -> Self {
+        let planner = PhysicalPlanner::new(catalog)
+            .with_settings(settings)
+            .with_parameters(parameters);
+        match statement {
+            BoundStatement::Query(logical) => Planned::Query {
+                fields: logical.fields(),
+                plan: planner.plan(&logical),
+            },
+            BoundStatement::Insert {
+                table,
+                source,
+                on_conflict,
+            } => Planned::Insert(Insert {
+                table,
+                source: planner.plan(&source),
+                on_conflict,
+            }),
+            BoundStatement::Update {
+                table,
+                assignments,
+                predicate,
+            } => Planned::Update(Update {
+                access: planner.access_path(&table, predicate.as_ref()),
+                table,
+                predicate,
+                assignments,
+            }),
+            BoundStatement::Delete { table, predicate } => Planned::Delete(Delete {
+                access: planner.access_path(&table, predicate.as_ref()),
+                table,
+                predicate,
+                limit: None,
+            }),
+            BoundStatement::CopyTo {
+                query,
+                file,
+                format,
+            } => Planned::CopyTo {
+                fields: query.fields(),
+                plan: planner.plan(&query),
+                file,
+                format,
+            },
+            BoundStatement::CreateMaterializedView {
+                name,
+                schema,
+                view,
+                query,
+                if_not_exists,
+            } => Planned::CreateView {
+                insert: Insert {
+                    table: name.clone(),
+                    source: planner.plan(&query),
+                    on_conflict: None,
+                },
+                name,
+                schema,
+                view,
+                if_not_exists,
+            },
+            BoundStatement::RefreshMaterializedView { name, query } => Planned::RefreshView {
+                insert: Insert {
+                    table: name,
+                    source: planner.plan(&query),
+                    on_conflict: None,
+                },
+            },
+            BoundStatement::Explain { analyze, statement } => match *statement {
+                BoundStatement::Query(logical) => Planned::Explain {
+                    analyze,
+                    plan: planner.plan(&logical),
+                },
+                statement => Planned::Other(statement),
+            },
+            statement => Planned::Other(statement),
+        }
+    }
+
+    pub(super) fn replace_parameters(&self, params: &[Value]) -> Self {
+        if params.is_empty() {
+            return self.clone();
+        }
+        match self {
+            Planned::Query { fields, plan } => Planned::Query {
+                fields: fields.clone(),
+                plan: plan.replace_parameters(params),
+            },
+            Planned::Insert(insert) => Planned::Insert(insert.replace_parameters(params)),
+            Planned::Update(update) => Planned::Update(update.replace_parameters(params)),
+            Planned::Delete(delete) => Planned::Delete(delete.replace_parameters(params)),
+            Planned::Explain { analyze, plan } => Planned::Explain {
+                analyze: *analyze,
+                plan: plan.replace_parameters(params),
+            },
+            Planned::CopyTo {
+                fields,
+                plan,
+                file,
+                format,
+            } => Planned::CopyTo {
+                fields: fields.clone(),
+                plan: plan.replace_parameters(params),
+                file: file.clone(),
+                format: format.clone(),
+            },
+            // Neither takes parameters.
+            Planned::CreateView { .. } | Planned::RefreshView { .. } | Planned::Other(_) => {
+                self.clone()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::super::{Engine, Output};
+    use super::*;
+    use crate::executor::KeyRange;
+    use crate::expr::Expr;
+    use std::ops::Bound;
+
+    fn index_scan(plan: &Plan) -> Option<&KeyRange> {
+        match plan {
+            Plan::IndexScan { range, .. } => Some(range),
+            Plan::Filter { input, .. } | Plan::Project { input, .. } => index_scan(input),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_prepared_statements() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT, score FLOAT)")
+            .unwrap();
+        let insert = engine.prepare("INSERT INTO t VALUES ($1, $2, $3)").unwrap();
+        assert_eq!(
+            [
+                Some(DataType::Int),
+                Some(DataType::Text),
+                Some(DataType::Float)
+            ],
+            insert.parameter_types()
+        );
+        for i in 0..100i64 {
+            let params = [i.into(), format!("n{i}").into(), i.into()];
+            assert_eq!(
+                Output::Affected(1),
+                engine.execute_prepared(&insert, &params).unwrap()
+            );
+        }
+
+        let select = engine
+            .prepare("SELECT name FROM t WHERE id = $1 AND score >= $2")
+            .unwrap();
+        assert_eq!(
+            [Some(DataType::Int), Some(DataType::Float)],
+            select.parameter_types()
+        );
+        let Planned::Query { plan, .. } = &select.planned else {
+            panic!("not a query");
+        };
+        let key = vec![Expr::Parameter(1)];
+        assert_eq!(
+            Some(&KeyRange {
+                start: Bound::Included(key.clone()),
+                end: Bound::Included(key),
+            }),
+            index_scan(plan)
+        );
+        let name = |engine: &mut Engine, params: &[Value]| {
+            engine
+                .execute_prepared(&select, params)
+                .unwrap()
+                .into_rows()
+        };
+        assert_eq!(
+            vec![vec![Value::from("n7")]],
+            name(&mut engine, &[7.into(), 5.into()])
+        );
+        assert_eq!(
+            Vec::<Vec<Value>>::new(),
+            name(&mut engine, &[7.into(), 8.into()])
+        );
+        assert_eq!(
+            Vec::<Vec<Value>>::new(),
+            name(&mut engine, &[Value::Null, 0.into()])
+        );
+
+        assert!(matches!(
+            engine.execute_prepared(&select, &[7.into()]),
+            Err(Error::ParameterCount {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            engine.execute_prepared(&select, &["7".into(), 0.into()]),
+            Err(Error::ParameterType {
+                number: 1,
+                expected: DataType::Int,
+                actual: DataType::Text,
+            })
+        ));
+
+        let update = engine
+            .prepare("UPDATE t SET score = $2 WHERE id < $1")
+            .unwrap();
+        assert_eq!(
+            Output::Affected(10),
+            engine
+                .execute_prepared(&update, &[10.into(), (-1.0).into()])
+                .unwrap()
+        );
+        let delete = engine.prepare("DELETE FROM t WHERE score = $1").unwrap();
+        assert_eq!(
+            Output::Affected(10),
+            engine.execute_prepared(&delete, &[(-1).into()]).unwrap()
+        );
+
+        // Untyped parameters accept anything, and unbound ones fail.
+        let echo = engine.prepare("SELECT $1").unwrap();
+        assert_eq!([None], echo.parameter_types());
+        assert_eq!(
+            vec![vec![Value::from("x")]],
+            engine
+                .execute_prepared(&echo, &["x".into()])
+                .unwrap()
+                .into_rows()
+        );
+        assert!(matches!(
+            engine.execute("SELECT $1"),
+            Err(Error::ParameterCount { .. })
+        ));
+
+        // A change to the catalog replans the statement.
+        engine.execute("DROP TABLE t").unwrap();
+        engine
+            .execute("CREATE TABLE t (id INT, name TEXT, score FLOAT)")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (7, 'again', 9)")
+            .unwrap();
+        assert_eq!(
+            vec![vec![Value::from("again")]],
+            name(&mut engine, &[7.into(), 5.into()])
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Reuse of query results across executions of the same query.
+//!
+//! A query run outside a transaction keeps its rows, keyed by its
+//! normalized SQL, its parameter values and its user, along with the
+//! commits that had changed each table it read so far, as the engine
+//! counts them for optimistic concurrency. Running it again finds the
+//! rows only while no commit has changed those tables since and the
+//! catalog is as it was; a stale entry is dropped when it is found.
+//!
+//! Transactions neither read nor fill the cache, since their own changes
+//! are not counted until they commit. Queries of system tables, whose
+//! rows the engine makes up as it runs, are not kept, nor results of more
+//! than [`MAX_CACHED_ROWS`] rows. Functions registered with the engine are
+//! taken to return the same for the same arguments, as the builtins do.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::planner::Field;
+use crate::value::Tuple;
+
+/// Rows a result may have and still be kept.
+pub const MAX_CACHED_ROWS: usize = 10_000;
+
+/// Query results by key, evicting the least recently used beyond
+/// `capacity`. Off, with a capacity of 0, unless configured otherwise.
+#[derive(Debug)]
+pub struct ResultCache {
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+    /// Keys by the tick of their last use, oldest first.
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    fields: Vec<Field>,
+    rows: Vec<Tuple>,
+    catalog_version: u64,
+    /// The tables read, with the commits that had changed each.
+    reads: Vec<(String, u64)>,
+    used: u64,
+}
+
+impl ResultCache {
+    /// A cache of at most `capacity` results; 0 disables caching.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Lookups that found a result still valid.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Shrinks the cache to `capacity` entries if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// The result kept for `key`, if the catalog is at `catalog_version`
+    /// and `commits` counts for each table read what it did then.
+    pub(super) fn get(
+        &mut self,
+        key: &str,
+        catalog_version: u64,
+        commits: impl Fn(&str) -> u64,
+    ) -> Option<(Vec<Field>, Vec<Tuple>)> {
+        let Some(entry) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+        let valid = entry.catalog_version == catalog_version
+            && (entry.reads.iter()).all(|(table, count)| commits(table) == *count);
+        if !valid {
+            self.misses += 1;
+            let entry = self.entries.remove(key).unwrap();
+            self.recency.remove(&entry.used);
+            return None;
+        }
+        self.hits += 1;
+        let key = self.recency.remove(&entry.used).unwrap();
+        self.tick += 1;
+        entry.used = self.tick;
+        self.recency.insert(self.tick, key);
+        Some((entry.fields.clone(), entry.rows.clone()))
+    }
+
+    /// Keeps `rows` for `key`, unless there are too many of them.
+    pub(super) fn insert(
+        &mut self,
+        key: String,
+        fields: Vec<Field>,
+        rows: Vec<Tuple>,
+        catalog_version: u64,
+        reads: Vec<(String, u64)>,
+    ) {
+        if self.capacity == 0 || rows.len() > MAX_CACHED_ROWS {
+            return;
+        }
+        self.tick += 1;
+        let entry = Entry {
+            fields,
+            rows,
+            catalog_version,
+            reads,
+            used: self.tick,
+        };
+        if let Some(old) = self.entries.insert(key.clone(), entry) {
+            self.recency.remove(&old.used);
+        }
+        self.recency.insert(self.tick, key);
+        self.evict();
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            let (_, key) = self.recency.pop_first().unwrap();
+            self.entries.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::super::Engine;
+    use crate::value::Value;
+
+    #[test]
+    fn test_invalidated_by_commits() {
+        let mut engine = engine();
+        engine.set_result_cache_capacity(4);
+        for sql in [
+            "CREATE TABLE t (id INT)",
+            "CREATE TABLE u (id INT)",
+            "INSERT INTO t VALUES (1)",
+            "INSERT INTO u VALUES (1)",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        let count =
+            |engine: &mut Engine, sql: &str| engine.execute(sql).unwrap().into_rows()[0][0].clone();
+        let lookups = |engine: &Engine| {
+            let cache = engine.result_cache();
+            (cache.hits(), cache.misses())
+        };
+        assert_eq!(Value::Int(1), count(&mut engine, "SELECT count(*) FROM t"));
+        assert_eq!(Value::Int(1), count(&mut engine, "select COUNT(*) from t"));
+        assert_eq!(Value::Int(1), count(&mut engine, "SELECT count(*) FROM u"));
+        assert_eq!((1, 2), lookups(&engine));
+
+        // A commit to t makes only the results that read it stale.
+        engine.execute("INSERT INTO t VALUES (2)").unwrap();
+        assert_eq!(Value::Int(2), count(&mut engine, "SELECT count(*) FROM t"));
+        assert_eq!(Value::Int(1), count(&mut engine, "SELECT count(*) FROM u"));
+        assert_eq!((2, 3), lookups(&engine));
+
+        // Transactions and system tables go around the cache.
+        engine.execute("BEGIN").unwrap();
+        engine.execute("INSERT INTO u VALUES (2)").unwrap();
+        assert_eq!(Value::Int(2), count(&mut engine, "SELECT count(*) FROM u"));
+        engine.execute("COMMIT").unwrap();
+        engine.execute("SELECT * FROM neru_stat_tables").unwrap();
+        engine.execute("SELECT * FROM neru_stat_tables").unwrap();
+        assert_eq!((2, 3), lookups(&engine));
+        assert_eq!(Value::Int(2), count(&mut engine, "SELECT count(*) FROM u"));
+        assert_eq!(2, engine.result_cache().len());
+    }
+
+    #[test]
+    fn test_keyed_by_null_ordering() {
+        let mut engine = engine();
+        engine.set_result_cache_capacity(4);
+        engine
+            .execute("CREATE TABLE t (id INT, score INT)")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (1, 20), (2, NULL), (3, 10)")
+            .unwrap();
+        let ids = |engine: &mut Engine| {
+            let rows = engine
+                .execute("SELECT id FROM t ORDER BY score DESC")
+                .unwrap();
+            rows.into_rows()
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>()
+        };
+        let ints = |ids: [i64; 3]| ids.map(Value::Int).to_vec();
+        assert_eq!(ints([1, 3, 2]), ids(&mut engine));
+        engine.execute("SET null_ordering TO high").unwrap();
+        assert_eq!(ints([2, 1, 3]), ids(&mut engine));
+        engine.execute("SET null_ordering TO low").unwrap();
+        assert_eq!(ints([1, 3, 2]), ids(&mut engine));
+        assert_eq!(1, engine.result_cache().hits());
+    }
+}
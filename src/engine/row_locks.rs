@@ -0,0 +1,180 @@
+//! The row locks of the engine's transactions, and waiting for them.
+//!
+//! Statements lock rows through the engine's [`LockManager`] for the
+//! transaction they run in, or, outside one, for an id of their own that
+//! lets go of its locks as the statement ends. A transaction keeps its
+//! locks until it commits or rolls back, which a deadlock does to it.
+//! Rows another transaction holds are waited for by running the statement
+//! again once it has ended, which [`retry_row_waits`] does.
+
+use std::time::{Duration, Instant};
+
+use super::prepared::Planned;
+use super::{Engine, Error};
+use crate::executor;
+use crate::lock::{self, LockManager, RowLocker};
+
+impl Engine {
+    /// The row locks of the engine's transactions.
+    pub fn row_locks(&self) -> &LockManager {
+        &self.row_locks
+    }
+
+    /// Whether statements that find a row locked fail with a
+    /// [`lock::Error::Wait`] to wait on, which takes letting go of the
+    /// engine, rather than as deadlocked. Off by default; a session
+    /// holding the engine from BEGIN to COMMIT cannot wait either way.
+    pub fn set_lock_waits(&mut self, waits: bool) {
+        self.lock_waits = waits;
+    }
+
+    /// How the statement about to run locks rows, if it may: for the
+    /// running transaction, or for one of its own outside one.
+    pub(super) fn row_locker(&mut self, planned: &Planned) -> Option<RowLocker> {
+        let locks = match planned {
+            Planned::Query { plan, .. } => plan.locks_rows(),
+            Planned::Update(_) | Planned::Delete(_) | Planned::RefreshView { .. } => true,
+            _ => false,
+        };
+        if !locks {
+            return None;
+        }
+        let optimistic = self.statement_reads.is_some();
+        if !self.in_transaction() && !optimistic {
+            self.last_transaction_id += 1;
+            self.transaction_id = self.last_transaction_id;
+        }
+        Some(RowLocker {
+            locks: self.row_locks.clone(),
+            owner: self.transaction_id,
+            can_wait: self.lock_waits && (optimistic || !self.in_transaction()),
+        })
+    }
+
+    /// Releases the row locks of the running transaction as it ends.
+    pub(super) fn release_row_locks(&mut self) {
+        self.row_locks.release(self.transaction_id);
+    }
+
+    /// Passes on what a statement returned, having rolled back the
+    /// transaction it ran in if it was deadlocked: the victim gives up its
+    /// locks so that the others can go on.
+    pub(super) fn abort_deadlocked<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        if matches!(
+            result,
+            Err(Error::Execute(executor::Error::Lock(lock::Error::Deadlock)))
+        ) && (self.in_transaction() || self.optimistic.is_some())
+        {
+            self.rollback()?;
+        }
+        result
+    }
+}
+
+/// Runs `attempt` again for as long as it fails on a row another
+/// transaction has locked, having waited for that one to end, until
+/// `timeout` has passed since the first attempt. `attempt` must let go of
+/// the engine before it returns, for the transaction waited for to end.
+pub fn retry_row_waits<T>(
+    timeout: Option<Duration>,
+    mut attempt: impl FnMut(Option<Instant>) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        match attempt(deadline) {
+            Err(Error::Execute(executor::Error::Lock(lock::Error::Wait(wait)))) => {
+                wait.wait(deadline, timeout.unwrap_or_default())?;
+            }
+            result => return result,
+        }
+    }
+}
+
+impl From<lock::Error> for Error {
+    fn from(e: lock::Error) -> Self {
+        Error::Execute(executor::Error::Lock(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::tests::engine;
+    use crate::engine::Concurrency;
+    use crate::error::ErrorCode;
+    use crate::value::Value;
+
+    fn ids(engine: &mut Engine, sql: &str) -> Result<Vec<i64>, Error> {
+        let rows = engine.execute(sql)?.into_rows();
+        let id = |row: &Vec<Value>| match row[0] {
+            Value::Int(id) => id,
+            ref value => panic!("not an id: {value:?}"),
+        };
+        Ok(rows.iter().map(id).collect())
+    }
+
+    #[test]
+    fn test_row_locks() {
+        let mut engine = engine();
+        engine.set_concurrency(Concurrency::Optimistic);
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, n INT)")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (1, 0), (2, 0), (3, 0)")
+            .unwrap();
+
+        // One transaction locks row 1 and, a statement later, row 2.
+        engine.execute("BEGIN").unwrap();
+        assert_eq!(
+            vec![1],
+            ids(
+                &mut engine,
+                "SELECT id FROM t WHERE id = 1 FOR NO KEY UPDATE"
+            )
+            .unwrap()
+        );
+        engine.execute("UPDATE t SET n = 1 WHERE id = 2").unwrap();
+        let id = engine.transaction_id;
+        let holder = engine.suspend_transaction().unwrap();
+        assert_eq!(2, engine.row_locks().held(id));
+
+        // Others skip them, fail at once or, with no way to wait here,
+        // are deadlocked; a weak enough mode shares them.
+        let sql = "SELECT id FROM t ORDER BY id LIMIT 1 FOR UPDATE SKIP LOCKED";
+        assert_eq!(vec![3], ids(&mut engine, sql).unwrap());
+        let e = ids(&mut engine, "SELECT id FROM t FOR SHARE NOWAIT").unwrap_err();
+        assert_eq!(ErrorCode::LockNotAvailable, e.code());
+        assert_eq!(
+            vec![1, 2, 3],
+            ids(&mut engine, "SELECT id FROM t ORDER BY id FOR KEY SHARE").unwrap()
+        );
+        let e = engine.execute("DELETE FROM t WHERE id = 1").unwrap_err();
+        assert_eq!(ErrorCode::DeadlockDetected, e.code());
+        // Locks outside a transaction go with their statement.
+        assert_eq!(2, engine.row_locks().held(id));
+        assert_eq!(0, engine.row_locks().held(engine.transaction_id));
+
+        // A deadlocked transaction is rolled back, giving up its locks.
+        engine.execute("BEGIN").unwrap();
+        ids(&mut engine, "SELECT id FROM t WHERE id = 3 FOR UPDATE").unwrap();
+        let e = engine
+            .execute("UPDATE t SET n = 2 WHERE id < 3")
+            .unwrap_err();
+        assert_eq!(ErrorCode::DeadlockDetected, e.code());
+        assert!(!engine.in_optimistic_transaction());
+        assert_eq!(0, engine.row_locks().held(engine.transaction_id));
+
+        // Until the holder ends, when its rows are free again.
+        engine.resume_transaction(holder).unwrap();
+        engine.execute("COMMIT").unwrap();
+        engine.execute("UPDATE t SET n = 2 WHERE id = 1").unwrap();
+        assert_eq!(
+            vec![vec![Value::Int(2)], vec![Value::Int(1)]],
+            engine
+                .execute("SELECT n FROM t WHERE id < 3 ORDER BY id")
+                .unwrap()
+                .into_rows()
+        );
+    }
+}
@@ -0,0 +1,294 @@
+//! Settings a session changes with SET and reads with SHOW.
+//!
+//! Besides the planner's knobs ([`PlannerSettings`]) they are
+//! `statement_timeout`, `lock_timeout`, `work_mem`, `memory_limit`,
+//! `log_min_duration_statement`, `search_path` and `transaction_isolation`.
+//! Sizes and durations are spelled as in a config file (see
+//! [`crate::database::config`]).
+//!
+//! Tables live in a single namespace, so `search_path` changes nothing
+//! but what SHOW reports; it is kept for clients that set it on connect.
+//! Likewise transactions run one at a time, or are validated at commit
+//! under optimistic concurrency, so they are all serializable: any level
+//! may be set, as clients do on connect, but it is raised to SERIALIZABLE
+//! and SHOW reports that.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::planner::{Error, PlannerSettings};
+
+/// The least `work_mem` worth spilling with.
+pub(crate) const MIN_WORK_MEM: usize = 64 << 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadUncommitted,
+    ReadCommitted,
+    RepeatableRead,
+    #[default]
+    Serializable,
+}
+
+impl fmt::Display for IsolationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IsolationLevel::ReadUncommitted => "read uncommitted",
+            IsolationLevel::ReadCommitted => "read committed",
+            IsolationLevel::RepeatableRead => "repeatable read",
+            IsolationLevel::Serializable => "serializable",
+        })
+    }
+}
+
+impl FromStr for IsolationLevel {
+    type Err = ();
+
+    /// A level as SQL spells it, in any case.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let words: Vec<_> = s.split_whitespace().collect();
+        match words.join(" ").to_lowercase().as_str() {
+            "read uncommitted" => Ok(IsolationLevel::ReadUncommitted),
+            "read committed" => Ok(IsolationLevel::ReadCommitted),
+            "repeatable read" => Ok(IsolationLevel::RepeatableRead),
+            "serializable" => Ok(IsolationLevel::Serializable),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionSettings {
+    pub planner: PlannerSettings,
+    /// `None` lets statements run for as long as they take.
+    pub statement_timeout: Option<Duration>,
+    /// How long a session waits for another to release the database
+    /// before giving up; `None` waits for as long as it takes.
+    pub lock_timeout: Option<Duration>,
+    /// Bytes each sort, aggregation or hash join may hold before it
+    /// spills; `None` never spills.
+    pub work_mem: Option<usize>,
+    /// Bytes a statement's operators may hold in all, past which it fails
+    /// rather than grow; `None` has no limit.
+    pub memory_limit: Option<usize>,
+    /// Statements running at least this long go to the slow query log;
+    /// `None` logs none.
+    pub log_min_duration_statement: Option<Duration>,
+    pub search_path: String,
+    /// The level transactions run at, which is always serializable.
+    pub isolation: IsolationLevel,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            planner: PlannerSettings::default(),
+            statement_timeout: None,
+            lock_timeout: None,
+            work_mem: None,
+            memory_limit: None,
+            log_min_duration_statement: None,
+            search_path: "\"$user\", public".to_string(),
+            isolation: IsolationLevel::default(),
+        }
+    }
+}
+
+impl SessionSettings {
+    /// Names of the settings besides the planner's.
+    const OWN_NAMES: &'static [&'static str] = &[
+        "lock_timeout",
+        "log_min_duration_statement",
+        "memory_limit",
+        "search_path",
+        "statement_timeout",
+        "transaction_isolation",
+        "work_mem",
+    ];
+
+    /// Names of every setting, in the order SHOW ALL lists them.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::OWN_NAMES
+            .iter()
+            .chain(PlannerSettings::NAMES)
+            .copied()
+    }
+
+    /// Changes a setting by name. A `statement_timeout` or `lock_timeout`
+    /// of 0, a `work_mem` or `memory_limit` of `unlimited` and a
+    /// `log_min_duration_statement` of -1 turn them off.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let invalid = || Error::InvalidSetting {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        match name {
+            "lock_timeout" => {
+                let timeout = parse_duration(value).ok_or_else(invalid)?;
+                self.lock_timeout = (!timeout.is_zero()).then_some(timeout);
+            }
+            "log_min_duration_statement" if value.trim() == "-1" => {
+                self.log_min_duration_statement = None
+            }
+            "log_min_duration_statement" => {
+                self.log_min_duration_statement = Some(parse_duration(value).ok_or_else(invalid)?)
+            }
+            "memory_limit" if value.eq_ignore_ascii_case("unlimited") => self.memory_limit = None,
+            "memory_limit" => {
+                let limit = parse_size(value)
+                    .filter(|&size| size >= MIN_WORK_MEM)
+                    .ok_or_else(invalid)?;
+                self.memory_limit = Some(limit);
+            }
+            "search_path" => self.search_path = value.to_string(),
+            "statement_timeout" => {
+                let timeout = parse_duration(value).ok_or_else(invalid)?;
+                self.statement_timeout = (!timeout.is_zero()).then_some(timeout);
+            }
+            "transaction_isolation" => {
+                value.parse::<IsolationLevel>().map_err(|()| invalid())?;
+                self.isolation = IsolationLevel::Serializable;
+            }
+            "work_mem" if value.eq_ignore_ascii_case("unlimited") => self.work_mem = None,
+            "work_mem" => {
+                let work_mem = parse_size(value)
+                    .filter(|&size| size >= MIN_WORK_MEM)
+                    .ok_or_else(invalid)?;
+                self.work_mem = Some(work_mem);
+            }
+            _ => self.planner.set(name, value)?,
+        }
+        Ok(())
+    }
+
+    /// The value of a setting, spelled so that [`SessionSettings::set`]
+    /// reads it back.
+    pub fn get(&self, name: &str) -> Result<String, Error> {
+        Ok(match name {
+            "lock_timeout" => self.lock_timeout.map_or("0".to_string(), format_duration),
+            "log_min_duration_statement" => self
+                .log_min_duration_statement
+                .map_or("-1".to_string(), format_duration),
+            "memory_limit" => (self.memory_limit).map_or("unlimited".to_string(), format_size),
+            "search_path" => self.search_path.clone(),
+            "statement_timeout" => self
+                .statement_timeout
+                .map_or("0".to_string(), format_duration),
+            "transaction_isolation" => self.isolation.to_string(),
+            "work_mem" => self.work_mem.map_or("unlimited".to_string(), format_size),
+            _ => self.planner.get(name)?,
+        })
+    }
+
+    /// Puts a setting back as it is in `defaults`.
+    pub fn reset(&mut self, name: &str, defaults: &SessionSettings) -> Result<(), Error> {
+        self.set(name, &defaults.get(name)?)
+    }
+}
+
+fn split_unit(value: &str) -> Option<(u64, &str)> {
+    let value = value.trim();
+    let end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let number = value[..end].parse().ok()?;
+    Some((number, value[end..].trim()))
+}
+
+/// Bytes, or a number with a unit of B, kB, MB or GB.
+pub(crate) fn parse_size(value: &str) -> Option<usize> {
+    let (number, unit) = split_unit(value)?;
+    let scale: u64 = match unit {
+        "" | "B" => 1,
+        "kB" | "KB" => 1 << 10,
+        "MB" => 1 << 20,
+        "GB" => 1 << 30,
+        _ => return None,
+    };
+    number.checked_mul(scale)?.try_into().ok()
+}
+
+/// Milliseconds, or a number with a unit of ms, s, min or h.
+pub(crate) fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = split_unit(value)?;
+    let millis: u64 = match unit {
+        "" | "ms" => 1,
+        "s" => 1000,
+        "min" => 60_000,
+        "h" => 3_600_000,
+        _ => return None,
+    };
+    Some(Duration::from_millis(number.checked_mul(millis)?))
+}
+
+/// `size` in the largest unit that divides it.
+fn format_size(size: usize) -> String {
+    let (scale, unit) = [(1 << 30, "GB"), (1 << 20, "MB"), (1 << 10, "kB")]
+        .into_iter()
+        .find(|(scale, _)| size.is_multiple_of(*scale))
+        .unwrap_or((1, "B"));
+    format!("{}{unit}", size / scale)
+}
+
+/// `duration` in whole milliseconds, in the largest unit that divides it.
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    let (scale, unit) = [(3_600_000, "h"), (60_000, "min"), (1000, "s")]
+        .into_iter()
+        .find(|(scale, _)| millis.is_multiple_of(*scale))
+        .unwrap_or((1, "ms"));
+    format!("{}{unit}", millis / scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_and_get() {
+        let mut settings = SessionSettings::default();
+        settings.set("statement_timeout", "90s").unwrap();
+        assert_eq!(Some(Duration::from_secs(90)), settings.statement_timeout);
+        assert_eq!("90s", settings.get("statement_timeout").unwrap());
+        settings.set("lock_timeout", "1500").unwrap();
+        assert_eq!(Some(Duration::from_millis(1500)), settings.lock_timeout);
+        assert_eq!("1500ms", settings.get("lock_timeout").unwrap());
+        settings.set("work_mem", "2048kB").unwrap();
+        assert_eq!("2MB", settings.get("work_mem").unwrap());
+        settings.set("memory_limit", "1GB").unwrap();
+        assert_eq!(Some(1 << 30), settings.memory_limit);
+        settings.set("memory_limit", "unlimited").unwrap();
+        assert_eq!("unlimited", settings.get("memory_limit").unwrap());
+        settings
+            .set("transaction_isolation", "REPEATABLE  read")
+            .unwrap();
+        assert_eq!(
+            "serializable",
+            settings.get("transaction_isolation").unwrap()
+        );
+        settings.set("log_min_duration_statement", "250").unwrap();
+        assert_eq!("250ms", settings.get("log_min_duration_statement").unwrap());
+        settings.set("enable_seqscan", "off").unwrap();
+        assert!(!settings.planner.enable_seqscan);
+
+        assert!(matches!(
+            settings.set("work_mem", "1kB"),
+            Err(Error::InvalidSetting { .. })
+        ));
+        assert!(matches!(
+            settings.set("transaction_isolation", "chaotic"),
+            Err(Error::InvalidSetting { .. })
+        ));
+        assert!(matches!(
+            settings.get("nope"),
+            Err(Error::UnknownSetting(_))
+        ));
+
+        let defaults = SessionSettings::default();
+        for name in SessionSettings::names() {
+            settings.reset(name, &defaults).unwrap();
+        }
+        assert_eq!(defaults, settings);
+    }
+}
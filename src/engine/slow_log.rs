@@ -0,0 +1,126 @@
+//! Statements that ran for longer than `log_min_duration_statement`.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::Duration;
+
+/// Entries kept unless configured otherwise.
+pub const DEFAULT_SLOW_QUERY_LOG_CAPACITY: usize = 100;
+
+/// A statement the slow query log recorded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowQuery {
+    pub sql: String,
+    pub duration: Duration,
+    /// Rows the statement returned, or inserted, updated or deleted.
+    pub rows: u64,
+    /// The plan as EXPLAIN shows it, for statements that have one.
+    pub plan: Option<String>,
+}
+
+impl fmt::Display for SlowQuery {
+    /// Formats the entry as a log line, without the plan.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "duration: {:.3} ms  rows: {}  statement: {}",
+            self.duration.as_secs_f64() * 1000.0,
+            self.rows,
+            self.sql
+        )
+    }
+}
+
+/// The latest slow statements, dropping the oldest beyond `capacity`.
+#[derive(Debug, Default)]
+pub struct SlowQueryLog {
+    capacity: usize,
+    entries: VecDeque<SlowQuery>,
+}
+
+impl SlowQueryLog {
+    /// A log keeping at most `capacity` statements; 0 keeps none.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Self::default()
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The statements kept, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &SlowQuery> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub(super) fn record(&mut self, query: SlowQuery) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(query);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::super::tests::engine;
+    use crate::value::Value;
+
+    #[test]
+    fn test_slow_query_log() {
+        let mut engine = engine();
+        engine.execute("CREATE TABLE t (id INT)").unwrap();
+        engine.execute("SELECT * FROM t").unwrap();
+        assert!(engine.slow_queries().is_empty());
+
+        let (sender, receiver) = mpsc::channel();
+        engine.set_slow_query_sink(move |query| sender.send(query.sql.clone()).unwrap());
+        engine.slow_queries_mut().set_capacity(2);
+        engine
+            .execute("SET log_min_duration_statement = 0")
+            .unwrap();
+        engine.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+        engine.execute("SELECT * FROM t WHERE id > 1").unwrap();
+        let rows = engine
+            .execute("SELECT sql, rows FROM neru_slow_queries")
+            .unwrap()
+            .into_rows();
+        assert_eq!(
+            vec![
+                vec![Value::from("INSERT INTO t VALUES (1), (2)"), Value::Int(2)],
+                vec![Value::from("SELECT * FROM t WHERE id > 1"), Value::Int(1)],
+            ],
+            rows
+        );
+        let plan = engine.slow_queries().iter().next().unwrap().plan.clone();
+        assert!(plan.unwrap().contains("Seq Scan on t"));
+        assert_eq!(3, receiver.try_iter().count());
+    }
+}
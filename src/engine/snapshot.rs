@@ -0,0 +1,39 @@
+//! Snapshot files written between statements.
+
+use std::path::Path;
+
+use super::{Engine, Error};
+use crate::backup::SnapshotFile;
+
+impl Engine {
+    /// Starts copying the database as it is now to a new file at `path`,
+    /// for [`Engine::copy_snapshots`] to write; see [`SnapshotFile`].
+    pub fn start_snapshot(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let snapshot = SnapshotFile::create(&self.bufmgr, path)?;
+        self.snapshots.push(snapshot);
+        Ok(())
+    }
+
+    /// Copies up to `limit` pages to the snapshot files being written,
+    /// oldest first, and returns how many it copied; fewer than `limit`
+    /// means every snapshot is complete. A snapshot that fails is
+    /// abandoned and its file removed, and the call returns its error.
+    pub fn copy_snapshots(&mut self, limit: u64) -> Result<u64, Error> {
+        let mut copied = 0;
+        while let Some(snapshot) = self.snapshots.first_mut() {
+            let result = snapshot.copy(&self.bufmgr, limit - copied);
+            copied += match result {
+                Ok(n) => n,
+                Err(e) => {
+                    self.snapshots.remove(0);
+                    return Err(e.into());
+                }
+            };
+            if copied == limit {
+                break;
+            }
+            self.snapshots.remove(0);
+        }
+        Ok(copied)
+    }
+}
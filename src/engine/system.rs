@@ -0,0 +1,235 @@
+//! The rows of the system tables, from the engine's own state.
+
+use std::time::Instant;
+
+use super::SlowQueryLog;
+use crate::catalog::SystemTable;
+use crate::executor::{self, ExecContext, Progress, QueryProgress, SystemTables};
+use crate::value::{Tuple, Value};
+
+/// What the statement being run sees of the engine.
+pub(super) struct SystemRows<'a> {
+    pub sql: &'a str,
+    pub started: Instant,
+    pub progress: &'a QueryProgress,
+    pub user: Option<&'a str>,
+    pub in_transaction: bool,
+    pub slow_queries: &'a SlowQueryLog,
+}
+
+fn text(text: Option<&str>) -> Value {
+    text.map_or(Value::Null, Value::from)
+}
+
+impl SystemTables for SystemRows<'_> {
+    fn rows(
+        &self,
+        table: SystemTable,
+        ctx: &ExecContext<'_>,
+    ) -> Result<Vec<Tuple>, executor::Error> {
+        let count = |n: u64| Value::Int(n as i64);
+        let millis = |seconds: f64| Value::Float(seconds * 1000.0);
+        Ok(match table {
+            SystemTable::StatBuffer => {
+                let stats = ctx.bufmgr.stats();
+                vec![vec![
+                    count(ctx.bufmgr.pool_size() as u64),
+                    count(ctx.bufmgr.num_pages()),
+                    count(stats.hits),
+                    count(stats.misses),
+                    stats.hit_ratio().map_or(Value::Null, Value::Float),
+                    count(stats.evictions),
+                    count(stats.dirty_evictions),
+                    count(stats.latch_waits),
+                    count(stats.disk.pages_read),
+                    count(stats.disk.pages_written),
+                    count(stats.disk.syncs),
+                ]]
+            }
+            SystemTable::StatTables => {
+                let mut tables: Vec<_> = ctx.catalog.tables().collect();
+                tables.sort_by(|a, b| a.name.cmp(&b.name));
+                tables
+                    .into_iter()
+                    .map(|table| {
+                        Ok(vec![
+                            Value::from(table.name.as_str()),
+                            count(table.schema.len() as u64),
+                            count(table.indexes.len() as u64),
+                            count(table.heap.page_ids(ctx.bufmgr)?.len() as u64),
+                            table
+                                .stats
+                                .as_ref()
+                                .map_or(Value::Null, |stats| count(stats.rows as u64)),
+                        ])
+                    })
+                    .collect::<Result<_, executor::Error>>()?
+            }
+            SystemTable::StatIndexes => {
+                let pending = (ctx.catalog.pending_indexes().iter()).map(|pending| {
+                    let error = pending.error.as_deref();
+                    (pending.table.as_str(), &pending.index, false, error)
+                });
+                let mut indexes: Vec<_> = (ctx.catalog.tables())
+                    .flat_map(|table| {
+                        let indexes = table.indexes.iter();
+                        indexes.map(|index| (table.name.as_str(), index, true, None))
+                    })
+                    .chain(pending)
+                    .collect();
+                indexes.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+                indexes
+                    .into_iter()
+                    .map(|(table, index, valid, error)| {
+                        let stats = index.btree.stats(ctx.bufmgr)?;
+                        Ok(vec![
+                            Value::from(index.name.as_str()),
+                            Value::from(table),
+                            count(stats.leaf_pages + stats.branch_pages),
+                            count(stats.leaf_pages),
+                            count(stats.pairs),
+                            Value::Float(stats.fill_factor()),
+                            Value::Float(stats.empty_ratio()),
+                            Value::Bool(valid),
+                            error.map_or(Value::Null, Value::from),
+                        ])
+                    })
+                    .collect::<Result<_, executor::Error>>()?
+            }
+            SystemTable::Locks => {
+                let mut locks = vec![];
+                // Taken by DiskManager::open so that no other process writes.
+                if !ctx.bufmgr.is_read_only() {
+                    locks.push(vec![
+                        "database file".into(),
+                        "exclusive".into(),
+                        Value::Null,
+                    ]);
+                }
+                // Sessions of a server wait for the transaction to end.
+                if self.in_transaction {
+                    locks.push(vec!["database".into(), "exclusive".into(), text(self.user)]);
+                }
+                locks
+            }
+            SystemTable::ActiveQueries => {
+                // As far as the statement has got when it reads the table.
+                let progress = self.progress.get();
+                let progress = progress.as_ref();
+                let counter = |field: fn(&Progress) -> u64| count(progress.map_or(0, field));
+                vec![vec![
+                    Value::from(self.sql),
+                    text(self.user),
+                    millis(self.started.elapsed().as_secs_f64()),
+                    Value::Bool(self.in_transaction),
+                    counter(|p| p.pages_scanned),
+                    counter(|p| p.pages_total),
+                    (progress.and_then(Progress::percent)).map_or(Value::Null, Value::Float),
+                    counter(|p| p.rows),
+                    counter(|p| p.spilled_bytes),
+                ]]
+            }
+            SystemTable::SlowQueries => self
+                .slow_queries
+                .iter()
+                .map(|query| {
+                    vec![
+                        Value::from(query.sql.as_str()),
+                        millis(query.duration.as_secs_f64()),
+                        count(query.rows),
+                        text(query.plan.as_deref()),
+                    ]
+                })
+                .collect(),
+            SystemTable::History => (ctx.bufmgr.history().iter())
+                .flat_map(|history| history.points())
+                .map(|point| vec![count(point.lsn), count(point.time), count(point.num_pages)])
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use crate::value::Value;
+
+    #[test]
+    fn test_system_tables() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        engine.execute("INSERT INTO t VALUES (1, 'a')").unwrap();
+        let mut query = |sql: &str| engine.execute(sql).unwrap().into_rows();
+        assert_eq!(
+            vec![vec![
+                Value::from("t"),
+                Value::Int(2),
+                Value::Int(1),
+                Value::Int(1),
+                Value::Null
+            ]],
+            query("SELECT * FROM neru_stat_tables")
+        );
+        query("ANALYZE t");
+        assert_eq!(
+            vec![vec![Value::Int(1)]],
+            query("SELECT analyzed_rows FROM neru_stat_tables WHERE name = 't'")
+        );
+        let buffer = query("SELECT pool_pages, hits > 0 FROM neru_stat_buffer");
+        assert_eq!(vec![vec![Value::Int(32), Value::Bool(true)]], buffer);
+
+        let sql = "SELECT sql, in_transaction FROM neru_active_queries";
+        assert_eq!(vec![vec![Value::from(sql), Value::Bool(false)]], query(sql));
+        assert_eq!(1, query("SELECT * FROM neru_locks").len());
+        query("BEGIN");
+        assert_eq!(
+            vec![vec![Value::from("database"), Value::Null]],
+            query("SELECT object, holder FROM neru_locks WHERE mode = 'exclusive' AND object <> 'database file'")
+        );
+        query("COMMIT");
+        assert!(engine
+            .execute("INSERT INTO neru_locks VALUES ('x', 'y', NULL)")
+            .is_err());
+    }
+
+    #[test]
+    fn test_reindex_packs_index() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        engine.execute("CREATE INDEX t_name ON t (name)").unwrap();
+        for i in 0..2000 {
+            let sql = format!("INSERT INTO t VALUES ({i}, 'name{:04}')", 1999 - i);
+            engine.execute(&sql).unwrap();
+        }
+        engine.execute("DELETE FROM t WHERE id % 10 <> 0").unwrap();
+        let mut query = |sql: &str| engine.execute(sql).unwrap().into_rows();
+        let stats = "SELECT leaf_pages, entries, fill_factor < 0.3, empty_ratio FROM neru_stat_indexes WHERE name = 't_name'";
+        let sparse = query(stats);
+        assert_eq!(Value::Int(200), sparse[0][1]);
+        assert_eq!(Value::Bool(true), sparse[0][2]);
+
+        query("REINDEX INDEX t_name");
+        let packed = query(stats);
+        let (Value::Int(before), Value::Int(after)) = (&sparse[0][0], &packed[0][0]) else {
+            panic!("leaf_pages of {sparse:?} and {packed:?}");
+        };
+        assert!(after < before);
+        assert_eq!(
+            vec![Value::Int(200), Value::Bool(false), Value::Float(0.0)],
+            packed[0][1..]
+        );
+        assert_eq!(
+            vec![vec![Value::Int(1990)]],
+            query("SELECT id FROM t WHERE name = 'name0009'")
+        );
+        query("INSERT INTO t VALUES (1, 'name0009')");
+        assert_eq!(2, query("SELECT id FROM t WHERE name = 'name0009'").len());
+        query("REINDEX TABLE t");
+        assert_eq!(2, query("SELECT * FROM neru_stat_indexes").len());
+        assert!(engine.execute("REINDEX INDEX nope").is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Holding writes back while dirty pages pile up.
+//!
+//! Outside a transaction, statements leave the pages they change dirty in
+//! the buffer pool, which writes them to the file only as it evicts them or
+//! at the next flush. A burst of writes can so leave most of the pool
+//! dirty, and the next BEGIN, commit or close then writes it all at once.
+//! With a [`WriteThrottle`], each statement that writes is admitted only
+//! after it has paid for some of that: past the soft limit it writes back
+//! a few of the least used dirty pages first, which slows writers down in
+//! step with how far behind the file is, and past the hard limit it writes
+//! back enough to bring the pool down to the soft limit, which blocks it
+//! for as long as that takes. Statements in a transaction are not held
+//! back, since their pages stay in the pool until it ends.
+
+use super::{Engine, Error};
+use crate::buffer::BufferPoolManager;
+
+/// Pages a statement writes back before it runs while the pool is past
+/// the soft limit.
+pub const THROTTLE_BATCH: usize = 16;
+
+/// Dirty pages at which writing statements are held back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteThrottle {
+    /// Past this, each writing statement first writes back up to
+    /// [`THROTTLE_BATCH`] pages.
+    pub soft_limit: Option<usize>,
+    /// Past this, each writing statement first writes back down to the
+    /// soft limit, or to this limit without one.
+    pub hard_limit: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Throttle {
+    pub limits: WriteThrottle,
+    /// Statements that wrote back a batch, and that wrote back down to
+    /// the soft limit, before they ran.
+    pub throttled: u64,
+    pub stalled: u64,
+}
+
+impl Throttle {
+    fn admit(&mut self, bufmgr: &BufferPoolManager) -> Result<(), Error> {
+        let WriteThrottle {
+            soft_limit,
+            hard_limit,
+        } = self.limits;
+        if soft_limit.is_none() && hard_limit.is_none() {
+            return Ok(());
+        }
+        let dirty = bufmgr.dirty_pages();
+        if let Some(hard_limit) = hard_limit.filter(|&limit| dirty > limit) {
+            let target = soft_limit.map_or(hard_limit, |soft| soft.min(hard_limit));
+            bufmgr.write_back(dirty - target)?;
+            self.stalled += 1;
+        } else if let Some(soft_limit) = soft_limit.filter(|&limit| dirty > limit) {
+            bufmgr.write_back(THROTTLE_BATCH.min(dirty - soft_limit))?;
+            self.throttled += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Engine {
+    /// Holds writing statements back once `throttle`'s limits are passed;
+    /// the default holds none back.
+    pub fn set_write_throttle(&mut self, throttle: WriteThrottle) {
+        self.throttle.limits = throttle;
+    }
+
+    /// Called before a statement that writes runs.
+    pub(super) fn admit_write(&mut self) -> Result<(), Error> {
+        if self.in_transaction() {
+            return Ok(());
+        }
+        self.throttle.admit(&self.bufmgr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::*;
+
+    #[test]
+    fn test_throttle_writes() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        engine.bufmgr().flush().unwrap();
+        engine.set_write_throttle(WriteThrottle {
+            soft_limit: Some(4),
+            hard_limit: Some(12),
+        });
+        let filler = "x".repeat(1000);
+        let insert = |engine: &mut Engine, from: usize| {
+            let rows: Vec<_> = (from..from + 60)
+                .map(|i| format!("({i}, '{filler}')"))
+                .collect();
+            let sql = format!("INSERT INTO t VALUES {}", rows.join(", "));
+            engine.execute(&sql).unwrap();
+        };
+        insert(&mut engine, 0);
+        let dirty = engine.bufmgr().dirty_pages();
+        assert!(dirty > 12, "{dirty} dirty pages");
+        insert(&mut engine, 60);
+        assert_eq!((0, 1), (engine.throttle.throttled, engine.throttle.stalled));
+
+        engine.bufmgr().write_back(usize::MAX).unwrap();
+        engine
+            .execute("UPDATE t SET name = 'y' WHERE id < 30")
+            .unwrap();
+        let dirty = engine.bufmgr().dirty_pages();
+        assert!((5..=12).contains(&dirty), "{dirty} dirty pages");
+        engine.execute("DELETE FROM t WHERE id = 0").unwrap();
+        assert_eq!((1, 1), (engine.throttle.throttled, engine.throttle.stalled));
+        assert!(engine.bufmgr().dirty_pages() < dirty);
+
+        // Reads are not held back.
+        engine.execute("SELECT * FROM t").unwrap();
+        assert_eq!(1, engine.metrics().throttled_writes);
+    }
+}
@@ -0,0 +1,174 @@
+//! Planning the triggers a statement may fire.
+//!
+//! A statement that changes a table is planned together with the action
+//! of each trigger on it, and with those of the triggers on the tables the
+//! actions change in turn, so that firing them costs no planning. The
+//! actions run with no user, as their definer, a superuser, would.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use super::prepared::Planned;
+use super::{Engine, Error};
+use crate::catalog::TriggerAction;
+use crate::executor::{PlannedTrigger, TriggerPlan, TriggerRow, Triggers};
+use crate::planner;
+use crate::sql;
+
+impl Engine {
+    /// Runs `function` for the triggers created with `EXECUTE FUNCTION
+    /// name()`. A function must be registered again each time the
+    /// database is opened; until it is, statements that would fire it
+    /// fail.
+    pub fn register_trigger_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(&mut TriggerRow) -> Result<bool, String> + Send + Sync + 'static,
+    ) {
+        self.trigger_functions
+            .insert(name.to_string(), Arc::new(function));
+        // Plans hold the functions they found.
+        self.catalog_version += 1;
+        self.plan_cache.clear();
+    }
+
+    /// The triggers that changing the rows of `table` may fire.
+    pub(super) fn plan_triggers(&self, table: &str) -> Result<Triggers, Error> {
+        let mut triggers = Triggers::new();
+        let mut pending = vec![table.to_string()];
+        let mut seen = BTreeSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            let Some(table) = self.catalog.table(&name) else {
+                continue;
+            };
+            for trigger in &table.triggers {
+                let planned = match &trigger.action {
+                    TriggerAction::Function(function) => PlannedTrigger {
+                        plan: TriggerPlan::Function(
+                            self.trigger_functions
+                                .get(function)
+                                .cloned()
+                                .ok_or_else(|| Error::UnknownTriggerFunction(function.clone()))?,
+                        ),
+                        parameter_types: vec![],
+                    },
+                    TriggerAction::Statement { definition, .. } => {
+                        self.plan_trigger_action(definition)?
+                    }
+                };
+                pending.extend(planned.plan.table().map(String::from));
+                triggers.insert(trigger.name.clone(), planned);
+            }
+        }
+        Ok(triggers)
+    }
+
+    fn plan_trigger_action(&self, definition: &str) -> Result<PlannedTrigger, Error> {
+        let statement = sql::parse_statement(definition)?;
+        let null_ordering = self.settings.planner.null_ordering;
+        let (statement, parameter_types) =
+            planner::bind_prepared(&self.catalog, &statement, None, null_ordering)?;
+        let statement = self.optimizer.optimize_statement(statement);
+        let plan = match Planned::new(
+            &self.catalog,
+            statement,
+            &parameter_types,
+            self.settings.planner.clone(),
+        ) {
+            Planned::Insert(insert) => TriggerPlan::Insert(insert),
+            Planned::Update(update) => TriggerPlan::Update(update),
+            Planned::Delete(delete) => TriggerPlan::Delete(delete),
+            _ => unreachable!("the parser only takes changes for actions"),
+        };
+        Ok(PlannedTrigger {
+            plan,
+            parameter_types,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::engine;
+    use super::super::{Error, Output};
+    use crate::executor;
+    use crate::value::Value;
+
+    #[test]
+    fn test_triggers() {
+        let mut engine = engine();
+        engine
+            .execute("CREATE TABLE t (id INT, name TEXT)")
+            .unwrap();
+        engine
+            .execute("CREATE TABLE log (old_id INT, new_id INT, doubled INT)")
+            .unwrap();
+        engine
+            .execute(
+                "CREATE TRIGGER audit AFTER INSERT OR UPDATE OR DELETE ON t \
+                 FOR EACH ROW INSERT INTO log VALUES (old.id, new.id, new.id * 2)",
+            )
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (1, 'a'), (2, 'b')")
+            .unwrap();
+        engine.execute("UPDATE t SET id = 3 WHERE id = 2").unwrap();
+        engine.execute("DELETE FROM t WHERE id = 1").unwrap();
+        let n = |n: i64| Value::Int(n);
+        assert_eq!(
+            vec![
+                vec![Value::Null, n(1), n(2)],
+                vec![Value::Null, n(2), n(4)],
+                vec![n(2), n(3), n(6)],
+                vec![n(1), Value::Null, Value::Null],
+            ],
+            engine.execute("SELECT * FROM log").unwrap().into_rows()
+        );
+
+        // A function may change the row a BEFORE trigger sees, or refuse it.
+        assert!(matches!(
+            engine.execute("CREATE TRIGGER upper BEFORE INSERT ON t EXECUTE FUNCTION upper()"),
+            Err(Error::UnknownTriggerFunction(_))
+        ));
+        engine.register_trigger_function("upper", |row| {
+            let new = row.new.as_mut().unwrap();
+            let Value::Text(name) = &new[1] else {
+                return Ok(false);
+            };
+            new[1] = Value::from(name.to_uppercase());
+            Ok(true)
+        });
+        engine
+            .execute("CREATE TRIGGER upper BEFORE INSERT ON t EXECUTE FUNCTION upper()")
+            .unwrap();
+        assert_eq!(
+            Output::Affected(1),
+            engine
+                .execute("INSERT INTO t VALUES (4, 'd'), (5, NULL)")
+                .unwrap()
+        );
+        assert_eq!(
+            vec![vec![n(3), Value::from("b")], vec![n(4), Value::from("D")]],
+            engine
+                .execute("SELECT * FROM t ORDER BY id")
+                .unwrap()
+                .into_rows()
+        );
+
+        // An action that fires itself runs out of depth.
+        engine
+            .execute("CREATE TRIGGER again AFTER INSERT ON log INSERT INTO log VALUES (new.old_id, 0, 0)")
+            .unwrap();
+        assert!(matches!(
+            engine.execute("INSERT INTO log VALUES (1, 1, 1)"),
+            Err(Error::Execute(executor::Error::TriggerDepth(_)))
+        ));
+        engine.execute("DROP TRIGGER again").unwrap();
+        engine.execute("DROP TRIGGER IF EXISTS again").unwrap();
+        assert!(engine.execute("DROP TRIGGER again").is_err());
+        engine.execute("INSERT INTO log VALUES (1, 1, 1)").unwrap();
+    }
+}
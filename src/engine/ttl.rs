@@ -0,0 +1,139 @@
+//! Deleting the rows whose time to live has run out.
+//!
+//! Expired rows are deleted as a DELETE of each table with a TTL, with
+//! the predicate `column <= cutoff`, would delete them: through an index
+//! on the column if there is one, firing triggers and reaching
+//! subscribers. A call deletes a bounded batch, so that a caller taking
+//! turns with other sessions never keeps them waiting for long.
+
+use super::prepared::Planned;
+use super::{Engine, Error, Output};
+use crate::catalog::Ttl;
+use crate::expr::{BinaryOp, Expr};
+use crate::planner::BoundStatement;
+
+impl Engine {
+    /// Deletes up to `limit` expired rows, from the tables with a TTL in
+    /// order of name, and returns how many it deleted; fewer than `limit`
+    /// means none are left. Nothing is deleted in a transaction, which
+    /// the deletes would join, or from a database opened read-only.
+    pub fn expire_rows(&mut self, limit: usize) -> Result<u64, Error> {
+        if self.in_transaction() || self.bufmgr.is_read_only() {
+            return Ok(0);
+        }
+        let now = Ttl::now();
+        let mut tables: Vec<(String, Ttl)> = (self.catalog.tables())
+            .filter_map(|table| Some((table.name.clone(), table.ttl?)))
+            .collect();
+        tables.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let mut expired = 0;
+        for (table, ttl) in tables {
+            let remaining = limit.saturating_sub(expired as usize);
+            if remaining == 0 {
+                break;
+            }
+            let predicate = Expr::binary(
+                BinaryOp::LtEq,
+                Expr::column(ttl.column),
+                Expr::literal(ttl.cutoff(now)),
+            );
+            let statement = BoundStatement::Delete {
+                table: table.clone(),
+                predicate: Some(predicate),
+            };
+            let planned =
+                Planned::new(&self.catalog, statement, &[], self.settings.planner.clone());
+            let Planned::Delete(mut delete) = planned else {
+                unreachable!("a DELETE plans as one");
+            };
+            delete.limit = Some(remaining);
+            let triggers = self.plan_triggers(&table)?;
+            let sql = format!("-- expire rows of {table}");
+            if let Output::Affected(n) = self.run(&sql, Planned::Delete(delete), &triggers)? {
+                expired += n;
+            }
+        }
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Engine, Error};
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{self, Ttl};
+    use crate::disk::DiskManager;
+    use crate::value::Value;
+
+    #[test]
+    fn test_expire_rows() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            let disk = DiskManager::open(file.path()).unwrap();
+            Engine::open(BufferPoolManager::new(disk, 32)).unwrap()
+        };
+        let rows = |engine: &mut Engine, sql: &str| engine.execute(sql).unwrap().into_rows();
+        let now = Ttl::now();
+        let mut engine = open();
+        for sql in [
+            "CREATE TABLE sessions (id INT, expires_at INT)",
+            "CREATE INDEX sessions_expiry ON sessions (expires_at)",
+            "ALTER TABLE sessions SET TTL (expires_at)",
+            "CREATE TABLE cache (id INT, created_at INT NOT NULL)",
+            "ALTER TABLE cache SET TTL (created_at, 60)",
+        ] {
+            engine.execute(sql).unwrap();
+        }
+        engine.bufmgr().flush().unwrap();
+        drop(engine);
+
+        let mut engine = open();
+        engine
+            .execute(&format!(
+                "INSERT INTO sessions VALUES (1, {}), (2, {}), (3, {}), (4, NULL)",
+                now - 10,
+                now - 5,
+                now + 3600
+            ))
+            .unwrap();
+        // With a duration, inserts leaving the column NULL get the time.
+        engine
+            .execute(&format!(
+                "INSERT INTO cache VALUES (1, NULL), (2, {})",
+                now - 61
+            ))
+            .unwrap();
+        let stamped = rows(&mut engine, "SELECT created_at FROM cache WHERE id = 1");
+        assert!(matches!(stamped[0][0], Value::Int(t) if (now..now + 10).contains(&t)));
+
+        // Batches stop at the limit; a short batch means none are left.
+        assert_eq!(2, engine.expire_rows(2).unwrap());
+        assert_eq!(1, engine.expire_rows(2).unwrap());
+        assert_eq!(0, engine.expire_rows(2).unwrap());
+        assert_eq!(
+            vec![vec![Value::Int(1)]],
+            rows(&mut engine, "SELECT id FROM cache")
+        );
+        assert_eq!(
+            vec![vec![Value::Int(3)], vec![Value::Int(4)]],
+            rows(&mut engine, "SELECT id FROM sessions ORDER BY id")
+        );
+
+        // Nothing expires in a transaction, or once the TTL is dropped.
+        engine.execute("UPDATE cache SET created_at = 0").unwrap();
+        engine.begin().unwrap();
+        assert_eq!(0, engine.expire_rows(100).unwrap());
+        engine.rollback().unwrap();
+        engine.execute("ALTER TABLE cache DROP TTL").unwrap();
+        assert_eq!(0, engine.expire_rows(100).unwrap());
+
+        engine.execute("CREATE TABLE named (name TEXT)").unwrap();
+        assert!(matches!(
+            engine.execute("ALTER TABLE named SET TTL (name)"),
+            Err(Error::Catalog(catalog::Error::TtlColumn(_)))
+        ));
+        assert!(engine
+            .execute("ALTER TABLE named SET TTL (missing)")
+            .is_err());
+    }
+}
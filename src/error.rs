@@ -0,0 +1,712 @@
+//! Stable codes for errors, so that embedders can tell kinds of failure
+//! apart without matching on messages or on the error enums of each
+//! module, whose variants change as the engine grows.
+//!
+//! Each module keeps its own error enum, and those of the modules above
+//! it wrap it as their `source`, so [`std::error::Error::source`] walks
+//! from a [`database::Error`] down to the I/O error under it. Any of the
+//! enums an [`engine::Error`] or a [`database::Error`] is made of has
+//! `code()`, classifying it as an [`ErrorCode`] with its SQLSTATE, and
+//! the ones that can name what they are about have `object()`.
+//!
+//! ```
+//! use neru7db::database::{Database, Options};
+//! use neru7db::error::{ErrorCode, Object};
+//!
+//! let mut db = Database::temporary(Options::default()).unwrap();
+//! let e = db.execute("SELECT * FROM missing").unwrap_err();
+//! assert_eq!(ErrorCode::UndefinedTable, e.code());
+//! assert_eq!(Some(Object::Table("missing".into())), e.object());
+//! ```
+
+use std::fmt;
+
+use crate::disk::PageId;
+use crate::{
+    backup, blob, btree, buffer, catalog, check, csv, database, dump, engine, executor, expr, heap,
+    lock, planner, sql, sqlite, tuple,
+};
+
+/// A kind of error, named after the PostgreSQL condition with the same
+/// SQLSTATE. New codes may be added, but a code's meaning and SQLSTATE do
+/// not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    SyntaxError,
+    /// Statements nesting deeper than [`sql::MAX_DEPTH`], or triggers
+    /// deeper than [`executor::MAX_TRIGGER_DEPTH`].
+    StatementTooComplex,
+    /// The statement is valid SQL but does not make sense, as when it
+    /// places an aggregate where none is allowed.
+    SemanticError,
+    InsufficientPrivilege,
+    UndefinedTable,
+    UndefinedColumn,
+    UndefinedFunction,
+    UndefinedParameter,
+    /// An index, trigger, user or setting that does not exist.
+    UndefinedObject,
+    /// A table or index whose name is taken.
+    DuplicateTable,
+    DuplicateColumn,
+    DuplicateAlias,
+    /// A trigger or user whose name is taken.
+    DuplicateObject,
+    /// A function registered under the name of a built-in one.
+    DuplicateFunction,
+    AmbiguousColumn,
+    DatatypeMismatch,
+    CannotCoerce,
+    GroupingError,
+    WindowingError,
+    InvalidTableDefinition,
+    /// A table where the statement needs a materialized view, or the other
+    /// way round.
+    WrongObjectType,
+    /// A table that materialized views still depend on.
+    DependentObjectsStillExist,
+    FeatureNotSupported,
+    InvalidParameterValue,
+    InvalidTextRepresentation,
+    BadCopyFileFormat,
+    DivisionByZero,
+    NumericValueOutOfRange,
+    NotNullViolation,
+    UniqueViolation,
+    /// A row outside the bound of its partition, or of every partition.
+    CheckViolation,
+    ActiveTransaction,
+    NoActiveTransaction,
+    /// An optimistic transaction that read what another changed.
+    SerializationFailure,
+    /// A transaction that waited for a row lock of one waiting for it.
+    DeadlockDetected,
+    /// `NOWAIT` found, or `lock_timeout` waited out, another session's
+    /// lock on the database or another transaction's on a row.
+    LockNotAvailable,
+    /// A change to a database open read-only.
+    ReadOnlySqlTransaction,
+    ProtocolViolation,
+    QueryCanceled,
+    /// A query ran out of its memory budget.
+    OutOfMemory,
+    /// The buffer pool has no page to spare.
+    InsufficientResources,
+    /// A tuple, key or transaction larger than the engine supports.
+    ProgramLimitExceeded,
+    /// A backup or file that is not what the operation needs.
+    ObjectNotInPrerequisiteState,
+    IoError,
+    ConfigFileError,
+    /// The database file, a backup or an imported file has damage.
+    DataCorrupted,
+    /// A trigger function that failed.
+    RaiseException,
+    /// A function registered by the embedder that failed.
+    ExternalRoutineException,
+    InternalError,
+}
+
+impl ErrorCode {
+    pub fn sqlstate(self) -> &'static str {
+        match self {
+            ErrorCode::SyntaxError => "42601",
+            ErrorCode::StatementTooComplex => "54001",
+            ErrorCode::SemanticError => "42000",
+            ErrorCode::InsufficientPrivilege => "42501",
+            ErrorCode::UndefinedTable => "42P01",
+            ErrorCode::UndefinedColumn => "42703",
+            ErrorCode::UndefinedFunction => "42883",
+            ErrorCode::UndefinedParameter => "42P02",
+            ErrorCode::UndefinedObject => "42704",
+            ErrorCode::DuplicateTable => "42P07",
+            ErrorCode::DuplicateColumn => "42701",
+            ErrorCode::DuplicateAlias => "42712",
+            ErrorCode::DuplicateObject => "42710",
+            ErrorCode::DuplicateFunction => "42723",
+            ErrorCode::AmbiguousColumn => "42702",
+            ErrorCode::DatatypeMismatch => "42804",
+            ErrorCode::CannotCoerce => "42846",
+            ErrorCode::GroupingError => "42803",
+            ErrorCode::WindowingError => "42P20",
+            ErrorCode::InvalidTableDefinition => "42P16",
+            ErrorCode::WrongObjectType => "42809",
+            ErrorCode::DependentObjectsStillExist => "2BP01",
+            ErrorCode::FeatureNotSupported => "0A000",
+            ErrorCode::InvalidParameterValue => "22023",
+            ErrorCode::InvalidTextRepresentation => "22P02",
+            ErrorCode::BadCopyFileFormat => "22P04",
+            ErrorCode::DivisionByZero => "22012",
+            ErrorCode::NumericValueOutOfRange => "22003",
+            ErrorCode::NotNullViolation => "23502",
+            ErrorCode::UniqueViolation => "23505",
+            ErrorCode::CheckViolation => "23514",
+            ErrorCode::ActiveTransaction => "25001",
+            ErrorCode::NoActiveTransaction => "25P01",
+            ErrorCode::SerializationFailure => "40001",
+            ErrorCode::DeadlockDetected => "40P01",
+            ErrorCode::LockNotAvailable => "55P03",
+            ErrorCode::ReadOnlySqlTransaction => "25006",
+            ErrorCode::ProtocolViolation => "08P01",
+            ErrorCode::QueryCanceled => "57014",
+            ErrorCode::OutOfMemory => "53200",
+            ErrorCode::InsufficientResources => "53000",
+            ErrorCode::ProgramLimitExceeded => "54000",
+            ErrorCode::ObjectNotInPrerequisiteState => "55000",
+            ErrorCode::IoError => "58030",
+            ErrorCode::ConfigFileError => "F0000",
+            ErrorCode::DataCorrupted => "XX001",
+            ErrorCode::RaiseException => "P0001",
+            ErrorCode::ExternalRoutineException => "38000",
+            ErrorCode::InternalError => "XX000",
+        }
+    }
+
+    /// The name of the condition, as in `unique_violation`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::SyntaxError => "syntax_error",
+            ErrorCode::StatementTooComplex => "statement_too_complex",
+            ErrorCode::SemanticError => "syntax_error_or_access_rule_violation",
+            ErrorCode::InsufficientPrivilege => "insufficient_privilege",
+            ErrorCode::UndefinedTable => "undefined_table",
+            ErrorCode::UndefinedColumn => "undefined_column",
+            ErrorCode::UndefinedFunction => "undefined_function",
+            ErrorCode::UndefinedParameter => "undefined_parameter",
+            ErrorCode::UndefinedObject => "undefined_object",
+            ErrorCode::DuplicateTable => "duplicate_table",
+            ErrorCode::DuplicateColumn => "duplicate_column",
+            ErrorCode::DuplicateAlias => "duplicate_alias",
+            ErrorCode::DuplicateObject => "duplicate_object",
+            ErrorCode::DuplicateFunction => "duplicate_function",
+            ErrorCode::AmbiguousColumn => "ambiguous_column",
+            ErrorCode::DatatypeMismatch => "datatype_mismatch",
+            ErrorCode::CannotCoerce => "cannot_coerce",
+            ErrorCode::GroupingError => "grouping_error",
+            ErrorCode::WindowingError => "windowing_error",
+            ErrorCode::InvalidTableDefinition => "invalid_table_definition",
+            ErrorCode::WrongObjectType => "wrong_object_type",
+            ErrorCode::DependentObjectsStillExist => "dependent_objects_still_exist",
+            ErrorCode::FeatureNotSupported => "feature_not_supported",
+            ErrorCode::InvalidParameterValue => "invalid_parameter_value",
+            ErrorCode::InvalidTextRepresentation => "invalid_text_representation",
+            ErrorCode::BadCopyFileFormat => "bad_copy_file_format",
+            ErrorCode::DivisionByZero => "division_by_zero",
+            ErrorCode::NumericValueOutOfRange => "numeric_value_out_of_range",
+            ErrorCode::NotNullViolation => "not_null_violation",
+            ErrorCode::UniqueViolation => "unique_violation",
+            ErrorCode::CheckViolation => "check_violation",
+            ErrorCode::ActiveTransaction => "active_sql_transaction",
+            ErrorCode::NoActiveTransaction => "no_active_sql_transaction",
+            ErrorCode::SerializationFailure => "serialization_failure",
+            ErrorCode::DeadlockDetected => "deadlock_detected",
+            ErrorCode::LockNotAvailable => "lock_not_available",
+            ErrorCode::ReadOnlySqlTransaction => "read_only_sql_transaction",
+            ErrorCode::ProtocolViolation => "protocol_violation",
+            ErrorCode::QueryCanceled => "query_canceled",
+            ErrorCode::OutOfMemory => "out_of_memory",
+            ErrorCode::InsufficientResources => "insufficient_resources",
+            ErrorCode::ProgramLimitExceeded => "program_limit_exceeded",
+            ErrorCode::ObjectNotInPrerequisiteState => "object_not_in_prerequisite_state",
+            ErrorCode::IoError => "io_error",
+            ErrorCode::ConfigFileError => "config_file_error",
+            ErrorCode::DataCorrupted => "data_corrupted",
+            ErrorCode::RaiseException => "raise_exception",
+            ErrorCode::ExternalRoutineException => "external_routine_exception",
+            ErrorCode::InternalError => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What an error is about.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Object {
+    Page(PageId),
+    Table(String),
+    Index(String),
+    Column(String),
+    /// A unique index whose constraint a statement broke.
+    Constraint(String),
+    Trigger(String),
+    User(String),
+    Setting(String),
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Page(page_id) => write!(f, "page {}", page_id.to_u64()),
+            Object::Table(name) => write!(f, "table {name:?}"),
+            Object::Index(name) => write!(f, "index {name:?}"),
+            Object::Column(name) => write!(f, "column {name:?}"),
+            Object::Constraint(name) => write!(f, "constraint {name:?}"),
+            Object::Trigger(name) => write!(f, "trigger {name:?}"),
+            Object::User(name) => write!(f, "user {name:?}"),
+            Object::Setting(name) => write!(f, "setting {name:?}"),
+        }
+    }
+}
+
+impl sql::Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            sql::Error::TooDeep { .. } => ErrorCode::StatementTooComplex,
+            _ => ErrorCode::SyntaxError,
+        }
+    }
+}
+
+impl planner::Error {
+    pub fn code(&self) -> ErrorCode {
+        use planner::Error as E;
+        match self {
+            E::TableNotFound(_) | E::UnknownTable(_) => ErrorCode::UndefinedTable,
+            E::TableExists(_) | E::IndexExists(_) => ErrorCode::DuplicateTable,
+            E::DatabaseExists(_) => ErrorCode::DuplicateObject,
+            E::DatabaseNotFound(_) => ErrorCode::UndefinedObject,
+            E::IndexNotFound(_)
+            | E::UserNotFound(_)
+            | E::TriggerNotFound(_)
+            | E::PolicyNotFound { .. }
+            | E::UnknownSetting(_) => ErrorCode::UndefinedObject,
+            E::ColumnNotFound(_) => ErrorCode::UndefinedColumn,
+            E::AmbiguousColumn(_) => ErrorCode::AmbiguousColumn,
+            E::DuplicateTable(_) | E::DuplicateCte(_) => ErrorCode::DuplicateAlias,
+            E::DuplicateColumn(_) => ErrorCode::DuplicateColumn,
+            E::MultiplePrimaryKeys(_) => ErrorCode::InvalidTableDefinition,
+            E::NotGrouped(_) | E::AggregateNotAllowed(_) | E::StarArgument(_) => {
+                ErrorCode::GroupingError
+            }
+            E::WindowNotAllowed(_) | E::WindowRequiresOver(_) => ErrorCode::WindowingError,
+            E::UnknownFunction(_) | E::ArgumentCount { .. } | E::ArgumentType { .. } => {
+                ErrorCode::UndefinedFunction
+            }
+            E::OperatorType { .. }
+            | E::OperandType { .. }
+            | E::AggregateType { .. }
+            | E::FunctionType { .. }
+            | E::ClauseType { .. }
+            | E::ColumnType { .. }
+            | E::ValuesType { .. }
+            | E::UnionType { .. }
+            | E::RecursiveType { .. } => ErrorCode::DatatypeMismatch,
+            E::InvalidCast { .. } => ErrorCode::CannotCoerce,
+            E::InvalidSetting { .. } | E::InvalidCopyOption { .. } => {
+                ErrorCode::InvalidParameterValue
+            }
+            E::UnknownCopyOption(_) | E::UnknownHint(_) | E::InvalidHint(_) => {
+                ErrorCode::SyntaxError
+            }
+            E::UserExists(_) | E::TriggerExists(_) | E::PolicyExists { .. } => {
+                ErrorCode::DuplicateObject
+            }
+            E::PermissionDenied { .. }
+            | E::ColumnPermissionDenied { .. }
+            | E::MustBeSuperuser(_) => ErrorCode::InsufficientPrivilege,
+            E::Unsupported(_) | E::ForUpdateNotAllowed(..) => ErrorCode::FeatureNotSupported,
+            E::ViewNotWritable(_) | E::NotAView(_) | E::NotPartitioned(_) => {
+                ErrorCode::WrongObjectType
+            }
+            E::InvalidView(_) | E::InvalidTrigger(_) => ErrorCode::DataCorrupted,
+            E::Auth(_) => ErrorCode::InternalError,
+            _ => ErrorCode::SemanticError,
+        }
+    }
+
+    pub fn object(&self) -> Option<Object> {
+        use planner::Error as E;
+        Some(match self {
+            E::TableNotFound(name)
+            | E::TableExists(name)
+            | E::UnknownTable(name)
+            | E::DuplicateTable(name)
+            | E::PermissionDenied { table: name, .. }
+            | E::ViewNotWritable(name)
+            | E::NotAView(name)
+            | E::NotPartitioned(name)
+            | E::PolicyExists { table: name, .. }
+            | E::PolicyNotFound { table: name, .. }
+            | E::InvalidView(name) => Object::Table(name.clone()),
+            E::IndexNotFound(name) | E::IndexExists(name) => Object::Index(name.clone()),
+            E::ColumnNotFound(name)
+            | E::AmbiguousColumn(name)
+            | E::DuplicateColumn(name)
+            | E::NotGrouped(name)
+            | E::ColumnPermissionDenied { column: name, .. }
+            | E::ColumnType { column: name, .. } => Object::Column(name.clone()),
+            E::UserExists(name) | E::UserNotFound(name) => Object::User(name.clone()),
+            E::TriggerExists(name) | E::TriggerNotFound(name) | E::InvalidTrigger(name) => {
+                Object::Trigger(name.clone())
+            }
+            E::UnknownSetting(name) | E::InvalidSetting { name, .. } => {
+                Object::Setting(name.clone())
+            }
+            _ => return None,
+        })
+    }
+}
+
+impl expr::Error {
+    pub fn code(&self) -> ErrorCode {
+        use expr::Error as E;
+        match self {
+            E::TypeMismatch { .. } | E::InvalidOperand { .. } => ErrorCode::DatatypeMismatch,
+            E::DivisionByZero => ErrorCode::DivisionByZero,
+            E::Overflow => ErrorCode::NumericValueOutOfRange,
+            E::InvalidCast { .. } => ErrorCode::InvalidTextRepresentation,
+            E::UnboundParameter(_) => ErrorCode::UndefinedParameter,
+            E::InvalidArgument { .. } => ErrorCode::InvalidParameterValue,
+            E::FunctionFailed { .. } => ErrorCode::ExternalRoutineException,
+            E::FunctionResult { .. } => ErrorCode::DatatypeMismatch,
+            E::ColumnOutOfRange(_) => ErrorCode::InternalError,
+        }
+    }
+}
+
+impl executor::Error {
+    pub fn code(&self) -> ErrorCode {
+        use executor::Error as E;
+        match self {
+            E::TableNotFound(_) => ErrorCode::UndefinedTable,
+            E::IndexNotFound(_) => ErrorCode::UndefinedObject,
+            E::ColumnCountMismatch { .. } => ErrorCode::SyntaxError,
+            E::TypeMismatch { .. } | E::AggregateType { .. } => ErrorCode::DatatypeMismatch,
+            E::NotNullViolation(_) => ErrorCode::NotNullViolation,
+            E::UniqueViolation(_) => ErrorCode::UniqueViolation,
+            E::NoPartition(_) | E::PartitionConstraint(_) => ErrorCode::CheckViolation,
+            E::PolicyViolation(_) => ErrorCode::InsufficientPrivilege,
+            E::OutOfBudget { .. } => ErrorCode::OutOfMemory,
+            E::Cancelled | E::StatementTimeout(_) => ErrorCode::QueryCanceled,
+            E::TriggerFailed { .. } => ErrorCode::RaiseException,
+            E::TriggerDepth(_) => ErrorCode::StatementTooComplex,
+            E::Trigger { source, .. } => source.code(),
+            E::Expr(e) => e.code(),
+            E::Heap(e) => e.code(),
+            E::BTree(e) => e.code(),
+            E::Tuple(_) => ErrorCode::DataCorrupted,
+            E::Io(_) => ErrorCode::IoError,
+            E::Lock(lock::Error::Deadlock) => ErrorCode::DeadlockDetected,
+            E::Lock(_) => ErrorCode::LockNotAvailable,
+            E::Catalog(e) => e.code(),
+        }
+    }
+
+    pub fn object(&self) -> Option<Object> {
+        use executor::Error as E;
+        match self {
+            E::TableNotFound(name)
+            | E::NoPartition(name)
+            | E::PartitionConstraint(name)
+            | E::PolicyViolation(name)
+            | E::Lock(lock::Error::NotAvailable(name)) => Some(Object::Table(name.clone())),
+            E::IndexNotFound(name) => Some(Object::Index(name.clone())),
+            E::TypeMismatch { column, .. } | E::NotNullViolation(column) => {
+                Some(Object::Column(column.clone()))
+            }
+            E::UniqueViolation(index) => Some(Object::Constraint(index.clone())),
+            E::TriggerFailed { trigger, .. } => Some(Object::Trigger(trigger.clone())),
+            E::Trigger { source, .. } => source.object(),
+            E::Heap(e) => e.object(),
+            E::Catalog(e) => e.object(),
+            _ => None,
+        }
+    }
+}
+
+impl catalog::Error {
+    pub fn code(&self) -> ErrorCode {
+        use catalog::Error as E;
+        match self {
+            E::TableExists(_) | E::IndexExists(_) => ErrorCode::DuplicateTable,
+            E::TableNotFound(_) => ErrorCode::UndefinedTable,
+            E::IndexNotFound(_)
+            | E::UserNotFound(_)
+            | E::TriggerNotFound(_)
+            | E::PolicyNotFound { .. } => ErrorCode::UndefinedObject,
+            E::ColumnNotFound(_) => ErrorCode::UndefinedColumn,
+            E::DuplicateColumn(_) => ErrorCode::DuplicateColumn,
+            E::NoColumns => ErrorCode::InvalidTableDefinition,
+            E::UserExists(_)
+            | E::TriggerExists(_)
+            | E::PolicyExists { .. }
+            | E::DatabaseExists(_) => ErrorCode::DuplicateObject,
+            E::DatabaseNotFound(_) => ErrorCode::UndefinedObject,
+            E::DuplicateKey(_) => ErrorCode::UniqueViolation,
+            E::NotATable(_) | E::NotAView(_) => ErrorCode::WrongObjectType,
+            E::HasDependents { .. } | E::UserHasPolicy { .. } => {
+                ErrorCode::DependentObjectsStillExist
+            }
+            E::PolicyOnPartition(_) => ErrorCode::WrongObjectType,
+            E::Partitioned(_) | E::NotPartitioned(_) | E::NotAPartition { .. } => {
+                ErrorCode::WrongObjectType
+            }
+            E::AlreadyPartition(_) => ErrorCode::ObjectNotInPrerequisiteState,
+            E::PartitionColumns { .. } | E::TtlColumn(_) => ErrorCode::DatatypeMismatch,
+            E::InvalidPartitionBound { .. } | E::PartitionOverlap { .. } => {
+                ErrorCode::InvalidTableDefinition
+            }
+            E::RowOutsideBound(_) => ErrorCode::CheckViolation,
+            E::Io(_) => ErrorCode::IoError,
+            E::Heap(e) => e.code(),
+            E::BTree(e) => e.code(),
+            E::Expr(e) => e.code(),
+            E::Corrupt(_) => ErrorCode::DataCorrupted,
+        }
+    }
+
+    pub fn object(&self) -> Option<Object> {
+        use catalog::Error as E;
+        Some(match self {
+            E::TableExists(name)
+            | E::TableNotFound(name)
+            | E::NotATable(name)
+            | E::NotAView(name)
+            | E::HasDependents { name, .. }
+            | E::Partitioned(name)
+            | E::NotPartitioned(name)
+            | E::AlreadyPartition(name)
+            | E::NotAPartition {
+                partition: name, ..
+            }
+            | E::PartitionColumns {
+                partition: name, ..
+            }
+            | E::InvalidPartitionBound {
+                partition: name, ..
+            }
+            | E::PartitionOverlap {
+                partition: name, ..
+            }
+            | E::PolicyExists { table: name, .. }
+            | E::PolicyNotFound { table: name, .. }
+            | E::PolicyOnPartition(name)
+            | E::RowOutsideBound(name) => Object::Table(name.clone()),
+            E::IndexExists(name) | E::IndexNotFound(name) | E::DuplicateKey(name) => {
+                Object::Index(name.clone())
+            }
+            E::ColumnNotFound(name) | E::DuplicateColumn(name) | E::TtlColumn(name) => {
+                Object::Column(name.clone())
+            }
+            E::UserExists(name) | E::UserNotFound(name) | E::UserHasPolicy { user: name, .. } => {
+                Object::User(name.clone())
+            }
+            E::TriggerExists(name) | E::TriggerNotFound(name) => Object::Trigger(name.clone()),
+            E::Heap(e) => return e.object(),
+            _ => return None,
+        })
+    }
+}
+
+impl heap::Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            heap::Error::TooLarge(_) => ErrorCode::ProgramLimitExceeded,
+            heap::Error::Tuple { .. } => ErrorCode::DataCorrupted,
+            heap::Error::Buffer(e) => e.code(),
+        }
+    }
+
+    pub fn object(&self) -> Option<Object> {
+        match self {
+            heap::Error::Tuple { rid, .. } => Some(Object::Page(rid.page_id)),
+            _ => None,
+        }
+    }
+}
+
+impl btree::Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            btree::Error::DuplicateKey => ErrorCode::UniqueViolation,
+            btree::Error::TooLarge(_) => ErrorCode::ProgramLimitExceeded,
+            btree::Error::Buffer(e) => e.code(),
+        }
+    }
+}
+
+impl buffer::Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            // A page that does not match its checksum.
+            buffer::Error::Io(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                ErrorCode::DataCorrupted
+            }
+            buffer::Error::Io(_) => ErrorCode::IoError,
+            buffer::Error::NoFreeBuffer => ErrorCode::InsufficientResources,
+            buffer::Error::TransactionTooLarge => ErrorCode::ProgramLimitExceeded,
+            buffer::Error::InTransaction => ErrorCode::ActiveTransaction,
+        }
+    }
+}
+
+impl check::Error {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            check::Error::Io(_) => ErrorCode::IoError,
+            check::Error::Buffer(e) => e.code(),
+        }
+    }
+}
+
+impl backup::Error {
+    pub fn code(&self) -> ErrorCode {
+        use backup::Error as E;
+        match self {
+            E::Io(_) => ErrorCode::IoError,
+            E::Buffer(e) => e.code(),
+            E::Check(e) => e.code(),
+            E::InTransaction => ErrorCode::ActiveTransaction,
+            E::Damaged(_) => ErrorCode::DataCorrupted,
+            E::EmptyChain | E::NotFull(_) | E::NotIncrement(_) => {
+                ErrorCode::ObjectNotInPrerequisiteState
+            }
+        }
+    }
+}
+
+impl engine::Error {
+    pub fn code(&self) -> ErrorCode {
+        use engine::Error as E;
+        match self {
+            E::Syntax(e) => e.code(),
+            E::Plan(e) => e.code(),
+            E::Execute(e) => e.code(),
+            E::Catalog(e) => e.code(),
+            E::Buffer(e) => e.code(),
+            E::Backup(e) => e.code(),
+            E::Csv(csv::Error::Syntax { .. }) => ErrorCode::BadCopyFileFormat,
+            E::Csv(csv::Error::Io(_)) | E::Io(_) => ErrorCode::IoError,
+            E::Attach { source, .. } => source.code(),
+            E::CopyData { .. } => ErrorCode::InvalidTextRepresentation,
+            E::InvalidAuditLog { .. } => ErrorCode::DataCorrupted,
+            E::TransactionActive => ErrorCode::ActiveTransaction,
+            E::NoTransaction => ErrorCode::NoActiveTransaction,
+            E::SerializationFailure(_) => ErrorCode::SerializationFailure,
+            E::LockNotAvailable | E::LockTimeout(_) => ErrorCode::LockNotAvailable,
+            E::PayloadTooLong => ErrorCode::InvalidParameterValue,
+            E::NoListener => ErrorCode::ObjectNotInPrerequisiteState,
+            E::ReadOnly => ErrorCode::ReadOnlySqlTransaction,
+            E::NoHistory(_) => ErrorCode::ObjectNotInPrerequisiteState,
+            E::ConcurrentIndexInTransaction => ErrorCode::ActiveTransaction,
+            E::ParameterCount { .. } => ErrorCode::ProtocolViolation,
+            E::ParameterType { .. } => ErrorCode::DatatypeMismatch,
+            E::UnknownTriggerFunction(_) => ErrorCode::UndefinedFunction,
+            E::BuiltinFunction(_) => ErrorCode::DuplicateFunction,
+        }
+    }
+
+    pub fn object(&self) -> Option<Object> {
+        use engine::Error as E;
+        match self {
+            E::Plan(e) => e.object(),
+            E::Execute(e) => e.object(),
+            E::Catalog(e) => e.object(),
+            _ => None,
+        }
+    }
+}
+
+impl database::Error {
+    pub fn code(&self) -> ErrorCode {
+        use database::Error as E;
+        match self {
+            E::Io(_) | E::Dump(dump::Error::Io(_)) => ErrorCode::IoError,
+            E::Engine(e) => e.code(),
+            E::Config(_) => ErrorCode::ConfigFileError,
+            E::Buffer(e) => e.code(),
+            E::Check(e) => e.code(),
+            E::Backup(e) => e.code(),
+            E::Blob(blob::Error::Buffer(e)) => e.code(),
+            E::Blob(blob::Error::Corrupt { .. }) => ErrorCode::DataCorrupted,
+            E::Sqlite(e) => match e {
+                sqlite::Error::Io(_) => ErrorCode::IoError,
+                sqlite::Error::NotSqlite | sqlite::Error::Wal => {
+                    ErrorCode::ObjectNotInPrerequisiteState
+                }
+                sqlite::Error::Corrupt(_) => ErrorCode::DataCorrupted,
+                sqlite::Error::Value { .. } => ErrorCode::DatatypeMismatch,
+            },
+            E::Dump(dump::Error::Heap(e)) => e.code(),
+            E::OldFormat(_) | E::NewerFormat(_) => ErrorCode::ObjectNotInPrerequisiteState,
+            E::Damaged(_) | E::Unrestored { .. } => ErrorCode::DataCorrupted,
+            E::NoSuchColumn(_) => ErrorCode::UndefinedColumn,
+            E::ColumnIndex { .. } => ErrorCode::UndefinedColumn,
+            E::Type { .. } => ErrorCode::DatatypeMismatch,
+            E::Batch { source, .. } => source.code(),
+            E::TransactionInBatch => ErrorCode::ActiveTransaction,
+        }
+    }
+
+    pub fn object(&self) -> Option<Object> {
+        use database::Error as E;
+        match self {
+            E::Engine(e) => e.object(),
+            E::Batch { source, .. } => source.object(),
+            E::Sqlite(sqlite::Error::Value { table, .. }) => Some(Object::Table(table.clone())),
+            E::Dump(dump::Error::Heap(e)) => e.object(),
+            E::NoSuchColumn(column) | E::Type { column, .. } => {
+                Some(Object::Column(column.clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl tuple::Error {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::DataCorrupted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, Options};
+
+    #[test]
+    fn test_codes_and_objects() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT NOT NULL)")
+            .unwrap();
+        db.execute("INSERT INTO t VALUES (1, 'a')").unwrap();
+        let cases = [
+            ("SELEC 1", ErrorCode::SyntaxError, None),
+            (
+                "SELECT nope FROM t",
+                ErrorCode::UndefinedColumn,
+                Some(Object::Column("nope".into())),
+            ),
+            (
+                "CREATE TABLE t (id INT)",
+                ErrorCode::DuplicateTable,
+                Some(Object::Table("t".into())),
+            ),
+            (
+                "INSERT INTO t VALUES (1, 'b')",
+                ErrorCode::UniqueViolation,
+                Some(Object::Constraint("t_pkey".into())),
+            ),
+            (
+                "INSERT INTO t VALUES (2, NULL)",
+                ErrorCode::NotNullViolation,
+                Some(Object::Column("name".into())),
+            ),
+            ("SELECT id / 0 FROM t", ErrorCode::DivisionByZero, None),
+            ("COMMIT", ErrorCode::NoActiveTransaction, None),
+        ];
+        for (sql, code, object) in cases {
+            let e = db.execute(sql).unwrap_err();
+            assert_eq!((code, object), (e.code(), e.object()), "{sql}: {e}");
+        }
+        assert_eq!("23505", ErrorCode::UniqueViolation.sqlstate());
+        assert_eq!("unique_violation", ErrorCode::UniqueViolation.to_string());
+    }
+}
@@ -0,0 +1,535 @@
+//! Hash aggregation with optional GROUP BY, spilling to disk when the
+//! group table outgrows work_mem.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+use super::memory::{tuple_size, MemoryContext, MemoryReservation};
+use super::spill::SpillFile;
+use super::{Batch, BoxExecutor, Error, ExecContext, Executor};
+use crate::expr::{self, AggregateState, Expr, UserAggregate};
+use crate::tuple;
+use crate::value::{Tuple, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AggregateFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    /// One the embedder registers.
+    User(UserAggregate),
+}
+
+impl fmt::Display for AggregateFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AggregateFunction::Count => "COUNT",
+            AggregateFunction::Sum => "SUM",
+            AggregateFunction::Min => "MIN",
+            AggregateFunction::Max => "MAX",
+            AggregateFunction::Avg => "AVG",
+            AggregateFunction::User(aggregate) => &aggregate.name,
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateExpr {
+    pub func: AggregateFunction,
+    /// `None` only for `COUNT(*)`, which counts rows rather than values.
+    pub arg: Option<Expr>,
+}
+
+impl AggregateExpr {
+    pub fn new(func: AggregateFunction, arg: Expr) -> Self {
+        Self {
+            func,
+            arg: Some(arg),
+        }
+    }
+
+    pub fn count_star() -> Self {
+        Self {
+            func: AggregateFunction::Count,
+            arg: None,
+        }
+    }
+}
+
+impl fmt::Display for AggregateExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.arg {
+            Some(arg) => write!(f, "{}({arg})", self.func),
+            None => write!(f, "{}(*)", self.func),
+        }
+    }
+}
+
+/// Running state of one aggregate within one group. NULL inputs are
+/// ignored by every function.
+#[derive(Debug)]
+pub(super) enum Accumulator {
+    Count(i64),
+    Sum(Value),
+    Min(Value),
+    Max(Value),
+    Avg {
+        sum: f64,
+        count: i64,
+    },
+    User {
+        aggregate: UserAggregate,
+        state: AggregateState,
+    },
+}
+
+impl Accumulator {
+    pub(super) fn new(func: &AggregateFunction) -> Self {
+        match func {
+            AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum(Value::Null),
+            AggregateFunction::Min => Accumulator::Min(Value::Null),
+            AggregateFunction::Max => Accumulator::Max(Value::Null),
+            AggregateFunction::Avg => Accumulator::Avg { sum: 0.0, count: 0 },
+            AggregateFunction::User(aggregate) => Accumulator::User {
+                aggregate: aggregate.clone(),
+                state: aggregate.init(),
+            },
+        }
+    }
+
+    pub(super) fn update(&mut self, value: &Value) -> Result<(), Error> {
+        if value.is_null() {
+            return Ok(());
+        }
+        let type_error = |func| Error::AggregateType {
+            func,
+            data_type: value.data_type().unwrap(),
+        };
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => {
+                *sum = match (&*sum, value) {
+                    (Value::Null, Value::Int(_) | Value::Float(_)) => value.clone(),
+                    (Value::Int(a), Value::Int(b)) => {
+                        Value::Int(a.checked_add(*b).ok_or(expr::Error::Overflow)?)
+                    }
+                    (Value::Int(a), Value::Float(b)) => Value::Float(*a as f64 + b),
+                    (Value::Float(a), Value::Int(b)) => Value::Float(a + *b as f64),
+                    (Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+                    _ => return Err(type_error(AggregateFunction::Sum)),
+                }
+            }
+            Accumulator::Min(min) => {
+                if min.is_null() || value.total_cmp(min).is_lt() {
+                    *min = value.clone();
+                }
+            }
+            Accumulator::Max(max) => {
+                if max.is_null() || value.total_cmp(max).is_gt() {
+                    *max = value.clone();
+                }
+            }
+            Accumulator::Avg { sum, count } => {
+                *sum += match value {
+                    Value::Int(i) => *i as f64,
+                    Value::Float(x) => *x,
+                    _ => return Err(type_error(AggregateFunction::Avg)),
+                };
+                *count += 1;
+            }
+            Accumulator::User { aggregate, state } => aggregate.accumulate(state, value)?,
+        }
+        Ok(())
+    }
+
+    /// Folds a whole column into the accumulator. Integer sums and counts
+    /// run in tight loops over the vector, and a registered aggregate folds
+    /// it into a state of its own to merge; everything else falls back to
+    /// [`Accumulator::update`].
+    fn update_batch(&mut self, values: &[Value]) -> Result<(), Error> {
+        let mut rest = values;
+        match self {
+            Accumulator::Count(count) => {
+                *count += values.iter().filter(|value| !value.is_null()).count() as i64;
+                return Ok(());
+            }
+            Accumulator::Sum(sum @ (Value::Null | Value::Int(_))) => {
+                let mut acc = match sum {
+                    Value::Int(i) => Some(*i),
+                    _ => None,
+                };
+                while let Some((value, tail)) = rest.split_first() {
+                    match value {
+                        Value::Int(i) => {
+                            let prev = acc.unwrap_or(0);
+                            acc = Some(prev.checked_add(*i).ok_or(expr::Error::Overflow)?);
+                        }
+                        Value::Null => {}
+                        _ => break,
+                    }
+                    rest = tail;
+                }
+                *sum = acc.map_or(Value::Null, Value::Int);
+            }
+            Accumulator::User { aggregate, state } => {
+                let mut batch = aggregate.init();
+                for value in values.iter().filter(|value| !value.is_null()) {
+                    aggregate.accumulate(&mut batch, value)?;
+                }
+                aggregate.merge(state, batch)?;
+                return Ok(());
+            }
+            _ => {}
+        }
+        rest.iter().try_for_each(|value| self.update(value))
+    }
+
+    /// The result for the values folded in so far, which more may follow.
+    pub(super) fn finish(&self) -> Result<Value, Error> {
+        Ok(match self {
+            Accumulator::Count(count) => Value::Int(*count),
+            Accumulator::Sum(value) | Accumulator::Min(value) | Accumulator::Max(value) => {
+                value.clone()
+            }
+            Accumulator::Avg { count: 0, .. } => Value::Null,
+            Accumulator::Avg { sum, count } => Value::Float(sum / *count as f64),
+            Accumulator::User { aggregate, state } => aggregate.finalize(state)?,
+        })
+    }
+}
+
+/// Spilled groups are split into this many partitions per level.
+pub(super) const SPILL_PARTITIONS: usize = 16;
+/// Beyond this many levels of repartitioning a table stays in memory.
+pub(super) const MAX_SPILL_DEPTH: usize = 4;
+
+pub(super) fn partition_of(encoded_key: &[u8], depth: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    depth.hash(&mut hasher);
+    encoded_key.hash(&mut hasher);
+    hasher.finish() as usize % SPILL_PARTITIONS
+}
+
+/// Groups seen so far, in order of first appearance.
+///
+/// Once the table outgrows work_mem, rows of groups not already in memory
+/// are written to spill partitions, each of which is aggregated on its own
+/// after the in-memory groups have been emitted.
+struct Groups<'m> {
+    ids: HashMap<Vec<u8>, usize>,
+    keys: Vec<Tuple>,
+    accumulators: Vec<Vec<Accumulator>>,
+    memory: &'m MemoryContext,
+    reservation: MemoryReservation<'m>,
+    depth: usize,
+    partitions: Vec<SpillFile>,
+}
+
+impl<'m> Groups<'m> {
+    fn new(
+        memory: &'m MemoryContext,
+        grouped: bool,
+        aggregates: &[AggregateExpr],
+        depth: usize,
+    ) -> Self {
+        let mut groups = Self {
+            ids: HashMap::new(),
+            keys: vec![],
+            accumulators: vec![],
+            memory,
+            reservation: memory.reservation(),
+            depth,
+            partitions: vec![],
+        };
+        // Without GROUP BY there is exactly one group, even for no input.
+        if !grouped {
+            groups.insert(vec![], vec![], aggregates);
+        }
+        groups
+    }
+
+    fn insert(&mut self, key: Tuple, encoded: Vec<u8>, aggregates: &[AggregateExpr]) -> usize {
+        self.keys.push(key);
+        self.accumulators.push(
+            aggregates
+                .iter()
+                .map(|agg| Accumulator::new(&agg.func))
+                .collect(),
+        );
+        self.ids.insert(encoded, self.keys.len() - 1);
+        self.keys.len() - 1
+    }
+
+    /// Folds one row into its group. `args` holds the argument of each
+    /// aggregate for that row.
+    fn push(
+        &mut self,
+        key: Tuple,
+        args: &[Value],
+        aggregates: &[AggregateExpr],
+    ) -> Result<(), Error> {
+        let mut encoded = vec![];
+        tuple::encode_key(&key, &mut encoded);
+        let id = match self.ids.get(&encoded) {
+            Some(&id) => id,
+            None => {
+                let spilling = !self.partitions.is_empty()
+                    || (self.reservation.exceeds_work_mem() && self.depth < MAX_SPILL_DEPTH);
+                if spilling {
+                    return self.spill(&encoded, key, args);
+                }
+                let size = tuple_size(&key)
+                    + encoded.len()
+                    + aggregates.len() * mem::size_of::<Accumulator>();
+                self.reservation.grow(size)?;
+                self.insert(key, encoded, aggregates)
+            }
+        };
+        for (acc, value) in self.accumulators[id].iter_mut().zip(args) {
+            acc.update(value)?;
+        }
+        Ok(())
+    }
+
+    fn spill(&mut self, encoded: &[u8], mut key: Tuple, args: &[Value]) -> Result<(), Error> {
+        if self.partitions.is_empty() {
+            self.partitions = (0..SPILL_PARTITIONS)
+                .map(|_| SpillFile::new(self.memory))
+                .collect::<Result<_, _>>()?;
+        }
+        key.extend_from_slice(args);
+        self.partitions[partition_of(encoded, self.depth)].write(self.memory, &key)
+    }
+
+    /// Returns the finished rows of the in-memory groups along with the
+    /// memory they occupy and the spilled partitions still to aggregate.
+    fn finish(self) -> Result<(Vec<Tuple>, MemoryReservation<'m>, Vec<Partition>), Error> {
+        let rows = self
+            .keys
+            .into_iter()
+            .zip(self.accumulators)
+            .map(|(mut row, accumulators)| {
+                for acc in &accumulators {
+                    row.push(acc.finish()?);
+                }
+                Ok(row)
+            })
+            .collect::<Result<_, Error>>()?;
+        let depth = self.depth + 1;
+        let partitions = self
+            .partitions
+            .into_iter()
+            .filter(|file| file.rows() > 0)
+            .map(|file| Partition { file, depth })
+            .collect();
+        Ok((rows, self.reservation, partitions))
+    }
+}
+
+struct Partition {
+    file: SpillFile,
+    depth: usize,
+}
+
+struct Output<'m> {
+    rows: std::vec::IntoIter<Tuple>,
+    reservation: MemoryReservation<'m>,
+    pending: Vec<Partition>,
+}
+
+/// Outputs one row per group: the GROUP BY values followed by the
+/// aggregate results.
+pub struct Aggregate<'a> {
+    input: BoxExecutor<'a>,
+    group_by: Vec<Expr>,
+    aggregates: Vec<AggregateExpr>,
+    memory: &'a MemoryContext,
+    output: Option<Output<'a>>,
+}
+
+impl<'a> Aggregate<'a> {
+    pub fn new(
+        ctx: &ExecContext<'a>,
+        input: BoxExecutor<'a>,
+        group_by: Vec<Expr>,
+        aggregates: Vec<AggregateExpr>,
+    ) -> Self {
+        Self {
+            input,
+            group_by,
+            aggregates,
+            memory: ctx.memory,
+            output: None,
+        }
+    }
+
+    fn groups(&self, depth: usize) -> Groups<'a> {
+        Groups::new(
+            self.memory,
+            !self.group_by.is_empty(),
+            &self.aggregates,
+            depth,
+        )
+    }
+
+    fn aggregate_rows(&mut self) -> Result<Groups<'a>, Error> {
+        let mut groups = self.groups(0);
+        while let Some(row) = self.input.next()? {
+            let key = self
+                .group_by
+                .iter()
+                .map(|expr| expr.eval(&row))
+                .collect::<Result<_, _>>()?;
+            let args = self
+                .aggregates
+                .iter()
+                .map(|agg| match &agg.arg {
+                    Some(arg) => arg.eval(&row),
+                    None => Ok(Value::Bool(true)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            groups.push(key, &args, &self.aggregates)?;
+        }
+        Ok(groups)
+    }
+
+    fn aggregate_batches(&mut self, batch_size: usize) -> Result<Groups<'a>, Error> {
+        let mut groups = self.groups(0);
+        while let Some(batch) = self.input.next_batch(batch_size)? {
+            let (columns, len) = (batch.columns(), batch.len());
+            let args = self
+                .aggregates
+                .iter()
+                .map(|agg| match &agg.arg {
+                    Some(arg) => arg.eval_batch(columns, len),
+                    None => Ok(vec![Value::Bool(true); len]),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if self.group_by.is_empty() {
+                for (acc, values) in groups.accumulators[0].iter_mut().zip(&args) {
+                    acc.update_batch(values)?;
+                }
+                continue;
+            }
+            let keys = self
+                .group_by
+                .iter()
+                .map(|expr| expr.eval_batch(columns, len))
+                .collect::<Result<Vec<_>, _>>()?;
+            for row in 0..len {
+                let key = keys.iter().map(|column| column[row].clone()).collect();
+                let row_args: Vec<_> = args.iter().map(|column| column[row].clone()).collect();
+                groups.push(key, &row_args, &self.aggregates)?;
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Aggregates a spilled partition, whose rows are the group key
+    /// followed by the aggregate arguments.
+    fn aggregate_partition(&self, partition: Partition) -> Result<Groups<'a>, Error> {
+        let mut groups = self.groups(partition.depth);
+        let mut reader = partition.file.into_reader()?;
+        while let Some(mut key) = reader.next_row()? {
+            let args = key.split_off(self.group_by.len());
+            groups.push(key, &args, &self.aggregates)?;
+        }
+        Ok(groups)
+    }
+
+    fn next_row(
+        &mut self,
+        build: impl FnOnce(&mut Self) -> Result<Groups<'a>, Error>,
+    ) -> Result<Option<Tuple>, Error> {
+        if self.output.is_none() {
+            let (rows, reservation, pending) = build(self)?.finish()?;
+            self.output = Some(Output {
+                rows: rows.into_iter(),
+                reservation,
+                pending,
+            });
+        }
+        loop {
+            let output = self.output.as_mut().unwrap();
+            if let Some(row) = output.rows.next() {
+                return Ok(Some(row));
+            }
+            let Some(partition) = output.pending.pop() else {
+                return Ok(None);
+            };
+            output.reservation.free();
+            let (rows, reservation, pending) = self.aggregate_partition(partition)?.finish()?;
+            let output = self.output.as_mut().unwrap();
+            output.rows = rows.into_iter();
+            output.reservation = reservation;
+            output.pending.extend(pending);
+        }
+    }
+
+    fn width(&self) -> usize {
+        self.group_by.len() + self.aggregates.len()
+    }
+}
+
+impl Executor for Aggregate<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        self.next_row(Self::aggregate_rows)
+    }
+
+    fn next_batch(&mut self, max_rows: usize) -> Result<Option<Batch>, Error> {
+        let mut rows = vec![];
+        while rows.len() < max_rows {
+            match self.next_row(|this| this.aggregate_batches(max_rows))? {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Batch::from_rows(rows, self.width())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulators() {
+        let values = [Value::Int(3), Value::Null, Value::Int(-1), Value::Int(4)];
+        let run = |func, batch: bool| {
+            let mut acc = Accumulator::new(&func);
+            if batch {
+                acc.update_batch(&values).unwrap();
+            } else {
+                values.iter().for_each(|value| acc.update(value).unwrap());
+            }
+            acc.finish().unwrap()
+        };
+        for batch in [false, true] {
+            assert_eq!(Value::Int(3), run(AggregateFunction::Count, batch));
+            assert_eq!(Value::Int(6), run(AggregateFunction::Sum, batch));
+            assert_eq!(Value::Int(-1), run(AggregateFunction::Min, batch));
+            assert_eq!(Value::Int(4), run(AggregateFunction::Max, batch));
+            assert_eq!(Value::Float(2.0), run(AggregateFunction::Avg, batch));
+        }
+        let mut sum = Accumulator::new(&AggregateFunction::Sum);
+        sum.update_batch(&[Value::Int(1), Value::Float(0.5), Value::Int(2)])
+            .unwrap();
+        assert_eq!(Value::Float(3.5), sum.finish().unwrap());
+        let mut sum = Accumulator::new(&AggregateFunction::Sum);
+        assert!(sum.update(&Value::Text("x".into())).is_err());
+        assert_eq!(
+            Value::Null,
+            Accumulator::new(&AggregateFunction::Avg).finish().unwrap()
+        );
+    }
+}
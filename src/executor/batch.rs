@@ -0,0 +1,89 @@
+use crate::value::{Tuple, Value};
+
+/// A vector of rows stored column by column.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Batch {
+    columns: Vec<Vec<Value>>,
+    len: usize,
+}
+
+impl Batch {
+    /// Builds a batch from column vectors of equal length. `len` is kept
+    /// separately so that batches without columns still count rows.
+    pub fn new(columns: Vec<Vec<Value>>, len: usize) -> Self {
+        assert!(columns.iter().all(|column| column.len() == len));
+        Self { columns, len }
+    }
+
+    pub fn from_rows(rows: Vec<Tuple>, width: usize) -> Self {
+        let len = rows.len();
+        let mut columns: Vec<Vec<Value>> = (0..width).map(|_| Vec::with_capacity(len)).collect();
+        for row in rows {
+            assert_eq!(row.len(), width);
+            for (column, value) in columns.iter_mut().zip(row) {
+                column.push(value);
+            }
+        }
+        Self { columns, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn columns(&self) -> &[Vec<Value>] {
+        &self.columns
+    }
+
+    pub fn column(&self, index: usize) -> &[Value] {
+        &self.columns[index]
+    }
+
+    pub fn row(&self, index: usize) -> Tuple {
+        self.columns
+            .iter()
+            .map(|column| column[index].clone())
+            .collect()
+    }
+
+    pub fn into_rows(self) -> Vec<Tuple> {
+        let mut rows: Vec<Tuple> = (0..self.len)
+            .map(|_| Vec::with_capacity(self.columns.len()))
+            .collect();
+        for column in self.columns {
+            for (row, value) in rows.iter_mut().zip(column) {
+                row.push(value);
+            }
+        }
+        rows
+    }
+
+    /// Keeps the rows whose entry in `selection` is true.
+    pub fn select(self, selection: &[bool]) -> Batch {
+        assert_eq!(selection.len(), self.len);
+        let len = selection.iter().filter(|&&selected| selected).count();
+        if len == self.len {
+            return self;
+        }
+        let columns = self
+            .columns
+            .into_iter()
+            .map(|column| {
+                column
+                    .into_iter()
+                    .zip(selection)
+                    .filter_map(|(value, &selected)| selected.then_some(value))
+                    .collect()
+            })
+            .collect();
+        Batch { columns, len }
+    }
+}
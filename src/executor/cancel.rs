@@ -0,0 +1,114 @@
+//! Stopping queries early.
+//!
+//! A plan started through an [`ExecContext`](super::ExecContext) that
+//! carries an [`Interrupt`] checks it as rows flow between its operators,
+//! once per batch or every [`DEFAULT_BATCH_SIZE`] rows, and fails with
+//! [`Error::Cancelled`] or [`Error::StatementTimeout`] once it fires.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Batch, BoxExecutor, Error, Executor, DEFAULT_BATCH_SIZE};
+use crate::value::Tuple;
+
+/// A flag another thread can raise to cancel the running query. Clones
+/// share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Lowers the flag, so that the next query can run.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+/// The reasons one query may stop before it is done.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    token: Option<CancellationToken>,
+    /// When the query times out, and the timeout to report.
+    deadline: Option<(Instant, Duration)>,
+}
+
+impl Interrupt {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_token(self, token: CancellationToken) -> Self {
+        Self {
+            token: Some(token),
+            ..self
+        }
+    }
+
+    /// Times the query out `timeout` from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            deadline: Some((Instant::now() + timeout, timeout)),
+            ..self
+        }
+    }
+
+    pub fn check(&self) -> Result<(), Error> {
+        if self
+            .token
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            return Err(Error::Cancelled);
+        }
+        match self.deadline {
+            Some((deadline, timeout)) if Instant::now() >= deadline => {
+                Err(Error::StatementTimeout(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(super) fn wrap<'a>(&'a self, inner: BoxExecutor<'a>) -> BoxExecutor<'a> {
+        Box::new(Interruptible {
+            inner,
+            interrupt: self,
+            rows: 0,
+        })
+    }
+}
+
+struct Interruptible<'a> {
+    inner: BoxExecutor<'a>,
+    interrupt: &'a Interrupt,
+    /// Rows produced one at a time since the last check.
+    rows: usize,
+}
+
+impl Executor for Interruptible<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        if self.rows == 0 {
+            self.interrupt.check()?;
+        }
+        self.rows = (self.rows + 1) % DEFAULT_BATCH_SIZE;
+        self.inner.next()
+    }
+
+    fn next_batch(&mut self, max_rows: usize) -> Result<Option<Batch>, Error> {
+        self.interrupt.check()?;
+        self.inner.next_batch(max_rows)
+    }
+}
@@ -0,0 +1,130 @@
+//! Capturing the row changes of INSERT, UPDATE and DELETE.
+//!
+//! A statement run through an [`ExecContext`](super::ExecContext) that
+//! carries a [`ChangeLog`] records each row it stores, replaces or
+//! removes there, as it does so. Rows left untouched, such as conflicts
+//! skipped by `ON CONFLICT DO NOTHING` or updates to the same values, are
+//! not recorded.
+//!
+//! A [`RowLog`] records the same rows where they are stored instead, for
+//! a few tables only: the engine catches an index built concurrently up
+//! with the rows of its table that changed while it was being built.
+//!
+//! A [`TableLog`] records only which tables a statement read and which it
+//! changed, whatever the rows, for validating optimistic transactions.
+
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+use crate::heap::Rid;
+use crate::value::Tuple;
+
+/// One row changed in a table, with its images before and after.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Insert {
+        table: String,
+        row: Tuple,
+    },
+    Update {
+        table: String,
+        before: Tuple,
+        after: Tuple,
+    },
+    Delete {
+        table: String,
+        row: Tuple,
+    },
+}
+
+impl Change {
+    pub fn table(&self) -> &str {
+        match self {
+            Change::Insert { table, .. }
+            | Change::Update { table, .. }
+            | Change::Delete { table, .. } => table,
+        }
+    }
+}
+
+/// The changes recorded so far, in the order they were made.
+#[derive(Debug, Default)]
+pub struct ChangeLog {
+    changes: Mutex<Vec<Change>>,
+}
+
+impl ChangeLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, change: Change) {
+        self.changes.lock().unwrap().push(change);
+    }
+
+    pub fn into_changes(self) -> Vec<Change> {
+        self.changes.into_inner().unwrap()
+    }
+}
+
+/// Each row a statement stored in or removed from the tables it watches,
+/// with its rid. An update records the row both before and after.
+#[derive(Debug, Default)]
+pub struct RowLog {
+    tables: Vec<String>,
+    rows: Mutex<Vec<(String, Rid, Tuple)>>,
+}
+
+impl RowLog {
+    pub fn new(tables: Vec<String>) -> Self {
+        Self {
+            tables,
+            rows: Mutex::default(),
+        }
+    }
+
+    pub fn watches(&self, table: &str) -> bool {
+        self.tables.iter().any(|watched| watched == table)
+    }
+
+    pub fn record(&self, table: &str, rid: Rid, row: Tuple) {
+        let row = (table.to_string(), rid, row);
+        self.rows.lock().unwrap().push(row);
+    }
+
+    pub fn into_rows(self) -> Vec<(String, Rid, Tuple)> {
+        self.rows.into_inner().unwrap()
+    }
+}
+
+/// The tables a statement read, including those it changed, and those it
+/// changed.
+#[derive(Debug, Default)]
+pub struct TableLog {
+    tables: Mutex<(BTreeSet<String>, BTreeSet<String>)>,
+}
+
+impl TableLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_read(&self, table: &str) {
+        let tables = &mut self.tables.lock().unwrap().0;
+        if !tables.contains(table) {
+            tables.insert(table.to_string());
+        }
+    }
+
+    pub fn record_write(&self, table: &str) {
+        let tables = &mut self.tables.lock().unwrap().1;
+        if !tables.contains(table) {
+            tables.insert(table.to_string());
+        }
+    }
+
+    /// The tables read and the tables changed, emptying the log.
+    pub fn take(&self) -> (BTreeSet<String>, BTreeSet<String>) {
+        std::mem::take(&mut *self.tables.lock().unwrap())
+    }
+}
@@ -0,0 +1,185 @@
+//! Operators for UNION and common table expressions.
+
+use std::collections::HashSet;
+use std::mem;
+use std::rc::Rc;
+
+use super::memory::{tuple_size, MemoryReservation};
+use super::{BoxExecutor, Error, ExecContext, Executor, Plan};
+use crate::tuple;
+use crate::value::Tuple;
+
+/// Rows of the CTEs and working tables in scope while a plan is started,
+/// innermost last.
+#[derive(Clone, Default)]
+pub(super) struct WorkTables(Vec<(usize, Rc<Vec<Tuple>>)>);
+
+impl WorkTables {
+    fn with(&self, id: usize, rows: Rc<Vec<Tuple>>) -> Self {
+        let mut tables = self.clone();
+        tables.0.push((id, rows));
+        tables
+    }
+
+    pub(super) fn get(&self, id: usize) -> Rc<Vec<Tuple>> {
+        let (_, rows) = self
+            .0
+            .iter()
+            .rev()
+            .find(|(other, _)| *other == id)
+            .unwrap_or_else(|| panic!("work table {id} is not in scope"));
+        Rc::clone(rows)
+    }
+}
+
+pub struct Union<'a> {
+    pub inputs: std::vec::IntoIter<BoxExecutor<'a>>,
+    pub current: Option<BoxExecutor<'a>>,
+}
+
+impl Executor for Union<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        while let Some(input) = &mut self.current {
+            if let Some(row) = input.next()? {
+                return Ok(Some(row));
+            }
+            self.current = self.inputs.next();
+        }
+        Ok(None)
+    }
+}
+
+pub struct WorkTableScan {
+    pub rows: Rc<Vec<Tuple>>,
+    pub next: usize,
+}
+
+impl Executor for WorkTableScan {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        let row = self.rows.get(self.next).cloned();
+        self.next += 1;
+        Ok(row)
+    }
+}
+
+pub struct Materialize<'a> {
+    ctx: ExecContext<'a>,
+    tables: WorkTables,
+    id: usize,
+    cte: &'a Plan,
+    input: &'a Plan,
+    /// `input`, once `cte` has run.
+    started: Option<BoxExecutor<'a>>,
+    reservation: MemoryReservation<'a>,
+}
+
+impl<'a> Materialize<'a> {
+    pub(super) fn new(
+        ctx: &ExecContext<'a>,
+        tables: &WorkTables,
+        id: usize,
+        cte: &'a Plan,
+        input: &'a Plan,
+    ) -> Self {
+        Self {
+            ctx: *ctx,
+            tables: tables.clone(),
+            id,
+            cte,
+            input,
+            started: None,
+            reservation: ctx.memory.reservation(),
+        }
+    }
+}
+
+impl Executor for Materialize<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        if self.started.is_none() {
+            let mut cte = self.cte.start_with(&self.ctx, &self.tables)?;
+            let mut rows = vec![];
+            while let Some(row) = cte.next()? {
+                self.reservation.grow(tuple_size(&row))?;
+                rows.push(row);
+            }
+            let tables = self.tables.with(self.id, Rc::new(rows));
+            self.started = Some(self.input.start_with(&self.ctx, &tables)?);
+        }
+        self.started.as_mut().unwrap().next()
+    }
+}
+
+/// Evaluates a recursive CTE a round at a time, streaming each round's
+/// rows as the next round's working table is built from them.
+pub struct RecursiveUnion<'a> {
+    ctx: ExecContext<'a>,
+    tables: WorkTables,
+    id: usize,
+    recursive: &'a Plan,
+    /// Keys of the rows produced so far, for UNION without ALL.
+    seen: Option<HashSet<Vec<u8>>>,
+    /// The round being read; `None` once a round adds nothing.
+    round: Option<BoxExecutor<'a>>,
+    /// Rows the current round has added.
+    added: Vec<Tuple>,
+    added_reservation: MemoryReservation<'a>,
+    /// Holds the working table the current round reads.
+    _work_reservation: MemoryReservation<'a>,
+    seen_reservation: MemoryReservation<'a>,
+}
+
+impl<'a> RecursiveUnion<'a> {
+    pub(super) fn new(
+        ctx: &ExecContext<'a>,
+        tables: &WorkTables,
+        id: usize,
+        anchor: BoxExecutor<'a>,
+        recursive: &'a Plan,
+        distinct: bool,
+    ) -> Self {
+        Self {
+            ctx: *ctx,
+            tables: tables.clone(),
+            id,
+            recursive,
+            seen: distinct.then(HashSet::new),
+            round: Some(anchor),
+            added: vec![],
+            added_reservation: ctx.memory.reservation(),
+            _work_reservation: ctx.memory.reservation(),
+            seen_reservation: ctx.memory.reservation(),
+        }
+    }
+}
+
+impl Executor for RecursiveUnion<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        while let Some(round) = &mut self.round {
+            let Some(row) = round.next()? else {
+                self.round = None;
+                if self.added.is_empty() {
+                    break;
+                }
+                let rows = Rc::new(mem::take(&mut self.added));
+                self._work_reservation =
+                    mem::replace(&mut self.added_reservation, self.ctx.memory.reservation());
+                let tables = self.tables.with(self.id, rows);
+                self.round = Some(self.recursive.start_with(&self.ctx, &tables)?);
+                continue;
+            };
+            if let Some(seen) = &mut self.seen {
+                let mut key = vec![];
+                tuple::encode_key(&row, &mut key);
+                let size = key.len();
+                if !seen.insert(key) {
+                    continue;
+                }
+                self.seen_reservation.grow(size)?;
+            }
+            self.added_reservation.grow(tuple_size(&row))?;
+            self.added.push(row.clone());
+            return Ok(Some(row));
+        }
+        Ok(None)
+    }
+}
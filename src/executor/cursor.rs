@@ -0,0 +1,126 @@
+use super::{BoxExecutor, Error};
+use crate::value::Tuple;
+
+/// Lazily pulls rows from a started plan.
+///
+/// Between fetches the executor tree keeps its position, along with the
+/// pin on whichever page it is reading. The tree is dropped, releasing
+/// those pins, as soon as it is exhausted, fails, or the cursor is closed.
+pub struct Cursor<'a> {
+    executor: Option<BoxExecutor<'a>>,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(executor: BoxExecutor<'a>) -> Self {
+        Self {
+            executor: Some(executor),
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.executor.is_none()
+    }
+
+    /// Returns the next row, or `None` once the result set is exhausted.
+    pub fn fetch_one(&mut self) -> Result<Option<Tuple>, Error> {
+        let Some(executor) = &mut self.executor else {
+            return Ok(None);
+        };
+        match executor.next() {
+            Ok(Some(row)) => Ok(Some(row)),
+            result => {
+                self.executor = None;
+                result
+            }
+        }
+    }
+
+    /// Returns up to `n` rows; fewer only when the result set runs out.
+    pub fn fetch(&mut self, n: usize) -> Result<Vec<Tuple>, Error> {
+        let mut rows = vec![];
+        while rows.len() < n {
+            match self.fetch_one()? {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+        Ok(rows)
+    }
+
+    pub fn close(&mut self) {
+        self.executor = None;
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = Result<Tuple, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.fetch_one().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::{self, BufferPoolManager};
+    use crate::catalog::{Catalog, Column, Schema};
+    use crate::disk::DiskManager;
+    use crate::executor::{ExecContext, Insert, Plan};
+    use crate::heap;
+    use crate::value::DataType;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test_cursor_releases_pins() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 4);
+        let mut catalog = Catalog::new();
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Int),
+            Column::new("name", DataType::Text),
+        ]);
+        catalog.create_table(&bufmgr, "t", schema).unwrap();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        for i in 0..1000i64 {
+            Insert {
+                table: "t".into(),
+                source: Plan::values(vec![vec![i.into(), format!("name {i}").into()]]),
+                on_conflict: None,
+            }
+            .execute(&ctx)
+            .unwrap();
+        }
+        let plan = Plan::SeqScan { table: "t".into() };
+        let expected = plan.collect(&ctx).unwrap();
+
+        let mut cursor = plan.cursor(&ctx).unwrap();
+        let mut rows = vec![];
+        for n in [0, 1, 10, 100, 10_000] {
+            let chunk = cursor.fetch(n).unwrap();
+            assert!(chunk.len() <= n);
+            rows.extend(chunk);
+        }
+        assert!(cursor.is_exhausted());
+        assert!(cursor.fetch(10).unwrap().is_empty());
+        assert_eq!(expected, rows);
+
+        // Each open scan pins its current page; spread four of them over
+        // different pages to fill the pool.
+        let mut cursors: Vec<_> = (0..4).map(|_| plan.cursor(&ctx).unwrap()).collect();
+        for (i, cursor) in cursors.iter_mut().enumerate() {
+            let rows = cursor.fetch(i * 250 + 1).unwrap();
+            assert_eq!(expected[i * 250], *rows.last().unwrap());
+        }
+        assert!(matches!(
+            plan.cursor(&ctx),
+            Err(crate::executor::Error::Heap(heap::Error::Buffer(
+                buffer::Error::NoFreeBuffer
+            )))
+        ));
+        cursors[0].close();
+        assert_eq!(expected[251..], cursors[1].fetch(expected.len()).unwrap());
+        assert!(cursors[1].is_exhausted());
+        assert_eq!(expected.len(), plan.cursor(&ctx).unwrap().count());
+        assert_eq!(Some(expected[501].clone()), cursors[2].fetch_one().unwrap());
+    }
+}
@@ -0,0 +1,934 @@
+//! INSERT, UPDATE and DELETE.
+//!
+//! Each statement keeps every index of its table consistent with the heap
+//! and returns the number of rows it affected. Index constraints are checked
+//! before a row is touched, so a violation leaves that row unchanged. Rows
+//! fire the table's [triggers](super::trigger) as they change, and a BEFORE
+//! trigger that refuses a change leaves the row out of the count.
+//!
+//! A row inserted into a partitioned table goes to the partition whose
+//! bound admits it, and an UPDATE or DELETE of one changes the rows of the
+//! partitions its predicate leaves room for. No row may leave the bound
+//! of the partition holding it.
+//!
+//! A row inserted with the column of a [time to live](crate::catalog::Ttl)
+//! NULL is stamped with the time of the insert if the TTL has a duration.
+
+use super::{
+    replace_optional, AccessPath, Change, Error, ExecContext, Plan, TableIter, TriggerRow,
+    DEFAULT_BATCH_SIZE,
+};
+use crate::catalog::{TableInfo, TriggerEvent, TriggerTiming, Ttl};
+use crate::expr::{self, Expr};
+use crate::heap::Rid;
+use crate::lock::{LockMode, LockWait};
+use crate::value::{Tuple, Value};
+
+/// Checks arity, coerces values to the column types and enforces NOT NULL.
+fn conform(table: &TableInfo, tuple: Tuple) -> Result<Tuple, Error> {
+    let columns = &table.schema.columns;
+    if tuple.len() != columns.len() {
+        return Err(Error::ColumnCountMismatch {
+            expected: columns.len(),
+            actual: tuple.len(),
+        });
+    }
+    tuple
+        .into_iter()
+        .zip(columns)
+        .map(|(value, column)| {
+            if value.is_null() && !column.nullable {
+                return Err(Error::NotNullViolation(column.name.clone()));
+            }
+            let actual = value.data_type();
+            value
+                .coerce_to(column.data_type)
+                .ok_or_else(|| Error::TypeMismatch {
+                    column: column.name.clone(),
+                    expected: column.data_type,
+                    actual: actual.unwrap(),
+                })
+        })
+        .collect()
+}
+
+/// Stamps a row inserted into `table` with the time of the insert if the
+/// TTL of the table has a duration and the row leaves its column NULL.
+fn stamp(table: &TableInfo, row: &mut [Value]) {
+    let Some(Ttl {
+        column,
+        duration: Some(_),
+    }) = table.ttl
+    else {
+        return;
+    };
+    if let Some(value) = row.get_mut(column).filter(|value| value.is_null()) {
+        *value = Value::Int(Ttl::now());
+    }
+}
+
+/// Fails if `tuple` is outside the bound of the partition `table`.
+fn check_partition(ctx: &ExecContext<'_>, table: &TableInfo, tuple: &[Value]) -> Result<(), Error> {
+    let Some(partition) = &table.partition_of else {
+        return Ok(());
+    };
+    match ctx.table(&partition.parent)?.partitioning {
+        Some(partitioning) if !partition.bound.contains(&tuple[partitioning.column]) => {
+            Err(Error::PartitionConstraint(table.name.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Fails unless the policies of `table` give `tuple` to the statement's
+/// user.
+fn check_policies(ctx: &ExecContext<'_>, table: &TableInfo, tuple: &[Value]) -> Result<(), Error> {
+    let Some(filter) = ctx
+        .user
+        .and_then(|user| ctx.catalog.row_filter(&table.name, user))
+    else {
+        return Ok(());
+    };
+    match filter.eval_predicate(tuple)? {
+        true => Ok(()),
+        false => Err(Error::PolicyViolation(table.name.clone())),
+    }
+}
+
+/// The partition of a partitioned `table` that `row` belongs in, with the
+/// row conformed to the table, or else `table` and the row as it is.
+fn route<'a>(
+    ctx: &ExecContext<'a>,
+    table: &'a TableInfo,
+    mut row: Tuple,
+) -> Result<(&'a TableInfo, Tuple), Error> {
+    if table.partitioning.is_none() {
+        return Ok((table, row));
+    }
+    stamp(table, &mut row);
+    let row = conform(table, row)?;
+    let partition = ctx
+        .catalog
+        .partition_for(table, &row)
+        .ok_or_else(|| Error::NoPartition(table.name.clone()))?;
+    Ok((partition, row))
+}
+
+/// The tables an UPDATE or DELETE of `table` reads, with the access path
+/// to each: the partitions of a partitioned table that `predicate` leaves
+/// room for, read whole, or else `table` itself.
+fn target_tables<'a>(
+    ctx: &ExecContext<'a>,
+    table: &str,
+    access: &AccessPath,
+    predicate: Option<&Expr>,
+) -> Result<Vec<(&'a TableInfo, AccessPath)>, Error> {
+    let info = ctx.table(table)?;
+    let Some(partitioning) = info.partitioning else {
+        return Ok(vec![(info, access.clone())]);
+    };
+    let admits = |partition: &TableInfo| {
+        let bound = &partition.partition_of.as_ref().unwrap().bound;
+        predicate.is_none_or(|predicate| bound.admits(partitioning.column, predicate))
+    };
+    Ok(ctx
+        .catalog
+        .partitions(table)
+        .into_iter()
+        .filter(|partition| admits(partition))
+        .map(|partition| (partition, AccessPath::SeqScan))
+        .collect())
+}
+
+/// Fails if `tuple` cannot be indexed, or if storing it would duplicate a
+/// unique key held by a row other than `rid`.
+fn check_indexes(
+    ctx: &ExecContext<'_>,
+    table: &TableInfo,
+    tuple: &[Value],
+    rid: Option<Rid>,
+) -> Result<(), Error> {
+    for index in &table.indexes {
+        index.check_entry_size(tuple)?;
+        if let Some(conflict) = index.find_conflict(ctx.bufmgr, tuple)? {
+            if Some(conflict) != rid {
+                return Err(Error::UniqueViolation(index.name.clone()));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Materializes the rows matching `predicate`, the first `limit` of them
+/// if there is a limit, before any of them is modified, so that rows moved
+/// by an update are not visited twice. Each is locked in `mode` as it is
+/// found.
+fn collect_targets(
+    ctx: &ExecContext<'_>,
+    table: &str,
+    access: &AccessPath,
+    predicate: Option<&Expr>,
+    limit: Option<usize>,
+    mode: LockMode,
+) -> Result<Vec<(Rid, Tuple)>, Error> {
+    let mut iter = TableIter::open(ctx, table, access)?;
+    let mut targets = vec![];
+    let mut read = 0;
+    while let Some((rid, tuple)) = iter.next_row()? {
+        if limit.is_some_and(|limit| targets.len() >= limit) {
+            break;
+        }
+        // Nothing has changed yet, so this is the place to give up.
+        if read % DEFAULT_BATCH_SIZE == 0 {
+            ctx.check_interrupt()?;
+        }
+        read += 1;
+        if let Some(predicate) = predicate {
+            if !predicate.eval_predicate(&tuple)? {
+                continue;
+            }
+        }
+        ctx.lock_row(table, rid, mode, LockWait::Wait)?;
+        targets.push((rid, tuple));
+    }
+    Ok(targets)
+}
+
+/// What an insert does when its row collides with an existing one on a
+/// unique index.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictAction {
+    DoNothing,
+    /// Expressions see the existing row followed by the row that was being
+    /// inserted (the "excluded" row), so column `i` of the excluded row is
+    /// `Expr::column(width + i)`.
+    DoUpdate {
+        assignments: Vec<(usize, Expr)>,
+        predicate: Option<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnConflict {
+    /// Unique index whose conflicts are handled; `None` handles conflicts
+    /// on any unique index. Conflicts on other indexes remain errors.
+    pub index: Option<String>,
+    pub action: ConflictAction,
+}
+
+/// Inserts every row produced by `source`: a [`Plan::Values`] list for
+/// `INSERT ... VALUES`, or any query for `INSERT ... SELECT`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table: String,
+    pub source: Plan,
+    pub on_conflict: Option<OnConflict>,
+}
+
+impl Insert {
+    /// Substitutes `params` for the parameters of the statement.
+    pub fn replace_parameters(&self, params: &[Value]) -> Self {
+        let on_conflict = self.on_conflict.as_ref().map(|on_conflict| OnConflict {
+            index: on_conflict.index.clone(),
+            action: match &on_conflict.action {
+                ConflictAction::DoNothing => ConflictAction::DoNothing,
+                ConflictAction::DoUpdate {
+                    assignments,
+                    predicate,
+                } => ConflictAction::DoUpdate {
+                    assignments: replace_assignments(assignments, params),
+                    predicate: replace_optional(predicate, params),
+                },
+            },
+        });
+        Self {
+            table: self.table.clone(),
+            source: self.source.replace_parameters(params),
+            on_conflict,
+        }
+    }
+
+    pub fn execute(&self, ctx: &ExecContext<'_>) -> Result<u64, Error> {
+        let table = ctx.table(&self.table)?;
+        ctx.record_write(&self.table);
+        let source = self.source.cursor(ctx)?;
+        let ctx = &ctx.for_table(&self.table);
+        let mut count = 0;
+        // A source reading the target table must not see the rows being
+        // inserted, so read it to completion first.
+        let partitions = ctx.catalog.partitions(&self.table);
+        if self.source.reads_table(&self.table)
+            || partitions.iter().any(|p| self.source.reads_table(&p.name))
+        {
+            for row in source.collect::<Result<Vec<_>, _>>()? {
+                let (table, row) = route(ctx, table, row)?;
+                let inserted = self.insert_row(ctx, table, row)?;
+                ctx.count_rows(inserted);
+                count += inserted;
+            }
+            return Ok(count);
+        }
+        for row in source {
+            let (table, row) = route(ctx, table, row?)?;
+            let inserted = self.insert_row(ctx, table, row)?;
+            ctx.count_rows(inserted);
+            count += inserted;
+        }
+        Ok(count)
+    }
+
+    fn insert_row(
+        &self,
+        ctx: &ExecContext<'_>,
+        table: &TableInfo,
+        mut row: Tuple,
+    ) -> Result<u64, Error> {
+        stamp(table, &mut row);
+        let mut tuple = conform(table, row)?;
+        if ctx.fires(table, TriggerTiming::Before, TriggerEvent::Insert) {
+            let mut row = TriggerRow::new(
+                table,
+                TriggerTiming::Before,
+                TriggerEvent::Insert,
+                None,
+                Some(tuple),
+            );
+            if !ctx.fire(table, &mut row)? {
+                return Ok(0);
+            }
+            let Some(new) = row.new else {
+                return Ok(0);
+            };
+            tuple = conform(table, new)?;
+        }
+        check_partition(ctx, table, &tuple)?;
+        check_policies(ctx, table, &tuple)?;
+        if let Some(on_conflict) = &self.on_conflict {
+            if let Some((rid, existing)) = find_handled_conflict(ctx, table, on_conflict, &tuple)? {
+                return resolve_conflict(ctx, table, &on_conflict.action, rid, existing, tuple);
+            }
+        }
+        check_indexes(ctx, table, &tuple, None)?;
+        let rid = table.heap.insert(ctx.bufmgr, &tuple)?;
+        for index in &table.indexes {
+            index.insert_entry(ctx.bufmgr, &tuple, rid)?;
+        }
+        ctx.record_row(&table.name, rid, || tuple.clone());
+        let after = ctx.fires(table, TriggerTiming::After, TriggerEvent::Insert);
+        let mut row = after.then(|| {
+            TriggerRow::new(
+                table,
+                TriggerTiming::After,
+                TriggerEvent::Insert,
+                None,
+                Some(tuple.clone()),
+            )
+        });
+        ctx.record_change(|| Change::Insert {
+            table: table.name.clone(),
+            row: tuple,
+        });
+        if let Some(row) = &mut row {
+            ctx.fire(table, row)?;
+        }
+        Ok(1)
+    }
+}
+
+fn find_handled_conflict(
+    ctx: &ExecContext<'_>,
+    table: &TableInfo,
+    on_conflict: &OnConflict,
+    tuple: &[Value],
+) -> Result<Option<(Rid, Tuple)>, Error> {
+    let indexes: Vec<_> = match &on_conflict.index {
+        Some(name) => {
+            let index = table
+                .index(name)
+                .ok_or_else(|| Error::IndexNotFound(name.clone()))?;
+            vec![index]
+        }
+        None => table.indexes.iter().collect(),
+    };
+    for index in indexes {
+        if let Some(rid) = index.find_conflict(ctx.bufmgr, tuple)? {
+            if let Some(existing) = table.heap.get(ctx.bufmgr, rid)? {
+                return Ok(Some((rid, existing)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn resolve_conflict(
+    ctx: &ExecContext<'_>,
+    table: &TableInfo,
+    action: &ConflictAction,
+    rid: Rid,
+    existing: Tuple,
+    excluded: Tuple,
+) -> Result<u64, Error> {
+    let ConflictAction::DoUpdate {
+        assignments,
+        predicate,
+    } = action
+    else {
+        return Ok(0);
+    };
+    let mut combined = existing.clone();
+    combined.extend(excluded);
+    if let Some(predicate) = predicate {
+        if !predicate.eval_predicate(&combined)? {
+            return Ok(0);
+        }
+    }
+    let new = assign(&existing, &combined, assignments)?;
+    Ok(replace_row(ctx, table, rid, &existing, new)?.into())
+}
+
+fn replace_assignments(assignments: &[(usize, Expr)], params: &[Value]) -> Vec<(usize, Expr)> {
+    assignments
+        .iter()
+        .map(|(column, expr)| (*column, expr.replace_parameters(params)))
+        .collect()
+}
+
+/// Applies `assignments`, evaluated against `input`, to a copy of `old`.
+fn assign(old: &[Value], input: &[Value], assignments: &[(usize, Expr)]) -> Result<Tuple, Error> {
+    let mut new = old.to_vec();
+    for (column, expr) in assignments {
+        let slot = new
+            .get_mut(*column)
+            .ok_or(expr::Error::ColumnOutOfRange(*column))?;
+        *slot = expr.eval(input)?;
+    }
+    Ok(new)
+}
+
+/// Replaces the row at `rid` with `new`, keeping every index in step.
+/// Returns false if a trigger refused the change.
+fn replace_row(
+    ctx: &ExecContext<'_>,
+    table: &TableInfo,
+    rid: Rid,
+    old: &Tuple,
+    new: Tuple,
+) -> Result<bool, Error> {
+    let mut new = conform(table, new)?;
+    if ctx.fires(table, TriggerTiming::Before, TriggerEvent::Update) {
+        let mut row = TriggerRow::new(
+            table,
+            TriggerTiming::Before,
+            TriggerEvent::Update,
+            Some(old.clone()),
+            Some(new),
+        );
+        if !ctx.fire(table, &mut row)? {
+            return Ok(false);
+        }
+        let Some(changed) = row.new else {
+            return Ok(false);
+        };
+        new = conform(table, changed)?;
+    }
+    check_partition(ctx, table, &new)?;
+    // The row replaced must be the user's too, which the scan of an
+    // UPDATE made sure of but a conflicting insert does not.
+    check_policies(ctx, table, old)?;
+    check_policies(ctx, table, &new)?;
+    if new == *old {
+        return Ok(true);
+    }
+    check_indexes(ctx, table, &new, Some(rid))?;
+    let new_rid = table.heap.update(ctx.bufmgr, rid, &new)?;
+    for index in &table.indexes {
+        if new_rid != rid || index.encode_key(old)? != index.encode_key(&new)? {
+            index.delete_entry(ctx.bufmgr, old, rid)?;
+            index.insert_entry(ctx.bufmgr, &new, new_rid)?;
+        }
+    }
+    ctx.record_row(&table.name, rid, || old.clone());
+    ctx.record_row(&table.name, new_rid, || new.clone());
+    let after = ctx.fires(table, TriggerTiming::After, TriggerEvent::Update);
+    let mut row = after.then(|| {
+        TriggerRow::new(
+            table,
+            TriggerTiming::After,
+            TriggerEvent::Update,
+            Some(old.clone()),
+            Some(new.clone()),
+        )
+    });
+    ctx.record_change(|| Change::Update {
+        table: table.name.clone(),
+        before: old.clone(),
+        after: new,
+    });
+    if let Some(row) = &mut row {
+        ctx.fire(table, row)?;
+    }
+    Ok(true)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub table: String,
+    pub access: AccessPath,
+    pub predicate: Option<Expr>,
+    /// `(column, expr)` pairs; expressions see the row before the update.
+    pub assignments: Vec<(usize, Expr)>,
+}
+
+impl Update {
+    pub fn replace_parameters(&self, params: &[Value]) -> Self {
+        Self {
+            table: self.table.clone(),
+            access: self.access.replace_parameters(params),
+            predicate: replace_optional(&self.predicate, params),
+            assignments: replace_assignments(&self.assignments, params),
+        }
+    }
+
+    pub fn execute(&self, ctx: &ExecContext<'_>) -> Result<u64, Error> {
+        let predicate = self.predicate.as_ref();
+        let mut count = 0;
+        for (table, access) in target_tables(ctx, &self.table, &self.access, predicate)? {
+            let ctx = &ctx.for_table(&table.name);
+            let mode = self.lock_mode(table);
+            let targets = collect_targets(ctx, &table.name, &access, predicate, None, mode)?;
+            // Not before: a statement that could not lock its rows changed
+            // nothing.
+            ctx.record_write(&self.table);
+            for (rid, old) in &targets {
+                let new = assign(old, old, &self.assignments)?;
+                let updated = u64::from(replace_row(ctx, table, *rid, old, new)?);
+                ctx.count_rows(updated);
+                count += updated;
+            }
+        }
+        Ok(count)
+    }
+
+    /// How the update locks its rows: changing a column of a unique index
+    /// changes a key, which weaker locks rely on staying as it is.
+    fn lock_mode(&self, table: &TableInfo) -> LockMode {
+        let changes_key = (table.indexes.iter().filter(|index| index.unique)).any(|index| {
+            index.columns().is_none_or(|columns| {
+                (self.assignments.iter()).any(|(column, _)| columns.contains(column))
+            })
+        });
+        match changes_key {
+            true => LockMode::Update,
+            false => LockMode::NoKeyUpdate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub table: String,
+    pub access: AccessPath,
+    pub predicate: Option<Expr>,
+    /// How many rows to delete at most, which SQL has no way to ask for;
+    /// the engine deletes expired rows in batches with it.
+    pub limit: Option<usize>,
+}
+
+impl Delete {
+    pub fn replace_parameters(&self, params: &[Value]) -> Self {
+        Self {
+            table: self.table.clone(),
+            access: self.access.replace_parameters(params),
+            predicate: replace_optional(&self.predicate, params),
+            limit: self.limit,
+        }
+    }
+
+    pub fn execute(&self, ctx: &ExecContext<'_>) -> Result<u64, Error> {
+        let predicate = self.predicate.as_ref();
+        let mut count = 0;
+        for (table, access) in target_tables(ctx, &self.table, &self.access, predicate)? {
+            let limit = self.limit.map(|limit| limit.saturating_sub(count as usize));
+            if limit == Some(0) {
+                break;
+            }
+            let ctx = &ctx.for_table(&table.name);
+            let targets = collect_targets(
+                ctx,
+                &table.name,
+                &access,
+                predicate,
+                limit,
+                LockMode::Update,
+            )?;
+            ctx.record_write(&self.table);
+            count += delete_rows(ctx, table, &targets)?;
+        }
+        Ok(count)
+    }
+}
+
+fn delete_rows(
+    ctx: &ExecContext<'_>,
+    table: &TableInfo,
+    targets: &[(Rid, Tuple)],
+) -> Result<u64, Error> {
+    let row = |timing, tuple: &Tuple| {
+        TriggerRow::new(
+            table,
+            timing,
+            TriggerEvent::Delete,
+            Some(tuple.clone()),
+            None,
+        )
+    };
+    let mut count = 0;
+    for (rid, tuple) in targets {
+        if ctx.fires(table, TriggerTiming::Before, TriggerEvent::Delete)
+            && !ctx.fire(table, &mut row(TriggerTiming::Before, tuple))?
+        {
+            continue;
+        }
+        for index in &table.indexes {
+            index.delete_entry(ctx.bufmgr, tuple, *rid)?;
+        }
+        table.heap.delete(ctx.bufmgr, *rid)?;
+        ctx.record_row(&table.name, *rid, || tuple.clone());
+        ctx.record_change(|| Change::Delete {
+            table: table.name.clone(),
+            row: tuple.clone(),
+        });
+        if ctx.fires(table, TriggerTiming::After, TriggerEvent::Delete) {
+            ctx.fire(table, &mut row(TriggerTiming::After, tuple))?;
+        }
+        ctx.count_rows(1);
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{Catalog, Column, Schema};
+    use crate::disk::DiskManager;
+    use crate::executor::{KeyRange, Plan};
+    use crate::expr::BinaryOp;
+    use crate::value::DataType;
+    use tempfile::tempfile;
+
+    fn setup() -> (BufferPoolManager, Catalog) {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 32);
+        let mut catalog = Catalog::new();
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Int).not_null(),
+            Column::new("name", DataType::Text),
+            Column::new("score", DataType::Int),
+        ]);
+        catalog.create_table(&bufmgr, "users", schema).unwrap();
+        catalog
+            .create_index(&bufmgr, "users", "users_id", &["id"], true)
+            .unwrap();
+        catalog
+            .create_index(&bufmgr, "users", "users_name", &["name"], false)
+            .unwrap();
+        {
+            let ctx = ExecContext::new(&bufmgr, &catalog);
+            for i in 0..100i64 {
+                let insert = Insert {
+                    table: "users".into(),
+                    source: Plan::values(vec![vec![
+                        i.into(),
+                        format!("user{}", i % 10).into(),
+                        (i * 10).into(),
+                    ]]),
+                    on_conflict: None,
+                };
+                assert_eq!(1, insert.execute(&ctx).unwrap());
+            }
+        }
+        (bufmgr, catalog)
+    }
+
+    fn by_index(ctx: &ExecContext<'_>, index: &str, key: Value) -> Vec<Tuple> {
+        Plan::IndexScan {
+            table: "users".into(),
+            index: index.into(),
+            range: KeyRange::eq(vec![key]),
+        }
+        .collect(ctx)
+        .unwrap()
+    }
+
+    fn id_eq(id: i64) -> Option<Expr> {
+        Some(Expr::binary(
+            BinaryOp::Eq,
+            Expr::column(0),
+            Expr::literal(id),
+        ))
+    }
+
+    #[test]
+    fn test_update_maintains_indexes() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let update = Update {
+            table: "users".into(),
+            access: AccessPath::IndexScan {
+                index: "users_name".into(),
+                range: KeyRange::eq(vec!["user3".into()]),
+            },
+            predicate: None,
+            assignments: vec![
+                (1, Expr::literal("renamed")),
+                (
+                    2,
+                    Expr::binary(BinaryOp::Add, Expr::column(2), Expr::literal(1i64)),
+                ),
+            ],
+        };
+        assert_eq!(10, update.execute(&ctx).unwrap());
+        assert!(by_index(&ctx, "users_name", "user3".into()).is_empty());
+        let renamed = by_index(&ctx, "users_name", "renamed".into());
+        assert_eq!(10, renamed.len());
+        assert!(renamed.iter().all(|row| matches!(
+            (&row[0], &row[2]),
+            (Value::Int(id), Value::Int(score)) if *score == id * 10 + 1
+        )));
+        assert_eq!(
+            vec![vec![Value::Int(13), "renamed".into(), Value::Int(131)]],
+            by_index(&ctx, "users_id", Value::Int(13))
+        );
+
+        // Growing tuples past their page's free space moves them to new rids.
+        let long_name = "x".repeat(800);
+        let update = Update {
+            table: "users".into(),
+            access: AccessPath::SeqScan,
+            predicate: Some(Expr::binary(
+                BinaryOp::Lt,
+                Expr::column(0),
+                Expr::literal(2i64),
+            )),
+            assignments: vec![(1, Expr::literal(long_name.as_str()))],
+        };
+        assert_eq!(2, update.execute(&ctx).unwrap());
+        assert_eq!(1, by_index(&ctx, "users_id", Value::Int(0)).len());
+        assert_eq!(1, by_index(&ctx, "users_id", Value::Int(1)).len());
+        assert_eq!(2, by_index(&ctx, "users_name", long_name.into()).len());
+
+        let update = Update {
+            predicate: id_eq(2),
+            assignments: vec![(1, Expr::literal("x".repeat(2000)))],
+            ..update
+        };
+        assert!(matches!(update.execute(&ctx), Err(Error::BTree(_))));
+        let row = by_index(&ctx, "users_id", Value::Int(2));
+        assert_eq!(Value::Text("user2".into()), row[0][1]);
+    }
+
+    #[test]
+    fn test_update_unique_violation() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let update = Update {
+            table: "users".into(),
+            access: AccessPath::SeqScan,
+            predicate: id_eq(1),
+            assignments: vec![(0, Expr::literal(2i64))],
+        };
+        assert!(matches!(
+            update.execute(&ctx),
+            Err(Error::UniqueViolation(_))
+        ));
+        assert_eq!(1, by_index(&ctx, "users_id", Value::Int(1)).len());
+
+        let update = Update {
+            predicate: id_eq(1),
+            assignments: vec![(0, Expr::literal(Value::Null))],
+            ..update
+        };
+        assert!(matches!(
+            update.execute(&ctx),
+            Err(Error::NotNullViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_delete_maintains_indexes() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let delete = Delete {
+            table: "users".into(),
+            access: AccessPath::SeqScan,
+            predicate: Some(Expr::binary(
+                BinaryOp::GtEq,
+                Expr::column(2),
+                Expr::literal(500i64),
+            )),
+            limit: None,
+        };
+        assert_eq!(50, delete.execute(&ctx).unwrap());
+        assert_eq!(0, delete.execute(&ctx).unwrap());
+        assert!(by_index(&ctx, "users_id", Value::Int(70)).is_empty());
+        assert_eq!(5, by_index(&ctx, "users_name", "user7".into()).len());
+        let remaining = Plan::SeqScan {
+            table: "users".into(),
+        }
+        .collect(&ctx)
+        .unwrap();
+        assert_eq!(50, remaining.len());
+
+        let insert = Insert {
+            table: "users".into(),
+            source: Plan::values(vec![vec![70i64.into(), Value::Null, Value::Null]]),
+            on_conflict: None,
+        };
+        assert_eq!(1, insert.execute(&ctx).unwrap());
+        assert_eq!(1, by_index(&ctx, "users_id", Value::Int(70)).len());
+    }
+
+    #[test]
+    fn test_insert_on_conflict() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let upsert = |values: Vec<Value>, index: Option<&str>, action: ConflictAction| Insert {
+            table: "users".into(),
+            source: Plan::values(vec![values]),
+            on_conflict: Some(OnConflict {
+                index: index.map(String::from),
+                action,
+            }),
+        };
+        let row = |id: i64| by_index(&ctx, "users_id", Value::Int(id));
+
+        let insert = upsert(
+            vec![5i64.into(), "new".into(), 1i64.into()],
+            None,
+            ConflictAction::DoNothing,
+        );
+        assert_eq!(0, insert.execute(&ctx).unwrap());
+        assert_eq!(Value::Text("user5".into()), row(5)[0][1]);
+
+        // name = excluded.name, score = score + excluded.score
+        let do_update = ConflictAction::DoUpdate {
+            assignments: vec![
+                (1, Expr::column(4)),
+                (
+                    2,
+                    Expr::binary(BinaryOp::Add, Expr::column(2), Expr::column(5)),
+                ),
+            ],
+            predicate: Some(Expr::binary(
+                BinaryOp::Lt,
+                Expr::column(2),
+                Expr::literal(100i64),
+            )),
+        };
+        let insert = upsert(
+            vec![5i64.into(), "new".into(), 1i64.into()],
+            Some("users_id"),
+            do_update.clone(),
+        );
+        assert_eq!(1, insert.execute(&ctx).unwrap());
+        assert_eq!(
+            vec![vec![Value::Int(5), "new".into(), Value::Int(51)]],
+            row(5)
+        );
+        assert_eq!(1, by_index(&ctx, "users_name", "new".into()).len());
+        assert_eq!(9, by_index(&ctx, "users_name", "user5".into()).len());
+
+        // The predicate rejects the update once the score reaches 100.
+        let insert = upsert(
+            vec![5i64.into(), "newer".into(), 60i64.into()],
+            None,
+            do_update.clone(),
+        );
+        assert_eq!(1, insert.execute(&ctx).unwrap());
+        assert_eq!(0, insert.execute(&ctx).unwrap());
+        assert_eq!(Value::Int(111), row(5)[0][2]);
+
+        let insert = upsert(
+            vec![1000i64.into(), "fresh".into(), Value::Null],
+            None,
+            do_update,
+        );
+        assert_eq!(1, insert.execute(&ctx).unwrap());
+        assert_eq!(1, row(1000).len());
+
+        // Conflicts outside the handled index are still violations.
+        let insert = upsert(
+            vec![7i64.into(), "user7".into(), Value::Null],
+            Some("users_name"),
+            ConflictAction::DoNothing,
+        );
+        assert!(matches!(
+            insert.execute(&ctx),
+            Err(Error::UniqueViolation(_))
+        ));
+    }
+
+    #[test]
+    fn test_insert_from_plans() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let scan = || Plan::SeqScan {
+            table: "users".into(),
+        };
+        let insert = Insert {
+            table: "users".into(),
+            source: Plan::Values {
+                rows: vec![
+                    vec![
+                        Expr::literal(100i64),
+                        Expr::literal("a"),
+                        Expr::literal(1i64),
+                    ],
+                    vec![
+                        Expr::binary(BinaryOp::Add, Expr::literal(100i64), Expr::literal(1i64)),
+                        Expr::literal("b"),
+                        Expr::literal(Value::Null),
+                    ],
+                ],
+            },
+            on_conflict: None,
+        };
+        assert_eq!(2, insert.execute(&ctx).unwrap());
+        assert_eq!(1, by_index(&ctx, "users_id", Value::Int(101)).len());
+
+        // Copy the table onto itself with shifted ids; rows inserted by the
+        // statement are not read back.
+        let insert = Insert {
+            table: "users".into(),
+            source: Plan::Project {
+                input: Box::new(scan()),
+                exprs: vec![
+                    Expr::binary(BinaryOp::Add, Expr::column(0), Expr::literal(1000i64)),
+                    Expr::column(1),
+                    Expr::column(2),
+                ],
+            },
+            on_conflict: None,
+        };
+        assert_eq!(102, insert.execute(&ctx).unwrap());
+        assert_eq!(204, scan().collect(&ctx).unwrap().len());
+        assert_eq!(20, by_index(&ctx, "users_name", "user3".into()).len());
+
+        // A failing row stops the statement; earlier rows stay inserted.
+        let insert = Insert {
+            table: "users".into(),
+            source: Plan::values(vec![
+                vec![5000i64.into(), Value::Null, Value::Null],
+                vec![5i64.into(), Value::Null, Value::Null],
+            ]),
+            on_conflict: None,
+        };
+        assert!(matches!(
+            insert.execute(&ctx),
+            Err(Error::UniqueViolation(_))
+        ));
+        assert_eq!(1, by_index(&ctx, "users_id", Value::Int(5000)).len());
+    }
+}
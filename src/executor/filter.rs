@@ -0,0 +1,38 @@
+use super::{Batch, BoxExecutor, Error, Executor};
+use crate::expr::Expr;
+use crate::value::Tuple;
+use crate::value::Value;
+
+pub struct Filter<'a> {
+    pub input: BoxExecutor<'a>,
+    pub predicate: Expr,
+}
+
+impl Executor for Filter<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        while let Some(tuple) = self.input.next()? {
+            if self.predicate.eval_predicate(&tuple)? {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_batch(&mut self, max_rows: usize) -> Result<Option<Batch>, Error> {
+        // Skip input batches that filter down to nothing rather than
+        // handing empty batches upstream.
+        while let Some(batch) = self.input.next_batch(max_rows)? {
+            let selection: Vec<bool> = self
+                .predicate
+                .eval_batch(batch.columns(), batch.len())?
+                .into_iter()
+                .map(|value| value == Value::Bool(true))
+                .collect();
+            let batch = batch.select(&selection);
+            if !batch.is_empty() {
+                return Ok(Some(batch));
+            }
+        }
+        Ok(None)
+    }
+}
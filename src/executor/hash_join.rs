@@ -0,0 +1,248 @@
+//! Equi-join that builds a hash table over the right input.
+//!
+//! A right input that outgrows work_mem is split into partitions by the
+//! hash of the join key, spilled to disk along with the left input split
+//! the same way, and each pair of partitions is joined on its own, split
+//! again if it too is too large, as aggregation does its groups.
+
+use std::collections::HashMap;
+
+use super::aggregate::{partition_of, MAX_SPILL_DEPTH, SPILL_PARTITIONS};
+use super::memory::{tuple_size, MemoryContext, MemoryReservation};
+use super::spill::{SpillFile, SpillReader};
+use super::{BoxExecutor, Error, ExecContext, Executor, JoinKind};
+use crate::expr::Expr;
+use crate::tuple;
+use crate::value::{Tuple, Value};
+
+/// Encodes the join key of `row`, or `None` if any part of it is NULL and
+/// so cannot equal anything.
+pub(super) fn join_key(keys: &[Expr], row: &[Value]) -> Result<Option<Vec<u8>>, Error> {
+    let values = keys
+        .iter()
+        .map(|key| key.eval(row))
+        .collect::<Result<Vec<_>, _>>()?;
+    if values.iter().any(Value::is_null) {
+        return Ok(None);
+    }
+    let mut key = vec![];
+    tuple::encode_key(&values, &mut key);
+    Ok(Some(key))
+}
+
+pub struct HashJoin<'a> {
+    left: BoxExecutor<'a>,
+    right: Option<BoxExecutor<'a>>,
+    kind: JoinKind,
+    left_keys: Vec<Expr>,
+    right_keys: Vec<Expr>,
+    predicate: Option<Expr>,
+    right_width: usize,
+    memory: &'a MemoryContext,
+    reservation: MemoryReservation<'a>,
+    /// Right rows by join key, in input order.
+    table: HashMap<Vec<u8>, Vec<Tuple>>,
+    /// Current left row, its key, the next candidate to try, and whether
+    /// it has matched anything yet.
+    outer: Option<(Tuple, Option<Vec<u8>>, usize, bool)>,
+    /// Set once the inputs have been split: left rows then come from the
+    /// partition being joined, and the others wait their turn.
+    spilled: bool,
+    probe: Option<SpillReader>,
+    pending: Vec<Partition>,
+}
+
+/// The right and left rows whose keys hash alike at `depth`.
+struct Partition {
+    right: SpillFile,
+    left: SpillFile,
+    depth: usize,
+}
+
+impl<'a> HashJoin<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ctx: &ExecContext<'a>,
+        left: BoxExecutor<'a>,
+        right: BoxExecutor<'a>,
+        kind: JoinKind,
+        left_keys: Vec<Expr>,
+        right_keys: Vec<Expr>,
+        predicate: Option<Expr>,
+        right_width: usize,
+    ) -> Self {
+        Self {
+            left,
+            right: Some(right),
+            kind,
+            left_keys,
+            right_keys,
+            predicate,
+            right_width,
+            memory: ctx.memory,
+            reservation: ctx.memory.reservation(),
+            table: HashMap::new(),
+            outer: None,
+            spilled: false,
+            probe: None,
+            pending: vec![],
+        }
+    }
+
+    fn build(&mut self) -> Result<(), Error> {
+        let Some(mut right) = self.right.take() else {
+            return Ok(());
+        };
+        if let Some(right) = self.load(|| right.next(), 0)? {
+            self.spilled = true;
+            let (memory, keys, kind) = (self.memory, &self.left_keys, self.kind);
+            self.pending = split(memory, keys, kind, right, || self.left.next(), 0)?;
+        }
+        Ok(())
+    }
+
+    /// Fills the table with the rows of `right`, or, once they outgrow
+    /// work_mem, spills them and those already in it to partitions split
+    /// at `depth`, which it returns.
+    fn load(
+        &mut self,
+        mut right: impl FnMut() -> Result<Option<Tuple>, Error>,
+        depth: usize,
+    ) -> Result<Option<Vec<SpillFile>>, Error> {
+        let mut files: Option<Vec<SpillFile>> = None;
+        while let Some(row) = right()? {
+            let Some(key) = join_key(&self.right_keys, &row)? else {
+                continue;
+            };
+            if files.is_none() && self.reservation.exceeds_work_mem() && depth < MAX_SPILL_DEPTH {
+                let mut spilled = spill_files(self.memory)?;
+                for (key, rows) in self.table.drain() {
+                    let file = &mut spilled[partition_of(&key, depth)];
+                    for row in rows {
+                        file.write(self.memory, &row)?;
+                    }
+                }
+                self.reservation.free();
+                files = Some(spilled);
+            }
+            match &mut files {
+                Some(files) => files[partition_of(&key, depth)].write(self.memory, &row)?,
+                None => {
+                    self.reservation.grow(tuple_size(&row) + key.len())?;
+                    self.table.entry(key).or_default().push(row);
+                }
+            }
+        }
+        Ok(files)
+    }
+
+    /// The next left row to join, from the input, or once it has been
+    /// split, from the partitions in turn, each with its right rows
+    /// loaded into the table first.
+    fn next_outer(&mut self) -> Result<Option<Tuple>, Error> {
+        if !self.spilled {
+            return self.left.next();
+        }
+        loop {
+            if let Some(probe) = &mut self.probe {
+                if let Some(row) = probe.next_row()? {
+                    return Ok(Some(row));
+                }
+            }
+            self.probe = None;
+            let Some(partition) = self.pending.pop() else {
+                return Ok(None);
+            };
+            self.table.clear();
+            self.reservation.free();
+            let mut right = partition.right.into_reader()?;
+            let mut left = partition.left.into_reader()?;
+            match self.load(|| right.next_row(), partition.depth)? {
+                None => self.probe = Some(left),
+                Some(right) => {
+                    let (memory, keys, kind) = (self.memory, &self.left_keys, self.kind);
+                    let depth = partition.depth;
+                    let split = split(memory, keys, kind, right, || left.next_row(), depth)?;
+                    self.pending.extend(split);
+                }
+            }
+        }
+    }
+}
+
+fn spill_files(memory: &MemoryContext) -> Result<Vec<SpillFile>, Error> {
+    (0..SPILL_PARTITIONS)
+        .map(|_| SpillFile::new(memory))
+        .collect()
+}
+
+/// Splits the rows of `left` at `depth` as the right ones in `right`
+/// were, into the pairs left to join. Left rows whose key is NULL match
+/// nothing, and are kept only for a left join, in any partition.
+fn split(
+    memory: &MemoryContext,
+    keys: &[Expr],
+    kind: JoinKind,
+    right: Vec<SpillFile>,
+    mut left: impl FnMut() -> Result<Option<Tuple>, Error>,
+    depth: usize,
+) -> Result<Vec<Partition>, Error> {
+    let mut files = spill_files(memory)?;
+    while let Some(row) = left()? {
+        let partition = match join_key(keys, &row)? {
+            Some(key) => partition_of(&key, depth),
+            None if kind == JoinKind::Left => 0,
+            None => continue,
+        };
+        files[partition].write(memory, &row)?;
+    }
+    let pairs = right.into_iter().zip(files);
+    Ok(pairs
+        .filter(|(right, left)| left.rows() > 0 && (right.rows() > 0 || kind == JoinKind::Left))
+        .map(|(right, left)| Partition {
+            right,
+            left,
+            depth: depth + 1,
+        })
+        .collect())
+}
+
+impl Executor for HashJoin<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        self.build()?;
+        loop {
+            let Some((outer, key, next, matched)) = &mut self.outer else {
+                match self.next_outer()? {
+                    Some(row) => {
+                        let key = join_key(&self.left_keys, &row)?;
+                        self.outer = Some((row, key, 0, false));
+                    }
+                    None => return Ok(None),
+                }
+                continue;
+            };
+            let candidates = key
+                .as_ref()
+                .and_then(|key| self.table.get(key))
+                .map_or(&[][..], Vec::as_slice);
+            while *next < candidates.len() {
+                let mut row = outer.clone();
+                row.extend(candidates[*next].iter().cloned());
+                *next += 1;
+                let passes = match &self.predicate {
+                    Some(predicate) => predicate.eval_predicate(&row)?,
+                    None => true,
+                };
+                if passes {
+                    *matched = true;
+                    return Ok(Some(row));
+                }
+            }
+            let (mut row, _, _, matched) = self.outer.take().unwrap();
+            if self.kind == JoinKind::Left && !matched {
+                row.extend(std::iter::repeat_n(Value::Null, self.right_width));
+                return Ok(Some(row));
+            }
+        }
+    }
+}
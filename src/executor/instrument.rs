@@ -0,0 +1,143 @@
+//! Per-operator counters for EXPLAIN ANALYZE and trace events.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{Batch, BoxExecutor, Error, Executor, Plan};
+use crate::trace::{self, Event};
+use crate::value::Tuple;
+
+/// What one plan node did while the plan ran.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OperatorStats {
+    /// Number of times the operator was started.
+    pub loops: u64,
+    pub rows: u64,
+    /// Time spent producing rows, including the time of its inputs.
+    pub time: Duration,
+}
+
+/// Collects [`OperatorStats`] for every node of a plan started through an
+/// [`ExecContext`](super::ExecContext) that carries it. Nodes are told
+/// apart by address, so the plan must not move while its stats are read.
+#[derive(Debug, Default)]
+pub struct Instrumentation {
+    nodes: Mutex<HashMap<usize, OperatorStats>>,
+}
+
+fn node_id(plan: &Plan) -> usize {
+    plan as *const Plan as usize
+}
+
+impl Instrumentation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stats of `plan`, or `None` if it never started.
+    pub fn stats(&self, plan: &Plan) -> Option<OperatorStats> {
+        self.nodes.lock().unwrap().get(&node_id(plan)).copied()
+    }
+
+    pub(super) fn wrap<'a>(&'a self, plan: &Plan, inner: BoxExecutor<'a>) -> BoxExecutor<'a> {
+        let id = node_id(plan);
+        self.nodes.lock().unwrap().entry(id).or_default().loops += 1;
+        Box::new(Instrumented {
+            inner,
+            instrumentation: self,
+            id,
+            rows: 0,
+            time: Duration::ZERO,
+        })
+    }
+}
+
+/// Counts locally and adds its totals to the shared stats when dropped.
+struct Instrumented<'a> {
+    inner: BoxExecutor<'a>,
+    instrumentation: &'a Instrumentation,
+    id: usize,
+    rows: u64,
+    time: Duration,
+}
+
+impl Executor for Instrumented<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        let start = Instant::now();
+        let row = self.inner.next();
+        self.time += start.elapsed();
+        if let Ok(Some(_)) = row {
+            self.rows += 1;
+        }
+        row
+    }
+
+    fn next_batch(&mut self, max_rows: usize) -> Result<Option<Batch>, Error> {
+        let start = Instant::now();
+        let batch = self.inner.next_batch(max_rows);
+        self.time += start.elapsed();
+        if let Ok(Some(batch)) = &batch {
+            self.rows += batch.len() as u64;
+        }
+        batch
+    }
+}
+
+impl Drop for Instrumented<'_> {
+    fn drop(&mut self) {
+        let mut nodes = self.instrumentation.nodes.lock().unwrap();
+        let stats = nodes.entry(self.id).or_default();
+        stats.rows += self.rows;
+        stats.time += self.time;
+    }
+}
+
+/// Wraps `inner` to emit an [`Event::Operator`] when it is dropped.
+pub(super) fn traced<'a>(name: &'static str, inner: BoxExecutor<'a>) -> BoxExecutor<'a> {
+    Box::new(Traced {
+        inner,
+        name,
+        rows: 0,
+        time: Duration::ZERO,
+    })
+}
+
+struct Traced<'a> {
+    inner: BoxExecutor<'a>,
+    name: &'static str,
+    rows: u64,
+    time: Duration,
+}
+
+impl Executor for Traced<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        let start = Instant::now();
+        let row = self.inner.next();
+        self.time += start.elapsed();
+        if let Ok(Some(_)) = row {
+            self.rows += 1;
+        }
+        row
+    }
+
+    fn next_batch(&mut self, max_rows: usize) -> Result<Option<Batch>, Error> {
+        let start = Instant::now();
+        let batch = self.inner.next_batch(max_rows);
+        self.time += start.elapsed();
+        if let Ok(Some(batch)) = &batch {
+            self.rows += batch.len() as u64;
+        }
+        batch
+    }
+}
+
+impl Drop for Traced<'_> {
+    fn drop(&mut self) {
+        trace::emit(&Event::Operator {
+            name: self.name,
+            rows: self.rows,
+            time: self.time,
+        });
+    }
+}
@@ -0,0 +1,92 @@
+//! Nested loop join over a materialized inner side.
+
+use super::memory::{tuple_size, MemoryReservation};
+use super::{BoxExecutor, Error, ExecContext, Executor};
+use crate::expr::Expr;
+use crate::value::{Tuple, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    /// Keeps every outer row, padding it with NULLs when nothing matches.
+    Left,
+}
+
+pub struct NestedLoopJoin<'a> {
+    left: BoxExecutor<'a>,
+    right: Option<BoxExecutor<'a>>,
+    kind: JoinKind,
+    predicate: Option<Expr>,
+    right_width: usize,
+    reservation: MemoryReservation<'a>,
+    inner: Vec<Tuple>,
+    /// Current outer row, the next inner row to try, and whether it has
+    /// matched anything yet.
+    outer: Option<(Tuple, usize, bool)>,
+}
+
+impl<'a> NestedLoopJoin<'a> {
+    pub fn new(
+        ctx: &ExecContext<'a>,
+        left: BoxExecutor<'a>,
+        right: BoxExecutor<'a>,
+        kind: JoinKind,
+        predicate: Option<Expr>,
+        right_width: usize,
+    ) -> Self {
+        Self {
+            left,
+            right: Some(right),
+            kind,
+            predicate,
+            right_width,
+            reservation: ctx.memory.reservation(),
+            inner: vec![],
+            outer: None,
+        }
+    }
+
+    fn materialize_inner(&mut self) -> Result<(), Error> {
+        let Some(mut right) = self.right.take() else {
+            return Ok(());
+        };
+        while let Some(row) = right.next()? {
+            self.reservation.grow(tuple_size(&row))?;
+            self.inner.push(row);
+        }
+        Ok(())
+    }
+}
+
+impl Executor for NestedLoopJoin<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        self.materialize_inner()?;
+        loop {
+            let Some((outer, next, matched)) = &mut self.outer else {
+                match self.left.next()? {
+                    Some(row) => self.outer = Some((row, 0, false)),
+                    None => return Ok(None),
+                }
+                continue;
+            };
+            while *next < self.inner.len() {
+                let mut row = outer.clone();
+                row.extend(self.inner[*next].iter().cloned());
+                *next += 1;
+                let passes = match &self.predicate {
+                    Some(predicate) => predicate.eval_predicate(&row)?,
+                    None => true,
+                };
+                if passes {
+                    *matched = true;
+                    return Ok(Some(row));
+                }
+            }
+            let (mut row, _, matched) = self.outer.take().unwrap();
+            if self.kind == JoinKind::Left && !matched {
+                row.extend(std::iter::repeat_n(Value::Null, self.right_width));
+                return Ok(Some(row));
+            }
+        }
+    }
+}
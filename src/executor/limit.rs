@@ -0,0 +1,31 @@
+use super::{BoxExecutor, Error, Executor};
+use crate::value::Tuple;
+
+pub struct Limit<'a> {
+    pub input: BoxExecutor<'a>,
+    /// Rows still to be returned; `None` is unlimited.
+    pub remaining: Option<usize>,
+    /// Rows still to be skipped.
+    pub offset: usize,
+}
+
+impl Executor for Limit<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        if self.remaining == Some(0) {
+            return Ok(None);
+        }
+        while self.offset > 0 {
+            if self.input.next()?.is_none() {
+                return Ok(None);
+            }
+            self.offset -= 1;
+        }
+        let row = self.input.next()?;
+        if row.is_some() {
+            if let Some(remaining) = &mut self.remaining {
+                *remaining -= 1;
+            }
+        }
+        Ok(row)
+    }
+}
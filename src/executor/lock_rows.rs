@@ -0,0 +1,160 @@
+use super::scan::TableIter;
+use super::{AccessPath, BoxExecutor, Error, ExecContext, Executor, Plan};
+use crate::catalog::Catalog;
+use crate::expr::Expr;
+use crate::heap::Rid;
+use crate::lock::{LockMode, LockWait};
+use crate::value::{Tuple, Value};
+
+/// The rows of a table, each followed by its rid.
+pub struct RidScan<'a> {
+    pub iter: TableIter<'a>,
+}
+
+impl Executor for RidScan<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        Ok(self.iter.next_row()?.map(|(rid, mut tuple)| {
+            tuple.push(Value::Bytes(rid.to_bytes().to_vec()));
+            tuple
+        }))
+    }
+}
+
+pub struct LockRows<'a> {
+    pub ctx: ExecContext<'a>,
+    pub input: BoxExecutor<'a>,
+    pub table: &'a str,
+    pub mode: LockMode,
+    pub wait: LockWait,
+}
+
+impl Executor for LockRows<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        while let Some(mut tuple) = self.input.next()? {
+            let Some(Value::Bytes(rid)) = tuple.pop() else {
+                unreachable!("the input ends in a rid");
+            };
+            let rid = Rid::from_bytes(&rid);
+            if (self.ctx).lock_row(self.table, rid, self.mode, self.wait)? {
+                return Ok(Some(tuple));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Plan {
+    /// The plan of a query that locks the rows it returns in `mode`, once
+    /// they are sorted and before any limit, so that rows SKIP LOCKED
+    /// leaves out do not count toward it. A query that scans no table is
+    /// left as it is; one whose rows are not those of one table fails
+    /// with what it has instead, such as joins.
+    pub fn lock_rows(
+        self,
+        catalog: &Catalog,
+        mode: LockMode,
+        wait: LockWait,
+    ) -> Result<Plan, &'static str> {
+        match self {
+            Plan::Limit {
+                input,
+                limit,
+                offset,
+            } => Ok(Plan::Limit {
+                input: Box::new(input.lock_rows(catalog, mode, wait)?),
+                limit,
+                offset,
+            }),
+            plan if !plan.scans_table() => Ok(plan),
+            plan => {
+                let (input, table, _) = plan.with_rids(catalog)?;
+                Ok(Plan::LockRows {
+                    input: Box::new(input),
+                    table,
+                    mode,
+                    wait,
+                })
+            }
+        }
+    }
+
+    /// This plan with each row followed by the rid of the row it stands
+    /// for, with the table of that row and the rid's column.
+    fn with_rids(self, catalog: &Catalog) -> Result<(Plan, String, usize), &'static str> {
+        let (table, access, predicate) = match self {
+            Plan::SeqScan { table } => (table, AccessPath::SeqScan, None),
+            Plan::ParallelSeqScan { table, predicate } => (table, AccessPath::SeqScan, predicate),
+            Plan::IndexScan {
+                table,
+                index,
+                range,
+            } => (table, AccessPath::IndexScan { index, range }, None),
+            Plan::Filter { input, predicate } => {
+                let (input, table, rid) = input.with_rids(catalog)?;
+                let input = Box::new(input);
+                return Ok((Plan::Filter { input, predicate }, table, rid));
+            }
+            Plan::Sort { input, keys } => {
+                let (input, table, rid) = input.with_rids(catalog)?;
+                let input = Box::new(input);
+                return Ok((Plan::Sort { input, keys }, table, rid));
+            }
+            Plan::Limit {
+                input,
+                limit,
+                offset,
+            } => {
+                let (input, table, rid) = input.with_rids(catalog)?;
+                let input = Box::new(input);
+                let limit = Plan::Limit {
+                    input,
+                    limit,
+                    offset,
+                };
+                return Ok((limit, table, rid));
+            }
+            Plan::Project { input, mut exprs } => {
+                let (input, table, rid) = input.with_rids(catalog)?;
+                let width = exprs.len();
+                exprs.push(Expr::column(rid));
+                let input = Box::new(input);
+                return Ok((Plan::Project { input, exprs }, table, width));
+            }
+            Plan::Materialize { id, cte, input } => {
+                let (input, table, rid) = input.with_rids(catalog)?;
+                let input = Box::new(input);
+                return Ok((Plan::Materialize { id, cte, input }, table, rid));
+            }
+            Plan::Union { .. } => return Err("partitioned tables"),
+            Plan::Unnest { .. } => return Err("set-returning functions"),
+            _ => return Err("joins"),
+        };
+        let width = catalog.table(&table).map_or(0, |t| t.schema.columns.len());
+        let scan = Plan::RidScan {
+            table: table.clone(),
+            access,
+        };
+        let plan = match predicate {
+            Some(predicate) => Plan::Filter {
+                input: Box::new(scan),
+                predicate,
+            },
+            None => scan,
+        };
+        Ok((plan, table, width))
+    }
+
+    /// Whether running the plan locks the rows it returns.
+    pub fn locks_rows(&self) -> bool {
+        matches!(self, Plan::LockRows { .. })
+            || self.inputs().iter().any(|input| input.locks_rows())
+    }
+
+    /// Whether the plan scans a table.
+    fn scans_table(&self) -> bool {
+        match self {
+            Plan::SeqScan { .. } | Plan::ParallelSeqScan { .. } | Plan::IndexScan { .. } => true,
+            plan => plan.inputs().iter().any(|input| input.scans_table()),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Per-query memory accounting.
+//!
+//! Operators that buffer rows charge them to the query's [`MemoryContext`]
+//! through a [`MemoryReservation`]. Each reservation is expected to stay
+//! within `work_mem`, spilling to disk once it grows past it; the sum of
+//! all reservations may never exceed the hard limit.
+
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use super::{Error, QueryProgress};
+use crate::value::Value;
+
+#[derive(Debug)]
+pub struct MemoryContext {
+    work_mem: usize,
+    hard_limit: Option<usize>,
+    /// Where spill files go; the system's temporary directory if unset.
+    temp_dir: Option<PathBuf>,
+    /// Where spills also count toward the statement's progress.
+    progress: Option<QueryProgress>,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+    spilled_bytes: AtomicU64,
+}
+
+impl MemoryContext {
+    pub const fn new(work_mem: usize, hard_limit: Option<usize>) -> Self {
+        Self {
+            work_mem,
+            hard_limit,
+            temp_dir: None,
+            progress: None,
+            used: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            spilled_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Never spills and never fails.
+    pub const fn unlimited() -> Self {
+        Self::new(usize::MAX, None)
+    }
+
+    pub fn with_temp_dir(self, temp_dir: Option<PathBuf>) -> Self {
+        Self { temp_dir, ..self }
+    }
+
+    pub fn with_progress(self, progress: QueryProgress) -> Self {
+        Self {
+            progress: Some(progress),
+            ..self
+        }
+    }
+
+    pub fn work_mem(&self) -> usize {
+        self.work_mem
+    }
+
+    pub fn hard_limit(&self) -> Option<usize> {
+        self.hard_limit
+    }
+
+    pub fn temp_dir(&self) -> Option<&Path> {
+        self.temp_dir.as_deref()
+    }
+
+    /// Bytes currently reserved.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Largest number of bytes reserved at once.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Bytes written to spill files.
+    pub fn spilled_bytes(&self) -> u64 {
+        self.spilled_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn reservation(&self) -> MemoryReservation<'_> {
+        MemoryReservation { ctx: self, size: 0 }
+    }
+
+    pub(super) fn record_spill(&self, bytes: usize) {
+        self.spilled_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Some(progress) = &self.progress {
+            progress.add_spilled_bytes(bytes as u64);
+        }
+    }
+}
+
+impl Default for MemoryContext {
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+/// Memory held by one operator. Released when dropped.
+#[derive(Debug)]
+pub struct MemoryReservation<'a> {
+    ctx: &'a MemoryContext,
+    size: usize,
+}
+
+impl MemoryReservation<'_> {
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the owner should start spilling.
+    pub fn exceeds_work_mem(&self) -> bool {
+        self.size > self.ctx.work_mem
+    }
+
+    pub fn grow(&mut self, bytes: usize) -> Result<(), Error> {
+        let used = self.ctx.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(limit) = self.ctx.hard_limit {
+            if used > limit {
+                self.ctx.used.fetch_sub(bytes, Ordering::Relaxed);
+                return Err(Error::OutOfBudget { limit });
+            }
+        }
+        self.ctx.peak.fetch_max(used, Ordering::Relaxed);
+        self.size += bytes;
+        Ok(())
+    }
+
+    pub fn free(&mut self) {
+        self.ctx.used.fetch_sub(self.size, Ordering::Relaxed);
+        self.size = 0;
+    }
+}
+
+impl Drop for MemoryReservation<'_> {
+    fn drop(&mut self) {
+        self.free();
+    }
+}
+
+/// Approximate in-memory footprint of a row.
+pub fn tuple_size(tuple: &[Value]) -> usize {
+    let payload: usize = tuple
+        .iter()
+        .map(|value| match value {
+            Value::Text(s) => s.len(),
+            Value::Bytes(b) => b.len(),
+            _ => 0,
+        })
+        .sum();
+    mem::size_of::<Vec<Value>>() + mem::size_of_val(tuple) + payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservations() {
+        let ctx = MemoryContext::new(100, Some(250));
+        let mut a = ctx.reservation();
+        a.grow(80).unwrap();
+        assert!(!a.exceeds_work_mem());
+        a.grow(80).unwrap();
+        assert!(a.exceeds_work_mem());
+        {
+            let mut b = ctx.reservation();
+            b.grow(50).unwrap();
+            assert_eq!(210, ctx.used());
+            assert!(matches!(b.grow(50), Err(Error::OutOfBudget { limit: 250 })));
+            assert_eq!(50, b.size());
+        }
+        assert_eq!(160, ctx.used());
+        drop(a);
+        assert_eq!(0, ctx.used());
+        assert_eq!(210, ctx.peak());
+    }
+}
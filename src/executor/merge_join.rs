@@ -0,0 +1,145 @@
+//! Equi-join of two inputs sorted on their join keys.
+
+use std::cmp::Ordering;
+
+use super::hash_join::join_key;
+use super::memory::{tuple_size, MemoryReservation};
+use super::{BoxExecutor, Error, ExecContext, Executor, JoinKind};
+use crate::expr::Expr;
+use crate::value::{Tuple, Value};
+
+/// Only the right rows sharing the current key are held in memory.
+pub struct MergeJoin<'a> {
+    left: BoxExecutor<'a>,
+    right: BoxExecutor<'a>,
+    kind: JoinKind,
+    left_keys: Vec<Expr>,
+    right_keys: Vec<Expr>,
+    predicate: Option<Expr>,
+    right_width: usize,
+    reservation: MemoryReservation<'a>,
+    /// Next right row not yet in a group, with its key.
+    peeked: Option<(Vec<u8>, Tuple)>,
+    started: bool,
+    /// Key of the last group read and the right rows that have it; the
+    /// group is empty when no right row had the key.
+    group_key: Option<Vec<u8>>,
+    group: Vec<Tuple>,
+    /// Current left row, the next group row to try, and whether it has
+    /// matched anything yet. Rows with a NULL key have no group.
+    outer: Option<(Tuple, Option<usize>, bool)>,
+}
+
+impl<'a> MergeJoin<'a> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        ctx: &ExecContext<'a>,
+        left: BoxExecutor<'a>,
+        right: BoxExecutor<'a>,
+        kind: JoinKind,
+        left_keys: Vec<Expr>,
+        right_keys: Vec<Expr>,
+        predicate: Option<Expr>,
+        right_width: usize,
+    ) -> Self {
+        Self {
+            left,
+            right,
+            kind,
+            left_keys,
+            right_keys,
+            predicate,
+            right_width,
+            reservation: ctx.memory.reservation(),
+            peeked: None,
+            started: false,
+            group_key: None,
+            group: vec![],
+            outer: None,
+        }
+    }
+
+    fn advance_right(&mut self) -> Result<(), Error> {
+        self.peeked = None;
+        while let Some(row) = self.right.next()? {
+            if let Some(key) = join_key(&self.right_keys, &row)? {
+                self.peeked = Some((key, row));
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes `group` hold the right rows whose key is `key`. Left keys only
+    /// grow, so the group is kept while it is not behind `key`.
+    fn seek(&mut self, key: &[u8]) -> Result<(), Error> {
+        if !self.started {
+            self.started = true;
+            self.advance_right()?;
+        }
+        if self
+            .group_key
+            .as_deref()
+            .is_some_and(|group_key| group_key >= key)
+        {
+            return Ok(());
+        }
+        self.group.clear();
+        self.reservation.free();
+        while let Some((peeked, _)) = &self.peeked {
+            match peeked.as_slice().cmp(key) {
+                Ordering::Less => self.advance_right()?,
+                Ordering::Equal => {
+                    let (_, row) = self.peeked.take().unwrap();
+                    self.reservation.grow(tuple_size(&row))?;
+                    self.group.push(row);
+                    self.advance_right()?;
+                }
+                Ordering::Greater => break,
+            }
+        }
+        self.group_key = Some(key.to_vec());
+        Ok(())
+    }
+}
+
+impl Executor for MergeJoin<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        loop {
+            let Some((outer, next, matched)) = &mut self.outer else {
+                let Some(row) = self.left.next()? else {
+                    return Ok(None);
+                };
+                let next = match join_key(&self.left_keys, &row)? {
+                    Some(key) => {
+                        self.seek(&key)?;
+                        (self.group_key.as_deref() == Some(key.as_slice())).then_some(0)
+                    }
+                    None => None,
+                };
+                self.outer = Some((row, next, false));
+                continue;
+            };
+            if let Some(next) = next {
+                while *next < self.group.len() {
+                    let mut row = outer.clone();
+                    row.extend(self.group[*next].iter().cloned());
+                    *next += 1;
+                    let passes = match &self.predicate {
+                        Some(predicate) => predicate.eval_predicate(&row)?,
+                        None => true,
+                    };
+                    if passes {
+                        *matched = true;
+                        return Ok(Some(row));
+                    }
+                }
+            }
+            let (mut row, _, matched) = self.outer.take().unwrap();
+            if self.kind == JoinKind::Left && !matched {
+                row.extend(std::iter::repeat_n(Value::Null, self.right_width));
+                return Ok(Some(row));
+            }
+        }
+    }
+}
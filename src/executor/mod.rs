@@ -0,0 +1,1662 @@
+//! Volcano-style query execution.
+//!
+//! A [`Plan`] describes a tree of operators; [`Plan::start`] instantiates
+//! it into executors that produce tuples one at a time, or, when driven
+//! through [`Executor::next_batch`], columnar [`Batch`]es of many rows.
+//! Data-modifying statements are separate plans in [`dml`] that run to
+//! completion and report the number of affected rows.
+
+mod aggregate;
+mod batch;
+mod cancel;
+mod changes;
+mod cte;
+mod cursor;
+pub mod dml;
+mod filter;
+mod hash_join;
+mod instrument;
+mod join;
+mod limit;
+mod lock_rows;
+mod memory;
+mod merge_join;
+mod parallel;
+mod pool;
+mod progress;
+mod project;
+mod scan;
+mod sort;
+mod spill;
+mod trigger;
+mod unnest;
+mod values;
+mod window;
+
+use std::io;
+use std::ops::Bound;
+use std::thread;
+use std::time::Duration;
+
+use crate::btree;
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{self, Catalog, SystemTable, TableInfo};
+use crate::expr::{self, Expr};
+use crate::heap::{self, Rid};
+use crate::lock::{self, LockMode, LockWait, RowLocker};
+use crate::trace;
+use crate::tuple;
+use crate::value::{DataType, Tuple, Value};
+
+pub use aggregate::{AggregateExpr, AggregateFunction};
+pub use batch::Batch;
+pub use cancel::{CancellationToken, Interrupt};
+pub use changes::{Change, ChangeLog, RowLog, TableLog};
+use cte::WorkTables;
+pub use cursor::Cursor;
+pub use dml::{ConflictAction, Delete, Insert, OnConflict, Update};
+pub use instrument::{Instrumentation, OperatorStats};
+pub use join::JoinKind;
+pub use memory::{MemoryContext, MemoryReservation};
+pub use pool::{PoolStats, WorkerPool};
+pub use progress::{Progress, QueryProgress, Running};
+pub use scan::TableIter;
+pub use sort::{NullOrdering, SortKey};
+pub use trigger::{
+    PlannedTrigger, TriggerFunction, TriggerPlan, TriggerRow, Triggers, MAX_TRIGGER_DEPTH,
+};
+pub use window::{Frame, WindowExpr, WindowFunction};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("table {0:?} does not exist")]
+    TableNotFound(String),
+    #[error("index {0:?} does not exist")]
+    IndexNotFound(String),
+    #[error("expected {expected} values, got {actual}")]
+    ColumnCountMismatch { expected: usize, actual: usize },
+    #[error("column {column:?} is of type {expected} but expression is of type {actual}")]
+    TypeMismatch {
+        column: String,
+        expected: DataType,
+        actual: DataType,
+    },
+    #[error("null value in column {0:?} violates not-null constraint")]
+    NotNullViolation(String),
+    #[error("duplicate key value violates unique index {0:?}")]
+    UniqueViolation(String),
+    #[error("no partition of {0:?} admits the row")]
+    NoPartition(String),
+    #[error("new row violates the partition bound of {0:?}")]
+    PartitionConstraint(String),
+    #[error("row violates the row security policies of {0:?}")]
+    PolicyViolation(String),
+    #[error("function {func} cannot be applied to {data_type}")]
+    AggregateType {
+        func: AggregateFunction,
+        data_type: DataType,
+    },
+    #[error("query exceeded its memory limit of {limit} bytes")]
+    OutOfBudget { limit: usize },
+    #[error("canceling statement due to user request")]
+    Cancelled,
+    #[error("canceling statement due to statement timeout of {0:?}")]
+    StatementTimeout(Duration),
+    #[error("trigger {trigger:?} failed: {message}")]
+    TriggerFailed { trigger: String, message: String },
+    #[error("triggers nest more than {0} levels deep")]
+    TriggerDepth(usize),
+    #[error("in trigger {trigger:?}: {source}")]
+    Trigger { trigger: String, source: Box<Error> },
+    #[error(transparent)]
+    Expr(#[from] expr::Error),
+    #[error(transparent)]
+    Heap(#[from] heap::Error),
+    #[error(transparent)]
+    BTree(#[from] btree::Error),
+    #[error(transparent)]
+    Tuple(#[from] tuple::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Lock(#[from] lock::Error),
+    #[error(transparent)]
+    Catalog(catalog::Error),
+}
+
+/// Index maintenance fails like the storage or expression beneath it.
+impl From<catalog::Error> for Error {
+    fn from(e: catalog::Error) -> Self {
+        match e {
+            catalog::Error::BTree(e) => Error::BTree(e),
+            catalog::Error::Heap(e) => Error::Heap(e),
+            catalog::Error::Expr(e) => Error::Expr(e),
+            e => Error::Catalog(e),
+        }
+    }
+}
+
+/// Everything an executor needs to reach storage.
+#[derive(Clone, Copy)]
+pub struct ExecContext<'a> {
+    pub bufmgr: &'a BufferPoolManager,
+    pub catalog: &'a Catalog,
+    /// Upper bound on the threads a single parallel operator may use.
+    pub max_parallel_workers: usize,
+    /// The threads parallel operators share with those of other queries.
+    pub pool: &'a WorkerPool,
+    pub memory: &'a MemoryContext,
+    /// Where operators record their stats, for EXPLAIN ANALYZE.
+    pub instrumentation: Option<&'a Instrumentation>,
+    /// Checked between operators to stop the query early.
+    pub interrupt: Option<&'a Interrupt>,
+    /// Where scans and data-modifying statements record how far they are.
+    pub progress: Option<&'a QueryProgress>,
+    /// Where data-modifying statements record the rows they change.
+    pub changes: Option<&'a ChangeLog>,
+    /// Where data-modifying statements record the rows they store and
+    /// remove, in the tables it watches.
+    pub rows: Option<&'a RowLog>,
+    /// Where statements record the tables they read and change.
+    pub tables: Option<&'a TableLog>,
+    /// What system tables hold; without it, scanning one fails.
+    pub system_tables: Option<&'a dyn SystemTables>,
+    /// The planned triggers; without them, changes fire none.
+    pub triggers: Option<&'a Triggers>,
+    /// How many triggers deep the running statement is.
+    pub trigger_depth: usize,
+    /// Who the statement runs as; rows it writes must be ones the
+    /// policies of their table give the user.
+    pub user: Option<&'a str>,
+    /// How the statement locks the rows it locks; without it, rows are
+    /// not locked.
+    pub locker: Option<&'a RowLocker>,
+}
+
+static UNLIMITED_MEMORY: MemoryContext = MemoryContext::unlimited();
+
+/// The pool of contexts given none, which only `max_parallel_workers`
+/// bounds.
+static UNBOUNDED_POOL: WorkerPool = WorkerPool::new(usize::MAX);
+
+impl<'a> ExecContext<'a> {
+    pub fn new(bufmgr: &'a BufferPoolManager, catalog: &'a Catalog) -> Self {
+        let max_parallel_workers = thread::available_parallelism().map_or(1, usize::from);
+        Self {
+            bufmgr,
+            catalog,
+            max_parallel_workers,
+            pool: &UNBOUNDED_POOL,
+            memory: &UNLIMITED_MEMORY,
+            instrumentation: None,
+            interrupt: None,
+            progress: None,
+            changes: None,
+            rows: None,
+            tables: None,
+            system_tables: None,
+            triggers: None,
+            trigger_depth: 0,
+            user: None,
+            locker: None,
+        }
+    }
+
+    pub fn with_memory(self, memory: &'a MemoryContext) -> Self {
+        Self { memory, ..self }
+    }
+
+    pub fn with_pool(self, pool: &'a WorkerPool) -> Self {
+        Self { pool, ..self }
+    }
+
+    pub fn with_instrumentation(self, instrumentation: &'a Instrumentation) -> Self {
+        Self {
+            instrumentation: Some(instrumentation),
+            ..self
+        }
+    }
+
+    pub fn with_system_tables(self, system_tables: &'a dyn SystemTables) -> Self {
+        Self {
+            system_tables: Some(system_tables),
+            ..self
+        }
+    }
+
+    pub fn with_interrupt(self, interrupt: &'a Interrupt) -> Self {
+        Self {
+            interrupt: Some(interrupt),
+            ..self
+        }
+    }
+
+    pub fn with_progress(self, progress: &'a QueryProgress) -> Self {
+        Self {
+            progress: Some(progress),
+            ..self
+        }
+    }
+
+    pub fn with_changes(self, changes: &'a ChangeLog) -> Self {
+        Self {
+            changes: Some(changes),
+            ..self
+        }
+    }
+
+    pub fn with_rows(self, rows: &'a RowLog) -> Self {
+        Self {
+            rows: Some(rows),
+            ..self
+        }
+    }
+
+    pub fn with_tables(self, tables: &'a TableLog) -> Self {
+        Self {
+            tables: Some(tables),
+            ..self
+        }
+    }
+
+    pub fn with_triggers(self, triggers: &'a Triggers) -> Self {
+        Self {
+            triggers: Some(triggers),
+            ..self
+        }
+    }
+
+    pub fn with_user(self, user: &'a str) -> Self {
+        Self {
+            user: Some(user),
+            ..self
+        }
+    }
+
+    pub fn with_locker(self, locker: &'a RowLocker) -> Self {
+        Self {
+            locker: Some(locker),
+            ..self
+        }
+    }
+
+    /// Locks row `rid` of `table` in `mode`, if the statement locks rows;
+    /// false if SKIP LOCKED leaves it out.
+    pub(crate) fn lock_row(
+        &self,
+        table: &str,
+        rid: Rid,
+        mode: LockMode,
+        wait: LockWait,
+    ) -> Result<bool, Error> {
+        match self.locker {
+            Some(locker) => Ok(locker.lock(table, rid, mode, wait)?),
+            None => Ok(true),
+        }
+    }
+
+    /// Records the change `change` builds if changes are being captured;
+    /// it is only called then, to spare copying rows otherwise.
+    pub(crate) fn record_change(&self, change: impl FnOnce() -> Change) {
+        if let Some(changes) = self.changes {
+            changes.record(change());
+        }
+    }
+
+    /// Records `row`, stored in or removed from `table` at `rid`, if the
+    /// rows of `table` are being watched.
+    pub(crate) fn record_row(&self, table: &str, rid: Rid, row: impl FnOnce() -> Tuple) {
+        if let Some(rows) = self.rows.filter(|rows| rows.watches(table)) {
+            rows.record(table, rid, row());
+        }
+    }
+
+    /// Counts `rows` returned or changed toward the statement's progress.
+    pub(crate) fn count_rows(&self, rows: u64) {
+        if let Some(progress) = self.progress {
+            progress.add_rows(rows);
+        }
+    }
+
+    /// Records that the statement changes `table`, which counts as
+    /// changing the partitions it has or the table it is one of as well.
+    pub(crate) fn record_write(&self, table: &str) {
+        let Some(tables) = self.tables else {
+            return;
+        };
+        tables.record_write(table);
+        for partition in self.catalog.partitions(table) {
+            tables.record_write(&partition.name);
+        }
+        let parent = self
+            .catalog
+            .table(table)
+            .and_then(|t| t.partition_of.as_ref());
+        if let Some(partition) = parent {
+            tables.record_write(&partition.parent);
+        }
+    }
+
+    pub fn with_max_parallel_workers(self, max_parallel_workers: usize) -> Self {
+        Self {
+            max_parallel_workers: max_parallel_workers.max(1),
+            ..self
+        }
+    }
+
+    /// Fails if the query has been cancelled or has timed out.
+    pub fn check_interrupt(&self) -> Result<(), Error> {
+        self.interrupt.map_or(Ok(()), Interrupt::check)
+    }
+
+    /// The context to read and write the pages of `table` in, which are
+    /// those of a temporary file or an attached database for their tables.
+    pub fn for_table(&self, table: &str) -> Self {
+        match self.catalog.table_bufmgr(table) {
+            Some(bufmgr) => Self { bufmgr, ..*self },
+            None => *self,
+        }
+    }
+
+    /// The table `name`, recorded as read if tables are being recorded.
+    pub fn table(&self, name: &str) -> Result<&'a TableInfo, Error> {
+        if let Some(tables) = self.tables {
+            tables.record_read(name);
+        }
+        self.catalog
+            .table(name)
+            .ok_or_else(|| Error::TableNotFound(name.to_string()))
+    }
+}
+
+/// Rows per batch when a caller has no better idea.
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
+pub trait Executor {
+    fn next(&mut self) -> Result<Option<Tuple>, Error>;
+
+    /// Produces up to `max_rows` rows at once, or `None` when exhausted.
+    ///
+    /// An executor driven through this method must only be driven through
+    /// it, and pulls from its own inputs the same way. The default gathers
+    /// rows from [`Executor::next`]; operators with vectorized kernels
+    /// override it.
+    fn next_batch(&mut self, max_rows: usize) -> Result<Option<Batch>, Error> {
+        let mut rows = vec![];
+        while rows.len() < max_rows {
+            match self.next()? {
+                Some(row) => rows.push(row),
+                None => break,
+            }
+        }
+        let Some(width) = rows.first().map(Vec::len) else {
+            return Ok(None);
+        };
+        Ok(Some(Batch::from_rows(rows, width)))
+    }
+}
+
+pub type BoxExecutor<'a> = Box<dyn Executor + 'a>;
+
+/// Produces the rows of [`SystemTable`]s, in the order of their schema's
+/// columns, for whoever owns the state they show.
+pub trait SystemTables: Sync {
+    fn rows(&self, table: SystemTable, ctx: &ExecContext<'_>) -> Result<Vec<Tuple>, Error>;
+}
+
+/// Range of index keys to visit. Bounds may name a prefix of the index
+/// columns, in which case they cover every key starting with that prefix.
+/// Key values are constant expressions, evaluated when the scan opens, so
+/// that prepared plans can look up parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    pub start: Bound<Vec<Expr>>,
+    pub end: Bound<Vec<Expr>>,
+}
+
+impl KeyRange {
+    pub fn full() -> Self {
+        Self {
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+        }
+    }
+
+    /// Keys equal to `key` (or starting with it, for a prefix).
+    pub fn eq(key: Vec<Value>) -> Self {
+        let key: Vec<_> = key.into_iter().map(Expr::Literal).collect();
+        Self {
+            start: Bound::Included(key.clone()),
+            end: Bound::Included(key),
+        }
+    }
+
+    pub fn replace_parameters(&self, params: &[Value]) -> Self {
+        let bound = |bound: &Bound<Vec<Expr>>| bound.as_ref().map(|key| replace_all(key, params));
+        Self {
+            start: bound(&self.start),
+            end: bound(&self.end),
+        }
+    }
+}
+
+fn replace_all(exprs: &[Expr], params: &[Value]) -> Vec<Expr> {
+    exprs
+        .iter()
+        .map(|expr| expr.replace_parameters(params))
+        .collect()
+}
+
+fn replace_optional(expr: &Option<Expr>, params: &[Value]) -> Option<Expr> {
+    expr.as_ref().map(|expr| expr.replace_parameters(params))
+}
+
+/// How a statement reaches the rows of its target table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccessPath {
+    SeqScan,
+    IndexScan { index: String, range: KeyRange },
+}
+
+impl AccessPath {
+    pub fn replace_parameters(&self, params: &[Value]) -> Self {
+        match self {
+            AccessPath::SeqScan => AccessPath::SeqScan,
+            AccessPath::IndexScan { index, range } => AccessPath::IndexScan {
+                index: index.clone(),
+                range: range.replace_parameters(params),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Plan {
+    /// Rows of expressions evaluated against an empty tuple.
+    Values {
+        rows: Vec<Vec<Expr>>,
+    },
+    SeqScan {
+        table: String,
+    },
+    /// The rows of a system table, from [`ExecContext::system_tables`].
+    SystemScan {
+        table: SystemTable,
+    },
+    /// Sequential scan split across up to `max_parallel_workers` threads,
+    /// each evaluating `predicate` on its share of the pages. Rows come out
+    /// in the same order as [`Plan::SeqScan`].
+    ParallelSeqScan {
+        table: String,
+        predicate: Option<Expr>,
+    },
+    IndexScan {
+        table: String,
+        index: String,
+        range: KeyRange,
+    },
+    /// The rows of `table` along `access`, each followed by its rid, for
+    /// a [`Plan::LockRows`] above; see [`Plan::lock_rows`].
+    RidScan {
+        table: String,
+        access: AccessPath,
+    },
+    /// Locks the row of `table` that each input row stands for, whose rid
+    /// its last column holds, and outputs it without that column. Rows
+    /// that SKIP LOCKED leaves out are not output.
+    LockRows {
+        input: Box<Plan>,
+        table: String,
+        mode: LockMode,
+        wait: LockWait,
+    },
+    Filter {
+        input: Box<Plan>,
+        predicate: Expr,
+    },
+    Project {
+        input: Box<Plan>,
+        exprs: Vec<Expr>,
+    },
+    Aggregate {
+        input: Box<Plan>,
+        group_by: Vec<Expr>,
+        aggregates: Vec<AggregateExpr>,
+    },
+    Sort {
+        input: Box<Plan>,
+        keys: Vec<SortKey>,
+    },
+    /// Each input row followed by one element of `array`, once per
+    /// element.
+    Unnest {
+        input: Box<Plan>,
+        array: Expr,
+    },
+    /// Expects `input` sorted by `partition_by` and then `order_by`; see
+    /// [`Plan::window`].
+    Window {
+        input: Box<Plan>,
+        partition_by: Vec<Expr>,
+        order_by: Vec<SortKey>,
+        functions: Vec<WindowExpr>,
+    },
+    /// Output rows are a left row followed by a right row; `right_width`
+    /// is the number of NULLs padding unmatched rows of a left join.
+    NestedLoopJoin {
+        left: Box<Plan>,
+        right: Box<Plan>,
+        kind: JoinKind,
+        predicate: Option<Expr>,
+        right_width: usize,
+    },
+    /// Joins rows whose `left_keys` equal their `right_keys`, building a
+    /// hash table over the right input; `predicate` is checked on the
+    /// joined row. NULL keys match nothing.
+    HashJoin {
+        left: Box<Plan>,
+        right: Box<Plan>,
+        kind: JoinKind,
+        left_keys: Vec<Expr>,
+        right_keys: Vec<Expr>,
+        predicate: Option<Expr>,
+        right_width: usize,
+    },
+    /// Like [`Plan::HashJoin`], but expects both inputs sorted ascending
+    /// on their keys and outputs rows in left order.
+    MergeJoin {
+        left: Box<Plan>,
+        right: Box<Plan>,
+        kind: JoinKind,
+        left_keys: Vec<Expr>,
+        right_keys: Vec<Expr>,
+        predicate: Option<Expr>,
+        right_width: usize,
+    },
+    Limit {
+        input: Box<Plan>,
+        limit: Option<usize>,
+        offset: usize,
+    },
+    /// The rows of each input in turn.
+    Union {
+        inputs: Vec<Plan>,
+    },
+    /// The `width` columns of the rows of the enclosing
+    /// [`Plan::Materialize`] or [`Plan::RecursiveUnion`] `id`.
+    WorkTable {
+        id: usize,
+        width: usize,
+    },
+    /// Runs `cte` to completion when first pulled, then produces the rows
+    /// of `input`, in which [`Plan::WorkTable`] `id` reads those of `cte`.
+    Materialize {
+        id: usize,
+        cte: Box<Plan>,
+        input: Box<Plan>,
+    },
+    /// The rows of `anchor`, then those of `recursive` run again and again
+    /// with [`Plan::WorkTable`] `id` holding the rows added by the previous
+    /// round, until a round adds none. With `distinct`, rows seen before
+    /// are not added again.
+    RecursiveUnion {
+        id: usize,
+        anchor: Box<Plan>,
+        recursive: Box<Plan>,
+        distinct: bool,
+    },
+}
+
+impl Plan {
+    pub fn start<'a>(&'a self, ctx: &ExecContext<'a>) -> Result<BoxExecutor<'a>, Error> {
+        self.start_with(ctx, &WorkTables::default())
+    }
+
+    fn start_with<'a>(
+        &'a self,
+        ctx: &ExecContext<'a>,
+        tables: &WorkTables,
+    ) -> Result<BoxExecutor<'a>, Error> {
+        let mut executor = self.start_operator(ctx, tables)?;
+        if let Some(interrupt) = ctx.interrupt {
+            executor = interrupt.wrap(executor);
+        }
+        if trace::enabled() {
+            executor = instrument::traced(self.name(), executor);
+        }
+        Ok(match ctx.instrumentation {
+            Some(instrumentation) => instrumentation.wrap(self, executor),
+            None => executor,
+        })
+    }
+
+    /// The kind of operator, as trace events name it.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Plan::Values { .. } => "Values",
+            Plan::SeqScan { .. } => "SeqScan",
+            Plan::SystemScan { .. } => "SystemScan",
+            Plan::ParallelSeqScan { .. } => "ParallelSeqScan",
+            Plan::IndexScan { .. } => "IndexScan",
+            Plan::RidScan { .. } => "RidScan",
+            Plan::LockRows { .. } => "LockRows",
+            Plan::Filter { .. } => "Filter",
+            Plan::Project { .. } => "Project",
+            Plan::Aggregate { .. } => "Aggregate",
+            Plan::Sort { .. } => "Sort",
+            Plan::Window { .. } => "Window",
+            Plan::Unnest { .. } => "Unnest",
+            Plan::NestedLoopJoin { .. } => "NestedLoopJoin",
+            Plan::HashJoin { .. } => "HashJoin",
+            Plan::MergeJoin { .. } => "MergeJoin",
+            Plan::Limit { .. } => "Limit",
+            Plan::Union { .. } => "Union",
+            Plan::WorkTable { .. } => "WorkTable",
+            Plan::Materialize { .. } => "Materialize",
+            Plan::RecursiveUnion { .. } => "RecursiveUnion",
+        }
+    }
+
+    fn start_operator<'a>(
+        &'a self,
+        ctx: &ExecContext<'a>,
+        tables: &WorkTables,
+    ) -> Result<BoxExecutor<'a>, Error> {
+        Ok(match self {
+            Plan::Values { rows } => Box::new(values::Values {
+                rows: rows.clone().into_iter(),
+            }),
+            Plan::SeqScan { table } => Box::new(scan::Scan {
+                iter: TableIter::open(ctx, table, &AccessPath::SeqScan)?,
+            }),
+            Plan::SystemScan { table } => {
+                let system_tables = ctx
+                    .system_tables
+                    .ok_or_else(|| Error::TableNotFound(table.to_string()))?;
+                Box::new(values::Rows {
+                    rows: system_tables.rows(*table, ctx)?.into_iter(),
+                })
+            }
+            Plan::ParallelSeqScan { table, predicate } => {
+                Box::new(parallel::ParallelScan::open(ctx, table, predicate.clone())?)
+            }
+            Plan::IndexScan {
+                table,
+                index,
+                range,
+            } => {
+                let access = AccessPath::IndexScan {
+                    index: index.clone(),
+                    range: range.clone(),
+                };
+                Box::new(scan::Scan {
+                    iter: TableIter::open(ctx, table, &access)?,
+                })
+            }
+            Plan::RidScan { table, access } => Box::new(lock_rows::RidScan {
+                iter: TableIter::open(ctx, table, access)?,
+            }),
+            Plan::LockRows {
+                input,
+                table,
+                mode,
+                wait,
+            } => Box::new(lock_rows::LockRows {
+                ctx: *ctx,
+                input: input.start_with(ctx, tables)?,
+                table,
+                mode: *mode,
+                wait: *wait,
+            }),
+            Plan::Filter { input, predicate } => Box::new(filter::Filter {
+                input: input.start_with(ctx, tables)?,
+                predicate: predicate.clone(),
+            }),
+            Plan::Project { input, exprs } => Box::new(project::Project {
+                input: input.start_with(ctx, tables)?,
+                exprs: exprs.clone(),
+            }),
+            Plan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+            } => Box::new(aggregate::Aggregate::new(
+                ctx,
+                input.start_with(ctx, tables)?,
+                group_by.clone(),
+                aggregates.clone(),
+            )),
+            Plan::Sort { input, keys } => Box::new(sort::Sort::new(
+                ctx,
+                input.start_with(ctx, tables)?,
+                keys.clone(),
+            )),
+            Plan::Window {
+                input,
+                partition_by,
+                order_by,
+                functions,
+            } => Box::new(window::Window::new(
+                ctx,
+                input.start_with(ctx, tables)?,
+                partition_by.clone(),
+                order_by.clone(),
+                functions.clone(),
+            )),
+            Plan::Unnest { input, array } => Box::new(unnest::Unnest {
+                input: input.start_with(ctx, tables)?,
+                array: array.clone(),
+                current: None,
+            }),
+            Plan::NestedLoopJoin {
+                left,
+                right,
+                kind,
+                predicate,
+                right_width,
+            } => Box::new(join::NestedLoopJoin::new(
+                ctx,
+                left.start_with(ctx, tables)?,
+                right.start_with(ctx, tables)?,
+                *kind,
+                predicate.clone(),
+                *right_width,
+            )),
+            Plan::HashJoin {
+                left,
+                right,
+                kind,
+                left_keys,
+                right_keys,
+                predicate,
+                right_width,
+            } => Box::new(hash_join::HashJoin::new(
+                ctx,
+                left.start_with(ctx, tables)?,
+                right.start_with(ctx, tables)?,
+                *kind,
+                left_keys.clone(),
+                right_keys.clone(),
+                predicate.clone(),
+                *right_width,
+            )),
+            Plan::MergeJoin {
+                left,
+                right,
+                kind,
+                left_keys,
+                right_keys,
+                predicate,
+                right_width,
+            } => Box::new(merge_join::MergeJoin::new(
+                ctx,
+                left.start_with(ctx, tables)?,
+                right.start_with(ctx, tables)?,
+                *kind,
+                left_keys.clone(),
+                right_keys.clone(),
+                predicate.clone(),
+                *right_width,
+            )),
+            Plan::Limit {
+                input,
+                limit,
+                offset,
+            } => Box::new(limit::Limit {
+                input: input.start_with(ctx, tables)?,
+                remaining: *limit,
+                offset: *offset,
+            }),
+            Plan::Union { inputs } => {
+                let mut inputs = inputs
+                    .iter()
+                    .map(|input| input.start_with(ctx, tables))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter();
+                Box::new(cte::Union {
+                    current: inputs.next(),
+                    inputs,
+                })
+            }
+            Plan::WorkTable { id, .. } => Box::new(cte::WorkTableScan {
+                rows: tables.get(*id),
+                next: 0,
+            }),
+            Plan::Materialize { id, cte, input } => {
+                Box::new(cte::Materialize::new(ctx, tables, *id, cte, input))
+            }
+            Plan::RecursiveUnion {
+                id,
+                anchor,
+                recursive,
+                distinct,
+            } => Box::new(cte::RecursiveUnion::new(
+                ctx,
+                tables,
+                *id,
+                anchor.start_with(ctx, tables)?,
+                recursive,
+                *distinct,
+            )),
+        })
+    }
+
+    /// A [`Plan::Values`] of literal rows.
+    pub fn values(rows: Vec<Tuple>) -> Plan {
+        let rows = rows
+            .into_iter()
+            .map(|row| row.into_iter().map(Expr::literal).collect())
+            .collect();
+        Plan::Values { rows }
+    }
+
+    /// Substitutes `params` for the parameters throughout the plan; see
+    /// [`Expr::replace_parameters`].
+    pub fn replace_parameters(&self, params: &[Value]) -> Plan {
+        let input = |input: &Plan| Box::new(input.replace_parameters(params));
+        let sort_keys = |keys: &[SortKey]| {
+            keys.iter()
+                .map(|key| SortKey {
+                    expr: key.expr.replace_parameters(params),
+                    descending: key.descending,
+                    nulls_first: key.nulls_first,
+                })
+                .collect()
+        };
+        match self {
+            Plan::Values { rows } => Plan::Values {
+                rows: rows.iter().map(|row| replace_all(row, params)).collect(),
+            },
+            Plan::SeqScan { .. } | Plan::SystemScan { .. } => self.clone(),
+            Plan::ParallelSeqScan { table, predicate } => Plan::ParallelSeqScan {
+                table: table.clone(),
+                predicate: replace_optional(predicate, params),
+            },
+            Plan::IndexScan {
+                table,
+                index,
+                range,
+            } => Plan::IndexScan {
+                table: table.clone(),
+                index: index.clone(),
+                range: range.replace_parameters(params),
+            },
+            Plan::RidScan { table, access } => Plan::RidScan {
+                table: table.clone(),
+                access: access.replace_parameters(params),
+            },
+            Plan::LockRows {
+                input: from,
+                table,
+                mode,
+                wait,
+            } => Plan::LockRows {
+                input: input(from),
+                table: table.clone(),
+                mode: *mode,
+                wait: *wait,
+            },
+            Plan::Filter {
+                input: from,
+                predicate,
+            } => Plan::Filter {
+                input: input(from),
+                predicate: predicate.replace_parameters(params),
+            },
+            Plan::Project { input: from, exprs } => Plan::Project {
+                input: input(from),
+                exprs: replace_all(exprs, params),
+            },
+            Plan::Aggregate {
+                input: from,
+                group_by,
+                aggregates,
+            } => Plan::Aggregate {
+                input: input(from),
+                group_by: replace_all(group_by, params),
+                aggregates: aggregates
+                    .iter()
+                    .map(|aggregate| AggregateExpr {
+                        func: aggregate.func.clone(),
+                        arg: replace_optional(&aggregate.arg, params),
+                    })
+                    .collect(),
+            },
+            Plan::Sort { input: from, keys } => Plan::Sort {
+                input: input(from),
+                keys: sort_keys(keys),
+            },
+            Plan::Window {
+                input: from,
+                partition_by,
+                order_by,
+                functions,
+            } => Plan::Window {
+                input: input(from),
+                partition_by: replace_all(partition_by, params),
+                order_by: sort_keys(order_by),
+                functions: functions
+                    .iter()
+                    .map(|function| WindowExpr {
+                        arg: replace_optional(&function.arg, params),
+                        ..function.clone()
+                    })
+                    .collect(),
+            },
+            Plan::Unnest { input: from, array } => Plan::Unnest {
+                input: input(from),
+                array: array.replace_parameters(params),
+            },
+            Plan::NestedLoopJoin {
+                left,
+                right,
+                kind,
+                predicate,
+                right_width,
+            } => Plan::NestedLoopJoin {
+                left: input(left),
+                right: input(right),
+                kind: *kind,
+                predicate: replace_optional(predicate, params),
+                right_width: *right_width,
+            },
+            Plan::HashJoin {
+                left,
+                right,
+                kind,
+                left_keys,
+                right_keys,
+                predicate,
+                right_width,
+            } => Plan::HashJoin {
+                left: input(left),
+                right: input(right),
+                kind: *kind,
+                left_keys: replace_all(left_keys, params),
+                right_keys: replace_all(right_keys, params),
+                predicate: replace_optional(predicate, params),
+                right_width: *right_width,
+            },
+            Plan::MergeJoin {
+                left,
+                right,
+                kind,
+                left_keys,
+                right_keys,
+                predicate,
+                right_width,
+            } => Plan::MergeJoin {
+                left: input(left),
+                right: input(right),
+                kind: *kind,
+                left_keys: replace_all(left_keys, params),
+                right_keys: replace_all(right_keys, params),
+                predicate: replace_optional(predicate, params),
+                right_width: *right_width,
+            },
+            Plan::Limit {
+                input: from,
+                limit,
+                offset,
+            } => Plan::Limit {
+                input: input(from),
+                limit: *limit,
+                offset: *offset,
+            },
+            Plan::Union { inputs } => Plan::Union {
+                inputs: inputs
+                    .iter()
+                    .map(|from| from.replace_parameters(params))
+                    .collect(),
+            },
+            Plan::WorkTable { .. } => self.clone(),
+            Plan::Materialize {
+                id,
+                cte,
+                input: from,
+            } => Plan::Materialize {
+                id: *id,
+                cte: input(cte),
+                input: input(from),
+            },
+            Plan::RecursiveUnion {
+                id,
+                anchor,
+                recursive,
+                distinct,
+            } => Plan::RecursiveUnion {
+                id: *id,
+                anchor: input(anchor),
+                recursive: input(recursive),
+                distinct: *distinct,
+            },
+        }
+    }
+
+    /// The plans whose rows this one consumes.
+    pub fn inputs(&self) -> Vec<&Plan> {
+        match self {
+            Plan::Values { .. }
+            | Plan::SeqScan { .. }
+            | Plan::SystemScan { .. }
+            | Plan::ParallelSeqScan { .. }
+            | Plan::IndexScan { .. }
+            | Plan::RidScan { .. }
+            | Plan::WorkTable { .. } => vec![],
+            Plan::LockRows { input, .. }
+            | Plan::Filter { input, .. }
+            | Plan::Project { input, .. }
+            | Plan::Aggregate { input, .. }
+            | Plan::Sort { input, .. }
+            | Plan::Window { input, .. }
+            | Plan::Unnest { input, .. }
+            | Plan::Limit { input, .. } => vec![input],
+            Plan::NestedLoopJoin { left, right, .. }
+            | Plan::HashJoin { left, right, .. }
+            | Plan::MergeJoin { left, right, .. }
+            | Plan::Materialize {
+                cte: left,
+                input: right,
+                ..
+            }
+            | Plan::RecursiveUnion {
+                anchor: left,
+                recursive: right,
+                ..
+            } => vec![left, right],
+            Plan::Union { inputs } => inputs.iter().collect(),
+        }
+    }
+
+    /// Whether executing the plan reads from `table`.
+    pub fn reads_table(&self, table: &str) -> bool {
+        match self {
+            Plan::SeqScan { table: name }
+            | Plan::ParallelSeqScan { table: name, .. }
+            | Plan::IndexScan { table: name, .. }
+            | Plan::RidScan { table: name, .. } => name == table,
+            plan => plan.inputs().iter().any(|input| input.reads_table(table)),
+        }
+    }
+
+    /// Whether executing the plan reads a system table, whose rows the
+    /// engine makes up rather than reads from the file.
+    pub fn reads_system_table(&self) -> bool {
+        match self {
+            Plan::SystemScan { .. } => true,
+            plan => plan.inputs().iter().any(|input| input.reads_system_table()),
+        }
+    }
+
+    /// Evaluates window `functions` over `input`, sorting it first so that
+    /// each partition arrives contiguously and in window order.
+    pub fn window(
+        input: Plan,
+        partition_by: Vec<Expr>,
+        order_by: Vec<SortKey>,
+        functions: Vec<WindowExpr>,
+    ) -> Plan {
+        let keys = partition_by
+            .iter()
+            .cloned()
+            .map(SortKey::asc)
+            .chain(order_by.iter().cloned())
+            .collect();
+        Plan::Window {
+            input: Box::new(Plan::Sort {
+                input: Box::new(input),
+                keys,
+            }),
+            partition_by,
+            order_by,
+            functions,
+        }
+    }
+
+    /// Runs the plan to completion and collects its output.
+    pub fn collect(&self, ctx: &ExecContext<'_>) -> Result<Vec<Tuple>, Error> {
+        self.cursor(ctx)?.collect()
+    }
+
+    /// Starts the plan and returns a cursor for reading its output on demand.
+    pub fn cursor<'a>(&'a self, ctx: &ExecContext<'a>) -> Result<Cursor<'a>, Error> {
+        Ok(Cursor::new(self.start(ctx)?))
+    }
+
+    /// Like [`Plan::collect`], but moves rows between operators in batches
+    /// of up to `batch_size` rows.
+    pub fn collect_batched(
+        &self,
+        ctx: &ExecContext<'_>,
+        batch_size: usize,
+    ) -> Result<Vec<Tuple>, Error> {
+        let mut executor = self.start(ctx)?;
+        let mut tuples = vec![];
+        while let Some(batch) = executor.next_batch(batch_size)? {
+            tuples.extend(batch.into_rows());
+        }
+        Ok(tuples)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{Column, Schema};
+    use crate::disk::DiskManager;
+    use crate::expr::BinaryOp;
+    use tempfile::tempfile;
+
+    fn setup() -> (BufferPoolManager, Catalog) {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 32);
+        let mut catalog = Catalog::new();
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Int),
+            Column::new("grp", DataType::Text),
+            Column::new("amount", DataType::Int),
+        ]);
+        catalog.create_table(&bufmgr, "t", schema).unwrap();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        for i in 0..500i64 {
+            let amount = if i % 7 == 0 {
+                Value::Null
+            } else {
+                Value::Int(i)
+            };
+            Insert {
+                table: "t".into(),
+                source: Plan::values(vec![vec![i.into(), format!("g{}", i % 3).into(), amount]]),
+                on_conflict: None,
+            }
+            .execute(&ctx)
+            .unwrap();
+        }
+        (bufmgr, catalog)
+    }
+
+    fn scan() -> Box<Plan> {
+        Box::new(Plan::SeqScan { table: "t".into() })
+    }
+
+    #[test]
+    fn test_batched_matches_tuple_at_a_time() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let filter = Plan::Filter {
+            input: scan(),
+            predicate: Expr::binary(
+                BinaryOp::Or,
+                Expr::binary(BinaryOp::Lt, Expr::column(0), Expr::literal(50i64)),
+                Expr::binary(BinaryOp::Eq, Expr::column(1), Expr::literal("g1")),
+            ),
+        };
+        let project = Plan::Project {
+            input: Box::new(filter.clone()),
+            exprs: vec![
+                Expr::binary(BinaryOp::Mul, Expr::column(2), Expr::literal(2i64)),
+                Expr::column(1),
+            ],
+        };
+        let aggregates = vec![
+            AggregateExpr::count_star(),
+            AggregateExpr::new(AggregateFunction::Count, Expr::column(2)),
+            AggregateExpr::new(AggregateFunction::Sum, Expr::column(2)),
+            AggregateExpr::new(AggregateFunction::Min, Expr::column(2)),
+            AggregateExpr::new(AggregateFunction::Max, Expr::column(0)),
+            AggregateExpr::new(AggregateFunction::Avg, Expr::column(2)),
+        ];
+        let grouped = Plan::Aggregate {
+            input: Box::new(filter.clone()),
+            group_by: vec![Expr::column(1)],
+            aggregates: aggregates.clone(),
+        };
+        let ungrouped = Plan::Aggregate {
+            input: scan(),
+            group_by: vec![],
+            aggregates,
+        };
+        for plan in [filter, project, grouped, ungrouped] {
+            let expected = plan.collect(&ctx).unwrap();
+            assert!(!expected.is_empty());
+            for batch_size in [1, 7, DEFAULT_BATCH_SIZE] {
+                assert_eq!(expected, plan.collect_batched(&ctx, batch_size).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_aggregate_results() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let empty = Plan::Filter {
+            input: scan(),
+            predicate: Expr::literal(false),
+        };
+        let plan = Plan::Aggregate {
+            input: Box::new(empty.clone()),
+            group_by: vec![],
+            aggregates: vec![
+                AggregateExpr::count_star(),
+                AggregateExpr::new(AggregateFunction::Sum, Expr::column(2)),
+            ],
+        };
+        assert_eq!(
+            vec![vec![Value::Int(0), Value::Null]],
+            plan.collect(&ctx).unwrap()
+        );
+        let plan = Plan::Aggregate {
+            input: Box::new(empty),
+            group_by: vec![Expr::column(1)],
+            aggregates: vec![AggregateExpr::count_star()],
+        };
+        assert!(plan.collect_batched(&ctx, 16).unwrap().is_empty());
+
+        let plan = Plan::Aggregate {
+            input: scan(),
+            group_by: vec![Expr::column(1)],
+            aggregates: vec![AggregateExpr::count_star()],
+        };
+        let counts = plan.collect_batched(&ctx, 64).unwrap();
+        assert_eq!(
+            vec![
+                vec!["g0".into(), Value::Int(167)],
+                vec!["g1".into(), Value::Int(167)],
+                vec!["g2".into(), Value::Int(166)],
+            ],
+            counts
+        );
+        let plan = Plan::Aggregate {
+            input: scan(),
+            group_by: vec![],
+            aggregates: vec![AggregateExpr::new(AggregateFunction::Sum, Expr::column(1))],
+        };
+        assert!(matches!(
+            plan.collect_batched(&ctx, 64),
+            Err(Error::AggregateType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_parallel_scan_matches_seq_scan() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        for i in 500..5000i64 {
+            let values = vec![i.into(), format!("g{}", i % 3).into(), i.into()];
+            Insert {
+                table: "t".into(),
+                source: Plan::values(vec![values]),
+                on_conflict: None,
+            }
+            .execute(&ctx)
+            .unwrap();
+        }
+        let predicate = Expr::binary(BinaryOp::Eq, Expr::column(1), Expr::literal("g2"));
+        for (predicate, expected) in [
+            (None, scan().collect(&ctx).unwrap()),
+            (
+                Some(predicate.clone()),
+                Plan::Filter {
+                    input: scan(),
+                    predicate,
+                }
+                .collect(&ctx)
+                .unwrap(),
+            ),
+        ] {
+            let plan = Plan::ParallelSeqScan {
+                table: "t".into(),
+                predicate,
+            };
+            for workers in [1, 3, 8] {
+                let ctx = ctx.with_max_parallel_workers(workers);
+                assert_eq!(expected, plan.collect(&ctx).unwrap());
+                assert_eq!(expected, plan.collect_batched(&ctx, 100).unwrap());
+            }
+        }
+
+        let plan = Plan::ParallelSeqScan {
+            table: "t".into(),
+            predicate: Some(Expr::binary(
+                BinaryOp::Add,
+                Expr::column(1),
+                Expr::literal(1i64),
+            )),
+        };
+        let ctx = ctx.with_max_parallel_workers(4);
+        assert!(matches!(plan.collect(&ctx), Err(Error::Expr(_))));
+    }
+
+    #[test]
+    fn test_aggregate_spills_past_work_mem() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let plan = Plan::Aggregate {
+            input: scan(),
+            group_by: vec![
+                Expr::binary(BinaryOp::Mod, Expr::column(0), Expr::literal(200i64)),
+                Expr::column(1),
+            ],
+            aggregates: vec![
+                AggregateExpr::count_star(),
+                AggregateExpr::new(AggregateFunction::Sum, Expr::column(2)),
+            ],
+        };
+        // Spilled groups are emitted after the in-memory ones.
+        let sorted = |mut rows: Vec<Tuple>| {
+            rows.sort_by_cached_key(|row| {
+                let mut key = vec![];
+                tuple::encode_key(row, &mut key);
+                key
+            });
+            rows
+        };
+        let expected = sorted(plan.collect(&ctx).unwrap());
+
+        let memory = MemoryContext::new(4096, None);
+        let ctx = ctx.with_memory(&memory);
+        for batch_size in [None, Some(64)] {
+            let rows = match batch_size {
+                None => plan.collect(&ctx).unwrap(),
+                Some(n) => plan.collect_batched(&ctx, n).unwrap(),
+            };
+            assert_eq!(expected, sorted(rows));
+        }
+        assert!(memory.spilled_bytes() > 0);
+        assert_eq!(0, memory.used());
+
+        let memory = MemoryContext::new(usize::MAX, Some(4096));
+        let ctx = ctx.with_memory(&memory);
+        assert!(matches!(
+            plan.collect(&ctx),
+            Err(Error::OutOfBudget { limit: 4096 })
+        ));
+        assert_eq!(0, memory.used());
+    }
+
+    #[test]
+    fn test_hash_join_spills_past_work_mem() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let modulo =
+            |column| Expr::binary(BinaryOp::Mod, Expr::column(column), Expr::literal(90i64));
+        let sorted = |mut rows: Vec<Tuple>| {
+            rows.sort_by_cached_key(|row| {
+                let mut key = vec![];
+                tuple::encode_key(row, &mut key);
+                key
+            });
+            rows
+        };
+        for kind in [JoinKind::Inner, JoinKind::Left] {
+            // Amounts of NULL match nothing.
+            let plan = Plan::HashJoin {
+                left: scan(),
+                right: scan(),
+                kind,
+                left_keys: vec![modulo(2)],
+                right_keys: vec![modulo(0)],
+                predicate: None,
+                right_width: 3,
+            };
+            let expected = sorted(plan.collect(&ctx).unwrap());
+            assert!(expected.len() > 500);
+
+            let memory = MemoryContext::new(4096, None);
+            let ctx = ctx.with_memory(&memory);
+            assert_eq!(expected, sorted(plan.collect(&ctx).unwrap()));
+            assert!(memory.spilled_bytes() > 0);
+            assert_eq!(0, memory.used());
+        }
+    }
+
+    #[test]
+    fn test_sort_spills_and_is_stable() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let by_grp = Plan::Sort {
+            input: scan(),
+            keys: vec![SortKey::desc(Expr::column(1))],
+        };
+        let by_amount = Plan::Sort {
+            input: scan(),
+            keys: vec![
+                SortKey::asc(Expr::column(2)),
+                SortKey::desc(Expr::column(0)),
+            ],
+        };
+        let expected_by_amount = by_amount.collect(&ctx).unwrap();
+        assert!(expected_by_amount[..72].iter().all(|row| row[2].is_null()));
+        assert_eq!(Value::Int(497), expected_by_amount[0][0]);
+
+        let memory = MemoryContext::new(4096, None);
+        let ctx = ctx.with_memory(&memory);
+        let rows = by_grp.collect(&ctx).unwrap();
+        assert!(memory.spilled_bytes() > 0);
+        assert_eq!(500, rows.len());
+        for pair in rows.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            assert!(a[1].total_cmp(&b[1]).is_ge());
+            if a[1] == b[1] {
+                assert!(a[0].total_cmp(&b[0]).is_lt());
+            }
+        }
+        assert_eq!(expected_by_amount, by_amount.collect(&ctx).unwrap());
+        assert_eq!(0, memory.used());
+    }
+
+    #[test]
+    fn test_parallel_sort_matches_serial_sort() {
+        let (bufmgr, catalog) = setup();
+        let rows = (0..20_000i64)
+            .map(|i| vec![Value::Int(i * 7919 % 13), Value::Int(i)])
+            .collect();
+        let plan = Plan::Sort {
+            input: Box::new(Plan::values(rows)),
+            keys: vec![SortKey::desc(Expr::column(0))],
+        };
+        let ctx = ExecContext::new(&bufmgr, &catalog).with_max_parallel_workers(1);
+        let expected = plan.collect(&ctx).unwrap();
+        assert!(expected[..2]
+            .windows(2)
+            .all(|pair| pair[0][1].total_cmp(&pair[1][1]).is_lt()));
+
+        let pool = WorkerPool::new(2);
+        let ctx = ctx.with_pool(&pool).with_max_parallel_workers(4);
+        assert_eq!(expected, plan.collect(&ctx).unwrap());
+        assert_eq!(4, pool.stats().jobs);
+        assert_eq!(1, pool.stats().saturated);
+    }
+
+    #[test]
+    fn test_join_algorithms_agree() {
+        let (bufmgr, catalog) = setup();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let left = Plan::Sort {
+            input: Box::new(Plan::Filter {
+                input: scan(),
+                predicate: Expr::binary(BinaryOp::Lt, Expr::column(0), Expr::literal(30i64)),
+            }),
+            keys: vec![SortKey::asc(Expr::column(0))],
+        };
+        let right = Plan::Sort {
+            input: Box::new(Plan::values(vec![
+                vec![15.into(), "w".into()],
+                vec![14.into(), "x".into()],
+                vec![Value::Null, "z".into()],
+                vec![14.into(), "y".into()],
+                vec![1000.into(), "v".into()],
+            ])),
+            keys: vec![SortKey::asc(Expr::column(0))],
+        };
+        let keys_match = Expr::binary(BinaryOp::Eq, Expr::column(0), Expr::column(3));
+        let not_y = Expr::binary(BinaryOp::NotEq, Expr::column(4), Expr::literal("y"));
+        for kind in [JoinKind::Inner, JoinKind::Left] {
+            for residual in [None, Some(not_y.clone())] {
+                let predicate = match &residual {
+                    Some(residual) => {
+                        Expr::binary(BinaryOp::And, keys_match.clone(), residual.clone())
+                    }
+                    None => keys_match.clone(),
+                };
+                let expected = Plan::NestedLoopJoin {
+                    left: Box::new(left.clone()),
+                    right: Box::new(right.clone()),
+                    kind,
+                    predicate: Some(predicate),
+                    right_width: 2,
+                }
+                .collect(&ctx)
+                .unwrap();
+                let matched = expected.iter().filter(|row| !row[3].is_null()).count();
+                assert_eq!(if residual.is_some() { 2 } else { 3 }, matched);
+                if kind == JoinKind::Left {
+                    assert_eq!(28 + matched, expected.len());
+                }
+                let hash = Plan::HashJoin {
+                    left: Box::new(left.clone()),
+                    right: Box::new(right.clone()),
+                    kind,
+                    left_keys: vec![Expr::column(0)],
+                    right_keys: vec![Expr::column(0)],
+                    predicate: residual.clone(),
+                    right_width: 2,
+                };
+                let merge = Plan::MergeJoin {
+                    left: Box::new(left.clone()),
+                    right: Box::new(right.clone()),
+                    kind,
+                    left_keys: vec![Expr::column(0)],
+                    right_keys: vec![Expr::column(0)],
+                    predicate: residual,
+                    right_width: 2,
+                };
+                assert_eq!(expected, hash.collect(&ctx).unwrap());
+                assert_eq!(expected, merge.collect(&ctx).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_window_functions() {
+        let (bufmgr, mut catalog) = setup();
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Int),
+            Column::new("dept", DataType::Text),
+            Column::new("salary", DataType::Int),
+        ]);
+        catalog.create_table(&bufmgr, "w", schema).unwrap();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let rows = [
+            (1, "a", Some(20)),
+            (2, "b", None),
+            (3, "a", Some(10)),
+            (4, "a", Some(30)),
+            (5, "b", Some(5)),
+            (6, "a", Some(20)),
+        ];
+        for (id, dept, salary) in rows {
+            let salary = salary.map_or(Value::Null, |s: i64| s.into());
+            Insert {
+                table: "w".into(),
+                source: Plan::values(vec![vec![Value::Int(id), dept.into(), salary]]),
+                on_conflict: None,
+            }
+            .execute(&ctx)
+            .unwrap();
+        }
+        let plan = Plan::window(
+            Plan::SeqScan { table: "w".into() },
+            vec![Expr::column(1)],
+            vec![SortKey::asc(Expr::column(2))],
+            vec![
+                WindowExpr::row_number(),
+                WindowExpr::rank(),
+                WindowExpr::aggregate(
+                    AggregateFunction::Sum,
+                    Some(Expr::column(2)),
+                    Frame::ToCurrentPeers,
+                ),
+                WindowExpr::aggregate(AggregateFunction::Count, None, Frame::Partition),
+                WindowExpr::aggregate(
+                    AggregateFunction::Sum,
+                    Some(Expr::column(2)),
+                    Frame::Rows {
+                        preceding: Some(1),
+                        following: Some(0),
+                    },
+                ),
+            ],
+        );
+        let result: Vec<Vec<Value>> = plan
+            .collect(&ctx)
+            .unwrap()
+            .into_iter()
+            .map(|row| {
+                let mut out = vec![row[0].clone()];
+                out.extend_from_slice(&row[3..]);
+                out
+            })
+            .collect();
+        let int = |values: [Option<i64>; 6]| -> Vec<Value> {
+            values
+                .into_iter()
+                .map(|v| v.map_or(Value::Null, Value::Int))
+                .collect()
+        };
+        assert_eq!(
+            vec![
+                int([Some(3), Some(1), Some(1), Some(10), Some(4), Some(10)]),
+                int([Some(1), Some(2), Some(2), Some(50), Some(4), Some(30)]),
+                int([Some(6), Some(3), Some(2), Some(50), Some(4), Some(40)]),
+                int([Some(4), Some(4), Some(4), Some(80), Some(4), Some(50)]),
+                int([Some(2), Some(1), Some(1), None, Some(2), None]),
+                int([Some(5), Some(2), Some(2), Some(5), Some(2), Some(5)]),
+            ],
+            result
+        );
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let (bufmgr, catalog) = setup();
+        let token = CancellationToken::new();
+        let interrupt = Interrupt::new().with_token(token.clone());
+        let ctx = ExecContext::new(&bufmgr, &catalog).with_interrupt(&interrupt);
+        let join = Plan::NestedLoopJoin {
+            left: scan(),
+            right: scan(),
+            kind: JoinKind::Inner,
+            predicate: None,
+            right_width: 3,
+        };
+        let mut cursor = join.cursor(&ctx).unwrap();
+        assert!(cursor.next().unwrap().is_ok());
+        token.cancel();
+        assert!(matches!(
+            cursor.collect::<Result<Vec<_>, _>>(),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            join.collect_batched(&ctx, 64),
+            Err(Error::Cancelled)
+        ));
+        // Nothing is deleted once the statement is cancelled.
+        let delete = Delete {
+            table: "t".into(),
+            access: AccessPath::SeqScan,
+            predicate: None,
+            limit: None,
+        };
+        assert!(matches!(delete.execute(&ctx), Err(Error::Cancelled)));
+        token.reset();
+        assert_eq!(500, scan().collect(&ctx).unwrap().len());
+
+        let interrupt = Interrupt::new().with_timeout(Duration::ZERO);
+        let ctx = ctx.with_interrupt(&interrupt);
+        assert!(matches!(
+            join.collect(&ctx),
+            Err(Error::StatementTimeout(timeout)) if timeout == Duration::ZERO
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+use std::slice;
+
+use super::{Error, ExecContext, Executor};
+use crate::buffer::BufferPoolManager;
+use crate::disk::PageId;
+use crate::expr::Expr;
+use crate::heap::HeapFile;
+use crate::value::Tuple;
+
+/// Pages handed to each worker per round. Rounds keep the amount of
+/// buffered output proportional to the worker count, not the table size.
+const PAGES_PER_WORKER: usize = 8;
+
+fn scan_pages(
+    bufmgr: &BufferPoolManager,
+    heap: HeapFile,
+    predicate: Option<&Expr>,
+    page_ids: &[PageId],
+) -> Result<Vec<Tuple>, Error> {
+    let mut tuples = vec![];
+    for &page_id in page_ids {
+        for (_, tuple) in heap.page_tuples(bufmgr, page_id)? {
+            if let Some(predicate) = predicate {
+                if !predicate.eval_predicate(&tuple)? {
+                    continue;
+                }
+            }
+            tuples.push(tuple);
+        }
+    }
+    Ok(tuples)
+}
+
+pub struct ParallelScan<'a> {
+    ctx: ExecContext<'a>,
+    heap: HeapFile,
+    predicate: Option<Expr>,
+    /// Snapshot of the page chain taken when the scan starts.
+    page_ids: Vec<PageId>,
+    next_page: usize,
+    output: std::vec::IntoIter<Tuple>,
+}
+
+impl<'a> ParallelScan<'a> {
+    pub fn open(
+        ctx: &ExecContext<'a>,
+        table: &str,
+        predicate: Option<Expr>,
+    ) -> Result<Self, Error> {
+        let ctx = &ctx.for_table(table);
+        let heap = ctx.table(table)?.heap;
+        let page_ids = heap.page_ids(ctx.bufmgr)?;
+        if let Some(progress) = ctx.progress {
+            progress.add_pages_total(page_ids.len() as u64);
+        }
+        Ok(Self {
+            ctx: *ctx,
+            heap,
+            predicate,
+            page_ids,
+            next_page: 0,
+            output: vec![].into_iter(),
+        })
+    }
+
+    /// Scans the next round of pages on the worker pool, a job per page so
+    /// that workers done with theirs can take over those of slower ones.
+    /// The pool returns the pages' tuples in page order.
+    fn scan_round(&mut self) -> Result<Vec<Tuple>, Error> {
+        let workers = self.ctx.max_parallel_workers.max(1);
+        let end = self
+            .page_ids
+            .len()
+            .min(self.next_page + workers * PAGES_PER_WORKER);
+        let round = &self.page_ids[self.next_page..end];
+        self.next_page = end;
+        if let Some(progress) = self.ctx.progress {
+            progress.add_pages_scanned(round.len() as u64);
+        }
+        let (bufmgr, heap, predicate) = (self.ctx.bufmgr, self.heap, self.predicate.as_ref());
+        if workers == 1 || round.len() == 1 {
+            return scan_pages(bufmgr, heap, predicate, round);
+        }
+        let jobs: Vec<_> = round
+            .iter()
+            .map(|page_id| move || scan_pages(bufmgr, heap, predicate, slice::from_ref(page_id)))
+            .collect();
+        let results = self.ctx.pool.run(workers, self.ctx.interrupt, jobs)?;
+        let mut tuples = vec![];
+        for result in results {
+            tuples.extend(result?);
+        }
+        Ok(tuples)
+    }
+}
+
+impl Executor for ParallelScan<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(tuple) = self.output.next() {
+                return Ok(Some(tuple));
+            }
+            if self.next_page == self.page_ids.len() {
+                return Ok(None);
+            }
+            self.output = self.scan_round()?.into_iter();
+        }
+    }
+}
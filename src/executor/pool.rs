@@ -0,0 +1,235 @@
+//! Worker threads shared by the parallel operators of every query.
+//!
+//! An operator hands the pool a list of jobs, and the pool runs them on
+//! the calling thread and on as many helper threads as both the query's
+//! limit, [`ExecContext::max_parallel_workers`](super::ExecContext), and
+//! the pool's room allow. So however many queries run at once, no more
+//! than [`WorkerPool::size`] helpers run between them; a query that finds
+//! the pool full runs its jobs on its own thread.
+//!
+//! The jobs are dealt out to the threads up front, and a thread that runs
+//! out of its own steals from the others, the jobs dealt first, so that a
+//! few slow jobs do not leave the others idle. Helpers are scoped threads
+//! started for each call, which lets jobs borrow from the query. Once the
+//! query is cancelled or times out, the threads take no more jobs: those
+//! left are dropped, and the call returns the interrupt's error after
+//! the jobs already running are done.
+
+use std::collections::VecDeque;
+use std::panic;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use super::{Error, Interrupt};
+
+/// What a [`WorkerPool`] has done since it was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    pub jobs: u64,
+    /// Jobs a thread took from another's share.
+    pub steals: u64,
+    /// Calls that wanted helpers and found the pool full.
+    pub saturated: u64,
+}
+
+#[derive(Debug)]
+pub struct WorkerPool {
+    size: usize,
+    /// Helper threads running.
+    busy: AtomicUsize,
+    jobs: AtomicU64,
+    steals: AtomicU64,
+    saturated: AtomicU64,
+}
+
+/// Gives its helpers back to the pool, however the call ends.
+struct Reservation<'a> {
+    pool: &'a WorkerPool,
+    helpers: usize,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.pool.busy.fetch_sub(self.helpers, Ordering::SeqCst);
+    }
+}
+
+impl WorkerPool {
+    /// A pool that runs at most `size` helper threads at once.
+    pub const fn new(size: usize) -> Self {
+        Self {
+            size,
+            busy: AtomicUsize::new(0),
+            jobs: AtomicU64::new(0),
+            steals: AtomicU64::new(0),
+            saturated: AtomicU64::new(0),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            jobs: self.jobs.load(Ordering::Relaxed),
+            steals: self.steals.load(Ordering::Relaxed),
+            saturated: self.saturated.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Takes up to `wanted` helpers from those free.
+    fn reserve(&self, wanted: usize) -> Reservation<'_> {
+        let mut busy = self.busy.load(Ordering::SeqCst);
+        loop {
+            let helpers = wanted.min(self.size.saturating_sub(busy));
+            match self.busy.compare_exchange(
+                busy,
+                busy + helpers,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => {
+                    if helpers < wanted {
+                        self.saturated.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Reservation {
+                        pool: self,
+                        helpers,
+                    };
+                }
+                Err(actual) => busy = actual,
+            }
+        }
+    }
+
+    /// Runs `jobs` on at most `limit` threads, counting the caller's, and
+    /// returns their results in the order of the jobs.
+    pub fn run<T, F>(
+        &self,
+        limit: usize,
+        interrupt: Option<&Interrupt>,
+        jobs: Vec<F>,
+    ) -> Result<Vec<T>, Error>
+    where
+        F: FnOnce() -> T + Send,
+        T: Send,
+    {
+        let count = jobs.len();
+        let wanted = limit.max(1).min(count).saturating_sub(1);
+        let reservation = self.reserve(wanted);
+        let threads = reservation.helpers + 1;
+        let mut queues: Vec<VecDeque<(usize, F)>> = (0..threads).map(|_| VecDeque::new()).collect();
+        for (i, job) in jobs.into_iter().enumerate() {
+            queues[i * threads / count.max(1)].push_back((i, job));
+        }
+        let queues: Vec<_> = queues.into_iter().map(Mutex::new).collect();
+        let stopped = AtomicBool::new(false);
+        let work = |me: usize| -> Result<Vec<(usize, T)>, Error> {
+            let mut done = vec![];
+            while !stopped.load(Ordering::SeqCst) {
+                if let Err(e) = interrupt.map_or(Ok(()), Interrupt::check) {
+                    stopped.store(true, Ordering::SeqCst);
+                    return Err(e);
+                }
+                let Some((i, job)) = self.next_job(&queues, me) else {
+                    break;
+                };
+                done.push((i, job()));
+            }
+            Ok(done)
+        };
+        let outcomes: Vec<_> = thread::scope(|scope| {
+            let helpers: Vec<_> = (1..threads)
+                .map(|me| scope.spawn(move || work(me)))
+                .collect();
+            let mut outcomes = vec![work(0)];
+            for helper in helpers {
+                let outcome = helper
+                    .join()
+                    .unwrap_or_else(|err| panic::resume_unwind(err));
+                outcomes.push(outcome);
+            }
+            outcomes
+        });
+        drop(reservation);
+        let mut results: Vec<Option<T>> = (0..count).map(|_| None).collect();
+        for outcome in outcomes {
+            for (i, result) in outcome? {
+                results[i] = Some(result);
+            }
+        }
+        Ok(results.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// The next job for thread `me`: the last of its own, or else the
+    /// first of the next thread's that has any left.
+    fn next_job<F>(&self, queues: &[Mutex<VecDeque<(usize, F)>>], me: usize) -> Option<(usize, F)> {
+        if let Some(job) = queues[me].lock().unwrap().pop_back() {
+            self.jobs.fetch_add(1, Ordering::Relaxed);
+            return Some(job);
+        }
+        for other in (1..queues.len()).map(|offset| (me + offset) % queues.len()) {
+            if let Some(job) = queues[other].lock().unwrap().pop_front() {
+                self.jobs.fetch_add(1, Ordering::Relaxed);
+                self.steals.fetch_add(1, Ordering::Relaxed);
+                return Some(job);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::CancellationToken;
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_and_steal() {
+        let pool = WorkerPool::new(2);
+        let jobs: Vec<_> = (0..8u64)
+            .map(|i| {
+                move || {
+                    // The first share is slow, for the others to steal from.
+                    if i < 2 {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    i * i
+                }
+            })
+            .collect();
+        let squares = pool.run(8, None, jobs).unwrap();
+        assert_eq!((0..8).map(|i| i * i).collect::<Vec<_>>(), squares);
+        let stats = pool.stats();
+        assert_eq!(8, stats.jobs);
+        assert!(stats.steals > 0, "{stats:?}");
+        assert_eq!(1, stats.saturated);
+        assert_eq!(0, pool.busy.load(Ordering::SeqCst));
+
+        // A limit of one runs everything on the caller.
+        let caller = thread::current().id();
+        let jobs: Vec<_> = (0..3).map(|_| || thread::current().id()).collect();
+        assert_eq!(vec![caller; 3], pool.run(1, None, jobs).unwrap());
+
+        let token = CancellationToken::new();
+        let interrupt = Interrupt::new().with_token(token.clone());
+        let ran = AtomicUsize::new(0);
+        let jobs: Vec<_> = (0..100)
+            .map(|_| {
+                || {
+                    ran.fetch_add(1, Ordering::SeqCst);
+                    token.cancel();
+                }
+            })
+            .collect();
+        assert!(matches!(
+            pool.run(3, Some(&interrupt), jobs),
+            Err(Error::Cancelled)
+        ));
+        assert!(ran.load(Ordering::SeqCst) <= 3);
+        assert_eq!(0, pool.busy.load(Ordering::SeqCst));
+    }
+}
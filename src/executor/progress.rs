@@ -0,0 +1,119 @@
+//! How far the running statement has got.
+//!
+//! A plan started through an [`ExecContext`](super::ExecContext) that
+//! carries a [`QueryProgress`] counts the heap pages its sequential scans
+//! read against those of the tables they scan, the rows it returns or
+//! changes, and, through its [`MemoryContext`](super::MemoryContext), the
+//! bytes it spills. Another thread holding a clone reads them as a
+//! [`Progress`] while it runs. Pages are counted as scans open, so the
+//! total grows as a plan starts scanning more tables, and index scans,
+//! whose pages cannot be told in advance, count no pages.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Where the running statement records its progress. Clones share it.
+#[derive(Debug, Clone, Default)]
+pub struct QueryProgress {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// The statement running and when it started, if one is.
+    running: Mutex<Option<(String, Instant)>>,
+    pages_total: AtomicU64,
+    pages_scanned: AtomicU64,
+    rows: AtomicU64,
+    spilled_bytes: AtomicU64,
+}
+
+/// What [`QueryProgress::get`] found of the statement running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress {
+    pub sql: String,
+    pub elapsed: Duration,
+    /// Pages of the tables the statement scans sequentially.
+    pub pages_total: u64,
+    pub pages_scanned: u64,
+    /// Rows returned, or inserted, updated or deleted, so far.
+    pub rows: u64,
+    pub spilled_bytes: u64,
+}
+
+impl Progress {
+    /// The share of `pages_total` scanned, from 0 to 100; `None` if the
+    /// statement has scanned no table.
+    pub fn percent(&self) -> Option<f64> {
+        (self.pages_total > 0)
+            .then(|| (self.pages_scanned as f64 / self.pages_total as f64 * 100.0).min(100.0))
+    }
+}
+
+impl QueryProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The progress of the statement running, or `None` between
+    /// statements.
+    pub fn get(&self) -> Option<Progress> {
+        let running = self.inner.running.lock().unwrap();
+        let (sql, started) = running.as_ref()?;
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        Some(Progress {
+            sql: sql.clone(),
+            elapsed: started.elapsed(),
+            pages_total: count(&self.inner.pages_total),
+            pages_scanned: count(&self.inner.pages_scanned),
+            rows: count(&self.inner.rows),
+            spilled_bytes: count(&self.inner.spilled_bytes),
+        })
+    }
+
+    /// Starts counting for `sql` from zero, until the returned guard is
+    /// dropped.
+    pub fn start(&self, sql: &str) -> Running {
+        for counter in [
+            &self.inner.pages_total,
+            &self.inner.pages_scanned,
+            &self.inner.rows,
+            &self.inner.spilled_bytes,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        *self.inner.running.lock().unwrap() = Some((sql.to_string(), Instant::now()));
+        Running {
+            progress: self.clone(),
+        }
+    }
+
+    pub(super) fn add_pages_total(&self, pages: u64) {
+        self.inner.pages_total.fetch_add(pages, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_pages_scanned(&self, pages: u64) {
+        self.inner.pages_scanned.fetch_add(pages, Ordering::Relaxed);
+    }
+
+    pub fn add_rows(&self, rows: u64) {
+        self.inner.rows.fetch_add(rows, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_spilled_bytes(&self, bytes: u64) {
+        self.inner.spilled_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+/// The statement [`QueryProgress::start`] counts for, until dropped.
+#[derive(Debug)]
+pub struct Running {
+    progress: QueryProgress,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        *self.progress.inner.running.lock().unwrap() = None;
+    }
+}
@@ -0,0 +1,34 @@
+use super::{Batch, BoxExecutor, Error, Executor};
+use crate::expr::Expr;
+use crate::value::Tuple;
+
+pub struct Project<'a> {
+    pub input: BoxExecutor<'a>,
+    pub exprs: Vec<Expr>,
+}
+
+impl Executor for Project<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        let Some(tuple) = self.input.next()? else {
+            return Ok(None);
+        };
+        let projected = self
+            .exprs
+            .iter()
+            .map(|expr| expr.eval(&tuple))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(projected))
+    }
+
+    fn next_batch(&mut self, max_rows: usize) -> Result<Option<Batch>, Error> {
+        let Some(batch) = self.input.next_batch(max_rows)? else {
+            return Ok(None);
+        };
+        let columns = self
+            .exprs
+            .iter()
+            .map(|expr| expr.eval_batch(batch.columns(), batch.len()))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Batch::new(columns, batch.len())))
+    }
+}
@@ -0,0 +1,140 @@
+use std::ops::Bound;
+
+use super::{AccessPath, Error, ExecContext, Executor, KeyRange, QueryProgress};
+use crate::btree::{self, SearchMode};
+use crate::buffer::BufferPoolManager;
+use crate::catalog::IndexInfo;
+use crate::expr::Expr;
+use crate::heap::{self, HeapFile, Rid};
+use crate::value::{Tuple, Value};
+
+fn encode(index: &IndexInfo, key: &[Expr]) -> Result<Vec<u8>, Error> {
+    let key = key
+        .iter()
+        .map(|expr| expr.eval(&[]))
+        .collect::<Result<Vec<Value>, _>>()?;
+    Ok(index.encode_values(&key))
+}
+
+enum Source {
+    Heap(heap::Scan),
+    Index {
+        iter: btree::Iter,
+        heap: HeapFile,
+        skip_prefix: Option<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    },
+}
+
+/// Cursor over the rows of one table along an [`AccessPath`], yielding
+/// each tuple together with its rid.
+pub struct TableIter<'a> {
+    bufmgr: &'a BufferPoolManager,
+    source: Source,
+    progress: Option<&'a QueryProgress>,
+    /// Pages of a sequential scan already counted toward `progress`.
+    pages_counted: u64,
+}
+
+impl<'a> TableIter<'a> {
+    pub fn open(ctx: &ExecContext<'a>, table: &str, access: &AccessPath) -> Result<Self, Error> {
+        let ctx = &ctx.for_table(table);
+        let table = ctx.table(table)?;
+        let source = match access {
+            AccessPath::SeqScan => {
+                if let Some(progress) = ctx.progress {
+                    // ANALYZE's count, if there is one, spares reading
+                    // the page chain twice.
+                    let pages = match &table.stats {
+                        Some(stats) => stats.pages,
+                        None => table.heap.page_ids(ctx.bufmgr)?.len(),
+                    };
+                    progress.add_pages_total(pages as u64);
+                }
+                Source::Heap(table.heap.scan(ctx.bufmgr)?)
+            }
+            AccessPath::IndexScan { index, range } => {
+                let index = table
+                    .index(index)
+                    .ok_or_else(|| Error::IndexNotFound(index.clone()))?;
+                let KeyRange { start, end } = range;
+                let (search_mode, skip_prefix) = match start {
+                    Bound::Unbounded => (SearchMode::Start, None),
+                    Bound::Included(key) => (SearchMode::Key(encode(index, key)?), None),
+                    Bound::Excluded(key) => {
+                        let key = encode(index, key)?;
+                        (SearchMode::Key(key.clone()), Some(key))
+                    }
+                };
+                let end = match end {
+                    Bound::Unbounded => Bound::Unbounded,
+                    Bound::Included(key) => Bound::Included(encode(index, key)?),
+                    Bound::Excluded(key) => Bound::Excluded(encode(index, key)?),
+                };
+                Source::Index {
+                    iter: index.btree.search(ctx.bufmgr, search_mode)?,
+                    heap: table.heap,
+                    skip_prefix,
+                    end,
+                }
+            }
+        };
+        Ok(Self {
+            bufmgr: ctx.bufmgr,
+            source,
+            progress: ctx.progress,
+            pages_counted: 0,
+        })
+    }
+
+    pub fn next_row(&mut self) -> Result<Option<(Rid, Tuple)>, Error> {
+        match &mut self.source {
+            Source::Heap(scan) => {
+                let row = scan.next(self.bufmgr)?;
+                if let Some(progress) = self.progress {
+                    progress.add_pages_scanned(scan.pages_read() - self.pages_counted);
+                    self.pages_counted = scan.pages_read();
+                }
+                Ok(row)
+            }
+            Source::Index {
+                iter,
+                heap,
+                skip_prefix,
+                end,
+            } => loop {
+                let Some((key, value)) = iter.next(self.bufmgr)? else {
+                    return Ok(None);
+                };
+                if let Some(prefix) = skip_prefix {
+                    if key.starts_with(prefix) {
+                        continue;
+                    }
+                    *skip_prefix = None;
+                }
+                let in_range = match end {
+                    Bound::Unbounded => true,
+                    Bound::Included(end) => key.as_slice() < end.as_slice() || key.starts_with(end),
+                    Bound::Excluded(end) => key.as_slice() < end.as_slice(),
+                };
+                if !in_range {
+                    return Ok(None);
+                }
+                let rid = Rid::from_bytes(&value);
+                if let Some(tuple) = heap.get(self.bufmgr, rid)? {
+                    return Ok(Some((rid, tuple)));
+                }
+            },
+        }
+    }
+}
+
+pub struct Scan<'a> {
+    pub iter: TableIter<'a>,
+}
+
+impl Executor for Scan<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        Ok(self.iter.next_row()?.map(|(_, tuple)| tuple))
+    }
+}
@@ -0,0 +1,298 @@
+//! ORDER BY, as an external merge sort once the input outgrows work_mem.
+//!
+//! Each key says where its NULLs go. A key that ORDER BY did not say it
+//! for has them where the [`NullOrdering`] in force at planning puts
+//! them.
+//!
+//! Entries in memory, whether all of the input or a run about to spill,
+//! are sorted on the worker pool once there are enough of them: a job
+//! sorts each chunk, and the sorted chunks are merged.
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::mem;
+
+use super::memory::{tuple_size, MemoryContext, MemoryReservation};
+use super::spill::{SpillFile, SpillReader};
+use super::{BoxExecutor, Error, ExecContext, Executor, Interrupt, WorkerPool};
+use crate::expr::Expr;
+use crate::value::{Tuple, Value};
+
+/// Where NULLs sort when ORDER BY or an index key does not say.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NullOrdering {
+    /// Below every other value: first in ascending order, last in
+    /// descending order.
+    #[default]
+    Low,
+    /// Above every other value, as in PostgreSQL: last in ascending
+    /// order, first in descending order.
+    High,
+}
+
+impl NullOrdering {
+    pub fn nulls_first(self, descending: bool) -> bool {
+        (self == NullOrdering::Low) != descending
+    }
+}
+
+impl std::fmt::Display for NullOrdering {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            NullOrdering::Low => "low",
+            NullOrdering::High => "high",
+        })
+    }
+}
+
+impl std::str::FromStr for NullOrdering {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_lowercase().as_str() {
+            "low" => Ok(NullOrdering::Low),
+            "high" => Ok(NullOrdering::High),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortKey {
+    pub expr: Expr,
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl SortKey {
+    /// Ascending with NULLs first.
+    pub fn asc(expr: Expr) -> Self {
+        Self {
+            expr,
+            descending: false,
+            nulls_first: true,
+        }
+    }
+
+    /// Descending with NULLs last.
+    pub fn desc(expr: Expr) -> Self {
+        Self {
+            expr,
+            descending: true,
+            nulls_first: false,
+        }
+    }
+
+    pub fn nulls_first(self, nulls_first: bool) -> Self {
+        Self {
+            nulls_first,
+            ..self
+        }
+    }
+}
+
+pub(super) fn eval_keys(keys: &[SortKey], row: &[Value]) -> Result<Tuple, Error> {
+    keys.iter().map(|key| Ok(key.expr.eval(row)?)).collect()
+}
+
+pub(super) fn compare_keys(keys: &[SortKey], a: &[Value], b: &[Value]) -> Ordering {
+    for ((key, a), b) in keys.iter().zip(a).zip(b) {
+        let ordering = match (a.is_null(), b.is_null()) {
+            (true, true) => Ordering::Equal,
+            (true, false) if key.nulls_first => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (false, true) if key.nulls_first => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) if key.descending => a.total_cmp(b).reverse(),
+            (false, false) => a.total_cmp(b),
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Entries each job of a parallel sort sorts at the least.
+const PARALLEL_SORT_CHUNK: usize = 4096;
+
+/// Jobs a parallel sort splits its entries into per worker, for those
+/// done first to take over the others'.
+const CHUNKS_PER_WORKER: usize = 2;
+
+/// A sorted run on disk with its smallest unread entry.
+struct Run {
+    reader: SpillReader,
+    head: Option<(Tuple, Tuple)>,
+}
+
+impl Run {
+    fn advance(&mut self, width: usize) -> Result<(), Error> {
+        self.head = self.reader.next_row()?.map(|mut keys| {
+            let row = keys.split_off(width);
+            (keys, row)
+        });
+        Ok(())
+    }
+}
+
+enum Output<'a> {
+    Memory {
+        rows: std::vec::IntoIter<Tuple>,
+        _reservation: MemoryReservation<'a>,
+    },
+    Merge(Vec<Run>),
+}
+
+/// Sorts its input; ties keep their input order.
+pub struct Sort<'a> {
+    input: BoxExecutor<'a>,
+    keys: Vec<SortKey>,
+    memory: &'a MemoryContext,
+    pool: &'a WorkerPool,
+    workers: usize,
+    interrupt: Option<&'a Interrupt>,
+    output: Option<Output<'a>>,
+}
+
+impl<'a> Sort<'a> {
+    pub fn new(ctx: &ExecContext<'a>, input: BoxExecutor<'a>, keys: Vec<SortKey>) -> Self {
+        Self {
+            input,
+            keys,
+            memory: ctx.memory,
+            pool: ctx.pool,
+            workers: ctx.max_parallel_workers,
+            interrupt: ctx.interrupt,
+            output: None,
+        }
+    }
+
+    fn sort_entries(&self, entries: &mut Vec<(Tuple, Tuple)>) -> Result<(), Error> {
+        let keys = &self.keys;
+        let chunks = (self.workers * CHUNKS_PER_WORKER).min(entries.len() / PARALLEL_SORT_CHUNK);
+        if self.workers == 1 || chunks <= 1 {
+            entries.sort_by(|(a, _), (b, _)| compare_keys(keys, a, b));
+            return Ok(());
+        }
+        let chunk_size = entries.len().div_ceil(chunks);
+        let jobs: Vec<_> = entries
+            .chunks_mut(chunk_size)
+            .map(|chunk| move || chunk.sort_by(|(a, _), (b, _)| compare_keys(keys, a, b)))
+            .collect();
+        self.pool.run(self.workers, self.interrupt, jobs)?;
+
+        let mut chunks = vec![];
+        while entries.len() > chunk_size {
+            let at = (entries.len() - 1) / chunk_size * chunk_size;
+            chunks.push(VecDeque::from(entries.split_off(at)));
+        }
+        chunks.push(VecDeque::from(mem::take(entries)));
+        chunks.reverse();
+        loop {
+            // Earlier chunks win ties, as earlier runs do.
+            let mut min: Option<usize> = None;
+            for i in 0..chunks.len() {
+                let Some((entry_keys, _)) = chunks[i].front() else {
+                    continue;
+                };
+                let smaller = match min {
+                    None => true,
+                    Some(j) => {
+                        let (min_keys, _) = chunks[j].front().unwrap();
+                        compare_keys(keys, entry_keys, min_keys).is_lt()
+                    }
+                };
+                if smaller {
+                    min = Some(i);
+                }
+            }
+            let Some(i) = min else {
+                return Ok(());
+            };
+            entries.push(chunks[i].pop_front().unwrap());
+        }
+    }
+
+    fn write_run(&self, entries: Vec<(Tuple, Tuple)>) -> Result<SpillFile, Error> {
+        let mut file = SpillFile::new(self.memory)?;
+        for (mut keys, row) in entries {
+            keys.extend(row);
+            file.write(self.memory, &keys)?;
+        }
+        Ok(file)
+    }
+
+    fn consume_input(&mut self) -> Result<Output<'a>, Error> {
+        let mut reservation = self.memory.reservation();
+        let mut entries = vec![];
+        let mut runs = vec![];
+        while let Some(row) = self.input.next()? {
+            let keys = eval_keys(&self.keys, &row)?;
+            reservation.grow(tuple_size(&keys) + tuple_size(&row))?;
+            entries.push((keys, row));
+            if reservation.exceeds_work_mem() {
+                self.sort_entries(&mut entries)?;
+                runs.push(self.write_run(mem::take(&mut entries))?);
+                reservation.free();
+            }
+        }
+        self.sort_entries(&mut entries)?;
+        if runs.is_empty() {
+            let rows: Vec<_> = entries.into_iter().map(|(_, row)| row).collect();
+            return Ok(Output::Memory {
+                rows: rows.into_iter(),
+                _reservation: reservation,
+            });
+        }
+        if !entries.is_empty() {
+            runs.push(self.write_run(entries)?);
+        }
+        let mut merge = vec![];
+        for file in runs {
+            let mut run = Run {
+                reader: file.into_reader()?,
+                head: None,
+            };
+            run.advance(self.keys.len())?;
+            merge.push(run);
+        }
+        Ok(Output::Merge(merge))
+    }
+}
+
+impl Executor for Sort<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        if self.output.is_none() {
+            self.output = Some(self.consume_input()?);
+        }
+        match self.output.as_mut().unwrap() {
+            Output::Memory { rows, .. } => Ok(rows.next()),
+            Output::Merge(runs) => {
+                // Earlier runs win ties, which keeps the sort stable.
+                let mut min: Option<usize> = None;
+                for (i, run) in runs.iter().enumerate() {
+                    let Some((keys, _)) = &run.head else {
+                        continue;
+                    };
+                    let smaller = match min {
+                        None => true,
+                        Some(j) => {
+                            let (min_keys, _) = runs[j].head.as_ref().unwrap();
+                            compare_keys(&self.keys, keys, min_keys).is_lt()
+                        }
+                    };
+                    if smaller {
+                        min = Some(i);
+                    }
+                }
+                let Some(i) = min else {
+                    return Ok(None);
+                };
+                let (_, row) = runs[i].head.take().unwrap();
+                runs[i].advance(self.keys.len())?;
+                Ok(Some(row))
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Anonymous temporary files holding rows that did not fit in work_mem.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use super::memory::MemoryContext;
+use super::Error;
+use crate::tuple;
+use crate::value::{Tuple, Value};
+
+/// Rows are stored as `[len u32][encoded tuple]` and read back in the
+/// order they were written.
+pub struct SpillFile {
+    writer: BufWriter<File>,
+    rows: u64,
+}
+
+impl SpillFile {
+    pub fn new(memory: &MemoryContext) -> Result<Self, Error> {
+        let file = match memory.temp_dir() {
+            Some(dir) => tempfile::tempfile_in(dir)?,
+            None => tempfile::tempfile()?,
+        };
+        Ok(Self {
+            writer: BufWriter::new(file),
+            rows: 0,
+        })
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+
+    pub fn write(&mut self, memory: &MemoryContext, row: &[Value]) -> Result<(), Error> {
+        let mut record = vec![];
+        tuple::encode(row, &mut record);
+        self.writer
+            .write_all(&(record.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&record)?;
+        memory.record_spill(4 + record.len());
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn into_reader(self) -> Result<SpillReader, Error> {
+        let mut file = self.writer.into_inner().map_err(|err| err.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(SpillReader {
+            reader: BufReader::new(file),
+            remaining: self.rows,
+        })
+    }
+}
+
+pub struct SpillReader {
+    reader: BufReader<File>,
+    remaining: u64,
+}
+
+impl SpillReader {
+    pub fn next_row(&mut self) -> Result<Option<Tuple>, Error> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        let mut len = [0u8; 4];
+        self.reader.read_exact(&mut len)?;
+        let mut record = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.reader.read_exact(&mut record)?;
+        Ok(Some(tuple::decode(&record)?))
+    }
+}
@@ -0,0 +1,209 @@
+//! Firing the triggers of a table around the rows a statement changes.
+//!
+//! The engine plans the action of every trigger a statement can reach,
+//! through the actions of other triggers too, into the [`Triggers`] its
+//! [`ExecContext`] carries; a context without them fires nothing. INSERT,
+//! UPDATE and DELETE fire the BEFORE triggers of a row ahead of checking
+//! and storing it, and the AFTER triggers once it is stored. An action
+//! runs one level deeper than the change that fired it, and a change more
+//! than [`MAX_TRIGGER_DEPTH`] levels deep fails the statement.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use super::{Delete, Error, ExecContext, Insert, Update};
+use crate::catalog::{RowImage, TableInfo, TriggerAction, TriggerEvent, TriggerTiming};
+use crate::value::{DataType, Tuple, Value};
+
+pub const MAX_TRIGGER_DEPTH: usize = 16;
+
+/// The change a trigger fires for. A function run BEFORE the change may
+/// replace `new` to change the row that is stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerRow {
+    pub trigger: String,
+    pub table: String,
+    pub timing: TriggerTiming,
+    pub event: TriggerEvent,
+    /// The row before the change; `None` for an insert.
+    pub old: Option<Tuple>,
+    /// The row after the change; `None` for a delete.
+    pub new: Option<Tuple>,
+}
+
+impl TriggerRow {
+    pub(super) fn new(
+        table: &TableInfo,
+        timing: TriggerTiming,
+        event: TriggerEvent,
+        old: Option<Tuple>,
+        new: Option<Tuple>,
+    ) -> Self {
+        Self {
+            trigger: String::new(),
+            table: table.name.clone(),
+            timing,
+            event,
+            old,
+            new,
+        }
+    }
+}
+
+/// A trigger function an embedder registers. It returns whether the
+/// change goes ahead, which only a BEFORE trigger can refuse; an error
+/// fails the statement with its message.
+pub type TriggerFunction = Arc<dyn Fn(&mut TriggerRow) -> Result<bool, String> + Send + Sync>;
+
+/// What a trigger runs, with parameters standing for columns of the row.
+#[derive(Clone)]
+pub enum TriggerPlan {
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    Function(TriggerFunction),
+}
+
+impl fmt::Debug for TriggerPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerPlan::Insert(insert) => f.debug_tuple("Insert").field(insert).finish(),
+            TriggerPlan::Update(update) => f.debug_tuple("Update").field(update).finish(),
+            TriggerPlan::Delete(delete) => f.debug_tuple("Delete").field(delete).finish(),
+            TriggerPlan::Function(_) => f.write_str("Function(..)"),
+        }
+    }
+}
+
+impl TriggerPlan {
+    /// The table the action changes, if it is a statement.
+    pub fn table(&self) -> Option<&str> {
+        match self {
+            TriggerPlan::Insert(insert) => Some(&insert.table),
+            TriggerPlan::Update(update) => Some(&update.table),
+            TriggerPlan::Delete(delete) => Some(&delete.table),
+            TriggerPlan::Function(_) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlannedTrigger {
+    pub plan: TriggerPlan,
+    /// Types the action expects of its parameters, which row values are
+    /// coerced to.
+    pub parameter_types: Vec<Option<DataType>>,
+}
+
+/// The planned triggers, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Triggers {
+    triggers: HashMap<String, PlannedTrigger>,
+}
+
+impl Triggers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, trigger: PlannedTrigger) {
+        self.triggers.insert(name.into(), trigger);
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.triggers.contains_key(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+}
+
+impl ExecContext<'_> {
+    /// Whether changing a row of `table` fires anything at `timing`.
+    pub(super) fn fires(
+        &self,
+        table: &TableInfo,
+        timing: TriggerTiming,
+        event: TriggerEvent,
+    ) -> bool {
+        self.triggers.is_some() && table.triggers.iter().any(|t| t.fires(timing, event))
+    }
+
+    /// Runs the triggers of `table` that fire for `row`, in order of name.
+    /// Returns whether the change goes ahead.
+    pub(super) fn fire(&self, table: &TableInfo, row: &mut TriggerRow) -> Result<bool, Error> {
+        let Some(triggers) = self.triggers else {
+            return Ok(true);
+        };
+        let (timing, event) = (row.timing, row.event);
+        let fired = table
+            .triggers
+            .iter()
+            .filter(|trigger| trigger.fires(timing, event));
+        for trigger in fired {
+            let Some(planned) = triggers.triggers.get(&trigger.name) else {
+                continue;
+            };
+            let (plan, parameters) = match (&planned.plan, &trigger.action) {
+                (TriggerPlan::Function(function), _) => {
+                    row.trigger = trigger.name.clone();
+                    let proceed = function(row).map_err(|message| Error::TriggerFailed {
+                        trigger: trigger.name.clone(),
+                        message,
+                    })?;
+                    if !proceed && timing == TriggerTiming::Before {
+                        return Ok(false);
+                    }
+                    continue;
+                }
+                (plan, TriggerAction::Statement { parameters, .. }) => (plan, parameters),
+                (_, TriggerAction::Function(_)) => continue,
+            };
+            if self.trigger_depth == MAX_TRIGGER_DEPTH {
+                return Err(Error::TriggerDepth(MAX_TRIGGER_DEPTH));
+            }
+            let params: Vec<Value> = parameters
+                .iter()
+                .enumerate()
+                .map(|(i, (image, column))| {
+                    let image = match image {
+                        RowImage::Old => &row.old,
+                        RowImage::New => &row.new,
+                    };
+                    let value = image
+                        .as_ref()
+                        .and_then(|row| row.get(*column))
+                        .cloned()
+                        .unwrap_or(Value::Null);
+                    match planned.parameter_types.get(i).copied().flatten() {
+                        Some(data_type) => value.clone().coerce_to(data_type).unwrap_or(value),
+                        None => value,
+                    }
+                })
+                .collect();
+            // Actions are bound as the schema's, and write as it does.
+            let ctx = ExecContext {
+                trigger_depth: self.trigger_depth + 1,
+                user: None,
+                ..*self
+            };
+            let result = match plan {
+                TriggerPlan::Insert(insert) => insert.replace_parameters(&params).execute(&ctx),
+                TriggerPlan::Update(update) => update.replace_parameters(&params).execute(&ctx),
+                TriggerPlan::Delete(delete) => delete.replace_parameters(&params).execute(&ctx),
+                TriggerPlan::Function(_) => unreachable!("run above"),
+            };
+            result.map_err(|e| match e {
+                // Name the trigger whose action failed, not those around it.
+                e @ (Error::Trigger { .. } | Error::TriggerDepth(_)) => e,
+                e => Error::Trigger {
+                    trigger: trigger.name.clone(),
+                    source: Box::new(e),
+                },
+            })?;
+        }
+        Ok(true)
+    }
+}
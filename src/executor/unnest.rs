@@ -0,0 +1,35 @@
+use std::vec;
+
+use super::{BoxExecutor, Error, Executor};
+use crate::expr::Expr;
+use crate::value::{Tuple, Value};
+
+/// Each input row once per element of `array`, with the element added.
+pub struct Unnest<'a> {
+    pub input: BoxExecutor<'a>,
+    pub array: Expr,
+    /// The current input row and the elements still to pair with it.
+    pub current: Option<(Tuple, vec::IntoIter<Value>)>,
+}
+
+impl Executor for Unnest<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some((row, items)) = &mut self.current {
+                if let Some(item) = items.next() {
+                    let mut row = row.clone();
+                    row.push(item);
+                    return Ok(Some(row));
+                }
+            }
+            let Some(row) = self.input.next()? else {
+                return Ok(None);
+            };
+            // A NULL array, like an empty one, adds no rows.
+            self.current = match self.array.eval(&row)? {
+                Value::Array(array) => Some((row, array.items.into_iter())),
+                _ => None,
+            };
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use super::{Error, Executor};
+use crate::expr::Expr;
+use crate::value::Tuple;
+
+pub struct Values {
+    pub rows: std::vec::IntoIter<Vec<Expr>>,
+}
+
+impl Executor for Values {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        let Some(row) = self.rows.next() else {
+            return Ok(None);
+        };
+        let tuple = row
+            .iter()
+            .map(|expr| expr.eval(&[]))
+            .collect::<Result<_, _>>()?;
+        Ok(Some(tuple))
+    }
+}
+
+/// Rows computed before the scan starts, such as those of a system table.
+pub struct Rows {
+    pub rows: std::vec::IntoIter<Tuple>,
+}
+
+impl Executor for Rows {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        Ok(self.rows.next())
+    }
+}
@@ -0,0 +1,263 @@
+//! Window functions over input sorted by PARTITION BY and then ORDER BY.
+
+use std::fmt;
+
+use super::aggregate::{Accumulator, AggregateFunction};
+use super::memory::{tuple_size, MemoryReservation};
+use super::sort::{compare_keys, eval_keys, SortKey};
+use super::{BoxExecutor, Error, ExecContext, Executor};
+use crate::expr::Expr;
+use crate::tuple;
+use crate::value::{Tuple, Value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowFunction {
+    RowNumber,
+    /// Rank with gaps: peers share the rank of the first of them.
+    Rank,
+    Aggregate(AggregateFunction),
+}
+
+impl fmt::Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowFunction::RowNumber => f.write_str("ROW_NUMBER"),
+            WindowFunction::Rank => f.write_str("RANK"),
+            WindowFunction::Aggregate(func) => func.fmt(f),
+        }
+    }
+}
+
+/// Rows of the partition an aggregate window function sees for each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    /// The whole partition.
+    Partition,
+    /// From the start of the partition through the last peer of the
+    /// current row. The SQL default when the window has an ORDER BY.
+    ToCurrentPeers,
+    /// Physical offsets around the current row; `None` is unbounded.
+    Rows {
+        preceding: Option<usize>,
+        following: Option<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowExpr {
+    pub func: WindowFunction,
+    /// Argument of an aggregate; `None` for ranking functions and COUNT(*).
+    pub arg: Option<Expr>,
+    /// Only meaningful for aggregates.
+    pub frame: Frame,
+}
+
+impl WindowExpr {
+    pub fn row_number() -> Self {
+        Self {
+            func: WindowFunction::RowNumber,
+            arg: None,
+            frame: Frame::Partition,
+        }
+    }
+
+    pub fn rank() -> Self {
+        Self {
+            func: WindowFunction::Rank,
+            arg: None,
+            frame: Frame::Partition,
+        }
+    }
+
+    pub fn aggregate(func: AggregateFunction, arg: Option<Expr>, frame: Frame) -> Self {
+        Self {
+            func: WindowFunction::Aggregate(func),
+            arg,
+            frame,
+        }
+    }
+}
+
+impl fmt::Display for WindowExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.func, &self.arg) {
+            (_, Some(arg)) => write!(f, "{}({arg})", self.func),
+            (WindowFunction::Aggregate(_), None) => write!(f, "{}(*)", self.func),
+            (func, None) => write!(f, "{func}()"),
+        }
+    }
+}
+
+/// Appends one column per window function to every input row. Buffers a
+/// single partition at a time.
+pub struct Window<'a> {
+    input: BoxExecutor<'a>,
+    partition_by: Vec<Expr>,
+    order_by: Vec<SortKey>,
+    functions: Vec<WindowExpr>,
+    /// Holds the partition currently being emitted.
+    reservation: MemoryReservation<'a>,
+    /// First row of the next partition, read while finding the end of the
+    /// current one, with its encoded partition key.
+    lookahead: Option<(Vec<u8>, Tuple)>,
+    output: std::vec::IntoIter<Tuple>,
+}
+
+impl<'a> Window<'a> {
+    pub fn new(
+        ctx: &ExecContext<'a>,
+        input: BoxExecutor<'a>,
+        partition_by: Vec<Expr>,
+        order_by: Vec<SortKey>,
+        functions: Vec<WindowExpr>,
+    ) -> Self {
+        Self {
+            input,
+            partition_by,
+            order_by,
+            functions,
+            reservation: ctx.memory.reservation(),
+            lookahead: None,
+            output: vec![].into_iter(),
+        }
+    }
+
+    fn partition_key(&self, row: &[Value]) -> Result<Vec<u8>, Error> {
+        let values = self
+            .partition_by
+            .iter()
+            .map(|expr| expr.eval(row))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut key = vec![];
+        tuple::encode_key(&values, &mut key);
+        Ok(key)
+    }
+
+    /// Reads the next partition, or returns `None` when the input is done.
+    fn next_partition(&mut self) -> Result<Option<Vec<Tuple>>, Error> {
+        let (key, first) = match self.lookahead.take() {
+            Some(entry) => entry,
+            None => match self.input.next()? {
+                Some(row) => (self.partition_key(&row)?, row),
+                None => return Ok(None),
+            },
+        };
+        self.reservation.free();
+        self.reservation.grow(tuple_size(&first))?;
+        let mut rows = vec![first];
+        while let Some(row) = self.input.next()? {
+            let next_key = self.partition_key(&row)?;
+            if next_key != key {
+                self.lookahead = Some((next_key, row));
+                break;
+            }
+            self.reservation.grow(tuple_size(&row))?;
+            rows.push(row);
+        }
+        Ok(Some(rows))
+    }
+
+    fn compute(&self, rows: &[Tuple]) -> Result<Vec<Vec<Value>>, Error> {
+        let order_keys = rows
+            .iter()
+            .map(|row| eval_keys(&self.order_by, row))
+            .collect::<Result<Vec<_>, _>>()?;
+        // peer_start[i]..peer_end[i] are the rows sorting equal to row i.
+        let mut peer_start = vec![0; rows.len()];
+        for i in 1..rows.len() {
+            peer_start[i] =
+                if compare_keys(&self.order_by, &order_keys[i - 1], &order_keys[i]).is_eq() {
+                    peer_start[i - 1]
+                } else {
+                    i
+                };
+        }
+        let mut peer_end = vec![rows.len(); rows.len()];
+        for i in (0..rows.len().saturating_sub(1)).rev() {
+            if peer_start[i + 1] == peer_start[i] {
+                peer_end[i] = peer_end[i + 1];
+            } else {
+                peer_end[i] = i + 1;
+            }
+        }
+        self.functions
+            .iter()
+            .map(|window| match &window.func {
+                WindowFunction::RowNumber => Ok((1..=rows.len() as i64).map(Value::Int).collect()),
+                WindowFunction::Rank => Ok(peer_start
+                    .iter()
+                    .map(|&start| Value::Int(start as i64 + 1))
+                    .collect()),
+                WindowFunction::Aggregate(func) => {
+                    let args = rows
+                        .iter()
+                        .map(|row| match &window.arg {
+                            Some(arg) => arg.eval(row),
+                            None => Ok(Value::Bool(true)),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    aggregate_frames(func, window.frame, &args, &peer_end)
+                }
+            })
+            .collect()
+    }
+}
+
+fn aggregate_frames(
+    func: &AggregateFunction,
+    frame: Frame,
+    args: &[Value],
+    peer_end: &[usize],
+) -> Result<Vec<Value>, Error> {
+    let fold = |values: &[Value]| -> Result<Value, Error> {
+        let mut acc = Accumulator::new(func);
+        for value in values {
+            acc.update(value)?;
+        }
+        acc.finish()
+    };
+    match frame {
+        Frame::Partition => Ok(vec![fold(args)?; args.len()]),
+        Frame::ToCurrentPeers => {
+            let mut acc = Accumulator::new(func);
+            let mut consumed = 0;
+            let mut results = Vec::with_capacity(args.len());
+            for &end in peer_end {
+                for value in &args[consumed..end] {
+                    acc.update(value)?;
+                }
+                consumed = end;
+                results.push(acc.finish()?);
+            }
+            Ok(results)
+        }
+        Frame::Rows {
+            preceding,
+            following,
+        } => (0..args.len())
+            .map(|i| {
+                let start = preceding.map_or(0, |n| i.saturating_sub(n));
+                let end = following.map_or(args.len(), |n| (i + n + 1).min(args.len()));
+                fold(&args[start..end])
+            })
+            .collect(),
+    }
+}
+
+impl Executor for Window<'_> {
+    fn next(&mut self) -> Result<Option<Tuple>, Error> {
+        loop {
+            if let Some(row) = self.output.next() {
+                return Ok(Some(row));
+            }
+            let Some(mut rows) = self.next_partition()? else {
+                return Ok(None);
+            };
+            let columns = self.compute(&rows)?;
+            for (i, row) in rows.iter_mut().enumerate() {
+                row.extend(columns.iter().map(|column| column[i].clone()));
+            }
+            self.output = rows.into_iter();
+        }
+    }
+}
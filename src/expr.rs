@@ -0,0 +1,1391 @@
+//! Scalar expressions evaluated against a tuple or a batch of columns.
+
+use std::any::Any;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::array::Array;
+use crate::collation::Collation;
+use crate::json::Json;
+use crate::value::{DataType, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("operator {op} cannot be applied to {lhs} and {rhs}")]
+    TypeMismatch {
+        op: BinaryOp,
+        lhs: String,
+        rhs: String,
+    },
+    #[error("operator {op} cannot be applied to {operand}")]
+    InvalidOperand { op: UnaryOp, operand: String },
+    #[error("division by zero")]
+    DivisionByZero,
+    #[error("integer overflow")]
+    Overflow,
+    #[error("column #{0} out of range")]
+    ColumnOutOfRange(usize),
+    #[error("cannot cast {value} to {data_type}")]
+    InvalidCast { value: String, data_type: DataType },
+    #[error("no value supplied for parameter ${0}")]
+    UnboundParameter(usize),
+    #[error("function {func}() cannot be applied to {argument}")]
+    InvalidArgument {
+        func: ScalarFunction,
+        argument: String,
+    },
+    #[error("function {func}() failed: {message}")]
+    FunctionFailed { func: String, message: String },
+    #[error("function {func}() returned {actual}, not {expected}")]
+    FunctionResult {
+        func: String,
+        expected: DataType,
+        actual: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+    Concat,
+    /// Pattern match with `%` and `_` wildcards.
+    Like,
+    /// The member of a JSON object with a text key, or the element of a
+    /// JSON array at an int index, as JSON.
+    JsonGet,
+    /// The same as text: a string as its contents, JSON null as NULL and
+    /// others as JSON text.
+    JsonGetText,
+    /// The element of an array at an int index, counting from 1, or NULL
+    /// past either end.
+    Subscript,
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "<>",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Concat => "||",
+            BinaryOp::Like => "LIKE",
+            BinaryOp::JsonGet => "->",
+            BinaryOp::JsonGetText => "->>",
+            BinaryOp::Subscript => "[]",
+        };
+        f.write_str(s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryOp::Not => f.write_str("NOT"),
+            UnaryOp::Neg => f.write_str("-"),
+        }
+    }
+}
+
+/// Built-in functions of a single value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarFunction {
+    Lower,
+    Upper,
+    /// Characters of a text, or bytes of a blob.
+    Length,
+    Abs,
+    /// The type of a JSON value, as text.
+    JsonTypeof,
+    /// Elements of an array.
+    Cardinality,
+}
+
+impl ScalarFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "lower" => Some(ScalarFunction::Lower),
+            "upper" => Some(ScalarFunction::Upper),
+            "length" => Some(ScalarFunction::Length),
+            "abs" => Some(ScalarFunction::Abs),
+            "json_typeof" => Some(ScalarFunction::JsonTypeof),
+            "cardinality" => Some(ScalarFunction::Cardinality),
+            _ => None,
+        }
+    }
+
+    /// The result type for an argument of type `arg`, or `None` if the
+    /// function does not apply to it.
+    pub fn return_type(&self, arg: DataType) -> Option<DataType> {
+        match (self, arg) {
+            (ScalarFunction::Lower | ScalarFunction::Upper, DataType::Text) => Some(DataType::Text),
+            (ScalarFunction::Length, DataType::Text | DataType::Bytes) => Some(DataType::Int),
+            (ScalarFunction::Abs, DataType::Int | DataType::Float) => Some(arg),
+            (ScalarFunction::JsonTypeof, DataType::Json) => Some(DataType::Text),
+            (ScalarFunction::Cardinality, DataType::Array(_)) => Some(DataType::Int),
+            _ => None,
+        }
+    }
+
+    fn eval(&self, arg: Value) -> Result<Value, Error> {
+        match (self, arg) {
+            (_, Value::Null) => Ok(Value::Null),
+            (ScalarFunction::Lower, Value::Text(s)) => Ok(Value::Text(s.to_lowercase())),
+            (ScalarFunction::Upper, Value::Text(s)) => Ok(Value::Text(s.to_uppercase())),
+            (ScalarFunction::Length, Value::Text(s)) => Ok(Value::Int(s.chars().count() as i64)),
+            (ScalarFunction::Length, Value::Bytes(b)) => Ok(Value::Int(b.len() as i64)),
+            (ScalarFunction::Abs, Value::Int(i)) => {
+                i.checked_abs().map(Value::Int).ok_or(Error::Overflow)
+            }
+            (ScalarFunction::Abs, Value::Float(x)) => Ok(Value::Float(x.abs())),
+            (ScalarFunction::JsonTypeof, Value::Json(json)) => Ok(json.type_name().into()),
+            (ScalarFunction::Cardinality, Value::Array(array)) => {
+                Ok(Value::Int(array.items.len() as i64))
+            }
+            (func, arg) => Err(Error::InvalidArgument {
+                func: *func,
+                argument: describe(&arg),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScalarFunction::Lower => "lower",
+            ScalarFunction::Upper => "upper",
+            ScalarFunction::Length => "length",
+            ScalarFunction::Abs => "abs",
+            ScalarFunction::JsonTypeof => "json_typeof",
+            ScalarFunction::Cardinality => "cardinality",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The body of a [`UserFunction`]: it is given the arguments, NULL or of
+/// the declared types, and returns the result or a message to fail with.
+pub type FunctionBody = Arc<dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync>;
+
+/// A scalar function the embedder registers, taking a fixed number of
+/// arguments of declared types. Calls are never folded into constants,
+/// since the function need not return the same for the same arguments.
+#[derive(Clone)]
+pub struct UserFunction {
+    pub name: String,
+    pub arguments: Vec<DataType>,
+    pub return_type: DataType,
+    body: FunctionBody,
+}
+
+impl UserFunction {
+    pub fn new(
+        name: &str,
+        arguments: &[DataType],
+        return_type: DataType,
+        body: impl Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arguments: arguments.to_vec(),
+            return_type,
+            body: Arc::new(body),
+        }
+    }
+
+    fn eval(&self, args: &[Value]) -> Result<Value, Error> {
+        let result = (self.body)(args).map_err(|message| Error::FunctionFailed {
+            func: self.name.clone(),
+            message,
+        })?;
+        let actual = describe(&result);
+        result
+            .coerce_to(self.return_type)
+            .ok_or_else(|| Error::FunctionResult {
+                func: self.name.clone(),
+                expected: self.return_type,
+                actual,
+            })
+    }
+}
+
+impl fmt::Debug for UserFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserFunction")
+            .field("name", &self.name)
+            .field("arguments", &self.arguments)
+            .field("return_type", &self.return_type)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Functions are told apart by name and signature, as the catalog holds
+/// one of each name.
+impl PartialEq for UserFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.arguments == other.arguments
+            && self.return_type == other.return_type
+    }
+}
+
+/// An aggregate function the embedder implements, such as a percentile
+/// or a HyperLogLog count. Each group starts from [`Aggregator::init`]
+/// and folds in its non-NULL arguments. The engine may fold parts of a
+/// group into states of their own and [merge](Aggregator::merge) them,
+/// so the result must not depend on how the rows were split.
+pub trait Aggregator: Send + Sync + 'static {
+    type State: Send + 'static;
+
+    fn init(&self) -> Self::State;
+
+    fn accumulate(&self, state: &mut Self::State, value: &Value) -> Result<(), String>;
+
+    /// Folds `other`, a state of other rows of the group, into `state`.
+    fn merge(&self, state: &mut Self::State, other: Self::State) -> Result<(), String>;
+
+    /// The result for the rows folded into `state`. Window functions read
+    /// it again after folding in more rows.
+    fn finalize(&self, state: &Self::State) -> Result<Value, String>;
+}
+
+/// The state of a [`UserAggregate`] for one group.
+pub type AggregateState = Box<dyn Any + Send>;
+
+/// [`Aggregator`] with its state type erased, so that aggregates of
+/// different states share one type.
+trait ErasedAggregator: Send + Sync {
+    fn init(&self) -> AggregateState;
+    fn accumulate(&self, state: &mut AggregateState, value: &Value) -> Result<(), String>;
+    fn merge(&self, state: &mut AggregateState, other: AggregateState) -> Result<(), String>;
+    fn finalize(&self, state: &AggregateState) -> Result<Value, String>;
+}
+
+const FOREIGN_STATE: &str = "the state of another aggregate";
+
+impl<A: Aggregator> ErasedAggregator for A {
+    fn init(&self) -> AggregateState {
+        Box::new(Aggregator::init(self))
+    }
+
+    fn accumulate(&self, state: &mut AggregateState, value: &Value) -> Result<(), String> {
+        let state = state.downcast_mut().expect(FOREIGN_STATE);
+        Aggregator::accumulate(self, state, value)
+    }
+
+    fn merge(&self, state: &mut AggregateState, other: AggregateState) -> Result<(), String> {
+        let state = state.downcast_mut().expect(FOREIGN_STATE);
+        let other = *other.downcast().expect(FOREIGN_STATE);
+        Aggregator::merge(self, state, other)
+    }
+
+    fn finalize(&self, state: &AggregateState) -> Result<Value, String> {
+        Aggregator::finalize(self, state.downcast_ref().expect(FOREIGN_STATE))
+    }
+}
+
+/// An [`Aggregator`] registered under a name, taking one argument of a
+/// declared type as the built-in aggregates do.
+#[derive(Clone)]
+pub struct UserAggregate {
+    pub name: String,
+    pub argument: DataType,
+    pub return_type: DataType,
+    aggregator: Arc<dyn ErasedAggregator>,
+}
+
+impl UserAggregate {
+    pub fn new(
+        name: &str,
+        argument: DataType,
+        return_type: DataType,
+        aggregator: impl Aggregator,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            argument,
+            return_type,
+            aggregator: Arc::new(aggregator),
+        }
+    }
+
+    pub fn init(&self) -> AggregateState {
+        self.aggregator.init()
+    }
+
+    pub fn accumulate(&self, state: &mut AggregateState, value: &Value) -> Result<(), Error> {
+        self.aggregator
+            .accumulate(state, value)
+            .map_err(|message| self.failed(message))
+    }
+
+    pub fn merge(&self, state: &mut AggregateState, other: AggregateState) -> Result<(), Error> {
+        self.aggregator
+            .merge(state, other)
+            .map_err(|message| self.failed(message))
+    }
+
+    pub fn finalize(&self, state: &AggregateState) -> Result<Value, Error> {
+        let result = self
+            .aggregator
+            .finalize(state)
+            .map_err(|message| self.failed(message))?;
+        let actual = describe(&result);
+        result
+            .coerce_to(self.return_type)
+            .ok_or_else(|| Error::FunctionResult {
+                func: self.name.clone(),
+                expected: self.return_type,
+                actual,
+            })
+    }
+
+    fn failed(&self, message: String) -> Error {
+        Error::FunctionFailed {
+            func: self.name.clone(),
+            message,
+        }
+    }
+}
+
+impl fmt::Debug for UserAggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserAggregate")
+            .field("name", &self.name)
+            .field("argument", &self.argument)
+            .field("return_type", &self.return_type)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for UserAggregate {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.argument == other.argument
+            && self.return_type == other.return_type
+    }
+}
+
+impl Eq for UserAggregate {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// Column of the input tuple, by position.
+    Column(usize),
+    Literal(Value),
+    /// Placeholder `$n` of a prepared statement, counting from 1; see
+    /// [`Expr::replace_parameters`].
+    Parameter(usize),
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+    },
+    Function {
+        func: ScalarFunction,
+        arg: Box<Expr>,
+    },
+    /// Call of a [`UserFunction`], with arguments of its declared types.
+    Call {
+        function: UserFunction,
+        args: Vec<Expr>,
+    },
+    /// `ARRAY[...]`, of items each of type `element` or NULL.
+    Array {
+        element: DataType,
+        items: Vec<Expr>,
+    },
+    /// `lhs op ANY (array)`: whether the comparison holds for some element
+    /// of the array, or with `all`, for every one. NULL where the answer
+    /// turns on comparisons with NULL.
+    Any {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        array: Box<Expr>,
+        all: bool,
+    },
+    /// The sort key of `expr` under a collation, which comparisons of
+    /// collated text are made on; see [`Collation::key`].
+    Collate {
+        expr: Box<Expr>,
+        collation: Collation,
+    },
+}
+
+impl Expr {
+    pub fn column(index: usize) -> Expr {
+        Expr::Column(index)
+    }
+
+    pub fn literal(value: impl Into<Value>) -> Expr {
+        Expr::Literal(value.into())
+    }
+
+    pub fn unary(op: UnaryOp, expr: Expr) -> Expr {
+        Expr::Unary {
+            op,
+            expr: Box::new(expr),
+        }
+    }
+
+    pub fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    /// The expression compared under `collation`; as it is for `binary`.
+    pub fn collate(self, collation: Collation) -> Expr {
+        match collation {
+            Collation::Binary => self,
+            collation => Expr::Collate {
+                expr: Box::new(self),
+                collation,
+            },
+        }
+    }
+
+    pub fn eval(&self, tuple: &[Value]) -> Result<Value, Error> {
+        match self {
+            Expr::Column(index) => tuple
+                .get(*index)
+                .cloned()
+                .ok_or(Error::ColumnOutOfRange(*index)),
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Parameter(n) => Err(Error::UnboundParameter(*n)),
+            Expr::Unary { op, expr } => eval_unary(*op, expr.eval(tuple)?),
+            Expr::Binary { op, lhs, rhs } => match op {
+                BinaryOp::And => {
+                    let lhs = lhs.eval(tuple)?;
+                    if lhs == Value::Bool(false) {
+                        return Ok(lhs);
+                    }
+                    eval_logical(*op, lhs, rhs.eval(tuple)?)
+                }
+                BinaryOp::Or => {
+                    let lhs = lhs.eval(tuple)?;
+                    if lhs == Value::Bool(true) {
+                        return Ok(lhs);
+                    }
+                    eval_logical(*op, lhs, rhs.eval(tuple)?)
+                }
+                _ => eval_binary(*op, lhs.eval(tuple)?, rhs.eval(tuple)?),
+            },
+            Expr::IsNull { expr, negated } => {
+                Ok(Value::Bool(expr.eval(tuple)?.is_null() != *negated))
+            }
+            Expr::Cast { expr, data_type } => cast(expr.eval(tuple)?, *data_type),
+            Expr::Function { func, arg } => func.eval(arg.eval(tuple)?),
+            Expr::Call { function, args } => {
+                let args: Vec<Value> = args
+                    .iter()
+                    .map(|arg| arg.eval(tuple))
+                    .collect::<Result<_, _>>()?;
+                function.eval(&args)
+            }
+            Expr::Array { element, items } => {
+                let items = items
+                    .iter()
+                    .map(|item| item.eval(tuple))
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::array(Array::new(*element, items)))
+            }
+            Expr::Any {
+                op,
+                lhs,
+                array,
+                all,
+            } => eval_any(*op, *all, lhs.eval(tuple)?, array.eval(tuple)?),
+            Expr::Collate { expr, collation } => Ok(collation.key(expr.eval(tuple)?)),
+        }
+    }
+
+    /// Evaluates the expression as a predicate: only TRUE passes.
+    pub fn eval_predicate(&self, tuple: &[Value]) -> Result<bool, Error> {
+        Ok(self.eval(tuple)? == Value::Bool(true))
+    }
+
+    /// Evaluates the expression over column vectors of `len` rows at once.
+    ///
+    /// Results match calling [`Expr::eval`] on each row, including AND/OR
+    /// short-circuiting: the right operand is only evaluated on the rows
+    /// whose left operand does not already decide the result.
+    pub fn eval_batch(&self, columns: &[Vec<Value>], len: usize) -> Result<Vec<Value>, Error> {
+        match self {
+            Expr::Column(index) => columns
+                .get(*index)
+                .cloned()
+                .ok_or(Error::ColumnOutOfRange(*index)),
+            Expr::Literal(value) => Ok(vec![value.clone(); len]),
+            Expr::Parameter(n) => Err(Error::UnboundParameter(*n)),
+            Expr::Unary { op, expr } => expr
+                .eval_batch(columns, len)?
+                .into_iter()
+                .map(|value| eval_unary(*op, value))
+                .collect(),
+            Expr::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                lhs,
+                rhs,
+            } => {
+                let decided = Value::Bool(*op == BinaryOp::Or);
+                let mut lhs = lhs.eval_batch(columns, len)?;
+                let pending: Vec<usize> = (0..len).filter(|&i| lhs[i] != decided).collect();
+                if pending.is_empty() {
+                    return Ok(lhs);
+                }
+                let rhs = if pending.len() == len {
+                    rhs.eval_batch(columns, len)?
+                } else {
+                    let subset: Vec<Vec<Value>> = columns
+                        .iter()
+                        .map(|column| pending.iter().map(|&i| column[i].clone()).collect())
+                        .collect();
+                    rhs.eval_batch(&subset, pending.len())?
+                };
+                for (i, rhs) in pending.into_iter().zip(rhs) {
+                    let lhs_value = std::mem::replace(&mut lhs[i], Value::Null);
+                    lhs[i] = eval_logical(*op, lhs_value, rhs)?;
+                }
+                Ok(lhs)
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                let lhs = lhs.eval_batch(columns, len)?;
+                let rhs = rhs.eval_batch(columns, len)?;
+                if let Some(result) = compare_ints(*op, &lhs, &rhs) {
+                    return Ok(result);
+                }
+                lhs.into_iter()
+                    .zip(rhs)
+                    .map(|(lhs, rhs)| eval_binary(*op, lhs, rhs))
+                    .collect()
+            }
+            Expr::IsNull { expr, negated } => Ok(expr
+                .eval_batch(columns, len)?
+                .into_iter()
+                .map(|value| Value::Bool(value.is_null() != *negated))
+                .collect()),
+            Expr::Cast { expr, data_type } => expr
+                .eval_batch(columns, len)?
+                .into_iter()
+                .map(|value| cast(value, *data_type))
+                .collect(),
+            Expr::Function { func, arg } => arg
+                .eval_batch(columns, len)?
+                .into_iter()
+                .map(|value| func.eval(value))
+                .collect(),
+            Expr::Call { function, args } => {
+                let args = args
+                    .iter()
+                    .map(|arg| arg.eval_batch(columns, len))
+                    .collect::<Result<Vec<_>, _>>()?;
+                (0..len)
+                    .map(|i| {
+                        let row: Vec<Value> = args.iter().map(|arg| arg[i].clone()).collect();
+                        function.eval(&row)
+                    })
+                    .collect()
+            }
+            Expr::Array { element, items } => {
+                let items = items
+                    .iter()
+                    .map(|item| item.eval_batch(columns, len))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((0..len)
+                    .map(|i| {
+                        let row = items.iter().map(|item| item[i].clone()).collect();
+                        Value::array(Array::new(*element, row))
+                    })
+                    .collect())
+            }
+            Expr::Any {
+                op,
+                lhs,
+                array,
+                all,
+            } => {
+                let lhs = lhs.eval_batch(columns, len)?;
+                let array = array.eval_batch(columns, len)?;
+                lhs.into_iter()
+                    .zip(array)
+                    .map(|(lhs, array)| eval_any(*op, *all, lhs, array))
+                    .collect()
+            }
+            Expr::Collate { expr, collation } => Ok(expr
+                .eval_batch(columns, len)?
+                .into_iter()
+                .map(|value| collation.key(value))
+                .collect()),
+        }
+    }
+
+    /// Rewrites every column reference through `f`, leaving the rest of
+    /// the expression as is.
+    pub fn map_columns(&self, f: &impl Fn(usize) -> usize) -> Expr {
+        self.replace_columns(&|index| Expr::Column(f(index)))
+    }
+
+    /// Replaces every column reference with the expression `f` returns
+    /// for it.
+    pub fn replace_columns(&self, f: &impl Fn(usize) -> Expr) -> Expr {
+        self.transform(&|expr| match expr {
+            Expr::Column(index) => Some(f(*index)),
+            _ => None,
+        })
+    }
+
+    /// Substitutes `params[n - 1]` for every `$n`. Parameters without a
+    /// value are left in place and fail when evaluated.
+    pub fn replace_parameters(&self, params: &[Value]) -> Expr {
+        self.transform(&|expr| match expr {
+            Expr::Parameter(n) => params.get(n - 1).cloned().map(Expr::Literal),
+            _ => None,
+        })
+    }
+
+    /// Rebuilds the expression, replacing each node for which `f` returns
+    /// a replacement and descending into the others.
+    fn transform(&self, f: &impl Fn(&Expr) -> Option<Expr>) -> Expr {
+        if let Some(expr) = f(self) {
+            return expr;
+        }
+        match self {
+            Expr::Column(_) | Expr::Literal(_) | Expr::Parameter(_) => self.clone(),
+            Expr::Unary { op, expr } => Expr::unary(*op, expr.transform(f)),
+            Expr::Binary { op, lhs, rhs } => Expr::binary(*op, lhs.transform(f), rhs.transform(f)),
+            Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: Box::new(expr.transform(f)),
+                negated: *negated,
+            },
+            Expr::Cast { expr, data_type } => Expr::Cast {
+                expr: Box::new(expr.transform(f)),
+                data_type: *data_type,
+            },
+            Expr::Function { func, arg } => Expr::Function {
+                func: *func,
+                arg: Box::new(arg.transform(f)),
+            },
+            Expr::Call { function, args } => Expr::Call {
+                function: function.clone(),
+                args: args.iter().map(|arg| arg.transform(f)).collect(),
+            },
+            Expr::Array { element, items } => Expr::Array {
+                element: *element,
+                items: items.iter().map(|item| item.transform(f)).collect(),
+            },
+            Expr::Any {
+                op,
+                lhs,
+                array,
+                all,
+            } => Expr::Any {
+                op: *op,
+                lhs: Box::new(lhs.transform(f)),
+                array: Box::new(array.transform(f)),
+                all: *all,
+            },
+            Expr::Collate { expr, collation } => Expr::Collate {
+                expr: Box::new(expr.transform(f)),
+                collation: *collation,
+            },
+        }
+    }
+
+    /// Whether the expression contains a parameter placeholder.
+    pub fn has_parameters(&self) -> bool {
+        match self {
+            Expr::Parameter(_) => true,
+            Expr::Column(_) | Expr::Literal(_) => false,
+            Expr::Unary { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::Cast { expr, .. }
+            | Expr::Function { arg: expr, .. }
+            | Expr::Collate { expr, .. } => expr.has_parameters(),
+            Expr::Binary { lhs, rhs, .. }
+            | Expr::Any {
+                lhs, array: rhs, ..
+            } => lhs.has_parameters() || rhs.has_parameters(),
+            Expr::Call { args, .. } | Expr::Array { items: args, .. } => {
+                args.iter().any(Expr::has_parameters)
+            }
+        }
+    }
+
+    /// Whether the expression calls a [`UserFunction`].
+    pub fn has_calls(&self) -> bool {
+        match self {
+            Expr::Call { .. } => true,
+            Expr::Column(_) | Expr::Literal(_) | Expr::Parameter(_) => false,
+            Expr::Unary { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::Cast { expr, .. }
+            | Expr::Function { arg: expr, .. }
+            | Expr::Collate { expr, .. } => expr.has_calls(),
+            Expr::Binary { lhs, rhs, .. }
+            | Expr::Any {
+                lhs, array: rhs, ..
+            } => lhs.has_calls() || rhs.has_calls(),
+            Expr::Array { items, .. } => items.iter().any(Expr::has_calls),
+        }
+    }
+
+    /// Calls `f` with every column the expression references.
+    pub fn visit_columns(&self, f: &mut impl FnMut(usize)) {
+        match self {
+            Expr::Column(index) => f(*index),
+            Expr::Literal(_) | Expr::Parameter(_) => {}
+            Expr::Unary { expr, .. }
+            | Expr::IsNull { expr, .. }
+            | Expr::Cast { expr, .. }
+            | Expr::Function { arg: expr, .. }
+            | Expr::Collate { expr, .. } => expr.visit_columns(f),
+            Expr::Binary { lhs, rhs, .. }
+            | Expr::Any {
+                lhs, array: rhs, ..
+            } => {
+                lhs.visit_columns(f);
+                rhs.visit_columns(f);
+            }
+            Expr::Call { args, .. } | Expr::Array { items: args, .. } => {
+                for arg in args {
+                    arg.visit_columns(f);
+                }
+            }
+        }
+    }
+}
+
+/// Kernel for the common case of comparing integer vectors. Returns `None`
+/// when `op` is not a comparison or a non-integer operand shows up.
+fn compare_ints(op: BinaryOp, lhs: &[Value], rhs: &[Value]) -> Option<Vec<Value>> {
+    let cmp: fn(&i64, &i64) -> bool = match op {
+        BinaryOp::Eq => i64::eq,
+        BinaryOp::NotEq => i64::ne,
+        BinaryOp::Lt => i64::lt,
+        BinaryOp::LtEq => i64::le,
+        BinaryOp::Gt => i64::gt,
+        BinaryOp::GtEq => i64::ge,
+        _ => return None,
+    };
+    let mut result = Vec::with_capacity(lhs.len());
+    for (lhs, rhs) in lhs.iter().zip(rhs) {
+        result.push(match (lhs, rhs) {
+            (Value::Int(a), Value::Int(b)) => Value::Bool(cmp(a, b)),
+            (Value::Null, _) | (_, Value::Null) => Value::Null,
+            _ => return None,
+        });
+    }
+    Some(result)
+}
+
+fn describe(value: &Value) -> String {
+    value
+        .data_type()
+        .map_or_else(|| "NULL".to_string(), |data_type| data_type.to_string())
+}
+
+/// Renders the expression for plan output: columns by position as `#i`,
+/// text literals quoted, and every binary operation parenthesized.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, &|f, index| write!(f, "#{index}"), false)
+    }
+}
+
+/// An expression shown with column names and literals as SQL writes
+/// them, so that it parses back; see [`Expr::display_with`].
+pub struct DisplayWith<'a> {
+    expr: &'a Expr,
+    columns: &'a [String],
+}
+
+impl fmt::Display for DisplayWith<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.expr.fmt_with(
+            f,
+            &|f, index| match self.columns.get(index) {
+                Some(name) => f.write_str(name),
+                None => write!(f, "#{index}"),
+            },
+            true,
+        )
+    }
+}
+
+type ColumnFormatter<'a> = dyn Fn(&mut fmt::Formatter<'_>, usize) -> fmt::Result + 'a;
+
+impl Expr {
+    /// Shows the expression with `columns[i]` in place of column `i`.
+    pub fn display_with<'a>(&'a self, columns: &'a [String]) -> DisplayWith<'a> {
+        DisplayWith {
+            expr: self,
+            columns,
+        }
+    }
+
+    fn fmt_with(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        column: &ColumnFormatter<'_>,
+        sql: bool,
+    ) -> fmt::Result {
+        match self {
+            Expr::Column(index) => column(f, *index),
+            Expr::Literal(value) if sql => f.write_str(&value.to_sql()),
+            Expr::Literal(Value::Text(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(value) => write!(f, "{value}"),
+            Expr::Parameter(n) => write!(f, "${n}"),
+            Expr::Unary {
+                op: UnaryOp::Not,
+                expr,
+            } => {
+                f.write_str("NOT ")?;
+                expr.fmt_with(f, column, sql)
+            }
+            Expr::Unary { op, expr } => {
+                write!(f, "{op}")?;
+                expr.fmt_with(f, column, sql)
+            }
+            Expr::Binary {
+                op: BinaryOp::Subscript,
+                lhs,
+                rhs,
+            } => {
+                lhs.fmt_with(f, column, sql)?;
+                f.write_str("[")?;
+                rhs.fmt_with(f, column, sql)?;
+                f.write_str("]")
+            }
+            Expr::Binary { op, lhs, rhs } => {
+                f.write_str("(")?;
+                lhs.fmt_with(f, column, sql)?;
+                write!(f, " {op} ")?;
+                rhs.fmt_with(f, column, sql)?;
+                f.write_str(")")
+            }
+            Expr::IsNull { expr, negated } => {
+                expr.fmt_with(f, column, sql)?;
+                f.write_str(if *negated { " IS NOT NULL" } else { " IS NULL" })
+            }
+            Expr::Cast { expr, data_type } => {
+                f.write_str("CAST(")?;
+                expr.fmt_with(f, column, sql)?;
+                write!(f, " AS {data_type})")
+            }
+            Expr::Function { func, arg } => {
+                write!(f, "{func}(")?;
+                arg.fmt_with(f, column, sql)?;
+                f.write_str(")")
+            }
+            Expr::Call { function, args } => {
+                write!(f, "{}(", function.name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    arg.fmt_with(f, column, sql)?;
+                }
+                f.write_str(")")
+            }
+            // An empty constructor has no element to take the type from.
+            Expr::Array { element, items } if items.is_empty() => {
+                write!(f, "CAST(ARRAY[] AS {element}[])")
+            }
+            Expr::Array { items, .. } => {
+                f.write_str("ARRAY[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    item.fmt_with(f, column, sql)?;
+                }
+                f.write_str("]")
+            }
+            Expr::Any {
+                op,
+                lhs,
+                array,
+                all,
+            } => {
+                f.write_str("(")?;
+                lhs.fmt_with(f, column, sql)?;
+                write!(f, " {op} {} (", if *all { "ALL" } else { "ANY" })?;
+                array.fmt_with(f, column, sql)?;
+                f.write_str("))")
+            }
+            Expr::Collate { expr, collation } => {
+                f.write_str("(")?;
+                expr.fmt_with(f, column, sql)?;
+                write!(f, " COLLATE {collation})")
+            }
+        }
+    }
+}
+
+fn eval_unary(op: UnaryOp, value: Value) -> Result<Value, Error> {
+    match (op, value) {
+        (_, Value::Null) => Ok(Value::Null),
+        (UnaryOp::Not, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (UnaryOp::Neg, Value::Int(i)) => i.checked_neg().map(Value::Int).ok_or(Error::Overflow),
+        (UnaryOp::Neg, Value::Float(x)) => Ok(Value::Float(-x)),
+        (op, value) => Err(Error::InvalidOperand {
+            op,
+            operand: describe(&value),
+        }),
+    }
+}
+
+/// Three-valued AND/OR over booleans and NULL.
+fn eval_logical(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, Error> {
+    let as_bool = |value: &Value| match value {
+        Value::Bool(b) => Ok(Some(*b)),
+        Value::Null => Ok(None),
+        _ => Err(()),
+    };
+    let (Ok(l), Ok(r)) = (as_bool(&lhs), as_bool(&rhs)) else {
+        return Err(Error::TypeMismatch {
+            op,
+            lhs: describe(&lhs),
+            rhs: describe(&rhs),
+        });
+    };
+    let result = match (op, l, r) {
+        (BinaryOp::And, Some(false), _) | (BinaryOp::And, _, Some(false)) => Some(false),
+        (BinaryOp::And, Some(true), Some(true)) => Some(true),
+        (BinaryOp::Or, Some(true), _) | (BinaryOp::Or, _, Some(true)) => Some(true),
+        (BinaryOp::Or, Some(false), Some(false)) => Some(false),
+        _ => None,
+    };
+    Ok(result.map_or(Value::Null, Value::Bool))
+}
+
+fn eval_binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, Error> {
+    if lhs.is_null() || rhs.is_null() {
+        return Ok(Value::Null);
+    }
+    let mismatch = |lhs: &Value, rhs: &Value| Error::TypeMismatch {
+        op,
+        lhs: describe(lhs),
+        rhs: describe(rhs),
+    };
+    let compare = |expected: fn(Ordering) -> bool| {
+        lhs.sql_cmp(&rhs)
+            .map(|ordering| Value::Bool(expected(ordering)))
+            .ok_or_else(|| mismatch(&lhs, &rhs))
+    };
+    match op {
+        BinaryOp::Eq => compare(Ordering::is_eq),
+        BinaryOp::NotEq => compare(Ordering::is_ne),
+        BinaryOp::Lt => compare(Ordering::is_lt),
+        BinaryOp::LtEq => compare(Ordering::is_le),
+        BinaryOp::Gt => compare(Ordering::is_gt),
+        BinaryOp::GtEq => compare(Ordering::is_ge),
+        BinaryOp::And | BinaryOp::Or => eval_logical(op, lhs, rhs),
+        BinaryOp::Concat => match (&lhs, &rhs) {
+            (Value::Text(a), Value::Text(b)) => Ok(Value::Text(format!("{a}{b}"))),
+            (Value::Bytes(a), Value::Bytes(b)) => Ok(Value::Bytes([&a[..], &b[..]].concat())),
+            (Value::Array(a), Value::Array(b)) if a.element == b.element => {
+                let items = [&a.items[..], &b.items[..]].concat();
+                Ok(Value::array(Array::new(a.element, items)))
+            }
+            _ => Err(mismatch(&lhs, &rhs)),
+        },
+        BinaryOp::Like => match (&lhs, &rhs) {
+            (Value::Text(text), Value::Text(pattern)) => {
+                let text: Vec<char> = text.chars().collect();
+                let pattern: Vec<char> = pattern.chars().collect();
+                Ok(Value::Bool(like(&text, &pattern)))
+            }
+            _ => Err(mismatch(&lhs, &rhs)),
+        },
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+            eval_arithmetic(op, &lhs, &rhs).ok_or_else(|| mismatch(&lhs, &rhs))?
+        }
+        BinaryOp::JsonGet | BinaryOp::JsonGetText => {
+            let Value::Json(json) = &lhs else {
+                return Err(mismatch(&lhs, &rhs));
+            };
+            let found = match &rhs {
+                Value::Text(key) => json.get(key),
+                Value::Int(index) => json.element(*index),
+                _ => return Err(mismatch(&lhs, &rhs)),
+            };
+            Ok(match (found, op) {
+                (None, _) | (Some(Json::Null), BinaryOp::JsonGetText) => Value::Null,
+                (Some(Json::String(s)), BinaryOp::JsonGetText) => Value::Text(s.clone()),
+                (Some(found), BinaryOp::JsonGetText) => Value::Text(found.to_string()),
+                (Some(found), _) => Value::Json(Box::new(found.clone())),
+            })
+        }
+        BinaryOp::Subscript => match (&lhs, &rhs) {
+            (Value::Array(array), Value::Int(index)) => {
+                Ok(array.get(*index).cloned().unwrap_or(Value::Null))
+            }
+            _ => Err(mismatch(&lhs, &rhs)),
+        },
+    }
+}
+
+/// Compares `lhs` with each element of `array` in turn, stopping at the
+/// first that decides the result.
+fn eval_any(op: BinaryOp, all: bool, lhs: Value, array: Value) -> Result<Value, Error> {
+    let array = match array {
+        Value::Null => return Ok(Value::Null),
+        Value::Array(array) => array,
+        array => {
+            return Err(Error::TypeMismatch {
+                op,
+                lhs: describe(&lhs),
+                rhs: describe(&array),
+            })
+        }
+    };
+    let mut result = Value::Bool(all);
+    for item in array.items {
+        match eval_binary(op, lhs.clone(), item)? {
+            Value::Bool(b) if b != all => return Ok(Value::Bool(b)),
+            Value::Bool(_) => {}
+            _ => result = Value::Null,
+        }
+    }
+    Ok(result)
+}
+
+fn eval_arithmetic(op: BinaryOp, lhs: &Value, rhs: &Value) -> Option<Result<Value, Error>> {
+    let result = match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            if matches!(op, BinaryOp::Div | BinaryOp::Mod) && b == 0 {
+                return Some(Err(Error::DivisionByZero));
+            }
+            let result = match op {
+                BinaryOp::Add => a.checked_add(b),
+                BinaryOp::Sub => a.checked_sub(b),
+                BinaryOp::Mul => a.checked_mul(b),
+                BinaryOp::Div => a.checked_div(b),
+                BinaryOp::Mod => a.checked_rem(b),
+                _ => unreachable!(),
+            };
+            result.map(Value::Int).ok_or(Error::Overflow)
+        }
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let a = lhs.clone().coerce_to(DataType::Float)?;
+            let b = rhs.clone().coerce_to(DataType::Float)?;
+            let (Value::Float(a), Value::Float(b)) = (a, b) else {
+                unreachable!()
+            };
+            let result = match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                BinaryOp::Mod => a % b,
+                _ => unreachable!(),
+            };
+            Ok(Value::Float(result))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Matches `text` against a LIKE pattern, where `%` matches any run of
+/// characters and `_` any single character.
+fn like(text: &[char], pattern: &[char]) -> bool {
+    // Greedy matching, backtracking only to the most recent `%`.
+    let (mut t, mut p) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '_' || pattern[p] == text[t]) {
+            t += 1;
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == '%' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == '%')
+}
+
+/// Explicit CAST. Unlike [`Value::coerce_to`] this may lose information,
+/// and fails at run time on text that does not parse.
+pub fn cast(value: Value, data_type: DataType) -> Result<Value, Error> {
+    let invalid = |value: &Value| Error::InvalidCast {
+        value: value.to_string(),
+        data_type,
+    };
+    let result = match (&value, data_type) {
+        (Value::Null, _) => Some(Value::Null),
+        (value, data_type) if value.data_type() == Some(data_type) => Some(value.clone()),
+        (Value::Int(i), DataType::Float) => Some(Value::Float(*i as f64)),
+        (Value::Int(i), DataType::Bool) => Some(Value::Bool(*i != 0)),
+        (Value::Float(x), DataType::Int) => {
+            let x = x.round();
+            (x >= i64::MIN as f64 && x < i64::MAX as f64).then_some(Value::Int(x as i64))
+        }
+        (Value::Bool(b), DataType::Int) => Some(Value::Int(*b as i64)),
+        (Value::Bytes(bytes), DataType::Text) => {
+            String::from_utf8(bytes.clone()).ok().map(Value::Text)
+        }
+        (value, DataType::Text) => Some(Value::Text(value.to_string())),
+        (Value::Text(s), DataType::Bytes) => Some(Value::Bytes(s.as_bytes().to_vec())),
+        (Value::Text(s), DataType::Int) => s.trim().parse().ok().map(Value::Int),
+        (Value::Text(s), DataType::Float) => s.trim().parse().ok().map(Value::Float),
+        (Value::Text(s), DataType::Bool) => match s.trim().to_lowercase().as_str() {
+            "true" | "t" | "yes" | "on" | "1" => Some(Value::Bool(true)),
+            "false" | "f" | "no" | "off" | "0" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        (Value::Text(s), DataType::Json | DataType::Array(_)) => Value::parse(s, data_type),
+        (Value::Array(array), DataType::Array(&element)) => {
+            let items = (array.items.iter())
+                .map(|item| cast(item.clone(), element))
+                .collect::<Result<_, _>>();
+            items
+                .ok()
+                .map(|items| Value::array(Array::new(element, items)))
+        }
+        (Value::Json(json), DataType::Int | DataType::Float | DataType::Bool) => match **json {
+            Json::Int(i) => cast(Value::Int(i), data_type).ok(),
+            Json::Float(x) => cast(Value::Float(x), data_type).ok(),
+            Json::Bool(b) if data_type == DataType::Bool => Some(Value::Bool(b)),
+            _ => None,
+        },
+        _ => None,
+    };
+    result.ok_or_else(|| invalid(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        let tuple = vec![Value::Int(10), Value::Text("a".into()), Value::Null];
+        let expr = Expr::binary(
+            BinaryOp::Mul,
+            Expr::column(0),
+            Expr::binary(BinaryOp::Add, Expr::literal(1i64), Expr::literal(0.5)),
+        );
+        assert_eq!(Value::Float(15.0), expr.eval(&tuple).unwrap());
+        let expr = Expr::binary(BinaryOp::Concat, Expr::column(1), Expr::literal("b"));
+        assert_eq!(Value::Text("ab".into()), expr.eval(&tuple).unwrap());
+        let expr = Expr::binary(BinaryOp::Div, Expr::column(0), Expr::literal(0i64));
+        assert!(matches!(expr.eval(&tuple), Err(Error::DivisionByZero)));
+        let expr = Expr::binary(BinaryOp::Add, Expr::column(0), Expr::column(1));
+        assert!(matches!(expr.eval(&tuple), Err(Error::TypeMismatch { .. })));
+
+        let call = |func, arg| Expr::Function {
+            func,
+            arg: Box::new(arg),
+        };
+        let expr = call(ScalarFunction::Upper, Expr::literal("Straße"));
+        assert_eq!(Value::Text("STRASSE".into()), expr.eval(&tuple).unwrap());
+        let expr = call(ScalarFunction::Length, Expr::literal("Straße"));
+        assert_eq!(Value::Int(6), expr.eval(&tuple).unwrap());
+        let expr = call(ScalarFunction::Abs, Expr::literal(i64::MIN));
+        assert!(matches!(expr.eval(&tuple), Err(Error::Overflow)));
+        let expr = call(ScalarFunction::Lower, Expr::column(2));
+        assert_eq!(Value::Null, expr.eval(&tuple).unwrap());
+        let expr = call(ScalarFunction::Lower, Expr::column(0));
+        assert!(matches!(
+            expr.eval(&tuple),
+            Err(Error::InvalidArgument { .. })
+        ));
+
+        let half = UserFunction::new(
+            "half",
+            &[DataType::Int],
+            DataType::Float,
+            |args| match &args[0] {
+                Value::Int(0) => Err("zero".into()),
+                Value::Int(i) => Ok(Value::Int(i / 2)),
+                _ => Ok(Value::from("odd")),
+            },
+        );
+        let call = |arg| Expr::Call {
+            function: half.clone(),
+            args: vec![arg],
+        };
+        assert_eq!(
+            Value::Float(5.0),
+            call(Expr::column(0)).eval(&tuple).unwrap()
+        );
+        assert!(matches!(
+            call(Expr::literal(0i64)).eval(&tuple),
+            Err(Error::FunctionFailed { .. })
+        ));
+        assert!(matches!(
+            call(Expr::column(2)).eval(&tuple),
+            Err(Error::FunctionResult { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_batch_matches_eval() {
+        let rows = [
+            vec![Value::Int(0), Value::Text("a".into())],
+            vec![Value::Int(5), Value::Null],
+            vec![Value::Null, Value::Text("c".into())],
+        ];
+        let columns: Vec<Vec<Value>> = (0..2)
+            .map(|i| rows.iter().map(|row| row[i].clone()).collect())
+            .collect();
+        // The division is only reached where the column is non-zero.
+        let expr = Expr::binary(
+            BinaryOp::And,
+            Expr::binary(BinaryOp::NotEq, Expr::column(0), Expr::literal(0i64)),
+            Expr::binary(
+                BinaryOp::Gt,
+                Expr::binary(BinaryOp::Div, Expr::literal(10i64), Expr::column(0)),
+                Expr::literal(1i64),
+            ),
+        );
+        let expected: Vec<Value> = rows.iter().map(|row| expr.eval(row).unwrap()).collect();
+        assert_eq!(expected, expr.eval_batch(&columns, rows.len()).unwrap());
+        assert_eq!(
+            vec![Value::Bool(false), Value::Bool(true), Value::Null],
+            expected
+        );
+        let expr = Expr::binary(BinaryOp::Concat, Expr::column(1), Expr::literal("!"));
+        let expected: Vec<Value> = rows.iter().map(|row| expr.eval(row).unwrap()).collect();
+        assert_eq!(expected, expr.eval_batch(&columns, rows.len()).unwrap());
+    }
+
+    #[test]
+    fn test_three_valued_logic() {
+        let tuple = vec![Value::Null];
+        let null_eq = Expr::binary(BinaryOp::Eq, Expr::column(0), Expr::literal(1i64));
+        assert_eq!(Value::Null, null_eq.eval(&tuple).unwrap());
+        assert!(!null_eq.eval_predicate(&tuple).unwrap());
+        let or = Expr::binary(BinaryOp::Or, null_eq.clone(), Expr::literal(true));
+        assert_eq!(Value::Bool(true), or.eval(&tuple).unwrap());
+        let and = Expr::binary(BinaryOp::And, null_eq, Expr::literal(false));
+        assert_eq!(Value::Bool(false), and.eval(&tuple).unwrap());
+        let is_null = Expr::IsNull {
+            expr: Box::new(Expr::column(0)),
+            negated: false,
+        };
+        assert!(is_null.eval_predicate(&tuple).unwrap());
+    }
+
+    #[test]
+    fn test_like_and_cast() {
+        let like = |text: &str, pattern: &str| {
+            Expr::binary(BinaryOp::Like, Expr::literal(text), Expr::literal(pattern))
+                .eval(&[])
+                .unwrap()
+                == Value::Bool(true)
+        };
+        assert!(like("hello", "h%o"));
+        assert!(like("hello", "_ell_"));
+        assert!(like("", "%"));
+        assert!(like("a%b", "a%%b"));
+        assert!(!like("hello", "h_o"));
+        assert!(!like("abc", "%b"));
+
+        assert_eq!(
+            Ok(Value::Int(42)),
+            cast(" 42 ".into(), DataType::Int).map_err(|_| ())
+        );
+        assert_eq!(
+            Ok(Value::Int(3)),
+            cast(Value::Float(2.5), DataType::Int).map_err(|_| ())
+        );
+        assert_eq!(
+            Ok(Value::Text("1.5".into())),
+            cast(Value::Float(1.5), DataType::Text).map_err(|_| ())
+        );
+        assert!(matches!(
+            cast("x".into(), DataType::Int),
+            Err(Error::InvalidCast { .. })
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        let expr = Expr::binary(
+            BinaryOp::And,
+            Expr::binary(BinaryOp::Eq, Expr::column(1), Expr::literal("it's")),
+            Expr::unary(
+                UnaryOp::Not,
+                Expr::IsNull {
+                    expr: Box::new(Expr::column(0)),
+                    negated: true,
+                },
+            ),
+        );
+        assert_eq!("((#1 = 'it''s') AND NOT #0 IS NOT NULL)", expr.to_string());
+        let columns = ["id".to_string()];
+        assert_eq!(
+            "((#1 = 'it''s') AND NOT id IS NOT NULL)",
+            expr.display_with(&columns).to_string()
+        );
+    }
+
+    #[test]
+    fn test_replace_parameters() {
+        let expr = Expr::binary(BinaryOp::Add, Expr::Parameter(1), Expr::Parameter(2));
+        assert_eq!("($1 + $2)", expr.to_string());
+        assert!(expr.has_parameters());
+        assert!(matches!(expr.eval(&[]), Err(Error::UnboundParameter(1))));
+        let bound = expr.replace_parameters(&[Value::Int(2), Value::Int(3)]);
+        assert!(!bound.has_parameters());
+        assert_eq!(Value::Int(5), bound.eval(&[]).unwrap());
+        assert!(matches!(
+            expr.replace_parameters(&[Value::Int(2)]).eval(&[]),
+            Err(Error::UnboundParameter(2))
+        ));
+    }
+}
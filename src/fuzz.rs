@@ -0,0 +1,142 @@
+//! Entry points for the fuzz targets in `fuzz/`, which run them under
+//! cargo-fuzz, as in `cargo +nightly fuzz run sql`. Each takes arbitrary
+//! bytes and must return, whatever they hold, without panicking or
+//! allocating beyond what the input accounts for. The tests run them on
+//! mutations of valid input, so the targets' ground is covered by
+//! `cargo test` too.
+
+use crate::buffer::BufferPoolManager;
+use crate::catalog::Catalog;
+use crate::disk::{DiskManager, PageId, PAGE_SIZE};
+use crate::sim::SimFile;
+use crate::{check, inspect, sql, tuple};
+
+/// Pages of a file image looked at; the rest of the input is ignored.
+const MAX_PAGES: usize = 64;
+
+/// Lexes, splits and parses `data` as a script, if it is UTF-8.
+pub fn sql(data: &[u8]) {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    let _ = sql::normalize(text);
+    let _ = sql::parse_hints(text);
+    let (statements, _) = sql::split_statements(text);
+    for statement in statements {
+        let _ = sql::parse_statement(statement);
+    }
+    let _ = sql::parse(text);
+}
+
+/// Decodes `data` as a heap tuple.
+pub fn tuple(data: &[u8]) {
+    let _ = tuple::decode(data);
+}
+
+/// Reads `data` as a database file: checks it, and if it passes, dumps
+/// every page as what the catalog says it is.
+pub fn page(data: &[u8]) {
+    let len = data.len().min(MAX_PAGES * PAGE_SIZE);
+    let image = data[..len - len % PAGE_SIZE].to_vec();
+    let Ok(disk) = DiskManager::with_storage(SimFile::from_image(image, 0)) else {
+        return;
+    };
+    let bufmgr = BufferPoolManager::new(disk, 16);
+    // Past the checker, pages are trusted: a database opens a file that
+    // was not closed cleanly only once the check finds no problems.
+    if !check::check(&bufmgr).is_ok_and(|report| report.is_ok()) {
+        return;
+    }
+    let Ok(catalog) = Catalog::open(&bufmgr) else {
+        return;
+    };
+    let Ok(map) = inspect::page_map(&bufmgr, &catalog) else {
+        return;
+    };
+    for page_id in 0..bufmgr.num_pages() {
+        let page_id = PageId(page_id);
+        let kind = map.get(&page_id).map(|owner| owner.kind);
+        let _ = inspect::dump_page(&bufmgr, page_id, kind);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, Options};
+    use crate::prop::Rng;
+
+    /// `input` with a few random bytes replaced, inserted or removed.
+    fn mutate(rng: &mut Rng, input: &[u8]) -> Vec<u8> {
+        let mut bytes = input.to_vec();
+        for _ in 0..1 + rng.below(4) {
+            let at = rng.below(bytes.len() + 1);
+            let byte = rng.next_u64() as u8;
+            match rng.below(3) {
+                0 if at < bytes.len() => bytes[at] = byte,
+                1 if at < bytes.len() => {
+                    bytes.remove(at);
+                }
+                _ => bytes.insert(at, byte),
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_sql_and_tuple() {
+        let scripts = [
+            "SELECT a, count(*) FROM t JOIN u ON t.id = u.id WHERE a > 1 GROUP BY a \
+             HAVING count(*) > 2 ORDER BY a DESC LIMIT 10",
+            "WITH RECURSIVE r(n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r) SELECT * FROM r",
+            "/*+ SeqScan(t) */ SELECT sum(x) OVER (PARTITION BY y ORDER BY z) FROM t",
+            "INSERT INTO t VALUES (1, 'a''b', x'00ff', -1.5e3, NULL); DELETE FROM t",
+            "CREATE TABLE t (id INT PRIMARY KEY, name TEXT NOT NULL); BEGIN; COMMIT",
+        ];
+        let mut rng = Rng::new(0);
+        for _ in 0..5000 {
+            let script = scripts[rng.below(scripts.len())].as_bytes();
+            sql(&mutate(&mut rng, script));
+        }
+        for depth in [sql::MAX_DEPTH, 100_000] {
+            sql(format!("SELECT {}1{}", "(".repeat(depth), ")".repeat(depth)).as_bytes());
+            sql(format!("SELECT {}1", "- ".repeat(depth)).as_bytes());
+            sql(format!("SELECT {}", "NOT ".repeat(depth)).as_bytes());
+        }
+
+        let mut record = vec![];
+        tuple::encode(
+            &[1i64.into(), "text".into(), vec![0u8; 3].into()],
+            &mut record,
+        );
+        for _ in 0..5000 {
+            tuple(&mutate(&mut rng, &record));
+        }
+        tuple(&[5, 0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_page() {
+        let file = SimFile::new(0);
+        let disk = DiskManager::with_storage(file.clone()).unwrap();
+        let mut db = Database::with_disk(disk, Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        db.execute("CREATE INDEX t_name ON t (name)").unwrap();
+        for i in 0..50 {
+            db.execute(&format!("INSERT INTO t VALUES ({i}, '{}')", "n".repeat(i)))
+                .unwrap();
+        }
+        db.close().unwrap();
+        let image = file.image();
+        let mut rng = Rng::new(0);
+        for _ in 0..500 {
+            let mut damaged = image.clone();
+            for _ in 0..1 + rng.below(16) {
+                let at = rng.below(damaged.len());
+                damaged[at] = rng.next_u64() as u8;
+            }
+            page(&damaged);
+        }
+    }
+}
@@ -0,0 +1,655 @@
+//! Unordered table storage: a chain of slotted pages holding tuples.
+//!
+//! A heap is identified by its meta page, which records the first and last
+//! data page of the chain. Tuples are addressed by [`Rid`]; a slot whose
+//! record is empty has been deleted and its slot number is not reused
+//! while its page is in the chain.
+//!
+//! The meta page also counts the live tuples and the data pages, which
+//! inserts, updates and deletes keep up to date, so that the size of a
+//! heap is known without a scan; see [`HeapFile::counts`].
+//!
+//! Tuples are appended to the last page, unless deletes have left room
+//! in an earlier one: the meta page lists up to [`MAX_ROOM_PAGES`] pages
+//! with at least [`ROOM_THRESHOLD`] bytes free, which inserts fill
+//! first, so that a table whose rows come and go stays about its size.
+//! Pages whose tuples have all been deleted stay in the chain until
+//! [`HeapFile::vacuum`] unlinks them and frees them for any structure of
+//! the file to reuse.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use crate::buffer::{self, Buffer, BufferPoolManager};
+use crate::disk::{PageId, PAGE_SIZE};
+use crate::slotted::Slotted;
+use crate::tuple;
+use crate::value::{Tuple, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("tuple too large: {0} bytes")]
+    TooLarge(usize),
+    #[error("tuple ({}, {}): {source}", .rid.page_id.to_u64(), .rid.slot_id)]
+    Tuple { rid: Rid, source: tuple::Error },
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+}
+
+pub(crate) const PAGE_HEADER_SIZE: usize = 8;
+/// Where the meta page counts live tuples and data pages. The bytes
+/// between these and the chain's ends, and the eight after them, are the
+/// catalog's, whose heap's meta page keeps its marks and the free list
+/// there.
+const ROWS_RANGE: Range<usize> = 32..40;
+const PAGES_RANGE: Range<usize> = 40..48;
+/// Where the meta page counts the pages emptied since the last vacuum,
+/// a hint of whether one is due, and the pages it lists with room, whose
+/// ids follow.
+const EMPTIED_RANGE: Range<usize> = 56..64;
+const ROOM_RANGE: Range<usize> = 64..72;
+/// Pages with room the meta page lists at most.
+pub const MAX_ROOM_PAGES: usize = (PAGE_SIZE - ROOM_RANGE.end) / 8;
+/// Free bytes past which a page is listed as having room.
+pub const ROOM_THRESHOLD: usize = PAGE_SIZE / 4;
+/// Room left for a single tuple on an empty page (slotted header and pointer).
+const MAX_TUPLE_SIZE: usize = PAGE_SIZE - PAGE_HEADER_SIZE - 8;
+
+/// Physical address of a tuple.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Rid {
+    pub page_id: PageId,
+    pub slot_id: u16,
+}
+
+impl Rid {
+    pub const SIZE: usize = 10;
+
+    /// Big-endian so that encoded rids sort in physical order.
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut bytes = [0u8; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.page_id.to_u64().to_be_bytes());
+        bytes[8..].copy_from_slice(&self.slot_id.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Rid {
+        Rid {
+            page_id: PageId(u64::from_be_bytes(bytes[..8].try_into().unwrap())),
+            slot_id: u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
+        }
+    }
+}
+
+fn decode(rid: Rid, record: &[u8]) -> Result<Tuple, Error> {
+    tuple::decode(record).map_err(|source| Error::Tuple { rid, source })
+}
+
+fn next_page_id(page: &[u8]) -> Option<PageId> {
+    PageId::from_bytes(&page[..PAGE_HEADER_SIZE]).valid()
+}
+
+fn set_next_page_id(page: &mut [u8], page_id: Option<PageId>) {
+    page[..PAGE_HEADER_SIZE].copy_from_slice(&PageId::from(page_id).to_bytes());
+}
+
+/// Changes the count in `range` of a meta page by `delta`.
+fn add_count(meta: &mut [u8], range: Range<usize>, delta: i64) {
+    let count = u64::from_le_bytes(meta[range.clone()].try_into().unwrap());
+    meta[range].copy_from_slice(&count.saturating_add_signed(delta).to_le_bytes());
+}
+
+/// The live tuples and data pages of a heap, as its meta page counts them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapCounts {
+    pub rows: u64,
+    pub pages: u64,
+}
+
+fn count(meta: &[u8], range: Range<usize>) -> u64 {
+    u64::from_le_bytes(meta[range].try_into().unwrap())
+}
+
+/// The pages a meta page lists with room, oldest first.
+pub(crate) fn room_pages(meta: &[u8]) -> Vec<PageId> {
+    let len = (count(meta, ROOM_RANGE) as usize).min(MAX_ROOM_PAGES);
+    (meta[ROOM_RANGE.end..].chunks_exact(8).take(len))
+        .map(PageId::from_bytes)
+        .collect()
+}
+
+fn set_room_pages(meta: &mut [u8], page_ids: &[PageId]) {
+    let page_ids = &page_ids[..page_ids.len().min(MAX_ROOM_PAGES)];
+    meta[ROOM_RANGE].copy_from_slice(&(page_ids.len() as u64).to_le_bytes());
+    let entries = meta[ROOM_RANGE.end..].chunks_exact_mut(8);
+    for (entry, page_id) in entries.zip(page_ids) {
+        entry.copy_from_slice(&page_id.to_bytes());
+    }
+}
+
+fn free_space(page: &[u8]) -> usize {
+    Slotted::new(&page[PAGE_HEADER_SIZE..]).free_space()
+}
+
+fn is_empty(page: &[u8]) -> bool {
+    let slotted = Slotted::new(&page[PAGE_HEADER_SIZE..]);
+    (0..slotted.num_slots()).all(|slot_id| slotted.data(slot_id).is_empty())
+}
+
+/// What a delete left of the page it deleted from.
+struct Left {
+    page_id: PageId,
+    room: bool,
+    empty: bool,
+}
+
+impl Left {
+    fn of(page_id: PageId, page: &[u8]) -> Self {
+        Self {
+            page_id,
+            room: free_space(page) >= ROOM_THRESHOLD,
+            empty: is_empty(page),
+        }
+    }
+}
+
+fn initialize_page(page: &mut [u8]) {
+    set_next_page_id(page, None);
+    Slotted::new(&mut page[PAGE_HEADER_SIZE..]).initialize();
+}
+
+/// Appends `record` to the page, returning its slot, or `None` when full.
+fn insert_record(page: &mut [u8], record: &[u8]) -> Option<u16> {
+    let mut slotted = Slotted::new(&mut page[PAGE_HEADER_SIZE..]);
+    let slot_id = slotted.num_slots();
+    slotted.insert(slot_id, record.len())?;
+    slotted.data_mut(slot_id).copy_from_slice(record);
+    Some(slot_id as u16)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapFile {
+    pub meta_page_id: PageId,
+}
+
+impl HeapFile {
+    pub fn create(bufmgr: &BufferPoolManager) -> Result<Self, Error> {
+        let meta_buffer = bufmgr.create_page()?;
+        let first_buffer = bufmgr.create_page()?;
+        initialize_page(&mut first_buffer.write()[..]);
+        let mut meta = meta_buffer.write();
+        meta[..8].copy_from_slice(&first_buffer.page_id.to_bytes());
+        meta[8..16].copy_from_slice(&first_buffer.page_id.to_bytes());
+        add_count(&mut meta[..], PAGES_RANGE, 1);
+        Ok(Self::new(meta_buffer.page_id))
+    }
+
+    pub fn new(meta_page_id: PageId) -> Self {
+        Self { meta_page_id }
+    }
+
+    pub fn first_page_id(&self, bufmgr: &BufferPoolManager) -> Result<PageId, Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let meta = meta_buffer.read();
+        Ok(PageId::from_bytes(&meta[..8]))
+    }
+
+    pub fn insert(&self, bufmgr: &BufferPoolManager, values: &[Value]) -> Result<Rid, Error> {
+        let mut record = vec![];
+        tuple::encode(values, &mut record);
+        self.insert_record(bufmgr, &record)
+    }
+
+    fn insert_record(&self, bufmgr: &BufferPoolManager, record: &[u8]) -> Result<Rid, Error> {
+        if record.is_empty() || record.len() > MAX_TUPLE_SIZE {
+            return Err(Error::TooLarge(record.len()));
+        }
+        // Holding the meta page serializes appends to the same heap.
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut meta = meta_buffer.write();
+        add_count(&mut meta[..], ROWS_RANGE, 1);
+        // The page listed last goes off the list once a tuple does not fit
+        // in the little room it has left; a tuple too large for a page
+        // with room goes on to the last page instead.
+        let mut room = room_pages(&meta[..]);
+        while let Some(&page_id) = room.last() {
+            let buffer = bufmgr.fetch_page(page_id)?;
+            let mut page = buffer.write();
+            if let Some(slot_id) = insert_record(&mut page[..], record) {
+                return Ok(Rid { page_id, slot_id });
+            }
+            if free_space(&page[..]) >= ROOM_THRESHOLD {
+                break;
+            }
+            room.pop();
+            set_room_pages(&mut meta[..], &room);
+        }
+        let last_buffer = bufmgr.fetch_page(PageId::from_bytes(&meta[8..16]))?;
+        let mut last_page = last_buffer.write();
+        if let Some(slot_id) = insert_record(&mut last_page[..], record) {
+            return Ok(Rid {
+                page_id: last_buffer.page_id,
+                slot_id,
+            });
+        }
+        let new_buffer = bufmgr.create_page()?;
+        let mut new_page = new_buffer.write();
+        initialize_page(&mut new_page[..]);
+        set_next_page_id(&mut last_page[..], Some(new_buffer.page_id));
+        meta[8..16].copy_from_slice(&new_buffer.page_id.to_bytes());
+        add_count(&mut meta[..], PAGES_RANGE, 1);
+        let slot_id = insert_record(&mut new_page[..], record).unwrap();
+        Ok(Rid {
+            page_id: new_buffer.page_id,
+            slot_id,
+        })
+    }
+
+    pub fn get(&self, bufmgr: &BufferPoolManager, rid: Rid) -> Result<Option<Tuple>, Error> {
+        let buffer = bufmgr.fetch_page(rid.page_id)?;
+        let page = buffer.read();
+        let slotted = Slotted::new(&page[PAGE_HEADER_SIZE..]);
+        if rid.slot_id as usize >= slotted.num_slots() {
+            return Ok(None);
+        }
+        let record = slotted.data(rid.slot_id as usize);
+        if record.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(decode(rid, record)?))
+    }
+
+    /// Overwrites the tuple at `rid`. The tuple moves to a new address when
+    /// it no longer fits in its page; the returned rid is authoritative.
+    pub fn update(
+        &self,
+        bufmgr: &BufferPoolManager,
+        rid: Rid,
+        values: &[Value],
+    ) -> Result<Rid, Error> {
+        let mut record = vec![];
+        tuple::encode(values, &mut record);
+        if record.len() > MAX_TUPLE_SIZE {
+            return Err(Error::TooLarge(record.len()));
+        }
+        let left = {
+            let buffer = bufmgr.fetch_page(rid.page_id)?;
+            let mut page = buffer.write();
+            let mut slotted = Slotted::new(&mut page[PAGE_HEADER_SIZE..]);
+            let slot_id = rid.slot_id as usize;
+            if slotted.resize(slot_id, record.len()).is_some() {
+                slotted.data_mut(slot_id).copy_from_slice(&record);
+                return Ok(rid);
+            }
+            slotted.resize(slot_id, 0).unwrap();
+            Left::of(rid.page_id, &page[..])
+        };
+        // Moved: counted again as it is inserted.
+        self.deleted(bufmgr, left)?;
+        self.insert_record(bufmgr, &record)
+    }
+
+    /// Deletes the tuple at `rid`. Returns whether it existed.
+    pub fn delete(&self, bufmgr: &BufferPoolManager, rid: Rid) -> Result<bool, Error> {
+        let left = {
+            let buffer = bufmgr.fetch_page(rid.page_id)?;
+            let mut page = buffer.write();
+            let mut slotted = Slotted::new(&mut page[PAGE_HEADER_SIZE..]);
+            let slot_id = rid.slot_id as usize;
+            if slot_id >= slotted.num_slots() || slotted.data(slot_id).is_empty() {
+                return Ok(false);
+            }
+            slotted.resize(slot_id, 0).unwrap();
+            Left::of(rid.page_id, &page[..])
+        };
+        // The meta page is latched after the data page is let go, as
+        // inserts latch the two the other way round.
+        self.deleted(bufmgr, left)?;
+        Ok(true)
+    }
+
+    /// Counts a deleted tuple, and lists its page if that has room now.
+    fn deleted(&self, bufmgr: &BufferPoolManager, left: Left) -> Result<(), Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut meta = meta_buffer.write();
+        add_count(&mut meta[..], ROWS_RANGE, -1);
+        if left.empty {
+            add_count(&mut meta[..], EMPTIED_RANGE, 1);
+        }
+        let last = PageId::from_bytes(&meta[8..16]);
+        let mut room = room_pages(&meta[..]);
+        if left.room
+            && left.page_id != last
+            && room.len() < MAX_ROOM_PAGES
+            && !room.contains(&left.page_id)
+        {
+            room.push(left.page_id);
+            set_room_pages(&mut meta[..], &room);
+        }
+        Ok(())
+    }
+
+    /// Pages emptied by deletes since the heap was last vacuumed, some of
+    /// which inserts may have filled again since.
+    pub fn emptied_pages(&self, bufmgr: &BufferPoolManager) -> Result<u64, Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let emptied = count(&meta_buffer.read()[..], EMPTIED_RANGE);
+        Ok(emptied)
+    }
+
+    /// Unlinks the data pages whose tuples have all been deleted, but the
+    /// first, and frees them for reuse, then lists the pages with room
+    /// anew. Returns how many pages it freed. The tuples left keep their
+    /// rids.
+    pub fn vacuum(&self, bufmgr: &BufferPoolManager) -> Result<u64, Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut meta = meta_buffer.write();
+        let mut buffer = bufmgr.fetch_page(PageId::from_bytes(&meta[..8]))?;
+        let mut room = vec![];
+        let mut freed = vec![];
+        loop {
+            let (free, linked) = {
+                let page = buffer.read();
+                (free_space(&page[..]), next_page_id(&page[..]))
+            };
+            if free >= ROOM_THRESHOLD {
+                room.push(buffer.page_id);
+            }
+            let mut next = linked;
+            while let Some(page_id) = next {
+                let page = bufmgr.fetch_page(page_id)?;
+                let page = page.read();
+                if !is_empty(&page[..]) {
+                    break;
+                }
+                freed.push(page_id);
+                next = next_page_id(&page[..]);
+            }
+            if next != linked {
+                set_next_page_id(&mut buffer.write()[..], next);
+            }
+            match next {
+                Some(page_id) => buffer = bufmgr.fetch_page(page_id)?,
+                None => break,
+            }
+        }
+        room.retain(|&page_id| page_id != buffer.page_id);
+        set_room_pages(&mut meta[..], &room);
+        meta[8..16].copy_from_slice(&buffer.page_id.to_bytes());
+        add_count(&mut meta[..], PAGES_RANGE, -(freed.len() as i64));
+        meta[EMPTIED_RANGE].fill(0);
+        for &page_id in &freed {
+            bufmgr.free_page(page_id)?;
+        }
+        Ok(freed.len() as u64)
+    }
+
+    /// Deletes every tuple by giving the heap a new, empty chain, and
+    /// frees the pages of the old one.
+    pub fn truncate(&self, bufmgr: &BufferPoolManager) -> Result<(), Error> {
+        let old = self.page_ids(bufmgr)?;
+        let first_buffer = bufmgr.create_page()?;
+        initialize_page(&mut first_buffer.write()[..]);
+        {
+            let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+            let mut meta = meta_buffer.write();
+            meta[..8].copy_from_slice(&first_buffer.page_id.to_bytes());
+            meta[8..16].copy_from_slice(&first_buffer.page_id.to_bytes());
+            for range in [ROWS_RANGE, PAGES_RANGE, EMPTIED_RANGE, ROOM_RANGE] {
+                meta[range].fill(0);
+            }
+            add_count(&mut meta[..], PAGES_RANGE, 1);
+        }
+        for page_id in old {
+            bufmgr.free_page(page_id)?;
+        }
+        Ok(())
+    }
+
+    /// Frees every page of the heap, which nothing may refer to any more.
+    pub fn free(&self, bufmgr: &BufferPoolManager) -> Result<(), Error> {
+        for page_id in self.page_ids(bufmgr)? {
+            bufmgr.free_page(page_id)?;
+        }
+        bufmgr.free_page(self.meta_page_id)?;
+        Ok(())
+    }
+
+    /// The live tuples and data pages the meta page counts, without a
+    /// scan. Heaps of files from before they were counted are counted by
+    /// the upgrade; see [`HeapFile::recount`].
+    pub fn counts(&self, bufmgr: &BufferPoolManager) -> Result<HeapCounts, Error> {
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let meta = meta_buffer.read();
+        Ok(HeapCounts {
+            rows: count(&meta[..], ROWS_RANGE),
+            pages: count(&meta[..], PAGES_RANGE),
+        })
+    }
+
+    /// Counts the live tuples and data pages by a scan, and records them
+    /// in the meta page.
+    pub fn recount(&self, bufmgr: &BufferPoolManager) -> Result<HeapCounts, Error> {
+        let mut counts = HeapCounts::default();
+        for page_id in self.page_ids(bufmgr)? {
+            let buffer = bufmgr.fetch_page(page_id)?;
+            let page = buffer.read();
+            let slotted = Slotted::new(&page[PAGE_HEADER_SIZE..]);
+            let live =
+                (0..slotted.num_slots()).filter(|&slot_id| !slotted.data(slot_id).is_empty());
+            counts.rows += live.count() as u64;
+            counts.pages += 1;
+        }
+        let meta_buffer = bufmgr.fetch_page(self.meta_page_id)?;
+        let mut meta = meta_buffer.write();
+        meta[ROWS_RANGE].copy_from_slice(&counts.rows.to_le_bytes());
+        meta[PAGES_RANGE].copy_from_slice(&counts.pages.to_le_bytes());
+        Ok(counts)
+    }
+
+    /// Ids of every data page, in chain order.
+    pub fn page_ids(&self, bufmgr: &BufferPoolManager) -> Result<Vec<PageId>, Error> {
+        let mut page_ids = vec![];
+        let mut page_id = Some(self.first_page_id(bufmgr)?);
+        while let Some(id) = page_id {
+            page_ids.push(id);
+            page_id = next_page_id(&bufmgr.fetch_page(id)?.read()[..]);
+        }
+        Ok(page_ids)
+    }
+
+    /// Live tuples of a single data page, in slot order.
+    pub fn page_tuples(
+        &self,
+        bufmgr: &BufferPoolManager,
+        page_id: PageId,
+    ) -> Result<Vec<(Rid, Tuple)>, Error> {
+        let buffer = bufmgr.fetch_page(page_id)?;
+        let page = buffer.read();
+        let slotted = Slotted::new(&page[PAGE_HEADER_SIZE..]);
+        let mut tuples = vec![];
+        for slot_id in 0..slotted.num_slots() {
+            let record = slotted.data(slot_id);
+            if record.is_empty() {
+                continue;
+            }
+            let rid = Rid {
+                page_id,
+                slot_id: slot_id as u16,
+            };
+            tuples.push((rid, decode(rid, record)?));
+        }
+        Ok(tuples)
+    }
+
+    pub fn scan(&self, bufmgr: &BufferPoolManager) -> Result<Scan, Error> {
+        let buffer = bufmgr.fetch_page(self.first_page_id(bufmgr)?)?;
+        Ok(Scan {
+            buffer: Some(buffer),
+            slot_id: 0,
+            pages_read: 1,
+        })
+    }
+}
+
+/// Sequential cursor over the live tuples of a heap, in physical order.
+pub struct Scan {
+    buffer: Option<Arc<Buffer>>,
+    slot_id: usize,
+    pages_read: u64,
+}
+
+impl Scan {
+    /// Pages the scan has fetched so far, the one it is on included.
+    pub fn pages_read(&self) -> u64 {
+        self.pages_read
+    }
+
+    pub fn next(&mut self, bufmgr: &BufferPoolManager) -> Result<Option<(Rid, Tuple)>, Error> {
+        while let Some(buffer) = &self.buffer {
+            let next_page_id = {
+                let page = buffer.read();
+                let slotted = Slotted::new(&page[PAGE_HEADER_SIZE..]);
+                while self.slot_id < slotted.num_slots() {
+                    let slot_id = self.slot_id;
+                    self.slot_id += 1;
+                    let record = slotted.data(slot_id);
+                    if record.is_empty() {
+                        continue;
+                    }
+                    let rid = Rid {
+                        page_id: buffer.page_id,
+                        slot_id: slot_id as u16,
+                    };
+                    return Ok(Some((rid, decode(rid, record)?)));
+                }
+                next_page_id(&page[..])
+            };
+            self.buffer = match next_page_id {
+                Some(page_id) => {
+                    self.pages_read += 1;
+                    Some(bufmgr.fetch_page(page_id)?)
+                }
+                None => None,
+            };
+            self.slot_id = 0;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::DiskManager;
+    use tempfile::tempfile;
+
+    fn scan_all(heap: &HeapFile, bufmgr: &BufferPoolManager) -> Vec<(Rid, Tuple)> {
+        let mut scan = heap.scan(bufmgr).unwrap();
+        let mut rows = vec![];
+        while let Some(row) = scan.next(bufmgr).unwrap() {
+            rows.push(row);
+        }
+        rows
+    }
+
+    #[test]
+    fn test_insert_update_delete() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 8);
+        let heap = HeapFile::create(&bufmgr).unwrap();
+        let mut rids = vec![];
+        for i in 0..1000 {
+            let row = vec![Value::Int(i), Value::Text(format!("row {i}"))];
+            rids.push(heap.insert(&bufmgr, &row).unwrap());
+        }
+        assert!(rids.iter().any(|rid| rid.page_id != rids[0].page_id));
+        assert_eq!(1000, scan_all(&heap, &bufmgr).len());
+
+        assert!(heap.delete(&bufmgr, rids[10]).unwrap());
+        assert!(!heap.delete(&bufmgr, rids[10]).unwrap());
+        assert_eq!(None, heap.get(&bufmgr, rids[10]).unwrap());
+
+        let small = vec![Value::Int(11), Value::Null];
+        assert_eq!(rids[11], heap.update(&bufmgr, rids[11], &small).unwrap());
+        assert_eq!(Some(small), heap.get(&bufmgr, rids[11]).unwrap());
+
+        let big = vec![Value::Int(12), Value::Text("x".repeat(2000))];
+        let moved = heap.update(&bufmgr, rids[12], &big).unwrap();
+        assert_ne!(rids[12], moved);
+        assert_eq!(None, heap.get(&bufmgr, rids[12]).unwrap());
+        assert_eq!(Some(big), heap.get(&bufmgr, moved).unwrap());
+
+        let rows = scan_all(&heap, &bufmgr);
+        assert_eq!(999, rows.len());
+        assert_eq!(moved, rows.last().unwrap().0);
+
+        let counts = heap.counts(&bufmgr).unwrap();
+        assert_eq!(999, counts.rows);
+        assert_eq!(heap.page_ids(&bufmgr).unwrap().len() as u64, counts.pages);
+        assert_eq!(counts, heap.recount(&bufmgr).unwrap());
+    }
+
+    #[test]
+    fn test_reuse_and_vacuum() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 16);
+        bufmgr.set_free_list(bufmgr.create_page().unwrap().page_id);
+        let heap = HeapFile::create(&bufmgr).unwrap();
+        let row = |i: i64| vec![Value::Int(i), Value::Text("x".repeat(100))];
+        let rids: Vec<_> = (0..400)
+            .map(|i| heap.insert(&bufmgr, &row(i)).unwrap())
+            .collect();
+        let pages = heap.counts(&bufmgr).unwrap().pages;
+
+        // Deleting every other row leaves room the next rows fill.
+        for rid in rids.iter().step_by(2) {
+            heap.delete(&bufmgr, *rid).unwrap();
+        }
+        for i in 0..150 {
+            heap.insert(&bufmgr, &row(i)).unwrap();
+        }
+        assert_eq!(pages, heap.counts(&bufmgr).unwrap().pages);
+
+        // Emptied pages are unlinked and freed, and reused by others.
+        let first = heap.first_page_id(&bufmgr).unwrap();
+        let emptied: Vec<_> = (scan_all(&heap, &bufmgr).into_iter())
+            .filter(|(rid, _)| rid.page_id != first)
+            .map(|(rid, _)| rid)
+            .collect();
+        for rid in &emptied[..emptied.len() / 2] {
+            heap.delete(&bufmgr, *rid).unwrap();
+        }
+        assert!(heap.emptied_pages(&bufmgr).unwrap() > 0);
+        let freed = heap.vacuum(&bufmgr).unwrap();
+        assert!(freed > 0);
+        assert_eq!(0, heap.emptied_pages(&bufmgr).unwrap());
+        let counts = heap.counts(&bufmgr).unwrap();
+        assert_eq!(pages - freed, counts.pages);
+        assert_eq!(counts, heap.recount(&bufmgr).unwrap());
+        assert_eq!(counts.rows as usize, scan_all(&heap, &bufmgr).len());
+
+        let num_pages = bufmgr.num_pages();
+        let other = HeapFile::create(&bufmgr).unwrap();
+        assert!(other.meta_page_id.to_u64() < num_pages);
+        assert_eq!(num_pages, bufmgr.num_pages());
+    }
+
+    #[test]
+    fn test_page_ids_and_tuples() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 8);
+        let heap = HeapFile::create(&bufmgr).unwrap();
+        for i in 0..500 {
+            heap.insert(&bufmgr, &[Value::Int(i), Value::Text("x".repeat(20))])
+                .unwrap();
+        }
+        let page_ids = heap.page_ids(&bufmgr).unwrap();
+        assert!(page_ids.len() > 1);
+        let rows: Vec<_> = page_ids
+            .iter()
+            .flat_map(|&page_id| heap.page_tuples(&bufmgr, page_id).unwrap())
+            .collect();
+        assert_eq!(scan_all(&heap, &bufmgr), rows);
+    }
+}
@@ -0,0 +1,481 @@
+//! A small HTTP interface, for integrations and dashboards that have no
+//! Postgres driver at hand.
+//!
+//! - `POST /query` runs one statement. The body is the SQL, or with
+//!   `Content-Type: application/json` an object such as
+//!   `{"sql": "SELECT * FROM t WHERE id = $1", "params": [7]}`. Queries
+//!   answer `{"columns": [{"name": ..., "type": ...}], "rows": [[...]]}`,
+//!   INSERT, UPDATE and DELETE `{"affected": n}`, and other statements
+//!   `{"done": true}`; failures answer `{"error": message}`.
+//! - `GET /health` answers `{"status": "ok"}` while the server runs.
+//! - `GET /metrics` reports the server's [`Metrics`] in the Prometheus
+//!   text format.
+//!
+//! Each connection carries one request, and the response closes it.
+//! Transaction control is refused, since a transaction would outlive the
+//! connection that began it. Once the database has users, `/query` takes
+//! HTTP basic authentication and runs the statement as the user; before
+//! then it takes the shared [`pgwire::Config::password`], if set, under
+//! any user name. The password travels in the clear, like everything
+//! else here, so a server that requires TLS will not serve HTTP.
+
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::{Mutex, PoisonError};
+
+use crate::auth;
+use crate::database::Database;
+use crate::engine;
+use crate::json::{self, Json};
+use crate::pgwire;
+use crate::server::Metrics;
+use crate::sql::{self, ast};
+use crate::value::Value;
+
+/// Bytes the request line and headers may take together.
+const MAX_HEAD_LEN: u64 = 64 * 1024;
+
+/// Bytes a request body may take.
+pub const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            headers: vec![],
+            body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        let mut body = "{\"error\": ".to_string();
+        json::write_string(&mut body, message);
+        body.push('}');
+        Self::json(status, body)
+    }
+
+    fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.status,
+            reason(self.status),
+            self.content_type,
+            self.body.len()
+        );
+        for (name, value) in &self.headers {
+            write!(head, "{name}: {value}\r\n").unwrap();
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(self.body.as_bytes())?;
+        writer.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Content Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Unknown",
+    }
+}
+
+/// Serves the one request of a connection, reading from `reader` and
+/// writing to `writer`, usually both halves of a socket. Logins are
+/// checked as `config` says for Postgres connections.
+pub fn serve(
+    db: &Mutex<Database>,
+    config: &pgwire::Config,
+    metrics: &Metrics,
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<(), Error> {
+    let mut reader = BufReader::new(reader);
+    let response = match read_request(&mut reader)? {
+        Ok(request) => route(db, config, metrics, &request),
+        Err(response) => response,
+    };
+    metrics.count_http_request(response.status);
+    response.write(writer)?;
+    Ok(())
+}
+
+/// Turns a connection away before reading its request.
+pub fn refuse(writer: impl Write, message: &str) -> Result<(), Error> {
+    Ok(Response::error(503, message).write(writer)?)
+}
+
+/// The request, or the response to send if it cannot be read.
+fn read_request(reader: &mut impl BufRead) -> Result<Result<Request, Response>, Error> {
+    let bad = |message: &str| Ok(Err(Response::error(400, message)));
+    let mut head = reader.by_ref().take(MAX_HEAD_LEN);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return bad("malformed request line");
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Ok(Err(Response::error(501, "only HTTP/1.x is supported")));
+    }
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let method = method.to_string();
+    let mut headers = vec![];
+    loop {
+        line.clear();
+        if head.read_line(&mut line)? == 0 {
+            return bad("request headers are incomplete or too long");
+        }
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return bad("malformed header");
+        };
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: vec![],
+    };
+    if request.header("Transfer-Encoding").is_some() {
+        return Ok(Err(Response::error(411, "send a Content-Length instead")));
+    }
+    let len = match request.header("Content-Length").map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(len)) if len <= MAX_BODY_LEN => len,
+        Some(Ok(_)) => return Ok(Err(Response::error(413, "request body is too large"))),
+        Some(Err(_)) => return bad("malformed Content-Length"),
+    };
+    request.body = vec![0; len];
+    reader.read_exact(&mut request.body)?;
+    Ok(Ok(request))
+}
+
+fn route(
+    db: &Mutex<Database>,
+    config: &pgwire::Config,
+    metrics: &Metrics,
+    request: &Request,
+) -> Response {
+    let allow = match request.path.as_str() {
+        "/query" => "POST",
+        "/health" | "/metrics" => "GET",
+        _ => return Response::error(404, "no such endpoint"),
+    };
+    if request.method != allow {
+        return Response::error(405, &format!("use {allow}")).with_header("Allow", allow);
+    }
+    match request.path.as_str() {
+        "/query" => query(db, config, request),
+        "/health" => Response::json(200, "{\"status\": \"ok\"}".to_string()),
+        _ => {
+            // A transaction may hold the database for long; its numbers
+            // are left out rather than waited for.
+            let db = db.try_lock().ok();
+            Response {
+                status: 200,
+                content_type: "text/plain; version=0.0.4",
+                headers: vec![],
+                body: metrics.render(db.as_deref()),
+            }
+        }
+    }
+}
+
+fn query(db: &Mutex<Database>, config: &pgwire::Config, request: &Request) -> Response {
+    let (sql, params) = match read_query(request) {
+        Ok(query) => query,
+        Err(message) => return Response::error(400, &message),
+    };
+    let (mut statements, rest) = sql::split_statements(&sql);
+    if !rest.trim().is_empty() {
+        statements.push(rest);
+    }
+    let sql = match statements.as_slice() {
+        [sql] => *sql,
+        [] => return Response::error(400, "no statement given"),
+        _ => return Response::error(400, "send one statement per request"),
+    };
+    if let Ok(ast::Statement::Transaction(_)) = sql::parse_statement(sql) {
+        return Response::error(400, "transactions are not supported over HTTP");
+    }
+    let lock = || db.lock().unwrap_or_else(PoisonError::into_inner);
+    let user = match authenticate(request, config, lock().engine()) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let user = match user {
+        Some((name, verifier, password)) => match verifier {
+            // Verified without the lock, since it takes a while on purpose.
+            Some(verifier) if verifier.verify(&password) => Some(name),
+            _ => return unauthorized("password authentication failed"),
+        },
+        None => None,
+    };
+    let mut db = lock();
+    let engine = db.engine_mut();
+    engine.set_user(user);
+    // Settings a session changed are not this request's.
+    engine.set_settings(engine.default_settings().clone());
+    let result = engine
+        .prepare(sql)
+        .and_then(|prepared| engine.execute_prepared(&prepared, &params));
+    match result {
+        Ok(output) => Response::json(200, output_json(output)),
+        Err(e @ engine::Error::Buffer(_)) => Response::error(500, &e.to_string()),
+        Err(e) => Response::error(400, &e.to_string()),
+    }
+}
+
+/// The SQL and parameters of a `/query` request.
+fn read_query(request: &Request) -> Result<(String, Vec<Value>), String> {
+    let body = String::from_utf8(request.body.clone()).map_err(|_| "body is not UTF-8")?;
+    let is_json = request
+        .header("Content-Type")
+        .is_some_and(|t| t.starts_with("application/json"));
+    if !is_json {
+        return Ok((body, vec![]));
+    }
+    let json = json::parse(&body)?;
+    let Some(Json::String(sql)) = json.get("sql") else {
+        return Err("expected a string \"sql\"".to_string());
+    };
+    let params = match json.get("params") {
+        None => vec![],
+        Some(Json::Array(params)) => params
+            .iter()
+            .map(|param| match param {
+                Json::Null => Ok(Value::Null),
+                Json::Bool(b) => Ok(Value::Bool(*b)),
+                Json::Int(i) => Ok(Value::Int(*i)),
+                Json::Float(x) => Ok(Value::Float(*x)),
+                Json::String(s) => Ok(Value::Text(s.clone())),
+                Json::Array(_) | Json::Object(_) => Err("parameters must be scalars".to_string()),
+            })
+            .collect::<Result<_, _>>()?,
+        Some(_) => return Err("expected an array \"params\"".to_string()),
+    };
+    Ok((sql.clone(), params))
+}
+
+/// A user name and password to check against the user's verifier;
+/// `None` while the database has no users, when the shared password, if
+/// any, has been checked already.
+type Credentials = (String, Option<auth::Verifier>, String);
+
+fn authenticate(
+    request: &Request,
+    config: &pgwire::Config,
+    engine: &engine::Engine,
+) -> Result<Option<Credentials>, Response> {
+    let catalog = engine.catalog();
+    let has_users = catalog.users().next().is_some();
+    if !has_users && config.password.is_none() {
+        return Ok(None);
+    }
+    let credentials = request
+        .header("Authorization")
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| auth::base64_decode(encoded.trim()))
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    let Some((name, password)) = credentials.as_deref().and_then(|c| c.split_once(':')) else {
+        return Err(unauthorized("authentication required"));
+    };
+    if !has_users {
+        if config.password.as_deref() != Some(password) {
+            return Err(unauthorized("password authentication failed"));
+        }
+        return Ok(None);
+    }
+    let verifier = catalog.user(name).and_then(|user| user.verifier.clone());
+    Ok(Some((name.to_string(), verifier, password.to_string())))
+}
+
+fn unauthorized(message: &str) -> Response {
+    Response::error(401, message).with_header("WWW-Authenticate", "Basic realm=\"neru7db\"")
+}
+
+fn output_json(output: engine::Output) -> String {
+    let mut out = String::new();
+    match output {
+        engine::Output::Rows { fields, rows } => {
+            out.push_str("{\"columns\": [");
+            for (i, field) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                out.push_str("{\"name\": ");
+                json::write_string(&mut out, &field.name);
+                out.push_str(", \"type\": ");
+                match field.data_type {
+                    Some(data_type) => json::write_string(&mut out, &data_type.to_string()),
+                    None => out.push_str("null"),
+                }
+                out.push('}');
+            }
+            out.push_str("], \"rows\": [");
+            for (i, row) in rows.iter().enumerate() {
+                out.push_str(if i > 0 { ", [" } else { "[" });
+                for (j, value) in row.iter().enumerate() {
+                    if j > 0 {
+                        out.push_str(", ");
+                    }
+                    json::write_value(&mut out, value);
+                }
+                out.push(']');
+            }
+            out.push_str("]}");
+        }
+        engine::Output::Affected(n) => write!(out, "{{\"affected\": {n}}}").unwrap(),
+        engine::Output::Done => out.push_str("{\"done\": true}"),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Options;
+
+    fn request(db: &Mutex<Database>, text: &str) -> (u16, String) {
+        request_with(db, &pgwire::Config::default(), text)
+    }
+
+    fn request_with(db: &Mutex<Database>, config: &pgwire::Config, text: &str) -> (u16, String) {
+        let metrics = Metrics::default();
+        let mut output = vec![];
+        serve(db, config, &metrics, text.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let status = output[9..12].parse().unwrap();
+        let body = output.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    fn post(db: &Mutex<Database>, headers: &str, body: &str) -> (u16, String) {
+        let text = format!(
+            "POST /query HTTP/1.1\r\nContent-Length: {}\r\n{headers}\r\n{body}",
+            body.len()
+        );
+        request(db, &text)
+    }
+
+    #[test]
+    fn test_query_endpoint() {
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        assert_eq!(
+            (200, "{\"done\": true}".to_string()),
+            post(&db, "", "CREATE TABLE t (id INT, name TEXT)")
+        );
+        let json = "Content-Type: application/json\r\n";
+        assert_eq!(
+            (200, "{\"affected\": 2}".to_string()),
+            post(
+                &db,
+                json,
+                r#"{"sql": "INSERT INTO t VALUES ($1, $2), (2, NULL)", "params": [1, "a\"b"]}"#
+            )
+        );
+        assert_eq!(
+            (
+                200,
+                "{\"columns\": [{\"name\": \"id\", \"type\": \"INT\"}, \
+                 {\"name\": \"name\", \"type\": \"TEXT\"}], \
+                 \"rows\": [[1, \"a\\\"b\"], [2, null]]}"
+                    .to_string()
+            ),
+            post(&db, "", "SELECT * FROM t ORDER BY id;")
+        );
+        assert_eq!(400, post(&db, "", "SELECT nope FROM t").0);
+        assert_eq!(400, post(&db, "", "BEGIN").0);
+        assert_eq!(400, post(&db, "", "SELECT 1; SELECT 2").0);
+        assert_eq!(400, post(&db, json, "{\"sql\": 1}").0);
+        assert_eq!(405, request(&db, "GET /query HTTP/1.1\r\n\r\n").0);
+        assert_eq!(404, request(&db, "GET /nope HTTP/1.1\r\n\r\n").0);
+        assert_eq!(200, request(&db, "GET /health HTTP/1.1\r\n\r\n").0);
+        let (status, metrics) = request(&db, "GET /metrics HTTP/1.0\r\n\r\n");
+        assert_eq!(200, status);
+        assert!(metrics.contains("\nneru7db_tables 1\n"), "{metrics}");
+
+        post(&db, "", "CREATE USER ann PASSWORD 'pw'");
+        assert_eq!(401, post(&db, "", "SELECT 1").0);
+        // "ann:pw" and "ann:no".
+        assert_eq!(
+            200,
+            post(&db, "Authorization: Basic YW5uOnB3\r\n", "SELECT 1").0
+        );
+        assert_eq!(
+            401,
+            post(&db, "Authorization: Basic YW5uOm5v\r\n", "SELECT 1").0
+        );
+        assert_eq!(
+            400,
+            post(&db, "Authorization: Basic YW5uOnB3\r\n", "SELECT * FROM t").0
+        );
+    }
+
+    #[test]
+    fn test_shared_password() {
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        let config = pgwire::Config {
+            password: Some("pw".to_string()),
+            ..pgwire::Config::default()
+        };
+        let post = |headers: &str| {
+            let text =
+                format!("POST /query HTTP/1.1\r\nContent-Length: 8\r\n{headers}\r\nSELECT 1");
+            request_with(&db, &config, &text).0
+        };
+        assert_eq!(401, post(""));
+        // "ann:pw" and "ann:no".
+        assert_eq!(200, post("Authorization: Basic YW5uOnB3\r\n"));
+        assert_eq!(401, post("Authorization: Basic YW5uOm5v\r\n"));
+    }
+}
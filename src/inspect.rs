@@ -0,0 +1,629 @@
+//! Looking inside a database file, for debugging the storage layer.
+//!
+//! Pages do not record what they hold, so [`page_map`] works it out by
+//! walking the catalog: its own heap, then every table's heap chain and
+//! every index's tree, and then the free list. The dumps read page bytes without trusting them, so
+//! that a damaged page is shown for what it is rather than panicking.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Write};
+
+use crate::btree::{node, BTree};
+use crate::buffer::{self, BufferPoolManager};
+use crate::catalog::{Catalog, CATALOG_PAGE_ID, FREE_LIST_RANGE};
+use crate::disk::{PageId, PAGE_SIZE};
+use crate::expr::Expr;
+use crate::heap::{self, HeapFile, Rid};
+use crate::slotted;
+use crate::tuple;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("page {0} is past the end of the file")]
+    NoSuchPage(u64),
+    #[error(transparent)]
+    Buffer(#[from] buffer::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageKind {
+    HeapMeta,
+    HeapData,
+    BTreeMeta,
+    BTreeLeaf,
+    BTreeBranch,
+    /// The page heading the free list.
+    FreeList,
+    Free,
+}
+
+impl fmt::Display for PageKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            PageKind::HeapMeta => "heap meta",
+            PageKind::HeapData => "heap data",
+            PageKind::BTreeMeta => "btree meta",
+            PageKind::BTreeLeaf => "btree leaf",
+            PageKind::BTreeBranch => "btree branch",
+            PageKind::FreeList => "free list",
+            PageKind::Free => "free",
+        })
+    }
+}
+
+/// What a page is and what it belongs to, such as `table t`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageOwner {
+    pub kind: PageKind,
+    pub object: String,
+}
+
+fn fetch(bufmgr: &BufferPoolManager, page_id: PageId) -> Result<Box<[u8]>, Error> {
+    if page_id.to_u64() >= bufmgr.num_pages() {
+        return Err(Error::NoSuchPage(page_id.to_u64()));
+    }
+    Ok(Box::new(*bufmgr.fetch_page(page_id)?.read()))
+}
+
+pub(crate) fn read_u16(page: &[u8], offset: usize) -> Option<usize> {
+    let bytes = page.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+pub(crate) fn read_page_id(page: &[u8], offset: usize) -> Option<PageId> {
+    PageId::from_bytes(&page[offset..offset + 8]).valid()
+}
+
+fn format_page_id(page_id: Option<PageId>) -> String {
+    match page_id {
+        Some(page_id) => page_id.to_u64().to_string(),
+        None => "none".to_string(),
+    }
+}
+
+/// The records of the slotted area starting at `base`, as page offsets, or
+/// `None` for a pointer that leads outside the area.
+fn slots(page: &[u8], base: usize) -> Vec<Option<(usize, usize)>> {
+    let num_slots = read_u16(page, base).unwrap_or(0);
+    let data_start = base + slotted::HEADER_SIZE;
+    (0..num_slots)
+        .map(|slot_id| {
+            let pointer = data_start + slot_id * slotted::POINTER_SIZE;
+            let offset = read_u16(page, pointer)?;
+            let len = read_u16(page, pointer + 2)?;
+            let start = data_start + offset;
+            (start + len <= page.len()).then_some((start, len))
+        })
+        .collect()
+}
+
+/// Which page is which, found by following the catalog and the free
+/// list. Pages none of them reaches are left out.
+pub fn page_map(
+    bufmgr: &BufferPoolManager,
+    catalog: &Catalog,
+) -> Result<BTreeMap<PageId, PageOwner>, Error> {
+    let mut map = BTreeMap::new();
+    if bufmgr.num_pages() > CATALOG_PAGE_ID.to_u64() {
+        map_heap(bufmgr, HeapFile::new(CATALOG_PAGE_ID), "catalog", &mut map)?;
+    }
+    for table in catalog.tables() {
+        map_heap(
+            bufmgr,
+            table.heap,
+            &format!("table {}", table.name),
+            &mut map,
+        )?;
+        for index in &table.indexes {
+            let object = format!("index {}", index.name);
+            map_btree(bufmgr, index.btree, &object, &mut map)?;
+        }
+    }
+    for pending in catalog.pending_indexes() {
+        let object = format!("pending index {}", pending.index.name);
+        map_btree(bufmgr, pending.index.btree, &object, &mut map)?;
+    }
+    if bufmgr.num_pages() > CATALOG_PAGE_ID.to_u64() {
+        map_free_list(bufmgr, &mut map)?;
+    }
+    Ok(map)
+}
+
+fn map_free_list(
+    bufmgr: &BufferPoolManager,
+    map: &mut BTreeMap<PageId, PageOwner>,
+) -> Result<(), Error> {
+    let owner = |kind| PageOwner {
+        kind,
+        object: "free list".to_string(),
+    };
+    let meta = fetch(bufmgr, CATALOG_PAGE_ID)?;
+    let Some(list) = read_page_id(&meta, FREE_LIST_RANGE.start).filter(|&id| id != PageId(0))
+    else {
+        return Ok(());
+    };
+    map.insert(list, owner(PageKind::FreeList));
+    let mut page_id = buffer::read_free_list(&fetch(bufmgr, list)?).0;
+    while let Some(id) = page_id.filter(|id| id.to_u64() < bufmgr.num_pages()) {
+        if map.insert(id, owner(PageKind::Free)).is_some() {
+            break;
+        }
+        page_id = read_page_id(&fetch(bufmgr, id)?, 0);
+    }
+    Ok(())
+}
+
+fn map_heap(
+    bufmgr: &BufferPoolManager,
+    heap: HeapFile,
+    object: &str,
+    map: &mut BTreeMap<PageId, PageOwner>,
+) -> Result<(), Error> {
+    let mut add = |page_id, kind| {
+        let owner = PageOwner {
+            kind,
+            object: object.to_string(),
+        };
+        map.insert(page_id, owner).is_none()
+    };
+    add(heap.meta_page_id, PageKind::HeapMeta);
+    let meta = fetch(bufmgr, heap.meta_page_id)?;
+    let mut page_id = read_page_id(&meta, 0);
+    // A page seen twice means the chain loops back on itself.
+    while let Some(id) = page_id.filter(|id| id.to_u64() < bufmgr.num_pages()) {
+        if !add(id, PageKind::HeapData) {
+            break;
+        }
+        page_id = read_page_id(&fetch(bufmgr, id)?, 0);
+    }
+    Ok(())
+}
+
+fn map_btree(
+    bufmgr: &BufferPoolManager,
+    btree: BTree,
+    object: &str,
+    map: &mut BTreeMap<PageId, PageOwner>,
+) -> Result<(), Error> {
+    let owner = |kind| PageOwner {
+        kind,
+        object: object.to_string(),
+    };
+    map.insert(btree.meta_page_id, owner(PageKind::BTreeMeta));
+    let meta = fetch(bufmgr, btree.meta_page_id)?;
+    let mut stack: Vec<PageId> = read_page_id(&meta, 0).into_iter().collect();
+    while let Some(page_id) = stack.pop() {
+        if page_id.to_u64() >= bufmgr.num_pages() || map.contains_key(&page_id) {
+            continue;
+        }
+        let page = fetch(bufmgr, page_id)?;
+        match page[0] {
+            node::NODE_TYPE_LEAF => {
+                map.insert(page_id, owner(PageKind::BTreeLeaf));
+            }
+            node::NODE_TYPE_BRANCH => {
+                map.insert(page_id, owner(PageKind::BTreeBranch));
+                stack.extend(read_page_id(&page, 8));
+                for (_, value) in btree_pairs(&page) {
+                    if value.len() == 8 {
+                        stack.extend(PageId::from_bytes(value).valid());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The pairs of a node that can be read; damaged slots are skipped.
+fn btree_pairs(page: &[u8]) -> Vec<(&[u8], &[u8])> {
+    slots(page, node::HEADER_SIZE)
+        .into_iter()
+        .flatten()
+        .filter_map(|(start, len)| split_pair(&page[start..start + len]))
+        .collect()
+}
+
+pub(crate) fn split_pair(record: &[u8]) -> Option<(&[u8], &[u8])> {
+    let key_len = read_u16(record, 0)?;
+    let rest = record.get(node::PAIR_HEADER_SIZE..)?;
+    (key_len <= rest.len()).then(|| rest.split_at(key_len))
+}
+
+/// Lines of hex and ASCII, 16 bytes each, labelled with their offset in
+/// the page, `base`. Runs of identical lines are folded into a `*`, as
+/// `hexdump` does.
+pub fn hex_dump(bytes: &[u8], base: usize) -> String {
+    let mut out = String::new();
+    let mut previous: Option<&[u8]> = None;
+    let mut folded = false;
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        if previous == Some(chunk) && chunk.len() == 16 {
+            if !folded {
+                out.push_str("  *\n");
+                folded = true;
+            }
+            continue;
+        }
+        previous = Some(chunk);
+        folded = false;
+        write!(out, "  {:04x} ", base + i * 16).unwrap();
+        for j in 0..16 {
+            match chunk.get(j) {
+                Some(byte) => write!(out, " {byte:02x}").unwrap(),
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        for &byte in chunk {
+            out.push(if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            });
+        }
+        out.push_str("|\n");
+    }
+    out
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn format_record(record: &[u8]) -> String {
+    match tuple::decode(record) {
+        Ok(values) => {
+            let values: Vec<String> = values
+                .into_iter()
+                .map(|value| Expr::Literal(value).to_string())
+                .collect();
+            format!("({})", values.join(", "))
+        }
+        Err(e) => format!("<{e}>"),
+    }
+}
+
+/// A page's header, slot directory and cells, decoded as `kind` says and
+/// in hex; without a kind all there is to show is the hex.
+pub fn dump_page(
+    bufmgr: &BufferPoolManager,
+    page_id: PageId,
+    kind: Option<PageKind>,
+) -> Result<String, Error> {
+    let page = fetch(bufmgr, page_id)?;
+    let mut out = match kind {
+        Some(kind) => format!("page {}: {kind}\n", page_id.to_u64()),
+        None => format!("page {}: not reached from the catalog\n", page_id.to_u64()),
+    };
+    match kind {
+        Some(PageKind::HeapMeta) => {
+            writeln!(
+                out,
+                "first page: {}",
+                format_page_id(read_page_id(&page, 0))
+            )
+            .unwrap();
+            writeln!(out, "last page: {}", format_page_id(read_page_id(&page, 8))).unwrap();
+            out.push_str(&hex_dump(&page[..16], 0));
+        }
+        Some(PageKind::FreeList) => {
+            let (head, count) = buffer::read_free_list(&page);
+            writeln!(out, "first free page: {}", format_page_id(head)).unwrap();
+            writeln!(out, "free pages: {count}").unwrap();
+            out.push_str(&hex_dump(&page[..16], 0));
+        }
+        Some(PageKind::Free) => {
+            let next = read_page_id(&page, 0);
+            writeln!(out, "next free page: {}", format_page_id(next)).unwrap();
+            out.push_str(&hex_dump(&page[..8], 0));
+        }
+        Some(PageKind::BTreeMeta) => {
+            writeln!(out, "root page: {}", format_page_id(read_page_id(&page, 0))).unwrap();
+            out.push_str(&hex_dump(&page[..8], 0));
+        }
+        Some(PageKind::HeapData) => {
+            let next = read_page_id(&page, 0);
+            writeln!(out, "next page: {}", format_page_id(next)).unwrap();
+            dump_slotted(&mut out, &page, heap::PAGE_HEADER_SIZE, |out, record| {
+                if record.is_empty() {
+                    out.push_str(" (deleted)\n");
+                } else {
+                    writeln!(out, " {}", format_record(record)).unwrap();
+                }
+            });
+        }
+        Some(PageKind::BTreeLeaf | PageKind::BTreeBranch) => {
+            if page[0] == node::NODE_TYPE_LEAF {
+                writeln!(out, "prev page: {}", format_page_id(read_page_id(&page, 8))).unwrap();
+                writeln!(
+                    out,
+                    "next page: {}",
+                    format_page_id(read_page_id(&page, 16))
+                )
+                .unwrap();
+            } else {
+                writeln!(
+                    out,
+                    "right child: {}",
+                    format_page_id(read_page_id(&page, 8))
+                )
+                .unwrap();
+            }
+            let leaf = page[0] == node::NODE_TYPE_LEAF;
+            dump_slotted(
+                &mut out,
+                &page,
+                node::HEADER_SIZE,
+                |out, record| match split_pair(record) {
+                    Some((key, value)) => {
+                        writeln!(out, " key {} -> {}", hex(key), format_value(value, leaf)).unwrap()
+                    }
+                    None => out.push_str(" <bad pair header>\n"),
+                },
+            );
+        }
+        None => out.push_str(&hex_dump(&page, 0)),
+    }
+    Ok(out)
+}
+
+/// A b-tree value: a child page in a branch, in a leaf usually a rid.
+fn format_value(value: &[u8], leaf: bool) -> String {
+    match (leaf, value.len()) {
+        (false, 8) => format!(
+            "child {}",
+            format_page_id(PageId::from_bytes(value).valid())
+        ),
+        (true, Rid::SIZE) => {
+            let rid = Rid::from_bytes(value);
+            format!("rid ({}, {})", rid.page_id.to_u64(), rid.slot_id)
+        }
+        _ => hex(value),
+    }
+}
+
+/// The header of the slotted area at `base`, then each slot with its
+/// record, described by `cell` and in hex.
+fn dump_slotted(
+    out: &mut String,
+    page: &[u8],
+    base: usize,
+    mut cell: impl FnMut(&mut String, &[u8]),
+) {
+    let num_slots = read_u16(page, base).unwrap_or(0);
+    let free_end = read_u16(page, base + 2).unwrap_or(0);
+    let pointers_end = base + slotted::HEADER_SIZE + num_slots * slotted::POINTER_SIZE;
+    writeln!(
+        out,
+        "slots: {num_slots}, free space {} bytes",
+        free_end.saturating_sub(num_slots * slotted::POINTER_SIZE)
+    )
+    .unwrap();
+    if pointers_end > PAGE_SIZE {
+        out.push_str(&hex_dump(&page[..base + slotted::HEADER_SIZE], 0));
+        out.push_str("slot directory runs past the end of the page\n");
+        return;
+    }
+    // The header and the slot directory.
+    out.push_str(&hex_dump(&page[..pointers_end], 0));
+    for (slot_id, slot) in slots(page, base).into_iter().enumerate() {
+        match slot {
+            Some((start, len)) => {
+                write!(out, "slot {slot_id}: offset {start}, {len} bytes:").unwrap();
+                let record = &page[start..start + len];
+                cell(out, record);
+                out.push_str(&hex_dump(record, start));
+            }
+            None => writeln!(out, "slot {slot_id}: points outside the page").unwrap(),
+        }
+    }
+}
+
+/// Every page of a heap's chain with its live tuples, by rid.
+pub fn walk_heap(bufmgr: &BufferPoolManager, heap: HeapFile) -> Result<String, Error> {
+    let meta = fetch(bufmgr, heap.meta_page_id)?;
+    let mut out = format!(
+        "heap {}: first page {}, last page {}\n",
+        heap.meta_page_id.to_u64(),
+        format_page_id(read_page_id(&meta, 0)),
+        format_page_id(read_page_id(&meta, 8)),
+    );
+    let mut seen = HashSet::new();
+    let mut page_id = read_page_id(&meta, 0);
+    while let Some(id) = page_id {
+        if !seen.insert(id) {
+            writeln!(out, "page {} again: the chain loops", id.to_u64()).unwrap();
+            break;
+        }
+        let page = fetch(bufmgr, id)?;
+        let slots = slots(&page, heap::PAGE_HEADER_SIZE);
+        let live = slots.iter().flatten().filter(|(_, len)| *len > 0).count();
+        writeln!(
+            out,
+            "page {}: {} slots, {live} live",
+            id.to_u64(),
+            slots.len()
+        )
+        .unwrap();
+        for (slot_id, slot) in slots.into_iter().enumerate() {
+            match slot {
+                Some((_, 0)) => {}
+                Some((start, len)) => writeln!(
+                    out,
+                    "  ({}, {slot_id}) {}",
+                    id.to_u64(),
+                    format_record(&page[start..start + len])
+                )
+                .unwrap(),
+                None => writeln!(out, "  ({}, {slot_id}) <outside the page>", id.to_u64()).unwrap(),
+            }
+        }
+        page_id = read_page_id(&page, 0);
+    }
+    Ok(out)
+}
+
+/// The nodes of a tree from its root down, indented by depth.
+pub fn walk_btree(bufmgr: &BufferPoolManager, btree: BTree) -> Result<String, Error> {
+    let meta = fetch(bufmgr, btree.meta_page_id)?;
+    let root = read_page_id(&meta, 0);
+    let mut out = format!(
+        "btree {}: root page {}\n",
+        btree.meta_page_id.to_u64(),
+        format_page_id(root)
+    );
+    if let Some(root) = root {
+        walk_node(bufmgr, root, 1, &mut HashSet::new(), &mut out)?;
+    }
+    Ok(out)
+}
+
+fn walk_node(
+    bufmgr: &BufferPoolManager,
+    page_id: PageId,
+    depth: usize,
+    seen: &mut HashSet<PageId>,
+    out: &mut String,
+) -> Result<(), Error> {
+    let indent = "  ".repeat(depth);
+    if !seen.insert(page_id) {
+        writeln!(
+            out,
+            "{indent}page {} again: the tree loops",
+            page_id.to_u64()
+        )
+        .unwrap();
+        return Ok(());
+    }
+    let page = fetch(bufmgr, page_id)?;
+    let pairs = btree_pairs(&page);
+    match page[0] {
+        node::NODE_TYPE_LEAF => {
+            writeln!(
+                out,
+                "{indent}page {}: leaf, {} pairs, prev {}, next {}",
+                page_id.to_u64(),
+                pairs.len(),
+                format_page_id(read_page_id(&page, 8)),
+                format_page_id(read_page_id(&page, 16)),
+            )
+            .unwrap();
+            for (key, value) in pairs {
+                writeln!(
+                    out,
+                    "{indent}  {} -> {}",
+                    hex(key),
+                    format_value(value, true)
+                )
+                .unwrap();
+            }
+        }
+        node::NODE_TYPE_BRANCH => {
+            writeln!(
+                out,
+                "{indent}page {}: branch, {} keys",
+                page_id.to_u64(),
+                pairs.len()
+            )
+            .unwrap();
+            for (key, value) in pairs {
+                writeln!(out, "{indent}  below {}:", hex(key)).unwrap();
+                match PageId::from_bytes(value).valid() {
+                    Some(child) if value.len() == 8 => {
+                        walk_node(bufmgr, child, depth + 2, seen, out)?
+                    }
+                    _ => writeln!(out, "{indent}    <bad child {}>", hex(value)).unwrap(),
+                }
+            }
+            writeln!(out, "{indent}  rest:").unwrap();
+            match read_page_id(&page, 8) {
+                Some(child) => walk_node(bufmgr, child, depth + 2, seen, out)?,
+                None => writeln!(out, "{indent}    <no right child>").unwrap(),
+            }
+        }
+        other => writeln!(
+            out,
+            "{indent}page {}: not a node (type byte {other:#04x})",
+            page_id.to_u64()
+        )
+        .unwrap(),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::DiskManager;
+    use crate::engine::Engine;
+
+    #[test]
+    fn test_inspect() {
+        let disk = DiskManager::new(tempfile::tempfile().unwrap()).unwrap();
+        let mut engine = Engine::open(BufferPoolManager::new(disk, 64)).unwrap();
+        engine
+            .execute("CREATE TABLE t (id INT PRIMARY KEY, name TEXT)")
+            .unwrap();
+        engine
+            .execute("INSERT INTO t VALUES (1, 'one'), (2, 'it''s')")
+            .unwrap();
+        engine.execute("DELETE FROM t WHERE id = 1").unwrap();
+        let bufmgr = engine.bufmgr();
+        let table = engine.catalog().table("t").unwrap();
+
+        let map = page_map(bufmgr, engine.catalog()).unwrap();
+        assert_eq!(PageKind::HeapMeta, map[&CATALOG_PAGE_ID].kind);
+        assert_eq!("catalog", map[&CATALOG_PAGE_ID].object);
+        let owner = &map[&table.heap.meta_page_id];
+        assert_eq!(
+            (PageKind::HeapMeta, "table t"),
+            (owner.kind, owner.object.as_str())
+        );
+        let index = &table.indexes[0];
+        assert_eq!(PageKind::BTreeMeta, map[&index.btree.meta_page_id].kind);
+        assert_eq!(bufmgr.num_pages() as usize, map.len());
+
+        let data_page_id = table.heap.first_page_id(bufmgr).unwrap();
+        let dump = dump_page(bufmgr, data_page_id, Some(PageKind::HeapData)).unwrap();
+        assert!(dump.contains("slots: 2, "), "{dump}");
+        assert!(
+            dump.contains("slot 0: offset 4096, 0 bytes: (deleted)\n"),
+            "{dump}"
+        );
+        assert!(dump.contains(" (2, 'it''s')\n"), "{dump}");
+        assert!(dump.contains("69 74  |..............it|\n"), "{dump}");
+
+        let heap = walk_heap(bufmgr, table.heap).unwrap();
+        let data_page = data_page_id.to_u64();
+        assert!(
+            heap.contains(&format!("page {data_page}: 2 slots, 1 live\n")),
+            "{heap}"
+        );
+        assert!(
+            heap.contains(&format!("  ({data_page}, 1) (2, 'it''s')\n")),
+            "{heap}"
+        );
+        let tree = walk_btree(bufmgr, index.btree).unwrap();
+        assert!(
+            tree.contains("leaf, 1 pairs, prev none, next none\n"),
+            "{tree}"
+        );
+        assert!(
+            tree.contains(&format!("-> rid ({data_page}, 1)\n")),
+            "{tree}"
+        );
+
+        assert!(matches!(
+            dump_page(bufmgr, PageId(bufmgr.num_pages()), None),
+            Err(Error::NoSuchPage(_))
+        ));
+        assert_eq!(
+            "  0000  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  |................|\n  \
+             *\n  \
+             0020  41 42                                            |AB|\n",
+            hex_dump(&[[0; 32].as_slice(), b"AB"].concat(), 0)
+        );
+    }
+}
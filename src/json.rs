@@ -0,0 +1,440 @@
+//! Just enough JSON for the HTTP endpoint, COPY and the JSON type.
+//!
+//! A value of the JSON type is kept [normalized](Json::normalize), as
+//! Postgres keeps `jsonb`: each object has one member per key, in order
+//! of key. Values order by type, from null through booleans, numbers,
+//! strings and arrays to objects, then by value; numbers compare as
+//! floats, and arrays and objects element by element.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Write};
+
+use crate::value::Value;
+
+/// Nesting allowed in documents read, so that a hostile one cannot
+/// exhaust the stack.
+pub const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// A number without a fraction or exponent that fits an i64.
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The element at `index` of an array, counting from the end if
+    /// `index` is negative.
+    pub fn element(&self, index: i64) -> Option<&Json> {
+        let Json::Array(items) = self else {
+            return None;
+        };
+        let index = if index < 0 {
+            items.len().checked_sub(index.unsigned_abs() as usize)?
+        } else {
+            index as usize
+        };
+        items.get(index)
+    }
+
+    /// What `json_typeof` calls the value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Int(_) | Json::Float(_) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// Sorts the members of every object by key, keeping the last of
+    /// those with the same key.
+    pub fn normalize(self) -> Json {
+        match self {
+            Json::Array(items) => Json::Array(items.into_iter().map(Json::normalize).collect()),
+            Json::Object(members) => {
+                let mut normalized: Vec<(String, Json)> = vec![];
+                for (key, value) in members {
+                    let value = value.normalize();
+                    match normalized.binary_search_by(|(k, _)| k.as_str().cmp(&key)) {
+                        Ok(i) => normalized[i].1 = value,
+                        Err(i) => normalized.insert(i, (key, value)),
+                    }
+                }
+                Json::Object(normalized)
+            }
+            json => json,
+        }
+    }
+
+    /// The order of values described in the [module documentation](self).
+    pub fn total_cmp(&self, other: &Json) -> Ordering {
+        fn rank(json: &Json) -> u8 {
+            match json {
+                Json::Null => 0,
+                Json::Bool(_) => 1,
+                Json::Int(_) | Json::Float(_) => 2,
+                Json::String(_) => 3,
+                Json::Array(_) => 4,
+                Json::Object(_) => 5,
+            }
+        }
+        match (self, other) {
+            (Json::Bool(a), Json::Bool(b)) => a.cmp(b),
+            (Json::String(a), Json::String(b)) => a.cmp(b),
+            (Json::Array(a), Json::Array(b)) => (a.iter().zip(b))
+                .map(|(a, b)| a.total_cmp(b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            (Json::Object(a), Json::Object(b)) => (a.iter().zip(b))
+                .map(|((ka, va), (kb, vb))| ka.cmp(kb).then_with(|| va.total_cmp(vb)))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len())),
+            _ => match (self.as_f64(), other.as_f64()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                _ => rank(self).cmp(&rank(other)),
+            },
+        }
+    }
+
+    /// The value of a number, as numbers compare.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Int(i) => Some(*i as f64),
+            Json::Float(x) => Some(*x),
+            _ => None,
+        }
+    }
+}
+
+/// Writes the value as compact JSON text.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Int(i) => write!(f, "{i}"),
+            Json::Float(x) => write!(f, "{x:?}"),
+            Json::String(s) => {
+                let mut out = String::new();
+                write_string(&mut out, s);
+                f.write_str(&out)
+            }
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(members) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    let mut out = String::new();
+                    write_string(&mut out, key);
+                    write!(f, "{out}: {value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// Parses a whole document; the error says what was wrong and where.
+pub fn parse(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let json = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(parser.error("end of input"));
+    }
+    Ok(json)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, expected: &str) -> String {
+        format!("expected {expected} at byte {}", self.pos)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, literal: &str) -> bool {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Json, String> {
+        if depth > MAX_DEPTH {
+            return Err("document is nested too deeply".to_string());
+        }
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut members = vec![];
+                self.skip_whitespace();
+                if self.eat("}") {
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.skip_whitespace();
+                    if !self.eat(":") {
+                        return Err(self.error("':'"));
+                    }
+                    members.push((key, self.value(depth + 1)?));
+                    self.skip_whitespace();
+                    if self.eat("}") {
+                        return Ok(Json::Object(members));
+                    }
+                    if !self.eat(",") {
+                        return Err(self.error("',' or '}'"));
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = vec![];
+                self.skip_whitespace();
+                if self.eat("]") {
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    self.skip_whitespace();
+                    if self.eat("]") {
+                        return Ok(Json::Array(items));
+                    }
+                    if !self.eat(",") {
+                        return Err(self.error("',' or ']'"));
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.eat("null") => Ok(Json::Null),
+            _ if self.eat("true") => Ok(Json::Bool(true)),
+            _ if self.eat("false") => Ok(Json::Bool(false)),
+            _ => Err(self.error("a value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while let Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).unwrap();
+        if let Ok(i) = text.parse() {
+            return Ok(Json::Int(i));
+        }
+        match text.parse::<f64>() {
+            Ok(x) if x.is_finite() => Ok(Json::Float(x)),
+            _ => {
+                self.pos = start;
+                Err(self.error("a number"))
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if !self.eat("\"") {
+            return Err(self.error("a string"));
+        }
+        let mut bytes = vec![];
+        loop {
+            let Some(&b) = self.bytes.get(self.pos) else {
+                return Err(self.error("'\"'"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.bytes.get(self.pos) else {
+                        return Err(self.error("an escape"));
+                    };
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("an escape")),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                _ => bytes.push(b),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| "string is not UTF-8".to_string())
+    }
+
+    /// The character of a `\uXXXX` escape, or of a surrogate pair of them.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let unit = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&unit) {
+            if !self.eat("\\u") {
+                return Err(self.error("a low surrogate"));
+            }
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("a low surrogate"));
+            }
+            0x10000 + ((unit - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            unit
+        };
+        char::from_u32(code).ok_or_else(|| self.error("a character"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let digits = self
+            .bytes
+            .get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("four hex digits"))?;
+        self.pos += 4;
+        Ok(digits)
+    }
+}
+
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Writes `value` as JSON, a JSON value as it is and an array as a JSON
+/// array. Floats JSON cannot hold and byte strings are written as
+/// strings in their Postgres text form.
+pub fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => write!(out, "{b}").unwrap(),
+        Value::Int(i) => write!(out, "{i}").unwrap(),
+        Value::Float(x) if x.is_finite() => write!(out, "{x:?}").unwrap(),
+        Value::Float(x) if x.is_nan() => out.push_str("\"NaN\""),
+        Value::Float(x) => out.push_str(if *x > 0.0 {
+            "\"Infinity\""
+        } else {
+            "\"-Infinity\""
+        }),
+        Value::Text(s) => write_string(out, s),
+        Value::Bytes(bytes) => {
+            let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+            write_string(out, &format!("\\x{hex}"));
+        }
+        Value::Json(json) => write!(out, "{json}").unwrap(),
+        Value::Array(array) => {
+            out.push('[');
+            for (i, item) in array.items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_value(out, item);
+            }
+            out.push(']');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_write() {
+        let json =
+            parse(r#" {"sql": "SELECT $1", "params": [1, -2.5e1, "é😀\n", true, null, []]} "#)
+                .unwrap();
+        assert_eq!(Some(&Json::String("SELECT $1".into())), json.get("sql"));
+        assert_eq!(
+            Some(&Json::Array(vec![
+                Json::Int(1),
+                Json::Float(-25.0),
+                Json::String("é😀\n".into()),
+                Json::Bool(true),
+                Json::Null,
+                Json::Array(vec![]),
+            ])),
+            json.get("params")
+        );
+        assert!(parse("[1,]").is_err());
+        assert!(parse("{} x").is_err());
+        assert!(parse(&"[".repeat(100)).is_err());
+        assert!(parse("1e999").is_err());
+
+        let json = parse(r#"{"b": 1, "a": [true, {"y": 2, "x": 1}], "b": "last"}"#).unwrap();
+        assert_eq!(
+            r#"{"a": [true, {"x": 1, "y": 2}], "b": "last"}"#,
+            json.normalize().to_string()
+        );
+        assert_eq!(Ordering::Equal, Json::Int(2).total_cmp(&Json::Float(2.0)));
+        assert_eq!(Some(&Json::Int(3)), parse("[1, 2, 3]").unwrap().element(-1));
+
+        let mut out = String::new();
+        for value in [
+            Value::Float(1.0),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Text("a\"\u{1}".into()),
+            Value::Bytes(vec![0xde, 0xad]),
+        ] {
+            write_value(&mut out, &value);
+            out.push(' ');
+        }
+        assert_eq!(r#"1.0 "-Infinity" "a\"\u0001" "\\xdead" "#, out);
+    }
+}
@@ -0,0 +1,40 @@
+pub mod array;
+pub mod auth;
+pub mod backup;
+pub mod bench;
+pub mod blob;
+pub mod btree;
+pub mod buffer;
+pub mod catalog;
+pub mod check;
+pub mod collation;
+pub mod csv;
+pub mod database;
+pub mod disk;
+pub mod dump;
+pub mod engine;
+pub mod error;
+pub mod executor;
+pub mod expr;
+pub mod fuzz;
+pub mod heap;
+pub mod http;
+pub mod inspect;
+pub mod json;
+pub mod lock;
+pub mod metrics;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod pgwire;
+pub mod planner;
+#[cfg(test)]
+mod prop;
+pub mod server;
+pub mod sim;
+pub mod slotted;
+pub mod sql;
+pub mod sqlite;
+pub mod stats;
+pub mod trace;
+pub mod tuple;
+pub mod value;
@@ -0,0 +1,392 @@
+//! Row locks, held until the transaction that took them ends.
+//!
+//! `SELECT ... FOR UPDATE`, `FOR NO KEY UPDATE`, `FOR SHARE` and `FOR KEY
+//! SHARE` lock the rows they return, UPDATE the rows it changes (in `FOR
+//! NO KEY UPDATE` mode unless it changes a column of a unique index) and
+//! DELETE the rows it removes (in `FOR UPDATE` mode). Two transactions can
+//! hold locks on one row only in modes that do not conflict:
+//!
+//! | held \ asked    | KEY SHARE | SHARE | NO KEY UPDATE | UPDATE |
+//! |-----------------|-----------|-------|---------------|--------|
+//! | FOR KEY SHARE   |           |       |               | X      |
+//! | FOR SHARE       |           |       | X             | X      |
+//! | FOR NO KEY UPDATE |         | X     | X             | X      |
+//! | FOR UPDATE      | X         | X     | X             | X      |
+//!
+//! A [`LockManager`] keeps the locks of every transaction, by table and
+//! rid, and what the waiting ones wait for. Rows are locked while a
+//! statement runs, with its session holding the engine, which the
+//! transaction holding a conflicting lock needs in order to end. So a
+//! statement does not wait in place: it fails with [`Error::Wait`], whose
+//! [`RowWait`] its session waits on once it has let go of the engine,
+//! before running the statement again. A wait that would close a cycle of
+//! transactions waiting for each other is a deadlock, and fails at once
+//! with [`Error::Deadlock`] instead, as does one that nothing could end
+//! because the session cannot let go of the engine.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::heap::Rid;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("could not obtain lock on row in relation {0:?}")]
+    NotAvailable(String),
+    #[error("deadlock detected: the transaction waited for one waiting for it")]
+    Deadlock,
+    #[error("canceling statement due to lock timeout: waited {0:?} for a row lock")]
+    Timeout(Duration),
+    #[error("row in relation {:?} is locked by another transaction", .0.table)]
+    Wait(RowWait),
+}
+
+/// How strongly a row is locked, weakest first. Each mode conflicts with
+/// every mode the one before it does, and more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LockMode {
+    KeyShare,
+    Share,
+    NoKeyUpdate,
+    Update,
+}
+
+impl LockMode {
+    /// Whether two transactions cannot hold a row in `self` and `other`.
+    pub fn conflicts_with(self, other: LockMode) -> bool {
+        use LockMode::*;
+        match (self, other) {
+            (Update, _) | (_, Update) => true,
+            (KeyShare, _) | (_, KeyShare) => false,
+            (Share, Share) => false,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for LockMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LockMode::KeyShare => "FOR KEY SHARE",
+            LockMode::Share => "FOR SHARE",
+            LockMode::NoKeyUpdate => "FOR NO KEY UPDATE",
+            LockMode::Update => "FOR UPDATE",
+        })
+    }
+}
+
+/// What a statement does about a row another transaction has locked in
+/// a conflicting mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockWait {
+    /// Waits for it, up to the session's `lock_timeout`.
+    Wait,
+    /// `NOWAIT`: fails at once.
+    NoWait,
+    /// `SKIP LOCKED`: leaves the row out.
+    SkipLocked,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The transactions holding each locked row, with their mode.
+    rows: HashMap<(String, Rid), Vec<(u64, LockMode)>>,
+    /// The rows each transaction holds.
+    held: HashMap<u64, HashSet<(String, Rid)>>,
+    /// The transactions each waiting transaction waits for.
+    waits: HashMap<u64, Vec<u64>>,
+}
+
+impl State {
+    /// Whether a transaction in `from`, or one they wait for, waits for
+    /// `target`.
+    fn reaches(&self, from: &[u64], target: u64) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = from.to_vec();
+        while let Some(owner) = stack.pop() {
+            if owner == target {
+                return true;
+            }
+            if seen.insert(owner) {
+                stack.extend(self.waits.get(&owner).into_iter().flatten());
+            }
+        }
+        false
+    }
+}
+
+/// The row locks of an engine's transactions. Clones share them.
+#[derive(Debug, Clone, Default)]
+pub struct LockManager {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    state: Mutex<State>,
+    /// Notified whenever a transaction releases its locks.
+    released: Condvar,
+}
+
+impl Inner {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks row `rid` of `table` in `mode` for transaction `owner`.
+    /// Returns false if SKIP LOCKED leaves it out. Fails with
+    /// [`Error::Wait`] when it must wait, if `can_wait`; otherwise a wait
+    /// is a deadlock.
+    pub fn lock(
+        &self,
+        owner: u64,
+        table: &str,
+        rid: Rid,
+        mode: LockMode,
+        wait: LockWait,
+        can_wait: bool,
+    ) -> Result<bool, Error> {
+        let mut state = self.inner.state();
+        let state = &mut *state;
+        let key = (table.to_string(), rid);
+        let holders = state.rows.entry(key.clone()).or_default();
+        let blockers: Vec<u64> = (holders.iter())
+            .filter(|&&(holder, held)| holder != owner && held.conflicts_with(mode))
+            .map(|&(holder, _)| holder)
+            .collect();
+        if blockers.is_empty() {
+            match holders.iter_mut().find(|(holder, _)| *holder == owner) {
+                Some((_, held)) => *held = (*held).max(mode),
+                None => holders.push((owner, mode)),
+            }
+            state.held.entry(owner).or_default().insert(key);
+            return Ok(true);
+        }
+        match wait {
+            LockWait::NoWait => Err(Error::NotAvailable(table.to_string())),
+            LockWait::SkipLocked => Ok(false),
+            LockWait::Wait if !can_wait || state.reaches(&blockers, owner) => Err(Error::Deadlock),
+            LockWait::Wait => {
+                state.waits.insert(owner, blockers.clone());
+                Err(Error::Wait(RowWait {
+                    locks: self.clone(),
+                    owner,
+                    table: table.to_string(),
+                    blockers,
+                }))
+            }
+        }
+    }
+
+    /// Releases every lock `owner` holds, at the end of its transaction.
+    pub fn release(&self, owner: u64) {
+        let mut state = self.inner.state();
+        state.waits.remove(&owner);
+        let Some(rows) = state.held.remove(&owner) else {
+            return;
+        };
+        for key in rows {
+            if let Some(holders) = state.rows.get_mut(&key) {
+                holders.retain(|&(holder, _)| holder != owner);
+                if holders.is_empty() {
+                    state.rows.remove(&key);
+                }
+            }
+        }
+        drop(state);
+        self.inner.released.notify_all();
+    }
+
+    /// The rows `owner` holds locks on.
+    pub fn held(&self, owner: u64) -> usize {
+        self.inner.state().held.get(&owner).map_or(0, HashSet::len)
+    }
+}
+
+/// How the running statement locks rows: for which transaction, and
+/// whether it can wait.
+#[derive(Debug, Clone)]
+pub struct RowLocker {
+    pub locks: LockManager,
+    pub owner: u64,
+    /// Whether the session lets go of the engine while it waits, so that
+    /// the transactions waited for can end.
+    pub can_wait: bool,
+}
+
+impl RowLocker {
+    /// Locks row `rid` of `table` as [`LockManager::lock`] does.
+    pub fn lock(
+        &self,
+        table: &str,
+        rid: Rid,
+        mode: LockMode,
+        wait: LockWait,
+    ) -> Result<bool, Error> {
+        (self.locks).lock(self.owner, table, rid, mode, wait, self.can_wait)
+    }
+}
+
+/// A transaction waiting for the transactions that hold a row it asked
+/// for, until dropped.
+#[derive(Debug)]
+pub struct RowWait {
+    locks: LockManager,
+    owner: u64,
+    table: String,
+    blockers: Vec<u64>,
+}
+
+impl RowWait {
+    /// Blocks until one of the transactions waited for has ended, or
+    /// fails once `deadline` has passed, which `timeout` after the
+    /// statement started gives.
+    pub fn wait(self, deadline: Option<Instant>, timeout: Duration) -> Result<(), Error> {
+        let inner = &self.locks.inner;
+        let mut state = inner.state();
+        while self
+            .blockers
+            .iter()
+            .all(|owner| state.held.contains_key(owner))
+        {
+            state = match deadline {
+                None => inner
+                    .released
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(Error::Timeout(timeout));
+                    }
+                    let waited = inner.released.wait_timeout(state, deadline - now);
+                    waited.unwrap_or_else(PoisonError::into_inner).0
+                }
+            };
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RowWait {
+    fn drop(&mut self) {
+        let mut state = self.locks.inner.state();
+        if state.waits.get(&self.owner) == Some(&self.blockers) {
+            state.waits.remove(&self.owner);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::PageId;
+    use std::thread;
+
+    fn rid(slot_id: u16) -> Rid {
+        Rid {
+            page_id: PageId(1),
+            slot_id,
+        }
+    }
+
+    #[test]
+    fn test_conflicts() {
+        use LockMode::*;
+        let modes = [KeyShare, Share, NoKeyUpdate, Update];
+        let conflicts = [
+            [false, false, false, true],
+            [false, false, true, true],
+            [false, true, true, true],
+            [true, true, true, true],
+        ];
+        for (held, row) in modes.iter().zip(conflicts) {
+            for (asked, conflict) in modes.iter().zip(row) {
+                assert_eq!(conflict, held.conflicts_with(*asked), "{held} {asked}");
+            }
+        }
+
+        let locks = LockManager::new();
+        let lock = |owner, mode, wait| locks.lock(owner, "t", rid(0), mode, wait, true);
+        assert!(lock(1, Share, LockWait::Wait).unwrap());
+        assert!(lock(2, KeyShare, LockWait::NoWait).unwrap());
+        assert!(lock(3, Share, LockWait::NoWait).unwrap());
+        assert!(matches!(
+            lock(4, NoKeyUpdate, LockWait::NoWait),
+            Err(Error::NotAvailable(_))
+        ));
+        assert!(!lock(4, Update, LockWait::SkipLocked).unwrap());
+        // Its own lock is no conflict, but those of the others are.
+        assert!(matches!(
+            lock(1, Update, LockWait::Wait),
+            Err(Error::Wait(wait)) if wait.blockers == [2, 3]
+        ));
+        locks.release(2);
+        locks.release(3);
+        assert!(lock(1, Update, LockWait::Wait).unwrap());
+        assert!(matches!(
+            lock(2, KeyShare, LockWait::Wait),
+            Err(Error::Wait(_))
+        ));
+        assert_eq!((1, 0), (locks.held(1), locks.held(2)));
+    }
+
+    #[test]
+    fn test_wait_and_deadlock() {
+        let locks = LockManager::new();
+        let lock = |owner, slot, can_wait| {
+            locks.lock(
+                owner,
+                "t",
+                rid(slot),
+                LockMode::Update,
+                LockWait::Wait,
+                can_wait,
+            )
+        };
+        lock(1, 1, true).unwrap();
+        lock(2, 2, true).unwrap();
+        assert!(matches!(lock(2, 1, false), Err(Error::Deadlock)));
+
+        // 2 waits for 1; 1 asking for what 2 holds closes the cycle.
+        let Err(Error::Wait(wait)) = lock(2, 1, true) else {
+            panic!("expected a wait");
+        };
+        assert!(matches!(lock(1, 2, true), Err(Error::Deadlock)));
+        let timeout = Duration::from_millis(10);
+        let start = Instant::now();
+        let deadline = Some(start + timeout);
+        let Err(Error::Wait(wait_again)) = lock(2, 1, true) else {
+            panic!("expected a wait");
+        };
+        drop(wait);
+        assert!(matches!(
+            wait_again.wait(deadline, timeout),
+            Err(Error::Timeout(_))
+        ));
+        assert!(start.elapsed() >= timeout);
+
+        // Once 1 ends, 2 gets the row.
+        let Err(Error::Wait(wait)) = lock(2, 1, true) else {
+            panic!("expected a wait");
+        };
+        let releaser = locks.clone();
+        let ending = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            releaser.release(1);
+        });
+        wait.wait(None, Duration::ZERO).unwrap();
+        ending.join().unwrap();
+        assert!(lock(2, 1, true).unwrap());
+        // Nothing waits for 3, so its asking for what 2 holds is no deadlock.
+        assert!(matches!(lock(3, 2, true), Err(Error::Wait(_))));
+    }
+}
@@ -1,3 +1,179 @@
-fn main() {
-    println!("Hello, world!");
+//! Maintenance commands for Neru7DB files.
+//!
+//! `neru7db check FILE` checks the file offline and prints a report; the
+//! exit status is 0 if it found nothing wrong, 1 if it found problems and
+//! 2 if the file could not be checked at all.
+//!
+//! `neru7db backup FILE DEST` copies a database that no server has open
+//! to the new file DEST; a running server is backed up with `BACKUP TO
+//! 'path'` instead. Given a full backup and any incremental ones taken
+//! after it, it copies only the pages changed since. `neru7db restore`
+//! checks a full backup, with any incremental ones after it, and puts it
+//! in place of FILE, which must not be open.
+//!
+//! `neru7db dump FILE [SCRIPT]` writes SQL that recreates the tables,
+//! rows and indexes of a database no server has open, to SCRIPT or
+//! standard output; `neru7db load FILE SCRIPT` runs such a script, or any
+//! other, against FILE.
+//!
+//! `neru7db import-sqlite SQLITE FILE` recreates the tables, rows and
+//! indexes of a SQLite database in FILE, which no server may have open,
+//! and lists what it could not bring over.
+//!
+//! `neru7db upgrade FILE` upgrades a file in an older format, which no
+//! server may have open, to the one this build writes, after backing it
+//! up next to it. Opening the file does the same unless `auto_upgrade` is
+//! off.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::process::ExitCode;
+
+use neru7db::check;
+use neru7db::database::{upgrade, Database, Options};
+
+const USAGE: &str = "\
+usage: neru7db COMMAND
+  check FILE            check a database file for damage
+  backup FILE DEST [FULL [INCREMENT...]]
+                        copy a database, or the pages changed since the
+                        backups given, to a new file
+  restore FULL [INCREMENT...] FILE
+                        replace a database with a backup
+  dump FILE [SCRIPT]    write a database out as SQL
+  load FILE SCRIPT      run the SQL in a script against a database
+  import-sqlite SQLITE FILE
+                        copy the tables of a SQLite database into one
+  upgrade FILE          upgrade a database to the current file format";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.as_slice() {
+        [command, path] if command == "check" => match check::check_file(path) {
+            Ok(report) => {
+                print!("{report}");
+                if report.is_ok() {
+                    ExitCode::SUCCESS
+                } else {
+                    ExitCode::FAILURE
+                }
+            }
+            Err(e) => {
+                eprintln!("neru7db: cannot check {path}: {e}");
+                ExitCode::from(2)
+            }
+        },
+        [command, path, dest, chain @ ..] if command == "backup" => {
+            let result = Database::open(path, Options::default()).and_then(|db| {
+                if chain.is_empty() {
+                    db.backup_to(dest)?;
+                } else {
+                    db.backup_incremental(chain, dest)?;
+                }
+                db.close()
+            });
+            match result {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("neru7db: cannot back up {path}: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        [command, chain @ .., path] if command == "restore" && !chain.is_empty() => {
+            match Database::restore_chain(chain, path) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("neru7db: cannot restore {path}: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        [command, path, script @ ..] if command == "dump" && script.len() <= 1 => {
+            let result = Database::open(path, Options::default()).and_then(|db| {
+                match script {
+                    [script] => db.dump(BufWriter::new(File::create(script)?))?,
+                    _ => db.dump(io::stdout().lock())?,
+                }
+                db.close()
+            });
+            match result {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("neru7db: cannot dump {path}: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        [command, path, script] if command == "load" => {
+            let result = fs::read_to_string(script)
+                .map_err(Into::into)
+                .and_then(|script| {
+                    let mut db = Database::open(path, Options::default())?;
+                    db.execute_script(&script)?;
+                    db.close()
+                });
+            match result {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("neru7db: cannot load {script} into {path}: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        [command, source, path] if command == "import-sqlite" => {
+            let result = Database::open(path, Options::default()).and_then(|mut db| {
+                let import = db.import_sqlite(source)?;
+                db.close()?;
+                Ok(import)
+            });
+            match result {
+                Ok(import) => {
+                    for (table, rows) in &import.tables {
+                        let plural = if *rows == 1 { "" } else { "s" };
+                        println!("table {table}: {rows} row{plural}");
+                    }
+                    for index in &import.indexes {
+                        println!("index {index}");
+                    }
+                    for skipped in &import.skipped {
+                        println!("skipped {skipped}");
+                    }
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("neru7db: cannot import {source}: {e}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        [command, path] if command == "upgrade" => match upgrade::upgrade_file(path) {
+            Ok(Some(upgrade)) => {
+                println!(
+                    "upgraded from version {} to {}; the original is in {}",
+                    upgrade.from,
+                    upgrade::FORMAT_VERSION,
+                    upgrade.backup.display()
+                );
+                ExitCode::SUCCESS
+            }
+            Ok(None) => {
+                println!("{path} needs no upgrade");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("neru7db: cannot upgrade {path}: {e}");
+                ExitCode::FAILURE
+            }
+        },
+        [flag] if flag == "-h" || flag == "--help" => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::from(2)
+        }
+    }
 }
@@ -0,0 +1,371 @@
+//! Counters, gauges and histograms of one database, for monitoring.
+//!
+//! [`Engine::metrics`](crate::engine::Engine::metrics) takes a snapshot
+//! of them as [`Metrics`], which [`Metrics::to_prometheus`] writes in the
+//! Prometheus text format. The HTTP endpoint `GET /metrics` serves that
+//! along with the counters of the server.
+
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+use crate::buffer::BufferStats;
+use crate::engine::MaintenanceStats;
+use crate::executor::PoolStats;
+
+/// Upper bounds of the buckets of [`Metrics::statement_duration`].
+pub const DURATION_BUCKETS: [Duration; 10] = [
+    Duration::from_micros(100),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+/// How many durations fell in each of [`DURATION_BUCKETS`], and beyond.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    counts: [u64; DURATION_BUCKETS.len() + 1],
+    sum: Duration,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, duration: Duration) {
+        let bucket = DURATION_BUCKETS.partition_point(|&bound| bound < duration);
+        self.counts[bucket] += 1;
+        self.sum += duration;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Durations at most each bound, cumulative as in Prometheus; the
+    /// last bound is `None`, for all of them.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        let bounds = DURATION_BUCKETS.iter().copied().map(Some).chain([None]);
+        bounds.zip(self.counts.iter().scan(0, |total, count| {
+            *total += count;
+            Some(*total)
+        }))
+    }
+}
+
+/// A snapshot of the metrics of one database.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    pub buffer: BufferStats,
+    pub buffer_pool_pages: usize,
+    /// Pages in the database file.
+    pub pages: u64,
+    /// 1 while a transaction is open, as the engine runs one at a time.
+    pub active_transactions: u64,
+    pub statements: u64,
+    pub failed_statements: u64,
+    /// Optimistic transactions committed, and those that failed to
+    /// validate; see [`Concurrency`](crate::engine::Concurrency).
+    pub optimistic_commits: u64,
+    pub serialization_failures: u64,
+    pub dirty_pages: usize,
+    /// Statements that wrote back dirty pages before they ran, as the
+    /// [write throttle](crate::engine::WriteThrottle) made them: a batch
+    /// past its soft limit, or down to it past the hard limit.
+    pub throttled_writes: u64,
+    pub stalled_writes: u64,
+    /// Time statements took to run once planned.
+    pub statement_duration: Histogram,
+    pub plan_cache_entries: usize,
+    pub plan_cache_hits: u64,
+    pub plan_cache_misses: u64,
+    pub result_cache_entries: usize,
+    pub result_cache_hits: u64,
+    pub result_cache_misses: u64,
+    /// Jobs the shared worker pool ran for parallel operators.
+    pub workers: PoolStats,
+    /// Steps of idle-time maintenance run.
+    pub maintenance: MaintenanceStats,
+}
+
+impl Metrics {
+    /// The metrics in the Prometheus text format, names prefixed with
+    /// `neru7db_`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: &dyn fmt::Display| {
+            writeln!(out, "# HELP neru7db_{name} {help}").unwrap();
+            writeln!(out, "# TYPE neru7db_{name} {kind}").unwrap();
+            writeln!(out, "neru7db_{name} {value}").unwrap();
+        };
+        metric("pages", "gauge", "Pages in the database file.", &self.pages);
+        metric(
+            "buffer_pool_pages",
+            "gauge",
+            "Pages the buffer pool holds.",
+            &self.buffer_pool_pages,
+        );
+        metric(
+            "buffer_hits_total",
+            "counter",
+            "Page fetches served from the buffer pool.",
+            &self.buffer.hits,
+        );
+        metric(
+            "buffer_misses_total",
+            "counter",
+            "Page fetches that read the database file.",
+            &self.buffer.misses,
+        );
+        metric(
+            "buffer_hit_ratio",
+            "gauge",
+            "Share of page fetches served from the buffer pool.",
+            &self.buffer.hit_ratio().unwrap_or(0.0),
+        );
+        metric(
+            "buffer_evictions_total",
+            "counter",
+            "Pages evicted from the buffer pool.",
+            &self.buffer.evictions,
+        );
+        metric(
+            "buffer_dirty_evictions_total",
+            "counter",
+            "Evicted pages that were written back first.",
+            &self.buffer.dirty_evictions,
+        );
+        metric(
+            "buffer_latch_waits_total",
+            "counter",
+            "Times a thread waited for another to let go of a page latch.",
+            &self.buffer.latch_waits,
+        );
+        metric(
+            "pages_read_total",
+            "counter",
+            "Pages read from the database file.",
+            &self.buffer.disk.pages_read,
+        );
+        metric(
+            "pages_written_total",
+            "counter",
+            "Pages written to the database file.",
+            &self.buffer.disk.pages_written,
+        );
+        metric(
+            "syncs_total",
+            "counter",
+            "Syncs of the database file to the disk.",
+            &self.buffer.disk.syncs,
+        );
+        metric(
+            "background_writes_total",
+            "counter",
+            "Pages the background writer wrote back.",
+            &self.buffer.disk.background_writes,
+        );
+        metric(
+            "yielded_writes_total",
+            "counter",
+            "Background writes that waited for page reads to finish first.",
+            &self.buffer.disk.yielded_writes,
+        );
+        metric(
+            "rate_limited_writes_total",
+            "counter",
+            "Background writes held back by background_write_rate.",
+            &self.buffer.disk.rate_limited_writes,
+        );
+        metric(
+            "full_page_writes_total",
+            "counter",
+            "Pages written whole to the full-page write journal or doublewrite area before in place.",
+            &self.buffer.disk.full_page_writes,
+        );
+        metric(
+            "journal_bytes_total",
+            "counter",
+            "Bytes written to the full-page write journal, after any compression.",
+            &self.buffer.disk.journal_bytes,
+        );
+        metric(
+            "active_transactions",
+            "gauge",
+            "Transactions open.",
+            &self.active_transactions,
+        );
+        metric(
+            "statements_total",
+            "counter",
+            "Statements run.",
+            &self.statements,
+        );
+        metric(
+            "failed_statements_total",
+            "counter",
+            "Statements that failed while running.",
+            &self.failed_statements,
+        );
+        metric(
+            "optimistic_commits_total",
+            "counter",
+            "Optimistic transactions committed.",
+            &self.optimistic_commits,
+        );
+        metric(
+            "serialization_failures_total",
+            "counter",
+            "Optimistic transactions that failed to validate.",
+            &self.serialization_failures,
+        );
+        metric(
+            "dirty_pages",
+            "gauge",
+            "Pages changed in the buffer pool but not yet in the file.",
+            &self.dirty_pages,
+        );
+        metric(
+            "throttled_writes_total",
+            "counter",
+            "Writing statements that wrote back a batch of dirty pages first.",
+            &self.throttled_writes,
+        );
+        metric(
+            "stalled_writes_total",
+            "counter",
+            "Writing statements that waited for the dirty pages to be written back.",
+            &self.stalled_writes,
+        );
+        metric(
+            "plan_cache_entries",
+            "gauge",
+            "Plans in the plan cache.",
+            &self.plan_cache_entries,
+        );
+        metric(
+            "plan_cache_hits_total",
+            "counter",
+            "Statements whose plan was found in the plan cache.",
+            &self.plan_cache_hits,
+        );
+        metric(
+            "plan_cache_misses_total",
+            "counter",
+            "Statements planned afresh.",
+            &self.plan_cache_misses,
+        );
+        metric(
+            "result_cache_entries",
+            "gauge",
+            "Query results in the result cache.",
+            &self.result_cache_entries,
+        );
+        metric(
+            "result_cache_hits_total",
+            "counter",
+            "Queries answered from the result cache.",
+            &self.result_cache_hits,
+        );
+        metric(
+            "result_cache_misses_total",
+            "counter",
+            "Cacheable queries that had to run.",
+            &self.result_cache_misses,
+        );
+        metric(
+            "worker_jobs_total",
+            "counter",
+            "Jobs the worker pool ran for parallel scans and sorts.",
+            &self.workers.jobs,
+        );
+        metric(
+            "worker_steals_total",
+            "counter",
+            "Jobs a worker took from another's share.",
+            &self.workers.steals,
+        );
+        metric(
+            "worker_saturated_total",
+            "counter",
+            "Parallel operators that found the worker pool full.",
+            &self.workers.saturated,
+        );
+        metric(
+            "vacuums_total",
+            "counter",
+            "Times maintenance dropped page versions past the history's retention.",
+            &self.maintenance.vacuums,
+        );
+        metric(
+            "maintenance_analyzes_total",
+            "counter",
+            "Tables maintenance gathered the statistics of.",
+            &self.maintenance.analyzes,
+        );
+        metric(
+            "checkpoints_total",
+            "counter",
+            "Times maintenance wrote back every dirty page and synced.",
+            &self.maintenance.checkpoints,
+        );
+        let name = "neru7db_statement_duration_seconds";
+        writeln!(
+            out,
+            "# HELP {name} Time statements took to run once planned."
+        )
+        .unwrap();
+        writeln!(out, "# TYPE {name} histogram").unwrap();
+        let histogram = &self.statement_duration;
+        for (bound, count) in histogram.buckets() {
+            match bound {
+                Some(bound) => writeln!(
+                    out,
+                    "{name}_bucket{{le=\"{}\"}} {count}",
+                    bound.as_secs_f64()
+                ),
+                None => writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}"),
+            }
+            .unwrap();
+        }
+        writeln!(out, "{name}_sum {}", histogram.sum().as_secs_f64()).unwrap();
+        writeln!(out, "{name}_count {}", histogram.count()).unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::{Database, Options};
+
+    #[test]
+    fn test_metrics() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        db.execute("INSERT INTO t VALUES (1), (2)").unwrap();
+        db.query("SELECT * FROM t").unwrap();
+        assert!(db.query("SELECT id / 0 FROM t").is_err());
+        db.execute("BEGIN").unwrap();
+        let metrics = db.metrics();
+        assert_eq!(1, metrics.active_transactions);
+        assert_eq!(1, metrics.failed_statements);
+        assert!(metrics.buffer.hits > 0);
+
+        let text = metrics.to_prometheus();
+        assert!(
+            text.contains("\nneru7db_failed_statements_total 1\n"),
+            "{text}"
+        );
+        let count = metrics.statement_duration.count();
+        assert_eq!(metrics.statements, count);
+        assert!(text.contains(&format!(
+            "\nneru7db_statement_duration_seconds_bucket{{le=\"+Inf\"}} {count}\n"
+        )));
+    }
+}
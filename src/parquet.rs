@@ -0,0 +1,417 @@
+//! Writing Parquet files, for COPY TO.
+//!
+//! Just the part of the format that analytical tools need to read a
+//! table: every column is optional, values are PLAIN encoded without
+//! compression in one data page per column and row group, and a row
+//! group is written every [`ROW_GROUP_ROWS`] rows. BOOL, INT and FLOAT
+//! become BOOLEAN, INT64 and DOUBLE; TEXT becomes a UTF-8 string, JSON
+//! a JSON string, an array the UTF-8 string of its text form and BYTES a
+//! plain BYTE_ARRAY. The metadata is in Thrift's compact
+//! protocol, written by hand since only a few structures are needed.
+
+use std::io::{self, Write};
+
+use crate::planner::Field;
+use crate::value::{DataType, Value};
+
+/// Rows gathered in memory before they are written as a row group.
+pub const ROW_GROUP_ROWS: usize = 64 * 1024;
+
+const MAGIC: &[u8] = b"PAR1";
+
+/// Physical types.
+const BOOLEAN: i32 = 0;
+const INT64: i32 = 2;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+
+const OPTIONAL: i32 = 1;
+const CONVERTED_UTF8: i32 = 0;
+const CONVERTED_JSON: i32 = 19;
+const PAGE_DATA: i32 = 0;
+const ENCODING_PLAIN: i32 = 0;
+const ENCODING_RLE: i32 = 3;
+const CODEC_UNCOMPRESSED: i32 = 0;
+
+struct Column {
+    name: String,
+    data_type: Option<DataType>,
+    /// Whether each row has a value, as definition levels.
+    defined: Vec<bool>,
+    /// The values, PLAIN encoded, except for booleans.
+    data: Vec<u8>,
+    bools: Vec<bool>,
+}
+
+impl Column {
+    fn physical_type(&self) -> i32 {
+        match self.data_type {
+            Some(DataType::Bool) => BOOLEAN,
+            Some(DataType::Int) => INT64,
+            Some(DataType::Float) => DOUBLE,
+            Some(DataType::Text | DataType::Bytes | DataType::Json | DataType::Array(_)) | None => {
+                BYTE_ARRAY
+            }
+        }
+    }
+
+    fn accepts(&self, value: &Value) -> bool {
+        match value.data_type() {
+            None => true,
+            Some(DataType::Int) => matches!(self.data_type, Some(DataType::Int | DataType::Float)),
+            data_type => data_type == self.data_type,
+        }
+    }
+
+    /// Adds a value the column [accepts](Self::accepts).
+    fn push(&mut self, value: &Value) {
+        self.defined.push(!value.is_null());
+        match (value, self.data_type) {
+            (Value::Bool(b), _) => self.bools.push(*b),
+            (Value::Int(i), Some(DataType::Float)) => self.data.extend((*i as f64).to_le_bytes()),
+            (Value::Int(i), _) => self.data.extend(i.to_le_bytes()),
+            (Value::Float(x), _) => self.data.extend(x.to_le_bytes()),
+            (Value::Text(s), _) => self.push_bytes(s.as_bytes()),
+            (Value::Bytes(bytes), _) => self.push_bytes(bytes),
+            (Value::Json(_) | Value::Array(_), _) => self.push_bytes(value.to_string().as_bytes()),
+            (Value::Null, _) => {}
+        }
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        self.data.extend((bytes.len() as u32).to_le_bytes());
+        self.data.extend(bytes);
+    }
+
+    /// The column's definition levels and values as a data page, which
+    /// leaves the column empty.
+    fn take_page(&mut self) -> Vec<u8> {
+        let mut levels = vec![];
+        let mut rest = &self.defined[..];
+        while let Some(&first) = rest.first() {
+            let run = rest.iter().take_while(|&&d| d == first).count();
+            write_varint(&mut levels, (run as u64) << 1);
+            levels.push(first.into());
+            rest = &rest[run..];
+        }
+        let mut page = (levels.len() as u32).to_le_bytes().to_vec();
+        page.extend(levels);
+        page.append(&mut self.data);
+        for bits in self.bools.chunks(8) {
+            page.push(
+                bits.iter()
+                    .rev()
+                    .fold(0, |byte, &b| byte << 1 | u8::from(b)),
+            );
+        }
+        self.bools.clear();
+        self.defined.clear();
+        page
+    }
+}
+
+struct ChunkMeta {
+    offset: u64,
+    size: u64,
+    num_values: u64,
+}
+
+struct RowGroup {
+    chunks: Vec<ChunkMeta>,
+    num_rows: u64,
+}
+
+pub struct Writer<W> {
+    output: W,
+    offset: u64,
+    columns: Vec<Column>,
+    buffered: usize,
+    row_groups: Vec<RowGroup>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Starts a file with a column for each of `fields`.
+    pub fn new(mut output: W, fields: &[Field]) -> io::Result<Self> {
+        output.write_all(MAGIC)?;
+        let columns = fields
+            .iter()
+            .map(|field| Column {
+                name: field.name.clone(),
+                data_type: field.data_type,
+                defined: vec![],
+                data: vec![],
+                bools: vec![],
+            })
+            .collect();
+        Ok(Self {
+            output,
+            offset: MAGIC.len() as u64,
+            columns,
+            buffered: 0,
+            row_groups: vec![],
+        })
+    }
+
+    pub fn write_row(&mut self, row: &[Value]) -> io::Result<()> {
+        let mut columns = self.columns.iter().zip(row);
+        if let Some((column, value)) = columns.find(|(column, value)| !column.accepts(value)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("column {:?} cannot hold {value}", column.name),
+            ));
+        }
+        for (column, value) in self.columns.iter_mut().zip(row) {
+            column.push(value);
+        }
+        self.buffered += 1;
+        if self.buffered == ROW_GROUP_ROWS {
+            self.flush_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn flush_row_group(&mut self) -> io::Result<()> {
+        let mut chunks = vec![];
+        for column in &mut self.columns {
+            let page = column.take_page();
+            let mut header = Thrift::new();
+            header.i32(1, PAGE_DATA);
+            header.i32(2, page.len() as i32);
+            header.i32(3, page.len() as i32);
+            header.begin_struct(5);
+            header.i32(1, self.buffered as i32);
+            header.i32(2, ENCODING_PLAIN);
+            header.i32(3, ENCODING_RLE);
+            header.i32(4, ENCODING_RLE);
+            header.end_struct();
+            header.end_struct();
+            self.output.write_all(&header.buf)?;
+            self.output.write_all(&page)?;
+            let size = (header.buf.len() + page.len()) as u64;
+            chunks.push(ChunkMeta {
+                offset: self.offset,
+                size,
+                num_values: self.buffered as u64,
+            });
+            self.offset += size;
+        }
+        self.row_groups.push(RowGroup {
+            chunks,
+            num_rows: self.buffered as u64,
+        });
+        self.buffered = 0;
+        Ok(())
+    }
+
+    /// Writes the rows still buffered and the footer, and hands back the
+    /// output.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.buffered > 0 {
+            self.flush_row_group()?;
+        }
+        let mut meta = Thrift::new();
+        meta.i32(1, 1);
+        meta.begin_list(2, Thrift::STRUCT, self.columns.len() + 1);
+        meta.begin_element();
+        meta.binary(4, b"schema");
+        meta.i32(5, self.columns.len() as i32);
+        meta.end_struct();
+        for column in &self.columns {
+            meta.begin_element();
+            meta.i32(1, column.physical_type());
+            meta.i32(3, OPTIONAL);
+            meta.binary(4, column.name.as_bytes());
+            if matches!(column.data_type, Some(DataType::Text | DataType::Array(_))) {
+                meta.i32(6, CONVERTED_UTF8);
+                // LogicalType { STRING: StringType {} }
+                meta.begin_struct(10);
+                meta.begin_struct(1);
+                meta.end_struct();
+                meta.end_struct();
+            }
+            if column.data_type == Some(DataType::Json) {
+                meta.i32(6, CONVERTED_JSON);
+                // LogicalType { JSON: JsonType {} }
+                meta.begin_struct(10);
+                meta.begin_struct(12);
+                meta.end_struct();
+                meta.end_struct();
+            }
+            meta.end_struct();
+        }
+        let num_rows = self
+            .row_groups
+            .iter()
+            .map(|group| group.num_rows)
+            .sum::<u64>();
+        meta.i64(3, num_rows as i64);
+        meta.begin_list(4, Thrift::STRUCT, self.row_groups.len());
+        for group in &self.row_groups {
+            meta.begin_element();
+            meta.begin_list(1, Thrift::STRUCT, group.chunks.len());
+            for (column, chunk) in self.columns.iter().zip(&group.chunks) {
+                meta.begin_element();
+                meta.i64(2, chunk.offset as i64);
+                meta.begin_struct(3);
+                meta.i32(1, column.physical_type());
+                meta.begin_list(2, Thrift::I32, 2);
+                meta.list_i32(ENCODING_PLAIN);
+                meta.list_i32(ENCODING_RLE);
+                meta.begin_list(3, Thrift::BINARY, 1);
+                meta.list_binary(column.name.as_bytes());
+                meta.i32(4, CODEC_UNCOMPRESSED);
+                meta.i64(5, chunk.num_values as i64);
+                meta.i64(6, chunk.size as i64);
+                meta.i64(7, chunk.size as i64);
+                meta.i64(9, chunk.offset as i64);
+                meta.end_struct();
+                meta.end_struct();
+            }
+            let size = group.chunks.iter().map(|chunk| chunk.size).sum::<u64>();
+            meta.i64(2, size as i64);
+            meta.i64(3, group.num_rows as i64);
+            meta.end_struct();
+        }
+        meta.binary(6, b"neru7db");
+        meta.end_struct();
+        self.output.write_all(&meta.buf)?;
+        self.output
+            .write_all(&(meta.buf.len() as u32).to_le_bytes())?;
+        self.output.write_all(MAGIC)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+/// A structure being written in Thrift's compact protocol.
+struct Thrift {
+    buf: Vec<u8>,
+    /// Id of the last field written in each open structure.
+    last_ids: Vec<i16>,
+}
+
+impl Thrift {
+    const I32: u8 = 5;
+    const I64: u8 = 6;
+    const BINARY: u8 = 8;
+    const LIST: u8 = 9;
+    const STRUCT: u8 = 12;
+
+    fn new() -> Self {
+        Self {
+            buf: vec![],
+            last_ids: vec![0],
+        }
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last_ids.last_mut().unwrap();
+        match id - *last {
+            delta @ 1..=15 => self.buf.push((delta as u8) << 4 | kind),
+            _ => {
+                self.buf.push(kind);
+                write_varint(&mut self.buf, zigzag(id.into()));
+            }
+        }
+        *last = id;
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, Self::I32);
+        self.list_i32(value);
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, Self::I64);
+        write_varint(&mut self.buf, zigzag(value));
+    }
+
+    fn binary(&mut self, id: i16, bytes: &[u8]) {
+        self.field(id, Self::BINARY);
+        self.list_binary(bytes);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, Self::STRUCT);
+        self.last_ids.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.buf.push(0);
+        self.last_ids.pop();
+    }
+
+    fn begin_list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, Self::LIST);
+        if len < 15 {
+            self.buf.push((len as u8) << 4 | kind);
+        } else {
+            self.buf.push(0xf0 | kind);
+            write_varint(&mut self.buf, len as u64);
+        }
+    }
+
+    /// Starts a structure that is an element of a list.
+    fn begin_element(&mut self) {
+        self.last_ids.push(0);
+    }
+
+    fn list_i32(&mut self, value: i32) {
+        write_varint(&mut self.buf, zigzag(value.into()));
+    }
+
+    fn list_binary(&mut self, bytes: &[u8]) {
+        write_varint(&mut self.buf, bytes.len() as u64);
+        self.buf.extend(bytes);
+    }
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write() {
+        let fields = [
+            Field::new("id", Some(DataType::Int)),
+            Field::new("name", Some(DataType::Text)),
+            Field::new("ok", Some(DataType::Bool)),
+        ];
+        let mut writer = Writer::new(vec![], &fields).unwrap();
+        writer
+            .write_row(&[1.into(), "a".into(), true.into()])
+            .unwrap();
+        writer
+            .write_row(&[2.into(), Value::Null, false.into()])
+            .unwrap();
+        assert!(writer
+            .write_row(&["x".into(), Value::Null, Value::Null])
+            .is_err());
+        let file = writer.finish().unwrap();
+        assert!(file.starts_with(MAGIC) && file.ends_with(MAGIC));
+        let len = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap());
+        let footer = &file[file.len() - 8 - len as usize..file.len() - 8];
+        // FileMetaData begins with version 1, then the schema list.
+        assert_eq!([0x15, 0x02, 0x19, 0x4c], footer[..4]);
+        // The first page ends in a run of two defined levels and the ids.
+        let ids = [
+            &[2, 0, 0, 0, 4, 1][..],
+            &1i64.to_le_bytes(),
+            &2i64.to_le_bytes(),
+        ]
+        .concat();
+        assert!(file.windows(ids.len()).any(|window| window == ids));
+    }
+}
@@ -0,0 +1,209 @@
+//! Framing: reading and writing the messages of protocol version 3.
+//!
+//! Every message but the startup packet is a tag byte, then its length as
+//! a big-endian i32 counting itself, then the body. The startup packet
+//! has no tag, and its first field says what kind it is.
+
+use std::io::{self, Read, Write};
+
+use super::Error;
+
+pub const PROTOCOL_VERSION: i32 = 196608;
+pub const SSL_REQUEST: i32 = 80877103;
+pub const GSSENC_REQUEST: i32 = 80877104;
+pub const CANCEL_REQUEST: i32 = 80877102;
+
+/// Larger messages are taken for garbage rather than read into memory.
+const MAX_MESSAGE_LEN: usize = 64 << 20;
+
+#[derive(Debug, PartialEq)]
+pub enum Startup {
+    Ssl,
+    GssEnc,
+    Cancel,
+    /// The connection's parameters, such as `user` and `database`.
+    Start(Vec<(String, String)>),
+}
+
+pub fn read_startup(r: &mut impl Read) -> Result<Startup, Error> {
+    let body = read_body(r)?;
+    let mut body = Body::new(&body);
+    match body.i32()? {
+        SSL_REQUEST => Ok(Startup::Ssl),
+        GSSENC_REQUEST => Ok(Startup::GssEnc),
+        CANCEL_REQUEST => Ok(Startup::Cancel),
+        PROTOCOL_VERSION => {
+            let mut params = vec![];
+            loop {
+                let name = body.cstr()?;
+                if name.is_empty() {
+                    break;
+                }
+                params.push((name, body.cstr()?));
+            }
+            Ok(Startup::Start(params))
+        }
+        version => Err(Error::Protocol(format!(
+            "unsupported protocol version {}.{}",
+            version >> 16,
+            version & 0xffff
+        ))),
+    }
+}
+
+/// The next message's tag and body, or `None` if the client hung up.
+pub fn read_message(r: &mut impl Read) -> Result<Option<(u8, Vec<u8>)>, Error> {
+    match read_tag(r)? {
+        Some(tag) => Ok(Some((tag, read_body(r)?))),
+        None => Ok(None),
+    }
+}
+
+/// The next message's tag, or `None` if the client hung up.
+pub fn read_tag(r: &mut impl Read) -> Result<Option<u8>, Error> {
+    let mut tag = [0];
+    match r.read_exact(&mut tag) {
+        Ok(()) => Ok(Some(tag[0])),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The rest of a message whose tag has been read.
+pub fn read_body(r: &mut impl Read) -> Result<Vec<u8>, Error> {
+    let mut len = [0; 4];
+    r.read_exact(&mut len)?;
+    let len = i32::from_be_bytes(len);
+    if !(4..=MAX_MESSAGE_LEN as i32).contains(&len) {
+        return Err(Error::Protocol(format!("invalid message length {len}")));
+    }
+    let mut body = vec![0; len as usize - 4];
+    r.read_exact(&mut body)?;
+    Ok(body)
+}
+
+/// Reads the fields of a message body in order.
+pub struct Body<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Body<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.bytes.len() {
+            return Err(Error::Protocol("message is too short".to_string()));
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn i16(&mut self) -> Result<i16, Error> {
+        Ok(i16::from_be_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, Error> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    /// A count of following items, which may not be negative.
+    pub fn count(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.i16()?).map_err(|_| Error::Protocol("negative count".to_string()))
+    }
+
+    /// A NUL-terminated string.
+    pub fn cstr(&mut self) -> Result<String, Error> {
+        let end = self
+            .bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| Error::Protocol("unterminated string".to_string()))?;
+        let s = String::from_utf8(self.bytes[..end].to_vec())
+            .map_err(|_| Error::Protocol("string is not UTF-8".to_string()))?;
+        self.bytes = &self.bytes[end + 1..];
+        Ok(s)
+    }
+}
+
+/// Buffers outgoing messages; nothing is sent until [`Writer::flush`].
+pub struct Writer<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    /// Where the message being written starts in `buf`.
+    start: Option<usize>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            buf: vec![],
+            start: None,
+        }
+    }
+
+    /// Starts a message; its fields are appended with the `put_*`
+    /// methods, and its length filled in by the next message or flush.
+    pub fn message(&mut self, tag: u8) -> &mut Self {
+        self.finish();
+        self.start = Some(self.buf.len());
+        self.buf.push(tag);
+        self.buf.extend_from_slice(&[0; 4]);
+        self
+    }
+
+    /// A single byte outside any message, as the answer to SSLRequest is.
+    pub fn raw_byte(&mut self, b: u8) -> &mut Self {
+        self.finish();
+        self.buf.push(b);
+        self
+    }
+
+    pub fn put_u8(&mut self, b: u8) -> &mut Self {
+        self.buf.push(b);
+        self
+    }
+
+    pub fn put_i16(&mut self, i: i16) -> &mut Self {
+        self.buf.extend_from_slice(&i.to_be_bytes());
+        self
+    }
+
+    pub fn put_i32(&mut self, i: i32) -> &mut Self {
+        self.buf.extend_from_slice(&i.to_be_bytes());
+        self
+    }
+
+    pub fn put_bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.buf.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn put_cstr(&mut self, s: &str) -> &mut Self {
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+        self
+    }
+
+    /// Fills in the length of the message being written, if any.
+    fn finish(&mut self) {
+        if let Some(start) = self.start.take() {
+            let len = (self.buf.len() - start - 1) as i32;
+            self.buf[start + 1..start + 5].copy_from_slice(&len.to_be_bytes());
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.finish();
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,706 @@
+//! The PostgreSQL frontend/backend protocol, so that psql and Postgres
+//! drivers can run statements against a [`Database`].
+//!
+//! Both the simple query protocol and the extended one (Parse, Bind,
+//! Describe, Execute, Sync) are served. Values travel in text or binary
+//! format as the client asks; see [`types`] for how our types map onto
+//! Postgres ones.
+//!
+//! Once the database has users, clients log in as one of them with
+//! SCRAM-SHA-256, which never sends the password, and their statements
+//! are checked against the user's privileges. Until then, one shared
+//! password, sent in the clear unless over TLS, if any, is all the
+//! authentication there is. SSLRequest is accepted with TLS if the
+//! config has a [`Tls`], and clients that do not ask for it can be
+//! turned away; see [`Tls`]. GSSAPI encryption requests are declined,
+//! as are cancel requests.
+//!
+//! Sessions share the database behind a mutex, so statements from
+//! different connections run one at a time, and a session in a
+//! transaction keeps the database to itself until COMMIT or ROLLBACK. A
+//! transaction left open when the connection ends is rolled back, as is
+//! one [`serve_socket`] finds idle for longer than
+//! [`Config::idle_in_transaction_timeout`] allows. Under
+//! [optimistic concurrency](crate::engine::Concurrency::Optimistic), a
+//! session in a transaction holds the database only while each statement
+//! runs, and COMMIT fails with SQLSTATE 40001 if another session changed
+//! what it read meanwhile.
+//!
+//! A session waits for the database as long as it takes, unless its
+//! `lock_timeout` is set, after which the statement fails with SQLSTATE
+//! 55P03. It waits the same way for rows another session's transaction
+//! has [locked](crate::lock), which under optimistic concurrency it does
+//! with the database let go, until that transaction ends. `FOR UPDATE
+//! NOWAIT` fails with 55P03 at once on such a row instead, and `SKIP
+//! LOCKED` leaves it out, through either protocol; waiting for the
+//! database is not waiting for a row, and both wait for it as any
+//! statement does. A wait that would close a cycle fails with 40P01, and
+//! rolls back the transaction that asked.
+//!
+//! NOTIFY reaches the sessions that LISTEN on its channel, including the
+//! one that sent it, as NotificationResponse messages ahead of their next
+//! ReadyForQuery. A session [`serve_socket`] finds sitting idle outside a
+//! transaction gets them within a few tens of milliseconds; one in a
+//! transaction gets them once it sends something, as in Postgres.
+
+mod message;
+mod session;
+mod tls;
+pub mod types;
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::database::Database;
+use crate::server::Workers;
+
+use message::Startup;
+use session::Session;
+
+pub use tls::Tls;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("protocol violation: {0}")]
+    Protocol(String),
+    #[error("cannot set up TLS: {0}")]
+    Tls(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The password clients must give while the database has no users;
+    /// `None` lets anyone in.
+    pub password: Option<String>,
+    /// What SSLRequest is accepted with, if it is.
+    pub tls: Option<Tls>,
+    /// Whether clients that do not ask for TLS are turned away.
+    pub require_tls: bool,
+    /// How long a client of [`serve_socket`] may sit idle in a
+    /// transaction before its session is ended and the transaction
+    /// rolled back, with SQLSTATE 25P03; `None` lets it sit.
+    pub idle_in_transaction_timeout: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            password: None,
+            tls: None,
+            require_tls: false,
+            idle_in_transaction_timeout: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Serves one connection until it ends, reading from `reader` and writing
+/// to `writer`, usually both halves of a socket.
+pub fn serve(
+    db: &Mutex<Database>,
+    config: &Config,
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<(), Error> {
+    serve_with(db, config, reader, writer, None)
+}
+
+/// Serves the connection of `stream` until it ends, running its messages
+/// on a worker of `workers` each, and ending a session left idle in a
+/// transaction for [`Config::idle_in_transaction_timeout`]. While the
+/// client is idle, the worker is free for others; a transaction keeping
+/// the database to itself runs without one, since those holding them
+/// may be waiting for it.
+pub fn serve_socket(
+    db: &Mutex<Database>,
+    config: &Config,
+    workers: &Workers,
+    stream: TcpStream,
+) -> Result<(), Error> {
+    let reader = stream.try_clone()?;
+    let socket = stream.try_clone()?;
+    serve_with(db, config, reader, stream, Some((socket, workers)))
+}
+
+fn serve_with(
+    db: &Mutex<Database>,
+    config: &Config,
+    reader: impl Read,
+    mut writer: impl Write,
+    socket: Option<(TcpStream, &Workers)>,
+) -> Result<(), Error> {
+    let mut reader = io::BufReader::new(reader);
+    let startup = loop {
+        match (message::read_startup(&mut reader)?, &config.tls) {
+            (Startup::Ssl, Some(tls)) => {
+                if !reader.buffer().is_empty() {
+                    let message = "received unencrypted data after SSL request";
+                    return Err(Error::Protocol(message.to_string()));
+                }
+                writer.write_all(b"S")?;
+                writer.flush()?;
+                let stream = tls.accept(reader, writer)?;
+                let reader = io::BufReader::new(stream.clone());
+                let session = Session::new(db, config, reader, stream, true);
+                return session.with_socket(socket).run(None);
+            }
+            // GSSAPI is not supported; the client may go on without, or
+            // ask for SSL next.
+            (Startup::Ssl | Startup::GssEnc, _) => {
+                writer.write_all(b"N")?;
+                writer.flush()?;
+            }
+            (startup, _) => break startup,
+        }
+    };
+    let session = Session::new(db, config, reader, writer, false);
+    session.with_socket(socket).run(Some(startup))
+}
+
+/// Turns a client away with a FATAL error before its session starts.
+pub fn refuse(writer: impl Write, code: &str, message: &str) -> Result<(), Error> {
+    let mut writer = message::Writer::new(writer);
+    writer
+        .message(b'E')
+        .put_u8(b'S')
+        .put_cstr("FATAL")
+        .put_u8(b'V')
+        .put_cstr("FATAL")
+        .put_u8(b'C')
+        .put_cstr(code)
+        .put_u8(b'M')
+        .put_cstr(message)
+        .put_u8(0);
+    Ok(writer.flush()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::message::{Body, Writer, PROTOCOL_VERSION};
+    use super::*;
+    use crate::database::Options;
+    use crate::engine::{Concurrency, OptimisticTransaction};
+    use crate::value::Value;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, ServerName};
+    use std::sync::Arc;
+
+    /// The messages the server sent, as tags and bodies.
+    fn messages(mut bytes: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let mut messages = vec![];
+        while let Some(message) = message::read_message(&mut bytes).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// The tags of `messages`, with a space after each ReadyForQuery
+    /// but the last.
+    fn tags(messages: &[(u8, Vec<u8>)]) -> String {
+        let tags: Vec<String> = messages
+            .iter()
+            .map(|(tag, _)| (*tag as char).to_string())
+            .collect();
+        tags.concat().replace('Z', "Z ").trim_end().to_string()
+    }
+
+    fn startup(password: Option<&str>) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+        body.extend_from_slice(b"user\0alice\0\0");
+        let mut bytes = ((body.len() + 4) as i32).to_be_bytes().to_vec();
+        bytes.extend(body);
+        if let Some(password) = password {
+            let mut w = Writer::new(&mut bytes);
+            w.message(b'p').put_cstr(password);
+            w.flush().unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_simple_and_extended_query() {
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        let config = Config {
+            password: Some("secret".to_string()),
+            ..Config::default()
+        };
+        let mut input = startup(Some("secret"));
+        let mut w = Writer::new(&mut input);
+        w.message(b'Q').put_cstr(
+            "CREATE TABLE t (id INT PRIMARY KEY, name TEXT); \
+             INSERT INTO t VALUES (1, 'a'), (2, NULL); SELECT * FROM t ORDER BY id",
+        );
+        w.message(b'Q').put_cstr("SELECT nope FROM t");
+        w.message(b'P')
+            .put_cstr("s")
+            .put_cstr("SELECT name FROM t WHERE id = $1")
+            .put_i16(0);
+        w.message(b'B')
+            .put_cstr("")
+            .put_cstr("s")
+            .put_i16(1)
+            .put_i16(1)
+            .put_i16(1)
+            .put_i32(8)
+            .put_bytes(&1i64.to_be_bytes())
+            .put_i16(0);
+        w.message(b'D').put_u8(b'P').put_cstr("");
+        w.message(b'E').put_cstr("").put_i32(0);
+        w.message(b'S');
+        w.message(b'X');
+        w.flush().unwrap();
+        drop(w);
+
+        let mut output = vec![];
+        serve(&db, &config, &input[..], &mut output).unwrap();
+        let messages = messages(&output);
+        // Authentication, parameter statuses and ReadyForQuery, then the
+        // three statements, the failed query and the extended round.
+        assert_eq!("RRSSSSSSZ CCTDDCZ EZ 12TDCZ", tags(&messages));
+        let tag = |i: usize| Body::new(&messages[i].1).cstr().unwrap();
+        assert_eq!("CREATE TABLE", tag(9));
+        assert_eq!("INSERT 0 2", tag(10));
+        assert_eq!("SELECT 2", tag(14));
+        let mut row = Body::new(&messages[13].1);
+        assert_eq!(2, row.i16().unwrap());
+        assert_eq!(1, row.i32().unwrap());
+        assert_eq!(b"2", row.bytes(1).unwrap());
+        assert_eq!(-1, row.i32().unwrap());
+        let error = String::from_utf8_lossy(&messages[16].1).to_string();
+        assert!(error.contains("42703"), "{error}");
+        let mut row = Body::new(&messages[21].1);
+        row.i16().unwrap();
+        row.i32().unwrap();
+        assert_eq!(b"a", row.bytes(1).unwrap());
+        assert_eq!("SELECT 1", tag(22));
+    }
+
+    #[test]
+    fn test_scram_login() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        for sql in [
+            "CREATE TABLE t (x INT)",
+            "CREATE TABLE secret (x INT)",
+            "CREATE USER alice PASSWORD 'pw'",
+            "GRANT SELECT ON t TO alice",
+        ] {
+            db.execute(sql).unwrap();
+        }
+        let db = Mutex::new(db);
+        let config = Config::default();
+        let (server_reader, mut client_writer) = io::pipe().unwrap();
+        let (mut client_reader, server_writer) = io::pipe().unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| serve(&db, &config, server_reader, server_writer).unwrap());
+            let mut next = || message::read_message(&mut client_reader).unwrap().unwrap();
+            client_writer.write_all(&startup(None)).unwrap();
+            let (tag, body) = next();
+            assert_eq!((b'R', 10), (tag, Body::new(&body).i32().unwrap()));
+
+            let client_first = "n,,n=,r=abcdefgh";
+            let mut w = Writer::new(&mut client_writer);
+            w.message(b'p')
+                .put_cstr(crate::auth::scram::MECHANISM)
+                .put_i32(client_first.len() as i32)
+                .put_bytes(client_first.as_bytes());
+            w.flush().unwrap();
+            let (_, body) = next();
+            let server_first = String::from_utf8_lossy(&body[4..]).to_string();
+            let client_final = crate::auth::scram::client_final("pw", client_first, &server_first);
+            w.message(b'p').put_bytes(client_final.as_bytes());
+            w.message(b'Q').put_cstr("SELECT * FROM t");
+            w.message(b'Q').put_cstr("SELECT * FROM secret");
+            w.message(b'X');
+            w.flush().unwrap();
+            drop(w);
+            drop(client_writer);
+
+            let mut rest = vec![];
+            client_reader.read_to_end(&mut rest).unwrap();
+            let messages = messages(&rest);
+            assert_eq!("RRSSSSSSZ TCZ EZ", tags(&messages));
+            assert_eq!(12, Body::new(&messages[0].1).i32().unwrap());
+            let error = String::from_utf8_lossy(&messages[12].1).to_string();
+            assert!(error.contains("42501"), "{error}");
+        });
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        let config = Config {
+            password: Some("secret".to_string()),
+            ..Config::default()
+        };
+        let mut output = vec![];
+        serve(&db, &config, &startup(Some("guess"))[..], &mut output).unwrap();
+        let messages = messages(&output);
+        assert_eq!("RE", tags(&messages));
+        assert!(String::from_utf8_lossy(&messages[1].1).contains("28P01"));
+    }
+
+    /// Reads `input`, locking `db` as another session would once the
+    /// first `split` bytes are read.
+    struct LockingReader<'a> {
+        input: &'a [u8],
+        split: usize,
+        db: &'a Mutex<Database>,
+        guard: Option<std::sync::MutexGuard<'a, Database>>,
+    }
+
+    impl Read for LockingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.split == 0 && self.guard.is_none() {
+                self.guard = Some(self.db.lock().unwrap());
+            }
+            let end = if self.guard.is_none() {
+                self.split
+            } else {
+                self.input.len()
+            };
+            let n = buf.len().min(end);
+            buf[..n].copy_from_slice(&self.input[..n]);
+            self.input = &self.input[n..];
+            self.split = self.split.saturating_sub(n);
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_lock_timeout() {
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        let mut input = startup(None);
+        let mut w = Writer::new(&mut input);
+        w.message(b'Q').put_cstr(
+            "CREATE TABLE t (id INT); INSERT INTO t VALUES (1); SET lock_timeout = '20ms'",
+        );
+        w.message(b'P')
+            .put_cstr("s")
+            .put_cstr("SELECT id FROM t FOR UPDATE SKIP LOCKED")
+            .put_i16(0);
+        w.message(b'S');
+        w.flush().unwrap();
+        drop(w);
+        let split = input.len();
+        let mut w = Writer::new(&mut input);
+        w.message(b'Q')
+            .put_cstr("SELECT * FROM t FOR UPDATE NOWAIT");
+        w.message(b'Q').put_cstr("SELECT * FROM t");
+        w.message(b'B')
+            .put_cstr("")
+            .put_cstr("s")
+            .put_i16(0)
+            .put_i16(0)
+            .put_i16(0);
+        w.message(b'E').put_cstr("").put_i32(0);
+        w.message(b'S');
+        w.message(b'X');
+        w.flush().unwrap();
+        drop(w);
+
+        let reader = LockingReader {
+            input: &input,
+            split,
+            db: &db,
+            guard: None,
+        };
+        let mut output = vec![];
+        serve(&db, &Config::default(), reader, &mut output).unwrap();
+        let messages = messages(&output);
+        // With the database held by another session, every statement
+        // waits for it until lock_timeout, whatever it says of rows.
+        let start = tags(&messages).find("CCCZ").unwrap();
+        assert_eq!("CCCZ 1Z EZ EZ 2EZ", &tags(&messages)[start..]);
+        let errors: Vec<_> = (messages.iter())
+            .filter(|(tag, _)| *tag == b'E')
+            .map(|(_, body)| String::from_utf8_lossy(body).to_string())
+            .collect();
+        assert_eq!(3, errors.len());
+        for error in errors {
+            assert!(
+                error.contains("55P03") && error.contains("lock timeout"),
+                "{error}"
+            );
+        }
+    }
+
+    /// A database under optimistic concurrency where a suspended
+    /// transaction holds row 1 of `t` FOR NO KEY UPDATE.
+    fn locked_row() -> (Mutex<Database>, OptimisticTransaction) {
+        let options = Options {
+            concurrency: Concurrency::Optimistic,
+            ..Options::default()
+        };
+        let mut db = Database::temporary(options).unwrap();
+        for sql in [
+            "CREATE TABLE t (id INT PRIMARY KEY)",
+            "INSERT INTO t VALUES (1), (2)",
+            "BEGIN",
+            "SELECT id FROM t WHERE id = 1 FOR NO KEY UPDATE",
+        ] {
+            db.execute(sql).unwrap();
+        }
+        let holder = db.engine_mut().suspend_transaction().unwrap();
+        (Mutex::new(db), holder)
+    }
+
+    #[test]
+    fn test_row_locks() {
+        let (db, holder) = locked_row();
+        let mut input = startup(None);
+        let mut w = Writer::new(&mut input);
+        for sql in [
+            "SET lock_timeout = '20ms'",
+            "SELECT id FROM t FOR UPDATE SKIP LOCKED",
+            "SELECT id FROM t FOR SHARE NOWAIT",
+            "UPDATE t SET id = 3 WHERE id = 1",
+            "SELECT id FROM t ORDER BY id FOR KEY SHARE",
+        ] {
+            w.message(b'Q').put_cstr(sql);
+        }
+        for sql in [
+            "SELECT id FROM t FOR UPDATE SKIP LOCKED",
+            "SELECT id FROM t FOR SHARE NOWAIT",
+        ] {
+            w.message(b'P').put_cstr("").put_cstr(sql).put_i16(0);
+            w.message(b'B')
+                .put_cstr("")
+                .put_cstr("")
+                .put_i16(0)
+                .put_i16(0)
+                .put_i16(0);
+            w.message(b'E').put_cstr("").put_i32(0);
+            w.message(b'S');
+        }
+        w.message(b'X');
+        w.flush().unwrap();
+        drop(w);
+
+        let mut output = vec![];
+        serve(&db, &Config::default(), &input[..], &mut output).unwrap();
+        let messages = messages(&output);
+        // Both protocols skip the locked row or fail on it at once; the
+        // update waits for it until lock_timeout, and KEY SHARE shares it.
+        let start = tags(&messages).find("CZ TDCZ").unwrap();
+        assert_eq!("CZ TDCZ EZ EZ TDDCZ 12DCZ 12EZ", &tags(&messages)[start..]);
+        let rows: Vec<_> = (messages.iter())
+            .filter(|(tag, _)| *tag == b'D')
+            .map(|(_, body)| {
+                let mut row = Body::new(body);
+                row.i16().unwrap();
+                let len = row.i32().unwrap() as usize;
+                String::from_utf8_lossy(row.bytes(len).unwrap()).to_string()
+            })
+            .collect();
+        assert_eq!(vec!["2", "1", "2", "2"], rows);
+        let errors: Vec<_> = (messages.iter())
+            .filter(|(tag, _)| *tag == b'E')
+            .map(|(_, body)| String::from_utf8_lossy(body).to_string())
+            .collect();
+        assert!(errors[0].contains("55P03") && errors[0].contains("lock on row"));
+        assert!(errors[1].contains("55P03") && errors[1].contains("lock timeout"));
+        assert!(errors[2].contains("55P03") && errors[2].contains("lock on row"));
+
+        // What failed or waited changed nothing the holder read.
+        let mut db = db.lock().unwrap();
+        db.engine_mut().resume_transaction(holder).unwrap();
+        db.execute("COMMIT").unwrap();
+    }
+
+    #[test]
+    fn test_row_wait() {
+        let (db, holder) = locked_row();
+        let mut input = startup(None);
+        let mut w = Writer::new(&mut input);
+        w.message(b'Q').put_cstr("UPDATE t SET id = 3 WHERE id = 1");
+        w.message(b'X');
+        w.flush().unwrap();
+        drop(w);
+
+        std::thread::scope(|scope| {
+            let session = scope.spawn(|| {
+                let mut output = vec![];
+                serve(&db, &Config::default(), &input[..], &mut output).unwrap();
+                output
+            });
+            // The update waits for the row with the database let go, so
+            // that its holder can commit.
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            let mut guard = db.lock().unwrap();
+            guard.engine_mut().resume_transaction(holder).unwrap();
+            guard.execute("COMMIT").unwrap();
+            drop(guard);
+            let output = session.join().unwrap();
+            assert!(tags(&messages(&output)).ends_with("Z CZ"));
+        });
+        let mut db = db.lock().unwrap();
+        let rows = db
+            .engine_mut()
+            .execute("SELECT id FROM t ORDER BY id")
+            .unwrap();
+        assert_eq!(
+            vec![vec![Value::Int(2)], vec![Value::Int(3)]],
+            rows.into_rows()
+        );
+    }
+
+    #[test]
+    fn test_tls() {
+        let dir = tempfile::tempdir().unwrap();
+        let (cert, key) = (dir.path().join("server.pem"), dir.path().join("server.key"));
+        std::fs::write(&cert, include_str!("testdata/server.pem")).unwrap();
+        std::fs::write(&key, include_str!("testdata/server.key")).unwrap();
+        let config = Config {
+            tls: Some(Tls::from_pem_files(&cert, &key).unwrap()),
+            require_tls: true,
+            ..Config::default()
+        };
+        assert!(matches!(
+            Tls::from_pem_files(&key, &key),
+            Err(Error::Tls(message)) if message.contains("no certificate")
+        ));
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        let mut ssl_request = 8i32.to_be_bytes().to_vec();
+        ssl_request.extend(message::SSL_REQUEST.to_be_bytes());
+        let mut input = startup(None);
+        let mut w = Writer::new(&mut input);
+        w.message(b'Q').put_cstr("SELECT 1");
+        w.message(b'X');
+        w.flush().unwrap();
+        drop(w);
+
+        // SSLRequest is accepted, and the session goes through TLS.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let (stream, _) = listener.accept().unwrap();
+                serve(&db, &config, stream.try_clone().unwrap(), stream).unwrap();
+            });
+            let mut socket = std::net::TcpStream::connect(addr).unwrap();
+            socket.write_all(&ssl_request).unwrap();
+            let mut answer = [0];
+            socket.read_exact(&mut answer).unwrap();
+            assert_eq!(*b"S", answer);
+            let mut roots = rustls::RootCertStore::empty();
+            let ca = include_bytes!("testdata/ca.pem");
+            roots
+                .add(CertificateDer::from_pem_slice(ca).unwrap())
+                .unwrap();
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            let client = (rustls::ClientConfig::builder_with_provider(provider))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            let name = ServerName::try_from("localhost").unwrap();
+            let connection = rustls::ClientConnection::new(Arc::new(client), name).unwrap();
+            let mut tls = rustls::StreamOwned::new(connection, socket);
+            tls.write_all(&input).unwrap();
+            let mut output = vec![];
+            tls.read_to_end(&mut output).unwrap();
+            assert!(tags(&messages(&output)).ends_with("Z TDCZ"));
+        });
+
+        // Unencrypted, the client is turned away, and what it sends
+        // after SSLRequest before the answer drops the connection.
+        let mut output = vec![];
+        serve(&db, &config, &input[..], &mut output).unwrap();
+        assert_eq!("E", tags(&messages(&output)));
+        assert!(String::from_utf8_lossy(&output).contains("28000"));
+        let sneaked = [&ssl_request[..], &input].concat();
+        let mut output = vec![];
+        assert!(matches!(
+            serve(&db, &config, &sneaked[..], &mut output),
+            Err(Error::Protocol(_))
+        ));
+        assert!(output.is_empty());
+
+        // Without TLS, SSLRequest is declined, and the session goes on.
+        let mut output = vec![];
+        serve(&db, &Config::default(), &sneaked[..], &mut output).unwrap();
+        assert_eq!(b'N', output[0]);
+        assert!(tags(&messages(&output[1..])).ends_with("Z TDCZ"));
+    }
+
+    #[test]
+    fn test_notifications() {
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        let mut input = startup(None);
+        let mut w = Writer::new(&mut input);
+        w.message(b'Q')
+            .put_cstr("LISTEN jobs; BEGIN; NOTIFY jobs, 'done'");
+        w.message(b'Q').put_cstr("COMMIT");
+        w.message(b'X');
+        w.flush().unwrap();
+        drop(w);
+
+        let mut output = vec![];
+        serve(&db, &Config::default(), &input[..], &mut output).unwrap();
+        let messages = messages(&output);
+        // The notification waits for the commit, then comes before the
+        // next ReadyForQuery.
+        assert!(tags(&messages).ends_with("CCCZ CAZ"));
+        let (_, body) = &messages[messages.len() - 2];
+        let mut body = Body::new(body);
+        assert_eq!(0, body.i32().unwrap());
+        assert_eq!("jobs", body.cstr().unwrap());
+        assert_eq!("done", body.cstr().unwrap());
+
+        // A session sitting idle gets them without sending anything.
+        let mut input = startup(None);
+        let mut w = Writer::new(&mut input);
+        w.message(b'Q').put_cstr("LISTEN jobs");
+        w.flush().unwrap();
+        drop(w);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let workers = Workers::new(1);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let (stream, _) = listener.accept().unwrap();
+                serve_socket(&db, &Config::default(), &workers, stream).unwrap();
+            });
+            let mut socket = std::net::TcpStream::connect(addr).unwrap();
+            socket.write_all(&input).unwrap();
+            let mut next = || message::read_message(&mut socket).unwrap().unwrap();
+            // Past the ReadyForQuery after startup, and the one after LISTEN.
+            for _ in 0..2 {
+                while next().0 != b'Z' {}
+            }
+            db.lock().unwrap().execute("NOTIFY jobs, 'idle'").unwrap();
+            let (tag, body) = next();
+            assert_eq!(b'A', tag);
+            assert!(body.ends_with(b"jobs\0idle\0"));
+            let mut w = Writer::new(&mut socket);
+            w.message(b'X');
+            w.flush().unwrap();
+        });
+    }
+
+    #[test]
+    fn test_temp_tables_per_session() {
+        let db = Mutex::new(Database::temporary(Options::default()).unwrap());
+        let session = |sql: &str| {
+            let mut input = startup(None);
+            let mut w = Writer::new(&mut input);
+            w.message(b'Q').put_cstr(sql);
+            w.message(b'X');
+            w.flush().unwrap();
+            drop(w);
+            let mut output = vec![];
+            serve(&db, &Config::default(), &input[..], &mut output).unwrap();
+            tags(&messages(&output))
+        };
+        let tags =
+            session("CREATE TEMP TABLE t (id INT); INSERT INTO t VALUES (1); SELECT * FROM t");
+        assert!(tags.ends_with("CCTDCZ"), "{tags}");
+        // Gone with the session that made it.
+        assert!(db.lock().unwrap().engine().catalog().table("t").is_none());
+        assert!(session("SELECT * FROM t").ends_with("EZ"));
+    }
+}
@@ -0,0 +1,860 @@
+//! One client connection, from startup to Terminate.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::message::{self, Body, Startup, Writer};
+use super::{types, Config, Error};
+use crate::auth::{self, scram, Verifier};
+use crate::catalog::TempTables;
+use crate::database::Database;
+use crate::engine::{
+    self, Engine, Listener, OptimisticTransaction, Output, PreparedStatement, SessionSettings,
+};
+use crate::planner::Field;
+use crate::server::Workers;
+use crate::sql::{self, Token};
+use crate::value::{DataType, Tuple, Value};
+
+/// How long a session waiting out a `lock_timeout` sleeps between tries
+/// for the database; the mutex cannot be waited on with a deadline.
+const LOCK_POLL: Duration = Duration::from_millis(1);
+
+/// How often a session sitting idle outside a transaction looks for
+/// notifications to send, between waits for the client's next message.
+const NOTIFICATION_POLL: Duration = Duration::from_millis(50);
+
+/// A statement made by Parse. Empty queries have nothing to prepare.
+struct Statement {
+    prepared: Option<PreparedStatement>,
+    parameter_types: Vec<Option<DataType>>,
+}
+
+/// A statement bound to parameters by Bind.
+struct Portal {
+    prepared: Option<PreparedStatement>,
+    params: Vec<Value>,
+    result_formats: Vec<i16>,
+    /// Rows left over from an Execute that stopped at its row limit.
+    pending: Option<VecDeque<Tuple>>,
+}
+
+/// An error to send the client, with its SQLSTATE code.
+struct ErrorResponse {
+    code: &'static str,
+    message: String,
+}
+
+impl ErrorResponse {
+    fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<engine::Error> for ErrorResponse {
+    fn from(e: engine::Error) -> Self {
+        Self::new(e.code().sqlstate(), e.to_string())
+    }
+}
+
+pub(super) struct Session<'a, R, W: Write> {
+    db: &'a Mutex<Database>,
+    config: &'a Config,
+    reader: R,
+    writer: Writer<W>,
+    statements: HashMap<String, Statement>,
+    portals: HashMap<String, Portal>,
+    /// Set by an error in the extended protocol: messages are skipped
+    /// until the next Sync.
+    failed: bool,
+    /// The database, kept locked from BEGIN until the transaction ends so
+    /// that other sessions cannot see or join it, or until the client has
+    /// sat idle in it for the config's `idle_in_transaction_timeout`.
+    held: Option<MutexGuard<'a, Database>>,
+    /// The session's transaction under optimistic concurrency, which
+    /// holds only its row locks between statements, taken out of the
+    /// engine while other sessions use it.
+    optimistic: Option<OptimisticTransaction>,
+    /// Who statements run as, once logged in as a user of the database.
+    user: Option<String>,
+    /// What SET has made of the engine's settings; `None` until the
+    /// first statement, which starts from the engine's defaults.
+    settings: Option<SessionSettings>,
+    /// Where the notifications of the channels LISTEN names arrive.
+    listener: Listener,
+    /// The session's temporary tables, put in the engine while its
+    /// statements run, and dropped with the session.
+    temp_tables: Option<TempTables>,
+    /// Whether the connection goes through TLS.
+    encrypted: bool,
+    /// The connection's socket, to time out reads on, and the workers
+    /// its messages take turns on; `None` for a connection that is not
+    /// one, which reads and runs as long as it takes.
+    socket: Option<(TcpStream, &'a Workers)>,
+}
+
+impl<'a, R: Read, W: Write> Session<'a, R, W> {
+    pub fn new(
+        db: &'a Mutex<Database>,
+        config: &'a Config,
+        reader: R,
+        writer: W,
+        encrypted: bool,
+    ) -> Self {
+        Self {
+            db,
+            config,
+            reader,
+            writer: Writer::new(writer),
+            statements: HashMap::new(),
+            portals: HashMap::new(),
+            failed: false,
+            held: None,
+            optimistic: None,
+            user: None,
+            settings: None,
+            listener: Listener::new(),
+            temp_tables: None,
+            encrypted,
+            socket: None,
+        }
+    }
+
+    pub fn with_socket(mut self, socket: Option<(TcpStream, &'a Workers)>) -> Self {
+        self.socket = socket;
+        self
+    }
+
+    /// Serves the session, starting with `startup` if its startup packet
+    /// has been read already.
+    pub fn run(mut self, startup: Option<Startup>) -> Result<(), Error> {
+        if !self.start(startup)? {
+            return Ok(());
+        }
+        while let Some((tag, body)) = self.next_message()? {
+            if tag == b'X' {
+                break;
+            }
+            // A transaction holding the database must not wait for a
+            // worker held by a session waiting for the database.
+            let workers = self.socket.as_ref().map(|&(_, workers)| workers);
+            let _turn = workers.filter(|_| self.held.is_none()).map(Workers::take);
+            if let Err(e) = self.handle(tag, &body) {
+                // A message we cannot read leaves us lost in the stream.
+                if let Error::Protocol(message) = &e {
+                    self.send_error("FATAL", &ErrorResponse::new("08P01", message.clone()));
+                    self.writer.flush()?;
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// The next message from the client, or `None` once it has hung up,
+    /// or sat idle in a transaction for longer than the config allows.
+    /// Outside a transaction, notifications are sent while it waits.
+    fn next_message(&mut self) -> Result<Option<(u8, Vec<u8>)>, Error> {
+        let in_transaction = self.held.is_some() || self.optimistic.is_some();
+        let Some((socket, _)) = &self.socket else {
+            return message::read_message(&mut self.reader);
+        };
+        let timeout = if in_transaction {
+            (self.config.idle_in_transaction_timeout).filter(|timeout| !timeout.is_zero())
+        } else {
+            Some(NOTIFICATION_POLL)
+        };
+        socket.set_read_timeout(timeout)?;
+        // The tag is one byte, so a read timing out has taken none of the
+        // message, and the next one starts it over.
+        let tag = loop {
+            match message::read_tag(&mut self.reader) {
+                Err(Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    if in_transaction {
+                        let message = "terminating connection due to idle-in-transaction timeout";
+                        self.send_error("FATAL", &ErrorResponse::new("25P03", message));
+                        self.writer.flush()?;
+                        return Ok(None);
+                    }
+                    if self.send_notifications() {
+                        self.writer.flush()?;
+                    }
+                }
+                result => break result?,
+            }
+        };
+        let Some(tag) = tag else {
+            return Ok(None);
+        };
+        if let Some((socket, _)) = &self.socket {
+            socket.set_read_timeout(None)?;
+        }
+        Ok(Some((tag, message::read_body(&mut self.reader)?)))
+    }
+
+    /// Negotiates the connection and authenticates the client. Returns
+    /// whether the session should go on.
+    fn start(&mut self, mut startup: Option<Startup>) -> Result<bool, Error> {
+        let params = loop {
+            let next = match startup.take() {
+                Some(startup) => startup,
+                None => message::read_startup(&mut self.reader)?,
+            };
+            match next {
+                // Neither is supported here; the client may go on without.
+                Startup::Ssl | Startup::GssEnc => {
+                    self.writer.raw_byte(b'N');
+                    self.writer.flush()?;
+                }
+                Startup::Cancel => return Ok(false),
+                Startup::Start(params) => break params,
+            }
+        };
+        if self.config.require_tls && !self.encrypted {
+            let message = "connections must be encrypted: connect with sslmode=require";
+            self.send_error("FATAL", &ErrorResponse::new("28000", message));
+            self.writer.flush()?;
+            return Ok(false);
+        }
+        let user = params
+            .iter()
+            .find(|(name, _)| name == "user")
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| Error::Protocol("no user name given".to_string()))?;
+        let (has_users, verifier) = self.with_engine(|engine| {
+            let catalog = engine.catalog();
+            let verifier = catalog.user(&user).and_then(|user| user.verifier.clone());
+            (catalog.users().next().is_some(), verifier)
+        });
+        if has_users {
+            if !self.authenticate(&user, verifier.as_ref())? {
+                return Ok(false);
+            }
+            self.user = Some(user);
+        } else if let Some(password) = &self.config.password {
+            self.writer.message(b'R').put_i32(3);
+            self.writer.flush()?;
+            let given = match message::read_message(&mut self.reader)? {
+                Some((b'p', body)) => Body::new(&body).cstr()?,
+                Some(_) => return Err(Error::Protocol("expected a password".to_string())),
+                None => return Ok(false),
+            };
+            if given != *password {
+                let message = format!("password authentication failed for user {user:?}");
+                self.send_error("FATAL", &ErrorResponse::new("28P01", message));
+                self.writer.flush()?;
+                return Ok(false);
+            }
+        }
+        self.writer.message(b'R').put_i32(0);
+        for (name, value) in [
+            ("server_version", "16.0"),
+            ("server_encoding", "UTF8"),
+            ("client_encoding", "UTF8"),
+            ("DateStyle", "ISO, MDY"),
+            ("integer_datetimes", "on"),
+            ("standard_conforming_strings", "on"),
+        ] {
+            self.writer.message(b'S').put_cstr(name).put_cstr(value);
+        }
+        self.ready_for_query()?;
+        Ok(true)
+    }
+
+    /// Runs a SCRAM-SHA-256 exchange with the client, which fails unless
+    /// `verifier` is that of the password it knows. Returns whether it
+    /// succeeded, having told the client why not if it did not.
+    fn authenticate(&mut self, user: &str, verifier: Option<&Verifier>) -> Result<bool, Error> {
+        self.writer
+            .message(b'R')
+            .put_i32(10)
+            .put_cstr(scram::MECHANISM)
+            .put_u8(0);
+        self.writer.flush()?;
+        let Some(body) = self.sasl_response()? else {
+            return Ok(false);
+        };
+        let mut body = Body::new(&body);
+        if body.cstr()? != scram::MECHANISM {
+            let message = "unsupported SASL mechanism";
+            self.send_error("FATAL", &ErrorResponse::new("28000", message));
+            self.writer.flush()?;
+            return Ok(false);
+        }
+        let len = body.i32()?.max(0) as usize;
+        let client_first = String::from_utf8_lossy(body.bytes(len)?).to_string();
+        let result = match scram::Exchange::start(verifier, &client_first) {
+            Ok((exchange, server_first)) => {
+                self.writer
+                    .message(b'R')
+                    .put_i32(11)
+                    .put_bytes(server_first.as_bytes());
+                self.writer.flush()?;
+                let Some(body) = self.sasl_response()? else {
+                    return Ok(false);
+                };
+                exchange.finish(&String::from_utf8_lossy(&body))
+            }
+            Err(e) => Err(e),
+        };
+        let error = match result {
+            Ok(server_final) => {
+                self.writer
+                    .message(b'R')
+                    .put_i32(12)
+                    .put_bytes(server_final.as_bytes());
+                return Ok(true);
+            }
+            Err(auth::Error::Failed) => ErrorResponse::new(
+                "28P01",
+                format!("password authentication failed for user {user:?}"),
+            ),
+            Err(e @ auth::Error::Random) => ErrorResponse::new("XX000", e.to_string()),
+            Err(e) => ErrorResponse::new("08P01", e.to_string()),
+        };
+        self.send_error("FATAL", &error);
+        self.writer.flush()?;
+        Ok(false)
+    }
+
+    /// The body of a SASLInitialResponse or SASLResponse message; `None`
+    /// if the client hung up.
+    fn sasl_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        match message::read_message(&mut self.reader)? {
+            Some((b'p', body)) => Ok(Some(body)),
+            Some(_) => Err(Error::Protocol("expected a SASL response".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn handle(&mut self, tag: u8, body: &[u8]) -> Result<(), Error> {
+        let mut body = Body::new(body);
+        if self.failed && !matches!(tag, b'S' | b'Q') {
+            return Ok(());
+        }
+        let result = match tag {
+            b'Q' => {
+                let sql = body.cstr()?;
+                self.simple_query(&sql);
+                return self.ready_for_query();
+            }
+            b'P' => self.parse(&mut body),
+            b'B' => self.bind(&mut body),
+            b'D' => self.describe(&mut body),
+            b'E' => self.execute(&mut body),
+            b'C' => self.close(&mut body),
+            b'S' => {
+                self.failed = false;
+                return self.ready_for_query();
+            }
+            b'H' => return Ok(self.writer.flush()?),
+            _ => {
+                return Err(Error::Protocol(format!(
+                    "unknown message type {:?}",
+                    tag as char
+                )))
+            }
+        };
+        match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                self.send_error("ERROR", &e);
+                self.failed = true;
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends the notifications that have arrived, then ReadyForQuery.
+    fn ready_for_query(&mut self) -> Result<(), Error> {
+        self.send_notifications();
+        let in_transaction = self.held.is_some() || self.optimistic.is_some();
+        let status = if in_transaction { b'T' } else { b'I' };
+        self.writer.message(b'Z').put_u8(status);
+        Ok(self.writer.flush()?)
+    }
+
+    /// Writes the notifications that have arrived, and a warning if any
+    /// were dropped. Returns whether there was anything to write.
+    fn send_notifications(&mut self) -> bool {
+        let mut sent = false;
+        for notification in self.listener.try_iter() {
+            (self.writer.message(b'A'))
+                .put_i32(0)
+                .put_cstr(&notification.channel)
+                .put_cstr(&notification.payload);
+            sent = true;
+        }
+        let dropped = self.listener.take_dropped();
+        if dropped > 0 {
+            let message = format!("{dropped} notifications were dropped for a full queue");
+            self.send_notice("WARNING", &ErrorResponse::new("01000", message));
+            sent = true;
+        }
+        sent
+    }
+
+    /// Runs each statement of a Query message in turn, stopping at the
+    /// first that fails.
+    fn simple_query(&mut self, text: &str) {
+        self.statements.remove("");
+        self.portals.remove("");
+        let (mut statements, rest) = sql::split_statements(text);
+        if !rest.trim().is_empty() {
+            statements.push(rest);
+        }
+        if statements.is_empty() {
+            self.writer.message(b'I');
+        }
+        for sql in statements {
+            match self.with_engine_waiting(|engine| engine.execute(sql)) {
+                Ok(output) => {
+                    if let Output::Rows { fields, .. } = &output {
+                        self.row_description(fields, &[]);
+                    }
+                    let tag = command_tag(sql, &output);
+                    for row in output.into_rows() {
+                        self.data_row(&row, &[]);
+                    }
+                    self.writer.message(b'C').put_cstr(&tag);
+                }
+                Err(e) => {
+                    self.send_error("ERROR", &e.into());
+                    break;
+                }
+            }
+        }
+    }
+
+    fn parse(&mut self, body: &mut Body) -> Result<Result<(), ErrorResponse>, Error> {
+        let name = body.cstr()?;
+        let text = body.cstr()?;
+        let declared = (0..body.count()?)
+            .map(|_| body.i32())
+            .collect::<Result<Vec<_>, _>>()?;
+        if !name.is_empty() && self.statements.contains_key(&name) {
+            let message = format!("prepared statement {name:?} already exists");
+            return Ok(Err(ErrorResponse::new("42P05", message)));
+        }
+        let (mut statements, rest) = sql::split_statements(&text);
+        if !rest.trim().is_empty() {
+            statements.push(rest);
+        }
+        let prepared = match statements.as_slice() {
+            [] => None,
+            [sql] => match self.with_engine_waiting(|engine| engine.prepare(sql)) {
+                Ok(prepared) => Some(prepared),
+                Err(e) => return Ok(Err(e.into())),
+            },
+            _ => {
+                let message = "cannot insert multiple commands into a prepared statement";
+                return Ok(Err(ErrorResponse::new("42601", message)));
+            }
+        };
+        // What the statement does not say, the client may.
+        let parameter_types = prepared
+            .as_ref()
+            .map_or(&[][..], |prepared| prepared.parameter_types())
+            .iter()
+            .enumerate()
+            .map(|(i, data_type)| {
+                data_type.or_else(|| declared.get(i).and_then(|&oid| types::data_type_of(oid)))
+            })
+            .collect();
+        let statement = Statement {
+            prepared,
+            parameter_types,
+        };
+        self.statements.insert(name, statement);
+        self.writer.message(b'1');
+        Ok(Ok(()))
+    }
+
+    fn bind(&mut self, body: &mut Body) -> Result<Result<(), ErrorResponse>, Error> {
+        let portal = body.cstr()?;
+        let name = body.cstr()?;
+        let formats = (0..body.count()?)
+            .map(|_| body.i16())
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut raw = vec![];
+        for _ in 0..body.count()? {
+            raw.push(match body.i32()? {
+                -1 => None,
+                len => Some(body.bytes(len.max(0) as usize)?),
+            });
+        }
+        let result_formats = (0..body.count()?)
+            .map(|_| body.i16())
+            .collect::<Result<Vec<_>, _>>()?;
+        let Some(statement) = self.statements.get(&name) else {
+            let message = format!("prepared statement {name:?} does not exist");
+            return Ok(Err(ErrorResponse::new("26000", message)));
+        };
+        if raw.len() != statement.parameter_types.len() {
+            let message = format!(
+                "bind message supplies {} parameters, but the statement requires {}",
+                raw.len(),
+                statement.parameter_types.len()
+            );
+            return Ok(Err(ErrorResponse::new("08P01", message)));
+        }
+        let mut params = vec![];
+        for (i, (bytes, &data_type)) in raw.iter().zip(&statement.parameter_types).enumerate() {
+            let value = match bytes {
+                None => Value::Null,
+                Some(bytes) => match types::decode(bytes, format_of(&formats, i), data_type) {
+                    Ok(value) => value,
+                    Err(message) => {
+                        let message = format!("parameter ${}: {message}", i + 1);
+                        return Ok(Err(ErrorResponse::new("22P02", message)));
+                    }
+                },
+            };
+            params.push(value);
+        }
+        let portal_state = Portal {
+            prepared: statement.prepared.clone(),
+            params,
+            result_formats,
+            pending: None,
+        };
+        self.portals.insert(portal, portal_state);
+        self.writer.message(b'2');
+        Ok(Ok(()))
+    }
+
+    fn describe(&mut self, body: &mut Body) -> Result<Result<(), ErrorResponse>, Error> {
+        let kind = body.u8()?;
+        let name = body.cstr()?;
+        let (fields, formats) = match kind {
+            b'S' => {
+                let Some(statement) = self.statements.get(&name) else {
+                    let message = format!("prepared statement {name:?} does not exist");
+                    return Ok(Err(ErrorResponse::new("26000", message)));
+                };
+                let oids: Vec<i32> = statement
+                    .parameter_types
+                    .iter()
+                    .map(|&data_type| types::oid(data_type))
+                    .collect();
+                let fields = statement
+                    .prepared
+                    .as_ref()
+                    .and_then(|p| p.fields())
+                    .map(<[Field]>::to_vec);
+                self.writer.message(b't').put_i16(oids.len() as i16);
+                for oid in oids {
+                    self.writer.put_i32(oid);
+                }
+                (fields, vec![])
+            }
+            b'P' => {
+                let Some(portal) = self.portals.get(&name) else {
+                    let message = format!("portal {name:?} does not exist");
+                    return Ok(Err(ErrorResponse::new("34000", message)));
+                };
+                let fields = portal
+                    .prepared
+                    .as_ref()
+                    .and_then(|p| p.fields())
+                    .map(<[Field]>::to_vec);
+                (fields, portal.result_formats.clone())
+            }
+            _ => {
+                return Err(Error::Protocol(format!(
+                    "cannot describe {:?}",
+                    kind as char
+                )))
+            }
+        };
+        match fields {
+            Some(fields) => self.row_description(&fields, &formats),
+            None => {
+                self.writer.message(b'n');
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    fn execute(&mut self, body: &mut Body) -> Result<Result<(), ErrorResponse>, Error> {
+        let name = body.cstr()?;
+        let max_rows = body.i32()?;
+        let Some(mut portal) = self.portals.remove(&name) else {
+            let message = format!("portal {name:?} does not exist");
+            return Ok(Err(ErrorResponse::new("34000", message)));
+        };
+        let result = self.execute_portal(&mut portal, max_rows);
+        self.portals.insert(name, portal);
+        Ok(result)
+    }
+
+    fn execute_portal(&mut self, portal: &mut Portal, max_rows: i32) -> Result<(), ErrorResponse> {
+        let Some(prepared) = &portal.prepared else {
+            self.writer.message(b'I');
+            return Ok(());
+        };
+        let mut rows = match portal.pending.take() {
+            Some(rows) => rows,
+            None => {
+                let output = self.with_engine_waiting(|engine| {
+                    engine.execute_prepared(prepared, &portal.params)
+                })?;
+                match output {
+                    Output::Rows { rows, .. } => rows.into(),
+                    output => {
+                        let tag = command_tag(prepared.sql(), &output);
+                        self.writer.message(b'C').put_cstr(&tag);
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        let limit = if max_rows > 0 {
+            max_rows as usize
+        } else {
+            usize::MAX
+        };
+        let mut sent = 0;
+        while sent < limit {
+            let Some(row) = rows.pop_front() else { break };
+            self.data_row(&row, &portal.result_formats);
+            sent += 1;
+        }
+        if rows.is_empty() {
+            let output = Output::Rows {
+                fields: vec![],
+                rows: vec![vec![]; sent],
+            };
+            let tag = command_tag(prepared.sql(), &output);
+            self.writer.message(b'C').put_cstr(&tag);
+        } else {
+            portal.pending = Some(rows);
+            self.writer.message(b's');
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, body: &mut Body) -> Result<Result<(), ErrorResponse>, Error> {
+        let kind = body.u8()?;
+        let name = body.cstr()?;
+        match kind {
+            b'S' => self.statements.remove(&name).map(drop),
+            b'P' => self.portals.remove(&name).map(drop),
+            _ => return Err(Error::Protocol(format!("cannot close {:?}", kind as char))),
+        };
+        self.writer.message(b'3');
+        Ok(Ok(()))
+    }
+
+    fn row_description(&mut self, fields: &[Field], formats: &[i16]) {
+        self.writer.message(b'T').put_i16(fields.len() as i16);
+        for (i, field) in fields.iter().enumerate() {
+            let oid = types::oid(field.data_type);
+            self.writer
+                .put_cstr(&field.name)
+                .put_i32(0)
+                .put_i16(0)
+                .put_i32(oid)
+                .put_i16(types::type_len(oid))
+                .put_i32(-1)
+                .put_i16(format_of(formats, i));
+        }
+    }
+
+    fn data_row(&mut self, row: &[Value], formats: &[i16]) {
+        self.writer.message(b'D').put_i16(row.len() as i16);
+        for (i, value) in row.iter().enumerate() {
+            match types::encode(value, format_of(formats, i)) {
+                Some(bytes) => self.writer.put_i32(bytes.len() as i32).put_bytes(&bytes),
+                None => self.writer.put_i32(-1),
+            };
+        }
+    }
+
+    fn send_error(&mut self, severity: &str, e: &ErrorResponse) {
+        self.send_response(b'E', severity, e);
+    }
+
+    fn send_notice(&mut self, severity: &str, e: &ErrorResponse) {
+        self.send_response(b'N', severity, e);
+    }
+
+    fn send_response(&mut self, tag: u8, severity: &str, e: &ErrorResponse) {
+        self.writer
+            .message(tag)
+            .put_u8(b'S')
+            .put_cstr(severity)
+            .put_u8(b'V')
+            .put_cstr(severity)
+            .put_u8(b'C')
+            .put_cstr(e.code)
+            .put_u8(b'M')
+            .put_cstr(&e.message)
+            .put_u8(0);
+    }
+
+    /// Runs `f` on the engine, locking the database unless the session's
+    /// transaction holds it already.
+    fn with_engine<T>(&mut self, f: impl FnOnce(&mut Engine) -> T) -> T {
+        let db = match self.held.take() {
+            Some(db) => db,
+            None => self.db.lock().unwrap_or_else(PoisonError::into_inner),
+        };
+        self.with_locked(db, f)
+    }
+
+    /// Runs `f` as [`Session::with_engine`] does, but waits for another
+    /// session holding the database, and for the transactions holding rows
+    /// `f` asks for, for no longer than the session's `lock_timeout` in
+    /// all. NOWAIT and SKIP LOCKED are for the rows alone: the database is
+    /// waited for whatever a statement says, as a table lock would be.
+    fn with_engine_waiting<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Engine) -> Result<T, engine::Error>,
+    ) -> Result<T, engine::Error> {
+        let timeout = self.settings.as_ref().and_then(|s| s.lock_timeout);
+        engine::retry_row_waits(timeout, |deadline| {
+            let db = match self.held.take() {
+                Some(db) => db,
+                None => self.lock_database(deadline, timeout)?,
+            };
+            self.with_locked(db, &mut f)
+        })
+    }
+
+    /// Locks the database, failing once `deadline`, `timeout` after the
+    /// statement started, has passed.
+    fn lock_database(
+        &self,
+        deadline: Option<Instant>,
+        timeout: Option<Duration>,
+    ) -> Result<MutexGuard<'a, Database>, engine::Error> {
+        let (Some(deadline), Some(timeout)) = (deadline, timeout) else {
+            return Ok(self.db.lock().unwrap_or_else(PoisonError::into_inner));
+        };
+        loop {
+            match self.db.try_lock() {
+                Ok(db) => return Ok(db),
+                Err(TryLockError::Poisoned(e)) => return Ok(e.into_inner()),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(engine::Error::LockTimeout(timeout));
+            }
+            thread::sleep(LOCK_POLL.min(deadline - now));
+        }
+    }
+
+    fn with_locked<T>(
+        &mut self,
+        mut db: MutexGuard<'a, Database>,
+        f: impl FnOnce(&mut Engine) -> T,
+    ) -> T {
+        let engine = db.engine_mut();
+        if let Some(transaction) = self.optimistic.take() {
+            (engine.resume_transaction(transaction))
+                .expect("the engine has no transaction between statements");
+        }
+        engine.set_user(self.user.clone());
+        let settings = match self.settings.take() {
+            Some(settings) => settings,
+            None => engine.default_settings().clone(),
+        };
+        engine.set_settings(settings);
+        engine.set_listener(Some(&self.listener));
+        engine.set_temp_tables(self.temp_tables.take());
+        engine.set_lock_waits(true);
+        let result = f(engine);
+        engine.set_lock_waits(false);
+        self.temp_tables = engine.take_temp_tables();
+        engine.set_listener(None);
+        self.settings = Some(engine.settings().clone());
+        self.optimistic = engine.suspend_transaction();
+        if db.engine().in_transaction() {
+            self.held = Some(db);
+        }
+        result
+    }
+}
+
+impl<R, W: Write> Drop for Session<'_, R, W> {
+    /// A transaction the client left open is rolled back, which brings
+    /// back the temporary tables as they were when it began, to be
+    /// dropped with the others, and lets go of the rows it locked.
+    fn drop(&mut self) {
+        if let Some(mut db) = self.held.take() {
+            let _ = db.engine_mut().rollback();
+            db.engine_mut().take_temp_tables();
+        } else if let Some(transaction) = self.optimistic.take() {
+            let mut db = self.db.lock().unwrap_or_else(PoisonError::into_inner);
+            let engine = db.engine_mut();
+            if engine.resume_transaction(transaction).is_ok() {
+                let _ = engine.rollback();
+            }
+        }
+    }
+}
+
+/// The format of column or parameter `i`: none given means text, one
+/// applies to all.
+fn format_of(formats: &[i16], i: usize) -> i16 {
+    match formats {
+        [] => types::TEXT_FORMAT,
+        [format] => *format,
+        formats => formats.get(i).copied().unwrap_or(types::TEXT_FORMAT),
+    }
+}
+
+/// The CommandComplete tag for `sql`, such as `SELECT 3` or
+/// `CREATE TABLE`.
+fn command_tag(sql: &str, output: &Output) -> String {
+    let words: Vec<String> = sql::tokenize(sql)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(token, _)| match token {
+            Token::Word {
+                value,
+                quoted: false,
+            } => Some(value.to_uppercase()),
+            _ => None,
+        })
+        .take(3)
+        .collect();
+    let first = words.first().map_or("", String::as_str);
+    match output {
+        Output::Rows { .. } if first == "EXPLAIN" || first == "SHOW" => first.to_string(),
+        Output::Rows { rows, .. } => format!("SELECT {}", rows.len()),
+        Output::Affected(n) if first == "INSERT" => format!("INSERT 0 {n}"),
+        // As PostgreSQL tags them, whatever the rows.
+        Output::Affected(n) if first == "CREATE" => format!("SELECT {n}"),
+        Output::Affected(_) if first == "REFRESH" => "REFRESH MATERIALIZED VIEW".to_string(),
+        Output::Affected(n) => format!("{first} {n}"),
+        Output::Done if first == "CREATE" || first == "DROP" => {
+            let object = words[1..]
+                .iter()
+                .find(|word| *word != "UNIQUE")
+                .map_or("", String::as_str);
+            if object == "MATERIALIZED" {
+                return format!("{first} MATERIALIZED VIEW");
+            }
+            format!("{first} {object}")
+        }
+        Output::Done => match first {
+            "START" => "START TRANSACTION".to_string(),
+            "END" => "COMMIT".to_string(),
+            "ABORT" => "ROLLBACK".to_string(),
+            first => first.to_string(),
+        },
+    }
+}
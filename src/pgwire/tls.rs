@@ -0,0 +1,142 @@
+//! TLS for client connections, with rustls.
+//!
+//! A client asks for TLS with SSLRequest before its startup packet. A
+//! server given a [`Tls`] answers `S` and runs the handshake over the
+//! connection, and the session then goes through it; without one it
+//! answers `N`, and the client goes on unencrypted or gives up, as its
+//! `sslmode` says. Nothing may follow SSLRequest before the answer: bytes
+//! that did would have come unencrypted, and could have been put there by
+//! someone in between, so the connection is dropped.
+//!
+//! Clients are not asked for certificates; they log in as the session's
+//! authentication says.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+use super::Error;
+
+/// The certificate chain and key the server proves itself with, for
+/// [`Config::tls`](super::Config::tls). With it, SSLRequest is answered
+/// with a TLS handshake rather than declined, and with
+/// [`Config::require_tls`](super::Config::require_tls) a client that
+/// starts its session without is turned away with SQLSTATE 28000.
+#[derive(Clone)]
+pub struct Tls {
+    config: Arc<ServerConfig>,
+}
+
+impl fmt::Debug for Tls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tls").finish_non_exhaustive()
+    }
+}
+
+impl Tls {
+    /// Loads the certificate chain in PEM file `cert`, the server's own
+    /// first, and its private key in PEM file `key`.
+    pub fn from_pem_files(cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self, Error> {
+        let (cert, key) = (cert.as_ref(), key.as_ref());
+        let failed = |path: &Path, e: &dyn fmt::Display| {
+            Error::Tls(format!("cannot read {}: {e}", path.display()))
+        };
+        let certs = (CertificateDer::pem_file_iter(cert))
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| failed(cert, &e))?;
+        if certs.is_empty() {
+            return Err(failed(cert, &"no certificate in it"));
+        }
+        let key = PrivateKeyDer::from_pem_file(key).map_err(|e| failed(key, &e))?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let config = (ServerConfig::builder_with_provider(provider))
+            .with_safe_default_protocol_versions()
+            .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+
+    /// Starts a TLS session with the client over `reader` and `writer`,
+    /// whose handshake runs as it is first read from or written to. Clone
+    /// the half for the other one.
+    pub(super) fn accept<R: Read, W: Write>(
+        &self,
+        reader: R,
+        writer: W,
+    ) -> Result<Half<R, W>, Error> {
+        let connection = (ServerConnection::new(Arc::clone(&self.config)))
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        let stream = StreamOwned::new(connection, Transport { reader, writer });
+        Ok(Half(Rc::new(RefCell::new(stream))))
+    }
+}
+
+/// The connection under TLS, read from one half and written to the other.
+struct Transport<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl<R: Read, W> Read for Transport<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R, W: Write> Write for Transport<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+type Stream<R, W> = StreamOwned<ServerConnection, Transport<R, W>>;
+
+/// A session's reading or writing half of a TLS connection.
+pub(super) struct Half<R: Read, W: Write>(Rc<RefCell<Stream<R, W>>>);
+
+impl<R: Read, W: Write> Clone for Half<R, W> {
+    fn clone(&self) -> Self {
+        Half(Rc::clone(&self.0))
+    }
+}
+
+impl<R: Read, W: Write> Read for Half<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read(buf)
+    }
+}
+
+impl<R: Read, W: Write> Write for Half<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+impl<R: Read, W: Write> Drop for Half<R, W> {
+    /// The last half to go tells the client the session is over, for it
+    /// to tell from a connection cut short.
+    fn drop(&mut self) {
+        if Rc::strong_count(&self.0) == 1 {
+            let mut stream = self.0.borrow_mut();
+            stream.conn.send_close_notify();
+            let _ = stream.flush();
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Postgres type OIDs for our types, and values in text and binary format.
+
+use crate::array::Array;
+use crate::value::{DataType, Value};
+
+pub const BOOL: i32 = 16;
+pub const BYTEA: i32 = 17;
+pub const INT8: i32 = 20;
+pub const INT2: i32 = 21;
+pub const INT4: i32 = 23;
+pub const TEXT: i32 = 25;
+pub const FLOAT4: i32 = 700;
+pub const FLOAT8: i32 = 701;
+pub const VARCHAR: i32 = 1043;
+pub const JSON: i32 = 114;
+pub const JSONB: i32 = 3802;
+pub const BOOL_ARRAY: i32 = 1000;
+pub const BYTEA_ARRAY: i32 = 1001;
+pub const INT2_ARRAY: i32 = 1005;
+pub const INT4_ARRAY: i32 = 1007;
+pub const TEXT_ARRAY: i32 = 1009;
+pub const VARCHAR_ARRAY: i32 = 1015;
+pub const INT8_ARRAY: i32 = 1016;
+pub const FLOAT4_ARRAY: i32 = 1021;
+pub const FLOAT8_ARRAY: i32 = 1022;
+pub const JSON_ARRAY: i32 = 199;
+pub const JSONB_ARRAY: i32 = 3807;
+
+/// The version byte that starts a `jsonb` in binary format.
+const JSONB_VERSION: u8 = 1;
+
+pub const TEXT_FORMAT: i16 = 0;
+pub const BINARY_FORMAT: i16 = 1;
+
+/// The type a column of `data_type` is described as. Columns that are
+/// always NULL are described as text.
+pub fn oid(data_type: Option<DataType>) -> i32 {
+    match data_type {
+        Some(DataType::Bool) => BOOL,
+        Some(DataType::Int) => INT8,
+        Some(DataType::Float) => FLOAT8,
+        Some(DataType::Text) | None => TEXT,
+        Some(DataType::Bytes) => BYTEA,
+        Some(DataType::Json) => JSONB,
+        Some(DataType::Array(element)) => match element {
+            DataType::Bool => BOOL_ARRAY,
+            DataType::Int => INT8_ARRAY,
+            DataType::Float => FLOAT8_ARRAY,
+            DataType::Bytes => BYTEA_ARRAY,
+            DataType::Json => JSONB_ARRAY,
+            _ => TEXT_ARRAY,
+        },
+    }
+}
+
+/// The size of the type in RowDescription; -1 for variable length.
+pub fn type_len(oid: i32) -> i16 {
+    match oid {
+        BOOL => 1,
+        INT8 | FLOAT8 => 8,
+        _ => -1,
+    }
+}
+
+/// The type a parameter the client declared as `oid` is read as.
+pub fn data_type_of(oid: i32) -> Option<DataType> {
+    match oid {
+        BOOL => Some(DataType::Bool),
+        INT2 | INT4 | INT8 => Some(DataType::Int),
+        FLOAT4 | FLOAT8 => Some(DataType::Float),
+        TEXT | VARCHAR => Some(DataType::Text),
+        BYTEA => Some(DataType::Bytes),
+        JSON | JSONB => Some(DataType::Json),
+        BOOL_ARRAY => DataType::array(DataType::Bool),
+        INT2_ARRAY | INT4_ARRAY | INT8_ARRAY => DataType::array(DataType::Int),
+        FLOAT4_ARRAY | FLOAT8_ARRAY => DataType::array(DataType::Float),
+        TEXT_ARRAY | VARCHAR_ARRAY => DataType::array(DataType::Text),
+        BYTEA_ARRAY => DataType::array(DataType::Bytes),
+        JSON_ARRAY | JSONB_ARRAY => DataType::array(DataType::Json),
+        _ => None,
+    }
+}
+
+/// `value` as a DataRow field; `None` for NULL.
+pub fn encode(value: &Value, format: i16) -> Option<Vec<u8>> {
+    if format == BINARY_FORMAT {
+        return match value {
+            Value::Null => None,
+            Value::Bool(b) => Some(vec![*b as u8]),
+            Value::Int(i) => Some(i.to_be_bytes().to_vec()),
+            Value::Float(x) => Some(x.to_be_bytes().to_vec()),
+            Value::Text(s) => Some(s.as_bytes().to_vec()),
+            Value::Bytes(bytes) => Some(bytes.clone()),
+            Value::Json(json) => {
+                let mut bytes = vec![JSONB_VERSION];
+                bytes.extend(json.to_string().into_bytes());
+                Some(bytes)
+            }
+            Value::Array(array) => Some(encode_array(array)),
+        };
+    }
+    let text = match value {
+        Value::Null => return None,
+        Value::Bool(b) => if *b { "t" } else { "f" }.to_string(),
+        Value::Float(x) if x.is_infinite() => {
+            if *x > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+        }
+        // The text form, with each element as text format has it.
+        Value::Array(array) => {
+            let items = (array.items.iter())
+                .map(|item| match encode(item, TEXT_FORMAT) {
+                    Some(text) => Value::Text(String::from_utf8(text).unwrap()),
+                    None => Value::Null,
+                })
+                .collect();
+            Array::new(DataType::Text, items).to_string()
+        }
+        value => value.to_string(),
+    };
+    Some(text.into_bytes())
+}
+
+/// An array in binary format: the number of dimensions, whether there
+/// are NULLs and the element type, then the length and lower bound of
+/// the one dimension, if any, then each element after its length.
+fn encode_array(array: &Array) -> Vec<u8> {
+    let dimensions = i32::from(!array.items.is_empty());
+    let nulls = array.items.iter().any(Value::is_null);
+    let mut bytes = vec![];
+    bytes.extend(dimensions.to_be_bytes());
+    bytes.extend(i32::from(nulls).to_be_bytes());
+    bytes.extend(oid(Some(array.element)).to_be_bytes());
+    if dimensions == 1 {
+        bytes.extend((array.items.len() as i32).to_be_bytes());
+        bytes.extend(1i32.to_be_bytes());
+    }
+    for item in &array.items {
+        match encode(item, BINARY_FORMAT) {
+            Some(item) => {
+                bytes.extend((item.len() as i32).to_be_bytes());
+                bytes.extend(item);
+            }
+            None => bytes.extend((-1i32).to_be_bytes()),
+        }
+    }
+    bytes
+}
+
+/// The elements of an array in binary format, read as `element`.
+fn decode_array(mut bytes: &[u8], element: DataType) -> Option<Value> {
+    fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        let head = bytes.get(..len)?;
+        *bytes = &bytes[len..];
+        Some(head)
+    }
+    let int = |bytes: &mut &[u8]| Some(i32::from_be_bytes(take(bytes, 4)?.try_into().unwrap()));
+    let dimensions = int(&mut bytes)?;
+    let (_nulls, _oid) = (int(&mut bytes)?, int(&mut bytes)?);
+    let len = match dimensions {
+        0 => 0,
+        1 => {
+            let len = usize::try_from(int(&mut bytes)?).ok()?;
+            int(&mut bytes)?;
+            len
+        }
+        _ => return None,
+    };
+    let mut items = vec![];
+    for _ in 0..len {
+        let item = match int(&mut bytes)? {
+            -1 => Value::Null,
+            item_len => {
+                let item = take(&mut bytes, usize::try_from(item_len).ok()?)?;
+                decode(item, BINARY_FORMAT, Some(element)).ok()?
+            }
+        };
+        items.push(item);
+    }
+    bytes
+        .is_empty()
+        .then(|| Value::array(Array::new(element, items)))
+}
+
+/// A parameter sent as `bytes` in `format`, read as `data_type`; text if
+/// nothing says what it is.
+pub fn decode(bytes: &[u8], format: i16, data_type: Option<DataType>) -> Result<Value, String> {
+    let data_type = data_type.unwrap_or(DataType::Text);
+    let invalid = || format!("invalid input for type {data_type}");
+    if format == BINARY_FORMAT {
+        return match data_type {
+            DataType::Bool => match bytes {
+                [b] => Ok(Value::Bool(*b != 0)),
+                _ => Err(invalid()),
+            },
+            DataType::Int => match bytes.len() {
+                2 => Ok(Value::Int(
+                    i16::from_be_bytes(bytes.try_into().unwrap()).into(),
+                )),
+                4 => Ok(Value::Int(
+                    i32::from_be_bytes(bytes.try_into().unwrap()).into(),
+                )),
+                8 => Ok(Value::Int(i64::from_be_bytes(bytes.try_into().unwrap()))),
+                _ => Err(invalid()),
+            },
+            DataType::Float => match bytes.len() {
+                4 => Ok(Value::Float(
+                    f32::from_be_bytes(bytes.try_into().unwrap()).into(),
+                )),
+                8 => Ok(Value::Float(f64::from_be_bytes(bytes.try_into().unwrap()))),
+                _ => Err(invalid()),
+            },
+            DataType::Text => String::from_utf8(bytes.to_vec())
+                .map(Value::Text)
+                .map_err(|_| invalid()),
+            DataType::Bytes => Ok(Value::Bytes(bytes.to_vec())),
+            // As `jsonb` after its version byte, or as `json`, which is
+            // text in either format.
+            DataType::Json => {
+                std::str::from_utf8(bytes.strip_prefix(&[JSONB_VERSION]).unwrap_or(bytes))
+                    .ok()
+                    .and_then(|text| Value::parse(text, DataType::Json))
+                    .ok_or_else(invalid)
+            }
+            DataType::Array(element) => decode_array(bytes, *element).ok_or_else(invalid),
+        };
+    }
+    let text = std::str::from_utf8(bytes).map_err(|_| invalid())?;
+    Value::parse(text, data_type).ok_or_else(invalid)
+}
@@ -0,0 +1,3612 @@
+//! Name resolution and type checking.
+//!
+//! Column references become positions in the input of the node that
+//! evaluates them. Once a query aggregates, expressions above the
+//! aggregation may only use grouping keys (matched structurally, so
+//! `GROUP BY a + 1` allows `SELECT a + 1`) and aggregate calls.
+//!
+//! Types are checked bottom-up. NULL literals have no type and fit
+//! anywhere. The only implicit coercion is widening INT to FLOAT, made
+//! explicit with a cast where a FLOAT column is assigned or a VALUES list
+//! mixes the two; operators compare and combine ints and floats as is.
+//!
+//! UNION columns are typed like VALUES columns. CTEs are bound in order,
+//! each seeing those before it, and read through work tables; once the
+//! query is bound, a CTE read only once is inlined in its place and any
+//! other is materialized, unless the query says which. A recursive CTE
+//! is the UNION of the arms before its last one and that last arm, which
+//! reads the rows the previous round added.
+//!
+//! Text compares under the collation of a column it comes from, unless
+//! an explicit COLLATE on either side of a comparison overrides it. Both
+//! sides of a comparison, ORDER BY keys and index keys are then wrapped in
+//! the collation's sort key, so an index on a collated column serves the
+//! comparisons made on it.
+//!
+//! Parameters (`$n`) start out untyped like NULL and take the type their
+//! context expects on first use: the other operand of a comparison or
+//! arithmetic, BOOL under AND/OR/NOT, TEXT in LIKE, or the column they are
+//! assigned to.
+//!
+//! A statement bound for a user is checked against the privileges the
+//! user holds: reading a table takes SELECT, and INSERT, UPDATE and
+//! DELETE take their own privilege, plus SELECT when they read the
+//! table's columns in a WHERE clause or an assignment. Changing the schema or
+//! the users takes a superuser, except that users may change their own
+//! password. The [policies](crate::catalog::PolicyInfo) of a table hold
+//! back the rows of it the user has not been given: every scan of the
+//! table filters them out, as do UPDATE and DELETE, and the executor
+//! refuses to write them.
+//!
+//! A materialized view reads like a table but only changes by refresh,
+//! which binds the query kept in its definition again.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+
+use super::logical::{BoundStatement, CopyFormat, Field, IndexDef, LogicalPlan};
+use super::Error;
+use crate::auth::Verifier;
+use crate::catalog::{
+    Catalog, Column, IndexKey, PartitionBound, Partitioning, Persistence, PolicyInfo, Privileges,
+    Schema, SystemTable, TableInfo, TriggerAction, TriggerInfo, Ttl, User, ViewInfo,
+};
+use crate::collation::Collation;
+use crate::csv;
+use crate::executor::{
+    AggregateExpr, AggregateFunction, ConflictAction, Frame, JoinKind, NullOrdering, OnConflict,
+    SortKey, WindowExpr,
+};
+use crate::expr::{BinaryOp, Expr, ScalarFunction, UnaryOp, UserFunction};
+use crate::lock::LockMode;
+use crate::sql::{self, ast};
+use crate::value::{DataType, Value};
+
+/// Checks `statement` against `catalog`.
+pub fn bind(catalog: &Catalog, statement: &ast::Statement) -> Result<BoundStatement, Error> {
+    bind_prepared(catalog, statement, None, NullOrdering::default()).map(|(statement, _)| statement)
+}
+
+/// Like [`bind`], but also returns the types inferred for the parameters
+/// `$1..$n` the statement uses; `None` where nothing constrains one. With
+/// a `user`, the statement must also be one the user may run; without,
+/// anything goes. Sort and index keys that do not say where their NULLs
+/// go have them where `null_ordering` puts them.
+pub fn bind_prepared(
+    catalog: &Catalog,
+    statement: &ast::Statement,
+    user: Option<&str>,
+    null_ordering: NullOrdering,
+) -> Result<(BoundStatement, Vec<Option<DataType>>), Error> {
+    let binder = Binder {
+        catalog,
+        user,
+        null_ordering,
+        parameters: RefCell::default(),
+        ctes: RefCell::default(),
+        work_tables: Cell::new(0),
+    };
+    let statement = binder.statement(statement)?;
+    Ok((statement, binder.parameters.into_inner()))
+}
+
+type Typed = (Expr, Option<DataType>);
+
+/// Window function results are referenced through placeholder columns
+/// from here up until the window nodes are laid out.
+const WINDOW_BASE: usize = usize::MAX / 2;
+
+#[derive(Debug, Clone)]
+struct ScopeColumn {
+    qualifier: String,
+    name: String,
+    data_type: Option<DataType>,
+    collation: Collation,
+    /// The table of a column the user may not read, of a table they were
+    /// granted other columns of.
+    denied: Option<String>,
+}
+
+/// The columns an expression can name, in input order.
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    columns: Vec<ScopeColumn>,
+}
+
+impl Scope {
+    fn new(qualifier: &str, fields: &[Field]) -> Self {
+        let columns = fields
+            .iter()
+            .map(|field| ScopeColumn {
+                qualifier: qualifier.to_string(),
+                name: field.name.clone(),
+                data_type: field.data_type,
+                collation: field.collation,
+                denied: None,
+            })
+            .collect();
+        Self { columns }
+    }
+
+    /// The columns of a table, which a table of an attached database
+    /// qualifies by its name there.
+    fn table(qualifier: &str, schema: &Schema) -> Self {
+        let qualifier = qualifier
+            .split_once('.')
+            .map_or(qualifier, |(_, table)| table);
+        Self::new(qualifier, &schema_fields(schema))
+    }
+
+    /// The scope with every column but `readable` denied, as of `table`.
+    fn readable(mut self, table: &str, readable: &BTreeSet<String>) -> Self {
+        for column in &mut self.columns {
+            if !readable.contains(&column.name) {
+                column.denied = Some(table.to_string());
+            }
+        }
+        self
+    }
+
+    fn has_qualifier(&self, qualifier: &str) -> bool {
+        self.columns.iter().any(|c| c.qualifier == qualifier)
+    }
+
+    fn concat(mut self, other: Scope) -> Result<Self, Error> {
+        for column in &other.columns {
+            if self.has_qualifier(&column.qualifier) {
+                return Err(Error::DuplicateTable(column.qualifier.clone()));
+            }
+        }
+        self.columns.extend(other.columns);
+        Ok(self)
+    }
+
+    fn resolve(&self, name: &[String]) -> Result<(usize, Option<DataType>), Error> {
+        let (qualifier, column) = match name {
+            [column] => (None, column),
+            [qualifier, column] => (Some(qualifier), column),
+            _ => return Err(Error::ColumnNotFound(name.join("."))),
+        };
+        if let Some(qualifier) = qualifier {
+            if !self.has_qualifier(qualifier) {
+                return Err(Error::UnknownTable(qualifier.clone()));
+            }
+        }
+        let mut matches = self.columns.iter().enumerate().filter(|(_, c)| {
+            c.name == *column && qualifier.is_none_or(|qualifier| c.qualifier == *qualifier)
+        });
+        let Some((i, found)) = matches.next() else {
+            return Err(Error::ColumnNotFound(name.join(".")));
+        };
+        if matches.next().is_some() {
+            return Err(Error::AmbiguousColumn(name.join(".")));
+        }
+        if let Some(table) = &found.denied {
+            return Err(Error::ColumnPermissionDenied {
+                column: found.name.clone(),
+                table: table.clone(),
+            });
+        }
+        Ok((i, found.data_type))
+    }
+}
+
+fn schema_fields(schema: &Schema) -> Vec<Field> {
+    schema
+        .columns
+        .iter()
+        .map(|column| Field::new(&column.name, Some(column.data_type)).collate(column.collation))
+        .collect()
+}
+
+/// Grouping keys and the aggregates collected so far, in output order.
+struct Grouping {
+    keys: Vec<(Typed, String)>,
+    aggregates: Vec<(AggregateExpr, Field)>,
+}
+
+struct WindowCall {
+    partition_by: Vec<Expr>,
+    order_by: Vec<SortKey>,
+    func: WindowExpr,
+    field: Field,
+}
+
+/// What an expression may refer to where it appears.
+struct ExprContext<'s> {
+    scope: &'s Scope,
+    /// Clause being bound, for error messages.
+    clause: &'static str,
+    /// Set above an aggregation.
+    grouping: Option<Grouping>,
+    /// Set where window functions are allowed.
+    windows: Option<Vec<WindowCall>>,
+}
+
+impl<'s> ExprContext<'s> {
+    fn plain(scope: &'s Scope, clause: &'static str) -> Self {
+        Self {
+            scope,
+            clause,
+            grouping: None,
+            windows: None,
+        }
+    }
+
+    /// The collation `expr` compares under: the one it names with COLLATE,
+    /// or else that of the column it is.
+    fn collation(&self, expr: &Expr) -> Collation {
+        match (expr, &self.grouping) {
+            (Expr::Collate { collation, .. }, _) => Some(*collation),
+            (Expr::Column(i), None) => self.scope.columns.get(*i).map(|c| c.collation),
+            (Expr::Column(i), Some(grouping)) => grouping
+                .keys
+                .get(*i)
+                .map(|((key, _), _)| ExprContext::plain(self.scope, self.clause).collation(key)),
+            _ => None,
+        }
+        .unwrap_or_default()
+    }
+
+    /// Puts both sides of a comparison under one collation: one named with
+    /// COLLATE on either side, or else that of a collated column.
+    fn compare(&self, lhs: Expr, rhs: Expr) -> (Expr, Expr) {
+        let explicit = |expr: &Expr| matches!(expr, Expr::Collate { .. });
+        let collation = match self.collation(&lhs) {
+            collation if explicit(&lhs) => collation,
+            collation if collation != Collation::Binary && !explicit(&rhs) => collation,
+            _ => self.collation(&rhs),
+        };
+        (
+            uncollated(lhs).collate(collation),
+            uncollated(rhs).collate(collation),
+        )
+    }
+
+    /// A sort key ordering by `expr` under its collation.
+    fn sort_key(&self, expr: Expr) -> Expr {
+        let collation = self.collation(&expr);
+        uncollated(expr).collate(collation)
+    }
+}
+
+fn aggregate_function(name: &str) -> Option<AggregateFunction> {
+    Some(match name {
+        "count" => AggregateFunction::Count,
+        "sum" => AggregateFunction::Sum,
+        "min" => AggregateFunction::Min,
+        "max" => AggregateFunction::Max,
+        "avg" => AggregateFunction::Avg,
+        _ => return None,
+    })
+}
+
+/// The aggregate called `name`, built in or registered.
+fn find_aggregate(catalog: &Catalog, name: &str) -> Option<AggregateFunction> {
+    aggregate_function(name).or_else(|| {
+        catalog
+            .aggregate(name)
+            .cloned()
+            .map(AggregateFunction::User)
+    })
+}
+
+/// Whether `name` is a function SQL knows without the catalog.
+pub fn is_builtin_function(name: &str) -> bool {
+    ScalarFunction::from_name(name).is_some()
+        || aggregate_function(name).is_some()
+        || matches!(name, "row_number" | "rank")
+}
+
+/// Whether `expr` calls an aggregate outside of a window.
+fn contains_aggregate(catalog: &Catalog, expr: &ast::Expr) -> bool {
+    let contains = |expr: &ast::Expr| contains_aggregate(catalog, expr);
+    match expr {
+        ast::Expr::Identifier(_) | ast::Expr::Literal(_) | ast::Expr::Parameter(_) => false,
+        ast::Expr::Unary { expr, .. }
+        | ast::Expr::IsNull { expr, .. }
+        | ast::Expr::Cast { expr, .. }
+        | ast::Expr::Collate { expr, .. } => contains(expr),
+        ast::Expr::Binary { lhs, rhs, .. } => contains(lhs) || contains(rhs),
+        ast::Expr::Between {
+            expr, low, high, ..
+        } => contains(expr) || contains(low) || contains(high),
+        ast::Expr::InList { expr, list, .. } => contains(expr) || list.iter().any(contains),
+        ast::Expr::Like { expr, pattern, .. }
+        | ast::Expr::Any {
+            expr,
+            array: pattern,
+            ..
+        } => contains(expr) || contains(pattern),
+        ast::Expr::Array(items) => items.iter().any(contains),
+        ast::Expr::Function(function) => {
+            (function.over.is_none() && find_aggregate(catalog, &function.name).is_some())
+                || function.args.iter().any(contains)
+                || function.over.as_ref().is_some_and(|over| {
+                    over.partition_by.iter().any(contains)
+                        || over.order_by.iter().any(|key| contains(&key.expr))
+                })
+        }
+    }
+}
+
+/// Output name of a select item without an alias.
+fn column_name(expr: &ast::Expr) -> String {
+    match expr {
+        ast::Expr::Identifier(name) => name.last().unwrap().clone(),
+        ast::Expr::Function(function) => function.name.clone(),
+        ast::Expr::Cast { expr, .. } | ast::Expr::Collate { expr, .. } => column_name(expr),
+        ast::Expr::Array(_) => "array".to_string(),
+        _ => "?column?".to_string(),
+    }
+}
+
+fn describe(data_type: Option<DataType>) -> String {
+    data_type.map_or_else(|| "NULL".to_string(), |data_type| data_type.to_string())
+}
+
+fn is_numeric(data_type: DataType) -> bool {
+    matches!(data_type, DataType::Int | DataType::Float)
+}
+
+fn binary_type(
+    op: BinaryOp,
+    lhs: Option<DataType>,
+    rhs: Option<DataType>,
+) -> Result<Option<DataType>, Error> {
+    let mismatch = || Error::OperatorType {
+        op,
+        lhs: describe(lhs),
+        rhs: describe(rhs),
+    };
+    let known = || lhs.into_iter().chain(rhs);
+    match op {
+        BinaryOp::And | BinaryOp::Or => {
+            if known().any(|t| t != DataType::Bool) {
+                return Err(mismatch());
+            }
+            Ok(Some(DataType::Bool))
+        }
+        BinaryOp::Eq
+        | BinaryOp::NotEq
+        | BinaryOp::Lt
+        | BinaryOp::LtEq
+        | BinaryOp::Gt
+        | BinaryOp::GtEq => match (lhs, rhs) {
+            (Some(l), Some(r)) if l != r && !(is_numeric(l) && is_numeric(r)) => Err(mismatch()),
+            _ => Ok(Some(DataType::Bool)),
+        },
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div | BinaryOp::Mod => {
+            if known().any(|t| !is_numeric(t)) {
+                return Err(mismatch());
+            }
+            if known().any(|t| t == DataType::Float) {
+                Ok(Some(DataType::Float))
+            } else {
+                Ok(known().next())
+            }
+        }
+        BinaryOp::Concat => match (lhs, rhs) {
+            (Some(l), Some(r)) if l != r => Err(mismatch()),
+            _ => match known().next() {
+                Some(t) if !matches!(t, DataType::Text | DataType::Bytes | DataType::Array(_)) => {
+                    Err(mismatch())
+                }
+                t => Ok(t),
+            },
+        },
+        BinaryOp::Like => {
+            if known().any(|t| t != DataType::Text) {
+                return Err(mismatch());
+            }
+            Ok(Some(DataType::Bool))
+        }
+        BinaryOp::JsonGet | BinaryOp::JsonGetText => {
+            let key = |t| matches!(t, DataType::Text | DataType::Int);
+            if lhs.is_some_and(|t| t != DataType::Json) || rhs.is_some_and(|t| !key(t)) {
+                return Err(mismatch());
+            }
+            Ok(Some(match op {
+                BinaryOp::JsonGet => DataType::Json,
+                _ => DataType::Text,
+            }))
+        }
+        BinaryOp::Subscript => match (lhs, rhs) {
+            (Some(DataType::Array(element)), None | Some(DataType::Int)) => Ok(Some(*element)),
+            (None, None | Some(DataType::Int)) => Ok(None),
+            _ => Err(mismatch()),
+        },
+    }
+}
+
+fn castable(from: DataType, to: DataType) -> bool {
+    use DataType::*;
+    if let (Array(from), Array(to)) = (from, to) {
+        return castable(*from, *to);
+    }
+    from == to
+        || from == Text
+        || to == Text
+        || matches!(
+            (from, to),
+            (Int, Float) | (Float, Int) | (Int, Bool) | (Bool, Int) | (Json, Int | Float | Bool)
+        )
+}
+
+fn aggregate_type(
+    func: &AggregateFunction,
+    arg: Option<DataType>,
+) -> Result<Option<DataType>, Error> {
+    let numeric = |arg: Option<DataType>| match arg {
+        Some(data_type) if !is_numeric(data_type) => Err(Error::AggregateType {
+            func: func.clone(),
+            data_type,
+        }),
+        _ => Ok(arg),
+    };
+    Ok(match func {
+        AggregateFunction::Count => Some(DataType::Int),
+        AggregateFunction::Sum => numeric(arg)?,
+        AggregateFunction::Avg => numeric(arg).map(|_| Some(DataType::Float))?,
+        AggregateFunction::Min | AggregateFunction::Max => arg,
+        AggregateFunction::User(aggregate) => Some(aggregate.return_type),
+    })
+}
+
+/// Converts a value of type `from` for storage in `column`.
+fn assign(column: &Column, expr: Expr, from: Option<DataType>) -> Result<Expr, Error> {
+    match from {
+        None => Ok(expr),
+        Some(from) if from == column.data_type => Ok(expr),
+        Some(DataType::Int) if column.data_type == DataType::Float => Ok(widen(expr)),
+        // Text is JSON or an array written out, as in a literal; it fails
+        // if it is not.
+        Some(DataType::Text) if matches!(column.data_type, DataType::Json | DataType::Array(_)) => {
+            Ok(Expr::Cast {
+                expr: Box::new(expr),
+                data_type: column.data_type,
+            })
+        }
+        Some(DataType::Array(DataType::Int))
+            if column.data_type == DataType::Array(&DataType::Float) =>
+        {
+            Ok(Expr::Cast {
+                expr: Box::new(expr),
+                data_type: column.data_type,
+            })
+        }
+        Some(actual) => Err(Error::ColumnType {
+            column: column.name.clone(),
+            expected: column.data_type,
+            actual,
+        }),
+    }
+}
+
+/// Whether any of `exprs` refers to a column.
+fn reads_columns<'e>(exprs: impl IntoIterator<Item = &'e Expr>) -> bool {
+    let mut reads = false;
+    for expr in exprs {
+        expr.visit_columns(&mut |_| reads = true);
+    }
+    reads
+}
+
+fn widen(expr: Expr) -> Expr {
+    Expr::Cast {
+        expr: Box::new(expr),
+        data_type: DataType::Float,
+    }
+}
+
+/// `expr` without the COLLATE on top of it, if it has one.
+fn uncollated(expr: Expr) -> Expr {
+    match expr {
+        Expr::Collate { expr, .. } => *expr,
+        expr => expr,
+    }
+}
+
+/// Whether values of `data_type` have text to collate.
+fn collatable(data_type: DataType) -> bool {
+    matches!(data_type, DataType::Text | DataType::Array(DataType::Text))
+}
+
+fn negate(expr: Expr, negated: bool) -> Expr {
+    if negated {
+        Expr::unary(UnaryOp::Not, expr)
+    } else {
+        expr
+    }
+}
+
+/// Evaluates a LIMIT or OFFSET argument, which may not refer to columns.
+fn constant_count(expr: &Typed, clause: &'static str) -> Result<usize, Error> {
+    match expr.0.eval(&[]) {
+        Ok(Value::Int(n)) if n >= 0 => Ok(n as usize),
+        _ => Err(Error::InvalidLimit(clause)),
+    }
+}
+
+fn frame(spec: &ast::WindowSpec) -> Result<Frame, Error> {
+    use ast::FrameBound::*;
+    let Some(frame) = spec.frame else {
+        return Ok(if spec.order_by.is_empty() {
+            Frame::Partition
+        } else {
+            Frame::ToCurrentPeers
+        });
+    };
+    let unsupported = Error::Unsupported("this window frame");
+    match (frame.units, frame.start, frame.end) {
+        (_, UnboundedPreceding, UnboundedFollowing) => Ok(Frame::Partition),
+        (ast::FrameUnits::Range, UnboundedPreceding, CurrentRow) => Ok(Frame::ToCurrentPeers),
+        (ast::FrameUnits::Rows, start, end) => {
+            let preceding = match start {
+                UnboundedPreceding => None,
+                Preceding(n) => Some(n as usize),
+                CurrentRow => Some(0),
+                _ => return Err(unsupported),
+            };
+            let following = match end {
+                UnboundedFollowing => None,
+                Following(n) => Some(n as usize),
+                CurrentRow => Some(0),
+                _ => return Err(unsupported),
+            };
+            Ok(Frame::Rows {
+                preceding,
+                following,
+            })
+        }
+        _ => Err(unsupported),
+    }
+}
+
+/// A CTE, or the working table of a recursive one, that table references
+/// can name.
+struct CteScope {
+    name: String,
+    id: usize,
+    fields: Vec<Field>,
+}
+
+struct Binder<'c> {
+    catalog: &'c Catalog,
+    user: Option<&'c str>,
+    null_ordering: NullOrdering,
+    /// Types of the parameters seen so far, by number.
+    parameters: RefCell<Vec<Option<DataType>>>,
+    /// CTEs in scope, innermost last; they hide tables of the same name.
+    ctes: RefCell<Vec<CteScope>>,
+    /// Work table ids handed out so far.
+    work_tables: Cell<usize>,
+}
+
+impl Binder<'_> {
+    /// Whether `key` has its NULLs first: as it says, or else where the
+    /// null ordering puts them.
+    fn nulls_first(&self, key: &ast::OrderByExpr) -> bool {
+        (key.nulls_first).unwrap_or_else(|| self.null_ordering.nulls_first(key.descending))
+    }
+
+    /// `expr` ordered as `key` says.
+    fn order_key(&self, expr: Expr, key: &ast::OrderByExpr) -> SortKey {
+        SortKey {
+            expr,
+            descending: key.descending,
+            nulls_first: self.nulls_first(key),
+        }
+    }
+
+    fn table(&self, name: &str) -> Result<&TableInfo, Error> {
+        self.catalog
+            .table(name)
+            .ok_or_else(|| Error::TableNotFound(name.to_string()))
+    }
+
+    /// A table that statements may change, which a materialized view is
+    /// not.
+    fn target_table(&self, name: &str) -> Result<&TableInfo, Error> {
+        let table = self.table(name)?;
+        if table.view.is_some() {
+            return Err(Error::ViewNotWritable(name.to_string()));
+        }
+        Ok(table)
+    }
+
+    /// Fails unless the user may do all of `privileges` to `table`, as
+    /// anyone may to a temporary table.
+    fn check(&self, table: &str, privileges: Privileges) -> Result<(), Error> {
+        let Some(user) = self.user else {
+            return Ok(());
+        };
+        if self.is_temporary(table) {
+            return Ok(());
+        }
+        let granted = self
+            .catalog
+            .user(user)
+            .map_or(Privileges::NONE, |user| user.privileges(table));
+        if granted.contains(privileges) {
+            return Ok(());
+        }
+        Err(Error::PermissionDenied {
+            privileges: privileges.without(granted),
+            table: table.to_string(),
+        })
+    }
+
+    /// The columns of `table` the user may read, if granted SELECT on
+    /// only some of them.
+    fn readable_columns(&self, table: &str) -> Option<&BTreeSet<String>> {
+        let user = self.catalog.user(self.user?)?;
+        if self.is_temporary(table) || user.privileges(table).contains(Privileges::SELECT) {
+            return None;
+        }
+        user.readable_columns(table)
+    }
+
+    /// What reading columns of `table` takes: SELECT on it, unless the
+    /// user was granted some of its columns, which its scope then checks.
+    fn read_privilege(&self, table: &str) -> Privileges {
+        match self.readable_columns(table) {
+            Some(_) => Privileges::NONE,
+            None => Privileges::SELECT,
+        }
+    }
+
+    /// The columns of `table` under `qualifier`, those the user may not
+    /// read failing when named.
+    fn table_scope(&self, qualifier: &str, table: &TableInfo) -> Scope {
+        let scope = Scope::table(qualifier, &table.schema);
+        match self.readable_columns(&table.name) {
+            Some(readable) => scope.readable(&table.name, readable),
+            None => scope,
+        }
+    }
+
+    /// The condition the rows of `table` the user has been given meet,
+    /// if the user has not been given them all.
+    fn row_filter(&self, table: &str) -> Option<Expr> {
+        self.catalog.row_filter(table, self.user?)
+    }
+
+    fn is_temporary(&self, table: &str) -> bool {
+        self.catalog.persistence(table) == Persistence::Temporary
+    }
+
+    /// Fails if `table` is a temporary or unlogged table, which `what`
+    /// is not supported for.
+    fn check_permanent(&self, table: &str, what: &'static str) -> Result<(), Error> {
+        match self.catalog.persistence(table) {
+            Persistence::Permanent => Ok(()),
+            _ => Err(Error::Unsupported(what)),
+        }
+    }
+
+    /// Fails unless the user is a superuser.
+    fn check_superuser(&self, action: &'static str) -> Result<(), Error> {
+        match self.user {
+            Some(user) if !self.catalog.user(user).is_some_and(|user| user.superuser) => {
+                Err(Error::MustBeSuperuser(action))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn statement(&self, statement: &ast::Statement) -> Result<BoundStatement, Error> {
+        match statement {
+            // Temporary tables are for their session to do with as it likes.
+            ast::Statement::CreateTable(create) if create.persistence == Persistence::Temporary => {
+            }
+            ast::Statement::DropTable { name, .. }
+            | ast::Statement::CreateIndex(ast::CreateIndex { table: name, .. })
+                if self.is_temporary(name) => {}
+            ast::Statement::DropIndex { name, .. }
+                if (self.catalog.index_table(name)).is_some_and(|t| self.is_temporary(&t.name)) => {
+            }
+            ast::Statement::CreateTable(_)
+            | ast::Statement::CreatePartition { .. }
+            | ast::Statement::AttachPartition { .. }
+            | ast::Statement::DetachPartition { .. }
+            | ast::Statement::SetTtl { .. }
+            | ast::Statement::CreateIndex(_)
+            | ast::Statement::DropTable { .. }
+            | ast::Statement::DropIndex { .. }
+            | ast::Statement::CreateMaterializedView(_)
+            | ast::Statement::DropMaterializedView { .. }
+            | ast::Statement::CreateTrigger(_)
+            | ast::Statement::DropTrigger { .. }
+            | ast::Statement::CreatePolicy(_)
+            | ast::Statement::DropPolicy { .. } => self.check_superuser("change the schema")?,
+            ast::Statement::RefreshMaterializedView { .. } => {
+                self.check_superuser("refresh materialized views")?
+            }
+            ast::Statement::Analyze { .. } => self.check_superuser("analyze tables")?,
+            ast::Statement::Reindex { .. } => self.check_superuser("rebuild indexes")?,
+            ast::Statement::Backup { .. } => self.check_superuser("back up the database")?,
+            ast::Statement::Attach { .. } | ast::Statement::Detach { .. } => {
+                self.check_superuser("attach databases")?
+            }
+            ast::Statement::CopyFrom { .. } | ast::Statement::CopyTo { .. } => {
+                self.check_superuser("copy to or from a file")?
+            }
+            ast::Statement::CreateUser { .. }
+            | ast::Statement::DropUser { .. }
+            | ast::Statement::Grant(_)
+            | ast::Statement::Revoke(_) => self.check_superuser("manage users")?,
+            ast::Statement::AlterUser { name, options } => {
+                let own_password = options.superuser.is_none() && self.user == Some(name.as_str());
+                if !own_password {
+                    self.check_superuser("manage users")?;
+                }
+            }
+            _ => {}
+        }
+        match statement {
+            ast::Statement::Select(query) => Ok(BoundStatement::Query(self.query(query)?)),
+            ast::Statement::AsOf { query, point } => Ok(BoundStatement::AsOf {
+                query: query.clone(),
+                point: *point,
+            }),
+            ast::Statement::Insert(insert) => self.insert(insert),
+            ast::Statement::Update(update) => self.update(update),
+            ast::Statement::Delete(delete) => self.delete(delete),
+            ast::Statement::CreateTable(create) => self.create_table(create),
+            ast::Statement::CreatePartition {
+                name,
+                if_not_exists,
+                parent,
+                bound,
+            } => {
+                if !if_not_exists && self.catalog.table(name).is_some() {
+                    return Err(Error::TableExists(name.clone()));
+                }
+                let parent = self.table(parent)?;
+                self.check_permanent(&parent.name, "partitions of temporary or unlogged tables")?;
+                Ok(BoundStatement::CreatePartition {
+                    name: name.clone(),
+                    parent: parent.name.clone(),
+                    bound: self.partition_bound(parent, bound)?,
+                    if_not_exists: *if_not_exists,
+                })
+            }
+            ast::Statement::AttachPartition {
+                table,
+                partition,
+                bound,
+            } => {
+                let parent = self.table(table)?;
+                self.table(partition)?;
+                for table in [table, partition] {
+                    self.check_permanent(table, "partitioning temporary or unlogged tables")?;
+                }
+                Ok(BoundStatement::AttachPartition {
+                    table: table.clone(),
+                    partition: partition.clone(),
+                    bound: self.partition_bound(parent, bound)?,
+                })
+            }
+            ast::Statement::DetachPartition { table, partition } => {
+                self.table(table)?;
+                self.table(partition)?;
+                for table in [table, partition] {
+                    self.check_permanent(table, "partitioning temporary or unlogged tables")?;
+                }
+                Ok(BoundStatement::DetachPartition {
+                    table: table.clone(),
+                    partition: partition.clone(),
+                })
+            }
+            ast::Statement::SetTtl { table, ttl } => {
+                let info = self.table(table)?;
+                self.check_permanent(table, "a time to live on temporary or unlogged tables")?;
+                let ttl = match ttl {
+                    Some(ttl) => Some(Ttl {
+                        column: (info.schema.columns.iter())
+                            .position(|c| c.name == ttl.column)
+                            .ok_or_else(|| Error::ColumnNotFound(ttl.column.clone()))?,
+                        duration: ttl.duration,
+                    }),
+                    None => None,
+                };
+                Ok(BoundStatement::SetTtl {
+                    table: table.clone(),
+                    ttl,
+                })
+            }
+            ast::Statement::CreateIndex(create) => self.create_index(create),
+            ast::Statement::DropTable { name, if_exists } => {
+                if !if_exists {
+                    self.table(name)?;
+                }
+                Ok(BoundStatement::DropTable {
+                    name: name.clone(),
+                    if_exists: *if_exists,
+                })
+            }
+            ast::Statement::DropIndex { name, if_exists } => {
+                // A pending index, whose build may have failed, is
+                // dropped like any other.
+                let pending = self.catalog.pending_index(name).is_some();
+                if !if_exists && !pending && !self.index_exists(name) {
+                    return Err(Error::IndexNotFound(name.clone()));
+                }
+                Ok(BoundStatement::DropIndex {
+                    name: name.clone(),
+                    if_exists: *if_exists,
+                })
+            }
+            ast::Statement::CreateMaterializedView(create) => self.create_materialized_view(create),
+            ast::Statement::CreateTrigger(create) => self.create_trigger(create),
+            ast::Statement::DropTrigger { name, if_exists } => {
+                if !if_exists && self.catalog.trigger(name).is_none() {
+                    return Err(Error::TriggerNotFound(name.clone()));
+                }
+                Ok(BoundStatement::DropTrigger {
+                    name: name.clone(),
+                    if_exists: *if_exists,
+                })
+            }
+            ast::Statement::CreatePolicy(create) => self.create_policy(create),
+            ast::Statement::DropPolicy {
+                name,
+                table,
+                if_exists,
+            } => {
+                let info = self.table(table)?;
+                if !if_exists && !info.policies.iter().any(|policy| policy.name == *name) {
+                    return Err(Error::PolicyNotFound {
+                        name: name.clone(),
+                        table: table.clone(),
+                    });
+                }
+                Ok(BoundStatement::DropPolicy {
+                    table: table.clone(),
+                    name: name.clone(),
+                    if_exists: *if_exists,
+                })
+            }
+            ast::Statement::RefreshMaterializedView { name } => {
+                let view = self.view(name)?;
+                let query = match sql::parse_statement(&view.definition) {
+                    Ok(ast::Statement::Select(query)) => self.query(&query)?,
+                    _ => return Err(Error::InvalidView(name.clone())),
+                };
+                Ok(BoundStatement::RefreshMaterializedView {
+                    name: name.clone(),
+                    query,
+                })
+            }
+            ast::Statement::DropMaterializedView { name, if_exists } => {
+                if !if_exists {
+                    self.view(name)?;
+                }
+                Ok(BoundStatement::DropMaterializedView {
+                    name: name.clone(),
+                    if_exists: *if_exists,
+                })
+            }
+            ast::Statement::Analyze { table } => {
+                let tables = match table {
+                    Some(name) => vec![self.table(name)?.name.clone()],
+                    None => {
+                        let mut names: Vec<_> =
+                            self.catalog.tables().map(|t| t.name.clone()).collect();
+                        names.sort();
+                        names
+                    }
+                };
+                Ok(BoundStatement::Analyze { tables })
+            }
+            ast::Statement::Reindex { name, table: true } => {
+                let mut indexes: Vec<_> = (self.table(name)?.indexes.iter())
+                    .map(|index| index.name.clone())
+                    .collect();
+                indexes.sort();
+                Ok(BoundStatement::Reindex { indexes })
+            }
+            ast::Statement::Reindex { name, table: false } => {
+                if !self.index_exists(name) {
+                    return Err(Error::IndexNotFound(name.clone()));
+                }
+                Ok(BoundStatement::Reindex {
+                    indexes: vec![name.clone()],
+                })
+            }
+            ast::Statement::Explain { analyze, statement } => {
+                let statement = match statement.as_ref() {
+                    ast::Statement::Select(query) => BoundStatement::Query(self.query(query)?),
+                    _ => {
+                        return Err(Error::Unsupported(
+                            "EXPLAIN of a statement other than SELECT",
+                        ))
+                    }
+                };
+                Ok(BoundStatement::Explain {
+                    analyze: *analyze,
+                    statement: Box::new(statement),
+                })
+            }
+            ast::Statement::Transaction(control) => Ok(BoundStatement::Transaction(*control)),
+            ast::Statement::Set { name, value } => Ok(BoundStatement::Set {
+                name: name.clone(),
+                value: value.clone(),
+            }),
+            ast::Statement::Show { name } => Ok(BoundStatement::Show { name: name.clone() }),
+            ast::Statement::CopyFrom {
+                table,
+                columns,
+                file,
+                options,
+            } => {
+                let table = self.target_table(table)?;
+                let columns = self.target_columns(table, columns.as_deref())?;
+                self.check(&table.name, Privileges::INSERT)?;
+                let format = copy_format(options)?;
+                if format == CopyFormat::Parquet {
+                    return Err(Error::Unsupported("COPY FROM in the Parquet format"));
+                }
+                Ok(BoundStatement::CopyFrom {
+                    table: table.name.clone(),
+                    columns,
+                    file: file.clone(),
+                    format,
+                })
+            }
+            ast::Statement::CopyTo {
+                source,
+                file,
+                options,
+            } => {
+                let query = match source {
+                    ast::CopySource::Query(query) => self.query(query)?,
+                    ast::CopySource::Table { name, columns } => {
+                        self.query(&table_query(name, columns.as_deref()))?
+                    }
+                };
+                Ok(BoundStatement::CopyTo {
+                    query,
+                    file: file.clone(),
+                    format: copy_format(options)?,
+                })
+            }
+            ast::Statement::Attach { path, name } => {
+                if self
+                    .catalog
+                    .attached()
+                    .any(|database| database.name == *name)
+                {
+                    return Err(Error::DatabaseExists(name.clone()));
+                }
+                Ok(BoundStatement::Attach {
+                    path: path.clone(),
+                    name: name.clone(),
+                })
+            }
+            ast::Statement::Detach { name } => {
+                if !self
+                    .catalog
+                    .attached()
+                    .any(|database| database.name == *name)
+                {
+                    return Err(Error::DatabaseNotFound(name.clone()));
+                }
+                Ok(BoundStatement::Detach { name: name.clone() })
+            }
+            ast::Statement::Backup { path, chain } => Ok(BoundStatement::Backup {
+                path: path.clone(),
+                chain: chain.clone(),
+            }),
+            ast::Statement::Notify { channel, payload } => Ok(BoundStatement::Notify {
+                channel: channel.clone(),
+                payload: payload.clone(),
+            }),
+            ast::Statement::Listen { channel } => Ok(BoundStatement::Listen {
+                channel: channel.clone(),
+            }),
+            ast::Statement::Unlisten { channel } => Ok(BoundStatement::Unlisten {
+                channel: channel.clone(),
+            }),
+            ast::Statement::CreateUser { name, options } => {
+                if self.catalog.user(name).is_some() {
+                    return Err(Error::UserExists(name.clone()));
+                }
+                let mut user = User::new(name);
+                user.verifier = options
+                    .password
+                    .as_ref()
+                    .and_then(|p| p.as_deref().map(Verifier::new))
+                    .transpose()?;
+                user.superuser = options.superuser.unwrap_or(false);
+                Ok(BoundStatement::CreateUser(user))
+            }
+            ast::Statement::AlterUser { name, options } => {
+                self.user_exists(name)?;
+                let verifier = options
+                    .password
+                    .as_ref()
+                    .map(|password| password.as_deref().map(Verifier::new).transpose())
+                    .transpose()?;
+                Ok(BoundStatement::AlterUser {
+                    name: name.clone(),
+                    verifier,
+                    superuser: options.superuser,
+                })
+            }
+            ast::Statement::DropUser { name, if_exists } => {
+                if !if_exists {
+                    self.user_exists(name)?;
+                }
+                if self.user == Some(name.as_str()) {
+                    return Err(Error::Unsupported("dropping the current user"));
+                }
+                Ok(BoundStatement::DropUser {
+                    name: name.clone(),
+                    if_exists: *if_exists,
+                })
+            }
+            ast::Statement::Grant(grant) | ast::Statement::Revoke(grant) => {
+                for table in &grant.tables {
+                    let info = self.table(table)?;
+                    if self.is_temporary(table) {
+                        return Err(Error::Unsupported("privileges on temporary tables"));
+                    }
+                    if let Some(column) = (grant.columns.iter())
+                        .find(|column| info.schema.column_index(column).is_none())
+                    {
+                        return Err(Error::ColumnNotFound(column.clone()));
+                    }
+                }
+                for user in &grant.users {
+                    self.user_exists(user)?;
+                }
+                Ok(match statement {
+                    ast::Statement::Grant(_) => BoundStatement::Grant(grant.clone()),
+                    _ => BoundStatement::Revoke(grant.clone()),
+                })
+            }
+        }
+    }
+
+    fn user_exists(&self, name: &str) -> Result<(), Error> {
+        match self.catalog.user(name) {
+            Some(_) => Ok(()),
+            None => Err(Error::UserNotFound(name.to_string())),
+        }
+    }
+
+    /// Gives an untyped parameter the type its context expects.
+    fn infer(&self, typed: &mut Typed, expected: Option<DataType>) {
+        if let (Expr::Parameter(n), None, Some(_)) = (&typed.0, typed.1, expected) {
+            self.parameters.borrow_mut()[n - 1] = expected;
+            typed.1 = expected;
+        }
+    }
+
+    fn index_exists(&self, name: &str) -> bool {
+        self.catalog.index_table(name).is_some()
+    }
+
+    fn expr(&self, expr: &ast::Expr, ctx: &mut ExprContext) -> Result<Typed, Error> {
+        if let Some(grouping) = &ctx.grouping {
+            let is_call = matches!(expr, ast::Expr::Function(function)
+                if function.over.is_some() || find_aggregate(self.catalog, &function.name).is_some());
+            if !is_call {
+                let mut plain = ExprContext::plain(ctx.scope, ctx.clause);
+                if let Ok((bound, _)) = self.expr(expr, &mut plain) {
+                    let key = grouping.keys.iter().position(|((key, _), _)| *key == bound);
+                    if let Some(i) = key {
+                        return Ok((Expr::column(i), grouping.keys[i].0 .1));
+                    }
+                }
+            }
+        }
+        match expr {
+            ast::Expr::Identifier(name) => {
+                let (i, data_type) = ctx.scope.resolve(name)?;
+                if ctx.grouping.is_some() {
+                    return Err(Error::NotGrouped(name.join(".")));
+                }
+                Ok((Expr::column(i), data_type))
+            }
+            ast::Expr::Literal(literal) => {
+                let value = match literal {
+                    ast::Literal::Null => Value::Null,
+                    ast::Literal::Bool(b) => Value::Bool(*b),
+                    ast::Literal::Int(i) => Value::Int(*i),
+                    ast::Literal::Float(x) => Value::Float(*x),
+                    ast::Literal::String(s) => Value::Text(s.clone()),
+                    ast::Literal::Blob(bytes) => Value::Bytes(bytes.clone()),
+                };
+                let data_type = value.data_type();
+                Ok((Expr::Literal(value), data_type))
+            }
+            ast::Expr::Parameter(n) => {
+                let mut parameters = self.parameters.borrow_mut();
+                if parameters.len() < *n {
+                    parameters.resize(*n, None);
+                }
+                Ok((Expr::Parameter(*n), parameters[n - 1]))
+            }
+            ast::Expr::Unary { op, expr } => {
+                let mut typed = self.expr(expr, ctx)?;
+                if *op == UnaryOp::Not {
+                    self.infer(&mut typed, Some(DataType::Bool));
+                }
+                let (expr, data_type) = typed;
+                let valid = match (op, data_type) {
+                    (_, None) => true,
+                    (UnaryOp::Not, Some(t)) => t == DataType::Bool,
+                    (UnaryOp::Neg, Some(t)) => is_numeric(t),
+                };
+                if let (false, Some(operand)) = (valid, data_type) {
+                    return Err(Error::OperandType { op: *op, operand });
+                }
+                let data_type = match op {
+                    UnaryOp::Not => Some(DataType::Bool),
+                    UnaryOp::Neg => data_type,
+                };
+                Ok((Expr::unary(*op, expr), data_type))
+            }
+            ast::Expr::Binary { op, lhs, rhs } => {
+                let mut lhs = self.expr(lhs, ctx)?;
+                let mut rhs = self.expr(rhs, ctx)?;
+                match op {
+                    BinaryOp::And | BinaryOp::Or => {
+                        self.infer(&mut lhs, Some(DataType::Bool));
+                        self.infer(&mut rhs, Some(DataType::Bool));
+                    }
+                    BinaryOp::Like => {
+                        self.infer(&mut lhs, Some(DataType::Text));
+                        self.infer(&mut rhs, Some(DataType::Text));
+                    }
+                    BinaryOp::Subscript => self.infer(&mut rhs, Some(DataType::Int)),
+                    _ => {
+                        self.infer(&mut lhs, rhs.1);
+                        self.infer(&mut rhs, lhs.1);
+                    }
+                }
+                let ((lhs, lhs_type), (rhs, rhs_type)) = (lhs, rhs);
+                let data_type = binary_type(*op, lhs_type, rhs_type)?;
+                let (lhs, rhs) = match op {
+                    BinaryOp::Eq
+                    | BinaryOp::NotEq
+                    | BinaryOp::Lt
+                    | BinaryOp::LtEq
+                    | BinaryOp::Gt
+                    | BinaryOp::GtEq => ctx.compare(lhs, rhs),
+                    _ => (lhs, rhs),
+                };
+                Ok((Expr::binary(*op, lhs, rhs), data_type))
+            }
+            ast::Expr::IsNull { expr, negated } => {
+                let (expr, _) = self.expr(expr, ctx)?;
+                let expr = Expr::IsNull {
+                    expr: Box::new(expr),
+                    negated: *negated,
+                };
+                Ok((expr, Some(DataType::Bool)))
+            }
+            ast::Expr::Between {
+                expr,
+                low,
+                high,
+                negated,
+            } => {
+                let mut expr = self.expr(expr, ctx)?;
+                let mut low = self.expr(low, ctx)?;
+                let mut high = self.expr(high, ctx)?;
+                self.infer(&mut expr, low.1.or(high.1));
+                self.infer(&mut low, expr.1);
+                self.infer(&mut high, expr.1);
+                let ((expr, data_type), (low, low_type), (high, high_type)) = (expr, low, high);
+                binary_type(BinaryOp::GtEq, data_type, low_type)?;
+                binary_type(BinaryOp::LtEq, data_type, high_type)?;
+                let (low_expr, low) = ctx.compare(expr.clone(), low);
+                let (high_expr, high) = ctx.compare(expr, high);
+                let between = Expr::binary(
+                    BinaryOp::And,
+                    Expr::binary(BinaryOp::GtEq, low_expr, low),
+                    Expr::binary(BinaryOp::LtEq, high_expr, high),
+                );
+                Ok((negate(between, *negated), Some(DataType::Bool)))
+            }
+            ast::Expr::InList {
+                expr,
+                list,
+                negated,
+            } => {
+                let items = list
+                    .iter()
+                    .map(|item| self.expr(item, ctx))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut expr = self.expr(expr, ctx)?;
+                self.infer(&mut expr, items.iter().find_map(|item| item.1));
+                let (expr, data_type) = expr;
+                let mut any: Option<Expr> = None;
+                for mut item in items {
+                    self.infer(&mut item, data_type);
+                    let (item, item_type) = item;
+                    binary_type(BinaryOp::Eq, data_type, item_type)?;
+                    let (lhs, item) = ctx.compare(expr.clone(), item);
+                    let eq = Expr::binary(BinaryOp::Eq, lhs, item);
+                    any = Some(match any {
+                        None => eq,
+                        Some(any) => Expr::binary(BinaryOp::Or, any, eq),
+                    });
+                }
+                let any = any.unwrap_or(Expr::literal(false));
+                Ok((negate(any, *negated), Some(DataType::Bool)))
+            }
+            ast::Expr::Like {
+                expr,
+                pattern,
+                negated,
+            } => {
+                let mut expr = self.expr(expr, ctx)?;
+                let mut pattern = self.expr(pattern, ctx)?;
+                self.infer(&mut expr, Some(DataType::Text));
+                self.infer(&mut pattern, Some(DataType::Text));
+                let ((expr, data_type), (pattern, pattern_type)) = (expr, pattern);
+                binary_type(BinaryOp::Like, data_type, pattern_type)?;
+                let like = Expr::binary(BinaryOp::Like, expr, pattern);
+                Ok((negate(like, *negated), Some(DataType::Bool)))
+            }
+            ast::Expr::Cast {
+                expr,
+                data_type: data_type @ DataType::Array(&element),
+            } if **expr == ast::Expr::Array(vec![]) => {
+                let array = Expr::Array {
+                    element,
+                    items: vec![],
+                };
+                Ok((array, Some(*data_type)))
+            }
+            ast::Expr::Cast { expr, data_type } => {
+                let (expr, from) = self.expr(expr, ctx)?;
+                if let Some(from) = from {
+                    if !castable(from, *data_type) {
+                        return Err(Error::InvalidCast {
+                            from,
+                            to: *data_type,
+                        });
+                    }
+                }
+                let cast = Expr::Cast {
+                    expr: Box::new(expr),
+                    data_type: *data_type,
+                };
+                Ok((cast, Some(*data_type)))
+            }
+            ast::Expr::Function(function) => self.function(function, ctx),
+            ast::Expr::Array(items) => self.array(items, ctx),
+            ast::Expr::Any {
+                op,
+                expr,
+                array,
+                all,
+            } => {
+                let mut lhs = self.expr(expr, ctx)?;
+                let mut array = self.expr(array, ctx)?;
+                self.infer(&mut lhs, array.1.and_then(DataType::element));
+                self.infer(&mut array, lhs.1.and_then(DataType::array));
+                let ((lhs, lhs_type), (array, array_type)) = (lhs, array);
+                let element = match array_type {
+                    Some(DataType::Array(element)) => Some(*element),
+                    None => None,
+                    Some(_) => {
+                        return Err(Error::OperatorType {
+                            op: *op,
+                            lhs: describe(lhs_type),
+                            rhs: describe(array_type),
+                        })
+                    }
+                };
+                binary_type(*op, lhs_type, element)?;
+                let (lhs, array) = ctx.compare(lhs, array);
+                let any = Expr::Any {
+                    op: *op,
+                    lhs: Box::new(lhs),
+                    array: Box::new(array),
+                    all: *all,
+                };
+                Ok((any, Some(DataType::Bool)))
+            }
+            ast::Expr::Collate { expr, collation } => {
+                let collation = Collation::from_name(collation)
+                    .ok_or_else(|| Error::UnknownCollation(collation.clone()))?;
+                let mut typed = self.expr(expr, ctx)?;
+                self.infer(&mut typed, Some(DataType::Text));
+                let (expr, data_type) = typed;
+                if let Some(data_type) = data_type.filter(|&t| !collatable(t)) {
+                    return Err(Error::CollationType(data_type));
+                }
+                let collate = Expr::Collate {
+                    expr: Box::new(uncollated(expr)),
+                    collation,
+                };
+                Ok((collate, data_type))
+            }
+        }
+    }
+
+    /// An array constructor, whose elements take their common type as
+    /// VALUES columns do. An empty one needs a CAST to give it a type.
+    fn array(&self, items: &[ast::Expr], ctx: &mut ExprContext) -> Result<Typed, Error> {
+        let mut typed = items
+            .iter()
+            .map(|item| self.expr(item, ctx))
+            .collect::<Result<Vec<_>, _>>()?;
+        if typed.is_empty() {
+            return Err(Error::EmptyArray);
+        }
+        let mut element = None;
+        for (_, data_type) in &typed {
+            element = common_type(element, *data_type)
+                .map_err(|(first, second)| Error::ArrayType { first, second })?;
+        }
+        // Elements of unknown type alone, such as ARRAY[NULL], make text.
+        let element = element.unwrap_or(DataType::Text);
+        let data_type = DataType::array(element).ok_or(Error::Unsupported("arrays of arrays"))?;
+        let items = typed
+            .iter_mut()
+            .map(|item| {
+                self.infer(item, Some(element));
+                match item.1 {
+                    Some(DataType::Int) if element == DataType::Float => widen(item.0.clone()),
+                    _ => item.0.clone(),
+                }
+            })
+            .collect();
+        Ok((Expr::Array { element, items }, Some(data_type)))
+    }
+
+    /// Binds an expression that must be a boolean condition.
+    fn predicate(
+        &self,
+        expr: &ast::Expr,
+        scope: &Scope,
+        clause: &'static str,
+    ) -> Result<Expr, Error> {
+        let typed = self.expr(expr, &mut ExprContext::plain(scope, clause))?;
+        check_predicate(typed, clause)
+    }
+
+    fn function(&self, function: &ast::Function, ctx: &mut ExprContext) -> Result<Typed, Error> {
+        if let Some(over) = &function.over {
+            if ctx.windows.is_none() {
+                return Err(Error::WindowNotAllowed(ctx.clause));
+            }
+            // Window functions cannot nest, but may use grouping keys and
+            // aggregates.
+            let windows = ctx.windows.take();
+            let call = self.window_call(function, over, ctx);
+            ctx.windows = windows;
+            let call = call?;
+            let data_type = call.field.data_type;
+            let calls = ctx.windows.as_mut().unwrap();
+            calls.push(call);
+            return Ok((Expr::column(WINDOW_BASE + calls.len() - 1), data_type));
+        }
+        if let Some(func) = ScalarFunction::from_name(&function.name) {
+            return self.scalar_function(func, function, ctx);
+        }
+        if let Some(user) = self.catalog.function(&function.name) {
+            return self.user_function(user, function, ctx);
+        }
+        let Some(func) = find_aggregate(self.catalog, &function.name) else {
+            if matches!(function.name.as_str(), "row_number" | "rank") {
+                return Err(Error::WindowRequiresOver(function.name.clone()));
+            }
+            return Err(Error::UnknownFunction(function.name.clone()));
+        };
+        if ctx.grouping.is_none() {
+            return Err(Error::AggregateNotAllowed(ctx.clause));
+        }
+        let mut args = ExprContext::plain(ctx.scope, "aggregate function calls");
+        let (aggregate, data_type) = self.aggregate(func, function, &mut args)?;
+        let grouping = ctx.grouping.as_mut().unwrap();
+        let j = match grouping
+            .aggregates
+            .iter()
+            .position(|(a, _)| *a == aggregate)
+        {
+            Some(j) => j,
+            None => {
+                let field = Field::new(&function.name, data_type);
+                grouping.aggregates.push((aggregate, field));
+                grouping.aggregates.len() - 1
+            }
+        };
+        Ok((Expr::column(grouping.keys.len() + j), data_type))
+    }
+
+    fn scalar_function(
+        &self,
+        func: ScalarFunction,
+        function: &ast::Function,
+        ctx: &mut ExprContext,
+    ) -> Result<Typed, Error> {
+        if function.star || function.distinct {
+            return Err(Error::Unsupported(
+                "* or DISTINCT in a scalar function call",
+            ));
+        }
+        let [arg] = &function.args[..] else {
+            return Err(Error::ArgumentCount {
+                func: function.name.clone(),
+                expected: 1,
+                actual: function.args.len(),
+            });
+        };
+        let mut arg = self.expr(arg, ctx)?;
+        if matches!(func, ScalarFunction::Lower | ScalarFunction::Upper) {
+            self.infer(&mut arg, Some(DataType::Text));
+        }
+        let data_type = match arg.1 {
+            Some(data_type) => Some(
+                func.return_type(data_type)
+                    .ok_or(Error::FunctionType { func, data_type })?,
+            ),
+            None => None,
+        };
+        let expr = Expr::Function {
+            func,
+            arg: Box::new(arg.0),
+        };
+        Ok((expr, data_type))
+    }
+
+    fn user_function(
+        &self,
+        user: &UserFunction,
+        function: &ast::Function,
+        ctx: &mut ExprContext,
+    ) -> Result<Typed, Error> {
+        if function.star || function.distinct {
+            return Err(Error::Unsupported(
+                "* or DISTINCT in a scalar function call",
+            ));
+        }
+        if function.args.len() != user.arguments.len() {
+            return Err(Error::ArgumentCount {
+                func: function.name.clone(),
+                expected: user.arguments.len(),
+                actual: function.args.len(),
+            });
+        }
+        let mut args = vec![];
+        for (i, (arg, &expected)) in function.args.iter().zip(&user.arguments).enumerate() {
+            let arg = self.expr(arg, ctx)?;
+            args.push(self.argument(&function.name, i + 1, arg, expected)?);
+        }
+        let expr = Expr::Call {
+            function: user.clone(),
+            args,
+        };
+        Ok((expr, Some(user.return_type)))
+    }
+
+    /// Argument `position` of a registered function, as the `expected`
+    /// type it declares.
+    fn argument(
+        &self,
+        func: &str,
+        position: usize,
+        mut arg: Typed,
+        expected: DataType,
+    ) -> Result<Expr, Error> {
+        self.infer(&mut arg, Some(expected));
+        match arg.1 {
+            None => Ok(arg.0),
+            Some(actual) if actual == expected => Ok(arg.0),
+            Some(DataType::Int) if expected == DataType::Float => Ok(Expr::Cast {
+                expr: Box::new(arg.0),
+                data_type: expected,
+            }),
+            Some(actual) => Err(Error::ArgumentType {
+                func: func.to_string(),
+                position,
+                expected,
+                actual,
+            }),
+        }
+    }
+
+    fn aggregate(
+        &self,
+        func: AggregateFunction,
+        function: &ast::Function,
+        ctx: &mut ExprContext,
+    ) -> Result<(AggregateExpr, Option<DataType>), Error> {
+        if function.distinct {
+            return Err(Error::Unsupported("DISTINCT in aggregate functions"));
+        }
+        if function.star {
+            if func != AggregateFunction::Count {
+                return Err(Error::StarArgument(function.name.clone()));
+            }
+            return Ok((AggregateExpr::count_star(), Some(DataType::Int)));
+        }
+        let [arg] = &function.args[..] else {
+            return Err(Error::ArgumentCount {
+                func: function.name.clone(),
+                expected: 1,
+                actual: function.args.len(),
+            });
+        };
+        let (mut arg, arg_type) = self.expr(arg, ctx)?;
+        if let AggregateFunction::User(aggregate) = &func {
+            arg = self.argument(&function.name, 1, (arg, arg_type), aggregate.argument)?;
+        }
+        let data_type = aggregate_type(&func, arg_type)?;
+        Ok((AggregateExpr::new(func, arg), data_type))
+    }
+
+    fn window_call(
+        &self,
+        function: &ast::Function,
+        over: &ast::WindowSpec,
+        ctx: &mut ExprContext,
+    ) -> Result<WindowCall, Error> {
+        let partition_by = over
+            .partition_by
+            .iter()
+            .map(|expr| Ok(self.expr(expr, ctx)?.0))
+            .collect::<Result<_, Error>>()?;
+        let order_by = over
+            .order_by
+            .iter()
+            .map(|key| {
+                let (expr, _) = self.expr(&key.expr, ctx)?;
+                Ok(self.order_key(ctx.sort_key(expr), key))
+            })
+            .collect::<Result<_, Error>>()?;
+        let ranking = match function.name.as_str() {
+            "row_number" => Some(WindowExpr::row_number()),
+            "rank" => Some(WindowExpr::rank()),
+            _ => None,
+        };
+        let (func, data_type) = if let Some(func) = ranking {
+            if !function.args.is_empty() || function.star {
+                return Err(Error::ArgumentCount {
+                    func: function.name.clone(),
+                    expected: 0,
+                    actual: function.args.len().max(1),
+                });
+            }
+            (func, Some(DataType::Int))
+        } else {
+            let Some(func) = find_aggregate(self.catalog, &function.name) else {
+                return Err(Error::UnknownFunction(function.name.clone()));
+            };
+            let (aggregate, data_type) = self.aggregate(func, function, ctx)?;
+            let func = WindowExpr::aggregate(aggregate.func, aggregate.arg, frame(over)?);
+            (func, data_type)
+        };
+        Ok(WindowCall {
+            partition_by,
+            order_by,
+            func,
+            field: Field::new(&function.name, data_type),
+        })
+    }
+
+    fn from(&self, from: &[ast::TableRef]) -> Result<(LogicalPlan, Scope), Error> {
+        let mut result: Option<(LogicalPlan, Scope)> = None;
+        for table_ref in from {
+            // unnest() sees the tables before it, and adds to their rows.
+            if let ast::TableRef::Unnest {
+                expr,
+                alias,
+                column,
+            } = table_ref
+            {
+                let (input, scope) = result.unwrap_or_else(Self::single_row);
+                result = Some(self.unnest(input, scope, expr, alias, column)?);
+                continue;
+            }
+            let (plan, scope) = self.table_ref(table_ref)?;
+            result = Some(match result {
+                None => (plan, scope),
+                Some((left, left_scope)) => {
+                    let join = LogicalPlan::Join {
+                        left: Box::new(left),
+                        right: Box::new(plan),
+                        kind: JoinKind::Inner,
+                        predicate: None,
+                    };
+                    (join, left_scope.concat(scope)?)
+                }
+            });
+        }
+        // Without FROM, the select list is evaluated once.
+        Ok(result.unwrap_or_else(Self::single_row))
+    }
+
+    fn single_row() -> (LogicalPlan, Scope) {
+        let values = LogicalPlan::Values {
+            rows: vec![vec![]],
+            fields: vec![],
+        };
+        (values, Scope::default())
+    }
+
+    fn unnest(
+        &self,
+        input: LogicalPlan,
+        scope: Scope,
+        expr: &ast::Expr,
+        alias: &Option<String>,
+        column: &Option<String>,
+    ) -> Result<(LogicalPlan, Scope), Error> {
+        let (array, data_type) = self.expr(expr, &mut ExprContext::plain(&scope, "FROM"))?;
+        let Some(DataType::Array(element)) = data_type else {
+            return Err(Error::UnnestType(describe(data_type)));
+        };
+        let alias = alias.as_deref().unwrap_or("unnest");
+        let field = Field::new(column.as_deref().unwrap_or(alias), Some(*element));
+        let scope = scope.concat(Scope::new(alias, std::slice::from_ref(&field)))?;
+        let unnest = LogicalPlan::Unnest {
+            input: Box::new(input),
+            array,
+            field,
+        };
+        Ok((unnest, scope))
+    }
+
+    fn table_ref(&self, table_ref: &ast::TableRef) -> Result<(LogicalPlan, Scope), Error> {
+        match table_ref {
+            ast::TableRef::Table { name, alias } => {
+                let qualifier = alias.as_ref().unwrap_or(name);
+                let ctes = self.ctes.borrow();
+                if let Some(cte) = ctes.iter().rev().find(|cte| cte.name == *name) {
+                    let scope = Scope::new(qualifier, &cte.fields);
+                    let work_table = LogicalPlan::WorkTable {
+                        id: cte.id,
+                        fields: cte.fields.clone(),
+                    };
+                    return Ok((work_table, scope));
+                }
+                if self.catalog.table(name).is_none() {
+                    if let Some(table) = SystemTable::lookup(name) {
+                        let schema = table.schema();
+                        let scope = Scope::table(qualifier, &schema);
+                        let scan = LogicalPlan::SystemScan {
+                            table,
+                            fields: schema_fields(&schema),
+                        };
+                        return Ok((scan, scope));
+                    }
+                }
+                let table = self.table(name)?;
+                self.check(name, self.read_privilege(name))?;
+                let scope = self.table_scope(qualifier, table);
+                let mut scan = LogicalPlan::Scan {
+                    table: name.clone(),
+                    fields: schema_fields(&table.schema),
+                };
+                if let Some(predicate) = self.row_filter(name) {
+                    scan = LogicalPlan::Filter {
+                        input: Box::new(scan),
+                        predicate,
+                    };
+                }
+                Ok((scan, scope))
+            }
+            ast::TableRef::Subquery { query, alias } => {
+                let plan = self.query(query)?;
+                let scope = Scope::new(alias, &plan.fields());
+                Ok((plan, scope))
+            }
+            // Within a join it sees no other table.
+            ast::TableRef::Unnest {
+                expr,
+                alias,
+                column,
+            } => {
+                let (input, scope) = Self::single_row();
+                self.unnest(input, scope, expr, alias, column)
+            }
+            ast::TableRef::Join {
+                left,
+                right,
+                kind,
+                on,
+            } => {
+                let (left, left_scope) = self.table_ref(left)?;
+                let (right, right_scope) = self.table_ref(right)?;
+                let scope = left_scope.concat(right_scope)?;
+                let predicate = on
+                    .as_ref()
+                    .map(|on| self.predicate(on, &scope, "JOIN conditions"))
+                    .transpose()?;
+                let kind = match kind {
+                    ast::JoinKind::Inner | ast::JoinKind::Cross => JoinKind::Inner,
+                    ast::JoinKind::Left => JoinKind::Left,
+                };
+                let join = LogicalPlan::Join {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    kind,
+                    predicate,
+                };
+                Ok((join, scope))
+            }
+        }
+    }
+
+    /// Binds a GROUP BY item, which may also be a select list position.
+    fn group_key(
+        &self,
+        expr: &ast::Expr,
+        select: &ast::Select,
+        scope: &Scope,
+    ) -> Result<(Typed, String), Error> {
+        let expr = match expr {
+            ast::Expr::Literal(ast::Literal::Int(n)) => {
+                let item = usize::try_from(*n)
+                    .ok()
+                    .and_then(|n| n.checked_sub(1))
+                    .and_then(|i| select.projection.get(i));
+                match item {
+                    Some(ast::SelectItem::Expr { expr, .. }) => expr,
+                    _ => return Err(Error::GroupByPosition(*n)),
+                }
+            }
+            expr => expr,
+        };
+        let typed = self.expr(expr, &mut ExprContext::plain(scope, "GROUP BY"))?;
+        Ok((typed, column_name(expr)))
+    }
+
+    fn query(&self, query: &ast::Query) -> Result<LogicalPlan, Error> {
+        let plan = match &query.with {
+            None => self.query_body(query)?,
+            Some(with) => {
+                let depth = self.ctes.borrow().len();
+                let plan = self.with(with, query, depth);
+                self.ctes.borrow_mut().truncate(depth);
+                plan?
+            }
+        };
+        if let Some(locking) = query.locking {
+            self.for_update(query, locking.mode, &plan)?;
+        }
+        Ok(plan)
+    }
+
+    /// Checks that the rows of a query `FOR UPDATE`, or locking them in
+    /// `mode`, are rows of tables, each one row of the output, that the
+    /// user may update.
+    fn for_update(
+        &self,
+        query: &ast::Query,
+        mode: LockMode,
+        plan: &LogicalPlan,
+    ) -> Result<(), Error> {
+        let select = &query.select;
+        let clause = if !query.unions.is_empty() {
+            Some("UNION")
+        } else if select.distinct {
+            Some("DISTINCT clause")
+        } else if !select.group_by.is_empty() {
+            Some("GROUP BY clause")
+        } else if select.having.is_some() {
+            Some("HAVING clause")
+        } else {
+            None
+        };
+        if let Some(clause) = clause {
+            return Err(Error::ForUpdateNotAllowed(mode, clause));
+        }
+        let mut node = plan;
+        loop {
+            node = match node {
+                LogicalPlan::Aggregate { .. } => {
+                    return Err(Error::ForUpdateNotAllowed(mode, "aggregate functions"))
+                }
+                LogicalPlan::Window { .. } => {
+                    return Err(Error::ForUpdateNotAllowed(mode, "window functions"))
+                }
+                LogicalPlan::Filter { input, .. }
+                | LogicalPlan::Project { input, .. }
+                | LogicalPlan::Sort { input, .. }
+                | LogicalPlan::Limit { input, .. } => input,
+                _ => break,
+            };
+        }
+        for table in plan.tables() {
+            self.target_table(&table)?;
+            self.check(&table, Privileges::UPDATE)?;
+        }
+        Ok(())
+    }
+
+    fn work_table(&self) -> usize {
+        let id = self.work_tables.get();
+        self.work_tables.set(id + 1);
+        id
+    }
+
+    /// Binds the CTEs of `with` and then the rest of `query`, placing each
+    /// CTE it reads.
+    fn with(
+        &self,
+        with: &ast::With,
+        query: &ast::Query,
+        depth: usize,
+    ) -> Result<LogicalPlan, Error> {
+        let mut ctes = vec![];
+        for cte in &with.ctes {
+            if self.ctes.borrow()[depth..]
+                .iter()
+                .any(|scope| scope.name == cte.name)
+            {
+                return Err(Error::DuplicateCte(cte.name.clone()));
+            }
+            let plan = if with.recursive {
+                self.recursive_cte(cte)?
+            } else {
+                self.cte(cte)?
+            };
+            let id = self.work_table();
+            self.ctes.borrow_mut().push(CteScope {
+                name: cte.name.clone(),
+                id,
+                fields: plan.fields(),
+            });
+            ctes.push((id, cte.materialized, plan));
+        }
+        let mut plan = self.query_body(query)?;
+        // Later CTEs go first, since they may read earlier ones.
+        for (id, materialized, cte) in ctes.into_iter().rev() {
+            let references = plan.work_table_references(id);
+            if references == 0 {
+                continue;
+            }
+            plan = if materialized.unwrap_or(references > 1) {
+                LogicalPlan::Materialize {
+                    id,
+                    cte: Box::new(cte),
+                    input: Box::new(plan),
+                }
+            } else {
+                plan.replace_work_table(id, &cte)
+            };
+        }
+        Ok(plan)
+    }
+
+    fn cte(&self, cte: &ast::Cte) -> Result<LogicalPlan, Error> {
+        let plan = self.query(&cte.query)?;
+        let fields = cte_fields(cte, plan.fields())?;
+        Ok(rename(plan, fields))
+    }
+
+    /// A CTE under WITH RECURSIVE, which becomes a recursive union if its
+    /// last UNION arm reads it.
+    fn recursive_cte(&self, cte: &ast::Cte) -> Result<LogicalPlan, Error> {
+        let query = &cte.query;
+        let Some((last, arms)) = query.unions.split_last() else {
+            return self.cte(cte);
+        };
+        if query.with.is_some() {
+            return self.cte(cte);
+        }
+        let anchor = self.union(&query.select, arms)?;
+        let fields = cte_fields(cte, anchor.fields())?;
+        let anchor = rename(anchor, fields.clone());
+
+        let id = self.work_table();
+        self.ctes.borrow_mut().push(CteScope {
+            name: cte.name.clone(),
+            id,
+            fields: fields.clone(),
+        });
+        let recursive = self.select(&last.select, &[], None, None);
+        self.ctes.borrow_mut().pop();
+        let recursive = recursive?;
+        if recursive.work_table_references(id) == 0 {
+            return self.cte(cte);
+        }
+        if !query.order_by.is_empty() || query.limit.is_some() || query.offset.is_some() {
+            return Err(Error::Unsupported(
+                "ORDER BY, LIMIT or OFFSET in a recursive query",
+            ));
+        }
+
+        let types: Vec<_> = recursive
+            .fields()
+            .into_iter()
+            .map(|f| f.data_type)
+            .collect();
+        if types.len() != fields.len() {
+            return Err(Error::UnionWidth);
+        }
+        for (i, (field, data_type)) in fields.iter().zip(&types).enumerate() {
+            match (field.data_type, *data_type) {
+                (Some(expected), Some(actual))
+                    if expected != actual
+                        && !(expected == DataType::Float && actual == DataType::Int) =>
+                {
+                    return Err(Error::RecursiveType {
+                        name: cte.name.clone(),
+                        column: i + 1,
+                        expected,
+                        actual,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(LogicalPlan::RecursiveUnion {
+            id,
+            anchor: Box::new(anchor),
+            recursive: Box::new(conform(recursive, &fields)),
+            distinct: !last.all,
+            fields,
+        })
+    }
+
+    /// `select` followed by the `arms` of a UNION, without ORDER BY.
+    fn union(&self, select: &ast::Select, arms: &[ast::Union]) -> Result<LogicalPlan, Error> {
+        let first = self.select(select, &[], None, None)?;
+        let arms = arms
+            .iter()
+            .map(|arm| Ok((arm.all, self.select(&arm.select, &[], None, None)?)))
+            .collect::<Result<_, Error>>()?;
+        union(first, arms)
+    }
+
+    fn query_body(&self, query: &ast::Query) -> Result<LogicalPlan, Error> {
+        let (limit, offset) = (query.limit.as_ref(), query.offset.as_ref());
+        if query.unions.is_empty() {
+            return self.select(&query.select, &query.order_by, limit, offset);
+        }
+        let mut plan = self.union(&query.select, &query.unions)?;
+        if !query.order_by.is_empty() {
+            // Only the output columns can be named.
+            let fields = plan.fields();
+            let scope = Scope::new("", &fields);
+            let keys = query
+                .order_by
+                .iter()
+                .map(|key| {
+                    let ctx = &mut ExprContext::plain(&scope, "ORDER BY");
+                    let expr = match &key.expr {
+                        ast::Expr::Literal(ast::Literal::Int(n)) => usize::try_from(*n)
+                            .ok()
+                            .filter(|&n| (1..=fields.len()).contains(&n))
+                            .map(|n| Expr::column(n - 1))
+                            .ok_or(Error::OrderByPosition(*n))?,
+                        expr => self.expr(expr, ctx)?.0,
+                    };
+                    Ok(self.order_key(ctx.sort_key(expr), key))
+                })
+                .collect::<Result<_, Error>>()?;
+            plan = LogicalPlan::Sort {
+                input: Box::new(plan),
+                keys,
+            };
+        }
+        self.limit(plan, limit, offset)
+    }
+
+    fn limit(
+        &self,
+        plan: LogicalPlan,
+        limit: Option<&ast::Expr>,
+        offset: Option<&ast::Expr>,
+    ) -> Result<LogicalPlan, Error> {
+        if limit.is_none() && offset.is_none() {
+            return Ok(plan);
+        }
+        let empty = Scope::default();
+        let count = |expr: Option<&ast::Expr>, clause| {
+            expr.map(|expr| {
+                let typed = self.expr(expr, &mut ExprContext::plain(&empty, clause))?;
+                constant_count(&typed, clause)
+            })
+            .transpose()
+        };
+        Ok(LogicalPlan::Limit {
+            input: Box::new(plan),
+            limit: count(limit, "LIMIT")?,
+            offset: count(offset, "OFFSET")?.unwrap_or(0),
+        })
+    }
+
+    fn select(
+        &self,
+        select: &ast::Select,
+        order_by: &[ast::OrderByExpr],
+        limit: Option<&ast::Expr>,
+        offset: Option<&ast::Expr>,
+    ) -> Result<LogicalPlan, Error> {
+        let (mut plan, scope) = self.from(&select.from)?;
+        if let Some(selection) = &select.selection {
+            plan = LogicalPlan::Filter {
+                input: Box::new(plan),
+                predicate: self.predicate(selection, &scope, "WHERE")?,
+            };
+        }
+
+        let aggregates = select.projection.iter().any(|item| match item {
+            ast::SelectItem::Expr { expr, .. } => contains_aggregate(self.catalog, expr),
+            _ => false,
+        }) || order_by
+            .iter()
+            .any(|key| contains_aggregate(self.catalog, &key.expr));
+        let grouping = if aggregates || !select.group_by.is_empty() || select.having.is_some() {
+            let keys = select
+                .group_by
+                .iter()
+                .map(|expr| self.group_key(expr, select, &scope))
+                .collect::<Result<_, _>>()?;
+            Some(Grouping {
+                keys,
+                aggregates: vec![],
+            })
+        } else {
+            None
+        };
+        let mut ctx = ExprContext {
+            scope: &scope,
+            clause: "SELECT",
+            grouping,
+            windows: Some(vec![]),
+        };
+
+        let mut items: Vec<(Expr, Field)> = vec![];
+        for item in &select.projection {
+            let columns: Vec<&ScopeColumn> = match item {
+                ast::SelectItem::Expr { expr, alias } => {
+                    let name = alias.clone().unwrap_or_else(|| column_name(expr));
+                    let (expr, data_type) = self.expr(expr, &mut ctx)?;
+                    let field = Field::new(name, data_type).collate(ctx.collation(&expr));
+                    items.push((uncollated(expr), field));
+                    continue;
+                }
+                ast::SelectItem::Wildcard => scope.columns.iter().collect(),
+                ast::SelectItem::QualifiedWildcard(qualifier) => {
+                    if !scope.has_qualifier(qualifier) {
+                        return Err(Error::UnknownTable(qualifier.clone()));
+                    }
+                    scope
+                        .columns
+                        .iter()
+                        .filter(|c| c.qualifier == *qualifier)
+                        .collect()
+                }
+            };
+            for column in columns {
+                let name = vec![column.qualifier.clone(), column.name.clone()];
+                let (expr, data_type) = self.expr(&ast::Expr::Identifier(name), &mut ctx)?;
+                let field = Field::new(&column.name, data_type).collate(ctx.collation(&expr));
+                items.push((expr, field));
+            }
+        }
+
+        let having = match &select.having {
+            Some(having) => {
+                let windows = ctx.windows.take();
+                ctx.clause = "HAVING";
+                let typed = self.expr(having, &mut ctx);
+                ctx.windows = windows;
+                Some(check_predicate(typed?, "HAVING")?)
+            }
+            None => None,
+        };
+
+        ctx.clause = "ORDER BY";
+        let mut hidden: Vec<(Expr, Field)> = vec![];
+        let mut keys = vec![];
+        for key in order_by {
+            let (column, collation) = match &key.expr {
+                ast::Expr::Literal(ast::Literal::Int(n)) => usize::try_from(*n)
+                    .ok()
+                    .filter(|&n| (1..=items.len()).contains(&n))
+                    .map(|n| (n - 1, items[n - 1].1.collation))
+                    .ok_or(Error::OrderByPosition(*n))?,
+                expr => {
+                    let alias = match expr {
+                        ast::Expr::Identifier(name) if name.len() == 1 => {
+                            let mut matches =
+                                (0..items.len()).filter(|&i| items[i].1.name == name[0]);
+                            let first = matches.next();
+                            if first.is_some() && matches.next().is_some() {
+                                return Err(Error::AmbiguousColumn(name[0].clone()));
+                            }
+                            first
+                        }
+                        _ => None,
+                    };
+                    match alias {
+                        Some(i) => (i, items[i].1.collation),
+                        None => {
+                            let (expr, data_type) = self.expr(expr, &mut ctx)?;
+                            let collation = ctx.collation(&expr);
+                            let expr = uncollated(expr);
+                            match items.iter().position(|(item, _)| *item == expr) {
+                                Some(i) => (i, collation),
+                                None => {
+                                    hidden.push((expr, Field::new("?column?", data_type)));
+                                    (items.len() + hidden.len() - 1, collation)
+                                }
+                            }
+                        }
+                    }
+                }
+            };
+            keys.push(self.order_key(Expr::column(column).collate(collation), key));
+        }
+        if select.distinct && !hidden.is_empty() {
+            return Err(Error::DistinctOrderBy);
+        }
+
+        let ExprContext {
+            grouping, windows, ..
+        } = ctx;
+        if let Some(grouping) = grouping {
+            let (group_by, mut fields): (Vec<_>, Vec<_>) = grouping
+                .keys
+                .into_iter()
+                .map(|((expr, data_type), name)| {
+                    let collation = ExprContext::plain(&scope, "GROUP BY").collation(&expr);
+                    (expr, Field::new(name, data_type).collate(collation))
+                })
+                .unzip();
+            let (aggregates, aggregate_fields): (Vec<_>, Vec<_>) =
+                grouping.aggregates.into_iter().unzip();
+            fields.extend(aggregate_fields);
+            plan = LogicalPlan::Aggregate {
+                input: Box::new(plan),
+                group_by,
+                aggregates,
+                fields,
+            };
+        }
+        if let Some(predicate) = having {
+            plan = LogicalPlan::Filter {
+                input: Box::new(plan),
+                predicate,
+            };
+        }
+        let windows = windows.unwrap();
+        if !windows.is_empty() {
+            plan = layout_windows(plan, windows, items.iter_mut().chain(&mut hidden));
+        }
+
+        let width = items.len();
+        let (mut exprs, mut fields): (Vec<_>, Vec<_>) = items.into_iter().unzip();
+        let visible = fields.clone();
+        let has_hidden = !hidden.is_empty();
+        for (expr, field) in hidden {
+            exprs.push(expr);
+            fields.push(field);
+        }
+        plan = LogicalPlan::Project {
+            input: Box::new(plan),
+            exprs,
+            fields,
+        };
+        if select.distinct {
+            let fields = plan.fields();
+            plan = LogicalPlan::Aggregate {
+                input: Box::new(plan),
+                group_by: (0..width).map(Expr::column).collect(),
+                aggregates: vec![],
+                fields,
+            };
+        }
+        if !keys.is_empty() {
+            plan = LogicalPlan::Sort {
+                input: Box::new(plan),
+                keys,
+            };
+        }
+        plan = self.limit(plan, limit, offset)?;
+        if has_hidden {
+            plan = LogicalPlan::Project {
+                input: Box::new(plan),
+                exprs: (0..width).map(Expr::column).collect(),
+                fields: visible,
+            };
+        }
+        Ok(plan)
+    }
+
+    /// A VALUES list; each column takes the type of its non-NULL values.
+    fn values(&self, rows: &[Vec<ast::Expr>]) -> Result<LogicalPlan, Error> {
+        let empty = Scope::default();
+        let width = rows.first().map_or(0, Vec::len);
+        let mut types: Vec<Option<DataType>> = vec![None; width];
+        let mut bound = vec![];
+        for row in rows {
+            if row.len() != width {
+                return Err(Error::ValuesWidth);
+            }
+            let mut exprs = vec![];
+            for (i, expr) in row.iter().enumerate() {
+                let (expr, data_type) =
+                    self.expr(expr, &mut ExprContext::plain(&empty, "VALUES"))?;
+                types[i] = common_type(types[i], data_type).map_err(|(first, second)| {
+                    Error::ValuesType {
+                        column: i + 1,
+                        first,
+                        second,
+                    }
+                })?;
+                exprs.push((expr, data_type));
+            }
+            bound.push(exprs);
+        }
+        let rows = bound
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .zip(&types)
+                    .map(|((expr, data_type), target)| match (data_type, target) {
+                        (Some(DataType::Int), Some(DataType::Float)) => widen(expr),
+                        _ => expr,
+                    })
+                    .collect()
+            })
+            .collect();
+        let fields = types
+            .into_iter()
+            .enumerate()
+            .map(|(i, data_type)| Field::new(format!("column{}", i + 1), data_type))
+            .collect();
+        Ok(LogicalPlan::Values { rows, fields })
+    }
+
+    /// Positions of the columns `names` lists, or all of them.
+    fn target_columns(
+        &self,
+        table: &TableInfo,
+        names: Option<&[String]>,
+    ) -> Result<Vec<usize>, Error> {
+        match names {
+            None => Ok((0..table.schema.columns.len()).collect()),
+            Some(names) => names
+                .iter()
+                .enumerate()
+                .map(|(k, name)| {
+                    if names[..k].contains(name) {
+                        return Err(Error::DuplicateColumn(name.clone()));
+                    }
+                    table
+                        .schema
+                        .column_index(name)
+                        .ok_or_else(|| Error::ColumnNotFound(name.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    fn insert(&self, insert: &ast::Insert) -> Result<BoundStatement, Error> {
+        let table = self.target_table(&insert.table)?;
+        let columns = &table.schema.columns;
+        let targets = self.target_columns(table, insert.columns.as_deref())?;
+        let source = match &insert.source {
+            ast::InsertSource::Values(rows) => {
+                let values = self.values(rows)?;
+                // Parameters in a VALUES list take the type of the column
+                // they go to.
+                if let LogicalPlan::Values { rows, .. } = &values {
+                    for row in rows {
+                        for (k, expr) in row.iter().enumerate().take(targets.len()) {
+                            if let Expr::Parameter(n) = expr {
+                                let mut typed = (expr.clone(), self.parameters.borrow()[n - 1]);
+                                self.infer(&mut typed, Some(columns[targets[k]].data_type));
+                            }
+                        }
+                    }
+                }
+                values
+            }
+            ast::InsertSource::Query(query) => self.query(query)?,
+        };
+        let fields = source.fields();
+        if fields.len() != targets.len() {
+            return Err(Error::ColumnCountMismatch {
+                expected: targets.len(),
+                actual: fields.len(),
+            });
+        }
+        // Lay the source columns out in table order, with NULL for the
+        // columns not listed.
+        let mut exprs = vec![Expr::literal(Value::Null); columns.len()];
+        for (k, &target) in targets.iter().enumerate() {
+            exprs[target] = assign(&columns[target], Expr::column(k), fields[k].data_type)?;
+        }
+        let identity = exprs.len() == fields.len()
+            && exprs
+                .iter()
+                .enumerate()
+                .all(|(i, expr)| *expr == Expr::column(i));
+        let source = if identity {
+            source
+        } else {
+            LogicalPlan::Project {
+                input: Box::new(source),
+                exprs,
+                fields: schema_fields(&table.schema),
+            }
+        };
+        let on_conflict = insert
+            .on_conflict
+            .as_ref()
+            .map(|on_conflict| self.on_conflict(table, on_conflict))
+            .transpose()?;
+        let mut privileges = Privileges::INSERT;
+        if let Some(OnConflict {
+            action:
+                ConflictAction::DoUpdate {
+                    assignments,
+                    predicate,
+                },
+            ..
+        }) = &on_conflict
+        {
+            privileges |= Privileges::UPDATE;
+            let exprs = assignments.iter().map(|(_, expr)| expr).chain(predicate);
+            if reads_columns(exprs) {
+                privileges |= self.read_privilege(&table.name);
+            }
+        }
+        self.check(&table.name, privileges)?;
+        Ok(BoundStatement::Insert {
+            table: table.name.clone(),
+            source,
+            on_conflict,
+        })
+    }
+
+    fn on_conflict(
+        &self,
+        table: &TableInfo,
+        on_conflict: &ast::OnConflict,
+    ) -> Result<OnConflict, Error> {
+        let index = match &on_conflict.columns {
+            None => None,
+            Some(names) => {
+                let mut columns = names
+                    .iter()
+                    .map(|name| {
+                        table
+                            .schema
+                            .column_index(name)
+                            .ok_or_else(|| Error::ColumnNotFound(name.clone()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                columns.sort_unstable();
+                columns.dedup();
+                let index = table.indexes.iter().find(|index| {
+                    let indexed = index.columns().map(|mut indexed| {
+                        indexed.sort_unstable();
+                        indexed
+                    });
+                    index.unique && index.predicate.is_none() && indexed == Some(columns.clone())
+                });
+                Some(index.ok_or(Error::NoConflictIndex)?.name.clone())
+            }
+        };
+        let action = match &on_conflict.action {
+            ast::ConflictAction::DoNothing => ConflictAction::DoNothing,
+            ast::ConflictAction::DoUpdate {
+                assignments,
+                selection,
+            } => {
+                let scope = self
+                    .table_scope(&table.name, table)
+                    .concat(self.table_scope("excluded", table))?;
+                ConflictAction::DoUpdate {
+                    assignments: self.assignments(table, assignments, &scope)?,
+                    predicate: selection
+                        .as_ref()
+                        .map(|selection| self.predicate(selection, &scope, "WHERE"))
+                        .transpose()?,
+                }
+            }
+        };
+        Ok(OnConflict { index, action })
+    }
+
+    fn assignments(
+        &self,
+        table: &TableInfo,
+        assignments: &[ast::Assignment],
+        scope: &Scope,
+    ) -> Result<Vec<(usize, Expr)>, Error> {
+        let mut bound: Vec<(usize, Expr)> = vec![];
+        for assignment in assignments {
+            let i = table
+                .schema
+                .column_index(&assignment.column)
+                .ok_or_else(|| Error::ColumnNotFound(assignment.column.clone()))?;
+            if bound.iter().any(|(j, _)| *j == i) {
+                return Err(Error::DuplicateColumn(assignment.column.clone()));
+            }
+            let mut ctx = ExprContext::plain(scope, "UPDATE");
+            let mut typed = self.expr(&assignment.value, &mut ctx)?;
+            self.infer(&mut typed, Some(table.schema.columns[i].data_type));
+            let (expr, data_type) = typed;
+            bound.push((i, assign(&table.schema.columns[i], expr, data_type)?));
+        }
+        Ok(bound)
+    }
+
+    fn update(&self, update: &ast::Update) -> Result<BoundStatement, Error> {
+        let table = self.target_table(&update.table)?;
+        let scope = self.table_scope(&table.name, table);
+        let assignments = self.assignments(table, &update.assignments, &scope)?;
+        let predicate = update
+            .selection
+            .as_ref()
+            .map(|selection| self.predicate(selection, &scope, "WHERE"))
+            .transpose()?;
+        let mut privileges = Privileges::UPDATE;
+        if reads_columns(assignments.iter().map(|(_, expr)| expr).chain(&predicate)) {
+            privileges |= self.read_privilege(&table.name);
+        }
+        self.check(&table.name, privileges)?;
+        Ok(BoundStatement::Update {
+            table: table.name.clone(),
+            assignments,
+            predicate: self.restrict(&table.name, predicate),
+        })
+    }
+
+    fn delete(&self, delete: &ast::Delete) -> Result<BoundStatement, Error> {
+        let table = self.target_table(&delete.table)?;
+        let scope = self.table_scope(&table.name, table);
+        let predicate = delete
+            .selection
+            .as_ref()
+            .map(|selection| self.predicate(selection, &scope, "WHERE"))
+            .transpose()?;
+        let mut privileges = Privileges::DELETE;
+        if reads_columns(&predicate) {
+            privileges |= self.read_privilege(&table.name);
+        }
+        self.check(&table.name, privileges)?;
+        Ok(BoundStatement::Delete {
+            table: table.name.clone(),
+            predicate: self.restrict(&table.name, predicate),
+        })
+    }
+
+    /// `predicate` of a statement changing `table`, narrowed to the rows
+    /// of it the user has been given.
+    fn restrict(&self, table: &str, predicate: Option<Expr>) -> Option<Expr> {
+        match (predicate, self.row_filter(table)) {
+            (Some(predicate), Some(filter)) => Some(Expr::binary(BinaryOp::And, predicate, filter)),
+            (predicate, filter) => predicate.or(filter),
+        }
+    }
+
+    fn create_table(&self, create: &ast::CreateTable) -> Result<BoundStatement, Error> {
+        let taken = self.catalog.name_taken(&create.name, create.persistence);
+        if !create.if_not_exists && taken {
+            return Err(Error::TableExists(create.name.clone()));
+        }
+        for (i, column) in create.columns.iter().enumerate() {
+            if create.columns[..i].iter().any(|c| c.name == column.name) {
+                return Err(Error::DuplicateColumn(column.name.clone()));
+            }
+        }
+        let check_columns = |columns: &[String]| {
+            for (k, name) in columns.iter().enumerate() {
+                if !create.columns.iter().any(|c| c.name == *name) {
+                    return Err(Error::ColumnNotFound(name.clone()));
+                }
+                if columns[..k].contains(name) {
+                    return Err(Error::DuplicateColumn(name.clone()));
+                }
+            }
+            Ok(())
+        };
+
+        let mut primary_key: Option<Vec<String>> = None;
+        let mut unique: Vec<Vec<String>> = vec![];
+        let column_constraints = create.columns.iter().flat_map(|column| {
+            let key = vec![column.name.clone()];
+            let primary = column
+                .primary_key
+                .then(|| ast::TableConstraint::PrimaryKey(key.clone()));
+            let unique = column.unique.then_some(ast::TableConstraint::Unique(key));
+            primary.into_iter().chain(unique)
+        });
+        for constraint in column_constraints.chain(create.constraints.iter().cloned()) {
+            match constraint {
+                ast::TableConstraint::PrimaryKey(columns) => {
+                    check_columns(&columns)?;
+                    if primary_key.is_some() {
+                        return Err(Error::MultiplePrimaryKeys(create.name.clone()));
+                    }
+                    primary_key = Some(columns);
+                }
+                ast::TableConstraint::Unique(columns) => {
+                    check_columns(&columns)?;
+                    unique.push(columns);
+                }
+            }
+        }
+
+        let in_primary_key =
+            |name: &String| primary_key.as_ref().is_some_and(|key| key.contains(name));
+        let mut columns = vec![];
+        for def in &create.columns {
+            let mut column = Column::new(&def.name, def.data_type);
+            if def.not_null || in_primary_key(&def.name) {
+                column = column.not_null();
+            }
+            if let Some(name) = &def.collation {
+                let collation = Collation::from_name(name)
+                    .ok_or_else(|| Error::UnknownCollation(name.clone()))?;
+                if !collatable(def.data_type) {
+                    return Err(Error::CollationType(def.data_type));
+                }
+                column = column.collate(collation);
+            }
+            columns.push(column);
+        }
+        let schema = Schema::new(columns);
+        let index = |name: String, columns: Vec<String>| IndexDef {
+            name,
+            table: create.name.clone(),
+            keys: columns
+                .iter()
+                .map(|name| {
+                    // Checked to exist above.
+                    let i = schema.column_index(name).unwrap();
+                    IndexKey::for_column(&schema, i)
+                })
+                .collect(),
+            predicate: None,
+            unique: true,
+        };
+        let mut indexes = vec![];
+        if let Some(columns) = primary_key {
+            indexes.push(index(format!("{}_pkey", create.name), columns));
+        }
+        for columns in unique {
+            let name = format!("{}_{}_key", create.name, columns.join("_"));
+            indexes.push(index(name, columns));
+        }
+        let partitioning = match &create.partition_by {
+            Some(_) if create.persistence != Persistence::Permanent => {
+                return Err(Error::Unsupported(
+                    "partitioned temporary or unlogged tables",
+                ));
+            }
+            Some((method, column)) => {
+                if !indexes.is_empty() {
+                    return Err(Error::Unsupported(
+                        "PRIMARY KEY and UNIQUE on partitioned tables",
+                    ));
+                }
+                let column = (create.columns.iter())
+                    .position(|c| c.name == *column)
+                    .ok_or_else(|| Error::ColumnNotFound(column.clone()))?;
+                Some(Partitioning {
+                    method: *method,
+                    column,
+                })
+            }
+            None => None,
+        };
+        Ok(BoundStatement::CreateTable {
+            name: create.name.clone(),
+            persistence: create.persistence,
+            schema,
+            indexes,
+            partitioning,
+            if_not_exists: create.if_not_exists,
+        })
+    }
+
+    /// A bound for a partition of `parent`, with range bounds coerced to
+    /// the type of its partition key.
+    fn partition_bound(
+        &self,
+        parent: &TableInfo,
+        bound: &ast::PartitionBoundSpec,
+    ) -> Result<PartitionBound, Error> {
+        let partitioning = parent
+            .partitioning
+            .ok_or_else(|| Error::NotPartitioned(parent.name.clone()))?;
+        let key = &parent.schema.columns[partitioning.column];
+        let scope = Scope::new("", &[]);
+        let value = |expr: Option<&ast::Expr>| -> Result<Option<Value>, Error> {
+            let Some(expr) = expr else {
+                return Ok(None);
+            };
+            let mut ctx = ExprContext::plain(&scope, "partition bounds");
+            let (expr, _) = self.expr(expr, &mut ctx)?;
+            let value = match expr.eval(&[]) {
+                Ok(value) if !expr.has_parameters() && !expr.has_calls() => value,
+                _ => {
+                    return Err(Error::Unsupported(
+                        "partition bounds that are not constants",
+                    ))
+                }
+            };
+            let Some(actual) = value.data_type() else {
+                return Err(Error::Unsupported("NULL partition bounds"));
+            };
+            let value = value.coerce_to(key.data_type).ok_or(Error::ColumnType {
+                column: key.name.clone(),
+                expected: key.data_type,
+                actual,
+            })?;
+            Ok(Some(value))
+        };
+        Ok(match bound {
+            ast::PartitionBoundSpec::Range { from, to } => PartitionBound::Range {
+                from: value(from.as_deref())?,
+                to: value(to.as_deref())?,
+            },
+            ast::PartitionBoundSpec::Hash { modulus, remainder } => PartitionBound::Hash {
+                modulus: *modulus,
+                remainder: *remainder,
+            },
+        })
+    }
+
+    fn view(&self, name: &str) -> Result<&ViewInfo, Error> {
+        self.table(name)?
+            .view
+            .as_ref()
+            .ok_or_else(|| Error::NotAView(name.to_string()))
+    }
+
+    fn create_materialized_view(
+        &self,
+        create: &ast::CreateMaterializedView,
+    ) -> Result<BoundStatement, Error> {
+        if !create.if_not_exists && self.catalog.table(&create.name).is_some() {
+            return Err(Error::TableExists(create.name.clone()));
+        }
+        let query = self.query(&create.query)?;
+        // The definition is bound again on refresh, with nothing to give
+        // parameters their values.
+        if !self.parameters.borrow().is_empty() {
+            return Err(Error::Unsupported("a parameter in a materialized view"));
+        }
+        let mut columns: Vec<Column> = vec![];
+        for field in query.fields() {
+            if columns.iter().any(|column| column.name == field.name) {
+                return Err(Error::DuplicateColumn(field.name));
+            }
+            // A column of bare NULLs has no type of its own.
+            let data_type = field.data_type.unwrap_or(DataType::Text);
+            columns.push(Column::new(field.name, data_type).collate(field.collation));
+        }
+        let dependencies = query.tables();
+        for table in &dependencies {
+            self.check_permanent(table, "materialized views of temporary or unlogged tables")?;
+        }
+        Ok(BoundStatement::CreateMaterializedView {
+            name: create.name.clone(),
+            schema: Schema::new(columns),
+            view: ViewInfo {
+                definition: create.definition.clone(),
+                dependencies,
+            },
+            query,
+            if_not_exists: create.if_not_exists,
+        })
+    }
+
+    fn create_policy(&self, create: &ast::CreatePolicy) -> Result<BoundStatement, Error> {
+        let table = self.target_table(&create.table)?;
+        self.check_permanent(&table.name, "policies on temporary or unlogged tables")?;
+        if table
+            .policies
+            .iter()
+            .any(|policy| policy.name == create.name)
+        {
+            return Err(Error::PolicyExists {
+                name: create.name.clone(),
+                table: create.table.clone(),
+            });
+        }
+        for user in &create.users {
+            self.user_exists(user)?;
+        }
+        let scope = Scope::table(&table.name, &table.schema);
+        let condition = self.predicate(&create.condition, &scope, "policy conditions")?;
+        if condition.has_parameters() {
+            return Err(Error::Unsupported("parameters in policy conditions"));
+        }
+        if condition.has_calls() {
+            return Err(Error::Unsupported(
+                "registered functions in policy conditions",
+            ));
+        }
+        let mut users = create.users.clone();
+        users.sort();
+        users.dedup();
+        Ok(BoundStatement::CreatePolicy {
+            table: create.table.clone(),
+            policy: PolicyInfo {
+                name: create.name.clone(),
+                users,
+                condition,
+            },
+        })
+    }
+
+    fn create_trigger(&self, create: &ast::CreateTrigger) -> Result<BoundStatement, Error> {
+        if self.catalog.trigger(&create.name).is_some() {
+            return Err(Error::TriggerExists(create.name.clone()));
+        }
+        let table = self.target_table(&create.table)?;
+        self.check_permanent(&table.name, "triggers on temporary or unlogged tables")?;
+        let action = match &create.action {
+            ast::TriggerBody::Function(name) => TriggerAction::Function(name.clone()),
+            ast::TriggerBody::Statement {
+                definition,
+                references,
+            } => {
+                let parameters = references
+                    .iter()
+                    .map(|(image, column)| {
+                        let i = table
+                            .schema
+                            .column_index(column)
+                            .ok_or_else(|| Error::ColumnNotFound(format!("{image}.{column}")))?;
+                        Ok((*image, i))
+                    })
+                    .collect::<Result<_, Error>>()?;
+                // Statements that fire the trigger plan the action again,
+                // but its mistakes should surface now.
+                let statement = sql::parse_statement(definition)
+                    .map_err(|_| Error::InvalidTrigger(create.name.clone()))?;
+                bind_prepared(self.catalog, &statement, None, self.null_ordering)?;
+                TriggerAction::Statement {
+                    definition: definition.clone(),
+                    parameters,
+                }
+            }
+        };
+        Ok(BoundStatement::CreateTrigger {
+            table: create.table.clone(),
+            trigger: TriggerInfo {
+                name: create.name.clone(),
+                timing: create.timing,
+                events: create.events.clone(),
+                action,
+            },
+        })
+    }
+
+    fn create_index(&self, create: &ast::CreateIndex) -> Result<BoundStatement, Error> {
+        if let Some(name) = &create.name {
+            if !create.if_not_exists && self.index_exists(name) {
+                return Err(Error::IndexExists(name.clone()));
+            }
+        }
+        let table = self.table(&create.table)?;
+        if create.concurrently {
+            self.check_permanent(&table.name, "CONCURRENTLY on temporary or unlogged tables")?;
+        }
+        let scope = Scope::table(&table.name, &table.schema);
+        let mut keys: Vec<IndexKey> = vec![];
+        for key in &create.keys {
+            if key.descending {
+                return Err(Error::Unsupported("descending index keys"));
+            }
+            let nulls_first = self.nulls_first(key);
+            let key = &key.expr;
+            let mut ctx = ExprContext::plain(&scope, "index expressions");
+            let (expr, data_type) = self.expr(key, &mut ctx)?;
+            // Entries are keyed as the column compares.
+            let expr = ctx.sort_key(expr);
+            if let ast::Expr::Identifier(name) = key {
+                if keys.iter().any(|other| other.expr == expr) {
+                    return Err(Error::DuplicateColumn(name.join(".")));
+                }
+            }
+            if expr.has_parameters() {
+                return Err(Error::Unsupported("parameters in index definitions"));
+            }
+            if expr.has_calls() {
+                return Err(Error::Unsupported(
+                    "registered functions in index definitions",
+                ));
+            }
+            let Some(data_type) = data_type else {
+                return Err(Error::Unsupported("index keys of unknown type"));
+            };
+            keys.push(IndexKey {
+                expr,
+                data_type,
+                nulls_first,
+            });
+        }
+        let predicate = create
+            .predicate
+            .as_ref()
+            .map(|predicate| self.predicate(predicate, &scope, "index predicates"))
+            .transpose()?;
+        if predicate.as_ref().is_some_and(Expr::has_parameters) {
+            return Err(Error::Unsupported("parameters in index definitions"));
+        }
+        if predicate.as_ref().is_some_and(Expr::has_calls) {
+            return Err(Error::Unsupported(
+                "registered functions in index definitions",
+            ));
+        }
+        let name = match &create.name {
+            Some(name) => name.clone(),
+            None => self.index_name(table, &create.keys),
+        };
+        Ok(BoundStatement::CreateIndex {
+            index: IndexDef {
+                name,
+                table: create.table.clone(),
+                keys,
+                predicate,
+                unique: create.unique,
+            },
+            if_not_exists: create.if_not_exists,
+            concurrently: create.concurrently,
+        })
+    }
+
+    /// A name for an unnamed index, from its table and key columns, made
+    /// unique with a number if taken: `t_a_b_idx`, `t_expr_idx1`.
+    fn index_name(&self, table: &TableInfo, keys: &[ast::OrderByExpr]) -> String {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| match &key.expr {
+                ast::Expr::Identifier(name) => name.last().unwrap().clone(),
+                _ => "expr".to_string(),
+            })
+            .collect();
+        let base = format!("{}_{}_idx", table.name, keys.join("_"));
+        (0..)
+            .map(|n| match n {
+                0 => base.clone(),
+                n => format!("{base}{n}"),
+            })
+            .find(|name| !self.index_exists(name))
+            .unwrap()
+    }
+}
+
+/// The type of a column holding values of both types, if there is one.
+fn common_type(
+    a: Option<DataType>,
+    b: Option<DataType>,
+) -> Result<Option<DataType>, (DataType, DataType)> {
+    match (a, b) {
+        (None, t) | (t, None) => Ok(t),
+        (Some(a), Some(b)) if a == b => Ok(Some(a)),
+        (Some(a), Some(b)) if is_numeric(a) && is_numeric(b) => Ok(Some(DataType::Float)),
+        (Some(a), Some(b)) => Err((a, b)),
+    }
+}
+
+/// The fields of `cte`, named by its column list where it has one.
+fn cte_fields(cte: &ast::Cte, mut fields: Vec<Field>) -> Result<Vec<Field>, Error> {
+    if cte.columns.len() > fields.len() {
+        return Err(Error::CteColumns {
+            name: cte.name.clone(),
+            expected: fields.len(),
+            actual: cte.columns.len(),
+        });
+    }
+    for (field, name) in fields.iter_mut().zip(&cte.columns) {
+        field.name = name.clone();
+    }
+    Ok(fields)
+}
+
+/// `plan` with its output described by `fields` instead.
+fn rename(plan: LogicalPlan, fields: Vec<Field>) -> LogicalPlan {
+    if plan.fields() == fields {
+        return plan;
+    }
+    match plan {
+        LogicalPlan::Project { input, exprs, .. } => LogicalPlan::Project {
+            input,
+            exprs,
+            fields,
+        },
+        plan => LogicalPlan::Project {
+            exprs: (0..plan.width()).map(Expr::column).collect(),
+            input: Box::new(plan),
+            fields,
+        },
+    }
+}
+
+/// `plan` with INT columns widened where `fields` says FLOAT.
+fn conform(plan: LogicalPlan, fields: &[Field]) -> LogicalPlan {
+    let types: Vec<_> = plan.fields().into_iter().map(|f| f.data_type).collect();
+    let widened =
+        |i: usize| types[i] == Some(DataType::Int) && fields[i].data_type == Some(DataType::Float);
+    if !(0..types.len()).any(widened) {
+        return plan;
+    }
+    let exprs = (0..types.len())
+        .map(|i| {
+            if widened(i) {
+                widen(Expr::column(i))
+            } else {
+                Expr::column(i)
+            }
+        })
+        .collect();
+    LogicalPlan::Project {
+        input: Box::new(plan),
+        exprs,
+        fields: fields.to_vec(),
+    }
+}
+
+fn distinct(plan: LogicalPlan) -> LogicalPlan {
+    let fields = plan.fields();
+    LogicalPlan::Aggregate {
+        group_by: (0..fields.len()).map(Expr::column).collect(),
+        input: Box::new(plan),
+        aggregates: vec![],
+        fields,
+    }
+}
+
+/// `first` combined with each of `arms`, left to right, keeping
+/// duplicates only for UNION ALL. Columns are named after those of
+/// `first`.
+fn union(first: LogicalPlan, arms: Vec<(bool, LogicalPlan)>) -> Result<LogicalPlan, Error> {
+    let mut fields = first.fields();
+    for (_, arm) in &arms {
+        let arm_fields = arm.fields();
+        if arm_fields.len() != fields.len() {
+            return Err(Error::UnionWidth);
+        }
+        for (i, (field, other)) in fields.iter_mut().zip(arm_fields).enumerate() {
+            field.data_type =
+                common_type(field.data_type, other.data_type).map_err(|(first, second)| {
+                    Error::UnionType {
+                        column: i + 1,
+                        first,
+                        second,
+                    }
+                })?;
+        }
+    }
+    let mut inputs = vec![conform(first, &fields)];
+    for (all, arm) in arms {
+        inputs.push(conform(arm, &fields));
+        if !all {
+            let union = LogicalPlan::Union {
+                inputs,
+                fields: fields.clone(),
+            };
+            inputs = vec![distinct(union)];
+        }
+    }
+    Ok(match inputs.len() {
+        1 => inputs.pop().unwrap(),
+        _ => LogicalPlan::Union { inputs, fields },
+    })
+}
+
+fn check_predicate(typed: Typed, clause: &'static str) -> Result<Expr, Error> {
+    match typed {
+        (_, Some(actual)) if actual != DataType::Bool => Err(Error::ClauseType { clause, actual }),
+        (expr, _) => Ok(expr),
+    }
+}
+
+/// Stacks one window node per distinct window over `plan` and points the
+/// placeholder columns in `exprs` at the results.
+fn layout_windows<'e>(
+    mut plan: LogicalPlan,
+    calls: Vec<WindowCall>,
+    exprs: impl Iterator<Item = &'e mut (Expr, Field)>,
+) -> LogicalPlan {
+    let mut windows: Vec<(Vec<Expr>, Vec<SortKey>, Vec<usize>)> = vec![];
+    for (id, call) in calls.iter().enumerate() {
+        match windows
+            .iter_mut()
+            .find(|(p, o, _)| *p == call.partition_by && *o == call.order_by)
+        {
+            Some((_, _, ids)) => ids.push(id),
+            None => windows.push((call.partition_by.clone(), call.order_by.clone(), vec![id])),
+        }
+    }
+    let mut position = vec![0; calls.len()];
+    let mut next = plan.width();
+    for (partition_by, order_by, ids) in windows {
+        for &id in &ids {
+            position[id] = next;
+            next += 1;
+        }
+        plan = LogicalPlan::Window {
+            input: Box::new(plan),
+            partition_by,
+            order_by,
+            functions: ids.iter().map(|&id| calls[id].func.clone()).collect(),
+            fields: ids.iter().map(|&id| calls[id].field.clone()).collect(),
+        };
+    }
+    let remap = |i: usize| {
+        if i >= WINDOW_BASE {
+            position[i - WINDOW_BASE]
+        } else {
+            i
+        }
+    };
+    for (expr, _) in exprs {
+        *expr = expr.map_columns(&remap);
+    }
+    plan
+}
+
+/// `SELECT columns FROM table`, or every column without a list.
+fn table_query(table: &str, columns: Option<&[String]>) -> ast::Query {
+    let projection = match columns {
+        None => vec![ast::SelectItem::Wildcard],
+        Some(columns) => columns
+            .iter()
+            .map(|column| ast::SelectItem::Expr {
+                expr: ast::Expr::Identifier(vec![column.clone()]),
+                alias: None,
+            })
+            .collect(),
+    };
+    ast::Query {
+        with: None,
+        select: ast::Select {
+            distinct: false,
+            projection,
+            from: vec![ast::TableRef::Table {
+                name: table.to_string(),
+                alias: None,
+            }],
+            selection: None,
+            group_by: vec![],
+            having: None,
+        },
+        unions: vec![],
+        order_by: vec![],
+        limit: None,
+        offset: None,
+        locking: None,
+    }
+}
+
+fn copy_format(options: &[ast::CopyOption]) -> Result<CopyFormat, Error> {
+    let mut csv = csv::Options::default();
+    let mut other = None;
+    for option in options {
+        let value = option.value.as_deref();
+        let invalid = || Error::InvalidCopyOption {
+            name: option.name.clone(),
+            value: value.unwrap_or_default().to_string(),
+        };
+        let char_value = || {
+            let mut chars = value.unwrap_or_default().chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c != '\n' && c != '\r' => Ok(c),
+                _ => Err(invalid()),
+            }
+        };
+        match option.name.as_str() {
+            "format" if value.is_some_and(|value| value.eq_ignore_ascii_case("csv")) => {}
+            "format" if value.is_some_and(|value| value.eq_ignore_ascii_case("json")) => {
+                other = Some(CopyFormat::Json);
+            }
+            "format" if value.is_some_and(|value| value.eq_ignore_ascii_case("parquet")) => {
+                if cfg!(not(feature = "parquet")) {
+                    return Err(Error::Unsupported(
+                        "the Parquet format in a build without the parquet feature",
+                    ));
+                }
+                other = Some(CopyFormat::Parquet);
+            }
+            "header" => {
+                csv.header = match value.map(str::to_lowercase).as_deref() {
+                    None | Some("true" | "on" | "1") => true,
+                    Some("false" | "off" | "0") => false,
+                    _ => return Err(invalid()),
+                }
+            }
+            "delimiter" => csv.delimiter = char_value()?,
+            "quote" => csv.quote = char_value()?,
+            "null" => csv.null = value.ok_or_else(invalid)?.to_string(),
+            "format" => return Err(invalid()),
+            _ => return Err(Error::UnknownCopyOption(option.name.clone())),
+        }
+        if csv.delimiter == csv.quote {
+            return Err(invalid());
+        }
+    }
+    let Some(format) = other else {
+        return Ok(CopyFormat::Csv(csv));
+    };
+    if options.iter().any(|option| option.name != "format") {
+        return Err(Error::Unsupported(
+            "COPY options other than FORMAT for JSON or Parquet",
+        ));
+    }
+    Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecContext;
+    use crate::planner::testing::{bind_sql, query, run, setup};
+
+    fn error(catalog: &Catalog, sql: &str) -> String {
+        bind_sql(catalog, sql).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_resolution_and_type_errors() {
+        let (_bufmgr, catalog) = setup();
+        let cases = [
+            ("SELECT * FROM nope", r#"table "nope" does not exist"#),
+            ("SELECT salry FROM emp", r#"column "salry" does not exist"#),
+            (
+                "SELECT id FROM emp JOIN dept ON dept = dept.id",
+                r#"column reference "id" is ambiguous"#,
+            ),
+            (
+                "SELECT d.id FROM emp e",
+                r#"missing FROM-clause entry for table "d""#,
+            ),
+            (
+                "SELECT * FROM emp, emp",
+                r#"table name "emp" specified more than once"#,
+            ),
+            (
+                "SELECT name, count(*) FROM emp GROUP BY dept",
+                r#"column "name" must appear in the GROUP BY clause or be used in an aggregate function"#,
+            ),
+            (
+                "SELECT id FROM emp WHERE sum(salary) > 1",
+                "aggregate functions are not allowed in WHERE",
+            ),
+            (
+                "SELECT sum(count(*)) FROM emp",
+                "aggregate functions are not allowed in aggregate function calls",
+            ),
+            (
+                "SELECT name + 1 FROM emp",
+                "operator + cannot be applied to TEXT and INT",
+            ),
+            (
+                "SELECT id FROM emp WHERE salary",
+                "expression in WHERE must be of type BOOL, not FLOAT",
+            ),
+            (
+                "SELECT sum(name) FROM emp",
+                "function SUM cannot be applied to TEXT",
+            ),
+            (
+                "SELECT trim(name) FROM emp",
+                "function trim() does not exist",
+            ),
+            (
+                "SELECT upper(salary) FROM emp",
+                "function upper() cannot be applied to FLOAT",
+            ),
+            (
+                "SELECT rank() FROM emp",
+                "window function rank() requires an OVER clause",
+            ),
+            ("SELECT CAST(x'00' AS INT)", "cannot cast BYTES to INT"),
+            (
+                "SELECT DISTINCT name FROM emp ORDER BY salary",
+                "for SELECT DISTINCT, ORDER BY expressions must appear in select list",
+            ),
+            (
+                "SELECT id FROM emp LIMIT id",
+                r#"column "id" does not exist"#,
+            ),
+            (
+                "SELECT id FROM emp LIMIT -1",
+                "argument of LIMIT must be a non-negative integer constant",
+            ),
+            (
+                "INSERT INTO emp (id, name) VALUES (1, 2)",
+                r#"column "name" is of type TEXT but expression is of type INT"#,
+            ),
+            (
+                "INSERT INTO emp (id) VALUES (1, 2)",
+                "expected 1 values, got 2",
+            ),
+            (
+                "INSERT INTO emp VALUES (1, 'a', 1, 1.0) ON CONFLICT (name) DO NOTHING",
+                "there is no unique index matching the ON CONFLICT specification",
+            ),
+            (
+                "UPDATE emp SET id = 1, id = 2",
+                r#"column "id" specified more than once"#,
+            ),
+            (
+                "CREATE TABLE t (a INT PRIMARY KEY, b INT, PRIMARY KEY (b))",
+                r#"multiple primary keys for table "t" are not allowed"#,
+            ),
+            ("DROP INDEX nope", r#"index "nope" does not exist"#),
+            (
+                "SELECT id FROM emp WHERE id = $1 AND name = $1",
+                "operator = cannot be applied to TEXT and INT",
+            ),
+            (
+                "SELECT count(*) FROM emp FOR UPDATE",
+                "FOR UPDATE is not allowed with aggregate functions",
+            ),
+            (
+                "SELECT DISTINCT name FROM emp FOR SHARE NOWAIT",
+                "FOR SHARE is not allowed with DISTINCT clause",
+            ),
+        ];
+        for (sql, message) in cases {
+            assert_eq!(message, error(&catalog, sql), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_parameter_types() {
+        let (_bufmgr, catalog) = setup();
+        let types = |sql| {
+            let statement = crate::sql::parse_statement(sql).unwrap();
+            bind_prepared(&catalog, &statement, None, NullOrdering::Low)
+                .unwrap()
+                .1
+        };
+        use DataType::*;
+        assert_eq!(
+            vec![Some(Float), Some(Text), None, Some(Bool)],
+            types("SELECT $3 FROM emp WHERE salary > $1 AND name LIKE $2 OR $4")
+        );
+        assert_eq!(
+            vec![Some(Int), None, Some(Int)],
+            types("SELECT id FROM emp WHERE id IN ($1, 2) AND dept BETWEEN 1 AND $3")
+        );
+        assert_eq!(
+            vec![Some(Float), Some(Int)],
+            types("UPDATE emp SET salary = $1 WHERE id = $2")
+        );
+        assert_eq!(
+            vec![Some(Text), Some(Int)],
+            types("INSERT INTO emp (name, id) VALUES ($1, $2)")
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let (bufmgr, catalog) = setup();
+        let plan = query(&catalog, "SELECT e.id AS n, salary * 2, 'x' FROM emp e");
+        assert_eq!(
+            vec![
+                Field::new("n", Some(DataType::Int)),
+                Field::new("?column?", Some(DataType::Float)),
+                Field::new("?column?", Some(DataType::Text)),
+            ],
+            plan.fields()
+        );
+        assert_eq!(
+            vec![vec![Value::Int(3)]],
+            run(&bufmgr, &catalog, "SELECT 1 + 2")
+        );
+
+        let rows = run(
+            &bufmgr,
+            &catalog,
+            "SELECT e.name, d.title FROM emp e LEFT JOIN dept d ON e.dept = d.id \
+             WHERE e.name LIKE '%e%' OR e.id IN (1, 2) ORDER BY e.id DESC",
+        );
+        let text = |s: &str| Value::Text(s.into());
+        assert_eq!(
+            vec![
+                vec![text("dee"), Value::Null],
+                vec![text("bob"), text("eng")],
+                vec![text("ann"), text("eng")],
+            ],
+            rows
+        );
+
+        // ORDER BY on a column that is not selected, with LIMIT/OFFSET.
+        let rows = run(
+            &bufmgr,
+            &catalog,
+            "SELECT name FROM emp ORDER BY salary LIMIT 2 OFFSET 1",
+        );
+        assert_eq!(vec![vec![text("bob")], vec![text("ann")]], rows);
+
+        let rows = run(
+            &bufmgr,
+            &catalog,
+            "SELECT DISTINCT dept FROM emp WHERE dept IS NOT NULL ORDER BY 1",
+        );
+        assert_eq!(vec![vec![Value::Int(1)], vec![Value::Int(2)]], rows);
+
+        let rows = run(
+            &bufmgr,
+            &catalog,
+            "SELECT s.total FROM (SELECT sum(salary) AS total FROM emp) AS s",
+        );
+        assert_eq!(vec![vec![Value::Float(350.0)]], rows);
+    }
+
+    #[test]
+    fn test_unions_and_ctes() {
+        let (bufmgr, catalog) = setup();
+        let int = |rows: &[i64]| -> Vec<Vec<Value>> {
+            rows.iter().map(|&n| vec![Value::Int(n)]).collect()
+        };
+        assert_eq!(
+            int(&[1, 2, 3, 4, 1]),
+            run(
+                &bufmgr,
+                &catalog,
+                "SELECT id FROM emp UNION ALL SELECT id FROM dept WHERE id = 1"
+            )
+        );
+        assert_eq!(
+            int(&[4, 3, 2]),
+            run(
+                &bufmgr,
+                &catalog,
+                "SELECT id FROM emp UNION SELECT id FROM dept ORDER BY id DESC LIMIT 3"
+            )
+        );
+        let plan = query(&catalog, "SELECT dept FROM emp UNION SELECT 1.5");
+        assert_eq!(
+            vec![Field::new("dept", Some(DataType::Float))],
+            plan.fields()
+        );
+
+        // A CTE read once is inlined, one read twice is materialized.
+        let plan = query(
+            &catalog,
+            "WITH e AS (SELECT id FROM emp WHERE dept = 1) SELECT * FROM e",
+        );
+        assert_eq!(0, plan.work_table_references(0));
+        let sql = "WITH e (n) AS (SELECT id FROM emp WHERE dept = 1) \
+                   SELECT a.n, b.n FROM e a JOIN e b ON a.n < b.n";
+        let plan = query(&catalog, sql);
+        let LogicalPlan::Materialize { input, .. } = &plan else {
+            panic!("{plan:?}");
+        };
+        assert_eq!(2, input.work_table_references(0));
+        assert_eq!(
+            vec![vec![Value::Int(1), Value::Int(2)]],
+            run(&bufmgr, &catalog, sql)
+        );
+        // Either can be asked for, and CTEs hide tables.
+        let plan = query(
+            &catalog,
+            "WITH emp AS MATERIALIZED (SELECT 1) SELECT * FROM emp",
+        );
+        assert!(matches!(plan, LogicalPlan::Materialize { .. }));
+        assert_eq!(
+            int(&[2]),
+            run(
+                &bufmgr,
+                &catalog,
+                "WITH a AS (SELECT 1 AS x), b AS NOT MATERIALIZED (SELECT x + 1 AS y FROM a) \
+                 SELECT y FROM b"
+            )
+        );
+
+        let sql = "WITH RECURSIVE n (i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 5) \
+                   SELECT sum(i) FROM n";
+        assert_eq!(int(&[15]), run(&bufmgr, &catalog, sql));
+        // UNION stops at rows seen before.
+        assert_eq!(
+            int(&[0, 1, 2]),
+            run(
+                &bufmgr,
+                &catalog,
+                "WITH RECURSIVE r (i) AS (SELECT 0 UNION SELECT (i + 1) % 3 FROM r) \
+                 SELECT i FROM r ORDER BY i"
+            )
+        );
+
+        for (sql, message) in [
+            (
+                "SELECT id, name FROM emp UNION SELECT id FROM dept",
+                "each UNION query must have the same number of columns",
+            ),
+            (
+                "SELECT id FROM emp UNION SELECT title FROM dept",
+                "UNION column 1 mixes INT and TEXT",
+            ),
+            (
+                "SELECT id FROM emp UNION SELECT id FROM dept ORDER BY salary",
+                "column \"salary\" does not exist",
+            ),
+            (
+                "WITH a AS (SELECT 1), a AS (SELECT 2) SELECT * FROM a",
+                "WITH query name \"a\" specified more than once",
+            ),
+            (
+                "WITH a (x, y) AS (SELECT 1) SELECT * FROM a",
+                "WITH query \"a\" has 1 columns available but 2 columns specified",
+            ),
+            (
+                "WITH RECURSIVE r AS (SELECT 1 AS i UNION ALL SELECT 'x' FROM r) SELECT * FROM r",
+                "recursive query \"r\" column 1 has type INT in its non-recursive term \
+                 but type TEXT in its recursive term",
+            ),
+            (
+                "WITH a AS (SELECT * FROM a) SELECT * FROM a",
+                "table \"a\" does not exist",
+            ),
+        ] {
+            assert_eq!(message, error(&catalog, sql), "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_aggregates_and_windows() {
+        let (bufmgr, catalog) = setup();
+        let rows = run(
+            &bufmgr,
+            &catalog,
+            "SELECT dept + 0, count(*), avg(salary) FROM emp GROUP BY dept + 0 \
+             HAVING max(salary) > 60 ORDER BY count(*) DESC, 1",
+        );
+        assert_eq!(
+            vec![
+                vec![Value::Int(1), Value::Int(2), Value::Float(90.0)],
+                vec![Value::Int(2), Value::Int(1), Value::Float(120.0)],
+            ],
+            rows
+        );
+
+        let rows = run(
+            &bufmgr,
+            &catalog,
+            "SELECT name, rank() OVER (ORDER BY salary DESC), \
+             sum(salary) OVER (PARTITION BY dept) FROM emp ORDER BY id",
+        );
+        let row = |name: &str, rank: i64, sum: f64| {
+            vec![
+                Value::Text(name.into()),
+                Value::Int(rank),
+                Value::Float(sum),
+            ]
+        };
+        assert_eq!(
+            vec![
+                row("ann", 2, 180.0),
+                row("bob", 3, 180.0),
+                row("cid", 1, 120.0),
+                row("dee", 4, 50.0),
+            ],
+            rows
+        );
+
+        // Window functions over grouped results.
+        let rows = run(
+            &bufmgr,
+            &catalog,
+            "SELECT dept, row_number() OVER (ORDER BY count(*) DESC, dept) \
+             FROM emp GROUP BY dept ORDER BY dept",
+        );
+        assert_eq!(
+            vec![
+                vec![Value::Null, Value::Int(2)],
+                vec![Value::Int(1), Value::Int(1)],
+                vec![Value::Int(2), Value::Int(3)],
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn test_dml_and_ddl() {
+        let (bufmgr, catalog) = setup();
+        let BoundStatement::Insert { source, .. } =
+            bind_sql(&catalog, "INSERT INTO emp (salary, id) VALUES (10, 5)").unwrap()
+        else {
+            panic!()
+        };
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        assert_eq!(
+            vec![vec![
+                Value::Int(5),
+                Value::Null,
+                Value::Null,
+                Value::Float(10.0)
+            ]],
+            source.to_plan().collect(&ctx).unwrap()
+        );
+
+        let statement = bind_sql(
+            &catalog,
+            "INSERT INTO emp VALUES (1, 'ann', 1, 1.0) ON CONFLICT (id) \
+             DO UPDATE SET salary = emp.salary + excluded.salary WHERE excluded.dept = 1",
+        )
+        .unwrap();
+        let BoundStatement::Insert { on_conflict, .. } = statement else {
+            panic!()
+        };
+        let add = Expr::binary(BinaryOp::Add, Expr::column(3), Expr::column(7));
+        let predicate = Expr::binary(BinaryOp::Eq, Expr::column(6), Expr::literal(1i64));
+        assert_eq!(
+            Some(OnConflict {
+                index: Some("emp_pkey".into()),
+                action: ConflictAction::DoUpdate {
+                    assignments: vec![(3, add)],
+                    predicate: Some(predicate),
+                },
+            }),
+            on_conflict
+        );
+
+        assert_eq!(
+            BoundStatement::Update {
+                table: "emp".into(),
+                assignments: vec![(3, widen(Expr::column(2)))],
+                predicate: Some(Expr::unary(
+                    UnaryOp::Not,
+                    Expr::binary(
+                        BinaryOp::And,
+                        Expr::binary(BinaryOp::GtEq, Expr::column(0), Expr::literal(1i64)),
+                        Expr::binary(BinaryOp::LtEq, Expr::column(0), Expr::literal(2i64)),
+                    ),
+                )),
+            },
+            bind_sql(
+                &catalog,
+                "UPDATE emp SET salary = dept WHERE id NOT BETWEEN 1 AND 2"
+            )
+            .unwrap()
+        );
+
+        let BoundStatement::CreateTable {
+            schema, indexes, ..
+        } = bind_sql(
+            &catalog,
+            "CREATE TABLE t (a INT, b TEXT UNIQUE, PRIMARY KEY (a))",
+        )
+        .unwrap()
+        else {
+            panic!()
+        };
+        assert!(!schema.columns[0].nullable);
+        let names: Vec<_> = indexes.iter().map(|index| index.name.as_str()).collect();
+        assert_eq!(vec!["t_pkey", "t_b_key"], names);
+        assert_eq!(
+            r#"table "emp" already exists"#,
+            error(&catalog, "CREATE TABLE emp (a INT)")
+        );
+        assert!(bind_sql(&catalog, "CREATE TABLE IF NOT EXISTS emp (a INT)").is_ok());
+        assert_eq!(
+            BoundStatement::Analyze {
+                tables: vec!["dept".into(), "emp".into()]
+            },
+            bind_sql(&catalog, "ANALYZE").unwrap()
+        );
+        assert_eq!(
+            r#"table "nope" does not exist"#,
+            error(&catalog, "ANALYZE nope")
+        );
+        assert!(matches!(
+            bind_sql(&catalog, "EXPLAIN SELECT id FROM emp").unwrap(),
+            BoundStatement::Explain { analyze: false, statement }
+                if matches!(*statement, BoundStatement::Query(_))
+        ));
+        assert_eq!(
+            "EXPLAIN of a statement other than SELECT is not supported",
+            error(&catalog, "EXPLAIN DELETE FROM emp")
+        );
+    }
+}
@@ -0,0 +1,620 @@
+//! Row count and cost estimates for executable plans.
+//!
+//! Costs are in units of one sequential page read, with per-row CPU work
+//! weighted as in PostgreSQL. Estimates use the statistics ANALYZE stores
+//! in the catalog; tables without them are assumed to be of a default
+//! size and predicates to have default selectivities.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::Bound;
+
+use crate::catalog::Catalog;
+use crate::executor::{AccessPath, JoinKind, KeyRange, Plan};
+use crate::expr::{BinaryOp, Expr, UnaryOp};
+use crate::stats::ColumnStats;
+use crate::value::Value;
+
+pub const SEQ_PAGE_COST: f64 = 1.0;
+pub const RANDOM_PAGE_COST: f64 = 4.0;
+pub const CPU_TUPLE_COST: f64 = 0.01;
+pub const CPU_OPERATOR_COST: f64 = 0.0025;
+
+/// The weights of the cost units, defaulting to the constants above.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostConstants {
+    pub seq_page_cost: f64,
+    pub random_page_cost: f64,
+    pub cpu_tuple_cost: f64,
+    pub cpu_operator_cost: f64,
+}
+
+impl Default for CostConstants {
+    fn default() -> Self {
+        Self {
+            seq_page_cost: SEQ_PAGE_COST,
+            random_page_cost: RANDOM_PAGE_COST,
+            cpu_tuple_cost: CPU_TUPLE_COST,
+            cpu_operator_cost: CPU_OPERATOR_COST,
+        }
+    }
+}
+
+/// Size assumed for a table that has not been analyzed.
+const DEFAULT_ROWS: f64 = 1000.0;
+const DEFAULT_ROWS_PER_PAGE: f64 = 50.0;
+const DEFAULT_EQ_SELECTIVITY: f64 = 0.1;
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+const DEFAULT_NULL_FRACTION: f64 = 0.01;
+const DEFAULT_SELECTIVITY: f64 = 0.5;
+/// Entries per B+tree node, for estimating the depth of an index.
+const INDEX_FANOUT: f64 = 100.0;
+/// Rounds a recursive CTE is assumed to run, as in PostgreSQL.
+const RECURSIVE_ROUNDS: f64 = 10.0;
+/// Elements an unnested array is assumed to hold, as in PostgreSQL.
+const ARRAY_ELEMENTS: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    /// Rows the plan is expected to produce.
+    pub rows: f64,
+    /// Total cost of running the plan to completion.
+    pub cost: f64,
+}
+
+/// An estimate along with what is known about each output column.
+struct Derived<'a> {
+    estimate: Estimate,
+    columns: Vec<Option<&'a ColumnStats>>,
+}
+
+impl<'a> Derived<'a> {
+    fn column(&self, expr: &Expr) -> Option<&'a ColumnStats> {
+        match expr {
+            Expr::Column(i) => self.columns.get(*i).copied().flatten(),
+            _ => None,
+        }
+    }
+
+    /// Number of distinct values of `expr`, which cannot exceed the rows.
+    fn distinct(&self, expr: &Expr) -> Option<f64> {
+        let stats = self.column(expr)?;
+        Some((stats.distinct.max(1) as f64).min(self.estimate.rows.max(1.0)))
+    }
+}
+
+pub struct CostModel<'a> {
+    catalog: &'a Catalog,
+    costs: CostConstants,
+    /// Rows of the work tables estimated so far, by id.
+    work_tables: RefCell<HashMap<usize, f64>>,
+}
+
+impl<'a> CostModel<'a> {
+    pub fn new(catalog: &'a Catalog) -> Self {
+        Self {
+            catalog,
+            costs: CostConstants::default(),
+            work_tables: RefCell::default(),
+        }
+    }
+
+    pub fn with_costs(mut self, costs: CostConstants) -> Self {
+        self.costs = costs;
+        self
+    }
+
+    /// Records the rows [`Plan::WorkTable`] `id` holds, for estimating
+    /// plans that read it before the plan defining it exists.
+    pub fn set_work_table_rows(&self, id: usize, rows: f64) {
+        self.work_tables.borrow_mut().insert(id, rows);
+    }
+
+    pub fn estimate(&self, plan: &Plan) -> Estimate {
+        self.derive(plan).estimate
+    }
+
+    fn table(&self, name: &str) -> (f64, f64, Vec<Option<&'a ColumnStats>>) {
+        let Some(table) = self.catalog.table(name) else {
+            return (DEFAULT_ROWS, DEFAULT_ROWS / DEFAULT_ROWS_PER_PAGE, vec![]);
+        };
+        match &table.stats {
+            Some(stats) => (
+                stats.rows as f64,
+                stats.pages.max(1) as f64,
+                stats.columns.iter().map(Some).collect(),
+            ),
+            None => (
+                DEFAULT_ROWS,
+                DEFAULT_ROWS / DEFAULT_ROWS_PER_PAGE,
+                vec![None; table.schema.len()],
+            ),
+        }
+    }
+
+    fn derive(&self, plan: &Plan) -> Derived<'a> {
+        let c = &self.costs;
+        let derived = |rows: f64, cost: f64, columns| Derived {
+            estimate: Estimate { rows, cost },
+            columns,
+        };
+        match plan {
+            Plan::Values { rows } => {
+                let width = rows.first().map_or(0, Vec::len);
+                let n = rows.len() as f64;
+                derived(n, n * c.cpu_tuple_cost, vec![None; width])
+            }
+            Plan::SeqScan { table } => {
+                let (rows, pages, columns) = self.table(table);
+                derived(
+                    rows,
+                    pages * c.seq_page_cost + rows * c.cpu_tuple_cost,
+                    columns,
+                )
+            }
+            // System tables are small and held in memory.
+            Plan::SystemScan { table } => {
+                let rows = DEFAULT_ROWS;
+                derived(
+                    rows,
+                    rows * c.cpu_tuple_cost,
+                    vec![None; table.schema().len()],
+                )
+            }
+            Plan::ParallelSeqScan { table, predicate } => {
+                let scan = self.derive(&Plan::SeqScan {
+                    table: table.clone(),
+                });
+                let Some(predicate) = predicate else {
+                    return scan;
+                };
+                let input = scan.estimate;
+                let selectivity = selectivity(predicate, &scan);
+                let cost = input.cost + input.rows * c.cpu_operator_cost;
+                derived(input.rows * selectivity, cost, scan.columns)
+            }
+            Plan::IndexScan {
+                table,
+                index,
+                range,
+            } => {
+                let (rows, _, columns) = self.table(table);
+                let index = self.catalog.table(table).and_then(|t| t.index(index));
+                let selectivity = index.map_or(DEFAULT_RANGE_SELECTIVITY, |index| {
+                    let keys: Vec<_> = index.keys.iter().map(|key| key.as_column()).collect();
+                    let selectivity = range_selectivity(range, &keys, &columns, index.unique, rows);
+                    // A partial index only holds the rows its predicate
+                    // accepts.
+                    let scan = derived(rows, 0.0, columns.clone());
+                    let covered = index
+                        .predicate
+                        .as_ref()
+                        .map_or(1.0, |predicate| self::selectivity(predicate, &scan));
+                    selectivity * covered
+                });
+                let matched = (rows * selectivity).max(1.0).min(rows.max(1.0));
+                let depth = rows.max(1.0).log(INDEX_FANOUT).ceil().max(1.0);
+                let cost = (depth + matched) * c.random_page_cost + matched * c.cpu_tuple_cost;
+                derived(matched, cost, columns)
+            }
+            Plan::RidScan { table, access } => {
+                let table = table.clone();
+                let mut scan = self.derive(&match access.clone() {
+                    AccessPath::SeqScan => Plan::SeqScan { table },
+                    AccessPath::IndexScan { index, range } => Plan::IndexScan {
+                        table,
+                        index,
+                        range,
+                    },
+                });
+                scan.columns.push(None);
+                scan
+            }
+            Plan::LockRows { input, .. } => {
+                let mut input = self.derive(input);
+                input.columns.pop();
+                input
+            }
+            Plan::Filter { input, predicate } => {
+                let input = self.derive(input);
+                let selectivity = selectivity(predicate, &input);
+                let Estimate { rows, cost } = input.estimate;
+                derived(
+                    rows * selectivity,
+                    cost + rows * c.cpu_operator_cost,
+                    input.columns,
+                )
+            }
+            Plan::Project { input, exprs } => {
+                let input = self.derive(input);
+                let columns = exprs.iter().map(|expr| input.column(expr)).collect();
+                let Estimate { rows, cost } = input.estimate;
+                let cost = cost + rows * exprs.len() as f64 * c.cpu_operator_cost;
+                derived(rows, cost, columns)
+            }
+            Plan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+            } => {
+                let input = self.derive(input);
+                let Estimate { rows, cost } = input.estimate;
+                let groups = if group_by.is_empty() {
+                    1.0
+                } else {
+                    group_by
+                        .iter()
+                        .map(|key| input.distinct(key).unwrap_or(rows * DEFAULT_SELECTIVITY))
+                        .product::<f64>()
+                        .clamp(1.0, rows.max(1.0))
+                };
+                let work = (group_by.len() + aggregates.len()) as f64 * c.cpu_operator_cost;
+                let mut columns: Vec<_> = group_by.iter().map(|key| input.column(key)).collect();
+                columns.extend(aggregates.iter().map(|_| None));
+                derived(
+                    groups,
+                    cost + rows * work + groups * c.cpu_tuple_cost,
+                    columns,
+                )
+            }
+            Plan::Sort { input, keys } => {
+                let input = self.derive(input);
+                let Estimate { rows, cost } = input.estimate;
+                let comparisons = rows * rows.max(2.0).log2() * keys.len().max(1) as f64;
+                let cost = cost + comparisons * 2.0 * c.cpu_operator_cost + rows * c.cpu_tuple_cost;
+                derived(rows, cost, input.columns)
+            }
+            Plan::Window {
+                input, functions, ..
+            } => {
+                let mut input = self.derive(input);
+                let Estimate { rows, cost } = input.estimate;
+                input.estimate.cost = cost + rows * functions.len() as f64 * c.cpu_operator_cost;
+                input.columns.extend(functions.iter().map(|_| None));
+                input
+            }
+            Plan::Unnest { input, .. } => {
+                let mut input = self.derive(input);
+                let Estimate { rows, cost } = input.estimate;
+                let rows_out = rows * ARRAY_ELEMENTS;
+                let cost = cost + rows * c.cpu_operator_cost + rows_out * c.cpu_tuple_cost;
+                input.estimate = Estimate {
+                    rows: rows_out,
+                    cost,
+                };
+                input.columns.push(None);
+                input
+            }
+            Plan::NestedLoopJoin {
+                left,
+                right,
+                kind,
+                predicate,
+                ..
+            } => {
+                let (left, right) = (self.derive(left), self.derive(right));
+                let (l, r) = (left.estimate, right.estimate);
+                let cost = l.cost
+                    + r.cost
+                    + r.rows * c.cpu_tuple_cost
+                    + l.rows * r.rows * c.cpu_operator_cost;
+                join(left, right, *kind, &[], predicate.as_ref(), cost, c)
+            }
+            Plan::HashJoin {
+                left,
+                right,
+                kind,
+                left_keys,
+                right_keys,
+                predicate,
+                ..
+            } => {
+                let (left, right) = (self.derive(left), self.derive(right));
+                let (l, r) = (left.estimate, right.estimate);
+                let keys = left_keys.len() as f64 * c.cpu_operator_cost;
+                let cost = l.cost + r.cost + r.rows * (c.cpu_tuple_cost + keys) + l.rows * keys;
+                let pairs: Vec<_> = left_keys.iter().zip(right_keys).collect();
+                join(left, right, *kind, &pairs, predicate.as_ref(), cost, c)
+            }
+            Plan::MergeJoin {
+                left,
+                right,
+                kind,
+                left_keys,
+                right_keys,
+                predicate,
+                ..
+            } => {
+                let (left, right) = (self.derive(left), self.derive(right));
+                let (l, r) = (left.estimate, right.estimate);
+                let keys = left_keys.len() as f64 * c.cpu_operator_cost;
+                let cost = l.cost + r.cost + (l.rows + r.rows) * keys;
+                let pairs: Vec<_> = left_keys.iter().zip(right_keys).collect();
+                join(left, right, *kind, &pairs, predicate.as_ref(), cost, c)
+            }
+            Plan::Limit {
+                input,
+                limit,
+                offset,
+            } => {
+                let mut input = self.derive(input);
+                let rows = (input.estimate.rows - *offset as f64).max(0.0);
+                input.estimate.rows = limit.map_or(rows, |limit| rows.min(limit as f64));
+                input
+            }
+            Plan::Union { inputs } => {
+                let inputs: Vec<_> = inputs.iter().map(|input| self.derive(input)).collect();
+                let width = inputs.first().map_or(0, |input| input.columns.len());
+                let rows = inputs.iter().map(|input| input.estimate.rows).sum();
+                let cost = inputs.iter().map(|input| input.estimate.cost).sum();
+                derived(rows, cost, vec![None; width])
+            }
+            Plan::WorkTable { id, width } => {
+                let rows = self.work_tables.borrow().get(id).copied();
+                let rows = rows.unwrap_or(DEFAULT_ROWS);
+                derived(rows, rows * c.cpu_tuple_cost, vec![None; *width])
+            }
+            Plan::Materialize { id, cte, input } => {
+                let cte = self.derive(cte).estimate;
+                self.set_work_table_rows(*id, cte.rows);
+                let mut input = self.derive(input);
+                input.estimate.cost += cte.cost + cte.rows * c.cpu_tuple_cost;
+                input
+            }
+            Plan::RecursiveUnion {
+                id,
+                anchor,
+                recursive,
+                distinct,
+            } => {
+                let anchor = self.derive(anchor);
+                self.set_work_table_rows(*id, anchor.estimate.rows);
+                let round = self.derive(recursive).estimate;
+                let rows = anchor.estimate.rows + round.rows * RECURSIVE_ROUNDS;
+                let mut cost = anchor.estimate.cost + round.cost * RECURSIVE_ROUNDS;
+                if *distinct {
+                    cost += rows * c.cpu_operator_cost;
+                }
+                derived(rows, cost, vec![None; anchor.columns.len()])
+            }
+        }
+    }
+}
+
+/// Combines the estimates of two join inputs, given the cost of the join
+/// itself apart from producing its output rows.
+fn join<'a>(
+    left: Derived<'a>,
+    right: Derived<'a>,
+    kind: JoinKind,
+    keys: &[(&Expr, &Expr)],
+    predicate: Option<&Expr>,
+    cost: f64,
+    c: &CostConstants,
+) -> Derived<'a> {
+    let (l, r) = (left.estimate, right.estimate);
+    let width = left.columns.len();
+    let mut columns = left.columns;
+    columns.extend(right.columns);
+    let joined = Derived {
+        estimate: Estimate {
+            rows: l.rows * r.rows,
+            cost,
+        },
+        columns,
+    };
+    let mut selectivity: f64 = keys
+        .iter()
+        .map(|(lhs, rhs)| {
+            let rhs = rhs.map_columns(&|i| i + width);
+            eq_columns_selectivity(&joined, lhs, &rhs)
+        })
+        .product();
+    if let Some(predicate) = predicate {
+        selectivity *= self::selectivity(predicate, &joined);
+    }
+    let mut rows = l.rows * r.rows * selectivity;
+    if kind == JoinKind::Left {
+        rows = rows.max(l.rows);
+    }
+    Derived {
+        estimate: Estimate {
+            rows,
+            cost: cost + rows * c.cpu_tuple_cost,
+        },
+        columns: joined.columns,
+    }
+}
+
+fn eq_columns_selectivity(input: &Derived, lhs: &Expr, rhs: &Expr) -> f64 {
+    match (input.column(lhs), input.column(rhs)) {
+        (Some(a), Some(b)) => {
+            let distinct = a.distinct.max(b.distinct).max(1) as f64;
+            (1.0 - a.null_fraction) * (1.0 - b.null_fraction) / distinct
+        }
+        _ => DEFAULT_EQ_SELECTIVITY,
+    }
+}
+
+fn selectivity(predicate: &Expr, input: &Derived) -> f64 {
+    let selectivity = match predicate {
+        Expr::Literal(Value::Bool(true)) => 1.0,
+        Expr::Literal(_) => 0.0,
+        Expr::Binary {
+            op: BinaryOp::And,
+            lhs,
+            rhs,
+        } => selectivity(lhs, input) * selectivity(rhs, input),
+        Expr::Binary {
+            op: BinaryOp::Or,
+            lhs,
+            rhs,
+        } => {
+            let (a, b) = (selectivity(lhs, input), selectivity(rhs, input));
+            a + b - a * b
+        }
+        Expr::Unary {
+            op: UnaryOp::Not,
+            expr,
+        } => 1.0 - selectivity(expr, input),
+        Expr::IsNull { expr, negated } => {
+            let nulls = input
+                .column(expr)
+                .map_or(DEFAULT_NULL_FRACTION, |stats| stats.null_fraction);
+            if *negated {
+                1.0 - nulls
+            } else {
+                nulls
+            }
+        }
+        Expr::Binary { op, lhs, rhs } => match (op, lhs.as_ref(), rhs.as_ref()) {
+            (BinaryOp::Eq | BinaryOp::NotEq, Expr::Column(_), Expr::Column(_)) => {
+                let eq = eq_columns_selectivity(input, lhs, rhs);
+                if *op == BinaryOp::Eq {
+                    eq
+                } else {
+                    1.0 - eq
+                }
+            }
+            (_, column @ Expr::Column(_), Expr::Literal(value)) => {
+                compare_selectivity(input.column(column), *op, value)
+            }
+            (_, Expr::Literal(value), column @ Expr::Column(_)) => {
+                let op = match op {
+                    BinaryOp::Lt => BinaryOp::Gt,
+                    BinaryOp::LtEq => BinaryOp::GtEq,
+                    BinaryOp::Gt => BinaryOp::Lt,
+                    BinaryOp::GtEq => BinaryOp::LtEq,
+                    op => *op,
+                };
+                compare_selectivity(input.column(column), op, value)
+            }
+            (BinaryOp::Eq, _, _) => DEFAULT_EQ_SELECTIVITY,
+            (BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq, _, _) => {
+                DEFAULT_RANGE_SELECTIVITY
+            }
+            _ => DEFAULT_SELECTIVITY,
+        },
+        _ => DEFAULT_SELECTIVITY,
+    };
+    selectivity.clamp(0.0, 1.0)
+}
+
+/// Selectivity of `column op value`.
+fn compare_selectivity(stats: Option<&ColumnStats>, op: BinaryOp, value: &Value) -> f64 {
+    if value.is_null() {
+        return 0.0;
+    }
+    match op {
+        BinaryOp::Eq => eq_selectivity(stats),
+        BinaryOp::NotEq => {
+            let non_null = stats.map_or(1.0, |stats| 1.0 - stats.null_fraction);
+            non_null - eq_selectivity(stats)
+        }
+        BinaryOp::Lt | BinaryOp::LtEq => {
+            let below = fraction_below(stats, value).unwrap_or(DEFAULT_RANGE_SELECTIVITY);
+            let eq = if op == BinaryOp::LtEq {
+                eq_selectivity(stats)
+            } else {
+                0.0
+            };
+            below + eq
+        }
+        BinaryOp::Gt | BinaryOp::GtEq => {
+            let Some(below) = fraction_below(stats, value) else {
+                return DEFAULT_RANGE_SELECTIVITY;
+            };
+            let non_null = stats.map_or(1.0, |stats| 1.0 - stats.null_fraction);
+            let eq = if op == BinaryOp::Gt {
+                eq_selectivity(stats)
+            } else {
+                0.0
+            };
+            non_null - below - eq
+        }
+        BinaryOp::Like => DEFAULT_EQ_SELECTIVITY,
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+fn eq_selectivity(stats: Option<&ColumnStats>) -> f64 {
+    match stats {
+        Some(stats) if stats.distinct > 0 => (1.0 - stats.null_fraction) / stats.distinct as f64,
+        Some(_) => 0.0,
+        None => DEFAULT_EQ_SELECTIVITY,
+    }
+}
+
+fn numeric(value: &Value) -> Option<f64> {
+    match value {
+        Value::Int(i) => Some(*i as f64),
+        Value::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+/// Fraction of all rows whose value is below `value`, interpolating
+/// between the column's minimum and maximum as if values were spread
+/// evenly. Only numeric columns can be interpolated.
+fn fraction_below(stats: Option<&ColumnStats>, value: &Value) -> Option<f64> {
+    let stats = stats?;
+    let (min, max) = (numeric(stats.min.as_ref()?)?, numeric(stats.max.as_ref()?)?);
+    let value = numeric(value)?;
+    let position = if max > min {
+        ((value - min) / (max - min)).clamp(0.0, 1.0)
+    } else if value > min {
+        1.0
+    } else {
+        0.0
+    };
+    Some(position * (1.0 - stats.null_fraction))
+}
+
+/// Fraction of a table's rows an index scan over `range` visits.
+fn range_selectivity(
+    range: &KeyRange,
+    index_columns: &[Option<usize>],
+    columns: &[Option<&ColumnStats>],
+    unique: bool,
+    rows: f64,
+) -> f64 {
+    let stats = |i: usize| {
+        index_columns
+            .get(i)
+            .copied()
+            .flatten()
+            .and_then(|column| columns.get(column).copied().flatten())
+    };
+    if let (Bound::Included(start), Bound::Included(end)) = (&range.start, &range.end) {
+        if start == end {
+            if unique && start.len() == index_columns.len() {
+                return 1.0 / rows.max(1.0);
+            }
+            return (0..start.len()).map(|i| eq_selectivity(stats(i))).product();
+        }
+    }
+    // Only the first column of a range is used; further columns only
+    // narrow it.
+    let first = |bound: &Bound<Vec<Expr>>| match bound {
+        Bound::Included(key) | Bound::Excluded(key) => match key.first() {
+            Some(Expr::Literal(value)) => Some(value.clone()),
+            _ => None,
+        },
+        Bound::Unbounded => None,
+    };
+    let below = |bound: &Bound<Vec<Expr>>, default: f64| {
+        first(bound)
+            .and_then(|value| fraction_below(stats(0), &value))
+            .unwrap_or(default)
+    };
+    let non_null = stats(0).map_or(1.0, |stats| 1.0 - stats.null_fraction);
+    let start = match &range.start {
+        Bound::Unbounded => 0.0,
+        bound => below(bound, 1.0 - DEFAULT_RANGE_SELECTIVITY),
+    };
+    let end = match &range.end {
+        Bound::Unbounded => 1.0,
+        bound => below(bound, DEFAULT_RANGE_SELECTIVITY).min(non_null),
+    };
+    (end - start).max(eq_selectivity(stats(0)))
+}
@@ -0,0 +1,377 @@
+//! EXPLAIN: executable plans rendered as indented trees.
+//!
+//! Each operator gets a line with its estimated cost and rows, followed
+//! by lines describing its expressions and then by its inputs, marked
+//! with `->`. EXPLAIN ANALYZE runs the plan first and adds what each
+//! operator actually did.
+
+use std::fmt::Display;
+use std::ops::Bound;
+use std::time::{Duration, Instant};
+
+use super::cost::CostModel;
+use crate::catalog::Catalog;
+use crate::executor::{
+    self, AccessPath, ExecContext, Instrumentation, JoinKind, KeyRange, Plan, SortKey,
+};
+use crate::expr::Expr;
+use crate::lock::LockWait;
+
+/// Renders `plan` with estimates.
+pub fn explain(catalog: &Catalog, plan: &Plan) -> String {
+    let mut lines = vec![];
+    Renderer {
+        cost: CostModel::new(catalog),
+        instrumentation: None,
+    }
+    .node(plan, "", "  ", &mut lines);
+    lines.join("\n")
+}
+
+/// Runs `plan` to completion, discarding its rows, and renders it with
+/// estimates and the rows, loops and time of each operator.
+pub fn explain_analyze(ctx: &ExecContext<'_>, plan: &Plan) -> Result<String, executor::Error> {
+    let instrumentation = Instrumentation::new();
+    let start = Instant::now();
+    {
+        let ctx = ctx.with_instrumentation(&instrumentation);
+        let mut executor = plan.start(&ctx)?;
+        while executor.next()?.is_some() {}
+    }
+    let elapsed = start.elapsed();
+    let mut lines = vec![];
+    Renderer {
+        cost: CostModel::new(ctx.catalog),
+        instrumentation: Some(&instrumentation),
+    }
+    .node(plan, "", "  ", &mut lines);
+    lines.push(format!("Execution time: {}", millis(elapsed)));
+    Ok(lines.join("\n"))
+}
+
+fn millis(time: Duration) -> String {
+    format!("{:.3} ms", time.as_secs_f64() * 1000.0)
+}
+
+fn list<T: Display>(items: &[T]) -> String {
+    items
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn sort_keys(keys: &[SortKey]) -> String {
+    let keys: Vec<_> = keys
+        .iter()
+        .map(|key| {
+            let order = if key.descending { " DESC" } else { "" };
+            // Only where they differ from the low null ordering.
+            let nulls = match (key.descending, key.nulls_first) {
+                (false, false) => " NULLS LAST",
+                (true, true) => " NULLS FIRST",
+                _ => "",
+            };
+            format!("{}{order}{nulls}", key.expr)
+        })
+        .collect();
+    list(&keys)
+}
+
+fn range(range: &KeyRange) -> String {
+    let key = |key: &[Expr]| format!("({})", list(key));
+    let start = match &range.start {
+        Bound::Included(key_values) => format!("[{}", key(key_values)),
+        Bound::Excluded(key_values) => format!("({}", key(key_values)),
+        Bound::Unbounded => "(-inf".to_string(),
+    };
+    let end = match &range.end {
+        Bound::Included(key_values) => format!("{}]", key(key_values)),
+        Bound::Excluded(key_values) => format!("{})", key(key_values)),
+        Bound::Unbounded => "+inf)".to_string(),
+    };
+    format!("{start}, {end}")
+}
+
+fn join_kind(kind: JoinKind) -> &'static str {
+    match kind {
+        JoinKind::Inner => "Inner",
+        JoinKind::Left => "Left",
+    }
+}
+
+struct Renderer<'a> {
+    cost: CostModel<'a>,
+    instrumentation: Option<&'a Instrumentation>,
+}
+
+impl Renderer<'_> {
+    /// Appends the lines of `plan`: its title after `prefix`, and its
+    /// details and inputs after `indent`.
+    fn node(&self, plan: &Plan, prefix: &str, indent: &str, lines: &mut Vec<String>) {
+        let (title, details, inputs) = describe(plan);
+        let estimate = self.cost.estimate(plan);
+        let mut line = format!(
+            "{prefix}{title}  (cost={:.2} rows={:.0})",
+            estimate.cost, estimate.rows
+        );
+        if let Some(instrumentation) = self.instrumentation {
+            match instrumentation.stats(plan) {
+                Some(stats) => line.push_str(&format!(
+                    " (actual rows={} loops={} time={})",
+                    stats.rows,
+                    stats.loops,
+                    millis(stats.time)
+                )),
+                None => line.push_str(" (never executed)"),
+            }
+        }
+        lines.push(line);
+        for detail in details {
+            lines.push(format!("{indent}{detail}"));
+        }
+        let prefix = format!("{indent}->  ");
+        let indent = format!("{indent}      ");
+        for input in inputs {
+            self.node(input, &prefix, &indent, lines);
+        }
+    }
+}
+
+/// Title, detail lines and inputs of one operator.
+fn describe(plan: &Plan) -> (String, Vec<String>, Vec<&Plan>) {
+    match plan {
+        Plan::Values { rows } => (format!("Values ({} rows)", rows.len()), vec![], vec![]),
+        Plan::SeqScan { table } => (format!("Seq Scan on {table}"), vec![], vec![]),
+        Plan::SystemScan { table } => (format!("System Scan on {table}"), vec![], vec![]),
+        Plan::ParallelSeqScan { table, predicate } => (
+            format!("Parallel Seq Scan on {table}"),
+            predicate.iter().map(|p| format!("Filter: {p}")).collect(),
+            vec![],
+        ),
+        Plan::IndexScan {
+            table,
+            index,
+            range: key_range,
+        } => (
+            format!("Index Scan using {index} on {table}"),
+            vec![format!("Range: {}", range(key_range))],
+            vec![],
+        ),
+        Plan::RidScan { table, access } => {
+            let scan = match access {
+                AccessPath::SeqScan => Plan::SeqScan {
+                    table: table.clone(),
+                },
+                AccessPath::IndexScan { index, range } => Plan::IndexScan {
+                    table: table.clone(),
+                    index: index.clone(),
+                    range: range.clone(),
+                },
+            };
+            let (title, details, _) = describe(&scan);
+            (title, details, vec![])
+        }
+        Plan::LockRows {
+            input, mode, wait, ..
+        } => {
+            let wait = match wait {
+                LockWait::Wait => "",
+                LockWait::NoWait => " NOWAIT",
+                LockWait::SkipLocked => " SKIP LOCKED",
+            };
+            (
+                "LockRows".to_string(),
+                vec![format!("Mode: {mode}{wait}")],
+                vec![input],
+            )
+        }
+        Plan::Filter { input, predicate } => (
+            "Filter".to_string(),
+            vec![format!("Predicate: {predicate}")],
+            vec![input],
+        ),
+        Plan::Project { input, exprs } => (
+            "Project".to_string(),
+            vec![format!("Output: {}", list(exprs))],
+            vec![input],
+        ),
+        Plan::Aggregate {
+            input,
+            group_by,
+            aggregates,
+        } => {
+            let mut details = vec![];
+            if !group_by.is_empty() {
+                details.push(format!("Group By: {}", list(group_by)));
+            }
+            if !aggregates.is_empty() {
+                details.push(format!("Aggregates: {}", list(aggregates)));
+            }
+            ("Aggregate".to_string(), details, vec![input])
+        }
+        Plan::Sort { input, keys } => (
+            "Sort".to_string(),
+            vec![format!("Sort Key: {}", sort_keys(keys))],
+            vec![input],
+        ),
+        Plan::Window {
+            input,
+            partition_by,
+            order_by,
+            functions,
+        } => {
+            let mut details = vec![format!("Functions: {}", list(functions))];
+            if !partition_by.is_empty() {
+                details.push(format!("Partition By: {}", list(partition_by)));
+            }
+            if !order_by.is_empty() {
+                details.push(format!("Order By: {}", sort_keys(order_by)));
+            }
+            ("Window".to_string(), details, vec![input])
+        }
+        Plan::Unnest { input, array } => (
+            "Unnest".to_string(),
+            vec![format!("Array: {array}")],
+            vec![input],
+        ),
+        Plan::NestedLoopJoin {
+            left,
+            right,
+            kind,
+            predicate,
+            ..
+        } => (
+            format!("Nested Loop {} Join", join_kind(*kind)),
+            predicate
+                .iter()
+                .map(|p| format!("Join Filter: {p}"))
+                .collect(),
+            vec![left, right],
+        ),
+        Plan::HashJoin {
+            left,
+            right,
+            kind,
+            left_keys,
+            right_keys,
+            predicate,
+            ..
+        }
+        | Plan::MergeJoin {
+            left,
+            right,
+            kind,
+            left_keys,
+            right_keys,
+            predicate,
+            ..
+        } => {
+            let name = match plan {
+                Plan::HashJoin { .. } => "Hash",
+                _ => "Merge",
+            };
+            let mut details = vec![format!(
+                "Keys: ({}) = ({})",
+                list(left_keys),
+                list(right_keys)
+            )];
+            details.extend(predicate.iter().map(|p| format!("Join Filter: {p}")));
+            (
+                format!("{name} {} Join", join_kind(*kind)),
+                details,
+                vec![left, right],
+            )
+        }
+        Plan::Limit {
+            input,
+            limit,
+            offset,
+        } => {
+            let mut details = vec![];
+            if let Some(limit) = limit {
+                details.push(format!("Limit: {limit}"));
+            }
+            if *offset > 0 {
+                details.push(format!("Offset: {offset}"));
+            }
+            ("Limit".to_string(), details, vec![input])
+        }
+        Plan::Union { inputs } => ("Append".to_string(), vec![], inputs.iter().collect()),
+        Plan::WorkTable { id, .. } => (format!("Work Table Scan #{id}"), vec![], vec![]),
+        Plan::Materialize { id, cte, input } => {
+            (format!("Materialize #{id}"), vec![], vec![cte, input])
+        }
+        Plan::RecursiveUnion {
+            id,
+            anchor,
+            recursive,
+            distinct,
+        } => {
+            let all = if *distinct { "" } else { " All" };
+            (
+                format!("Recursive Union{all} #{id}"),
+                vec![],
+                vec![anchor, recursive],
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::testing::{query, setup};
+    use crate::planner::{Optimizer, PhysicalPlanner};
+
+    #[test]
+    fn test_explain() {
+        let (bufmgr, mut catalog) = setup();
+        let plan = |catalog: &Catalog, sql| {
+            let logical = Optimizer::new().optimize(query(catalog, sql));
+            PhysicalPlanner::new(catalog).plan(&logical)
+        };
+        // Unanalyzed tables are assumed to be large enough for the index.
+        let text = explain(
+            &catalog,
+            &plan(&catalog, "SELECT name FROM emp WHERE id = 3"),
+        );
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(6, lines.len(), "{text}");
+        assert!(lines[0].starts_with("Project  (cost="), "{text}");
+        assert_eq!("  Output: #1", lines[1]);
+        assert!(lines[2].starts_with("  ->  Filter  (cost="), "{text}");
+        assert_eq!("        Predicate: (#0 = 3)", lines[3]);
+        assert!(
+            lines[4].starts_with("        ->  Index Scan using emp_pkey on emp  (cost="),
+            "{text}"
+        );
+        assert_eq!("              Range: [(3), (3)]", lines[5]);
+
+        for table in ["emp", "dept"] {
+            catalog.analyze(&bufmgr, table).unwrap();
+        }
+        let plan = plan(
+            &catalog,
+            "SELECT d.title, count(*) FROM emp e JOIN dept d ON e.dept = d.id \
+             GROUP BY d.title",
+        );
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        let text = explain_analyze(&ctx, &plan).unwrap();
+        assert!(text.contains("Join  (cost="), "{text}");
+        assert!(text.contains("Keys: "), "{text}");
+        assert!(
+            text.lines()
+                .next()
+                .unwrap()
+                .contains("(actual rows=2 loops=1 time="),
+            "{text}"
+        );
+        assert!(
+            text.contains("Seq Scan on emp  (cost=1.04 rows=4) (actual rows=4 loops=1"),
+            "{text}"
+        );
+        assert!(text.lines().last().unwrap().starts_with("Execution time: "));
+        assert!(!text.contains("never executed"));
+    }
+}
@@ -0,0 +1,757 @@
+use crate::auth::Verifier;
+use crate::catalog::{
+    IndexKey, PartitionBound, Partitioning, Persistence, PolicyInfo, Schema, SystemTable,
+    TriggerInfo, Ttl, User, ViewInfo,
+};
+use crate::collation::Collation;
+use crate::csv;
+use crate::executor::{AggregateExpr, JoinKind, OnConflict, Plan, SortKey, WindowExpr};
+use crate::expr::Expr;
+use crate::sql::ast::{AsOfPoint, Grant, Query, TransactionControl};
+use crate::value::DataType;
+
+/// An output column of a plan node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    /// `None` when every value is NULL, e.g. a bare NULL literal.
+    pub data_type: Option<DataType>,
+    /// What the column's text compares under, carried from a collated
+    /// table column through the queries that select it.
+    pub collation: Collation,
+}
+
+impl Field {
+    pub fn new(name: impl Into<String>, data_type: Option<DataType>) -> Self {
+        Self {
+            name: name.into(),
+            data_type,
+            collation: Collation::Binary,
+        }
+    }
+
+    pub fn collate(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+}
+
+/// A query tree whose names have been resolved: every column reference is
+/// a position in the node's input, as in [`Plan`], and every node knows the
+/// names and types of its output columns.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LogicalPlan {
+    Values {
+        rows: Vec<Vec<Expr>>,
+        fields: Vec<Field>,
+    },
+    Scan {
+        table: String,
+        fields: Vec<Field>,
+    },
+    SystemScan {
+        table: SystemTable,
+        fields: Vec<Field>,
+    },
+    Filter {
+        input: Box<LogicalPlan>,
+        predicate: Expr,
+    },
+    Project {
+        input: Box<LogicalPlan>,
+        exprs: Vec<Expr>,
+        fields: Vec<Field>,
+    },
+    /// Left columns followed by right columns; the predicate sees both.
+    Join {
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        kind: JoinKind,
+        predicate: Option<Expr>,
+    },
+    /// Grouping keys followed by aggregate results.
+    Aggregate {
+        input: Box<LogicalPlan>,
+        group_by: Vec<Expr>,
+        aggregates: Vec<AggregateExpr>,
+        fields: Vec<Field>,
+    },
+    /// The input columns followed by one column per function; `fields`
+    /// only describes the added columns.
+    Window {
+        input: Box<LogicalPlan>,
+        partition_by: Vec<Expr>,
+        order_by: Vec<SortKey>,
+        functions: Vec<WindowExpr>,
+        fields: Vec<Field>,
+    },
+    /// The input columns followed by one element of `array`, in a row
+    /// per element; `field` describes the added column.
+    Unnest {
+        input: Box<LogicalPlan>,
+        array: Expr,
+        field: Field,
+    },
+    Sort {
+        input: Box<LogicalPlan>,
+        keys: Vec<SortKey>,
+    },
+    Limit {
+        input: Box<LogicalPlan>,
+        limit: Option<usize>,
+        offset: usize,
+    },
+    /// The rows of each input in turn, as UNION ALL.
+    Union {
+        inputs: Vec<LogicalPlan>,
+        fields: Vec<Field>,
+    },
+    /// The rows of the CTE materialized as `id` by an enclosing
+    /// [`LogicalPlan::Materialize`], or the working table of the
+    /// [`LogicalPlan::RecursiveUnion`] `id`.
+    WorkTable {
+        id: usize,
+        fields: Vec<Field>,
+    },
+    /// Computes `cte` once, for every [`LogicalPlan::WorkTable`] `id` in
+    /// `input` to read; produces the rows of `input`.
+    Materialize {
+        id: usize,
+        cte: Box<LogicalPlan>,
+        input: Box<LogicalPlan>,
+    },
+    /// The rows of `anchor`, then those of `recursive` run again and again
+    /// with [`LogicalPlan::WorkTable`] `id` holding the rows added by the
+    /// previous round, until a round adds none. With `distinct`, rows seen
+    /// before are not added again.
+    RecursiveUnion {
+        id: usize,
+        anchor: Box<LogicalPlan>,
+        recursive: Box<LogicalPlan>,
+        distinct: bool,
+        fields: Vec<Field>,
+    },
+}
+
+impl LogicalPlan {
+    pub fn fields(&self) -> Vec<Field> {
+        match self {
+            LogicalPlan::Values { fields, .. }
+            | LogicalPlan::Scan { fields, .. }
+            | LogicalPlan::SystemScan { fields, .. }
+            | LogicalPlan::Project { fields, .. }
+            | LogicalPlan::Aggregate { fields, .. }
+            | LogicalPlan::Union { fields, .. }
+            | LogicalPlan::WorkTable { fields, .. }
+            | LogicalPlan::RecursiveUnion { fields, .. } => fields.clone(),
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. }
+            | LogicalPlan::Materialize { input, .. } => input.fields(),
+            LogicalPlan::Join { left, right, .. } => {
+                let mut fields = left.fields();
+                fields.extend(right.fields());
+                fields
+            }
+            LogicalPlan::Window { input, fields, .. } => {
+                let mut all = input.fields();
+                all.extend(fields.iter().cloned());
+                all
+            }
+            LogicalPlan::Unnest { input, field, .. } => {
+                let mut all = input.fields();
+                all.push(field.clone());
+                all
+            }
+        }
+    }
+
+    /// Number of output columns.
+    pub fn width(&self) -> usize {
+        match self {
+            LogicalPlan::Values { fields, .. }
+            | LogicalPlan::Scan { fields, .. }
+            | LogicalPlan::SystemScan { fields, .. }
+            | LogicalPlan::Project { fields, .. }
+            | LogicalPlan::Aggregate { fields, .. }
+            | LogicalPlan::Union { fields, .. }
+            | LogicalPlan::WorkTable { fields, .. }
+            | LogicalPlan::RecursiveUnion { fields, .. } => fields.len(),
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. }
+            | LogicalPlan::Materialize { input, .. } => input.width(),
+            LogicalPlan::Join { left, right, .. } => left.width() + right.width(),
+            LogicalPlan::Window { input, fields, .. } => input.width() + fields.len(),
+            LogicalPlan::Unnest { input, .. } => input.width() + 1,
+        }
+    }
+
+    /// Rebuilds the node with `f` applied to each of its inputs.
+    pub fn map_children(self, mut f: impl FnMut(LogicalPlan) -> LogicalPlan) -> LogicalPlan {
+        let mut f = |input: Box<LogicalPlan>| Box::new(f(*input));
+        match self {
+            LogicalPlan::Values { .. }
+            | LogicalPlan::Scan { .. }
+            | LogicalPlan::SystemScan { .. }
+            | LogicalPlan::WorkTable { .. } => self,
+            LogicalPlan::Filter { input, predicate } => LogicalPlan::Filter {
+                input: f(input),
+                predicate,
+            },
+            LogicalPlan::Project {
+                input,
+                exprs,
+                fields,
+            } => LogicalPlan::Project {
+                input: f(input),
+                exprs,
+                fields,
+            },
+            LogicalPlan::Join {
+                left,
+                right,
+                kind,
+                predicate,
+            } => LogicalPlan::Join {
+                left: f(left),
+                right: f(right),
+                kind,
+                predicate,
+            },
+            LogicalPlan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+                fields,
+            } => LogicalPlan::Aggregate {
+                input: f(input),
+                group_by,
+                aggregates,
+                fields,
+            },
+            LogicalPlan::Window {
+                input,
+                partition_by,
+                order_by,
+                functions,
+                fields,
+            } => LogicalPlan::Window {
+                input: f(input),
+                partition_by,
+                order_by,
+                functions,
+                fields,
+            },
+            LogicalPlan::Unnest {
+                input,
+                array,
+                field,
+            } => LogicalPlan::Unnest {
+                input: f(input),
+                array,
+                field,
+            },
+            LogicalPlan::Sort { input, keys } => LogicalPlan::Sort {
+                input: f(input),
+                keys,
+            },
+            LogicalPlan::Limit {
+                input,
+                limit,
+                offset,
+            } => LogicalPlan::Limit {
+                input: f(input),
+                limit,
+                offset,
+            },
+            LogicalPlan::Union { inputs, fields } => LogicalPlan::Union {
+                inputs: inputs
+                    .into_iter()
+                    .map(|input| *f(Box::new(input)))
+                    .collect(),
+                fields,
+            },
+            LogicalPlan::Materialize { id, cte, input } => LogicalPlan::Materialize {
+                id,
+                cte: f(cte),
+                input: f(input),
+            },
+            LogicalPlan::RecursiveUnion {
+                id,
+                anchor,
+                recursive,
+                distinct,
+                fields,
+            } => LogicalPlan::RecursiveUnion {
+                id,
+                anchor: f(anchor),
+                recursive: f(recursive),
+                distinct,
+                fields,
+            },
+        }
+    }
+
+    /// Number of [`LogicalPlan::WorkTable`] `id` nodes in the tree.
+    pub fn work_table_references(&self, id: usize) -> usize {
+        let inputs: Vec<&LogicalPlan> = match self {
+            LogicalPlan::Values { .. }
+            | LogicalPlan::Scan { .. }
+            | LogicalPlan::SystemScan { .. } => vec![],
+            LogicalPlan::WorkTable { id: other, .. } => return usize::from(*other == id),
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Project { input, .. }
+            | LogicalPlan::Aggregate { input, .. }
+            | LogicalPlan::Window { input, .. }
+            | LogicalPlan::Unnest { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. } => vec![input],
+            LogicalPlan::Join { left, right, .. } => vec![left, right],
+            LogicalPlan::Union { inputs, .. } => inputs.iter().collect(),
+            LogicalPlan::Materialize { cte, input, .. } => vec![cte, input],
+            LogicalPlan::RecursiveUnion {
+                anchor, recursive, ..
+            } => vec![anchor, recursive],
+        };
+        inputs
+            .into_iter()
+            .map(|input| input.work_table_references(id))
+            .sum()
+    }
+
+    /// Names of the tables the plan scans, in order and without repeats.
+    pub fn tables(&self) -> Vec<String> {
+        let mut tables = vec![];
+        self.collect_tables(&mut tables);
+        tables.sort();
+        tables.dedup();
+        tables
+    }
+
+    fn collect_tables(&self, tables: &mut Vec<String>) {
+        let inputs: Vec<&LogicalPlan> = match self {
+            LogicalPlan::Scan { table, .. } => {
+                tables.push(table.clone());
+                vec![]
+            }
+            LogicalPlan::Values { .. }
+            | LogicalPlan::SystemScan { .. }
+            | LogicalPlan::WorkTable { .. } => vec![],
+            LogicalPlan::Filter { input, .. }
+            | LogicalPlan::Project { input, .. }
+            | LogicalPlan::Aggregate { input, .. }
+            | LogicalPlan::Window { input, .. }
+            | LogicalPlan::Unnest { input, .. }
+            | LogicalPlan::Sort { input, .. }
+            | LogicalPlan::Limit { input, .. } => vec![input],
+            LogicalPlan::Join { left, right, .. } => vec![left, right],
+            LogicalPlan::Union { inputs, .. } => inputs.iter().collect(),
+            LogicalPlan::Materialize { cte, input, .. } => vec![cte, input],
+            LogicalPlan::RecursiveUnion {
+                anchor, recursive, ..
+            } => vec![anchor, recursive],
+        };
+        for input in inputs {
+            input.collect_tables(tables);
+        }
+    }
+
+    /// Puts `plan` in place of every [`LogicalPlan::WorkTable`] `id`.
+    pub fn replace_work_table(self, id: usize, plan: &LogicalPlan) -> LogicalPlan {
+        match self {
+            LogicalPlan::WorkTable { id: other, .. } if other == id => plan.clone(),
+            node => node.map_children(|input| input.replace_work_table(id, plan)),
+        }
+    }
+
+    /// Rebuilds the node with `f` applied to each of its own expressions;
+    /// inputs are left alone.
+    pub fn map_exprs(self, f: &impl Fn(&Expr) -> Expr) -> LogicalPlan {
+        let keys = |keys: Vec<SortKey>| {
+            keys.into_iter()
+                .map(|key| SortKey {
+                    expr: f(&key.expr),
+                    descending: key.descending,
+                    nulls_first: key.nulls_first,
+                })
+                .collect()
+        };
+        let all = |exprs: Vec<Expr>| exprs.iter().map(f).collect();
+        match self {
+            LogicalPlan::Values { rows, fields } => LogicalPlan::Values {
+                rows: rows.into_iter().map(all).collect(),
+                fields,
+            },
+            LogicalPlan::Scan { .. } | LogicalPlan::SystemScan { .. } => self,
+            LogicalPlan::Filter { input, predicate } => LogicalPlan::Filter {
+                input,
+                predicate: f(&predicate),
+            },
+            LogicalPlan::Project {
+                input,
+                exprs,
+                fields,
+            } => LogicalPlan::Project {
+                input,
+                exprs: all(exprs),
+                fields,
+            },
+            LogicalPlan::Join {
+                left,
+                right,
+                kind,
+                predicate,
+            } => LogicalPlan::Join {
+                left,
+                right,
+                kind,
+                predicate: predicate.as_ref().map(f),
+            },
+            LogicalPlan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+                fields,
+            } => LogicalPlan::Aggregate {
+                input,
+                group_by: all(group_by),
+                aggregates: aggregates
+                    .into_iter()
+                    .map(|aggregate| AggregateExpr {
+                        arg: aggregate.arg.as_ref().map(f),
+                        ..aggregate
+                    })
+                    .collect(),
+                fields,
+            },
+            LogicalPlan::Window {
+                input,
+                partition_by,
+                order_by,
+                functions,
+                fields,
+            } => LogicalPlan::Window {
+                input,
+                partition_by: all(partition_by),
+                order_by: keys(order_by),
+                functions: functions
+                    .into_iter()
+                    .map(|function| WindowExpr {
+                        arg: function.arg.as_ref().map(f),
+                        ..function
+                    })
+                    .collect(),
+                fields,
+            },
+            LogicalPlan::Unnest {
+                input,
+                array,
+                field,
+            } => LogicalPlan::Unnest {
+                input,
+                array: f(&array),
+                field,
+            },
+            LogicalPlan::Sort { input, keys: sort } => LogicalPlan::Sort {
+                input,
+                keys: keys(sort),
+            },
+            LogicalPlan::Limit { .. }
+            | LogicalPlan::Union { .. }
+            | LogicalPlan::WorkTable { .. }
+            | LogicalPlan::Materialize { .. }
+            | LogicalPlan::RecursiveUnion { .. } => self,
+        }
+    }
+
+    /// Translates the tree node for node into an executable plan.
+    pub fn to_plan(&self) -> Plan {
+        match self {
+            LogicalPlan::Values { rows, .. } => Plan::Values { rows: rows.clone() },
+            LogicalPlan::Scan { table, .. } => Plan::SeqScan {
+                table: table.clone(),
+            },
+            LogicalPlan::SystemScan { table, .. } => Plan::SystemScan { table: *table },
+            LogicalPlan::Filter { input, predicate } => Plan::Filter {
+                input: Box::new(input.to_plan()),
+                predicate: predicate.clone(),
+            },
+            LogicalPlan::Project { input, exprs, .. } => Plan::Project {
+                input: Box::new(input.to_plan()),
+                exprs: exprs.clone(),
+            },
+            LogicalPlan::Join {
+                left,
+                right,
+                kind,
+                predicate,
+            } => Plan::NestedLoopJoin {
+                left: Box::new(left.to_plan()),
+                right: Box::new(right.to_plan()),
+                kind: *kind,
+                predicate: predicate.clone(),
+                right_width: right.width(),
+            },
+            LogicalPlan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+                ..
+            } => Plan::Aggregate {
+                input: Box::new(input.to_plan()),
+                group_by: group_by.clone(),
+                aggregates: aggregates.clone(),
+            },
+            LogicalPlan::Window {
+                input,
+                partition_by,
+                order_by,
+                functions,
+                ..
+            } => Plan::window(
+                input.to_plan(),
+                partition_by.clone(),
+                order_by.clone(),
+                functions.clone(),
+            ),
+            LogicalPlan::Unnest { input, array, .. } => Plan::Unnest {
+                input: Box::new(input.to_plan()),
+                array: array.clone(),
+            },
+            LogicalPlan::Sort { input, keys } => Plan::Sort {
+                input: Box::new(input.to_plan()),
+                keys: keys.clone(),
+            },
+            LogicalPlan::Limit {
+                input,
+                limit,
+                offset,
+            } => Plan::Limit {
+                input: Box::new(input.to_plan()),
+                limit: *limit,
+                offset: *offset,
+            },
+            LogicalPlan::Union { inputs, .. } => Plan::Union {
+                inputs: inputs.iter().map(LogicalPlan::to_plan).collect(),
+            },
+            LogicalPlan::WorkTable { id, fields } => Plan::WorkTable {
+                id: *id,
+                width: fields.len(),
+            },
+            LogicalPlan::Materialize { id, cte, input } => Plan::Materialize {
+                id: *id,
+                cte: Box::new(cte.to_plan()),
+                input: Box::new(input.to_plan()),
+            },
+            LogicalPlan::RecursiveUnion {
+                id,
+                anchor,
+                recursive,
+                distinct,
+                ..
+            } => Plan::RecursiveUnion {
+                id: *id,
+                anchor: Box::new(anchor.to_plan()),
+                recursive: Box::new(recursive.to_plan()),
+                distinct: *distinct,
+            },
+        }
+    }
+}
+
+/// An index to create, with keys and predicate over the table's columns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexDef {
+    pub name: String,
+    pub table: String,
+    pub keys: Vec<IndexKey>,
+    pub predicate: Option<Expr>,
+    pub unique: bool,
+}
+
+/// How COPY lays out rows in its file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyFormat {
+    Csv(csv::Options),
+    /// One JSON object per line, keyed by column name.
+    Json,
+    /// Written only; see [`crate::parquet`].
+    Parquet,
+}
+
+/// A statement checked against the catalog and ready to plan.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoundStatement {
+    Query(LogicalPlan),
+    /// `query` is bound when it runs, against the catalog as of `point`.
+    AsOf {
+        query: Box<Query>,
+        point: AsOfPoint,
+    },
+    /// `source` produces full rows in table column order.
+    Insert {
+        table: String,
+        source: LogicalPlan,
+        on_conflict: Option<OnConflict>,
+    },
+    Update {
+        table: String,
+        assignments: Vec<(usize, Expr)>,
+        predicate: Option<Expr>,
+    },
+    Delete {
+        table: String,
+        predicate: Option<Expr>,
+    },
+    /// `indexes` back the table's PRIMARY KEY and UNIQUE constraints.
+    CreateTable {
+        name: String,
+        persistence: Persistence,
+        schema: Schema,
+        indexes: Vec<IndexDef>,
+        partitioning: Option<Partitioning>,
+        if_not_exists: bool,
+    },
+    /// A new table with the columns of `parent`, attached to it.
+    CreatePartition {
+        name: String,
+        parent: String,
+        bound: PartitionBound,
+        if_not_exists: bool,
+    },
+    AttachPartition {
+        table: String,
+        partition: String,
+        bound: PartitionBound,
+    },
+    DetachPartition {
+        table: String,
+        partition: String,
+    },
+    SetTtl {
+        table: String,
+        ttl: Option<Ttl>,
+    },
+    CreateIndex {
+        index: IndexDef,
+        if_not_exists: bool,
+        /// Built a batch at a time by [`crate::engine::Engine::build_indexes`].
+        concurrently: bool,
+    },
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    DropIndex {
+        name: String,
+        if_exists: bool,
+    },
+    /// `query` produces the rows of a new table with `schema`.
+    CreateMaterializedView {
+        name: String,
+        schema: Schema,
+        view: ViewInfo,
+        query: LogicalPlan,
+        if_not_exists: bool,
+    },
+    /// Replaces the rows of the view with those of `query`, its own
+    /// query bound again.
+    RefreshMaterializedView {
+        name: String,
+        query: LogicalPlan,
+    },
+    DropMaterializedView {
+        name: String,
+        if_exists: bool,
+    },
+    CreateTrigger {
+        table: String,
+        trigger: TriggerInfo,
+    },
+    DropTrigger {
+        name: String,
+        if_exists: bool,
+    },
+    CreatePolicy {
+        table: String,
+        policy: PolicyInfo,
+    },
+    DropPolicy {
+        table: String,
+        name: String,
+        if_exists: bool,
+    },
+    /// Tables whose statistics to recompute, in name order.
+    Analyze {
+        tables: Vec<String>,
+    },
+    /// Indexes to rebuild, in name order.
+    Reindex {
+        indexes: Vec<String>,
+    },
+    /// Only queries can be explained.
+    Explain {
+        analyze: bool,
+        statement: Box<BoundStatement>,
+    },
+    Transaction(TransactionControl),
+    /// As [`crate::sql::ast::Statement::Set`]; names are checked when it
+    /// runs.
+    Set {
+        name: Option<String>,
+        value: Option<String>,
+    },
+    Show {
+        name: Option<String>,
+    },
+    /// Each record of `file` holds `columns` of `table`, in that order.
+    CopyFrom {
+        table: String,
+        columns: Vec<usize>,
+        file: String,
+        format: CopyFormat,
+    },
+    CopyTo {
+        query: LogicalPlan,
+        file: String,
+        format: CopyFormat,
+    },
+    /// As [`crate::sql::ast::Statement::Attach`].
+    Attach {
+        path: String,
+        name: String,
+    },
+    Detach {
+        name: String,
+    },
+    /// As [`crate::sql::ast::Statement::Backup`].
+    Backup {
+        path: String,
+        chain: Vec<String>,
+    },
+    /// As [`crate::sql::ast::Statement::Notify`], and the two after it.
+    Notify {
+        channel: String,
+        payload: String,
+    },
+    Listen {
+        channel: String,
+    },
+    Unlisten {
+        channel: Option<String>,
+    },
+    CreateUser(User),
+    /// Passwords are already hashed; `None` leaves a setting as it is.
+    AlterUser {
+        name: String,
+        verifier: Option<Option<Verifier>>,
+        superuser: Option<bool>,
+    },
+    DropUser {
+        name: String,
+        if_exists: bool,
+    },
+    Grant(Grant),
+    Revoke(Grant),
+}
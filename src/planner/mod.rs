@@ -0,0 +1,225 @@
+//! From syntax trees to executable plans.
+//!
+//! The binder ([`bind`]) resolves the names in a parsed statement against
+//! the catalog and type-checks its expressions, producing a
+//! [`BoundStatement`] whose queries are [`LogicalPlan`]s. Anything that
+//! would only fail at run time because of what a statement says, rather
+//! than the data it meets, is reported here. The [`Optimizer`] then
+//! rewrites those plans with rules that need no statistics, and the
+//! [`PhysicalPlanner`] picks access paths, join order and join algorithms
+//! by the estimates of the [`CostModel`], within the limits set by
+//! [`PlannerSettings`].
+
+mod binder;
+mod cost;
+mod explain;
+mod logical;
+mod optimizer;
+mod physical;
+mod settings;
+#[cfg(test)]
+mod testing;
+
+use crate::auth;
+use crate::catalog::Privileges;
+use crate::executor::AggregateFunction;
+use crate::expr::{BinaryOp, ScalarFunction, UnaryOp};
+use crate::lock::LockMode;
+use crate::value::DataType;
+
+pub use binder::{bind, bind_prepared, is_builtin_function};
+pub use cost::{CostConstants, CostModel, Estimate};
+pub use explain::{explain, explain_analyze};
+pub use logical::{BoundStatement, CopyFormat, Field, IndexDef, LogicalPlan};
+pub use optimizer::{ColumnPruning, ConstantFolding, Optimizer, PredicatePushdown, Rule};
+pub use physical::PhysicalPlanner;
+pub use settings::{PlannerSettings, ScanHint};
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("table {0:?} does not exist")]
+    TableNotFound(String),
+    #[error("table {0:?} already exists")]
+    TableExists(String),
+    #[error("index {0:?} does not exist")]
+    IndexNotFound(String),
+    #[error("index {0:?} already exists")]
+    IndexExists(String),
+    #[error("a database is already attached as {0:?}")]
+    DatabaseExists(String),
+    #[error("no database is attached as {0:?}")]
+    DatabaseNotFound(String),
+    #[error("column {0:?} does not exist")]
+    ColumnNotFound(String),
+    #[error("column reference {0:?} is ambiguous")]
+    AmbiguousColumn(String),
+    #[error("missing FROM-clause entry for table {0:?}")]
+    UnknownTable(String),
+    #[error("table name {0:?} specified more than once")]
+    DuplicateTable(String),
+    #[error("column {0:?} specified more than once")]
+    DuplicateColumn(String),
+    #[error("multiple primary keys for table {0:?} are not allowed")]
+    MultiplePrimaryKeys(String),
+    #[error("column {0:?} must appear in the GROUP BY clause or be used in an aggregate function")]
+    NotGrouped(String),
+    #[error("aggregate functions are not allowed in {0}")]
+    AggregateNotAllowed(&'static str),
+    #[error("window functions are not allowed in {0}")]
+    WindowNotAllowed(&'static str),
+    #[error("{0} is not allowed with {1}")]
+    ForUpdateNotAllowed(LockMode, &'static str),
+    #[error("function {0}() does not exist")]
+    UnknownFunction(String),
+    #[error("function {func}() takes {expected} argument(s), got {actual}")]
+    ArgumentCount {
+        func: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("{0}(*) is not allowed; only COUNT(*) is")]
+    StarArgument(String),
+    #[error("window function {0}() requires an OVER clause")]
+    WindowRequiresOver(String),
+    #[error("operator {op} cannot be applied to {lhs} and {rhs}")]
+    OperatorType {
+        op: BinaryOp,
+        lhs: String,
+        rhs: String,
+    },
+    #[error("operator {op} cannot be applied to {operand}")]
+    OperandType { op: UnaryOp, operand: DataType },
+    #[error("function {func} cannot be applied to {data_type}")]
+    AggregateType {
+        func: AggregateFunction,
+        data_type: DataType,
+    },
+    #[error("function {func}() cannot be applied to {data_type}")]
+    FunctionType {
+        func: ScalarFunction,
+        data_type: DataType,
+    },
+    #[error("argument {position} of function {func}() must be of type {expected}, not {actual}")]
+    ArgumentType {
+        func: String,
+        position: usize,
+        expected: DataType,
+        actual: DataType,
+    },
+    #[error("cannot cast {from} to {to}")]
+    InvalidCast { from: DataType, to: DataType },
+    #[error("expression in {clause} must be of type BOOL, not {actual}")]
+    ClauseType {
+        clause: &'static str,
+        actual: DataType,
+    },
+    #[error("column {column:?} is of type {expected} but expression is of type {actual}")]
+    ColumnType {
+        column: String,
+        expected: DataType,
+        actual: DataType,
+    },
+    #[error("expected {expected} values, got {actual}")]
+    ColumnCountMismatch { expected: usize, actual: usize },
+    #[error("VALUES lists must all be the same length")]
+    ValuesWidth,
+    #[error("VALUES column {column} mixes {first} and {second}")]
+    ValuesType {
+        column: usize,
+        first: DataType,
+        second: DataType,
+    },
+    #[error("ARRAY elements mix {first} and {second}")]
+    ArrayType { first: DataType, second: DataType },
+    #[error("cannot determine the type of an empty array")]
+    EmptyArray,
+    #[error("argument of unnest() must be an array, not {0}")]
+    UnnestType(String),
+    #[error("collation {0:?} does not exist")]
+    UnknownCollation(String),
+    #[error("collations apply to text, not {0}")]
+    CollationType(DataType),
+    #[error("each UNION query must have the same number of columns")]
+    UnionWidth,
+    #[error("UNION column {column} mixes {first} and {second}")]
+    UnionType {
+        column: usize,
+        first: DataType,
+        second: DataType,
+    },
+    #[error("WITH query name {0:?} specified more than once")]
+    DuplicateCte(String),
+    #[error("WITH query {name:?} has {expected} columns available but {actual} columns specified")]
+    CteColumns {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+    #[error(
+        "recursive query {name:?} column {column} has type {expected} in its non-recursive term \
+         but type {actual} in its recursive term"
+    )]
+    RecursiveType {
+        name: String,
+        column: usize,
+        expected: DataType,
+        actual: DataType,
+    },
+    #[error("argument of {0} must be a non-negative integer constant")]
+    InvalidLimit(&'static str),
+    #[error("ORDER BY position {0} is not in select list")]
+    OrderByPosition(i64),
+    #[error("GROUP BY position {0} is not in select list")]
+    GroupByPosition(i64),
+    #[error("for SELECT DISTINCT, ORDER BY expressions must appear in select list")]
+    DistinctOrderBy,
+    #[error("there is no unique index matching the ON CONFLICT specification")]
+    NoConflictIndex,
+    #[error("unrecognized configuration parameter {0:?}")]
+    UnknownSetting(String),
+    #[error("invalid value for parameter {name:?}: {value:?}")]
+    InvalidSetting { name: String, value: String },
+    #[error("unrecognized COPY option {0:?}")]
+    UnknownCopyOption(String),
+    #[error("invalid value for COPY option {name:?}: {value:?}")]
+    InvalidCopyOption { name: String, value: String },
+    #[error("unrecognized hint {0:?}")]
+    UnknownHint(String),
+    #[error("invalid arguments to hint {0:?}")]
+    InvalidHint(String),
+    #[error("user {0:?} already exists")]
+    UserExists(String),
+    #[error("user {0:?} does not exist")]
+    UserNotFound(String),
+    #[error("trigger {0:?} already exists")]
+    TriggerExists(String),
+    #[error("trigger {0:?} does not exist")]
+    TriggerNotFound(String),
+    #[error("policy {name:?} for table {table:?} already exists")]
+    PolicyExists { name: String, table: String },
+    #[error("policy {name:?} for table {table:?} does not exist")]
+    PolicyNotFound { name: String, table: String },
+    #[error("permission denied for table {table:?}: {privileges} required")]
+    PermissionDenied {
+        privileges: Privileges,
+        table: String,
+    },
+    #[error("permission denied for column {column:?} of table {table:?}: SELECT required")]
+    ColumnPermissionDenied { column: String, table: String },
+    #[error("must be superuser to {0}")]
+    MustBeSuperuser(&'static str),
+    #[error("cannot change materialized view {0:?}")]
+    ViewNotWritable(String),
+    #[error("{0:?} is not a materialized view")]
+    NotAView(String),
+    #[error("{0:?} is not partitioned")]
+    NotPartitioned(String),
+    #[error("the stored query of materialized view {0:?} does not parse")]
+    InvalidView(String),
+    #[error("the action of trigger {0:?} does not parse")]
+    InvalidTrigger(String),
+    #[error("{0} is not supported")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Auth(#[from] auth::Error),
+}
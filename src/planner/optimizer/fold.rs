@@ -0,0 +1,218 @@
+//! Constant folding and boolean simplification.
+
+use super::Rule;
+use crate::expr::{BinaryOp, Expr, UnaryOp};
+use crate::planner::logical::LogicalPlan;
+use crate::value::Value;
+
+/// Evaluates constant subexpressions, simplifies AND/OR with constant
+/// operands, and drops filters that always pass or replaces those that
+/// never do with an empty input.
+pub struct ConstantFolding;
+
+impl Rule for ConstantFolding {
+    fn name(&self) -> &'static str {
+        "constant_folding"
+    }
+
+    fn apply(&self, plan: LogicalPlan) -> LogicalPlan {
+        let plan = plan
+            .map_children(|input| self.apply(input))
+            .map_exprs(&fold);
+        match plan {
+            LogicalPlan::Filter { input, predicate } => match predicate {
+                Expr::Literal(Value::Bool(true)) => *input,
+                Expr::Literal(Value::Bool(false) | Value::Null) => LogicalPlan::Values {
+                    rows: vec![],
+                    fields: input.fields(),
+                },
+                predicate => LogicalPlan::Filter { input, predicate },
+            },
+            LogicalPlan::Join {
+                left,
+                right,
+                kind,
+                predicate: Some(Expr::Literal(Value::Bool(true))),
+            } => LogicalPlan::Join {
+                left,
+                right,
+                kind,
+                predicate: None,
+            },
+            plan => plan,
+        }
+    }
+}
+
+/// Folds a row-level predicate; `None` if it always holds.
+pub(super) fn fold_predicate(predicate: Expr) -> Option<Expr> {
+    match fold(&predicate) {
+        Expr::Literal(Value::Bool(true)) => None,
+        predicate => Some(predicate),
+    }
+}
+
+pub(super) fn fold(expr: &Expr) -> Expr {
+    let folded = match expr {
+        Expr::Column(_) | Expr::Literal(_) | Expr::Parameter(_) => return expr.clone(),
+        Expr::Unary { op, expr } => match (op, fold(expr)) {
+            (
+                UnaryOp::Not,
+                Expr::Unary {
+                    op: UnaryOp::Not,
+                    expr,
+                },
+            ) => return *expr,
+            (op, expr) => Expr::unary(*op, expr),
+        },
+        Expr::Binary {
+            op: op @ (BinaryOp::And | BinaryOp::Or),
+            lhs,
+            rhs,
+        } => {
+            // FALSE decides an AND and TRUE an OR, even against NULL; the
+            // other constant leaves the result to the remaining operand.
+            let deciding = Value::Bool(*op == BinaryOp::Or);
+            let neutral = Value::Bool(*op == BinaryOp::And);
+            let (lhs, rhs) = (fold(lhs), fold(rhs));
+            match (&lhs, &rhs) {
+                (Expr::Literal(value), _) | (_, Expr::Literal(value)) if *value == deciding => {
+                    return Expr::Literal(deciding)
+                }
+                (Expr::Literal(value), _) if *value == neutral => return rhs,
+                (_, Expr::Literal(value)) if *value == neutral => return lhs,
+                _ => Expr::binary(*op, lhs, rhs),
+            }
+        }
+        Expr::Binary { op, lhs, rhs } => {
+            let (lhs, rhs) = (fold(lhs), fold(rhs));
+            // Every other operator yields NULL on a NULL operand.
+            let null = Expr::Literal(Value::Null);
+            if lhs == null || rhs == null {
+                return null;
+            }
+            Expr::binary(*op, lhs, rhs)
+        }
+        Expr::IsNull { expr, negated } => Expr::IsNull {
+            expr: Box::new(fold(expr)),
+            negated: *negated,
+        },
+        Expr::Cast { expr, data_type } => Expr::Cast {
+            expr: Box::new(fold(expr)),
+            data_type: *data_type,
+        },
+        Expr::Function { func, arg } => Expr::Function {
+            func: *func,
+            arg: Box::new(fold(arg)),
+        },
+        Expr::Call { function, args } => Expr::Call {
+            function: function.clone(),
+            args: args.iter().map(fold).collect(),
+        },
+        Expr::Array { element, items } => Expr::Array {
+            element: *element,
+            items: items.iter().map(fold).collect(),
+        },
+        Expr::Any {
+            op,
+            lhs,
+            array,
+            all,
+        } => Expr::Any {
+            op: *op,
+            lhs: Box::new(fold(lhs)),
+            array: Box::new(fold(array)),
+            all: *all,
+        },
+        Expr::Collate { expr, collation } => Expr::Collate {
+            expr: Box::new(fold(expr)),
+            collation: *collation,
+        },
+    };
+    let constant = match &folded {
+        Expr::Unary { expr, .. }
+        | Expr::IsNull { expr, .. }
+        | Expr::Cast { expr, .. }
+        | Expr::Function { arg: expr, .. }
+        | Expr::Collate { expr, .. } => matches!(**expr, Expr::Literal(_)),
+        Expr::Binary { lhs, rhs, .. }
+        | Expr::Any {
+            lhs, array: rhs, ..
+        } => matches!(**lhs, Expr::Literal(_)) && matches!(**rhs, Expr::Literal(_)),
+        Expr::Array { items, .. } => items.iter().all(|item| matches!(item, Expr::Literal(_))),
+        _ => false,
+    };
+    // Leave expressions that fail, such as 1 / 0, to fail at run time, and
+    // only if they are reached.
+    if constant {
+        if let Ok(value) = folded.eval(&[]) {
+            return Expr::Literal(value);
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::testing::{query, setup};
+
+    #[test]
+    fn test_fold() {
+        let x = Expr::column(0);
+        let lit = |value: Value| Expr::literal(value);
+        let cases = [
+            (
+                Expr::binary(
+                    BinaryOp::Lt,
+                    x.clone(),
+                    Expr::binary(BinaryOp::Mul, lit(2i64.into()), lit(3i64.into())),
+                ),
+                Expr::binary(BinaryOp::Lt, x.clone(), lit(6i64.into())),
+            ),
+            (
+                Expr::binary(BinaryOp::And, lit(true.into()), x.clone()),
+                x.clone(),
+            ),
+            (
+                Expr::binary(BinaryOp::And, x.clone(), lit(false.into())),
+                lit(false.into()),
+            ),
+            (
+                Expr::binary(BinaryOp::Or, lit(Value::Null), lit(true.into())),
+                lit(true.into()),
+            ),
+            (
+                Expr::binary(BinaryOp::Eq, x.clone(), lit(Value::Null)),
+                lit(Value::Null),
+            ),
+            (
+                Expr::unary(UnaryOp::Not, Expr::unary(UnaryOp::Not, x.clone())),
+                x.clone(),
+            ),
+            (
+                Expr::binary(BinaryOp::Div, lit(1i64.into()), lit(0i64.into())),
+                Expr::binary(BinaryOp::Div, lit(1i64.into()), lit(0i64.into())),
+            ),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(expected, fold(&expr), "{expr:?}");
+        }
+    }
+
+    #[test]
+    fn test_constant_filters() {
+        let (_bufmgr, catalog) = setup();
+        let plan = ConstantFolding.apply(query(&catalog, "SELECT id FROM emp WHERE 1 < 2"));
+        let LogicalPlan::Project { input, .. } = plan else {
+            panic!()
+        };
+        assert!(matches!(*input, LogicalPlan::Scan { .. }));
+
+        let plan = ConstantFolding.apply(query(&catalog, "SELECT id FROM emp WHERE id = NULL"));
+        let LogicalPlan::Project { input, .. } = plan else {
+            panic!()
+        };
+        assert!(matches!(*input, LogicalPlan::Values { ref rows, .. } if rows.is_empty()));
+    }
+}
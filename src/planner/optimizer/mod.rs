@@ -0,0 +1,184 @@
+//! Rewrites of logical plans that are always improvements, whatever the
+//! data looks like: no statistics are consulted.
+//!
+//! An [`Optimizer`] runs its [`Rule`]s in order, repeating the whole
+//! sequence until the plan stops changing, since one rule's output is
+//! often another's opportunity: folding a predicate may let it be pushed
+//! down, and a pushed-down filter frees columns for pruning.
+
+mod fold;
+mod prune;
+mod pushdown;
+
+use super::logical::{BoundStatement, LogicalPlan};
+use crate::expr::{BinaryOp, Expr};
+
+pub use fold::ConstantFolding;
+pub use prune::ColumnPruning;
+pub use pushdown::PredicatePushdown;
+
+/// Rules are `Send` so that an engine can move between threads.
+pub trait Rule: Send {
+    fn name(&self) -> &'static str;
+
+    /// Rewrites the whole tree. Must preserve the plan's output columns
+    /// and the rows it produces.
+    fn apply(&self, plan: LogicalPlan) -> LogicalPlan;
+}
+
+/// Upper bound on rule sequence repetitions, in case rules keep undoing
+/// each other.
+const MAX_PASSES: usize = 8;
+
+pub struct Optimizer {
+    rules: Vec<Box<dyn Rule>>,
+}
+
+impl Default for Optimizer {
+    /// Folding, then pushdown, then pruning.
+    fn default() -> Self {
+        Self::empty()
+            .with_rule(ConstantFolding)
+            .with_rule(PredicatePushdown)
+            .with_rule(ColumnPruning)
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An optimizer without rules, which leaves plans alone.
+    pub fn empty() -> Self {
+        Self { rules: vec![] }
+    }
+
+    pub fn with_rule(mut self, rule: impl Rule + 'static) -> Self {
+        self.rules.push(Box::new(rule));
+        self
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|rule| rule.name())
+    }
+
+    pub fn optimize(&self, mut plan: LogicalPlan) -> LogicalPlan {
+        for _ in 0..MAX_PASSES {
+            let before = plan.clone();
+            for rule in &self.rules {
+                plan = rule.apply(plan);
+            }
+            if plan == before {
+                break;
+            }
+        }
+        plan
+    }
+
+    /// Optimizes the plans a statement contains. Predicates of updates
+    /// and deletes are only folded.
+    pub fn optimize_statement(&self, statement: BoundStatement) -> BoundStatement {
+        match statement {
+            BoundStatement::Query(plan) => BoundStatement::Query(self.optimize(plan)),
+            BoundStatement::Insert {
+                table,
+                source,
+                on_conflict,
+            } => BoundStatement::Insert {
+                table,
+                source: self.optimize(source),
+                on_conflict,
+            },
+            BoundStatement::Update {
+                table,
+                assignments,
+                predicate,
+            } => BoundStatement::Update {
+                table,
+                assignments: assignments
+                    .into_iter()
+                    .map(|(i, expr)| (i, fold::fold(&expr)))
+                    .collect(),
+                predicate: predicate.and_then(fold::fold_predicate),
+            },
+            BoundStatement::Delete { table, predicate } => BoundStatement::Delete {
+                table,
+                predicate: predicate.and_then(fold::fold_predicate),
+            },
+            BoundStatement::Explain { analyze, statement } => BoundStatement::Explain {
+                analyze,
+                statement: Box::new(self.optimize_statement(*statement)),
+            },
+            statement => statement,
+        }
+    }
+}
+
+/// Splits a predicate into its top-level AND operands.
+pub(super) fn conjuncts(predicate: Expr) -> Vec<Expr> {
+    match predicate {
+        Expr::Binary {
+            op: BinaryOp::And,
+            lhs,
+            rhs,
+        } => {
+            let mut conjuncts = self::conjuncts(*lhs);
+            conjuncts.extend(self::conjuncts(*rhs));
+            conjuncts
+        }
+        predicate => vec![predicate],
+    }
+}
+
+/// ANDs `conjuncts` together; `None` if there are none.
+pub(super) fn conjunction(conjuncts: Vec<Expr>) -> Option<Expr> {
+    conjuncts
+        .into_iter()
+        .reduce(|lhs, rhs| Expr::binary(BinaryOp::And, lhs, rhs))
+}
+
+fn columns(expr: &Expr) -> Vec<usize> {
+    let mut columns = vec![];
+    expr.visit_columns(&mut |i| columns.push(i));
+    columns
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::testing::{execute, query, setup};
+
+    #[test]
+    fn test_optimized_plans_return_the_same_rows() {
+        let (bufmgr, catalog) = setup();
+        let queries = [
+            "SELECT e.name, d.title FROM emp e JOIN dept d ON e.dept = d.id \
+             WHERE d.title <> 'hr' AND e.salary > 10 * 5 AND 1 = 1 ORDER BY e.name",
+            "SELECT d.title, count(e.id) FROM dept d LEFT JOIN emp e \
+             ON e.dept = d.id AND e.salary > 90 GROUP BY d.title HAVING d.title <> 'x' \
+             ORDER BY 1",
+            "SELECT x.n FROM (SELECT name AS n, salary FROM emp) x \
+             WHERE x.salary < 100 OR NULL ORDER BY x.n",
+            "SELECT name, rank() OVER (ORDER BY salary) FROM emp WHERE NOT (dept IS NULL) \
+             ORDER BY 2",
+            "SELECT id FROM emp WHERE 1 > 2",
+            "WITH e AS (SELECT id, name, dept FROM emp) SELECT a.name FROM e a JOIN e b \
+             ON a.dept = b.id WHERE a.id > 1 UNION ALL SELECT title FROM dept ORDER BY 1",
+            "WITH RECURSIVE r (i, s) AS (SELECT id, salary FROM emp WHERE dept = 1 \
+             UNION SELECT i + 1, s / 2 FROM r WHERE i < 4) SELECT i FROM r WHERE s > 30 \
+             ORDER BY 1",
+        ];
+        let optimizer = Optimizer::new();
+        for sql in queries {
+            let plan = query(&catalog, sql);
+            let optimized = optimizer.optimize(plan.clone());
+            assert_eq!(plan.fields(), optimized.fields(), "{sql}");
+            assert_eq!(
+                execute(&bufmgr, &catalog, &plan),
+                execute(&bufmgr, &catalog, &optimized),
+                "{sql}"
+            );
+        }
+    }
+}
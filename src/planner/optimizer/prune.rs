@@ -0,0 +1,356 @@
+//! Dropping columns nothing above uses.
+
+use std::collections::BTreeSet;
+
+use super::{columns, Rule};
+use crate::expr::Expr;
+use crate::planner::logical::LogicalPlan;
+
+/// Removes unused projections, aggregates and window functions, and
+/// narrows the inputs of operators that buffer rows (joins, sorts and
+/// windows) to the columns still needed, so less is copied and spilled.
+pub struct ColumnPruning;
+
+impl Rule for ColumnPruning {
+    fn name(&self) -> &'static str {
+        "column_pruning"
+    }
+
+    fn apply(&self, plan: LogicalPlan) -> LogicalPlan {
+        keep_all(plan)
+    }
+}
+
+/// Prunes below `plan` without dropping any of its own columns.
+fn keep_all(plan: LogicalPlan) -> LogicalPlan {
+    let required = (0..plan.width()).collect();
+    let (plan, kept) = prune(plan, &required);
+    debug_assert_eq!(kept, (0..kept.len()).collect::<Vec<_>>());
+    plan
+}
+
+type Columns = BTreeSet<usize>;
+
+fn used_by<'e>(exprs: impl IntoIterator<Item = &'e Expr>) -> Columns {
+    exprs.into_iter().flat_map(columns).collect()
+}
+
+/// Points the column references in `expr` at their new positions, given
+/// the old position of each new column.
+fn remap(expr: &Expr, kept: &[usize]) -> Expr {
+    expr.map_columns(&|i| kept.iter().position(|&k| k == i).unwrap())
+}
+
+/// Rewrites `plan` to produce at least the `required` columns. Returns the
+/// new plan along with the old position of each of its output columns,
+/// which are in their original order.
+fn prune(plan: LogicalPlan, required: &Columns) -> (LogicalPlan, Vec<usize>) {
+    match plan {
+        LogicalPlan::Values { rows, fields } => {
+            let rows = rows
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .enumerate()
+                        .filter(|(i, _)| required.contains(i))
+                        .map(|(_, expr)| expr)
+                        .collect()
+                })
+                .collect();
+            let fields = fields
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| required.contains(i))
+                .map(|(_, field)| field)
+                .collect();
+            (
+                LogicalPlan::Values { rows, fields },
+                required.iter().copied().collect(),
+            )
+        }
+        // Every reader of a work table sees the same rows, so neither the
+        // table nor what produces its rows can lose columns.
+        LogicalPlan::Scan { .. }
+        | LogicalPlan::SystemScan { .. }
+        | LogicalPlan::WorkTable { .. } => {
+            let width = plan.width();
+            (plan, (0..width).collect())
+        }
+        LogicalPlan::Union { .. } | LogicalPlan::RecursiveUnion { .. } => {
+            let width = plan.width();
+            (plan.map_children(keep_all), (0..width).collect())
+        }
+        LogicalPlan::Materialize { id, cte, input } => {
+            let (input, kept) = prune(*input, required);
+            let materialize = LogicalPlan::Materialize {
+                id,
+                cte: Box::new(keep_all(*cte)),
+                input: Box::new(input),
+            };
+            (materialize, kept)
+        }
+        LogicalPlan::Filter { input, predicate } => {
+            let mut needed = required.clone();
+            needed.extend(columns(&predicate));
+            let (input, kept) = prune(*input, &needed);
+            let filter = LogicalPlan::Filter {
+                input: Box::new(input),
+                predicate: remap(&predicate, &kept),
+            };
+            (filter, kept)
+        }
+        LogicalPlan::Project {
+            input,
+            exprs,
+            fields,
+        } => {
+            let (exprs, fields): (Vec<_>, Vec<_>) = exprs
+                .into_iter()
+                .zip(fields)
+                .enumerate()
+                .filter(|(i, _)| required.contains(i))
+                .map(|(_, pair)| pair)
+                .unzip();
+            let (input, kept) = prune(*input, &used_by(&exprs));
+            let project = LogicalPlan::Project {
+                input: Box::new(input),
+                exprs: exprs.iter().map(|expr| remap(expr, &kept)).collect(),
+                fields,
+            };
+            (project, required.iter().copied().collect())
+        }
+        LogicalPlan::Join {
+            left,
+            right,
+            kind,
+            predicate,
+        } => {
+            let width = left.width();
+            let mut needed = required.clone();
+            needed.extend(predicate.iter().flat_map(columns));
+            let left_needed = needed.iter().copied().filter(|&i| i < width).collect();
+            let right_needed = needed
+                .iter()
+                .filter(|&&i| i >= width)
+                .map(|i| i - width)
+                .collect();
+            let (left, left_kept) = narrow(*left, &left_needed);
+            let (right, right_kept) = narrow(*right, &right_needed);
+            let mut kept = left_kept;
+            kept.extend(right_kept.into_iter().map(|i| i + width));
+            let join = LogicalPlan::Join {
+                left: Box::new(left),
+                right: Box::new(right),
+                kind,
+                predicate: predicate.map(|predicate| remap(&predicate, &kept)),
+            };
+            (join, kept)
+        }
+        LogicalPlan::Aggregate {
+            input,
+            group_by,
+            aggregates,
+            fields,
+        } => {
+            // Every grouping key stays: it defines the groups.
+            let keys = group_by.len();
+            let mut fields = fields.into_iter();
+            let key_fields: Vec<_> = fields.by_ref().take(keys).collect();
+            let (aggregates, aggregate_fields): (Vec<_>, Vec<_>) = aggregates
+                .into_iter()
+                .zip(fields)
+                .enumerate()
+                .filter(|(j, _)| required.contains(&(keys + j)))
+                .map(|(_, pair)| pair)
+                .unzip();
+            let mut kept: Vec<usize> = (0..keys).collect();
+            kept.extend(required.iter().copied().filter(|&i| i >= keys));
+            let mut needed = used_by(&group_by);
+            needed.extend(used_by(aggregates.iter().flat_map(|a| &a.arg)));
+            let (input, input_kept) = prune(*input, &needed);
+            let aggregate = LogicalPlan::Aggregate {
+                input: Box::new(input),
+                group_by: group_by.iter().map(|e| remap(e, &input_kept)).collect(),
+                aggregates: aggregates
+                    .into_iter()
+                    .map(|mut aggregate| {
+                        aggregate.arg = aggregate.arg.map(|arg| remap(&arg, &input_kept));
+                        aggregate
+                    })
+                    .collect(),
+                fields: key_fields.into_iter().chain(aggregate_fields).collect(),
+            };
+            (aggregate, kept)
+        }
+        LogicalPlan::Window {
+            input,
+            partition_by,
+            order_by,
+            functions,
+            fields,
+        } => {
+            let width = input.width();
+            let (functions, fields): (Vec<_>, Vec<_>) = functions
+                .into_iter()
+                .zip(fields)
+                .enumerate()
+                .filter(|(k, _)| required.contains(&(width + k)))
+                .map(|(_, pair)| pair)
+                .unzip();
+            let passed: Columns = required.iter().copied().filter(|&i| i < width).collect();
+            if functions.is_empty() {
+                return prune(*input, &passed);
+            }
+            let added: Vec<usize> = required.iter().copied().filter(|&i| i >= width).collect();
+            let mut needed = passed;
+            needed.extend(used_by(&partition_by));
+            needed.extend(used_by(order_by.iter().map(|key| &key.expr)));
+            needed.extend(used_by(functions.iter().flat_map(|f| &f.arg)));
+            let (input, mut kept) = narrow(*input, &needed);
+            let window = LogicalPlan::Window {
+                input: Box::new(input),
+                partition_by: partition_by.iter().map(|e| remap(e, &kept)).collect(),
+                order_by: order_by
+                    .into_iter()
+                    .map(|mut key| {
+                        key.expr = remap(&key.expr, &kept);
+                        key
+                    })
+                    .collect(),
+                functions: functions
+                    .into_iter()
+                    .map(|mut function| {
+                        function.arg = function.arg.map(|arg| remap(&arg, &kept));
+                        function
+                    })
+                    .collect(),
+                fields,
+            };
+            kept.extend(added);
+            (window, kept)
+        }
+        // The element column stays even if unused: it sets the row count.
+        LogicalPlan::Unnest {
+            input,
+            array,
+            field,
+        } => {
+            let width = input.width();
+            let mut needed: Columns = required.iter().copied().filter(|&i| i < width).collect();
+            needed.extend(columns(&array));
+            let (input, mut kept) = narrow(*input, &needed);
+            let unnest = LogicalPlan::Unnest {
+                input: Box::new(input),
+                array: remap(&array, &kept),
+                field,
+            };
+            kept.push(width);
+            (unnest, kept)
+        }
+        LogicalPlan::Sort { input, keys } => {
+            let mut needed = required.clone();
+            needed.extend(used_by(keys.iter().map(|key| &key.expr)));
+            let (input, kept) = narrow(*input, &needed);
+            let sort = LogicalPlan::Sort {
+                input: Box::new(input),
+                keys: keys
+                    .into_iter()
+                    .map(|mut key| {
+                        key.expr = remap(&key.expr, &kept);
+                        key
+                    })
+                    .collect(),
+            };
+            (sort, kept)
+        }
+        LogicalPlan::Limit {
+            input,
+            limit,
+            offset,
+        } => {
+            let (input, kept) = prune(*input, required);
+            let limit = LogicalPlan::Limit {
+                input: Box::new(input),
+                limit,
+                offset,
+            };
+            (limit, kept)
+        }
+    }
+}
+
+/// Like [`prune`], but projects away whatever `input` keeps beyond
+/// `needed`, for operators that hold on to their input rows.
+fn narrow(input: LogicalPlan, needed: &Columns) -> (LogicalPlan, Vec<usize>) {
+    let (input, kept) = prune(input, needed);
+    if kept.len() == needed.len() {
+        return (input, kept);
+    }
+    let fields = input.fields();
+    let positions: Vec<usize> = needed
+        .iter()
+        .map(|i| kept.iter().position(|k| k == i).unwrap())
+        .collect();
+    let project = LogicalPlan::Project {
+        input: Box::new(input),
+        exprs: positions.iter().map(|&p| Expr::column(p)).collect(),
+        fields: positions.iter().map(|&p| fields[p].clone()).collect(),
+    };
+    (project, needed.iter().copied().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::testing::{query, setup};
+
+    #[test]
+    fn test_prune() {
+        let (_bufmgr, catalog) = setup();
+        // Only `name` and `dept` of emp and `id` of dept reach the join.
+        let plan = ColumnPruning.apply(query(
+            &catalog,
+            "SELECT e.name FROM emp e JOIN dept d ON e.dept = d.id",
+        ));
+        let LogicalPlan::Project { input, exprs, .. } = plan else {
+            panic!()
+        };
+        assert_eq!(vec![Expr::column(0)], exprs);
+        let LogicalPlan::Join {
+            left,
+            right,
+            predicate,
+            ..
+        } = *input
+        else {
+            panic!()
+        };
+        assert_eq!(2, left.width());
+        assert_eq!(1, right.width());
+        assert_eq!(
+            Some(Expr::binary(
+                crate::expr::BinaryOp::Eq,
+                Expr::column(1),
+                Expr::column(2)
+            )),
+            predicate
+        );
+
+        // Unused aggregates and window functions disappear.
+        let plan = ColumnPruning.apply(query(
+            &catalog,
+            "SELECT s.dept FROM (SELECT dept, sum(salary), count(*), \
+             rank() OVER (ORDER BY dept) FROM emp GROUP BY dept) s",
+        ));
+        let LogicalPlan::Project { input, .. } = plan else {
+            panic!()
+        };
+        let LogicalPlan::Project { input, .. } = *input else {
+            panic!()
+        };
+        let LogicalPlan::Aggregate { aggregates, .. } = *input else {
+            panic!("{input:?}")
+        };
+        assert!(aggregates.is_empty());
+    }
+}
@@ -0,0 +1,273 @@
+//! Moving filters towards the scans they apply to.
+
+use super::{columns, conjunction, conjuncts, Rule};
+use crate::executor::JoinKind;
+use crate::expr::Expr;
+use crate::planner::logical::LogicalPlan;
+
+/// Pushes each AND operand of a filter as far down the tree as it can go
+/// without changing results: into the join input whose columns it uses,
+/// below projections and sorts, and below aggregations when it only uses
+/// grouping keys. Join conditions that only use one input move into that
+/// input too, where the join kind allows it.
+pub struct PredicatePushdown;
+
+impl Rule for PredicatePushdown {
+    fn name(&self) -> &'static str {
+        "predicate_pushdown"
+    }
+
+    fn apply(&self, plan: LogicalPlan) -> LogicalPlan {
+        push(plan)
+    }
+}
+
+fn push(plan: LogicalPlan) -> LogicalPlan {
+    match plan {
+        LogicalPlan::Filter { input, predicate } => push_filter(*input, conjuncts(predicate)),
+        LogicalPlan::Join {
+            left,
+            right,
+            kind,
+            predicate,
+        } => push_join(
+            *left,
+            *right,
+            kind,
+            predicate.map(conjuncts).unwrap_or_default(),
+        ),
+        plan => plan.map_children(push),
+    }
+}
+
+fn filter(input: LogicalPlan, conjuncts: Vec<Expr>) -> LogicalPlan {
+    match conjunction(conjuncts) {
+        Some(predicate) => LogicalPlan::Filter {
+            input: Box::new(input),
+            predicate,
+        },
+        None => input,
+    }
+}
+
+/// Places `conjuncts`, which filter the output of `input`, as low as they go.
+fn push_filter(input: LogicalPlan, mut conjuncts: Vec<Expr>) -> LogicalPlan {
+    match input {
+        LogicalPlan::Filter { input, predicate } => {
+            conjuncts.extend(super::conjuncts(predicate));
+            push_filter(*input, conjuncts)
+        }
+        LogicalPlan::Project {
+            input,
+            exprs,
+            fields,
+        } => {
+            // Only through plain columns and constants, so that nothing is
+            // computed twice.
+            let (below, above): (Vec<_>, Vec<_>) = conjuncts.into_iter().partition(|conjunct| {
+                columns(conjunct)
+                    .into_iter()
+                    .all(|i| matches!(exprs[i], Expr::Column(_) | Expr::Literal(_)))
+            });
+            let below = below
+                .iter()
+                .map(|conjunct| conjunct.replace_columns(&|i| exprs[i].clone()))
+                .collect();
+            let project = LogicalPlan::Project {
+                input: Box::new(push_filter(*input, below)),
+                exprs,
+                fields,
+            };
+            filter(project, above)
+        }
+        LogicalPlan::Join {
+            left,
+            right,
+            kind,
+            predicate,
+        } => {
+            let mut on = predicate.map(super::conjuncts).unwrap_or_default();
+            match kind {
+                JoinKind::Inner => {
+                    on.extend(conjuncts);
+                    push_join(*left, *right, kind, on)
+                }
+                // Filtering the padded rows of a left join is not the same
+                // as joining fewer rows, so only conditions on the left
+                // input go down.
+                JoinKind::Left => {
+                    let width = left.width();
+                    let (lefts, above): (Vec<_>, Vec<_>) = conjuncts
+                        .into_iter()
+                        .partition(|conjunct| columns(conjunct).into_iter().all(|i| i < width));
+                    filter(push_join(filter(*left, lefts), *right, kind, on), above)
+                }
+            }
+        }
+        LogicalPlan::Sort { input, keys } => LogicalPlan::Sort {
+            input: Box::new(push_filter(*input, conjuncts)),
+            keys,
+        },
+        // Conditions on the input rows hold for every element paired with
+        // them.
+        LogicalPlan::Unnest {
+            input,
+            array,
+            field,
+        } => {
+            let width = input.width();
+            let (below, above): (Vec<_>, Vec<_>) = conjuncts
+                .into_iter()
+                .partition(|conjunct| columns(conjunct).into_iter().all(|i| i < width));
+            let unnest = LogicalPlan::Unnest {
+                input: Box::new(push_filter(*input, below)),
+                array,
+                field,
+            };
+            filter(unnest, above)
+        }
+        // A filter on grouping keys removes whole groups, which is the same
+        // as removing their rows first. Without keys there is always one
+        // group, even over no rows, so nothing may move.
+        LogicalPlan::Aggregate {
+            input,
+            group_by,
+            aggregates,
+            fields,
+        } if !group_by.is_empty() => {
+            let keys = group_by.len();
+            let (below, above): (Vec<_>, Vec<_>) = conjuncts
+                .into_iter()
+                .partition(|conjunct| columns(conjunct).into_iter().all(|i| i < keys));
+            let below = below
+                .iter()
+                .map(|conjunct| conjunct.replace_columns(&|i| group_by[i].clone()))
+                .collect();
+            let aggregate = LogicalPlan::Aggregate {
+                input: Box::new(push_filter(*input, below)),
+                group_by,
+                aggregates,
+                fields,
+            };
+            filter(aggregate, above)
+        }
+        input => filter(push(input), conjuncts),
+    }
+}
+
+fn push_join(left: LogicalPlan, right: LogicalPlan, kind: JoinKind, on: Vec<Expr>) -> LogicalPlan {
+    let width = left.width();
+    let (mut lefts, mut rights, mut rest) = (vec![], vec![], vec![]);
+    for conjunct in on {
+        let columns = columns(&conjunct);
+        if kind == JoinKind::Inner && columns.iter().all(|&i| i < width) {
+            lefts.push(conjunct);
+        } else if !columns.is_empty() && columns.iter().all(|&i| i >= width) {
+            rights.push(conjunct.map_columns(&|i| i - width));
+        } else {
+            rest.push(conjunct);
+        }
+    }
+    LogicalPlan::Join {
+        left: Box::new(push(filter(left, lefts))),
+        right: Box::new(push(filter(right, rights))),
+        kind,
+        predicate: conjunction(rest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expr::BinaryOp;
+    use crate::planner::testing::{query, setup};
+
+    #[test]
+    fn test_pushdown_through_joins() {
+        let (_bufmgr, catalog) = setup();
+        let plan = PredicatePushdown.apply(query(
+            &catalog,
+            "SELECT * FROM emp e JOIN dept d ON e.dept = d.id \
+             WHERE e.salary > 90 AND d.title = 'eng' AND e.id + d.id > 2",
+        ));
+        let LogicalPlan::Project { input, .. } = plan else {
+            panic!()
+        };
+        let LogicalPlan::Join {
+            left,
+            right,
+            predicate,
+            ..
+        } = *input
+        else {
+            panic!("{input:?}")
+        };
+        assert!(matches!(*left, LogicalPlan::Filter { .. }));
+        let LogicalPlan::Filter {
+            predicate: title, ..
+        } = *right
+        else {
+            panic!()
+        };
+        // `d.title` is column 1 of the right input.
+        assert_eq!(
+            Expr::binary(BinaryOp::Eq, Expr::column(1), Expr::literal("eng")),
+            title
+        );
+        assert_eq!(2, conjuncts(predicate.unwrap()).len());
+
+        // WHERE conditions on the padded side of a left join stay put.
+        let plan = PredicatePushdown.apply(query(
+            &catalog,
+            "SELECT * FROM emp e LEFT JOIN dept d ON e.dept = d.id AND d.id > 1 \
+             WHERE d.title IS NULL AND e.id > 1",
+        ));
+        let LogicalPlan::Project { input, .. } = plan else {
+            panic!()
+        };
+        let LogicalPlan::Filter { input, .. } = *input else {
+            panic!("{input:?}")
+        };
+        let LogicalPlan::Join {
+            left,
+            right,
+            predicate,
+            ..
+        } = *input
+        else {
+            panic!()
+        };
+        assert!(matches!(*left, LogicalPlan::Filter { .. }));
+        assert!(matches!(*right, LogicalPlan::Filter { .. }));
+        assert_eq!(1, conjuncts(predicate.unwrap()).len());
+    }
+
+    #[test]
+    fn test_pushdown_through_aggregates() {
+        let (_bufmgr, catalog) = setup();
+        let plan = PredicatePushdown.apply(query(
+            &catalog,
+            "SELECT dept, count(*) FROM emp GROUP BY dept HAVING dept > 1 AND count(*) > 1",
+        ));
+        let LogicalPlan::Project { input, .. } = plan else {
+            panic!()
+        };
+        let LogicalPlan::Filter { input, predicate } = *input else {
+            panic!()
+        };
+        assert_eq!(
+            Expr::binary(BinaryOp::Gt, Expr::column(1), Expr::literal(1i64)),
+            predicate
+        );
+        let LogicalPlan::Aggregate { input, .. } = *input else {
+            panic!()
+        };
+        let LogicalPlan::Filter { predicate, .. } = *input else {
+            panic!()
+        };
+        assert_eq!(
+            Expr::binary(BinaryOp::Gt, Expr::column(2), Expr::literal(1i64)),
+            predicate
+        );
+    }
+}
@@ -0,0 +1,1022 @@
+//! Choosing how to execute a logical plan.
+//!
+//! Unlike [`LogicalPlan::to_plan`], which translates node for node, the
+//! [`PhysicalPlanner`] weighs alternatives with the [`CostModel`]: a
+//! sequential or an index scan for each filtered table, the order in which
+//! a tree of inner joins is evaluated, and the algorithm of each join. A
+//! partitioned table is read through those of its partitions that the
+//! predicate on it does not rule out.
+//! Choices that [`PlannerSettings`] disable are charged a prohibitive
+//! cost, so they are only made when there is no alternative.
+
+use std::ops::Bound;
+
+use super::cost::CostModel;
+use super::logical::LogicalPlan;
+use super::optimizer::{conjunction, conjuncts};
+use super::settings::PlannerSettings;
+use crate::catalog::{Catalog, IndexInfo, IndexKey};
+use crate::executor::{AccessPath, JoinKind, KeyRange, Plan, SortKey};
+use crate::expr::{BinaryOp, Expr};
+use crate::value::DataType;
+
+/// Join trees with more relations than this keep their written order,
+/// since every ordering of them is considered otherwise.
+const MAX_REORDERED_RELATIONS: usize = 8;
+
+/// Added to the cost of a plan for each disabled choice in it.
+const DISABLE_COST: f64 = 1.0e10;
+
+/// A physical plan along with the types of its output columns.
+#[derive(Clone)]
+struct Input {
+    plan: Plan,
+    types: Vec<Option<DataType>>,
+}
+
+/// A join of some of the relations of a join tree. `layout` gives the
+/// position in the tree's output of each column.
+#[derive(Clone)]
+struct Joined {
+    input: Input,
+    layout: Vec<usize>,
+    cost: f64,
+}
+
+/// A condition of a join tree, with the set of relations it references.
+struct Conjunct {
+    expr: Expr,
+    relations: usize,
+}
+
+pub struct PhysicalPlanner<'a> {
+    catalog: &'a Catalog,
+    cost: CostModel<'a>,
+    settings: PlannerSettings,
+    /// Types of the parameters the plan will be executed with.
+    parameters: &'a [Option<DataType>],
+}
+
+impl<'a> PhysicalPlanner<'a> {
+    pub fn new(catalog: &'a Catalog) -> Self {
+        Self {
+            catalog,
+            cost: CostModel::new(catalog),
+            settings: PlannerSettings::default(),
+            parameters: &[],
+        }
+    }
+
+    pub fn with_settings(self, settings: PlannerSettings) -> Self {
+        Self {
+            cost: CostModel::new(self.catalog).with_costs(settings.costs),
+            settings,
+            ..self
+        }
+    }
+
+    /// Lets index scans use parameters of these types as keys, for a plan
+    /// prepared once and executed with different values. Parameters of
+    /// unknown type are left to filters.
+    pub fn with_parameters(self, parameters: &'a [Option<DataType>]) -> Self {
+        Self { parameters, ..self }
+    }
+
+    pub fn plan(&self, logical: &LogicalPlan) -> Plan {
+        let plan = |input: &LogicalPlan| Box::new(self.plan(input));
+        match logical {
+            LogicalPlan::Scan { table, .. } => self.scan(table, None),
+            LogicalPlan::SystemScan { table, .. } => Plan::SystemScan { table: *table },
+            LogicalPlan::Filter { input, predicate } => match input.as_ref() {
+                LogicalPlan::Scan { table, .. } => self.scan(table, Some(predicate)),
+                input => Plan::Filter {
+                    input: plan(input),
+                    predicate: predicate.clone(),
+                },
+            },
+            LogicalPlan::Join {
+                kind: JoinKind::Inner,
+                ..
+            } => self.join_tree(logical),
+            LogicalPlan::Join {
+                left,
+                right,
+                kind,
+                predicate,
+            } => self.join(
+                &self.input(left),
+                &self.input(right),
+                *kind,
+                predicate.clone(),
+            ),
+            LogicalPlan::Project { input, exprs, .. } => Plan::Project {
+                input: plan(input),
+                exprs: exprs.clone(),
+            },
+            LogicalPlan::Aggregate {
+                input,
+                group_by,
+                aggregates,
+                ..
+            } => Plan::Aggregate {
+                input: plan(input),
+                group_by: group_by.clone(),
+                aggregates: aggregates.clone(),
+            },
+            LogicalPlan::Window {
+                input,
+                partition_by,
+                order_by,
+                functions,
+                ..
+            } => Plan::window(
+                self.plan(input),
+                partition_by.clone(),
+                order_by.clone(),
+                functions.clone(),
+            ),
+            LogicalPlan::Unnest { input, array, .. } => Plan::Unnest {
+                input: plan(input),
+                array: array.clone(),
+            },
+            LogicalPlan::Sort { input, keys } => self.sort(input, keys),
+            LogicalPlan::Limit {
+                input,
+                limit,
+                offset,
+            } => Plan::Limit {
+                input: plan(input),
+                limit: *limit,
+                offset: *offset,
+            },
+            LogicalPlan::Values { .. } | LogicalPlan::WorkTable { .. } => logical.to_plan(),
+            LogicalPlan::Union { inputs, .. } => Plan::Union {
+                inputs: inputs.iter().map(|input| self.plan(input)).collect(),
+            },
+            LogicalPlan::Materialize { id, cte, input } => {
+                let cte = self.plan(cte);
+                self.cost
+                    .set_work_table_rows(*id, self.cost.estimate(&cte).rows);
+                Plan::Materialize {
+                    id: *id,
+                    cte: Box::new(cte),
+                    input: plan(input),
+                }
+            }
+            LogicalPlan::RecursiveUnion {
+                id,
+                anchor,
+                recursive,
+                distinct,
+                ..
+            } => {
+                let anchor = self.plan(anchor);
+                self.cost
+                    .set_work_table_rows(*id, self.cost.estimate(&anchor).rows);
+                Plan::RecursiveUnion {
+                    id: *id,
+                    anchor: Box::new(anchor),
+                    recursive: plan(recursive),
+                    distinct: *distinct,
+                }
+            }
+        }
+    }
+
+    /// The cheapest way for an update or delete to find the rows of
+    /// `table` matching `predicate`. The partitions of a partitioned
+    /// table are read whole.
+    pub fn access_path(&self, table: &str, predicate: Option<&Expr>) -> AccessPath {
+        if self
+            .catalog
+            .table(table)
+            .is_some_and(|info| info.partitioning.is_some())
+        {
+            return AccessPath::SeqScan;
+        }
+        let scan = match self.scan(table, predicate) {
+            Plan::Filter { input, .. } => *input,
+            scan => scan,
+        };
+        match scan {
+            Plan::IndexScan { index, range, .. } => AccessPath::IndexScan { index, range },
+            _ => AccessPath::SeqScan,
+        }
+    }
+
+    fn input(&self, logical: &LogicalPlan) -> Input {
+        Input {
+            plan: self.plan(logical),
+            types: logical
+                .fields()
+                .into_iter()
+                .map(|field| field.data_type)
+                .collect(),
+        }
+    }
+
+    /// The estimated cost of `plan`, with the penalty for what it does
+    /// that the settings disable.
+    fn cost(&self, plan: &Plan) -> f64 {
+        self.cost.estimate(plan).cost + self.disabled(plan) as f64 * DISABLE_COST
+    }
+
+    /// Number of nodes in `plan` that the settings disable.
+    fn disabled(&self, plan: &Plan) -> usize {
+        let settings = &self.settings;
+        let disabled = match plan {
+            Plan::SeqScan { table } | Plan::ParallelSeqScan { table, .. } => {
+                !settings.seq_scan_allowed(table)
+            }
+            Plan::IndexScan { table, index, .. } => !settings.index_scan_allowed(table, index),
+            Plan::NestedLoopJoin { .. } => !settings.enable_nestloop,
+            Plan::HashJoin { .. } => !settings.enable_hashjoin,
+            Plan::MergeJoin { .. } => !settings.enable_mergejoin,
+            _ => false,
+        };
+        let inputs = plan.inputs().into_iter().map(|input| self.disabled(input));
+        usize::from(disabled) + inputs.sum::<usize>()
+    }
+
+    fn cheapest(&self, candidates: impl IntoIterator<Item = Plan>) -> Plan {
+        candidates
+            .into_iter()
+            .map(|plan| (self.cost(&plan), plan))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, plan)| plan)
+            .expect("no candidate plans")
+    }
+
+    /// Reads `table` through whichever access path is cheapest, then
+    /// filters with the whole predicate: index ranges only narrow the rows
+    /// it is checked against. An index that the predicate does not narrow
+    /// is read whole only when sequential scans are disabled.
+    fn scan(&self, table: &str, predicate: Option<&Expr>) -> Plan {
+        let filter = |scan| match predicate {
+            Some(predicate) => Plan::Filter {
+                input: Box::new(scan),
+                predicate: predicate.clone(),
+            },
+            None => scan,
+        };
+        let seq_scan = filter(Plan::SeqScan {
+            table: table.to_string(),
+        });
+        let Some(info) = self.catalog.table(table) else {
+            return seq_scan;
+        };
+        if let Some(partitioning) = info.partitioning {
+            return self.partitioned_scan(table, partitioning.column, predicate);
+        }
+        let conjuncts = predicate.cloned().map(conjuncts).unwrap_or_default();
+        let full_scans = !self.settings.seq_scan_allowed(table);
+        let index_scans = info.indexes.iter().filter_map(|index| {
+            if !implies(&conjuncts, index.predicate.as_ref()) {
+                return None;
+            }
+            let range = index_range(index, &conjuncts, self.parameters)
+                .or_else(|| full_scans.then(KeyRange::full))?;
+            Some(filter(Plan::IndexScan {
+                table: table.to_string(),
+                index: index.name.clone(),
+                range,
+            }))
+        });
+        self.cheapest(std::iter::once(seq_scan).chain(index_scans))
+    }
+
+    /// Reads the partitions of `table` that `predicate` leaves room for,
+    /// each as [`PhysicalPlanner::scan`] would, with `column` the key.
+    fn partitioned_scan(&self, table: &str, column: usize, predicate: Option<&Expr>) -> Plan {
+        let mut inputs: Vec<Plan> = (self.catalog.partitions(table).into_iter())
+            .filter(|partition| {
+                let bound = &partition.partition_of.as_ref().unwrap().bound;
+                predicate.is_none_or(|predicate| bound.admits(column, predicate))
+            })
+            .map(|partition| self.scan(&partition.name, predicate))
+            .collect();
+        match inputs.len() {
+            0 => Plan::Values { rows: vec![] },
+            1 => inputs.pop().unwrap(),
+            _ => Plan::Union { inputs },
+        }
+    }
+
+    /// Picks the cheapest algorithm for joining `left` to `right` on
+    /// `predicate`, which sees both sides' columns.
+    fn join(&self, left: &Input, right: &Input, kind: JoinKind, predicate: Option<Expr>) -> Plan {
+        let width = left.types.len();
+        let right_width = right.types.len();
+        let nested_loop = Plan::NestedLoopJoin {
+            left: Box::new(left.plan.clone()),
+            right: Box::new(right.plan.clone()),
+            kind,
+            predicate: predicate.clone(),
+            right_width,
+        };
+        let (mut left_keys, mut right_keys, mut residual) = (vec![], vec![], vec![]);
+        for conjunct in predicate.map(conjuncts).unwrap_or_default() {
+            if let Expr::Binary {
+                op: BinaryOp::Eq,
+                lhs,
+                rhs,
+            } = &conjunct
+            {
+                if let (&Expr::Column(a), &Expr::Column(b)) = (lhs.as_ref(), rhs.as_ref()) {
+                    let (a, b) = (a.min(b), a.max(b));
+                    let comparable = |a: usize, b: usize| {
+                        let (lhs, rhs) = (left.types[a], right.types[b]);
+                        lhs.is_some() && lhs == rhs
+                    };
+                    if a < width && b >= width && comparable(a, b - width) {
+                        left_keys.push(Expr::Column(a));
+                        right_keys.push(Expr::Column(b - width));
+                        continue;
+                    }
+                }
+            }
+            residual.push(conjunct);
+        }
+        if left_keys.is_empty() {
+            return nested_loop;
+        }
+        let residual = conjunction(residual);
+        let hash = Plan::HashJoin {
+            left: Box::new(left.plan.clone()),
+            right: Box::new(right.plan.clone()),
+            kind,
+            left_keys: left_keys.clone(),
+            right_keys: right_keys.clone(),
+            predicate: residual.clone(),
+            right_width,
+        };
+        let merge = Plan::MergeJoin {
+            left: Box::new(self.sorted(left.plan.clone(), &left_keys)),
+            right: Box::new(self.sorted(right.plan.clone(), &right_keys)),
+            kind,
+            left_keys,
+            right_keys,
+            predicate: residual,
+            right_width,
+        };
+        self.cheapest([nested_loop, hash, merge])
+    }
+
+    /// Sorts the rows of `input` on `keys`, or reads them in that order
+    /// from an index of the table under its projection where that is
+    /// cheaper. An index can stand in for keys that are ascending, are
+    /// its leading keys once projected and put NULLs where its own do.
+    fn sort(&self, input: &LogicalPlan, keys: &[SortKey]) -> Plan {
+        let sort = Plan::Sort {
+            input: Box::new(self.plan(input)),
+            keys: keys.to_vec(),
+        };
+        let LogicalPlan::Project { input, exprs, .. } = input else {
+            return sort;
+        };
+        let (table, predicate) = match input.as_ref() {
+            LogicalPlan::Scan { table, .. } => (table, None),
+            LogicalPlan::Filter { input, predicate } => match input.as_ref() {
+                LogicalPlan::Scan { table, .. } => (table, Some(predicate)),
+                _ => return sort,
+            },
+            _ => return sort,
+        };
+        let Some(info) = self.catalog.table(table) else {
+            return sort;
+        };
+        if info.partitioning.is_some() {
+            return sort;
+        }
+        let conjuncts = predicate.cloned().map(conjuncts).unwrap_or_default();
+        let in_order = |index: &IndexInfo| {
+            keys.len() <= index.keys.len()
+                && keys.iter().zip(&index.keys).all(|(key, index_key)| {
+                    !key.descending
+                        && key.nulls_first == index_key.nulls_first
+                        && key.expr.replace_columns(&|i| exprs[i].clone()) == index_key.expr
+                })
+        };
+        let index_scans = (info.indexes.iter())
+            .filter(|index| in_order(index) && implies(&conjuncts, index.predicate.as_ref()))
+            .map(|index| {
+                let range =
+                    index_range(index, &conjuncts, self.parameters).unwrap_or_else(KeyRange::full);
+                let scan = Plan::IndexScan {
+                    table: table.clone(),
+                    index: index.name.clone(),
+                    range,
+                };
+                let scan = match predicate {
+                    Some(predicate) => Plan::Filter {
+                        input: Box::new(scan),
+                        predicate: predicate.clone(),
+                    },
+                    None => scan,
+                };
+                Plan::Project {
+                    input: Box::new(scan),
+                    exprs: exprs.clone(),
+                }
+            });
+        self.cheapest(std::iter::once(sort).chain(index_scans))
+    }
+
+    /// Sorts `plan` ascending on `keys`, NULLs first, unless it is an
+    /// index scan already in that order.
+    fn sorted(&self, plan: Plan, keys: &[Expr]) -> Plan {
+        let scan = match &plan {
+            Plan::Filter { input, .. } => input.as_ref(),
+            plan => plan,
+        };
+        if let Plan::IndexScan { table, index, .. } = scan {
+            let index = self.catalog.table(table).and_then(|t| t.index(index));
+            let in_order = index.is_some_and(|index| {
+                keys.len() <= index.keys.len()
+                    && keys
+                        .iter()
+                        .zip(&index.keys)
+                        .all(|(key, index_key)| *key == index_key.expr && index_key.nulls_first)
+            });
+            if in_order {
+                return plan;
+            }
+        }
+        Plan::Sort {
+            input: Box::new(plan),
+            keys: keys.iter().cloned().map(SortKey::asc).collect(),
+        }
+    }
+
+    /// Plans a tree of inner joins, choosing the order in which its
+    /// relations are joined. The output keeps the tree's column order.
+    fn join_tree(&self, logical: &LogicalPlan) -> Plan {
+        let mut relations = vec![];
+        let mut exprs = vec![];
+        flatten(logical, 0, &mut relations, &mut exprs);
+        let offsets: Vec<usize> = relations
+            .iter()
+            .scan(0, |offset, relation: &&LogicalPlan| {
+                let start = *offset;
+                *offset += relation.width();
+                Some(start)
+            })
+            .collect();
+        let width = logical.width();
+        let relation_of = |column: usize| offsets.iter().rposition(|&start| start <= column);
+        let mut conjuncts: Vec<Conjunct> = exprs
+            .into_iter()
+            .map(|expr| {
+                let mut relations = 0;
+                expr.visit_columns(&mut |column| {
+                    relations |= 1 << relation_of(column).unwrap();
+                });
+                Conjunct { expr, relations }
+            })
+            .collect();
+
+        // Conditions on a single relation filter it before any join;
+        // constant ones filter the first.
+        let leaves: Vec<Joined> = relations
+            .iter()
+            .enumerate()
+            .map(|(i, relation)| {
+                let offset = offsets[i];
+                let local: Vec<_> = conjuncts
+                    .iter()
+                    .filter(|c| c.relations == 1 << i || (c.relations == 0 && i == 0))
+                    .map(|c| c.expr.map_columns(&|column| column - offset))
+                    .collect();
+                let logical = match conjunction(local) {
+                    Some(predicate) => LogicalPlan::Filter {
+                        input: Box::new((*relation).clone()),
+                        predicate,
+                    },
+                    None => (*relation).clone(),
+                };
+                let input = self.input(&logical);
+                Joined {
+                    cost: self.cost(&input.plan),
+                    layout: (offset..offset + relation.width()).collect(),
+                    input,
+                }
+            })
+            .collect();
+        conjuncts.retain(|c| c.relations.count_ones() > 1);
+
+        let n = leaves.len();
+        let best = if n > MAX_REORDERED_RELATIONS {
+            let mut leaves = leaves.into_iter().enumerate();
+            let (_, first) = leaves.next().unwrap();
+            leaves
+                .fold((1, first), |(set, left), (i, right)| {
+                    let joined = self.join_sets(&left, set, &right, 1 << i, &conjuncts, width);
+                    (set | 1 << i, joined)
+                })
+                .1
+        } else {
+            // Best join of every subset of the relations, from smaller
+            // subsets to larger ones.
+            let mut best: Vec<Option<Joined>> = vec![None; 1 << n];
+            for (i, leaf) in leaves.into_iter().enumerate() {
+                best[1 << i] = Some(leaf);
+            }
+            for set in 1..1usize << n {
+                if set.count_ones() < 2 {
+                    continue;
+                }
+                let mut left = (set - 1) & set;
+                while left > 0 {
+                    let right = set & !left;
+                    if let (Some(l), Some(r)) = (&best[left], &best[right]) {
+                        let joined = self.join_sets(l, left, r, right, &conjuncts, width);
+                        if best[set].as_ref().is_none_or(|b| joined.cost < b.cost) {
+                            best[set] = Some(joined);
+                        }
+                    }
+                    left = (left - 1) & set;
+                }
+            }
+            best.pop().flatten().unwrap()
+        };
+        if best.layout.iter().copied().eq(0..width) {
+            return best.input.plan;
+        }
+        let mut positions = vec![0; width];
+        for (position, &column) in best.layout.iter().enumerate() {
+            positions[column] = position;
+        }
+        Plan::Project {
+            input: Box::new(best.input.plan),
+            exprs: positions.into_iter().map(Expr::Column).collect(),
+        }
+    }
+
+    /// Joins two disjoint sets of relations on the conditions that span
+    /// them.
+    fn join_sets(
+        &self,
+        left: &Joined,
+        left_set: usize,
+        right: &Joined,
+        right_set: usize,
+        conjuncts: &[Conjunct],
+        width: usize,
+    ) -> Joined {
+        let layout: Vec<usize> = left.layout.iter().chain(&right.layout).copied().collect();
+        let mut positions = vec![usize::MAX; width];
+        for (position, &column) in layout.iter().enumerate() {
+            positions[column] = position;
+        }
+        let set = left_set | right_set;
+        let predicate = conjunction(
+            conjuncts
+                .iter()
+                .filter(|c| {
+                    c.relations & !set == 0
+                        && c.relations & !left_set != 0
+                        && c.relations & !right_set != 0
+                })
+                .map(|c| c.expr.map_columns(&|column| positions[column]))
+                .collect(),
+        );
+        let plan = self.join(&left.input, &right.input, JoinKind::Inner, predicate);
+        let types = left.input.types.iter().chain(&right.input.types).copied();
+        Joined {
+            cost: self.cost(&plan),
+            input: Input {
+                plan,
+                types: types.collect(),
+            },
+            layout,
+        }
+    }
+}
+
+/// Collects the relations of a tree of inner joins, left to right, and
+/// its join conditions in terms of the tree's output columns.
+fn flatten<'p>(
+    plan: &'p LogicalPlan,
+    offset: usize,
+    relations: &mut Vec<&'p LogicalPlan>,
+    conjuncts: &mut Vec<Expr>,
+) {
+    let LogicalPlan::Join {
+        left,
+        right,
+        kind: JoinKind::Inner,
+        predicate,
+    } = plan
+    else {
+        relations.push(plan);
+        return;
+    };
+    flatten(left, offset, relations, conjuncts);
+    flatten(right, offset + left.width(), relations, conjuncts);
+    if let Some(predicate) = predicate {
+        let predicate = predicate.map_columns(&|column| column + offset);
+        conjuncts.extend(self::conjuncts(predicate));
+    }
+}
+
+/// Whether `conjuncts` imply a partial index's `predicate`: as in most
+/// databases, only when each of its conjuncts appears among them.
+fn implies(conjuncts: &[Expr], predicate: Option<&Expr>) -> bool {
+    predicate.is_none_or(|predicate| {
+        self::conjuncts(predicate.clone())
+            .iter()
+            .all(|conjunct| conjuncts.contains(conjunct))
+    })
+}
+
+/// A literal or parameter of the index key's own type that `conjunct`
+/// compares the key against, with the comparison as if the key were on
+/// the left.
+fn comparison<'e>(
+    conjunct: &'e Expr,
+    index_key: &IndexKey,
+    parameters: &[Option<DataType>],
+) -> Option<(BinaryOp, &'e Expr)> {
+    let Expr::Binary { op, lhs, rhs } = conjunct else {
+        return None;
+    };
+    let (op, key) = match (lhs.as_ref(), rhs.as_ref()) {
+        (lhs, key) if *lhs == index_key.expr => (*op, key),
+        (key, rhs) if *rhs == index_key.expr => {
+            let op = match op {
+                BinaryOp::Lt => BinaryOp::Gt,
+                BinaryOp::LtEq => BinaryOp::GtEq,
+                BinaryOp::Gt => BinaryOp::Lt,
+                BinaryOp::GtEq => BinaryOp::LtEq,
+                op => *op,
+            };
+            (op, key)
+        }
+        _ => return None,
+    };
+    let parameter_type = |n: usize| parameters.get(n - 1).copied().flatten();
+    let key_type = match key {
+        Expr::Literal(value) => value.data_type(),
+        Expr::Parameter(n) => parameter_type(*n),
+        // What a parameter compared with a collated key becomes.
+        Expr::Collate { expr, .. } => match **expr {
+            Expr::Parameter(n) => parameter_type(n),
+            _ => None,
+        },
+        _ => None,
+    };
+    (key_type == Some(index_key.data_type)).then_some((op, key))
+}
+
+/// The keys of `index` that the conjuncts restrict by equality on a prefix
+/// of its keys and then by a range on the next one, if any.
+fn index_range(
+    index: &IndexInfo,
+    conjuncts: &[Expr],
+    parameters: &[Option<DataType>],
+) -> Option<KeyRange> {
+    let comparison = |conjunct, key| comparison(conjunct, key, parameters);
+    let mut prefix = vec![];
+    let mut next = None;
+    for index_key in &index.keys {
+        let eq = conjuncts
+            .iter()
+            .find_map(|conjunct| match comparison(conjunct, index_key)? {
+                (BinaryOp::Eq, key) => Some(key.clone()),
+                _ => None,
+            });
+        match eq {
+            Some(key) => prefix.push(key),
+            None => {
+                next = Some(index_key);
+                break;
+            }
+        }
+    }
+    let (mut lower, mut upper) = (None, None);
+    if let Some(index_key) = next {
+        for conjunct in conjuncts {
+            match comparison(conjunct, index_key) {
+                Some((BinaryOp::Gt, key)) if lower.is_none() => lower = Some((key, false)),
+                Some((BinaryOp::GtEq, key)) if lower.is_none() => lower = Some((key, true)),
+                Some((BinaryOp::Lt, key)) if upper.is_none() => upper = Some((key, false)),
+                Some((BinaryOp::LtEq, key)) if upper.is_none() => upper = Some((key, true)),
+                _ => {}
+            }
+        }
+    }
+    if prefix.is_empty() && lower.is_none() && upper.is_none() {
+        return None;
+    }
+    let bound = |edge: Option<(&Expr, bool)>| match edge {
+        Some((last, inclusive)) => {
+            let mut key = prefix.clone();
+            key.push(last.clone());
+            if inclusive {
+                Bound::Included(key)
+            } else {
+                Bound::Excluded(key)
+            }
+        }
+        None if prefix.is_empty() => Bound::Unbounded,
+        None => Bound::Included(prefix.clone()),
+    };
+    Some(KeyRange {
+        start: bound(lower),
+        end: bound(upper),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::BufferPoolManager;
+    use crate::catalog::{Column, Schema};
+    use crate::disk::DiskManager;
+    use crate::executor::{ExecContext, Insert};
+    use crate::planner::testing::query;
+    use crate::planner::Optimizer;
+    use crate::tuple;
+    use crate::value::Tuple;
+    use tempfile::tempfile;
+
+    /// `big` has 2000 rows and a primary key, `small` one row per `grp`
+    /// of `big`, and each row of `mid` points at a row of `big`.
+    fn setup(analyze: bool) -> (BufferPoolManager, Catalog) {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 64);
+        let mut catalog = Catalog::new();
+        let tables = [
+            (
+                "big",
+                vec![
+                    ("id", DataType::Int),
+                    ("grp", DataType::Int),
+                    ("note", DataType::Text),
+                ],
+            ),
+            (
+                "small",
+                vec![("grp", DataType::Int), ("label", DataType::Text)],
+            ),
+            (
+                "mid",
+                vec![("id", DataType::Int), ("big_id", DataType::Int)],
+            ),
+        ];
+        for (name, columns) in tables {
+            let columns = columns
+                .into_iter()
+                .map(|(name, data_type)| Column::new(name, data_type))
+                .collect();
+            catalog
+                .create_table(&bufmgr, name, Schema::new(columns))
+                .unwrap();
+        }
+        catalog
+            .create_index(&bufmgr, "big", "big_pkey", &["id"], true)
+            .unwrap();
+        let big = (0..2000i64)
+            .map(|i| vec![i.into(), (i % 50).into(), format!("n{i}").into()])
+            .collect();
+        let small = (0..50i64)
+            .map(|i| vec![i.into(), format!("g{i}").into()])
+            .collect();
+        let mid = (0..500i64)
+            .map(|i| vec![i.into(), (i * 7 % 2000).into()])
+            .collect();
+        let ctx = ExecContext::new(&bufmgr, &catalog);
+        for (table, rows) in [("big", big), ("small", small), ("mid", mid)] {
+            Insert {
+                table: table.into(),
+                source: Plan::values(rows),
+                on_conflict: None,
+            }
+            .execute(&ctx)
+            .unwrap();
+        }
+        if analyze {
+            for table in ["big", "small", "mid"] {
+                catalog.analyze(&bufmgr, table).unwrap();
+            }
+        }
+        (bufmgr, catalog)
+    }
+
+    fn nodes(plan: &Plan) -> Vec<&Plan> {
+        let mut nodes = vec![plan];
+        nodes.extend(plan.inputs().into_iter().flat_map(self::nodes));
+        nodes
+    }
+
+    fn sorted(mut rows: Vec<Tuple>) -> Vec<Tuple> {
+        rows.sort_by_cached_key(|row| {
+            let mut key = vec![];
+            tuple::encode_key(row, &mut key);
+            key
+        });
+        rows
+    }
+
+    /// Plans `sql` and checks that the plan returns the same rows as the
+    /// naive translation of the logical plan.
+    fn planned(bufmgr: &BufferPoolManager, catalog: &Catalog, sql: &str) -> Plan {
+        let logical = Optimizer::new().optimize(query(catalog, sql));
+        let plan = PhysicalPlanner::new(catalog).plan(&logical);
+        let ctx = ExecContext::new(bufmgr, catalog);
+        assert_eq!(
+            sorted(logical.to_plan().collect(&ctx).unwrap()),
+            sorted(plan.collect(&ctx).unwrap()),
+            "{sql}"
+        );
+        plan
+    }
+
+    fn index_scans(plan: &Plan) -> Vec<&KeyRange> {
+        nodes(plan)
+            .into_iter()
+            .filter_map(|node| match node {
+                Plan::IndexScan { range, .. } => Some(range),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_access_paths() {
+        let (bufmgr, catalog) = setup(true);
+        let plan = planned(&bufmgr, &catalog, "SELECT note FROM big WHERE id = 42");
+        assert_eq!(vec![&KeyRange::eq(vec![42.into()])], index_scans(&plan));
+        let plan = planned(
+            &bufmgr,
+            &catalog,
+            "SELECT note FROM big WHERE 5 > id AND grp = 1",
+        );
+        assert_eq!(
+            vec![&KeyRange {
+                start: Bound::Unbounded,
+                end: Bound::Excluded(vec![Expr::literal(5)]),
+            }],
+            index_scans(&plan)
+        );
+        for sql in [
+            "SELECT note FROM big WHERE id > 10",
+            "SELECT note FROM big WHERE grp = 3",
+            "SELECT note FROM big WHERE id = 4.0",
+        ] {
+            assert!(
+                index_scans(&planned(&bufmgr, &catalog, sql)).is_empty(),
+                "{sql}"
+            );
+        }
+
+        let planner = PhysicalPlanner::new(&catalog);
+        let id = |op, value: i64| Expr::binary(op, Expr::column(0), Expr::literal(value));
+        assert_eq!(
+            AccessPath::IndexScan {
+                index: "big_pkey".into(),
+                range: KeyRange {
+                    start: Bound::Included(vec![Expr::literal(10)]),
+                    end: Bound::Included(vec![Expr::literal(12)]),
+                },
+            },
+            planner.access_path(
+                "big",
+                Some(&Expr::binary(
+                    BinaryOp::And,
+                    id(BinaryOp::GtEq, 10),
+                    id(BinaryOp::LtEq, 12)
+                ))
+            )
+        );
+        assert_eq!(AccessPath::SeqScan, planner.access_path("big", None));
+    }
+
+    #[test]
+    fn test_join_order_and_algorithms() {
+        let (bufmgr, catalog) = setup(true);
+        let plan = planned(
+            &bufmgr,
+            &catalog,
+            "SELECT b.note, s.label FROM big b JOIN small s ON b.grp = s.grp",
+        );
+        let Some(Plan::HashJoin { right, .. }) = nodes(&plan)
+            .into_iter()
+            .find(|node| matches!(node, Plan::HashJoin { .. }))
+        else {
+            panic!("expected a hash join: {plan:?}");
+        };
+        // The hash table is built over the smaller input.
+        assert!(right.reads_table("small"));
+
+        let plan = planned(
+            &bufmgr,
+            &catalog,
+            "SELECT s.label, b.note, m.id FROM small s, big b, mid m \
+             WHERE m.big_id = b.id AND b.grp = s.grp AND m.id < 10",
+        );
+        let joins = nodes(&plan)
+            .into_iter()
+            .filter(|node| {
+                matches!(
+                    node,
+                    Plan::NestedLoopJoin { .. } | Plan::HashJoin { .. } | Plan::MergeJoin { .. }
+                )
+            })
+            .count();
+        assert_eq!(2, joins);
+        assert!(!nodes(&plan).iter().any(|node| matches!(
+            node,
+            Plan::NestedLoopJoin {
+                predicate: None,
+                ..
+            }
+        )));
+
+        let plan = planned(
+            &bufmgr,
+            &catalog,
+            "SELECT s.label, b.id FROM small s LEFT JOIN big b ON b.grp = s.grp AND b.id < 100",
+        );
+        assert!(nodes(&plan).iter().any(|node| matches!(
+            node,
+            Plan::HashJoin {
+                kind: JoinKind::Left,
+                ..
+            } | Plan::MergeJoin {
+                kind: JoinKind::Left,
+                ..
+            }
+        )));
+
+        // Without statistics, every table looks alike and the plan still
+        // returns the right rows.
+        let (bufmgr, catalog) = setup(false);
+        for sql in [
+            "SELECT * FROM mid m JOIN big b ON m.big_id = b.id JOIN small s ON s.grp = b.grp",
+            "SELECT count(*) FROM mid m, small s WHERE m.id = s.grp",
+        ] {
+            planned(&bufmgr, &catalog, sql);
+        }
+    }
+
+    #[test]
+    fn test_planner_settings() {
+        let (bufmgr, catalog) = setup(true);
+        let plan_with = |settings: &PlannerSettings, sql| {
+            let logical = Optimizer::new().optimize(query(&catalog, sql));
+            let plan = PhysicalPlanner::new(&catalog)
+                .with_settings(settings.clone())
+                .plan(&logical);
+            let ctx = ExecContext::new(&bufmgr, &catalog);
+            assert_eq!(
+                sorted(logical.to_plan().collect(&ctx).unwrap()),
+                sorted(plan.collect(&ctx).unwrap()),
+                "{sql}"
+            );
+            plan
+        };
+        let mut settings = PlannerSettings::default();
+        settings.set("enable_indexscan", "off").unwrap();
+        let plan = plan_with(&settings, "SELECT note FROM big WHERE id = 42");
+        assert!(index_scans(&plan).is_empty());
+
+        // With no other way to read the table, a disabled scan still runs.
+        settings.set("enable_seqscan", "off").unwrap();
+        let plan = plan_with(&settings, "SELECT note FROM big WHERE grp = 3");
+        assert!(nodes(&plan)
+            .iter()
+            .any(|node| matches!(node, Plan::SeqScan { .. })));
+        settings.set("enable_indexscan", "on").unwrap();
+        let plan = plan_with(&settings, "SELECT note FROM big");
+        assert_eq!(vec![&KeyRange::full()], index_scans(&plan));
+
+        // Cheap random reads make a range scan worth it.
+        let mut settings = PlannerSettings::default();
+        settings.set("random_page_cost", "0").unwrap();
+        let plan = plan_with(&settings, "SELECT note FROM big WHERE id > 10");
+        assert_eq!(1, index_scans(&plan).len());
+
+        let join = "SELECT b.note, s.label FROM big b JOIN small s ON b.grp = s.grp";
+        let algorithms = |plan: &Plan| -> Vec<&'static str> {
+            nodes(plan)
+                .into_iter()
+                .filter_map(|node| match node {
+                    Plan::NestedLoopJoin { .. } => Some("nestloop"),
+                    Plan::HashJoin { .. } => Some("hashjoin"),
+                    Plan::MergeJoin { .. } => Some("mergejoin"),
+                    _ => None,
+                })
+                .collect()
+        };
+        for algorithm in ["nestloop", "hashjoin", "mergejoin"] {
+            let mut settings = PlannerSettings::default();
+            for other in ["nestloop", "hashjoin", "mergejoin"] {
+                if other != algorithm {
+                    settings.set(&format!("enable_{other}"), "off").unwrap();
+                }
+            }
+            assert_eq!(vec![algorithm], algorithms(&plan_with(&settings, join)));
+        }
+    }
+}
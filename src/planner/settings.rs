@@ -0,0 +1,265 @@
+//! Knobs for working around bad plans: switches for access paths and
+//! join algorithms, the cost constants, and per-table scan choices made
+//! by hints. Besides these, `null_ordering` puts the NULLs of ORDER BY
+//! and index keys that do not say where: `low`, the default, sorts them
+//! below every other value, and `high` above, as PostgreSQL does. Plans
+//! and indexes keep the ordering they were made with.
+//!
+//! As in PostgreSQL, a disabled choice is only discouraged: the planner
+//! still takes it when nothing else can run the query.
+
+use std::collections::HashMap;
+
+use super::cost::CostConstants;
+use super::Error;
+use crate::executor::NullOrdering;
+use crate::sql::Hint;
+
+/// How a hint tells the planner to read a table.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanHint {
+    Seq,
+    /// Through one of these indexes, or any index if there are none.
+    Index(Vec<String>),
+    NoSeq,
+    NoIndex,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannerSettings {
+    pub enable_seqscan: bool,
+    pub enable_indexscan: bool,
+    pub enable_nestloop: bool,
+    pub enable_hashjoin: bool,
+    pub enable_mergejoin: bool,
+    pub null_ordering: NullOrdering,
+    pub costs: CostConstants,
+    /// Scans forced or forbidden by hints, by table name.
+    pub scans: HashMap<String, ScanHint>,
+}
+
+impl Default for PlannerSettings {
+    fn default() -> Self {
+        Self {
+            enable_seqscan: true,
+            enable_indexscan: true,
+            enable_nestloop: true,
+            enable_hashjoin: true,
+            enable_mergejoin: true,
+            null_ordering: NullOrdering::Low,
+            costs: CostConstants::default(),
+            scans: HashMap::new(),
+        }
+    }
+}
+
+impl PlannerSettings {
+    /// Names of the settings [`PlannerSettings::set`] accepts.
+    pub const NAMES: &'static [&'static str] = &[
+        "enable_seqscan",
+        "enable_indexscan",
+        "enable_nestloop",
+        "enable_hashjoin",
+        "enable_mergejoin",
+        "null_ordering",
+        "seq_page_cost",
+        "random_page_cost",
+        "cpu_tuple_cost",
+        "cpu_operator_cost",
+    ];
+
+    /// Whether `table` may be read sequentially.
+    pub fn seq_scan_allowed(&self, table: &str) -> bool {
+        match self.scans.get(table) {
+            Some(ScanHint::Seq) => true,
+            Some(ScanHint::Index(_) | ScanHint::NoSeq) => false,
+            Some(ScanHint::NoIndex) | None => self.enable_seqscan,
+        }
+    }
+
+    /// Whether `table` may be read through `index`.
+    pub fn index_scan_allowed(&self, table: &str, index: &str) -> bool {
+        match self.scans.get(table) {
+            Some(ScanHint::Index(indexes)) => {
+                indexes.is_empty() || indexes.iter().any(|name| name == index)
+            }
+            Some(ScanHint::Seq | ScanHint::NoIndex) => false,
+            Some(ScanHint::NoSeq) | None => self.enable_indexscan,
+        }
+    }
+
+    /// Changes a setting by name, parsing `value` as SET would: booleans
+    /// as on/off, true/false or 1/0, costs as non-negative numbers, the
+    /// null ordering as low or high.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        let invalid = || Error::InvalidSetting {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        let flag = || match value.to_lowercase().as_str() {
+            "on" | "true" | "1" => Ok(true),
+            "off" | "false" | "0" => Ok(false),
+            _ => Err(invalid()),
+        };
+        let cost = || match value.parse::<f64>() {
+            Ok(cost) if cost.is_finite() && cost >= 0.0 => Ok(cost),
+            _ => Err(invalid()),
+        };
+        match name {
+            "enable_seqscan" => self.enable_seqscan = flag()?,
+            "enable_indexscan" => self.enable_indexscan = flag()?,
+            "enable_nestloop" => self.enable_nestloop = flag()?,
+            "enable_hashjoin" => self.enable_hashjoin = flag()?,
+            "enable_mergejoin" => self.enable_mergejoin = flag()?,
+            "null_ordering" => self.null_ordering = value.parse().map_err(|()| invalid())?,
+            "seq_page_cost" => self.costs.seq_page_cost = cost()?,
+            "random_page_cost" => self.costs.random_page_cost = cost()?,
+            "cpu_tuple_cost" => self.costs.cpu_tuple_cost = cost()?,
+            "cpu_operator_cost" => self.costs.cpu_operator_cost = cost()?,
+            _ => return Err(Error::UnknownSetting(name.to_string())),
+        }
+        Ok(())
+    }
+
+    /// The value of a setting, as SHOW would print it.
+    pub fn get(&self, name: &str) -> Result<String, Error> {
+        let flag = |on: bool| if on { "on" } else { "off" }.to_string();
+        Ok(match name {
+            "enable_seqscan" => flag(self.enable_seqscan),
+            "enable_indexscan" => flag(self.enable_indexscan),
+            "enable_nestloop" => flag(self.enable_nestloop),
+            "enable_hashjoin" => flag(self.enable_hashjoin),
+            "enable_mergejoin" => flag(self.enable_mergejoin),
+            "null_ordering" => self.null_ordering.to_string(),
+            "seq_page_cost" => self.costs.seq_page_cost.to_string(),
+            "random_page_cost" => self.costs.random_page_cost.to_string(),
+            "cpu_tuple_cost" => self.costs.cpu_tuple_cost.to_string(),
+            "cpu_operator_cost" => self.costs.cpu_operator_cost.to_string(),
+            _ => return Err(Error::UnknownSetting(name.to_string())),
+        })
+    }
+
+    /// These settings as changed by a statement's hints:
+    ///
+    /// - `SeqScan(t)`, `IndexScan(t [index ...])`, `NoSeqScan(t)` and
+    ///   `NoIndexScan(t)` choose how table `t` is read;
+    /// - `NoNestLoop`, `NoHashJoin` and `NoMergeJoin` disable a join
+    ///   algorithm, and `NestLoop`, `HashJoin` and `MergeJoin` disable
+    ///   the other two;
+    /// - `Set(name value)` changes a setting as [`PlannerSettings::set`].
+    pub fn with_hints(&self, hints: &[Hint]) -> Result<Self, Error> {
+        let mut settings = self.clone();
+        for hint in hints {
+            let invalid = || Error::InvalidHint(hint.name.clone());
+            let scan = |scan| match hint.args.as_slice() {
+                [table] => Ok((table.clone(), scan)),
+                _ => Err(invalid()),
+            };
+            // Which of nested loop, hash and merge joins stay enabled.
+            let joins = match hint.name.as_str() {
+                "nonestloop" => Some((false, true, true)),
+                "nohashjoin" => Some((true, false, true)),
+                "nomergejoin" => Some((true, true, false)),
+                "nestloop" => Some((true, false, false)),
+                "hashjoin" => Some((false, true, false)),
+                "mergejoin" => Some((false, false, true)),
+                _ => None,
+            };
+            if let Some((nestloop, hashjoin, mergejoin)) = joins {
+                if !hint.args.is_empty() {
+                    return Err(invalid());
+                }
+                settings.enable_nestloop &= nestloop;
+                settings.enable_hashjoin &= hashjoin;
+                settings.enable_mergejoin &= mergejoin;
+                continue;
+            }
+            let (table, scan) = match hint.name.as_str() {
+                "seqscan" => scan(ScanHint::Seq)?,
+                "noseqscan" => scan(ScanHint::NoSeq)?,
+                "noindexscan" => scan(ScanHint::NoIndex)?,
+                "indexscan" => match hint.args.split_first() {
+                    Some((table, indexes)) => (table.clone(), ScanHint::Index(indexes.to_vec())),
+                    None => return Err(invalid()),
+                },
+                "set" => {
+                    let [name, value] = hint.args.as_slice() else {
+                        return Err(invalid());
+                    };
+                    settings.set(name, value)?;
+                    continue;
+                }
+                _ => return Err(Error::UnknownHint(hint.name.clone())),
+            };
+            settings.scans.insert(table, scan);
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql::parse_hints;
+
+    #[test]
+    fn test_settings_and_hints() {
+        let mut settings = PlannerSettings::default();
+        for name in PlannerSettings::NAMES {
+            let value = settings.get(name).unwrap();
+            settings.set(name, &value).unwrap();
+        }
+        assert_eq!(PlannerSettings::default(), settings);
+        settings.set("enable_hashjoin", "OFF").unwrap();
+        settings.set("random_page_cost", "1.1").unwrap();
+        settings.set("null_ordering", "HIGH").unwrap();
+        assert_eq!("off", settings.get("enable_hashjoin").unwrap());
+        assert_eq!("high", settings.get("null_ordering").unwrap());
+        assert_eq!("1.1", settings.get("random_page_cost").unwrap());
+        assert_eq!(
+            Err(Error::InvalidSetting {
+                name: "cpu_tuple_cost".into(),
+                value: "-1".into()
+            }),
+            settings.set("cpu_tuple_cost", "-1")
+        );
+        assert_eq!(
+            Err(Error::UnknownSetting("work_mem".into())),
+            settings.get("work_mem")
+        );
+
+        let hints = parse_hints(
+            "/*+ IndexScan(t t_a) NoSeqScan(u) MergeJoin Set(enable_seqscan off) */ SELECT 1",
+        )
+        .unwrap();
+        let hinted = settings.with_hints(&hints).unwrap();
+        assert!(hinted.index_scan_allowed("t", "t_a"));
+        assert!(!hinted.index_scan_allowed("t", "t_b"));
+        assert!(!hinted.seq_scan_allowed("t"));
+        assert!(!hinted.seq_scan_allowed("u"));
+        assert!(hinted.index_scan_allowed("u", "u_a"));
+        assert!(!hinted.seq_scan_allowed("v"));
+        assert!(hinted.enable_mergejoin);
+        assert!(!hinted.enable_nestloop && !hinted.enable_hashjoin);
+        // Hints leave the settings they start from alone.
+        assert!(settings.seq_scan_allowed("t") && settings.enable_nestloop);
+
+        for (sql, error) in [
+            (
+                "/*+ Leading(t u) */ SELECT 1",
+                Error::UnknownHint("leading".into()),
+            ),
+            (
+                "/*+ SeqScan */ SELECT 1",
+                Error::InvalidHint("seqscan".into()),
+            ),
+            (
+                "/*+ Set(geqo on) */ SELECT 1",
+                Error::UnknownSetting("geqo".into()),
+            ),
+        ] {
+            let hints = parse_hints(sql).unwrap();
+            assert_eq!(Err(error), settings.with_hints(&hints), "{sql}");
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Fixtures shared by the planner tests: an `emp` table with a unique
+//! index on `id`, and a `dept` table.
+
+use super::{bind, BoundStatement, Error, LogicalPlan};
+use crate::buffer::BufferPoolManager;
+use crate::catalog::{Catalog, Column, Schema};
+use crate::disk::DiskManager;
+use crate::executor::{ExecContext, Insert, Plan};
+use crate::sql::parse_statement;
+use crate::value::{DataType, Tuple, Value};
+use tempfile::tempfile;
+
+pub fn setup() -> (BufferPoolManager, Catalog) {
+    let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+    let bufmgr = BufferPoolManager::new(disk, 32);
+    let mut catalog = Catalog::new();
+    let emp = Schema::new(vec![
+        Column::new("id", DataType::Int).not_null(),
+        Column::new("name", DataType::Text),
+        Column::new("dept", DataType::Int),
+        Column::new("salary", DataType::Float),
+    ]);
+    catalog.create_table(&bufmgr, "emp", emp).unwrap();
+    catalog
+        .create_index(&bufmgr, "emp", "emp_pkey", &["id"], true)
+        .unwrap();
+    let dept = Schema::new(vec![
+        Column::new("id", DataType::Int),
+        Column::new("title", DataType::Text),
+    ]);
+    catalog.create_table(&bufmgr, "dept", dept).unwrap();
+    let ctx = ExecContext::new(&bufmgr, &catalog);
+    let emp_rows = vec![
+        vec![1.into(), "ann".into(), 1.into(), 100.0.into()],
+        vec![2.into(), "bob".into(), 1.into(), 80.0.into()],
+        vec![3.into(), "cid".into(), 2.into(), 120.0.into()],
+        vec![4.into(), "dee".into(), Value::Null, 50.0.into()],
+    ];
+    let dept_rows = vec![
+        vec![1.into(), "eng".into()],
+        vec![2.into(), "ops".into()],
+        vec![3.into(), "hr".into()],
+    ];
+    for (table, rows) in [("emp", emp_rows), ("dept", dept_rows)] {
+        Insert {
+            table: table.into(),
+            source: Plan::values(rows),
+            on_conflict: None,
+        }
+        .execute(&ctx)
+        .unwrap();
+    }
+    (bufmgr, catalog)
+}
+
+pub fn bind_sql(catalog: &Catalog, sql: &str) -> Result<BoundStatement, Error> {
+    bind(catalog, &parse_statement(sql).unwrap())
+}
+
+pub fn query(catalog: &Catalog, sql: &str) -> LogicalPlan {
+    match bind_sql(catalog, sql).unwrap() {
+        BoundStatement::Query(plan) => plan,
+        statement => panic!("not a query: {statement:?}"),
+    }
+}
+
+pub fn execute(bufmgr: &BufferPoolManager, catalog: &Catalog, plan: &LogicalPlan) -> Vec<Tuple> {
+    let ctx = ExecContext::new(bufmgr, catalog);
+    plan.to_plan().collect(&ctx).unwrap()
+}
+
+pub fn run(bufmgr: &BufferPoolManager, catalog: &Catalog, sql: &str) -> Vec<Tuple> {
+    execute(bufmgr, catalog, &query(catalog, sql))
+}
@@ -0,0 +1,90 @@
+//! Property tests over random sequences of operations.
+//!
+//! [`check`] runs a property against operation sequences generated from
+//! seeds, and when one fails, shrinks it by dropping operations while it
+//! still fails, reporting the seed and the shortest sequence found. It
+//! does what the proptest crate would for these tests, without the
+//! dependency.
+
+use std::fmt::Debug;
+use std::panic::{self, AssertUnwindSafe};
+
+/// A seeded xorshift64* generator.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Spread small seeds out; xorshift never leaves zero.
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Up to `max_len` random bytes.
+    pub fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+        let len = self.below(max_len + 1);
+        (0..len).map(|_| self.next_u64() as u8).collect()
+    }
+}
+
+/// Runs `property` on the operations `generate` makes for seeds
+/// `0..cases`, panicking with a shrunk sequence on the first failure. A
+/// property fails by returning an error or by panicking.
+pub fn check<Op: Debug + Clone>(
+    cases: u64,
+    generate: impl Fn(&mut Rng) -> Vec<Op>,
+    property: impl Fn(&[Op]) -> Result<(), String>,
+) {
+    let fails = |ops: &[Op]| -> Option<String> {
+        match panic::catch_unwind(AssertUnwindSafe(|| property(ops))) {
+            Ok(Ok(())) => None,
+            Ok(Err(message)) => Some(message),
+            Err(payload) => Some(
+                payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_else(|| "panicked".to_string()),
+            ),
+        }
+    };
+    for seed in 0..cases {
+        let mut ops = generate(&mut Rng::new(seed));
+        let Some(mut message) = fails(&ops) else {
+            continue;
+        };
+        let generated = ops.len();
+        // Drop ever smaller runs of operations while the failure stays.
+        let mut chunk = ops.len() / 2;
+        while chunk > 0 {
+            let mut start = 0;
+            while start < ops.len() {
+                let end = (start + chunk).min(ops.len());
+                let candidate: Vec<Op> = [&ops[..start], &ops[end..]].concat();
+                match fails(&candidate) {
+                    Some(m) => {
+                        ops = candidate;
+                        message = m;
+                    }
+                    None => start += chunk,
+                }
+            }
+            chunk /= 2;
+        }
+        panic!(
+            "property failed for seed {seed}, shrunk from {generated} to {} operations: \
+             {message}\n{ops:#?}",
+            ops.len()
+        );
+    }
+}
@@ -0,0 +1,526 @@
+//! A network server speaking the PostgreSQL protocol.
+//!
+//! A [`Server`] accepts connections and gives each a thread of its own,
+//! which serves it with [`pgwire`] until the client leaves. Each
+//! connection is a session of its own, with its own prepared statements,
+//! portals and transaction; the sessions share one database and its
+//! buffer pool. No more than [`Config::workers`] run messages at once,
+//! the others waiting for a free worker; a client sitting idle holds
+//! none, only its thread asleep on the socket. Connections past
+//! `max_connections` are turned away, and a client idle in a
+//! transaction for [`pgwire::Config::idle_in_transaction_timeout`] is
+//! cut off, since its transaction may be keeping the database from all.
+//!
+//! The threads are plain ones rather than tasks on an async runtime such
+//! as tokio. The storage engine blocks, on page reads and on the mutex
+//! of the database, whose guard a transaction keeps from one message to
+//! the next and could not carry across awaits on a runtime that moves
+//! tasks between threads; every statement would go to a blocking thread
+//! anyway. With `max_connections` bounding them, a thread per connection
+//! costs a stack each and no more.
+//!
+//! With [`Config::http`] set, the server also listens for HTTP requests,
+//! which [`http`] serves in turns on the same workers, one request per
+//! connection; see there for the endpoints. They take the same logins,
+//! and are refused when TLS is required, since they have none.
+//!
+//! Unless [`Config::expire_interval`] is `None`, a thread of its own
+//! deletes the rows whose time to live has run out every so often, in
+//! batches of [`Config::expire_batch`] rows that sessions can run
+//! between. It skips its turn while a session holds the database.
+//! Another builds the indexes of `CREATE INDEX CONCURRENTLY` the same
+//! way, [`Config::index_build_batch`] pages at a time. Unless
+//! [`Config::write_back_interval`] is `None`, a third writes back dirty
+//! pages outside transactions, so that commits and evictions find fewer
+//! left to write. It goes through the buffer pool rather than the
+//! database, and so runs beside sessions, at background priority: its
+//! writes yield to the pages sessions read, at the rate the
+//! `background_write_rate` option allows.
+//!
+//! Unless [`Config::maintenance_interval`] is `None`, another thread runs
+//! the database's [idle-time maintenance](crate::engine::Maintenance) a
+//! step at a time, while sessions leave the database be; the handle of
+//! [`Engine::maintenance`](crate::engine::Engine::maintenance) pauses
+//! and triggers its tasks.
+//!
+//! [`Server::run`] returns once [`ShutdownHandle::shutdown`] has been
+//! called and the open sessions have ended, and closes the database.
+//!
+//! Connections are encrypted with TLS for clients that ask, once
+//! [`pgwire::Config::tls`] holds the server's certificate and key, and
+//! [`pgwire::Config::require_tls`] turns away those that do not; see
+//! [`pgwire::Tls`]. Logins are safe either way, since SCRAM never sends
+//! the password, but without TLS statements and rows are not.
+//!
+//! There is no replication, which would take a write-ahead log the
+//! storage engine does not have. A standby copy can be taken from a
+//! database closed cleanly, and opened elsewhere with
+//! [`Database::open_read_only`] to serve reads, or with
+//! [`Database::open`] to take over from the primary; failover is left to
+//! the tooling that takes the copies. Nor, for want of the same log, is
+//! there a replicated mode agreeing on changes through Raft.
+
+mod metrics;
+mod workers;
+
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::buffer;
+use crate::database::{self, Database};
+use crate::{http, pgwire};
+
+pub use metrics::Metrics;
+pub use workers::{Turn, Workers};
+
+/// How long an HTTP client may take to send its request.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the expiring thread checks for shutdown while it waits.
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Database(#[from] database::Error),
+    #[error("HTTP cannot be served when TLS is required, since it has no TLS")]
+    HttpWithoutTls,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub listen: String,
+    /// Where to listen for HTTP requests, if anywhere.
+    pub http: Option<String>,
+    /// Connections whose messages run at once.
+    pub workers: usize,
+    /// Connections open at once, idle or not.
+    pub max_connections: usize,
+    pub protocol: pgwire::Config,
+    /// How often to delete expired rows; `None` leaves them.
+    pub expire_interval: Option<Duration>,
+    /// Expired rows deleted at a time.
+    pub expire_batch: usize,
+    /// How often to go on with indexes being built concurrently.
+    pub index_build_interval: Duration,
+    /// Table pages scanned at a time for an index being built.
+    pub index_build_batch: usize,
+    /// How often to write back dirty pages; `None` leaves them to commits
+    /// and evictions.
+    pub write_back_interval: Option<Duration>,
+    /// Dirty pages written back at a time.
+    pub write_back_batch: usize,
+    /// How often to look for idle-time maintenance to run; `None` leaves
+    /// it to be run by hand.
+    pub maintenance_interval: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:5432".to_string(),
+            http: None,
+            workers: thread::available_parallelism().map_or(4, usize::from),
+            max_connections: 100,
+            protocol: pgwire::Config::default(),
+            expire_interval: Some(Duration::from_secs(10)),
+            expire_batch: 1000,
+            index_build_interval: Duration::from_millis(100),
+            index_build_batch: 64,
+            write_back_interval: Some(Duration::from_millis(200)),
+            write_back_batch: 64,
+            maintenance_interval: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+pub struct Server {
+    db: Mutex<Database>,
+    listener: TcpListener,
+    http: Option<TcpListener>,
+    workers: Workers,
+    config: Config,
+    metrics: Metrics,
+    shutdown: Arc<AtomicBool>,
+}
+
+enum Connection {
+    Postgres(TcpStream),
+    Http(TcpStream),
+}
+
+/// Stops a running [`Server`] from another thread.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    shutdown: Arc<AtomicBool>,
+    addrs: Vec<SocketAddr>,
+}
+
+impl ShutdownHandle {
+    /// Stops accepting connections. Sessions already open run on until
+    /// their clients leave.
+    pub fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wakes the accepting threads, which check the flag first.
+        for addr in &self.addrs {
+            let _ = TcpStream::connect(addr);
+        }
+    }
+}
+
+impl Server {
+    /// Listens where `config` says. HTTP, having no TLS, is refused when
+    /// [`pgwire::Config::require_tls`] is set.
+    pub fn bind(db: Database, config: Config) -> Result<Self, Error> {
+        if config.http.is_some() && config.protocol.require_tls {
+            return Err(Error::HttpWithoutTls);
+        }
+        let listener = TcpListener::bind(&config.listen)?;
+        let http = config.http.as_ref().map(TcpListener::bind).transpose()?;
+        Ok(Self {
+            db: Mutex::new(db),
+            listener,
+            http,
+            workers: Workers::new(config.workers),
+            config,
+            metrics: Metrics::default(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Where HTTP requests are taken, if [`Config::http`] was set.
+    pub fn http_addr(&self) -> Option<io::Result<SocketAddr>> {
+        self.http.as_ref().map(TcpListener::local_addr)
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    pub fn shutdown_handle(&self) -> io::Result<ShutdownHandle> {
+        let mut addrs = vec![self.local_addr()?];
+        if let Some(addr) = self.http_addr() {
+            addrs.push(addr?);
+        }
+        Ok(ShutdownHandle {
+            shutdown: Arc::clone(&self.shutdown),
+            addrs,
+        })
+    }
+
+    /// Serves connections until shut down, then closes the database.
+    pub fn run(self) -> Result<(), Error> {
+        let server = &self;
+        thread::scope(|scope| {
+            if let Some(http) = &self.http {
+                scope.spawn(move || server.accept(scope, http, Connection::Http));
+            }
+            if let Some(interval) = self.config.expire_interval {
+                let batch = self.config.expire_batch;
+                scope.spawn(move || {
+                    server.in_background(
+                        interval,
+                        batch,
+                        |batch| server.with_database(batch, Database::expire_rows),
+                        "delete expired rows",
+                    )
+                });
+            }
+            let interval = self.config.index_build_interval;
+            let batch = self.config.index_build_batch;
+            scope.spawn(move || {
+                server.in_background(
+                    interval,
+                    batch,
+                    |batch| server.with_database(batch, Database::build_indexes),
+                    "build indexes",
+                )
+            });
+            let writer = {
+                let db = self.db.lock().unwrap();
+                (!db.is_read_only()).then(|| db.engine().bufmgr().background_writer())
+            };
+            if let (Some(interval), Some(writer)) = (self.config.write_back_interval, writer) {
+                let batch = self.config.write_back_batch;
+                scope.spawn(move || {
+                    server.in_background(
+                        interval,
+                        batch,
+                        |batch| match writer.write_back(batch) {
+                            // Its pages stay in the pool until it ends.
+                            Err(buffer::Error::InTransaction) => None,
+                            result => Some(result.map(|n| n as u64)),
+                        },
+                        "write back dirty pages",
+                    )
+                });
+            }
+            if let Some(interval) = self.config.maintenance_interval {
+                scope.spawn(move || {
+                    server.in_background(
+                        interval,
+                        1,
+                        |_| {
+                            server.with_database(1, |db, _| {
+                                Ok(u64::from(db.run_maintenance()?.is_some()))
+                            })
+                        },
+                        "run maintenance",
+                    )
+                });
+            }
+            self.accept(scope, &self.listener, Connection::Postgres);
+        });
+        let db = self.db.into_inner().unwrap_or_else(|e| e.into_inner());
+        db.close()?;
+        Ok(())
+    }
+
+    /// Serves the connections of `listener`, each on a thread of its own
+    /// in `scope`, until shut down.
+    fn accept<'scope>(
+        &'scope self,
+        scope: &'scope thread::Scope<'scope, '_>,
+        listener: &TcpListener,
+        connection: fn(TcpStream) -> Connection,
+    ) {
+        for stream in listener.incoming() {
+            if self.shutdown.load(Ordering::SeqCst) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("neru7db: cannot accept a connection: {e}");
+                    continue;
+                }
+            };
+            let open = &self.metrics.open_connections;
+            if open.load(Ordering::SeqCst) >= self.config.max_connections {
+                self.metrics.count_refused();
+                let message = "sorry, too many clients already";
+                match connection(stream) {
+                    Connection::Postgres(stream) => {
+                        let _ = pgwire::refuse(stream, "53300", message);
+                    }
+                    Connection::Http(stream) => {
+                        let _ = http::refuse(stream, message);
+                    }
+                }
+                continue;
+            }
+            open.fetch_add(1, Ordering::SeqCst);
+            let connection = connection(stream);
+            scope.spawn(move || {
+                self.serve(connection);
+                open.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    /// Calls `task` with batches of up to `batch` every `interval`, as
+    /// long as it fills them, until shut down; `task` returns `None` to
+    /// wait a turn. Errors are logged as failures to do `what`.
+    fn in_background<E: fmt::Display>(
+        &self,
+        interval: Duration,
+        batch: usize,
+        mut task: impl FnMut(usize) -> Option<Result<u64, E>>,
+        what: &str,
+    ) {
+        let mut next = Instant::now() + interval;
+        while !self.shutdown.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now < next {
+                thread::sleep(SHUTDOWN_POLL.min(next - now));
+                continue;
+            }
+            next = now + interval;
+            let batch = batch.max(1);
+            while !self.shutdown.load(Ordering::SeqCst) {
+                match task(batch) {
+                    Some(Ok(n)) if n as usize == batch => continue,
+                    None | Some(Ok(_)) => break,
+                    Some(Err(e)) => {
+                        eprintln!("neru7db: cannot {what}: {e}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs `task` on the database, or `None` if a session holds it, as
+    /// one in a transaction does.
+    fn with_database(
+        &self,
+        batch: usize,
+        task: fn(&mut Database, usize) -> Result<u64, database::Error>,
+    ) -> Option<Result<u64, database::Error>> {
+        let mut db = self.db.try_lock().ok()?;
+        Some(task(&mut db, batch))
+    }
+
+    fn serve(&self, connection: Connection) {
+        let (Connection::Postgres(stream) | Connection::Http(stream)) = &connection;
+        let peer = stream
+            .peer_addr()
+            .map_or("unknown peer".to_string(), |addr| addr.to_string());
+        let result = match connection {
+            Connection::Postgres(stream) => {
+                self.metrics.count_postgres_connection();
+                let config = &self.config.protocol;
+                pgwire::serve_socket(&self.db, config, &self.workers, stream)
+                    .map_err(|e| e.to_string())
+            }
+            // The request takes its turn as a whole, being short.
+            Connection::Http(stream) => stream
+                .set_read_timeout(Some(HTTP_TIMEOUT))
+                .and_then(|()| stream.try_clone())
+                .map_err(http::Error::from)
+                .and_then(|reader| {
+                    let _turn = self.workers.take();
+                    let config = &self.config.protocol;
+                    http::serve(&self.db, config, &self.metrics, reader, stream)
+                })
+                .map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            eprintln!("neru7db: {peer}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use super::*;
+    use crate::database::Options;
+
+    fn startup(stream: &mut TcpStream) -> Vec<u8> {
+        let mut body = 196608i32.to_be_bytes().to_vec();
+        body.extend_from_slice(b"user\0me\0\0");
+        let mut packet = ((body.len() + 4) as i32).to_be_bytes().to_vec();
+        packet.extend(body);
+        stream.write_all(&packet).unwrap();
+        let mut reply = vec![];
+        // Up to and including ReadyForQuery, which is 6 bytes long.
+        while reply.len() < 6 || reply[reply.len() - 6] != b'Z' {
+            let mut buf = [0; 256];
+            let n = stream.read(&mut buf).unwrap();
+            assert!(n > 0, "server hung up");
+            reply.extend_from_slice(&buf[..n]);
+        }
+        reply
+    }
+
+    #[test]
+    fn test_server() {
+        let db = Database::temporary(Options::default()).unwrap();
+        let config = Config {
+            listen: "127.0.0.1:0".to_string(),
+            http: Some("127.0.0.1:0".to_string()),
+            workers: 1,
+            max_connections: 1,
+            ..Config::default()
+        };
+        let server = Server::bind(db, config).unwrap();
+        let addr = server.local_addr().unwrap();
+        let http_addr = server.http_addr().unwrap().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+        let running = thread::spawn(move || server.run());
+
+        let mut first = TcpStream::connect(addr).unwrap();
+        assert_eq!(b'R', startup(&mut first)[0]);
+        let mut second = TcpStream::connect(addr).unwrap();
+        let mut refused = vec![];
+        second.read_to_end(&mut refused).unwrap();
+        assert_eq!(b'E', refused[0]);
+        assert!(String::from_utf8_lossy(&refused).contains("53300"));
+
+        let mut busy = TcpStream::connect(http_addr).unwrap();
+        let mut response = String::new();
+        busy.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+
+        first.write_all(&[b'X', 0, 0, 0, 4]).unwrap();
+        drop(first);
+        // The session ends on its own time; until then HTTP is refused too.
+        let response = loop {
+            let mut http = TcpStream::connect(http_addr).unwrap();
+            // A refusal may reset the connection under the request.
+            let _ = http.write_all(b"GET /health HTTP/1.1\r\n\r\n");
+            let mut response = String::new();
+            let _ = http.read_to_string(&mut response);
+            if response.starts_with("HTTP/1.1 200") {
+                break response;
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        assert!(response.ends_with("{\"status\": \"ok\"}"), "{response}");
+        handle.shutdown();
+        running.join().unwrap().unwrap();
+
+        let db = Database::temporary(Options::default()).unwrap();
+        let mut config = Config {
+            listen: "127.0.0.1:0".to_string(),
+            http: Some("127.0.0.1:0".to_string()),
+            ..Config::default()
+        };
+        config.protocol.require_tls = true;
+        let result = Server::bind(db, config);
+        assert!(matches!(result, Err(Error::HttpWithoutTls)));
+    }
+
+    #[test]
+    fn test_idle_connections() {
+        let db = Database::temporary(Options::default()).unwrap();
+        let mut config = Config {
+            listen: "127.0.0.1:0".to_string(),
+            workers: 1,
+            ..Config::default()
+        };
+        config.protocol.idle_in_transaction_timeout = Some(Duration::from_millis(100));
+        let server = Server::bind(db, config).unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = server.shutdown_handle().unwrap();
+        let running = thread::spawn(move || server.run());
+
+        // An idle client leaves the one worker to the next.
+        let mut idle = TcpStream::connect(addr).unwrap();
+        startup(&mut idle);
+        let mut busy = TcpStream::connect(addr).unwrap();
+        startup(&mut busy);
+        let mut query = vec![b'Q', 0, 0, 0, 10];
+        query.extend_from_slice(b"BEGIN\0");
+        busy.write_all(&query).unwrap();
+        let mut reply = vec![];
+        while !reply.ends_with(b"Z\0\0\0\x05T") {
+            let mut buf = [0; 256];
+            let n = busy.read(&mut buf).unwrap();
+            assert!(n > 0, "server hung up");
+            reply.extend_from_slice(&buf[..n]);
+        }
+        // Then, idle in its transaction, is cut off.
+        let mut reply = vec![];
+        busy.read_to_end(&mut reply).unwrap();
+        assert_eq!(b'E', reply[0]);
+        assert!(String::from_utf8_lossy(&reply).contains("25P03"));
+
+        drop(idle);
+        handle.shutdown();
+        running.join().unwrap().unwrap();
+    }
+}
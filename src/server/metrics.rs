@@ -0,0 +1,108 @@
+//! Counters a [`Server`](super::Server) keeps for `GET /metrics`.
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use crate::database::Database;
+
+#[derive(Debug)]
+pub struct Metrics {
+    started: Instant,
+    /// Connections open, idle or not.
+    pub(super) open_connections: AtomicUsize,
+    postgres_connections: AtomicU64,
+    http_requests: AtomicU64,
+    http_errors: AtomicU64,
+    refused: AtomicU64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            open_connections: AtomicUsize::new(0),
+            postgres_connections: AtomicU64::new(0),
+            http_requests: AtomicU64::new(0),
+            http_errors: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    pub(super) fn count_postgres_connection(&self) {
+        self.postgres_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn count_refused(&self) {
+        self.refused.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an HTTP request answered with `status`.
+    pub fn count_http_request(&self, status: u16) {
+        self.http_requests.fetch_add(1, Ordering::Relaxed);
+        if status >= 400 {
+            self.http_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The metrics in the Prometheus text format, with those of `db` when
+    /// it is given.
+    pub fn render(&self, db: Option<&Database>) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: &dyn std::fmt::Display| {
+            writeln!(out, "# HELP neru7db_{name} {help}").unwrap();
+            writeln!(out, "# TYPE neru7db_{name} {kind}").unwrap();
+            writeln!(out, "neru7db_{name} {value}").unwrap();
+        };
+        let count = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        metric(
+            "uptime_seconds",
+            "gauge",
+            "Seconds since the server started.",
+            &self.started.elapsed().as_secs_f64(),
+        );
+        metric(
+            "open_connections",
+            "gauge",
+            "Connections open, idle or not.",
+            &self.open_connections.load(Ordering::Relaxed),
+        );
+        metric(
+            "postgres_connections_total",
+            "counter",
+            "Postgres protocol connections accepted.",
+            &count(&self.postgres_connections),
+        );
+        metric(
+            "http_requests_total",
+            "counter",
+            "HTTP requests answered.",
+            &count(&self.http_requests),
+        );
+        metric(
+            "http_errors_total",
+            "counter",
+            "HTTP requests answered with an error status.",
+            &count(&self.http_errors),
+        );
+        metric(
+            "refused_connections_total",
+            "counter",
+            "Connections turned away for being too many.",
+            &count(&self.refused),
+        );
+        let Some(db) = db else {
+            return out;
+        };
+        metric(
+            "tables",
+            "gauge",
+            "Tables in the catalog.",
+            &db.engine().catalog().tables().count(),
+        );
+        out.push_str(&db.metrics().to_prometheus());
+        out
+    }
+}
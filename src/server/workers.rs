@@ -0,0 +1,66 @@
+//! Turns for connections to run on, so that no more than a
+//! [`Server`](super::Server)'s `workers` do at once.
+
+use std::sync::{Condvar, Mutex, PoisonError};
+
+#[derive(Debug)]
+pub struct Workers {
+    free: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// A worker taken with [`Workers::take`], freed when dropped.
+#[derive(Debug)]
+pub struct Turn<'a>(&'a Workers);
+
+impl Workers {
+    pub fn new(workers: usize) -> Self {
+        Self {
+            free: Mutex::new(workers.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Waits for a free worker and takes it.
+    pub fn take(&self) -> Turn<'_> {
+        let free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut free = (self.freed.wait_while(free, |free| *free == 0))
+            .unwrap_or_else(PoisonError::into_inner);
+        *free -= 1;
+        Turn(self)
+    }
+}
+
+impl Drop for Turn<'_> {
+    fn drop(&mut self) {
+        *self.0.free.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+        self.0.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_workers() {
+        let workers = Workers::new(2);
+        let (running, most) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| {
+                    let _turn = workers.take();
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+        assert!(most.load(Ordering::SeqCst) <= 2);
+    }
+}
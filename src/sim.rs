@@ -0,0 +1,453 @@
+//! Simulated storage, for testing what the database makes of faults.
+//!
+//! A [`SimFile`] holds in memory what a file would, as two images: what
+//! the last sync made durable, and what reads see, which adds the writes
+//! the OS would still be holding. [`Faults`] make chosen operations fail,
+//! once or for good as if the process died there, and [`SimFile::crash`]
+//! loses power: each write since the last sync reaches the durable image
+//! or not, and one of them may be torn, cut off at a sector. Choices
+//! come from a seed, so a failing run can be replayed.
+//!
+//! Handles are clones sharing one file, so a test keeps one while a
+//! [`DiskManager`](crate::disk::DiskManager) owns another.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use crate::disk::Storage;
+
+/// Writes are torn at a multiple of this many bytes.
+pub const SECTOR_SIZE: usize = 512;
+
+/// Operations to fail, counted together from 0 over reads, writes and
+/// syncs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Faults {
+    /// Fails this operation with an error, leaving the file as it was.
+    pub fail_at: Option<u64>,
+    /// Fails this operation and every later one, as if the process died
+    /// on reaching it. A write crashed on may be torn.
+    pub crash_at: Option<u64>,
+}
+
+#[derive(Debug)]
+struct State {
+    durable: Vec<u8>,
+    current: Vec<u8>,
+    /// Writes since the last sync, oldest first.
+    pending: Vec<(usize, Vec<u8>)>,
+    faults: Faults,
+    operations: u64,
+    crashed: bool,
+    rng: u64,
+}
+
+impl State {
+    /// xorshift64*, which is plenty for picking faults.
+    fn random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn coin(&mut self) -> bool {
+        self.random() >> 63 == 0
+    }
+
+    /// Counts an operation, failing it if it is one of the faults.
+    fn begin(&mut self) -> io::Result<u64> {
+        let operation = self.operations;
+        self.operations += 1;
+        if self.crashed || self.faults.crash_at == Some(operation) {
+            self.crashed = true;
+            return Err(io::Error::other("simulated crash"));
+        }
+        if self.faults.fail_at == Some(operation) {
+            return Err(io::Error::other("simulated I/O error"));
+        }
+        Ok(operation)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) {
+        let end = offset + data.len();
+        if self.current.len() < end {
+            self.current.resize(end, 0);
+        }
+        self.current[offset..end].copy_from_slice(data);
+        self.pending.push((offset, data.to_vec()));
+    }
+
+    /// `data` cut off at a random sector.
+    fn torn<'a>(&mut self, data: &'a [u8]) -> &'a [u8] {
+        let sectors = data.len().div_ceil(SECTOR_SIZE).max(1) as u64;
+        let kept = (self.random() % sectors) as usize * SECTOR_SIZE;
+        &data[..kept]
+    }
+}
+
+/// A file in memory whose faults a test chooses; see the [module](self).
+#[derive(Debug, Clone)]
+pub struct SimFile {
+    state: Arc<Mutex<State>>,
+}
+
+impl SimFile {
+    /// An empty file making its choices from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self::from_image(vec![], seed)
+    }
+
+    /// A file holding `image`, all of it durable.
+    pub fn from_image(image: Vec<u8>, seed: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                durable: image.clone(),
+                current: image,
+                pending: vec![],
+                faults: Faults::default(),
+                operations: 0,
+                crashed: false,
+                // xorshift never leaves zero.
+                rng: seed | 1,
+            })),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        self.lock().faults = faults;
+    }
+
+    /// Operations so far, including failed ones.
+    pub fn operations(&self) -> u64 {
+        self.lock().operations
+    }
+
+    /// Whether operations fail for good since a [`Faults::crash_at`].
+    pub fn has_crashed(&self) -> bool {
+        self.lock().crashed
+    }
+
+    /// Whether every write so far is durable.
+    pub fn is_synced(&self) -> bool {
+        self.lock().pending.is_empty()
+    }
+
+    /// The bytes reads would see now.
+    pub fn image(&self) -> Vec<u8> {
+        self.lock().current.clone()
+    }
+
+    /// Loses power: returns what the disk holds afterwards, as a new file
+    /// with its own seed. Each write since the last sync is kept or lost
+    /// at random, and the last one kept may be torn. This file is left
+    /// crashed.
+    pub fn crash(&self) -> SimFile {
+        let mut state = self.lock();
+        state.crashed = true;
+        let mut image = state.durable.clone();
+        let pending = std::mem::take(&mut state.pending);
+        let kept: Vec<_> = pending.iter().filter(|_| state.coin()).collect();
+        for (i, (offset, data)) in kept.iter().enumerate() {
+            let data = if i + 1 == kept.len() && state.coin() {
+                state.torn(data)
+            } else {
+                data
+            };
+            let end = offset + data.len();
+            if image.len() < end {
+                image.resize(end, 0);
+            }
+            image[*offset..end].copy_from_slice(data);
+        }
+        state.pending = pending;
+        let seed = state.random();
+        SimFile::from_image(image, seed)
+    }
+}
+
+impl Storage for SimFile {
+    fn size(&mut self) -> io::Result<u64> {
+        let mut state = self.lock();
+        state.begin()?;
+        Ok(state.current.len() as u64)
+    }
+
+    fn read_at(&mut self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+        let mut state = self.lock();
+        state.begin()?;
+        let offset = offset as usize;
+        let Some(bytes) = state.current.get(offset..offset + data.len()) else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        data.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut state = self.lock();
+        let operation = state.operations;
+        if let Err(e) = state.begin() {
+            if state.faults.crash_at == Some(operation) {
+                let torn = state.torn(data).to_vec();
+                state.write(offset as usize, &torn);
+            }
+            return Err(e);
+        }
+        state.write(offset as usize, data);
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let mut state = self.lock();
+        state.begin()?;
+        state.durable = state.current.clone();
+        state.pending.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, Database, Options};
+    use crate::disk::DiskManager;
+
+    const ROWS_PER_COMMIT: i64 = 20;
+
+    fn options() -> Options {
+        Options {
+            pool_size: 16,
+            ..Options::default()
+        }
+    }
+
+    fn open(file: &SimFile) -> Result<Database, database::Error> {
+        Database::with_disk(DiskManager::with_storage(file.clone())?, options())
+    }
+
+    /// A file kept beside `of`, such as its journal of full-page writes,
+    /// which stops with it when it crashes, as the two would on losing
+    /// power together.
+    struct Beside {
+        file: SimFile,
+        of: SimFile,
+    }
+
+    impl Beside {
+        fn alive(&self) -> io::Result<()> {
+            match self.of.has_crashed() {
+                true => Err(io::Error::other("simulated crash")),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl Storage for Beside {
+        fn size(&mut self) -> io::Result<u64> {
+            self.alive()?;
+            self.file.size()
+        }
+
+        fn read_at(&mut self, offset: u64, data: &mut [u8]) -> io::Result<()> {
+            self.alive()?;
+            self.file.read_at(offset, data)
+        }
+
+        fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+            self.alive()?;
+            self.file.write_at(offset, data)
+        }
+
+        fn sync(&mut self) -> io::Result<()> {
+            self.alive()?;
+            self.file.sync()
+        }
+    }
+
+    fn open_with_journal(
+        file: &SimFile,
+        journal: &SimFile,
+        compress: bool,
+    ) -> Result<Database, database::Error> {
+        let journal = Beside {
+            file: journal.clone(),
+            of: file.clone(),
+        };
+        let disk = DiskManager::with_storage(file.clone())?.with_full_page_writes(journal)?;
+        Database::with_disk(disk.with_journal_compression(compress), options())
+    }
+
+    fn open_with_doublewrite(file: &SimFile, area: &SimFile) -> Result<Database, database::Error> {
+        let area = Beside {
+            file: area.clone(),
+            of: file.clone(),
+        };
+        let disk = DiskManager::with_storage(file.clone())?.with_doublewrite(area)?;
+        Database::with_disk(disk, options())
+    }
+
+    /// Commits rows `0..` a batch at a time until the storage fails,
+    /// returning how many committed.
+    fn workload(db: &mut Database) -> i64 {
+        let mut committed = 0;
+        loop {
+            let values: Vec<_> = (committed..committed + ROWS_PER_COMMIT)
+                .map(|id| format!("({id}, '{}')", "x".repeat(id as usize % 300)))
+                .collect();
+            let batch = db.transaction(|db| {
+                db.execute(&format!("INSERT INTO t VALUES {}", values.join(", ")))
+            });
+            if batch.is_err() {
+                return committed;
+            }
+            committed += ROWS_PER_COMMIT;
+        }
+    }
+
+    /// Without full-page writes or a doublewrite area, as with both
+    /// options off, a crash may tear a page, which the check then finds.
+    #[test]
+    fn test_crash_and_recover() {
+        let setup = SimFile::new(0);
+        let mut db = open(&setup).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, padding TEXT)")
+            .unwrap();
+        db.close().unwrap();
+        let image = setup.image();
+
+        let (mut refused, mut recovered) = (0, 0);
+        for seed in 0..200 {
+            let file = SimFile::from_image(image.clone(), seed);
+            // Crash somewhere in the first few hundred operations.
+            file.set_faults(Faults {
+                fail_at: None,
+                crash_at: Some(seed * 7 % 400),
+            });
+            let committed = match open(&file) {
+                Ok(mut db) => workload(&mut db),
+                Err(_) => 0,
+            };
+            assert!(file.has_crashed());
+            let synced = file.is_synced();
+
+            let after = file.crash();
+            let mut db = match open(&after) {
+                Ok(db) => db,
+                Err(e) => {
+                    // Damage found is damage reported, but a crash with
+                    // every write durable must lose nothing.
+                    assert!(!synced, "seed {seed}: {e}");
+                    refused += 1;
+                    continue;
+                }
+            };
+            recovered += 1;
+            let ids: Vec<(i64,)> = db.query_as("SELECT id FROM t ORDER BY id").unwrap();
+            let rows = ids.len() as i64;
+            assert!(
+                ids.iter().map(|&(id,)| id).eq(0..rows),
+                "seed {seed}: rows missing"
+            );
+            assert!(
+                rows == committed || rows == committed + ROWS_PER_COMMIT,
+                "seed {seed}: {rows} rows after committing {committed}"
+            );
+        }
+        assert!(refused + recovered == 200 && recovered > 0);
+    }
+
+    #[test]
+    fn test_full_page_writes_survive_every_crash() {
+        let setup = SimFile::new(0);
+        let mut db = open(&setup).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, padding TEXT)")
+            .unwrap();
+        db.close().unwrap();
+        let image = setup.image();
+
+        let mut replayed = [0; 2];
+        for seed in 0..200 {
+            let file = SimFile::from_image(image.clone(), seed);
+            let journal = SimFile::new(seed);
+            file.set_faults(Faults {
+                fail_at: None,
+                crash_at: Some(seed * 7 % 400),
+            });
+            // Every other run compresses the journal.
+            let compress = seed % 2 == 1;
+            let committed = match open_with_journal(&file, &journal, compress) {
+                Ok(mut db) => workload(&mut db),
+                Err(_) => 0,
+            };
+            assert!(file.has_crashed());
+
+            let (after, journal) = (file.crash(), journal.crash());
+            let mut disk = DiskManager::with_storage(after.clone()).unwrap();
+            replayed[usize::from(compress)] +=
+                usize::from(disk.replay_journal(journal.clone()).unwrap() > 0);
+            drop(disk);
+            let mut db = open_with_journal(&after, &journal, compress)
+                .unwrap_or_else(|e| panic!("seed {seed}: {e}"));
+            let ids: Vec<(i64,)> = db.query_as("SELECT id FROM t ORDER BY id").unwrap();
+            let rows = ids.len() as i64;
+            assert!(
+                ids.iter().map(|&(id,)| id).eq(0..rows),
+                "seed {seed}: rows missing"
+            );
+            assert!(
+                rows == committed || rows == committed + ROWS_PER_COMMIT,
+                "seed {seed}: {rows} rows after committing {committed}"
+            );
+        }
+        assert!(replayed.iter().all(|&replayed| replayed > 0));
+    }
+
+    #[test]
+    fn test_doublewrite_repairs_torn_pages() {
+        let setup = SimFile::new(0);
+        let mut db = open(&setup).unwrap();
+        db.execute("CREATE TABLE t (id INT PRIMARY KEY, padding TEXT)")
+            .unwrap();
+        db.close().unwrap();
+        let image = setup.image();
+
+        let mut replayed = 0;
+        for seed in 0..200 {
+            let file = SimFile::from_image(image.clone(), seed);
+            let area = SimFile::new(seed);
+            file.set_faults(Faults {
+                fail_at: None,
+                crash_at: Some(seed * 7 % 400),
+            });
+            let committed = match open_with_doublewrite(&file, &area) {
+                Ok(mut db) => workload(&mut db),
+                Err(_) => 0,
+            };
+            assert!(file.has_crashed());
+
+            let (after, area) = (file.crash(), area.crash());
+            let mut disk = DiskManager::with_storage(after.clone()).unwrap();
+            replayed += usize::from(disk.replay_doublewrite(area.clone()).unwrap() > 0);
+            drop(disk);
+            // Pages torn in place have a copy whole in the area.
+            let mut db =
+                open_with_doublewrite(&after, &area).unwrap_or_else(|e| panic!("seed {seed}: {e}"));
+            let ids: Vec<(i64,)> = db.query_as("SELECT id FROM t ORDER BY id").unwrap();
+            let rows = ids.len() as i64;
+            assert!(
+                ids.iter().map(|&(id,)| id).eq(0..rows),
+                "seed {seed}: rows missing"
+            );
+            assert!(
+                rows == committed || rows == committed + ROWS_PER_COMMIT,
+                "seed {seed}: {rows} rows after committing {committed}"
+            );
+        }
+        assert!(replayed > 0);
+    }
+}
@@ -0,0 +1,281 @@
+//! Slotted page layout for variable-length records.
+//!
+//! ```text
+//! +--------+------------------+-----------+-------------------+
+//! | header | slot pointers -> |   free    | <- record data    |
+//! +--------+------------------+-----------+-------------------+
+//! ```
+//!
+//! The header holds the number of slots and the offset where free space
+//! ends; each pointer is an `(offset, len)` pair relative to the body.
+
+use std::ops::Range;
+
+pub(crate) const HEADER_SIZE: usize = 4;
+pub(crate) const POINTER_SIZE: usize = 4;
+
+pub struct Slotted<B> {
+    bytes: B,
+}
+
+impl<B: AsRef<[u8]>> Slotted<B> {
+    pub fn new(bytes: B) -> Self {
+        assert!(bytes.as_ref().len() > HEADER_SIZE);
+        Self { bytes }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.bytes.as_ref().len() - HEADER_SIZE
+    }
+
+    pub fn num_slots(&self) -> usize {
+        self.read_u16(0) as usize
+    }
+
+    pub fn free_space(&self) -> usize {
+        self.free_space_offset() - self.pointers_size()
+    }
+
+    pub fn data(&self, index: usize) -> &[u8] {
+        let range = self.data_range(index);
+        &self.bytes.as_ref()[range]
+    }
+
+    /// Byte range of the record at `index`, relative to the start of the
+    /// slotted area (header included).
+    pub fn data_range(&self, index: usize) -> Range<usize> {
+        assert!(index < self.num_slots(), "slot index out of range");
+        let (offset, len) = self.pointer(index);
+        HEADER_SIZE + offset..HEADER_SIZE + offset + len
+    }
+
+    fn free_space_offset(&self) -> usize {
+        self.read_u16(2) as usize
+    }
+
+    fn pointers_size(&self) -> usize {
+        POINTER_SIZE * self.num_slots()
+    }
+
+    fn pointer(&self, index: usize) -> (usize, usize) {
+        let base = HEADER_SIZE + POINTER_SIZE * index;
+        (
+            self.read_u16(base) as usize,
+            self.read_u16(base + 2) as usize,
+        )
+    }
+
+    fn read_u16(&self, at: usize) -> u16 {
+        let bytes = self.bytes.as_ref();
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Slotted<B> {
+    pub fn initialize(&mut self) {
+        let capacity = self.capacity() as u16;
+        self.write_u16(0, 0);
+        self.write_u16(2, capacity);
+    }
+
+    /// Inserts an empty record of `len` bytes at `index`, shifting later
+    /// slots up by one. Returns `None` when the page is full.
+    pub fn insert(&mut self, index: usize, len: usize) -> Option<()> {
+        if self.free_space() < POINTER_SIZE + len {
+            return None;
+        }
+        let num_slots = self.num_slots();
+        assert!(index <= num_slots);
+        let start = HEADER_SIZE + POINTER_SIZE * index;
+        let end = HEADER_SIZE + POINTER_SIZE * num_slots;
+        self.bytes
+            .as_mut()
+            .copy_within(start..end, start + POINTER_SIZE);
+        let offset = self.free_space_offset() - len;
+        self.write_u16(2, offset as u16);
+        self.write_u16(0, (num_slots + 1) as u16);
+        self.set_pointer(index, offset, len);
+        Some(())
+    }
+
+    /// Removes the slot at `index`, shifting later slots down by one.
+    pub fn remove(&mut self, index: usize) {
+        self.resize(index, 0).unwrap();
+        let num_slots = self.num_slots();
+        let start = HEADER_SIZE + POINTER_SIZE * (index + 1);
+        let end = HEADER_SIZE + POINTER_SIZE * num_slots;
+        self.bytes
+            .as_mut()
+            .copy_within(start..end, start - POINTER_SIZE);
+        self.write_u16(0, (num_slots - 1) as u16);
+    }
+
+    /// Changes the length of the record at `index`, keeping its trailing
+    /// bytes. Returns `None` when growing it does not fit.
+    pub fn resize(&mut self, index: usize, len_new: usize) -> Option<()> {
+        let (offset, len) = self.pointer(index);
+        let len_incr = len_new as isize - len as isize;
+        if len_incr == 0 {
+            return Some(());
+        }
+        if len_incr > self.free_space() as isize {
+            return None;
+        }
+        let free_space_offset = self.free_space_offset();
+        let new_free_space_offset = (free_space_offset as isize - len_incr) as usize;
+        self.write_u16(2, new_free_space_offset as u16);
+        self.body_mut()
+            .copy_within(free_space_offset..offset, new_free_space_offset);
+        for i in 0..self.num_slots() {
+            let (slot_offset, slot_len) = self.pointer(i);
+            // Records below this one move with it; an empty record may
+            // share its offset with the record above, which stays.
+            if i == index || slot_offset + slot_len <= offset {
+                self.set_pointer(i, (slot_offset as isize - len_incr) as usize, slot_len);
+            }
+        }
+        let (offset, _) = self.pointer(index);
+        self.set_pointer(index, offset, len_new);
+        Some(())
+    }
+
+    pub fn data_mut(&mut self, index: usize) -> &mut [u8] {
+        let range = self.data_range(index);
+        &mut self.bytes.as_mut()[range]
+    }
+
+    fn set_pointer(&mut self, index: usize, offset: usize, len: usize) {
+        let base = HEADER_SIZE + POINTER_SIZE * index;
+        self.write_u16(base, offset as u16);
+        self.write_u16(base + 2, len as u16);
+    }
+
+    fn body_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes.as_mut()[HEADER_SIZE..]
+    }
+
+    fn write_u16(&mut self, at: usize, value: u16) {
+        self.bytes.as_mut()[at..at + 2].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prop::{self, Rng};
+
+    #[test]
+    fn test_insert_resize_remove() {
+        let mut page = vec![0u8; 128];
+        let mut slotted = Slotted::new(page.as_mut_slice());
+        slotted.initialize();
+        let insert = |slotted: &mut Slotted<&mut [u8]>, index: usize, buf: &[u8]| {
+            slotted.insert(index, buf.len()).unwrap();
+            slotted.data_mut(index).copy_from_slice(buf);
+        };
+        insert(&mut slotted, 0, b"world");
+        insert(&mut slotted, 0, b"hello");
+        insert(&mut slotted, 1, b", ");
+        insert(&mut slotted, 3, b"!");
+        let joined: Vec<u8> = (0..slotted.num_slots())
+            .flat_map(|i| slotted.data(i).to_vec())
+            .collect();
+        assert_eq!(b"hello, world!", joined.as_slice());
+
+        slotted.resize(1, 4).unwrap();
+        slotted.data_mut(1).copy_from_slice(b" my ");
+        assert_eq!(b"hello", slotted.data(0));
+        assert_eq!(b" my ", slotted.data(1));
+        assert_eq!(b"world", slotted.data(2));
+
+        slotted.remove(0);
+        assert_eq!(3, slotted.num_slots());
+        assert_eq!(b" my ", slotted.data(0));
+        assert_eq!(b"!", slotted.data(2));
+    }
+
+    #[test]
+    fn test_full_page() {
+        let mut page = vec![0u8; 32];
+        let mut slotted = Slotted::new(page.as_mut_slice());
+        slotted.initialize();
+        assert_eq!(28, slotted.free_space());
+        assert!(slotted.insert(0, 24).is_some());
+        assert!(slotted.insert(1, 1).is_none());
+        assert!(slotted.resize(0, 25).is_none());
+    }
+
+    /// Slot indexes are reduced modulo the slots there are when applied,
+    /// so a shrunk sequence stays valid.
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(usize, Vec<u8>),
+        Remove(usize),
+        Update(usize, Vec<u8>),
+    }
+
+    fn generate(rng: &mut Rng) -> Vec<Op> {
+        (0..rng.below(200))
+            .map(|_| match rng.below(4) {
+                0 => Op::Remove(rng.below(64)),
+                1 => Op::Update(rng.below(64), rng.bytes(40)),
+                _ => Op::Insert(rng.below(64), rng.bytes(40)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_against_model() {
+        prop::check(256, generate, |ops| {
+            let mut page = vec![0u8; 256];
+            let mut slotted = Slotted::new(page.as_mut_slice());
+            slotted.initialize();
+            let mut model: Vec<Vec<u8>> = vec![];
+            let free = |model: &[Vec<u8>]| {
+                let used: usize = model.iter().map(|r| POINTER_SIZE + r.len()).sum();
+                252 - used
+            };
+            for op in ops {
+                match op {
+                    Op::Insert(index, data) => {
+                        let index = index % (model.len() + 1);
+                        let fits = POINTER_SIZE + data.len() <= free(&model);
+                        if slotted.insert(index, data.len()).is_some() != fits {
+                            return Err(format!("{op:?}: fits is {fits}"));
+                        }
+                        if fits {
+                            slotted.data_mut(index).copy_from_slice(data);
+                            model.insert(index, data.clone());
+                        }
+                    }
+                    Op::Remove(_) | Op::Update(..) if model.is_empty() => {}
+                    Op::Remove(index) => {
+                        let index = index % model.len();
+                        slotted.remove(index);
+                        model.remove(index);
+                    }
+                    Op::Update(index, data) => {
+                        let index = index % model.len();
+                        let fits = data.len() <= model[index].len() + free(&model);
+                        if slotted.resize(index, data.len()).is_some() != fits {
+                            return Err(format!("{op:?}: fits is {fits}"));
+                        }
+                        if fits {
+                            slotted.data_mut(index).copy_from_slice(data);
+                            model[index] = data.clone();
+                        }
+                    }
+                }
+                if slotted.free_space() != free(&model) {
+                    return Err(format!("free space after {op:?}"));
+                }
+                let records: Vec<&[u8]> =
+                    (0..slotted.num_slots()).map(|i| slotted.data(i)).collect();
+                if records != model.iter().map(Vec::as_slice).collect::<Vec<_>>() {
+                    return Err(format!("records after {op:?}: {records:?}"));
+                }
+            }
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,594 @@
+//! Syntax tree produced by the parser. Names are as written, with unquoted
+//! identifiers lowercased; nothing here has been checked against the
+//! catalog yet.
+
+use crate::catalog::{
+    PartitionMethod, Persistence, Privileges, RowImage, TriggerEvent, TriggerTiming,
+};
+use crate::expr::{BinaryOp, UnaryOp};
+pub use crate::lock::{LockMode, LockWait};
+use crate::value::DataType;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Select(Box<Query>),
+    /// `query AS OF LSN n` or `query AS OF TIMESTAMP seconds`.
+    AsOf {
+        query: Box<Query>,
+        point: AsOfPoint,
+    },
+    Insert(Insert),
+    Update(Update),
+    Delete(Delete),
+    CreateTable(CreateTable),
+    /// `CREATE TABLE [IF NOT EXISTS] name PARTITION OF parent FOR VALUES
+    /// bound`.
+    CreatePartition {
+        name: String,
+        if_not_exists: bool,
+        parent: String,
+        bound: PartitionBoundSpec,
+    },
+    /// `ALTER TABLE table ATTACH PARTITION partition FOR VALUES bound`.
+    AttachPartition {
+        table: String,
+        partition: String,
+        bound: PartitionBoundSpec,
+    },
+    /// `ALTER TABLE table DETACH PARTITION partition`.
+    DetachPartition {
+        table: String,
+        partition: String,
+    },
+    /// `ALTER TABLE table SET TTL (column [, seconds])`, or with `ttl`
+    /// `None`, `ALTER TABLE table DROP TTL`.
+    SetTtl {
+        table: String,
+        ttl: Option<TtlSpec>,
+    },
+    CreateIndex(CreateIndex),
+    DropTable {
+        name: String,
+        if_exists: bool,
+    },
+    DropIndex {
+        name: String,
+        if_exists: bool,
+    },
+    CreateMaterializedView(CreateMaterializedView),
+    /// `REFRESH MATERIALIZED VIEW name`.
+    RefreshMaterializedView {
+        name: String,
+    },
+    DropMaterializedView {
+        name: String,
+        if_exists: bool,
+    },
+    CreateTrigger(CreateTrigger),
+    DropTrigger {
+        name: String,
+        if_exists: bool,
+    },
+    CreatePolicy(CreatePolicy),
+    /// `DROP POLICY [IF EXISTS] name ON table`.
+    DropPolicy {
+        name: String,
+        table: String,
+        if_exists: bool,
+    },
+    /// `ANALYZE [table]`; without a table, every table is analyzed.
+    Analyze {
+        table: Option<String>,
+    },
+    /// `REINDEX INDEX name` or, for every index of a table, `REINDEX
+    /// TABLE name`.
+    Reindex {
+        name: String,
+        table: bool,
+    },
+    /// `EXPLAIN [ANALYZE] statement`.
+    Explain {
+        analyze: bool,
+        statement: Box<Statement>,
+    },
+    Transaction(TransactionControl),
+    /// `SET name TO value`. A `value` of `None` is DEFAULT, as from
+    /// `RESET name`; a `name` of `None` is `RESET ALL`.
+    Set {
+        name: Option<String>,
+        value: Option<String>,
+    },
+    /// `SHOW name`, or `SHOW ALL` without a name.
+    Show {
+        name: Option<String>,
+    },
+    /// `COPY table [(columns)] FROM 'file' [WITH (options)]`.
+    CopyFrom {
+        table: String,
+        /// `None` means all columns in table order.
+        columns: Option<Vec<String>>,
+        file: String,
+        options: Vec<CopyOption>,
+    },
+    /// `COPY table [(columns)] TO 'file'` or `COPY (query) TO 'file'`,
+    /// with options as for [`Statement::CopyFrom`].
+    CopyTo {
+        source: CopySource,
+        file: String,
+        options: Vec<CopyOption>,
+    },
+    /// `ATTACH [DATABASE] 'path' AS name`.
+    Attach {
+        path: String,
+        name: String,
+    },
+    /// `DETACH [DATABASE] name`.
+    Detach {
+        name: String,
+    },
+    /// `BACKUP TO 'path' [INCREMENTAL FROM 'full' [, 'increment' ...]]`.
+    Backup {
+        path: String,
+        chain: Vec<String>,
+    },
+    /// `NOTIFY channel [, 'payload']`; the payload is empty without one.
+    Notify {
+        channel: String,
+        payload: String,
+    },
+    /// `LISTEN channel`.
+    Listen {
+        channel: String,
+    },
+    /// `UNLISTEN channel`, or `UNLISTEN *` for every channel.
+    Unlisten {
+        channel: Option<String>,
+    },
+    CreateUser {
+        name: String,
+        options: UserOptions,
+    },
+    AlterUser {
+        name: String,
+        options: UserOptions,
+    },
+    DropUser {
+        name: String,
+        if_exists: bool,
+    },
+    Grant(Grant),
+    Revoke(Grant),
+}
+
+/// The state of the database an AS OF query reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsOfPoint {
+    /// A log sequence number.
+    Lsn(u64),
+    /// Seconds since the Unix epoch.
+    Timestamp(u64),
+}
+
+/// The options of CREATE USER and ALTER USER; `None` where the statement
+/// says nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserOptions {
+    /// `PASSWORD 'text'`, or `PASSWORD NULL` for none.
+    pub password: Option<Option<String>>,
+    /// `SUPERUSER` or `NOSUPERUSER`.
+    pub superuser: Option<bool>,
+}
+
+/// `GRANT privileges ON [TABLE] tables TO users`, or REVOKE with FROM.
+/// SELECT may name columns, as in `SELECT (a, b)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grant {
+    /// Privileges on the whole of each table.
+    pub privileges: Privileges,
+    /// Columns of each table SELECT is for alone.
+    pub columns: Vec<String>,
+    pub tables: Vec<String>,
+    pub users: Vec<String>,
+}
+
+/// `BEGIN`, `COMMIT` or `ROLLBACK`, or one of their synonyms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionControl {
+    Begin,
+    Commit,
+    Rollback,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub with: Option<With>,
+    pub select: Select,
+    /// `UNION [ALL]` arms after `select`, combined left to right.
+    pub unions: Vec<Union>,
+    /// Applies to the combined rows when there are unions.
+    pub order_by: Vec<OrderByExpr>,
+    pub limit: Option<Expr>,
+    pub offset: Option<Expr>,
+    /// `FOR UPDATE` or another locking clause.
+    pub locking: Option<Locking>,
+}
+
+/// `FOR UPDATE`, `FOR NO KEY UPDATE`, `FOR SHARE` or `FOR KEY SHARE`,
+/// with what to do about rows locked already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locking {
+    pub mode: LockMode,
+    pub wait: LockWait,
+}
+
+/// `WITH [RECURSIVE] cte, ...`. Each CTE can see those before it and,
+/// under RECURSIVE, itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct With {
+    pub recursive: bool,
+    pub ctes: Vec<Cte>,
+}
+
+/// `name [(columns)] AS [[NOT] MATERIALIZED] (query)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cte {
+    pub name: String,
+    /// Renames the leading columns of the query; empty to keep its names.
+    pub columns: Vec<String>,
+    /// `None` leaves the choice to the planner.
+    pub materialized: Option<bool>,
+    pub query: Box<Query>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Union {
+    pub all: bool,
+    pub select: Select,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub distinct: bool,
+    pub projection: Vec<SelectItem>,
+    /// Comma-separated FROM items, each possibly a tree of joins.
+    pub from: Vec<TableRef>,
+    pub selection: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    /// `*`
+    Wildcard,
+    /// `t.*`
+    QualifiedWildcard(String),
+    Expr {
+        expr: Expr,
+        alias: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableRef {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    Subquery {
+        query: Box<Query>,
+        alias: String,
+    },
+    Join {
+        left: Box<TableRef>,
+        right: Box<TableRef>,
+        kind: JoinKind,
+        /// `None` only for cross joins.
+        on: Option<Expr>,
+    },
+    /// `unnest(expr) [AS] alias [(column)]`: a row per element of an
+    /// array, which may refer to the tables before it in the FROM list.
+    Unnest {
+        expr: Expr,
+        alias: Option<String>,
+        column: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Cross,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByExpr {
+    pub expr: Expr,
+    pub descending: bool,
+    /// `NULLS FIRST` or `NULLS LAST`, if given.
+    pub nulls_first: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A column reference, optionally qualified: `["t", "a"]` for `t.a`.
+    Identifier(Vec<String>),
+    Literal(Literal),
+    /// `$n`, counting from 1.
+    Parameter(usize),
+    Unary {
+        op: UnaryOp,
+        expr: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    IsNull {
+        expr: Box<Expr>,
+        negated: bool,
+    },
+    Between {
+        expr: Box<Expr>,
+        low: Box<Expr>,
+        high: Box<Expr>,
+        negated: bool,
+    },
+    InList {
+        expr: Box<Expr>,
+        list: Vec<Expr>,
+        negated: bool,
+    },
+    Like {
+        expr: Box<Expr>,
+        pattern: Box<Expr>,
+        negated: bool,
+    },
+    Cast {
+        expr: Box<Expr>,
+        data_type: DataType,
+    },
+    Function(Function),
+    /// `ARRAY[...]`.
+    Array(Vec<Expr>),
+    /// `expr op ANY (array)`, or `op ALL (array)` when `all` is set.
+    Any {
+        op: BinaryOp,
+        expr: Box<Expr>,
+        array: Box<Expr>,
+        all: bool,
+    },
+    /// `expr COLLATE collation`.
+    Collate {
+        expr: Box<Expr>,
+        collation: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub args: Vec<Expr>,
+    /// `COUNT(*)`.
+    pub star: bool,
+    pub distinct: bool,
+    pub over: Option<WindowSpec>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowSpec {
+    pub partition_by: Vec<Expr>,
+    pub order_by: Vec<OrderByExpr>,
+    pub frame: Option<WindowFrame>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameUnits {
+    Rows,
+    Range,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(u64),
+    CurrentRow,
+    Following(u64),
+    UnboundedFollowing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowFrame {
+    pub units: FrameUnits,
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopySource {
+    Table {
+        name: String,
+        columns: Option<Vec<String>>,
+    },
+    Query(Box<Query>),
+}
+
+/// `name [value]` in the options of COPY; the value is as written,
+/// whether a word, a string or a number.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyOption {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertSource {
+    Values(Vec<Vec<Expr>>),
+    Query(Box<Query>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Insert {
+    pub table: String,
+    /// Target columns; `None` means all columns in table order.
+    pub columns: Option<Vec<String>>,
+    pub source: InsertSource,
+    pub on_conflict: Option<OnConflict>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnConflict {
+    /// Columns of the unique index the clause applies to, if named.
+    pub columns: Option<Vec<String>>,
+    pub action: ConflictAction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConflictAction {
+    DoNothing,
+    DoUpdate {
+        assignments: Vec<Assignment>,
+        selection: Option<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub column: String,
+    pub value: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update {
+    pub table: String,
+    pub assignments: Vec<Assignment>,
+    pub selection: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delete {
+    pub table: String,
+    pub selection: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnDef {
+    pub name: String,
+    pub data_type: DataType,
+    pub not_null: bool,
+    pub primary_key: bool,
+    pub unique: bool,
+    pub collation: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableConstraint {
+    PrimaryKey(Vec<String>),
+    Unique(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTable {
+    pub name: String,
+    /// `CREATE TEMP TABLE` or `CREATE UNLOGGED TABLE`.
+    pub persistence: Persistence,
+    pub if_not_exists: bool,
+    pub columns: Vec<ColumnDef>,
+    pub constraints: Vec<TableConstraint>,
+    /// `PARTITION BY {RANGE | HASH} (column)`.
+    pub partition_by: Option<(PartitionMethod, String)>,
+}
+
+/// The bound after `FOR VALUES`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PartitionBoundSpec {
+    /// `FROM (value) TO (value)`; `None` is MINVALUE below and MAXVALUE
+    /// above.
+    Range {
+        from: Option<Box<Expr>>,
+        to: Option<Box<Expr>>,
+    },
+    /// `WITH (MODULUS modulus, REMAINDER remainder)`.
+    Hash { modulus: u64, remainder: u64 },
+}
+
+/// The time to live after `SET TTL`: the column holding a time, and the
+/// seconds a row lives after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TtlSpec {
+    pub column: String,
+    pub duration: Option<u64>,
+}
+
+/// `CREATE MATERIALIZED VIEW [IF NOT EXISTS] name AS query`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateMaterializedView {
+    pub name: String,
+    pub if_not_exists: bool,
+    pub query: Box<Query>,
+    /// The text of the query, normalized, to keep in the catalog and
+    /// parse again on refresh.
+    pub definition: String,
+}
+
+/// `CREATE TRIGGER name {BEFORE | AFTER} event [OR event ...] ON table
+/// [FOR EACH ROW] action`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateTrigger {
+    pub name: String,
+    pub table: String,
+    pub timing: TriggerTiming,
+    pub events: Vec<TriggerEvent>,
+    pub action: TriggerBody,
+}
+
+/// `CREATE POLICY name ON table [TO {users | PUBLIC}] USING (condition)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatePolicy {
+    pub name: String,
+    pub table: String,
+    /// Empty for PUBLIC, as without TO.
+    pub users: Vec<String>,
+    pub condition: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerBody {
+    /// An INSERT, UPDATE or DELETE as normalized text, in which each
+    /// `new.column` and `old.column` became parameter `$n` for the `n`th
+    /// entry of `references`.
+    Statement {
+        definition: String,
+        references: Vec<(RowImage, String)>,
+    },
+    /// `EXECUTE FUNCTION name()`.
+    Function(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreateIndex {
+    /// Chosen from the table and keys when omitted.
+    pub name: Option<String>,
+    pub table: String,
+    /// Columns, or expressions of them in parentheses, each with the
+    /// order it is kept in.
+    pub keys: Vec<OrderByExpr>,
+    /// `WHERE` clause of a partial index.
+    pub predicate: Option<Expr>,
+    pub unique: bool,
+    /// `CONCURRENTLY`: built while the table stays writable.
+    pub concurrently: bool,
+    pub if_not_exists: bool,
+}
@@ -0,0 +1,103 @@
+//! Planner hints, written in a `/*+ ... */` comment at the start of a
+//! statement, as in `/*+ IndexScan(t t_pkey) NoHashJoin */ SELECT ...`.
+
+use super::lexer::{tokenize, Token};
+use super::{Error, Position};
+
+/// A hint name with its arguments. Unquoted words are lowercased, as
+/// elsewhere in SQL; what the hints mean is up to the planner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hint {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/// The hints leading `sql`, or following its EXPLAIN [ANALYZE]. Hint
+/// comments anywhere else are ignored like other comments.
+pub fn parse_hints(sql: &str) -> Result<Vec<Hint>, Error> {
+    for (token, position) in tokenize(sql)? {
+        match token {
+            Token::Hint(hint) => return hints(&hint, position),
+            token if token.is_keyword("explain") || token.is_keyword("analyze") => {}
+            _ => break,
+        }
+    }
+    Ok(vec![])
+}
+
+/// Parses the body of a hint comment: names, each optionally followed by
+/// a parenthesized list of words, numbers or strings.
+fn hints(body: &str, position: Position) -> Result<Vec<Hint>, Error> {
+    let invalid = || Error::InvalidHint { position };
+    let mut tokens = tokenize(body)
+        .map_err(|_| invalid())?
+        .into_iter()
+        .peekable();
+    let mut hints = vec![];
+    loop {
+        let name = match tokens.next().map(|(token, _)| token) {
+            Some(Token::Word { value, .. }) => value,
+            Some(Token::Eof) => return Ok(hints),
+            _ => return Err(invalid()),
+        };
+        let mut args = vec![];
+        if tokens
+            .next_if(|(token, _)| *token == Token::LParen)
+            .is_some()
+        {
+            loop {
+                match tokens.next().map(|(token, _)| token) {
+                    Some(
+                        Token::Word { value, .. } | Token::Number(value) | Token::String(value),
+                    ) => args.push(value),
+                    Some(Token::Comma) if !args.is_empty() => {}
+                    Some(Token::RParen) => break,
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+        hints.push(Hint { name, args });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hint(name: &str, args: &[&str]) -> Hint {
+        Hint {
+            name: name.into(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_hints() {
+        assert_eq!(
+            vec![
+                hint("indexscan", &["t", "t_pkey"]),
+                hint("nohashjoin", &[]),
+                hint("set", &["random_page_cost", "1.5"]),
+            ],
+            parse_hints(
+                "/*+ IndexScan(t t_pkey) NoHashJoin\n Set(random_page_cost, 1.5) */ SELECT * FROM t"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            vec![hint("seqscan", &["T"])],
+            parse_hints("EXPLAIN ANALYZE /*+ SeqScan(\"T\") */ SELECT 1").unwrap()
+        );
+        // Only a leading comment holds hints, and plain comments do not.
+        assert!(parse_hints("SELECT /*+ SeqScan(t) */ 1")
+            .unwrap()
+            .is_empty());
+        assert!(parse_hints("/* SeqScan(t) */ SELECT 1").unwrap().is_empty());
+        assert_eq!(
+            Err(Error::InvalidHint {
+                position: Position { line: 1, column: 1 }
+            }),
+            parse_hints("/*+ SeqScan(t */ SELECT 1")
+        );
+    }
+}
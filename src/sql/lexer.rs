@@ -0,0 +1,488 @@
+use std::fmt;
+
+use super::{Error, Position};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    /// A keyword or identifier. Unquoted words are lowercased; keywords
+    /// are recognized by the parser, so only unquoted words can be one.
+    Word {
+        value: String,
+        quoted: bool,
+    },
+    /// Numeric literal as written.
+    Number(String),
+    String(String),
+    /// `x'..'` hex literal.
+    Blob(Vec<u8>),
+    /// `$n` placeholder for the n-th parameter, counting from 1.
+    Parameter(usize),
+    /// Body of a `/*+ ... */` comment, which carries planner hints.
+    Hint(String),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Semicolon,
+    Period,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Concat,
+    /// `->`, which takes a member or element of a JSON value.
+    Arrow,
+    /// `->>`, which takes one as text.
+    LongArrow,
+    Eof,
+}
+
+impl Token {
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word { value, quoted: false } if value == keyword)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word {
+                value,
+                quoted: true,
+            } => write!(f, "\"{value}\""),
+            Token::Word { value, .. } => write!(f, "{value:?}"),
+            Token::Number(n) => write!(f, "number {n}"),
+            Token::String(s) => write!(f, "string '{s}'"),
+            Token::Blob(_) => f.write_str("blob literal"),
+            Token::Parameter(n) => write!(f, "parameter ${n}"),
+            Token::Hint(_) => f.write_str("planner hint"),
+            Token::LParen => f.write_str("\"(\""),
+            Token::RParen => f.write_str("\")\""),
+            Token::LBracket => f.write_str("\"[\""),
+            Token::RBracket => f.write_str("\"]\""),
+            Token::Comma => f.write_str("\",\""),
+            Token::Semicolon => f.write_str("\";\""),
+            Token::Period => f.write_str("\".\""),
+            Token::Star => f.write_str("\"*\""),
+            Token::Plus => f.write_str("\"+\""),
+            Token::Minus => f.write_str("\"-\""),
+            Token::Slash => f.write_str("\"/\""),
+            Token::Percent => f.write_str("\"%\""),
+            Token::Eq => f.write_str("\"=\""),
+            Token::NotEq => f.write_str("\"<>\""),
+            Token::Lt => f.write_str("\"<\""),
+            Token::LtEq => f.write_str("\"<=\""),
+            Token::Gt => f.write_str("\">\""),
+            Token::GtEq => f.write_str("\">=\""),
+            Token::Concat => f.write_str("\"||\""),
+            Token::Arrow => f.write_str("\"->\""),
+            Token::LongArrow => f.write_str("\"->>\""),
+            Token::Eof => f.write_str("end of input"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    position: Position,
+}
+
+impl Lexer<'_> {
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let ch = self.chars.next()?;
+        if ch == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(ch)
+    }
+
+    fn bump_if(&mut self, ch: char) -> bool {
+        if self.peek() == Some(ch) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) -> Result<(), Error> {
+        loop {
+            match self.peek() {
+                Some(ch) if ch.is_whitespace() => {
+                    self.bump();
+                }
+                Some('-') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.next() != Some('-') {
+                        return Ok(());
+                    }
+                    while !matches!(self.bump(), Some('\n') | None) {}
+                }
+                Some('/') => {
+                    let mut lookahead = self.chars.clone();
+                    lookahead.next();
+                    if lookahead.next() != Some('*') || lookahead.next() == Some('+') {
+                        return Ok(());
+                    }
+                    let position = self.position;
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.bump_if('/') => break,
+                            Some(_) => {}
+                            None => return Err(Error::UnterminatedComment { position }),
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Reads the body of a literal delimited by `quote`, which doubles to
+    /// escape itself. The opening quote has already been consumed.
+    fn quoted(&mut self, quote: char, position: Position) -> Result<String, Error> {
+        let mut s = String::new();
+        loop {
+            match self.bump() {
+                Some(ch) if ch == quote => {
+                    if !self.bump_if(quote) {
+                        return Ok(s);
+                    }
+                    s.push(quote);
+                }
+                Some(ch) => s.push(ch),
+                None => return Err(Error::UnterminatedString { position }),
+            }
+        }
+    }
+
+    fn number(&mut self, first: char, position: Position) -> Result<Token, Error> {
+        let mut text = String::from(first);
+        while let Some(ch) = self.peek() {
+            let exponent_sign = matches!(ch, '+' | '-') && text.ends_with(['e', 'E']);
+            if ch.is_ascii_alphanumeric() || ch == '.' || exponent_sign {
+                text.push(ch);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        if text.parse::<i64>().is_err() && text.parse::<f64>().is_err() {
+            return Err(Error::InvalidNumber { text, position });
+        }
+        Ok(Token::Number(text))
+    }
+
+    fn next_token(&mut self) -> Result<(Token, Position), Error> {
+        self.skip_whitespace_and_comments()?;
+        let position = self.position;
+        let Some(ch) = self.bump() else {
+            return Ok((Token::Eof, position));
+        };
+        let token = match ch {
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            ',' => Token::Comma,
+            ';' => Token::Semicolon,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '-' if self.bump_if('>') => match self.bump_if('>') {
+                true => Token::LongArrow,
+                false => Token::Arrow,
+            },
+            '-' => Token::Minus,
+            '/' if self.bump_if('*') => {
+                // Other comments were skipped along with whitespace.
+                self.bump();
+                let mut hint = String::new();
+                loop {
+                    match self.bump() {
+                        Some('*') if self.bump_if('/') => break,
+                        Some(ch) => hint.push(ch),
+                        None => return Err(Error::UnterminatedComment { position }),
+                    }
+                }
+                Token::Hint(hint.trim().to_string())
+            }
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            '=' => Token::Eq,
+            '<' if self.bump_if('=') => Token::LtEq,
+            '<' if self.bump_if('>') => Token::NotEq,
+            '<' => Token::Lt,
+            '>' if self.bump_if('=') => Token::GtEq,
+            '>' => Token::Gt,
+            '!' if self.bump_if('=') => Token::NotEq,
+            '|' if self.bump_if('|') => Token::Concat,
+            '.' if self.peek().is_some_and(|ch| ch.is_ascii_digit()) => {
+                self.number('.', position)?
+            }
+            '.' => Token::Period,
+            '\'' => Token::String(self.quoted('\'', position)?),
+            '"' => Token::Word {
+                value: self.quoted('"', position)?,
+                quoted: true,
+            },
+            'x' | 'X' if self.peek() == Some('\'') => {
+                self.bump();
+                let hex = self.quoted('\'', position)?;
+                Token::Blob(decode_hex(&hex).ok_or(Error::InvalidBlob { position })?)
+            }
+            '$' => {
+                let mut digits = String::new();
+                while let Some(ch) = self.peek().filter(char::is_ascii_digit) {
+                    digits.push(ch);
+                    self.bump();
+                }
+                match digits.parse() {
+                    Ok(n) if n > 0 => Token::Parameter(n),
+                    _ => return Err(Error::InvalidParameter { position }),
+                }
+            }
+            ch if ch.is_ascii_digit() => self.number(ch, position)?,
+            ch if ch.is_alphabetic() || ch == '_' => {
+                let mut value = String::from(ch);
+                while let Some(ch) = self.peek() {
+                    if !(ch.is_alphanumeric() || ch == '_') {
+                        break;
+                    }
+                    value.push(ch);
+                    self.bump();
+                }
+                Token::Word {
+                    value: value.to_lowercase(),
+                    quoted: false,
+                }
+            }
+            ch => return Err(Error::UnexpectedChar { ch, position }),
+        };
+        Ok((token, position))
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Splits `sql` into tokens, each with the position it starts at. The
+/// last token is always [`Token::Eof`].
+pub fn tokenize(sql: &str) -> Result<Vec<(Token, Position)>, Error> {
+    let mut lexer = Lexer {
+        chars: sql.chars().peekable(),
+        position: Position { line: 1, column: 1 },
+    };
+    let mut tokens = vec![];
+    loop {
+        let (token, position) = lexer.next_token()?;
+        let eof = token == Token::Eof;
+        tokens.push((token, position));
+        if eof {
+            return Ok(tokens);
+        }
+    }
+}
+
+/// Canonical text of `sql`, equal for statements that differ only in
+/// whitespace, comments, keyword case or a trailing semicolon. Hints
+/// are kept, normalized in turn, since they change the plan.
+pub fn normalize(sql: &str) -> Result<String, Error> {
+    let mut tokens = tokenize(sql)?;
+    tokens.pop();
+    if tokens
+        .last()
+        .is_some_and(|(token, _)| *token == Token::Semicolon)
+    {
+        tokens.pop();
+    }
+    Ok(render(&tokens))
+}
+
+/// `tokens` written out as SQL, separated by single spaces.
+pub fn render(tokens: &[(Token, Position)]) -> String {
+    let words: Vec<String> = tokens
+        .iter()
+        .map(|(token, _)| match token.clone() {
+            Token::Word {
+                value,
+                quoted: true,
+            } => format!("\"{}\"", value.replace('"', "\"\"")),
+            Token::Word { value, .. } | Token::Number(value) => value,
+            Token::String(s) => format!("'{}'", s.replace('\'', "''")),
+            Token::Blob(bytes) => {
+                let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+                format!("x'{hex}'")
+            }
+            Token::Parameter(n) => format!("${n}"),
+            Token::Hint(hint) => format!("/*+ {} */", normalize(&hint).unwrap_or(hint)),
+            token => token.to_string().trim_matches('"').to_string(),
+        })
+        .collect();
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(sql: &str) -> Vec<Token> {
+        tokenize(sql)
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect()
+    }
+
+    fn word(value: &str) -> Token {
+        Token::Word {
+            value: value.into(),
+            quoted: false,
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            vec![
+                word("select"),
+                Token::Word {
+                    value: "Mixed Case".into(),
+                    quoted: true
+                },
+                Token::Comma,
+                Token::String("it's".into()),
+                Token::Concat,
+                Token::Number("1.5e-3".into()),
+                Token::NotEq,
+                Token::Number(".5".into()),
+                Token::LtEq,
+                Token::Blob(vec![0xde, 0xad]),
+                word("from"),
+                word("t"),
+                Token::Period,
+                Token::Star,
+                Token::Semicolon,
+                Token::Eof,
+            ],
+            tokens(
+                "SELECT \"Mixed Case\", 'it''s' || 1.5e-3 != .5 -- comment\n\
+                 <= X'DEAD' /* block\ncomment */ FROM t.*;"
+            )
+        );
+        assert_eq!(
+            vec![
+                Token::Number("1".into()),
+                Token::Minus,
+                Token::Number("2".into()),
+                Token::Eof
+            ],
+            tokens("1-2")
+        );
+        assert_eq!(
+            vec![Token::Parameter(12), Token::Eq, word("a"), Token::Eof],
+            tokens("$12=a")
+        );
+        assert_eq!(
+            vec![
+                word("doc"),
+                Token::Arrow,
+                Token::String("a".into()),
+                Token::LongArrow,
+                Token::Minus,
+                Token::Number("1".into()),
+                Token::Eof
+            ],
+            tokens("doc->'a'->>-1")
+        );
+        assert_eq!(
+            vec![Token::Hint("SeqScan(t)".into()), word("x"), Token::Eof],
+            tokens("/*+ SeqScan(t) */ /* not a hint */ x")
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(
+            normalize("select \"A\"\"b\",'it''s' from t where x>=$1 and y = x'0A'").unwrap(),
+            normalize(
+                "SELECT  \"A\"\"b\" , 'it''s'\n-- comment\nFROM T WHERE x >= $1 AND y=X'0a';"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            "select \"A\"\"b\" , 'it''s' from t where x >= $1 and y = x'0a'",
+            normalize("select \"A\"\"b\",'it''s' from t where x>=$1 and y = x'0A'").unwrap()
+        );
+        assert_ne!(
+            normalize("SELECT 'a'").unwrap(),
+            normalize("SELECT a").unwrap()
+        );
+        assert_eq!(
+            "/*+ seqscan ( t ) */ select 1",
+            normalize("/*+ SeqScan(t) */ SELECT 1").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_tokenize_errors() {
+        let position = |line, column| Position { line, column };
+        assert_eq!(
+            Err(Error::UnterminatedString {
+                position: position(2, 3)
+            }),
+            tokenize("a\n  'abc")
+        );
+        assert_eq!(
+            Err(Error::UnexpectedChar {
+                ch: '?',
+                position: position(1, 8)
+            }),
+            tokenize("select ?")
+        );
+        assert_eq!(
+            Err(Error::InvalidNumber {
+                text: "12abc".into(),
+                position: position(1, 1)
+            }),
+            tokenize("12abc")
+        );
+        assert!(matches!(tokenize("x'abc'"), Err(Error::InvalidBlob { .. })));
+        assert!(matches!(
+            tokenize("$0"),
+            Err(Error::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            tokenize("$ 1"),
+            Err(Error::InvalidParameter { .. })
+        ));
+        assert!(matches!(
+            tokenize("/* open"),
+            Err(Error::UnterminatedComment { .. })
+        ));
+    }
+}
@@ -0,0 +1,71 @@
+//! SQL front end: a hand-written lexer and recursive-descent parser that
+//! turn query text into an [`ast::Statement`]. Planner hints in a leading
+//! `/*+ ... */` comment are read apart from the statement.
+
+pub mod ast;
+mod hint;
+mod lexer;
+mod parser;
+mod split;
+
+use std::fmt;
+
+pub use hint::{parse_hints, Hint};
+pub use lexer::{normalize, render, tokenize, Token};
+pub use parser::{parse, parse_statement, MAX_DEPTH};
+pub use split::split_statements;
+
+/// 1-based location in the query text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum Error {
+    #[error("syntax error at {position}: unexpected character {ch:?}")]
+    UnexpectedChar { ch: char, position: Position },
+    #[error("syntax error at {position}: unterminated quoted literal")]
+    UnterminatedString { position: Position },
+    #[error("syntax error at {position}: unterminated comment")]
+    UnterminatedComment { position: Position },
+    #[error("syntax error at {position}: invalid number {text:?}")]
+    InvalidNumber { text: String, position: Position },
+    #[error("syntax error at {position}: invalid hex literal")]
+    InvalidBlob { position: Position },
+    #[error("syntax error at {position}: parameters are written $1, $2, ...")]
+    InvalidParameter { position: Position },
+    #[error("syntax error at {position}: invalid planner hint")]
+    InvalidHint { position: Position },
+    #[error("syntax error at {position}: statement nests too deeply")]
+    TooDeep { position: Position },
+    #[error("syntax error at {position}: expected {expected}, found {found}")]
+    Unexpected {
+        expected: String,
+        found: String,
+        position: Position,
+    },
+}
+
+impl Error {
+    pub fn position(&self) -> Position {
+        match self {
+            Error::UnexpectedChar { position, .. }
+            | Error::UnterminatedString { position }
+            | Error::UnterminatedComment { position }
+            | Error::InvalidNumber { position, .. }
+            | Error::InvalidBlob { position }
+            | Error::InvalidParameter { position }
+            | Error::InvalidHint { position }
+            | Error::TooDeep { position }
+            | Error::Unexpected { position, .. } => *position,
+        }
+    }
+}
@@ -0,0 +1,2441 @@
+use super::ast::*;
+use super::lexer::{render, tokenize, Token};
+use super::{Error, Position};
+use crate::catalog::{
+    PartitionMethod, Persistence, Privileges, RowImage, TriggerEvent, TriggerTiming,
+};
+use crate::expr::{BinaryOp, UnaryOp};
+use crate::value::DataType;
+
+/// Words that cannot be used as bare identifiers or aliases.
+const RESERVED: &[&str] = &[
+    "all", "and", "as", "asc", "between", "by", "cast", "collate", "create", "cross", "delete",
+    "desc", "distinct", "do", "drop", "false", "for", "from", "group", "having", "in", "inner",
+    "insert", "into", "is", "join", "left", "like", "limit", "not", "null", "of", "offset", "on",
+    "or", "order", "outer", "over", "select", "set", "table", "true", "union", "unique", "update",
+    "values", "where", "with",
+];
+
+/// How deeply expressions, queries and joins may nest, so that hostile
+/// input cannot overflow the stack of the parser or of the planner.
+pub const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    index: usize,
+    depth: usize,
+}
+
+impl Parser {
+    /// A parser over the tokens of `sql`, without the hints, which the
+    /// planner reads separately (see [`super::parse_hints`]).
+    fn new(sql: &str) -> Result<Self, Error> {
+        let mut tokens = tokenize(sql)?;
+        tokens.retain(|(token, _)| !matches!(token, Token::Hint(_)));
+        Ok(Self {
+            tokens,
+            index: 0,
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.index].0
+    }
+
+    fn peek_nth(&self, n: usize) -> &Token {
+        let index = (self.index + n).min(self.tokens.len() - 1);
+        &self.tokens[index].0
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.index].0.clone();
+        if token != Token::Eof {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, expected: impl Into<String>) -> Result<T, Error> {
+        let (found, position) = &self.tokens[self.index];
+        Err(Error::Unexpected {
+            expected: expected.into(),
+            found: found.to_string(),
+            position: *position,
+        })
+    }
+
+    /// Runs `f` one level deeper, failing past [`MAX_DEPTH`].
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::TooDeep {
+                position: self.tokens[self.index].1,
+            });
+        }
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn consume(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<(), Error> {
+        if self.consume(token) {
+            Ok(())
+        } else {
+            self.error(token.to_string())
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_keyword(keyword) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes the whole sequence of keywords, or nothing.
+    fn keywords(&mut self, keywords: &[&str]) -> bool {
+        let matches = keywords
+            .iter()
+            .enumerate()
+            .all(|(i, keyword)| self.peek_nth(i).is_keyword(keyword));
+        if matches {
+            self.index += keywords.len();
+        }
+        matches
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            self.error(keyword.to_uppercase())
+        }
+    }
+
+    fn expect_keywords(&mut self, keywords: &[&str]) -> Result<(), Error> {
+        for keyword in keywords {
+            self.expect_keyword(keyword)?;
+        }
+        Ok(())
+    }
+
+    fn is_identifier(token: &Token) -> bool {
+        match token {
+            Token::Word { quoted: true, .. } => true,
+            Token::Word { value, .. } => !RESERVED.contains(&value.as_str()),
+            _ => false,
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, Error> {
+        if !Self::is_identifier(self.peek()) {
+            return self.error("identifier");
+        }
+        let Token::Word { value, .. } = self.next() else {
+            unreachable!()
+        };
+        Ok(value)
+    }
+
+    /// A table name, which may be qualified with the name of an attached
+    /// database: `other.t`.
+    fn table_name(&mut self) -> Result<String, Error> {
+        let name = self.identifier()?;
+        if self.peek() != &Token::Period {
+            return Ok(name);
+        }
+        self.next();
+        Ok(format!("{name}.{}", self.identifier()?))
+    }
+
+    fn comma_separated<T>(
+        &mut self,
+        mut f: impl FnMut(&mut Self) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        let mut items = vec![f(self)?];
+        while self.consume(&Token::Comma) {
+            items.push(f(self)?);
+        }
+        Ok(items)
+    }
+
+    fn parenthesized_identifiers(&mut self) -> Result<Vec<String>, Error> {
+        self.expect(&Token::LParen)?;
+        let columns = self.comma_separated(Self::identifier)?;
+        self.expect(&Token::RParen)?;
+        Ok(columns)
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        match self.peek() {
+            token if Self::starts_query(token) => {
+                let query = Box::new(self.query()?);
+                if !self.keywords(&["as", "of"]) {
+                    return Ok(Statement::Select(query));
+                }
+                let point = if self.keyword("lsn") {
+                    AsOfPoint::Lsn(self.unsigned("log sequence number")?)
+                } else {
+                    self.expect_keyword("timestamp")?;
+                    AsOfPoint::Timestamp(self.unsigned("seconds since the Unix epoch")?)
+                };
+                Ok(Statement::AsOf { query, point })
+            }
+            token if token.is_keyword("insert") => self.insert(),
+            token if token.is_keyword("update") => self.update(),
+            token if token.is_keyword("delete") => self.delete(),
+            token if token.is_keyword("create") => self.create(),
+            token if token.is_keyword("drop") => self.drop(),
+            token if token.is_keyword("alter") => self.alter(),
+            token if token.is_keyword("grant") => {
+                self.next();
+                Ok(Statement::Grant(self.grant("to")?))
+            }
+            token if token.is_keyword("revoke") => {
+                self.next();
+                Ok(Statement::Revoke(self.grant("from")?))
+            }
+            token if token.is_keyword("refresh") => {
+                self.next();
+                self.expect_keywords(&["materialized", "view"])?;
+                let name = self.identifier()?;
+                Ok(Statement::RefreshMaterializedView { name })
+            }
+            token if token.is_keyword("analyze") => {
+                self.next();
+                let table = if Self::is_identifier(self.peek()) {
+                    Some(self.identifier()?)
+                } else {
+                    None
+                };
+                Ok(Statement::Analyze { table })
+            }
+            token if token.is_keyword("reindex") => {
+                self.next();
+                let table = self.keyword("table");
+                if !table {
+                    self.expect_keyword("index")?;
+                }
+                let name = self.identifier()?;
+                Ok(Statement::Reindex { name, table })
+            }
+            token if token.is_keyword("explain") => {
+                self.next();
+                let analyze = self.keyword("analyze");
+                let statement = Box::new(self.statement()?);
+                Ok(Statement::Explain { analyze, statement })
+            }
+            token if token.is_keyword("set") => {
+                self.next();
+                self.set()
+            }
+            token if token.is_keyword("reset") => {
+                self.next();
+                let name = if self.keyword("all") {
+                    None
+                } else {
+                    Some(self.identifier()?)
+                };
+                Ok(Statement::Set { name, value: None })
+            }
+            token if token.is_keyword("show") => {
+                self.next();
+                let name = if self.keyword("all") {
+                    None
+                } else {
+                    Some(self.identifier()?)
+                };
+                Ok(Statement::Show { name })
+            }
+            token if token.is_keyword("copy") => {
+                self.next();
+                self.copy()
+            }
+            token if token.is_keyword("attach") => {
+                self.next();
+                self.keyword("database");
+                let path = self.file_name()?;
+                self.expect_keyword("as")?;
+                let name = self.identifier()?;
+                Ok(Statement::Attach { path, name })
+            }
+            token if token.is_keyword("detach") => {
+                self.next();
+                self.keyword("database");
+                let name = self.identifier()?;
+                Ok(Statement::Detach { name })
+            }
+            token if token.is_keyword("backup") => {
+                self.next();
+                self.expect_keyword("to")?;
+                let path = self.file_name()?;
+                let mut chain = vec![];
+                if self.keyword("incremental") {
+                    self.expect_keyword("from")?;
+                    chain.push(self.file_name()?);
+                    while self.consume(&Token::Comma) {
+                        chain.push(self.file_name()?);
+                    }
+                }
+                Ok(Statement::Backup { path, chain })
+            }
+            token if token.is_keyword("notify") => {
+                self.next();
+                let channel = self.identifier()?;
+                let payload = match self.consume(&Token::Comma) {
+                    true => match self.peek() {
+                        Token::String(payload) => {
+                            let payload = payload.clone();
+                            self.next();
+                            payload
+                        }
+                        _ => return self.error("payload string"),
+                    },
+                    false => String::new(),
+                };
+                Ok(Statement::Notify { channel, payload })
+            }
+            token if token.is_keyword("listen") => {
+                self.next();
+                let channel = self.identifier()?;
+                Ok(Statement::Listen { channel })
+            }
+            token if token.is_keyword("unlisten") => {
+                self.next();
+                let channel = match self.consume(&Token::Star) {
+                    true => None,
+                    false => Some(self.identifier()?),
+                };
+                Ok(Statement::Unlisten { channel })
+            }
+            token if token.is_keyword("start") => {
+                self.next();
+                self.expect_keyword("transaction")?;
+                Ok(Statement::Transaction(TransactionControl::Begin))
+            }
+            token if token.is_keyword("begin") => {
+                self.next();
+                self.transaction_noise();
+                Ok(Statement::Transaction(TransactionControl::Begin))
+            }
+            token if token.is_keyword("commit") || token.is_keyword("end") => {
+                self.next();
+                self.transaction_noise();
+                Ok(Statement::Transaction(TransactionControl::Commit))
+            }
+            token if token.is_keyword("rollback") || token.is_keyword("abort") => {
+                self.next();
+                self.transaction_noise();
+                Ok(Statement::Transaction(TransactionControl::Rollback))
+            }
+            _ => self.error("statement"),
+        }
+    }
+
+    /// The rest of `SET [SESSION] name {TO | =} {value [, ...] | DEFAULT}`
+    /// or `SET SESSION CHARACTERISTICS AS TRANSACTION ISOLATION LEVEL
+    /// level`. A list of values is kept as one, joined with commas.
+    fn set(&mut self) -> Result<Statement, Error> {
+        if self.keyword("session") && self.keyword("characteristics") {
+            self.expect_keywords(&["as", "transaction", "isolation", "level"])?;
+            let level = if self.keyword("serializable") {
+                "serializable"
+            } else if self.keyword("repeatable") {
+                self.expect_keyword("read")?;
+                "repeatable read"
+            } else if self.keyword("read") {
+                if self.keyword("committed") {
+                    "read committed"
+                } else {
+                    self.expect_keyword("uncommitted")?;
+                    "read uncommitted"
+                }
+            } else {
+                return self.error("isolation level");
+            };
+            return Ok(Statement::Set {
+                name: Some("transaction_isolation".to_string()),
+                value: Some(level.to_string()),
+            });
+        }
+        let name = self.identifier()?;
+        if !self.keyword("to") {
+            self.expect(&Token::Eq)?;
+        }
+        if self.keyword("default") {
+            return Ok(Statement::Set {
+                name: Some(name),
+                value: None,
+            });
+        }
+        let values = self.comma_separated(|parser| {
+            let negative =
+                matches!(parser.peek_nth(1), Token::Number(_)) && parser.consume(&Token::Minus);
+            let value = match parser.peek() {
+                Token::Word {
+                    value,
+                    quoted: true,
+                } => format!("\"{value}\""),
+                Token::Number(number) if negative => format!("-{number}"),
+                Token::Word { value, .. } | Token::String(value) | Token::Number(value) => {
+                    value.clone()
+                }
+                _ => return parser.error("setting value"),
+            };
+            parser.next();
+            Ok(value)
+        })?;
+        Ok(Statement::Set {
+            name: Some(name),
+            value: Some(values.join(", ")),
+        })
+    }
+
+    /// The optional `TRANSACTION` or `WORK` after BEGIN, COMMIT and
+    /// ROLLBACK.
+    fn transaction_noise(&mut self) {
+        if !self.keyword("transaction") {
+            self.keyword("work");
+        }
+    }
+
+    fn starts_query(token: &Token) -> bool {
+        token.is_keyword("select") || token.is_keyword("with")
+    }
+
+    fn query(&mut self) -> Result<Query, Error> {
+        self.nested(Self::query_body)
+    }
+
+    fn query_body(&mut self) -> Result<Query, Error> {
+        let with = if self.keyword("with") {
+            let recursive = self.keyword("recursive");
+            let ctes = self.comma_separated(Self::cte)?;
+            Some(With { recursive, ctes })
+        } else {
+            None
+        };
+        let select = self.select()?;
+        let mut unions = vec![];
+        while self.keyword("union") {
+            let all = self.keyword("all");
+            if !all {
+                self.keyword("distinct");
+            }
+            let select = self.select()?;
+            unions.push(Union { all, select });
+        }
+        let mut order_by = vec![];
+        if self.keywords(&["order", "by"]) {
+            order_by = self.comma_separated(Self::order_by_expr)?;
+        }
+        let limit = if self.keyword("limit") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let offset = if self.keyword("offset") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let mode = if self.keywords(&["for", "update"]) {
+            Some(LockMode::Update)
+        } else if self.keywords(&["for", "no", "key", "update"]) {
+            Some(LockMode::NoKeyUpdate)
+        } else if self.keywords(&["for", "share"]) {
+            Some(LockMode::Share)
+        } else if self.keywords(&["for", "key", "share"]) {
+            Some(LockMode::KeyShare)
+        } else {
+            None
+        };
+        let locking = mode.map(|mode| {
+            let wait = if self.keyword("nowait") {
+                LockWait::NoWait
+            } else if self.keywords(&["skip", "locked"]) {
+                LockWait::SkipLocked
+            } else {
+                LockWait::Wait
+            };
+            Locking { mode, wait }
+        });
+        Ok(Query {
+            with,
+            select,
+            unions,
+            order_by,
+            limit,
+            offset,
+            locking,
+        })
+    }
+
+    fn cte(&mut self) -> Result<Cte, Error> {
+        let name = self.identifier()?;
+        let columns = if self.peek() == &Token::LParen {
+            self.parenthesized_identifiers()?
+        } else {
+            vec![]
+        };
+        self.expect_keyword("as")?;
+        let materialized = if self.keyword("materialized") {
+            Some(true)
+        } else if self.keywords(&["not", "materialized"]) {
+            Some(false)
+        } else {
+            None
+        };
+        self.expect(&Token::LParen)?;
+        let query = Box::new(self.query()?);
+        self.expect(&Token::RParen)?;
+        Ok(Cte {
+            name,
+            columns,
+            materialized,
+            query,
+        })
+    }
+
+    fn select(&mut self) -> Result<Select, Error> {
+        self.expect_keyword("select")?;
+        let distinct = self.keyword("distinct");
+        if !distinct {
+            self.keyword("all");
+        }
+        let projection = self.comma_separated(Self::select_item)?;
+        let from = if self.keyword("from") {
+            self.comma_separated(Self::table_ref)?
+        } else {
+            vec![]
+        };
+        let selection = if self.keyword("where") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let group_by = if self.keywords(&["group", "by"]) {
+            self.comma_separated(Self::expr)?
+        } else {
+            vec![]
+        };
+        let having = if self.keyword("having") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(Select {
+            distinct,
+            projection,
+            from,
+            selection,
+            group_by,
+            having,
+        })
+    }
+
+    fn select_item(&mut self) -> Result<SelectItem, Error> {
+        if self.consume(&Token::Star) {
+            return Ok(SelectItem::Wildcard);
+        }
+        if Self::is_identifier(self.peek())
+            && self.peek_nth(1) == &Token::Period
+            && self.peek_nth(2) == &Token::Star
+        {
+            let table = self.identifier()?;
+            self.index += 2;
+            return Ok(SelectItem::QualifiedWildcard(table));
+        }
+        let expr = self.expr()?;
+        let alias = self.alias()?;
+        Ok(SelectItem::Expr { expr, alias })
+    }
+
+    fn alias(&mut self) -> Result<Option<String>, Error> {
+        // The AS OF of a query, after its last table or select item.
+        if self.peek().is_keyword("as") && self.peek_nth(1).is_keyword("of") {
+            return Ok(None);
+        }
+        if self.keyword("as") {
+            return Ok(Some(self.identifier()?));
+        }
+        if Self::is_identifier(self.peek()) {
+            return Ok(Some(self.identifier()?));
+        }
+        Ok(None)
+    }
+
+    fn order_by_expr(&mut self) -> Result<OrderByExpr, Error> {
+        let expr = self.expr()?;
+        let descending = if self.keyword("desc") {
+            true
+        } else {
+            self.keyword("asc");
+            false
+        };
+        let nulls_first = if self.keyword("nulls") {
+            if self.keyword("first") {
+                Some(true)
+            } else {
+                self.expect_keyword("last")?;
+                Some(false)
+            }
+        } else {
+            None
+        };
+        Ok(OrderByExpr {
+            expr,
+            descending,
+            nulls_first,
+        })
+    }
+
+    fn table_ref(&mut self) -> Result<TableRef, Error> {
+        let mut table = self.table_factor()?;
+        loop {
+            let kind = if self.keywords(&["cross", "join"]) {
+                JoinKind::Cross
+            } else if self.keyword("join") || self.keywords(&["inner", "join"]) {
+                JoinKind::Inner
+            } else if self.keywords(&["left", "join"]) || self.keywords(&["left", "outer", "join"])
+            {
+                JoinKind::Left
+            } else {
+                return Ok(table);
+            };
+            let right = self.table_factor()?;
+            let on = if kind == JoinKind::Cross {
+                None
+            } else {
+                self.expect_keyword("on")?;
+                Some(self.expr()?)
+            };
+            table = TableRef::Join {
+                left: Box::new(table),
+                right: Box::new(right),
+                kind,
+                on,
+            };
+        }
+    }
+
+    fn table_factor(&mut self) -> Result<TableRef, Error> {
+        if self.consume(&Token::LParen) {
+            if Self::starts_query(self.peek()) {
+                let query = Box::new(self.query()?);
+                self.expect(&Token::RParen)?;
+                let Some(alias) = self.alias()? else {
+                    return self.error("alias for subquery");
+                };
+                return Ok(TableRef::Subquery { query, alias });
+            }
+            let table = self.nested(Self::table_ref)?;
+            self.expect(&Token::RParen)?;
+            return Ok(table);
+        }
+        if self.peek().is_keyword("unnest") && self.peek_nth(1) == &Token::LParen {
+            self.next();
+            self.next();
+            let expr = self.expr()?;
+            self.expect(&Token::RParen)?;
+            let alias = self.alias()?;
+            let column = if alias.is_some() && self.consume(&Token::LParen) {
+                let column = self.identifier()?;
+                self.expect(&Token::RParen)?;
+                Some(column)
+            } else {
+                None
+            };
+            return Ok(TableRef::Unnest {
+                expr,
+                alias,
+                column,
+            });
+        }
+        let name = self.table_name()?;
+        let alias = self.alias()?;
+        Ok(TableRef::Table { name, alias })
+    }
+
+    fn insert(&mut self) -> Result<Statement, Error> {
+        self.expect_keywords(&["insert", "into"])?;
+        let table = self.table_name()?;
+        let columns = if self.peek() == &Token::LParen {
+            Some(self.parenthesized_identifiers()?)
+        } else {
+            None
+        };
+        let source = if self.keyword("values") {
+            InsertSource::Values(self.comma_separated(|p| {
+                p.expect(&Token::LParen)?;
+                let row = p.comma_separated(Self::expr)?;
+                p.expect(&Token::RParen)?;
+                Ok(row)
+            })?)
+        } else if Self::starts_query(self.peek()) {
+            InsertSource::Query(Box::new(self.query()?))
+        } else {
+            return self.error("VALUES or SELECT");
+        };
+        let on_conflict = if self.keywords(&["on", "conflict"]) {
+            let columns = if self.peek() == &Token::LParen {
+                Some(self.parenthesized_identifiers()?)
+            } else {
+                None
+            };
+            self.expect_keyword("do")?;
+            let action = if self.keyword("nothing") {
+                ConflictAction::DoNothing
+            } else if self.keywords(&["update", "set"]) {
+                let assignments = self.comma_separated(Self::assignment)?;
+                let selection = if self.keyword("where") {
+                    Some(self.expr()?)
+                } else {
+                    None
+                };
+                ConflictAction::DoUpdate {
+                    assignments,
+                    selection,
+                }
+            } else {
+                return self.error("NOTHING or UPDATE SET");
+            };
+            Some(OnConflict { columns, action })
+        } else {
+            None
+        };
+        Ok(Statement::Insert(Insert {
+            table,
+            columns,
+            source,
+            on_conflict,
+        }))
+    }
+
+    fn assignment(&mut self) -> Result<Assignment, Error> {
+        let column = self.identifier()?;
+        self.expect(&Token::Eq)?;
+        let value = self.expr()?;
+        Ok(Assignment { column, value })
+    }
+
+    fn update(&mut self) -> Result<Statement, Error> {
+        self.expect_keyword("update")?;
+        let table = self.table_name()?;
+        self.expect_keyword("set")?;
+        let assignments = self.comma_separated(Self::assignment)?;
+        let selection = if self.keyword("where") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(Statement::Update(Update {
+            table,
+            assignments,
+            selection,
+        }))
+    }
+
+    fn delete(&mut self) -> Result<Statement, Error> {
+        self.expect_keywords(&["delete", "from"])?;
+        let table = self.table_name()?;
+        let selection = if self.keyword("where") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        Ok(Statement::Delete(Delete { table, selection }))
+    }
+
+    fn create(&mut self) -> Result<Statement, Error> {
+        self.expect_keyword("create")?;
+        let persistence = if self.keyword("temp") || self.keyword("temporary") {
+            Persistence::Temporary
+        } else if self.keyword("unlogged") {
+            Persistence::Unlogged
+        } else {
+            Persistence::Permanent
+        };
+        if persistence != Persistence::Permanent {
+            self.expect_keyword("table")?;
+            return self.create_table(persistence);
+        }
+        if self.keyword("table") {
+            return self.create_table(persistence);
+        }
+        if self.keywords(&["materialized", "view"]) {
+            return self.create_materialized_view();
+        }
+        if self.keyword("trigger") {
+            return self.create_trigger();
+        }
+        if self.keyword("policy") {
+            return self.create_policy();
+        }
+        if self.keyword("user") {
+            let name = self.identifier()?;
+            let options = self.user_options()?;
+            return Ok(Statement::CreateUser { name, options });
+        }
+        let unique = self.keyword("unique");
+        if self.keyword("index") {
+            return self.create_index(unique);
+        }
+        self.error(if unique {
+            "INDEX"
+        } else {
+            "TABLE, MATERIALIZED VIEW, INDEX, TRIGGER, POLICY or USER"
+        })
+    }
+
+    fn copy(&mut self) -> Result<Statement, Error> {
+        let source = if self.consume(&Token::LParen) {
+            let query = self.query()?;
+            self.expect(&Token::RParen)?;
+            CopySource::Query(Box::new(query))
+        } else {
+            let name = self.identifier()?;
+            let columns = if self.peek() == &Token::LParen {
+                Some(self.parenthesized_identifiers()?)
+            } else {
+                None
+            };
+            CopySource::Table { name, columns }
+        };
+        let from = match &source {
+            CopySource::Table { .. } if self.keyword("from") => true,
+            _ if self.keyword("to") => false,
+            CopySource::Table { .. } => return self.error("FROM or TO"),
+            CopySource::Query(_) => return self.error("TO"),
+        };
+        let file = self.file_name()?;
+        let mut options = vec![];
+        let parenthesized = self.keyword("with") || self.peek() == &Token::LParen;
+        if parenthesized {
+            self.expect(&Token::LParen)?;
+            options = self.comma_separated(|parser| {
+                // Names such as NULL are reserved elsewhere.
+                let Token::Word { value: name, .. } = parser.peek().clone() else {
+                    return parser.error("option name");
+                };
+                parser.next();
+                let value = match parser.peek() {
+                    Token::Comma | Token::RParen => None,
+                    Token::Word { value, .. } | Token::String(value) | Token::Number(value) => {
+                        let value = value.clone();
+                        parser.next();
+                        Some(value)
+                    }
+                    _ => return parser.error("option value"),
+                };
+                Ok(CopyOption { name, value })
+            })?;
+            self.expect(&Token::RParen)?;
+        }
+        Ok(match source {
+            CopySource::Table { name, columns } if from => Statement::CopyFrom {
+                table: name,
+                columns,
+                file,
+                options,
+            },
+            source => Statement::CopyTo {
+                source,
+                file,
+                options,
+            },
+        })
+    }
+
+    /// A string literal naming a file.
+    fn file_name(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Token::String(name) => {
+                let name = name.clone();
+                self.next();
+                Ok(name)
+            }
+            _ => self.error("file name"),
+        }
+    }
+
+    fn user_options(&mut self) -> Result<UserOptions, Error> {
+        self.keyword("with");
+        let mut options = UserOptions::default();
+        loop {
+            if self.keyword("password") {
+                options.password = Some(if let Token::String(password) = self.peek() {
+                    let password = password.clone();
+                    self.next();
+                    Some(password)
+                } else if self.keyword("null") {
+                    None
+                } else {
+                    return self.error("password");
+                });
+            } else if self.keyword("superuser") {
+                options.superuser = Some(true);
+            } else if self.keyword("nosuperuser") {
+                options.superuser = Some(false);
+            } else {
+                return Ok(options);
+            }
+        }
+    }
+
+    /// The rest of GRANT or REVOKE, whose users follow `to_or_from`.
+    fn grant(&mut self, to_or_from: &str) -> Result<Grant, Error> {
+        let mut columns = vec![];
+        let privileges = if self.keyword("all") {
+            self.keyword("privileges");
+            Privileges::ALL
+        } else {
+            let privileges = self.comma_separated(|parser| {
+                let Token::Word { value, .. } = parser.peek() else {
+                    return parser.error("privilege");
+                };
+                let Some(privilege) = Privileges::from_name(value) else {
+                    return parser.error("privilege");
+                };
+                parser.next();
+                if privilege == Privileges::SELECT && parser.peek() == &Token::LParen {
+                    columns.extend(parser.parenthesized_identifiers()?);
+                    return Ok(Privileges::NONE);
+                }
+                Ok(privilege)
+            })?;
+            privileges
+                .into_iter()
+                .fold(Privileges::NONE, |all, p| all | p)
+        };
+        self.expect_keyword("on")?;
+        self.keyword("table");
+        let tables = self.comma_separated(Self::identifier)?;
+        self.expect_keyword(to_or_from)?;
+        let users = self.comma_separated(Self::identifier)?;
+        Ok(Grant {
+            privileges,
+            columns,
+            tables,
+            users,
+        })
+    }
+
+    fn alter(&mut self) -> Result<Statement, Error> {
+        self.expect_keyword("alter")?;
+        if self.keyword("user") {
+            let name = self.identifier()?;
+            let options = self.user_options()?;
+            return Ok(Statement::AlterUser { name, options });
+        }
+        if !self.keyword("table") {
+            return self.error("TABLE or USER");
+        }
+        let table = self.identifier()?;
+        if self.keywords(&["attach", "partition"]) {
+            let partition = self.identifier()?;
+            self.expect_keywords(&["for", "values"])?;
+            let bound = self.partition_bound()?;
+            return Ok(Statement::AttachPartition {
+                table,
+                partition,
+                bound,
+            });
+        }
+        if self.keywords(&["detach", "partition"]) {
+            let partition = self.identifier()?;
+            return Ok(Statement::DetachPartition { table, partition });
+        }
+        if self.keywords(&["set", "ttl"]) {
+            self.expect(&Token::LParen)?;
+            let column = self.identifier()?;
+            let duration = if self.consume(&Token::Comma) {
+                Some(self.unsigned("a number of seconds")?)
+            } else {
+                None
+            };
+            self.expect(&Token::RParen)?;
+            let ttl = Some(TtlSpec { column, duration });
+            return Ok(Statement::SetTtl { table, ttl });
+        }
+        if self.keywords(&["drop", "ttl"]) {
+            return Ok(Statement::SetTtl { table, ttl: None });
+        }
+        self.error("ATTACH PARTITION, DETACH PARTITION, SET TTL or DROP TTL")
+    }
+
+    fn partition_bound(&mut self) -> Result<PartitionBoundSpec, Error> {
+        if self.keyword("from") {
+            let from = self.range_bound("minvalue")?;
+            self.expect_keyword("to")?;
+            let to = self.range_bound("maxvalue")?;
+            return Ok(PartitionBoundSpec::Range { from, to });
+        }
+        self.expect_keyword("with")?;
+        self.expect(&Token::LParen)?;
+        self.expect_keyword("modulus")?;
+        let modulus = self.unsigned("modulus")?;
+        self.expect(&Token::Comma)?;
+        self.expect_keyword("remainder")?;
+        let remainder = self.unsigned("remainder")?;
+        self.expect(&Token::RParen)?;
+        Ok(PartitionBoundSpec::Hash { modulus, remainder })
+    }
+
+    /// `(value)`, or `(unbounded)` for none.
+    fn range_bound(&mut self, unbounded: &str) -> Result<Option<Box<Expr>>, Error> {
+        self.expect(&Token::LParen)?;
+        let bound = if self.keyword(unbounded) {
+            None
+        } else {
+            Some(Box::new(self.expr()?))
+        };
+        self.expect(&Token::RParen)?;
+        Ok(bound)
+    }
+
+    fn unsigned(&mut self, expected: &str) -> Result<u64, Error> {
+        let Token::Number(text) = self.peek() else {
+            return self.error(expected);
+        };
+        let Ok(n) = text.parse() else {
+            return self.error(expected);
+        };
+        self.next();
+        Ok(n)
+    }
+
+    fn create_table(&mut self, persistence: Persistence) -> Result<Statement, Error> {
+        let if_not_exists = self.keywords(&["if", "not", "exists"]);
+        let name = self.identifier()?;
+        if persistence == Persistence::Permanent && self.keywords(&["partition", "of"]) {
+            let parent = self.identifier()?;
+            self.expect_keywords(&["for", "values"])?;
+            let bound = self.partition_bound()?;
+            return Ok(Statement::CreatePartition {
+                name,
+                if_not_exists,
+                parent,
+                bound,
+            });
+        }
+        self.expect(&Token::LParen)?;
+        let mut columns = vec![];
+        let mut constraints = vec![];
+        loop {
+            if self.keywords(&["primary", "key"]) {
+                constraints.push(TableConstraint::PrimaryKey(
+                    self.parenthesized_identifiers()?,
+                ));
+            } else if self.keyword("unique") {
+                constraints.push(TableConstraint::Unique(self.parenthesized_identifiers()?));
+            } else {
+                columns.push(self.column_def()?);
+            }
+            if !self.consume(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RParen)?;
+        let partition_by = if self.keywords(&["partition", "by"]) {
+            let method = if self.keyword("range") {
+                PartitionMethod::Range
+            } else if self.keyword("hash") {
+                PartitionMethod::Hash
+            } else {
+                return self.error("RANGE or HASH");
+            };
+            self.expect(&Token::LParen)?;
+            let column = self.identifier()?;
+            self.expect(&Token::RParen)?;
+            Some((method, column))
+        } else {
+            None
+        };
+        Ok(Statement::CreateTable(CreateTable {
+            name,
+            persistence,
+            if_not_exists,
+            columns,
+            constraints,
+            partition_by,
+        }))
+    }
+
+    fn create_materialized_view(&mut self) -> Result<Statement, Error> {
+        let if_not_exists = self.keywords(&["if", "not", "exists"]);
+        let name = self.identifier()?;
+        self.expect_keyword("as")?;
+        let start = self.index;
+        let query = Box::new(self.query()?);
+        let definition = render(&self.tokens[start..self.index]);
+        Ok(Statement::CreateMaterializedView(CreateMaterializedView {
+            name,
+            if_not_exists,
+            query,
+            definition,
+        }))
+    }
+
+    fn create_trigger(&mut self) -> Result<Statement, Error> {
+        let name = self.identifier()?;
+        let timing = if self.keyword("before") {
+            TriggerTiming::Before
+        } else if self.keyword("after") {
+            TriggerTiming::After
+        } else {
+            return self.error("BEFORE or AFTER");
+        };
+        let mut events = vec![];
+        loop {
+            let event = if self.keyword("insert") {
+                TriggerEvent::Insert
+            } else if self.keyword("update") {
+                TriggerEvent::Update
+            } else if self.keyword("delete") {
+                TriggerEvent::Delete
+            } else {
+                return self.error("INSERT, UPDATE or DELETE");
+            };
+            if !events.contains(&event) {
+                events.push(event);
+            }
+            if !self.keyword("or") {
+                break;
+            }
+        }
+        self.expect_keyword("on")?;
+        let table = self.identifier()?;
+        if self.keyword("for") {
+            self.keyword("each");
+            self.expect_keyword("row")?;
+        }
+        let action = if self.keyword("execute") {
+            if !self.keyword("function") {
+                self.expect_keyword("procedure")?;
+            }
+            let function = self.identifier()?;
+            self.expect(&Token::LParen)?;
+            self.expect(&Token::RParen)?;
+            TriggerBody::Function(function)
+        } else {
+            let start = self.index;
+            match self.peek() {
+                token if token.is_keyword("insert") => self.insert()?,
+                token if token.is_keyword("update") => self.update()?,
+                token if token.is_keyword("delete") => self.delete()?,
+                _ => return self.error("INSERT, UPDATE, DELETE or EXECUTE FUNCTION"),
+            };
+            self.trigger_statement(start)?
+        };
+        Ok(Statement::CreateTrigger(CreateTrigger {
+            name,
+            table,
+            timing,
+            events,
+            action,
+        }))
+    }
+
+    fn create_policy(&mut self) -> Result<Statement, Error> {
+        let name = self.identifier()?;
+        self.expect_keyword("on")?;
+        let table = self.identifier()?;
+        let users = match self.keyword("to") {
+            true if self.keyword("public") => vec![],
+            true => self.comma_separated(Self::identifier)?,
+            false => vec![],
+        };
+        self.expect_keyword("using")?;
+        self.expect(&Token::LParen)?;
+        let condition = self.expr()?;
+        self.expect(&Token::RParen)?;
+        Ok(Statement::CreatePolicy(CreatePolicy {
+            name,
+            table,
+            users,
+            condition,
+        }))
+    }
+
+    /// The statement parsed since token `start`, with its references to
+    /// columns of the changed row made parameters.
+    fn trigger_statement(&self, start: usize) -> Result<TriggerBody, Error> {
+        let mut tokens = vec![];
+        let mut references: Vec<(RowImage, String)> = vec![];
+        let mut i = start;
+        while i < self.index {
+            let (token, position) = &self.tokens[i];
+            if let Token::Parameter(_) = token {
+                return Err(Error::Unexpected {
+                    expected: "a column of NEW or OLD".into(),
+                    found: token.to_string(),
+                    position: *position,
+                });
+            }
+            let image = if token.is_keyword("new") {
+                Some(RowImage::New)
+            } else if token.is_keyword("old") {
+                Some(RowImage::Old)
+            } else {
+                None
+            };
+            if let (Some(image), Some([(Token::Period, _), (Token::Word { value, .. }, _)])) =
+                (image, self.tokens[..self.index].get(i + 1..i + 3))
+            {
+                let reference = (image, value.clone());
+                let n = match references.iter().position(|r| *r == reference) {
+                    Some(n) => n,
+                    None => {
+                        references.push(reference);
+                        references.len() - 1
+                    }
+                };
+                tokens.push((Token::Parameter(n + 1), *position));
+                i += 3;
+                continue;
+            }
+            tokens.push((token.clone(), *position));
+            i += 1;
+        }
+        Ok(TriggerBody::Statement {
+            definition: render(&tokens),
+            references,
+        })
+    }
+
+    fn column_def(&mut self) -> Result<ColumnDef, Error> {
+        let name = self.identifier()?;
+        let data_type = self.data_type()?;
+        let mut column = ColumnDef {
+            name,
+            data_type,
+            not_null: false,
+            primary_key: false,
+            unique: false,
+            collation: None,
+        };
+        loop {
+            if self.keywords(&["not", "null"]) {
+                column.not_null = true;
+            } else if self.keyword("null") {
+                column.not_null = false;
+            } else if self.keywords(&["primary", "key"]) {
+                column.primary_key = true;
+            } else if self.keyword("unique") {
+                column.unique = true;
+            } else if self.keyword("collate") {
+                column.collation = Some(self.identifier()?);
+            } else {
+                return Ok(column);
+            }
+        }
+    }
+
+    fn data_type(&mut self) -> Result<DataType, Error> {
+        let Token::Word {
+            value,
+            quoted: false,
+        } = self.peek()
+        else {
+            return self.error("data type");
+        };
+        let data_type = match value.as_str() {
+            "bool" | "boolean" => DataType::Bool,
+            "int" | "integer" | "bigint" | "smallint" => DataType::Int,
+            "float" | "real" | "double" => DataType::Float,
+            "text" | "varchar" | "char" | "string" => DataType::Text,
+            "bytes" | "bytea" | "blob" => DataType::Bytes,
+            "json" | "jsonb" => DataType::Json,
+            _ => return self.error("data type"),
+        };
+        let is_double = value == "double";
+        self.next();
+        if is_double {
+            self.keyword("precision");
+        }
+        // Length modifiers such as VARCHAR(255) are accepted and ignored.
+        if data_type == DataType::Text && self.consume(&Token::LParen) {
+            let Token::Number(_) = self.next() else {
+                return self.error("length");
+            };
+            self.expect(&Token::RParen)?;
+        }
+        if self.consume(&Token::LBracket) {
+            self.expect(&Token::RBracket)?;
+            // One level only: the element type read above is never an array.
+            return Ok(DataType::array(data_type).expect("scalar element type"));
+        }
+        Ok(data_type)
+    }
+
+    fn create_index(&mut self, unique: bool) -> Result<Statement, Error> {
+        let concurrently = self.keyword("concurrently");
+        let if_not_exists = self.keywords(&["if", "not", "exists"]);
+        let name = if if_not_exists || !self.peek().is_keyword("on") {
+            Some(self.identifier()?)
+        } else {
+            None
+        };
+        self.expect_keyword("on")?;
+        let table = self.identifier()?;
+        self.expect(&Token::LParen)?;
+        let keys = self.comma_separated(Self::order_by_expr)?;
+        self.expect(&Token::RParen)?;
+        let predicate = self.keyword("where").then(|| self.expr()).transpose()?;
+        Ok(Statement::CreateIndex(CreateIndex {
+            name,
+            table,
+            keys,
+            predicate,
+            unique,
+            concurrently,
+            if_not_exists,
+        }))
+    }
+
+    fn drop(&mut self) -> Result<Statement, Error> {
+        self.expect_keyword("drop")?;
+        if self.keyword("user") {
+            let if_exists = self.keywords(&["if", "exists"]);
+            let name = self.identifier()?;
+            return Ok(Statement::DropUser { name, if_exists });
+        }
+        if self.keywords(&["materialized", "view"]) {
+            let if_exists = self.keywords(&["if", "exists"]);
+            let name = self.identifier()?;
+            return Ok(Statement::DropMaterializedView { name, if_exists });
+        }
+        if self.keyword("trigger") {
+            let if_exists = self.keywords(&["if", "exists"]);
+            let name = self.identifier()?;
+            return Ok(Statement::DropTrigger { name, if_exists });
+        }
+        if self.keyword("policy") {
+            let if_exists = self.keywords(&["if", "exists"]);
+            let name = self.identifier()?;
+            self.expect_keyword("on")?;
+            let table = self.identifier()?;
+            return Ok(Statement::DropPolicy {
+                name,
+                table,
+                if_exists,
+            });
+        }
+        let table = if self.keyword("table") {
+            true
+        } else if self.keyword("index") {
+            false
+        } else {
+            return self.error("TABLE, MATERIALIZED VIEW, INDEX, TRIGGER, POLICY or USER");
+        };
+        let if_exists = self.keywords(&["if", "exists"]);
+        let name = self.identifier()?;
+        Ok(if table {
+            Statement::DropTable { name, if_exists }
+        } else {
+            Statement::DropIndex { name, if_exists }
+        })
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        self.nested(Self::or)
+    }
+
+    fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Self::binary(BinaryOp::Or, expr, self.and()?);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Self::binary(BinaryOp::And, expr, self.not()?);
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, Error> {
+        if self.keyword("not") {
+            return Ok(Expr::Unary {
+                op: UnaryOp::Not,
+                expr: Box::new(self.nested(Self::not)?),
+            });
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.concat()?;
+        loop {
+            let op = match self.peek() {
+                Token::Eq => Some(BinaryOp::Eq),
+                Token::NotEq => Some(BinaryOp::NotEq),
+                Token::Lt => Some(BinaryOp::Lt),
+                Token::LtEq => Some(BinaryOp::LtEq),
+                Token::Gt => Some(BinaryOp::Gt),
+                Token::GtEq => Some(BinaryOp::GtEq),
+                _ => None,
+            };
+            if let Some(op) = op {
+                self.next();
+                let quantified = ["any", "some", "all"]
+                    .iter()
+                    .any(|keyword| self.peek().is_keyword(keyword))
+                    && self.peek_nth(1) == &Token::LParen;
+                if quantified {
+                    let all = self.next().is_keyword("all");
+                    self.next();
+                    let array = self.expr()?;
+                    self.expect(&Token::RParen)?;
+                    expr = Expr::Any {
+                        op,
+                        expr: Box::new(expr),
+                        array: Box::new(array),
+                        all,
+                    };
+                    continue;
+                }
+                expr = Self::binary(op, expr, self.concat()?);
+                continue;
+            }
+            if self.keyword("is") {
+                let negated = self.keyword("not");
+                self.expect_keyword("null")?;
+                expr = Expr::IsNull {
+                    expr: Box::new(expr),
+                    negated,
+                };
+                continue;
+            }
+            let negated = self.peek().is_keyword("not")
+                && ["between", "in", "like"]
+                    .iter()
+                    .any(|keyword| self.peek_nth(1).is_keyword(keyword));
+            if negated {
+                self.next();
+            }
+            expr = if self.keyword("between") {
+                let low = self.concat()?;
+                self.expect_keyword("and")?;
+                let high = self.concat()?;
+                Expr::Between {
+                    expr: Box::new(expr),
+                    low: Box::new(low),
+                    high: Box::new(high),
+                    negated,
+                }
+            } else if self.keyword("in") {
+                self.expect(&Token::LParen)?;
+                let list = self.comma_separated(Self::expr)?;
+                self.expect(&Token::RParen)?;
+                Expr::InList {
+                    expr: Box::new(expr),
+                    list,
+                    negated,
+                }
+            } else if self.keyword("like") {
+                Expr::Like {
+                    expr: Box::new(expr),
+                    pattern: Box::new(self.concat()?),
+                    negated,
+                }
+            } else {
+                return Ok(expr);
+            };
+        }
+    }
+
+    /// `||` and the JSON operators, which bind alike.
+    fn concat(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.additive()?;
+        loop {
+            let op = match self.peek() {
+                Token::Concat => BinaryOp::Concat,
+                Token::Arrow => BinaryOp::JsonGet,
+                Token::LongArrow => BinaryOp::JsonGetText,
+                _ => return Ok(expr),
+            };
+            self.next();
+            expr = Self::binary(op, expr, self.additive()?);
+        }
+    }
+
+    fn additive(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Sub,
+                _ => return Ok(expr),
+            };
+            self.next();
+            expr = Self::binary(op, expr, self.multiplicative()?);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Token::Star => BinaryOp::Mul,
+                Token::Slash => BinaryOp::Div,
+                Token::Percent => BinaryOp::Mod,
+                _ => return Ok(expr),
+            };
+            self.next();
+            expr = Self::binary(op, expr, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, Error> {
+        if self.consume(&Token::Plus) {
+            return self.nested(Self::unary);
+        }
+        if self.peek() == &Token::Minus {
+            // Fold the sign into numeric literals so that i64::MIN parses.
+            if let Token::Number(text) = self.peek_nth(1) {
+                let text = format!("-{text}");
+                self.next();
+                return self.number(&text);
+            }
+            self.next();
+            return Ok(Expr::Unary {
+                op: UnaryOp::Neg,
+                expr: Box::new(self.nested(Self::unary)?),
+            });
+        }
+        self.subscript()
+    }
+
+    /// `expr[index]` and `expr COLLATE collation`, which bind tighter
+    /// than any operator.
+    fn subscript(&mut self) -> Result<Expr, Error> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.consume(&Token::LBracket) {
+                let index = self.expr()?;
+                self.expect(&Token::RBracket)?;
+                expr = Self::binary(BinaryOp::Subscript, expr, index);
+            } else if self.keyword("collate") {
+                expr = Expr::Collate {
+                    expr: Box::new(expr),
+                    collation: self.identifier()?,
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn number(&mut self, text: &str) -> Result<Expr, Error> {
+        let literal = if text.contains(['.', 'e', 'E']) {
+            text.parse().ok().map(Literal::Float)
+        } else {
+            text.parse().ok().map(Literal::Int)
+        };
+        let Some(literal) = literal else {
+            let position = self.tokens[self.index].1;
+            return Err(Error::InvalidNumber {
+                text: text.to_string(),
+                position,
+            });
+        };
+        self.next();
+        Ok(Expr::Literal(literal))
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let literal = match self.peek().clone() {
+            Token::Number(text) => return self.number(&text),
+            Token::String(s) => Literal::String(s),
+            Token::Blob(b) => Literal::Blob(b),
+            Token::Parameter(n) => {
+                self.next();
+                return Ok(Expr::Parameter(n));
+            }
+            Token::LParen => {
+                self.next();
+                let expr = self.expr()?;
+                self.expect(&Token::RParen)?;
+                return Ok(expr);
+            }
+            token if token.is_keyword("null") => Literal::Null,
+            token if token.is_keyword("true") => Literal::Bool(true),
+            token if token.is_keyword("false") => Literal::Bool(false),
+            token if token.is_keyword("cast") => {
+                self.next();
+                self.expect(&Token::LParen)?;
+                let expr = self.expr()?;
+                self.expect_keyword("as")?;
+                let data_type = self.data_type()?;
+                self.expect(&Token::RParen)?;
+                return Ok(Expr::Cast {
+                    expr: Box::new(expr),
+                    data_type,
+                });
+            }
+            token if token.is_keyword("array") && self.peek_nth(1) == &Token::LBracket => {
+                self.next();
+                self.next();
+                let items = if self.peek() == &Token::RBracket {
+                    vec![]
+                } else {
+                    self.comma_separated(Self::expr)?
+                };
+                self.expect(&Token::RBracket)?;
+                return Ok(Expr::Array(items));
+            }
+            token if Self::is_identifier(&token) => return self.identifier_or_function(),
+            _ => return self.error("expression"),
+        };
+        self.next();
+        Ok(Expr::Literal(literal))
+    }
+
+    fn identifier_or_function(&mut self) -> Result<Expr, Error> {
+        let name = self.identifier()?;
+        if self.consume(&Token::LParen) {
+            return self.function(name);
+        }
+        let mut parts = vec![name];
+        while self.consume(&Token::Period) {
+            parts.push(self.identifier()?);
+        }
+        Ok(Expr::Identifier(parts))
+    }
+
+    fn function(&mut self, name: String) -> Result<Expr, Error> {
+        let mut function = Function {
+            name,
+            args: vec![],
+            star: false,
+            distinct: false,
+            over: None,
+        };
+        if self.consume(&Token::Star) {
+            function.star = true;
+        } else if self.peek() != &Token::RParen {
+            function.distinct = self.keyword("distinct");
+            function.args = self.comma_separated(Self::expr)?;
+        }
+        self.expect(&Token::RParen)?;
+        if self.keyword("over") {
+            function.over = Some(self.window_spec()?);
+        }
+        Ok(Expr::Function(function))
+    }
+
+    fn window_spec(&mut self) -> Result<WindowSpec, Error> {
+        self.expect(&Token::LParen)?;
+        let partition_by = if self.keywords(&["partition", "by"]) {
+            self.comma_separated(Self::expr)?
+        } else {
+            vec![]
+        };
+        let order_by = if self.keywords(&["order", "by"]) {
+            self.comma_separated(Self::order_by_expr)?
+        } else {
+            vec![]
+        };
+        let units = if self.keyword("rows") {
+            Some(FrameUnits::Rows)
+        } else if self.keyword("range") {
+            Some(FrameUnits::Range)
+        } else {
+            None
+        };
+        let frame = match units {
+            Some(units) => {
+                let (start, end) = if self.keyword("between") {
+                    let start = self.frame_bound()?;
+                    self.expect_keyword("and")?;
+                    (start, self.frame_bound()?)
+                } else {
+                    (self.frame_bound()?, FrameBound::CurrentRow)
+                };
+                Some(WindowFrame { units, start, end })
+            }
+            None => None,
+        };
+        self.expect(&Token::RParen)?;
+        Ok(WindowSpec {
+            partition_by,
+            order_by,
+            frame,
+        })
+    }
+
+    fn frame_bound(&mut self) -> Result<FrameBound, Error> {
+        if self.keywords(&["current", "row"]) {
+            return Ok(FrameBound::CurrentRow);
+        }
+        if self.keyword("unbounded") {
+            if self.keyword("preceding") {
+                return Ok(FrameBound::UnboundedPreceding);
+            }
+            self.expect_keyword("following")?;
+            return Ok(FrameBound::UnboundedFollowing);
+        }
+        let offset = match self.peek() {
+            Token::Number(text) => text.parse::<u64>().ok(),
+            _ => None,
+        };
+        let Some(offset) = offset else {
+            return self.error("frame bound");
+        };
+        self.next();
+        if self.keyword("preceding") {
+            return Ok(FrameBound::Preceding(offset));
+        }
+        self.expect_keyword("following")?;
+        Ok(FrameBound::Following(offset))
+    }
+}
+
+/// Parses a script of `;`-separated statements.
+pub fn parse(sql: &str) -> Result<Vec<Statement>, Error> {
+    let mut parser = Parser::new(sql)?;
+    let mut statements = vec![];
+    loop {
+        while parser.consume(&Token::Semicolon) {}
+        if parser.peek() == &Token::Eof {
+            return Ok(statements);
+        }
+        statements.push(parser.statement()?);
+        if parser.peek() != &Token::Eof {
+            parser.expect(&Token::Semicolon)?;
+        }
+    }
+}
+
+/// Parses exactly one statement, with an optional trailing `;`.
+pub fn parse_statement(sql: &str) -> Result<Statement, Error> {
+    let mut parser = Parser::new(sql)?;
+    let statement = parser.statement()?;
+    parser.consume(&Token::Semicolon);
+    if parser.peek() != &Token::Eof {
+        return parser.error("end of statement");
+    }
+    Ok(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ident(name: &str) -> Expr {
+        Expr::Identifier(name.split('.').map(String::from).collect())
+    }
+
+    fn int(i: i64) -> Expr {
+        Expr::Literal(Literal::Int(i))
+    }
+
+    fn bin(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Parser::binary(op, lhs, rhs)
+    }
+
+    fn expr(sql: &str) -> Expr {
+        let Statement::Select(query) = parse_statement(&format!("SELECT {sql}")).unwrap() else {
+            panic!("not a select");
+        };
+        let SelectItem::Expr { expr, .. } = query.select.projection[0].clone() else {
+            panic!("not an expression");
+        };
+        expr
+    }
+
+    #[test]
+    fn test_expression_precedence() {
+        assert_eq!(
+            bin(
+                BinaryOp::Or,
+                bin(BinaryOp::Eq, ident("a"), int(1)),
+                bin(
+                    BinaryOp::And,
+                    Expr::Unary {
+                        op: UnaryOp::Not,
+                        expr: Box::new(ident("b")),
+                    },
+                    bin(
+                        BinaryOp::Lt,
+                        bin(
+                            BinaryOp::Add,
+                            ident("t.c"),
+                            bin(BinaryOp::Mul, int(2), int(-3))
+                        ),
+                        bin(BinaryOp::Sub, int(4), int(1)),
+                    ),
+                ),
+            ),
+            expr("a = 1 OR NOT b AND t.c + 2 * -3 < (4 - 1)")
+        );
+        assert_eq!(int(i64::MIN), expr("-9223372036854775808"));
+        assert_eq!(
+            Expr::Any {
+                op: BinaryOp::Lt,
+                expr: Box::new(bin(
+                    BinaryOp::Mul,
+                    bin(BinaryOp::Subscript, ident("a"), int(1)),
+                    int(2)
+                )),
+                array: Box::new(Expr::Array(vec![int(3), ident("b")])),
+                all: true,
+            },
+            expr("a[1] * 2 < ALL (ARRAY[3, b])")
+        );
+        assert_eq!(
+            bin(BinaryOp::Eq, ident("a"), Expr::Parameter(2)),
+            expr("a = $2")
+        );
+        assert_eq!(
+            Expr::Between {
+                expr: Box::new(ident("x")),
+                low: Box::new(int(1)),
+                high: Box::new(int(2)),
+                negated: true,
+            },
+            expr("x NOT BETWEEN 1 AND 2")
+        );
+        assert_eq!(
+            Expr::IsNull {
+                expr: Box::new(Expr::InList {
+                    expr: Box::new(ident("x")),
+                    list: vec![int(1), Expr::Literal(Literal::Null)],
+                    negated: false,
+                }),
+                negated: true,
+            },
+            expr("x IN (1, NULL) IS NOT NULL")
+        );
+        assert_eq!(
+            Expr::Cast {
+                expr: Box::new(Expr::Literal(Literal::String("1.5".into()))),
+                data_type: DataType::Float,
+            },
+            expr("CAST('1.5' AS DOUBLE PRECISION)")
+        );
+        assert_eq!(
+            Expr::Function(Function {
+                name: "sum".into(),
+                args: vec![ident("x")],
+                star: false,
+                distinct: false,
+                over: Some(WindowSpec {
+                    partition_by: vec![ident("g")],
+                    order_by: vec![OrderByExpr {
+                        expr: ident("y"),
+                        descending: true,
+                        nulls_first: None,
+                    }],
+                    frame: Some(WindowFrame {
+                        units: FrameUnits::Rows,
+                        start: FrameBound::Preceding(2),
+                        end: FrameBound::CurrentRow,
+                    }),
+                }),
+            }),
+            expr("SUM(x) OVER (PARTITION BY g ORDER BY y DESC ROWS 2 PRECEDING)")
+        );
+    }
+
+    #[test]
+    fn test_select() {
+        let statement = parse_statement(
+            "select distinct u.name as n, count(*) total from users u \
+             left join orders o on o.user_id = u.id, (select 1 x) s \
+             where u.id > 10 group by u.name having count(*) > 1 \
+             order by total desc nulls last, n limit 10 offset 5;",
+        )
+        .unwrap();
+        let table = |name: &str, alias: &str| TableRef::Table {
+            name: name.into(),
+            alias: Some(alias.into()),
+        };
+        let count_star = Expr::Function(Function {
+            name: "count".into(),
+            args: vec![],
+            star: true,
+            distinct: false,
+            over: None,
+        });
+        assert_eq!(
+            Statement::Select(Box::new(Query {
+                with: None,
+                select: Select {
+                    distinct: true,
+                    projection: vec![
+                        SelectItem::Expr {
+                            expr: ident("u.name"),
+                            alias: Some("n".into()),
+                        },
+                        SelectItem::Expr {
+                            expr: count_star.clone(),
+                            alias: Some("total".into()),
+                        },
+                    ],
+                    from: vec![
+                        TableRef::Join {
+                            left: Box::new(table("users", "u")),
+                            right: Box::new(table("orders", "o")),
+                            kind: JoinKind::Left,
+                            on: Some(bin(BinaryOp::Eq, ident("o.user_id"), ident("u.id"))),
+                        },
+                        TableRef::Subquery {
+                            query: Box::new(Query {
+                                with: None,
+                                select: Select {
+                                    distinct: false,
+                                    projection: vec![SelectItem::Expr {
+                                        expr: int(1),
+                                        alias: Some("x".into()),
+                                    }],
+                                    from: vec![],
+                                    selection: None,
+                                    group_by: vec![],
+                                    having: None,
+                                },
+                                unions: vec![],
+                                order_by: vec![],
+                                limit: None,
+                                offset: None,
+                                locking: None,
+                            }),
+                            alias: "s".into(),
+                        },
+                    ],
+                    selection: Some(bin(BinaryOp::Gt, ident("u.id"), int(10))),
+                    group_by: vec![ident("u.name")],
+                    having: Some(bin(BinaryOp::Gt, count_star, int(1))),
+                },
+                unions: vec![],
+                order_by: vec![
+                    OrderByExpr {
+                        expr: ident("total"),
+                        descending: true,
+                        nulls_first: Some(false),
+                    },
+                    OrderByExpr {
+                        expr: ident("n"),
+                        descending: false,
+                        nulls_first: None,
+                    },
+                ],
+                limit: Some(int(10)),
+                offset: Some(int(5)),
+                locking: None,
+            })),
+            statement
+        );
+    }
+
+    #[test]
+    fn test_with_and_union() {
+        let Statement::Select(query) = parse_statement(
+            "WITH RECURSIVE t (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM t WHERE n < 5), \
+             u AS NOT MATERIALIZED (SELECT 2) \
+             SELECT n FROM t UNION SELECT * FROM u ORDER BY 1",
+        )
+        .unwrap() else {
+            panic!("not a query");
+        };
+        let with = query.with.unwrap();
+        assert!(with.recursive);
+        assert_eq!(2, with.ctes.len());
+        let cte = &with.ctes[0];
+        assert_eq!(
+            ("t", &["n".to_string()][..], None),
+            (&cte.name[..], &cte.columns[..], cte.materialized)
+        );
+        assert_eq!(1, cte.query.unions.len());
+        assert!(cte.query.unions[0].all);
+        assert_eq!(Some(false), with.ctes[1].materialized);
+        assert_eq!(1, query.unions.len());
+        assert!(!query.unions[0].all);
+        assert_eq!(1, query.order_by.len());
+
+        assert!(matches!(
+            parse_statement("SELECT * FROM (WITH a AS (SELECT 1) SELECT * FROM a) s").unwrap(),
+            Statement::Select(_)
+        ));
+        assert!(parse_statement("WITH a (SELECT 1) SELECT 1").is_err());
+
+        let locking = |mode, wait| Some(Locking { mode, wait });
+        for (sql, expected) in [
+            ("SELECT * FROM t", None),
+            (
+                "SELECT * FROM t FOR UPDATE",
+                locking(LockMode::Update, LockWait::Wait),
+            ),
+            (
+                "SELECT * FROM t LIMIT 1 FOR NO KEY UPDATE NOWAIT",
+                locking(LockMode::NoKeyUpdate, LockWait::NoWait),
+            ),
+            (
+                "SELECT * FROM t FOR SHARE SKIP LOCKED",
+                locking(LockMode::Share, LockWait::SkipLocked),
+            ),
+            (
+                "SELECT * FROM t FOR KEY SHARE",
+                locking(LockMode::KeyShare, LockWait::Wait),
+            ),
+        ] {
+            let Statement::Select(query) = parse_statement(sql).unwrap() else {
+                panic!("not a query");
+            };
+            assert_eq!(expected, query.locking, "{sql}");
+        }
+    }
+
+    #[test]
+    fn test_dml_and_ddl() {
+        let statements = parse(
+            "CREATE TABLE IF NOT EXISTS users (
+                 id INT PRIMARY KEY,
+                 name VARCHAR(32) NOT NULL UNIQUE COLLATE nocase,
+                 avatar BLOB,
+                 UNIQUE (name, avatar)
+             );
+             CREATE UNIQUE INDEX CONCURRENTLY ON users ((lower(name)), id NULLS FIRST) WHERE avatar IS NULL;
+             INSERT INTO users (id, name) VALUES (1, 'a'), (2, x'00ff')
+                 ON CONFLICT (id) DO UPDATE SET name = excluded.name WHERE users.id > 0;
+             INSERT INTO users SELECT * FROM users ON CONFLICT DO NOTHING;
+             UPDATE users SET name = name || '!', id = id + 1 WHERE id = 1;
+             DELETE FROM users;;
+             DROP INDEX IF EXISTS users_name;
+             DROP TABLE users",
+        )
+        .unwrap();
+        assert_eq!(8, statements.len());
+        assert_eq!(
+            Statement::CreateTable(CreateTable {
+                name: "users".into(),
+                persistence: Persistence::Permanent,
+                if_not_exists: true,
+                columns: vec![
+                    ColumnDef {
+                        name: "id".into(),
+                        data_type: DataType::Int,
+                        not_null: false,
+                        primary_key: true,
+                        unique: false,
+                        collation: None,
+                    },
+                    ColumnDef {
+                        name: "name".into(),
+                        data_type: DataType::Text,
+                        not_null: true,
+                        primary_key: false,
+                        unique: true,
+                        collation: Some("nocase".into()),
+                    },
+                    ColumnDef {
+                        name: "avatar".into(),
+                        data_type: DataType::Bytes,
+                        not_null: false,
+                        primary_key: false,
+                        unique: false,
+                        collation: None,
+                    },
+                ],
+                constraints: vec![TableConstraint::Unique(vec![
+                    "name".into(),
+                    "avatar".into()
+                ])],
+                partition_by: None,
+            }),
+            statements[0]
+        );
+        assert_eq!(
+            Statement::CreateIndex(CreateIndex {
+                name: None,
+                table: "users".into(),
+                keys: vec![
+                    OrderByExpr {
+                        expr: Expr::Function(Function {
+                            name: "lower".into(),
+                            args: vec![ident("name")],
+                            star: false,
+                            distinct: false,
+                            over: None,
+                        }),
+                        descending: false,
+                        nulls_first: None,
+                    },
+                    OrderByExpr {
+                        expr: ident("id"),
+                        descending: false,
+                        nulls_first: Some(true),
+                    },
+                ],
+                predicate: Some(Expr::IsNull {
+                    expr: Box::new(ident("avatar")),
+                    negated: false,
+                }),
+                unique: true,
+                concurrently: true,
+                if_not_exists: false,
+            }),
+            statements[1]
+        );
+        assert_eq!(
+            Statement::Insert(Insert {
+                table: "users".into(),
+                columns: Some(vec!["id".into(), "name".into()]),
+                source: InsertSource::Values(vec![
+                    vec![int(1), Expr::Literal(Literal::String("a".into()))],
+                    vec![int(2), Expr::Literal(Literal::Blob(vec![0, 255]))],
+                ]),
+                on_conflict: Some(OnConflict {
+                    columns: Some(vec!["id".into()]),
+                    action: ConflictAction::DoUpdate {
+                        assignments: vec![Assignment {
+                            column: "name".into(),
+                            value: ident("excluded.name"),
+                        }],
+                        selection: Some(bin(BinaryOp::Gt, ident("users.id"), int(0))),
+                    },
+                }),
+            }),
+            statements[2]
+        );
+        assert!(matches!(
+            &statements[3],
+            Statement::Insert(Insert {
+                source: InsertSource::Query(_),
+                on_conflict: Some(OnConflict {
+                    columns: None,
+                    action: ConflictAction::DoNothing
+                }),
+                ..
+            })
+        ));
+        assert_eq!(
+            Statement::Delete(Delete {
+                table: "users".into(),
+                selection: None,
+            }),
+            statements[5]
+        );
+        assert_eq!(
+            Statement::DropIndex {
+                name: "users_name".into(),
+                if_exists: true,
+            },
+            statements[6]
+        );
+        assert_eq!(
+            Statement::Analyze {
+                table: Some("users".into())
+            },
+            parse_statement("ANALYZE users").unwrap()
+        );
+        assert_eq!(
+            Statement::Analyze { table: None },
+            parse_statement("analyze;").unwrap()
+        );
+        assert_eq!(
+            Statement::Reindex {
+                name: "users".into(),
+                table: true
+            },
+            parse_statement("REINDEX TABLE users").unwrap()
+        );
+        assert!(parse_statement("REINDEX users").is_err());
+        assert_eq!(
+            Statement::Attach {
+                path: "other.ndb".into(),
+                name: "other".into()
+            },
+            parse_statement("ATTACH 'other.ndb' AS other").unwrap()
+        );
+        assert_eq!(
+            Statement::Detach {
+                name: "other".into()
+            },
+            parse_statement("DETACH DATABASE other").unwrap()
+        );
+        assert_eq!(
+            Statement::Delete(Delete {
+                table: "other.t".into(),
+                selection: None
+            }),
+            parse_statement("DELETE FROM other.t").unwrap()
+        );
+        assert_eq!(
+            Statement::Transaction(TransactionControl::Begin),
+            parse_statement("START TRANSACTION").unwrap()
+        );
+        assert_eq!(
+            Statement::Transaction(TransactionControl::Rollback),
+            parse_statement("rollback work").unwrap()
+        );
+        assert_eq!(
+            Statement::CreateUser {
+                name: "alice".into(),
+                options: UserOptions {
+                    password: Some(Some("pw".into())),
+                    superuser: Some(false),
+                },
+            },
+            parse_statement("CREATE USER alice WITH PASSWORD 'pw' NOSUPERUSER").unwrap()
+        );
+        assert_eq!(
+            Statement::Revoke(Grant {
+                privileges: Privileges::INSERT | Privileges::DELETE,
+                columns: vec![],
+                tables: vec!["t".into(), "u".into()],
+                users: vec!["alice".into()],
+            }),
+            parse_statement("REVOKE insert, DELETE ON TABLE t, u FROM alice").unwrap()
+        );
+        assert_eq!(
+            Statement::Grant(Grant {
+                privileges: Privileges::UPDATE,
+                columns: vec!["a".into(), "b".into()],
+                tables: vec!["t".into()],
+                users: vec!["bob".into()],
+            }),
+            parse_statement("GRANT SELECT (a, b), UPDATE ON t TO bob").unwrap()
+        );
+        assert_eq!(
+            Statement::Set {
+                name: Some("search_path".into()),
+                value: Some("\"$user\", public".into()),
+            },
+            parse_statement("SET SESSION search_path TO \"$user\", public").unwrap()
+        );
+        assert_eq!(
+            Statement::Set {
+                name: Some("transaction_isolation".into()),
+                value: Some("read committed".into()),
+            },
+            parse_statement(
+                "SET SESSION CHARACTERISTICS AS TRANSACTION ISOLATION LEVEL READ COMMITTED"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Statement::Set {
+                name: Some("work_mem".into()),
+                value: None,
+            },
+            parse_statement("SET work_mem = DEFAULT").unwrap()
+        );
+        assert_eq!(
+            Statement::Set {
+                name: None,
+                value: None,
+            },
+            parse_statement("RESET ALL").unwrap()
+        );
+        assert_eq!(
+            Statement::Show {
+                name: Some("work_mem".into())
+            },
+            parse_statement("show work_mem").unwrap()
+        );
+        assert_eq!(
+            Statement::Backup {
+                path: "/tmp/db.bak".into(),
+                chain: vec![],
+            },
+            parse_statement("BACKUP TO '/tmp/db.bak'").unwrap()
+        );
+        assert_eq!(
+            Statement::Backup {
+                path: "2".into(),
+                chain: vec!["0".into(), "1".into()],
+            },
+            parse_statement("BACKUP TO '2' INCREMENTAL FROM '0', '1'").unwrap()
+        );
+        assert_eq!(
+            Statement::CopyFrom {
+                table: "t".into(),
+                columns: Some(vec!["a".into(), "b".into()]),
+                file: "t.csv".into(),
+                options: vec![
+                    CopyOption {
+                        name: "header".into(),
+                        value: None,
+                    },
+                    CopyOption {
+                        name: "delimiter".into(),
+                        value: Some(";".into()),
+                    },
+                ],
+            },
+            parse_statement("COPY t (a, b) FROM 't.csv' WITH (HEADER, DELIMITER ';')").unwrap()
+        );
+        assert!(matches!(
+            parse_statement("COPY (SELECT 1) TO 'one.csv'").unwrap(),
+            Statement::CopyTo {
+                source: CopySource::Query(_),
+                ..
+            }
+        ));
+        let Statement::CreateMaterializedView(create) = parse_statement(
+            "CREATE MATERIALIZED VIEW IF NOT EXISTS totals AS\n  SELECT name, Sum(n) FROM t GROUP BY name",
+        )
+        .unwrap() else {
+            panic!("not a materialized view");
+        };
+        assert!(create.if_not_exists);
+        assert_eq!(
+            "select name , sum ( n ) from t group by name",
+            create.definition
+        );
+        assert_eq!(
+            Statement::Select(create.query),
+            parse_statement(&create.definition).unwrap()
+        );
+        assert_eq!(
+            Statement::RefreshMaterializedView {
+                name: "totals".into()
+            },
+            parse_statement("REFRESH MATERIALIZED VIEW totals").unwrap()
+        );
+        assert_eq!(
+            Statement::DropMaterializedView {
+                name: "totals".into(),
+                if_exists: true,
+            },
+            parse_statement("DROP MATERIALIZED VIEW IF EXISTS totals").unwrap()
+        );
+        assert_eq!(
+            Statement::CreateTrigger(CreateTrigger {
+                name: "audit".into(),
+                table: "t".into(),
+                timing: TriggerTiming::After,
+                events: vec![TriggerEvent::Update, TriggerEvent::Delete],
+                action: TriggerBody::Statement {
+                    definition: "insert into log values ( $1 , $2 , $1 * 2 )".into(),
+                    references: vec![(RowImage::Old, "id".into()), (RowImage::New, "id".into())],
+                },
+            }),
+            parse_statement(
+                "CREATE TRIGGER audit AFTER UPDATE OR DELETE OR UPDATE ON t FOR EACH ROW \
+                 INSERT INTO log VALUES (old.id, NEW.id, old.id * 2)"
+            )
+            .unwrap()
+        );
+        assert!(matches!(
+            parse_statement("CREATE TRIGGER f BEFORE INSERT ON t EXECUTE FUNCTION check_row()"),
+            Ok(Statement::CreateTrigger(CreateTrigger {
+                action: TriggerBody::Function(name),
+                ..
+            })) if name == "check_row"
+        ));
+        assert!(
+            parse_statement("CREATE TRIGGER f BEFORE INSERT ON t DELETE FROM u WHERE a = $1")
+                .is_err()
+        );
+        assert_eq!(
+            Statement::DropTrigger {
+                name: "audit".into(),
+                if_exists: false,
+            },
+            parse_statement("DROP TRIGGER audit").unwrap()
+        );
+        assert!(matches!(
+            parse_statement("CREATE POLICY own ON t TO alice, bob USING (tenant = 'a')"),
+            Ok(Statement::CreatePolicy(CreatePolicy { users, .. })) if users == ["alice", "bob"]
+        ));
+        assert!(matches!(
+            parse_statement("CREATE POLICY open ON t TO PUBLIC USING (true)"),
+            Ok(Statement::CreatePolicy(CreatePolicy { users, .. })) if users.is_empty()
+        ));
+        assert_eq!(
+            Statement::DropPolicy {
+                name: "own".into(),
+                table: "t".into(),
+                if_exists: true,
+            },
+            parse_statement("DROP POLICY IF EXISTS own ON t").unwrap()
+        );
+        for (sql, expected) in [
+            ("CREATE TEMP TABLE t (id INT)", Persistence::Temporary),
+            ("create temporary table t (id int)", Persistence::Temporary),
+            (
+                "CREATE UNLOGGED TABLE IF NOT EXISTS t (id INT)",
+                Persistence::Unlogged,
+            ),
+        ] {
+            assert!(matches!(
+                parse_statement(sql),
+                Ok(Statement::CreateTable(CreateTable { persistence, .. })) if persistence == expected
+            ));
+        }
+        assert!(parse_statement("CREATE TEMP INDEX ON t (id)").is_err());
+        assert!(
+            parse_statement("CREATE TEMP TABLE t PARTITION OF p FOR VALUES FROM (1) TO (2)")
+                .is_err()
+        );
+        assert!(matches!(
+            parse_statement("EXPLAIN ANALYZE SELECT 1").unwrap(),
+            Statement::Explain { analyze: true, statement }
+                if matches!(*statement, Statement::Select(_))
+        ));
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let error = parse_statement("SELECT a,\n  FROM t").unwrap_err();
+        assert_eq!(
+            Error::Unexpected {
+                expected: "expression".into(),
+                found: "\"from\"".into(),
+                position: Position { line: 2, column: 3 },
+            },
+            error
+        );
+        assert_eq!(
+            "syntax error at line 2, column 3: expected expression, found \"from\"",
+            error.to_string()
+        );
+        assert!(matches!(
+            parse_statement("SELECT 99999999999999999999"),
+            Err(Error::InvalidNumber { .. })
+        ));
+        let nested = |depth| format!("SELECT {}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse_statement(&nested(MAX_DEPTH - 2)).is_ok());
+        assert!(matches!(
+            parse_statement(&nested(MAX_DEPTH - 1)),
+            Err(Error::TooDeep { .. })
+        ));
+        assert!(matches!(
+            parse_statement("SELECT 1 SELECT 2"),
+            Err(Error::Unexpected { .. })
+        ));
+        assert!(matches!(
+            parse("SELECT 1; SELECT"),
+            Err(Error::Unexpected { .. })
+        ));
+        assert!(parse_statement("CREATE TABLE t (a WIDGET)").is_err());
+        assert!(parse_statement("SELECT * FROM (SELECT 1)").is_err());
+    }
+}
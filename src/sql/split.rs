@@ -0,0 +1,71 @@
+//! Cutting a script into statements without parsing them.
+
+/// Splits `text` at the semicolons that end statements, skipping those in
+/// quotes and comments. Returns the complete statements, without their
+/// semicolons and leaving out empty ones, and whatever follows the last
+/// semicolon, which may be the start of a statement still being typed.
+pub fn split_statements(text: &str) -> (Vec<&str>, &str) {
+    #[derive(PartialEq)]
+    enum State {
+        Normal,
+        Quoted(char),
+        LineComment,
+        BlockComment,
+    }
+    let mut state = State::Normal;
+    let mut statements = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        let next = chars.peek().map(|&(_, ch)| ch);
+        match state {
+            State::Normal => match ch {
+                '\'' | '"' => state = State::Quoted(ch),
+                '-' if next == Some('-') => state = State::LineComment,
+                '/' if next == Some('*') => {
+                    chars.next();
+                    state = State::BlockComment;
+                }
+                ';' => {
+                    let statement = text[start..i].trim();
+                    if !statement.is_empty() {
+                        statements.push(statement);
+                    }
+                    start = i + 1;
+                }
+                _ => {}
+            },
+            // A doubled quote leaves and re-enters the quotes.
+            State::Quoted(quote) if ch == quote => state = State::Normal,
+            State::LineComment if ch == '\n' => state = State::Normal,
+            State::BlockComment if ch == '*' && next == Some('/') => {
+                chars.next();
+                state = State::Normal;
+            }
+            _ => {}
+        }
+    }
+    (statements, &text[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_statements() {
+        let (statements, rest) = split_statements(
+            "SELECT 1; ;\nSELECT ';', \"a;b\" -- c;\nFROM t; /* ; */ /*+ SeqScan(t) */ SELECT",
+        );
+        assert_eq!(
+            vec!["SELECT 1", "SELECT ';', \"a;b\" -- c;\nFROM t"],
+            statements
+        );
+        assert_eq!(" /* ; */ /*+ SeqScan(t) */ SELECT", rest);
+        assert_eq!((vec![], "SELECT 'it'';"), split_statements("SELECT 'it'';"));
+        assert_eq!(
+            (vec!["SELECT 'it'''"], ""),
+            split_statements("SELECT 'it''';")
+        );
+    }
+}
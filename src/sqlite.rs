@@ -0,0 +1,991 @@
+//! Reading SQLite 3 database files, to import them.
+//!
+//! [`File`] reads the b-trees of a file SQLite wrote, and [`Schema`]
+//! turns the tables and indexes in its schema into ones this database
+//! can hold; [`Database::import_sqlite`](crate::database::Database::import_sqlite)
+//! puts the two together.
+//!
+//! Declared column types map by SQLite's affinity rules: INT types are
+//! INT; CHAR, CLOB and TEXT types, dates and times, and columns without a
+//! type are TEXT; BLOB is BYTES; REAL, FLOAT and DOUBLE are FLOAT, as are
+//! the other numeric types, except that BOOLEAN is BOOL. Since SQLite
+//! stores any value in any column, values are converted where nothing is
+//! lost (an integer to a float or to text, 0 and 1 to a boolean) and rows
+//! holding others are refused. Primary keys, NOT NULL and UNIQUE are
+//! kept; defaults, CHECK and foreign keys are not. Tables WITHOUT ROWID,
+//! virtual tables, views, triggers and indexes on expressions or with a
+//! WHERE clause are skipped and listed in [`Schema::skipped`].
+
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::value::{DataType, Tuple, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("not a SQLite 3 database")]
+    NotSqlite,
+    #[error("the SQLite database has a write-ahead log; checkpoint it first")]
+    Wal,
+    #[error("the SQLite database is damaged: {0}")]
+    Corrupt(String),
+    #[error(
+        "row {rowid} of table {table:?}: column {column:?} is {data_type} but the value is {value}"
+    )]
+    Value {
+        table: String,
+        rowid: i64,
+        column: String,
+        data_type: DataType,
+        value: String,
+    },
+}
+
+const MAGIC: &[u8] = b"SQLite format 3\0";
+const HEADER_SIZE: usize = 100;
+const INTERIOR_TABLE: u8 = 0x05;
+const LEAF_TABLE: u8 = 0x0d;
+/// Deeper than any b-tree SQLite builds, so a loop of child pointers in
+/// a damaged file is caught.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+pub struct File {
+    file: fs::File,
+    page_size: usize,
+    /// The page size less the bytes reserved at the end of each page.
+    usable_size: usize,
+    encoding: Encoding,
+}
+
+impl File {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut file = fs::File::open(path)?;
+        let mut header = [0; HEADER_SIZE];
+        file.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::NotSqlite,
+            _ => e.into(),
+        })?;
+        if !header.starts_with(MAGIC) {
+            return Err(Error::NotSqlite);
+        }
+        // Pages committed to the log are not in the file until a checkpoint.
+        let mut wal = path.as_os_str().to_owned();
+        wal.push("-wal");
+        if header[18] == 2 && fs::metadata(wal).is_ok_and(|wal| wal.len() > 0) {
+            return Err(Error::Wal);
+        }
+        let page_size = match u16::from_be_bytes([header[16], header[17]]) {
+            1 => 65536,
+            size => size as usize,
+        };
+        if !page_size.is_power_of_two() || page_size < 512 {
+            return Err(Error::Corrupt(format!("page size {page_size}")));
+        }
+        let usable_size = page_size - header[20] as usize;
+        let encoding = match u32::from_be_bytes(header[56..60].try_into().unwrap()) {
+            2 => Encoding::Utf16Le,
+            3 => Encoding::Utf16Be,
+            _ => Encoding::Utf8,
+        };
+        Ok(Self {
+            file,
+            page_size,
+            usable_size,
+            encoding,
+        })
+    }
+
+    fn page(&self, number: u32) -> Result<Vec<u8>, Error> {
+        if number == 0 {
+            return Err(Error::Corrupt("pointer to page 0".to_string()));
+        }
+        let mut page = vec![0; self.page_size];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start((number as u64 - 1) * self.page_size as u64))?;
+        file.read_exact(&mut page).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => Error::Corrupt(format!("page {number} is missing")),
+            _ => e.into(),
+        })?;
+        Ok(page)
+    }
+
+    /// The rowid and values of each record in the table b-tree rooted at
+    /// page `root`, in rowid order.
+    fn records(&self, root: u32) -> Records<'_> {
+        Records {
+            file: self,
+            root: Some(root),
+            stack: vec![],
+        }
+    }
+
+    /// Reads the tables and indexes in the file's schema.
+    pub fn schema(&self) -> Result<Schema, Error> {
+        let mut schema = Schema::default();
+        let mut indexes = vec![];
+        for record in self.records(1) {
+            let (_, values) = record?;
+            let text = |i: usize| match values.get(i) {
+                Some(Value::Text(s)) => s.as_str(),
+                _ => "",
+            };
+            let (kind, name, table, sql) = (text(0), text(1), text(2), text(4));
+            // sqlite_sequence and the like, and the indexes behind UNIQUE
+            // and PRIMARY KEY constraints, which have no SQL.
+            if name.starts_with("sqlite_") {
+                continue;
+            }
+            let root = match values.get(3) {
+                Some(&Value::Int(root)) => root as u32,
+                _ => 0,
+            };
+            match kind {
+                "table" => match parse_table(sql) {
+                    Ok(mut parsed) => {
+                        parsed.root = root;
+                        schema.tables.push(parsed);
+                    }
+                    Err(reason) => schema.skipped.push(format!("table {name}: {reason}")),
+                },
+                "index" => indexes.push((name.to_string(), table.to_string(), sql.to_string())),
+                _ => schema
+                    .skipped
+                    .push(format!("{kind} {name}: {kind}s are not imported")),
+            }
+        }
+        for (name, table, sql) in indexes {
+            let Some(table) = schema
+                .tables
+                .iter()
+                .find(|t| t.name.eq_ignore_ascii_case(&table))
+            else {
+                schema
+                    .skipped
+                    .push(format!("index {name}: its table is not imported"));
+                continue;
+            };
+            match parse_index(&sql, table) {
+                Ok(index) => schema.indexes.push(index),
+                Err(reason) => schema.skipped.push(format!("index {name}: {reason}")),
+            }
+        }
+        Ok(schema)
+    }
+
+    /// The rows of `table`, with each value converted to its column's type.
+    pub fn rows<'a>(&'a self, table: &'a Table) -> impl Iterator<Item = Result<Tuple, Error>> + 'a {
+        self.records(table.root).map(move |record| {
+            let (rowid, mut values) = record?;
+            // Columns added by ALTER TABLE are missing from older records.
+            values.resize(table.columns.len(), Value::Null);
+            if let Some(i) = table.rowid_alias {
+                values[i] = Value::Int(rowid);
+            }
+            values
+                .into_iter()
+                .zip(&table.columns)
+                .map(|(value, column)| {
+                    convert(&value, column.data_type).ok_or_else(|| Error::Value {
+                        table: table.name.clone(),
+                        rowid,
+                        column: column.name.clone(),
+                        data_type: column.data_type,
+                        value: value.to_string(),
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// The rowid and payload of the cell at `offset` in a table leaf page.
+    fn leaf_cell(&self, page: &[u8], offset: usize) -> Result<(i64, Vec<Value>), Error> {
+        let damaged = || Error::Corrupt(format!("bad cell at offset {offset}"));
+        let (len, n) = varint(page.get(offset..).ok_or_else(damaged)?).ok_or_else(damaged)?;
+        let (rowid, m) = varint(&page[offset + n..]).ok_or_else(damaged)?;
+        let len = len as usize;
+        let start = offset + n + m;
+        // How much of the payload is on the page; see SQLite's file format.
+        let usable = self.usable_size;
+        let max_local = usable - 35;
+        let local = if len <= max_local {
+            len
+        } else {
+            let min_local = (usable - 12) * 32 / 255 - 23;
+            let local = min_local + (len - min_local) % (usable - 4);
+            if local <= max_local {
+                local
+            } else {
+                min_local
+            }
+        };
+        let mut payload = page.get(start..start + local).ok_or_else(damaged)?.to_vec();
+        if local < len {
+            let pointer = page
+                .get(start + local..start + local + 4)
+                .ok_or_else(damaged)?;
+            let mut next = u32::from_be_bytes(pointer.try_into().unwrap());
+            while payload.len() < len {
+                let overflow = self.page(next)?;
+                let take = (len - payload.len()).min(usable - 4);
+                payload.extend(&overflow[4..4 + take]);
+                next = u32::from_be_bytes(overflow[..4].try_into().unwrap());
+            }
+        }
+        let values = self.record(&payload).ok_or_else(damaged)?;
+        Ok((rowid as i64, values))
+    }
+
+    /// Decodes a record: the serial types of its values, then the values.
+    fn record(&self, payload: &[u8]) -> Option<Vec<Value>> {
+        let (header_len, mut at) = varint(payload)?;
+        let mut body = payload.get(header_len as usize..)?;
+        let mut values = vec![];
+        while at < header_len as usize {
+            let (serial_type, n) = varint(payload.get(at..header_len as usize)?)?;
+            at += n;
+            let size = match serial_type {
+                0 | 8 | 9 => 0,
+                1..=4 => serial_type as usize,
+                5 => 6,
+                6 | 7 => 8,
+                10 | 11 => return None,
+                n => (n as usize - 12) / 2,
+            };
+            let bytes = body.get(..size)?;
+            body = &body[size..];
+            values.push(match serial_type {
+                0 => Value::Null,
+                1..=6 => Value::Int(
+                    bytes[1..]
+                        .iter()
+                        .fold(bytes[0] as i8 as i64, |n, &b| n << 8 | b as i64),
+                ),
+                7 => Value::Float(f64::from_be_bytes(bytes.try_into().unwrap())),
+                8 => Value::Int(0),
+                9 => Value::Int(1),
+                n if n % 2 == 0 => Value::Bytes(bytes.to_vec()),
+                _ => Value::Text(self.text(bytes)?),
+            });
+        }
+        Some(values)
+    }
+
+    fn text(&self, bytes: &[u8]) -> Option<String> {
+        let units = |from: fn([u8; 2]) -> u16| {
+            let units: Vec<_> = bytes
+                .chunks_exact(2)
+                .map(|pair| from([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16(&units).ok()
+        };
+        match self.encoding {
+            Encoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Utf16Le => units(u16::from_le_bytes),
+            Encoding::Utf16Be => units(u16::from_be_bytes),
+        }
+    }
+}
+
+/// A page of a table b-tree being walked, and the next of its cells.
+struct Node {
+    page: Vec<u8>,
+    /// Where the b-tree header starts: after the file header on page 1.
+    header: usize,
+    interior: bool,
+    cells: usize,
+    next: usize,
+}
+
+impl Node {
+    fn cell_offset(&self, i: usize) -> Result<usize, Error> {
+        let at = self.header + if self.interior { 12 } else { 8 } + 2 * i;
+        let bytes = self
+            .page
+            .get(at..at + 2)
+            .ok_or_else(|| Error::Corrupt("cell pointer past the page".to_string()))?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    /// The page number of the `i`-th child, the rightmost after the cells.
+    fn child(&self, i: usize) -> Result<u32, Error> {
+        let at = if i == self.cells {
+            self.header + 8
+        } else {
+            self.cell_offset(i)?
+        };
+        let bytes = self
+            .page
+            .get(at..at + 4)
+            .ok_or_else(|| Error::Corrupt("child pointer past the page".to_string()))?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+}
+
+struct Records<'a> {
+    file: &'a File,
+    root: Option<u32>,
+    stack: Vec<Node>,
+}
+
+impl Records<'_> {
+    fn push(&mut self, number: u32) -> Result<(), Error> {
+        if self.stack.len() == MAX_DEPTH {
+            return Err(Error::Corrupt("a b-tree is too deep".to_string()));
+        }
+        let page = self.file.page(number)?;
+        let header = if number == 1 { HEADER_SIZE } else { 0 };
+        let interior = match page[header] {
+            INTERIOR_TABLE => true,
+            LEAF_TABLE => false,
+            _ => {
+                return Err(Error::Corrupt(format!(
+                    "page {number} is not in a table b-tree"
+                )))
+            }
+        };
+        let cells = u16::from_be_bytes([page[header + 3], page[header + 4]]) as usize;
+        self.stack.push(Node {
+            page,
+            header,
+            interior,
+            cells,
+            next: 0,
+        });
+        Ok(())
+    }
+
+    fn next_record(&mut self) -> Result<Option<(i64, Vec<Value>)>, Error> {
+        if let Some(root) = self.root.take() {
+            self.push(root)?;
+        }
+        while let Some(node) = self.stack.last_mut() {
+            let i = node.next;
+            node.next += 1;
+            if node.interior && i <= node.cells {
+                let child = node.child(i)?;
+                self.push(child)?;
+            } else if !node.interior && i < node.cells {
+                let offset = node.cell_offset(i)?;
+                return self.file.leaf_cell(&node.page, offset).map(Some);
+            } else {
+                self.stack.pop();
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<(i64, Vec<Value>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.next_record();
+        if record.is_err() {
+            self.stack.clear();
+        }
+        record.transpose()
+    }
+}
+
+/// SQLite's variable-length integer: big-endian groups of 7 bits, up to
+/// 9 bytes, the last of which has all 8.
+fn varint(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut n = 0;
+    for (i, &b) in bytes.iter().enumerate().take(9) {
+        if i == 8 {
+            return Some((n << 8 | b as u64, 9));
+        }
+        n = n << 7 | (b & 0x7f) as u64;
+        if b < 0x80 {
+            return Some((n, i + 1));
+        }
+    }
+    None
+}
+
+fn convert(value: &Value, data_type: DataType) -> Option<Value> {
+    match (value, data_type) {
+        (Value::Null, _) => Some(Value::Null),
+        (&Value::Int(n @ (0 | 1)), DataType::Bool) => Some(Value::Bool(n == 1)),
+        (Value::Int(_) | Value::Float(_), DataType::Text) => Some(Value::Text(value.to_string())),
+        (Value::Text(s), DataType::Bytes) => Some(Value::Bytes(s.clone().into_bytes())),
+        (Value::Text(s), DataType::Int | DataType::Float | DataType::Json) => {
+            Value::parse(s, data_type)
+        }
+        _ => value.clone().coerce_to(data_type),
+    }
+}
+
+/// What [`Database::import_sqlite`](crate::database::Database::import_sqlite)
+/// brought over.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Import {
+    /// Each table and how many rows it got.
+    pub tables: Vec<(String, u64)>,
+    pub indexes: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Schema {
+    pub tables: Vec<Table>,
+    pub indexes: Vec<Index>,
+    /// What is not imported, and why, e.g. `view v: views are not imported`.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub primary_key: Vec<String>,
+    pub unique: Vec<Vec<String>>,
+    root: u32,
+    /// The INTEGER PRIMARY KEY, which SQLite keeps as the rowid.
+    rowid_alias: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataType,
+    pub not_null: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Index {
+    pub name: String,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub unique: bool,
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_all(names: &[String]) -> String {
+    names
+        .iter()
+        .map(|name| quote(name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl Table {
+    pub fn create_sql(&self) -> String {
+        let mut definitions: Vec<_> = self
+            .columns
+            .iter()
+            .map(|column| {
+                let not_null = if column.not_null { " NOT NULL" } else { "" };
+                format!("{} {}{not_null}", quote(&column.name), column.data_type)
+            })
+            .collect();
+        if !self.primary_key.is_empty() {
+            definitions.push(format!("PRIMARY KEY ({})", quote_all(&self.primary_key)));
+        }
+        for unique in &self.unique {
+            definitions.push(format!("UNIQUE ({})", quote_all(unique)));
+        }
+        format!(
+            "CREATE TABLE {} ({})",
+            quote(&self.name),
+            definitions.join(", ")
+        )
+    }
+
+    /// An INSERT of one row, with a parameter for each column.
+    pub fn insert_sql(&self) -> String {
+        let parameters: Vec<_> = (1..=self.columns.len()).map(|i| format!("${i}")).collect();
+        format!(
+            "INSERT INTO {} VALUES ({})",
+            quote(&self.name),
+            parameters.join(", ")
+        )
+    }
+
+    /// A DROP of the table, which undoes a failed import.
+    pub fn drop_sql(&self) -> String {
+        format!("DROP TABLE {}", quote(&self.name))
+    }
+
+    /// The columns named `names`, which SQLite matches ignoring case.
+    fn columns(&self, names: &[String]) -> Result<Vec<String>, String> {
+        names
+            .iter()
+            .map(|name| {
+                self.columns
+                    .iter()
+                    .find(|column| column.name.eq_ignore_ascii_case(name))
+                    .map(|column| column.name.clone())
+                    .ok_or_else(|| format!("no column named {name:?}"))
+            })
+            .collect()
+    }
+}
+
+impl Index {
+    pub fn create_sql(&self) -> String {
+        format!(
+            "CREATE {}INDEX {} ON {} ({})",
+            if self.unique { "UNIQUE " } else { "" },
+            quote(&self.name),
+            quote(&self.table),
+            quote_all(&self.columns)
+        )
+    }
+}
+
+/// A token of SQLite's SQL, as far as the schema needs them.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// An unquoted word, lowercased.
+    Word(String),
+    /// A name in double quotes, backticks or brackets.
+    Quoted(String),
+    Literal,
+    Punct(char),
+}
+
+impl Token {
+    fn is(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(word) if word == keyword)
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self {
+            Token::Word(name) | Token::Quoted(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '-' if chars.next_if_eq(&'-').is_some() => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.next_if_eq(&'*').is_some() => {
+                while let Some(c) = chars.next() {
+                    if c == '*' && chars.next_if_eq(&'/').is_some() {
+                        break;
+                    }
+                }
+            }
+            '"' | '`' | '[' | '\'' => {
+                let close = if c == '[' { ']' } else { c };
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        None => return Err("unterminated quote".to_string()),
+                        Some(c) if c == close => {
+                            if close == ']' || chars.next_if_eq(&close).is_none() {
+                                break;
+                            }
+                            text.push(close);
+                        }
+                        Some(c) => text.push(c),
+                    }
+                }
+                tokens.push(if c == '\'' {
+                    Token::Literal
+                } else {
+                    Token::Quoted(text)
+                });
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|&c| c.is_alphanumeric() || c == '_' || c == '$')
+                {
+                    word.push(c);
+                }
+                tokens.push(if c.is_ascii_digit() {
+                    Token::Literal
+                } else {
+                    Token::Word(word.to_lowercase())
+                });
+            }
+            c => tokens.push(Token::Punct(c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Splits the parenthesized list at the start of `tokens` at its commas,
+/// and returns the items and the tokens after it.
+fn split_list(tokens: &[Token]) -> Result<(Vec<&[Token]>, &[Token]), String> {
+    if tokens.first() != Some(&Token::Punct('(')) {
+        return Err("expected a parenthesized list".to_string());
+    }
+    let mut items = vec![];
+    let (mut depth, mut start) = (0, 1);
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punct('(') => depth += 1,
+            Token::Punct(')') if depth == 1 => {
+                items.push(&tokens[start..i]);
+                return Ok((items, &tokens[i + 1..]));
+            }
+            Token::Punct(')') => depth -= 1,
+            Token::Punct(',') if depth == 1 => {
+                items.push(&tokens[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    Err("unbalanced parentheses".to_string())
+}
+
+/// Skips to after `keyword` and an IF NOT EXISTS, and reads the name
+/// there, dropping a schema name before it.
+fn object_name<'a>(tokens: &'a [Token], keyword: &str) -> Result<(String, &'a [Token]), String> {
+    let mut at = tokens
+        .iter()
+        .position(|token| token.is(keyword))
+        .ok_or("not a CREATE statement")?
+        + 1;
+    if tokens.get(at).is_some_and(|token| token.is("if")) {
+        at += 3;
+    }
+    if tokens.get(at + 1) == Some(&Token::Punct('.')) {
+        at += 2;
+    }
+    let name = tokens
+        .get(at)
+        .and_then(Token::name)
+        .ok_or("expected a name")?;
+    Ok((name.to_string(), &tokens[at + 1..]))
+}
+
+/// The column names of a list such as `(a, b COLLATE nocase DESC)`.
+fn column_list(items: &[&[Token]]) -> Result<Vec<String>, String> {
+    items
+        .iter()
+        .map(|item| match item {
+            [name, rest @ ..]
+                if name.name().is_some()
+                    && rest.iter().all(|token| {
+                        ["asc", "desc", "collate"].iter().any(|k| token.is(k))
+                            || token.name().is_some()
+                    }) =>
+            {
+                Ok(name.name().unwrap().to_string())
+            }
+            _ => Err("indexes on expressions are not supported".to_string()),
+        })
+        .collect()
+}
+
+fn data_type(declared: &str) -> DataType {
+    let declared = declared.to_uppercase();
+    let has = |words: &[&str]| words.iter().any(|word| declared.contains(word));
+    if has(&["INT"]) {
+        DataType::Int
+    } else if has(&["JSON"]) {
+        DataType::Json
+    } else if has(&["CHAR", "CLOB", "TEXT", "DATE", "TIME"]) || declared.is_empty() {
+        DataType::Text
+    } else if has(&["BLOB"]) {
+        DataType::Bytes
+    } else if has(&["BOOL"]) {
+        DataType::Bool
+    } else {
+        DataType::Float
+    }
+}
+
+/// Words that end a column's declared type.
+const CONSTRAINT_WORDS: &[&str] = &[
+    "constraint",
+    "primary",
+    "not",
+    "null",
+    "unique",
+    "check",
+    "default",
+    "collate",
+    "references",
+    "generated",
+    "as",
+];
+
+fn parse_table(sql: &str) -> Result<Table, String> {
+    let tokens = tokenize(sql)?;
+    if tokens.first().is_some_and(|token| token.is("create"))
+        && tokens.get(1).is_some_and(|token| token.is("virtual"))
+    {
+        return Err("virtual tables are not imported".to_string());
+    }
+    let (name, rest) = object_name(&tokens, "table")?;
+    let (definitions, rest) = split_list(rest)?;
+    if rest.iter().any(|token| token.is("without")) {
+        return Err("tables WITHOUT ROWID are not supported".to_string());
+    }
+    let mut table = Table {
+        name,
+        columns: vec![],
+        primary_key: vec![],
+        unique: vec![],
+        root: 0,
+        rowid_alias: None,
+    };
+    let mut primary_key = None;
+    let mut column_keys = vec![];
+    let mut declared_types = vec![];
+    for mut definition in definitions {
+        if definition
+            .first()
+            .is_some_and(|token| token.is("constraint"))
+        {
+            definition = definition.get(2..).unwrap_or_default();
+        }
+        match definition.first() {
+            Some(token) if token.is("primary") => {
+                let (items, _) = split_list(definition.get(2..).unwrap_or_default())?;
+                primary_key = Some(column_list(&items)?);
+            }
+            Some(token) if token.is("unique") => {
+                let (items, _) = split_list(&definition[1..])?;
+                table.unique.push(column_list(&items)?);
+            }
+            Some(token) if token.is("check") || token.is("foreign") => {}
+            Some(token) => {
+                let name = token.name().ok_or("expected a column name")?.to_string();
+                let mut rest = &definition[1..];
+                let mut words = vec![];
+                while let Some(Token::Word(word)) = rest.first() {
+                    if CONSTRAINT_WORDS.contains(&word.as_str()) {
+                        break;
+                    }
+                    words.push(word.as_str());
+                    rest = &rest[1..];
+                }
+                if rest.first() == Some(&Token::Punct('(')) {
+                    rest = split_list(rest)?.1;
+                }
+                let declared = words.join(" ");
+                let mut column = Column {
+                    name,
+                    data_type: data_type(&declared),
+                    not_null: false,
+                };
+                let mut depth = 0;
+                for (i, token) in rest.iter().enumerate() {
+                    match token {
+                        Token::Punct('(') => depth += 1,
+                        Token::Punct(')') => depth -= 1,
+                        _ if depth > 0 => {}
+                        _ if token.is("primary") => column_keys.push(column.name.clone()),
+                        _ if token.is("not") && rest.get(i + 1).is_some_and(|t| t.is("null")) => {
+                            column.not_null = true;
+                        }
+                        _ if token.is("unique") => table.unique.push(vec![column.name.clone()]),
+                        _ if token.is("generated") || token.is("as") => {
+                            return Err("generated columns are not supported".to_string())
+                        }
+                        _ => {}
+                    }
+                }
+                table.columns.push(column);
+                declared_types.push(declared);
+            }
+            None => return Err("empty column definition".to_string()),
+        }
+    }
+    table.primary_key = match primary_key {
+        Some(key) => key,
+        None if column_keys.len() > 1 => return Err("more than one primary key".to_string()),
+        None => column_keys,
+    };
+    if let [key] = table.primary_key.as_slice() {
+        let i = table
+            .columns
+            .iter()
+            .position(|column| column.name.eq_ignore_ascii_case(key))
+            .ok_or_else(|| format!("no column named {key:?}"))?;
+        // Only a key declared exactly INTEGER is the rowid.
+        if declared_types[i].eq_ignore_ascii_case("integer") {
+            table.rowid_alias = Some(i);
+        }
+    }
+    table.primary_key = table.columns(&table.primary_key)?;
+    table.unique = table
+        .unique
+        .iter()
+        .map(|columns| table.columns(columns))
+        .collect::<Result<_, _>>()?;
+    Ok(table)
+}
+
+fn parse_index(sql: &str, table: &Table) -> Result<Index, String> {
+    let tokens = tokenize(sql)?;
+    let unique = tokens.get(1).is_some_and(|token| token.is("unique"));
+    let (name, rest) = object_name(&tokens, "index")?;
+    let rest = match rest {
+        [on, _table, rest @ ..] if on.is("on") => rest,
+        _ => return Err("expected ON and a table".to_string()),
+    };
+    let (items, rest) = split_list(rest)?;
+    if rest.iter().any(|token| token.is("where")) {
+        return Err("partial indexes are not supported".to_string());
+    }
+    let columns = table.columns(&column_list(&items)?)?;
+    Ok(Index {
+        name,
+        table: table.name.clone(),
+        columns,
+        unique,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, Options};
+
+    fn varint(mut n: u64) -> Vec<u8> {
+        let mut bytes = vec![(n & 0x7f) as u8];
+        n >>= 7;
+        while n > 0 {
+            bytes.push((n & 0x7f) as u8 | 0x80);
+            n >>= 7;
+        }
+        bytes.reverse();
+        bytes
+    }
+
+    /// A table leaf cell holding `values` as a record.
+    fn cell(rowid: u64, values: &[Value]) -> Vec<u8> {
+        let (mut types, mut body) = (vec![], vec![]);
+        for value in values {
+            match value {
+                Value::Null => types.push(0),
+                Value::Int(n) => {
+                    types.push(6);
+                    body.extend(n.to_be_bytes());
+                }
+                Value::Float(x) => {
+                    types.push(7);
+                    body.extend(x.to_be_bytes());
+                }
+                Value::Text(s) => {
+                    types.extend(varint(13 + 2 * s.len() as u64));
+                    body.extend(s.as_bytes());
+                }
+                _ => unreachable!(),
+            }
+        }
+        let mut payload = varint(types.len() as u64 + 1);
+        payload.extend(types);
+        payload.extend(body);
+        let mut cell = varint(payload.len() as u64);
+        cell.extend(varint(rowid));
+        cell.extend(payload);
+        cell
+    }
+
+    fn leaf_page(header: usize, cells: &[Vec<u8>]) -> Vec<u8> {
+        let mut page = vec![0; 512];
+        page[header] = LEAF_TABLE;
+        page[header + 3..header + 5].copy_from_slice(&(cells.len() as u16).to_be_bytes());
+        let mut end = page.len();
+        for (i, cell) in cells.iter().enumerate() {
+            end -= cell.len();
+            page[end..end + cell.len()].copy_from_slice(cell);
+            let at = header + 8 + 2 * i;
+            page[at..at + 2].copy_from_slice(&(end as u16).to_be_bytes());
+        }
+        page
+    }
+
+    #[test]
+    fn test_import() {
+        let schema = [
+            ("table", "t", 2, "CREATE TABLE [T] (id INTEGER PRIMARY KEY, name varchar(8) NOT NULL, n, flag BOOLEAN DEFAULT (0))"),
+            ("index", "t_name", 3, "CREATE INDEX t_name ON t (Name COLLATE nocase)"),
+            ("index", "t_lower", 4, "CREATE INDEX t_lower ON t (lower(name))"),
+            ("view", "v", 0, "CREATE VIEW v AS SELECT 1"),
+        ];
+        let cells: Vec<_> = schema
+            .iter()
+            .enumerate()
+            .map(|(i, &(kind, name, root, sql))| {
+                let values = [
+                    kind.into(),
+                    name.into(),
+                    "t".into(),
+                    Value::Int(root),
+                    sql.into(),
+                ];
+                cell(i as u64 + 1, &values)
+            })
+            .collect();
+        let mut file = leaf_page(HEADER_SIZE, &cells);
+        file[..16].copy_from_slice(MAGIC);
+        file[16..18].copy_from_slice(&512u16.to_be_bytes());
+        file[56..60].copy_from_slice(&1u32.to_be_bytes());
+        file.extend(leaf_page(
+            0,
+            &[
+                cell(1, &[Value::Null, "a".into(), 5.into(), 1.into()]),
+                cell(7, &[Value::Null, "b".into(), 1.5.into()]),
+            ],
+        ));
+        let sqlite = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(sqlite.path(), &file).unwrap();
+
+        let mut db = Database::temporary(Options::default()).unwrap();
+        let import = db.import_sqlite(sqlite.path()).unwrap();
+        assert_eq!(
+            Import {
+                tables: vec![("T".to_string(), 2)],
+                indexes: vec!["t_name".to_string()],
+                skipped: vec![
+                    "view v: views are not imported".to_string(),
+                    "index t_lower: indexes on expressions are not supported".to_string(),
+                ],
+            },
+            import
+        );
+        let rows = db
+            .query_as::<(i64, String, String, Option<bool>)>("SELECT * FROM \"T\" ORDER BY id")
+            .unwrap();
+        assert_eq!(
+            vec![
+                (1, "a".to_string(), "5".to_string(), Some(true)),
+                (7, "b".to_string(), "1.5".to_string(), None),
+            ],
+            rows
+        );
+        assert!(db
+            .execute("INSERT INTO \"T\" VALUES (7, 'c', '', NULL)")
+            .is_err());
+
+        file[512] = 0x07;
+        std::fs::write(sqlite.path(), &file).unwrap();
+        let mut db = Database::temporary(Options::default()).unwrap();
+        let error = db.import_sqlite(sqlite.path());
+        assert!(matches!(
+            error,
+            Err(crate::database::Error::Sqlite(Error::Corrupt(_)))
+        ));
+        assert!(db.execute("SELECT * FROM \"T\"").is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Table statistics gathered by ANALYZE for the cost-based planner.
+
+use std::collections::HashSet;
+
+use crate::buffer::BufferPoolManager;
+use crate::heap::{self, HeapFile};
+use crate::tuple;
+use crate::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    /// Number of distinct non-NULL values.
+    pub distinct: usize,
+    pub null_fraction: f64,
+    /// Smallest and largest non-NULL values; `None` when every value is NULL.
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    pub rows: usize,
+    pub pages: usize,
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Scans the whole heap and summarizes each of its `width` columns.
+pub fn analyze(
+    bufmgr: &BufferPoolManager,
+    heap: &HeapFile,
+    width: usize,
+) -> Result<TableStats, heap::Error> {
+    let pages = heap.page_ids(bufmgr)?.len();
+    let mut distinct = vec![HashSet::new(); width];
+    let mut nulls = vec![0usize; width];
+    let mut min: Vec<Option<Value>> = vec![None; width];
+    let mut max: Vec<Option<Value>> = vec![None; width];
+    let mut rows = 0;
+    let mut scan = heap.scan(bufmgr)?;
+    while let Some((_, tuple)) = scan.next(bufmgr)? {
+        rows += 1;
+        for (i, value) in tuple.into_iter().enumerate().take(width) {
+            if value.is_null() {
+                nulls[i] += 1;
+                continue;
+            }
+            let mut key = vec![];
+            tuple::encode_key(std::slice::from_ref(&value), &mut key);
+            distinct[i].insert(key);
+            if min[i]
+                .as_ref()
+                .is_none_or(|min| value.total_cmp(min).is_lt())
+            {
+                min[i] = Some(value.clone());
+            }
+            if max[i]
+                .as_ref()
+                .is_none_or(|max| value.total_cmp(max).is_gt())
+            {
+                max[i] = Some(value);
+            }
+        }
+    }
+    let columns = (0..width)
+        .map(|i| ColumnStats {
+            distinct: distinct[i].len(),
+            null_fraction: if rows == 0 {
+                0.0
+            } else {
+                nulls[i] as f64 / rows as f64
+            },
+            min: min[i].take(),
+            max: max[i].take(),
+        })
+        .collect();
+    Ok(TableStats {
+        rows,
+        pages,
+        columns,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::{Catalog, Column, Schema};
+    use crate::disk::DiskManager;
+    use crate::value::DataType;
+    use tempfile::tempfile;
+
+    #[test]
+    fn test_analyze() {
+        let disk = DiskManager::new(tempfile().unwrap()).unwrap();
+        let bufmgr = BufferPoolManager::new(disk, 16);
+        let mut catalog = Catalog::new();
+        let schema = Schema::new(vec![
+            Column::new("id", DataType::Int),
+            Column::new("tag", DataType::Text),
+        ]);
+        let heap = catalog.create_table(&bufmgr, "t", schema).unwrap().heap;
+        for i in 0..1000i64 {
+            let tag = if i % 4 == 0 {
+                Value::Null
+            } else {
+                format!("t{}", i % 10).into()
+            };
+            heap.insert(&bufmgr, &[i.into(), tag]).unwrap();
+        }
+        let stats = catalog.analyze(&bufmgr, "t").unwrap().clone();
+        assert_eq!(1000, stats.rows);
+        assert!(stats.pages > 1);
+        assert_eq!(
+            ColumnStats {
+                distinct: 1000,
+                null_fraction: 0.0,
+                min: Some(Value::Int(0)),
+                max: Some(Value::Int(999)),
+            },
+            stats.columns[0]
+        );
+        assert_eq!(10, stats.columns[1].distinct);
+        assert_eq!(0.25, stats.columns[1].null_fraction);
+        assert_eq!(Some("t0".into()), stats.columns[1].min);
+        assert_eq!(Some(&stats), catalog.table("t").unwrap().stats.as_ref());
+    }
+}
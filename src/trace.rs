@@ -0,0 +1,173 @@
+//! Events for diagnosing performance: page reads and writes, evictions,
+//! syncs and the time each operator of a plan took.
+//!
+//! Nothing is recorded until a [`Subscriber`] is installed with
+//! [`set_subscriber`]; until then each place that could emit an event
+//! costs one relaxed atomic load, so the instrumentation stays compiled
+//! in rather than behind a feature. [`Logger`] writes events as logfmt
+//! lines, and a subscriber of one's own can forward them to a `tracing`
+//! or `log` backend.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::disk::PageId;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event<'a> {
+    /// A page read from the file into the buffer pool.
+    PageRead {
+        page_id: PageId,
+        elapsed: Duration,
+    },
+    PageWrite {
+        page_id: PageId,
+        elapsed: Duration,
+    },
+    /// A frame of the buffer pool given over to another page.
+    Eviction {
+        page_id: PageId,
+        dirty: bool,
+    },
+    /// The file flushed to stable storage.
+    Sync {
+        elapsed: Duration,
+    },
+    /// An operator finished, having produced `rows` rows in `time`,
+    /// counting the time of its inputs.
+    Operator {
+        name: &'a str,
+        rows: u64,
+        time: Duration,
+    },
+}
+
+impl fmt::Display for Event<'_> {
+    /// Formats the event as logfmt, times in microseconds.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::PageRead { page_id, elapsed } => write!(
+                f,
+                "event=page_read page_id={} elapsed_us={}",
+                page_id.to_u64(),
+                elapsed.as_micros()
+            ),
+            Event::PageWrite { page_id, elapsed } => write!(
+                f,
+                "event=page_write page_id={} elapsed_us={}",
+                page_id.to_u64(),
+                elapsed.as_micros()
+            ),
+            Event::Eviction { page_id, dirty } => write!(
+                f,
+                "event=eviction page_id={} dirty={dirty}",
+                page_id.to_u64()
+            ),
+            Event::Sync { elapsed } => {
+                write!(f, "event=sync elapsed_us={}", elapsed.as_micros())
+            }
+            Event::Operator { name, rows, time } => write!(
+                f,
+                "event=operator name={name} rows={rows} time_us={}",
+                time.as_micros()
+            ),
+        }
+    }
+}
+
+/// Receives every event from any thread.
+pub trait Subscriber: Send + Sync {
+    fn event(&self, event: &Event<'_>);
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static SUBSCRIBER: RwLock<Option<Arc<dyn Subscriber>>> = RwLock::new(None);
+
+/// Sends events to `subscriber` from now on, in place of any other.
+pub fn set_subscriber(subscriber: Arc<dyn Subscriber>) {
+    *SUBSCRIBER.write().unwrap() = Some(subscriber);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Stops sending events.
+pub fn clear_subscriber() {
+    ENABLED.store(false, Ordering::Relaxed);
+    *SUBSCRIBER.write().unwrap() = None;
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// When a subscriber is installed, the time to measure an event from.
+pub(crate) fn start() -> Option<Instant> {
+    enabled().then(Instant::now)
+}
+
+pub(crate) fn emit(event: &Event<'_>) {
+    if let Some(subscriber) = &*SUBSCRIBER.read().unwrap() {
+        subscriber.event(event);
+    }
+}
+
+/// Writes each event on a line of its own.
+pub struct Logger<W> {
+    output: Mutex<W>,
+}
+
+impl<W: Write + Send> Logger<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            output: Mutex::new(output),
+        }
+    }
+}
+
+impl Logger<io::Stderr> {
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl<W: Write + Send> Subscriber for Logger<W> {
+    fn event(&self, event: &Event<'_>) {
+        let _ = writeln!(self.output.lock().unwrap(), "neru7db: {event}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{Database, Options};
+
+    #[derive(Default)]
+    struct Collect(Mutex<Vec<String>>);
+
+    impl Subscriber for Collect {
+        fn event(&self, event: &Event<'_>) {
+            self.0.lock().unwrap().push(event.to_string());
+        }
+    }
+
+    #[test]
+    fn test_subscriber() {
+        let mut db = Database::temporary(Options::default()).unwrap();
+        db.execute("CREATE TABLE t (id INT)").unwrap();
+        db.execute("INSERT INTO t VALUES (1), (2), (3)").unwrap();
+        let collect = Arc::new(Collect::default());
+        set_subscriber(collect.clone());
+        db.query("SELECT id FROM t WHERE id > 1").unwrap();
+        db.execute("INSERT INTO t VALUES (4)").unwrap();
+        db.engine().bufmgr().flush().unwrap();
+        clear_subscriber();
+        // Other tests run at the same time, so only look for ours.
+        let events = collect.0.lock().unwrap();
+        assert!(events
+            .iter()
+            .any(|event| event.starts_with("event=operator name=Filter rows=2 ")));
+        assert!(events.iter().any(|event| event.starts_with("event=sync ")));
+    }
+}
@@ -0,0 +1,438 @@
+//! Byte encodings of values: a self-describing record format for heap
+//! tuples, and an order-preserving ("memcmpable") format for index keys.
+//!
+//! A JSON value is kept in a binary form of its own in both, a tag for
+//! each node followed by its contents, so that reading it back takes no
+//! parsing of text. An array is kept as its element type and the record
+//! or, in a key, the keys of its elements.
+
+use crate::array::Array;
+use crate::json::{Json, MAX_DEPTH};
+use crate::value::{DataType, Tuple, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("malformed tuple: {0}")]
+    Malformed(&'static str),
+}
+
+const TAG_NULL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_FLOAT: u8 = 4;
+const TAG_TEXT: u8 = 5;
+const TAG_BYTES: u8 = 6;
+const TAG_JSON: u8 = 7;
+const TAG_ARRAY: u8 = 8;
+
+/// The types an array can hold, by the byte that stands for them.
+const ELEMENT_TYPES: [DataType; 6] = [
+    DataType::Bool,
+    DataType::Int,
+    DataType::Float,
+    DataType::Text,
+    DataType::Bytes,
+    DataType::Json,
+];
+
+const JSON_NULL: u8 = 0;
+const JSON_FALSE: u8 = 1;
+const JSON_TRUE: u8 = 2;
+const JSON_INT: u8 = 3;
+const JSON_FLOAT: u8 = 4;
+const JSON_STRING: u8 = 5;
+const JSON_ARRAY: u8 = 6;
+const JSON_OBJECT: u8 = 7;
+
+pub fn encode(values: &[Value], dst: &mut Vec<u8>) {
+    for value in values {
+        match value {
+            Value::Null => dst.push(TAG_NULL),
+            Value::Bool(false) => dst.push(TAG_FALSE),
+            Value::Bool(true) => dst.push(TAG_TRUE),
+            Value::Int(i) => {
+                dst.push(TAG_INT);
+                dst.extend_from_slice(&i.to_le_bytes());
+            }
+            Value::Float(x) => {
+                dst.push(TAG_FLOAT);
+                dst.extend_from_slice(&x.to_le_bytes());
+            }
+            Value::Text(s) => {
+                dst.push(TAG_TEXT);
+                dst.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                dst.extend_from_slice(s.as_bytes());
+            }
+            Value::Bytes(bytes) => {
+                dst.push(TAG_BYTES);
+                dst.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                dst.extend_from_slice(bytes);
+            }
+            Value::Json(json) => {
+                dst.push(TAG_JSON);
+                prefix_len(dst, |dst| encode_json(json, dst));
+            }
+            Value::Array(array) => {
+                dst.push(TAG_ARRAY);
+                let element = ELEMENT_TYPES.iter().position(|t| *t == array.element);
+                dst.push(element.expect("arrays hold scalars") as u8);
+                prefix_len(dst, |dst| encode(&array.items, dst));
+            }
+        }
+    }
+}
+
+/// Writes with `write`, preceded by the four bytes of the length written.
+fn prefix_len(dst: &mut Vec<u8>, write: impl FnOnce(&mut Vec<u8>)) {
+    let start = dst.len();
+    dst.extend_from_slice(&[0; 4]);
+    write(dst);
+    let len = (dst.len() - start - 4) as u32;
+    dst[start..start + 4].copy_from_slice(&len.to_le_bytes());
+}
+
+/// `len` as the four bytes that prefix what it counts.
+fn extend_len(dst: &mut Vec<u8>, len: usize) {
+    dst.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn encode_json(json: &Json, dst: &mut Vec<u8>) {
+    match json {
+        Json::Null => dst.push(JSON_NULL),
+        Json::Bool(false) => dst.push(JSON_FALSE),
+        Json::Bool(true) => dst.push(JSON_TRUE),
+        Json::Int(i) => {
+            dst.push(JSON_INT);
+            dst.extend_from_slice(&i.to_le_bytes());
+        }
+        Json::Float(x) => {
+            dst.push(JSON_FLOAT);
+            dst.extend_from_slice(&x.to_le_bytes());
+        }
+        Json::String(s) => {
+            dst.push(JSON_STRING);
+            extend_len(dst, s.len());
+            dst.extend_from_slice(s.as_bytes());
+        }
+        Json::Array(items) => {
+            dst.push(JSON_ARRAY);
+            extend_len(dst, items.len());
+            for item in items {
+                encode_json(item, dst);
+            }
+        }
+        Json::Object(members) => {
+            dst.push(JSON_OBJECT);
+            extend_len(dst, members.len());
+            for (key, value) in members {
+                extend_len(dst, key.len());
+                dst.extend_from_slice(key.as_bytes());
+                encode_json(value, dst);
+            }
+        }
+    }
+}
+
+fn take<'a>(src: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if src.len() < len {
+        return Err(Error::Malformed("unexpected end of record"));
+    }
+    let (head, tail) = src.split_at(len);
+    *src = tail;
+    Ok(head)
+}
+
+fn take_len(src: &mut &[u8]) -> Result<usize, Error> {
+    Ok(u32::from_le_bytes(take(src, 4)?.try_into().unwrap()) as usize)
+}
+
+fn take_var<'a>(src: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    let len = take_len(src)?;
+    take(src, len)
+}
+
+fn take_str<'a>(src: &mut &'a [u8]) -> Result<&'a str, Error> {
+    std::str::from_utf8(take_var(src)?).map_err(|_| Error::Malformed("text is not valid UTF-8"))
+}
+
+pub fn decode(src: &[u8]) -> Result<Tuple, Error> {
+    decode_values(src, true)
+}
+
+/// Decodes values, arrays among them only if `arrays`: the elements of
+/// an array are not arrays.
+fn decode_values(mut src: &[u8], arrays: bool) -> Result<Tuple, Error> {
+    let mut values = vec![];
+    while let Some((&tag, rest)) = src.split_first() {
+        src = rest;
+        let value = match tag {
+            TAG_NULL => Value::Null,
+            TAG_FALSE => Value::Bool(false),
+            TAG_TRUE => Value::Bool(true),
+            TAG_INT => Value::Int(i64::from_le_bytes(take(&mut src, 8)?.try_into().unwrap())),
+            TAG_FLOAT => Value::Float(f64::from_le_bytes(take(&mut src, 8)?.try_into().unwrap())),
+            TAG_TEXT => Value::Text(take_str(&mut src)?.to_string()),
+            TAG_BYTES => Value::Bytes(take_var(&mut src)?.to_vec()),
+            TAG_JSON => {
+                let mut json = take_var(&mut src)?;
+                let value = decode_json(&mut json, 0)?;
+                if !json.is_empty() {
+                    return Err(Error::Malformed("trailing bytes after JSON"));
+                }
+                Value::Json(Box::new(value))
+            }
+            TAG_ARRAY if arrays => {
+                let element = *(ELEMENT_TYPES.get(take(&mut src, 1)?[0] as usize))
+                    .ok_or(Error::Malformed("unknown element type"))?;
+                let items = decode_values(take_var(&mut src)?, false)?;
+                if (items.iter()).any(|item| !item.is_null() && item.data_type() != Some(element)) {
+                    return Err(Error::Malformed("array element of another type"));
+                }
+                Value::array(Array::new(element, items))
+            }
+            _ => return Err(Error::Malformed("unknown value tag")),
+        };
+        values.push(value);
+    }
+    Ok(values)
+}
+
+fn decode_json(src: &mut &[u8], depth: usize) -> Result<Json, Error> {
+    if depth > MAX_DEPTH {
+        return Err(Error::Malformed("JSON is nested too deeply"));
+    }
+    let json = match take(src, 1)?[0] {
+        JSON_NULL => Json::Null,
+        JSON_FALSE => Json::Bool(false),
+        JSON_TRUE => Json::Bool(true),
+        JSON_INT => Json::Int(i64::from_le_bytes(take(src, 8)?.try_into().unwrap())),
+        JSON_FLOAT => Json::Float(f64::from_le_bytes(take(src, 8)?.try_into().unwrap())),
+        JSON_STRING => Json::String(take_str(src)?.to_string()),
+        JSON_ARRAY => {
+            let len = take_len(src)?;
+            // Each item takes a byte at least.
+            let mut items = Vec::with_capacity(len.min(src.len()));
+            for _ in 0..len {
+                items.push(decode_json(src, depth + 1)?);
+            }
+            Json::Array(items)
+        }
+        JSON_OBJECT => {
+            let len = take_len(src)?;
+            let mut members = Vec::with_capacity(len.min(src.len()));
+            for _ in 0..len {
+                let key = take_str(src)?.to_string();
+                members.push((key, decode_json(src, depth + 1)?));
+            }
+            Json::Object(members)
+        }
+        _ => return Err(Error::Malformed("unknown JSON tag")),
+    };
+    Ok(json)
+}
+
+const KEY_NULL: u8 = 0;
+const KEY_NOT_NULL: u8 = 1;
+const KEY_NULL_LAST: u8 = 2;
+const ESCAPE_LENGTH: usize = 9;
+
+/// Appends the memcmpable encoding of `values` to `dst`.
+///
+/// Byte-wise comparison of two encodings agrees with comparing the values
+/// column by column (NULLs first), and no encoding is a prefix of another
+/// encoding of the same arity. Ints and floats must not share a column.
+pub fn encode_key(values: &[Value], dst: &mut Vec<u8>) {
+    encode_key_ordered(values, |_| true, dst);
+}
+
+/// Like [`encode_key`], but the NULLs of column `i` sort after every
+/// other value unless `nulls_first(i)`.
+pub fn encode_key_ordered(
+    values: &[Value],
+    nulls_first: impl Fn(usize) -> bool,
+    dst: &mut Vec<u8>,
+) {
+    for (i, value) in values.iter().enumerate() {
+        if value.is_null() {
+            dst.push(if nulls_first(i) {
+                KEY_NULL
+            } else {
+                KEY_NULL_LAST
+            });
+            continue;
+        }
+        dst.push(KEY_NOT_NULL);
+        match value {
+            Value::Null => unreachable!(),
+            Value::Bool(b) => dst.push(*b as u8),
+            Value::Int(i) => dst.extend_from_slice(&((*i as u64) ^ (1 << 63)).to_be_bytes()),
+            Value::Float(x) => encode_float_key(*x, dst),
+            Value::Text(s) => encode_bytes(s.as_bytes(), dst),
+            Value::Bytes(bytes) => encode_bytes(bytes, dst),
+            Value::Json(json) => encode_json_key(json, dst),
+            // As for JSON arrays: a prefix orders first.
+            Value::Array(array) => {
+                for item in &array.items {
+                    dst.push(1);
+                    encode_key(std::slice::from_ref(item), dst);
+                }
+                dst.push(0);
+            }
+        }
+    }
+}
+
+fn encode_float_key(x: f64, dst: &mut Vec<u8>) {
+    let bits = if x == 0.0 {
+        0f64.to_bits()
+    } else {
+        x.to_bits()
+    };
+    let bits = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits ^ (1 << 63)
+    };
+    dst.extend_from_slice(&bits.to_be_bytes());
+}
+
+/// A tag for the type of each node, in the order of types, then its
+/// value. The items of arrays and the members of objects each follow a
+/// 1 byte, and a 0 byte ends them, so that a prefix orders first.
+fn encode_json_key(json: &Json, dst: &mut Vec<u8>) {
+    match json {
+        Json::Null => dst.push(1),
+        Json::Bool(b) => dst.push(2 + *b as u8),
+        Json::Int(_) | Json::Float(_) => {
+            dst.push(4);
+            encode_float_key(json.as_f64().unwrap(), dst);
+        }
+        Json::String(s) => {
+            dst.push(5);
+            encode_bytes(s.as_bytes(), dst);
+        }
+        Json::Array(items) => {
+            dst.push(6);
+            for item in items {
+                dst.push(1);
+                encode_json_key(item, dst);
+            }
+            dst.push(0);
+        }
+        Json::Object(members) => {
+            dst.push(7);
+            for (key, value) in members {
+                dst.push(1);
+                encode_bytes(key.as_bytes(), dst);
+                encode_json_key(value, dst);
+            }
+            dst.push(0);
+        }
+    }
+}
+
+fn encode_bytes(mut src: &[u8], dst: &mut Vec<u8>) {
+    loop {
+        let copy_len = (ESCAPE_LENGTH - 1).min(src.len());
+        dst.extend_from_slice(&src[..copy_len]);
+        src = &src[copy_len..];
+        dst.resize(dst.len() + ESCAPE_LENGTH - 1 - copy_len, 0);
+        if src.is_empty() {
+            dst.push(copy_len as u8);
+            break;
+        }
+        dst.push(ESCAPE_LENGTH as u8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use super::*;
+    use crate::json;
+
+    fn key(values: &[Value]) -> Vec<u8> {
+        let mut buf = vec![];
+        encode_key(values, &mut buf);
+        buf
+    }
+
+    #[test]
+    fn test_record_round_trip() {
+        let values = vec![
+            Value::Null,
+            Value::Bool(true),
+            Value::Int(-42),
+            Value::Float(1.5),
+            Value::Text("hello".into()),
+            Value::Bytes(vec![0, 1, 2]),
+            Value::json(json::parse(r#"{"b": [1, 2.5, "x"], "a": {"c": null}}"#).unwrap()),
+            Value::parse("{a,NULL,\"b c\"}", DataType::array(DataType::Text).unwrap()).unwrap(),
+        ];
+        let mut buf = vec![];
+        encode(&values, &mut buf);
+        assert_eq!(values, decode(&buf).unwrap());
+        assert!(decode(&buf[..buf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_key_order() {
+        let ints = [i64::MIN, -1, 0, 1, i64::MAX];
+        for pair in ints.windows(2) {
+            assert!(key(&[Value::Int(pair[0])]) < key(&[Value::Int(pair[1])]));
+        }
+        let floats = [f64::NEG_INFINITY, -2.5, -0.0, 0.5, f64::INFINITY];
+        for pair in floats.windows(2) {
+            assert!(key(&[Value::Float(pair[0])]) <= key(&[Value::Float(pair[1])]));
+        }
+        let texts = ["", "a", "ab", "ab\0", "abcdefghij", "b"];
+        for pair in texts.windows(2) {
+            assert!(key(&[pair[0].into()]) < key(&[pair[1].into()]));
+        }
+        let docs = [
+            "null",
+            "false",
+            "true",
+            "-1",
+            "0.5",
+            "1",
+            r#""""#,
+            r#""a""#,
+            "[]",
+            "[1]",
+            "[1, 2]",
+            "[2]",
+            "{}",
+            r#"{"a": 1}"#,
+            r#"{"a": 1, "b": 1}"#,
+            r#"{"b": 0}"#,
+        ];
+        for pair in docs.windows(2) {
+            let [a, b] = [pair[0], pair[1]].map(|doc| Value::json(json::parse(doc).unwrap()));
+            assert_eq!(Ordering::Less, a.total_cmp(&b), "{a} < {b}");
+            assert!(key(&[a]) < key(&[b]), "{} < {}", pair[0], pair[1]);
+        }
+        let arrays = ["{}", "{NULL}", "{-1}", "{-1,NULL}", "{-1,5}", "{0}"];
+        for pair in arrays.windows(2) {
+            let int_array = DataType::array(DataType::Int).unwrap();
+            let [a, b] = [pair[0], pair[1]].map(|text| Value::parse(text, int_array).unwrap());
+            assert!(key(&[a]) < key(&[b]), "{} < {}", pair[0], pair[1]);
+        }
+        assert!(key(&[Value::Null]) < key(&[Value::Int(i64::MIN)]));
+        let nulls_last = |values: &[Value]| {
+            let mut buf = vec![];
+            encode_key_ordered(values, |i| i == 0, &mut buf);
+            buf
+        };
+        assert!(
+            nulls_last(&[Value::Null, Value::Null]) < nulls_last(&[Value::Int(0), Value::Null])
+        );
+        assert!(
+            nulls_last(&[Value::Int(0), Value::Int(i64::MAX)])
+                < nulls_last(&[Value::Int(0), Value::Null])
+        );
+        assert!(key(&["a".into(), Value::Int(9)]) < key(&["b".into(), Value::Int(0)]));
+    }
+}
@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::array::Array;
+use crate::json::{self, Json};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    Bool,
+    Int,
+    Float,
+    Text,
+    Bytes,
+    Json,
+    /// Arrays of a scalar type; see [`DataType::array`].
+    Array(&'static DataType),
+}
+
+impl DataType {
+    /// The type of arrays of `element`, unless it is itself an array.
+    pub fn array(element: DataType) -> Option<DataType> {
+        Some(DataType::Array(match element {
+            DataType::Bool => &DataType::Bool,
+            DataType::Int => &DataType::Int,
+            DataType::Float => &DataType::Float,
+            DataType::Text => &DataType::Text,
+            DataType::Bytes => &DataType::Bytes,
+            DataType::Json => &DataType::Json,
+            DataType::Array(_) => return None,
+        }))
+    }
+
+    /// The type of the elements of an array type.
+    pub fn element(self) -> Option<DataType> {
+        match self {
+            DataType::Array(element) => Some(*element),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DataType::Bool => "BOOL",
+            DataType::Int => "INT",
+            DataType::Float => "FLOAT",
+            DataType::Text => "TEXT",
+            DataType::Bytes => "BYTES",
+            DataType::Json => "JSON",
+            DataType::Array(element) => return write!(f, "{element}[]"),
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Bytes(Vec<u8>),
+    /// Normalized; see [`Json::normalize`].
+    Json(Box<Json>),
+    Array(Box<Array>),
+}
+
+impl Value {
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::Null => None,
+            Value::Bool(_) => Some(DataType::Bool),
+            Value::Int(_) => Some(DataType::Int),
+            Value::Float(_) => Some(DataType::Float),
+            Value::Text(_) => Some(DataType::Text),
+            Value::Bytes(_) => Some(DataType::Bytes),
+            Value::Json(_) => Some(DataType::Json),
+            Value::Array(array) => DataType::array(array.element),
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// SQL comparison: `None` when either side is NULL or the types are not
+    /// comparable. Ints and floats compare numerically.
+    pub fn sql_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+            (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::Int(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Value::Int(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::Text(a), Value::Text(b)) => Some(a.cmp(b)),
+            (Value::Bytes(a), Value::Bytes(b)) => Some(a.cmp(b)),
+            (Value::Json(a), Value::Json(b)) => Some(a.total_cmp(b)),
+            (Value::Array(a), Value::Array(b)) => Some(a.total_cmp(b)),
+            _ => None,
+        }
+    }
+
+    /// Total order used by sorting: NULLs first, then by type, then by value.
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        fn rank(value: &Value) -> u8 {
+            match value {
+                Value::Null => 0,
+                Value::Bool(_) => 1,
+                Value::Int(_) | Value::Float(_) => 2,
+                Value::Text(_) => 3,
+                Value::Bytes(_) => 4,
+                Value::Json(_) => 5,
+                Value::Array(_) => 6,
+            }
+        }
+        match (self, other) {
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (Value::Int(a), Value::Float(b)) => (*a as f64).total_cmp(b),
+            (Value::Float(a), Value::Int(b)) => a.total_cmp(&(*b as f64)),
+            _ => self
+                .sql_cmp(other)
+                .unwrap_or_else(|| rank(self).cmp(&rank(other))),
+        }
+    }
+
+    /// Reads `text` as a value of `data_type`, as Postgres spells values
+    /// in text: booleans as `t`, `true`, `on` and so on, and byte strings
+    /// in hex after `\x`.
+    pub fn parse(text: &str, data_type: DataType) -> Option<Value> {
+        match data_type {
+            DataType::Bool => match text.trim().to_lowercase().as_str() {
+                "t" | "true" | "on" | "yes" | "1" => Some(Value::Bool(true)),
+                "f" | "false" | "off" | "no" | "0" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            DataType::Int => text.trim().parse().ok().map(Value::Int),
+            DataType::Float => text.trim().parse().ok().map(Value::Float),
+            DataType::Text => Some(Value::Text(text.to_string())),
+            DataType::Bytes => {
+                let hex = text.strip_prefix("\\x")?;
+                if hex.len() % 2 != 0 || !hex.is_ascii() {
+                    return None;
+                }
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+                    .collect::<Option<_>>()
+                    .map(Value::Bytes)
+            }
+            DataType::Json => json::parse(text).ok().map(Value::json),
+            DataType::Array(element) => Array::parse(text, *element).map(Value::array),
+        }
+    }
+
+    /// A JSON value, normalized.
+    pub fn json(json: Json) -> Value {
+        Value::Json(Box::new(json.normalize()))
+    }
+
+    pub fn array(array: Array) -> Value {
+        Value::Array(Box::new(array))
+    }
+
+    /// The value as a SQL literal that reads back as the same value.
+    pub fn to_sql(&self) -> String {
+        match self {
+            Value::Null => "NULL".to_string(),
+            Value::Bool(b) => b.to_string().to_uppercase(),
+            // Its magnitude is out of range without the minus sign.
+            Value::Int(i64::MIN) => format!("CAST('{}' AS INT)", i64::MIN),
+            Value::Int(i) => i.to_string(),
+            Value::Float(x) if x.is_finite() => format!("{x:?}"),
+            Value::Float(_) => format!("CAST('{self}' AS FLOAT)"),
+            Value::Text(s) => format!("'{}'", s.replace('\'', "''")),
+            Value::Bytes(bytes) => {
+                let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+                format!("x'{hex}'")
+            }
+            Value::Json(json) => {
+                let text = json.to_string().replace('\'', "''");
+                format!("CAST('{text}' AS JSON)")
+            }
+            Value::Array(array) => {
+                let text = array.to_string().replace('\'', "''");
+                format!("CAST('{text}' AS {}[])", array.element)
+            }
+        }
+    }
+
+    /// Converts the value to `data_type`, allowing only lossless implicit
+    /// coercions (currently int to float, and so int arrays to float
+    /// arrays).
+    pub fn coerce_to(self, data_type: DataType) -> Option<Value> {
+        match (self, data_type) {
+            (Value::Null, _) => Some(Value::Null),
+            (Value::Int(i), DataType::Float) => Some(Value::Float(i as f64)),
+            (Value::Array(array), DataType::Array(&element)) if array.element != element => {
+                let items = (array.items.into_iter())
+                    .map(|item| item.coerce_to(element))
+                    .collect::<Option<_>>()?;
+                Some(Value::array(Array::new(element, items)))
+            }
+            (value, data_type) if value.data_type() == Some(data_type) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("NULL"),
+            Value::Bool(b) => write!(f, "{b}"),
+            Value::Int(i) => write!(f, "{i}"),
+            Value::Float(x) => write!(f, "{x}"),
+            Value::Text(s) => f.write_str(s),
+            Value::Bytes(bytes) => {
+                f.write_str("\\x")?;
+                for b in bytes {
+                    write!(f, "{b:02x}")?;
+                }
+                Ok(())
+            }
+            Value::Json(json) => write!(f, "{json}"),
+            Value::Array(array) => write!(f, "{array}"),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Text(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Text(s)
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(bytes: Vec<u8>) -> Self {
+        Value::Bytes(bytes)
+    }
+}
+
+pub type Tuple = Vec<Value>;